@@ -0,0 +1,192 @@
+//! Scripted bot clients.
+
+use crate::connection::Connection;
+use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    ChatMessageClientbound, CreativeInventoryAction, Face, PlayerBlockPlacement,
+    PlayerPositionAndLookClientbound,
+};
+use feather_core::{Block, BlockPosition, Item, ItemStack, PacketType};
+use feather_integration::TestServer;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Distance walked per tick, in blocks. This
+/// is approximately the vanilla walking speed.
+const WALK_SPEED: f64 = 0.2;
+/// Chance per tick of changing direction.
+const TURN_CHANCE: f64 = 0.05;
+/// Chance per tick of breaking or placing a block.
+const INTERACT_CHANCE: f64 = 0.05;
+/// Interval between latency probes.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A bot which walks around randomly, breaking
+/// and placing blocks beneath it.
+pub struct Bot {
+    client: Connection,
+    /// The bot's position, known once it has spawned.
+    position: Option<(f64, f64, f64)>,
+    /// Walking direction in radians.
+    direction: f64,
+    /// The time at which the pending latency
+    /// probe was sent, if any.
+    probe_sent: Option<Instant>,
+    last_probe: Instant,
+}
+
+impl Bot {
+    pub fn new(client: Connection) -> Self {
+        Self {
+            client,
+            position: None,
+            direction: 0.0,
+            probe_sent: None,
+            last_probe: Instant::now(),
+        }
+    }
+
+    /// Returns whether the bot has spawned.
+    pub fn has_spawned(&self) -> bool {
+        self.position.is_some()
+    }
+
+    /// Runs the bot's behavior for one tick. Latencies of
+    /// completed probes are pushed onto `latencies`.
+    ///
+    /// The server is only used to find blocks which the
+    /// bot can interact with; a real client would know
+    /// these from the chunks it has received.
+    pub fn tick(
+        &mut self,
+        server: &TestServer,
+        rng: &mut StdRng,
+        radius: f64,
+        latencies: &mut Vec<Duration>,
+    ) {
+        if self.client.is_disconnected() {
+            return;
+        }
+
+        let (mut x, mut y, mut z) = match self.position {
+            Some(position) => position,
+            None => {
+                if self.spawn().is_none() {
+                    return;
+                }
+                self.position.unwrap()
+            }
+        };
+
+        self.read_probe(latencies);
+        // Packets are not otherwise inspected, so
+        // discard them to keep memory usage constant.
+        self.client.clear();
+
+        // Walk, turning back towards spawn if too far away.
+        if x * x + z * z > radius * radius {
+            self.direction = z.atan2(x) + PI;
+        } else if rng.gen_bool(TURN_CHANCE) {
+            self.direction = rng.gen_range(0.0, 2.0 * PI);
+        }
+        x += self.direction.cos() * WALK_SPEED;
+        z += self.direction.sin() * WALK_SPEED;
+
+        // Follow the terrain if it is loaded.
+        if let Some(ground) = highest_block(server, x, z) {
+            y = f64::from(ground.y + 1);
+        }
+
+        self.client.move_to(x, y, z);
+        self.position = Some((x, y, z));
+
+        if rng.gen_bool(INTERACT_CHANCE) {
+            self.interact(server, rng, x, y, z);
+        }
+
+        if self.probe_sent.is_none() && self.last_probe.elapsed() >= PROBE_INTERVAL {
+            self.client.chat("/tps");
+            self.probe_sent = Some(Instant::now());
+            self.last_probe = Instant::now();
+        }
+    }
+
+    /// Handles the spawn of the bot, returning `None`
+    /// if it has not spawned yet.
+    fn spawn(&mut self) -> Option<()> {
+        let packet = self.client.take::<PlayerPositionAndLookClientbound>(
+            PacketType::PlayerPositionAndLookClientbound,
+        )?;
+        self.position = Some((packet.x, packet.y, packet.z));
+
+        // Give the bot blocks to place.
+        self.client.send(CreativeInventoryAction::new(
+            SLOT_HOTBAR_OFFSET as i16,
+            Some(ItemStack::new(Item::Stone, 64)),
+        ));
+
+        Some(())
+    }
+
+    /// Checks for the response to the pending latency probe.
+    fn read_probe(&mut self, latencies: &mut Vec<Duration>) {
+        let sent = match self.probe_sent {
+            Some(sent) => sent,
+            None => return,
+        };
+
+        let responded = self
+            .client
+            .take_all(PacketType::ChatMessageClientbound)
+            .iter()
+            .any(|packet| {
+                cast_packet::<ChatMessageClientbound>(&**packet)
+                    .json_data
+                    .contains("TPS from last")
+            });
+        if responded {
+            latencies.push(sent.elapsed());
+            self.probe_sent = None;
+        }
+    }
+
+    /// Breaks the block beneath the bot, or places
+    /// a block on top of it.
+    fn interact(&mut self, server: &TestServer, rng: &mut StdRng, x: f64, y: f64, z: f64) {
+        let below = BlockPosition::new(x.floor() as i32, y as i32 - 1, z.floor() as i32);
+        // Interacting with blocks in unloaded
+        // chunks causes the bot to be kicked.
+        match server.block_at(below) {
+            None | Some(Block::Air) | Some(Block::Bedrock) => return,
+            Some(_) => (),
+        }
+
+        if rng.gen() {
+            self.client.break_block(below);
+        } else {
+            self.client.send(PlayerBlockPlacement::new(
+                below,
+                Face::Top,
+                0,
+                0.5,
+                1.0,
+                0.5,
+            ));
+        }
+    }
+}
+
+/// Returns the position of the highest non-air
+/// block in the given column, or `None` if it is
+/// not loaded.
+fn highest_block(server: &TestServer, x: f64, z: f64) -> Option<BlockPosition> {
+    let (x, z) = (x.floor() as i32, z.floor() as i32);
+    (0..256)
+        .rev()
+        .map(|y| BlockPosition::new(x, y, z))
+        .find(|pos| server.block_at(*pos) != Some(Block::Air))
+        .filter(|pos| server.block_at(*pos).is_some())
+}
@@ -0,0 +1,279 @@
+//! A minimal client connection over TCP.
+//!
+//! Packets are encoded and decoded with the server's own
+//! `MinecraftCodec`, with compression enabled if the server
+//! requests it. Only the packets inspected by bots are parsed;
+//! others are framed and decompressed, but not read, since many
+//! clientbound packets cannot be read yet.
+
+use bytes::BytesMut;
+use failure::Error;
+use feather_core::network::cast_packet;
+use feather_core::network::codec::MinecraftCodec;
+use feather_core::network::packet::implementation::{
+    ChatMessageServerbound, DisconnectLogin, Handshake, HandshakeState, KeepAliveClientbound,
+    KeepAliveServerbound, LoginStart, PlayerDigging, PlayerDiggingStatus, PlayerPosition,
+    SetCompression,
+};
+use feather_core::network::packet::{PacketDirection, PacketStage};
+use feather_core::{BlockPosition, Packet, PacketType};
+use feather_server::PROTOCOL_VERSION;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::codec::{Decoder, Encoder};
+
+/// The maximum time to wait for the
+/// server to accept and log in a client.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The packets whose data is read.
+const PARSED_PACKETS: &[PacketType] = &[
+    PacketType::SetCompression,
+    PacketType::LoginSuccess,
+    PacketType::DisconnectLogin,
+    PacketType::KeepAliveClientbound,
+    PacketType::PlayerPositionAndLookClientbound,
+    PacketType::ChatMessageClientbound,
+];
+
+/// A client which has logged in to a server.
+pub struct Connection {
+    stream: TcpStream,
+    codec: MinecraftCodec,
+    /// Data received but not yet decoded.
+    read_buf: BytesMut,
+    /// Encoded packets not yet written to the socket.
+    write_buf: BytesMut,
+    /// Packets received but not yet consumed.
+    received: Vec<Box<dyn Packet>>,
+    disconnected: bool,
+}
+
+impl Connection {
+    /// Connects to the server at the given address and logs
+    /// in with the given username, blocking until the login
+    /// has completed. The server must be in offline mode.
+    pub fn connect(address: SocketAddr, username: &str) -> Result<Self, Error> {
+        let stream = connect_with_retry(address)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(LOGIN_TIMEOUT))?;
+
+        let mut codec = MinecraftCodec::new(PacketDirection::Clientbound);
+        codec.parse_only(PARSED_PACKETS);
+
+        let mut connection = Self {
+            stream,
+            codec,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            received: vec![],
+            disconnected: false,
+        };
+
+        connection.send(Handshake::new(
+            PROTOCOL_VERSION,
+            address.ip().to_string(),
+            address.port(),
+            HandshakeState::Login,
+        ));
+        connection.codec.set_stage(PacketStage::Login);
+        connection.send(LoginStart::new(username.to_string()));
+        connection.login()?;
+
+        connection.stream.set_nonblocking(true)?;
+        Ok(connection)
+    }
+
+    /// Reads packets until the server has
+    /// sent the Login Success packet.
+    fn login(&mut self) -> Result<(), Error> {
+        loop {
+            let packet = match self.codec.decode(&mut self.read_buf)? {
+                Some(packet) => packet,
+                None => {
+                    if !self.read()? {
+                        failure::bail!("connection closed during login");
+                    }
+                    continue;
+                }
+            };
+
+            match packet.ty() {
+                PacketType::SetCompression => {
+                    let threshold = cast_packet::<SetCompression>(&*packet).threshold.0;
+                    self.codec.enable_compression(threshold as usize);
+                }
+                PacketType::LoginSuccess => {
+                    self.codec.set_stage(PacketStage::Play);
+                    return Ok(());
+                }
+                PacketType::DisconnectLogin => failure::bail!(
+                    "disconnected during login: {}",
+                    cast_packet::<DisconnectLogin>(&*packet).reason
+                ),
+                ty => failure::bail!("unexpected packet {:?} during login", ty),
+            }
+        }
+    }
+
+    /// Sends a packet to the server. Data which cannot be
+    /// written without blocking is sent by later calls.
+    pub fn send<P: Packet + 'static>(&mut self, packet: P) {
+        if self.disconnected {
+            return;
+        }
+        if let Err(e) = self.codec.encode(Box::new(packet), &mut self.write_buf) {
+            self.disconnect(&Error::from(e));
+            return;
+        }
+        if let Err(e) = self.write() {
+            self.disconnect(&e);
+        }
+    }
+
+    /// Moves the client to the given position.
+    pub fn move_to(&mut self, x: f64, y: f64, z: f64) {
+        self.send(PlayerPosition::new(x, y, z, true));
+    }
+
+    /// Breaks the block at the given position.
+    /// The client must be in creative mode.
+    pub fn break_block(&mut self, pos: BlockPosition) {
+        self.send(PlayerDigging::new(
+            PlayerDiggingStatus::StartedDigging,
+            pos,
+            0,
+        ));
+    }
+
+    /// Sends a chat message or, if the
+    /// message starts with `/`, a command.
+    pub fn chat(&mut self, message: &str) {
+        self.send(ChatMessageServerbound::new(message.to_string()));
+    }
+
+    /// Returns whether the connection has been closed.
+    pub fn is_disconnected(&mut self) -> bool {
+        self.poll();
+        self.disconnected
+    }
+
+    /// Removes and returns all received packets
+    /// of the given type, in the order they were
+    /// received.
+    pub fn take_all(&mut self, ty: PacketType) -> Vec<Box<dyn Packet>> {
+        self.poll();
+        let (matching, rest): (Vec<_>, Vec<_>) = self
+            .received
+            .drain(..)
+            .partition(|packet| packet.ty() == ty);
+        self.received = rest;
+        matching
+    }
+
+    /// Removes and returns the first received
+    /// packet of the given type.
+    pub fn take<P: Packet + Clone + 'static>(&mut self, ty: PacketType) -> Option<P> {
+        self.poll();
+        let index = self.received.iter().position(|packet| packet.ty() == ty)?;
+        let packet = self.received.remove(index);
+        Some(cast_packet::<P>(&*packet).clone())
+    }
+
+    /// Discards all received packets.
+    pub fn clear(&mut self) {
+        self.poll();
+        self.received.clear();
+    }
+
+    /// Reads and decodes packets sent by the server,
+    /// answering keep-alives as a client would.
+    fn poll(&mut self) {
+        if self.disconnected {
+            return;
+        }
+
+        if let Err(e) = self.receive() {
+            self.disconnect(&e);
+        }
+    }
+
+    fn receive(&mut self) -> Result<(), Error> {
+        // Read all data which is available without blocking.
+        loop {
+            match self.read() {
+                Ok(true) => (),
+                Ok(false) => {
+                    self.disconnected = true;
+                    break;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        while let Some(packet) = self.codec.decode(&mut self.read_buf)? {
+            if packet.ty() == PacketType::KeepAliveClientbound {
+                let id = cast_packet::<KeepAliveClientbound>(&*packet).keep_alive_id;
+                let response = KeepAliveServerbound::new(id as i64);
+                self.codec.encode(Box::new(response), &mut self.write_buf)?;
+            } else {
+                self.received.push(packet);
+            }
+        }
+
+        if self.disconnected {
+            return Ok(());
+        }
+        self.write()
+    }
+
+    /// Reads data from the socket into `read_buf`,
+    /// returning `false` if the connection was closed.
+    fn read(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 8192];
+        let n = self.stream.read(&mut buf)?;
+        self.read_buf.extend_from_slice(&buf[..n]);
+        Ok(n > 0)
+    }
+
+    /// Writes as much of `write_buf` as
+    /// possible without blocking.
+    fn write(&mut self) -> Result<(), Error> {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => failure::bail!("connection closed"),
+                Ok(n) => self.write_buf.advance(n),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, error: &Error) {
+        warn!("Bot disconnected: {}", error);
+        self.disconnected = true;
+    }
+}
+
+/// Connects to the given address, retrying until the
+/// server's listener has started or `LOGIN_TIMEOUT`
+/// has elapsed.
+fn connect_with_retry(address: SocketAddr) -> Result<TcpStream, Error> {
+    let start = Instant::now();
+    loop {
+        match TcpStream::connect(address) {
+            Ok(stream) => return Ok(stream),
+            Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => {
+                if start.elapsed() > LOGIN_TIMEOUT {
+                    failure::bail!("could not connect to {}", address);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
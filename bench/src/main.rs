@@ -0,0 +1,141 @@
+//! Load-testing tool for Feather.
+//!
+//! Runs a server in-process at the normal tick rate and connects
+//! scripted bots to it over TCP, which walk around and break and
+//! place blocks.
+//! TPS, tick durations, command latency, memory usage and chunk
+//! counts are reported at a fixed interval, optionally to a CSV file,
+//! so that performance can be compared between builds. Bots connect
+//! gradually, so each run also shows how the server degrades as the
+//! number of players grows.
+//!
+//! Latency is measured as the time between a bot sending `/tps`
+//! and receiving the response. This covers encoding, compressing and
+//! writing the packets on both sides, the server's IO workers, the
+//! time until the next tick handles the command, and flushing the
+//! response at the end of that tick. Since packets are only handled
+//! during ticks, this increases when ticks take too long. Connections
+//! use the loopback interface and the server runs in offline mode, so
+//! network latency and encryption are not included.
+
+#[macro_use]
+extern crate clap;
+#[macro_use]
+extern crate log;
+
+mod bot;
+mod connection;
+mod report;
+
+use bot::Bot;
+use clap::{App, ArgMatches};
+use connection::Connection;
+use failure::Error;
+use feather_integration::TestServer;
+use feather_server::config::Config;
+use feather_server::timings::TIMINGS;
+use feather_server::TICK_TIME;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use report::{Reporter, Window};
+use std::fs::File;
+use std::process::exit;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[tokio::main]
+async fn main() {
+    simple_logger::init_with_level(log::Level::Warn).unwrap();
+
+    if let Err(e) = run() {
+        error!("An error occurred: {}", e);
+        exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml).get_matches();
+
+    let bot_count: usize = arg(&matches, "bots")?;
+    let join_rate: f64 = arg(&matches, "join-rate")?;
+    let duration = Duration::from_secs(arg(&matches, "duration")?);
+    let interval = Duration::from_secs(arg(&matches, "interval")?);
+    let radius: f64 = arg(&matches, "radius")?;
+    let seed: u64 = arg(&matches, "seed")?;
+
+    let mut config = Config::default();
+    config.server.view_distance = arg(&matches, "view-distance")?;
+    config.world.generator = matches.value_of("generator").unwrap().to_string();
+    config.world.seed = seed.to_string();
+
+    let csv = match matches.value_of("output") {
+        Some(path) => Some(File::create(path)?),
+        None => None,
+    };
+
+    println!(
+        "Running {} bots for {}s on a new '{}' world",
+        bot_count,
+        duration.as_secs(),
+        config.world.generator
+    );
+
+    let (mut server, address) = TestServer::listen(config);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reporter = Reporter::new(csv)?;
+    let mut bots: Vec<Bot> = vec![];
+
+    let start = Instant::now();
+    let mut window = Window::new();
+    while start.elapsed() < duration {
+        let tick_start = Instant::now();
+
+        let due = ((start.elapsed().as_secs_f64() * join_rate) as usize + 1).min(bot_count);
+        while bots.len() < due {
+            let client = Connection::connect(address, &format!("bot{}", bots.len()))?;
+            bots.push(Bot::new(client));
+        }
+
+        for bot in &mut bots {
+            bot.tick(&server, &mut rng, radius, &mut window.latencies);
+        }
+
+        TIMINGS.lock().record_tick_start(tick_start);
+        let server_start = Instant::now();
+        server.tick();
+        window.tick_durations.push(server_start.elapsed());
+
+        if window.elapsed() >= interval {
+            let spawned = bots.iter().filter(|bot| bot.has_spawned()).count();
+            reporter.write(&window.finish(start.elapsed(), spawned))?;
+            window = Window::new();
+        }
+
+        // Run at the normal tick rate, starting the
+        // next tick immediately if running behind.
+        let elapsed = tick_start.elapsed();
+        let tick_time = Duration::from_millis(TICK_TIME);
+        if elapsed < tick_time {
+            std::thread::sleep(tick_time - elapsed);
+        }
+    }
+
+    println!();
+    for line in TIMINGS.lock().report() {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn arg<T>(matches: &ArgMatches, name: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|e| failure::format_err!("invalid value '{}' for {}: {}", value, name, e))
+}
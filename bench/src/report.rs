@@ -0,0 +1,185 @@
+//! Collection and output of performance samples.
+
+use feather_server::metrics::METRICS;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Measurements collected over one report interval.
+pub struct Window {
+    start: Instant,
+    pub tick_durations: Vec<Duration>,
+    pub latencies: Vec<Duration>,
+}
+
+impl Window {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            tick_durations: vec![],
+            latencies: vec![],
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Summarizes the window into a row of the report.
+    pub fn finish(&self, time: Duration, bots: usize) -> Row {
+        let mut ticks = self.tick_durations.clone();
+        ticks.sort();
+
+        Row {
+            time: time.as_secs_f64(),
+            bots,
+            tps: ticks.len() as f64 / self.elapsed().as_secs_f64(),
+            tick_mean: millis(mean(&ticks)),
+            tick_p99: millis(percentile(&ticks, 0.99)),
+            tick_max: millis(ticks.last().copied().unwrap_or_default()),
+            latency_mean: millis(mean(&self.latencies)),
+            latency_max: millis(self.latencies.iter().max().copied().unwrap_or_default()),
+            memory: resident_memory().map(|bytes| bytes as f64 / (1024.0 * 1024.0)),
+            loaded_chunks: METRICS.loaded_chunks.load(Ordering::Relaxed),
+            entities: METRICS.entities.load(Ordering::Relaxed),
+            pending_chunk_loads: METRICS.pending_chunk_loads.load(Ordering::Relaxed),
+            lighting_updates: METRICS.lighting_updates.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One row of the report. Durations are in
+/// milliseconds and memory is in megabytes.
+pub struct Row {
+    pub time: f64,
+    pub bots: usize,
+    pub tps: f64,
+    pub tick_mean: f64,
+    pub tick_p99: f64,
+    pub tick_max: f64,
+    pub latency_mean: f64,
+    pub latency_max: f64,
+    pub memory: Option<f64>,
+    pub loaded_chunks: u64,
+    pub entities: u64,
+    pub pending_chunk_loads: u64,
+    pub lighting_updates: u64,
+}
+
+const COLUMNS: [&str; 13] = [
+    "time_s",
+    "bots",
+    "tps",
+    "tick_mean_ms",
+    "tick_p99_ms",
+    "tick_max_ms",
+    "latency_mean_ms",
+    "latency_max_ms",
+    "memory_mb",
+    "loaded_chunks",
+    "entities",
+    "pending_chunk_loads",
+    "lighting_updates",
+];
+
+impl Row {
+    fn values(&self) -> Vec<String> {
+        vec![
+            format!("{:.0}", self.time),
+            self.bots.to_string(),
+            format!("{:.1}", self.tps),
+            format!("{:.2}", self.tick_mean),
+            format!("{:.2}", self.tick_p99),
+            format!("{:.2}", self.tick_max),
+            format!("{:.2}", self.latency_mean),
+            format!("{:.2}", self.latency_max),
+            self.memory
+                .map_or_else(|| String::from("-"), |memory| format!("{:.1}", memory)),
+            self.loaded_chunks.to_string(),
+            self.entities.to_string(),
+            self.pending_chunk_loads.to_string(),
+            self.lighting_updates.to_string(),
+        ]
+    }
+}
+
+/// Writes rows to standard output and,
+/// optionally, to a CSV file.
+pub struct Reporter {
+    csv: Option<File>,
+}
+
+impl Reporter {
+    pub fn new(csv: Option<File>) -> io::Result<Self> {
+        let mut reporter = Self { csv };
+
+        println!("{}", table_line(&COLUMNS));
+        if let Some(csv) = &mut reporter.csv {
+            writeln!(csv, "{}", COLUMNS.join(","))?;
+        }
+
+        Ok(reporter)
+    }
+
+    pub fn write(&mut self, row: &Row) -> io::Result<()> {
+        let values = row.values();
+
+        println!("{}", table_line(&values));
+        if let Some(csv) = &mut self.csv {
+            writeln!(csv, "{}", values.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn table_line<S: AsRef<str>>(values: &[S]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{:>10}", value.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn mean(durations: &[Duration]) -> Duration {
+    if durations.is_empty() {
+        return Duration::default();
+    }
+    durations.iter().sum::<Duration>() / durations.len() as u32
+}
+
+/// Returns the given percentile of sorted durations.
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::default();
+    }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+/// Returns the resident memory of the process in bytes.
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    // The second field of statm is the resident set size in pages.
+    const PAGE_SIZE: u64 = 4096;
+
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * PAGE_SIZE)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+    None
+}
@@ -35,6 +35,7 @@ extern crate num_derive;
 #[allow(clippy::all)] // No, generated code isn't idiomatic. Too bad
 mod blocks;
 mod mappings;
+pub mod properties;
 
 use crate::mappings::NativeMappings;
 pub use blocks::*;
@@ -72,8 +73,20 @@ pub trait BlockExt {
     /// light will be stopped by this block.
     fn is_opaque(&self) -> bool;
 
+    /// Returns the number of light levels absorbed by this
+    /// block, in addition to the level lost for each block
+    /// light travels. Opaque blocks absorb all light.
+    fn opacity(&self) -> u8;
+
     /// Returns the light level emitted by this block.
-    fn light_emission(&self) -> u8;
+    fn emitted_light(&self) -> u8;
+
+    /// Returns whether this block is a fluid, i.e.
+    /// water or lava.
+    fn is_fluid(&self) -> bool;
+
+    /// Returns the base color of this block on maps.
+    fn map_color(&self) -> u8;
 }
 
 impl BlockExt for Block {
@@ -104,154 +117,27 @@ impl BlockExt for Block {
     }
 
     fn is_solid(&self) -> bool {
-        // TODO: there are likely a few missing in this list
-        match self {
-            Block::Air
-            | Block::OakSapling(_)
-            | Block::SpruceSapling(_)
-            | Block::BirchSapling(_)
-            | Block::JungleSapling(_)
-            | Block::AcaciaSapling(_)
-            | Block::DarkOakSapling(_)
-            | Block::Water(_)
-            | Block::Lava(_)
-            | Block::Grass
-            | Block::Fern
-            | Block::DeadBush
-            | Block::Seagrass
-            | Block::TallSeagrass(_)
-            | Block::Dandelion
-            | Block::Poppy
-            | Block::BlueOrchid
-            | Block::Allium
-            | Block::AzureBluet
-            | Block::RedTulip
-            | Block::OrangeTulip
-            | Block::WhiteTulip
-            | Block::PinkTulip
-            | Block::OxeyeDaisy
-            | Block::BrownMushroom
-            | Block::RedMushroom
-            | Block::Torch
-            | Block::WallTorch(_)
-            | Block::Fire(_)
-            | Block::Wheat(_)
-            | Block::Sign(_)
-            | Block::Ladder(_)
-            | Block::Rail(_)
-            | Block::WallSign(_)
-            | Block::Lever(_)
-            | Block::StonePressurePlate(_)
-            | Block::OakPressurePlate(_)
-            | Block::SprucePressurePlate(_)
-            | Block::BirchPressurePlate(_)
-            | Block::JunglePressurePlate(_)
-            | Block::AcaciaPressurePlate(_)
-            | Block::DarkOakPressurePlate(_)
-            | Block::RedstoneTorch(_)
-            | Block::RedstoneWallTorch(_)
-            | Block::StoneButton(_)
-            | Block::Snow(_)
-            | Block::SugarCane(_)
-            | Block::Repeater(_)
-            | Block::AttachedMelonStem(_)
-            | Block::AttachedPumpkinStem(_)
-            | Block::MelonStem(_)
-            | Block::PumpkinStem(_)
-            | Block::Vine(_)
-            | Block::Carrots(_)
-            | Block::Potatoes(_)
-            | Block::OakButton(_)
-            | Block::SpruceButton(_)
-            | Block::BirchButton(_)
-            | Block::JungleButton(_)
-            | Block::AcaciaButton(_)
-            | Block::DarkOakButton(_)
-            | Block::LightWeightedPressurePlate(_)
-            | Block::HeavyWeightedPressurePlate(_)
-            | Block::Comparator(_)
-            | Block::WhiteCarpet
-            | Block::OrangeCarpet
-            | Block::MagentaCarpet
-            | Block::LightBlueCarpet
-            | Block::YellowCarpet
-            | Block::LimeCarpet
-            | Block::PinkCarpet
-            | Block::GrayCarpet
-            | Block::LightGrayCarpet
-            | Block::CyanCarpet
-            | Block::PurpleCarpet
-            | Block::BlueCarpet
-            | Block::BrownCarpet
-            | Block::GreenCarpet
-            | Block::RedCarpet
-            | Block::BlackCarpet
-            | Block::Sunflower(_)
-            | Block::Lilac(_)
-            | Block::RoseBush(_)
-            | Block::Peony(_)
-            | Block::TallGrass(_)
-            | Block::LargeFern(_)
-            | Block::Kelp(_)
-            | Block::KelpPlant
-            | Block::DriedKelpBlock
-            | Block::VoidAir
-            | Block::CaveAir => false,
-            _ => true,
-        }
+        properties::is_solid(self.native_state_id())
     }
 
     fn is_opaque(&self) -> bool {
-        if !self.is_solid() {
-            return false;
-        }
+        properties::is_opaque(self.native_state_id())
+    }
 
-        // TODO
-        match self {
-            Block::Air | Block::Glass | Block::GlassPane(_) | Block::IronBars(_) => false,
-            _ => true,
-        }
+    fn opacity(&self) -> u8 {
+        properties::opacity(self.native_state_id())
     }
 
-    fn light_emission(&self) -> u8 {
-        match self {
-            Block::Beacon
-            | Block::EndGateway
-            | Block::EndPortal
-            | Block::Fire(_)
-            | Block::Glowstone
-            | Block::JackOLantern(_)
-            | Block::Lava(_)
-            | Block::RedstoneLamp(RedstoneLampData { lit: true })
-            | Block::SeaLantern
-            | Block::SeaPickle(SeaPickleData {
-                waterlogged: true,
-                pickles: 4,
-            })
-            | Block::Conduit(_) => 15,
-            Block::EndRod(_) | Block::Torch => 14,
-            Block::Furnace(_) => 13,
-            Block::SeaPickle(SeaPickleData {
-                waterlogged: true,
-                pickles: 3,
-            }) => 12,
-            Block::NetherPortal(_) => 11,
-            Block::SeaPickle(SeaPickleData {
-                waterlogged: true,
-                pickles: 2,
-            }) => 9,
-            Block::EnderChest(_) | Block::RedstoneTorch(_) => 7,
-            Block::SeaPickle(SeaPickleData {
-                waterlogged: true,
-                pickles: 1,
-            }) => 6,
-            Block::MagmaBlock => 3,
-            Block::BrewingStand(_)
-            | Block::BrownMushroom
-            | Block::DragonEgg
-            | Block::EndPortalFrame(_) => 1,
-            _ => 0,
-        }
+    fn emitted_light(&self) -> u8 {
+        properties::emitted_light(self.native_state_id())
+    }
+
+    fn is_fluid(&self) -> bool {
+        properties::is_fluid(self.native_state_id())
+    }
+
+    fn map_color(&self) -> u8 {
+        properties::map_color(self.native_state_id())
     }
 }
 
@@ -0,0 +1,723 @@
+//! Lookup tables for frequently queried block properties.
+//!
+//! Lighting and physics query the properties of blocks in
+//! their inner loops. Rather than matching on the `Block` enum
+//! each time, the properties of every block state are computed
+//! once and stored in tables indexed by native state ID: bitsets
+//! for boolean properties and byte arrays for emitted light,
+//! opacity and map colors.
+//!
+//! The functions in this module take native state IDs, so they
+//! can be used directly on chunk data without converting
+//! to a `Block` first. `BlockExt` uses these tables as well.
+
+use crate::{Block, BlockExt, NATIVE_TO_INTERNAL};
+
+/// The opacity of blocks which stop light.
+pub const MAX_OPACITY: u8 = 15;
+
+lazy_static! {
+    static ref PROPERTIES: BlockProperties = BlockProperties::compute();
+}
+
+/// Returns whether the block with the given native state ID is solid.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn is_solid(state_id: u16) -> bool {
+    PROPERTIES.solid.get(state_id)
+}
+
+/// Returns whether the block with the given native state ID is opaque.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn is_opaque(state_id: u16) -> bool {
+    PROPERTIES.opaque.get(state_id)
+}
+
+/// Returns the number of light levels absorbed by the block with
+/// the given native state ID, in addition to the level lost for
+/// each block travelled. Opaque blocks have an opacity of
+/// `MAX_OPACITY`.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn opacity(state_id: u16) -> u8 {
+    PROPERTIES.opacity[state_id as usize]
+}
+
+/// Returns whether the block with the given native state ID is a fluid.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn is_fluid(state_id: u16) -> bool {
+    PROPERTIES.fluid.get(state_id)
+}
+
+/// Returns the light level emitted by the block
+/// with the given native state ID.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn emitted_light(state_id: u16) -> u8 {
+    PROPERTIES.emitted_light[state_id as usize]
+}
+
+/// Returns the base map color of the block with the
+/// given native state ID, from 0 (transparent) to 51.
+///
+/// Map pixels store the base color multiplied by
+/// four plus a shade from 0 to 3.
+///
+/// # Panics
+/// Panics if the state ID is invalid.
+pub fn map_color(state_id: u16) -> u8 {
+    PROPERTIES.map_color[state_id as usize]
+}
+
+/// A fixed-size set of state IDs.
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet(vec![0; (len + 63) / 64])
+    }
+
+    fn get(&self, index: u16) -> bool {
+        let index = index as usize;
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set(&mut self, index: u16, value: bool) {
+        let index = index as usize;
+        if value {
+            self.0[index / 64] |= 1 << (index % 64);
+        } else {
+            self.0[index / 64] &= !(1 << (index % 64));
+        }
+    }
+}
+
+/// The properties of every block state.
+struct BlockProperties {
+    solid: BitSet,
+    opaque: BitSet,
+    fluid: BitSet,
+    opacity: Vec<u8>,
+    emitted_light: Vec<u8>,
+    map_color: Vec<u8>,
+}
+
+impl BlockProperties {
+    fn compute() -> Self {
+        let len = NATIVE_TO_INTERNAL.len();
+        let mut properties = Self {
+            solid: BitSet::new(len),
+            opaque: BitSet::new(len),
+            fluid: BitSet::new(len),
+            opacity: vec![0; len],
+            emitted_light: vec![0; len],
+            map_color: vec![0; len],
+        };
+
+        for state_id in 0..len as u16 {
+            let block = Block::from_native_state_id(state_id).unwrap();
+            properties.solid.set(state_id, compute_solid(block));
+            properties.opaque.set(state_id, compute_opaque(block));
+            properties.fluid.set(state_id, compute_fluid(block));
+            properties.opacity[state_id as usize] = compute_opacity(block);
+            properties.emitted_light[state_id as usize] = compute_emitted_light(block);
+            properties.map_color[state_id as usize] = compute_map_color(block);
+        }
+
+        properties
+    }
+}
+
+/// Returns whether the given block is solid.
+fn compute_solid(block: Block) -> bool {
+    // TODO: there are likely a few missing in this list
+    match block {
+        Block::Air
+        | Block::OakSapling(_)
+        | Block::SpruceSapling(_)
+        | Block::BirchSapling(_)
+        | Block::JungleSapling(_)
+        | Block::AcaciaSapling(_)
+        | Block::DarkOakSapling(_)
+        | Block::Water(_)
+        | Block::Lava(_)
+        | Block::Grass
+        | Block::Fern
+        | Block::DeadBush
+        | Block::Seagrass
+        | Block::TallSeagrass(_)
+        | Block::Dandelion
+        | Block::Poppy
+        | Block::BlueOrchid
+        | Block::Allium
+        | Block::AzureBluet
+        | Block::RedTulip
+        | Block::OrangeTulip
+        | Block::WhiteTulip
+        | Block::PinkTulip
+        | Block::OxeyeDaisy
+        | Block::BrownMushroom
+        | Block::RedMushroom
+        | Block::Torch
+        | Block::WallTorch(_)
+        | Block::Fire(_)
+        | Block::Wheat(_)
+        | Block::Sign(_)
+        | Block::Ladder(_)
+        | Block::Rail(_)
+        | Block::WallSign(_)
+        | Block::Lever(_)
+        | Block::StonePressurePlate(_)
+        | Block::OakPressurePlate(_)
+        | Block::SprucePressurePlate(_)
+        | Block::BirchPressurePlate(_)
+        | Block::JunglePressurePlate(_)
+        | Block::AcaciaPressurePlate(_)
+        | Block::DarkOakPressurePlate(_)
+        | Block::RedstoneTorch(_)
+        | Block::RedstoneWallTorch(_)
+        | Block::StoneButton(_)
+        | Block::Snow(_)
+        | Block::SugarCane(_)
+        | Block::Repeater(_)
+        | Block::AttachedMelonStem(_)
+        | Block::AttachedPumpkinStem(_)
+        | Block::MelonStem(_)
+        | Block::PumpkinStem(_)
+        | Block::Vine(_)
+        | Block::Carrots(_)
+        | Block::Potatoes(_)
+        | Block::OakButton(_)
+        | Block::SpruceButton(_)
+        | Block::BirchButton(_)
+        | Block::JungleButton(_)
+        | Block::AcaciaButton(_)
+        | Block::DarkOakButton(_)
+        | Block::LightWeightedPressurePlate(_)
+        | Block::HeavyWeightedPressurePlate(_)
+        | Block::Comparator(_)
+        | Block::WhiteCarpet
+        | Block::OrangeCarpet
+        | Block::MagentaCarpet
+        | Block::LightBlueCarpet
+        | Block::YellowCarpet
+        | Block::LimeCarpet
+        | Block::PinkCarpet
+        | Block::GrayCarpet
+        | Block::LightGrayCarpet
+        | Block::CyanCarpet
+        | Block::PurpleCarpet
+        | Block::BlueCarpet
+        | Block::BrownCarpet
+        | Block::GreenCarpet
+        | Block::RedCarpet
+        | Block::BlackCarpet
+        | Block::Sunflower(_)
+        | Block::Lilac(_)
+        | Block::RoseBush(_)
+        | Block::Peony(_)
+        | Block::TallGrass(_)
+        | Block::LargeFern(_)
+        | Block::Kelp(_)
+        | Block::KelpPlant
+        | Block::DriedKelpBlock
+        | Block::VoidAir
+        | Block::CaveAir => false,
+        _ => true,
+    }
+}
+
+/// Returns whether the given block is opaque.
+fn compute_opaque(block: Block) -> bool {
+    compute_opacity(block) == MAX_OPACITY
+}
+
+/// Returns the number of light levels absorbed by the given block.
+fn compute_opacity(block: Block) -> u8 {
+    let (name, props) = block.to_name_and_props();
+    let has_prop = |key: &str, expected: &str| {
+        props
+            .iter()
+            .any(|(prop, value)| *prop == key && value == expected)
+    };
+    // Waterlogged blocks absorb light like water.
+    let waterlogged = has_prop("waterlogged", "true");
+
+    // Slabs and stairs only stop light if they fill the whole block.
+    if name.ends_with("_slab") || name.ends_with("_stairs") {
+        return if has_prop("type", "double") {
+            MAX_OPACITY
+        } else if waterlogged {
+            2
+        } else {
+            0
+        };
+    }
+
+    match block {
+        Block::Ice | Block::FrostedIce(_) => return 2,
+        Block::OakLeaves(_)
+        | Block::SpruceLeaves(_)
+        | Block::BirchLeaves(_)
+        | Block::JungleLeaves(_)
+        | Block::AcaciaLeaves(_)
+        | Block::DarkOakLeaves(_)
+        | Block::Cobweb => return 1,
+        _ => (),
+    }
+    if compute_fluid(block) || waterlogged {
+        return 2;
+    }
+    if !compute_solid(block) {
+        return 0;
+    }
+
+    // TODO
+    match block {
+        Block::Air | Block::Glass | Block::GlassPane(_) | Block::IronBars(_) => 0,
+        _ => MAX_OPACITY,
+    }
+}
+
+/// The light levels emitted by blocks, as in vanilla. Blocks
+/// with a `lit` property only emit light while they are lit.
+const EMITTERS: &[(&str, u8)] = &[
+    ("minecraft:beacon", 15),
+    ("minecraft:conduit", 15),
+    ("minecraft:end_gateway", 15),
+    ("minecraft:end_portal", 15),
+    ("minecraft:fire", 15),
+    ("minecraft:glowstone", 15),
+    ("minecraft:jack_o_lantern", 15),
+    ("minecraft:lava", 15),
+    ("minecraft:redstone_lamp", 15),
+    ("minecraft:sea_lantern", 15),
+    ("minecraft:end_rod", 14),
+    ("minecraft:torch", 14),
+    ("minecraft:wall_torch", 14),
+    ("minecraft:furnace", 13),
+    ("minecraft:nether_portal", 11),
+    ("minecraft:redstone_ore", 9),
+    ("minecraft:ender_chest", 7),
+    ("minecraft:redstone_torch", 7),
+    ("minecraft:redstone_wall_torch", 7),
+    ("minecraft:magma_block", 3),
+    ("minecraft:brewing_stand", 1),
+    ("minecraft:brown_mushroom", 1),
+    ("minecraft:dragon_egg", 1),
+    ("minecraft:end_portal_frame", 1),
+];
+
+/// Returns the light level emitted by the given block.
+fn compute_emitted_light(block: Block) -> u8 {
+    let (name, props) = block.to_name_and_props();
+    let prop = |key: &str| {
+        props
+            .iter()
+            .find(|(prop, _)| *prop == key)
+            .map(|(_, value)| value.as_str())
+    };
+
+    // Sea pickles glow brighter the more there are,
+    // but only under water.
+    if name == "minecraft:sea_pickle" {
+        if prop("waterlogged") != Some("true") {
+            return 0;
+        }
+        let pickles: u8 = prop("pickles").and_then(|p| p.parse().ok()).unwrap_or(1);
+        return 3 + 3 * pickles;
+    }
+
+    if prop("lit") == Some("false") {
+        return 0;
+    }
+    EMITTERS
+        .iter()
+        .find(|(emitter, _)| *emitter == name)
+        .map_or(0, |(_, level)| *level)
+}
+
+/// Returns whether the given block is a fluid.
+fn compute_fluid(block: Block) -> bool {
+    match block {
+        Block::Water(_) | Block::Lava(_) => true,
+        _ => false,
+    }
+}
+
+/// Base map colors.
+mod color {
+    pub const AIR: u8 = 0;
+    pub const GRASS: u8 = 1;
+    pub const SAND: u8 = 2;
+    pub const WOOL: u8 = 3;
+    pub const FIRE: u8 = 4;
+    pub const ICE: u8 = 5;
+    pub const METAL: u8 = 6;
+    pub const PLANT: u8 = 7;
+    pub const SNOW: u8 = 8;
+    pub const CLAY: u8 = 9;
+    pub const DIRT: u8 = 10;
+    pub const STONE: u8 = 11;
+    pub const WATER: u8 = 12;
+    pub const WOOD: u8 = 13;
+    pub const QUARTZ: u8 = 14;
+    pub const ORANGE: u8 = 15;
+    pub const MAGENTA: u8 = 16;
+    pub const YELLOW: u8 = 18;
+    pub const LIME: u8 = 19;
+    pub const PINK: u8 = 20;
+    pub const GRAY: u8 = 21;
+    pub const CYAN: u8 = 23;
+    pub const PURPLE: u8 = 24;
+    pub const BLUE: u8 = 25;
+    pub const BROWN: u8 = 26;
+    pub const GREEN: u8 = 27;
+    pub const RED: u8 = 28;
+    pub const BLACK: u8 = 29;
+    pub const GOLD: u8 = 30;
+    pub const DIAMOND: u8 = 31;
+    pub const LAPIS: u8 = 32;
+    pub const EMERALD: u8 = 33;
+    pub const PODZOL: u8 = 34;
+    pub const NETHER: u8 = 35;
+    /// The first of the sixteen terracotta colors,
+    /// which are in the same order as the dye colors.
+    pub const WHITE_TERRACOTTA: u8 = 36;
+}
+
+/// The dye colors in order, and their base map colors.
+const DYES: [(&str, u8); 16] = [
+    ("white_", 8),
+    ("orange_", 15),
+    ("magenta_", 16),
+    ("light_blue_", 17),
+    ("yellow_", 18),
+    ("lime_", 19),
+    ("pink_", 20),
+    ("gray_", 21),
+    ("light_gray_", 22),
+    ("cyan_", 23),
+    ("purple_", 24),
+    ("blue_", 25),
+    ("brown_", 26),
+    ("green_", 27),
+    ("red_", 28),
+    ("black_", 29),
+];
+
+/// Blocks which come in each dye color.
+const DYED_BLOCKS: &[&str] = &[
+    "wool",
+    "carpet",
+    "concrete",
+    "concrete_powder",
+    "stained_glass",
+    "stained_glass_pane",
+    "terracotta",
+    "glazed_terracotta",
+    "shulker_box",
+    "bed",
+    "banner",
+    "wall_banner",
+];
+
+/// Returns the base map color of the given block.
+fn compute_map_color(block: Block) -> u8 {
+    use color::*;
+
+    let (name, _) = block.to_name_and_props();
+    let name = name.trim_start_matches("minecraft:");
+
+    for (i, (prefix, dye_color)) in DYES.iter().enumerate() {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        match &name[prefix.len()..] {
+            "terracotta" => return WHITE_TERRACOTTA + i as u8,
+            rest if DYED_BLOCKS.contains(&rest) => return *dye_color,
+            _ => (),
+        }
+    }
+
+    if name.ends_with("_leaves") || name.ends_with("_sapling") {
+        return PLANT;
+    }
+    if name.starts_with("potted_") || name.ends_with("_button") {
+        return AIR;
+    }
+    if name.starts_with("dead_") && name.contains("coral") {
+        return GRAY;
+    }
+    if name.contains("coral") {
+        return match name.split('_').next() {
+            Some("tube") => BLUE,
+            Some("brain") => PINK,
+            Some("bubble") => PURPLE,
+            Some("fire") => RED,
+            _ => YELLOW,
+        };
+    }
+
+    let name = name.trim_start_matches("stripped_");
+    let woods = [
+        ("dark_oak_", BROWN),
+        ("oak_", WOOD),
+        ("spruce_", PODZOL),
+        ("birch_", SAND),
+        ("jungle_", DIRT),
+        ("acacia_", ORANGE),
+    ];
+    if let Some((_, wood_color)) = woods.iter().find(|(prefix, _)| name.starts_with(prefix)) {
+        return *wood_color;
+    }
+
+    match name {
+        "grass_block" | "slime_block" => return GRASS,
+        "sand" | "glowstone" | "bone_block" | "turtle_egg" => return SAND,
+        "cobweb" | "mushroom_stem" => return WOOL,
+        "lava" | "tnt" | "fire" | "redstone_block" => return FIRE,
+        "ice" | "packed_ice" | "frosted_ice" | "blue_ice" => return ICE,
+        "iron_block"
+        | "iron_door"
+        | "iron_trapdoor"
+        | "iron_bars"
+        | "anvil"
+        | "chipped_anvil"
+        | "damaged_anvil"
+        | "brewing_stand"
+        | "heavy_weighted_pressure_plate" => return METAL,
+        "grass"
+        | "fern"
+        | "tall_grass"
+        | "large_fern"
+        | "vine"
+        | "lily_pad"
+        | "wheat"
+        | "carrots"
+        | "potatoes"
+        | "beetroots"
+        | "melon_stem"
+        | "pumpkin_stem"
+        | "attached_melon_stem"
+        | "attached_pumpkin_stem"
+        | "sugar_cane"
+        | "cactus"
+        | "dandelion"
+        | "poppy"
+        | "blue_orchid"
+        | "allium"
+        | "azure_bluet"
+        | "red_tulip"
+        | "orange_tulip"
+        | "white_tulip"
+        | "pink_tulip"
+        | "oxeye_daisy"
+        | "sunflower"
+        | "lilac"
+        | "rose_bush"
+        | "peony"
+        | "brown_mushroom"
+        | "red_mushroom" => return PLANT,
+        "snow" | "snow_block" => return SNOW,
+        "clay" => return CLAY,
+        "dirt" | "coarse_dirt" | "farmland" | "grass_path" | "granite" | "polished_granite" => {
+            return DIRT
+        }
+        "water" | "seagrass" | "tall_seagrass" | "kelp" | "kelp_plant" | "bubble_column" => {
+            return WATER
+        }
+        "crafting_table" | "bookshelf" | "chest" | "trapped_chest" | "note_block" | "jukebox"
+        | "dead_bush" | "sign" | "wall_sign" | "daylight_detector" | "petrified_oak_slab" => {
+            return WOOD
+        }
+        "diorite" | "polished_diorite" | "sea_lantern" => return QUARTZ,
+        "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "red_sand" | "terracotta" => {
+            return ORANGE
+        }
+        "hay_block" | "sponge" | "wet_sponge" => return YELLOW,
+        "melon" => return LIME,
+        "mycelium" | "chorus_plant" | "chorus_flower" | "shulker_box" => return PURPLE,
+        "soul_sand" | "brown_mushroom_block" | "dried_kelp_block" => return BROWN,
+        "end_portal_frame" => return GREEN,
+        "red_mushroom_block" | "nether_wart" | "nether_wart_block" | "enchanting_table" => {
+            return RED
+        }
+        "obsidian" | "coal_block" | "dragon_egg" | "end_portal" | "end_gateway" => return BLACK,
+        "gold_block" | "light_weighted_pressure_plate" => return GOLD,
+        "diamond_block" | "beacon" | "conduit" => return DIAMOND,
+        "lapis_block" => return LAPIS,
+        "emerald_block" => return EMERALD,
+        "podzol" => return PODZOL,
+        "netherrack" | "magma_block" | "nether_quartz_ore" => return NETHER,
+        "glass" | "glass_pane" | "barrier" => return AIR,
+        _ => (),
+    }
+
+    // Families of building blocks, such as stairs and slabs
+    if name.contains("red_sandstone") {
+        ORANGE
+    } else if name.contains("sandstone") || name.starts_with("end_stone") {
+        SAND
+    } else if name.contains("quartz") {
+        QUARTZ
+    } else if name.contains("prismarine_brick") || name.contains("dark_prismarine") {
+        DIAMOND
+    } else if name.contains("prismarine") {
+        CYAN
+    } else if name.contains("purpur") {
+        MAGENTA
+    } else if name.contains("nether_brick") {
+        NETHER
+    } else if name.contains("brick") && !name.contains("stone_brick") {
+        RED
+    } else if compute_solid(block) {
+        STONE
+    } else {
+        AIR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FurnaceData, FurnaceFacing, LavaData, OakLeavesData, OakSlabData, OakSlabType,
+        OakStairsData, OakStairsFacing, OakStairsHalf, OakStairsShape, RedstoneLampData,
+        RedstoneTorchData, RedstoneWallTorchData, RedstoneWallTorchFacing, SeaPickleData,
+        WaterData,
+    };
+
+    #[test]
+    fn test_properties() {
+        let stone = Block::Stone.native_state_id();
+        assert!(is_solid(stone));
+        assert!(is_opaque(stone));
+        assert!(!is_fluid(stone));
+        assert_eq!(emitted_light(stone), 0);
+
+        let glass = Block::Glass.native_state_id();
+        assert!(is_solid(glass));
+        assert!(!is_opaque(glass));
+
+        let water = Block::Water(WaterData { level: 0 }).native_state_id();
+        assert!(is_fluid(water));
+        assert!(!is_solid(water));
+
+        let lava = Block::Lava(LavaData { level: 3 }).native_state_id();
+        assert!(is_fluid(lava));
+        assert_eq!(emitted_light(lava), 15);
+
+        let leaves = Block::OakLeaves(OakLeavesData {
+            distance: 1,
+            persistent: false,
+        });
+        assert_eq!(leaves.opacity(), 1);
+        assert!(!leaves.is_opaque());
+        assert_eq!(opacity(water), 2);
+        assert_eq!(opacity(glass), 0);
+        assert_eq!(opacity(stone), MAX_OPACITY);
+
+        let lamp = Block::RedstoneLamp(RedstoneLampData { lit: true });
+        assert_eq!(lamp.emitted_light(), 15);
+        let lamp = Block::RedstoneLamp(RedstoneLampData { lit: false });
+        assert_eq!(lamp.emitted_light(), 0);
+    }
+
+    #[test]
+    fn test_emitted_light() {
+        let pickles = |pickles, waterlogged| {
+            Block::SeaPickle(SeaPickleData {
+                waterlogged,
+                pickles,
+            })
+            .emitted_light()
+        };
+        assert_eq!(pickles(1, true), 6);
+        assert_eq!(pickles(4, true), 15);
+        assert_eq!(pickles(4, false), 0);
+
+        let furnace = |lit| {
+            Block::Furnace(FurnaceData {
+                lit,
+                facing: FurnaceFacing::North,
+            })
+            .emitted_light()
+        };
+        assert_eq!(furnace(true), 13);
+        assert_eq!(furnace(false), 0);
+
+        assert_eq!(
+            Block::RedstoneTorch(RedstoneTorchData { lit: true }).emitted_light(),
+            7
+        );
+        assert_eq!(
+            Block::RedstoneWallTorch(RedstoneWallTorchData {
+                facing: RedstoneWallTorchFacing::North,
+                lit: false,
+            })
+            .emitted_light(),
+            0
+        );
+        assert_eq!(Block::Torch.emitted_light(), 14);
+        assert_eq!(Block::Glowstone.emitted_light(), 15);
+    }
+
+    #[test]
+    fn test_slab_opacity() {
+        let slab = |ty, waterlogged| Block::OakSlab(OakSlabData { ty, waterlogged });
+        assert_eq!(slab(OakSlabType::Bottom, false).opacity(), 0);
+        assert!(!slab(OakSlabType::Top, false).is_opaque());
+        assert_eq!(slab(OakSlabType::Top, true).opacity(), 2);
+        assert_eq!(slab(OakSlabType::Double, false).opacity(), MAX_OPACITY);
+        assert!(slab(OakSlabType::Double, false).is_opaque());
+
+        let stairs = Block::OakStairs(OakStairsData {
+            shape: OakStairsShape::Straight,
+            half: OakStairsHalf::Bottom,
+            facing: OakStairsFacing::North,
+            waterlogged: false,
+        });
+        assert_eq!(stairs.opacity(), 0);
+    }
+
+    #[test]
+    fn test_map_colors() {
+        let color = |block: Block| map_color(block.native_state_id());
+        assert_eq!(color(Block::Air), color::AIR);
+        assert_eq!(color(Block::Glass), color::AIR);
+        assert_eq!(color(Block::Torch), color::AIR);
+        assert_eq!(color(Block::Stone), color::STONE);
+        assert_eq!(color(Block::GrassBlock(Default::default())), color::GRASS);
+        assert_eq!(color(Block::Water(WaterData { level: 0 })), color::WATER);
+        assert_eq!(color(Block::OakPlanks), color::WOOD);
+        assert_eq!(color(Block::DarkOakPlanks), color::BROWN);
+        assert_eq!(color(Block::WhiteWool), color::SNOW);
+        assert_eq!(color(Block::RedWool), color::RED);
+        assert_eq!(color(Block::Terracotta), color::ORANGE);
+        assert_eq!(color(Block::BlackTerracotta), 51);
+        assert_eq!(color(Block::Sandstone), color::SAND);
+        assert_eq!(color(Block::Bricks), color::RED);
+        assert_eq!(color(Block::StoneBricks), color::STONE);
+    }
+
+    #[test]
+    fn test_bit_set() {
+        let mut set = BitSet::new(100);
+        set.set(0, true);
+        set.set(64, true);
+        set.set(99, true);
+        set.set(64, false);
+        assert!(set.get(0));
+        assert!(!set.get(64));
+        assert!(set.get(99));
+        assert!(!set.get(1));
+    }
+}
@@ -1,6 +1,7 @@
 //! Module for creating and modifying inventories of any type.
 
 use crate::item::Item;
+use crate::item_tag::{BannerPattern, Enchantment, Fireworks, ItemDisplay, ItemTag};
 use smallvec::{Array, SmallVec};
 use std::cmp::min;
 
@@ -136,6 +137,22 @@ pub fn max_size(item: Item) -> u8 {
         | Item::RedBed
         | Item::BlackBed
         | Item::ShulkerBox
+        | Item::WhiteShulkerBox
+        | Item::OrangeShulkerBox
+        | Item::MagentaShulkerBox
+        | Item::LightBlueShulkerBox
+        | Item::YellowShulkerBox
+        | Item::LimeShulkerBox
+        | Item::PinkShulkerBox
+        | Item::GrayShulkerBox
+        | Item::LightGrayShulkerBox
+        | Item::CyanShulkerBox
+        | Item::PurpleShulkerBox
+        | Item::BlueShulkerBox
+        | Item::BrownShulkerBox
+        | Item::GreenShulkerBox
+        | Item::RedShulkerBox
+        | Item::BlackShulkerBox
         | Item::TurtleEgg
         | Item::TurtleHelmet
         | Item::FishingRod
@@ -213,6 +230,62 @@ pub fn max_size(item: Item) -> u8 {
     }
 }
 
+/// Returns the number of damage points an item can take
+/// before it breaks, or `None` if it can't be damaged.
+pub fn max_durability(item: Item) -> Option<i32> {
+    let durability = match item {
+        Item::GoldenSword
+        | Item::GoldenShovel
+        | Item::GoldenPickaxe
+        | Item::GoldenAxe
+        | Item::GoldenHoe => 32,
+        Item::WoodenSword
+        | Item::WoodenShovel
+        | Item::WoodenPickaxe
+        | Item::WoodenAxe
+        | Item::WoodenHoe => 59,
+        Item::StoneSword
+        | Item::StoneShovel
+        | Item::StonePickaxe
+        | Item::StoneAxe
+        | Item::StoneHoe => 131,
+        Item::IronSword | Item::IronShovel | Item::IronPickaxe | Item::IronAxe | Item::IronHoe => {
+            250
+        }
+        Item::DiamondSword
+        | Item::DiamondShovel
+        | Item::DiamondPickaxe
+        | Item::DiamondAxe
+        | Item::DiamondHoe => 1561,
+        Item::LeatherHelmet => 55,
+        Item::LeatherChestplate => 80,
+        Item::LeatherLeggings => 75,
+        Item::LeatherBoots => 65,
+        Item::GoldenHelmet => 77,
+        Item::GoldenChestplate => 112,
+        Item::GoldenLeggings => 105,
+        Item::GoldenBoots => 91,
+        Item::ChainmailHelmet | Item::IronHelmet => 165,
+        Item::ChainmailChestplate | Item::IronChestplate => 240,
+        Item::ChainmailLeggings | Item::IronLeggings => 225,
+        Item::ChainmailBoots | Item::IronBoots => 195,
+        Item::DiamondHelmet => 363,
+        Item::DiamondChestplate => 528,
+        Item::DiamondLeggings => 495,
+        Item::DiamondBoots => 429,
+        Item::TurtleHelmet => 275,
+        Item::Bow => 384,
+        Item::FishingRod | Item::FlintAndSteel => 64,
+        Item::Shears => 238,
+        Item::Shield => 336,
+        Item::Trident => 250,
+        Item::Elytra => 432,
+        Item::CarrotOnAStick => 25,
+        _ => return None,
+    };
+    Some(durability)
+}
+
 /// The various types of inventories ("windows").
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum InventoryType {
@@ -296,7 +369,7 @@ impl Inventory {
         // First, look for slots already having the type.
         for slot in COLLECT_SEARCH_ORDER.iter() {
             if let Some(slot_item) = self.item_at(*slot).cloned() {
-                if slot_item.ty == item.ty {
+                if slot_item.stacks_with(&item) {
                     self.add_to_stack(&mut item, &slot_item, *slot, &mut affected_slots);
 
                     if item.amount == 0 {
@@ -309,7 +382,7 @@ impl Inventory {
         for slot in COLLECT_SEARCH_ORDER.iter() {
             let slot_item = self.item_at(*slot).cloned();
             if slot_item.is_none() {
-                let fake = ItemStack::new(item.ty, 0);
+                let fake = item.with_amount(0);
                 self.add_to_stack(&mut item, &fake, *slot, &mut affected_slots);
                 if item.amount == 0 {
                     return (affected_slots, 0);
@@ -317,7 +390,7 @@ impl Inventory {
             }
 
             if let Some(slot_item) = slot_item {
-                if slot_item.ty == item.ty {
+                if slot_item.stacks_with(&item) {
                     self.add_to_stack(&mut item, &slot_item, *slot, &mut affected_slots);
 
                     if item.amount == 0 {
@@ -341,7 +414,7 @@ impl Inventory {
         let added = min(item.amount, max_size(item.ty) - slot_item.amount);
         item.amount -= added;
 
-        self.set_item_at(slot, ItemStack::new(slot_item.ty, slot_item.amount + added));
+        self.set_item_at(slot, slot_item.with_amount(slot_item.amount + added));
         affected_slots.push(slot);
     }
 
@@ -359,18 +432,185 @@ impl Inventory {
 /// Represents an item stack.
 ///
 /// An item stack includes a type, an amount, and a bunch of properties (enchantments, etc.)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct ItemStack {
     /// The type of this item.
     pub ty: Item,
     /// The number of items in this stack.
     pub amount: u8,
-    // TODO enchantments, more
+    /// The NBT tag of this stack, if it has one.
+    pub tag: Option<ItemTag>,
 }
 
 impl ItemStack {
     pub fn new(ty: Item, amount: u8) -> Self {
-        Self { ty, amount }
+        Self {
+            ty,
+            amount,
+            tag: None,
+        }
+    }
+
+    /// Returns a copy of this stack with a different amount.
+    pub fn with_amount(&self, amount: u8) -> Self {
+        Self {
+            amount,
+            ..self.clone()
+        }
+    }
+
+    /// Returns whether this stack and another can be combined
+    /// into a single stack: they must have the same type and tag.
+    pub fn stacks_with(&self, other: &ItemStack) -> bool {
+        self.ty == other.ty && self.tag() == other.tag()
+    }
+
+    /// Returns the tag of this stack if it is not empty.
+    pub fn tag(&self) -> Option<&ItemTag> {
+        self.tag.as_ref().filter(|tag| !tag.is_empty())
+    }
+
+    /// Returns the tag of this stack, creating an empty one if necessary.
+    pub fn tag_mut(&mut self) -> &mut ItemTag {
+        self.tag.get_or_insert_with(ItemTag::default)
+    }
+
+    pub fn enchantments(&self) -> &[Enchantment] {
+        self.tag().map_or(&[][..], |tag| &tag.enchantments[..])
+    }
+
+    /// Returns the level of the enchantment with the given ID,
+    /// or `None` if the stack does not have the enchantment.
+    pub fn enchantment_level(&self, id: &str) -> Option<i16> {
+        self.enchantments()
+            .iter()
+            .find(|enchantment| enchantment.id == id)
+            .map(|enchantment| enchantment.level)
+    }
+
+    /// Adds an enchantment, replacing any existing
+    /// enchantment with the same ID.
+    pub fn add_enchantment(&mut self, id: &str, level: i16) {
+        let enchantments = &mut self.tag_mut().enchantments;
+        enchantments.retain(|enchantment| enchantment.id != id);
+        enchantments.push(Enchantment {
+            id: id.to_string(),
+            level,
+        });
+    }
+
+    /// Returns the custom name of this stack as a JSON text component.
+    pub fn custom_name(&self) -> Option<&str> {
+        self.display()?.name.as_deref()
+    }
+
+    /// Sets the custom name of this stack, which must be
+    /// a JSON text component.
+    pub fn set_custom_name(&mut self, name: Option<String>) {
+        self.display_mut().name = name;
+    }
+
+    pub fn lore(&self) -> &[String] {
+        self.display().map_or(&[][..], |display| &display.lore[..])
+    }
+
+    pub fn set_lore(&mut self, lore: Vec<String>) {
+        self.display_mut().lore = lore;
+    }
+
+    pub fn is_unbreakable(&self) -> bool {
+        self.tag().map_or(false, |tag| tag.unbreakable)
+    }
+
+    pub fn set_unbreakable(&mut self, unbreakable: bool) {
+        self.tag_mut().unbreakable = unbreakable;
+    }
+
+    /// Returns the ID of the map shown by this stack,
+    /// if it is a filled map.
+    pub fn map_id(&self) -> Option<i32> {
+        self.tag()?.map
+    }
+
+    pub fn set_map_id(&mut self, id: i32) {
+        self.tag_mut().map = Some(id);
+    }
+
+    /// Returns the durability lost by this stack.
+    pub fn damage(&self) -> i32 {
+        self.tag().map_or(0, |tag| tag.damage)
+    }
+
+    pub fn set_damage(&mut self, damage: i32) {
+        self.tag_mut().damage = damage;
+    }
+
+    /// Damages this stack by the given number of points, unless it
+    /// is unbreakable or can't be damaged. Returns whether the stack
+    /// broke, in which case it should be removed.
+    pub fn add_damage(&mut self, amount: i32) -> bool {
+        let max = match max_durability(self.ty) {
+            Some(max) if !self.is_unbreakable() => max,
+            _ => return false,
+        };
+        let damage = self.damage() + amount;
+        self.set_damage(damage);
+        damage > max
+    }
+
+    /// Returns the flight duration and explosions
+    /// of this stack, if it is a firework rocket.
+    pub fn fireworks(&self) -> Option<&Fireworks> {
+        self.tag()?.fireworks.as_ref()
+    }
+
+    /// Returns the ID of the potion held by this
+    /// stack, if it is a potion.
+    pub fn potion(&self) -> Option<&str> {
+        self.tag()?.potion.as_deref()
+    }
+
+    pub fn set_potion(&mut self, potion: Option<String>) {
+        self.tag_mut().potion = potion;
+    }
+
+    /// Returns the color of this stack if it is dyed leather armor.
+    pub fn color(&self) -> Option<i32> {
+        self.display()?.color
+    }
+
+    pub fn set_color(&mut self, color: Option<i32>) {
+        self.display_mut().color = color;
+    }
+
+    /// Returns the patterns of this stack if it is a banner.
+    pub fn banner_patterns(&self) -> &[BannerPattern] {
+        self.tag()
+            .and_then(|tag| tag.block_entity_tag.as_ref())
+            .map_or(&[][..], |tag| &tag.patterns[..])
+    }
+
+    /// Removes the top pattern of this banner,
+    /// returning it if there was one.
+    pub fn pop_banner_pattern(&mut self) -> Option<BannerPattern> {
+        self.tag_mut().block_entity_tag.as_mut()?.patterns.pop()
+    }
+
+    fn display(&self) -> Option<&ItemDisplay> {
+        self.tag()?.display.as_ref()
+    }
+
+    fn display_mut(&mut self) -> &mut ItemDisplay {
+        self.tag_mut()
+            .display
+            .get_or_insert_with(ItemDisplay::default)
+    }
+}
+
+// An empty tag is equivalent to no tag.
+impl PartialEq for ItemStack {
+    fn eq(&self, other: &Self) -> bool {
+        self.amount == other.amount && self.stacks_with(other)
     }
 }
 
@@ -434,6 +674,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_collect_item_different_tags() {
+        let mut inv = Inventory::new(InventoryType::Player, 46);
+        inv.set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::Stick, 1));
+
+        let mut named = ItemStack::new(Item::Stick, 1);
+        named.set_custom_name(Some(String::from(r#"{"text":"Wand"}"#)));
+        inv.collect_item(named.clone());
+        assert_eq!(
+            inv.item_at(SLOT_HOTBAR_OFFSET).unwrap(),
+            &ItemStack::new(Item::Stick, 1)
+        );
+        assert_eq!(inv.item_at(SLOT_HOTBAR_OFFSET + 1).unwrap(), &named);
+    }
+
+    #[test]
+    fn test_item_stack_tag() {
+        let mut item = ItemStack::new(Item::DiamondSword, 1);
+        assert!(item.tag().is_none());
+        assert_eq!(item.enchantment_level("minecraft:sharpness"), None);
+
+        item.add_enchantment("minecraft:sharpness", 1);
+        item.add_enchantment("minecraft:sharpness", 5);
+        assert_eq!(item.enchantments().len(), 1);
+        assert_eq!(item.enchantment_level("minecraft:sharpness"), Some(5));
+
+        item.set_lore(vec![String::from("Sharp")]);
+        item.set_unbreakable(true);
+        assert_eq!(item.lore(), &[String::from("Sharp")]);
+        assert!(item.is_unbreakable());
+        assert_eq!(item.custom_name(), None);
+
+        // Empty tags are equivalent to no tag.
+        let mut empty = ItemStack::new(Item::Stone, 1);
+        empty.tag_mut();
+        assert_eq!(empty, ItemStack::new(Item::Stone, 1));
+    }
+
+    #[test]
+    fn test_add_damage() {
+        let mut item = ItemStack::new(Item::WoodenPickaxe, 1);
+        assert!(!item.add_damage(59));
+        assert_eq!(item.damage(), 59);
+        assert!(item.add_damage(1));
+
+        let mut unbreakable = ItemStack::new(Item::WoodenPickaxe, 1);
+        unbreakable.set_unbreakable(true);
+        assert!(!unbreakable.add_damage(100));
+        assert_eq!(unbreakable.damage(), 0);
+
+        let mut stone = ItemStack::new(Item::Stone, 1);
+        assert!(!stone.add_damage(1));
+        assert!(stone.tag().is_none());
+    }
+
     #[test]
     fn test_collect_item_overstack() {
         let mut inv = Inventory::new(InventoryType::Player, 46);
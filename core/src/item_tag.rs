@@ -0,0 +1,306 @@
+//! The NBT tag of item stacks, which stores additional
+//! properties such as enchantments and custom names.
+//!
+//! The same format is used in the Slot network type
+//! and in saved inventories. Tags which do not have a typed
+//! field here are preserved as they are.
+
+use crate::nbt::{self, Value};
+use crate::player_data::InventorySlot;
+use std::collections::HashMap;
+
+/// The NBT tag of an item stack.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ItemTag {
+    #[serde(
+        rename = "Enchantments",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub enchantments: Vec<Enchantment>,
+    /// The enchantments stored in an enchanted book, which
+    /// are applied to the item it is combined with.
+    #[serde(
+        rename = "StoredEnchantments",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub stored_enchantments: Vec<Enchantment>,
+    #[serde(rename = "display", default)]
+    pub display: Option<ItemDisplay>,
+    #[serde(
+        rename = "Unbreakable",
+        default,
+        deserialize_with = "nbt::deserialize_bool",
+        skip_serializing_if = "is_false"
+    )]
+    pub unbreakable: bool,
+    #[serde(
+        rename = "AttributeModifiers",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub attribute_modifiers: Vec<AttributeModifier>,
+    /// The ID of the map shown by a filled map.
+    #[serde(rename = "map", default)]
+    pub map: Option<i32>,
+    /// The block entity data of a block item, which is
+    /// transferred to the block entity when it is placed.
+    #[serde(rename = "BlockEntityTag", default)]
+    pub block_entity_tag: Option<BlockEntityTag>,
+    /// The durability lost by an item which can be damaged.
+    #[serde(rename = "Damage", default, skip_serializing_if = "is_zero")]
+    pub damage: i32,
+    /// The flight duration and explosions of a firework rocket.
+    #[serde(rename = "Fireworks", default)]
+    pub fireworks: Option<Fireworks>,
+    /// The namespaced ID of the potion held by a potion
+    /// item, e.g. `minecraft:water`.
+    #[serde(rename = "Potion", default)]
+    pub potion: Option<String>,
+    /// Any other tags.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl ItemTag {
+    /// Returns whether this tag contains no data, in
+    /// which case it does not need to be stored.
+    pub fn is_empty(&self) -> bool {
+        self.enchantments.is_empty()
+            && self.stored_enchantments.is_empty()
+            && self.display.as_ref().map_or(true, ItemDisplay::is_empty)
+            && !self.unbreakable
+            && self.attribute_modifiers.is_empty()
+            && self.map.is_none()
+            && self
+                .block_entity_tag
+                .as_ref()
+                .map_or(true, BlockEntityTag::is_empty)
+            && self.damage == 0
+            && self.fireworks.is_none()
+            && self.potion.is_none()
+            && self.other.is_empty()
+    }
+}
+
+fn is_false(x: &bool) -> bool {
+    !*x
+}
+
+fn is_zero(x: &i32) -> bool {
+    *x == 0
+}
+
+/// An enchantment on an item.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Enchantment {
+    /// The namespaced ID of the enchantment, e.g. `minecraft:sharpness`.
+    pub id: String,
+    #[serde(rename = "lvl")]
+    pub level: i16,
+}
+
+/// The `display` tag of an item.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemDisplay {
+    /// The custom name of the item as a JSON text component.
+    #[serde(rename = "Name")]
+    pub name: Option<String>,
+    /// Lines of lore shown below the item name.
+    #[serde(rename = "Lore", default, skip_serializing_if = "Vec::is_empty")]
+    pub lore: Vec<String>,
+    /// The color of dyed leather armor as an RGB value.
+    #[serde(rename = "color")]
+    pub color: Option<i32>,
+}
+
+impl ItemDisplay {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.lore.is_empty() && self.color.is_none()
+    }
+}
+
+/// The `BlockEntityTag` tag of an item.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockEntityTag {
+    /// The items of a container, such as a shulker box.
+    /// The slot of each item is its index in the container.
+    #[serde(rename = "Items", default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<InventorySlot>,
+    /// The patterns of a banner, from the bottom layer to the top one.
+    #[serde(rename = "Patterns", default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<BannerPattern>,
+    /// Any other tags.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl BlockEntityTag {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.patterns.is_empty() && self.other.is_empty()
+    }
+}
+
+/// A pattern applied to a banner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BannerPattern {
+    /// The code of the pattern, e.g. `bs` for a base stripe.
+    #[serde(rename = "Pattern")]
+    pub pattern: String,
+    /// The color of the pattern, from 0 for white to 15 for black.
+    #[serde(rename = "Color")]
+    pub color: i32,
+}
+
+/// The `Fireworks` tag of a firework rocket.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fireworks {
+    /// The flight duration, which is
+    /// the number of gunpowder used to
+    /// craft the rocket.
+    #[serde(rename = "Flight", default)]
+    pub flight: i8,
+    #[serde(rename = "Explosions", default, skip_serializing_if = "Vec::is_empty")]
+    pub explosions: Vec<FireworkExplosion>,
+}
+
+/// An explosion of a firework rocket, or of a firework star.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FireworkExplosion {
+    /// The shape of the explosion: 0 for a small ball, 1 for
+    /// a large ball, 2 for a star, 3 for a creeper face and
+    /// 4 for a burst.
+    #[serde(rename = "Type", default)]
+    pub shape: i8,
+    #[serde(
+        rename = "Flicker",
+        default,
+        deserialize_with = "nbt::deserialize_bool",
+        skip_serializing_if = "is_false"
+    )]
+    pub flicker: bool,
+    #[serde(
+        rename = "Trail",
+        default,
+        deserialize_with = "nbt::deserialize_bool",
+        skip_serializing_if = "is_false"
+    )]
+    pub trail: bool,
+    /// The colors of the explosion as RGB values.
+    #[serde(rename = "Colors", with = "nbt::array::int", default)]
+    pub colors: Vec<i32>,
+    /// The colors to which the explosion fades.
+    #[serde(rename = "FadeColors", with = "nbt::array::int", default)]
+    pub fade_colors: Vec<i32>,
+}
+
+/// An attribute modifier applied while an item is held or worn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttributeModifier {
+    /// The attribute to modify, e.g. `generic.attackDamage`.
+    #[serde(rename = "AttributeName")]
+    pub attribute: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// The slot in which the modifier applies, e.g. `mainhand`.
+    /// If unset, the modifier applies in all slots.
+    #[serde(rename = "Slot")]
+    pub slot: Option<String>,
+    /// 0 to add the amount, 1 to add the amount multiplied by
+    /// the base value, 2 to multiply by one plus the amount.
+    #[serde(rename = "Operation")]
+    pub operation: i32,
+    #[serde(rename = "Amount")]
+    pub amount: f64,
+    #[serde(rename = "UUIDMost")]
+    pub uuid_most: i64,
+    #[serde(rename = "UUIDLeast")]
+    pub uuid_least: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_tag_roundtrip() {
+        let mut other = HashMap::new();
+        other.insert(String::from("HideFlags"), Value::Int(2));
+
+        let tag = ItemTag {
+            enchantments: vec![Enchantment {
+                id: String::from("minecraft:sharpness"),
+                level: 5,
+            }],
+            stored_enchantments: vec![],
+            display: Some(ItemDisplay {
+                name: Some(String::from(r#"{"text":"Excalibur"}"#)),
+                lore: vec![String::from("Pulled from a stone")],
+                color: None,
+            }),
+            unbreakable: true,
+            attribute_modifiers: vec![AttributeModifier {
+                attribute: String::from("generic.attackDamage"),
+                name: String::from("Sharpened"),
+                slot: Some(String::from("mainhand")),
+                operation: 0,
+                amount: 2.5,
+                uuid_most: 1,
+                uuid_least: 2,
+            }],
+            map: None,
+            block_entity_tag: Some(BlockEntityTag {
+                items: vec![InventorySlot {
+                    count: 3,
+                    slot: 26,
+                    item: String::from("minecraft:stone"),
+                    tag: None,
+                }],
+                patterns: vec![BannerPattern {
+                    pattern: String::from("bs"),
+                    color: 14,
+                }],
+                other: HashMap::new(),
+            }),
+            damage: 10,
+            fireworks: Some(Fireworks {
+                flight: 2,
+                explosions: vec![FireworkExplosion {
+                    shape: 1,
+                    flicker: true,
+                    trail: false,
+                    colors: vec![0xB0_2E_26],
+                    fade_colors: vec![],
+                }],
+            }),
+            potion: None,
+            other,
+        };
+        assert!(!tag.is_empty());
+
+        let value = nbt::to_value(&tag).unwrap();
+        match &value {
+            Value::Compound(map) => {
+                assert_eq!(map["Unbreakable"], Value::Byte(1));
+                assert_eq!(map["Damage"], Value::Int(10));
+                assert_eq!(map["HideFlags"], Value::Int(2));
+            }
+            value => panic!("expected a compound, got {:?}", value),
+        }
+
+        let read: ItemTag = nbt::from_value(value).unwrap();
+        assert_eq!(read, tag);
+    }
+
+    #[test]
+    fn test_parse_snbt() {
+        let value =
+            nbt::snbt::parse(r#"{Enchantments:[{id:"sharpness",lvl:5s}],Unbreakable:1b}"#).unwrap();
+        let tag: ItemTag = nbt::from_value(value).unwrap();
+        assert_eq!(tag.enchantments[0].id, "sharpness");
+        assert_eq!(tag.enchantments[0].level, 5);
+        assert!(tag.unbreakable);
+        assert!(tag.display.is_none());
+    }
+}
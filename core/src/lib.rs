@@ -27,8 +27,10 @@ mod biomes;
 pub mod bytes_ext;
 pub mod entitymeta;
 pub mod inventory;
+pub mod item_tag;
 pub mod network;
 pub mod prelude;
+pub mod recipe;
 mod save;
 
 pub use biomes::Biome;
@@ -36,11 +38,12 @@ pub use entitymeta::EntityMetadata;
 pub use feather_items as item;
 pub use inventory::{ItemStack, Slot};
 pub use item::{Item, ItemExt};
+pub use item_tag::ItemTag;
 pub use network::packet::{implementation as packet, Packet, PacketType};
-pub use save::{entity, level, player_data, region};
+pub use save::{entity, level, map, nbt, player_data, region, schematic};
 pub use world::{
     block::{self, Block, BlockExt},
-    chunk::{Chunk, ChunkSection},
+    chunk::{Chunk, ChunkSection, ScheduledTick},
     BlockPosition, ChunkPosition, Position,
 };
 
@@ -1,6 +1,7 @@
 use crate::bytes_ext::TryGetError;
 use crate::network::mctypes::{McTypeRead, McTypeWrite};
-use crate::network::packet::{PacketDirection, PacketId, PacketStage};
+use crate::network::packet::{EncodedPacket, PacketDirection, PacketId, PacketStage};
+use crate::network::pool::BUFFER_POOL;
 use crate::{Packet, PacketType};
 use aes::Aes128;
 use bytes::{Buf, BufMut, BytesMut};
@@ -33,6 +34,11 @@ pub enum Error {
     PacketTooLarge(usize),
     #[fail(display = "Invalid packet ID {} for stage {:?}", _0, _1)]
     InvalidPacketId(u32, PacketStage),
+    #[fail(
+        display = "Packet claimed to decompress to {} bytes, but decompressed to {}",
+        _0, _1
+    )]
+    DecompressedLengthMismatch(usize, usize),
 }
 
 /// Codec for encoding and decoding Minecraft packets.
@@ -50,11 +56,11 @@ pub struct MinecraftCodec {
     /// Cached buffer for writing header data.
     /// Using this avoids reallocations.
     header_buffer: BytesMut,
-    /// Cached buffer into which we write decompressed
-    /// data. Using this avoids reallocations.
-    decompressed_buffer: Vec<u8>,
     /// Index into `src` of next byte to decrypt.
     decrypt_index: usize,
+    /// The packet types whose data is parsed,
+    /// or `None` if all packets are parsed.
+    parsed: Option<Vec<PacketType>>,
 }
 
 impl MinecraftCodec {
@@ -66,8 +72,8 @@ impl MinecraftCodec {
             decrypter: None,
             compression_threshold: None,
             header_buffer: BytesMut::with_capacity(HEADER_SIZE),
-            decompressed_buffer: vec![],
             decrypt_index: 0,
+            parsed: None,
         }
     }
 
@@ -85,32 +91,26 @@ impl MinecraftCodec {
     pub fn set_stage(&mut self, stage: PacketStage) {
         self.stage = stage;
     }
-}
-
-impl Encoder for MinecraftCodec {
-    type Item = Box<dyn Packet>;
-    type Error = io::Error;
-
-    fn encode(&mut self, packet: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        // Reserve space for the packet header (at most 2 * 5 bytes, for length + data length).
-        // `header` will contain the first 10 bytes of the buffer, while `dst`
-        // still contains the rest.
-        // "Data length" refers to the uncompressed size of the packet.
-        // Since we cannot know the size of the header in advance, thanks to varints,
-        // we reserve the maximum size and copy the header in with a correct offset.
-        assert!(dst.is_empty());
-        dst.reserve(HEADER_SIZE);
-        let mut header = dst.split_to(HEADER_SIZE);
-        assert!(dst.is_empty());
-        assert!(header.is_empty());
 
-        // Zero out `header`.
-        header.extend_from_slice(&[0u8; HEADER_SIZE]);
+    /// Only parses the data of packets with the given types.
+    /// Other packets are still decrypted and decompressed, but
+    /// are decoded to their default value. This allows clients
+    /// to receive packets which cannot be read yet.
+    pub fn parse_only(&mut self, types: &[PacketType]) {
+        self.parsed = Some(types.to_vec());
+    }
 
+    /// Writes the ID and data of a packet to `dst`, after `header`.
+    /// Returns the uncompressed data length to write into the header,
+    /// or `None` if compression is disabled.
+    fn write_packet(
+        &self,
+        packet: &dyn Packet,
+        dst: &mut BytesMut,
+        header: &mut BytesMut,
+    ) -> Option<usize> {
         // Write raw packet data to `dst`.
-        let ty = packet.ty();
-        trace!("Sending packet with type {:?}", ty);
-        dst.push_var_int(ty.get_id().0 as i32);
+        dst.push_var_int(packet.ty().get_id().0 as i32);
         packet.write_to(dst);
 
         // If compression is enabled, we follow a more complex course of action:
@@ -119,14 +119,15 @@ impl Encoder for MinecraftCodec {
         // * Otherwise, we move forward into the buffer, allocating
         // another header and then writing the compressed bytes
         // to the capacity after that.
-        let data_len: Option<usize> = if let Some(threshold) = self.compression_threshold {
+        if let Some(threshold) = self.compression_threshold {
             let data_len = dst.len();
             if data_len >= threshold {
-                // Allocate new header
-                dst.reserve(HEADER_SIZE);
+                // Allocate new header, along with enough
+                // capacity for the compressed data.
+                dst.reserve(HEADER_SIZE + max_compressed_len(data_len));
 
                 let uncompressed = dst.split_to(data_len);
-                header = dst.split_to(HEADER_SIZE);
+                *header = dst.split_to(HEADER_SIZE);
 
                 assert!(dst.is_empty());
                 // Compress data into `compressed`.
@@ -139,6 +140,75 @@ impl Encoder for MinecraftCodec {
             }
         } else {
             None
+        }
+    }
+
+    /// Writes the ID and already serialized data of a packet to `dst`.
+    /// If the packet is compressed, the data is compressed straight
+    /// from `data` without first being copied into `dst`.
+    fn write_encoded(&mut self, ty: PacketType, data: &[u8], dst: &mut BytesMut) -> Option<usize> {
+        // `header_buffer` is unused until the header is written.
+        self.header_buffer.push_var_int(ty.get_id().0 as i32);
+        let data_len = self.header_buffer.len() + data.len();
+
+        let compressed = self
+            .compression_threshold
+            .map(|threshold| data_len >= threshold);
+
+        if compressed == Some(true) {
+            dst.reserve(max_compressed_len(data_len));
+            let mut encoder = ZlibEncoder::new(dst.writer(), Compression::default());
+            encoder.write_all(&self.header_buffer).unwrap();
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap();
+        } else {
+            dst.reserve(data_len);
+            dst.extend_from_slice(&self.header_buffer);
+            dst.extend_from_slice(data);
+        }
+
+        self.header_buffer.clear();
+
+        compressed.map(|compressed| if compressed { data_len } else { 0 })
+    }
+}
+
+/// Returns the maximum length of `len` bytes
+/// once compressed by zlib, as computed by `compressBound`.
+fn max_compressed_len(len: usize) -> usize {
+    len + (len >> 12) + (len >> 14) + (len >> 25) + 13
+}
+
+impl Encoder for MinecraftCodec {
+    type Item = Box<dyn Packet>;
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        // Reserve space for the packet header (at most 2 * 5 bytes, for length + data length).
+        // `header` will contain the first 10 bytes of the buffer, while `dst`
+        // still contains the rest.
+        // "Data length" refers to the uncompressed size of the packet.
+        // Since we cannot know the size of the header in advance, thanks to varints,
+        // we reserve the maximum size and copy the header in with a correct offset.
+        //
+        // Earlier frames may still be waiting in `dst` to be flushed,
+        // so the packet is encoded on its own and appended to them.
+        let frames = dst.split_to(dst.len());
+        dst.reserve(HEADER_SIZE);
+        let mut header = dst.split_to(HEADER_SIZE);
+
+        // Zero out `header`.
+        header.extend_from_slice(&[0u8; HEADER_SIZE]);
+
+        let ty = packet.ty();
+        trace!("Sending packet with type {:?}", ty);
+
+        // Encoded packets are written (or compressed) straight
+        // from their shared buffer.
+        let data_len = if let Some(encoded) = packet.as_any().downcast_ref::<EncodedPacket>() {
+            self.write_encoded(ty, encoded.data(), dst)
+        } else {
+            self.write_packet(&*packet, dst, &mut header)
         };
 
         // Figure out the length of `data_length` encoded.
@@ -179,6 +249,9 @@ impl Encoder for MinecraftCodec {
             crypter.encrypt(dst);
         }
 
+        let packet = std::mem::replace(dst, frames);
+        dst.unsplit(packet);
+
         Ok(())
     }
 }
@@ -224,26 +297,42 @@ impl Decoder for MinecraftCodec {
 
         // If compression is enabled:
         // * Read the data length field. If 0, continue as normal: the packet is not compressed.
-        // * Decompress remaining bytes into `self.decompressed_buffer`.
-        // * Update `cursor` to read from `self.decompressed_buffer`.
+        // * Decompress remaining bytes into a buffer from the buffer pool.
+        // * Update `cursor` to read from the decompressed data.
+        let mut decompressed = None;
         if let Some(threshold) = self.compression_threshold {
-            let data_length = cursor.try_get_var_int()?;
-
-            if data_length != 0 {
-                self.decompressed_buffer.clear();
+            let data_length = cursor.try_get_var_int()? as usize;
 
-                let mut decoder = ZlibDecoder::new(cursor);
-                decoder.read_to_end(&mut self.decompressed_buffer)?;
+            if data_length > MAX_PACKET_LEN {
+                return Err(Error::PacketTooLarge(data_length).into());
+            }
 
-                let actual_data_length = self.decompressed_buffer.len();
-                if actual_data_length < threshold {
-                    return Err(
-                        Error::CompressedPacketTooSmall(actual_data_length, threshold).into(),
-                    );
+            if data_length != 0 {
+                // At most one byte more than the claimed length is
+                // decompressed, which bounds the amount of data a
+                // malicious client can make us decompress and is
+                // enough to detect packets lying about their length.
+                let mut buf = BUFFER_POOL.get(data_length + 1);
+                buf.reserve(data_length + 1);
+                let mut writer = buf.writer();
+                let mut decoder = ZlibDecoder::new(&mut cursor).take(data_length as u64 + 1);
+                std::io::copy(&mut decoder, &mut writer)?;
+                let buf = writer.into_inner();
+
+                if buf.len() != data_length {
+                    return Err(Error::DecompressedLengthMismatch(data_length, buf.len()).into());
                 }
+                decompressed = Some(buf);
+            }
+        }
 
-                cursor = Cursor::new(&self.decompressed_buffer);
+        if let Some(buf) = decompressed.as_ref() {
+            let threshold = self.compression_threshold.unwrap_or(0);
+            if buf.len() < threshold {
+                return Err(Error::CompressedPacketTooSmall(buf.len(), threshold).into());
             }
+
+            cursor = Cursor::new(&buf[..]);
         }
 
         // Read packet.
@@ -253,7 +342,17 @@ impl Decoder for MinecraftCodec {
                 .map_err(|_| Error::InvalidPacketId(id, self.stage))?;
 
         let mut packet = packet_type.get_implementation();
-        packet.read_from(&mut cursor)?;
+        let parse = match &self.parsed {
+            Some(parsed) => parsed.contains(&packet_type),
+            None => true,
+        };
+        if parse {
+            packet.read_from(&mut cursor)?;
+        }
+
+        if let Some(buf) = decompressed {
+            BUFFER_POOL.put(buf);
+        }
 
         trace!("Received packet with type {:?}", packet_type);
 
@@ -263,3 +362,159 @@ impl Decoder for MinecraftCodec {
         Ok(Some(packet))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::cast_packet;
+    use crate::network::packet::implementation::{ChatMessageServerbound, KeepAliveServerbound};
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+    use futures::{Sink, SinkExt};
+    use std::pin::Pin;
+    use tokio::codec::Framed;
+
+    fn roundtrip(threshold: Option<usize>, packet: Box<dyn Packet>) -> String {
+        let mut encoder = MinecraftCodec::new(PacketDirection::Clientbound);
+        let mut decoder = MinecraftCodec::new(PacketDirection::Serverbound);
+        for codec in &mut [&mut encoder, &mut decoder] {
+            codec.set_stage(PacketStage::Play);
+            if let Some(threshold) = threshold {
+                codec.enable_compression(threshold);
+            }
+        }
+
+        let mut buf = BytesMut::new();
+        encoder.encode(packet, &mut buf).unwrap();
+        let packet = decoder.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+
+        cast_packet::<ChatMessageServerbound>(&*packet)
+            .message
+            .clone()
+    }
+
+    #[test]
+    fn test_buffered_packets() {
+        let mut framed = Framed::new(
+            Cursor::new(vec![]),
+            MinecraftCodec::new(PacketDirection::Clientbound),
+        );
+        framed.codec_mut().set_stage(PacketStage::Play);
+        framed.codec_mut().enable_compression(256);
+
+        let long = "a".repeat(1024);
+        let messages = ["first", "second", long.as_str()];
+        block_on(async {
+            for message in &messages {
+                let packet: Box<dyn Packet> =
+                    Box::new(ChatMessageServerbound::new(message.to_string()));
+                poll_fn(|cx| Pin::new(&mut framed).poll_ready(cx))
+                    .await
+                    .unwrap();
+                Pin::new(&mut framed).start_send(packet).unwrap();
+            }
+            framed.flush().await.unwrap();
+        });
+
+        let mut decoder = MinecraftCodec::new(PacketDirection::Serverbound);
+        decoder.set_stage(PacketStage::Play);
+        decoder.enable_compression(256);
+        let mut buf = BytesMut::from(framed.into_inner().into_inner());
+        for message in &messages {
+            let packet = decoder.decode(&mut buf).unwrap().unwrap();
+            assert_eq!(
+                cast_packet::<ChatMessageServerbound>(&*packet).message,
+                *message
+            );
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decompressed_length_mismatch() {
+        // The uncompressed packet ID and data.
+        let mut body = BytesMut::new();
+        let mut encoder = MinecraftCodec::new(PacketDirection::Clientbound);
+        encoder.set_stage(PacketStage::Play);
+        encoder
+            .encode(
+                Box::new(ChatMessageServerbound::new("a".repeat(64))),
+                &mut body,
+            )
+            .unwrap();
+        let mut cursor = Cursor::new(&body[..]);
+        cursor.try_get_var_int().unwrap();
+        let body = &body[cursor.position() as usize..];
+
+        let decode = |data_length: usize| {
+            let mut compressed = ZlibEncoder::new(vec![], Compression::default());
+            compressed.write_all(body).unwrap();
+            let mut packet = BytesMut::new();
+            packet.push_var_int(data_length as i32);
+            packet.extend_from_slice(&compressed.finish().unwrap());
+            let mut frame = BytesMut::new();
+            frame.push_var_int(packet.len() as i32);
+            frame.extend_from_slice(&packet);
+
+            let mut decoder = MinecraftCodec::new(PacketDirection::Serverbound);
+            decoder.set_stage(PacketStage::Play);
+            decoder.enable_compression(0);
+            decoder.decode(&mut frame)
+        };
+
+        assert!(decode(body.len()).unwrap().is_some());
+        for data_length in &[body.len() - 1, body.len() + 1, 4 * body.len()] {
+            let error = decode(*data_length).unwrap_err();
+            match error.downcast_ref::<Error>() {
+                Some(Error::DecompressedLengthMismatch(claimed, actual)) => {
+                    assert_eq!(*claimed, *data_length);
+                    assert_eq!(*actual, body.len().min(*data_length + 1));
+                }
+                _ => panic!("unexpected error {}", error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_only() {
+        let mut encoder = MinecraftCodec::new(PacketDirection::Clientbound);
+        encoder.set_stage(PacketStage::Play);
+        let mut buf = BytesMut::new();
+        encoder
+            .encode(Box::new(KeepAliveServerbound::new(5)), &mut buf)
+            .unwrap();
+        encoder
+            .encode(
+                Box::new(ChatMessageServerbound::new("test".to_string())),
+                &mut buf,
+            )
+            .unwrap();
+
+        let mut decoder = MinecraftCodec::new(PacketDirection::Serverbound);
+        decoder.set_stage(PacketStage::Play);
+        decoder.parse_only(&[PacketType::ChatMessageServerbound]);
+
+        let packet = decoder.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cast_packet::<KeepAliveServerbound>(&*packet).id, 0);
+        let packet = decoder.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(
+            cast_packet::<ChatMessageServerbound>(&*packet).message,
+            "test"
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_encoded_packets() {
+        let message = "a".repeat(1024);
+        let packet = ChatMessageServerbound::new(message.clone());
+
+        for threshold in &[None, Some(0), Some(256), Some(4096)] {
+            assert_eq!(roundtrip(*threshold, packet.box_clone()), message);
+
+            let encoded = EncodedPacket::new(packet.box_clone());
+            assert_eq!(roundtrip(*threshold, Box::new(encoded)), message);
+        }
+    }
+}
@@ -5,7 +5,6 @@ use crate::world::BlockPosition;
 use bytes::{Buf, BytesMut};
 use feather_items::{Item, ItemExt};
 use serde::{Deserialize, Serialize};
-use std::io::Read;
 
 /// Identifies a type to which Minecraft-specific
 /// types (`VarInt`, `VarLong`, etc.) can be written.
@@ -15,6 +14,9 @@ pub trait McTypeWrite {
     ///
     /// Returns the number of bytes used to encode this integer.
     fn push_var_int(&mut self, x: i32) -> usize;
+    /// Writes a `VarLong` to the object, returning
+    /// the number of bytes used to encode it.
+    fn push_var_long(&mut self, x: i64) -> usize;
     /// Writes a string to the object. This method
     /// will first write the length of the string in bytes
     /// encodes as a `VarInt` and will then write
@@ -39,6 +41,8 @@ pub trait McTypeRead {
     /// `Some(x)` if successful or `None` if the object
     /// does not contain a valid `VarInt`.
     fn try_get_var_int(&mut self) -> Result<i32, TryGetError>;
+    /// Reads a `VarLong` from this object.
+    fn try_get_var_long(&mut self) -> Result<i64, TryGetError>;
     /// Reads a string from the object.
     fn try_get_string(&mut self) -> Result<String, TryGetError>;
 
@@ -72,6 +76,26 @@ impl McTypeWrite for BytesMut {
         bytes_written
     }
 
+    fn push_var_long(&mut self, x: i64) -> usize {
+        // Shift as unsigned so that negative values terminate.
+        let mut x = x as u64;
+        let mut bytes_written = 0;
+        loop {
+            let mut temp = (x & 0b0111_1111) as u8;
+            x >>= 7;
+            if x != 0 {
+                temp |= 0b1000_0000;
+            }
+            self.push_u8(temp);
+            bytes_written += 1;
+            if x == 0 {
+                break;
+            }
+        }
+
+        bytes_written
+    }
+
     /// Writes a string to the object. This method
     /// will first write the length of the string in bytes
     /// encodes as a `VarInt` and will then write
@@ -116,7 +140,15 @@ impl McTypeWrite for BytesMut {
         if let Some(slot) = slot.as_ref() {
             self.push_var_int(slot.ty.native_protocol_id());
             self.push_i8(slot.amount as i8);
-            self.push_i8(0x00); // TAG_End - TODO item NBT support
+
+            match slot.tag() {
+                Some(tag) => {
+                    let mut temp = vec![];
+                    crate::nbt::to_writer(&mut temp, tag, None).unwrap(); // Item tags are always valid NBT
+                    self.extend_from_slice(&temp);
+                }
+                None => self.push_i8(0x00), // TAG_End
+            }
         }
     }
 }
@@ -147,6 +179,28 @@ impl<B: Buf> McTypeRead for B {
         Ok(result)
     }
 
+    fn try_get_var_long(&mut self) -> Result<i64, TryGetError> {
+        let mut num_read = 0;
+        let mut result = 0;
+        loop {
+            if self.remaining() == 0 {
+                return Err(TryGetError::NotEnoughBytes);
+            }
+            let read = self.try_get_u8()?;
+            let value = i64::from(read & 0b0111_1111);
+            result |= value << (7 * num_read);
+
+            num_read += 1;
+            if num_read > 10 {
+                return Err(TryGetError::NotEnoughBytes);
+            }
+            if read & 0b1000_0000 == 0 {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
     /// Reads a string from the object.
     fn try_get_string(&mut self) -> Result<String, TryGetError> {
         let len = self.try_get_var_int();
@@ -188,10 +242,12 @@ impl<B: Buf> McTypeRead for B {
     }
 
     fn try_get_uuid(&mut self) -> Result<Uuid, TryGetError> {
+        if self.remaining() < 16 {
+            return Err(TryGetError::NotEnoughBytes);
+        }
+
         let mut bytes = [0u8; 16];
-        self.bytes()
-            .read(&mut bytes)
-            .map_err(|_| TryGetError::NotEnoughBytes)?;
+        self.copy_to_slice(&mut bytes);
         Ok(Uuid::from_bytes(bytes))
     }
 
@@ -210,9 +266,21 @@ impl<B: Buf> McTypeRead for B {
         let ty = Item::from_native_protocol_id(id).ok_or(TryGetError::InvalidValue)?;
         let amount = self.try_get_i8()? as u8;
 
-        // TODO NBT support
+        let mut stack = ItemStack::new(ty, amount);
 
-        Ok(Some(ItemStack::new(ty, amount)))
+        // A TAG_End byte indicates that there is no tag.
+        if !self.has_remaining() {
+            return Err(TryGetError::NotEnoughBytes);
+        }
+        if self.bytes()[0] == 0x00 {
+            self.advance(1);
+        } else {
+            let (_, value) = crate::nbt::read_root(&mut self.by_ref().reader())
+                .map_err(|_| TryGetError::InvalidValue)?;
+            stack.tag = Some(crate::nbt::from_value(value).map_err(|_| TryGetError::InvalidValue)?);
+        }
+
+        Ok(Some(stack))
     }
 }
 
@@ -221,6 +289,41 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    #[test]
+    fn test_slot_roundtrip() {
+        let mut stack = ItemStack::new(Item::DiamondSword, 1);
+        stack.add_enchantment("minecraft:sharpness", 5);
+        stack.set_custom_name(Some(String::from(r#"{"text":"Excalibur"}"#)));
+
+        let mut buf = BytesMut::new();
+        buf.push_slot(&Some(stack.clone()));
+        buf.push_slot(&Some(ItemStack::new(Item::Stone, 64)));
+        buf.push_slot(&None);
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.try_get_slot(), Ok(Some(stack)));
+        assert_eq!(
+            cursor.try_get_slot(),
+            Ok(Some(ItemStack::new(Item::Stone, 64)))
+        );
+        assert_eq!(cursor.try_get_slot(), Ok(None));
+        assert!(!cursor.has_remaining());
+    }
+
+    #[test]
+    fn test_uuid_roundtrip() {
+        let uuid = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+
+        let mut buf = BytesMut::new();
+        buf.push_uuid(&uuid);
+        buf.push_i8(1);
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.try_get_uuid(), Ok(uuid));
+        assert_eq!(cursor.try_get_i8(), Ok(1));
+        assert_eq!(cursor.try_get_uuid(), Err(TryGetError::NotEnoughBytes));
+    }
+
     #[test]
     fn test_read_var_int() {
         // Examples from wiki.vg
@@ -228,4 +331,16 @@ mod tests {
         buf.extend_from_slice(&[0xff, 0x01]);
         assert_eq!(Cursor::new(&buf).try_get_var_int(), Ok(255));
     }
+
+    #[test]
+    fn test_var_long_roundtrip() {
+        let mut buf = BytesMut::new();
+        assert_eq!(buf.push_var_long(2_147_483_648), 5);
+        assert_eq!(buf.push_var_long(-1), 10);
+
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(cursor.try_get_var_long(), Ok(2_147_483_648));
+        assert_eq!(cursor.try_get_var_long(), Ok(-1));
+        assert!(!cursor.has_remaining());
+    }
 }
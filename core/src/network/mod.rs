@@ -1,7 +1,14 @@
 pub mod codec;
 pub mod mctypes;
 pub mod packet;
+pub mod pool;
 
+/// Downcasts a packet to its concrete type. Encoded
+/// packets are cast to the type of the packet they contain.
 pub fn cast_packet<P: packet::Packet + 'static + Send>(packet: &dyn packet::Packet) -> &P {
+    let packet = match packet.as_any().downcast_ref::<packet::EncodedPacket>() {
+        Some(encoded) => encoded.packet(),
+        None => packet,
+    };
     packet.as_any().downcast_ref().unwrap()
 }
@@ -0,0 +1,112 @@
+//! Packets which are serialized once and then shared
+//! between connections.
+
+use super::{AsAny, Packet, PacketType};
+use crate::network::pool::BUFFER_POOL;
+use bytes::{Bytes, BytesMut};
+use std::any::Any;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// A packet whose data has already been serialized.
+///
+/// The serialized data is stored in a reference-counted
+/// `Bytes` buffer, so cloning an `EncodedPacket` or sending it
+/// to several connections never copies or reserializes
+/// the data. This is useful for large packets, such as chunk
+/// data, which are broadcast to many players.
+///
+/// The codec recognizes encoded packets and writes (or compresses)
+/// their data straight from the shared buffer. The buffer is taken
+/// from the buffer pool and returned to it once the last clone
+/// of the packet is dropped.
+#[derive(Clone)]
+pub struct EncodedPacket {
+    /// The original packet.
+    packet: Arc<dyn Packet>,
+    /// The serialized packet data, excluding the packet ID.
+    data: Bytes,
+}
+
+impl EncodedPacket {
+    /// Serializes the given packet. If the packet
+    /// has already been encoded, it is returned as is.
+    pub fn new(packet: Box<dyn Packet>) -> Self {
+        if let Some(encoded) = packet.as_any().downcast_ref::<EncodedPacket>() {
+            return encoded.clone();
+        }
+
+        let mut data = BUFFER_POOL.get(0);
+        packet.write_to(&mut data);
+
+        Self {
+            packet: Arc::from(packet),
+            data: data.freeze(),
+        }
+    }
+
+    /// Returns the packet which was encoded.
+    pub fn packet(&self) -> &dyn Packet {
+        &*self.packet
+    }
+
+    /// Returns the serialized packet data, excluding the packet ID.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+impl Drop for EncodedPacket {
+    fn drop(&mut self) {
+        let data = std::mem::replace(&mut self.data, Bytes::new());
+        // Only succeeds for the last clone.
+        if let Ok(buf) = data.try_mut() {
+            BUFFER_POOL.put(buf);
+        }
+    }
+}
+
+impl AsAny for EncodedPacket {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Packet for EncodedPacket {
+    fn read_from(&mut self, _buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.extend_from_slice(&self.data);
+    }
+
+    fn ty(&self) -> PacketType {
+        self.packet.ty()
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::packet::implementation::ChangeGameState;
+
+    #[test]
+    fn test_encoded_packet() {
+        let packet = ChangeGameState::new(7, 1.0);
+        let mut expected = BytesMut::new();
+        packet.write_to(&mut expected);
+
+        let encoded = EncodedPacket::new(Box::new(packet));
+        assert_eq!(encoded.ty(), PacketType::ChangeGameState);
+        assert_eq!(encoded.data().as_ref(), expected.as_ref());
+
+        // Encoding again shares the existing buffer.
+        let reencoded = EncodedPacket::new(encoded.box_clone());
+        assert_eq!(reencoded.data().as_ptr(), encoded.data().as_ptr());
+    }
+}
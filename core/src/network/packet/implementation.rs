@@ -3,8 +3,11 @@ use super::*;
 use crate::bytes_ext::{BytesExt, BytesMutExt};
 use crate::entitymeta::{EntityMetaIo, EntityMetadata};
 use crate::inventory::ItemStack;
+use crate::nbt;
 use crate::network::packet::PacketStage::Play;
+use crate::network::pool::BUFFER_POOL;
 use crate::prelude::*;
+use crate::recipe::{CookingMethod, Ingredient, Recipe, RecipeKind};
 use crate::world::chunk::Chunk;
 use crate::{Biome, ClientboundAnimation, Hand};
 use bytes::{Buf, BufMut};
@@ -635,7 +638,7 @@ pub struct UpdateStructureBlock {
     pub rotation: VarInt,
     pub metadata: String,
     pub integrity: f32,
-    // TODO seed: VarLong,
+    pub seed: VarLong,
     pub flags: u8,
 }
 
@@ -972,11 +975,43 @@ pub struct BlockBreakAnimation {
     pub destroy_stage: i8,
 }
 
-#[derive(Default, AsAny, new, Packet, Clone)]
+#[derive(Default, AsAny, new, Clone)]
 pub struct UpdateBlockEntity {
     pub location: BlockPosition,
+    /// The type of block entity being updated,
+    /// e.g. 7 for structure blocks.
     pub action: u8,
-    // TODO pub data: NbtTag
+    /// The data of the block entity. If `None`,
+    /// the block entity is removed.
+    pub data: Option<nbt::Value>,
+}
+
+impl Packet for UpdateBlockEntity {
+    fn read_from(&mut self, buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.push_position(&self.location);
+        buf.push_u8(self.action);
+
+        match &self.data {
+            Some(data) => {
+                let mut temp = vec![];
+                nbt::write_root(&mut temp, "", data).unwrap(); // Block entity data is always a compound
+                buf.extend_from_slice(&temp);
+            }
+            None => buf.push_u8(0), // TAG_End
+        }
+    }
+
+    fn ty(&self) -> PacketType {
+        PacketType::UpdateBlockEntity
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        box_clone_impl!(self);
+    }
 }
 
 #[derive(Default, AsAny, new, Packet, Clone)]
@@ -1126,12 +1161,42 @@ pub struct ConfirmTransactionClientbound {
 }
 
 #[derive(Default, AsAny, new, Packet, Clone)]
+pub struct CloseWindowClientbound {
+    pub window_id: u8,
+}
+
+#[derive(Default, AsAny, new, Clone)]
 pub struct OpenWindow {
     pub window_id: u8,
     pub window_type: String,
     pub window_title: String, // Chat
     pub number_of_slots: u8,
-    pub entity_id: i32,
+    pub entity_id: i32, // Only sent for horse windows
+}
+
+impl Packet for OpenWindow {
+    fn read_from(&mut self, buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.push_u8(self.window_id);
+        buf.push_string(&self.window_type);
+        buf.push_string(&self.window_title);
+        buf.push_u8(self.number_of_slots);
+
+        if self.window_type == "EntityHorse" {
+            buf.push_i32(self.entity_id);
+        }
+    }
+
+    fn ty(&self) -> PacketType {
+        PacketType::OpenWindow
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        box_clone_impl!(self);
+    }
 }
 
 #[derive(Default, AsAny, new, Clone)]
@@ -1302,6 +1367,14 @@ pub struct KeepAliveClientbound {
 #[derive(Default, AsAny, new, Clone)]
 pub struct ChunkData {
     pub chunk: Chunk,
+    /// Whether to send sky light, which clients
+    /// only expect in the Overworld environment.
+    pub sky_light: bool,
+    /// Whether this is a full chunk, which includes biomes.
+    /// Otherwise, clients only replace the sections present
+    /// in `chunk` and keep the rest.
+    #[new(value = "true")]
+    pub full_chunk: bool,
 }
 
 impl Packet for ChunkData {
@@ -1312,7 +1385,7 @@ impl Packet for ChunkData {
     fn write_to(&self, buf: &mut BytesMut) {
         buf.push_i32(self.chunk.position().x);
         buf.push_i32(self.chunk.position().z);
-        buf.push_bool(true); // Full chunk - assume true
+        buf.push_bool(self.full_chunk);
 
         // Produce primary bit mask
         let mut primary_mask = {
@@ -1327,60 +1400,72 @@ impl Packet for ChunkData {
 
         buf.push_var_int(primary_mask as i32);
 
-        // TODO: approximate appropriate capacity
-        let mut temp_buf = BytesMut::new();
-
-        for section in self.chunk.sections() {
-            if let Some(section) = section {
-                temp_buf.push_u8(section.bits_per_block());
-
-                let palette = section.palette();
-                if let Some(palette) = palette {
-                    let mut palette_buf = BytesMut::with_capacity(palette.len() + 4);
-                    for val in palette {
-                        palette_buf.push_var_int(i32::from(*val));
-                    }
-
-                    temp_buf.push_var_int(palette.len() as i32);
-                    temp_buf.extend_from_slice(&palette_buf);
-                }
-
-                let data = section.data();
-                let data = data.inner();
-                temp_buf.push_var_int(data.len() as i32);
-
-                temp_buf.reserve(data.len());
-                for val in data {
-                    temp_buf.push_u64(*val);
+        // Sections are written almost exactly as they are stored,
+        // so the size of the data can be computed up front.
+        let sections: Vec<_> = self.chunk.sections().into_iter().flatten().collect();
+        let light_len = if self.sky_light { 4096 } else { 2048 };
+        let capacity = sections
+            .iter()
+            .map(|section| {
+                let palette_len = section.palette().map_or(0, |palette| 5 + palette.len() * 3);
+                1 + palette_len + 5 + section.data().inner().len() * 8 + light_len
+            })
+            .sum::<usize>()
+            + if self.full_chunk { 256 * 4 } else { 0 };
+        let mut temp_buf = BUFFER_POOL.get(capacity);
+
+        for section in sections {
+            temp_buf.push_u8(section.bits_per_block());
+
+            if let Some(palette) = section.palette() {
+                temp_buf.push_var_int(palette.len() as i32);
+                for val in palette {
+                    temp_buf.push_var_int(i32::from(*val));
                 }
+            }
 
-                // Light
-                let sky_light_data = section.sky_light();
-                let block_light_data = section.block_light();
-
-                block_light_data
-                    .inner()
-                    .iter()
-                    .chain(sky_light_data.inner().iter())
-                    .for_each(|data| {
-                        temp_buf.reserve(8);
-                        temp_buf.put_u64_le(*data);
-                    });
+            let data = section.data().inner();
+            temp_buf.push_var_int(data.len() as i32);
+            for val in data {
+                temp_buf.push_u64(*val);
             }
+
+            // Light
+            let sky_light_data: &[u64] = if self.sky_light {
+                &section.sky_light().inner()[..]
+            } else {
+                &[]
+            };
+            temp_buf.reserve(light_len);
+            section
+                .block_light()
+                .inner()
+                .iter()
+                .chain(sky_light_data.iter())
+                .for_each(|data| temp_buf.put_u64_le(*data));
         }
 
         // Biomes
-        temp_buf.reserve(256 * 4);
-        self.chunk
-            .biomes()
-            .iter()
-            .map(|biome| biome.protocol_id())
-            .for_each(|id| temp_buf.push_i32(id));
+        if self.full_chunk {
+            self.chunk
+                .biomes()
+                .iter()
+                .map(|biome| biome.protocol_id())
+                .for_each(|id| temp_buf.push_i32(id));
+        }
 
         buf.push_var_int(temp_buf.len() as i32);
         buf.extend_from_slice(&temp_buf);
-
-        buf.push_var_int(0); // Block entities — TODO
+        BUFFER_POOL.put(temp_buf);
+
+        // Block entities
+        let block_entities: Vec<_> = self.chunk.block_entities().map(|(_, data)| data).collect();
+        buf.push_var_int(block_entities.len() as i32);
+        for data in block_entities {
+            let mut temp = vec![];
+            nbt::write_root(&mut temp, "", data).unwrap(); // Block entity data is always a compound
+            buf.extend_from_slice(&temp);
+        }
     }
 
     fn ty(&self) -> PacketType {
@@ -1425,7 +1510,85 @@ pub struct JoinGame {
     pub reduced_debug_info: bool,
 }
 
-// TODO MapData
+/// Updates the contents of a map item: its icons
+/// and, optionally, a rectangle of its pixels.
+#[derive(Default, AsAny, new, Clone)]
+pub struct MapData {
+    pub map_id: VarInt,
+    pub scale: i8,
+    pub tracking_position: bool,
+    pub icons: Vec<MapIcon>,
+    pub pixels: Option<MapPixels>,
+}
+
+/// An icon displayed on a map, such as a player marker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapIcon {
+    pub ty: VarInt,
+    /// The position of the icon, from -128 to 127
+    /// in both directions, with 0 at the center.
+    pub x: i8,
+    pub z: i8,
+    /// The rotation of the icon, from 0 to 15,
+    /// in steps of 22.5 degrees clockwise from south.
+    pub direction: u8,
+    pub display_name: Option<String>,
+}
+
+/// A rectangle of updated map pixels, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapPixels {
+    pub columns: u8,
+    pub rows: u8,
+    pub x: u8,
+    pub z: u8,
+    pub data: Vec<u8>,
+}
+
+impl Packet for MapData {
+    fn read_from(&mut self, buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.push_var_int(self.map_id);
+        buf.push_i8(self.scale);
+        buf.push_bool(self.tracking_position);
+
+        buf.push_var_int(self.icons.len() as i32);
+        for icon in &self.icons {
+            buf.push_var_int(icon.ty);
+            buf.push_i8(icon.x);
+            buf.push_i8(icon.z);
+            buf.push_u8(icon.direction);
+            buf.push_bool(icon.display_name.is_some());
+            if let Some(display_name) = &icon.display_name {
+                buf.push_string(display_name);
+            }
+        }
+
+        match &self.pixels {
+            Some(pixels) if pixels.columns > 0 => {
+                buf.push_u8(pixels.columns);
+                buf.push_u8(pixels.rows);
+                buf.push_u8(pixels.x);
+                buf.push_u8(pixels.z);
+                buf.push_var_int(pixels.data.len() as i32);
+                buf.extend_from_slice(&pixels.data);
+            }
+            _ => buf.push_u8(0),
+        }
+    }
+
+    fn ty(&self) -> PacketType {
+        PacketType::MapData
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        box_clone_impl!(self);
+    }
+}
+
 // TODO EntityPacket
 
 #[derive(Default, AsAny, new, Packet, Clone)]
@@ -1632,7 +1795,52 @@ pub struct UseBed {
     pub location: BlockPosition,
 }
 
-// TODO Unlock Recipes
+/// Unlocks recipes in the recipe book of a player. The recipes
+/// must have been declared using the Declare Recipes packet.
+#[derive(Default, AsAny, new, Clone)]
+pub struct UnlockRecipes {
+    /// 0 to initialize the recipe book, 1 to add
+    /// recipes and 2 to remove recipes.
+    pub action: VarInt,
+    pub crafting_book_open: bool,
+    pub filtering_craftable: bool,
+    pub recipe_ids: Vec<String>,
+    /// The recipes shown as newly unlocked when
+    /// the recipe book is initialized.
+    pub highlighted_ids: Vec<String>,
+}
+
+impl Packet for UnlockRecipes {
+    fn read_from(&mut self, buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.push_var_int(self.action);
+        buf.push_bool(self.crafting_book_open);
+        buf.push_bool(self.filtering_craftable);
+
+        buf.push_var_int(self.recipe_ids.len() as i32);
+        for id in &self.recipe_ids {
+            buf.push_string(id);
+        }
+
+        if self.action == 0 {
+            buf.push_var_int(self.highlighted_ids.len() as i32);
+            for id in &self.highlighted_ids {
+                buf.push_string(id);
+            }
+        }
+    }
+
+    fn ty(&self) -> PacketType {
+        PacketType::UnlockRecipes
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        box_clone_impl!(self);
+    }
+}
 
 #[derive(Default, AsAny, new, Clone)]
 pub struct DestroyEntities {
@@ -1727,6 +1935,21 @@ pub struct EntityEquipment {
     pub item: Slot,
 }
 
+#[derive(Default, AsAny, new, Packet, Clone)]
+pub struct SetExperience {
+    /// Progress towards the next level, between 0 and 1.
+    pub experience_bar: f32,
+    pub level: VarInt,
+    pub total_experience: VarInt,
+}
+
+#[derive(Default, AsAny, new, Packet, Clone)]
+pub struct UpdateHealth {
+    pub health: f32,
+    pub food: VarInt,
+    pub food_saturation: f32,
+}
+
 // TODO Select Advancement Tab
 // TODO World Border
 
@@ -1747,3 +1970,126 @@ pub struct CollectItem {
     pub collector: VarInt,
     pub count: VarInt,
 }
+
+#[derive(Default, AsAny, new, Packet, Clone)]
+pub struct EntityEffect {
+    pub entity_id: VarInt,
+    pub effect_id: i8,
+    pub amplifier: i8,
+    pub duration: VarInt,
+    /// 0x01 if the effect is ambient, e.g. from a beacon;
+    /// 0x02 if particles should be shown.
+    pub flags: i8,
+}
+
+#[derive(Default, AsAny, new, Packet, Clone)]
+pub struct EntityTeleport {
+    pub entity_id: VarInt,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: u8,
+    pub pitch: u8,
+    pub on_ground: bool,
+}
+
+/// Declares the recipes known to the server. Recipe types
+/// which do not exist in 1.13.2, namely stonecutting, smithing
+/// and cooking methods other than smelting, are omitted.
+#[derive(Default, AsAny, new, Clone)]
+pub struct DeclareRecipes {
+    pub recipes: Vec<Recipe>,
+}
+
+impl DeclareRecipes {
+    /// Returns whether a recipe can be sent to 1.13.2 clients.
+    pub fn is_supported(recipe: &Recipe) -> bool {
+        match &recipe.kind {
+            RecipeKind::Shaped { .. } | RecipeKind::Shapeless { .. } | RecipeKind::Special(_) => {
+                true
+            }
+            RecipeKind::Cooking { method, .. } => *method == CookingMethod::Smelting,
+            RecipeKind::Stonecutting { .. } | RecipeKind::Smithing { .. } => false,
+        }
+    }
+}
+
+fn push_ingredient(buf: &mut BytesMut, ingredient: &Ingredient) {
+    buf.push_var_int(ingredient.0.len() as i32);
+    for item in &ingredient.0 {
+        buf.push_slot(&Some(ItemStack::new(*item, 1)));
+    }
+}
+
+impl Packet for DeclareRecipes {
+    fn read_from(&mut self, buf: &mut Cursor<&[u8]>) -> Result<(), failure::Error> {
+        unimplemented!()
+    }
+
+    fn write_to(&self, buf: &mut BytesMut) {
+        let recipes: Vec<&Recipe> = self
+            .recipes
+            .iter()
+            .filter(|recipe| Self::is_supported(recipe))
+            .collect();
+
+        buf.push_var_int(recipes.len() as i32);
+        for recipe in recipes {
+            buf.push_string(&recipe.id);
+
+            match &recipe.kind {
+                RecipeKind::Shaped {
+                    width,
+                    height,
+                    ingredients,
+                    result,
+                } => {
+                    buf.push_string("crafting_shaped");
+                    buf.push_var_int(*width as i32);
+                    buf.push_var_int(*height as i32);
+                    buf.push_string(&recipe.group);
+                    for ingredient in ingredients {
+                        push_ingredient(buf, ingredient);
+                    }
+                    buf.push_slot(&Some(result.clone()));
+                }
+                RecipeKind::Shapeless {
+                    ingredients,
+                    result,
+                } => {
+                    buf.push_string("crafting_shapeless");
+                    buf.push_string(&recipe.group);
+                    buf.push_var_int(ingredients.len() as i32);
+                    for ingredient in ingredients {
+                        push_ingredient(buf, ingredient);
+                    }
+                    buf.push_slot(&Some(result.clone()));
+                }
+                RecipeKind::Cooking {
+                    ingredient,
+                    result,
+                    experience,
+                    cooking_time,
+                    ..
+                } => {
+                    buf.push_string("smelting");
+                    buf.push_string(&recipe.group);
+                    push_ingredient(buf, ingredient);
+                    buf.push_slot(&Some(result.clone()));
+                    buf.push_f32(*experience);
+                    buf.push_var_int(*cooking_time as i32);
+                }
+                RecipeKind::Special(ty) => buf.push_string(ty),
+                RecipeKind::Stonecutting { .. } | RecipeKind::Smithing { .. } => unreachable!(),
+            }
+        }
+    }
+
+    fn ty(&self) -> PacketType {
+        PacketType::DeclareRecipes
+    }
+
+    fn box_clone(&self) -> Box<dyn Packet> {
+        box_clone_impl!(self);
+    }
+}
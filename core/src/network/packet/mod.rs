@@ -1,3 +1,4 @@
+mod encoded;
 #[allow(unused)]
 #[allow(clippy::too_many_arguments)]
 pub mod implementation;
@@ -6,6 +7,8 @@ use hashbrown::HashMap;
 use std::any::Any;
 use std::io::Cursor;
 
+pub use encoded::EncodedPacket;
+
 pub trait AsAny {
     fn as_any(&self) -> &dyn Any;
 }
@@ -451,11 +454,36 @@ lazy_static! {
             PacketType::AnimationClientbound,
         );
 
+        m.insert(
+            PacketId(0x09, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::UpdateBlockEntity,
+        );
         m.insert(
             PacketId(0x0E, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::ChatMessageClientbound,
         );
 
+        m.insert(
+            PacketId(0x12, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::ConfirmTransactionClientbound,
+        );
+        m.insert(
+            PacketId(0x13, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::CloseWindowClientbound,
+        );
+        m.insert(
+            PacketId(0x14, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::OpenWindow,
+        );
+        m.insert(
+            PacketId(0x15, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::WindowItems,
+        );
+        m.insert(
+            PacketId(0x16, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::WindowProperty,
+        );
+
         m.insert(
             PacketId(0x17, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::SetSlot,
@@ -465,6 +493,10 @@ lazy_static! {
             PacketId(0x1B, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::DisconnectPlay,
         );
+        m.insert(
+            PacketId(0x1C, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::EntityStatus,
+        );
 
         m.insert(
             PacketId(0x1F, PacketDirection::Clientbound, PacketStage::Play),
@@ -496,6 +528,11 @@ lazy_static! {
             PacketType::JoinGame,
         );
 
+        m.insert(
+            PacketId(0x26, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::MapData,
+        );
+
         m.insert(
             PacketId(0x28, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::EntityRelativeMove,
@@ -521,10 +558,18 @@ lazy_static! {
             PacketType::PlayerPositionAndLookClientbound,
         );
 
+        m.insert(
+            PacketId(0x34, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::UnlockRecipes,
+        );
         m.insert(
             PacketId(0x35, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::DestroyEntities,
         );
+        m.insert(
+            PacketId(0x36, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::RemoveEntityEffect,
+        );
 
         m.insert(
             PacketId(0x37, PacketDirection::Clientbound, PacketStage::Play),
@@ -551,6 +596,16 @@ lazy_static! {
             PacketType::EntityEquipment,
         );
 
+        m.insert(
+            PacketId(0x43, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::SetExperience,
+        );
+
+        m.insert(
+            PacketId(0x44, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::UpdateHealth,
+        );
+
         m.insert(
             PacketId(0x49, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::SpawnPosition,
@@ -565,6 +620,15 @@ lazy_static! {
             PacketId(0x4F, PacketDirection::Clientbound, PacketStage::Play),
             PacketType::CollectItem,
         );
+        m.insert(
+            PacketId(0x53, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::EntityEffect,
+        );
+
+        m.insert(
+            PacketId(0x54, PacketDirection::Clientbound, PacketStage::Play),
+            PacketType::DeclareRecipes,
+        );
 
         m
     };
@@ -0,0 +1,120 @@
+//! A pool of reusable buffers for encoding and decoding packets.
+//!
+//! Buffers are grouped into size classes. `BufferPool::get`
+//! returns a buffer from the smallest class which fits the requested
+//! capacity, allocating a new one if none is available, and
+//! `BufferPool::put` returns a buffer to the largest class
+//! it fits into. Very large buffers are not pooled, so that a single
+//! large packet does not keep its memory allocated forever.
+
+use bytes::BytesMut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+lazy_static! {
+    /// The global buffer pool.
+    pub static ref BUFFER_POOL: BufferPool = BufferPool::default();
+}
+
+/// Capacities of the buffers in each size class.
+const SIZE_CLASSES: [usize; 4] = [512, 4096, 32_768, 262_144];
+
+/// The maximum number of buffers retained in each size class.
+const MAX_BUFFERS_PER_CLASS: usize = 64;
+
+/// A pool of `BytesMut` buffers.
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    classes: [Mutex<Vec<BytesMut>>; 4],
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BufferPool {
+    /// Returns an empty buffer with at least the given capacity.
+    pub fn get(&self, capacity: usize) -> BytesMut {
+        let class = match SIZE_CLASSES.iter().position(|size| *size >= capacity) {
+            Some(class) => class,
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return BytesMut::with_capacity(capacity);
+            }
+        };
+
+        match self.classes[class].lock().unwrap().pop() {
+            Some(buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(SIZE_CLASSES[class])
+            }
+        }
+    }
+
+    /// Returns a buffer to the pool. Its contents are discarded.
+    pub fn put(&self, mut buf: BytesMut) {
+        let capacity = buf.capacity();
+        if capacity > SIZE_CLASSES[SIZE_CLASSES.len() - 1] * 2 {
+            return;
+        }
+
+        let class = match SIZE_CLASSES.iter().rposition(|size| *size <= capacity) {
+            Some(class) => class,
+            None => return,
+        };
+
+        let mut buffers = self.classes[class].lock().unwrap();
+        if buffers.len() < MAX_BUFFERS_PER_CLASS {
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+
+    /// Returns the number of requests which were served
+    /// with a pooled buffer.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of requests which required
+    /// a buffer to be allocated.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::default();
+
+        let mut buf = pool.get(1000);
+        assert!(buf.capacity() >= 4096);
+        assert_eq!(pool.misses(), 1);
+
+        buf.extend_from_slice(&[1, 2, 3]);
+        pool.put(buf);
+
+        let buf = pool.get(2000);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 4096);
+        assert_eq!(pool.hits(), 1);
+
+        // Smaller requests don't use larger classes.
+        pool.put(buf);
+        pool.get(100);
+        assert_eq!(pool.hits(), 1);
+        assert_eq!(pool.misses(), 2);
+
+        // Requests larger than any class are not pooled.
+        let buf = pool.get(10_000_000);
+        assert!(buf.capacity() >= 10_000_000);
+        pool.put(buf);
+        assert_eq!(pool.misses(), 3);
+    }
+}
@@ -0,0 +1,219 @@
+//! Typed representations of crafting, cooking
+//! and other recipes.
+
+use crate::{Item, ItemStack};
+
+/// A set of items, any of which is accepted in a recipe slot.
+/// An empty ingredient matches only empty slots.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ingredient(pub Vec<Item>);
+
+impl Ingredient {
+    /// Returns whether the given slot
+    /// contents satisfy this ingredient.
+    pub fn test(&self, item: Option<Item>) -> bool {
+        match item {
+            Some(item) => self.0.contains(&item),
+            None => self.0.is_empty(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A recipe, identified by a namespaced
+/// ID such as `minecraft:stick`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipe {
+    pub id: String,
+    /// Recipes with the same group are
+    /// shown together in the recipe book.
+    pub group: String,
+    pub kind: RecipeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Copy, Eq, Hash)]
+pub enum CookingMethod {
+    Smelting,
+    Blasting,
+    Smoking,
+    CampfireCooking,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecipeKind {
+    /// A crafting recipe with a fixed arrangement. `ingredients`
+    /// is in row-major order and has `width * height` entries.
+    Shaped {
+        width: u32,
+        height: u32,
+        ingredients: Vec<Ingredient>,
+        result: ItemStack,
+    },
+    /// A crafting recipe where the
+    /// arrangement of ingredients is irrelevant.
+    Shapeless {
+        ingredients: Vec<Ingredient>,
+        result: ItemStack,
+    },
+    Cooking {
+        method: CookingMethod,
+        ingredient: Ingredient,
+        result: ItemStack,
+        experience: f32,
+        /// The number of ticks to cook for.
+        cooking_time: u32,
+    },
+    Stonecutting {
+        ingredient: Ingredient,
+        result: ItemStack,
+    },
+    Smithing {
+        base: Ingredient,
+        addition: Ingredient,
+        result: ItemStack,
+    },
+    /// A recipe implemented in code, such as
+    /// dyeing armor, with its type, for example
+    /// `crafting_special_armordye`.
+    Special(String),
+}
+
+impl Recipe {
+    /// Returns the result of this recipe,
+    /// or `None` for special recipes.
+    pub fn result(&self) -> Option<&ItemStack> {
+        match &self.kind {
+            RecipeKind::Shaped { result, .. }
+            | RecipeKind::Shapeless { result, .. }
+            | RecipeKind::Cooking { result, .. }
+            | RecipeKind::Stonecutting { result, .. }
+            | RecipeKind::Smithing { result, .. } => Some(result),
+            RecipeKind::Special(_) => None,
+        }
+    }
+
+    /// Returns whether this recipe matches a crafting grid of the
+    /// given width, with slots in row-major order. Shaped recipes
+    /// may be placed anywhere in the grid and mirrored horizontally.
+    pub fn matches_grid(&self, grid: &[Option<Item>], grid_width: usize) -> bool {
+        match &self.kind {
+            RecipeKind::Shaped {
+                width,
+                height,
+                ingredients,
+                ..
+            } => {
+                let (width, height) = (*width as usize, *height as usize);
+                let grid_height = grid.len() / grid_width;
+                if width > grid_width || height > grid_height {
+                    return false;
+                }
+
+                for x in 0..=grid_width - width {
+                    for y in 0..=grid_height - height {
+                        for mirrored in &[false, true] {
+                            let matches = (0..grid.len()).all(|index| {
+                                let (gx, gy) = (index % grid_width, index / grid_width);
+                                let ingredient =
+                                    if gx >= x && gx < x + width && gy >= y && gy < y + height {
+                                        let rx = if *mirrored {
+                                            width - 1 - (gx - x)
+                                        } else {
+                                            gx - x
+                                        };
+                                        &ingredients[(gy - y) * width + rx]
+                                    } else {
+                                        &EMPTY
+                                    };
+                                ingredient.test(grid[index])
+                            });
+                            if matches {
+                                return true;
+                            }
+                        }
+                    }
+                }
+                false
+            }
+            RecipeKind::Shapeless { ingredients, .. } => {
+                let mut remaining: Vec<&Ingredient> = ingredients.iter().collect();
+                for item in grid.iter().filter_map(|item| *item) {
+                    match remaining
+                        .iter()
+                        .position(|ingredient| ingredient.test(Some(item)))
+                    {
+                        Some(index) => {
+                            remaining.swap_remove(index);
+                        }
+                        None => return false,
+                    }
+                }
+                remaining.is_empty()
+            }
+            _ => false,
+        }
+    }
+}
+
+static EMPTY: Ingredient = Ingredient(Vec::new());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(kind: RecipeKind) -> Recipe {
+        Recipe {
+            id: String::from("test:recipe"),
+            group: String::new(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_shaped() {
+        let planks = Ingredient(vec![Item::OakPlanks, Item::BirchPlanks]);
+        let stick = Ingredient(vec![Item::Stick]);
+        let axe = recipe(RecipeKind::Shaped {
+            width: 2,
+            height: 3,
+            ingredients: vec![
+                planks.clone(),
+                planks.clone(),
+                planks,
+                stick.clone(),
+                Ingredient::default(),
+                stick,
+            ],
+            result: ItemStack::new(Item::WoodenAxe, 1),
+        });
+
+        let (p, s) = (Some(Item::OakPlanks), Some(Item::Stick));
+        assert!(axe.matches_grid(&[p, p, None, p, s, None, None, s, None], 3));
+        assert!(axe.matches_grid(&[None, p, p, None, s, p, None, s, None], 3));
+        assert!(!axe.matches_grid(&[p, p, None, p, s, None, None, s, s], 3));
+        assert!(!axe.matches_grid(&[p, p, p, s], 2));
+    }
+
+    #[test]
+    fn test_shapeless() {
+        let book = recipe(RecipeKind::Shapeless {
+            ingredients: vec![
+                Ingredient(vec![Item::Paper]),
+                Ingredient(vec![Item::Paper]),
+                Ingredient(vec![Item::Paper]),
+                Ingredient(vec![Item::Leather]),
+            ],
+            result: ItemStack::new(Item::Book, 1),
+        });
+
+        let (p, l) = (Some(Item::Paper), Some(Item::Leather));
+        assert!(book.matches_grid(&[p, p, l, p], 2));
+        assert!(book.matches_grid(&[None, p, None, l, p, None, p, None, None], 3));
+        assert!(!book.matches_grid(&[p, p, l, None], 2));
+        assert!(!book.matches_grid(&[p, p, l, l], 2));
+        assert_eq!(book.result(), Some(&ItemStack::new(Item::Book, 1)));
+    }
+}
@@ -1,6 +1,5 @@
+use crate::nbt::{self, Value};
 use crate::{Item, Position};
-use nbt::Value;
-use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "id")]
@@ -21,14 +20,20 @@ pub enum EntityData {
     Horse(AnimalData),
     #[serde(rename = "minecraft:llama")]
     Llama(AnimalData),
-    #[serde(rename = "minectaft:mooshroom")]
+    #[serde(rename = "minecraft:mooshroom")]
     Mooshroom(AnimalData),
-    #[serde(rename = "minecraft:rabbit}")]
+    #[serde(rename = "minecraft:rabbit")]
     Rabbit(AnimalData),
     #[serde(rename = "minecraft:squid")]
     Squid(AnimalData),
     #[serde(rename = "minecraft:donkey")]
     Donkey(AnimalData),
+    #[serde(rename = "minecraft:zombie_pigman")]
+    ZombiePigman(AnimalData),
+    #[serde(rename = "minecraft:zombie")]
+    Zombie(AnimalData),
+    #[serde(rename = "minecraft:skeleton")]
+    Skeleton(AnimalData),
 
     /// Fallback type for unknown entities
     #[serde(other)]
@@ -36,48 +41,16 @@ pub enum EntityData {
 }
 
 impl EntityData {
+    /// Converts the entity data to NBT.
+    ///
+    /// # Panics
+    /// Panics if the entity is of an unknown type.
     pub fn into_nbt_value(self) -> Value {
-        let mut map = HashMap::new();
-
-        map.insert(
-            String::from("id"),
-            Value::String(
-                match self {
-                    EntityData::Item(_) => "minecraft:item",
-                    EntityData::Arrow(_) => "minecraft:arrow",
-                    EntityData::Cow(_) => "minecraft:cow",
-                    EntityData::Pig(_) => "minecraft:pig",
-                    EntityData::Chicken(_) => "minecraft:chicken",
-                    EntityData::Sheep(_) => "minecraft:sheep",
-                    EntityData::Horse(_) => "minecraft:horse",
-                    EntityData::Llama(_) => "minecraft:llama",
-                    EntityData::Mooshroom(_) => "minecraft:mooshroom",
-                    EntityData::Rabbit(_) => "minecraft:rabbit",
-                    EntityData::Squid(_) => "minecraft:squid",
-                    EntityData::Donkey(_) => "minecraft:donkey",
-                    EntityData::Unknown => panic!("Cannot write unknown entities"),
-                }
-                .to_string(),
-            ),
-        );
-
-        match self {
-            EntityData::Item(data) => data.write_to_map(&mut map),
-            EntityData::Arrow(data) => data.write_to_map(&mut map),
-            EntityData::Cow(data) => data.write_to_map(&mut map),
-            EntityData::Pig(data) => data.write_to_map(&mut map),
-            EntityData::Chicken(data) => data.write_to_map(&mut map),
-            EntityData::Sheep(data) => data.write_to_map(&mut map),
-            EntityData::Horse(data) => data.write_to_map(&mut map),
-            EntityData::Llama(data) => data.write_to_map(&mut map),
-            EntityData::Mooshroom(data) => data.write_to_map(&mut map),
-            EntityData::Rabbit(data) => data.write_to_map(&mut map),
-            EntityData::Squid(data) => data.write_to_map(&mut map),
-            EntityData::Donkey(data) => data.write_to_map(&mut map),
-            EntityData::Unknown => unreachable!(),
+        if let EntityData::Unknown = self {
+            panic!("Cannot write unknown entities");
         }
 
-        Value::Compound(map)
+        nbt::to_value(&self).expect("Entity data is not valid NBT")
     }
 }
 
@@ -92,23 +65,6 @@ pub struct BaseEntityData {
     pub velocity: Vec<f64>,
 }
 
-impl BaseEntityData {
-    fn write_to_map(self, map: &mut HashMap<String, Value>) {
-        map.insert(
-            String::from("Pos"),
-            Value::List(self.position.into_iter().map(Value::Double).collect()),
-        );
-        map.insert(
-            String::from("Rotation"),
-            Value::List(self.rotation.into_iter().map(Value::Float).collect()),
-        );
-        map.insert(
-            String::from("Motion"),
-            Value::List(self.velocity.into_iter().map(Value::Double).collect()),
-        );
-    }
-}
-
 impl BaseEntityData {
     /// Creates a `BaseEntityData` from a position and velocity.
     pub fn new(pos: Position, velocity: glm::DVec3) -> Self {
@@ -163,11 +119,32 @@ impl Default for BaseEntityData {
 pub struct AnimalData {
     #[serde(flatten)]
     pub base: BaseEntityData,
+    /// Whether the entity never despawns.
+    #[serde(
+        rename = "PersistenceRequired",
+        default,
+        deserialize_with = "nbt::deserialize_bool"
+    )]
+    pub persistence_required: bool,
+    /// The custom name of the entity as a JSON text component,
+    /// which is set by name tags.
+    #[serde(
+        rename = "CustomName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_name: Option<String>,
 }
 
 impl AnimalData {
-    fn write_to_map(self, map: &mut HashMap<String, Value>) {
-        self.base.write_to_map(map);
+    /// Creates an `AnimalData` for an entity
+    /// which isn't persistent and has no name.
+    pub fn new(base: BaseEntityData) -> Self {
+        Self {
+            base,
+            persistence_required: false,
+            custom_name: None,
+        }
     }
 }
 
@@ -180,13 +157,6 @@ pub struct ItemData {
     pub item: String,
 }
 
-impl ItemData {
-    fn write_to_map(self, map: &mut HashMap<String, Value>) {
-        map.insert(String::from("Count"), Value::Byte(self.count as i8));
-        map.insert(String::from("id"), Value::String(self.item));
-    }
-}
-
 impl Default for ItemData {
     fn default() -> Self {
         Self {
@@ -212,22 +182,6 @@ pub struct ItemEntityData {
     pub item: ItemData,
 }
 
-impl ItemEntityData {
-    fn write_to_map(self, map: &mut HashMap<String, Value>) {
-        self.entity.write_to_map(map);
-
-        let mut item = HashMap::new();
-        self.item.write_to_map(&mut item);
-        map.insert(String::from("Item"), Value::Compound(item));
-
-        map.insert(String::from("Age"), Value::Short(self.age));
-        map.insert(
-            String::from("PickupDelay"),
-            Value::Byte(self.pickup_delay as i8),
-        );
-    }
-}
-
 /// Data for an Arrow entity (`minecraft:arrow`).
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ArrowEntityData {
@@ -236,19 +190,8 @@ pub struct ArrowEntityData {
     pub entity: BaseEntityData,
 
     // Arrow-specific tags
-
-    // TODO: Change this field to `bool` when issue with hematite_nbt is resolved.
-    // See: https://github.com/PistonDevelopers/hematite_nbt/issues/43
-    #[serde(rename = "crit")]
-    pub critical: u8,
-}
-
-impl ArrowEntityData {
-    fn write_to_map(self, map: &mut HashMap<String, Value>) {
-        self.entity.write_to_map(map);
-
-        map.insert(String::from("crit"), Value::Byte(self.critical as i8));
-    }
+    #[serde(rename = "crit", deserialize_with = "nbt::deserialize_bool")]
+    pub critical: bool,
 }
 
 #[cfg(test)]
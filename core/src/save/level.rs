@@ -27,13 +27,30 @@ pub struct LevelData {
     #[serde(default)]
     #[serde(rename = "BorderCenterZ")]
     pub border_center_z: f64,
-    #[serde(default)]
+    #[serde(default = "default_border_damage_per_block")]
     #[serde(rename = "BorderDamagePerBlock")]
     pub border_damage_per_block: f64,
+    #[serde(default = "default_border_safe_zone")]
     #[serde(rename = "BorderSafeZone")]
     pub border_safe_zone: f64,
+    #[serde(default = "default_border_size")]
     #[serde(rename = "BorderSize")]
     pub border_size: f64,
+    /// The size the border is moving towards.
+    #[serde(default = "default_border_size")]
+    #[serde(rename = "BorderSizeLerpTarget")]
+    pub border_size_lerp_target: f64,
+    /// The time, in milliseconds, until the border
+    /// reaches `border_size_lerp_target`.
+    #[serde(default)]
+    #[serde(rename = "BorderSizeLerpTime")]
+    pub border_size_lerp_time: i64,
+    #[serde(default = "default_border_warning_blocks")]
+    #[serde(rename = "BorderWarningBlocks")]
+    pub border_warning_blocks: f64,
+    #[serde(default = "default_border_warning_time")]
+    #[serde(rename = "BorderWarningTime")]
+    pub border_warning_time: f64,
 
     #[serde(rename = "clearWeatherTime")]
     pub clear_weather_time: i32,
@@ -78,15 +95,74 @@ pub struct LevelData {
     pub generator_name: String,
     #[serde(rename = "generatorOptions")]
     pub generator_options: Option<SuperflatGeneratorOptions>,
+
+    /// The game rules of the world. As in vanilla,
+    /// values are stored as strings, even for booleans
+    /// and numbers.
+    #[serde(default)]
+    #[serde(rename = "GameRules")]
+    pub game_rules: HashMap<String, String>,
+}
+
+fn default_border_damage_per_block() -> f64 {
+    0.2
+}
+
+fn default_border_safe_zone() -> f64 {
+    5.0
+}
+
+fn default_border_size() -> f64 {
+    60_000_000.0
 }
 
+fn default_border_warning_blocks() -> f64 {
+    5.0
+}
+
+fn default_border_warning_time() -> f64 {
+    15.0
+}
+
+/// The game rules of new worlds and their
+/// default values, as in vanilla 1.13.2.
+pub const DEFAULT_GAME_RULES: &[(&str, &str)] = &[
+    ("announceAdvancements", "true"),
+    ("commandBlockOutput", "true"),
+    ("disableElytraMovementCheck", "false"),
+    ("doDaylightCycle", "true"),
+    ("doEntityDrops", "true"),
+    ("doFireTick", "true"),
+    ("doLimitedCrafting", "false"),
+    ("doMobLoot", "true"),
+    ("doMobSpawning", "true"),
+    ("doTileDrops", "true"),
+    ("doWeatherCycle", "true"),
+    ("gameLoopFunction", "-"),
+    ("keepInventory", "false"),
+    ("logAdminCommands", "true"),
+    ("maxCommandChainLength", "65536"),
+    ("maxEntityCramming", "24"),
+    ("mobGriefing", "true"),
+    ("naturalRegeneration", "true"),
+    ("randomTickSpeed", "3"),
+    ("reducedDebugInfo", "false"),
+    ("sendCommandFeedback", "true"),
+    ("showDeathMessages", "true"),
+    ("spawnRadius", "10"),
+    ("spectatorsGenerateChunks", "true"),
+];
+
 /// Represents level version data.
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct LevelVersion {
     #[serde(rename = "Id")]
-    id: i32,
+    pub id: i32,
     #[serde(rename = "Name")]
-    name: String,
+    pub name: String,
+    #[serde(default)]
+    #[serde(rename = "Snapshot")]
+    pub snapshot: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -144,11 +220,40 @@ pub enum LevelGeneratorType {
 }
 
 impl LevelData {
+    /// Returns the value of the given game rule. If the rule
+    /// isn't set in the level file, its default value is returned.
+    pub fn game_rule(&self, name: &str) -> Option<&str> {
+        self.game_rules.get(name).map(String::as_str).or_else(|| {
+            DEFAULT_GAME_RULES
+                .iter()
+                .find(|(rule, _)| *rule == name)
+                .map(|(_, value)| *value)
+        })
+    }
+
+    /// Returns the value of a boolean game rule, or `false`
+    /// if the rule is unknown or isn't a boolean.
+    pub fn game_rule_bool(&self, name: &str) -> bool {
+        self.game_rule(name) == Some("true")
+    }
+
+    pub fn set_game_rule(&mut self, name: &str, value: &str) {
+        self.game_rules.insert(name.to_string(), value.to_string());
+    }
+
+    /// Returns the default game rules of new worlds.
+    pub fn default_game_rules() -> HashMap<String, String> {
+        DEFAULT_GAME_RULES
+            .iter()
+            .map(|(rule, value)| (rule.to_string(), value.to_string()))
+            .collect()
+    }
+
     pub fn generator_type(&self) -> LevelGeneratorType {
         match self.generator_name.to_lowercase().as_str() {
             "default" => LevelGeneratorType::Default,
             "flat" => LevelGeneratorType::Flat,
-            "largeBiomes" => LevelGeneratorType::LargeBiomes,
+            "largebiomes" => LevelGeneratorType::LargeBiomes,
             "amplified" => LevelGeneratorType::Amplified,
             "buffet" => LevelGeneratorType::Buffet,
             "debug_all_block_states" => LevelGeneratorType::Debug,
@@ -202,5 +307,62 @@ mod tests {
         assert_eq!(level.thunder_time, 5252);
         assert_eq!(level.generator_name, "default");
         assert!(level.generator_options.is_none());
+        assert_eq!(level.generator_type(), LevelGeneratorType::Default);
+        assert_eq!(level.version.name, "1.13.2");
+        assert!(level.game_rule_bool("doDaylightCycle"));
+        assert_eq!(level.game_rule("randomTickSpeed"), Some("3"));
+    }
+
+    #[test]
+    fn test_level_roundtrip() {
+        let cursor = Cursor::new(include_bytes!("level.dat").to_vec());
+        let mut level = deserialize_level_file(cursor).unwrap();
+        level.set_game_rule("keepInventory", "true");
+        level.border_size = 1000.0;
+        level.border_center_x = 25.5;
+
+        let mut buf = vec![];
+        save_level_file(
+            &Root {
+                data: level.clone(),
+            },
+            &mut buf,
+        )
+        .unwrap();
+        let read = deserialize_level_file(Cursor::new(buf)).unwrap();
+
+        assert_eq!(read.seed, level.seed);
+        assert_eq!(read.day_time, level.day_time);
+        assert_eq!(read.time, level.time);
+        assert_eq!(read.spawn_y, level.spawn_y);
+        assert_eq!(read.game_rules, level.game_rules);
+        assert!(read.game_rule_bool("keepInventory"));
+        assert!((read.border_size - 1000.0).abs() < std::f64::EPSILON);
+        assert!((read.border_center_x - 25.5).abs() < std::f64::EPSILON);
+        assert_eq!(read.version.id, level.version.id);
+    }
+
+    #[test]
+    fn test_game_rules() {
+        let mut level = LevelData::default();
+        assert!(level.game_rule_bool("doMobSpawning"));
+        assert!(!level.game_rule_bool("keepInventory"));
+        assert_eq!(level.game_rule("unknownRule"), None);
+
+        level.set_game_rule("doMobSpawning", "false");
+        assert!(!level.game_rule_bool("doMobSpawning"));
+        assert_eq!(
+            LevelData::default_game_rules().len(),
+            DEFAULT_GAME_RULES.len()
+        );
+    }
+
+    #[test]
+    fn test_generator_type() {
+        let mut level = LevelData::default();
+        level.generator_name = String::from("largeBiomes");
+        assert_eq!(level.generator_type(), LevelGeneratorType::LargeBiomes);
+        level.generator_name = String::from("FLAT");
+        assert_eq!(level.generator_type(), LevelGeneratorType::Flat);
     }
 }
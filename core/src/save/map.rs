@@ -0,0 +1,140 @@
+//! Loading and saving of the data of map items, stored in
+//! `data/map_<id>.dat` within the world directory, and of the
+//! counter from which new map IDs are allocated, stored in
+//! `data/idcounts.dat`.
+
+use crate::nbt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The width and height of a map, in pixels.
+pub const MAP_SIZE: usize = 128;
+
+/// The largest scale of a map. At scale `n`,
+/// each pixel covers `2^n` by `2^n` blocks.
+pub const MAX_SCALE: u8 = 4;
+
+const DATA_VERSION: i32 = 1631;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapRoot {
+    pub data: MapData,
+    #[serde(rename = "DataVersion", default)]
+    pub data_version: i32,
+}
+
+/// The contents of a single map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapData {
+    pub scale: i8,
+    pub dimension: i32,
+    #[serde(rename = "trackingPosition")]
+    pub tracking_position: bool,
+    #[serde(rename = "unlimitedTracking", default)]
+    pub unlimited_tracking: bool,
+    #[serde(rename = "xCenter")]
+    pub x_center: i32,
+    #[serde(rename = "zCenter")]
+    pub z_center: i32,
+    /// The color of each pixel, row by row.
+    #[serde(with = "nbt::array::byte")]
+    pub colors: Vec<i8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdCountsRoot {
+    pub data: IdCounts,
+    #[serde(rename = "DataVersion", default)]
+    pub data_version: i32,
+}
+
+/// The last allocated ID of each type of numbered data.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdCounts {
+    /// The ID of the most recently created map, or `None`
+    /// if no maps have been created yet.
+    pub map: Option<i32>,
+}
+
+fn load_from_file<R: Read>(reader: R) -> Result<MapData, nbt::Error> {
+    let root: MapRoot = nbt::from_gzip_reader(reader)?;
+    Ok(root.data)
+}
+
+pub fn load_map(world_dir: &Path, id: i32) -> Result<MapData, nbt::Error> {
+    let file = File::open(map_path(world_dir, id))?;
+    load_from_file(file)
+}
+
+fn save_to_file<W: Write>(mut writer: W, data: MapData) -> Result<(), nbt::Error> {
+    let root = MapRoot {
+        data,
+        data_version: DATA_VERSION,
+    };
+    nbt::to_gzip_writer(&mut writer, &root, None)
+}
+
+pub fn save_map(world_dir: &Path, id: i32, data: MapData) -> Result<(), nbt::Error> {
+    fs::create_dir_all(world_dir.join("data"))?;
+    let file = File::create(map_path(world_dir, id))?;
+    save_to_file(file, data)
+}
+
+/// Loads the ID counters of the world. If the world
+/// has none yet, the default counters are returned.
+pub fn load_id_counts(world_dir: &Path) -> Result<IdCounts, nbt::Error> {
+    let file = match File::open(id_counts_path(world_dir)) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(IdCounts::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let root: IdCountsRoot = nbt::from_gzip_reader(file)?;
+    Ok(root.data)
+}
+
+pub fn save_id_counts(world_dir: &Path, counts: IdCounts) -> Result<(), nbt::Error> {
+    fs::create_dir_all(world_dir.join("data"))?;
+    let file = File::create(id_counts_path(world_dir))?;
+    let root = IdCountsRoot {
+        data: counts,
+        data_version: DATA_VERSION,
+    };
+    nbt::to_gzip_writer(file, &root, None)
+}
+
+fn map_path(world_dir: &Path, id: i32) -> PathBuf {
+    world_dir.join("data").join(format!("map_{}.dat", id))
+}
+
+fn id_counts_path(world_dir: &Path) -> PathBuf {
+    world_dir.join("data").join("idcounts.dat")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_map_roundtrip() {
+        let mut colors = vec![0; MAP_SIZE * MAP_SIZE];
+        colors[1] = 11 * 4 + 2;
+        colors[MAP_SIZE * MAP_SIZE - 1] = -128;
+
+        let data = MapData {
+            scale: 2,
+            dimension: 0,
+            tracking_position: true,
+            unlimited_tracking: false,
+            x_center: 64,
+            z_center: -448,
+            colors,
+        };
+
+        let mut buf = vec![];
+        save_to_file(&mut buf, data.clone()).unwrap();
+        let read = load_from_file(Cursor::new(buf)).unwrap();
+        assert_eq!(read, data);
+    }
+}
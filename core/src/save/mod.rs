@@ -1,8 +1,12 @@
 //! Module containing functions for loading and saving to
 //! world saves. Currently includes region file loading,
-//! player data loading, and level data loading.
+//! player data loading, level data loading, map data and
+//! schematics, as well as the NBT format used by all of them.
 
 pub mod entity;
 pub mod level;
+pub mod map;
+pub mod nbt;
 pub mod player_data;
 pub mod region;
+pub mod schematic;
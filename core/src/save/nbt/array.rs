@@ -0,0 +1,58 @@
+//! Storage of sequences as NBT arrays rather than lists,
+//! for use with `#[serde(with = "...")]`:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Section {
+//!     #[serde(rename = "BlockStates", with = "nbt::array::long")]
+//!     states: Vec<i64>,
+//! }
+//! ```
+//!
+//! Other serializers store the sequences as usual.
+
+/// Newtype struct names recognized by the serializer.
+pub(super) const BYTE_ARRAY: &str = "__nbt_byte_array";
+pub(super) const INT_ARRAY: &str = "__nbt_int_array";
+pub(super) const LONG_ARRAY: &str = "__nbt_long_array";
+
+macro_rules! array_module {
+    ($name:ident, $ty:ty, $marker:ident, $doc:expr) => {
+        #[doc = $doc]
+        pub mod $name {
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            pub fn serialize<S: Serializer>(
+                values: &[$ty],
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct(super::$marker, values)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Vec<$ty>, D::Error> {
+                Vec::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+array_module!(
+    byte,
+    i8,
+    BYTE_ARRAY,
+    "Stores a `Vec<i8>` as a `TAG_Byte_Array`."
+);
+array_module!(
+    int,
+    i32,
+    INT_ARRAY,
+    "Stores a `Vec<i32>` as a `TAG_Int_Array`."
+);
+array_module!(
+    long,
+    i64,
+    LONG_ARRAY,
+    "Stores a `Vec<i64>` as a `TAG_Long_Array`."
+);
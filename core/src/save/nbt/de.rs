@@ -0,0 +1,284 @@
+//! Conversion of `Value`s to `Deserialize` types.
+
+use super::*;
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+};
+use std::collections::hash_map;
+use std::vec;
+
+/// Converts NBT to a value.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+struct ValueDeserializer(Value);
+
+impl<'de> Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Byte(x) => visitor.visit_i8(x),
+            Value::Short(x) => visitor.visit_i16(x),
+            Value::Int(x) => visitor.visit_i32(x),
+            Value::Long(x) => visitor.visit_i64(x),
+            Value::Float(x) => visitor.visit_f32(x),
+            Value::Double(x) => visitor.visit_f64(x),
+            Value::ByteArray(values) => visit_list(values.into_iter().map(Value::Byte), visitor),
+            Value::String(s) => visitor.visit_string(s),
+            Value::List(values) => visit_list(values, visitor),
+            Value::Compound(map) => visitor.visit_map(CompoundAccess {
+                iter: map.into_iter(),
+                value: None,
+            }),
+            Value::IntArray(values) => visit_list(values.into_iter().map(Value::Int), visitor),
+            Value::LongArray(values) => visit_list(values.into_iter().map(Value::Long), visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Byte(x) => visitor.visit_bool(x != 0),
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Byte(x) => visitor.visit_u8(x as u8),
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Short(x) => visitor.visit_u16(x as u16),
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Int(x) => visitor.visit_u32(x as u32),
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Long(x) => visitor.visit_u64(x as u64),
+            value => ValueDeserializer(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Missing values are handled by serde.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Compound(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().unwrap();
+                visitor.visit_enum(VariantDeserializer { variant, value })
+            }
+            _ => Err(Error::Message(String::from(
+                "Expected a string or a compound with a single entry for an enum",
+            ))),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 f32 f64 char str string bytes byte_buf
+        unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+fn visit_list<'de, I, V>(values: I, visitor: V) -> Result<V::Value, Error>
+where
+    I: IntoIterator<Item = Value>,
+    V: Visitor<'de>,
+{
+    let values: Vec<Value> = values.into_iter().collect();
+    visitor.visit_seq(ListAccess(values.into_iter()))
+}
+
+struct ListAccess(vec::IntoIter<Value>);
+
+impl<'de> SeqAccess<'de> for ListAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(ValueDeserializer(value)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct CompoundAccess {
+    iter: hash_map::IntoIter<String, Value>,
+    /// The value of the entry whose key was last returned.
+    value: Option<Value>,
+}
+
+impl<'de> MapAccess<'de> for CompoundAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| Error::Message(String::from("Value requested before its key")))?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Deserializes an enum variant stored as a compound
+/// with the variant name as its only key.
+struct VariantDeserializer {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer {
+    type Error = Error;
+    type Variant = ValueDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, ValueDeserializer(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Line(i32, i32),
+        Rect { width: u16, height: u16 },
+    }
+
+    #[test]
+    fn test_enums() {
+        for shape in vec![
+            Shape::Point,
+            Shape::Circle(1.5),
+            Shape::Line(1, 2),
+            Shape::Rect {
+                width: 65535,
+                height: 1,
+            },
+        ] {
+            let value = to_value(&shape).unwrap();
+            assert_eq!(from_value::<Shape>(value).unwrap(), shape);
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        assert_eq!(from_value::<u8>(Value::Byte(-1)).unwrap(), 255);
+        assert_eq!(
+            from_value::<u64>(Value::Long(-1)).unwrap(),
+            u64::max_value()
+        );
+        assert!(!from_value::<bool>(Value::Byte(0)).unwrap());
+        assert!(from_value::<u8>(Value::Int(-1)).is_err());
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        #[derive(Deserialize)]
+        struct Data {
+            present: i32,
+            missing: Option<i32>,
+        }
+
+        let mut map = HashMap::new();
+        map.insert(String::from("present"), Value::Int(1));
+        map.insert(String::from("unknown"), Value::String(String::from("a")));
+
+        let data: Data = from_value(Value::Compound(map)).unwrap();
+        assert_eq!(data.present, 1);
+        assert_eq!(data.missing, None);
+    }
+}
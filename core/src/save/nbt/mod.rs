@@ -0,0 +1,363 @@
+//! Reading and writing of NBT data.
+//!
+//! This module implements the full NBT format, including long
+//! arrays, lists of compounds and the Modified UTF-8 encoding used
+//! for strings, on top of the `Value` type of the `nbt` crate.
+//! Types implementing serde's `Serialize` and `Deserialize`
+//! can be converted to and from NBT declaratively:
+//!
+//! * `bool`s are stored as bytes.
+//! * Unsigned integers are stored as the signed type of the same width.
+//! * Sequences are stored as lists; use the modules in `array`
+//! with `#[serde(with = "...")]` to store them as byte, int or long arrays.
+//! * `None` values are omitted from compounds.
+//! * Unit enum variants are stored as strings; other variants
+//! as a compound with the variant name as its only key.
+//!
+//! The `snbt` module implements the textual format used
+//! for NBT in commands.
+//!
+//! Note that serde buffers the contents of internally tagged enums
+//! and of structs with flattened fields, which loses the information
+//! that a byte is a `bool`. Such fields need
+//! `#[serde(deserialize_with = "nbt::deserialize_bool")]`.
+
+use serde::de::{DeserializeOwned, Deserializer, Visitor};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+pub mod array;
+mod de;
+mod read;
+mod ser;
+pub mod snbt;
+mod write;
+
+pub use ::nbt::Value;
+pub use de::from_value;
+pub use read::read_root;
+pub use ser::to_value;
+pub use write::write_root;
+
+// Tag type IDs.
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+/// Returns the type ID of the tag used to store a value.
+fn tag_id(value: &Value) -> u8 {
+    match value {
+        Value::Byte(_) => TAG_BYTE,
+        Value::Short(_) => TAG_SHORT,
+        Value::Int(_) => TAG_INT,
+        Value::Long(_) => TAG_LONG,
+        Value::Float(_) => TAG_FLOAT,
+        Value::Double(_) => TAG_DOUBLE,
+        Value::ByteArray(_) => TAG_BYTE_ARRAY,
+        Value::String(_) => TAG_STRING,
+        Value::List(_) => TAG_LIST,
+        Value::Compound(_) => TAG_COMPOUND,
+        Value::IntArray(_) => TAG_INT_ARRAY,
+        Value::LongArray(_) => TAG_LONG_ARRAY,
+    }
+}
+
+/// Serializes a value as the root compound of an NBT file.
+/// The root compound is unnamed unless `name` is set.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(
+    writer: &mut W,
+    value: &T,
+    name: Option<&str>,
+) -> Result<(), Error> {
+    write_root(writer, name.unwrap_or(""), &to_value(value)?)
+}
+
+/// Deserializes a value from the root compound of an NBT file.
+pub fn from_reader<R: Read, T: DeserializeOwned>(mut reader: R) -> Result<T, Error> {
+    let (_, value) = read_root(&mut reader)?;
+    from_value(value)
+}
+
+/// Like `to_writer`, but compresses the data using gzip.
+pub fn to_gzip_writer<W: Write, T: Serialize + ?Sized>(
+    writer: &mut W,
+    value: &T,
+    name: Option<&str>,
+) -> Result<(), Error> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    to_writer(&mut encoder, value, name)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Like `from_reader`, but decompresses the data using gzip.
+pub fn from_gzip_reader<R: Read, T: DeserializeOwned>(reader: R) -> Result<T, Error> {
+    from_reader(GzDecoder::new(reader))
+}
+
+/// Like `to_writer`, but compresses the data using zlib.
+pub fn to_zlib_writer<W: Write, T: Serialize + ?Sized>(
+    writer: &mut W,
+    value: &T,
+    name: Option<&str>,
+) -> Result<(), Error> {
+    let mut encoder = ZlibEncoder::new(writer, Compression::default());
+    to_writer(&mut encoder, value, name)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Like `from_reader`, but decompresses the data using zlib.
+pub fn from_zlib_reader<R: Read, T: DeserializeOwned>(reader: R) -> Result<T, Error> {
+    from_reader(ZlibDecoder::new(reader))
+}
+
+/// Deserializes a `bool` from either a boolean or an integer,
+/// for use with `#[serde(deserialize_with = "...")]` on fields
+/// whose contents are buffered by serde. See the module documentation.
+pub fn deserialize_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    struct BoolVisitor;
+
+    impl<'de> Visitor<'de> for BoolVisitor {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+            f.write_str("a boolean or a byte")
+        }
+
+        fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<bool, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<bool, E> {
+            Ok(v != 0)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<bool, E> {
+            Ok(v != 0)
+        }
+    }
+
+    deserializer.deserialize_any(BoolVisitor)
+}
+
+/// An error which occurred while reading,
+/// writing or converting NBT data.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occurred
+    Io(io::Error),
+    /// The data contained an unknown tag type
+    InvalidTag(u8),
+    /// The root tag was not a compound
+    NoRootCompound,
+    /// A string was not valid Modified UTF-8
+    InvalidString,
+    /// An array or list had a negative length
+    NegativeLength(i32),
+    /// Tags were nested too deeply
+    TooDeep,
+    /// A list contained elements of different types
+    HeterogeneousList,
+    /// A map had keys which were not strings
+    NonStringKey,
+    /// A value could not be represented in NBT
+    Unsupported(&'static str),
+    /// A custom error raised by serde
+    Message(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::InvalidTag(id) => write!(f, "Invalid NBT tag type {}", id),
+            Error::NoRootCompound => f.write_str("The root tag is not a compound"),
+            Error::InvalidString => f.write_str("String is not valid Modified UTF-8"),
+            Error::NegativeLength(len) => write!(f, "Negative length {}", len),
+            Error::TooDeep => f.write_str("Tags are nested too deeply"),
+            Error::HeterogeneousList => f.write_str("List elements have different types"),
+            Error::NonStringKey => f.write_str("Compound keys must be strings"),
+            Error::Unsupported(what) => write!(f, "{} cannot be represented in NBT", what),
+            Error::Message(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Player {
+        #[serde(rename = "Name")]
+        name: String,
+        #[serde(rename = "OnGround")]
+        on_ground: bool,
+        #[serde(rename = "Pos")]
+        position: Vec<f64>,
+        #[serde(rename = "Inventory")]
+        inventory: Vec<Slot>,
+        #[serde(rename = "Seen", with = "array::long")]
+        seen: Vec<i64>,
+        #[serde(rename = "Data", with = "array::byte")]
+        data: Vec<i8>,
+        #[serde(rename = "Spawn")]
+        spawn: Option<Vec<i32>>,
+        #[serde(rename = "Gamemode")]
+        gamemode: Gamemode,
+        #[serde(rename = "Tags")]
+        tags: HashMap<String, i16>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Slot {
+        #[serde(rename = "Count")]
+        count: u8,
+        #[serde(rename = "id")]
+        item: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Gamemode {
+        Survival,
+        Creative,
+    }
+
+    fn player() -> Player {
+        let mut tags = HashMap::new();
+        tags.insert(String::from("level"), 3);
+
+        Player {
+            name: String::from("caelunshun\0 \u{1F980}"),
+            on_ground: true,
+            position: vec![1.0, 64.5, -3.0],
+            inventory: vec![
+                Slot {
+                    count: 200,
+                    item: String::from("minecraft:stone"),
+                },
+                Slot {
+                    count: 1,
+                    item: String::from("minecraft:feather"),
+                },
+            ],
+            seen: vec![i64::min_value(), 0, i64::max_value()],
+            data: vec![-1, 0, 1],
+            spawn: None,
+            gamemode: Gamemode::Creative,
+            tags,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let player = player();
+
+        let mut buf = vec![];
+        to_writer(&mut buf, &player, None).unwrap();
+        let read: Player = from_reader(Cursor::new(&buf)).unwrap();
+        assert_eq!(read, player);
+
+        let mut buf = vec![];
+        to_gzip_writer(&mut buf, &player, Some("root")).unwrap();
+        let read: Player = from_gzip_reader(Cursor::new(&buf)).unwrap();
+        assert_eq!(read, player);
+    }
+
+    #[test]
+    fn test_to_value() {
+        let value = to_value(&player()).unwrap();
+        let map = match value {
+            Value::Compound(map) => map,
+            value => panic!("expected a compound, got {:?}", value),
+        };
+
+        assert_eq!(map["OnGround"], Value::Byte(1));
+        assert_eq!(
+            map["Seen"],
+            Value::LongArray(vec![i64::min_value(), 0, i64::max_value()])
+        );
+        assert_eq!(map["Data"], Value::ByteArray(vec![-1, 0, 1]));
+        assert_eq!(map["Gamemode"], Value::String(String::from("Creative")));
+        assert!(!map.contains_key("Spawn"));
+
+        match &map["Inventory"] {
+            Value::List(slots) => {
+                assert_eq!(slots.len(), 2);
+                match &slots[0] {
+                    Value::Compound(slot) => assert_eq!(slot["Count"], Value::Byte(200u8 as i8)),
+                    slot => panic!("expected a compound, got {:?}", slot),
+                }
+            }
+            inventory => panic!("expected a list, got {:?}", inventory),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_bool() {
+        #[derive(Deserialize)]
+        #[serde(tag = "id")]
+        enum Tagged {
+            Arrow {
+                #[serde(deserialize_with = "deserialize_bool")]
+                crit: bool,
+            },
+        }
+
+        let mut map = HashMap::new();
+        map.insert(String::from("id"), Value::String(String::from("Arrow")));
+        map.insert(String::from("crit"), Value::Byte(1));
+
+        match from_value(Value::Compound(map)).unwrap() {
+            Tagged::Arrow { crit } => assert!(crit),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(to_value(&(1i8, 1i32)).is_err());
+        assert!(to_writer(&mut vec![], &5i32, None).is_err());
+
+        let mut map = HashMap::new();
+        map.insert(1, 2);
+        assert!(to_value(&map).is_err());
+    }
+}
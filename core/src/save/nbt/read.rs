@@ -0,0 +1,231 @@
+//! Reading of NBT data in the binary format.
+
+use super::*;
+use byteorder::{BigEndian, ReadBytesExt};
+use std::collections::HashMap;
+
+/// The maximum depth to which tags may be nested,
+/// matching the limit used by the vanilla server.
+const MAX_DEPTH: usize = 512;
+
+/// The maximum number of elements for which space is allocated
+/// up front when reading a list or array. The length is read from
+/// untrusted data, so larger lists grow as they are read instead.
+const MAX_PREALLOCATION: usize = 4096;
+
+/// Reads the root tag of an NBT file, returning
+/// its name and value. The root tag must be a compound.
+pub fn read_root<R: Read>(reader: &mut R) -> Result<(String, Value), Error> {
+    if reader.read_u8()? != TAG_COMPOUND {
+        return Err(Error::NoRootCompound);
+    }
+
+    let name = read_string(reader)?;
+    let value = read_payload(reader, TAG_COMPOUND, 0)?;
+    Ok((name, value))
+}
+
+fn read_payload<R: Read>(reader: &mut R, tag: u8, depth: usize) -> Result<Value, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::TooDeep);
+    }
+
+    let value = match tag {
+        TAG_BYTE => Value::Byte(reader.read_i8()?),
+        TAG_SHORT => Value::Short(reader.read_i16::<BigEndian>()?),
+        TAG_INT => Value::Int(reader.read_i32::<BigEndian>()?),
+        TAG_LONG => Value::Long(reader.read_i64::<BigEndian>()?),
+        TAG_FLOAT => Value::Float(reader.read_f32::<BigEndian>()?),
+        TAG_DOUBLE => Value::Double(reader.read_f64::<BigEndian>()?),
+        TAG_BYTE_ARRAY => {
+            let bytes = read_bytes(reader, read_len(reader)?)?;
+            Value::ByteArray(bytes.into_iter().map(|x| x as i8).collect())
+        }
+        TAG_STRING => Value::String(read_string(reader)?),
+        TAG_LIST => {
+            let tag = reader.read_u8()?;
+            let len = read_len(reader)?;
+
+            let mut values = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+            for _ in 0..len {
+                values.push(read_payload(reader, tag, depth + 1)?);
+            }
+            Value::List(values)
+        }
+        TAG_COMPOUND => {
+            let mut map = HashMap::new();
+            loop {
+                let tag = reader.read_u8()?;
+                if tag == TAG_END {
+                    break;
+                }
+
+                let name = read_string(reader)?;
+                let value = read_payload(reader, tag, depth + 1)?;
+                map.insert(name, value);
+            }
+            Value::Compound(map)
+        }
+        TAG_INT_ARRAY => {
+            let len = read_len(reader)?;
+            let mut values = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+            for _ in 0..len {
+                values.push(reader.read_i32::<BigEndian>()?);
+            }
+            Value::IntArray(values)
+        }
+        TAG_LONG_ARRAY => {
+            let len = read_len(reader)?;
+            let mut values = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+            for _ in 0..len {
+                values.push(reader.read_i64::<BigEndian>()?);
+            }
+            Value::LongArray(values)
+        }
+        tag => return Err(Error::InvalidTag(tag)),
+    };
+
+    Ok(value)
+}
+
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    let len = reader.read_i32::<BigEndian>()?;
+    if len < 0 {
+        return Err(Error::NegativeLength(len));
+    }
+    Ok(len as usize)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let len = reader.read_u16::<BigEndian>()?;
+    decode_mutf8(&read_bytes(reader, len as usize)?)
+}
+
+/// Decodes a Modified UTF-8 string.
+///
+/// Strings written by some tools use four-byte UTF-8 sequences
+/// rather than surrogate pairs for supplementary characters;
+/// these are accepted as well.
+pub(super) fn decode_mutf8(bytes: &[u8]) -> Result<String, Error> {
+    // Strings which are valid UTF-8 decode to the same characters.
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return Ok(s.to_string());
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().copied();
+    while let Some(b) = iter.next() {
+        let unit = if b < 0x80 {
+            u16::from(b)
+        } else if b & 0xE0 == 0xC0 {
+            (u16::from(b & 0x1F) << 6) | continuation(iter.next())?
+        } else if b & 0xF0 == 0xE0 {
+            let high = continuation(iter.next())?;
+            let low = continuation(iter.next())?;
+            (u16::from(b & 0x0F) << 12) | (high << 6) | low
+        } else {
+            return Err(Error::InvalidString);
+        };
+        units.push(unit);
+    }
+
+    String::from_utf16(&units).map_err(|_| Error::InvalidString)
+}
+
+/// Returns the payload of a continuation byte.
+fn continuation(byte: Option<u8>) -> Result<u16, Error> {
+    match byte {
+        Some(b) if b & 0xC0 == 0x80 => Ok(u16::from(b & 0x3F)),
+        _ => Err(Error::InvalidString),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_decode_mutf8() {
+        assert_eq!(decode_mutf8(b"feather").unwrap(), "feather");
+        assert_eq!(decode_mutf8(&[b'a', 0xC0, 0x80, b'b']).unwrap(), "a\0b");
+        assert_eq!(
+            decode_mutf8(&[0xED, 0xA0, 0xBE, 0xED, 0xB6, 0x80]).unwrap(),
+            "\u{1F980}"
+        );
+        assert_eq!(decode_mutf8("\u{1F980}".as_bytes()).unwrap(), "\u{1F980}");
+
+        // Unpaired surrogate
+        assert!(decode_mutf8(&[0xED, 0xA0, 0xBE]).is_err());
+        // Truncated sequence
+        assert!(decode_mutf8(&[b'a', 0xC0]).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut nested = HashMap::new();
+        nested.insert(
+            String::from("name"),
+            Value::String(String::from("\0\u{1F980}")),
+        );
+        nested.insert(String::from("float"), Value::Float(0.5));
+
+        let mut map = HashMap::new();
+        map.insert(String::from("byte"), Value::Byte(-1));
+        map.insert(String::from("short"), Value::Short(-300));
+        map.insert(String::from("int"), Value::Int(1 << 20));
+        map.insert(String::from("long"), Value::Long(i64::min_value()));
+        map.insert(String::from("double"), Value::Double(-2.25));
+        map.insert(String::from("bytes"), Value::ByteArray(vec![1, -2, 3]));
+        map.insert(String::from("ints"), Value::IntArray(vec![4, -5]));
+        map.insert(String::from("longs"), Value::LongArray(vec![6, -7]));
+        map.insert(String::from("empty"), Value::List(vec![]));
+        map.insert(
+            String::from("compounds"),
+            Value::List(vec![
+                Value::Compound(nested.clone()),
+                Value::Compound(nested),
+            ]),
+        );
+        map.insert(
+            String::from("lists"),
+            Value::List(vec![
+                Value::List(vec![Value::Short(1)]),
+                Value::List(vec![Value::Int(2)]),
+            ]),
+        );
+        let value = Value::Compound(map);
+
+        let mut buf = vec![];
+        write_root(&mut buf, "Level", &value).unwrap();
+
+        let (name, read) = read_root(&mut Cursor::new(&buf)).unwrap();
+        assert_eq!(name, "Level");
+        assert_eq!(read, value);
+    }
+
+    #[test]
+    fn test_invalid() {
+        // Root is not a compound
+        assert!(read_root(&mut Cursor::new(&[1u8, 0, 0, 5][..])).is_err());
+        // Invalid tag type
+        assert!(read_root(&mut Cursor::new(&[10u8, 0, 0, 13, 0, 0, 0][..])).is_err());
+        // Negative length
+        assert!(read_root(&mut Cursor::new(
+            &[10u8, 0, 0, 7, 0, 0, 255, 255, 255, 255][..]
+        ))
+        .is_err());
+        // Truncated
+        assert!(read_root(&mut Cursor::new(&[10u8, 0, 0, 7, 0, 0, 0, 0, 0, 9, 1][..])).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Conversion of `Serialize` types to `Value`s.
+
+use super::array::{BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+use super::*;
+use serde::ser::{self, Serialize};
+use std::collections::HashMap;
+
+/// Converts a value to NBT.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value
+        .serialize(Serializer)?
+        .ok_or(Error::Unsupported("A unit or `None` value"))
+}
+
+/// Converts a value which is stored inside
+/// a list or as the value of an enum variant.
+fn to_element<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(Serializer)?.ok_or(Error::Unsupported(
+        "A list or enum variant containing a unit or `None`",
+    ))
+}
+
+/// Serializer producing `Value`s. Units and `None`
+/// have no NBT representation; they produce `None`,
+/// which compounds omit.
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeVariant<SerializeList>;
+    type SerializeMap = SerializeCompound;
+    type SerializeStruct = SerializeCompound;
+    type SerializeStructVariant = SerializeVariant<SerializeCompound>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Byte(v as i8)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Byte(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Short(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Int(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Long(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Byte(v as i8)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Short(v as i16)))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Int(v as i32)))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Long(v as i64)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::String(v.to_string())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::ByteArray(v.iter().map(|x| *x as i8).collect())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::String(variant.to_string())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        let value = value.serialize(self)?;
+        match name {
+            BYTE_ARRAY | INT_ARRAY | LONG_ARRAY => into_array(name, value).map(Some),
+            _ => Ok(value),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        Ok(Some(single_entry(variant, to_element(value)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Ok(SerializeList {
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Ok(SerializeCompound {
+            map: HashMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Converts a list serialized for one of the array
+/// newtype structs into the corresponding array.
+fn into_array(name: &'static str, value: Option<Value>) -> Result<Value, Error> {
+    let values = match value {
+        Some(Value::List(values)) => values,
+        // `serialize_bytes` already produces a byte array.
+        Some(Value::ByteArray(values)) if name == BYTE_ARRAY => {
+            return Ok(Value::ByteArray(values))
+        }
+        _ => return Err(Error::Unsupported("An array which is not a sequence")),
+    };
+
+    let invalid = || Error::Unsupported("An array with elements of the wrong type");
+    let array = match name {
+        BYTE_ARRAY => Value::ByteArray(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Byte(x) => Ok(x),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        INT_ARRAY => Value::IntArray(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Int(x) => Ok(x),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => Value::LongArray(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Long(x) => Ok(x),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+    };
+
+    Ok(array)
+}
+
+/// Returns a compound with a single entry.
+fn single_entry(key: &str, value: Value) -> Value {
+    let mut map = HashMap::with_capacity(1);
+    map.insert(key.to_string(), value);
+    Value::Compound(map)
+}
+
+struct SerializeList {
+    values: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let value = to_element(value)?;
+        if let Some(first) = self.values.first() {
+            if tag_id(first) != tag_id(&value) {
+                return Err(Error::HeterogeneousList);
+            }
+        }
+
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::List(self.values)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeCompound {
+    map: HashMap<String, Value>,
+    /// The key of the entry currently being serialized.
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeCompound {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            Some(Value::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::NonStringKey),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Message(String::from("Value serialized without a key")))?;
+
+        if let Some(value) = value.serialize(Serializer)? {
+            self.map.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(Value::Compound(self.map)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeCompound {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.map.insert(key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Serializes an enum variant as a compound
+/// with the variant name as its only key.
+struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        let value = ser::SerializeSeq::end(self.inner)?.unwrap();
+        Ok(Some(single_entry(self.variant, value)))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeCompound> {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        let value = ser::SerializeStruct::end(self.inner)?.unwrap();
+        Ok(Some(single_entry(self.variant, value)))
+    }
+}
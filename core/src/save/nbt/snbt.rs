@@ -0,0 +1,509 @@
+//! Stringified NBT, the textual format used for NBT
+//! in commands, for example `{NoAI:1b,Tags:["a","b"]}`.
+//!
+//! Numbers are typed by a suffix: `b` for bytes, `s` for shorts,
+//! `L` for longs, `f` for floats and `d` for doubles. Numbers without
+//! a suffix are ints, or doubles if they contain a decimal point.
+//! `true` and `false` are bytes. Arrays are written as `[B;1b,2b]`,
+//! `[I;1,2]` and `[L;1L,2L]`. Any other unquoted token is a string.
+
+use super::*;
+use std::collections::HashMap;
+
+/// The maximum depth to which compounds and lists may be nested.
+const MAX_DEPTH: usize = 512;
+
+/// Parses SNBT, which must make up the entire input.
+pub fn parse(input: &str) -> Result<Value, ParseError> {
+    let (value, rest) = parse_prefix(input)?;
+
+    if !rest.trim_start().is_empty() {
+        return Err(ParseError {
+            position: input.len() - rest.trim_start().len(),
+            message: "Unexpected trailing characters",
+        });
+    }
+
+    Ok(value)
+}
+
+/// Parses a single SNBT value at the start of the input,
+/// returning the value and the remaining input. This is how
+/// command arguments followed by other arguments are parsed.
+pub fn parse_prefix(input: &str) -> Result<(Value, &str), ParseError> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    Ok((value, &input[parser.pos..]))
+}
+
+/// Formats a value as SNBT. Compound entries
+/// are written in alphabetical order.
+///
+/// ```ignore
+/// let snbt = Snbt(&value).to_string();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Snbt<'a>(pub &'a Value);
+
+impl<'a> Display for Snbt<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Value::Byte(x) => write!(f, "{}b", x),
+            Value::Short(x) => write!(f, "{}s", x),
+            Value::Int(x) => write!(f, "{}", x),
+            Value::Long(x) => write!(f, "{}L", x),
+            Value::Float(x) => write!(f, "{}f", x),
+            Value::Double(x) => write!(f, "{}d", x),
+            Value::ByteArray(values) => {
+                f.write_str("[B;")?;
+                write_separated(f, values, |f, x| write!(f, "{}b", x))?;
+                f.write_str("]")
+            }
+            Value::String(s) => write_quoted(f, s),
+            Value::List(values) => {
+                f.write_str("[")?;
+                write_separated(f, values, |f, value| write!(f, "{}", Snbt(value)))?;
+                f.write_str("]")
+            }
+            Value::Compound(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+
+                    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+                        f.write_str(key)?;
+                    } else {
+                        write_quoted(f, key)?;
+                    }
+                    write!(f, ":{}", Snbt(value))?;
+                }
+                f.write_str("}")
+            }
+            Value::IntArray(values) => {
+                f.write_str("[I;")?;
+                write_separated(f, values, |f, x| write!(f, "{}", x))?;
+                f.write_str("]")
+            }
+            Value::LongArray(values) => {
+                f.write_str("[L;")?;
+                write_separated(f, values, |f, x| write!(f, "{}L", x))?;
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+fn write_separated<T>(
+    f: &mut Formatter,
+    values: &[T],
+    write: impl Fn(&mut Formatter, &T) -> fmt::Result,
+) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+        write(f, value)?;
+    }
+    Ok(())
+}
+
+fn write_quoted(f: &mut Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    f.write_str("\"")
+}
+
+/// An error which occurred while parsing SNBT.
+#[derive(Debug, Fail)]
+#[fail(display = "{} at position {}", message, position)]
+pub struct ParseError {
+    /// The byte offset in the input at which the error occurred.
+    pub position: usize,
+    pub message: &'static str,
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '+'
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+    /// The number of compounds and lists currently being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            position: self.pos,
+            message,
+        }
+    }
+
+    fn expect(&mut self, expected: char, message: &'static str) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Consumes the separator after an element of a compound
+    /// or list, returning whether another element follows.
+    fn separator(&mut self, close: char, message: &'static str) -> Result<bool, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(',') => {
+                self.bump();
+                Ok(true)
+            }
+            Some(c) if c == close => {
+                self.bump();
+                Ok(false)
+            }
+            _ => Err(self.error(message)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == '{' || c == '[' => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error("Tags are nested too deeply"));
+                }
+
+                self.depth += 1;
+                let value = if c == '{' {
+                    self.compound()
+                } else {
+                    self.list()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') | Some('\'') => Ok(Value::String(self.quoted()?)),
+            _ => {
+                let token = self.unquoted();
+                if token.is_empty() {
+                    Err(self.error("Expected a value"))
+                } else {
+                    Ok(parse_token(token))
+                }
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Result<Value, ParseError> {
+        self.bump(); // '{'
+
+        let mut map = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Value::Compound(map));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.quoted()?,
+                _ => {
+                    let key = self.unquoted();
+                    if key.is_empty() {
+                        return Err(self.error("Expected a key"));
+                    }
+                    key.to_string()
+                }
+            };
+
+            self.expect(':', "Expected ':'")?;
+            let value = self.value()?;
+            map.insert(key, value);
+
+            if !self.separator('}', "Expected ',' or '}'")? {
+                return Ok(Value::Compound(map));
+            }
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, ParseError> {
+        self.bump(); // '['
+
+        let rest = &self.input[self.pos..];
+        if rest.starts_with("B;") || rest.starts_with("I;") || rest.starts_with("L;") {
+            return self.array();
+        }
+
+        let mut values: Vec<Value> = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Value::List(values));
+        }
+
+        loop {
+            let start = self.pos;
+            let value = self.value()?;
+            if let Some(first) = values.first() {
+                if tag_id(first) != tag_id(&value) {
+                    return Err(ParseError {
+                        position: start,
+                        message: "List elements must all have the same type",
+                    });
+                }
+            }
+            values.push(value);
+
+            if !self.separator(']', "Expected ',' or ']'")? {
+                return Ok(Value::List(values));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        let ty = self.bump().unwrap();
+        self.bump(); // ';'
+
+        let mut bytes = vec![];
+        let mut ints = vec![];
+        let mut longs = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+        } else {
+            loop {
+                let start = self.pos;
+                match (ty, self.value()?) {
+                    ('B', Value::Byte(x)) => bytes.push(x),
+                    ('I', Value::Int(x)) => ints.push(x),
+                    ('L', Value::Long(x)) => longs.push(x),
+                    _ => {
+                        return Err(ParseError {
+                            position: start,
+                            message: "Array elements must match the array type",
+                        })
+                    }
+                }
+
+                if !self.separator(']', "Expected ',' or ']'")? {
+                    break;
+                }
+            }
+        }
+
+        Ok(match ty {
+            'B' => Value::ByteArray(bytes),
+            'I' => Value::IntArray(ints),
+            _ => Value::LongArray(longs),
+        })
+    }
+
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let quote = self.bump().unwrap();
+
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('\\') => match self.bump() {
+                    Some(c) if c == quote || c == '\\' => s.push(c),
+                    _ => return Err(self.error("Invalid escape sequence")),
+                },
+                Some(c) if c == quote => return Ok(s),
+                Some(c) => s.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        &self.input[start..self.pos]
+    }
+}
+
+/// Interprets an unquoted token as a number or boolean
+/// if possible, and as a string otherwise.
+fn parse_token(token: &str) -> Value {
+    if token == "true" {
+        return Value::Byte(1);
+    }
+    if token == "false" {
+        return Value::Byte(0);
+    }
+
+    let (body, suffix) = token.split_at(token.len() - 1);
+    let value = match suffix {
+        "b" | "B" if is_integer(body) => body.parse().ok().map(Value::Byte),
+        "s" | "S" if is_integer(body) => body.parse().ok().map(Value::Short),
+        "l" | "L" if is_integer(body) => body.parse().ok().map(Value::Long),
+        "f" | "F" if is_decimal(body) => body.parse().ok().map(Value::Float),
+        "d" | "D" if is_decimal(body) => body.parse().ok().map(Value::Double),
+        _ if is_integer(token) => token.parse().ok().map(Value::Int),
+        _ if is_decimal(token) && token.contains('.') => token.parse().ok().map(Value::Double),
+        _ => None,
+    };
+
+    value.unwrap_or_else(|| Value::String(token.to_string()))
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.trim_start_matches(|c| c == '+' || c == '-');
+    s.len() - digits.len() <= 1 && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_decimal(s: &str) -> bool {
+    s.chars().any(|c| c.is_ascii_digit())
+        && s.chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || c == '+' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Value)>) -> Value {
+        Value::Compound(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse(r#"{Enchantments:[{id:"sharpness",lvl:5}]}"#).unwrap(),
+            compound(vec![(
+                "Enchantments",
+                Value::List(vec![compound(vec![
+                    ("id", Value::String(String::from("sharpness"))),
+                    ("lvl", Value::Int(5)),
+                ])])
+            )])
+        );
+        assert_eq!(
+            parse("{NoAI:1b}").unwrap(),
+            compound(vec![("NoAI", Value::Byte(1))])
+        );
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse("1b").unwrap(), Value::Byte(1));
+        assert_eq!(parse("-2S").unwrap(), Value::Short(-2));
+        assert_eq!(parse("3").unwrap(), Value::Int(3));
+        assert_eq!(parse("4L").unwrap(), Value::Long(4));
+        assert_eq!(parse("0.5f").unwrap(), Value::Float(0.5));
+        assert_eq!(parse("1.5").unwrap(), Value::Double(1.5));
+        assert_eq!(parse("2d").unwrap(), Value::Double(2.0));
+        assert_eq!(parse("true").unwrap(), Value::Byte(1));
+
+        // Tokens which are not numbers are strings.
+        assert_eq!(
+            parse("stone").unwrap(),
+            Value::String(String::from("stone"))
+        );
+        assert_eq!(parse("300b").unwrap(), Value::String(String::from("300b")));
+        assert_eq!(
+            parse(r#"'it\'s "quoted"'"#).unwrap(),
+            Value::String(String::from(r#"it's "quoted""#))
+        );
+
+        assert_eq!(parse("[B; 1b, 2b]").unwrap(), Value::ByteArray(vec![1, 2]));
+        assert_eq!(parse("[I;]").unwrap(), Value::IntArray(vec![]));
+        assert_eq!(parse("[L;-1L]").unwrap(), Value::LongArray(vec![-1]));
+        assert_eq!(
+            parse(" [ 1 , 2 ] ").unwrap(),
+            Value::List(vec![Value::Int(1), Value::Int(2)])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("[1, 2b]").is_err());
+        assert!(parse("[B; 1]").is_err());
+        assert!(parse(r#"{a:"unterminated}"#).is_err());
+        assert!(parse("{a:1,}").is_err());
+        assert!(parse("{a 1}").is_err());
+        assert!(parse("").is_err());
+        assert!(parse(&"[".repeat(1000)).is_err());
+
+        let error = parse("{a:1} b").unwrap_err();
+        assert_eq!(error.position, 6);
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        let (value, rest) = parse_prefix("{Count:1b} 64").unwrap();
+        assert_eq!(value, compound(vec![("Count", Value::Byte(1))]));
+        assert_eq!(rest, " 64");
+    }
+
+    #[test]
+    fn test_format() {
+        let value = compound(vec![
+            ("b", Value::Byte(-1)),
+            ("a", Value::List(vec![Value::Short(1), Value::Short(2)])),
+            ("long key", Value::LongArray(vec![1, 2])),
+            ("s", Value::String(String::from("say \"hi\""))),
+            ("f", Value::Float(1.5)),
+            ("d", Value::Double(-2.0)),
+            ("i", Value::IntArray(vec![3])),
+            ("bytes", Value::ByteArray(vec![])),
+            ("l", Value::Long(7)),
+        ]);
+
+        let snbt = Snbt(&value).to_string();
+        assert_eq!(
+            snbt,
+            r#"{a:[1s,2s],b:-1b,bytes:[B;],d:-2d,f:1.5f,i:[I;3],l:7L,"long key":[L;1L,2L],s:"say \"hi\""}"#
+        );
+        assert_eq!(parse(&snbt).unwrap(), value);
+    }
+}
@@ -0,0 +1,144 @@
+//! Writing of NBT data in the binary format.
+
+use super::*;
+use byteorder::{BigEndian, WriteBytesExt};
+use std::borrow::Cow;
+
+/// Writes a compound as the root tag of an NBT file.
+pub fn write_root<W: Write>(writer: &mut W, name: &str, value: &Value) -> Result<(), Error> {
+    if tag_id(value) != TAG_COMPOUND {
+        return Err(Error::NoRootCompound);
+    }
+
+    writer.write_u8(TAG_COMPOUND)?;
+    write_string(writer, name)?;
+    write_payload(writer, value)
+}
+
+fn write_payload<W: Write>(writer: &mut W, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Byte(x) => writer.write_i8(*x)?,
+        Value::Short(x) => writer.write_i16::<BigEndian>(*x)?,
+        Value::Int(x) => writer.write_i32::<BigEndian>(*x)?,
+        Value::Long(x) => writer.write_i64::<BigEndian>(*x)?,
+        Value::Float(x) => writer.write_f32::<BigEndian>(*x)?,
+        Value::Double(x) => writer.write_f64::<BigEndian>(*x)?,
+        Value::ByteArray(values) => {
+            write_len(writer, values.len())?;
+            let bytes: Vec<u8> = values.iter().map(|x| *x as u8).collect();
+            writer.write_all(&bytes)?;
+        }
+        Value::String(s) => write_string(writer, s)?,
+        Value::List(values) => {
+            let tag = values.first().map_or(TAG_END, tag_id);
+            if values.iter().any(|value| tag_id(value) != tag) {
+                return Err(Error::HeterogeneousList);
+            }
+
+            writer.write_u8(tag)?;
+            write_len(writer, values.len())?;
+            for value in values {
+                write_payload(writer, value)?;
+            }
+        }
+        Value::Compound(map) => {
+            for (name, value) in map {
+                writer.write_u8(tag_id(value))?;
+                write_string(writer, name)?;
+                write_payload(writer, value)?;
+            }
+            writer.write_u8(TAG_END)?;
+        }
+        Value::IntArray(values) => {
+            write_len(writer, values.len())?;
+            for x in values {
+                writer.write_i32::<BigEndian>(*x)?;
+            }
+        }
+        Value::LongArray(values) => {
+            write_len(writer, values.len())?;
+            for x in values {
+                writer.write_i64::<BigEndian>(*x)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_len<W: Write>(writer: &mut W, len: usize) -> Result<(), Error> {
+    if len > i32::max_value() as usize {
+        return Err(Error::Unsupported(
+            "A list or array with more than 2^31 - 1 elements",
+        ));
+    }
+
+    writer.write_i32::<BigEndian>(len as i32)?;
+    Ok(())
+}
+
+fn write_string<W: Write>(writer: &mut W, s: &str) -> Result<(), Error> {
+    let bytes = encode_mutf8(s);
+    if bytes.len() > u16::max_value() as usize {
+        return Err(Error::Unsupported("A string longer than 65535 bytes"));
+    }
+
+    writer.write_u16::<BigEndian>(bytes.len() as u16)?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Encodes a string as Modified UTF-8, which differs from
+/// UTF-8 in that NUL is encoded using two bytes and characters
+/// outside the Basic Multilingual Plane are encoded as surrogate pairs.
+pub(super) fn encode_mutf8(s: &str) -> Cow<[u8]> {
+    // Only NUL and four-byte sequences need to be re-encoded.
+    if !s.bytes().any(|b| b == 0 || b >= 0xF0) {
+        return Cow::Borrowed(s.as_bytes());
+    }
+
+    let mut bytes = Vec::with_capacity(s.len() + 2);
+    for unit in s.encode_utf16() {
+        if unit != 0 && unit < 0x80 {
+            bytes.push(unit as u8);
+        } else if unit < 0x800 {
+            bytes.push(0xC0 | (unit >> 6) as u8);
+            bytes.push(0x80 | (unit & 0x3F) as u8);
+        } else {
+            bytes.push(0xE0 | (unit >> 12) as u8);
+            bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+            bytes.push(0x80 | (unit & 0x3F) as u8);
+        }
+    }
+
+    Cow::Owned(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encode_mutf8() {
+        assert_eq!(encode_mutf8("feather").as_ref(), b"feather");
+        assert_eq!(encode_mutf8("é").as_ref(), "é".as_bytes());
+        assert_eq!(encode_mutf8("a\0b").as_ref(), &[b'a', 0xC0, 0x80, b'b']);
+        assert_eq!(
+            encode_mutf8("\u{1F980}").as_ref(),
+            &[0xED, 0xA0, 0xBE, 0xED, 0xB6, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_write_root() {
+        let mut map = HashMap::new();
+        map.insert(String::from("a"), Value::Short(1));
+
+        let mut buf = vec![];
+        write_root(&mut buf, "", &Value::Compound(map)).unwrap();
+        assert_eq!(buf, vec![10, 0, 0, 2, 0, 1, b'a', 0, 1, 0]);
+
+        assert!(write_root(&mut vec![], "", &Value::Int(1)).is_err());
+    }
+}
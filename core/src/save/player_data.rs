@@ -5,7 +5,8 @@ use crate::inventory::{
     SlotIndex, HOTBAR_SIZE, INVENTORY_SIZE, SLOT_ARMOR_MAX, SLOT_ARMOR_MIN, SLOT_HOTBAR_OFFSET,
     SLOT_INVENTORY_OFFSET, SLOT_OFFHAND,
 };
-use crate::ItemStack;
+use crate::nbt;
+use crate::{ItemStack, ItemTag};
 use feather_items::Item;
 use std::fs;
 use std::io::{Read, Write};
@@ -23,10 +24,36 @@ pub struct PlayerData {
     pub gamemode: i32,
     #[serde(rename = "Inventory")]
     pub inventory: Vec<InventorySlot>,
+
+    #[serde(rename = "Health", default = "default_health")]
+    pub health: f32,
+    #[serde(rename = "foodLevel", default = "default_food_level")]
+    pub food_level: i32,
+    #[serde(rename = "foodSaturationLevel", default = "default_food_saturation")]
+    pub food_saturation: f32,
+    #[serde(rename = "XpLevel", default)]
+    pub xp_level: i32,
+    /// Progress towards the next level, between 0 and 1.
+    #[serde(rename = "XpP", default)]
+    pub xp_progress: f32,
+    #[serde(rename = "XpTotal", default)]
+    pub xp_total: i32,
+}
+
+fn default_health() -> f32 {
+    20.0
+}
+
+fn default_food_level() -> i32 {
+    20
+}
+
+fn default_food_saturation() -> f32 {
+    5.0
 }
 
 /// Represents a single inventory slot (including position index).
-#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct InventorySlot {
     #[serde(rename = "Count")]
     pub count: i8,
@@ -34,6 +61,8 @@ pub struct InventorySlot {
     pub slot: i8,
     #[serde(rename = "id")]
     pub item: String,
+    #[serde(rename = "tag")]
+    pub tag: Option<ItemTag>,
 }
 
 impl InventorySlot {
@@ -42,6 +71,7 @@ impl InventorySlot {
         ItemStack {
             ty: Item::from_identifier(self.item.as_str()).unwrap_or(Item::Air),
             amount: self.count as u8,
+            tag: self.tag.clone(),
         }
     }
 
@@ -67,6 +97,19 @@ impl InventorySlot {
             count: stack.amount as i8,
             slot,
             item: stack.ty.identifier().to_string(),
+            tag: stack.tag().cloned(),
+        }
+    }
+
+    /// Converts an item in a container, such as a shulker box,
+    /// to an `InventorySlot`. The slot is the index of the
+    /// item in the container.
+    pub fn from_container_index(index: usize, stack: &ItemStack) -> Self {
+        Self {
+            count: stack.amount as i8,
+            slot: index as i8,
+            item: stack.ty.identifier().to_string(),
+            tag: stack.tag().cloned(),
         }
     }
 
@@ -93,7 +136,7 @@ impl InventorySlot {
 }
 
 fn load_from_file<R: Read>(reader: R) -> Result<PlayerData, nbt::Error> {
-    nbt::from_gzip_reader::<_, PlayerData>(reader)
+    nbt::from_gzip_reader(reader)
 }
 
 pub fn load_player_data(world_dir: &Path, uuid: Uuid) -> Result<PlayerData, nbt::Error> {
@@ -133,12 +176,31 @@ mod tests {
         assert_eq!(player.gamemode, i32::from(Gamemode::Creative.get_id()));
     }
 
+    #[test]
+    fn test_player_roundtrip() {
+        let cursor = Cursor::new(include_bytes!("player.dat").to_vec());
+        let player = load_from_file(cursor).unwrap();
+
+        let mut buf = vec![];
+        save_to_file(&mut buf, player.clone()).unwrap();
+        let read = load_from_file(Cursor::new(buf)).unwrap();
+
+        assert_eq!(read.gamemode, player.gamemode);
+        assert_eq!(read.inventory, player.inventory);
+        assert_eq!(read.food_level, player.food_level);
+        assert_eq!(read.xp_level, player.xp_level);
+        assert_eq!(read.xp_total, player.xp_total);
+        assert!((read.health - player.health).abs() < std::f32::EPSILON);
+        assert_eq!(read.entity.read_position(), player.entity.read_position());
+    }
+
     #[test]
     fn test_convert_item() {
         let slot = InventorySlot {
             count: 1,
             slot: 2,
             item: String::from(Item::Feather.identifier()),
+            tag: None,
         };
 
         let item_stack = slot.to_stack();
@@ -152,6 +214,7 @@ mod tests {
             count: 1,
             slot: 2,
             item: String::from("invalid:identifier"),
+            tag: None,
         };
 
         let item_stack = slot.to_stack();
@@ -187,6 +250,7 @@ mod tests {
                 slot: src,
                 count: 1,
                 item: String::from(Item::Stone.identifier()),
+                tag: None,
             };
             assert_eq!(slot.convert_index().unwrap(), expected);
             assert_eq!(
@@ -201,6 +265,7 @@ mod tests {
                 slot: *invalid_slot as i8,
                 count: 1,
                 item: String::from("invalid:identifier"),
+                tag: None,
             };
             assert!(slot.convert_index().is_none());
         }
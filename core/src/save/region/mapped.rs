@@ -0,0 +1,118 @@
+//! Memory-mapped reads of region files.
+
+use memmap::Mmap;
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+/// A read-only memory map of a region file.
+///
+/// Chunks are parsed straight from the mapped memory, so their
+/// data is not copied into an intermediate buffer first, and
+/// reads of recently used regions are served from the page cache
+/// without any system calls.
+///
+/// Memory-mapping a file is only sound if the mapped bytes are not
+/// modified while they are read. A `RegionHandle` drops its map before
+/// it writes to the file, so that later reads map the file again, but
+/// the `ChunkData` read from it may keep the old map alive. Writes never
+/// change the sectors of such data: a chunk is only saved once it has
+/// been parsed, so the sectors being read are never freed and reused,
+/// and region files are only ever extended, never truncated.
+///
+/// The map only hands out bounds-checked slices, and since it is
+/// `Send` and `Sync`, it can be shared with other threads.
+pub struct RegionMap {
+    map: Mmap,
+}
+
+impl RegionMap {
+    /// Maps the given file. Returns `None` if the
+    /// file is empty, since empty files cannot be mapped.
+    pub fn new(file: &File) -> io::Result<Option<Self>> {
+        if file.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+
+        // Safety: region files are only modified through
+        // the handle owning this map, which drops it first.
+        let map = unsafe { Mmap::map(file)? };
+        Ok(Some(Self { map }))
+    }
+
+    /// Returns the `len` bytes starting at `start`, or `None`
+    /// if the range extends past the end of the file.
+    pub fn get(&self, start: usize, len: usize) -> Option<&[u8]> {
+        self.map.get(start..start.checked_add(len)?)
+    }
+}
+
+/// The raw data of a chunk: the compression type followed by the
+/// compressed NBT data, as returned by `RegionHandle::read_chunk`.
+///
+/// The data refers to the memory map of the region file if it could
+/// be mapped, so it isn't copied even if it is sent to another thread
+/// to be parsed.
+pub struct ChunkData(Source);
+
+enum Source {
+    Mapped(Arc<RegionMap>, Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl ChunkData {
+    /// Returns the `len` bytes starting at `start` in the given
+    /// map, or `None` if the range extends past the end of the file.
+    pub(super) fn mapped(map: &Arc<RegionMap>, start: usize, len: usize) -> Option<Self> {
+        map.get(start, len)?;
+        Some(ChunkData(Source::Mapped(
+            Arc::clone(map),
+            start..start + len,
+        )))
+    }
+
+    pub(super) fn owned(data: Vec<u8>) -> Self {
+        ChunkData(Source::Owned(data))
+    }
+}
+
+impl Deref for ChunkData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Source::Mapped(map, range) => &map.map[range.clone()],
+            Source::Owned(data) => data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_region_map() {
+        let path = std::env::temp_dir().join(format!("region_map_{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        assert!(RegionMap::new(&file).unwrap().is_none());
+
+        file.write_all(&[1, 2, 3, 4]).unwrap();
+        let map = RegionMap::new(&File::open(&path).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(map.get(1, 2), Some(&[2, 3][..]));
+        assert_eq!(map.get(4, 0), Some(&[][..]));
+        assert_eq!(map.get(3, 2), None);
+        assert_eq!(map.get(usize::max_value(), 2), None);
+
+        let map = Arc::new(map);
+        assert_eq!(&*ChunkData::mapped(&map, 1, 3).unwrap(), &[2, 3, 4][..]);
+        assert!(ChunkData::mapped(&map, 2, 3).is_none());
+
+        drop(map);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
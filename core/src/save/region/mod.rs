@@ -7,21 +7,27 @@ use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::{Cursor, SeekFrom};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fs, io, iter};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde::Deserialize;
 
+use crate::nbt::{self, Value};
 use crate::save::entity::EntityData;
 use crate::world::block::*;
-use crate::world::chunk::{BitArray, Chunk, ChunkSection};
-use crate::world::ChunkPosition;
+use crate::world::chunk::{BitArray, Chunk, ChunkSection, HeightmapKind, ScheduledTick};
+use crate::world::{BlockPosition, ChunkPosition};
 use crate::Biome;
 use bitvec::bitvec;
 use bitvec::vec::BitVec;
 use feather_blocks::Block;
 
-mod blob;
+mod mapped;
+
+pub use mapped::ChunkData;
+use mapped::RegionMap;
 
 /// The length and width of a region, in chunks.
 const REGION_SIZE: usize = 32;
@@ -33,6 +39,10 @@ const DATA_VERSION: i32 = 1631;
 /// Length, in bytes, of a sector.
 const SECTOR_BYTES: usize = 4096;
 
+/// The maximum number of sectors used by a chunk, since
+/// sector counts are stored in a single byte.
+const MAX_CHUNK_SECTORS: usize = 255;
+
 /// Represents the data for a chunk after the "Chunk [x, y]" tag.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChunkRoot {
@@ -45,17 +55,116 @@ pub struct ChunkRoot {
 /// Represents the level data for a chunk.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChunkLevel {
-    // TODO heightmaps, etc.
     #[serde(rename = "xPos")]
     x_pos: i32,
     #[serde(rename = "zPos")]
     z_pos: i32,
+    #[serde(rename = "LastUpdate", default)]
+    last_update: i64,
+    #[serde(rename = "InhabitedTime", default)]
+    inhabited_time: i64,
+    #[serde(rename = "Status", default)]
+    status: String,
     #[serde(rename = "Sections")]
     sections: Vec<LevelSection>,
-    #[serde(rename = "Biomes")]
+    #[serde(rename = "Biomes", with = "nbt::array::int")]
     biomes: Vec<i32>,
+    #[serde(rename = "Heightmaps", default)]
+    heightmaps: LevelHeightmaps,
     #[serde(rename = "Entities")]
     entities: Vec<EntityData>,
+    #[serde(rename = "TileEntities", default)]
+    tile_entities: Vec<Value>,
+    #[serde(rename = "Structures", default)]
+    structures: LevelStructures,
+    #[serde(rename = "TileTicks", default)]
+    tile_ticks: Vec<Value>,
+    #[serde(rename = "LiquidTicks", default)]
+    liquid_ticks: Vec<Value>,
+
+    // Tags which are not used yet. They are not read, and
+    // written with the empty values vanilla expects. TODO
+    #[serde(rename = "ToBeTicked", skip_deserializing)]
+    to_be_ticked: Vec<Value>,
+    #[serde(rename = "LiquidsToBeTicked", skip_deserializing)]
+    liquids_to_be_ticked: Vec<Vec<Value>>,
+    #[serde(rename = "PostProcessing", skip_deserializing)]
+    post_processing: Vec<Vec<Value>>,
+}
+
+/// Represents the heightmaps of a chunk.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LevelHeightmaps {
+    #[serde(rename = "MOTION_BLOCKING", with = "nbt::array::long")]
+    motion_blocking: Vec<i64>,
+    #[serde(rename = "MOTION_BLOCKING_NO_LEAVES", with = "nbt::array::long")]
+    motion_blocking_no_leaves: Vec<i64>,
+    #[serde(rename = "OCEAN_FLOOR", with = "nbt::array::long")]
+    ocean_floor: Vec<i64>,
+    #[serde(rename = "OCEAN_FLOOR_WG", with = "nbt::array::long")]
+    ocean_floor_wg: Vec<i64>,
+    #[serde(rename = "WORLD_SURFACE", with = "nbt::array::long")]
+    world_surface: Vec<i64>,
+    #[serde(rename = "WORLD_SURFACE_WG", with = "nbt::array::long")]
+    world_surface_wg: Vec<i64>,
+}
+
+impl Default for LevelHeightmaps {
+    fn default() -> Self {
+        // TODO: compute the remaining heightmaps
+        Self {
+            motion_blocking: vec![0; HEIGHTMAP_LEN],
+            motion_blocking_no_leaves: vec![0; HEIGHTMAP_LEN],
+            ocean_floor: vec![0; HEIGHTMAP_LEN],
+            ocean_floor_wg: vec![0; HEIGHTMAP_LEN],
+            world_surface: vec![0; HEIGHTMAP_LEN],
+            world_surface_wg: vec![0; HEIGHTMAP_LEN],
+        }
+    }
+}
+
+/// Represents the structures starting in and
+/// reaching into a chunk.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LevelStructures {
+    #[serde(rename = "Starts", default)]
+    starts: HashMap<String, Value>,
+    #[serde(rename = "References", default)]
+    references: HashMap<String, StructureReferences>,
+}
+
+/// The positions of the chunks containing the starts of
+/// a structure, as encoded by `encode_chunk_position`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct StructureReferences(#[serde(with = "nbt::array::long")] Vec<i64>);
+
+/// The ID of structure starts which vanilla writes
+/// for structures not starting in a chunk.
+const INVALID_STRUCTURE: &str = "INVALID";
+
+/// Encodes a chunk position into a long,
+/// as used by structure references.
+fn encode_chunk_position(pos: ChunkPosition) -> i64 {
+    (i64::from(pos.x) & 0xFFFF_FFFF) | ((i64::from(pos.z) & 0xFFFF_FFFF) << 32)
+}
+
+fn decode_chunk_position(value: i64) -> ChunkPosition {
+    ChunkPosition::new(value as i32, (value >> 32) as i32)
+}
+
+/// The number of longs in a heightmap, which
+/// stores 256 heights using 9 bits each.
+const HEIGHTMAP_LEN: usize = 256 * 9 / 64;
+
+/// Encodes a heightmap of a chunk as stored in region files.
+fn encode_heightmap(chunk: &Chunk, kind: HeightmapKind) -> Vec<i64> {
+    let mut heights = BitArray::new(9, 256);
+    for x in 0..16 {
+        for z in 0..16 {
+            heights.set((z << 4) | x, chunk.height_at(kind, x, z) as u64);
+        }
+    }
+    heights.inner().iter().map(|x| *x as i64).collect()
 }
 
 /// Represents a chunk section in a region file.
@@ -63,13 +172,13 @@ pub struct ChunkLevel {
 pub struct LevelSection {
     #[serde(rename = "Y")]
     y: i8,
-    #[serde(rename = "BlockStates")]
+    #[serde(rename = "BlockStates", with = "nbt::array::long")]
     states: Vec<i64>,
     #[serde(rename = "Palette")]
     palette: Vec<LevelPaletteEntry>,
-    #[serde(rename = "BlockLight")]
+    #[serde(rename = "BlockLight", with = "nbt::array::byte")]
     block_light: Vec<i8>,
-    #[serde(rename = "SkyLight")]
+    #[serde(rename = "SkyLight", with = "nbt::array::byte")]
     sky_light: Vec<i8>,
 }
 
@@ -109,6 +218,10 @@ pub struct RegionHandle {
     header: RegionHeader,
     /// Sector allocator to allocate sectors where we can store chunks.
     allocator: SectorAllocator,
+    /// A memory map of the file, used to read chunks. It is
+    /// created when a chunk is loaded and dropped whenever
+    /// the file is written to.
+    map: Option<Arc<RegionMap>>,
 }
 
 impl RegionHandle {
@@ -119,12 +232,24 @@ impl RegionHandle {
     /// # Panics
     /// Panics if the specified chunk position is not within this
     /// region file.
-    pub fn load_chunk(
-        &mut self,
-        mut pos: ChunkPosition,
-    ) -> Result<(Chunk, Vec<EntityData>), Error> {
-        // Get a copy of the original position before clipping
-        let original_pos = pos;
+    pub fn load_chunk(&mut self, pos: ChunkPosition) -> Result<(Chunk, Vec<EntityData>), Error> {
+        let data = self.read_chunk(pos)?;
+        parse_chunk(pos, &data)
+    }
+
+    /// Reads the raw data of the chunk at the given position (global,
+    /// not region-relative) without parsing it. The data can be
+    /// parsed with `parse_chunk`, possibly on another thread.
+    ///
+    /// The data refers to the memory map of the file if possible,
+    /// so it isn't copied.
+    ///
+    /// The specified chunk is expected to be contained within this region.
+    ///
+    /// # Panics
+    /// Panics if the specified chunk position is not within this
+    /// region file.
+    pub fn read_chunk(&mut self, mut pos: ChunkPosition) -> Result<ChunkData, Error> {
         // Clip chunk position to region-local coordinates.
         pos.x %= 32;
         pos.z %= 32;
@@ -138,72 +263,49 @@ impl RegionHandle {
             return Err(Error::ChunkNotExist);
         }
 
-        // Seek to the offset position. Note that since the offset in the header
-        // is in "sectors" of 4KiB each, the value needs to be multiplied by SECTOR_BYTES
+        // Note that since the offset in the header is in "sectors"
+        // of 4KiB each, the value needs to be multiplied by SECTOR_BYTES
         // to get the offset in bytes.
-        self.file
-            .seek(SeekFrom::Start(u64::from(offset) * SECTOR_BYTES as u64))
-            .map_err(Error::Io)?;
-
-        // A chunk begins with a four-byte, big-endian value
-        // indicating the exact length of the chunk's data
-        // in bytes.
-        let len = self.file.read_u32::<BigEndian>().map_err(Error::Io)?;
+        self.chunk_data(offset as usize * SECTOR_BYTES)
+    }
 
-        // Avoid DoS attacks
-        if len > 1_048_576 {
-            return Err(Error::ChunkTooLarge(len as usize));
+    /// Returns the data of the chunk starting at the given
+    /// byte offset: the compression type followed by the
+    /// compressed NBT data.
+    ///
+    /// The data is read from the memory map of the file if
+    /// possible and from the file otherwise.
+    fn chunk_data(&mut self, start: usize) -> Result<ChunkData, Error> {
+        if self.map.is_none() {
+            // If the file cannot be mapped, fall back to reading it.
+            self.map = RegionMap::new(&self.file).unwrap_or(None).map(Arc::new);
         }
 
-        if len == 0 {
-            return Err(Error::ChunkTooLarge(0));
+        if let Some(map) = &self.map {
+            // A chunk begins with a four-byte, big-endian value
+            // indicating the exact length of the chunk's data
+            // in bytes.
+            let len = map
+                .get(start, 4)
+                .ok_or_else(unexpected_eof)?
+                .read_u32::<BigEndian>()
+                .map_err(Error::Io)?;
+            check_chunk_len(len)?;
+
+            return ChunkData::mapped(map, start + 4, len as usize).ok_or_else(unexpected_eof);
         }
 
+        self.file
+            .seek(SeekFrom::Start(start as u64))
+            .map_err(Error::Io)?;
+
+        let len = self.file.read_u32::<BigEndian>().map_err(Error::Io)?;
+        check_chunk_len(len)?;
+
         // Read `len` bytes into memory.
         let mut buf = vec![0u8; len as usize];
         self.file.read_exact(&mut buf).map_err(Error::Io)?;
-
-        // The compression type is indicated by a byte.
-        // 1 corresponds to gzip compression, while 2
-        // corresponds to zlib.
-        let compression_type = buf[0];
-
-        // Parse NBT data
-        let cursor = Cursor::new(&buf[1..]);
-        let root: ChunkRoot = match compression_type {
-            1 => nbt::from_gzip_reader(cursor).map_err(Error::Nbt)?,
-            2 => nbt::from_zlib_reader(cursor).map_err(Error::Nbt)?,
-            _ => return Err(Error::InvalidCompression(compression_type)),
-        };
-
-        // Check data version
-        if root.data_version != DATA_VERSION {
-            return Err(Error::UnsupportedDataVersion(root.data_version));
-        }
-
-        let level = &root.level;
-
-        let mut chunk = Chunk::new(original_pos);
-
-        // Read sections
-        for section in &level.sections {
-            read_section_into_chunk(section, &mut chunk)?;
-        }
-
-        // Read biomes
-        if level.biomes.len() != 256 {
-            return Err(Error::IndexOutOfBounds);
-        }
-        for index in 0..256 {
-            let id = level.biomes[index];
-            chunk.biomes_mut()[index] =
-                Biome::from_protocol_id(id).ok_or_else(|| Error::InvalidBiomeId(id))?;
-        }
-
-        // Chunk was not modified, but it thinks it was: disable this
-        chunk.check_modified();
-
-        Ok((chunk, level.entities.to_vec()))
+        Ok(ChunkData::owned(buf))
     }
 
     /// Saves the given chunk to this region file. The header will be updated
@@ -215,31 +317,38 @@ impl RegionHandle {
         let chunk_pos = chunk.position();
 
         let (local_x, local_z) = (chunk_pos.x % 32, chunk_pos.z % 32);
-
-        // Find position in header and deallocate it if it currently exists.
-        let location = self
-            .header
-            .location_for_chunk(ChunkPosition::new(local_x, local_z));
-        if location.exists() {
-            self.allocator.free(location.0);
-        }
+        let local_pos = ChunkPosition::new(local_x, local_z);
 
         // Write chunk to `ChunkRoot` tag.
         let root = chunk_to_chunk_root(chunk, entities);
 
-        let blob = blob::chunk_root_to_blob(root);
-
         // Write to intermediate buffer, because we need to know the length.
         let mut buf = Vec::with_capacity(4096);
         buf.write_u8(2).map_err(Error::Io)?; // Compression type: zlib
 
-        blob.to_zlib_writer(&mut buf)
-            .expect("Could not write chunk blob");
+        nbt::to_zlib_writer(&mut buf, &root, None).map_err(Error::Nbt)?;
 
         let total_len = buf.len() + 4; // 4 bytes for length header
 
         let sectors = (total_len + SECTOR_BYTES - 1) / SECTOR_BYTES;
 
+        // The header stores the sector count in a single byte,
+        // so larger chunks can't be saved. The old data is kept.
+        if sectors > MAX_CHUNK_SECTORS {
+            return Err(Error::ChunkTooLarge(total_len));
+        }
+
+        // Later reads must map the file again. See `RegionMap`
+        // for why data still referring to the old map is sound.
+        self.map = None;
+
+        // Find position in header and deallocate it if it currently exists.
+        // The freed sectors may be reused for the new data.
+        let location = self.header.location_for_chunk(local_pos);
+        if location.exists() {
+            self.allocator.free(location.0);
+        }
+
         let block = self.allocator.allocate(sectors as u32);
 
         // Write to file
@@ -252,8 +361,10 @@ impl RegionHandle {
             .map_err(Error::Io)?;
         self.file.write_all(&buf).map_err(Error::Io)?;
 
-        // Write padding to align to sector count
-        let padding_count = SECTOR_BYTES - total_len % SECTOR_BYTES;
+        // Write padding to align to sector count. Data which
+        // already fills its last sector needs no padding, and
+        // writing a full sector would overwrite the next chunk.
+        let padding_count = (SECTOR_BYTES - total_len % SECTOR_BYTES) % SECTOR_BYTES;
 
         for _ in 0..padding_count {
             self.file.write_u8(0).map_err(Error::Io)?;
@@ -261,7 +372,8 @@ impl RegionHandle {
 
         // Update header
         self.header
-            .set_location_for_chunk(ChunkPosition::new(local_x, local_z), ChunkLocation(block));
+            .set_location_for_chunk(local_pos, ChunkLocation(block));
+        self.header.set_timestamp_for_chunk(local_pos, unix_time());
         self.save_header().map_err(Error::Io)?;
 
         Ok(())
@@ -274,6 +386,115 @@ impl RegionHandle {
     }
 }
 
+/// Parses the raw data of the chunk at the given position,
+/// as returned by `RegionHandle::read_chunk`.
+pub fn parse_chunk(pos: ChunkPosition, data: &[u8]) -> Result<(Chunk, Vec<EntityData>), Error> {
+    // The compression type is indicated by a byte.
+    // 1 corresponds to gzip compression, while 2
+    // corresponds to zlib.
+    let compression_type = *data.first().ok_or(Error::EmptyChunk)?;
+
+    // Parse NBT data
+    let cursor = Cursor::new(&data[1..]);
+    let root: ChunkRoot = match compression_type {
+        1 => nbt::from_gzip_reader(cursor).map_err(Error::Nbt)?,
+        2 => nbt::from_zlib_reader(cursor).map_err(Error::Nbt)?,
+        _ => return Err(Error::InvalidCompression(compression_type)),
+    };
+
+    // Check data version
+    if root.data_version != DATA_VERSION {
+        return Err(Error::UnsupportedDataVersion(root.data_version));
+    }
+
+    let level = &root.level;
+
+    let mut chunk = Chunk::new(pos);
+
+    // Read sections
+    for section in &level.sections {
+        read_section_into_chunk(section, &mut chunk)?;
+    }
+    // Stored heightmaps aren't trusted, since
+    // they may be missing or out of date.
+    chunk.recalculate_heightmaps();
+
+    // Read biomes
+    if level.biomes.len() != 256 {
+        return Err(Error::IndexOutOfBounds);
+    }
+    for index in 0..256 {
+        let id = level.biomes[index];
+        chunk.biomes_mut()[index] =
+            Biome::from_protocol_id(id).ok_or_else(|| Error::InvalidBiomeId(id))?;
+    }
+
+    // Read block entities
+    for data in &level.tile_entities {
+        if let Some((x, y, z)) = block_entity_position(data) {
+            if y >= 0 && y < 256 {
+                chunk.set_block_entity_at(
+                    x as usize & 0xf,
+                    y as usize,
+                    z as usize & 0xf,
+                    data.clone(),
+                );
+            }
+        }
+    }
+
+    // Read structures
+    for (name, start) in &level.structures.starts {
+        if structure_id(start) != Some(INVALID_STRUCTURE) {
+            chunk.set_structure_start(name, start.clone());
+        }
+    }
+    for (name, references) in &level.structures.references {
+        for reference in &references.0 {
+            chunk.add_structure_reference(name, decode_chunk_position(*reference));
+        }
+    }
+
+    // Read scheduled ticks
+    let ticks = level
+        .tile_ticks
+        .iter()
+        .chain(&level.liquid_ticks)
+        .filter_map(|data| read_scheduled_tick(data, &chunk))
+        .collect();
+    chunk.set_scheduled_ticks(ticks);
+
+    // Chunk was not modified, but it thinks it was: disable this
+    chunk.check_modified();
+
+    Ok((chunk, level.entities.to_vec()))
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32)
+}
+
+/// Checks that the length of a chunk's data is valid.
+fn check_chunk_len(len: u32) -> Result<(), Error> {
+    // Avoid DoS attacks
+    if len > 1_048_576 {
+        return Err(Error::ChunkTooLarge(len as usize));
+    }
+
+    if len == 0 {
+        return Err(Error::EmptyChunk);
+    }
+
+    Ok(())
+}
+
+fn unexpected_eof() -> Error {
+    Error::Io(io::ErrorKind::UnexpectedEof.into())
+}
+
 fn read_section_into_chunk(section: &LevelSection, chunk: &mut Chunk) -> Result<(), Error> {
     let data = &section.states;
 
@@ -373,11 +594,156 @@ fn chunk_to_chunk_root(chunk: &Chunk, entities: Vec<EntityData>) -> ChunkRoot {
                 .map(|biome| biome.protocol_id())
                 .collect(),
             entities,
+            last_update: 0,    // TODO
+            inhabited_time: 0, // TODO
+            status: String::from("postprocessed"),
+            heightmaps: LevelHeightmaps {
+                motion_blocking: encode_heightmap(chunk, HeightmapKind::MotionBlocking),
+                world_surface: encode_heightmap(chunk, HeightmapKind::WorldSurface),
+                ..LevelHeightmaps::default()
+            },
+            tile_entities: chunk
+                .block_entities()
+                .map(|(_, data)| data.clone())
+                .collect(),
+            structures: LevelStructures {
+                starts: chunk
+                    .structure_starts()
+                    .map(|(name, data)| (name.to_string(), data.clone()))
+                    .collect(),
+                references: chunk
+                    .all_structure_references()
+                    .map(|(name, starts)| {
+                        let starts = starts.iter().copied().map(encode_chunk_position);
+                        (name.to_string(), StructureReferences(starts.collect()))
+                    })
+                    .collect(),
+            },
+            tile_ticks: write_scheduled_ticks(chunk, false),
+            liquid_ticks: write_scheduled_ticks(chunk, true),
+            to_be_ticked: vec![],
+            liquids_to_be_ticked: vec![vec![]; 16],
+            post_processing: vec![vec![]; 16],
         },
         data_version: DATA_VERSION,
     }
 }
 
+/// Returns the world position stored in the
+/// `x`, `y` and `z` tags of a block entity.
+fn block_entity_position(data: &Value) -> Option<(i32, i32, i32)> {
+    let map = match data {
+        Value::Compound(map) => map,
+        _ => return None,
+    };
+
+    let coord = |name: &str| match map.get(name) {
+        Some(Value::Int(value)) => Some(*value),
+        _ => None,
+    };
+
+    Some((coord("x")?, coord("y")?, coord("z")?))
+}
+
+/// Returns the ID of the block or fluid saved with ticks scheduled
+/// for the given block. As in vanilla, flowing fluids have
+/// different IDs than fluid sources.
+fn scheduled_tick_id(block: Block) -> String {
+    let (name, _) = block.to_name_and_props();
+    let flowing = match block {
+        Block::Water(data) => data.level != 0,
+        Block::Lava(data) => data.level != 0,
+        _ => false,
+    };
+
+    if flowing {
+        name.replacen("minecraft:", "minecraft:flowing_", 1)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Converts the ticks scheduled in a chunk into the compounds saved
+/// in the `LiquidTicks` tag if `liquids` is true, or in the
+/// `TileTicks` tag otherwise.
+fn write_scheduled_ticks(chunk: &Chunk, liquids: bool) -> Vec<Value> {
+    chunk
+        .scheduled_ticks()
+        .iter()
+        .filter_map(|tick| {
+            let (x, y, z) = local_position(chunk, tick.pos)?;
+            let block = chunk.block_at(x, y, z);
+            let liquid = match block {
+                Block::Water(_) | Block::Lava(_) => true,
+                _ => false,
+            };
+            if liquid != liquids {
+                return None;
+            }
+
+            let mut data = HashMap::new();
+            data.insert(String::from("i"), Value::String(scheduled_tick_id(block)));
+            data.insert(String::from("x"), Value::Int(tick.pos.x));
+            data.insert(String::from("y"), Value::Int(tick.pos.y));
+            data.insert(String::from("z"), Value::Int(tick.pos.z));
+            data.insert(String::from("t"), Value::Int(tick.delay));
+            data.insert(String::from("p"), Value::Int(tick.priority));
+            Some(Value::Compound(data))
+        })
+        .collect()
+}
+
+/// Reads a scheduled tick saved in the `TileTicks` or
+/// `LiquidTicks` tag. Ticks for blocks other than the
+/// one at their position are skipped.
+fn read_scheduled_tick(data: &Value, chunk: &Chunk) -> Option<ScheduledTick> {
+    let map = match data {
+        Value::Compound(map) => map,
+        _ => return None,
+    };
+
+    let int = |name: &str| match map.get(name) {
+        Some(Value::Int(value)) => Some(*value),
+        _ => None,
+    };
+    let id = match map.get("i") {
+        Some(Value::String(id)) => id,
+        _ => return None,
+    };
+
+    let pos = BlockPosition::new(int("x")?, int("y")?, int("z")?);
+    let (x, y, z) = local_position(chunk, pos)?;
+    if scheduled_tick_id(chunk.block_at(x, y, z)) != *id {
+        return None;
+    }
+
+    Some(ScheduledTick {
+        pos,
+        delay: int("t")?,
+        priority: int("p").unwrap_or(0),
+    })
+}
+
+/// Converts a world position to a position inside
+/// the given chunk, if the position is inside it.
+fn local_position(chunk: &Chunk, pos: BlockPosition) -> Option<(usize, usize, usize)> {
+    if pos.chunk_pos() != chunk.position() || pos.y < 0 || pos.y >= 256 {
+        return None;
+    }
+    Some((pos.x as usize & 0xf, pos.y as usize, pos.z as usize & 0xf))
+}
+
+/// Returns the `id` tag of a structure start.
+fn structure_id(data: &Value) -> Option<&str> {
+    match data {
+        Value::Compound(map) => match map.get("id") {
+            Some(Value::String(id)) => Some(id.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn convert_palette(section: &mut ChunkSection) -> Vec<LevelPaletteEntry> {
     section.convert_palette_to_section();
     raw_palette_to_palette_entries(section.palette().unwrap())
@@ -513,6 +879,8 @@ pub enum Error {
     Nbt(nbt::Error),
     /// The chunk was too large
     ChunkTooLarge(usize),
+    /// The chunk contained no data
+    EmptyChunk,
     /// The chunk contained an invalid compression type
     InvalidCompression(u8),
     /// An IO error occurred
@@ -542,6 +910,7 @@ impl Display for Error {
             Error::ChunkTooLarge(size) => {
                 f.write_str(&format!("Chunk is too large: {} bytes", size))?
             }
+            Error::EmptyChunk => f.write_str("Chunk contains no data")?,
             Error::InvalidCompression(id) => {
                 f.write_str(&format!("Chunk uses invalid compression type {}", id))?
             }
@@ -590,6 +959,7 @@ pub fn load_region(dir: &PathBuf, pos: RegionPosition) -> Result<RegionHandle, E
         file,
         header,
         allocator,
+        map: None,
     })
 }
 
@@ -621,6 +991,7 @@ pub fn create_region(dir: &PathBuf, pos: RegionPosition) -> Result<RegionHandle,
         file,
         header,
         allocator,
+        map: None,
     })
 }
 
@@ -730,6 +1101,13 @@ impl RegionHeader {
         self.locations[index] = location;
     }
 
+    /// Sets the time at which the given chunk was last saved,
+    /// in seconds since the Unix epoch.
+    fn set_timestamp_for_chunk(&mut self, pos: ChunkPosition, timestamp: u32) {
+        let index = Self::index(pos);
+        self.timestamps[index] = timestamp;
+    }
+
     /// Writes this header to the given writer.
     fn write_to<W>(&self, w: &mut W) -> Result<(), io::Error>
     where
@@ -789,6 +1167,180 @@ impl RegionPosition {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chunk_root_roundtrip() {
+        let mut chunk = Chunk::new(ChunkPosition::new(1, -2));
+        chunk.set_block_at(0, 0, 0, Block::Stone);
+        let mut block_entity = HashMap::new();
+        block_entity.insert(String::from("x"), Value::Int(19));
+        block_entity.insert(String::from("y"), Value::Int(64));
+        block_entity.insert(String::from("z"), Value::Int(-28));
+        chunk.set_block_entity_at(3, 64, 4, Value::Compound(block_entity));
+        let mut start = HashMap::new();
+        start.insert(String::from("id"), Value::String(String::from("Village")));
+        chunk.set_structure_start("Village", Value::Compound(start));
+        chunk.add_structure_reference("Village", ChunkPosition::new(-3, 7));
+        let root = chunk_to_chunk_root(&chunk, vec![]);
+
+        let mut buf = vec![];
+        nbt::to_writer(&mut buf, &root, None).unwrap();
+        let read: ChunkRoot = nbt::from_reader(Cursor::new(&buf)).unwrap();
+
+        assert_eq!(read.data_version, DATA_VERSION);
+        assert_eq!(read.level.x_pos, 1);
+        assert_eq!(read.level.z_pos, -2);
+        assert_eq!(read.level.biomes, root.level.biomes);
+        assert_eq!(read.level.sections.len(), 1);
+        assert_eq!(read.level.sections[0].states, root.level.sections[0].states);
+        assert_eq!(read.level.sections[0].sky_light.len(), 2048);
+        assert_eq!(read.level.tile_entities.len(), 1);
+        assert_eq!(
+            block_entity_position(&read.level.tile_entities[0]),
+            Some((19, 64, -28))
+        );
+        assert_eq!(
+            structure_id(&read.level.structures.starts["Village"]),
+            Some("Village")
+        );
+        assert!(read.level.tile_ticks.is_empty());
+        assert_eq!(
+            read.level.structures.references["Village"],
+            StructureReferences(vec![encode_chunk_position(ChunkPosition::new(-3, 7))])
+        );
+
+        // Arrays are stored as array tags rather than lists.
+        let (_, value) = nbt::read_root(&mut Cursor::new(&buf)).unwrap();
+        let level = match value {
+            Value::Compound(mut map) => map.remove("Level").unwrap(),
+            _ => panic!(),
+        };
+        match level {
+            Value::Compound(map) => match &map["Biomes"] {
+                Value::IntArray(biomes) => assert_eq!(biomes.len(), 256),
+                biomes => panic!("expected an int array, got {:?}", biomes),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_scheduled_ticks() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0));
+        chunk.set_block_at(1, 64, 1, Block::Lava(LavaData { level: 0 }));
+        chunk.set_block_at(2, 64, 1, Block::Water(WaterData { level: 3 }));
+        chunk.set_block_at(3, 64, 1, Block::Stone);
+        let tick = |x| ScheduledTick {
+            pos: BlockPosition::new(x, 64, 1),
+            delay: 10,
+            priority: 0,
+        };
+        // The last tick is outside of the chunk.
+        chunk.set_scheduled_ticks(vec![tick(1), tick(2), tick(3), tick(16)]);
+
+        let liquid_ticks = write_scheduled_ticks(&chunk, true);
+        let tile_ticks = write_scheduled_ticks(&chunk, false);
+        assert_eq!(liquid_ticks.len(), 2);
+        assert_eq!(tile_ticks.len(), 1);
+        match &liquid_ticks[1] {
+            Value::Compound(map) => assert_eq!(
+                map["i"],
+                Value::String(String::from("minecraft:flowing_water"))
+            ),
+            data => panic!("expected a compound, got {:?}", data),
+        }
+
+        assert_eq!(read_scheduled_tick(&liquid_ticks[0], &chunk), Some(tick(1)));
+        assert_eq!(read_scheduled_tick(&tile_ticks[0], &chunk), Some(tick(3)));
+        // Ticks for blocks which were replaced are skipped.
+        chunk.set_block_at(3, 64, 1, Block::Dirt);
+        assert_eq!(read_scheduled_tick(&tile_ticks[0], &chunk), None);
+    }
+
+    #[test]
+    fn test_save_and_load_chunks() {
+        let dir = std::env::temp_dir().join(format!("feather-region-{}", std::process::id()));
+        let pos = RegionPosition::from_chunk(ChunkPosition::new(0, 0));
+        let mut handle = create_region(&dir, pos).unwrap();
+
+        let mut first = Chunk::new(ChunkPosition::new(0, 0));
+        first.set_block_at(1, 64, 1, Block::Stone);
+        let mut second = Chunk::new(ChunkPosition::new(3, 5));
+        second.set_block_at(2, 10, 3, Block::Glowstone);
+        second.add_structure_reference("Village", ChunkPosition::new(1, 4));
+        second.set_block_at(2, 11, 3, Block::Water(WaterData { level: 2 }));
+        let ticks = vec![
+            ScheduledTick {
+                pos: BlockPosition::new(50, 10, 83),
+                delay: 4,
+                priority: -1,
+            },
+            ScheduledTick {
+                pos: BlockPosition::new(50, 11, 83),
+                delay: 5,
+                priority: 0,
+            },
+        ];
+        second.set_scheduled_ticks(ticks.clone());
+        handle.save_chunk(&first, vec![]).unwrap();
+        handle.save_chunk(&second, vec![]).unwrap();
+
+        // Overwriting a chunk reuses its sectors.
+        first.set_block_at(1, 65, 1, Block::Dirt);
+        handle.save_chunk(&first, vec![]).unwrap();
+        drop(handle);
+
+        let mut handle = load_region(&dir, pos).unwrap();
+        let (first, _) = handle.load_chunk(ChunkPosition::new(0, 0)).unwrap();
+        assert_eq!(first.block_at(1, 64, 1), Block::Stone);
+        assert_eq!(first.block_at(1, 65, 1), Block::Dirt);
+        let (second, _) = handle.load_chunk(ChunkPosition::new(3, 5)).unwrap();
+        assert_eq!(second.block_at(2, 10, 3), Block::Glowstone);
+        assert_eq!(
+            second.structure_references("Village"),
+            &[ChunkPosition::new(1, 4)][..]
+        );
+        assert_eq!(second.scheduled_ticks(), ticks.as_slice());
+
+        // Chunks can also be read and parsed separately.
+        let data = handle.read_chunk(ChunkPosition::new(3, 5)).unwrap();
+        let (parsed, _) = parse_chunk(ChunkPosition::new(3, 5), &data).unwrap();
+        assert_eq!(parsed.block_at(2, 10, 3), Block::Glowstone);
+        assert!(match handle.read_chunk(ChunkPosition::new(4, 5)) {
+            Err(Error::ChunkNotExist) => true,
+            _ => false,
+        });
+        assert!(match parse_chunk(ChunkPosition::new(3, 5), &[]) {
+            Err(Error::EmptyChunk) => true,
+            _ => false,
+        });
+        assert!(handle
+            .header
+            .location_for_chunk(ChunkPosition::new(0, 0))
+            .exists());
+        assert_ne!(handle.header.timestamps[0], 0);
+
+        assert_eq!(
+            handle.file.metadata().unwrap().len() % SECTOR_BYTES as u64,
+            0
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encode_chunk_position() {
+        for pos in &[
+            ChunkPosition::new(0, 0),
+            ChunkPosition::new(-1, 1),
+            ChunkPosition::new(1_875_000, -1_875_000),
+        ] {
+            assert_eq!(decode_chunk_position(encode_chunk_position(*pos)), *pos);
+        }
+        assert_eq!(
+            encode_chunk_position(ChunkPosition::new(-1, 0)),
+            0xFFFF_FFFF
+        );
+    }
+
     #[test]
     fn test_sector_allocator() {
         let header = RegionHeader {
@@ -0,0 +1,278 @@
+//! Loading and saving of schematics, i.e. boxes of blocks
+//! stored outside of a world.
+//!
+//! Two formats are supported: Sponge schematics (`.schem`, versions
+//! 1 and 2), as used by WorldEdit, and vanilla structure files
+//! (`.nbt`), as used by structure blocks. Block entities and entities
+//! are not yet supported and are ignored when loading.
+
+use crate::world::BlockPosition;
+use feather_blocks::Block;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+mod sponge;
+mod structure;
+mod transform;
+
+pub use transform::{Mirror, Rotation};
+
+/// The data version written to schematics,
+/// corresponding to 1.13.2.
+const DATA_VERSION: i32 = 1631;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error: {}", _0)]
+    Io(io::Error),
+    #[fail(display = "invalid NBT: {}", _0)]
+    Nbt(nbt::Error),
+    #[fail(display = "unsupported schematic version {}", _0)]
+    UnsupportedVersion(i32),
+    #[fail(display = "unknown block {}", _0)]
+    InvalidBlock(String),
+    #[fail(display = "invalid palette index {}", _0)]
+    InvalidPaletteIndex(i32),
+    #[fail(display = "block data does not match the size of the schematic")]
+    InvalidBlockData,
+    #[fail(display = "unknown schematic format; expected .schem or .nbt")]
+    UnknownFormat,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<nbt::Error> for Error {
+    fn from(e: nbt::Error) -> Self {
+        Error::Nbt(e)
+    }
+}
+
+/// A schematic file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Sponge schematic, `.schem`.
+    Sponge,
+    /// Vanilla structure file, `.nbt`.
+    Structure,
+}
+
+impl Format {
+    /// Determines the format of a file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "schem" => Some(Format::Sponge),
+            "nbt" => Some(Format::Structure),
+            _ => None,
+        }
+    }
+
+    /// Returns the file extension used by this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Sponge => "schem",
+            Format::Structure => "nbt",
+        }
+    }
+}
+
+/// A box of blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    size: (i32, i32, i32),
+    /// The position of the minimum corner relative
+    /// to the schematic's origin. When pasting, the
+    /// origin is placed at the target position.
+    offset: BlockPosition,
+    /// Blocks in Y, Z, X order.
+    blocks: Vec<Block>,
+}
+
+impl Schematic {
+    /// Creates a schematic from a list of blocks in Y, Z, X order.
+    ///
+    /// # Panics
+    /// Panics if the number of blocks does not match `size`.
+    pub fn new(size: (i32, i32, i32), offset: BlockPosition, blocks: Vec<Block>) -> Self {
+        assert_eq!(
+            blocks.len(),
+            size.0 as usize * size.1 as usize * size.2 as usize
+        );
+        Self {
+            size,
+            offset,
+            blocks,
+        }
+    }
+
+    /// Loads a schematic, determining its
+    /// format from the file extension.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let format = Format::from_path(path).ok_or(Error::UnknownFormat)?;
+        let reader = BufReader::new(File::open(path)?);
+        match format {
+            Format::Sponge => sponge::read(reader),
+            Format::Structure => structure::read(reader),
+        }
+    }
+
+    /// Saves a schematic, determining its
+    /// format from the file extension.
+    ///
+    /// Structure files have no origin, so the
+    /// offset is lost when saving to one.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let format = Format::from_path(path).ok_or(Error::UnknownFormat)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            Format::Sponge => sponge::write(self, &mut writer),
+            Format::Structure => structure::write(self, &mut writer),
+        }
+    }
+
+    /// Returns the size of the schematic along each axis.
+    pub fn size(&self) -> (i32, i32, i32) {
+        self.size
+    }
+
+    pub fn offset(&self) -> BlockPosition {
+        self.offset
+    }
+
+    /// Returns the number of blocks in the schematic.
+    pub fn volume(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the block at the given position
+    /// relative to the minimum corner.
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[self.index(x, y, z)]
+    }
+
+    fn index(&self, x: i32, y: i32, z: i32) -> usize {
+        let (size_x, _, size_z) = self.size;
+        ((y * size_z + z) * size_x + x) as usize
+    }
+}
+
+/// Parses a block state in the form
+/// `minecraft:name[property=value,...]`.
+fn parse_block_state(state: &str) -> Option<Block> {
+    let (name, props) = match state.find('[') {
+        Some(start) => (&state[..start], state[start + 1..].strip_suffix(']')?),
+        None => (state, ""),
+    };
+
+    let mut map = HashMap::new();
+    for prop in props.split(',').filter(|prop| !prop.is_empty()) {
+        let mut parts = prop.splitn(2, '=');
+        map.insert(parts.next()?.to_string(), parts.next()?.to_string());
+    }
+
+    Block::from_name_and_props(name, &map)
+}
+
+/// Formats a block state in the form
+/// `minecraft:name[property=value,...]`.
+fn format_block_state(block: Block) -> String {
+    let (name, mut props) = block.to_name_and_props();
+    if props.is_empty() {
+        return name.to_string();
+    }
+
+    props.sort();
+    let props: Vec<String> = props
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    format!("{}[{}]", name, props.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schematic() -> Schematic {
+        Schematic::new(
+            (2, 1, 3),
+            BlockPosition::new(-1, 0, 2),
+            vec![
+                Block::Stone,
+                Block::Air,
+                Block::Dirt,
+                parse_block_state(
+                    "minecraft:oak_stairs[facing=east,half=top,shape=straight,waterlogged=false]",
+                )
+                .unwrap(),
+                Block::StructureVoid,
+                Block::Glowstone,
+            ],
+        )
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            Format::from_path(Path::new("house.schem")),
+            Some(Format::Sponge)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("dir/house.nbt")),
+            Some(Format::Structure)
+        );
+        assert_eq!(Format::from_path(Path::new("house.schematic")), None);
+        assert_eq!(Format::from_path(Path::new("house")), None);
+    }
+
+    #[test]
+    fn test_block_state() {
+        assert_eq!(parse_block_state("minecraft:stone"), Some(Block::Stone));
+        assert_eq!(parse_block_state("minecraft:nonexistent"), None);
+        assert_eq!(parse_block_state("minecraft:oak_stairs[facing=east"), None);
+
+        let stairs = "minecraft:oak_stairs[facing=east,half=top,shape=straight,waterlogged=false]";
+        assert_eq!(
+            format_block_state(parse_block_state(stairs).unwrap()),
+            stairs
+        );
+    }
+
+    #[test]
+    fn test_block_at() {
+        let schematic = schematic();
+        assert_eq!(schematic.block_at(0, 0, 0), Block::Stone);
+        assert_eq!(schematic.block_at(0, 0, 1), Block::Dirt);
+        assert_eq!(schematic.block_at(1, 0, 2), Block::Glowstone);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir();
+        let schematic = schematic();
+
+        for format in &[Format::Sponge, Format::Structure] {
+            let path = dir.join(format!(
+                "feather-schematic-{}.{}",
+                std::process::id(),
+                format.extension()
+            ));
+            schematic.save(&path).unwrap();
+            let loaded = Schematic::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.size(), schematic.size());
+            assert_eq!(loaded.blocks, schematic.blocks);
+            if *format == Format::Sponge {
+                assert_eq!(loaded.offset(), schematic.offset());
+            } else {
+                assert_eq!(loaded.offset(), BlockPosition::new(0, 0, 0));
+            }
+        }
+    }
+}
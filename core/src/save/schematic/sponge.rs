@@ -0,0 +1,181 @@
+//! Sponge schematics, versions 1 and 2.
+//! See https://github.com/SpongePowered/Schematic-Specification.
+
+use super::{format_block_state, parse_block_state, Error, Schematic, DATA_VERSION};
+use crate::world::BlockPosition;
+use feather_blocks::Block;
+use nbt::{Blob, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// The version written to schematics.
+const VERSION: i32 = 2;
+
+#[derive(Deserialize, Debug)]
+struct SpongeSchematic {
+    #[serde(rename = "Version")]
+    version: i32,
+    #[serde(rename = "Width")]
+    width: i16,
+    #[serde(rename = "Height")]
+    height: i16,
+    #[serde(rename = "Length")]
+    length: i16,
+    #[serde(rename = "Offset")]
+    offset: Option<Vec<i32>>,
+    #[serde(rename = "Metadata")]
+    metadata: Option<Metadata>,
+    #[serde(rename = "Palette")]
+    palette: HashMap<String, i32>,
+    #[serde(rename = "BlockData")]
+    block_data: Vec<i8>,
+}
+
+/// Metadata written by WorldEdit, which stores the origin
+/// here rather than in `Offset`.
+#[derive(Deserialize, Debug)]
+struct Metadata {
+    #[serde(rename = "WEOffsetX")]
+    offset_x: Option<i32>,
+    #[serde(rename = "WEOffsetY")]
+    offset_y: Option<i32>,
+    #[serde(rename = "WEOffsetZ")]
+    offset_z: Option<i32>,
+}
+
+pub fn read<R: Read>(reader: R) -> Result<Schematic, Error> {
+    let file: SpongeSchematic = nbt::from_gzip_reader(reader)?;
+    if file.version != 1 && file.version != 2 {
+        return Err(Error::UnsupportedVersion(file.version));
+    }
+
+    let mut palette = vec![None; file.palette.len()];
+    for (state, index) in &file.palette {
+        let block = parse_block_state(state).ok_or_else(|| Error::InvalidBlock(state.clone()))?;
+        let slot = palette
+            .get_mut(*index as usize)
+            .ok_or(Error::InvalidPaletteIndex(*index))?;
+        *slot = Some(block);
+    }
+
+    let size = (
+        i32::from(file.width as u16),
+        i32::from(file.height as u16),
+        i32::from(file.length as u16),
+    );
+    let volume = size.0 as usize * size.1 as usize * size.2 as usize;
+
+    let mut blocks = Vec::with_capacity(volume);
+    let mut data = file.block_data.iter().map(|byte| *byte as u8);
+    while blocks.len() < volume {
+        let index = read_varint(&mut data).ok_or(Error::InvalidBlockData)?;
+        let block = palette
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::InvalidPaletteIndex(index))?;
+        blocks.push(block);
+    }
+
+    let offset = match (&file.metadata, &file.offset) {
+        (
+            Some(Metadata {
+                offset_x: Some(x),
+                offset_y: Some(y),
+                offset_z: Some(z),
+            }),
+            _,
+        ) => BlockPosition::new(*x, *y, *z),
+        (_, Some(offset)) if offset.len() == 3 => {
+            BlockPosition::new(offset[0], offset[1], offset[2])
+        }
+        _ => BlockPosition::default(),
+    };
+
+    Ok(Schematic::new(size, offset, blocks))
+}
+
+pub fn write<W: Write>(schematic: &Schematic, writer: &mut W) -> Result<(), Error> {
+    let mut palette: HashMap<Block, i32> = HashMap::new();
+    let mut block_data = vec![];
+    for block in &schematic.blocks {
+        let len = palette.len() as i32;
+        let index = *palette.entry(*block).or_insert(len);
+        write_varint(&mut block_data, index);
+    }
+
+    let palette_value = palette
+        .iter()
+        .map(|(block, index)| (format_block_state(*block), Value::Int(*index)))
+        .collect();
+
+    let (width, height, length) = schematic.size;
+    let offset = schematic.offset;
+
+    let mut metadata = HashMap::new();
+    metadata.insert(String::from("WEOffsetX"), Value::Int(offset.x));
+    metadata.insert(String::from("WEOffsetY"), Value::Int(offset.y));
+    metadata.insert(String::from("WEOffsetZ"), Value::Int(offset.z));
+
+    let mut blob = Blob::named("Schematic");
+    blob.insert("Version", VERSION)?;
+    blob.insert("DataVersion", DATA_VERSION)?;
+    blob.insert("Width", width as u16 as i16)?;
+    blob.insert("Height", height as u16 as i16)?;
+    blob.insert("Length", length as u16 as i16)?;
+    blob.insert(
+        "Offset",
+        Value::IntArray(vec![offset.x, offset.y, offset.z]),
+    )?;
+    blob.insert("Metadata", Value::Compound(metadata))?;
+    blob.insert("PaletteMax", palette.len() as i32)?;
+    blob.insert("Palette", Value::Compound(palette_value))?;
+    blob.insert(
+        "BlockData",
+        Value::ByteArray(block_data.into_iter().map(|byte| byte as i8).collect()),
+    )?;
+
+    blob.to_gzip_writer(writer)?;
+    Ok(())
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<i32> {
+    let mut value = 0;
+    for i in 0..5 {
+        let byte = bytes.next()?;
+        value |= i32::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for value in &[0, 1, 127, 128, 300, 1 << 20] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, *value);
+            assert_eq!(read_varint(&mut bytes.into_iter()), Some(*value));
+        }
+
+        assert_eq!(read_varint(&mut vec![0x80].into_iter()), None);
+    }
+}
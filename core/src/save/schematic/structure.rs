@@ -0,0 +1,128 @@
+//! Vanilla structure files, as saved by structure blocks.
+//!
+//! Positions which are not listed in a structure file are
+//! structure voids, which leave the existing block in place
+//! when the structure is placed. These are represented
+//! as `Block::StructureVoid` and omitted when saving.
+
+use super::{Error, Schematic, DATA_VERSION};
+use crate::world::BlockPosition;
+use feather_blocks::Block;
+use nbt::{Blob, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+#[derive(Deserialize, Debug)]
+struct Structure {
+    size: Vec<i32>,
+    palette: Vec<PaletteEntry>,
+    blocks: Vec<StructureBlock>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PaletteEntry {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Properties")]
+    props: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StructureBlock {
+    pos: Vec<i32>,
+    state: i32,
+}
+
+pub fn read<R: Read>(reader: R) -> Result<Schematic, Error> {
+    let file: Structure = nbt::from_gzip_reader(reader)?;
+
+    let palette = file
+        .palette
+        .iter()
+        .map(|entry| {
+            Block::from_name_and_props(&entry.name, &entry.props.clone().unwrap_or_default())
+                .ok_or_else(|| Error::InvalidBlock(entry.name.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let size = match file.size.as_slice() {
+        [x, y, z] if *x >= 0 && *y >= 0 && *z >= 0 => (*x, *y, *z),
+        _ => return Err(Error::InvalidBlockData),
+    };
+    let volume = size.0 as usize * size.1 as usize * size.2 as usize;
+
+    let mut blocks = vec![Block::StructureVoid; volume];
+    for block in &file.blocks {
+        let (x, y, z) = match block.pos.as_slice() {
+            [x, y, z] => (*x, *y, *z),
+            _ => return Err(Error::InvalidBlockData),
+        };
+        if x < 0 || y < 0 || z < 0 || x >= size.0 || y >= size.1 || z >= size.2 {
+            return Err(Error::InvalidBlockData);
+        }
+
+        let state = *palette
+            .get(block.state as usize)
+            .ok_or(Error::InvalidPaletteIndex(block.state))?;
+        blocks[((y * size.2 + z) * size.0 + x) as usize] = state;
+    }
+
+    Ok(Schematic::new(size, BlockPosition::default(), blocks))
+}
+
+pub fn write<W: Write>(schematic: &Schematic, writer: &mut W) -> Result<(), Error> {
+    let (size_x, size_y, size_z) = schematic.size;
+
+    let mut palette: HashMap<Block, i32> = HashMap::new();
+    let mut palette_entries = vec![];
+    let mut blocks = vec![];
+    for y in 0..size_y {
+        for z in 0..size_z {
+            for x in 0..size_x {
+                let block = schematic.block_at(x, y, z);
+                if block == Block::StructureVoid {
+                    continue;
+                }
+
+                let state = *palette.entry(block).or_insert_with(|| {
+                    palette_entries.push(palette_entry(block));
+                    palette_entries.len() as i32 - 1
+                });
+
+                let mut map = HashMap::new();
+                map.insert(String::from("pos"), int_list(&[x, y, z]));
+                map.insert(String::from("state"), Value::Int(state));
+                blocks.push(Value::Compound(map));
+            }
+        }
+    }
+
+    let mut blob = Blob::new();
+    blob.insert("DataVersion", DATA_VERSION)?;
+    blob.insert("size", int_list(&[size_x, size_y, size_z]))?;
+    blob.insert("palette", Value::List(palette_entries))?;
+    blob.insert("blocks", Value::List(blocks))?;
+    blob.insert("entities", Value::List(vec![]))?;
+
+    blob.to_gzip_writer(writer)?;
+    Ok(())
+}
+
+fn palette_entry(block: Block) -> Value {
+    let (name, props) = block.to_name_and_props();
+
+    let mut map = HashMap::new();
+    map.insert(String::from("Name"), Value::String(name.to_string()));
+    if !props.is_empty() {
+        let props = props
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value)))
+            .collect();
+        map.insert(String::from("Properties"), Value::Compound(props));
+    }
+    Value::Compound(map)
+}
+
+fn int_list(values: &[i32]) -> Value {
+    Value::List(values.iter().copied().map(Value::Int).collect())
+}
@@ -0,0 +1,353 @@
+//! Rotation and mirroring of schematics.
+//!
+//! Block positions are transformed around the schematic's
+//! origin. Block states are transformed as well, so that,
+//! for example, stairs keep facing the same way relative to
+//! the rest of the schematic. This covers the `facing`,
+//! `axis`, `rotation` and per-side connection properties,
+//! as well as rail shapes and left/right variants.
+
+use super::Schematic;
+use crate::world::BlockPosition;
+use feather_blocks::Block;
+use std::collections::HashMap;
+
+/// A clockwise rotation around the Y axis, as seen from above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Returns the rotation by the given number of degrees,
+    /// which must be a multiple of 90. Negative values rotate
+    /// counterclockwise. Returns `None` for no rotation.
+    pub fn from_degrees(degrees: i32) -> Option<Self> {
+        match degrees.rem_euclid(360) {
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of quarter turns.
+    fn quarters(self) -> i32 {
+        match self {
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        }
+    }
+
+    fn position(self, pos: BlockPosition) -> BlockPosition {
+        let (mut x, mut z) = (pos.x, pos.z);
+        for _ in 0..self.quarters() {
+            // North (-Z) becomes east (+X).
+            let old_x = x;
+            x = -z;
+            z = old_x;
+        }
+        BlockPosition::new(x, pos.y, z)
+    }
+
+    fn direction(self, direction: &str) -> &'static str {
+        let mut index = match DIRECTIONS.iter().position(|d| *d == direction) {
+            Some(index) => index,
+            None => return direction_name(direction),
+        };
+        index = (index + self.quarters() as usize) % 4;
+        DIRECTIONS[index]
+    }
+}
+
+/// A reflection across a vertical plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    /// Negates X coordinates, swapping east and west.
+    X,
+    /// Negates Z coordinates, swapping north and south.
+    Z,
+}
+
+impl Mirror {
+    fn position(self, pos: BlockPosition) -> BlockPosition {
+        match self {
+            Mirror::X => BlockPosition::new(-pos.x, pos.y, pos.z),
+            Mirror::Z => BlockPosition::new(pos.x, pos.y, -pos.z),
+        }
+    }
+
+    fn direction(self, direction: &str) -> &'static str {
+        match (self, direction) {
+            (Mirror::X, "east") => "west",
+            (Mirror::X, "west") => "east",
+            (Mirror::Z, "north") => "south",
+            (Mirror::Z, "south") => "north",
+            _ => direction_name(direction),
+        }
+    }
+}
+
+/// Horizontal directions in clockwise order.
+const DIRECTIONS: [&str; 4] = ["north", "east", "south", "west"];
+
+/// Returns the static name of a direction, or
+/// an empty string if it is not a direction.
+fn direction_name(direction: &str) -> &'static str {
+    match direction {
+        "north" => "north",
+        "east" => "east",
+        "south" => "south",
+        "west" => "west",
+        "up" => "up",
+        "down" => "down",
+        _ => "",
+    }
+}
+
+impl Schematic {
+    /// Returns the schematic rotated around its origin.
+    pub fn rotated(&self, rotation: Rotation) -> Schematic {
+        self.transformed(
+            |pos| rotation.position(pos),
+            |block| transform_block(block, |d| rotation.direction(d), rotation.quarters(), None),
+        )
+    }
+
+    /// Returns the schematic mirrored around its origin.
+    pub fn mirrored(&self, mirror: Mirror) -> Schematic {
+        self.transformed(
+            |pos| mirror.position(pos),
+            |block| transform_block(block, |d| mirror.direction(d), 0, Some(mirror)),
+        )
+    }
+
+    fn transformed<P, B>(&self, position: P, mut block: B) -> Schematic
+    where
+        P: Fn(BlockPosition) -> BlockPosition,
+        B: FnMut(Block) -> Block,
+    {
+        let (size_x, size_y, size_z) = self.size;
+        let far = self.offset + BlockPosition::new(size_x - 1, size_y - 1, size_z - 1);
+        let (a, b) = (position(self.offset), position(far));
+        let min = BlockPosition::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let size = ((a.x - b.x).abs() + 1, size_y, (a.z - b.z).abs() + 1);
+
+        let mut blocks = vec![Block::Air; self.blocks.len()];
+        // Schematics usually contain few distinct
+        // blocks, so cache their transformations.
+        let mut cache: HashMap<Block, Block> = HashMap::new();
+        for y in 0..size_y {
+            for z in 0..size_z {
+                for x in 0..size_x {
+                    let old = self.block_at(x, y, z);
+                    let new = *cache.entry(old).or_insert_with(|| block(old));
+
+                    let pos = position(self.offset + BlockPosition::new(x, y, z));
+                    let (x, y, z) = (pos.x - min.x, pos.y - min.y, pos.z - min.z);
+                    blocks[((y * size.2 + z) * size.0 + x) as usize] = new;
+                }
+            }
+        }
+
+        Schematic::new(size, min, blocks)
+    }
+}
+
+/// Transforms the properties of a block.
+///
+/// `direction` maps a direction to the transformed direction,
+/// `quarters` is the number of clockwise quarter turns and
+/// `mirror` is the mirror being applied, if any.
+fn transform_block<F>(block: Block, direction: F, quarters: i32, mirror: Option<Mirror>) -> Block
+where
+    F: Fn(&str) -> &'static str,
+{
+    let (name, props) = block.to_name_and_props();
+    if props.is_empty() {
+        return block;
+    }
+
+    let mut map = HashMap::new();
+    for (prop, value) in &props {
+        let new_prop = match direction(*prop) {
+            "" => *prop,
+            // Fences, walls, glass panes, vines etc.
+            side => side,
+        };
+
+        let new_value = match *prop {
+            "facing" => match direction(value.as_str()) {
+                "" => value.clone(),
+                facing => facing.to_string(),
+            },
+            "axis" if quarters % 2 == 1 => match value.as_str() {
+                "x" => String::from("z"),
+                "z" => String::from("x"),
+                _ => value.clone(),
+            },
+            "rotation" => match value.parse::<i32>() {
+                Ok(rotation) => transform_rotation(rotation, quarters, mirror).to_string(),
+                Err(_) => value.clone(),
+            },
+            "shape" if value.chars().all(|c| c.is_ascii_lowercase() || c == '_') => {
+                transform_shape(value, &direction, mirror.is_some())
+            }
+            "hinge" | "type" if mirror.is_some() => swap_left_right(value),
+            _ => value.clone(),
+        };
+
+        map.insert(new_prop.to_string(), new_value);
+    }
+
+    Block::from_name_and_props(name, &map).unwrap_or(block)
+}
+
+/// Transforms a `rotation` property, which has
+/// 16 values going clockwise from south.
+fn transform_rotation(rotation: i32, quarters: i32, mirror: Option<Mirror>) -> i32 {
+    match mirror {
+        Some(Mirror::X) => (16 - rotation).rem_euclid(16),
+        Some(Mirror::Z) => (8 - rotation).rem_euclid(16),
+        None => (rotation + quarters * 4).rem_euclid(16),
+    }
+}
+
+/// Transforms a `shape` property, which is either a stair
+/// shape such as `inner_left` or a rail shape such as
+/// `ascending_east` or `north_west`.
+fn transform_shape<F>(shape: &str, direction: &F, mirrored: bool) -> String
+where
+    F: Fn(&str) -> &'static str,
+{
+    let parts: Vec<&str> = shape.split('_').collect();
+    match parts.as_slice() {
+        ["ascending", d] => format!("ascending_{}", direction(*d)),
+        [a, b] if !direction(*a).is_empty() && !direction(*b).is_empty() => {
+            let (a, b) = (direction(*a), direction(*b));
+            let is_z = |d: &str| d == "north" || d == "south";
+            match (is_z(a), is_z(b)) {
+                (true, true) => String::from("north_south"),
+                (false, false) => String::from("east_west"),
+                // Curved rails list the north/south direction first.
+                (true, false) => format!("{}_{}", a, b),
+                (false, true) => format!("{}_{}", b, a),
+            }
+        }
+        _ if mirrored => swap_left_right(shape),
+        _ => shape.to_string(),
+    }
+}
+
+fn swap_left_right(value: &str) -> String {
+    if value.contains("left") {
+        value.replace("left", "right")
+    } else {
+        value.replace("right", "left")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_block_state;
+    use super::*;
+
+    fn block(state: &str) -> Block {
+        parse_block_state(state).unwrap()
+    }
+
+    #[test]
+    fn test_rotation_from_degrees() {
+        assert_eq!(Rotation::from_degrees(90), Some(Rotation::Clockwise90));
+        assert_eq!(Rotation::from_degrees(-90), Some(Rotation::Clockwise270));
+        assert_eq!(Rotation::from_degrees(540), Some(Rotation::Clockwise180));
+        assert_eq!(Rotation::from_degrees(0), None);
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+
+    #[test]
+    fn test_rotate_positions() {
+        // A 2x1x3 box whose minimum corner is at the origin.
+        let blocks = vec![
+            Block::Stone,
+            Block::Air,
+            Block::Air,
+            Block::Air,
+            Block::Air,
+            Block::Dirt,
+        ];
+        let schematic = Schematic::new((2, 1, 3), BlockPosition::new(0, 0, 0), blocks);
+
+        let rotated = schematic.rotated(Rotation::Clockwise90);
+        assert_eq!(rotated.size(), (3, 1, 2));
+        assert_eq!(rotated.offset(), BlockPosition::new(-2, 0, 0));
+        // (0, 0, 0) stays at the origin.
+        assert_eq!(rotated.block_at(2, 0, 0), Block::Stone);
+        // (1, 0, 2) moves to (-2, 0, 1).
+        assert_eq!(rotated.block_at(0, 0, 1), Block::Dirt);
+
+        let back = rotated
+            .rotated(Rotation::Clockwise180)
+            .rotated(Rotation::Clockwise90);
+        assert_eq!(back, schematic);
+    }
+
+    #[test]
+    fn test_mirror_positions() {
+        let blocks = vec![Block::Stone, Block::Dirt];
+        let schematic = Schematic::new((2, 1, 1), BlockPosition::new(1, 0, 0), blocks);
+
+        let mirrored = schematic.mirrored(Mirror::X);
+        assert_eq!(mirrored.offset(), BlockPosition::new(-2, 0, 0));
+        assert_eq!(mirrored.block_at(0, 0, 0), Block::Dirt);
+        assert_eq!(mirrored.block_at(1, 0, 0), Block::Stone);
+
+        assert_eq!(schematic.mirrored(Mirror::Z).offset(), schematic.offset());
+    }
+
+    #[test]
+    fn test_transform_blocks() {
+        let stairs = block(
+            "minecraft:oak_stairs[facing=north,half=bottom,shape=inner_left,waterlogged=false]",
+        );
+        let schematic = Schematic::new((1, 1, 1), BlockPosition::new(0, 0, 0), vec![stairs]);
+
+        assert_eq!(
+            schematic.rotated(Rotation::Clockwise90).block_at(0, 0, 0),
+            block(
+                "minecraft:oak_stairs[facing=east,half=bottom,shape=inner_left,waterlogged=false]"
+            )
+        );
+        assert_eq!(
+            schematic.mirrored(Mirror::Z).block_at(0, 0, 0),
+            block("minecraft:oak_stairs[facing=south,half=bottom,shape=inner_right,waterlogged=false]")
+        );
+
+        let log = block("minecraft:oak_log[axis=x]");
+        assert_eq!(
+            transform_block(log, |d| Rotation::Clockwise90.direction(d), 1, None),
+            block("minecraft:oak_log[axis=z]")
+        );
+
+        let rail = block("minecraft:rail[shape=north_east]");
+        assert_eq!(
+            transform_block(rail, |d| Rotation::Clockwise90.direction(d), 1, None),
+            block("minecraft:rail[shape=south_east]")
+        );
+
+        let fence = block(
+            "minecraft:oak_fence[east=false,north=true,south=false,waterlogged=false,west=false]",
+        );
+        assert_eq!(
+            transform_block(fence, |d| Rotation::Clockwise90.direction(d), 1, None),
+            block("minecraft:oak_fence[east=true,north=false,south=false,waterlogged=false,west=false]")
+        );
+
+        assert_eq!(transform_rotation(0, 1, None), 4);
+        assert_eq!(transform_rotation(4, 0, Some(Mirror::X)), 12);
+        assert_eq!(transform_rotation(0, 0, Some(Mirror::Z)), 8);
+    }
+}
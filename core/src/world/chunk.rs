@@ -1,6 +1,8 @@
 use super::block::*;
-use super::ChunkPosition;
+use super::{BlockPosition, ChunkPosition};
+use crate::nbt::Value;
 use crate::Biome;
+use hashbrown::HashMap;
 use multimap::MultiMap;
 
 /// The number of bits used for each block
@@ -36,6 +38,43 @@ const SECTION_VOLUME: usize = (SECTION_HEIGHT * SECTION_WIDTH * SECTION_WIDTH) a
 /// The number of chunk sections in a column.
 const NUM_SECTIONS: usize = 16;
 
+/// The sky light of sections missing from a chunk.
+const ELIDED_SKY_LIGHT: u8 = 15;
+
+/// The kinds of heightmaps maintained by chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeightmapKind {
+    /// The highest blocks which block motion or contain a fluid.
+    MotionBlocking,
+    /// The highest blocks which aren't air.
+    WorldSurface,
+}
+
+impl HeightmapKind {
+    /// All kinds of heightmaps.
+    pub const ALL: [HeightmapKind; 2] =
+        [HeightmapKind::MotionBlocking, HeightmapKind::WorldSurface];
+
+    /// Returns whether the given block counts
+    /// towards heightmaps of this kind.
+    pub fn includes(self, block: Block) -> bool {
+        match self {
+            HeightmapKind::MotionBlocking => block.is_solid() || block.is_fluid(),
+            HeightmapKind::WorldSurface => match block {
+                Block::Air | Block::CaveAir | Block::VoidAir => false,
+                _ => true,
+            },
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            HeightmapKind::MotionBlocking => 0,
+            HeightmapKind::WorldSurface => 1,
+        }
+    }
+}
+
 /// A chunk column consisting
 /// of a 16x256x16 section of blocks.
 /// A chunk column maintains an array
@@ -52,14 +91,53 @@ pub struct Chunk {
     /// When an entry in this array is set to `None`,
     /// the section at the entry's Y coordinate
     /// is assumed to empty, meaning that it consists
-    /// of only air.
+    /// of only air, is fully exposed to the sky
+    /// and has no block light. Sections are created
+    /// when they are modified and removed again once
+    /// they are in this state, which saves memory for
+    /// chunks with many empty sections.
     sections: [Option<ChunkSection>; NUM_SECTIONS],
     /// The biomes in this section, indexable by
     /// ((z << 4) | x).
     biomes: [Biome; SECTION_WIDTH * SECTION_WIDTH],
+    /// The NBT data of the block entities in this chunk,
+    /// keyed by their position in chunk-local coordinates.
+    block_entities: HashMap<(usize, usize, usize), Value>,
+    /// The NBT data of the structures starting in this chunk,
+    /// keyed by the name of the structure, such as `Village`.
+    structure_starts: HashMap<String, Value>,
+    /// The positions of the chunks containing the starts of
+    /// the structures reaching into this chunk, keyed by
+    /// the name of the structure.
+    structure_references: HashMap<String, Vec<ChunkPosition>>,
+    /// The block ticks scheduled in this chunk when it was last
+    /// saved or loaded. While the chunk is loaded, the server
+    /// keeps its scheduled ticks instead.
+    scheduled_ticks: Vec<ScheduledTick>,
     /// Whether this chunk has been modified since the most recent
     /// call to `check_modified`().
     modified: bool,
+    /// A bit mask of the sections whose light may have changed
+    /// since the most recent call to `take_dirty_light`().
+    /// Bit `i` corresponds to the section at index `i`.
+    dirty_light: u16,
+    /// The heightmaps of this chunk, indexed by `HeightmapKind::index`
+    /// and then by ((z << 4) | x). Each value is the Y coordinate
+    /// above the highest block of the column included in the heightmap,
+    /// or 0 if there is none.
+    heightmaps: [[u16; SECTION_WIDTH * SECTION_WIDTH]; 2],
+}
+
+/// A block update scheduled to happen in a later tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScheduledTick {
+    /// The position of the block, in world coordinates.
+    pub pos: BlockPosition,
+    /// The number of ticks until the update happens.
+    pub delay: i32,
+    /// Among the ticks due in the same tick,
+    /// those with lower priorities happen first.
+    pub priority: i32,
 }
 
 impl Default for Chunk {
@@ -78,6 +156,12 @@ impl Default for Chunk {
             modified: true,
             sections,
             biomes: [Biome::Plains; SECTION_WIDTH * SECTION_WIDTH],
+            block_entities: HashMap::new(),
+            structure_starts: HashMap::new(),
+            structure_references: HashMap::new(),
+            scheduled_ticks: vec![],
+            dirty_light: 0,
+            heightmaps: [[0; SECTION_WIDTH * SECTION_WIDTH]; 2],
         }
     }
 }
@@ -134,6 +218,7 @@ impl Chunk {
     pub fn set_block_at(&mut self, x: usize, y: usize, z: usize, block: Block) {
         Self::check_coords(x, y, z);
         self.modified = true;
+        self.update_heightmaps(x, y, z, block);
 
         let chunk_section = &mut self.sections[y / 16];
 
@@ -146,12 +231,61 @@ impl Chunk {
                 return; // Nothing to do - section already empty
             }
 
-            let new_section = ChunkSection::default();
+            let new_section = ChunkSection::elided();
             self.set_section_at(y / 16, Some(new_section));
             section = self.section_mut(y / 16).unwrap();
         }
 
         section.set_block_at(x, y % 16, z, block);
+
+        if block == Block::Air && section.can_elide() {
+            self.set_section_at(y / 16, None);
+        }
+    }
+
+    /// Returns the Y coordinate above the highest block of the
+    /// given column included in the heightmap of the given kind,
+    /// or 0 if the column has no such block.
+    pub fn height_at(&self, kind: HeightmapKind, x: usize, z: usize) -> usize {
+        assert!(x < CHUNK_WIDTH && z < CHUNK_WIDTH);
+        usize::from(self.heightmaps[kind.index()][(z << 4) | x])
+    }
+
+    /// Updates the heightmaps of a column before the
+    /// block at the given position is replaced by `block`.
+    fn update_heightmaps(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        for kind in HeightmapKind::ALL.iter() {
+            let height = self.height_at(*kind, x, z);
+            let new_height = if kind.includes(block) {
+                height.max(y + 1)
+            } else if height == y + 1 {
+                // The highest block was removed, so look for the next one.
+                (0..y)
+                    .rev()
+                    .find(|y| kind.includes(self.block_at(x, *y, z)))
+                    .map_or(0, |y| y + 1)
+            } else {
+                height
+            };
+            self.heightmaps[kind.index()][(z << 4) | x] = new_height as u16;
+        }
+    }
+
+    /// Recalculates the heightmaps of this chunk from its
+    /// blocks. This is needed after sections were replaced
+    /// using `set_section_at`, which doesn't update them.
+    pub fn recalculate_heightmaps(&mut self) {
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_WIDTH {
+                for kind in HeightmapKind::ALL.iter() {
+                    let height = (0..CHUNK_HEIGHT)
+                        .rev()
+                        .find(|y| kind.includes(self.block_at(x, *y, z)))
+                        .map_or(0, |y| y + 1);
+                    self.heightmaps[kind.index()][(z << 4) | x] = height as u16;
+                }
+            }
+        }
     }
 
     pub fn sky_light_at(&self, x: usize, y: usize, z: usize) -> u8 {
@@ -159,7 +293,7 @@ impl Chunk {
         let chunk_section = self.section_for_y(y);
         match chunk_section {
             Some(chunk_section) => chunk_section.sky_light_at(x, y % 16, z),
-            None => 0,
+            None => ELIDED_SKY_LIGHT,
         }
     }
 
@@ -173,23 +307,48 @@ impl Chunk {
     }
 
     pub fn set_sky_light_at(&mut self, x: usize, y: usize, z: usize, value: u8) {
-        Self::check_coords(x, y, z);
+        // Unchanged values don't allocate a section.
+        if self.sky_light_at(x, y, z) == value {
+            return;
+        }
+        self.dirty_light |= 1 << (y / 16);
         let chunk_section = self.section_for_y_mut(y);
         chunk_section.set_sky_light_at(x, y % 16, z, value);
     }
 
     pub fn set_block_light_at(&mut self, x: usize, y: usize, z: usize, value: u8) {
-        Self::check_coords(x, y, z);
+        if self.block_light_at(x, y, z) == value {
+            return;
+        }
+        self.dirty_light |= 1 << (y / 16);
         let chunk_section = self.section_for_y_mut(y);
         chunk_section.set_block_light_at(x, y % 16, z, value);
     }
 
+    /// Returns a bit mask of the sections whose light may have
+    /// changed since the last call to `take_dirty_light`().
+    pub fn dirty_light(&self) -> u16 {
+        self.dirty_light
+    }
+
+    /// Returns a bit mask of the sections whose light may have
+    /// changed since the last call to this function, and resets
+    /// it. Bit `i` corresponds to the section at index `i`.
+    ///
+    /// Sections accessed through `section_mut` or `sections_mut`
+    /// are assumed to have changed.
+    pub fn take_dirty_light(&mut self) -> u16 {
+        let dirty = self.dirty_light;
+        self.dirty_light = 0;
+        dirty
+    }
+
     fn section_for_y(&self, y: usize) -> &Option<ChunkSection> {
         &self.sections[y / 16]
     }
 
     fn section_for_y_mut(&mut self, y: usize) -> &mut ChunkSection {
-        self.sections[y / 16].get_or_insert_with(ChunkSection::default)
+        self.sections[y / 16].get_or_insert_with(ChunkSection::elided)
     }
 
     fn check_coords(x: usize, y: usize, z: usize) {
@@ -208,6 +367,11 @@ impl Chunk {
     /// in this chunk.
     pub fn sections_mut(&mut self) -> Vec<Option<&mut ChunkSection>> {
         self.modified = true;
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.is_some() {
+                self.dirty_light |= 1 << i;
+            }
+        }
         self.sections.iter_mut().map(|sec| sec.as_mut()).collect()
     }
 
@@ -237,17 +401,24 @@ impl Chunk {
     pub fn section_mut(&mut self, index: usize) -> Option<&mut ChunkSection> {
         assert!(index < NUM_SECTIONS);
         self.modified = true;
+        if self.sections[index].is_some() {
+            self.dirty_light |= 1 << index;
+        }
         self.sections[index].as_mut()
     }
 
     /// Sets the section at the given section index.
+    ///
+    /// The heightmaps of the chunk are not updated;
+    /// see `recalculate_heightmaps`.
     pub fn set_section_at(&mut self, index: usize, section: Option<ChunkSection>) {
         assert!(index < NUM_SECTIONS);
         self.sections[index] = section;
         self.modified = true;
     }
 
-    /// Optimizes each section in this chunk.
+    /// Optimizes each section in this chunk,
+    /// removing sections which can be elided.
     ///
     /// Returns the number of sections which were actually
     /// optimized - sections which have not been
@@ -264,7 +435,7 @@ impl Chunk {
                     count += 1;
                 }
 
-                if section.empty() {
+                if section.can_elide() {
                     to_remove.push(i);
                 }
             }
@@ -309,6 +480,108 @@ impl Chunk {
         self.biomes[index] = biome;
     }
 
+    /// Returns the NBT data of the block entity at the specified
+    /// position in this chunk's local coordinate space, if there is one.
+    ///
+    /// The data is stored as it is saved in the world, including
+    /// the `id`, `x`, `y` and `z` tags.
+    pub fn block_entity_at(&self, x: usize, y: usize, z: usize) -> Option<&Value> {
+        self.block_entities.get(&(x, y, z))
+    }
+
+    /// Sets the NBT data of the block entity at the specified
+    /// position in this chunk's local coordinate space.
+    ///
+    /// # Panics
+    /// Panics if `x >= 16 || y >= 256 || z >= 16`.
+    pub fn set_block_entity_at(&mut self, x: usize, y: usize, z: usize, data: Value) {
+        Self::check_coords(x, y, z);
+        self.modified = true;
+        self.block_entities.insert((x, y, z), data);
+    }
+
+    /// Removes the block entity at the specified position in this
+    /// chunk's local coordinate space, returning its data.
+    pub fn remove_block_entity_at(&mut self, x: usize, y: usize, z: usize) -> Option<Value> {
+        let removed = self.block_entities.remove(&(x, y, z));
+        if removed.is_some() {
+            self.modified = true;
+        }
+        removed
+    }
+
+    /// Returns an iterator over the block entities in this chunk
+    /// along with their positions in chunk-local coordinates.
+    pub fn block_entities(&self) -> impl Iterator<Item = ((usize, usize, usize), &Value)> {
+        self.block_entities.iter().map(|(pos, data)| (*pos, data))
+    }
+
+    /// Returns the NBT data of the structure with the
+    /// given name starting in this chunk, if there is one.
+    pub fn structure_start(&self, name: &str) -> Option<&Value> {
+        self.structure_starts.get(name)
+    }
+
+    /// Sets the NBT data of the structure with the
+    /// given name starting in this chunk.
+    pub fn set_structure_start(&mut self, name: &str, data: Value) {
+        self.modified = true;
+        self.structure_starts.insert(name.to_string(), data);
+    }
+
+    /// Returns an iterator over the structures starting in
+    /// this chunk, along with the names of the structures.
+    pub fn structure_starts(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.structure_starts
+            .iter()
+            .map(|(name, data)| (name.as_str(), data))
+    }
+
+    /// Returns the positions of the chunks containing the starts of
+    /// the structures with the given name reaching into this chunk.
+    pub fn structure_references(&self, name: &str) -> &[ChunkPosition] {
+        self.structure_references
+            .get(name)
+            .map_or(&[], |starts| starts.as_slice())
+    }
+
+    /// Records that the structure with the given name starting
+    /// in the chunk at `start` reaches into this chunk.
+    pub fn add_structure_reference(&mut self, name: &str, start: ChunkPosition) {
+        let starts = self
+            .structure_references
+            .entry(name.to_string())
+            .or_insert_with(Vec::new);
+        if !starts.contains(&start) {
+            starts.push(start);
+            self.modified = true;
+        }
+    }
+
+    /// Returns an iterator over the names of the structures reaching
+    /// into this chunk, along with the chunks they start in.
+    pub fn all_structure_references(&self) -> impl Iterator<Item = (&str, &[ChunkPosition])> {
+        self.structure_references
+            .iter()
+            .map(|(name, starts)| (name.as_str(), starts.as_slice()))
+    }
+
+    /// Returns the block ticks scheduled in this chunk.
+    pub fn scheduled_ticks(&self) -> &[ScheduledTick] {
+        &self.scheduled_ticks
+    }
+
+    /// Sets the block ticks scheduled in this chunk,
+    /// which are saved along with its blocks.
+    pub fn set_scheduled_ticks(&mut self, ticks: Vec<ScheduledTick>) {
+        self.scheduled_ticks = ticks;
+    }
+
+    /// Removes and returns the block ticks scheduled in this chunk.
+    pub fn take_scheduled_ticks(&mut self) -> Vec<ScheduledTick> {
+        std::mem::replace(&mut self.scheduled_ticks, vec![])
+    }
+
     /// Checks whether this chunk has been modified since the last
     /// call to this function.
     pub fn check_modified(&mut self) -> bool {
@@ -332,8 +605,9 @@ pub struct ChunkSection {
     /// The block state data for this chunk section.
     data: BitArray,
     /// This section's palette. `None` if using the global palette.
-    /// The palette should always remain sorted so that a binary
-    /// search can be performed on it.
+    /// New entries are appended to the palette, so the indices
+    /// of existing entries never change. This is the same layout
+    /// used by the network protocol and Anvil region files.
     palette: Option<Vec<u16>>,
     /// The number of solid blocks in this chunk, i.e. those
     /// that are not air. This value is used to figure out when
@@ -349,68 +623,49 @@ pub struct ChunkSection {
 }
 
 impl ChunkSection {
-    /// Creates a new, empty `ChunkSection`.
+    /// Creates a new `ChunkSection` from its raw parts,
+    /// as read from the network or a region file.
     pub fn new(
-        mut data: BitArray,
-        mut palette: Option<Vec<u16>>,
+        data: BitArray,
+        palette: Option<Vec<u16>>,
         block_light: BitArray,
         sky_light: BitArray,
     ) -> Self {
-        // Correct palette if not using the global palette
-        if let Some(palette) = palette.as_mut() {
-            Self::correct_data_and_palette(&mut data, palette);
-        }
-
-        // Count solid blocks
-        let mut solid_block_count = 0;
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    if data.get(block_index(x, y, z)) != 0 {
-                        solid_block_count += 1;
-                    }
-                }
-            }
-        }
-
-        Self {
+        let mut section = Self {
             data,
             palette,
-            solid_block_count,
+            solid_block_count: 0,
             dirty: false,
             block_light,
             sky_light,
-        }
-    }
+        };
 
-    /// Corrects a given raw palette and data array.
-    ///
-    /// Since chunk data stored by external sources
-    /// (e.g. Vanilla) might not require a sorted palette
-    /// like Feather does, we need to sort the palette and
-    /// correct data in the array when reading from external
-    /// sources.
-    ///
-    /// The correction is done in-place.
-    fn correct_data_and_palette(data: &mut BitArray, palette: &mut Vec<u16>) {
-        let original_palette = palette.clone(); // Palette without sorting guarantees
+        // Count solid blocks
+        let air = Block::Air.native_state_id();
+        section.solid_block_count = (0..SECTION_VOLUME)
+            .filter(|&index| section.global_id(index) != air)
+            .count() as u16;
+
+        // Region files may contain section palettes with more bits per
+        // block than the protocol allows for them.
+        if section.palette.is_some() && section.data.bits_per_value > MAX_BITS_PER_BLOCK {
+            section.use_global_palette();
+        }
 
-        palette.sort_unstable();
+        section
+    }
 
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    // Replace index into palette of each block with
-                    // new index into the sorted palette.
-                    let block_index = block_index(x, y, z);
-                    let old_index = data.get(block_index);
-                    let new_index = palette
-                        .binary_search(&original_palette[old_index as usize])
-                        .unwrap();
-                    data.set(block_index, new_index as u64);
-                }
-            }
-        }
+    /// Creates a section with the contents assumed for
+    /// sections missing from a chunk: only air, full
+    /// sky light and no block light.
+    pub fn elided() -> Self {
+        let mut section = Self::default();
+        section.sky_light = BitArray::from_raw(
+            vec![u64::max_value(); SECTION_VOLUME * 4 / 64],
+            4,
+            SECTION_VOLUME,
+        );
+        section
     }
 
     /// Returns whether this chunk section is empty.
@@ -418,17 +673,34 @@ impl ChunkSection {
         self.solid_block_count == 0
     }
 
-    /// Retrieves the block at the given position in this chunk section.
-    /// The position is local to this section.
-    pub fn block_at(&self, x: usize, y: usize, z: usize) -> Block {
-        let index = block_index(x, y, z);
+    /// Returns whether this section can be removed from its
+    /// chunk without losing information, i.e. whether it
+    /// is identical to `ChunkSection::elided()`.
+    pub fn can_elide(&self) -> bool {
+        self.empty()
+            && self.block_light.inner().iter().all(|&word| word == 0)
+            && self
+                .sky_light
+                .inner()
+                .iter()
+                .all(|&word| word == u64::max_value())
+    }
+
+    /// Returns the global palette ID of the
+    /// block at the given index into the data array.
+    fn global_id(&self, index: usize) -> u16 {
         let block_id = self.data.get(index);
 
-        let global_id = match &self.palette {
-            Some(palette) => palette[block_id as usize] as u16,
+        match &self.palette {
+            Some(palette) => palette[block_id as usize],
             None => block_id as u16,
-        };
+        }
+    }
 
+    /// Retrieves the block at the given position in this chunk section.
+    /// The position is local to this section.
+    pub fn block_at(&self, x: usize, y: usize, z: usize) -> Block {
+        let global_id = self.global_id(block_index(x, y, z));
         Block::from_native_state_id(global_id).unwrap()
     }
 
@@ -437,70 +709,6 @@ impl ChunkSection {
     pub fn set_block_at(&mut self, x: usize, y: usize, z: usize, block: Block) {
         self.dirty = true;
 
-        let index = block_index(x, y, z);
-        let block_id = block.native_state_id();
-
-        // The value that will be put into the
-        let mut paletted_index;
-        if let Some(palette) = self.palette.as_mut() {
-            // Retrieve the block index from the palette.
-
-            // If necessary, add the block to the palette.
-            match palette.binary_search(&block_id) {
-                Ok(index) => paletted_index = index,
-                Err(insertion_index) => {
-                    palette.insert(insertion_index, block_id);
-                    paletted_index = insertion_index;
-
-                    // Resize if necessary
-                    if needed_bits((palette.len() - 1) as u64) > self.data.bits_per_value {
-                        let new_bits_per_value = self.data.bits_per_value + 1;
-                        if new_bits_per_value <= MAX_BITS_PER_BLOCK {
-                            self.data = self.data.resize_to(self.data.bits_per_value + 1).unwrap();
-                            paletted_index = insertion_index;
-                        } else {
-                            // Switch to the global palette
-                            let mut new_data = BitArray::new(GLOBAL_BITS_PER_BLOCK, SECTION_VOLUME);
-                            for _x in 0..16 {
-                                for _y in 0..16 {
-                                    for _z in 0..16 {
-                                        let block = self.block_at(_x, _y, _z);
-                                        new_data.set(
-                                            block_index(_x, _y, _z),
-                                            block.native_state_id() as u64,
-                                        );
-                                    }
-                                }
-                            }
-
-                            self.palette = None;
-                            paletted_index = block_id as usize;
-                            self.data = new_data;
-                        }
-                    }
-
-                    // Correct data, since palette entries after
-                    // the one which was inserted will be offsetted
-                    // by one.
-                    for x in 0..16 {
-                        for y in 0..16 {
-                            for z in 0..16 {
-                                let index = block_index(x, y, z);
-
-                                let entry = self.data.get(index);
-                                if entry >= insertion_index as u64 {
-                                    self.data.set(index, entry + 1);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            // Use the global palette.
-            paletted_index = block_id as usize;
-        }
-
         let old_block = self.block_at(x, y, z);
         if block == Block::Air && old_block != Block::Air {
             self.solid_block_count -= 1;
@@ -508,10 +716,52 @@ impl ChunkSection {
             self.solid_block_count += 1;
         }
 
-        self.data.set(index, paletted_index as u64);
+        let paletted_index = self.palette_index(block.native_state_id());
+        self.data.set(block_index(x, y, z), paletted_index as u64);
         debug_assert_eq!(self.block_at(x, y, z), block);
     }
 
+    /// Returns the value to store in the data array for the block
+    /// with the given global ID, adding the block to the palette
+    /// if necessary. When the palette outgrows the data array, the
+    /// array is resized or the global palette is switched to.
+    fn palette_index(&mut self, block_id: u16) -> usize {
+        let palette = match self.palette.as_mut() {
+            Some(palette) => palette,
+            None => return block_id as usize,
+        };
+
+        if let Some(index) = palette.iter().position(|&entry| entry == block_id) {
+            return index;
+        }
+
+        palette.push(block_id);
+        let index = palette.len() - 1;
+        if needed_bits(index as u64) <= self.data.bits_per_value {
+            return index;
+        }
+
+        let new_bits_per_value = self.data.bits_per_value + 1;
+        if new_bits_per_value <= MAX_BITS_PER_BLOCK {
+            self.data = self.data.resize_to(new_bits_per_value).unwrap();
+            index
+        } else {
+            self.use_global_palette();
+            block_id as usize
+        }
+    }
+
+    /// Switches this section to the global palette.
+    fn use_global_palette(&mut self) {
+        let mut data = BitArray::new(GLOBAL_BITS_PER_BLOCK, SECTION_VOLUME);
+        for index in 0..SECTION_VOLUME {
+            data.set(index, u64::from(self.global_id(index)));
+        }
+
+        self.palette = None;
+        self.data = data;
+    }
+
     /// Optimizes this chunk section, reducing the bits
     /// per block value as much as possible and removing unused
     /// entries from the palette.
@@ -528,47 +778,36 @@ impl ChunkSection {
 
         self.dirty = false;
 
-        // Replace palette with new one.
-        let mut new_palette = vec![];
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    let block = self.block_at(x, y, z).native_state_id();
-                    match new_palette.binary_search(&block) {
-                        Ok(_) => (),
-                        Err(insert_index) => {
-                            new_palette.insert(insert_index, block);
+        // Build a new palette containing only the blocks in use.
+        let mut palette = vec![];
+        let mut entries = Vec::with_capacity(SECTION_VOLUME);
+        for index in 0..SECTION_VOLUME {
+            let block = self.global_id(index);
+            let entry = match palette.iter().position(|&entry| entry == block) {
+                Some(entry) => entry,
+                None => {
+                    if palette.len() == 1 << MAX_BITS_PER_BLOCK {
+                        // Too many distinct blocks for a section palette.
+                        if self.palette.is_some() {
+                            self.use_global_palette();
                         }
+                        return true;
                     }
+                    palette.push(block);
+                    palette.len() - 1
                 }
-            }
+            };
+            entries.push(entry);
         }
 
-        // Recalculate all block IDs to match with the new palette.
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    let block = self.block_at(x, y, z).native_state_id();
-                    self.data.set(
-                        block_index(x, y, z),
-                        new_palette.binary_search(&block).unwrap() as u64,
-                    );
-                }
-            }
+        let bits_per_block = needed_bits((palette.len() - 1) as u64).max(MIN_BITS_PER_BLOCK);
+        let mut data = BitArray::new(bits_per_block, SECTION_VOLUME);
+        for (index, entry) in entries.into_iter().enumerate() {
+            data.set(index, entry as u64);
         }
 
-        self.palette = Some(new_palette);
-
-        // Recalculate bits per block value.
-        let mut new_bits_per_block = needed_bits(self.palette.as_ref().unwrap().len() as u64);
-        if new_bits_per_block > MAX_BITS_PER_BLOCK {
-            self.palette = None;
-        } else {
-            if new_bits_per_block < MIN_BITS_PER_BLOCK {
-                new_bits_per_block = MIN_BITS_PER_BLOCK;
-            }
-            self.data = self.data.resize_to(new_bits_per_block).unwrap();
-        }
+        self.palette = Some(palette);
+        self.data = data;
 
         true // Chunk was optimized
     }
@@ -866,6 +1105,44 @@ mod tests {
         assert_eq!(chunk.position(), pos);
     }
 
+    #[test]
+    fn chunk_structures() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0));
+        chunk.check_modified();
+
+        assert!(chunk.structure_start("Village").is_none());
+        assert!(chunk.structure_references("Village").is_empty());
+
+        chunk.set_structure_start("Village", Value::Int(1));
+        assert!(chunk.check_modified());
+        assert_eq!(chunk.structure_start("Village"), Some(&Value::Int(1)));
+
+        let start = ChunkPosition::new(-2, 3);
+        chunk.add_structure_reference("Village", start);
+        assert!(chunk.check_modified());
+        chunk.add_structure_reference("Village", start);
+        assert!(!chunk.check_modified());
+        assert_eq!(chunk.structure_references("Village"), &[start][..]);
+        assert_eq!(chunk.all_structure_references().count(), 1);
+    }
+
+    #[test]
+    fn chunk_block_entities() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0));
+        chunk.check_modified();
+
+        assert!(chunk.block_entity_at(1, 2, 3).is_none());
+        chunk.set_block_entity_at(1, 2, 3, Value::Int(5));
+        assert!(chunk.check_modified());
+        assert_eq!(chunk.block_entity_at(1, 2, 3), Some(&Value::Int(5)));
+        assert_eq!(chunk.block_entities().count(), 1);
+
+        assert_eq!(chunk.remove_block_entity_at(1, 2, 3), Some(Value::Int(5)));
+        assert!(chunk.check_modified());
+        assert!(chunk.remove_block_entity_at(1, 2, 3).is_none());
+        assert!(!chunk.check_modified());
+    }
+
     #[test]
     fn chunk_new_with_default_biome() {
         let pos = ChunkPosition::new(0, 0);
@@ -1123,11 +1400,83 @@ mod tests {
     }
 
     #[test]
-    fn test_correct_data_and_palette() {
-        let mut data = BitArray::new(4, 4096);
-        let mut palette = vec![0, 4, 2, 7, 3];
-        ChunkSection::correct_data_and_palette(&mut data, &mut palette);
-        assert_eq!(palette.len(), 5);
+    fn section_with_unsorted_palette() {
+        let mut data = BitArray::new(4, SECTION_VOLUME);
+        data.set(block_index(0, 0, 0), 1);
+        data.set(block_index(1, 0, 0), 2);
+
+        let palette = vec![
+            Block::Stone.native_state_id(),
+            Block::Air.native_state_id(),
+            Block::Dirt.native_state_id(),
+        ];
+        let section = ChunkSection::new(
+            data,
+            Some(palette.clone()),
+            BitArray::new(4, SECTION_VOLUME),
+            BitArray::new(4, SECTION_VOLUME),
+        );
+
+        // The palette is used as-is.
+        assert_eq!(section.palette(), Some(&palette));
+        assert_eq!(section.block_at(0, 0, 0), Block::Air);
+        assert_eq!(section.block_at(1, 0, 0), Block::Dirt);
+        assert_eq!(section.block_at(2, 0, 0), Block::Stone);
+        assert_eq!(section.solid_block_count, 4095);
+    }
+
+    #[test]
+    fn palette_entries_are_stable() {
+        let mut section = ChunkSection::default();
+
+        section.set_block_at(0, 0, 0, Block::Stone);
+        section.set_block_at(1, 0, 0, Block::Cobblestone);
+        let stone_entry = section.data().get(block_index(0, 0, 0));
+
+        for (i, id) in (1..100).enumerate() {
+            section.set_block_at(
+                i % 16,
+                1 + i / 16,
+                0,
+                Block::from_native_state_id(id).unwrap(),
+            );
+        }
+
+        assert_eq!(section.bits_per_block(), 7);
+        assert_eq!(section.data().get(block_index(0, 0, 0)), stone_entry);
+        assert_eq!(section.block_at(0, 0, 0), Block::Stone);
+        assert_eq!(section.block_at(1, 0, 0), Block::Cobblestone);
+    }
+
+    #[test]
+    fn optimize_section() {
+        let mut section = ChunkSection::default();
+        for id in 1..300usize {
+            section.set_block_at(
+                id % 16,
+                id / 256,
+                (id / 16) % 16,
+                Block::from_native_state_id(id as u16).unwrap(),
+            );
+        }
+        assert!(section.palette().is_none());
+
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in 0..2 {
+                    section.set_block_at(x, y, z, Block::Air);
+                }
+            }
+        }
+        section.set_block_at(3, 0, 3, Block::Stone);
+
+        assert!(section.optimize());
+        assert!(!section.optimize());
+
+        assert_eq!(section.palette().unwrap().len(), 2);
+        assert_eq!(section.bits_per_block(), MIN_BITS_PER_BLOCK);
+        assert_eq!(section.block_at(3, 0, 3), Block::Stone);
+        assert_eq!(section.block_at(4, 0, 3), Block::Air);
     }
 
     #[test]
@@ -1214,4 +1563,90 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_heightmaps() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.height_at(HeightmapKind::WorldSurface, 3, 4), 0);
+
+        chunk.set_block_at(3, 10, 4, Block::Stone);
+        chunk.set_block_at(3, 12, 4, Block::Water(Default::default()));
+        chunk.set_block_at(3, 20, 4, Block::Grass);
+        assert_eq!(chunk.height_at(HeightmapKind::WorldSurface, 3, 4), 21);
+        assert_eq!(chunk.height_at(HeightmapKind::MotionBlocking, 3, 4), 13);
+        assert_eq!(chunk.height_at(HeightmapKind::WorldSurface, 4, 3), 0);
+
+        chunk.set_block_at(3, 20, 4, Block::Air);
+        chunk.set_block_at(3, 12, 4, Block::Air);
+        assert_eq!(chunk.height_at(HeightmapKind::WorldSurface, 3, 4), 11);
+        assert_eq!(chunk.height_at(HeightmapKind::MotionBlocking, 3, 4), 11);
+
+        // Blocks below the top don't change the heightmaps.
+        chunk.set_block_at(3, 5, 4, Block::Stone);
+        chunk.set_block_at(3, 5, 4, Block::Air);
+        assert_eq!(chunk.height_at(HeightmapKind::WorldSurface, 3, 4), 11);
+
+        let mut copy = Chunk::default();
+        for (i, section) in chunk.sections().into_iter().enumerate() {
+            copy.set_section_at(i, section.cloned());
+        }
+        assert_eq!(copy.height_at(HeightmapKind::WorldSurface, 3, 4), 0);
+        copy.recalculate_heightmaps();
+        assert_eq!(copy.height_at(HeightmapKind::WorldSurface, 3, 4), 11);
+        assert_eq!(copy.height_at(HeightmapKind::MotionBlocking, 3, 4), 11);
+    }
+
+    #[test]
+    fn test_dirty_light() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.take_dirty_light(), 0);
+
+        // Unchanged values don't mark the section.
+        chunk.set_sky_light_at(0, 20, 0, 15);
+        chunk.set_block_light_at(0, 40, 0, 0);
+        assert_eq!(chunk.take_dirty_light(), 0);
+
+        chunk.set_sky_light_at(0, 20, 0, 14);
+        chunk.set_block_light_at(0, 40, 0, 3);
+        chunk.set_block_light_at(0, 255, 0, 3);
+        assert_eq!(chunk.take_dirty_light(), 0b1000_0000_0000_0110);
+        assert_eq!(chunk.take_dirty_light(), 0);
+
+        chunk.set_block_at(0, 0, 0, Block::Stone);
+        assert_eq!(chunk.take_dirty_light(), 0);
+        chunk.sections_mut();
+        assert_eq!(chunk.take_dirty_light(), 0b1000_0000_0000_0111);
+    }
+
+    #[test]
+    fn test_section_elision() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.sky_light_at(0, 20, 0), 15);
+        assert_eq!(chunk.block_light_at(0, 20, 0), 0);
+
+        // Trivial light values don't allocate a section.
+        chunk.set_sky_light_at(0, 20, 0, 15);
+        chunk.set_block_light_at(0, 20, 0, 0);
+        assert!(chunk.section(1).is_none());
+
+        chunk.set_block_at(0, 20, 0, Block::Stone);
+        assert!(chunk.section(1).is_some());
+        assert_eq!(chunk.sky_light_at(1, 20, 0), 15);
+
+        // Removing the only block drops the section again.
+        chunk.set_block_at(0, 20, 0, Block::Air);
+        assert!(chunk.section(1).is_none());
+
+        // Sections with block light are kept.
+        chunk.set_block_at(0, 20, 0, Block::Stone);
+        chunk.set_block_light_at(1, 20, 0, 14);
+        chunk.set_block_at(0, 20, 0, Block::Air);
+        chunk.optimize();
+        assert!(chunk.section(1).is_some());
+        assert_eq!(chunk.block_light_at(1, 20, 0), 14);
+
+        chunk.set_block_light_at(1, 20, 0, 0);
+        chunk.optimize();
+        assert!(chunk.section(1).is_none());
+    }
 }
@@ -1,5 +1,7 @@
+use crate::nbt::Value;
 use crate::world::block::*;
 use crate::world::chunk::Chunk;
+use crate::Biome;
 use glm::{DVec3, Vec3};
 use hashbrown::HashMap;
 use std::fmt;
@@ -313,6 +315,52 @@ impl ChunkMap {
         }
     }
 
+    /// Returns the block light at the specified location,
+    /// or `None` if the chunk containing it is not loaded.
+    pub fn block_light_at(&self, pos: BlockPosition) -> Option<u8> {
+        if pos.y > 255 || pos.y < 0 {
+            return None;
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        Some(self.chunk_at(pos.chunk_pos())?.block_light_at(x, y, z))
+    }
+
+    /// Returns the sky light at the specified location,
+    /// or `None` if the chunk containing it is not loaded.
+    ///
+    /// This is the light the block would receive at noon;
+    /// see `light_level_at` for the light at a given time.
+    pub fn sky_light_at(&self, pos: BlockPosition) -> Option<u8> {
+        if pos.y > 255 || pos.y < 0 {
+            return None;
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        Some(self.chunk_at(pos.chunk_pos())?.sky_light_at(x, y, z))
+    }
+
+    /// Returns the light level at the specified location at
+    /// the given time of day, from 0 to 24000, as used by vanilla
+    /// to decide whether mobs may spawn. This is the greater of
+    /// the block light and the sky light darkened for the time.
+    ///
+    /// Returns `None` if the chunk containing it is not loaded.
+    pub fn light_level_at(&self, pos: BlockPosition, time_of_day: u64) -> Option<u8> {
+        let sky_light = self
+            .sky_light_at(pos)?
+            .saturating_sub(sky_darkening(time_of_day));
+        Some(self.block_light_at(pos)?.max(sky_light))
+    }
+
+    /// Returns the biome of the column containing the
+    /// specified location, or `None` if the chunk containing
+    /// it is not loaded. The biome doesn't depend on `pos.y`.
+    pub fn biome_at(&self, pos: BlockPosition) -> Option<Biome> {
+        let (x, _, z) = chunk_relative_pos(pos);
+        Some(self.chunk_at(pos.chunk_pos())?.biome_at(x, z))
+    }
+
     /// Sets the block at the given position.
     /// If the chunk in which the position resides
     /// does not exist, `Err` is returned. In all
@@ -337,6 +385,44 @@ impl ChunkMap {
         }
     }
 
+    /// Returns the NBT data of the block entity at the given
+    /// position, or `None` if there is none or the chunk
+    /// containing the position is not loaded.
+    pub fn block_entity_at(&self, pos: BlockPosition) -> Option<&Value> {
+        if pos.y > 255 || pos.y < 0 {
+            return None;
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        self.chunk_at(pos.chunk_pos())?.block_entity_at(x, y, z)
+    }
+
+    /// Sets the NBT data of the block entity at the given position.
+    /// If the chunk in which the position resides does not exist,
+    /// `Err` is returned.
+    pub fn set_block_entity_at(&mut self, pos: BlockPosition, data: Value) -> Result<(), ()> {
+        if pos.y > 255 || pos.y < 0 {
+            return Err(());
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        let chunk = self.chunk_at_mut(pos.chunk_pos()).ok_or(())?;
+        chunk.set_block_entity_at(x, y, z, data);
+        Ok(())
+    }
+
+    /// Removes the block entity at the given position,
+    /// returning its data if there was one.
+    pub fn remove_block_entity_at(&mut self, pos: BlockPosition) -> Option<Value> {
+        if pos.y > 255 || pos.y < 0 {
+            return None;
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        self.chunk_at_mut(pos.chunk_pos())?
+            .remove_block_entity_at(x, y, z)
+    }
+
     /// Sets the chunk at the given location.
     pub fn set_chunk_at(&mut self, pos: ChunkPosition, chunk: Chunk) {
         self.chunk_map.insert(pos, chunk);
@@ -366,6 +452,98 @@ impl Default for ChunkMap {
     }
 }
 
+/// A view of a `ChunkMap` which speeds up accessing
+/// groups of clustered blocks, such as during lighting
+/// or fluid updates, by avoiding repeated lookups of
+/// the same chunk.
+///
+/// The most recently accessed chunk is taken out of the
+/// chunk map and cached until a block in another chunk
+/// is accessed. Since the cache holds a unique reference
+/// to the chunk map, the missing chunk can't be observed;
+/// it is put back when the cache is dropped.
+pub struct ChunkCache<'a> {
+    chunk_map: &'a mut ChunkMap,
+    current: Option<Chunk>,
+}
+
+impl<'a> ChunkCache<'a> {
+    pub fn new(chunk_map: &'a mut ChunkMap) -> Self {
+        Self {
+            chunk_map,
+            current: None,
+        }
+    }
+
+    /// Retrieves the chunk at the specified location.
+    /// If the chunk is not loaded, `None` will be returned.
+    pub fn chunk_at_mut(&mut self, pos: ChunkPosition) -> Option<&mut Chunk> {
+        if self.current.as_ref().map(Chunk::position) != Some(pos) {
+            let chunk = self.chunk_map.unload_chunk_at(pos)?;
+            if let Some(previous) = self.current.replace(chunk) {
+                self.chunk_map.set_chunk_at(previous.position(), previous);
+            }
+        }
+
+        self.current.as_mut()
+    }
+
+    /// Returns whether the chunk at the specified location is loaded.
+    pub fn is_loaded(&mut self, pos: ChunkPosition) -> bool {
+        self.chunk_at_mut(pos).is_some()
+    }
+
+    /// Retrieves the block at the specified
+    /// location. If the chunk in which the block
+    /// exists is not loaded, `None` is returned.
+    pub fn block_at(&mut self, pos: BlockPosition) -> Option<Block> {
+        if pos.y > 255 || pos.y < 0 {
+            return None;
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        Some(self.chunk_at_mut(pos.chunk_pos())?.block_at(x, y, z))
+    }
+
+    /// Sets the block at the given position.
+    /// If the chunk in which the position resides
+    /// does not exist, `Err` is returned.
+    pub fn set_block_at(&mut self, pos: BlockPosition, block: Block) -> Result<(), ()> {
+        if pos.y > 255 || pos.y < 0 {
+            return Err(());
+        }
+
+        let (x, y, z) = chunk_relative_pos(pos);
+        self.chunk_at_mut(pos.chunk_pos())
+            .ok_or(())?
+            .set_block_at(x, y, z, block);
+        Ok(())
+    }
+}
+
+impl<'a> Drop for ChunkCache<'a> {
+    fn drop(&mut self) {
+        if let Some(chunk) = self.current.take() {
+            self.chunk_map.set_chunk_at(chunk.position(), chunk);
+        }
+    }
+}
+
+/// Returns the number of levels by which sky light is
+/// darkened at the given time of day, from 0 at noon
+/// to 11 at night. Weather is not taken into account.
+pub fn sky_darkening(time_of_day: u64) -> u8 {
+    // The angle of the sun, as calculated by vanilla.
+    let time = (time_of_day % 24_000) as f32 / 24_000.0 - 0.25;
+    let time = if time < 0.0 { time + 1.0 } else { time };
+    let smoothed = 1.0 - ((f64::from(time) * std::f64::consts::PI).cos() as f32 + 1.0) / 2.0;
+    let angle = time + (smoothed - time) / 3.0;
+
+    let daylight = (angle * std::f32::consts::PI * 2.0).cos() * 2.0 + 0.5;
+    let darkness = 1.0 - daylight.max(0.0).min(1.0);
+    (darkness * 11.0) as u8
+}
+
 pub fn chunk_relative_pos(block_pos: BlockPosition) -> (usize, usize, usize) {
     (
         block_pos.x as usize & 0xf,
@@ -455,4 +633,92 @@ mod tests {
             Block::Air
         );
     }
+
+    #[test]
+    fn test_chunk_cache() {
+        let mut world = ChunkMap::new();
+        for &(x, z) in &[(0, 0), (1, 0)] {
+            let pos = ChunkPosition::new(x, z);
+            world.set_chunk_at(pos, Chunk::new(pos));
+        }
+
+        {
+            let mut cache = ChunkCache::new(&mut world);
+            assert!(cache.is_loaded(ChunkPosition::new(0, 0)));
+            assert!(!cache.is_loaded(ChunkPosition::new(2, 0)));
+
+            cache
+                .set_block_at(BlockPosition::new(15, 64, 3), Block::Stone)
+                .unwrap();
+            cache
+                .set_block_at(BlockPosition::new(16, 64, 3), Block::Dirt)
+                .unwrap();
+            assert!(cache
+                .set_block_at(BlockPosition::new(32, 64, 3), Block::Dirt)
+                .is_err());
+
+            assert_eq!(
+                cache.block_at(BlockPosition::new(15, 64, 3)),
+                Some(Block::Stone)
+            );
+            assert_eq!(cache.block_at(BlockPosition::new(32, 64, 3)), None);
+        }
+
+        // All chunks are put back into the map.
+        assert_eq!(world.chunks().len(), 2);
+        assert_eq!(
+            world.block_at(BlockPosition::new(15, 64, 3)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            world.block_at(BlockPosition::new(16, 64, 3)),
+            Some(Block::Dirt)
+        );
+    }
+
+    #[test]
+    fn test_light_queries() {
+        let mut world = ChunkMap::new();
+        let pos = ChunkPosition::new(0, 0);
+        let mut chunk = Chunk::new(pos);
+        chunk.set_block_light_at(3, 64, 3, 9);
+        chunk.set_sky_light_at(3, 64, 3, 12);
+        chunk.set_sky_light_at(4, 64, 3, 5);
+        world.set_chunk_at(pos, chunk);
+
+        let lit = BlockPosition::new(3, 64, 3);
+        let dark = BlockPosition::new(4, 64, 3);
+        assert_eq!(world.block_light_at(lit), Some(9));
+        assert_eq!(world.sky_light_at(lit), Some(12));
+        assert_eq!(world.sky_light_at(BlockPosition::new(16, 64, 3)), None);
+
+        assert_eq!(sky_darkening(6000), 0);
+        assert_eq!(sky_darkening(18000), 11);
+        assert_eq!(sky_darkening(24000 + 6000), 0);
+
+        // At noon, sky light wins; at night, block light does.
+        assert_eq!(world.light_level_at(lit, 6000), Some(12));
+        assert_eq!(world.light_level_at(lit, 18000), Some(9));
+        assert_eq!(world.light_level_at(dark, 18000), Some(0));
+        assert_eq!(world.light_level_at(BlockPosition::new(0, 300, 0), 0), None);
+    }
+
+    #[test]
+    fn test_biome_at() {
+        let mut world = ChunkMap::new();
+        let pos = ChunkPosition::new(-1, 0);
+        let mut chunk = Chunk::new(pos);
+        chunk.set_biome_at(15, 2, Biome::Desert);
+        world.set_chunk_at(pos, chunk);
+
+        assert_eq!(
+            world.biome_at(BlockPosition::new(-1, 300, 2)),
+            Some(Biome::Desert)
+        );
+        assert_eq!(
+            world.biome_at(BlockPosition::new(-2, 64, 2)),
+            Some(Biome::Plains)
+        );
+        assert_eq!(world.biome_at(BlockPosition::new(0, 64, 2)), None);
+    }
 }
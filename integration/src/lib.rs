@@ -0,0 +1,336 @@
+//! Harness for end-to-end tests of the server.
+//!
+//! A `TestServer` runs the full server—every system, with
+//! its own world directory—in-process, and is ticked manually
+//! by the test. `TestClient`s connect to it at the protocol level:
+//! they are handed to the server as if they had completed the login
+//! sequence, after which they exchange play packets with the server
+//! over the same channels as the IO workers. Sockets, encryption
+//! and compression are not involved. A server started with
+//! `TestServer::listen` also accepts connections over TCP.
+//!
+//! ```ignore
+//! let mut server = TestServer::new();
+//! let mut alice = server.join("alice");
+//! let mut bob = server.join("bob");
+//!
+//! alice.chat("Hello");
+//! server.tick();
+//! let message = bob.expect::<ChatMessageClientbound>(PacketType::ChatMessageClientbound);
+//! ```
+
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    ChatMessageServerbound, JoinGame, PlayerDigging, PlayerDiggingStatus, PlayerPosition,
+};
+use feather_core::world::ChunkMap;
+use feather_core::{Block, BlockPosition, Packet, PacketType};
+use feather_server::config::{Config, SharedConfig};
+use feather_server::io::{
+    ListenerToServerMessage, NetworkIoManager, NewClientInfo, ServerToWorkerMessage,
+};
+use feather_server::PlayerCount;
+use futures::channel::mpsc::UnboundedReceiver;
+use specs::{Dispatcher, Entity, World, WorldExt};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+use uuid::Uuid;
+
+/// The maximum time to wait for a condition
+/// in `TestServer::tick_until`.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server running in-process.
+pub struct TestServer {
+    pub world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    world_dir: PathBuf,
+}
+
+impl TestServer {
+    /// Starts a server with the default configuration
+    /// on a new superflat world.
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        config.world.generator = String::from("flat");
+        config.server.view_distance = 2;
+        Self::with_config(config)
+    }
+
+    /// Starts a server with the given configuration. The world
+    /// name is replaced by a fresh temporary directory, which is
+    /// removed when the server is dropped.
+    pub fn with_config(config: Config) -> Self {
+        Self::start(config, |_, _| NetworkIoManager::in_memory())
+    }
+
+    /// Starts a server with the given configuration which
+    /// accepts connections on a free local port, returning
+    /// the server and its address. Online mode is disabled,
+    /// since clients cannot authenticate with Mojang.
+    ///
+    /// # Panics
+    /// Panics if not called from within a Tokio runtime.
+    pub fn listen(mut config: Config) -> (Self, SocketAddr) {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        config.server.online_mode = false;
+        config.server.address = address.ip().to_string();
+        config.server.port = address.port();
+        config.server.listeners.clear();
+
+        let server = Self::start(config, |config, player_count| {
+            let listeners = config.get().server.listeners().unwrap();
+            NetworkIoManager::start(listeners, config, player_count, Arc::new(None))
+        });
+        (server, address)
+    }
+
+    fn start(
+        mut config: Config,
+        ioman: impl FnOnce(Arc<SharedConfig>, Arc<PlayerCount>) -> NetworkIoManager,
+    ) -> Self {
+        let world_dir = std::env::temp_dir().join(format!("feather-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&world_dir).unwrap();
+        config.world.name = world_dir.to_str().unwrap().to_string();
+
+        let level = feather_server::create_level(&config);
+        let shared_config = Arc::new(SharedConfig::new(Arc::new(config)));
+        let player_count = Arc::new(PlayerCount::default());
+        let ioman = ioman(Arc::clone(&shared_config), Arc::clone(&player_count));
+
+        let (mut world, dispatcher) =
+            feather_server::init_world(shared_config, player_count, ioman, level);
+        feather_server::load_spawn_chunks(&mut world);
+
+        Self {
+            world,
+            dispatcher,
+            world_dir,
+        }
+    }
+
+    /// Runs a single tick.
+    pub fn tick(&mut self) {
+        feather_server::tick(&mut self.world, &mut self.dispatcher);
+    }
+
+    /// Runs the given number of ticks.
+    pub fn tick_n(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Ticks the server until `condition` returns `true`.
+    ///
+    /// Chunks are loaded asynchronously, so conditions which
+    /// depend on chunks may take some time to become true.
+    ///
+    /// # Panics
+    /// Panics if the condition is not met within `TIMEOUT`.
+    pub fn tick_until(&mut self, mut condition: impl FnMut(&mut Self) -> bool) {
+        let start = Instant::now();
+        while !condition(self) {
+            if start.elapsed() > TIMEOUT {
+                panic!("condition not met within {:?}", TIMEOUT);
+            }
+            self.tick();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Connects a client which has completed the
+    /// login sequence with the given username.
+    ///
+    /// The client has not yet joined; use `join`
+    /// to wait until it has spawned.
+    pub fn connect(&mut self, username: &str) -> TestClient {
+        let (server_sender, receiver) = futures::channel::mpsc::unbounded();
+        let (sender, server_receiver) = crossbeam::unbounded();
+
+        let uuid = Uuid::new_v4();
+        let info = NewClientInfo {
+            ip: SocketAddr::new("127.0.0.1".parse().unwrap(), 25565),
+            username: username.to_string(),
+            profile: vec![],
+            uuid,
+            sender: server_sender,
+            receiver: server_receiver,
+        };
+        self.world
+            .fetch::<NetworkIoManager>()
+            .listener_sender
+            .send(ListenerToServerMessage::NewClient(info))
+            .unwrap();
+
+        TestClient {
+            username: username.to_string(),
+            uuid,
+            entity_id: None,
+            sender,
+            receiver,
+            received: vec![],
+            disconnected: false,
+        }
+    }
+
+    /// Connects a client and ticks the server until it
+    /// has received its chunks and spawned.
+    pub fn join(&mut self, username: &str) -> TestClient {
+        let mut client = self.connect(username);
+        self.tick_until(|_| client.has_received(PacketType::PlayerPositionAndLookClientbound));
+
+        let join_game = client.expect::<JoinGame>(PacketType::JoinGame);
+        client.entity_id = Some(join_game.entity_id);
+        client
+    }
+
+    /// Returns the entity of a client which has joined.
+    pub fn entity(&self, client: &TestClient) -> Entity {
+        let id = client.entity_id.expect("client has not joined");
+        self.world.entities().entity(id as u32)
+    }
+
+    /// Returns the block at the given position,
+    /// or `None` if its chunk is not loaded.
+    pub fn block_at(&self, pos: BlockPosition) -> Option<Block> {
+        self.world.fetch::<ChunkMap>().block_at(pos)
+    }
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.world_dir);
+    }
+}
+
+/// A fake client connected to a `TestServer`.
+pub struct TestClient {
+    pub username: String,
+    pub uuid: Uuid,
+    /// The network ID of the client's entity,
+    /// known once the client has joined.
+    pub entity_id: Option<i32>,
+    sender: crossbeam::Sender<ServerToWorkerMessage>,
+    receiver: UnboundedReceiver<ServerToWorkerMessage>,
+    /// Packets received but not yet
+    /// consumed by the test.
+    received: Vec<Box<dyn Packet>>,
+    disconnected: bool,
+}
+
+impl TestClient {
+    /// Sends a packet to the server. It is
+    /// handled during the next tick.
+    pub fn send<P: Packet + 'static>(&self, packet: P) {
+        let _ = self
+            .sender
+            .send(ServerToWorkerMessage::NotifyPacketReceived(Box::new(
+                packet,
+            )));
+    }
+
+    /// Moves the client to the given position.
+    pub fn move_to(&self, x: f64, y: f64, z: f64) {
+        self.send(PlayerPosition::new(x, y, z, true));
+    }
+
+    /// Breaks the block at the given position.
+    /// The client must be in creative mode.
+    pub fn break_block(&self, pos: BlockPosition) {
+        self.send(PlayerDigging::new(
+            PlayerDiggingStatus::StartedDigging,
+            pos,
+            0,
+        ));
+    }
+
+    /// Sends a chat message or, if the
+    /// message starts with `/`, a command.
+    pub fn chat(&self, message: &str) {
+        self.send(ChatMessageServerbound::new(message.to_string()));
+    }
+
+    /// Closes the connection, as if the client
+    /// had disconnected.
+    pub fn disconnect(&self) {
+        let _ = self
+            .sender
+            .send(ServerToWorkerMessage::NotifyDisconnect(String::from(
+                "Disconnected",
+            )));
+    }
+
+    /// Returns whether the server has disconnected the client.
+    pub fn is_disconnected(&mut self) -> bool {
+        self.poll();
+        self.disconnected
+    }
+
+    /// Returns whether a packet of the given type has
+    /// been received and not yet consumed.
+    pub fn has_received(&mut self, ty: PacketType) -> bool {
+        self.poll();
+        self.received.iter().any(|packet| packet.ty() == ty)
+    }
+
+    /// Removes and returns all received packets
+    /// of the given type, in the order they were
+    /// received.
+    pub fn take_all(&mut self, ty: PacketType) -> Vec<Box<dyn Packet>> {
+        self.poll();
+        let (matching, rest): (Vec<_>, Vec<_>) = self
+            .received
+            .drain(..)
+            .partition(|packet| packet.ty() == ty);
+        self.received = rest;
+        matching
+    }
+
+    /// Removes and returns the first received
+    /// packet of the given type.
+    pub fn take<P: Packet + Clone + 'static>(&mut self, ty: PacketType) -> Option<P> {
+        self.poll();
+        let index = self.received.iter().position(|packet| packet.ty() == ty)?;
+        let packet = self.received.remove(index);
+        Some(cast_packet::<P>(&*packet).clone())
+    }
+
+    /// Removes and returns the first received
+    /// packet of the given type.
+    ///
+    /// # Panics
+    /// Panics if no such packet has been received.
+    pub fn expect<P: Packet + Clone + 'static>(&mut self, ty: PacketType) -> P {
+        self.take(ty)
+            .unwrap_or_else(|| panic!("{} did not receive {:?}", self.username, ty))
+    }
+
+    /// Discards all received packets.
+    pub fn clear(&mut self) {
+        self.poll();
+        self.received.clear();
+    }
+
+    /// Moves packets sent by the server into `received`.
+    fn poll(&mut self) {
+        while let Ok(Some(msg)) = self.receiver.try_next() {
+            match msg {
+                ServerToWorkerMessage::SendPacket(packet) => self.received.push(packet),
+                ServerToWorkerMessage::Disconnect => self.disconnected = true,
+                _ => panic!("Client received invalid message from server"),
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use feather_core::network::packet::implementation::BlockChange;
+use feather_core::{Block, BlockExt, BlockPosition, PacketType};
+use feather_integration::TestServer;
+
+#[test]
+fn test_break_block() {
+    let mut server = TestServer::new();
+    let alice = server.join("alice");
+    let mut bob = server.join("bob");
+
+    // Top layer of the default superflat world.
+    let pos = BlockPosition::new(1, 3, 1);
+    assert_eq!(server.block_at(pos), Some(Block::GrassBlock));
+
+    alice.break_block(pos);
+    server.tick_n(2);
+
+    assert_eq!(server.block_at(pos), Some(Block::Air));
+
+    let packet = bob.expect::<BlockChange>(PacketType::BlockChange);
+    assert_eq!(packet.location, pos);
+    assert_eq!(packet.block_id, i32::from(Block::Air.native_state_id()));
+}
+
+#[test]
+fn test_break_block_out_of_view() {
+    let mut server = TestServer::new();
+    let alice = server.join("alice");
+    let mut bob = server.join("bob");
+
+    // Move Bob far enough away that the
+    // broken block's chunk is unloaded for him.
+    bob.move_to(200.0, 100.0, 200.0);
+    server.tick_until(|_| bob.has_received(PacketType::UnloadChunk));
+    server.tick();
+    bob.clear();
+
+    let pos = BlockPosition::new(1, 3, 1);
+    alice.break_block(pos);
+    server.tick_n(2);
+
+    assert_eq!(server.block_at(pos), Some(Block::Air));
+    assert!(!bob.has_received(PacketType::BlockChange));
+}
@@ -0,0 +1,54 @@
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::ChatMessageClientbound;
+use feather_core::PacketType;
+use feather_integration::{TestClient, TestServer};
+
+fn messages(client: &mut TestClient) -> Vec<String> {
+    client
+        .take_all(PacketType::ChatMessageClientbound)
+        .iter()
+        .map(|packet| {
+            cast_packet::<ChatMessageClientbound>(&**packet)
+                .json_data
+                .clone()
+        })
+        .collect()
+}
+
+#[test]
+fn test_chat() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+    let mut bob = server.join("bob");
+    server.tick();
+    alice.clear();
+    bob.clear();
+
+    alice.chat("Hello, world!");
+    server.tick_n(2);
+
+    for client in &mut [&mut alice, &mut bob] {
+        let messages = messages(client);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Hello, world!"));
+        assert!(messages[0].contains("alice"));
+    }
+}
+
+#[test]
+fn test_command_response() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+    let mut bob = server.join("bob");
+    server.tick();
+    alice.clear();
+    bob.clear();
+
+    alice.chat("/nonexistent");
+    server.tick_n(2);
+
+    let messages = messages(&mut alice);
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("Unknown command."));
+    assert!(!bob.has_received(PacketType::ChatMessageClientbound));
+}
@@ -0,0 +1,56 @@
+use feather_core::network::packet::implementation::{
+    DestroyEntities, EntityRelativeMove, SpawnPlayer,
+};
+use feather_core::PacketType;
+use feather_integration::TestServer;
+
+#[test]
+fn test_players_visible() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+    let mut bob = server.join("bob");
+    server.tick_n(2);
+
+    // Alice sees Bob join...
+    let packet = alice.expect::<SpawnPlayer>(PacketType::SpawnPlayer);
+    assert_eq!(Some(packet.entity_id), bob.entity_id);
+    assert_eq!(packet.player_uuid, bob.uuid);
+
+    // ...and Bob sees Alice, who was already online.
+    let packet = bob.expect::<SpawnPlayer>(PacketType::SpawnPlayer);
+    assert_eq!(Some(packet.entity_id), alice.entity_id);
+    assert_eq!(packet.player_uuid, alice.uuid);
+}
+
+#[test]
+fn test_movement_visible() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+    let bob = server.join("bob");
+    server.tick();
+    alice.clear();
+
+    bob.move_to(1.0, 100.0, 0.0);
+    server.tick_n(2);
+
+    let packet = alice.expect::<EntityRelativeMove>(PacketType::EntityRelativeMove);
+    assert_eq!(Some(packet.entity_id), bob.entity_id);
+    assert_eq!(packet.delta_x, 32 * 128);
+    assert_eq!(packet.delta_y, 0);
+    assert_eq!(packet.delta_z, 0);
+}
+
+#[test]
+fn test_disconnect_destroys_entity() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+    let bob = server.join("bob");
+    server.tick();
+    alice.clear();
+
+    bob.disconnect();
+    server.tick_until(|_| alice.has_received(PacketType::DestroyEntities));
+
+    let packet = alice.expect::<DestroyEntities>(PacketType::DestroyEntities);
+    assert_eq!(packet.entity_ids, vec![bob.entity_id.unwrap()]);
+}
@@ -0,0 +1,45 @@
+use feather_core::network::packet::implementation::{ChatMessageClientbound, SpawnPosition};
+use feather_core::PacketType;
+use feather_integration::TestServer;
+use specs::WorldExt;
+
+#[test]
+fn test_join() {
+    let mut server = TestServer::new();
+    let mut alice = server.join("alice");
+
+    // View distance 2 gives a 5x5 area of chunks.
+    assert_eq!(alice.take_all(PacketType::ChunkData).len(), 25);
+
+    let spawn = alice.expect::<SpawnPosition>(PacketType::SpawnPosition);
+    assert_eq!(spawn.location.y, 100);
+
+    let entity = server.entity(&alice);
+    assert!(server.world.entities().is_alive(entity));
+
+    server.tick();
+    let message = alice.expect::<ChatMessageClientbound>(PacketType::ChatMessageClientbound);
+    assert!(message.json_data.contains("multiplayer.player.joined"));
+    assert!(message.json_data.contains("alice"));
+}
+
+#[test]
+fn test_disconnect() {
+    let mut server = TestServer::new();
+    let alice = server.join("alice");
+    let mut bob = server.join("bob");
+    server.tick();
+    bob.clear();
+
+    let entity = server.entity(&alice);
+    alice.disconnect();
+    server.tick_until(|server| !server.world.entities().is_alive(entity));
+    server.tick();
+
+    let messages = bob.take_all(PacketType::ChatMessageClientbound);
+    assert!(messages.iter().any(|packet| {
+        feather_core::network::cast_packet::<ChatMessageClientbound>(&**packet)
+            .json_data
+            .contains("multiplayer.player.left")
+    }));
+}
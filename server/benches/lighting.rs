@@ -0,0 +1,52 @@
+//! Benchmarking of lighting newly generated chunks, comparing
+//! lighting a batch of chunks on one thread and in parallel.
+
+#[macro_use]
+extern crate criterion;
+
+use criterion::{BenchmarkId, Criterion};
+use feather_core::{Chunk, ChunkPosition};
+use feather_server::lighting;
+use feather_server::worldgen::{ComposableGenerator, WorldGenerator};
+
+const BATCH_SIZES: [i32; 2] = [4, 16];
+
+const SEED: u64 = 7_867_835_453;
+
+/// Generates a square batch of `size` chunks.
+fn generate_batch(size: i32) -> Vec<Chunk> {
+    let generator = ComposableGenerator::default_with_seed(SEED);
+    let width = (size as f64).sqrt() as i32;
+
+    (0..size)
+        .map(|i| generator.generate_chunk(ChunkPosition::new(i % width, i / width)))
+        .collect()
+}
+
+pub fn light_chunks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("light_chunks");
+
+    for size in BATCH_SIZES.iter() {
+        let batch = generate_batch(*size);
+
+        group.bench_with_input(BenchmarkId::new("serial", size), &batch, |b, batch| {
+            b.iter(|| {
+                let mut chunks = batch.clone();
+                chunks
+                    .iter_mut()
+                    .for_each(|chunk| lighting::calculate_light(chunk, true));
+                chunks
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", size), &batch, |b, batch| {
+            b.iter(|| {
+                let mut chunks = batch.clone();
+                lighting::light_chunks(&mut chunks, true);
+                chunks
+            })
+        });
+    }
+}
+
+criterion_group!(benches, light_chunks);
+criterion_main!(benches);
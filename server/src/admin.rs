@@ -0,0 +1,785 @@
+//! HTTP admin API.
+//!
+//! When enabled, an HTTP server is started on a separate thread
+//! which allows web panels to manage the server. Every request
+//! must carry the configured token in an `Authorization: Bearer`
+//! header. Requests and responses are JSON:
+//!
+//! * `GET /status` - version, player count, TPS and loaded chunks.
+//! * `GET /players` - the online players and their positions.
+//! * `POST /players/<name>/kick` - kicks a player. Accepts an
+//! optional `reason`.
+//! * `GET /bans` - the list of bans.
+//! * `PUT /bans/<name>` - bans a player, kicking them if they
+//! are online. Accepts an optional `reason`.
+//! * `DELETE /bans/<name>` - removes a ban.
+//! * `POST /command` - runs the `command` with the privileges
+//! of the console and returns its output.
+//!
+//! Requests are forwarded to `AdminSystem`, which handles them
+//! on the server thread.
+
+use crate::bans::{self, BanList};
+use crate::commands::{CommandEvent, ConsoleComponent};
+use crate::config::{AdminApi, Config};
+use crate::entity::{NamedComponent, PlayerComponent, PositionComponent};
+use crate::systems::ADMIN_API;
+use crate::timings::{DispatcherBuilderExt, TIMINGS};
+use crate::{disconnect_player, PlayerCount, TickCount, PROTOCOL_VERSION, SERVER_VERSION, TPS};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use feather_core::world::ChunkMap;
+use serde_json::Value;
+use shrev::EventChannel;
+use specs::{
+    DispatcherBuilder, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System, Write,
+    WriteStorage,
+};
+use std::io::{self, Read as _, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The maximum size of a request, including headers.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// The time to wait for the server to handle a request
+/// and, for commands, to finish producing output.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The number of ticks after which the sender of a
+/// command is removed, by which time all command
+/// systems have handled it.
+const COMMAND_SENDER_TICKS: u64 = 2;
+
+/// The reason given when kicking a player
+/// if the request does not specify one.
+pub const DEFAULT_KICK_REASON: &str = "Kicked by an operator.";
+
+/// An action requested through the API.
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Status,
+    Players,
+    Kick { name: String, reason: String },
+    Bans,
+    Ban { name: String, reason: String },
+    Unban { name: String },
+    Command { command: String },
+}
+
+/// The result of an action.
+enum Response {
+    Json(u16, Value),
+    /// The output of a command. The command has
+    /// finished once the channel is disconnected.
+    Output(Receiver<String>),
+}
+
+struct Request {
+    action: Action,
+    response: Sender<Response>,
+}
+
+/// Resource containing requests received by the HTTP server.
+pub struct AdminRequests(Receiver<Request>);
+
+/// Starts the admin API HTTP server on a new thread.
+pub fn start_server(config: &AdminApi) -> Result<AdminRequests, failure::Error> {
+    if config.token.is_empty() {
+        bail!("The admin API token must be set");
+    }
+    let addr: SocketAddr = config.address.parse()?;
+
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving admin API on http://{}", addr);
+
+    let (sender, receiver) = crossbeam::unbounded();
+    let token = Arc::new(config.token.clone());
+
+    std::thread::Builder::new()
+        .name(String::from("Admin API"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!("Failed to accept admin API connection: {}", e);
+                        continue;
+                    }
+                };
+
+                // Commands may take several ticks, so handle each
+                // connection separately to avoid blocking others.
+                let sender = sender.clone();
+                let token = Arc::clone(&token);
+                let _ = std::thread::Builder::new()
+                    .name(String::from("Admin API Connection"))
+                    .spawn(move || {
+                        if let Err(e) = handle_connection(stream, &token, &sender) {
+                            debug!("Failed to handle admin API request: {}", e);
+                        }
+                    });
+            }
+        })?;
+
+    Ok(AdminRequests(receiver))
+}
+
+/// The parts of an HTTP request used by the API.
+#[derive(Debug, PartialEq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    content_length: usize,
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    token: &str,
+    sender: &Sender<Request>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+
+    let (request, body) = match read_request(&mut stream)? {
+        Some(request) => request,
+        None => return write_response(&mut stream, 400, &error("Malformed request")),
+    };
+
+    let (status, body) = if !is_authorized(request.authorization.as_deref(), token) {
+        (401, error("Missing or invalid token"))
+    } else {
+        match route(&request.method, &request.path, &body) {
+            Ok(action) => execute(action, sender),
+            Err((status, message)) => (status, error(message)),
+        }
+    };
+
+    write_response(&mut stream, status, &body)
+}
+
+/// Reads a request and its body, returning
+/// `None` if the request is malformed.
+fn read_request(stream: &mut TcpStream) -> io::Result<Option<(HttpRequest, Vec<u8>)>> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    let head_end = loop {
+        if let Some(index) = find(&data, b"\r\n\r\n") {
+            break index + 4;
+        }
+        if data.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        data.extend_from_slice(&buf[..n]);
+    };
+
+    let request = match parse_head(&String::from_utf8_lossy(&data[..head_end])) {
+        Some(request) => request,
+        None => return Ok(None),
+    };
+    if head_end + request.content_length > MAX_REQUEST_SIZE {
+        return Ok(None);
+    }
+
+    let mut body = data.split_off(head_end);
+    while body.len() < request.content_length {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+    body.truncate(request.content_length);
+
+    Ok(Some((request, body)))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parses the request line and headers.
+fn parse_head(head: &str) -> Option<HttpRequest> {
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    // Ignore the query string.
+    let path = target.split('?').next()?.to_string();
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    for line in lines.filter(|line| !line.is_empty()) {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next()?.trim();
+        let value = parts.next()?.trim();
+
+        if name.eq_ignore_ascii_case("Authorization") {
+            authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse().ok()?;
+        }
+    }
+
+    Some(HttpRequest {
+        method,
+        path,
+        authorization,
+        content_length,
+    })
+}
+
+/// Checks the `Authorization` header against the token.
+/// The comparison takes the same time regardless of
+/// where the header differs from the token.
+fn is_authorized(authorization: Option<&str>, token: &str) -> bool {
+    let provided = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(provided) => provided.as_bytes(),
+        None => return false,
+    };
+    let token = token.as_bytes();
+
+    provided.len() == token.len()
+        && provided
+            .iter()
+            .zip(token)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Determines the action for a request, or the
+/// status and message of the error response.
+fn route(method: &str, path: &str, body: &[u8]) -> Result<Action, (u16, &'static str)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let action = match (method, segments.as_slice()) {
+        ("GET", ["status"]) => Action::Status,
+        ("GET", ["players"]) => Action::Players,
+        ("POST", ["players", name, "kick"]) => Action::Kick {
+            name: name.to_string(),
+            reason: field(body, "reason")?.unwrap_or_else(|| DEFAULT_KICK_REASON.to_string()),
+        },
+        ("GET", ["bans"]) => Action::Bans,
+        ("PUT", ["bans", name]) => Action::Ban {
+            name: name.to_string(),
+            reason: field(body, "reason")?.unwrap_or_else(|| bans::DEFAULT_REASON.to_string()),
+        },
+        ("DELETE", ["bans", name]) => Action::Unban {
+            name: name.to_string(),
+        },
+        ("POST", ["command"]) => {
+            let command = field(body, "command")?.unwrap_or_default();
+            let command = command.trim().trim_start_matches('/');
+            if command.is_empty() {
+                return Err((400, "Missing command"));
+            }
+            Action::Command {
+                command: command.to_string(),
+            }
+        }
+        _ => return Err((404, "Not found")),
+    };
+
+    Ok(action)
+}
+
+/// Returns a string field of a JSON object body.
+/// An empty body has no fields.
+fn field(body: &[u8], name: &str) -> Result<Option<String>, (u16, &'static str)> {
+    if body.is_empty() {
+        return Ok(None);
+    }
+
+    let value: Value = serde_json::from_slice(body).map_err(|_| (400, "Invalid JSON"))?;
+    match value.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err((400, "Invalid field type")),
+    }
+}
+
+/// Forwards an action to the server and waits for the response.
+fn execute(action: Action, sender: &Sender<Request>) -> (u16, Value) {
+    let (response_tx, response_rx) = crossbeam::bounded(1);
+    let request = Request {
+        action,
+        response: response_tx,
+    };
+    if sender.send(request).is_err() {
+        return (503, error("Server is not running"));
+    }
+
+    match response_rx.recv_timeout(RESPONSE_TIMEOUT) {
+        Ok(Response::Json(status, body)) => (status, body),
+        Ok(Response::Output(output)) => {
+            let deadline = Instant::now() + RESPONSE_TIMEOUT;
+            let mut lines = vec![];
+            loop {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match output.recv_timeout(timeout) {
+                    Ok(line) => lines.push(line),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return (504, error("Command did not finish in time"))
+                    }
+                }
+            }
+            (200, json!({ "output": lines }))
+        }
+        Err(_) => (503, error("Server did not respond")),
+    }
+}
+
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    };
+    let body = body.to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// System which handles requests to the admin API.
+#[derive(Default)]
+pub struct AdminSystem {
+    /// Entities created to send commands, and
+    /// the tick at which they were created.
+    senders: Vec<(Entity, u64)>,
+    ticks: u64,
+}
+
+impl<'a> System<'a> for AdminSystem {
+    type SystemData = (
+        Option<Read<'a, AdminRequests>>,
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        Write<'a, EventChannel<CommandEvent>>,
+        Write<'a, BanList>,
+        WriteStorage<'a, ConsoleComponent>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, PositionComponent>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Arc<PlayerCount>>,
+        Read<'a, TickCount>,
+        Read<'a, ChunkMap>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            requests,
+            entities,
+            lazy,
+            mut command_events,
+            mut ban_list,
+            mut consoles,
+            nameds,
+            players,
+            positions,
+            config,
+            player_count,
+            tick_count,
+            chunk_map,
+        ) = data;
+
+        // Removing the sender of a command drops its
+        // output channel, completing the response.
+        let ticks = self.ticks;
+        self.senders.retain(|(entity, created)| {
+            let done = ticks - created >= COMMAND_SENDER_TICKS;
+            if done {
+                let _ = entities.delete(*entity);
+            }
+            !done
+        });
+        self.ticks += 1;
+
+        let requests = match requests {
+            Some(requests) => requests,
+            None => return,
+        };
+
+        let find_player = |name: &str| {
+            (&entities, &nameds, &players)
+                .join()
+                .find(|(_, named, _)| named.display_name.eq_ignore_ascii_case(name))
+                .map(|(entity, _, _)| entity)
+        };
+
+        while let Ok(request) = requests.0.try_recv() {
+            let response = match request.action {
+                Action::Status => Response::Json(
+                    200,
+                    json!({
+                        "version": SERVER_VERSION,
+                        "protocol": PROTOCOL_VERSION,
+                        "motd": config.server.motd,
+                        "players": player_count.0.load(Ordering::SeqCst),
+                        "max_players": config.server.max_players,
+                        "tps": TIMINGS.lock().tps(TPS as usize),
+                        "tick": tick_count.0,
+                        "loaded_chunks": chunk_map.inner().len(),
+                    }),
+                ),
+                Action::Players => {
+                    let list: Vec<Value> = (&entities, &nameds, &players)
+                        .join()
+                        .map(|(entity, named, player)| {
+                            let position = positions.get(entity).map(|position| {
+                                let pos = position.current;
+                                json!({
+                                    "x": pos.x,
+                                    "y": pos.y,
+                                    "z": pos.z,
+                                    "yaw": pos.yaw,
+                                    "pitch": pos.pitch,
+                                })
+                            });
+                            json!({
+                                "name": named.display_name,
+                                "uuid": named.uuid.to_string(),
+                                "gamemode": player.gamemode,
+                                "position": position,
+                            })
+                        })
+                        .collect();
+                    Response::Json(200, json!(list))
+                }
+                Action::Kick { name, reason } => match find_player(&name) {
+                    Some(player) => {
+                        info!("Admin API kicked {}: {}", name, reason);
+                        disconnect_player(player, reason, &lazy);
+                        Response::Json(200, json!({ "kicked": name }))
+                    }
+                    None => Response::Json(404, error("Player is not online")),
+                },
+                Action::Bans => Response::Json(200, json!(ban_list.bans())),
+                Action::Ban { name, reason } => {
+                    info!("Admin API banned {}: {}", name, reason);
+                    ban_list.ban(&name, &reason);
+                    if let Some(player) = find_player(&name) {
+                        let message = bans::kick_message(ban_list.get(&name).unwrap());
+                        disconnect_player(player, message, &lazy);
+                    }
+                    Response::Json(200, json!({ "banned": name }))
+                }
+                Action::Unban { name } => {
+                    if ban_list.unban(&name) {
+                        info!("Admin API unbanned {}", name);
+                        Response::Json(200, json!({ "unbanned": name }))
+                    } else {
+                        Response::Json(404, error("Player is not banned"))
+                    }
+                }
+                Action::Command { command } => {
+                    let (output_tx, output_rx) = crossbeam::unbounded();
+                    let sender = entities.create();
+                    consoles
+                        .insert(
+                            sender,
+                            ConsoleComponent {
+                                output: Some(output_tx),
+                            },
+                        )
+                        .unwrap();
+                    self.senders.push((sender, ticks));
+
+                    info!("Admin API issued server command: /{}", command);
+                    // `route` ensures the command is not empty.
+                    let event = CommandEvent::parse(sender, &format!("/{}", command)).unwrap();
+                    command_events.single_write(event);
+
+                    Response::Output(output_rx)
+                }
+            };
+
+            let _ = request.response.send(response);
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(AdminSystem::default(), ADMIN_API, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::UnknownCommandSystem;
+    use crate::testframework as t;
+    use crossbeam::channel::TryRecvError;
+    use feather_core::network::packet::PacketType;
+    use specs::{Dispatcher, World, WorldExt};
+
+    #[test]
+    fn test_parse_head() {
+        let request = parse_head(
+            "POST /players/alice/kick?x=1 HTTP/1.1\r\nauthorization: Bearer abc\r\nContent-Length: 12\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            HttpRequest {
+                method: String::from("POST"),
+                path: String::from("/players/alice/kick"),
+                authorization: Some(String::from("Bearer abc")),
+                content_length: 12,
+            }
+        );
+
+        assert!(parse_head("GET\r\n\r\n").is_none());
+        assert!(parse_head("GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized(Some("Bearer secret"), "secret"));
+        assert!(!is_authorized(Some("Bearer secrets"), "secret"));
+        assert!(!is_authorized(Some("Bearer Secret"), "secret"));
+        assert!(!is_authorized(Some("secret"), "secret"));
+        assert!(!is_authorized(None, "secret"));
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("GET", "/status", b""), Ok(Action::Status));
+        assert_eq!(route("GET", "/players/", b""), Ok(Action::Players));
+        assert_eq!(
+            route("POST", "/players/alice/kick", b""),
+            Ok(Action::Kick {
+                name: String::from("alice"),
+                reason: String::from(DEFAULT_KICK_REASON),
+            })
+        );
+        assert_eq!(
+            route("PUT", "/bans/alice", br#"{"reason": "Griefing"}"#),
+            Ok(Action::Ban {
+                name: String::from("alice"),
+                reason: String::from("Griefing"),
+            })
+        );
+        assert_eq!(
+            route("DELETE", "/bans/alice", b""),
+            Ok(Action::Unban {
+                name: String::from("alice"),
+            })
+        );
+        assert_eq!(
+            route("POST", "/command", br#"{"command": "/tps"}"#),
+            Ok(Action::Command {
+                command: String::from("tps"),
+            })
+        );
+
+        assert_eq!(route("POST", "/command", b"").unwrap_err().0, 400);
+        assert_eq!(route("POST", "/command", b"{").unwrap_err().0, 400);
+        assert_eq!(
+            route("POST", "/command", br#"{"command": 1}"#)
+                .unwrap_err()
+                .0,
+            400
+        );
+        assert_eq!(route("DELETE", "/status", b"").unwrap_err().0, 404);
+        assert_eq!(route("GET", "/", b"").unwrap_err().0, 404);
+    }
+
+    fn world<'a, 'b>() -> (World, Dispatcher<'a, 'b>, Sender<Request>) {
+        let (mut w, d) = t::builder()
+            .with(AdminSystem::default(), "")
+            .with(UnknownCommandSystem::default(), "")
+            .build();
+        let (tx, rx) = crossbeam::unbounded();
+        w.insert(AdminRequests(rx));
+        (w, d, tx)
+    }
+
+    fn request(sender: &Sender<Request>, action: Action) -> Receiver<Response> {
+        let (tx, rx) = crossbeam::bounded(1);
+        sender
+            .send(Request {
+                action,
+                response: tx,
+            })
+            .unwrap();
+        rx
+    }
+
+    fn json_response(rx: &Receiver<Response>) -> (u16, Value) {
+        match rx.try_recv().unwrap() {
+            Response::Json(status, body) => (status, body),
+            Response::Output(_) => panic!("expected JSON response"),
+        }
+    }
+
+    fn add_player(w: &mut World, name: &str) -> t::Player {
+        let player = t::add_player(w);
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = name.to_string();
+        player
+    }
+
+    #[test]
+    fn test_players_and_kick() {
+        let (mut w, mut d, sender) = world();
+        let player = add_player(&mut w, "alice");
+
+        let rx = request(&sender, Action::Players);
+        d.dispatch(&w);
+        let (status, body) = json_response(&rx);
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["name"], "alice");
+        assert_eq!(body[0]["gamemode"], "Creative");
+
+        let rx = request(
+            &sender,
+            Action::Kick {
+                name: String::from("bob"),
+                reason: String::from(DEFAULT_KICK_REASON),
+            },
+        );
+        d.dispatch(&w);
+        assert_eq!(json_response(&rx).0, 404);
+
+        let rx = request(
+            &sender,
+            Action::Kick {
+                name: String::from("Alice"),
+                reason: String::from(DEFAULT_KICK_REASON),
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(json_response(&rx).0, 200);
+        t::assert_packet_received(&player, PacketType::DisconnectPlay);
+    }
+
+    #[test]
+    fn test_bans() {
+        let (mut w, mut d, sender) = world();
+        let player = add_player(&mut w, "alice");
+
+        let rx = request(
+            &sender,
+            Action::Ban {
+                name: String::from("alice"),
+                reason: String::from("Griefing"),
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(json_response(&rx).0, 200);
+        assert!(w.fetch::<BanList>().get("alice").is_some());
+        t::assert_packet_received(&player, PacketType::DisconnectPlay);
+
+        let rx = request(&sender, Action::Bans);
+        d.dispatch(&w);
+        assert_eq!(json_response(&rx).1[0]["reason"], "Griefing");
+
+        let unban = Action::Unban {
+            name: String::from("alice"),
+        };
+        let rx = request(&sender, unban.clone());
+        d.dispatch(&w);
+        assert_eq!(json_response(&rx).0, 200);
+        let rx = request(&sender, unban);
+        d.dispatch(&w);
+        assert_eq!(json_response(&rx).0, 404);
+    }
+
+    #[test]
+    fn test_command() {
+        let (w, mut d, sender) = world();
+
+        let rx = request(
+            &sender,
+            Action::Command {
+                command: String::from("nonexistent"),
+            },
+        );
+        d.dispatch(&w);
+        let output = match rx.try_recv().unwrap() {
+            Response::Output(output) => output,
+            Response::Json(..) => panic!("expected command output"),
+        };
+
+        for _ in 0..=COMMAND_SENDER_TICKS {
+            d.dispatch(&w);
+            w.maintain();
+        }
+
+        let lines: Vec<String> = output.try_iter().collect();
+        assert_eq!(lines, vec!["Unknown command."]);
+        assert_eq!(output.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let requests = start_server(&AdminApi {
+            enabled: true,
+            address: addr.to_string(),
+            token: String::from("secret"),
+        })
+        .unwrap();
+
+        let send = move |request: &'static [u8]| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = send(b"GET /status HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"));
+
+        let client = std::thread::spawn(move || {
+            send(b"GET /status HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n")
+        });
+        let request = requests.0.recv_timeout(RESPONSE_TIMEOUT).unwrap();
+        assert_eq!(request.action, Action::Status);
+        request
+            .response
+            .send(Response::Json(200, json!({ "tps": 20.0 })))
+            .unwrap();
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(r#"{"tps":20.0}"#));
+    }
+
+    #[test]
+    fn test_empty_token() {
+        assert!(start_server(&AdminApi::default()).is_err());
+    }
+}
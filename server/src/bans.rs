@@ -0,0 +1,145 @@
+//! The list of players who are banned from the server.
+//!
+//! Bans are stored by username in `banned_players.json`
+//! and checked when a player joins. The list is written
+//! back to the file whenever it is modified.
+
+use crate::lang::Message;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The path of the ban list file.
+pub const BAN_LIST_PATH: &str = "banned_players.json";
+
+/// The default reason given for a ban.
+pub const DEFAULT_REASON: &str = "Banned by an operator.";
+
+/// A single ban.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub name: String,
+    pub reason: String,
+    /// The time at which the ban was created,
+    /// in RFC 3339 format.
+    pub created: String,
+}
+
+/// Resource containing all bans.
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: Vec<Ban>,
+    /// The file to save the list to, or `None`
+    /// if it should only be kept in memory.
+    path: Option<PathBuf>,
+}
+
+impl BanList {
+    /// Loads the ban list from the given file. If the
+    /// file does not exist, the list is empty and the
+    /// file is created once a ban is added.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, failure::Error> {
+        let path = path.as_ref();
+        let bans = match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            bans,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Returns the ban of the player with the
+    /// given name, if they are banned.
+    pub fn get(&self, name: &str) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| ban.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all bans.
+    pub fn bans(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    /// Bans a player, replacing any existing ban.
+    pub fn ban(&mut self, name: &str, reason: &str) {
+        self.bans.retain(|ban| !ban.name.eq_ignore_ascii_case(name));
+        self.bans.push(Ban {
+            name: name.to_string(),
+            reason: reason.to_string(),
+            created: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        });
+        self.save();
+    }
+
+    /// Removes the ban of a player, returning
+    /// whether they were banned.
+    pub fn unban(&mut self, name: &str) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|ban| !ban.name.eq_ignore_ascii_case(name));
+        let removed = self.bans.len() != len;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let json = serde_json::to_string_pretty(&self.bans).unwrap();
+        if let Err(e) = fs::write(path, json) {
+            error!("Failed to save ban list to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Returns the message with which a banned
+/// player is disconnected.
+pub fn kick_message(ban: &Ban) -> Message {
+    Message::translate("multiplayer.disconnect.banned.reason").with(&ban.reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ban_list() {
+        let mut list = BanList::default();
+        assert!(list.get("alice").is_none());
+
+        list.ban("Alice", "Griefing");
+        assert_eq!(list.get("alice").unwrap().reason, "Griefing");
+
+        list.ban("alice", DEFAULT_REASON);
+        assert_eq!(list.bans().len(), 1);
+        assert_eq!(list.get("ALICE").unwrap().reason, DEFAULT_REASON);
+
+        assert!(list.unban("alice"));
+        assert!(!list.unban("alice"));
+        assert!(list.bans().is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("feather-bans-{}.json", uuid::Uuid::new_v4()));
+
+        let mut list = BanList::load(&path).unwrap();
+        assert!(list.bans().is_empty());
+        list.ban("bob", "Spamming");
+
+        let loaded = BanList::load(&path).unwrap();
+        assert_eq!(loaded.bans(), list.bans());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
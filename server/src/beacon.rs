@@ -0,0 +1,773 @@
+//! Beacons, which give status effects to nearby players.
+//!
+//! A beacon is powered by a pyramid of iron, gold, emerald or
+//! diamond blocks below it, which can be up to four levels high.
+//! Every `UPDATE_INTERVAL` ticks, the levels of each beacon in
+//! the loaded chunks are counted and, if its beam reaches the
+//! sky, the effects chosen in its window are given to players
+//! within range. The levels and effects are stored in the
+//! beacon's block entity.
+//!
+//! The beacon window is a container window, see the
+//! `container` module. Its payment slot doesn't keep its
+//! item once the window is closed.
+
+use crate::container::{ContainerKind, OpenContainerComponent, WINDOW_ID};
+use crate::dimension::DimensionComponent;
+use crate::effect::{add_effect, EffectInstance, EffectsComponent, StatusEffect};
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::systems::{BEACON_EFFECT, BEACON_UPDATE};
+use crate::timings::DispatcherBuilderExt;
+use crate::TickCount;
+use feather_core::nbt;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    SetBeaconEffect, SetSlot, UpdateBlockEntity, WindowProperty,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, BlockExt, Item, PacketType};
+use hashbrown::HashMap;
+use specs::storage::MaskedStorage;
+use specs::{
+    DispatcherBuilder, Entities, Join, Read, ReadStorage, Storage, System, Write, WriteStorage,
+};
+use std::ops::Deref;
+
+/// The number of ticks between updates of beacons.
+pub const UPDATE_INTERVAL: u64 = 80;
+
+/// The maximum number of levels of a beacon's pyramid.
+pub const MAX_LEVELS: i32 = 4;
+
+/// The action of the Update Block Entity packet for beacons.
+const BLOCK_ENTITY_ACTION: u8 = 3;
+
+/// The ID of beacon block entities.
+const BLOCK_ENTITY_ID: &str = "minecraft:beacon";
+
+/// The height of the world, which beams and
+/// the range of beacons extend to.
+const WORLD_HEIGHT: i32 = 256;
+
+/// Window properties of the beacon window.
+const PROPERTY_LEVELS: i16 = 0;
+const PROPERTY_PRIMARY: i16 = 1;
+const PROPERTY_SECONDARY: i16 = 2;
+
+/// The primary effects which become available at each
+/// level. Regeneration is only available as a secondary effect.
+const EFFECTS_BY_LEVEL: [&[StatusEffect]; 3] = [
+    &[StatusEffect::Speed, StatusEffect::Haste],
+    &[StatusEffect::Resistance, StatusEffect::JumpBoost],
+    &[StatusEffect::Strength],
+];
+
+/// The block entity of a beacon, as saved in chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BeaconEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// The number of levels of the pyramid,
+    /// as of the last update of the beacon.
+    #[serde(rename = "Levels", default)]
+    pub levels: i32,
+    /// The ID of the primary effect, or 0 if none.
+    #[serde(rename = "Primary", default)]
+    pub primary: i32,
+    /// The ID of the secondary effect, or 0 if none.
+    #[serde(rename = "Secondary", default)]
+    pub secondary: i32,
+    /// The custom name of the beacon as a JSON text component.
+    #[serde(
+        rename = "CustomName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_name: Option<String>,
+}
+
+impl BeaconEntity {
+    pub fn new(pos: BlockPosition) -> Self {
+        Self {
+            id: BLOCK_ENTITY_ID.to_string(),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            ..Default::default()
+        }
+    }
+
+    pub fn primary_effect(&self) -> Option<StatusEffect> {
+        StatusEffect::from_id(self.primary)
+    }
+
+    pub fn secondary_effect(&self) -> Option<StatusEffect> {
+        StatusEffect::from_id(self.secondary)
+    }
+
+    /// Returns the effects given by this beacon to players in range.
+    pub fn effects(&self) -> Vec<EffectInstance> {
+        let primary = match self.primary_effect() {
+            Some(primary) if self.levels > 0 => primary,
+            _ => return vec![],
+        };
+        let secondary = self
+            .secondary_effect()
+            .filter(|_| self.levels >= MAX_LEVELS);
+        let duration = (9 + self.levels as u32 * 2) * 20;
+
+        let mut effects = vec![];
+        let amplifier = if secondary == Some(primary) { 1 } else { 0 };
+        effects.push(beacon_effect(primary, amplifier, duration));
+        if let Some(secondary) = secondary.filter(|secondary| *secondary != primary) {
+            effects.push(beacon_effect(secondary, 0, duration));
+        }
+        effects
+    }
+
+    /// Returns the horizontal range of this beacon.
+    pub fn range(&self) -> i32 {
+        self.levels * 10 + 10
+    }
+
+    fn to_packet(&self, pos: BlockPosition) -> Option<UpdateBlockEntity> {
+        let data = nbt::to_value(self).ok()?;
+        Some(UpdateBlockEntity::new(pos, BLOCK_ENTITY_ACTION, Some(data)))
+    }
+}
+
+fn beacon_effect(effect: StatusEffect, amplifier: u8, duration: u32) -> EffectInstance {
+    EffectInstance {
+        ambient: true,
+        ..EffectInstance::new(effect, amplifier, duration)
+    }
+}
+
+/// Returns whether the given effects may be chosen in
+/// a beacon with the given number of levels.
+pub fn is_valid_choice(
+    levels: i32,
+    primary: Option<StatusEffect>,
+    secondary: Option<StatusEffect>,
+) -> bool {
+    let available = EFFECTS_BY_LEVEL
+        .iter()
+        .take(levels.max(0) as usize)
+        .flat_map(|effects| effects.iter());
+
+    match primary {
+        Some(primary) if available.clone().any(|effect| *effect == primary) => (),
+        Some(_) => return false,
+        None => return secondary.is_none(),
+    }
+
+    match secondary {
+        None => true,
+        Some(secondary) => {
+            levels >= MAX_LEVELS
+                && (secondary == StatusEffect::Regeneration || Some(secondary) == primary)
+        }
+    }
+}
+
+/// Returns the block entity of the beacon at the given position.
+pub fn load_beacon(chunk_map: &ChunkMap, pos: BlockPosition) -> BeaconEntity {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value(data.clone()).ok())
+        .unwrap_or_else(|| BeaconEntity::new(pos))
+}
+
+/// Stores the block entity of a beacon.
+pub fn store_beacon(chunk_map: &mut ChunkMap, pos: BlockPosition, beacon: &BeaconEntity) {
+    match nbt::to_value(beacon) {
+        Ok(data) => {
+            chunk_map.set_block_entity_at(pos, data).ok();
+        }
+        Err(e) => warn!("Failed to store beacon at {:?}: {}", pos, e),
+    }
+}
+
+/// Returns whether the given block can be part of a beacon's pyramid.
+pub fn is_pyramid_block(block: Block) -> bool {
+    match block {
+        Block::IronBlock | Block::GoldBlock | Block::EmeraldBlock | Block::DiamondBlock => true,
+        _ => false,
+    }
+}
+
+/// Returns whether the given item can be used to pay for
+/// the effects of a beacon.
+pub fn is_payment_item(item: Item) -> bool {
+    match item {
+        Item::IronIngot | Item::GoldIngot | Item::Emerald | Item::Diamond => true,
+        _ => false,
+    }
+}
+
+/// Returns the number of complete levels of the pyramid
+/// below the beacon at the given position. The level `n`
+/// below the beacon is a square of `2n + 1` by `2n + 1` blocks.
+pub fn pyramid_levels(chunk_map: &ChunkMap, pos: BlockPosition) -> i32 {
+    for level in 1..=MAX_LEVELS {
+        let y = pos.y - level;
+        if y < 0 {
+            return level - 1;
+        }
+
+        for x in pos.x - level..=pos.x + level {
+            for z in pos.z - level..=pos.z + level {
+                let block = chunk_map.block_at(BlockPosition::new(x, y, z));
+                if !block.map_or(false, is_pyramid_block) {
+                    return level - 1;
+                }
+            }
+        }
+    }
+
+    MAX_LEVELS
+}
+
+/// A section of a beacon's beam with a single color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeamSegment {
+    /// The red, green and blue components
+    /// of the color, between 0 and 1.
+    pub color: [f32; 3],
+    /// The number of blocks covered by this segment.
+    pub height: i32,
+}
+
+/// The colors of the dyes, by name.
+const DYE_COLORS: [(&str, u32); 16] = [
+    ("white", 0xF9_FF_FE),
+    ("orange", 0xF9_80_1D),
+    ("magenta", 0xC7_4E_BD),
+    ("light_blue", 0x3A_B3_DA),
+    ("yellow", 0xFE_D8_3D),
+    ("lime", 0x80_C7_1F),
+    ("pink", 0xF3_8B_AA),
+    ("gray", 0x47_4F_52),
+    ("light_gray", 0x9D_9D_97),
+    ("cyan", 0x16_9C_9C),
+    ("purple", 0x89_32_B8),
+    ("blue", 0x3C_44_AA),
+    ("brown", 0x83_54_32),
+    ("green", 0x5E_7C_16),
+    ("red", 0xB0_2E_26),
+    ("black", 0x1D_1D_21),
+];
+
+/// Returns the color which stained glass
+/// or a stained glass pane gives to a beam.
+fn glass_color(block: Block) -> Option<[f32; 3]> {
+    let name = block.to_name_and_props().0;
+    let name = name.trim_start_matches("minecraft:");
+    let dye = name
+        .trim_end_matches("_stained_glass_pane")
+        .trim_end_matches("_stained_glass");
+    if dye == name {
+        return None;
+    }
+
+    let (_, color) = DYE_COLORS.iter().find(|(color, _)| *color == dye)?;
+    Some([
+        ((color >> 16) & 0xFF) as f32 / 255.0,
+        ((color >> 8) & 0xFF) as f32 / 255.0,
+        (color & 0xFF) as f32 / 255.0,
+    ])
+}
+
+/// Returns the segments of the beam of the beacon at the
+/// given position, from bottom to top, or `None` if the
+/// beam is blocked before it reaches the sky.
+///
+/// As in vanilla, the beam is white until it passes through
+/// stained glass, after which each piece of glass mixes its
+/// color with the current one.
+pub fn beam_segments(chunk_map: &ChunkMap, pos: BlockPosition) -> Option<Vec<BeamSegment>> {
+    let mut segments = vec![BeamSegment {
+        color: [1.0, 1.0, 1.0],
+        height: 1,
+    }];
+    let mut colored = false;
+
+    for y in pos.y + 1..WORLD_HEIGHT {
+        let block = match chunk_map.block_at(BlockPosition::new(pos.x, y, pos.z)) {
+            Some(block) => block,
+            None => break,
+        };
+        let current = segments.last_mut().unwrap();
+
+        let mut color = match glass_color(block) {
+            Some(color) => color,
+            None => {
+                if block.is_opaque() && block != Block::Bedrock {
+                    return None;
+                }
+                current.height += 1;
+                continue;
+            }
+        };
+
+        if colored {
+            for (component, current) in color.iter_mut().zip(current.color.iter()) {
+                *component = (*component + current) / 2.0;
+            }
+        }
+        colored = true;
+
+        if color == current.color {
+            current.height += 1;
+        } else {
+            segments.push(BeamSegment { color, height: 1 });
+        }
+    }
+
+    Some(segments)
+}
+
+/// The beams of the beacons in loaded chunks,
+/// as of their last update. Beacons whose beam
+/// is blocked aren't included.
+#[derive(Debug, Default)]
+pub struct BeaconBeams(pub HashMap<BlockPosition, Vec<BeamSegment>>);
+
+/// Sends the levels and effects of a beacon
+/// as properties of a player's beacon window.
+pub fn send_window_properties(network: &NetworkComponent, beacon: &BeaconEntity) {
+    for (property, value) in &[
+        (PROPERTY_LEVELS, beacon.levels),
+        (PROPERTY_PRIMARY, beacon.primary),
+        (PROPERTY_SECONDARY, beacon.secondary),
+    ] {
+        send_packet_to_player(
+            network,
+            WindowProperty::new(WINDOW_ID, *property, *value as i16),
+        );
+    }
+}
+
+/// Sends the state of a beacon to all players in the primary
+/// dimension, and its window properties to those viewing it.
+fn broadcast<D>(
+    pos: BlockPosition,
+    beacon: &BeaconEntity,
+    networks: &ReadStorage<NetworkComponent>,
+    dimensions: &ReadStorage<DimensionComponent>,
+    open_containers: &Storage<OpenContainerComponent, D>,
+) where
+    D: Deref<Target = MaskedStorage<OpenContainerComponent>>,
+{
+    if let Some(packet) = beacon.to_packet(pos) {
+        for (network, _) in (networks, !dimensions).join() {
+            send_packet_to_player(network, packet.clone());
+        }
+    }
+
+    for (network, open) in (networks, open_containers).join() {
+        if open.pos == pos && open.kind == ContainerKind::Beacon {
+            send_window_properties(network, beacon);
+        }
+    }
+}
+
+/// System which updates the beacons in loaded chunks every
+/// `UPDATE_INTERVAL` ticks and gives their effects to
+/// players in range.
+pub struct BeaconUpdateSystem;
+
+impl<'a> System<'a> for BeaconUpdateSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, BeaconBeams>,
+        WriteStorage<'a, EffectsComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadStorage<'a, OpenContainerComponent>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut chunk_map,
+            mut beams,
+            mut effects,
+            positions,
+            players,
+            networks,
+            dimensions,
+            open_containers,
+            entities,
+            tick,
+        ) = data;
+
+        if tick.0 % UPDATE_INTERVAL != 0 {
+            return;
+        }
+
+        let positions_of_beacons: Vec<BlockPosition> = chunk_map
+            .chunks()
+            .values()
+            .flat_map(|chunk| {
+                let chunk_pos = chunk.position();
+                chunk
+                    .block_entities()
+                    .filter(|(_, data)| is_beacon_entity(data))
+                    .map(move |((x, y, z), _)| {
+                        BlockPosition::new(
+                            chunk_pos.x * 16 + x as i32,
+                            y as i32,
+                            chunk_pos.z * 16 + z as i32,
+                        )
+                    })
+            })
+            .collect();
+
+        beams.0.clear();
+
+        for pos in positions_of_beacons {
+            if chunk_map.block_at(pos) != Some(Block::Beacon) {
+                continue;
+            }
+
+            let mut beacon = load_beacon(&chunk_map, pos);
+            let segments = beam_segments(&chunk_map, pos);
+            let levels = if segments.is_some() {
+                pyramid_levels(&chunk_map, pos)
+            } else {
+                0
+            };
+
+            if levels != beacon.levels {
+                beacon.levels = levels;
+                store_beacon(&mut chunk_map, pos, &beacon);
+                broadcast(pos, &beacon, &networks, &dimensions, &open_containers);
+            }
+
+            let segments = continue_if_none!(segments);
+            beams.0.insert(pos, segments);
+
+            let beacon_effects = beacon.effects();
+            if beacon_effects.is_empty() {
+                continue;
+            }
+
+            // The range extends horizontally from the
+            // beacon, and vertically up to the sky.
+            let range = beacon.range();
+            let (min_x, max_x) = ((pos.x - range) as f64, (pos.x + 1 + range) as f64);
+            let (min_z, max_z) = ((pos.z - range) as f64, (pos.z + 1 + range) as f64);
+            let min_y = (pos.y - range) as f64;
+
+            for (entity, position, _, _) in (&entities, &positions, &players, !&dimensions).join() {
+                let p = position.current;
+                let in_range = p.x >= min_x
+                    && p.x <= max_x
+                    && p.z >= min_z
+                    && p.z <= max_z
+                    && p.y >= min_y
+                    && p.y <= WORLD_HEIGHT as f64;
+                if !in_range {
+                    continue;
+                }
+
+                for effect in &beacon_effects {
+                    add_effect(entity, *effect, &mut effects, &networks);
+                }
+            }
+        }
+    }
+}
+
+fn is_beacon_entity(data: &nbt::Value) -> bool {
+    match data {
+        nbt::Value::Compound(map) => {
+            map.get("id") == Some(&nbt::Value::String(BLOCK_ENTITY_ID.to_string()))
+        }
+        _ => false,
+    }
+}
+
+/// System which handles Set Beacon Effect packets, sent when a
+/// player confirms the effects chosen in a beacon window. The
+/// item in the payment slot is consumed.
+pub struct BeaconEffectSystem;
+
+impl<'a> System<'a> for BeaconEffectSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        Write<'a, ChunkMap>,
+        WriteStorage<'a, OpenContainerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (packet_queue, mut chunk_map, mut open_containers, networks, dimensions) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::SetBeaconEffect) {
+            let packet = cast_packet::<SetBeaconEffect>(&*packet);
+            let primary = StatusEffect::from_id(packet.primary_effect);
+            let secondary = StatusEffect::from_id(packet.secondary_effect);
+
+            let pos = {
+                let open = continue_if_none!(open_containers.get_mut(player));
+                if open.kind != ContainerKind::Beacon {
+                    continue;
+                }
+                let payment = open.slots.get(0).and_then(Option::as_ref);
+                if !payment.map_or(false, |stack| is_payment_item(stack.ty)) {
+                    continue;
+                }
+
+                let beacon = load_beacon(&chunk_map, open.pos);
+                if primary.is_none() || !is_valid_choice(beacon.levels, primary, secondary) {
+                    continue;
+                }
+
+                open.slots[0] = None;
+                open.pos
+            };
+
+            if let Some(network) = networks.get(player) {
+                send_packet_to_player(network, SetSlot::new(WINDOW_ID as i8, 0, None));
+            }
+
+            let mut beacon = load_beacon(&chunk_map, pos);
+            beacon.primary = primary.map_or(0, StatusEffect::id);
+            beacon.secondary = secondary.map_or(0, StatusEffect::id);
+            store_beacon(&mut chunk_map, pos, &beacon);
+            broadcast(pos, &beacon, &networks, &dimensions, &open_containers);
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(BeaconEffectSystem, BEACON_EFFECT, &[]);
+    dispatcher.add_timed(BeaconUpdateSystem, BEACON_UPDATE, &[BEACON_EFFECT]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::BEACON_SIZE;
+    use crate::testframework as t;
+    use feather_core::{Chunk, ChunkPosition, ItemStack};
+    use specs::WorldExt;
+
+    fn chunk_map() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let pos = ChunkPosition::new(x, z);
+                chunk_map.set_chunk_at(pos, Chunk::new(pos));
+            }
+        }
+        chunk_map
+    }
+
+    fn build_pyramid(chunk_map: &mut ChunkMap, pos: BlockPosition, levels: i32) {
+        for level in 1..=levels {
+            for x in pos.x - level..=pos.x + level {
+                for z in pos.z - level..=pos.z + level {
+                    chunk_map
+                        .set_block_at(BlockPosition::new(x, pos.y - level, z), Block::IronBlock)
+                        .unwrap();
+                }
+            }
+        }
+        chunk_map.set_block_at(pos, Block::Beacon).unwrap();
+    }
+
+    #[test]
+    fn test_pyramid_levels() {
+        let mut chunk_map = chunk_map();
+        let pos = BlockPosition::new(0, 10, 0);
+        assert_eq!(pyramid_levels(&chunk_map, pos), 0);
+
+        build_pyramid(&mut chunk_map, pos, 2);
+        assert_eq!(pyramid_levels(&chunk_map, pos), 2);
+
+        // A missing block in the second level
+        // leaves only the first one complete.
+        chunk_map
+            .set_block_at(BlockPosition::new(2, 8, -2), Block::Air)
+            .unwrap();
+        assert_eq!(pyramid_levels(&chunk_map, pos), 1);
+
+        build_pyramid(&mut chunk_map, pos, 5);
+        assert_eq!(pyramid_levels(&chunk_map, pos), MAX_LEVELS);
+    }
+
+    #[test]
+    fn test_beam_segments() {
+        let mut chunk_map = chunk_map();
+        let pos = BlockPosition::new(0, 10, 0);
+
+        let segments = beam_segments(&chunk_map, pos).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].height, WORLD_HEIGHT - pos.y);
+
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 12, 0), Block::RedStainedGlass)
+            .unwrap();
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 13, 0), Block::BlueStainedGlass)
+            .unwrap();
+        let segments = beam_segments(&chunk_map, pos).unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].height, 2);
+        assert_eq!(
+            segments[1].color,
+            glass_color(Block::RedStainedGlass).unwrap()
+        );
+        assert_eq!(segments[1].height, 1);
+
+        // The blue glass is mixed with the red glass below it.
+        let red = glass_color(Block::RedStainedGlass).unwrap();
+        let blue = glass_color(Block::BlueStainedGlass).unwrap();
+        assert_eq!(segments[2].color[2], (red[2] + blue[2]) / 2.0);
+        assert_eq!(segments[2].height, WORLD_HEIGHT - 13);
+
+        // Opaque blocks other than bedrock block the beam.
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 20, 0), Block::Bedrock)
+            .unwrap();
+        assert!(beam_segments(&chunk_map, pos).is_some());
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 30, 0), Block::Stone)
+            .unwrap();
+        assert!(beam_segments(&chunk_map, pos).is_none());
+    }
+
+    #[test]
+    fn test_is_valid_choice() {
+        use StatusEffect::*;
+
+        assert!(is_valid_choice(1, Some(Speed), None));
+        assert!(!is_valid_choice(1, Some(Strength), None));
+        assert!(!is_valid_choice(0, Some(Speed), None));
+        assert!(is_valid_choice(3, Some(Strength), None));
+        assert!(!is_valid_choice(4, Some(Regeneration), None));
+        assert!(!is_valid_choice(3, Some(Speed), Some(Regeneration)));
+        assert!(is_valid_choice(4, Some(Speed), Some(Regeneration)));
+        assert!(is_valid_choice(4, Some(Haste), Some(Haste)));
+        assert!(!is_valid_choice(4, Some(Haste), Some(Speed)));
+    }
+
+    #[test]
+    fn test_beacon_effects() {
+        let mut beacon = BeaconEntity::new(BlockPosition::new(0, 0, 0));
+        beacon.levels = 4;
+        beacon.primary = StatusEffect::Haste.id();
+        beacon.secondary = StatusEffect::Haste.id();
+        let effects = beacon.effects();
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].amplifier, 1);
+        assert_eq!(effects[0].duration, 17 * 20);
+        assert!(effects[0].ambient);
+
+        beacon.secondary = StatusEffect::Regeneration.id();
+        let effects = beacon.effects();
+        assert_eq!(effects.len(), 2);
+        assert_eq!(effects[0].amplifier, 0);
+        assert_eq!(effects[1].effect, StatusEffect::Regeneration);
+
+        beacon.levels = 0;
+        assert!(beacon.effects().is_empty());
+    }
+
+    #[test]
+    fn test_update_gives_effects() {
+        let (mut w, mut d) = t::builder().with(BeaconUpdateSystem, "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+
+        let pos = BlockPosition::new(0, 3, 0);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            build_pyramid(&mut chunk_map, pos, 1);
+            let mut beacon = BeaconEntity::new(pos);
+            beacon.primary = StatusEffect::Speed.id();
+            store_beacon(&mut chunk_map, pos, &beacon);
+        }
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(load_beacon(&w.fetch::<ChunkMap>(), pos).levels, 1);
+        assert!(w.fetch::<BeaconBeams>().0.contains_key(&pos));
+        t::assert_packet_received(&player, PacketType::UpdateBlockEntity);
+
+        let effects = w.read_component::<EffectsComponent>();
+        let speed = effects
+            .get(player.entity)
+            .unwrap()
+            .get(StatusEffect::Speed)
+            .unwrap();
+        assert_eq!(speed.amplifier, 0);
+        assert_eq!(speed.duration, 11 * 20);
+    }
+
+    #[test]
+    fn test_set_beacon_effect() {
+        let (mut w, mut d) = t::builder().with(BeaconEffectSystem, "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+
+        let pos = BlockPosition::new(0, 3, 0);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            build_pyramid(&mut chunk_map, pos, 1);
+            let mut beacon = BeaconEntity::new(pos);
+            beacon.levels = 1;
+            store_beacon(&mut chunk_map, pos, &beacon);
+        }
+        w.write_component::<OpenContainerComponent>()
+            .insert(
+                player.entity,
+                OpenContainerComponent {
+                    pos,
+                    kind: ContainerKind::Beacon,
+                    size: BEACON_SIZE,
+                    cursor: None,
+                    slots: vec![Some(ItemStack::new(Item::Emerald, 1))],
+                },
+            )
+            .unwrap();
+
+        // Strength requires three levels.
+        t::receive_packet(
+            &player,
+            &w,
+            SetBeaconEffect::new(StatusEffect::Strength.id(), 0),
+        );
+        d.dispatch(&w);
+        assert_eq!(load_beacon(&w.fetch::<ChunkMap>(), pos).primary, 0);
+
+        t::receive_packet(
+            &player,
+            &w,
+            SetBeaconEffect::new(StatusEffect::Haste.id(), 0),
+        );
+        d.dispatch(&w);
+
+        let beacon = load_beacon(&w.fetch::<ChunkMap>(), pos);
+        assert_eq!(beacon.primary_effect(), Some(StatusEffect::Haste));
+        assert_eq!(beacon.secondary_effect(), None);
+        assert!(w
+            .read_component::<OpenContainerComponent>()
+            .get(player.entity)
+            .unwrap()
+            .slots[0]
+            .is_none());
+        t::assert_packet_received(&player, PacketType::SetSlot);
+    }
+}
@@ -4,12 +4,49 @@ use specs::{Builder, Entities, LazyUpdate, Read, System, Write};
 
 use feather_core::world::ChunkMap;
 
-use feather_blocks::{Block, BlockExt};
+use feather_blocks::Block;
 
 use crate::blocks::{BlockNotifyEvent, BlockUpdateCause, BlockUpdateEvent};
 use crate::entity::{falling_block, PositionComponent, VelocityComponent};
 use feather_core::Position;
 
+/// Returns whether the given block falls when
+/// the block below it can be fallen through.
+pub fn is_gravity_block(block: Block) -> bool {
+    match block {
+        Block::Sand
+        | Block::RedSand
+        | Block::Gravel
+        | Block::Anvil(_)
+        | Block::ChippedAnvil(_)
+        | Block::DamagedAnvil(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns whether falling blocks fall through the given
+/// block, destroying it if they land inside it.
+pub fn can_fall_through(block: Block) -> bool {
+    match block {
+        Block::Air
+        | Block::CaveAir
+        | Block::VoidAir
+        | Block::Fire(_)
+        | Block::Water(_)
+        | Block::Lava(_)
+        | Block::Grass
+        | Block::Fern
+        | Block::DeadBush
+        | Block::Seagrass
+        | Block::Vine(_)
+        | Block::Snow(_) => true,
+        _ => false,
+    }
+}
+
+/// System which turns gravity-affected blocks into
+/// falling block entities when they lose support.
+///
 /// This system listens to `BlockNotifyEvent`s.
 #[derive(Default)]
 pub struct FallingBlockCreationSystem {
@@ -30,41 +67,121 @@ impl<'a> System<'a> for FallingBlockCreationSystem {
 
         // Process events
         for event in events.read(&mut self.reader.as_mut().unwrap()) {
-            match event.block {
-                Block::Sand | Block::RedSand | Block::Gravel => {
-                    let mut below = event.pos;
-                    below.y -= 1;
-
-                    if !chunk_map.block_at(below).unwrap_or(Block::Air).is_solid() {
-                        chunk_map.set_block_at(event.pos, Block::Air).unwrap();
-
-                        let update_event = BlockUpdateEvent {
-                            cause: BlockUpdateCause::FallingBlock,
-                            pos: event.pos,
-                            old_block: event.block,
-                            new_block: Block::Air,
-                        };
-
-                        block_update.single_write(update_event);
-
-                        let mut entity_pos: Position = event.pos.world_pos();
-                        // Center position on block
-                        entity_pos.x += 0.5;
-                        entity_pos.z += 0.5;
-
-                        falling_block::create(&lazy, &entities, event.block, entity_pos)
-                            .with(PositionComponent {
-                                current: entity_pos,
-                                previous: entity_pos,
-                            })
-                            .with(VelocityComponent::default())
-                            .build();
-                    }
-                }
-                _ => (),
+            if !is_gravity_block(event.block) {
+                continue;
+            }
+
+            // A block may be notified several times in a tick,
+            // but it only falls once.
+            if chunk_map.block_at(event.pos) != Some(event.block) {
+                continue;
+            }
+
+            let mut below = event.pos;
+            below.y -= 1;
+
+            // Blocks above unloaded chunks don't fall.
+            if !chunk_map.block_at(below).map_or(false, can_fall_through) {
+                continue;
             }
+
+            chunk_map.set_block_at(event.pos, Block::Air).unwrap();
+
+            let update_event = BlockUpdateEvent {
+                cause: BlockUpdateCause::FallingBlock,
+                pos: event.pos,
+                old_block: event.block,
+                new_block: Block::Air,
+            };
+
+            block_update.single_write(update_event);
+
+            let mut entity_pos: Position = event.pos.world_pos();
+            // Center position on block
+            entity_pos.x += 0.5;
+            entity_pos.z += 0.5;
+
+            falling_block::create(&lazy, &entities, event.block, entity_pos)
+                .with(PositionComponent {
+                    current: entity_pos,
+                    previous: entity_pos,
+                })
+                .with(VelocityComponent::default())
+                .build();
         }
     }
 
     setup_impl!(reader);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::falling_block::FallingBlockComponent;
+    use crate::testframework as t;
+    use feather_blocks::{AnvilData, AnvilFacing, BlockExt};
+    use feather_core::world::BlockPosition;
+    use specs::{Join, WorldExt};
+
+    fn notify(w: &specs::World, pos: BlockPosition) {
+        let block = w.fetch::<ChunkMap>().block_at(pos).unwrap();
+        for _ in 0..2 {
+            t::trigger_event(
+                w,
+                BlockNotifyEvent {
+                    block,
+                    pos,
+                    notified_by: pos,
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_falling_block_creation() {
+        let (mut w, mut d) = t::builder()
+            .with(FallingBlockCreationSystem::default(), "")
+            .build();
+        w.register::<FallingBlockComponent>();
+        t::populate_with_air(&mut w);
+
+        let anvil = Block::Anvil(AnvilData {
+            facing: AnvilFacing::North,
+        });
+        t::set_block(0, 64, 0, Block::Sand, &w);
+        t::set_block(1, 63, 0, Block::Stone, &w);
+        t::set_block(1, 64, 0, Block::Gravel, &w);
+        t::set_block(2, 64, 0, anvil, &w);
+
+        notify(&w, BlockPosition::new(0, 64, 0));
+        notify(&w, BlockPosition::new(1, 64, 0));
+        notify(&w, BlockPosition::new(2, 64, 0));
+        d.dispatch(&w);
+        w.maintain();
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::Air)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(1, 64, 0)),
+            Some(Block::Gravel)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(2, 64, 0)),
+            Some(Block::Air)
+        );
+
+        // Each block only falls once.
+        let mut falling: Vec<Block> = w
+            .read_component::<FallingBlockComponent>()
+            .join()
+            .map(|falling| falling.block)
+            .collect();
+        falling.sort_by_key(|block| block.native_state_id());
+        let mut expected = vec![Block::Sand, anvil];
+        expected.sort_by_key(|block| block.native_state_id());
+        assert_eq!(falling, expected);
+    }
+}
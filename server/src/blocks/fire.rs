@@ -0,0 +1,589 @@
+//! Fire, which spreads to and burns away flammable blocks.
+//!
+//! As in vanilla, fire runs on block ticks scheduled every 30 to 39
+//! ticks. On each tick a fire ages, may burn away or ignite the
+//! flammable blocks next to it and may spread to air blocks next to
+//! flammable blocks within a few blocks of it, preferably upwards.
+//! Fires which are rained on can be extinguished, and fires which
+//! are no longer next to a flammable block burn out, unless they
+//! burn on netherrack or magma blocks. All of this is disabled by
+//! the `doFireTick` game rule.
+
+use crate::blocks::{
+    self, is_leaves, is_log, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent,
+};
+use crate::weather::{is_raining_at, Weather};
+use feather_blocks::{Block, BlockExt, FireData};
+use feather_core::level::LevelData;
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{Read, System, Write};
+
+/// The minimum number of ticks between fire ticks.
+const TICK_RATE: u64 = 30;
+/// The age at which fires stop aging.
+const MAX_AGE: i32 = 15;
+/// The difficulty used for the spread chance. The
+/// server always runs on normal difficulty.
+const DIFFICULTY: i32 = 2;
+
+/// Returns how likely fire is to spread next to the given
+/// block and how likely the block is to burn away, or `None`
+/// if the block isn't flammable.
+pub fn flammability(block: Block) -> Option<(i32, i32)> {
+    let flammability = match block {
+        Block::OakPlanks
+        | Block::SprucePlanks
+        | Block::BirchPlanks
+        | Block::JunglePlanks
+        | Block::AcaciaPlanks
+        | Block::DarkOakPlanks
+        | Block::OakSlab(_)
+        | Block::SpruceSlab(_)
+        | Block::BirchSlab(_)
+        | Block::JungleSlab(_)
+        | Block::AcaciaSlab(_)
+        | Block::DarkOakSlab(_)
+        | Block::OakFenceGate(_)
+        | Block::SpruceFenceGate(_)
+        | Block::BirchFenceGate(_)
+        | Block::JungleFenceGate(_)
+        | Block::AcaciaFenceGate(_)
+        | Block::DarkOakFenceGate(_)
+        | Block::OakFence(_)
+        | Block::SpruceFence(_)
+        | Block::BirchFence(_)
+        | Block::JungleFence(_)
+        | Block::AcaciaFence(_)
+        | Block::DarkOakFence(_)
+        | Block::OakStairs(_)
+        | Block::SpruceStairs(_)
+        | Block::BirchStairs(_)
+        | Block::JungleStairs(_)
+        | Block::AcaciaStairs(_)
+        | Block::DarkOakStairs(_) => (5, 20),
+        Block::CoalBlock => (5, 5),
+        Block::Bookshelf => (30, 20),
+        Block::Tnt(_) => (15, 100),
+        Block::Vine(_) => (15, 100),
+        Block::HayBlock(_) => (60, 20),
+        Block::DriedKelpBlock => (30, 60),
+        Block::Grass
+        | Block::Fern
+        | Block::DeadBush
+        | Block::Sunflower(_)
+        | Block::Lilac(_)
+        | Block::RoseBush(_)
+        | Block::Peony(_)
+        | Block::TallGrass(_)
+        | Block::LargeFern(_)
+        | Block::Dandelion
+        | Block::Poppy
+        | Block::BlueOrchid
+        | Block::Allium
+        | Block::AzureBluet
+        | Block::RedTulip
+        | Block::OrangeTulip
+        | Block::WhiteTulip
+        | Block::PinkTulip
+        | Block::OxeyeDaisy => (60, 100),
+        Block::WhiteWool
+        | Block::OrangeWool
+        | Block::MagentaWool
+        | Block::LightBlueWool
+        | Block::YellowWool
+        | Block::LimeWool
+        | Block::PinkWool
+        | Block::GrayWool
+        | Block::LightGrayWool
+        | Block::CyanWool
+        | Block::PurpleWool
+        | Block::BlueWool
+        | Block::BrownWool
+        | Block::GreenWool
+        | Block::RedWool
+        | Block::BlackWool => (30, 60),
+        Block::WhiteCarpet
+        | Block::OrangeCarpet
+        | Block::MagentaCarpet
+        | Block::LightBlueCarpet
+        | Block::YellowCarpet
+        | Block::LimeCarpet
+        | Block::PinkCarpet
+        | Block::GrayCarpet
+        | Block::LightGrayCarpet
+        | Block::CyanCarpet
+        | Block::PurpleCarpet
+        | Block::BlueCarpet
+        | Block::BrownCarpet
+        | Block::GreenCarpet
+        | Block::RedCarpet
+        | Block::BlackCarpet => (60, 20),
+        block if is_log(block) => (5, 5),
+        block if is_leaves(block) => (30, 60),
+        _ => return None,
+    };
+    Some(flammability)
+}
+
+fn is_flammable(block: Option<Block>) -> bool {
+    block.and_then(flammability).is_some()
+}
+
+/// Returns whether fire burns forever on the given block.
+fn is_infiniburn(block: Option<Block>) -> bool {
+    match block {
+        Some(Block::Netherrack) | Some(Block::MagmaBlock) => true,
+        _ => false,
+    }
+}
+
+fn offset(pos: BlockPosition, x: i32, y: i32, z: i32) -> BlockPosition {
+    BlockPosition::new(pos.x + x, pos.y + y, pos.z + z)
+}
+
+fn neighbours(pos: BlockPosition) -> [BlockPosition; 6] {
+    [
+        offset(pos, 1, 0, 0),
+        offset(pos, -1, 0, 0),
+        offset(pos, 0, -1, 0),
+        offset(pos, 0, 1, 0),
+        offset(pos, 0, 0, -1),
+        offset(pos, 0, 0, 1),
+    ]
+}
+
+fn has_solid_ground(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    chunk_map
+        .block_at(offset(pos, 0, -1, 0))
+        .map_or(false, |below| below.is_solid())
+}
+
+fn has_flammable_neighbour(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    neighbours(pos)
+        .iter()
+        .any(|&neighbour| is_flammable(chunk_map.block_at(neighbour)))
+}
+
+/// Returns whether fire can burn at the given position:
+/// on a solid block or next to a flammable block.
+pub fn can_support_fire(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    has_solid_ground(chunk_map, pos) || has_flammable_neighbour(chunk_map, pos)
+}
+
+/// Returns the fire with the given age placed at the given position,
+/// which is attached to the flammable blocks around it unless it
+/// burns on the block below it.
+pub fn fire_state(chunk_map: &ChunkMap, pos: BlockPosition, age: i32) -> Block {
+    let below = chunk_map.block_at(offset(pos, 0, -1, 0));
+    if below.map_or(false, |below| below.is_solid()) || is_flammable(below) {
+        return Block::Fire(FireData {
+            age,
+            ..FireData::default()
+        });
+    }
+
+    let flammable = |x, y, z| is_flammable(chunk_map.block_at(offset(pos, x, y, z)));
+    Block::Fire(FireData {
+        age,
+        north: flammable(0, 0, -1),
+        east: flammable(1, 0, 0),
+        south: flammable(0, 0, 1),
+        west: flammable(-1, 0, 0),
+        up: flammable(0, 1, 0),
+    })
+}
+
+fn tick_delay<R: Rng>(rng: &mut R) -> u64 {
+    TICK_RATE + rng.gen_range(0, 10)
+}
+
+/// System which schedules block ticks for new fires
+/// and for fires next to changed blocks which can no
+/// longer burn.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct FireScheduleSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for FireScheduleSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, ChunkMap>,
+        Write<'a, BlockTicks>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, chunk_map, mut ticks) = data;
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if let Block::Fire(_) = event.new_block {
+                ticks.schedule_block_tick(event.pos, tick_delay(&mut rng), 0);
+            }
+
+            for &pos in neighbours(event.pos).iter() {
+                if let Some(Block::Fire(_)) = chunk_map.block_at(pos) {
+                    if !can_support_fire(&chunk_map, pos) {
+                        ticks.schedule_block_tick(pos, 1, 0);
+                    }
+                }
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which lets fire spread, burn blocks
+/// and go out on its scheduled block ticks.
+///
+/// This system listens to `BlockTickEvent`s.
+#[derive(Default)]
+pub struct FireTickSystem {
+    reader: Option<ReaderId<BlockTickEvent>>,
+}
+
+impl<'a> System<'a> for FireTickSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, BlockTicks>,
+        Read<'a, LevelData>,
+        Read<'a, Weather>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events, mut ticks, level, weather) = data;
+
+        let do_fire_tick = level.game_rule_bool("doFireTick");
+
+        let mut world = FireWorld {
+            chunk_map: &mut *chunk_map,
+            events: &mut *events,
+            weather: *weather,
+            rng: rand::thread_rng(),
+        };
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            if !do_fire_tick {
+                continue;
+            }
+            let age = match world.chunk_map.block_at(event.pos) {
+                Some(Block::Fire(data)) => data.age,
+                _ => continue,
+            };
+
+            if world.tick(event.pos, age) {
+                let delay = tick_delay(&mut world.rng);
+                ticks.schedule_block_tick(event.pos, delay, 0);
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// The blocks changed by fire.
+struct FireWorld<'a, R: Rng> {
+    chunk_map: &'a mut ChunkMap,
+    events: &'a mut EventChannel<BlockUpdateEvent>,
+    weather: Weather,
+    rng: R,
+}
+
+impl<'a, R: Rng> FireWorld<'a, R> {
+    fn set_block(&mut self, pos: BlockPosition, block: Block) {
+        blocks::set_block(
+            self.chunk_map,
+            self.events,
+            pos,
+            block,
+            BlockUpdateCause::Fire,
+        );
+    }
+
+    fn is_raining_at(&self, pos: BlockPosition) -> bool {
+        is_raining_at(self.weather, self.chunk_map, pos)
+    }
+
+    /// Returns whether rain falls on the fire at the given
+    /// position or on the blocks next to it.
+    fn is_rained_on(&self, pos: BlockPosition) -> bool {
+        self.is_raining_at(pos)
+            || [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                .iter()
+                .any(|&(x, z)| self.is_raining_at(offset(pos, x, 0, z)))
+    }
+
+    /// Runs a tick of the fire at the given position.
+    /// Returns whether the fire is still burning.
+    fn tick(&mut self, pos: BlockPosition, age: i32) -> bool {
+        if !can_support_fire(self.chunk_map, pos) {
+            self.set_block(pos, Block::Air);
+            return false;
+        }
+
+        let infiniburn = is_infiniburn(self.chunk_map.block_at(offset(pos, 0, -1, 0)));
+        if !infiniburn && self.is_rained_on(pos) && self.rng.gen::<f32>() < 0.2 + age as f32 * 0.03
+        {
+            self.set_block(pos, Block::Air);
+            return false;
+        }
+
+        let new_age = (age + self.rng.gen_range(0, 3) / 2).min(MAX_AGE);
+        if new_age != age {
+            let fire = fire_state(self.chunk_map, pos, new_age);
+            self.set_block(pos, fire);
+        }
+
+        if !infiniburn {
+            if !has_flammable_neighbour(self.chunk_map, pos) {
+                if !has_solid_ground(self.chunk_map, pos) || age > 3 {
+                    self.set_block(pos, Block::Air);
+                    return false;
+                }
+                return true;
+            }
+
+            let below = self.chunk_map.block_at(offset(pos, 0, -1, 0));
+            if age == MAX_AGE && self.rng.gen_range(0, 4) == 0 && !is_flammable(below) {
+                self.set_block(pos, Block::Air);
+                return false;
+            }
+        }
+
+        // Blocks above and below are less likely to burn.
+        for (i, &neighbour) in neighbours(pos).iter().enumerate() {
+            let chance = if i == 2 || i == 3 { 250 } else { 300 };
+            self.burn(neighbour, chance, age);
+        }
+
+        self.spread(pos, age);
+        true
+    }
+
+    /// Burns the block at the given position with a chance
+    /// of one in `chance` times its flammability, sometimes
+    /// setting fire to it instead of burning it away.
+    fn burn(&mut self, pos: BlockPosition, chance: i32, age: i32) {
+        let block = self.chunk_map.block_at(pos);
+        let (_, burn_chance) = match block.and_then(flammability) {
+            Some(flammability) => flammability,
+            None => return,
+        };
+        if self.rng.gen_range(0, chance) >= burn_chance {
+            return;
+        }
+
+        if self.rng.gen_range(0, age + 10) < 5 && !self.is_raining_at(pos) {
+            let new_age = (age + self.rng.gen_range(0, 5) / 4).min(MAX_AGE);
+            let fire = fire_state(self.chunk_map, pos, new_age);
+            self.set_block(pos, fire);
+        } else {
+            self.set_block(pos, Block::Air);
+        }
+    }
+
+    /// Spreads the fire at the given position to the air
+    /// blocks around it which are next to flammable blocks.
+    fn spread(&mut self, pos: BlockPosition, age: i32) {
+        for x in -1..=1 {
+            for z in -1..=1 {
+                for y in -1..=4 {
+                    if x == 0 && y == 0 && z == 0 {
+                        continue;
+                    }
+
+                    let target = offset(pos, x, y, z);
+                    if self.chunk_map.block_at(target) != Some(Block::Air) {
+                        continue;
+                    }
+
+                    let encouragement = neighbours(target)
+                        .iter()
+                        .filter_map(|&neighbour| self.chunk_map.block_at(neighbour))
+                        .filter_map(flammability)
+                        .map(|(encouragement, _)| encouragement)
+                        .max()
+                        .unwrap_or(0);
+                    if encouragement == 0 {
+                        continue;
+                    }
+
+                    // Fire spreads upwards more easily.
+                    let chance = 100 + (y - 1).max(0) * 100;
+                    let spread = (encouragement + 40 + DIFFICULTY * 7) / (age + 30);
+                    if spread > 0
+                        && self.rng.gen_range(0, chance) <= spread
+                        && !self.is_raining_at(target)
+                    {
+                        let new_age = (age + self.rng.gen_range(0, 5) / 4).min(MAX_AGE);
+                        let fire = fire_state(self.chunk_map, target, new_age);
+                        self.set_block(target, fire);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use feather_blocks::{OakLogAxis, OakLogData};
+    use rand::rngs::mock::StepRng;
+    use specs::{Dispatcher, World, WorldExt};
+
+    fn fire(age: i32) -> Block {
+        Block::Fire(FireData {
+            age,
+            ..FireData::default()
+        })
+    }
+
+    fn world<'a, 'b>() -> (World, Dispatcher<'a, 'b>) {
+        let (mut w, d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(FireTickSystem::default(), "fire", &["ticks"])
+            .with_dep(FireScheduleSystem::default(), "schedule", &["fire"])
+            .build();
+        t::populate_with_air(&mut w);
+        w.insert(LevelData::default());
+        w.insert(Weather::default());
+        (w, d)
+    }
+
+    #[test]
+    fn test_fire_state() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+
+        t::set_block(0, 63, 0, Block::Stone, &w);
+        t::set_block(2, 64, 1, Block::OakPlanks, &w);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert!(can_support_fire(&chunk_map, BlockPosition::new(0, 64, 0)));
+        assert_eq!(
+            fire_state(&chunk_map, BlockPosition::new(0, 64, 0), 0),
+            fire(0)
+        );
+
+        assert!(can_support_fire(&chunk_map, BlockPosition::new(2, 64, 0)));
+        assert_eq!(
+            fire_state(&chunk_map, BlockPosition::new(2, 64, 0), 0),
+            Block::Fire(FireData {
+                south: true,
+                ..FireData::default()
+            })
+        );
+
+        assert!(!can_support_fire(&chunk_map, BlockPosition::new(5, 64, 5)));
+    }
+
+    #[test]
+    fn test_fire_burns_out() {
+        let (mut w, mut d) = world();
+        t::place(&w, BlockPosition::new(0, 63, 0), Block::Stone);
+        t::place(&w, BlockPosition::new(0, 64, 0), fire(0));
+
+        t::run_ticks(&mut w, &mut d, 2000);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), Block::Air);
+    }
+
+    #[test]
+    fn test_infiniburn() {
+        let (mut w, mut d) = world();
+        t::place(&w, BlockPosition::new(0, 63, 0), Block::Netherrack);
+        t::place(&w, BlockPosition::new(0, 64, 0), fire(0));
+
+        t::run_ticks(&mut w, &mut d, 1000);
+        match t::block_at(&w, BlockPosition::new(0, 64, 0)) {
+            Block::Fire(_) => (),
+            block => panic!("fire went out: {:?}", block),
+        }
+    }
+
+    #[test]
+    fn test_fire_spreads() {
+        let (mut w, mut d) = world();
+        for x in 0..8 {
+            for z in 0..8 {
+                t::place(&w, BlockPosition::new(x, 63, z), Block::OakPlanks);
+            }
+        }
+        t::place(&w, BlockPosition::new(3, 64, 3), fire(0));
+
+        t::run_ticks(&mut w, &mut d, 4000);
+        let burned = (0..64)
+            .filter(|i| t::block_at(&w, BlockPosition::new(i / 8, 63, i % 8)) != Block::OakPlanks)
+            .count();
+        assert!(burned > 1);
+    }
+
+    #[test]
+    fn test_fire_needs_support() {
+        let (mut w, mut d) = world();
+        t::place(&w, BlockPosition::new(0, 63, 0), Block::OakPlanks);
+        t::place(&w, BlockPosition::new(0, 64, 0), fire(0));
+        t::run_ticks(&mut w, &mut d, 1);
+
+        t::place(&w, BlockPosition::new(0, 63, 0), Block::Air);
+        t::run_ticks(&mut w, &mut d, 3);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), Block::Air);
+    }
+
+    #[test]
+    fn test_rain_extinguishes() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+        t::set_block(0, 63, 0, Block::Stone, &w);
+        t::set_block(
+            1,
+            64,
+            0,
+            Block::OakLog(OakLogData {
+                axis: OakLogAxis::Y,
+            }),
+            &w,
+        );
+        t::set_block(0, 64, 0, fire(0), &w);
+
+        let pos = BlockPosition::new(0, 64, 0);
+        let mut chunk_map = w.fetch_mut::<ChunkMap>();
+        let mut events = EventChannel::new();
+        let mut world = FireWorld {
+            chunk_map: &mut *chunk_map,
+            events: &mut events,
+            weather: Weather {
+                rain_level: 1.0,
+                thunder_level: 0.0,
+            },
+            rng: StepRng::new(0, 0),
+        };
+        assert!(!world.tick(pos, 0));
+        assert_eq!(world.chunk_map.block_at(pos), Some(Block::Air));
+
+        world.chunk_map.set_block_at(pos, fire(0)).unwrap();
+        world.weather = Weather::default();
+        assert!(world.tick(pos, 0));
+        assert_eq!(world.chunk_map.block_at(pos), Some(fire(0)));
+    }
+
+    #[test]
+    fn test_do_fire_tick() {
+        let (mut w, mut d) = world();
+        w.fetch_mut::<LevelData>()
+            .set_game_rule("doFireTick", "false");
+        t::place(&w, BlockPosition::new(0, 63, 0), Block::Stone);
+        t::place(&w, BlockPosition::new(0, 64, 0), fire(0));
+
+        t::run_ticks(&mut w, &mut d, 1000);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), fire(0));
+    }
+}
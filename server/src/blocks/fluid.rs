@@ -0,0 +1,577 @@
+//! Flowing water and lava.
+//!
+//! As in vanilla, fluids are updated by scheduled block ticks: when a
+//! fluid or a block next to it changes, the fluid is scheduled to be
+//! updated later, after 5 ticks for water and 30 ticks for lava. Updating a
+//! fluid recomputes its level from the fluids around it, then lets it
+//! spread, downwards if possible and otherwise towards the nearest drop.
+
+use crate::blocks::{self, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent};
+use feather_blocks::{Block, BlockExt, LavaData, WaterData};
+use feather_core::world::{BlockPosition, ChunkMap};
+use shrev::{EventChannel, ReaderId};
+use specs::{Read, System, Write};
+
+/// The level of falling fluids, which spread like sources
+/// but disappear when no fluid is above them.
+const FALLING_LEVEL: i32 = 8;
+
+/// The amount of fluid in sources and falling fluids.
+const MAX_AMOUNT: i32 = 8;
+
+/// Returned by `slope_distance` when there is no drop nearby.
+const NO_DROP: i32 = 1000;
+
+const UP: BlockPosition = BlockPosition::new(0, 1, 0);
+const DOWN: BlockPosition = BlockPosition::new(0, -1, 0);
+const HORIZONTAL: [BlockPosition; 4] = [
+    BlockPosition::new(0, 0, -1),
+    BlockPosition::new(0, 0, 1),
+    BlockPosition::new(-1, 0, 0),
+    BlockPosition::new(1, 0, 0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    /// Returns the fluid in the given block and its level.
+    fn of(block: Block) -> Option<(Fluid, i32)> {
+        match block {
+            Block::Water(data) => Some((Fluid::Water, data.level)),
+            Block::Lava(data) => Some((Fluid::Lava, data.level)),
+            _ => None,
+        }
+    }
+
+    fn block(self, level: i32) -> Block {
+        match self {
+            Fluid::Water => Block::Water(WaterData { level }),
+            Fluid::Lava => Block::Lava(LavaData { level }),
+        }
+    }
+
+    fn is_in(self, block: Block) -> bool {
+        Fluid::of(block).map_or(false, |(fluid, _)| fluid == self)
+    }
+
+    fn is_source(self, block: Block) -> bool {
+        Fluid::of(block) == Some((self, 0))
+    }
+
+    /// Returns the number of ticks between updates of the fluid.
+    fn tick_rate(self) -> u64 {
+        match self {
+            Fluid::Water => 5,
+            Fluid::Lava => 30,
+        }
+    }
+
+    /// Returns by how much the amount of the fluid
+    /// decreases for each block it flows sideways.
+    fn amount_drop(self) -> i32 {
+        match self {
+            Fluid::Water => 1,
+            Fluid::Lava => 2,
+        }
+    }
+
+    /// Returns how many blocks away the fluid
+    /// looks for a drop to flow towards.
+    fn slope_distance(self) -> i32 {
+        match self {
+            Fluid::Water => 4,
+            Fluid::Lava => 2,
+        }
+    }
+}
+
+/// Converts a fluid level into the amount of fluid in the block.
+fn amount(level: i32) -> i32 {
+    if level == 0 || level >= FALLING_LEVEL {
+        MAX_AMOUNT
+    } else {
+        MAX_AMOUNT - level
+    }
+}
+
+fn opposite(dir: BlockPosition) -> BlockPosition {
+    BlockPosition::new(-dir.x, -dir.y, -dir.z)
+}
+
+/// Returns whether fluids wash away the given block.
+fn is_replaceable(block: Block) -> bool {
+    match block {
+        Block::Air
+        | Block::CaveAir
+        | Block::VoidAir
+        | Block::Fire(_)
+        | Block::Grass
+        | Block::Fern
+        | Block::DeadBush
+        | Block::Snow(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns whether a fluid may flow into the given block.
+fn can_flow_into(block: Block, fluid: Fluid) -> bool {
+    match Fluid::of(block) {
+        Some((other, level)) => other == fluid && level != 0 && level < FALLING_LEVEL,
+        None => is_replaceable(block),
+    }
+}
+
+/// Returns whether a fluid above the given block would fall into it.
+fn is_hole(block: Block, fluid: Fluid) -> bool {
+    can_flow_into(block, fluid) || fluid.is_in(block)
+}
+
+/// System which schedules block ticks for fluids
+/// which changed or are next to a changed block.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct FluidScheduleSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for FluidScheduleSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, ChunkMap>,
+        Write<'a, BlockTicks>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, chunk_map, mut ticks) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let offsets = [BlockPosition::new(0, 0, 0), UP, DOWN];
+            for &offset in offsets.iter().chain(HORIZONTAL.iter()) {
+                let pos = event.pos + offset;
+                let (fluid, level) = continue_if_none!(chunk_map.block_at(pos).and_then(Fluid::of));
+
+                // Lava hardens as soon as water reaches it.
+                let delay =
+                    if fluid == Fluid::Lava && lava_reaction(&chunk_map, pos, level).is_some() {
+                        1
+                    } else {
+                        fluid.tick_rate()
+                    };
+                ticks.schedule_block_tick(pos, delay, 0);
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which updates fluids when their block ticks
+/// run, letting them flow and lava react with water.
+///
+/// This system listens to `BlockTickEvent`s.
+#[derive(Default)]
+pub struct FluidTickSystem {
+    reader: Option<ReaderId<BlockTickEvent>>,
+}
+
+impl<'a> System<'a> for FluidTickSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events) = data;
+
+        let mut world = FluidWorld {
+            chunk_map: &mut *chunk_map,
+            events: &mut *events,
+        };
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            world.tick(event.pos);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// The blocks changed by fluids.
+struct FluidWorld<'a> {
+    chunk_map: &'a mut ChunkMap,
+    events: &'a mut EventChannel<BlockUpdateEvent>,
+}
+
+impl<'a> FluidWorld<'a> {
+    fn block_at(&self, pos: BlockPosition) -> Option<Block> {
+        self.chunk_map.block_at(pos)
+    }
+
+    fn set_block(&mut self, pos: BlockPosition, block: Block) {
+        blocks::set_block(
+            self.chunk_map,
+            self.events,
+            pos,
+            block,
+            BlockUpdateCause::Fluid,
+        );
+    }
+
+    /// Updates the fluid at the given position.
+    fn tick(&mut self, pos: BlockPosition) {
+        let (fluid, mut level) = match self.block_at(pos).and_then(Fluid::of) {
+            Some(fluid) => fluid,
+            None => return,
+        };
+
+        if fluid == Fluid::Lava {
+            if let Some(block) = lava_reaction(self.chunk_map, pos, level) {
+                self.set_block(pos, block);
+                return;
+            }
+        }
+
+        if level != 0 {
+            let block = flowing_state(self.chunk_map, pos, fluid);
+            self.set_block(pos, block);
+            level = match Fluid::of(block) {
+                Some((_, level)) => level,
+                None => return,
+            };
+        }
+
+        self.spread(pos, fluid, level);
+    }
+
+    /// Lets a fluid spread downwards if possible, and sideways otherwise.
+    fn spread(&mut self, pos: BlockPosition, fluid: Fluid, level: i32) {
+        let below = pos + DOWN;
+        let below_block = match self.block_at(below) {
+            Some(block) => block,
+            None => return,
+        };
+
+        if fluid == Fluid::Lava && Fluid::Water.is_in(below_block) {
+            // Lava flowing into water turns it into stone.
+            self.set_block(below, Block::Stone);
+            return;
+        }
+
+        if can_flow_into(below_block, fluid) {
+            self.set_block(below, fluid.block(FALLING_LEVEL));
+
+            // Fluids only spread sideways while falling
+            // when surrounded by sources.
+            let sources = HORIZONTAL
+                .iter()
+                .filter(|&&dir| {
+                    self.block_at(pos + dir)
+                        .map_or(false, |b| fluid.is_source(b))
+                })
+                .count();
+            if sources >= 3 {
+                self.spread_sideways(pos, fluid, level);
+            }
+        } else if level == 0 || !is_hole(below_block, fluid) {
+            self.spread_sideways(pos, fluid, level);
+        }
+    }
+
+    fn spread_sideways(&mut self, pos: BlockPosition, fluid: Fluid, level: i32) {
+        let new_amount = amount(level) - fluid.amount_drop();
+        if new_amount <= 0 {
+            return;
+        }
+        let new_block = fluid.block(MAX_AMOUNT - new_amount);
+
+        for dir in flow_directions(self.chunk_map, pos, fluid) {
+            let target = pos + dir;
+            let block = continue_if_none!(self.block_at(target));
+
+            let replace = match Fluid::of(block) {
+                Some((_, other_level)) => {
+                    can_flow_into(block, fluid) && amount(other_level) < new_amount
+                }
+                None => is_replaceable(block),
+            };
+            if replace {
+                self.set_block(target, new_block);
+            }
+        }
+    }
+}
+
+/// Returns the block lava turns into if water is next to or
+/// above it: obsidian for sources and cobblestone otherwise.
+fn lava_reaction(chunk_map: &ChunkMap, pos: BlockPosition, level: i32) -> Option<Block> {
+    let touches_water = HORIZONTAL.iter().chain(std::iter::once(&UP)).any(|&dir| {
+        chunk_map
+            .block_at(pos + dir)
+            .map_or(false, |block| Fluid::Water.is_in(block))
+    });
+    if !touches_water {
+        return None;
+    }
+
+    if level == 0 {
+        Some(Block::Obsidian)
+    } else {
+        Some(Block::Cobblestone)
+    }
+}
+
+/// Returns the block a flowing fluid turns into,
+/// given the fluids around it.
+fn flowing_state(chunk_map: &ChunkMap, pos: BlockPosition, fluid: Fluid) -> Block {
+    if chunk_map
+        .block_at(pos + UP)
+        .map_or(false, |block| fluid.is_in(block))
+    {
+        return fluid.block(FALLING_LEVEL);
+    }
+
+    let mut max_amount = 0;
+    let mut sources = 0;
+    for &dir in &HORIZONTAL {
+        let (other, level) = continue_if_none!(chunk_map.block_at(pos + dir).and_then(Fluid::of));
+        if other != fluid {
+            continue;
+        }
+        if level == 0 {
+            sources += 1;
+        }
+        max_amount = max_amount.max(amount(level));
+    }
+
+    // Water between two sources becomes a source
+    // if it is on top of a solid block or water.
+    if fluid == Fluid::Water && sources >= 2 {
+        let below = chunk_map.block_at(pos + DOWN);
+        if below.map_or(false, |block| block.is_solid() || fluid.is_source(block)) {
+            return fluid.block(0);
+        }
+    }
+
+    let new_amount = max_amount - fluid.amount_drop();
+    if new_amount <= 0 {
+        Block::Air
+    } else {
+        fluid.block(MAX_AMOUNT - new_amount)
+    }
+}
+
+/// Returns the directions in which a fluid spreads sideways:
+/// those towards the nearest drop within its slope distance,
+/// or all directions it can flow in if there is no such drop.
+fn flow_directions(chunk_map: &ChunkMap, pos: BlockPosition, fluid: Fluid) -> Vec<BlockPosition> {
+    let mut shortest = NO_DROP;
+    let mut directions = vec![];
+
+    for &dir in &HORIZONTAL {
+        let target = pos + dir;
+        if !chunk_map
+            .block_at(target)
+            .map_or(false, |block| can_flow_into(block, fluid))
+        {
+            continue;
+        }
+
+        let distance = if chunk_map
+            .block_at(target + DOWN)
+            .map_or(false, |block| is_hole(block, fluid))
+        {
+            0
+        } else {
+            slope_distance(chunk_map, target, fluid, 1, opposite(dir))
+        };
+
+        if distance < shortest {
+            shortest = distance;
+            directions.clear();
+        }
+        if distance == shortest {
+            directions.push(dir);
+        }
+    }
+
+    directions
+}
+
+/// Returns the number of blocks from the given position to
+/// the nearest drop, not going back in the direction `from`.
+fn slope_distance(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    fluid: Fluid,
+    distance: i32,
+    from: BlockPosition,
+) -> i32 {
+    let mut shortest = NO_DROP;
+
+    for &dir in &HORIZONTAL {
+        if dir == from {
+            continue;
+        }
+        let next = pos + dir;
+        if !chunk_map
+            .block_at(next)
+            .map_or(false, |block| can_flow_into(block, fluid))
+        {
+            continue;
+        }
+
+        if chunk_map
+            .block_at(next + DOWN)
+            .map_or(false, |block| is_hole(block, fluid))
+        {
+            return distance;
+        }
+
+        if distance < fluid.slope_distance() {
+            shortest = shortest.min(slope_distance(
+                chunk_map,
+                next,
+                fluid,
+                distance + 1,
+                opposite(dir),
+            ));
+        }
+    }
+
+    shortest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use specs::World;
+
+    fn water(level: i32) -> Block {
+        Block::Water(WaterData { level })
+    }
+
+    fn lava(level: i32) -> Block {
+        Block::Lava(LavaData { level })
+    }
+
+    fn setup<'a, 'b>() -> (World, specs::Dispatcher<'a, 'b>) {
+        let (mut w, d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(FluidTickSystem::default(), "fluid", &["ticks"])
+            .with_dep(FluidScheduleSystem::default(), "schedule", &["fluid"])
+            .build();
+        t::populate_with_air(&mut w);
+
+        // A stone floor at y = 63.
+        for x in -16..16 {
+            for z in -16..16 {
+                t::set_block(x, 63, z, Block::Stone, &w);
+            }
+        }
+        (w, d)
+    }
+
+    #[test]
+    fn test_water_spreads() {
+        let (mut w, mut d) = setup();
+        t::place(&w, BlockPosition::new(0, 64, 0), water(0));
+
+        // Water isn't updated before its tick rate.
+        t::run_ticks(&mut w, &mut d, 5);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), Block::Air);
+
+        t::run_ticks(&mut w, &mut d, 100);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), water(0));
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), water(1));
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, -3)), water(3));
+        assert_eq!(t::block_at(&w, BlockPosition::new(7, 64, 0)), water(7));
+        assert_eq!(t::block_at(&w, BlockPosition::new(8, 64, 0)), Block::Air);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 65, 0)), Block::Air);
+    }
+
+    #[test]
+    fn test_water_flows_down() {
+        let (mut w, mut d) = setup();
+        // A hole two blocks east of the source.
+        t::set_block(2, 63, 0, Block::Air, &w);
+        t::set_block(2, 62, 0, Block::Stone, &w);
+        t::place(&w, BlockPosition::new(0, 64, 0), water(0));
+
+        t::run_ticks(&mut w, &mut d, 100);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(2, 63, 0)),
+            water(FALLING_LEVEL)
+        );
+        // Water flows towards the hole.
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), water(1));
+        assert_eq!(t::block_at(&w, BlockPosition::new(-1, 64, 0)), Block::Air);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 1)), Block::Air);
+    }
+
+    #[test]
+    fn test_water_source_creation() {
+        let (mut w, mut d) = setup();
+        t::place(&w, BlockPosition::new(0, 64, 0), water(0));
+        t::place(&w, BlockPosition::new(2, 64, 0), water(0));
+
+        t::run_ticks(&mut w, &mut d, 100);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), water(0));
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 1)), water(1));
+    }
+
+    #[test]
+    fn test_water_recedes() {
+        let (mut w, mut d) = setup();
+        t::place(&w, BlockPosition::new(0, 64, 0), water(0));
+        t::run_ticks(&mut w, &mut d, 100);
+
+        t::place(&w, BlockPosition::new(0, 64, 0), Block::Air);
+        t::run_ticks(&mut w, &mut d, 300);
+        for x in -8..=8 {
+            assert_eq!(t::block_at(&w, BlockPosition::new(x, 64, 0)), Block::Air);
+        }
+    }
+
+    #[test]
+    fn test_lava_spreads() {
+        let (mut w, mut d) = setup();
+        t::place(&w, BlockPosition::new(0, 64, 0), lava(0));
+
+        t::run_ticks(&mut w, &mut d, 300);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), lava(2));
+        assert_eq!(t::block_at(&w, BlockPosition::new(3, 64, 0)), lava(6));
+        assert_eq!(t::block_at(&w, BlockPosition::new(4, 64, 0)), Block::Air);
+    }
+
+    #[test]
+    fn test_lava_reactions() {
+        let (mut w, mut d) = setup();
+        t::set_block(0, 64, 0, lava(0), &w);
+        t::set_block(4, 64, 0, lava(4), &w);
+        t::set_block(0, 64, 4, water(0), &w);
+        t::place(&w, BlockPosition::new(1, 64, 0), water(1));
+        t::place(&w, BlockPosition::new(4, 65, 0), water(0));
+        t::place(&w, BlockPosition::new(0, 65, 4), lava(0));
+
+        t::run_ticks(&mut w, &mut d, 2);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            Block::Obsidian
+        );
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(4, 64, 0)),
+            Block::Cobblestone
+        );
+
+        // Lava flowing down into water turns it into stone.
+        t::run_ticks(&mut w, &mut d, 30);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 4)), Block::Stone);
+    }
+}
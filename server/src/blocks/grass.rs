@@ -0,0 +1,178 @@
+//! Spreading and decay of grass blocks on random ticks.
+
+use crate::blocks::{self, random_tick_light, BlockUpdateCause, BlockUpdateEvent, RandomTickEvent};
+use feather_blocks::{Block, BlockExt, GrassBlockData};
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{Read, System, Write};
+
+/// The light level above a grass block needed for it to spread.
+const MIN_SPREAD_LIGHT: u8 = 9;
+/// The light level above a dirt block needed for grass to spread to it.
+const MIN_GRASS_LIGHT: u8 = 4;
+/// The number of blocks grass tries to spread to on each random tick.
+const SPREAD_ATTEMPTS: usize = 4;
+
+fn above(pos: BlockPosition) -> BlockPosition {
+    BlockPosition::new(pos.x, pos.y + 1, pos.z)
+}
+
+/// Returns whether grass survives below the given block.
+fn can_survive_below(block: Option<Block>) -> bool {
+    match block {
+        Some(Block::Water(_)) | None => false,
+        Some(block) => !block.is_opaque(),
+    }
+}
+
+/// System which lets grass spread to nearby dirt
+/// and decay into dirt when covered.
+///
+/// This system listens to `RandomTickEvent`s.
+#[derive(Default)]
+pub struct GrassSpreadSystem {
+    reader: Option<ReaderId<RandomTickEvent>>,
+}
+
+impl<'a> System<'a> for GrassSpreadSystem {
+    type SystemData = (
+        Read<'a, EventChannel<RandomTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events) = data;
+        let mut rng = rand::thread_rng();
+
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            match chunk_map.block_at(event.pos) {
+                Some(Block::GrassBlock(_)) => (),
+                _ => continue,
+            }
+
+            if !can_survive_below(chunk_map.block_at(above(event.pos))) {
+                blocks::set_block(
+                    &mut chunk_map,
+                    &mut events,
+                    event.pos,
+                    Block::Dirt,
+                    BlockUpdateCause::RandomTick,
+                );
+                continue;
+            }
+
+            if random_tick_light(&chunk_map, above(event.pos)) < MIN_SPREAD_LIGHT {
+                continue;
+            }
+
+            for _ in 0..SPREAD_ATTEMPTS {
+                let target = BlockPosition::new(
+                    event.pos.x + rng.gen_range(-1, 2),
+                    event.pos.y + rng.gen_range(-3, 2),
+                    event.pos.z + rng.gen_range(-1, 2),
+                );
+                if chunk_map.block_at(target) != Some(Block::Dirt) {
+                    continue;
+                }
+
+                let covering = chunk_map.block_at(above(target));
+                if !can_survive_below(covering)
+                    || random_tick_light(&chunk_map, above(target)) < MIN_GRASS_LIGHT
+                {
+                    continue;
+                }
+
+                let snowy = match covering {
+                    Some(Block::Snow(_)) => true,
+                    _ => false,
+                };
+                blocks::set_block(
+                    &mut chunk_map,
+                    &mut events,
+                    target,
+                    Block::GrassBlock(GrassBlockData { snowy }),
+                    BlockUpdateCause::RandomTick,
+                );
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::ChunkPosition;
+    use specs::{World, WorldExt};
+
+    fn grass() -> Block {
+        Block::GrassBlock(GrassBlockData { snowy: false })
+    }
+
+    fn random_tick(w: &World, x: i32, y: i32, z: i32) {
+        t::trigger_event(
+            w,
+            RandomTickEvent {
+                pos: BlockPosition::new(x, y, z),
+                block: t::block_at(w, BlockPosition::new(x, y, z)),
+            },
+        );
+    }
+
+    #[test]
+    fn test_grass_spread() {
+        let (mut w, mut d) = t::builder().with(GrassSpreadSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        t::set_block(1, 63, 1, grass(), &w);
+        t::set_block(2, 63, 1, Block::Dirt, &w);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let chunk = chunk_map.chunk_at_mut(ChunkPosition::new(0, 0)).unwrap();
+            chunk.set_sky_light_at(1, 64, 1, 15);
+            chunk.set_sky_light_at(2, 64, 1, 15);
+        }
+
+        for _ in 0..100 {
+            random_tick(&w, 1, 63, 1);
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 63, 1)), grass());
+    }
+
+    #[test]
+    fn test_grass_needs_light() {
+        let (mut w, mut d) = t::builder().with(GrassSpreadSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        t::set_block(1, 63, 1, grass(), &w);
+        t::set_block(2, 63, 1, Block::Dirt, &w);
+
+        for _ in 0..100 {
+            random_tick(&w, 1, 63, 1);
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 63, 1)), Block::Dirt);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 63, 1)), grass());
+    }
+
+    #[test]
+    fn test_grass_decay() {
+        let (mut w, mut d) = t::builder().with(GrassSpreadSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        t::set_block(1, 63, 1, grass(), &w);
+        t::set_block(1, 64, 1, Block::Stone, &w);
+        random_tick(&w, 1, 63, 1);
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 63, 1)), Block::Dirt);
+    }
+}
@@ -0,0 +1,401 @@
+//! Decay of leaves which are no longer connected to a tree.
+//!
+//! As in vanilla, leaves store their distance to the nearest log in
+//! their `distance` property: leaves next to a log have a distance of
+//! one, and other leaves have a distance one greater than the nearest
+//! leaves around them, up to `MAX_DISTANCE`. Distances are recomputed
+//! on a block tick after a neighbouring block changes. Leaves at the
+//! maximum distance which weren't placed by players decay on random
+//! ticks and drop the loot of their loot table.
+
+use crate::blocks::{
+    self, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent, RandomTickEvent,
+};
+use crate::loot::{drop_at_block, LootContext, LootTables};
+use crate::TickCount;
+use feather_blocks::{
+    AcaciaLeavesData, BirchLeavesData, Block, DarkOakLeavesData, JungleLeavesData, OakLeavesData,
+    SpruceLeavesData,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use shrev::{EventChannel, ReaderId};
+use specs::{Entities, LazyUpdate, Read, System, Write};
+
+/// The distance of leaves which aren't connected to a log.
+pub const MAX_DISTANCE: i32 = 7;
+
+/// The offsets of the blocks adjacent to a block.
+const NEIGHBOURS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// Returns the distance and persistence of the given leaves.
+fn leaves_data(block: Block) -> Option<(i32, bool)> {
+    match block {
+        Block::OakLeaves(data) => Some((data.distance, data.persistent)),
+        Block::SpruceLeaves(data) => Some((data.distance, data.persistent)),
+        Block::BirchLeaves(data) => Some((data.distance, data.persistent)),
+        Block::JungleLeaves(data) => Some((data.distance, data.persistent)),
+        Block::AcaciaLeaves(data) => Some((data.distance, data.persistent)),
+        Block::DarkOakLeaves(data) => Some((data.distance, data.persistent)),
+        _ => None,
+    }
+}
+
+/// Returns the given leaves with another distance and persistence.
+fn with_leaves_data(block: Block, distance: i32, persistent: bool) -> Block {
+    match block {
+        Block::OakLeaves(_) => Block::OakLeaves(OakLeavesData {
+            distance,
+            persistent,
+        }),
+        Block::SpruceLeaves(_) => Block::SpruceLeaves(SpruceLeavesData {
+            distance,
+            persistent,
+        }),
+        Block::BirchLeaves(_) => Block::BirchLeaves(BirchLeavesData {
+            distance,
+            persistent,
+        }),
+        Block::JungleLeaves(_) => Block::JungleLeaves(JungleLeavesData {
+            distance,
+            persistent,
+        }),
+        Block::AcaciaLeaves(_) => Block::AcaciaLeaves(AcaciaLeavesData {
+            distance,
+            persistent,
+        }),
+        Block::DarkOakLeaves(_) => Block::DarkOakLeaves(DarkOakLeavesData {
+            distance,
+            persistent,
+        }),
+        block => block,
+    }
+}
+
+/// Returns whether the given block is leaves.
+pub fn is_leaves(block: Block) -> bool {
+    leaves_data(block).is_some()
+}
+
+/// Returns whether the given block is a log or
+/// wood block, which keeps leaves from decaying.
+pub fn is_log(block: Block) -> bool {
+    match block {
+        Block::OakLog(_)
+        | Block::SpruceLog(_)
+        | Block::BirchLog(_)
+        | Block::JungleLog(_)
+        | Block::AcaciaLog(_)
+        | Block::DarkOakLog(_)
+        | Block::StrippedOakLog(_)
+        | Block::StrippedSpruceLog(_)
+        | Block::StrippedBirchLog(_)
+        | Block::StrippedJungleLog(_)
+        | Block::StrippedAcaciaLog(_)
+        | Block::StrippedDarkOakLog(_)
+        | Block::OakWood(_)
+        | Block::SpruceWood(_)
+        | Block::BirchWood(_)
+        | Block::JungleWood(_)
+        | Block::AcaciaWood(_)
+        | Block::DarkOakWood(_)
+        | Block::StrippedOakWood(_)
+        | Block::StrippedSpruceWood(_)
+        | Block::StrippedBirchWood(_)
+        | Block::StrippedJungleWood(_)
+        | Block::StrippedAcaciaWood(_)
+        | Block::StrippedDarkOakWood(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns whether the given block is leaves which
+/// are not connected to a log and decay.
+pub fn can_decay(block: Block) -> bool {
+    match leaves_data(block) {
+        Some((distance, persistent)) => distance >= MAX_DISTANCE && !persistent,
+        None => false,
+    }
+}
+
+/// Returns the given block as placed by a player. Leaves
+/// placed by players are persistent and never decay.
+pub fn placed_by_player(block: Block) -> Block {
+    match leaves_data(block) {
+        Some((distance, _)) => with_leaves_data(block, distance, true),
+        None => block,
+    }
+}
+
+/// Computes the distance of leaves at the given
+/// position from the blocks around them.
+fn leaves_distance(chunk_map: &ChunkMap, pos: BlockPosition) -> i32 {
+    let mut distance = MAX_DISTANCE;
+
+    for (dx, dy, dz) in NEIGHBOURS.iter() {
+        let neighbour = BlockPosition::new(pos.x + dx, pos.y + dy, pos.z + dz);
+        let block = continue_if_none!(chunk_map.block_at(neighbour));
+        if is_log(block) {
+            return 1;
+        }
+        if let Some((neighbour_distance, _)) = leaves_data(block) {
+            distance = distance.min(neighbour_distance + 1);
+        }
+    }
+
+    distance
+}
+
+/// System which schedules block ticks for leaves
+/// next to blocks which changed, so that their
+/// distances are recomputed.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct LeavesScheduleSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for LeavesScheduleSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, ChunkMap>,
+        Write<'a, BlockTicks>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, chunk_map, mut ticks) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if is_leaves(event.new_block) {
+                ticks.schedule_block_tick(event.pos, 1, 0);
+            }
+
+            for (dx, dy, dz) in NEIGHBOURS.iter() {
+                let pos = BlockPosition::new(event.pos.x + dx, event.pos.y + dy, event.pos.z + dz);
+                if chunk_map.block_at(pos).map_or(false, is_leaves) {
+                    ticks.schedule_block_tick(pos, 1, 0);
+                }
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which recomputes the distances of
+/// leaves on their scheduled block ticks.
+///
+/// This system listens to `BlockTickEvent`s.
+#[derive(Default)]
+pub struct LeavesDistanceSystem {
+    reader: Option<ReaderId<BlockTickEvent>>,
+}
+
+impl<'a> System<'a> for LeavesDistanceSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events) = data;
+
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            let block = continue_if_none!(chunk_map.block_at(event.pos));
+            let (_, persistent) = continue_if_none!(leaves_data(block));
+
+            let distance = leaves_distance(&chunk_map, event.pos);
+            blocks::set_block(
+                &mut chunk_map,
+                &mut events,
+                event.pos,
+                with_leaves_data(block, distance, persistent),
+                BlockUpdateCause::Leaves,
+            );
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which lets leaves which are no longer
+/// connected to a log decay on random ticks.
+///
+/// This system listens to `RandomTickEvent`s.
+#[derive(Default)]
+pub struct LeavesDecaySystem {
+    reader: Option<ReaderId<RandomTickEvent>>,
+}
+
+impl<'a> System<'a> for LeavesDecaySystem {
+    type SystemData = (
+        Read<'a, EventChannel<RandomTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, LootTables>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events, tables, lazy, entities, tick) = data;
+        let mut rng = rand::thread_rng();
+
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            if !can_decay(event.block) || chunk_map.block_at(event.pos) != Some(event.block) {
+                continue;
+            }
+
+            blocks::set_block(
+                &mut chunk_map,
+                &mut events,
+                event.pos,
+                Block::Air,
+                BlockUpdateCause::Leaves,
+            );
+            let drops = tables.block_drops(event.block, &LootContext::default(), &mut rng);
+            drop_at_block(&lazy, &entities, event.pos, drops, tick.0, &mut rng);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use feather_blocks::{OakLogAxis, OakLogData};
+    use specs::{World, WorldExt};
+
+    fn leaves(distance: i32) -> Block {
+        Block::OakLeaves(OakLeavesData {
+            distance,
+            persistent: false,
+        })
+    }
+
+    fn update(w: &World, x: i32, y: i32, z: i32, old_block: Block, new_block: Block) {
+        t::set_block(x, y, z, new_block, w);
+        t::trigger_event(
+            w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Test,
+                pos: BlockPosition::new(x, y, z),
+                old_block,
+                new_block,
+            },
+        );
+    }
+
+    #[test]
+    fn test_leaves_distance() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+
+        let log = Block::OakLog(OakLogData {
+            axis: OakLogAxis::Y,
+        });
+        t::set_block(0, 64, 0, log, &w);
+        t::set_block(1, 64, 0, leaves(3), &w);
+        t::set_block(2, 64, 0, leaves(1), &w);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(leaves_distance(&chunk_map, BlockPosition::new(1, 64, 0)), 1);
+        assert_eq!(leaves_distance(&chunk_map, BlockPosition::new(3, 64, 0)), 2);
+        assert_eq!(
+            leaves_distance(&chunk_map, BlockPosition::new(5, 64, 0)),
+            MAX_DISTANCE
+        );
+    }
+
+    #[test]
+    fn test_distance_update() {
+        let (mut w, mut d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(LeavesDistanceSystem::default(), "distance", &["ticks"])
+            .with_dep(LeavesScheduleSystem::default(), "schedule", &["distance"])
+            .build();
+        t::populate_with_air(&mut w);
+
+        let log = Block::OakLog(OakLogData {
+            axis: OakLogAxis::Y,
+        });
+        update(&w, 0, 64, 0, Block::Air, log);
+        for x in 1..10 {
+            update(&w, x, 64, 0, Block::Air, leaves(MAX_DISTANCE));
+        }
+
+        t::run_ticks(&mut w, &mut d, 50);
+        for x in 1..7 {
+            assert_eq!(t::block_at(&w, BlockPosition::new(x, 64, 0)), leaves(x));
+        }
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(9, 64, 0)),
+            leaves(MAX_DISTANCE)
+        );
+
+        // Cutting down the log disconnects the leaves.
+        update(&w, 0, 64, 0, log, Block::Air);
+        t::run_ticks(&mut w, &mut d, 50);
+        for x in 1..10 {
+            assert_eq!(
+                t::block_at(&w, BlockPosition::new(x, 64, 0)),
+                leaves(MAX_DISTANCE)
+            );
+        }
+    }
+
+    #[test]
+    fn test_leaves_decay() {
+        let (mut w, mut d) = t::builder().with(LeavesDecaySystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        w.insert(LootTables::bundled());
+
+        let persistent = Block::OakLeaves(OakLeavesData {
+            distance: MAX_DISTANCE,
+            persistent: true,
+        });
+        t::set_block(0, 64, 0, leaves(MAX_DISTANCE), &w);
+        t::set_block(1, 64, 0, leaves(2), &w);
+        t::set_block(2, 64, 0, persistent, &w);
+
+        for x in 0..3 {
+            t::trigger_event(
+                &w,
+                RandomTickEvent {
+                    pos: BlockPosition::new(x, 64, 0),
+                    block: t::block_at(&w, BlockPosition::new(x, 64, 0)),
+                },
+            );
+        }
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), Block::Air);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), leaves(2));
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), persistent);
+    }
+
+    #[test]
+    fn test_placed_by_player() {
+        assert_eq!(
+            placed_by_player(leaves(MAX_DISTANCE)),
+            Block::OakLeaves(OakLeavesData {
+                distance: MAX_DISTANCE,
+                persistent: true,
+            })
+        );
+        assert_eq!(placed_by_player(Block::Stone), Block::Stone);
+    }
+}
@@ -1,27 +1,42 @@
 mod falling;
-
-pub use falling::FallingBlockCreationSystem;
+mod fire;
+mod fluid;
+mod grass;
+mod leaves;
+mod plants;
+mod random_tick;
+mod tick;
+
+pub use falling::{can_fall_through, is_gravity_block, FallingBlockCreationSystem};
+pub use fire::{can_support_fire, fire_state, flammability, FireScheduleSystem, FireTickSystem};
+pub use fluid::{FluidScheduleSystem, FluidTickSystem};
+pub use grass::GrassSpreadSystem;
+pub use leaves::{
+    can_decay, is_leaves, is_log, placed_by_player, LeavesDecaySystem, LeavesDistanceSystem,
+    LeavesScheduleSystem,
+};
+pub use plants::{CropGrowthSystem, SaplingGrowthSystem};
+pub use random_tick::{random_tick_light, ticks_randomly, RandomTickEvent, RandomTickSystem};
+pub use tick::{BlockTickEvent, BlockTickSystem, BlockTicks};
 
 use shrev::{EventChannel, ReaderId};
 use specs::{DispatcherBuilder, Entity, Read, System, Write};
 
 use feather_blocks::Block;
-use feather_core::world::{BlockPosition, ChunkMap};
-
-use crate::systems::{BLOCK_FALLING_CREATION, BLOCK_UPDATE_PROPAGATE};
-use hashbrown::HashSet;
-
-lazy_static! {
-    /// List of block types that need to be notified
-    /// of adjacent block updates.
-    static ref BLOCKS_TO_NOTIFY: HashSet<Block> = {
-        let mut set = HashSet::new();
-        // Falling blocks
-        set.insert(Block::Sand);
-        set.insert(Block::RedSand);
-        set.insert(Block::Gravel);
-        set
-    };
+use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition};
+
+use crate::systems::{
+    BLOCK_FALLING_CREATION, BLOCK_TICK, BLOCK_UPDATE_PROPAGATE, CROP_GROWTH, FIRE_SCHEDULE,
+    FIRE_TICK, FLUID_SCHEDULE, FLUID_TICK, GRASS_SPREAD, LEAVES_DECAY, LEAVES_DISTANCE,
+    LEAVES_SCHEDULE, RANDOM_TICK, SAPLING_GROWTH,
+};
+use crate::timings::DispatcherBuilderExt;
+
+/// Returns whether the given block needs to be
+/// notified of adjacent block updates.
+fn needs_notify(block: Block) -> bool {
+    // Falling blocks
+    is_gravity_block(block)
 }
 
 /// Event triggered when a block is updated.
@@ -40,6 +55,15 @@ pub struct BlockUpdateEvent {
     pub new_block: Block,
 }
 
+/// Event triggered in place of `BlockUpdateEvent`s when
+/// many blocks in a chunk are changed at once, such as by
+/// `WorldEdit`. The chunk is relit and resent as a whole.
+#[derive(Debug, Clone)]
+pub struct BulkBlockUpdateEvent {
+    /// The chunk in which blocks were changed.
+    pub chunk: ChunkPosition,
+}
+
 /// The possible causes of a block update event.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BlockUpdateCause {
@@ -47,10 +71,64 @@ pub enum BlockUpdateCause {
     Player(Entity),
     /// Indicates that a falling block updated the block.
     FallingBlock,
+    /// Indicates that a plugin updated the block.
+    Plugin,
+    /// Indicates that a script updated the block.
+    Script,
+    /// Indicates that the block was changed by a `WorldEdit` operation.
+    WorldEdit,
+    /// Indicates that a portal was lit or destroyed.
+    Portal,
+    /// Indicates that lightning started a fire.
+    Lightning,
+    /// Indicates that a fluid flowed or reacted with another fluid.
+    Fluid,
+    /// Indicates that a plant grew or grass spread on a random tick.
+    RandomTick,
+    /// Indicates that fire spread, burned a block or went out.
+    Fire,
+    /// Indicates that leaves updated their distance to a log or decayed.
+    Leaves,
+    /// Indicates that a block was powered or unpowered by redstone.
+    Redstone,
+    /// Indicates that a piston moved or broke the block.
+    Piston,
+    /// Indicates that a pressed button popped out.
+    Button,
+    /// Indicates that chests were connected into or
+    /// disconnected from a double chest.
+    Chest,
+    /// Indicates that a furnace was lit or went out.
+    Furnace,
     /// A test block update caused, used for unit testing.
     Test,
 }
 
+/// Sets the block at the given position, triggering a
+/// `BlockUpdateEvent` if it changed. Returns whether the
+/// block changed.
+pub fn set_block(
+    chunk_map: &mut ChunkMap,
+    events: &mut EventChannel<BlockUpdateEvent>,
+    pos: BlockPosition,
+    block: Block,
+    cause: BlockUpdateCause,
+) -> bool {
+    let old_block = match chunk_map.block_at(pos) {
+        Some(old_block) if old_block != block => old_block,
+        _ => return false,
+    };
+
+    chunk_map.set_block_at(pos, block).unwrap();
+    events.single_write(BlockUpdateEvent {
+        cause,
+        pos,
+        old_block,
+        new_block: block,
+    });
+    true
+}
+
 #[derive(Debug, Clone)]
 pub struct BlockNotifyEvent {
     pub block: Block,
@@ -90,7 +168,7 @@ impl<'a> System<'a> for BlockUpdatePropagateSystem {
                         adjacent.z += z;
                         let block = chunk_map.block_at(adjacent);
                         if let Some(block) = block {
-                            if BLOCKS_TO_NOTIFY.contains(&block) {
+                            if needs_notify(block) {
                                 notify_events.push(BlockNotifyEvent {
                                     block,
                                     pos: adjacent,
@@ -110,19 +188,38 @@ impl<'a> System<'a> for BlockUpdatePropagateSystem {
     setup_impl!(reader);
 }
 
-pub fn init_logic(_dispatcher: &mut DispatcherBuilder) {
-    // TODO
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(BlockTickSystem, BLOCK_TICK, &[]);
+    dispatcher.add_timed(RandomTickSystem, RANDOM_TICK, &[]);
 }
 
 pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(
+    dispatcher.add_timed(
         BlockUpdatePropagateSystem::default(),
         BLOCK_UPDATE_PROPAGATE,
         &[],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         FallingBlockCreationSystem::default(),
         BLOCK_FALLING_CREATION,
         &[BLOCK_UPDATE_PROPAGATE],
     );
+    dispatcher.add_timed(FluidTickSystem::default(), FLUID_TICK, &[]);
+    dispatcher.add_timed(
+        FluidScheduleSystem::default(),
+        FLUID_SCHEDULE,
+        &[FLUID_TICK],
+    );
+    dispatcher.add_timed(CropGrowthSystem::default(), CROP_GROWTH, &[]);
+    dispatcher.add_timed(SaplingGrowthSystem::default(), SAPLING_GROWTH, &[]);
+    dispatcher.add_timed(GrassSpreadSystem::default(), GRASS_SPREAD, &[]);
+    dispatcher.add_timed(LeavesDistanceSystem::default(), LEAVES_DISTANCE, &[]);
+    dispatcher.add_timed(
+        LeavesScheduleSystem::default(),
+        LEAVES_SCHEDULE,
+        &[LEAVES_DISTANCE],
+    );
+    dispatcher.add_timed(LeavesDecaySystem::default(), LEAVES_DECAY, &[]);
+    dispatcher.add_timed(FireTickSystem::default(), FIRE_TICK, &[]);
+    dispatcher.add_timed(FireScheduleSystem::default(), FIRE_SCHEDULE, &[FIRE_TICK]);
 }
@@ -0,0 +1,525 @@
+//! Growth of crops and saplings on random ticks.
+//!
+//! Crops grow faster on moist farmland and slower when planted
+//! among crops of the same kind, as in vanilla. Saplings grow into
+//! small trees shaped like oaks, made of the wood of their kind.
+
+use crate::blocks::{
+    self, is_leaves, random_tick_light, BlockUpdateCause, BlockUpdateEvent, RandomTickEvent,
+};
+use feather_blocks::{
+    AcaciaLeavesData, AcaciaLogAxis, AcaciaLogData, AcaciaSaplingData, BeetrootsData,
+    BirchLeavesData, BirchLogAxis, BirchLogData, BirchSaplingData, Block, CarrotsData,
+    DarkOakLeavesData, DarkOakLogAxis, DarkOakLogData, DarkOakSaplingData, JungleLeavesData,
+    JungleLogAxis, JungleLogData, JungleSaplingData, OakLeavesData, OakLogAxis, OakLogData,
+    OakSaplingData, PotatoesData, SpruceLeavesData, SpruceLogAxis, SpruceLogData,
+    SpruceSaplingData, WheatData,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{Read, System, Write};
+use std::mem;
+
+/// The light level plants need to grow.
+const MIN_GROWTH_LIGHT: u8 = 9;
+
+/// Returns the age of a crop and the age at which it is fully grown.
+fn crop_age(block: Block) -> Option<(i32, i32)> {
+    match block {
+        Block::Wheat(data) => Some((data.age, 7)),
+        Block::Carrots(data) => Some((data.age, 7)),
+        Block::Potatoes(data) => Some((data.age, 7)),
+        Block::Beetroots(data) => Some((data.age, 3)),
+        _ => None,
+    }
+}
+
+/// Returns the given crop with another age.
+fn with_age(block: Block, age: i32) -> Block {
+    match block {
+        Block::Wheat(_) => Block::Wheat(WheatData { age }),
+        Block::Carrots(_) => Block::Carrots(CarrotsData { age }),
+        Block::Potatoes(_) => Block::Potatoes(PotatoesData { age }),
+        Block::Beetroots(_) => Block::Beetroots(BeetrootsData { age }),
+        block => block,
+    }
+}
+
+/// Returns how fast the crop at the given position grows,
+/// depending on the farmland around it and on whether crops
+/// of the same kind are planted next to it.
+fn growth_chance(chunk_map: &ChunkMap, pos: BlockPosition, crop: Block) -> f32 {
+    let mut chance = 1.0;
+
+    for dx in -1..=1 {
+        for dz in -1..=1 {
+            let below = BlockPosition::new(pos.x + dx, pos.y - 1, pos.z + dz);
+            let mut farmland = match chunk_map.block_at(below) {
+                Some(Block::Farmland(data)) if data.moisture > 0 => 3.0,
+                Some(Block::Farmland(_)) => 1.0,
+                _ => 0.0,
+            };
+            if dx != 0 || dz != 0 {
+                farmland /= 4.0;
+            }
+            chance += farmland;
+        }
+    }
+
+    let same_crop = |dx: i32, dz: i32| {
+        chunk_map
+            .block_at(BlockPosition::new(pos.x + dx, pos.y, pos.z + dz))
+            .map_or(false, |block| {
+                mem::discriminant(&block) == mem::discriminant(&crop)
+            })
+    };
+    let row_x = same_crop(-1, 0) || same_crop(1, 0);
+    let row_z = same_crop(0, -1) || same_crop(0, 1);
+    let diagonal = same_crop(-1, -1) || same_crop(1, -1) || same_crop(1, 1) || same_crop(-1, 1);
+    if (row_x && row_z) || diagonal {
+        chance /= 2.0;
+    }
+
+    chance
+}
+
+/// System which lets crops grow on random ticks.
+///
+/// This system listens to `RandomTickEvent`s.
+#[derive(Default)]
+pub struct CropGrowthSystem {
+    reader: Option<ReaderId<RandomTickEvent>>,
+}
+
+impl<'a> System<'a> for CropGrowthSystem {
+    type SystemData = (
+        Read<'a, EventChannel<RandomTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events) = data;
+        let mut rng = rand::thread_rng();
+
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            let (age, max_age) = continue_if_none!(crop_age(event.block));
+            if age >= max_age || chunk_map.block_at(event.pos) != Some(event.block) {
+                continue;
+            }
+
+            let above = BlockPosition::new(event.pos.x, event.pos.y + 1, event.pos.z);
+            if random_tick_light(&chunk_map, above) < MIN_GROWTH_LIGHT {
+                continue;
+            }
+
+            let chance = growth_chance(&chunk_map, event.pos, event.block);
+            if rng.gen_range(0, (25.0 / chance) as u32 + 1) == 0 {
+                blocks::set_block(
+                    &mut chunk_map,
+                    &mut events,
+                    event.pos,
+                    with_age(event.block, age + 1),
+                    BlockUpdateCause::RandomTick,
+                );
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// The kinds of wood of saplings and trees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TreeKind {
+    Oak,
+    Spruce,
+    Birch,
+    Jungle,
+    Acacia,
+    DarkOak,
+}
+
+impl TreeKind {
+    fn min_height(self) -> i32 {
+        match self {
+            TreeKind::Birch => 5,
+            _ => 4,
+        }
+    }
+
+    fn log(self) -> Block {
+        match self {
+            TreeKind::Oak => Block::OakLog(OakLogData {
+                axis: OakLogAxis::Y,
+            }),
+            TreeKind::Spruce => Block::SpruceLog(SpruceLogData {
+                axis: SpruceLogAxis::Y,
+            }),
+            TreeKind::Birch => Block::BirchLog(BirchLogData {
+                axis: BirchLogAxis::Y,
+            }),
+            TreeKind::Jungle => Block::JungleLog(JungleLogData {
+                axis: JungleLogAxis::Y,
+            }),
+            TreeKind::Acacia => Block::AcaciaLog(AcaciaLogData {
+                axis: AcaciaLogAxis::Y,
+            }),
+            TreeKind::DarkOak => Block::DarkOakLog(DarkOakLogData {
+                axis: DarkOakLogAxis::Y,
+            }),
+        }
+    }
+
+    /// Returns the leaves with the given distance from the trunk.
+    fn leaves(self, distance: i32) -> Block {
+        let persistent = false;
+        match self {
+            TreeKind::Oak => Block::OakLeaves(OakLeavesData {
+                distance,
+                persistent,
+            }),
+            TreeKind::Spruce => Block::SpruceLeaves(SpruceLeavesData {
+                distance,
+                persistent,
+            }),
+            TreeKind::Birch => Block::BirchLeaves(BirchLeavesData {
+                distance,
+                persistent,
+            }),
+            TreeKind::Jungle => Block::JungleLeaves(JungleLeavesData {
+                distance,
+                persistent,
+            }),
+            TreeKind::Acacia => Block::AcaciaLeaves(AcaciaLeavesData {
+                distance,
+                persistent,
+            }),
+            TreeKind::DarkOak => Block::DarkOakLeaves(DarkOakLeavesData {
+                distance,
+                persistent,
+            }),
+        }
+    }
+}
+
+/// Returns the kind of a sapling and its growth stage.
+fn sapling(block: Block) -> Option<(TreeKind, i32)> {
+    match block {
+        Block::OakSapling(data) => Some((TreeKind::Oak, data.stage)),
+        Block::SpruceSapling(data) => Some((TreeKind::Spruce, data.stage)),
+        Block::BirchSapling(data) => Some((TreeKind::Birch, data.stage)),
+        Block::JungleSapling(data) => Some((TreeKind::Jungle, data.stage)),
+        Block::AcaciaSapling(data) => Some((TreeKind::Acacia, data.stage)),
+        Block::DarkOakSapling(data) => Some((TreeKind::DarkOak, data.stage)),
+        _ => None,
+    }
+}
+
+fn with_stage(block: Block, stage: i32) -> Block {
+    match block {
+        Block::OakSapling(_) => Block::OakSapling(OakSaplingData { stage }),
+        Block::SpruceSapling(_) => Block::SpruceSapling(SpruceSaplingData { stage }),
+        Block::BirchSapling(_) => Block::BirchSapling(BirchSaplingData { stage }),
+        Block::JungleSapling(_) => Block::JungleSapling(JungleSaplingData { stage }),
+        Block::AcaciaSapling(_) => Block::AcaciaSapling(AcaciaSaplingData { stage }),
+        Block::DarkOakSapling(_) => Block::DarkOakSapling(DarkOakSaplingData { stage }),
+        block => block,
+    }
+}
+
+/// Returns whether trees may grow through the given block.
+fn is_replaceable(block: Block) -> bool {
+    match block {
+        Block::Air | Block::CaveAir | Block::Grass | Block::Fern => true,
+        block => is_leaves(block) || sapling(block).is_some(),
+    }
+}
+
+fn can_grow_on(block: Block) -> bool {
+    match block {
+        Block::GrassBlock(_) | Block::Dirt | Block::CoarseDirt | Block::Podzol(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns the blocks of a tree growing from a sapling at the given
+/// position, or `None` if there is no room for the tree to grow.
+fn tree_blocks<R: Rng>(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    kind: TreeKind,
+    rng: &mut R,
+) -> Option<Vec<(BlockPosition, Block)>> {
+    let height = kind.min_height() + rng.gen_range(0, 3);
+    let top = pos.y + height;
+    if top + 1 > 255 {
+        return None;
+    }
+
+    let below = BlockPosition::new(pos.x, pos.y - 1, pos.z);
+    if !can_grow_on(chunk_map.block_at(below)?) {
+        return None;
+    }
+
+    let mut blocks = vec![(below, Block::Dirt)];
+    for y in pos.y..top {
+        let trunk = BlockPosition::new(pos.x, y, pos.z);
+        if !is_replaceable(chunk_map.block_at(trunk)?) {
+            return None;
+        }
+        blocks.push((trunk, kind.log()));
+    }
+
+    // As in vanilla, the two lower layers of leaves have a radius
+    // of two and the upper ones a radius of one, and corners are
+    // filled randomly except in the top layer.
+    for y in top - 3..=top {
+        let layer = y - top;
+        let radius = 1 - layer / 2;
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                let corner = dx.abs() == radius && dz.abs() == radius;
+                if corner && (layer == 0 || rng.gen()) {
+                    continue;
+                }
+
+                let leaves_pos = BlockPosition::new(pos.x + dx, y, pos.z + dz);
+                match chunk_map.block_at(leaves_pos) {
+                    Some(block) if block == Block::Air || is_leaves(block) => (),
+                    _ => continue,
+                }
+
+                // Leaves decay unless they are close
+                // enough to a log, counting steps.
+                let mut distance = dx.abs() + dz.abs();
+                if y >= top {
+                    distance += y - top + 1;
+                }
+                blocks.push((leaves_pos, kind.leaves(distance.max(1))));
+            }
+        }
+    }
+
+    Some(blocks)
+}
+
+/// System which lets saplings grow into trees on random ticks.
+///
+/// This system listens to `RandomTickEvent`s.
+#[derive(Default)]
+pub struct SaplingGrowthSystem {
+    reader: Option<ReaderId<RandomTickEvent>>,
+}
+
+impl<'a> System<'a> for SaplingGrowthSystem {
+    type SystemData = (
+        Read<'a, EventChannel<RandomTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut chunk_map, mut events) = data;
+        let mut rng = rand::thread_rng();
+
+        for event in tick_events.read(self.reader.as_mut().unwrap()) {
+            let (kind, stage) = continue_if_none!(sapling(event.block));
+            if chunk_map.block_at(event.pos) != Some(event.block) {
+                continue;
+            }
+
+            let above = BlockPosition::new(event.pos.x, event.pos.y + 1, event.pos.z);
+            if random_tick_light(&chunk_map, above) < MIN_GROWTH_LIGHT || rng.gen_range(0, 7) != 0 {
+                continue;
+            }
+
+            // Saplings grow in two stages.
+            let changes = if stage == 0 {
+                vec![(event.pos, with_stage(event.block, 1))]
+            } else {
+                continue_if_none!(tree_blocks(&chunk_map, event.pos, kind, &mut rng))
+            };
+
+            for (pos, block) in changes {
+                blocks::set_block(
+                    &mut chunk_map,
+                    &mut events,
+                    pos,
+                    block,
+                    BlockUpdateCause::RandomTick,
+                );
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_blocks::{FarmlandData, GrassBlockData};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use specs::{World, WorldExt};
+
+    /// Sets the sky light of the column at the given position.
+    fn light_column(w: &World, x: usize, z: usize) {
+        let mut chunk_map = w.fetch_mut::<ChunkMap>();
+        let chunk = chunk_map
+            .chunk_at_mut(feather_core::ChunkPosition::new(0, 0))
+            .unwrap();
+        for y in 0..256 {
+            chunk.set_sky_light_at(x, y, z, 15);
+        }
+    }
+
+    fn random_tick(w: &World, x: i32, y: i32, z: i32) {
+        let block = t::block_at(w, BlockPosition::new(x, y, z));
+        t::trigger_event(
+            w,
+            RandomTickEvent {
+                pos: BlockPosition::new(x, y, z),
+                block,
+            },
+        );
+    }
+
+    #[test]
+    fn test_growth_chance() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+
+        let wheat = Block::Wheat(WheatData { age: 0 });
+        t::set_block(1, 64, 1, wheat, &w);
+        let pos = BlockPosition::new(1, 64, 1);
+        assert!((growth_chance(&w.fetch(), pos, wheat) - 1.0).abs() < std::f32::EPSILON);
+
+        for x in 0..3 {
+            for z in 0..3 {
+                t::set_block(x, 63, z, Block::Farmland(FarmlandData { moisture: 7 }), &w);
+            }
+        }
+        assert!((growth_chance(&w.fetch(), pos, wheat) - 10.0).abs() < std::f32::EPSILON);
+
+        // Crops in rows in both directions grow slower.
+        t::set_block(0, 64, 1, wheat, &w);
+        t::set_block(1, 64, 0, wheat, &w);
+        assert!((growth_chance(&w.fetch(), pos, wheat) - 5.0).abs() < std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_crop_growth() {
+        let (mut w, mut d) = t::builder().with(CropGrowthSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        t::set_block(1, 63, 1, Block::Farmland(FarmlandData { moisture: 7 }), &w);
+        t::set_block(1, 64, 1, Block::Carrots(CarrotsData { age: 0 }), &w);
+        t::set_block(3, 64, 3, Block::Wheat(WheatData { age: 7 }), &w);
+        light_column(&w, 1, 1);
+        light_column(&w, 3, 3);
+
+        for _ in 0..200 {
+            random_tick(&w, 1, 64, 1);
+            random_tick(&w, 3, 64, 3);
+            d.dispatch(&w);
+            w.maintain();
+        }
+
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(1, 64, 1)),
+            Block::Carrots(CarrotsData { age: 7 })
+        );
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(3, 64, 3)),
+            Block::Wheat(WheatData { age: 7 })
+        );
+    }
+
+    #[test]
+    fn test_crops_need_light() {
+        let (mut w, mut d) = t::builder().with(CropGrowthSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        let wheat = Block::Wheat(WheatData { age: 0 });
+        t::set_block(1, 63, 1, Block::Farmland(FarmlandData { moisture: 7 }), &w);
+        t::set_block(1, 64, 1, wheat, &w);
+
+        for _ in 0..100 {
+            random_tick(&w, 1, 64, 1);
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 1)), wheat);
+    }
+
+    #[test]
+    fn test_tree_blocks() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+        let mut rng = XorShiftRng::seed_from_u64(0);
+
+        let pos = BlockPosition::new(4, 64, 4);
+        t::set_block(
+            4,
+            64,
+            4,
+            Block::BirchSapling(BirchSaplingData { stage: 1 }),
+            &w,
+        );
+        assert!(tree_blocks(&w.fetch(), pos, TreeKind::Birch, &mut rng).is_none());
+
+        t::set_block(
+            4,
+            63,
+            4,
+            Block::GrassBlock(GrassBlockData { snowy: false }),
+            &w,
+        );
+        let blocks = tree_blocks(&w.fetch(), pos, TreeKind::Birch, &mut rng).unwrap();
+        assert!(blocks.contains(&(BlockPosition::new(4, 63, 4), Block::Dirt)));
+        let logs = blocks
+            .iter()
+            .filter(|(_, block)| *block == TreeKind::Birch.log())
+            .count();
+        assert!(logs >= 5 && logs < 8);
+        assert!(blocks.iter().any(|(_, block)| is_leaves(*block)));
+
+        // Trees don't grow through solid blocks.
+        t::set_block(4, 66, 4, Block::Stone, &w);
+        assert!(tree_blocks(&w.fetch(), pos, TreeKind::Birch, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_sapling_growth() {
+        let (mut w, mut d) = t::builder()
+            .with(SaplingGrowthSystem::default(), "")
+            .build();
+        t::populate_with_air(&mut w);
+
+        t::set_block(4, 63, 4, Block::Dirt, &w);
+        t::set_block(4, 64, 4, Block::OakSapling(OakSaplingData { stage: 0 }), &w);
+        light_column(&w, 4, 4);
+
+        for _ in 0..500 {
+            if t::block_at(&w, BlockPosition::new(4, 64, 4)) == TreeKind::Oak.log() {
+                break;
+            }
+            random_tick(&w, 4, 64, 4);
+            d.dispatch(&w);
+            w.maintain();
+        }
+
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(4, 64, 4)),
+            TreeKind::Oak.log()
+        );
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(4, 65, 4)),
+            TreeKind::Oak.log()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Random block ticks, which make plants grow and grass spread.
+//!
+//! As in vanilla, `randomTickSpeed` random blocks are picked in each
+//! non-empty section of every loaded chunk each tick, and a
+//! `RandomTickEvent` is triggered for those affected by random ticks.
+
+use crate::blocks::can_decay;
+use feather_blocks::Block;
+use feather_core::level::LevelData;
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::EventChannel;
+use specs::{Read, System, Write};
+
+/// The number of blocks picked in each section per
+/// tick when the `randomTickSpeed` game rule is invalid.
+const DEFAULT_RANDOM_TICK_SPEED: u32 = 3;
+
+/// Event triggered when a block affected by random ticks is picked.
+#[derive(Debug, Clone)]
+pub struct RandomTickEvent {
+    pub pos: BlockPosition,
+    pub block: Block,
+}
+
+/// Returns whether random ticks affect the given block.
+pub fn ticks_randomly(block: Block) -> bool {
+    match block {
+        Block::Wheat(_)
+        | Block::Carrots(_)
+        | Block::Potatoes(_)
+        | Block::Beetroots(_)
+        | Block::OakSapling(_)
+        | Block::SpruceSapling(_)
+        | Block::BirchSapling(_)
+        | Block::JungleSapling(_)
+        | Block::AcaciaSapling(_)
+        | Block::DarkOakSapling(_)
+        | Block::GrassBlock(_) => true,
+        block => can_decay(block),
+    }
+}
+
+/// Returns the light level at the given position used by random
+/// ticks: the greater of the block light and the sky light, which
+/// isn't darkened at night. Returns 0 if the chunk isn't loaded.
+pub fn random_tick_light(chunk_map: &ChunkMap, pos: BlockPosition) -> u8 {
+    let sky_light = chunk_map.sky_light_at(pos).unwrap_or(0);
+    let block_light = chunk_map.block_light_at(pos).unwrap_or(0);
+    sky_light.max(block_light)
+}
+
+/// System which picks random blocks and
+/// triggers `RandomTickEvent`s for them.
+pub struct RandomTickSystem;
+
+impl<'a> System<'a> for RandomTickSystem {
+    type SystemData = (
+        Read<'a, ChunkMap>,
+        Read<'a, LevelData>,
+        Write<'a, EventChannel<RandomTickEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (chunk_map, level, mut events) = data;
+
+        let speed = level
+            .game_rule("randomTickSpeed")
+            .and_then(|speed| speed.parse().ok())
+            .unwrap_or(DEFAULT_RANDOM_TICK_SPEED);
+        if speed == 0 {
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut ticked = vec![];
+        for (chunk_pos, chunk) in chunk_map.chunks() {
+            for index in 0..16 {
+                let section = match chunk.section(index) {
+                    Some(section) if !section.empty() => section,
+                    _ => continue,
+                };
+
+                for _ in 0..speed {
+                    let (x, y, z) = (
+                        rng.gen_range(0, 16),
+                        rng.gen_range(0, 16),
+                        rng.gen_range(0, 16),
+                    );
+                    let block = section.block_at(x, y, z);
+                    if ticks_randomly(block) {
+                        let pos = BlockPosition::new(
+                            chunk_pos.x * 16 + x as i32,
+                            (index * 16 + y) as i32,
+                            chunk_pos.z * 16 + z as i32,
+                        );
+                        ticked.push(RandomTickEvent { pos, block });
+                    }
+                }
+            }
+        }
+
+        events.iter_write(ticked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_blocks::WheatData;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_random_tick_system() {
+        let (mut w, mut d) = t::builder().with(RandomTickSystem, "").build();
+        t::populate_with_air(&mut w);
+        w.insert(LevelData::default());
+
+        // Fill a section with wheat so every picked block is ticked.
+        for x in 0..16 {
+            for y in 64..80 {
+                for z in 0..16 {
+                    t::set_block(x, y, z, Block::Wheat(WheatData { age: 0 }), &w);
+                }
+            }
+        }
+        let mut reader = t::reader::<RandomTickEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+        let events = t::triggered_events::<RandomTickEvent>(&w, &mut reader);
+        assert_eq!(events.len(), DEFAULT_RANDOM_TICK_SPEED as usize);
+        for event in events {
+            assert!((64..80).contains(&event.pos.y));
+            assert!((0..16).contains(&event.pos.x));
+        }
+
+        w.fetch_mut::<LevelData>()
+            .set_game_rule("randomTickSpeed", "0");
+        d.dispatch(&w);
+        w.maintain();
+        assert!(t::triggered_events::<RandomTickEvent>(&w, &mut reader).is_empty());
+    }
+}
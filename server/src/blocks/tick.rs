@@ -0,0 +1,254 @@
+//! Block updates scheduled for later ticks.
+//!
+//! Mechanics which change blocks after a delay, such as fluids,
+//! schedule block ticks with `BlockTicks::schedule_block_tick`.
+//! Once a tick is due, a `BlockTickEvent` is triggered for the
+//! block at its position. The ticks scheduled in a chunk are saved
+//! along with it and scheduled again when the chunk is loaded.
+
+use crate::TickCount;
+use feather_blocks::Block;
+use feather_core::world::chunk::ScheduledTick;
+use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition};
+use shrev::EventChannel;
+use specs::{Read, System, Write};
+use std::collections::{BTreeMap, HashMap};
+
+/// The maximum number of block ticks run in one server tick.
+const MAX_TICKS_PER_TICK: usize = 65_536;
+
+/// Event triggered when a block tick scheduled
+/// with `BlockTicks::schedule_block_tick` is due.
+#[derive(Debug, Clone)]
+pub struct BlockTickEvent {
+    /// The position of the ticked block.
+    pub pos: BlockPosition,
+    /// The block at the position when the tick ran.
+    pub block: Block,
+}
+
+/// The block ticks scheduled to run in later ticks.
+#[derive(Default, Debug)]
+pub struct BlockTicks {
+    /// The current tick, as counted by `TickCount`.
+    tick: u64,
+    /// The positions of the scheduled ticks, by the tick they're due
+    /// in and their priority. A position may still be listed after
+    /// its tick was rescheduled earlier or removed.
+    pending: BTreeMap<(u64, i32), Vec<BlockPosition>>,
+    /// The tick and priority of the tick scheduled at each position.
+    scheduled: HashMap<BlockPosition, (u64, i32)>,
+}
+
+impl BlockTicks {
+    /// Schedules a tick for the block at the given position
+    /// in `delay` ticks. Among the ticks due in the same tick,
+    /// those with lower priorities run first.
+    ///
+    /// A position has at most one scheduled tick, so
+    /// this does nothing if a tick is already scheduled
+    /// to run earlier at the position.
+    pub fn schedule_block_tick(&mut self, pos: BlockPosition, delay: u64, priority: i32) {
+        let key = (self.tick + delay, priority);
+        if self
+            .scheduled
+            .get(&pos)
+            .map_or(false, |&scheduled| scheduled <= key)
+        {
+            return;
+        }
+
+        self.scheduled.insert(pos, key);
+        self.pending.entry(key).or_default().push(pos);
+    }
+
+    /// Returns the number of ticks until the tick scheduled
+    /// at the given position runs, if one is scheduled.
+    pub fn scheduled_delay(&self, pos: BlockPosition) -> Option<u64> {
+        self.scheduled
+            .get(&pos)
+            .map(|&(tick, _)| tick.saturating_sub(self.tick))
+    }
+
+    /// Removes and returns at most `max` positions whose
+    /// ticks are due, in the order they should run.
+    fn take_due(&mut self, max: usize) -> Vec<BlockPosition> {
+        let mut due = vec![];
+
+        while due.len() < max {
+            let key = match self.pending.keys().next() {
+                Some(&key) if key.0 <= self.tick => key,
+                _ => break,
+            };
+            let mut positions = self.pending.remove(&key).unwrap_or_default().into_iter();
+
+            for pos in &mut positions {
+                // Skip positions which were rescheduled or removed.
+                if self.scheduled.get(&pos) == Some(&key) {
+                    self.scheduled.remove(&pos);
+                    due.push(pos);
+                    if due.len() == max {
+                        break;
+                    }
+                }
+            }
+
+            let remaining: Vec<BlockPosition> = positions.collect();
+            if !remaining.is_empty() {
+                self.pending.insert(key, remaining);
+            }
+        }
+
+        due
+    }
+
+    /// Returns the ticks scheduled in each chunk, with
+    /// their delays counted from the current tick.
+    pub fn ticks_by_chunk(&self) -> HashMap<ChunkPosition, Vec<ScheduledTick>> {
+        let mut chunks: HashMap<ChunkPosition, Vec<ScheduledTick>> = HashMap::new();
+        for (&pos, &key) in &self.scheduled {
+            chunks
+                .entry(pos.chunk_pos())
+                .or_default()
+                .push(self.to_scheduled_tick(pos, key));
+        }
+        chunks
+    }
+
+    /// Removes and returns the ticks scheduled in the given chunk,
+    /// with their delays counted from the current tick.
+    pub fn remove_chunk_ticks(&mut self, chunk: ChunkPosition) -> Vec<ScheduledTick> {
+        let positions: Vec<BlockPosition> = self
+            .scheduled
+            .keys()
+            .copied()
+            .filter(|pos| pos.chunk_pos() == chunk)
+            .collect();
+
+        positions
+            .into_iter()
+            .map(|pos| {
+                let key = self.scheduled.remove(&pos).unwrap();
+                self.to_scheduled_tick(pos, key)
+            })
+            .collect()
+    }
+
+    /// Schedules the ticks saved with a chunk which was loaded.
+    pub fn load_chunk_ticks(&mut self, ticks: Vec<ScheduledTick>) {
+        for tick in ticks {
+            self.schedule_block_tick(tick.pos, tick.delay.max(0) as u64, tick.priority);
+        }
+    }
+
+    fn to_scheduled_tick(&self, pos: BlockPosition, (tick, priority): (u64, i32)) -> ScheduledTick {
+        ScheduledTick {
+            pos,
+            delay: tick.saturating_sub(self.tick) as i32,
+            priority,
+        }
+    }
+}
+
+/// System which triggers `BlockTickEvent`s for due block ticks.
+pub struct BlockTickSystem;
+
+impl<'a> System<'a> for BlockTickSystem {
+    type SystemData = (
+        Write<'a, BlockTicks>,
+        Read<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockTickEvent>>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut ticks, chunk_map, mut events, tick_count) = data;
+
+        ticks.tick = tick_count.0;
+        for pos in ticks.take_due(MAX_TICKS_PER_TICK) {
+            let block = continue_if_none!(chunk_map.block_at(pos));
+            events.single_write(BlockTickEvent { pos, block });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_block_ticks() {
+        let mut ticks = BlockTicks::default();
+        let a = BlockPosition::new(0, 64, 0);
+        let b = BlockPosition::new(1, 64, 0);
+        let c = BlockPosition::new(2, 64, 0);
+
+        ticks.schedule_block_tick(a, 10, 0);
+        ticks.schedule_block_tick(a, 20, 0);
+        assert_eq!(ticks.scheduled_delay(a), Some(10));
+        ticks.schedule_block_tick(b, 20, 0);
+        ticks.schedule_block_tick(b, 10, 1);
+        ticks.schedule_block_tick(c, 10, -1);
+
+        ticks.tick = 9;
+        assert!(ticks.take_due(10).is_empty());
+        ticks.tick = 20;
+        assert_eq!(ticks.take_due(2), vec![c, a]);
+        assert_eq!(ticks.take_due(10), vec![b]);
+        // The position rescheduled earlier only runs once.
+        assert!(ticks.take_due(10).is_empty());
+        assert_eq!(ticks.scheduled_delay(b), None);
+    }
+
+    #[test]
+    fn test_chunk_ticks() {
+        let mut ticks = BlockTicks::default();
+        ticks.tick = 100;
+        let inside = BlockPosition::new(3, 64, 3);
+        let outside = BlockPosition::new(-3, 64, 3);
+        ticks.schedule_block_tick(inside, 5, 1);
+        ticks.schedule_block_tick(outside, 7, 0);
+
+        let chunk = ChunkPosition::new(0, 0);
+        let saved = ScheduledTick {
+            pos: inside,
+            delay: 5,
+            priority: 1,
+        };
+        assert_eq!(ticks.ticks_by_chunk()[&chunk], vec![saved]);
+        assert_eq!(ticks.remove_chunk_ticks(chunk), vec![saved]);
+        assert_eq!(ticks.scheduled_delay(inside), None);
+        assert_eq!(ticks.scheduled_delay(outside), Some(7));
+
+        // Removed ticks don't run.
+        ticks.tick = 110;
+        assert_eq!(ticks.take_due(10), vec![outside]);
+
+        ticks.load_chunk_ticks(vec![saved]);
+        assert_eq!(ticks.scheduled_delay(inside), Some(5));
+    }
+
+    #[test]
+    fn test_block_tick_system() {
+        let (mut w, mut d) = t::builder().with(BlockTickSystem, "").build();
+        t::populate_with_air(&mut w);
+        t::set_block(0, 64, 0, Block::Stone, &w);
+
+        w.fetch_mut::<BlockTicks>()
+            .schedule_block_tick(BlockPosition::new(0, 64, 0), 1, 0);
+        let mut reader = t::reader::<BlockTickEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+        assert!(t::triggered_events::<BlockTickEvent>(&w, &mut reader).is_empty());
+
+        w.fetch_mut::<TickCount>().0 = 1;
+        d.dispatch(&w);
+        w.maintain();
+        let events = t::triggered_events::<BlockTickEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].block, Block::Stone);
+    }
+}
@@ -0,0 +1,293 @@
+//! Cauldrons, which hold up to three levels of water.
+//!
+//! Players fill cauldrons with water buckets and water
+//! bottles, and empty them with buckets and glass bottles.
+//! A cauldron with some water also washes the dye off
+//! leather armor and the top pattern off banners.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::entity::PlayerComponent;
+use crate::player::{InventoryComponent, InventoryUpdateEvent};
+use crate::systems::CAULDRON_USE;
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::CauldronData;
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, Gamemode, Item, ItemStack};
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entity, Read, ReadStorage, System, Write, WriteStorage};
+
+/// The water level of a full cauldron.
+pub const MAX_LEVEL: i32 = 3;
+
+/// The potion held by water bottles.
+const WATER_POTION: &str = "minecraft:water";
+
+/// Event triggered when a player uses an item on a cauldron.
+#[derive(Debug, Clone)]
+pub struct CauldronUseEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+    /// The hand holding the item, as sent in
+    /// the Player Block Placement packet.
+    pub hand: i32,
+}
+
+/// Returns the water level of a cauldron and the item
+/// replacing one of the used items after using the given
+/// stack on a cauldron with the given level, or `None` if
+/// the item can't be used on the cauldron.
+pub fn use_item(level: i32, stack: &ItemStack) -> Option<(i32, ItemStack)> {
+    match stack.ty {
+        Item::WaterBucket if level < MAX_LEVEL => {
+            Some((MAX_LEVEL, ItemStack::new(Item::Bucket, 1)))
+        }
+        Item::Bucket if level == MAX_LEVEL => Some((0, ItemStack::new(Item::WaterBucket, 1))),
+        Item::GlassBottle if level > 0 => {
+            let mut bottle = ItemStack::new(Item::Potion, 1);
+            bottle.set_potion(Some(WATER_POTION.to_string()));
+            Some((level - 1, bottle))
+        }
+        Item::Potion if level < MAX_LEVEL && stack.potion() == Some(WATER_POTION) => {
+            Some((level + 1, ItemStack::new(Item::GlassBottle, 1)))
+        }
+        Item::LeatherHelmet
+        | Item::LeatherChestplate
+        | Item::LeatherLeggings
+        | Item::LeatherBoots
+            if level > 0 && stack.color().is_some() =>
+        {
+            let mut washed = stack.with_amount(1);
+            washed.set_color(None);
+            Some((level - 1, washed))
+        }
+        ty if level > 0 && is_banner(ty) && !stack.banner_patterns().is_empty() => {
+            let mut washed = stack.with_amount(1);
+            washed.pop_banner_pattern();
+            Some((level - 1, washed))
+        }
+        _ => None,
+    }
+}
+
+fn is_banner(item: Item) -> bool {
+    match item {
+        Item::WhiteBanner
+        | Item::OrangeBanner
+        | Item::MagentaBanner
+        | Item::LightBlueBanner
+        | Item::YellowBanner
+        | Item::LimeBanner
+        | Item::PinkBanner
+        | Item::GrayBanner
+        | Item::LightGrayBanner
+        | Item::CyanBanner
+        | Item::PurpleBanner
+        | Item::BlueBanner
+        | Item::BrownBanner
+        | Item::GreenBanner
+        | Item::RedBanner
+        | Item::BlackBanner => true,
+        _ => false,
+    }
+}
+
+/// System which fills and empties cauldrons
+/// when players use items on them.
+///
+/// This system listens to `CauldronUseEvent`s.
+#[derive(Default)]
+pub struct CauldronUseSystem {
+    reader: Option<ReaderId<CauldronUseEvent>>,
+}
+
+impl<'a> System<'a> for CauldronUseSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CauldronUseEvent>>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            mut inventories,
+            players,
+            mut chunk_map,
+            mut block_updates,
+            mut inventory_updates,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let player = event.player;
+            let old_level = match chunk_map.block_at(event.pos) {
+                Some(Block::Cauldron(data)) => data.level,
+                _ => continue,
+            };
+            let inventory = continue_if_none!(inventories.get_mut(player));
+            let slot = inventory.hand_slot(event.hand);
+            let stack = continue_if_none!(inventory.item_at(slot)).clone();
+            let (level, result) = continue_if_none!(use_item(old_level, &stack));
+
+            if level != old_level {
+                let old_block = Block::Cauldron(CauldronData { level: old_level });
+                let new_block = Block::Cauldron(CauldronData { level });
+                chunk_map.set_block_at(event.pos, new_block).unwrap();
+                block_updates.single_write(BlockUpdateEvent {
+                    cause: BlockUpdateCause::Player(player),
+                    pos: event.pos,
+                    old_block,
+                    new_block,
+                });
+            }
+
+            // As in vanilla, players in creative mode keep their
+            // buckets and bottles, but washed items are replaced.
+            let creative = players
+                .get(player)
+                .map_or(false, |player| player.gamemode == Gamemode::Creative);
+            if creative && result.ty != stack.ty {
+                continue;
+            }
+
+            let mut slots = smallvec![slot];
+            if stack.amount == 1 {
+                inventory.set_item_at(slot, result);
+            } else {
+                inventory.set_item_at(slot, stack.with_amount(stack.amount - 1));
+                let (affected, _) = inventory.collect_item(result);
+                slots.extend(affected);
+            }
+            inventory_updates.single_write(InventoryUpdateEvent { slots, player });
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(CauldronUseSystem::default(), CAULDRON_USE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+    use feather_core::item_tag::{BannerPattern, BlockEntityTag};
+    use specs::WorldExt;
+
+    #[test]
+    fn test_use_item() {
+        let water_bucket = ItemStack::new(Item::WaterBucket, 1);
+        assert_eq!(
+            use_item(1, &water_bucket),
+            Some((3, ItemStack::new(Item::Bucket, 1)))
+        );
+        assert_eq!(use_item(3, &water_bucket), None);
+
+        let bucket = ItemStack::new(Item::Bucket, 1);
+        assert_eq!(use_item(2, &bucket), None);
+        assert_eq!(
+            use_item(3, &bucket),
+            Some((0, ItemStack::new(Item::WaterBucket, 1)))
+        );
+
+        let (level, bottle) = use_item(2, &ItemStack::new(Item::GlassBottle, 4)).unwrap();
+        assert_eq!(level, 1);
+        assert_eq!(bottle.potion(), Some(WATER_POTION));
+        assert_eq!(
+            use_item(1, &bottle),
+            Some((2, ItemStack::new(Item::GlassBottle, 1)))
+        );
+        assert_eq!(use_item(0, &ItemStack::new(Item::GlassBottle, 1)), None);
+
+        let mut boots = ItemStack::new(Item::LeatherBoots, 1);
+        assert_eq!(use_item(3, &boots), None);
+        boots.set_color(Some(0x00_66_CC));
+        let (level, washed) = use_item(3, &boots).unwrap();
+        assert_eq!(level, 2);
+        assert_eq!(washed.color(), None);
+
+        assert_eq!(use_item(3, &ItemStack::new(Item::Stone, 1)), None);
+    }
+
+    #[test]
+    fn test_wash_banner() {
+        let mut banner = ItemStack::new(Item::RedBanner, 2);
+        assert_eq!(use_item(1, &banner), None);
+
+        let pattern = |code: &str| BannerPattern {
+            pattern: code.to_string(),
+            color: 15,
+        };
+        banner.tag_mut().block_entity_tag = Some(BlockEntityTag {
+            items: vec![],
+            patterns: vec![pattern("bs"), pattern("cr")],
+            other: Default::default(),
+        });
+
+        let (level, washed) = use_item(1, &banner).unwrap();
+        assert_eq!(level, 0);
+        assert_eq!(washed.amount, 1);
+        assert_eq!(washed.banner_patterns(), &[pattern("bs")][..]);
+        assert_eq!(use_item(0, &banner), None);
+    }
+
+    #[test]
+    fn test_fill_bottle() {
+        let (mut w, mut d) = t::builder().with(CauldronUseSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::GlassBottle, 2));
+
+        let pos = BlockPosition::new(0, 64, 0);
+        t::set_block(
+            pos.x,
+            pos.y,
+            pos.z,
+            Block::Cauldron(CauldronData { level: 3 }),
+            &w,
+        );
+
+        let mut reader = t::reader(&w);
+        t::trigger_event(
+            &w,
+            CauldronUseEvent {
+                player: player.entity,
+                pos,
+                hand: 0,
+            },
+        );
+        d.dispatch(&w);
+
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(pos),
+            Some(Block::Cauldron(CauldronData { level: 2 }))
+        );
+        assert_eq!(
+            t::triggered_events::<BlockUpdateEvent>(&w, &mut reader).len(),
+            1
+        );
+
+        let inventories = w.read_component::<InventoryComponent>();
+        let inventory = inventories.get(player.entity).unwrap();
+        assert_eq!(inventory.item_at(SLOT_HOTBAR_OFFSET).unwrap().amount, 1);
+        let potion = inventory
+            .items()
+            .iter()
+            .flatten()
+            .find(|stack| stack.ty == Item::Potion)
+            .unwrap();
+        assert_eq!(potion.potion(), Some(WATER_POTION));
+    }
+}
@@ -0,0 +1,364 @@
+//! Chests, which are containers of 27 slots. Two chests next
+//! to each other and facing the same way are connected into a
+//! double chest of 54 slots, which is opened in a single window.
+//!
+//! Each half of a double chest stores its own items in its block
+//! entity. The right half, seen from the front, holds the first
+//! 27 slots of the window. The lid of a chest is open while
+//! players view it, which is shown to players nearby using
+//! Block Action packets.
+
+use crate::blocks::{self, BlockUpdateCause, BlockUpdateEvent};
+use crate::container::{container_parts, ContainerKind, OpenContainerComponent};
+use crate::interact::broadcast_sound;
+use crate::redstone::facing;
+use crate::systems::{CHEST_CONNECT, CHEST_VIEWERS, CONTAINER_OPEN};
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use feather_blocks::{Block, ChestData, ChestType};
+use feather_core::network::packet::implementation::BlockAction;
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Join, Read, ReadStorage, System, Write};
+use std::collections::HashMap;
+
+/// The ID of chests in the block registry, used by `BlockAction`.
+const CHEST_ID: i32 = 142;
+/// The `BlockAction` action ID which sets the number of
+/// players viewing a chest.
+const ACTION_VIEWERS: u8 = 1;
+
+/// Returns the position of the other half of the
+/// double chest at the given position, if the given
+/// block is one half of a double chest.
+pub fn other_half(block: Block, pos: BlockPosition) -> Option<BlockPosition> {
+    let data = match block {
+        Block::Chest(data) => data,
+        _ => return None,
+    };
+    let facing = facing(block)?;
+    match data.ty {
+        ChestType::Left => Some(facing.clockwise().of(pos)),
+        ChestType::Right => Some(facing.clockwise().opposite().of(pos)),
+        ChestType::Single => None,
+    }
+}
+
+/// Returns the positions of the right and the left half of the
+/// double chest at the given position, or `None` if there is no
+/// double chest there.
+pub fn chest_halves(chunk_map: &ChunkMap, pos: BlockPosition) -> Option<[BlockPosition; 2]> {
+    let block = chunk_map.block_at(pos)?;
+    let other = other_half(block, pos)?;
+    if other_half(chunk_map.block_at(other)?, other) != Some(pos) {
+        return None;
+    }
+    match block {
+        Block::Chest(ChestData {
+            ty: ChestType::Right,
+            ..
+        }) => Some([pos, other]),
+        _ => Some([other, pos]),
+    }
+}
+
+fn with_type(data: ChestData, ty: ChestType) -> Block {
+    Block::Chest(ChestData { ty, ..data })
+}
+
+/// System which connects chests placed next to a chest
+/// facing the same way into a double chest, and which
+/// disconnects the remaining half of a broken double chest.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct ChestConnectSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for ChestConnectSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut chunk_map, mut events) = data;
+
+        let updates: Vec<BlockUpdateEvent> = events
+            .read(self.reader.as_mut().unwrap())
+            .cloned()
+            .collect();
+
+        for update in updates {
+            let pos = update.pos;
+            match (update.old_block, update.new_block) {
+                // Only the state of the chest changed
+                (Block::Chest(_), Block::Chest(_)) => (),
+                (old, _) if other_half(old, pos).is_some() => {
+                    let other = other_half(old, pos).unwrap();
+                    if let Some(Block::Chest(data)) = chunk_map.block_at(other) {
+                        if other_half(Block::Chest(data), other) == Some(pos) {
+                            let single = with_type(data, ChestType::Single);
+                            blocks::set_block(
+                                &mut chunk_map,
+                                &mut events,
+                                other,
+                                single,
+                                BlockUpdateCause::Chest,
+                            );
+                        }
+                    }
+                }
+                (
+                    _,
+                    Block::Chest(
+                        data @ ChestData {
+                            ty: ChestType::Single,
+                            ..
+                        },
+                    ),
+                ) => {
+                    let facing = continue_if_none!(facing(Block::Chest(data)));
+                    let sides = [
+                        (facing.clockwise(), ChestType::Left, ChestType::Right),
+                        (
+                            facing.clockwise().opposite(),
+                            ChestType::Right,
+                            ChestType::Left,
+                        ),
+                    ];
+                    for &(dir, ty, other_ty) in sides.iter() {
+                        let side = dir.of(pos);
+                        let other = match chunk_map.block_at(side) {
+                            Some(Block::Chest(other)) => other,
+                            _ => continue,
+                        };
+                        if other.ty != ChestType::Single || other.facing != data.facing {
+                            continue;
+                        }
+
+                        let other = with_type(other, other_ty);
+                        blocks::set_block(
+                            &mut chunk_map,
+                            &mut events,
+                            pos,
+                            with_type(data, ty),
+                            BlockUpdateCause::Chest,
+                        );
+                        blocks::set_block(
+                            &mut chunk_map,
+                            &mut events,
+                            side,
+                            other,
+                            BlockUpdateCause::Chest,
+                        );
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which opens and closes the lids of chests
+/// as players start and stop viewing them.
+#[derive(Default)]
+pub struct ChestViewerSystem {
+    /// The number of players viewing each chest,
+    /// as last sent to players.
+    viewers: HashMap<BlockPosition, usize>,
+}
+
+impl<'a> System<'a> for ChestViewerSystem {
+    type SystemData = (
+        Read<'a, ChunkMap>,
+        ReadStorage<'a, OpenContainerComponent>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (chunk_map, open_containers, util) = data;
+
+        let mut viewers = HashMap::new();
+        for open in open_containers.join() {
+            if open.kind == ContainerKind::Chest {
+                *viewers.entry(open.pos).or_insert(0) += 1;
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut changes: Vec<(BlockPosition, usize, usize)> = viewers
+            .iter()
+            .map(|(&pos, &count)| (pos, self.viewers.get(&pos).copied().unwrap_or(0), count))
+            .collect();
+        changes.extend(
+            self.viewers
+                .iter()
+                .filter(|(pos, _)| !viewers.contains_key(pos))
+                .map(|(&pos, &old_count)| (pos, old_count, 0)),
+        );
+        for (pos, old_count, count) in changes {
+            if count == old_count {
+                continue;
+            }
+            // The chest was broken
+            if chunk_map.block_at(pos).and_then(ContainerKind::of) != Some(ContainerKind::Chest) {
+                continue;
+            }
+
+            for part in container_parts(&chunk_map, pos) {
+                let packet = BlockAction::new(part, ACTION_VIEWERS, count.min(255) as u8, CHEST_ID);
+                util.broadcast_chunk_update(part.chunk_pos(), packet, None);
+            }
+
+            let sound = if old_count == 0 {
+                "block.chest.open"
+            } else if count == 0 {
+                "block.chest.close"
+            } else {
+                continue;
+            };
+            broadcast_sound(&util, pos, sound, 0.5, rng.gen_range(0.9, 1.0), None);
+        }
+
+        self.viewers = viewers;
+    }
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ChestConnectSystem::default(), CHEST_CONNECT, &[]);
+    dispatcher.add_timed(
+        ChestViewerSystem::default(),
+        CHEST_VIEWERS,
+        &[CONTAINER_OPEN],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::CHEST_SIZE;
+    use crate::testframework as t;
+    use feather_blocks::ChestFacing;
+    use feather_core::network::cast_packet;
+    use feather_core::PacketType;
+    use specs::{World, WorldExt};
+
+    fn chest(ty: ChestType) -> Block {
+        Block::Chest(ChestData {
+            facing: ChestFacing::South,
+            ty,
+            waterlogged: false,
+        })
+    }
+
+    #[test]
+    fn test_chest_halves() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+
+        // Facing south, the left half is on the east side.
+        t::set_block(1, 64, 0, chest(ChestType::Left), &w);
+        t::set_block(0, 64, 0, chest(ChestType::Right), &w);
+        t::set_block(5, 64, 0, chest(ChestType::Left), &w);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        let halves = Some([BlockPosition::new(0, 64, 0), BlockPosition::new(1, 64, 0)]);
+        assert_eq!(
+            chest_halves(&chunk_map, BlockPosition::new(0, 64, 0)),
+            halves
+        );
+        assert_eq!(
+            chest_halves(&chunk_map, BlockPosition::new(1, 64, 0)),
+            halves
+        );
+        assert_eq!(chest_halves(&chunk_map, BlockPosition::new(5, 64, 0)), None);
+    }
+
+    #[test]
+    fn test_connect_and_disconnect() {
+        let (mut w, mut d) = t::builder().with(ChestConnectSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        let place = |w: &World, x: i32, old_block: Block, new_block: Block| {
+            t::set_block(x, 64, 0, new_block, w);
+            t::trigger_event(
+                w,
+                BlockUpdateEvent {
+                    cause: BlockUpdateCause::Test,
+                    pos: BlockPosition::new(x, 64, 0),
+                    old_block,
+                    new_block,
+                },
+            );
+        };
+        let block_at = |w: &World, x: i32| {
+            w.fetch::<ChunkMap>()
+                .block_at(BlockPosition::new(x, 64, 0))
+                .unwrap()
+        };
+
+        place(&w, 0, Block::Air, chest(ChestType::Single));
+        d.dispatch(&w);
+        place(&w, 1, Block::Air, chest(ChestType::Single));
+        d.dispatch(&w);
+        assert_eq!(block_at(&w, 0), chest(ChestType::Right));
+        assert_eq!(block_at(&w, 1), chest(ChestType::Left));
+
+        // A third chest stays single.
+        place(&w, 2, Block::Air, chest(ChestType::Single));
+        d.dispatch(&w);
+        assert_eq!(block_at(&w, 2), chest(ChestType::Single));
+
+        place(&w, 0, chest(ChestType::Right), Block::Air);
+        d.dispatch(&w);
+        assert_eq!(block_at(&w, 1), chest(ChestType::Single));
+    }
+
+    #[test]
+    fn test_viewers() {
+        let (mut w, mut d) = t::builder().with(ChestViewerSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        let other = t::add_player(&mut w);
+
+        let pos = BlockPosition::new(0, 64, 0);
+        t::set_block(0, 64, 0, chest(ChestType::Single), &w);
+        w.write_component::<OpenContainerComponent>()
+            .insert(
+                player.entity,
+                OpenContainerComponent {
+                    pos,
+                    kind: ContainerKind::Chest,
+                    size: CHEST_SIZE,
+                    cursor: None,
+                    slots: vec![],
+                },
+            )
+            .unwrap();
+        d.dispatch(&w);
+        w.maintain();
+
+        t::assert_packet_received(&other, PacketType::BlockAction);
+        t::assert_packet_received(&other, PacketType::NamedSoundEffect);
+
+        // Nothing is sent while the number of viewers stays the same.
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_packet_not_received(&other, PacketType::BlockAction);
+
+        w.write_component::<OpenContainerComponent>()
+            .remove(player.entity);
+        d.dispatch(&w);
+        w.maintain();
+        let packet = t::assert_packet_received(&other, PacketType::BlockAction);
+        let packet = cast_packet::<BlockAction>(&*packet);
+        assert_eq!(packet.action_param, 0);
+    }
+}
@@ -6,6 +6,7 @@ use crossbeam::channel::{Receiver, Sender};
 use shrev::{EventChannel, ReaderId};
 use specs::{
     Component, DispatcherBuilder, Entity, Read, ReadExpect, ReadStorage, System, World, Write,
+    WriteExpect,
 };
 use std::sync::atomic::{AtomicU32, Ordering};
 
@@ -13,11 +14,15 @@ use feather_core::world::{ChunkMap, ChunkPosition};
 
 use rayon::prelude::*;
 
+use crate::blocks::BlockTicks;
 use crate::config::Config;
+use crate::dimension::{DimensionComponent, Dimensions, PRIMARY_DIMENSION};
 use crate::entity::EntityDestroyEvent;
+use crate::metrics::METRICS;
 use crate::systems::{CHUNK_HOLD_REMOVE, CHUNK_LOAD, CHUNK_OPTIMIZE, CHUNK_UNLOAD};
+use crate::timings::DispatcherBuilderExt;
 use crate::worldgen::WorldGenerator;
-use crate::{chunkworker, current_time_in_millis, TickCount, TPS};
+use crate::{chunkworker, current_time_in_millis, TickCount, TICK_TIME, TPS};
 use feather_core::entity::EntityData;
 use feather_core::Chunk;
 use hashbrown::HashSet;
@@ -63,16 +68,22 @@ impl<'a> System<'a> for ChunkLoadSystem {
         Write<'a, ChunkMap>,
         Write<'a, EventChannel<ChunkLoadEvent>>,
         Write<'a, EventChannel<ChunkLoadFailEvent>>,
+        Write<'a, BlockTicks>,
         ReadExpect<'a, ChunkWorkerHandle>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut chunk_map, mut load_events, mut fail_events, handle) = data;
+        let (mut chunk_map, mut load_events, mut fail_events, mut block_ticks, handle) = data;
 
         while let Ok(reply) = handle.receiver.try_recv() {
             if let chunkworker::Reply::LoadedChunk(pos, result) = reply {
+                METRICS.pending_chunk_loads.fetch_sub(1, Ordering::Relaxed);
                 match result {
-                    Ok((chunk, entities)) => {
+                    Ok((mut chunk, entities)) => {
+                        // Clients receive the light of the whole
+                        // chunk when it is sent to them.
+                        chunk.take_dirty_light();
+                        block_ticks.load_chunk_ticks(chunk.take_scheduled_ticks());
                         chunk_map.set_chunk_at(pos, chunk);
 
                         // Trigger event
@@ -98,8 +109,13 @@ impl<'a> System<'a> for ChunkLoadSystem {
         let world_name = &world.fetch_mut::<Arc<Config>>().world.name.clone();
         let world_dir = Path::new(world_name);
 
+        let has_skylight = world
+            .fetch::<Dimensions>()
+            .get(PRIMARY_DIMENSION)
+            .map_or(true, |settings| settings.has_skylight);
+
         info!("Starting chunk worker thread");
-        let (sender, receiver) = chunkworker::start(world_dir, generator);
+        let (sender, receiver) = chunkworker::start(world_dir, generator, has_skylight);
         world.insert(ChunkWorkerHandle { sender, receiver });
 
         Self::SystemData::setup(world);
@@ -113,6 +129,8 @@ impl<'a> System<'a> for ChunkLoadSystem {
 /// In the event that the requested chunk does not exist
 /// in the world save, it will be generated asynchronously.
 pub fn load_chunk(handle: &ChunkWorkerHandle, pos: ChunkPosition) {
+    METRICS.pending_chunk_loads.fetch_add(1, Ordering::Relaxed);
+
     // Send request to chunk worker thread
     handle
         .sender
@@ -139,9 +157,13 @@ pub fn save_chunk(handle: &ChunkWorkerHandle, chunk: Arc<Chunk>, entities: Vec<E
 /// the movement, while other players would be outside of the view
 /// distance. This technique allows for higher performance and
 /// avoids constant nearby entity queries.
+///
+/// Chunks can also be forced to stay loaded without an
+/// entity holding them, for example by plugins.
 #[derive(Default, Clone, Debug)]
 pub struct ChunkHolders {
     inner: MultiMap<ChunkPosition, Entity>,
+    forced: HashSet<ChunkPosition>,
 }
 
 impl ChunkHolders {
@@ -150,11 +172,58 @@ impl ChunkHolders {
     }
 
     pub fn chunk_has_holders(&self, chunk: ChunkPosition) -> bool {
+        if self.forced.contains(&chunk) {
+            return true;
+        }
+
         let holders = self.holders_for(chunk);
 
         !(holders.is_none() || holders.unwrap().is_empty())
     }
 
+    /// Returns the number of chunks which have at
+    /// least one holder or are forced to stay loaded.
+    pub fn held_chunk_count(&self) -> usize {
+        let held = self
+            .inner
+            .iter_all()
+            .filter(|(_, holders)| !holders.is_empty())
+            .count();
+        let forced_only = self
+            .forced
+            .iter()
+            .filter(|chunk| self.holders_for(**chunk).map_or(true, |h| h.is_empty()))
+            .count();
+        held + forced_only
+    }
+
+    /// Forces the given chunk to stay loaded until
+    /// `release_forced` is called. This doesn't load
+    /// the chunk if it isn't loaded already.
+    pub fn force(&mut self, chunk: ChunkPosition) {
+        self.forced.insert(chunk);
+    }
+
+    /// Stops forcing the given chunk to stay loaded.
+    /// It is unloaded once it has no other holders.
+    pub fn release_forced(
+        &mut self,
+        chunk: ChunkPosition,
+        events: &mut EventChannel<ChunkHolderReleaseEvent>,
+    ) {
+        if self.forced.remove(&chunk) {
+            events.single_write(ChunkHolderReleaseEvent {
+                entity: None,
+                chunk,
+            });
+        }
+    }
+
+    /// Returns the chunks which are forced to stay loaded.
+    pub fn forced_chunks(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.forced.iter().copied()
+    }
+
     pub fn insert_holder(&mut self, chunk: ChunkPosition, holder: Entity) {
         self.inner.insert(chunk, holder);
     }
@@ -172,7 +241,7 @@ impl ChunkHolders {
 
                 // Trigger event
                 let event = ChunkHolderReleaseEvent {
-                    entity: holder,
+                    entity: Some(holder),
                     chunk,
                 };
                 events.single_write(event);
@@ -184,8 +253,9 @@ impl ChunkHolders {
 /// Event triggered when a chunk holder is released.
 #[derive(Clone, Debug)]
 pub struct ChunkHolderReleaseEvent {
-    /// The entity which previously held the chunk.
-    pub entity: Entity,
+    /// The entity which previously held the chunk,
+    /// or `None` if the chunk was forced to stay loaded.
+    pub entity: Option<Entity>,
     /// The chunk which the holder was released from.
     pub chunk: ChunkPosition,
 }
@@ -198,6 +268,47 @@ pub struct ChunkUnloadQueue {
     queue: VecDeque<ChunkUnload>,
 }
 
+impl ChunkUnloadQueue {
+    /// Returns the number of chunks queued for unloading.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues a chunk to be unloaded at the given tick count.
+    pub fn push(&mut self, chunk: ChunkPosition, time: u64) {
+        self.queue.push_back(ChunkUnload { chunk, time });
+    }
+
+    /// Removes and returns the next chunk which is due to be
+    /// unloaded at the given tick count, skipping chunks
+    /// which have gained new holders since they were queued.
+    pub fn pop_due(&mut self, tick: u64, holders: &ChunkHolders) -> Option<ChunkPosition> {
+        // Since chunks are queued in the back and taken out
+        // from the front, the chunks in the front of the queue
+        // were queued the longest time ago. If the front chunk
+        // isn't due yet, none of the chunks behind it are either.
+        while let Some(unload) = self.queue.front() {
+            if tick < unload.time {
+                return None;
+            }
+
+            let chunk = unload.chunk;
+            self.queue.pop_front();
+
+            // Don't unload if new chunk holders have appeared.
+            if !holders.chunk_has_holders(chunk) {
+                return Some(chunk);
+            }
+        }
+
+        None
+    }
+}
+
 /// A chunk to be unloaded.
 #[derive(Clone, Debug, Default)]
 struct ChunkUnload {
@@ -207,17 +318,13 @@ struct ChunkUnload {
     time: u64,
 }
 
-/// The amount of time, in ticks, between the time
-/// a chunk is queued for unloading and when it is unloaded.
-const CHUNK_UNLOAD_TIME: u64 = TPS * 5; // 5 seconds - TODO make this configurable
-
 /// System for unloading chunks when they have no holders.
 /// This system performs multiple actions:
 ///
 /// * It listens to `ChunkHolderReleaseEvent` and
 /// checks if a chunk has no holders. If so, it queues
 /// the chunk to be unloaded after some period of time
-/// (defined by a constant).
+/// (defined by `world.chunk_unload_delay` in the config).
 /// * It goes through chunks which are currently
 /// queued to be loaded and unloads them if the
 /// period of time has elapsed.
@@ -246,7 +353,9 @@ impl<'a> System<'a> for ChunkUnloadSystem {
         Read<'a, EventChannel<ChunkHolderReleaseEvent>>,
         Write<'a, ChunkUnloadQueue>,
         Read<'a, ChunkHolders>,
+        Write<'a, BlockTicks>,
         Read<'a, TickCount>,
+        Read<'a, Arc<Config>>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -256,50 +365,29 @@ impl<'a> System<'a> for ChunkUnloadSystem {
             release_events,
             mut unload_queue,
             holders,
+            mut block_ticks,
             tick_count,
+            config,
         ) = data;
+        let unload_delay = config.world.chunk_unload_delay.as_millis() as u64 / TICK_TIME;
 
         // Handle holder release events.
         for event in release_events.read(&mut self.reader.as_mut().unwrap()) {
             // If the chunk now has zero holders, queue it for unloading.
             if !holders.chunk_has_holders(event.chunk) {
-                let unload = ChunkUnload {
-                    chunk: event.chunk,
-                    time: tick_count.0 + CHUNK_UNLOAD_TIME,
-                };
-                unload_queue.queue.push_back(unload);
+                unload_queue.push(event.chunk, tick_count.0 + unload_delay);
             }
         }
 
         // Unload chunks which are finished in the queue.
-
-        // Since chunks are queued in the back and taken out
-        // from the front, the chunks in the front of the vector
-        // were queued the longest time ago. Because of this,
-        // we go through the unloads in the front of the queue
-        // to find which chunks to unload.
-        while let Some(unload) = unload_queue.queue.front() {
-            if tick_count.0 >= unload.time {
-                // Don't unload if new chunk holders have appeared.
-                if holders.chunk_has_holders(unload.chunk) {
-                    unload_queue.queue.pop_front();
-                    continue;
-                }
-
-                // Unload chunk and pop from queue.
-                if let Some(chunk) = chunk_map.unload_chunk_at(unload.chunk) {
-                    let event = ChunkUnloadEvent {
-                        chunk: Arc::new(chunk),
-                    };
-                    unload_events.single_write(event);
-                }
-
-                unload_queue.queue.pop_front();
-            } else {
-                // We're done - all chunks farther up in
-                // the queue were queued before this one,
-                // so it isn't time to unload any of those.
-                break;
+        while let Some(pos) = unload_queue.pop_due(tick_count.0, &holders) {
+            if let Some(mut chunk) = chunk_map.unload_chunk_at(pos) {
+                // The scheduled ticks are saved with the chunk.
+                chunk.set_scheduled_ticks(block_ticks.remove_chunk_ticks(pos));
+                let event = ChunkUnloadEvent {
+                    chunk: Arc::new(chunk),
+                };
+                unload_events.single_write(event);
             }
         }
     }
@@ -361,18 +449,32 @@ impl<'a> System<'a> for ChunkHoldRemoveSystem {
         Write<'a, ChunkHolders>,
         ReadStorage<'a, ChunkHolderComponent>,
         Write<'a, EventChannel<ChunkHolderReleaseEvent>>,
+        WriteExpect<'a, Dimensions>,
+        ReadStorage<'a, DimensionComponent>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (events, mut holders, holder_comps, mut release_events) = data;
+        let (
+            events,
+            mut holders,
+            holder_comps,
+            mut release_events,
+            mut dimensions,
+            dimension_comps,
+        ) = data;
 
         for event in events.read(&mut self.reader.as_mut().unwrap()) {
             // If entity had chunk holds, remove them all
             if let Some(holder_comp) = holder_comps.get(event.entity) {
                 debug!("Removing chunk holds for entity {:?}", event.entity);
-                holder_comp.holds.iter().for_each(|chunk| {
-                    holders.remove_holder(*chunk, event.entity, &mut release_events);
-                });
+                let dimension = DimensionComponent::of(dimension_comps.get(event.entity));
+                holder_comp
+                    .holds
+                    .iter()
+                    .for_each(|chunk| match dimensions.world_mut(dimension) {
+                        Some(world) => world.remove_holder(*chunk, event.entity),
+                        None => holders.remove_holder(*chunk, event.entity, &mut release_events),
+                    });
             }
         }
     }
@@ -444,21 +546,21 @@ impl<'a> System<'a> for ChunkOptimizeSystem {
 }
 
 pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(ChunkLoadSystem, CHUNK_LOAD, &[]);
-    dispatcher.add(ChunkOptimizeSystem, CHUNK_OPTIMIZE, &[]);
+    dispatcher.add_timed(ChunkLoadSystem, CHUNK_LOAD, &[]);
+    dispatcher.add_timed(ChunkOptimizeSystem, CHUNK_OPTIMIZE, &[]);
 }
 
 pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(ChunkUnloadSystem::default(), CHUNK_UNLOAD, &[]);
-    dispatcher.add(ChunkHoldRemoveSystem::default(), CHUNK_HOLD_REMOVE, &[]);
+    dispatcher.add_timed(ChunkUnloadSystem::default(), CHUNK_UNLOAD, &[]);
+    dispatcher.add_timed(ChunkHoldRemoveSystem::default(), CHUNK_HOLD_REMOVE, &[]);
 }
 
 #[cfg(test)]
 mod tests {
     use specs::{RunNow, World, WorldExt};
 
-    use feather_core::world::chunk::Chunk;
-    use feather_core::world::ChunkPosition;
+    use feather_core::world::chunk::{Chunk, ScheduledTick};
+    use feather_core::world::{BlockPosition, ChunkPosition};
 
     use super::*;
 
@@ -473,11 +575,15 @@ mod tests {
 
         let chunk_map = ChunkMap::new();
         let pos = ChunkPosition::new(0, 0);
+        let tick_pos = BlockPosition::new(1, 64, 1);
+        let mut chunk = Chunk::new(pos);
+        chunk.set_scheduled_ticks(vec![ScheduledTick {
+            pos: tick_pos,
+            delay: 3,
+            priority: 0,
+        }]);
         send2
-            .send(chunkworker::Reply::LoadedChunk(
-                pos,
-                Ok((Chunk::new(pos), vec![])),
-            ))
+            .send(chunkworker::Reply::LoadedChunk(pos, Ok((chunk, vec![]))))
             .unwrap();
 
         let load_event_channel = EventChannel::<ChunkLoadEvent>::new();
@@ -489,6 +595,7 @@ mod tests {
         world.insert(handle);
         world.insert(load_event_channel);
         world.insert(fail_event_channel);
+        world.insert(BlockTicks::default());
 
         system.run_now(&world);
 
@@ -498,6 +605,13 @@ mod tests {
 
         assert!(chunk.is_some());
         assert!(chunk.unwrap().position() == pos);
+
+        // The ticks saved with the chunk are scheduled again.
+        assert!(chunk.unwrap().scheduled_ticks().is_empty());
+        assert_eq!(
+            world.fetch::<BlockTicks>().scheduled_delay(tick_pos),
+            Some(3)
+        );
     }
 
     #[test]
@@ -519,4 +633,51 @@ mod tests {
             _ => panic!(),
         }
     }
+
+    #[test]
+    fn test_forced_chunk_unload() {
+        use crate::testframework as t;
+
+        let (mut w, mut d) = t::builder().with(ChunkUnloadSystem::default(), "").build();
+        let mut config = Config::default();
+        config.world.chunk_unload_delay = std::time::Duration::from_secs(0);
+        w.insert(Arc::new(config));
+
+        let pos = ChunkPosition::new(3, 4);
+        w.fetch_mut::<ChunkMap>().set_chunk_at(pos, Chunk::new(pos));
+        let tick_pos = BlockPosition::new(50, 64, 70);
+        w.fetch_mut::<BlockTicks>()
+            .schedule_block_tick(tick_pos, 10, 0);
+        w.fetch_mut::<ChunkHolders>().force(pos);
+        assert_eq!(w.fetch::<ChunkHolders>().held_chunk_count(), 1);
+        let mut reader = t::reader::<ChunkUnloadEvent>(&w);
+
+        // Forced chunks stay loaded without other holders.
+        t::trigger_event(
+            &w,
+            ChunkHolderReleaseEvent {
+                entity: None,
+                chunk: pos,
+            },
+        );
+        d.dispatch(&w);
+        assert!(w.fetch::<ChunkMap>().chunk_at(pos).is_some());
+        assert!(w.fetch::<ChunkUnloadQueue>().is_empty());
+
+        w.fetch_mut::<ChunkHolders>().release_forced(
+            pos,
+            &mut w.fetch_mut::<EventChannel<ChunkHolderReleaseEvent>>(),
+        );
+        assert_eq!(w.fetch::<ChunkHolders>().held_chunk_count(), 0);
+        d.dispatch(&w);
+        assert!(w.fetch::<ChunkMap>().chunk_at(pos).is_none());
+        let unloaded = t::triggered_events(&w, &mut reader);
+        assert_eq!(unloaded.len(), 1);
+        assert_eq!(unloaded[0].chunk.position(), pos);
+
+        // Scheduled ticks are saved with the unloaded chunk.
+        assert_eq!(unloaded[0].chunk.scheduled_ticks().len(), 1);
+        assert_eq!(unloaded[0].chunk.scheduled_ticks()[0].pos, tick_pos);
+        assert_eq!(w.fetch::<BlockTicks>().scheduled_delay(tick_pos), None);
+    }
 }
@@ -2,13 +2,18 @@
 //! of chunks. It receives load and save requests from the server
 //! (over a channel) and executes them.
 //!
-//! If a chunk cannot be loaded, it is generated on the Rayon thread pool
-//! instead.
+//! Region files are read on the worker thread, but chunks are
+//! deserialized on the Rayon thread pool, straight from the memory
+//! map of the region file, so a burst of load requests
+//! (for example when a player joins or travels quickly) is parsed in
+//! parallel. If a chunk cannot be loaded, it is generated on the Rayon
+//! thread pool instead. Replies may thus arrive in any order.
+use crate::lighting;
 use crate::worldgen::WorldGenerator;
 use crossbeam::channel::{Receiver, Sender};
 use feather_core::entity::EntityData;
 use feather_core::region;
-use feather_core::region::{RegionHandle, RegionPosition};
+use feather_core::region::{ChunkData, RegionHandle, RegionPosition};
 use feather_core::world::chunk::Chunk;
 use feather_core::world::ChunkPosition;
 use hashbrown::HashMap;
@@ -20,6 +25,9 @@ use std::time::{SystemTime, UNIX_EPOCH};
 pub enum Reply {
     LoadedChunk(ChunkPosition, Result<(Chunk, Vec<EntityData>), Error>),
     SavedChunk(ChunkPosition),
+    /// Sent in response to `Request::ShutDown` once
+    /// all previous requests have been handled.
+    ShutDown,
 }
 
 #[derive(Clone)]
@@ -82,6 +90,9 @@ struct ChunkWorker {
 
     /// World generator for new chunks.
     world_generator: Arc<dyn WorldGenerator>,
+    /// Whether the world has sky light. If not,
+    /// the sky light of loaded chunks is cleared.
+    has_skylight: bool,
 }
 
 /// Starts a chunk worker on a new thread.
@@ -90,6 +101,7 @@ struct ChunkWorker {
 pub fn start(
     world_dir: &Path,
     world_gen: Arc<dyn WorldGenerator>,
+    has_skylight: bool,
 ) -> (Sender<Request>, Receiver<Reply>) {
     let (request_tx, request_rx) = crossbeam::channel::unbounded();
     let (reply_tx, reply_rx) = crossbeam::channel::unbounded();
@@ -100,6 +112,7 @@ pub fn start(
         receiver: request_rx,
         open_regions: HashMap::new(),
         world_generator: world_gen,
+        has_skylight,
     };
 
     // Without changing the stack size,
@@ -131,6 +144,10 @@ fn run(mut worker: ChunkWorker) {
         }
     }
 
+    // Close region files before acknowledging the shutdown.
+    worker.open_regions.clear();
+    let _ = worker.sender.send(Reply::ShutDown);
+
     info!("Chunk worker terminating");
 }
 
@@ -145,22 +162,31 @@ fn load_chunk(worker: &mut ChunkWorker, pos: ChunkPosition) -> Option<Reply> {
         &mut file.handle,
         &Arc::from(worker.sender.clone()),
         &worker.world_generator,
+        worker.has_skylight,
     )
 }
 
+/// Reads the chunk at the specified position from the given
+/// region file. The chunk is parsed on the Rayon thread pool,
+/// which sends the reply, so the worker can move on to the
+/// next request while the chunk is deserialized.
 fn load_chunk_from_handle(
     pos: ChunkPosition,
     handle: &mut RegionHandle,
     sender: &Arc<Sender<Reply>>,
     generator: &Arc<dyn WorldGenerator>,
+    has_skylight: bool,
 ) -> Option<Reply> {
-    let result = handle.load_chunk(pos);
+    let result = handle.read_chunk(pos);
 
     match result {
-        Ok(chunk) => Some(Reply::LoadedChunk(pos, Ok(chunk))),
+        Ok(data) => {
+            schedule_parse_chunk(sender, pos, data, has_skylight);
+            None
+        }
         Err(e) => match e {
             region::Error::ChunkNotExist => {
-                schedule_generate_new_chunk(sender, pos, generator);
+                schedule_generate_new_chunk(sender, pos, generator, has_skylight);
                 None
             }
             err => Some(Reply::LoadedChunk(pos, Err(Error::LoadError(err)))),
@@ -168,24 +194,62 @@ fn load_chunk_from_handle(
     }
 }
 
+/// Parses the data of a chunk read from a region file
+/// asynchronously, sending the result to the provided Sender.
+fn schedule_parse_chunk(
+    sender: &Arc<Sender<Reply>>,
+    pos: ChunkPosition,
+    data: ChunkData,
+    has_skylight: bool,
+) {
+    let sender = sender.clone();
+    rayon::spawn(move || {
+        let reply = parse_chunk(pos, &data, has_skylight);
+        sender.send(reply).unwrap();
+    });
+}
+
+/// Parses the data of a chunk read from a region file
+/// synchronously, returning a Reply to send to a Sender.
+fn parse_chunk(pos: ChunkPosition, data: &[u8], has_skylight: bool) -> Reply {
+    let result = region::parse_chunk(pos, data)
+        .map(|(mut chunk, entities)| {
+            // Chunks imported from other worlds may have sky light.
+            if !has_skylight {
+                lighting::clear_sky_light(&mut chunk);
+            }
+            (chunk, entities)
+        })
+        .map_err(Error::LoadError);
+    Reply::LoadedChunk(pos, result)
+}
+
 /// Generates a new chunk asynchronously,
 /// sending the result to the provided Sender.
 fn schedule_generate_new_chunk(
     sender: &Arc<Sender<Reply>>,
     pos: ChunkPosition,
     generator: &Arc<dyn WorldGenerator>,
+    has_skylight: bool,
 ) {
     let sender = sender.clone();
     let generator = Arc::clone(generator);
     rayon::spawn(move || {
-        sender.send(generate_new_chunk(pos, &generator)).unwrap();
+        let reply = generate_new_chunk(pos, &generator, has_skylight);
+        sender.send(reply).unwrap();
     });
 }
 
 /// Generates a new chunk synchronously,
 /// returning a Reply to send to a Sender.
-fn generate_new_chunk(pos: ChunkPosition, generator: &Arc<dyn WorldGenerator>) -> Reply {
-    Reply::LoadedChunk(pos, Ok((generator.generate_chunk(pos), vec![])))
+fn generate_new_chunk(
+    pos: ChunkPosition,
+    generator: &Arc<dyn WorldGenerator>,
+    has_skylight: bool,
+) -> Reply {
+    let mut chunk = generator.generate_chunk(pos);
+    lighting::calculate_light(&mut chunk, has_skylight);
+    Reply::LoadedChunk(pos, Ok((chunk, vec![])))
 }
 
 /// Saves the chunk at the specified position.
@@ -0,0 +1,109 @@
+//! Scheduling of ticks in the main loop.
+//!
+//! Ticks are scheduled at fixed intervals of `TICK_TIME`
+//! milliseconds. When a tick overruns its slot, the following
+//! ticks run immediately until the server has caught up, so
+//! that the number of ticks per second stays at `TPS` despite
+//! occasional long ticks. After a stall of more than
+//! `MAX_CATCH_UP_TICKS` ticks, however, catching up would keep
+//! the server busy for a long time; the missed ticks are skipped
+//! instead and a warning is logged.
+
+use crate::TICK_TIME;
+use std::time::{Duration, Instant};
+
+/// The maximum number of missed ticks which are run
+/// to catch up after a long tick: one second's worth.
+pub const MAX_CATCH_UP_TICKS: u64 = 20;
+
+/// The nominal duration of a tick.
+const TICK_DURATION: Duration = Duration::from_millis(TICK_TIME);
+
+/// What the main loop should do after a tick has run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextTick {
+    /// Sleep for the given duration before running the next tick.
+    Sleep(Duration),
+    /// Run the next tick immediately to catch up.
+    CatchUp,
+    /// The server is too far behind to catch up. The given
+    /// number of ticks are skipped and the next tick runs immediately.
+    Skip(u64),
+}
+
+/// Keeps track of when the next tick is due.
+#[derive(Debug, Clone)]
+pub struct TickClock {
+    /// The time at which the next tick should start.
+    next_tick: Instant,
+}
+
+impl TickClock {
+    /// Creates a clock whose first tick is due at `now`.
+    pub fn new(now: Instant) -> Self {
+        Self { next_tick: now }
+    }
+
+    /// Advances the clock after a tick has finished
+    /// at `now`, returning when to run the next tick.
+    pub fn advance(&mut self, now: Instant) -> NextTick {
+        self.next_tick += TICK_DURATION;
+
+        if now < self.next_tick {
+            return NextTick::Sleep(self.next_tick - now);
+        }
+
+        // The number of tick slots which have passed
+        // in addition to the one which is due now.
+        let behind = ((now - self.next_tick).as_nanos() / TICK_DURATION.as_nanos()) as u64;
+
+        if behind >= MAX_CATCH_UP_TICKS {
+            self.next_tick = now;
+            NextTick::Skip(behind)
+        } else {
+            NextTick::CatchUp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_clock() {
+        let start = Instant::now();
+        let mut clock = TickClock::new(start);
+
+        // Short tick
+        assert_eq!(
+            clock.advance(start + Duration::from_millis(10)),
+            NextTick::Sleep(Duration::from_millis(40))
+        );
+
+        // The ticks due at 100, 150 and 200 ms run immediately.
+        let now = start + Duration::from_millis(200);
+        assert_eq!(clock.advance(now), NextTick::CatchUp);
+        assert_eq!(clock.advance(now), NextTick::CatchUp);
+        assert_eq!(clock.advance(now), NextTick::CatchUp);
+        assert_eq!(
+            clock.advance(now),
+            NextTick::Sleep(Duration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn test_tick_clock_skip() {
+        let start = Instant::now();
+        let mut clock = TickClock::new(start);
+
+        let now = start + Duration::from_secs(5);
+        assert_eq!(clock.advance(now), NextTick::Skip(99));
+
+        // The clock restarts from the stall.
+        assert_eq!(
+            clock.advance(now + Duration::from_millis(20)),
+            NextTick::Sleep(Duration::from_millis(30))
+        );
+    }
+}
@@ -0,0 +1,1086 @@
+//! Health, damage and death of mobs and players.
+//!
+//! Players attack mobs and other players by left-clicking them within
+//! `REACH` blocks. The damage of an attack depends on the held item,
+//! including its sharpness level; see `attack_damage`. Attacks knock
+//! the entity back and wear down the weapon. Attack cooldowns, critical
+//! hits and sweeping attacks aren't implemented yet. Arrows damage the
+//! entities they hit depending on their speed, and burning entities
+//! take damage every second.
+//!
+//! Damage is dealt by writing a `DamageEvent`. The `DamageSystem`
+//! emits an `EntityDamageEvent` on the `EventBus` before applying
+//! it, so that plugins can change or cancel it. After being hurt,
+//! an entity is invulnerable for `INVULNERABILITY_TICKS` ticks,
+//! during which only the part of an attack exceeding the previous
+//! damage is applied, as in vanilla.
+//!
+//! Mobs whose health reaches zero play their death animation, drop
+//! the loot from `minecraft:entities/<type>` and are removed
+//! `DEATH_TICKS` ticks later.
+//!
+//! Players in creative and spectator mode take no damage. The health
+//! of other players is kept in sync with their `PlayerStatsComponent`,
+//! so that it is saved. Dead players respawn at the world spawn when
+//! they click the respawn button; they don't drop their inventory yet.
+
+use crate::chunk_logic::ChunkHolders;
+use crate::dimension::{DimensionChangeEvent, PRIMARY_DIMENSION};
+use crate::entity::{
+    self, ArrowComponent, ChunkEntities, EntityDestroyEvent, Metadata, PlayerComponent,
+    PositionComponent, VelocityComponent,
+};
+use crate::event::{EntityDamageEvent, EventBus};
+use crate::loot::{self, LootContext, LootTables};
+use crate::mob::MobComponent;
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::physics::{nearby_entities, AABBExt, PhysicsComponent};
+use crate::player::{InventoryComponent, InventoryUpdateEvent, PlayerStatsComponent};
+use crate::systems::{
+    ARROW_HIT, DAMAGE, ENTITY_PHYSICS, FIRE, MOB_DEATH, PLAYER_ATTACK, PLAYER_RESPAWN,
+};
+use crate::timings::DispatcherBuilderExt;
+use crate::tool::{self, ToolKind, ToolTier};
+use crate::util::{protocol_velocity, Util};
+use crate::TickCount;
+use feather_core::level::LevelData;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    ClientStatus, DestroyEntities, EntityStatus, EntityVelocity, UpdateHealth, UseEntity,
+    UseEntityType,
+};
+use feather_core::world::ChunkMap;
+use feather_core::{BlockExt, Gamemode, ItemStack, PacketType, Position};
+use glm::DVec3;
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DenseVecStorage, DispatcherBuilder, Entities, Entity, Join, LazyUpdate, Read,
+    ReadStorage, System, WorldExt, Write, WriteStorage,
+};
+
+/// The maximum distance from which players can attack.
+pub const REACH: f64 = 6.0;
+/// The number of ticks for which entities
+/// are invulnerable after being hurt.
+pub const INVULNERABILITY_TICKS: u64 = 10;
+/// The number of ticks after which dead mobs are removed.
+pub const DEATH_TICKS: u64 = 20;
+/// The maximum health of players.
+pub const PLAYER_MAX_HEALTH: f32 = 20.0;
+/// The horizontal speed at which attacks knock entities back.
+const KNOCKBACK: f64 = 0.4;
+/// The damage of an arrow per block per tick of its speed.
+const ARROW_DAMAGE: f64 = 2.0;
+/// Arrows slower than this, in blocks per tick,
+/// are stuck and don't damage entities.
+const MIN_ARROW_SPEED: f64 = 0.1;
+/// How often burning entities take damage, in ticks.
+const FIRE_DAMAGE_INTERVAL: u64 = 20;
+/// The action of Client Status packets sent by
+/// players who click the respawn button.
+const ACTION_RESPAWN: i32 = 0;
+
+/// The Entity Status which plays the hurt animation.
+const STATUS_HURT: i8 = 2;
+/// The Entity Status which plays the death animation.
+const STATUS_DEATH: i8 = 3;
+
+/// The health of a mob or player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthComponent {
+    /// The health, in half-hearts.
+    pub health: f32,
+    pub max_health: f32,
+    /// The tick at which the entity was last hurt, if ever.
+    pub hurt_tick: Option<u64>,
+    /// The damage taken when the entity was last hurt.
+    pub last_damage: f32,
+    /// The entity which last hurt this entity, if any.
+    pub last_damager: Option<Entity>,
+    /// The tick at which the entity died, if it is dead.
+    pub death_tick: Option<u64>,
+}
+
+impl HealthComponent {
+    /// Returns the health of an entity which hasn't been hurt.
+    pub fn new(max_health: f32) -> Self {
+        Self {
+            health: max_health,
+            max_health,
+            hurt_tick: None,
+            last_damage: 0.0,
+            last_damager: None,
+            death_tick: None,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.death_tick.is_some()
+    }
+
+    /// Returns whether the entity is still
+    /// invulnerable after being hurt.
+    pub fn is_invulnerable(&self, tick: u64) -> bool {
+        self.hurt_tick
+            .map_or(false, |hurt| tick < hurt + INVULNERABILITY_TICKS)
+    }
+}
+
+impl Component for HealthComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks an entity which is on fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FireComponent {
+    /// The number of ticks for which the entity keeps burning.
+    pub ticks: u64,
+}
+
+impl Component for FireComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Sets an entity on fire for the given number
+/// of ticks, unless it already burns for longer.
+pub fn ignite(
+    entity: Entity,
+    ticks: u64,
+    fires: &mut WriteStorage<FireComponent>,
+    metadatas: &mut WriteStorage<Metadata>,
+) {
+    match fires.get_mut(entity) {
+        Some(fire) => fire.ticks = fire.ticks.max(ticks),
+        None => {
+            fires.insert(entity, FireComponent { ticks }).unwrap();
+            if let Some(metadata) = metadatas.get_mut(entity) {
+                metadata.set_on_fire(true);
+            }
+        }
+    }
+}
+
+/// Event triggered to deal damage to an entity.
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    pub entity: Entity,
+    /// The entity which deals the damage, if any.
+    pub damager: Option<Entity>,
+    /// The damage, in half-hearts.
+    pub damage: f32,
+    /// The horizontal direction in which the
+    /// entity is knocked back, if at all.
+    pub knockback: Option<(f64, f64)>,
+}
+
+/// Returns the damage dealt by an attack with the given item.
+pub fn attack_damage(held: Option<&ItemStack>) -> f32 {
+    let tool = held.and_then(|stack| tool::tool(stack.ty));
+    let damage = match tool.and_then(|tool| tool.tier.map(|tier| (tool.kind, tier))) {
+        Some((ToolKind::Sword, tier)) => match tier {
+            ToolTier::Wood | ToolTier::Gold => 4.0,
+            ToolTier::Stone => 5.0,
+            ToolTier::Iron => 6.0,
+            ToolTier::Diamond => 7.0,
+        },
+        Some((ToolKind::Axe, tier)) => match tier {
+            ToolTier::Wood | ToolTier::Gold => 7.0,
+            ToolTier::Stone | ToolTier::Iron | ToolTier::Diamond => 9.0,
+        },
+        Some((ToolKind::Pickaxe, tier)) => match tier {
+            ToolTier::Wood | ToolTier::Gold => 2.0,
+            ToolTier::Stone => 3.0,
+            ToolTier::Iron => 4.0,
+            ToolTier::Diamond => 5.0,
+        },
+        Some((ToolKind::Shovel, tier)) => match tier {
+            ToolTier::Wood | ToolTier::Gold => 2.5,
+            ToolTier::Stone => 3.5,
+            ToolTier::Iron => 4.5,
+            ToolTier::Diamond => 5.5,
+        },
+        _ => 1.0,
+    };
+
+    let sharpness = held
+        .and_then(|stack| stack.enchantment_level("minecraft:sharpness"))
+        .unwrap_or(0);
+    if sharpness > 0 {
+        damage + 0.5 * f32::from(sharpness) + 0.5
+    } else {
+        damage
+    }
+}
+
+/// Returns the durability lost by a tool when it is used to attack.
+fn weapon_damage(held: &ItemStack) -> i32 {
+    match tool::tool(held.ty).map(|tool| tool.kind) {
+        Some(ToolKind::Sword) => 1,
+        Some(ToolKind::Shears) | None => 0,
+        Some(_) => 2,
+    }
+}
+
+/// System which handles Use Entity packets
+/// sent when players attack entities.
+pub struct PlayerAttackSystem;
+
+impl<'a> System<'a> for PlayerAttackSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        Entities<'a>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, HealthComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<DamageEvent>>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            entities,
+            players,
+            positions,
+            healths,
+            mut inventories,
+            networks,
+            mut damage_events,
+            mut update_events,
+            util,
+        ) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::UseEntity) {
+            let packet = cast_packet::<UseEntity>(&*packet);
+            match packet.ty {
+                UseEntityType::Attack => (),
+                _ => continue,
+            }
+
+            let target = entities.entity(packet.target as u32);
+            if target == player || !entities.is_alive(target) {
+                continue;
+            }
+            match healths.get(target) {
+                Some(health) if !health.is_dead() => (),
+                _ => continue,
+            }
+            let gamemode = continue_if_none!(players.get(player)).gamemode;
+            if gamemode == Gamemode::Spectator {
+                continue;
+            }
+            let pos = continue_if_none!(positions.get(player)).current;
+            let target_pos = continue_if_none!(positions.get(target)).current;
+            if pos.distance_squared(target_pos) > REACH * REACH {
+                continue;
+            }
+
+            let inventory = continue_if_none!(inventories.get_mut(player));
+            let held = inventory.item_in_main_hand().cloned();
+            let yaw = f64::from(pos.yaw).to_radians();
+            damage_events.single_write(DamageEvent {
+                entity: target,
+                damager: Some(player),
+                damage: attack_damage(held.as_ref()),
+                knockback: Some((-yaw.sin(), yaw.cos())),
+            });
+
+            let wear = held.as_ref().map_or(0, weapon_damage);
+            if gamemode != Gamemode::Creative && wear > 0 {
+                tool::damage_held_item(
+                    player,
+                    inventory,
+                    wear,
+                    &networks,
+                    &mut update_events,
+                    &util,
+                );
+            }
+        }
+    }
+}
+
+/// System which applies `DamageEvent`s, knocking
+/// entities back and dropping the loot of killed mobs.
+#[derive(Default)]
+pub struct DamageSystem {
+    reader: Option<ReaderId<DamageEvent>>,
+}
+
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = (
+        Read<'a, EventChannel<DamageEvent>>,
+        Read<'a, EventBus>,
+        WriteStorage<'a, HealthComponent>,
+        WriteStorage<'a, Metadata>,
+        WriteStorage<'a, VelocityComponent>,
+        WriteStorage<'a, PlayerStatsComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, MobComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Read<'a, LootTables>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            bus,
+            mut healths,
+            mut metadatas,
+            mut velocities,
+            mut stats_comps,
+            positions,
+            mobs,
+            players,
+            inventories,
+            networks,
+            tables,
+            lazy,
+            entities,
+            tick,
+            util,
+        ) = data;
+
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let health = continue_if_none!(healths.get_mut(event.entity));
+            if health.is_dead() {
+                continue;
+            }
+            if let Some(player) = players.get(event.entity) {
+                match player.gamemode {
+                    Gamemode::Creative | Gamemode::Spectator => continue,
+                    Gamemode::Survival | Gamemode::Adventure => (),
+                }
+            }
+
+            let mut bus_event = EntityDamageEvent {
+                entity: event.entity,
+                damager: event.damager,
+                damage: event.damage,
+                cancelled: false,
+            };
+            if !bus.emit(&mut bus_event) {
+                continue;
+            }
+
+            // While invulnerable, entities only take the
+            // damage exceeding the damage they were hurt by.
+            let invulnerable = health.is_invulnerable(tick.0);
+            let damage = if invulnerable {
+                if bus_event.damage <= health.last_damage {
+                    continue;
+                }
+                bus_event.damage - health.last_damage
+            } else {
+                health.hurt_tick = Some(tick.0);
+                bus_event.damage
+            };
+            health.last_damage = bus_event.damage;
+            health.health = (health.health - damage).max(0.0);
+            if event.damager.is_some() {
+                health.last_damager = event.damager;
+            }
+
+            if let Some(metadata) = metadatas.get_mut(event.entity) {
+                metadata.set_health(health.health);
+            }
+            let network = networks.get(event.entity);
+            if let Some(stats) = stats_comps.get_mut(event.entity) {
+                stats.health = health.health;
+                if let Some(network) = network {
+                    send_packet_to_player(
+                        network,
+                        UpdateHealth::new(stats.health, stats.food_level, stats.food_saturation),
+                    );
+                }
+            }
+
+            let id = event.entity.id() as i32;
+            if !invulnerable {
+                util.broadcast_entity_update(
+                    event.entity,
+                    EntityStatus::new(id, STATUS_HURT),
+                    None,
+                );
+
+                if let (Some((x, z)), Some(pos)) = (event.knockback, positions.get(event.entity)) {
+                    let on_ground = pos.current.on_ground;
+                    match (network, velocities.get_mut(event.entity)) {
+                        // Players are moved by their client.
+                        (Some(network), _) => {
+                            let velocity = knockback(glm::vec3(0.0, 0.0, 0.0), x, z, on_ground);
+                            let (x, y, z) = protocol_velocity(velocity);
+                            send_packet_to_player(network, EntityVelocity::new(id, x, y, z));
+                        }
+                        (None, Some(velocity)) => {
+                            velocity.0 = knockback(velocity.0, x, z, on_ground);
+                        }
+                        (None, None) => (),
+                    }
+                }
+            }
+
+            if health.health > 0.0 {
+                continue;
+            }
+            health.death_tick = Some(tick.0);
+            util.broadcast_entity_update(event.entity, EntityStatus::new(id, STATUS_DEATH), None);
+
+            let mob = continue_if_none!(mobs.get(event.entity));
+            let pos = continue_if_none!(positions.get(event.entity)).current;
+            let killer = health.last_damager;
+            let weapon = killer
+                .and_then(|killer| inventories.get(killer))
+                .and_then(|inventory| inventory.item_in_main_hand());
+            let ctx = LootContext {
+                killed_by_player: killer.map_or(false, |killer| players.get(killer).is_some()),
+                looting: weapon
+                    .and_then(|weapon| weapon.enchantment_level("minecraft:looting"))
+                    .map_or(0, |level| level.max(0) as u32),
+                ..Default::default()
+            };
+            let table = mob.kind.loot_table(metadatas.get(event.entity));
+            let drops = tables.generate(&table, &ctx, &mut rng);
+            loot::drop_at_entity(&lazy, &entities, pos, drops, tick.0, &mut rng);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// Returns the velocity of an entity after it is knocked
+/// back in the given horizontal direction.
+fn knockback(velocity: DVec3, x: f64, z: f64, on_ground: bool) -> DVec3 {
+    let length = (x * x + z * z).sqrt().max(std::f64::EPSILON);
+    let y = if on_ground {
+        (velocity.y / 2.0 + KNOCKBACK).min(KNOCKBACK)
+    } else {
+        velocity.y
+    };
+    glm::vec3(
+        velocity.x / 2.0 + x / length * KNOCKBACK,
+        y,
+        velocity.z / 2.0 + z / length * KNOCKBACK,
+    )
+}
+
+/// System which removes mobs `DEATH_TICKS`
+/// ticks after they died.
+pub struct MobDeathSystem;
+
+impl<'a> System<'a> for MobDeathSystem {
+    type SystemData = (
+        ReadStorage<'a, HealthComponent>,
+        ReadStorage<'a, MobComponent>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (healths, mobs, mut destroy_events, entities, tick) = data;
+
+        for (entity, health, _) in (&entities, &healths, &mobs).join() {
+            match health.death_tick {
+                Some(death) if tick.0 >= death + DEATH_TICKS => (),
+                _ => continue,
+            }
+            destroy_events.single_write(EntityDestroyEvent { entity });
+            entities.delete(entity).unwrap();
+        }
+    }
+}
+
+/// System which damages the entities hit by flying
+/// arrows, removing the arrows which hit an entity.
+pub struct ArrowHitSystem;
+
+impl<'a> System<'a> for ArrowHitSystem {
+    type SystemData = (
+        ReadStorage<'a, ArrowComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, VelocityComponent>,
+        ReadStorage<'a, HealthComponent>,
+        ReadStorage<'a, PhysicsComponent>,
+        Read<'a, ChunkEntities>,
+        Write<'a, EventChannel<DamageEvent>>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            arrows,
+            positions,
+            velocities,
+            healths,
+            physics,
+            chunk_entities,
+            mut damage_events,
+            mut destroy_events,
+            entities,
+        ) = data;
+
+        for (entity, arrow, position, velocity) in
+            (&entities, &arrows, &positions, &velocities).join()
+        {
+            let speed = glm::length(&velocity.0);
+            if speed < MIN_ARROW_SPEED {
+                continue;
+            }
+
+            // Arrows move more than a block per tick, so
+            // the middle of their path is checked too.
+            let (previous, current) = (position.previous.as_vec(), position.current.as_vec());
+            let points = [(previous + current) / 2.0, current];
+            let nearby = nearby_entities(
+                &chunk_entities,
+                &positions,
+                position.current,
+                glm::vec3(speed + 1.0, speed + 2.0, speed + 1.0),
+            );
+            let target = nearby.into_iter().find(|target| {
+                if *target == entity || Some(*target) == arrow.shooter {
+                    return false;
+                }
+                match healths.get(*target) {
+                    Some(health) if !health.is_dead() => (),
+                    _ => return false,
+                }
+                let target_pos = match positions.get(*target) {
+                    Some(pos) => pos.current,
+                    None => return false,
+                };
+                // Players have no physics component.
+                let size = physics
+                    .get(*target)
+                    .map_or(glm::vec3(0.6, 1.8, 0.6), |physics| physics.bbox.size());
+                points.iter().any(|point| hits(*point, target_pos, size))
+            });
+            let target = continue_if_none!(target);
+
+            damage_events.single_write(DamageEvent {
+                entity: target,
+                damager: arrow.shooter,
+                damage: (speed * ARROW_DAMAGE).ceil() as f32,
+                knockback: Some((velocity.0.x, velocity.0.z)),
+            });
+            destroy_events.single_write(EntityDestroyEvent { entity });
+            entities.delete(entity).unwrap();
+        }
+    }
+}
+
+/// Returns whether an arrow at the given point hits an
+/// entity at `pos` with a bounding box of the given size.
+fn hits(point: DVec3, pos: Position, size: DVec3) -> bool {
+    // The bounding box of arrows is 0.5 blocks wide.
+    (point.x - pos.x).abs() <= size.x / 2.0 + 0.25
+        && (point.z - pos.z).abs() <= size.z / 2.0 + 0.25
+        && point.y >= pos.y - 0.25
+        && point.y <= pos.y + size.y + 0.25
+}
+
+/// System which damages burning entities every
+/// `FIRE_DAMAGE_INTERVAL` ticks and extinguishes
+/// them once they stop burning or enter water.
+pub struct FireSystem;
+
+impl<'a> System<'a> for FireSystem {
+    type SystemData = (
+        WriteStorage<'a, FireComponent>,
+        WriteStorage<'a, Metadata>,
+        ReadStorage<'a, PositionComponent>,
+        Read<'a, ChunkMap>,
+        Write<'a, EventChannel<DamageEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut fires, mut metadatas, positions, chunk_map, mut damage_events, entities) = data;
+
+        let mut extinguished = vec![];
+        for (entity, fire) in (&entities, &mut fires).join() {
+            if fire.ticks % FIRE_DAMAGE_INTERVAL == 0 {
+                damage_events.single_write(DamageEvent {
+                    entity,
+                    damager: None,
+                    damage: 1.0,
+                    knockback: None,
+                });
+            }
+            fire.ticks = fire.ticks.saturating_sub(1);
+
+            let in_water = positions
+                .get(entity)
+                .and_then(|pos| chunk_map.block_at(pos.current.block_pos()))
+                .map_or(false, |block| block.is_fluid());
+            if fire.ticks == 0 || in_water {
+                extinguished.push(entity);
+            }
+        }
+
+        for entity in extinguished {
+            fires.remove(entity);
+            if let Some(metadata) = metadatas.get_mut(entity) {
+                metadata.set_on_fire(false);
+            }
+        }
+    }
+}
+
+/// System which respawns dead players at the world
+/// spawn when they click the respawn button.
+pub struct PlayerRespawnSystem;
+
+impl<'a> System<'a> for PlayerRespawnSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        WriteStorage<'a, HealthComponent>,
+        WriteStorage<'a, PlayerStatsComponent>,
+        WriteStorage<'a, Metadata>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<DimensionChangeEvent>>,
+        Read<'a, LevelData>,
+        Read<'a, LazyUpdate>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            mut healths,
+            mut stats_comps,
+            mut metadatas,
+            networks,
+            mut dimension_events,
+            level,
+            lazy,
+            util,
+        ) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::ClientStatus) {
+            let packet = cast_packet::<ClientStatus>(&*packet);
+            if packet.action_id != ACTION_RESPAWN {
+                continue;
+            }
+            let health = continue_if_none!(healths.get_mut(player));
+            if !health.is_dead() {
+                continue;
+            }
+
+            *health = HealthComponent::new(health.max_health);
+            if let Some(metadata) = metadatas.get_mut(player) {
+                metadata.set_health(health.health);
+            }
+            if let Some(stats) = stats_comps.get_mut(player) {
+                let initial = PlayerStatsComponent::default();
+                stats.health = health.health;
+                stats.food_level = initial.food_level;
+                stats.food_saturation = initial.food_saturation;
+                if let Some(network) = networks.get(player) {
+                    send_packet_to_player(
+                        network,
+                        UpdateHealth::new(stats.health, stats.food_level, stats.food_saturation),
+                    );
+                }
+            }
+
+            let spawn = position!(
+                f64::from(level.spawn_x),
+                f64::from(level.spawn_y),
+                f64::from(level.spawn_z)
+            );
+            dimension_events.single_write(DimensionChangeEvent {
+                entity: player,
+                dimension: PRIMARY_DIMENSION,
+                position: spawn,
+            });
+
+            // Clients remove dead players, so the player
+            // is sent again to those near the spawn.
+            util.broadcast_entity_update(
+                player,
+                DestroyEntities::new(vec![player.id() as i32]),
+                Some(player),
+            );
+            lazy.exec(move |world| {
+                let holders = world.fetch::<ChunkHolders>();
+                let networks = world.read_component::<NetworkComponent>();
+                let lazy = world.fetch::<LazyUpdate>();
+                for holder in holders.holders_for(spawn.chunk_pos()).unwrap_or(&[]) {
+                    if *holder != player && networks.get(*holder).is_some() {
+                        entity::send_entity_to_player(&lazy, *holder, player);
+                    }
+                }
+            });
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(PlayerAttackSystem, PLAYER_ATTACK, &[]);
+    dispatcher.add_timed(MobDeathSystem, MOB_DEATH, &[]);
+    dispatcher.add_timed(ArrowHitSystem, ARROW_HIT, &[ENTITY_PHYSICS]);
+    dispatcher.add_timed(FireSystem, FIRE, &[]);
+    dispatcher.add_timed(PlayerRespawnSystem, PLAYER_RESPAWN, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(DamageSystem::default(), DAMAGE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{arrow, ItemComponent};
+    use crate::event::EventPriority;
+    use crate::mob::{self, MobKind};
+    use crate::testframework as t;
+    use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+    use feather_core::{ChunkPosition, Item};
+    use specs::{Builder, World, WorldExt};
+
+    fn spawn_pig(w: &mut World) -> Entity {
+        let pig = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Pig,
+            position!(2.0, 0.0, 0.0),
+        );
+        w.maintain();
+        pig
+    }
+
+    fn health(w: &World, entity: Entity) -> HealthComponent {
+        w.read_component::<HealthComponent>()
+            .get(entity)
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_attack_damage() {
+        let damage = |item| attack_damage(Some(&ItemStack::new(item, 1)));
+        assert!((attack_damage(None) - 1.0).abs() < 1e-6);
+        assert!((damage(Item::Stick) - 1.0).abs() < 1e-6);
+        assert!((damage(Item::DiamondSword) - 7.0).abs() < 1e-6);
+        assert!((damage(Item::StoneAxe) - 9.0).abs() < 1e-6);
+        assert!((damage(Item::IronShovel) - 4.5).abs() < 1e-6);
+
+        let mut sword = ItemStack::new(Item::IronSword, 1);
+        sword.add_enchantment("minecraft:sharpness", 2);
+        assert!((attack_damage(Some(&sword)) - 7.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_attack() {
+        let (mut w, mut d) = t::builder()
+            .with(PlayerAttackSystem, "attack")
+            .with_dep(DamageSystem::default(), "damage", &["attack"])
+            .build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::WoodenSword, 1));
+        let pig = spawn_pig(&mut w);
+
+        let attack = |w: &World| {
+            t::receive_packet(
+                &player,
+                w,
+                UseEntity::new(pig.id() as i32, UseEntityType::Attack),
+            );
+        };
+
+        attack(&w);
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 6.0).abs() < 1e-6);
+        // The player faces south, so the pig is knocked back southwards.
+        let velocity = t::entity_vel(&w, pig).unwrap();
+        assert!((velocity.z - 0.4).abs() < 1e-6);
+        assert!(velocity.y > 0.0);
+        let sword = w
+            .read_component::<InventoryComponent>()
+            .get(player.entity)
+            .unwrap()
+            .item_in_main_hand()
+            .cloned()
+            .unwrap();
+        assert_eq!(sword.damage(), 1);
+
+        // The pig is invulnerable right after being hurt.
+        attack(&w);
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 6.0).abs() < 1e-6);
+
+        w.fetch_mut::<TickCount>().0 += INVULNERABILITY_TICKS;
+        attack(&w);
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 2.0).abs() < 1e-6);
+        assert_eq!(health(&w, pig).last_damager, Some(player.entity));
+    }
+
+    #[test]
+    fn test_attack_out_of_reach() {
+        let (mut w, mut d) = t::builder()
+            .with(PlayerAttackSystem, "attack")
+            .with_dep(DamageSystem::default(), "damage", &["attack"])
+            .build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let pig = spawn_pig(&mut w);
+        t::set_entity_pos(&w, pig, position!(10.0, 0.0, 0.0));
+
+        t::receive_packet(
+            &player,
+            &w,
+            UseEntity::new(pig.id() as i32, UseEntityType::Attack),
+        );
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_cancelled_damage() {
+        let (mut w, mut d) = t::builder().with(DamageSystem::default(), "damage").build();
+        t::populate_with_air(&mut w);
+        let pig = spawn_pig(&mut w);
+        w.fetch_mut::<EventBus>().register(
+            EventPriority::Normal,
+            |event: &mut EntityDamageEvent| {
+                event.damage *= 2.0;
+                if event.damage > 5.0 {
+                    event.cancelled = true;
+                }
+            },
+        );
+
+        let damage = |w: &World, damage| {
+            t::trigger_event(
+                w,
+                DamageEvent {
+                    entity: pig,
+                    damager: None,
+                    damage,
+                    knockback: None,
+                },
+            );
+        };
+
+        damage(&w, 3.0);
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 10.0).abs() < 1e-6);
+
+        damage(&w, 2.0);
+        d.dispatch(&w);
+        assert!((health(&w, pig).health - 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_death() {
+        let (mut w, mut d) = t::builder()
+            .with(DamageSystem::default(), "damage")
+            .with(MobDeathSystem, "death")
+            .build();
+        w.insert(LootTables::bundled());
+        w.register::<ItemComponent>();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let pig = spawn_pig(&mut w);
+
+        t::trigger_event(
+            &w,
+            DamageEvent {
+                entity: pig,
+                damager: Some(player.entity),
+                damage: 20.0,
+                knockback: None,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let pig_health = health(&w, pig);
+        assert!(pig_health.is_dead());
+        assert!(pig_health.health.abs() < 1e-6);
+        {
+            let items = w.read_component::<ItemComponent>();
+            let stacks: Vec<_> = items.join().map(|item| item.stack.clone()).collect();
+            assert_eq!(stacks.len(), 1);
+            assert_eq!(stacks[0].ty, Item::Porkchop);
+        }
+
+        // Dead mobs take no further damage.
+        t::trigger_event(
+            &w,
+            DamageEvent {
+                entity: pig,
+                damager: Some(player.entity),
+                damage: 20.0,
+                knockback: None,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.read_component::<ItemComponent>().join().count(), 1);
+
+        w.fetch_mut::<TickCount>().0 += DEATH_TICKS - 1;
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_not_removed(&w, pig);
+
+        w.fetch_mut::<TickCount>().0 += 1;
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_removed(&w, pig);
+    }
+
+    #[test]
+    fn test_player_damage() {
+        let (mut w, mut d) = t::builder().with(DamageSystem::default(), "damage").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerStatsComponent>()
+            .insert(player.entity, PlayerStatsComponent::default())
+            .unwrap();
+
+        let damage = |w: &World| {
+            t::trigger_event(
+                w,
+                DamageEvent {
+                    entity: player.entity,
+                    damager: None,
+                    damage: 5.0,
+                    knockback: Some((1.0, 0.0)),
+                },
+            );
+        };
+
+        // Players in creative mode are immune.
+        damage(&w);
+        d.dispatch(&w);
+        assert!((health(&w, player.entity).health - PLAYER_MAX_HEALTH).abs() < 1e-6);
+
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        damage(&w);
+        d.dispatch(&w);
+        assert!((health(&w, player.entity).health - 15.0).abs() < 1e-6);
+        let stats = *w
+            .read_component::<PlayerStatsComponent>()
+            .get(player.entity)
+            .unwrap();
+        assert!((stats.health - 15.0).abs() < 1e-6);
+
+        let packet = t::assert_packet_received(&player, PacketType::UpdateHealth);
+        assert!((cast_packet::<UpdateHealth>(&*packet).health - 15.0).abs() < 1e-6);
+        let packet = t::assert_packet_received(&player, PacketType::EntityVelocity);
+        assert!(cast_packet::<EntityVelocity>(&*packet).velocity_x > 0);
+    }
+
+    #[test]
+    fn test_player_respawn() {
+        let (mut w, mut d) = t::builder().with(PlayerRespawnSystem, "respawn").build();
+        w.insert(LevelData::default());
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let mut reader = t::reader::<DimensionChangeEvent>(&w);
+
+        // Living players can't respawn.
+        t::receive_packet(&player, &w, ClientStatus::new(ACTION_RESPAWN));
+        d.dispatch(&w);
+        w.maintain();
+        assert!(t::triggered_events(&w, &mut reader).is_empty());
+
+        {
+            let mut healths = w.write_component::<HealthComponent>();
+            let health = healths.get_mut(player.entity).unwrap();
+            health.health = 0.0;
+            health.death_tick = Some(0);
+        }
+        t::receive_packet(&player, &w, ClientStatus::new(ACTION_RESPAWN));
+        d.dispatch(&w);
+        w.maintain();
+
+        let health = health(&w, player.entity);
+        assert!(!health.is_dead());
+        assert!((health.health - PLAYER_MAX_HEALTH).abs() < 1e-6);
+        let events = t::triggered_events(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, player.entity);
+        assert_eq!(events[0].dimension, PRIMARY_DIMENSION);
+    }
+
+    #[test]
+    fn test_arrow_hit() {
+        let (mut w, mut d) = t::builder()
+            .with(ArrowHitSystem, "arrow_hit")
+            .with_dep(DamageSystem::default(), "damage", &["arrow_hit"])
+            .build();
+        t::populate_with_air(&mut w);
+        let pig = spawn_pig(&mut w);
+        w.fetch_mut::<ChunkEntities>()
+            .add_to_chunk(ChunkPosition::new(0, 0), pig);
+
+        let arrow = arrow::create(&w.fetch::<LazyUpdate>(), &w.entities(), false, None)
+            .with(PositionComponent {
+                current: position!(1.8, 0.5, 0.0),
+                previous: position!(0.3, 0.5, 0.0),
+            })
+            .with(VelocityComponent(glm::vec3(1.5, 0.0, 0.0)))
+            .build();
+        w.maintain();
+
+        d.dispatch(&w);
+        w.maintain();
+        // The damage is the speed of the arrow times two, rounded up.
+        assert!((health(&w, pig).health - 7.0).abs() < 1e-6);
+        assert!(t::entity_vel(&w, pig).unwrap().x > 0.0);
+        t::assert_removed(&w, arrow);
+    }
+
+    #[test]
+    fn test_fire() {
+        let (mut w, mut d) = t::builder()
+            .with(FireSystem, "fire")
+            .with_dep(DamageSystem::default(), "damage", &["fire"])
+            .build();
+        t::populate_with_air(&mut w);
+        let pig = spawn_pig(&mut w);
+        {
+            let mut fires = w.write_component::<FireComponent>();
+            let mut metadatas = w.write_component::<Metadata>();
+            ignite(pig, 40, &mut fires, &mut metadatas);
+        }
+
+        for _ in 0..40 {
+            d.dispatch(&w);
+            w.maintain();
+            w.fetch_mut::<TickCount>().0 += 1;
+        }
+
+        // Burning entities take a point of damage every second.
+        assert!((health(&w, pig).health - 8.0).abs() < 1e-6);
+        assert!(w.read_component::<FireComponent>().get(pig).is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Command parsing and dispatch.
+//!
+//! Chat messages beginning with a slash are not broadcasted;
+//! instead, `PlayerChatSystem` triggers a `CommandEvent`. Systems
+//! implementing a command listen for `CommandEvent`s with the
+//! corresponding name. Every command must be registered in the
+//! `CommandRegistry` so that unknown commands can be reported
+//! to the sender.
+//!
+//! Commands may also be sent by the server console, which is
+//! represented by an entity with a `ConsoleComponent`. Use `reply`
+//! and `is_privileged` to handle both kinds of sender.
+//!
+//! Replies are `Message`s so that they can be translated;
+//! see the `lang` module.
+
+use crate::config::Config;
+use crate::entity::NamedComponent;
+use crate::lang::{Locale, Message};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::UNKNOWN_COMMAND;
+use crate::timings::DispatcherBuilderExt;
+use crossbeam::Sender;
+use feather_core::network::packet::implementation::ChatMessageClientbound;
+use hashbrown::HashSet;
+use shrev::{EventChannel, ReaderId};
+use specs::{Component, DispatcherBuilder, Entity, HashMapStorage, Read, ReadStorage, System};
+
+/// Event triggered when a command is executed.
+#[derive(Debug, Clone)]
+pub struct CommandEvent {
+    /// The entity which executed the command.
+    pub sender: Entity,
+    /// The name of the command, without the leading slash.
+    pub name: String,
+    /// The whitespace-separated arguments to the command.
+    pub args: Vec<String>,
+}
+
+impl CommandEvent {
+    /// Parses a command from a chat message. Returns `None`
+    /// if the message is not a command.
+    pub fn parse(sender: Entity, message: &str) -> Option<Self> {
+        if !message.starts_with('/') {
+            return None;
+        }
+
+        let mut parts = message[1..].split_whitespace();
+        let name = parts.next()?.to_lowercase();
+        let args = parts.map(str::to_string).collect();
+
+        Some(Self { sender, name, args })
+    }
+}
+
+/// Resource containing the names of all known commands.
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry(HashSet<String>);
+
+impl CommandRegistry {
+    /// Registers a command.
+    pub fn register(&mut self, name: &str) {
+        self.0.insert(name.to_lowercase());
+    }
+
+    /// Unregisters a command, returning whether it was registered.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.0.remove(&name.to_lowercase())
+    }
+
+    /// Returns whether a command with the given name exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    /// Returns the names of all registered commands.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// Component for entities which send commands with
+/// the privileges of the server console, such as the
+/// console itself and the admin API.
+#[derive(Default, Debug)]
+pub struct ConsoleComponent {
+    /// The channel replies are sent to. If `None`,
+    /// replies are logged.
+    pub output: Option<Sender<String>>,
+}
+
+impl Component for ConsoleComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// The name of the console, used when
+/// logging commands sent by it.
+pub const CONSOLE_NAME: &str = "CONSOLE";
+
+/// Returns the message sent to players who execute
+/// a command without the necessary permissions.
+pub fn no_permission() -> Message {
+    Message::translate("feather.command.no_permission")
+}
+
+/// Returns a message describing the correct usage of
+/// a command, such as `/function <name>`.
+pub fn usage(syntax: &str) -> Message {
+    Message::translate("feather.command.usage").with(syntax)
+}
+
+/// Returns whether the player with the given name
+/// is a server operator.
+pub fn is_operator(config: &Config, name: &str) -> bool {
+    config
+        .server
+        .operators
+        .iter()
+        .any(|operator| operator.eq_ignore_ascii_case(name))
+}
+
+/// Returns whether the sender of a command is
+/// the console or an operator.
+pub fn is_privileged(
+    config: &Config,
+    sender: Entity,
+    nameds: &ReadStorage<NamedComponent>,
+    consoles: &ReadStorage<ConsoleComponent>,
+) -> bool {
+    consoles.contains(sender)
+        || nameds
+            .get(sender)
+            .map_or(false, |named| is_operator(config, &named.display_name))
+}
+
+/// Returns the name of the sender of a command.
+pub fn sender_name<'a>(sender: Entity, nameds: &'a ReadStorage<NamedComponent>) -> &'a str {
+    nameds
+        .get(sender)
+        .map_or(CONSOLE_NAME, |named| named.display_name.as_str())
+}
+
+/// Sends a reply to the sender of a command. Replies to the
+/// console are translated and logged or sent to its output.
+pub fn reply(
+    sender: Entity,
+    networks: &ReadStorage<NetworkComponent>,
+    consoles: &ReadStorage<ConsoleComponent>,
+    locale: &Locale,
+    message: impl Into<Message>,
+) {
+    let message = message.into();
+    if let Some(console) = consoles.get(sender) {
+        let text = locale.text(&message);
+        match &console.output {
+            Some(output) => {
+                let _ = output.send(text);
+            }
+            None => info!("{}", text),
+        }
+    } else if let Some(network) = networks.get(sender) {
+        send_message(network, locale, message);
+    }
+}
+
+/// Sends a system message to a player.
+pub fn send_message(network: &NetworkComponent, locale: &Locale, message: impl Into<Message>) {
+    let json_data = locale.component(&message.into()).to_string();
+    send_packet_to_player(
+        network,
+        ChatMessageClientbound {
+            json_data,
+            position: 1, // System message
+        },
+    );
+}
+
+/// System which notifies players when they execute
+/// a command which doesn't exist.
+#[derive(Default)]
+pub struct UnknownCommandSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl<'a> System<'a> for UnknownCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, CommandRegistry>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, registry, locale, networks, consoles) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if registry.contains(&event.name) {
+                continue;
+            }
+
+            reply(
+                event.sender,
+                &networks,
+                &consoles,
+                &locale,
+                Message::translate("command.unknown.command"),
+            );
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(UnknownCommandSystem::default(), UNKNOWN_COMMAND, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::PacketType;
+    use specs::{Builder, WorldExt};
+
+    #[test]
+    fn test_parse_command() {
+        let (mut w, _) = t::builder().build();
+        let sender = w.create_entity().build();
+
+        let command = CommandEvent::parse(sender, "/Give  player diamond").unwrap();
+        assert_eq!(command.name, "give");
+        assert_eq!(command.args, vec!["player", "diamond"]);
+
+        assert!(CommandEvent::parse(sender, "hello").is_none());
+        assert!(CommandEvent::parse(sender, "/").is_none());
+    }
+
+    #[test]
+    fn test_is_operator() {
+        let mut config = Config::default();
+        assert!(!is_operator(&config, "caelunshun"));
+
+        config.server.operators.push(String::from("Caelunshun"));
+        assert!(is_operator(&config, "caelunshun"));
+        assert!(!is_operator(&config, "someone"));
+    }
+
+    #[test]
+    fn test_is_privileged() {
+        let (mut w, _) = t::builder().build();
+        w.register::<ConsoleComponent>();
+        let player = t::add_player(&mut w);
+        let console = w.create_entity().with(ConsoleComponent::default()).build();
+
+        let mut config = Config::default();
+        {
+            let nameds = w.read_component::<NamedComponent>();
+            let consoles = w.read_component::<ConsoleComponent>();
+            assert!(is_privileged(&config, console, &nameds, &consoles));
+            assert!(!is_privileged(&config, player.entity, &nameds, &consoles));
+            assert_eq!(sender_name(console, &nameds), CONSOLE_NAME);
+        }
+
+        let name = w
+            .read_component::<NamedComponent>()
+            .get(player.entity)
+            .unwrap()
+            .display_name
+            .clone();
+        config.server.operators.push(name);
+        let nameds = w.read_component::<NamedComponent>();
+        let consoles = w.read_component::<ConsoleComponent>();
+        assert!(is_privileged(&config, player.entity, &nameds, &consoles));
+    }
+
+    #[test]
+    fn test_reply_output() {
+        let (mut w, _) = t::builder().build();
+        w.register::<ConsoleComponent>();
+        let (tx, rx) = crossbeam::unbounded();
+        let console = w
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+
+        let networks = w.read_component::<NetworkComponent>();
+        let consoles = w.read_component::<ConsoleComponent>();
+        let locale = Locale::default();
+        reply(console, &networks, &consoles, &locale, "Hello");
+        assert_eq!(rx.try_recv().unwrap(), "Hello");
+
+        reply(console, &networks, &consoles, &locale, usage("/test"));
+        assert_eq!(rx.try_recv().unwrap(), "Usage: /test");
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let (mut w, mut d) = t::builder()
+            .with(UnknownCommandSystem::default(), "")
+            .build();
+        w.fetch_mut::<CommandRegistry>().register("known");
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/known").unwrap());
+
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_packet_not_received(&player, PacketType::ChatMessageClientbound);
+
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/unknown").unwrap());
+
+        d.dispatch(&w);
+        w.maintain();
+        let packet = t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+        let packet = cast_packet::<ChatMessageClientbound>(&*packet);
+        assert!(packet.json_data.contains("command.unknown.command"));
+    }
+}
@@ -1,5 +1,8 @@
 use failure::_core::time::Duration;
+use parking_lot::RwLock;
 use std::fs::read_to_string;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
 #[derive(Debug, Fail)]
 pub enum ConfigError {
@@ -7,6 +10,8 @@ pub enum ConfigError {
     Parse(#[fail(cause)] toml::de::Error),
     #[fail(display = "Failed to read configuration file: {}", _0)]
     Io(#[fail(cause)] std::io::Error),
+    #[fail(display = "Invalid server address: {}", _0)]
+    Address(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -18,8 +23,21 @@ pub struct Config {
     pub log: Log,
     pub resource_pack: ResourcePack,
     pub world: World,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
+    pub admin_api: AdminApi,
+    #[serde(default)]
+    pub rollback: Rollback,
+    #[serde(default)]
+    pub lighting: Lighting,
+    #[serde(default)]
+    pub scripting: Scripting,
 }
 
+/// The path to the configuration file.
+pub const CONFIG_PATH: &str = "feather.toml";
+
 pub const DEFAULT_CONFIG_STR: &str = include_str!("../config/feather.toml");
 
 impl Default for Config {
@@ -28,6 +46,28 @@ impl Default for Config {
     }
 }
 
+/// The configuration, shared between the server
+/// thread and the IO threads. It may be replaced
+/// at runtime when the configuration is reloaded.
+#[derive(Debug, Default)]
+pub struct SharedConfig(RwLock<Arc<Config>>);
+
+impl SharedConfig {
+    pub fn new(config: Arc<Config>) -> Self {
+        SharedConfig(RwLock::new(config))
+    }
+
+    /// Returns the current configuration.
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.0.read())
+    }
+
+    /// Replaces the current configuration.
+    pub fn set(&self, config: Arc<Config>) {
+        *self.0.write() = config;
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct IO {
     pub compression_threshold: i32,
@@ -45,6 +85,113 @@ pub struct Server {
     pub address: String,
     pub port: u16,
     pub default_gamemode: String,
+    /// Whether only players in `whitelisted_players`
+    /// are allowed to join.
+    #[serde(default)]
+    pub whitelist: bool,
+    #[serde(default)]
+    pub whitelisted_players: Vec<String>,
+    /// Players allowed to execute administrative
+    /// commands, such as `/stop` and `/reload`.
+    #[serde(default)]
+    pub operators: Vec<String>,
+    /// The message with which players are kicked
+    /// when the server shuts down.
+    #[serde(default = "default_shutdown_message")]
+    pub shutdown_message: String,
+    /// The language messages are translated into,
+    /// such as `en_us`. See the `lang` module.
+    #[serde(default = "default_locale")]
+    pub locale: String,
+    /// The number of threads used to run systems
+    /// in parallel, or 0 for one per CPU core.
+    #[serde(default)]
+    pub worker_threads: usize,
+    /// The addresses to listen on. If empty, the
+    /// server listens on `address` and `port`.
+    #[serde(default)]
+    pub listeners: Vec<Listener>,
+    /// Settings for lowering the view distance
+    /// while the server is overloaded.
+    #[serde(default)]
+    pub dynamic_view_distance: DynamicViewDistance,
+}
+
+impl Server {
+    /// Returns the listeners to start, falling back to
+    /// `address` and `port` if none are configured.
+    pub fn listeners(&self) -> Result<Vec<Listener>, ConfigError> {
+        if !self.listeners.is_empty() {
+            return Ok(self.listeners.clone());
+        }
+
+        let ip: IpAddr = self
+            .address
+            .parse()
+            .map_err(|_| ConfigError::Address(self.address.clone()))?;
+        Ok(vec![Listener {
+            address: SocketAddr::new(ip, self.port),
+            dual_stack: true,
+            proxy_protocol: false,
+        }])
+    }
+}
+
+/// An address on which to accept connections.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Listener {
+    /// The socket address, such as `0.0.0.0:25565`
+    /// or `[::]:25565`.
+    pub address: SocketAddr,
+    /// Whether an IPv6 listener also accepts IPv4
+    /// connections. Has no effect for IPv4 addresses.
+    #[serde(default = "default_dual_stack")]
+    pub dual_stack: bool,
+    /// Whether connections begin with a PROXY protocol
+    /// (version 1) header, as sent by HAProxy and similar
+    /// load balancers. The address in the header is used
+    /// as the client's address.
+    #[serde(default)]
+    pub proxy_protocol: bool,
+}
+
+/// Settings for the dynamic view distance.
+/// See the `view_distance` module.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DynamicViewDistance {
+    pub enabled: bool,
+    /// The view distance is never lowered below this value.
+    pub min_view_distance: u8,
+    /// The view distance is lowered while ticks
+    /// take longer than this on average.
+    #[serde(with = "humantime_serde")]
+    pub max_tick_time: Duration,
+    /// The view distance is lowered while the server uses
+    /// more than this many megabytes of memory. 0 disables the limit.
+    pub max_memory: u64,
+}
+
+impl Default for DynamicViewDistance {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_view_distance: 4,
+            max_tick_time: Duration::from_millis(40),
+            max_memory: 0,
+        }
+    }
+}
+
+fn default_dual_stack() -> bool {
+    true
+}
+
+fn default_shutdown_message() -> String {
+    String::from("Server closed")
+}
+
+fn default_locale() -> String {
+    String::from(crate::lang::DEFAULT_LOCALE)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -53,6 +200,23 @@ pub struct Gameplay {
     pub animal_spawning: bool,
     pub pvp: bool,
     pub nerf_spawner_mobs: bool,
+    /// The percentage of players who must be
+    /// sleeping to skip the night.
+    #[serde(default = "default_sleeping_percentage")]
+    pub sleeping_percentage: u8,
+    /// The number of mobs at which natural spawning
+    /// stops, regardless of the number of players.
+    /// Mobs which never despawn aren't counted.
+    #[serde(default = "default_max_mobs")]
+    pub max_mobs: usize,
+}
+
+fn default_sleeping_percentage() -> u8 {
+    100
+}
+
+fn default_max_mobs() -> usize {
+    200
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -73,6 +237,106 @@ pub struct World {
     pub seed: String,
     #[serde(with = "humantime_serde")]
     pub save_interval: Duration,
+    /// The time for which chunks without holders
+    /// stay loaded before they are unloaded.
+    #[serde(with = "humantime_serde", default = "default_chunk_unload_delay")]
+    pub chunk_unload_delay: Duration,
+}
+
+fn default_chunk_unload_delay() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Metrics {
+    pub enabled: bool,
+    pub address: String,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("127.0.0.1:9100"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdminApi {
+    pub enabled: bool,
+    pub address: String,
+    /// The token which clients must send in the
+    /// `Authorization: Bearer` header.
+    pub token: String,
+}
+
+impl Default for AdminApi {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("127.0.0.1:8081"),
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rollback {
+    /// Whether to log changes made by players
+    /// so that they can be rolled back.
+    pub enabled: bool,
+    /// The time for which changes are kept in the log,
+    /// or 0 to keep them forever.
+    #[serde(with = "humantime_serde", default = "default_rollback_max_age")]
+    pub max_age: Duration,
+}
+
+impl Default for Rollback {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age: default_rollback_max_age(),
+        }
+    }
+}
+
+fn default_rollback_max_age() -> Duration {
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lighting {
+    /// The maximum number of lighting updates performed
+    /// each tick. Remaining updates are deferred to the
+    /// following ticks.
+    pub max_updates_per_tick: usize,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            max_updates_per_tick: 4096,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scripting {
+    /// The maximum number of operations a script may perform
+    /// in a single call before it is stopped, or 0 for no limit.
+    pub max_operations: u64,
+    /// The maximum depth of nested function calls in scripts.
+    pub max_call_levels: usize,
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        Self {
+            max_operations: 1_000_000,
+            max_call_levels: 32,
+        }
+    }
 }
 
 /// Loads the configuration from the given file/
@@ -114,12 +378,21 @@ mod tests {
         assert_eq!(server.view_distance, 6);
         assert_eq!(server.address, "0.0.0.0");
         assert_eq!(server.port, 25565);
+        assert_eq!(server.whitelist, false);
+        assert!(server.whitelisted_players.is_empty());
+        assert!(server.operators.is_empty());
+        assert_eq!(server.shutdown_message, "Server closed");
+        assert_eq!(server.locale, "en_us");
+        assert_eq!(server.worker_threads, 0);
+        assert!(server.listeners.is_empty());
+        assert_eq!(server.dynamic_view_distance, DynamicViewDistance::default());
 
         let gameplay = &config.gameplay;
         assert_eq!(gameplay.animal_spawning, true);
         assert_eq!(gameplay.monster_spawning, true);
         assert_eq!(gameplay.pvp, true);
         assert_eq!(gameplay.nerf_spawner_mobs, false);
+        assert_eq!(gameplay.max_mobs, 200);
 
         let log = &config.log;
         assert_eq!(log.level, "debug");
@@ -133,5 +406,78 @@ mod tests {
         assert_eq!(world.generator, "default");
         assert_eq!(world.seed, "");
         assert_eq!(world.save_interval.as_millis(), 1000 * 60);
+        assert_eq!(world.chunk_unload_delay.as_secs(), 5);
+
+        let metrics = &config.metrics;
+        assert_eq!(metrics.enabled, false);
+        assert_eq!(metrics.address, "127.0.0.1:9100");
+
+        let admin_api = &config.admin_api;
+        assert_eq!(admin_api.enabled, false);
+        assert_eq!(admin_api.address, "127.0.0.1:8081");
+        assert_eq!(admin_api.token, "");
+
+        assert_eq!(config.rollback.enabled, false);
+        assert_eq!(config.rollback.max_age, default_rollback_max_age());
+        assert_eq!(config.lighting, Lighting::default());
+        assert_eq!(config.scripting, Scripting::default());
+    }
+
+    #[test]
+    fn test_listeners() {
+        let mut server = Config::default().server;
+        assert_eq!(
+            server.listeners().unwrap(),
+            vec![Listener {
+                address: "0.0.0.0:25565".parse().unwrap(),
+                dual_stack: true,
+                proxy_protocol: false,
+            }]
+        );
+
+        server.address = String::from("::");
+        assert_eq!(
+            server.listeners().unwrap()[0].address,
+            "[::]:25565".parse().unwrap()
+        );
+
+        server.address = String::from("localhost");
+        assert!(server.listeners().is_err());
+    }
+
+    #[test]
+    fn test_load_listeners() {
+        let input = DEFAULT_CONFIG_STR.to_string()
+            + r#"
+[[server.listeners]]
+address = "0.0.0.0:25565"
+
+[[server.listeners]]
+address = "[::]:25566"
+dual_stack = false
+proxy_protocol = true
+"#;
+        let config = load(input).unwrap();
+        let listeners = config.server.listeners().unwrap();
+        assert_eq!(listeners.len(), 2);
+
+        assert_eq!(listeners[0].address, "0.0.0.0:25565".parse().unwrap());
+        assert!(listeners[0].dual_stack);
+        assert!(!listeners[0].proxy_protocol);
+
+        assert_eq!(listeners[1].address, "[::]:25566".parse().unwrap());
+        assert!(!listeners[1].dual_stack);
+        assert!(listeners[1].proxy_protocol);
+    }
+
+    #[test]
+    fn test_shared_config() {
+        let shared = SharedConfig::new(Arc::new(Config::default()));
+        assert_eq!(shared.get().server.motd, "A Feather server");
+
+        let mut config = Config::default();
+        config.server.motd = String::from("Reloaded");
+        shared.set(Arc::new(config));
+        assert_eq!(shared.get().server.motd, "Reloaded");
     }
 }
@@ -0,0 +1,579 @@
+//! The interactive server console.
+//!
+//! Commands entered on the console are executed with the
+//! console entity as their sender; see `commands::ConsoleComponent`.
+//! When standard input is a terminal, the console provides line
+//! editing, command history and tab completion of command and
+//! player names, and log messages are colored.
+//!
+//! The console logger prints log messages above the line being
+//! edited, so output from other threads does not interfere
+//! with typing. Otherwise, lines are read from standard
+//! input as they are, for use with pipes and service managers.
+
+use crate::commands::{CommandEvent, CommandRegistry, ConsoleComponent, CONSOLE_NAME};
+use crate::entity::{NamedComponent, PlayerComponent};
+use crate::systems::CONSOLE;
+use crate::timings::DispatcherBuilderExt;
+use crate::TPS;
+use crossbeam::{Receiver, Sender};
+use crossterm::cursor::MoveLeft;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::queue;
+use crossterm::style::{Color, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType};
+use log::{Level, Log, Metadata, Record};
+use parking_lot::Mutex;
+use shrev::EventChannel;
+use specs::{
+    Builder, DispatcherBuilder, Entity, Join, Read, ReadStorage, System, World, WorldExt, Write,
+};
+use std::io::{self, BufRead, Write as _};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// The prompt shown before the line being edited.
+const PROMPT: &str = "> ";
+/// The maximum number of lines kept in the history.
+const HISTORY_LENGTH: usize = 100;
+
+lazy_static! {
+    /// State of the line being edited, shared between
+    /// the input thread and the logger.
+    static ref LINE: Mutex<Line> = Mutex::new(Line::default());
+}
+
+/// The line being edited on the console.
+#[derive(Debug, Default)]
+struct Line {
+    /// Whether the terminal is in raw mode and
+    /// the prompt is displayed.
+    active: bool,
+    buffer: Vec<char>,
+    /// The index in `buffer` of the cursor.
+    cursor: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.buffer = text.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    /// Clears the prompt from the terminal so that
+    /// other output can be printed.
+    fn clear(&self, out: &mut impl io::Write) -> io::Result<()> {
+        write!(out, "\r")?;
+        queue!(out, Clear(ClearType::CurrentLine)).map_err(to_io_error)
+    }
+
+    /// Draws the prompt, the buffer and the cursor.
+    fn draw(&self, out: &mut impl io::Write) -> io::Result<()> {
+        self.clear(out)?;
+        write!(out, "{}{}", PROMPT, self.text())?;
+        let offset = self.buffer.len() - self.cursor;
+        if offset > 0 {
+            queue!(out, MoveLeft(offset as u16)).map_err(to_io_error)?;
+        }
+        out.flush()
+    }
+}
+
+fn to_io_error(e: crossterm::ErrorKind) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// Names available for tab completion.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Completions {
+    pub commands: Vec<String>,
+    pub players: Vec<String>,
+}
+
+impl Completions {
+    /// Completes the last word of `line`, which is
+    /// a command name if it is the first word and a
+    /// player name otherwise. Returns the candidates
+    /// and the index at which the word starts.
+    pub fn complete<'a>(&'a self, line: &str) -> (usize, Vec<&'a str>) {
+        let start = line.rfind(' ').map_or(0, |index| index + 1);
+        let word = &line[start..];
+
+        let (start, word, names) = if start == 0 {
+            // Commands may be entered with or without a slash.
+            let slash = word.starts_with('/') as usize;
+            (slash, &word[slash..], &self.commands)
+        } else {
+            (start, word, &self.players)
+        };
+
+        let word = word.to_lowercase();
+        let mut candidates: Vec<_> = names
+            .iter()
+            .filter(|name| name.to_lowercase().starts_with(&word))
+            .map(String::as_str)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        (start, candidates)
+    }
+}
+
+/// Returns the longest common prefix of the given strings.
+fn common_prefix<'a>(strings: &[&'a str]) -> &'a str {
+    let first = match strings.first() {
+        Some(first) => *first,
+        None => return "",
+    };
+
+    let mut len = first.len();
+    for string in &strings[1..] {
+        len = first
+            .char_indices()
+            .zip(string.chars())
+            .take_while(|((_, a), b)| a.eq_ignore_ascii_case(b))
+            .last()
+            .map_or(0, |((index, c), _)| index + c.len_utf8())
+            .min(len);
+    }
+
+    &first[..len]
+}
+
+/// Resource for communicating with the console thread.
+pub struct Console {
+    lines: Receiver<String>,
+    completions: Arc<Mutex<Completions>>,
+}
+
+/// Installs the console logger.
+pub fn init_log(level: Level) {
+    let logger = ConsoleLogger {
+        level,
+        colored: atty::is(atty::Stream::Stdout),
+    };
+    log::set_boxed_logger(Box::new(logger)).unwrap();
+    log::set_max_level(level.to_level_filter());
+}
+
+/// Starts the console thread.
+pub fn start() -> Console {
+    let (sender, lines) = crossbeam::unbounded();
+    let completions = Arc::new(Mutex::new(Completions::default()));
+
+    let interactive = atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stdout);
+    let thread_completions = Arc::clone(&completions);
+    std::thread::Builder::new()
+        .name(String::from("Console"))
+        .spawn(move || {
+            if !interactive {
+                read_plain(sender);
+            } else if let Err(e) = read_interactive(&sender, &thread_completions) {
+                restore();
+                error!("Console input failed: {}", e);
+            }
+        })
+        .expect("failed to start console thread");
+
+    Console { lines, completions }
+}
+
+/// Restores the terminal to its normal state.
+/// This must be called before the server exits.
+pub fn restore() {
+    let mut line = LINE.lock();
+    if line.active {
+        line.active = false;
+        let mut stdout = io::stdout();
+        let _ = line.clear(&mut stdout);
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Reads lines from standard input without line editing.
+fn read_plain(sender: Sender<String>) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) => {
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                error!("Failed to read from standard input: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Reads lines from the terminal with line editing,
+/// history and tab completion.
+fn read_interactive(
+    sender: &Sender<String>,
+    completions: &Mutex<Completions>,
+) -> crossterm::Result<()> {
+    terminal::enable_raw_mode()?;
+    {
+        let mut line = LINE.lock();
+        line.active = true;
+        line.draw(&mut io::stdout())?;
+    }
+
+    let mut history: Vec<String> = vec![];
+    // Index into `history` while browsing it.
+    let mut history_index = None;
+
+    loop {
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+
+        let mut guard = LINE.lock();
+        let line = &mut *guard;
+        let mut stdout = io::stdout();
+
+        match key {
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                // Raw mode disables the interrupt signal,
+                // so stop the server ourselves.
+                line.set("");
+                let _ = sender.send(String::from("stop"));
+            }
+            KeyEvent {
+                code: KeyCode::Char('d'),
+                modifiers: KeyModifiers::CONTROL,
+            } if line.buffer.is_empty() => {
+                let _ = sender.send(String::from("stop"));
+            }
+            KeyEvent {
+                code: KeyCode::Char('u'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                let cursor = line.cursor;
+                line.buffer.drain(..cursor);
+                line.cursor = 0;
+            }
+            KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+            } if !modifiers.contains(KeyModifiers::CONTROL) => {
+                let cursor = line.cursor;
+                line.buffer.insert(cursor, c);
+                line.cursor += 1;
+                history_index = None;
+            }
+            KeyEvent { code, .. } => match code {
+                KeyCode::Enter => {
+                    let text = line.text();
+                    line.set("");
+                    history_index = None;
+
+                    write!(stdout, "\r\n")?;
+                    if !text.trim().is_empty() {
+                        if history.last() != Some(&text) {
+                            history.push(text.clone());
+                            if history.len() > HISTORY_LENGTH {
+                                history.remove(0);
+                            }
+                        }
+                        let _ = sender.send(text);
+                    }
+                }
+                KeyCode::Backspace if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    let cursor = line.cursor;
+                    line.buffer.remove(cursor);
+                }
+                KeyCode::Delete if line.cursor < line.buffer.len() => {
+                    let cursor = line.cursor;
+                    line.buffer.remove(cursor);
+                }
+                KeyCode::Left if line.cursor > 0 => line.cursor -= 1,
+                KeyCode::Right if line.cursor < line.buffer.len() => line.cursor += 1,
+                KeyCode::Home => line.cursor = 0,
+                KeyCode::End => line.cursor = line.buffer.len(),
+                KeyCode::Up if !history.is_empty() => {
+                    let index =
+                        history_index.map_or(history.len() - 1, |i: usize| i.saturating_sub(1));
+                    history_index = Some(index);
+                    line.set(&history[index]);
+                }
+                KeyCode::Down => match history_index {
+                    Some(index) if index + 1 < history.len() => {
+                        history_index = Some(index + 1);
+                        line.set(&history[index + 1]);
+                    }
+                    Some(_) => {
+                        history_index = None;
+                        line.set("");
+                    }
+                    None => (),
+                },
+                KeyCode::Tab => {
+                    let before: String = line.buffer[..line.cursor].iter().collect();
+                    let completions = completions.lock();
+                    let (start, candidates) = completions.complete(&before);
+
+                    let prefix = common_prefix(&candidates);
+                    let word_len = before[start..].chars().count();
+                    if candidates.len() == 1 || prefix.chars().count() > word_len {
+                        // Replace the word with the completion.
+                        let mut completed = String::from(&before[..start]);
+                        completed.push_str(prefix);
+                        if candidates.len() == 1 {
+                            completed.push(' ');
+                        }
+                        let after: String = line.buffer[line.cursor..].iter().collect();
+                        line.set(&(completed.clone() + &after));
+                        line.cursor = completed.chars().count();
+                    } else if candidates.len() > 1 {
+                        line.clear(&mut stdout)?;
+                        write!(stdout, "{}\r\n", candidates.join("  "))?;
+                    }
+                }
+                _ => (),
+            },
+        }
+
+        line.draw(&mut stdout)?;
+    }
+}
+
+/// Logger which prints above the console prompt.
+struct ConsoleLogger {
+    level: Level,
+    colored: bool,
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = LINE.lock();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        if line.active {
+            let _ = line.clear(&mut out);
+        }
+
+        let _ = write!(
+            out,
+            "{} ",
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        );
+        if self.colored {
+            let color = match record.level() {
+                Level::Error => Color::Red,
+                Level::Warn => Color::Yellow,
+                Level::Info => Color::Green,
+                Level::Debug => Color::Cyan,
+                Level::Trace => Color::Magenta,
+            };
+            let _ = queue!(out, SetForegroundColor(color));
+            let _ = write!(out, "{:<5}", record.level());
+            let _ = queue!(out, ResetColor);
+        } else {
+            let _ = write!(out, "{:<5}", record.level());
+        }
+        let _ = write!(out, " [{}] {}", record.target(), record.args());
+
+        // Raw mode doesn't translate newlines.
+        if line.active {
+            let _ = write!(out, "\r\n");
+            let _ = line.draw(&mut out);
+        } else {
+            let _ = writeln!(out);
+        }
+        let _ = out.flush();
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Parses a line entered on the console into a command.
+/// The leading slash is optional.
+pub fn parse_line(console: Entity, line: &str) -> Option<CommandEvent> {
+    let line = line.trim();
+    if line.starts_with('/') {
+        CommandEvent::parse(console, line)
+    } else {
+        CommandEvent::parse(console, &format!("/{}", line))
+    }
+}
+
+/// System which executes commands entered on the console
+/// and updates the names available for tab completion.
+#[derive(Default)]
+pub struct ConsoleSystem {
+    console: Option<Entity>,
+    ticks: u64,
+}
+
+impl<'a> System<'a> for ConsoleSystem {
+    type SystemData = (
+        Option<Read<'a, Console>>,
+        Write<'a, EventChannel<CommandEvent>>,
+        Read<'a, CommandRegistry>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, PlayerComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (console, mut command_events, registry, nameds, players) = data;
+        let console = match console {
+            Some(console) => console,
+            None => return,
+        };
+        let entity = self.console.unwrap();
+
+        while let Ok(line) = console.lines.try_recv() {
+            if let Some(command) = parse_line(entity, &line) {
+                info!(
+                    "{} issued server command: /{}",
+                    CONSOLE_NAME,
+                    line.trim().trim_start_matches('/')
+                );
+                command_events.single_write(command);
+            }
+        }
+
+        // Completions don't need to be precise,
+        // so only update them once per second.
+        if self.ticks % TPS == 0 {
+            let completions = Completions {
+                commands: registry.names().map(String::from).collect(),
+                players: (&nameds, &players)
+                    .join()
+                    .map(|(named, _)| named.display_name.clone())
+                    .collect(),
+            };
+            *console.completions.lock() = completions;
+        }
+        self.ticks += 1;
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        world.register::<ConsoleComponent>();
+        self.console = Some(
+            world
+                .create_entity()
+                .with(ConsoleComponent::default())
+                .build(),
+        );
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ConsoleSystem::default(), CONSOLE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+
+    fn completions() -> Completions {
+        Completions {
+            commands: vec![
+                String::from("stop"),
+                String::from("timings"),
+                String::from("tps"),
+            ],
+            players: vec![String::from("caelunshun"), String::from("Steve")],
+        }
+    }
+
+    #[test]
+    fn test_complete() {
+        let completions = completions();
+
+        assert_eq!(completions.complete("t"), (0, vec!["timings", "tps"]));
+        assert_eq!(completions.complete("/st"), (1, vec!["stop"]));
+        assert_eq!(completions.complete("tell s"), (5, vec!["Steve"]));
+        assert_eq!(
+            completions.complete("tell "),
+            (5, vec!["Steve", "caelunshun"])
+        );
+        assert!(completions.complete("x").1.is_empty());
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(&["timings", "tps"]), "t");
+        assert_eq!(common_prefix(&["reload", "reset"]), "re");
+        assert_eq!(common_prefix(&["stop"]), "stop");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let (mut w, _) = t::builder().build();
+        let console = w.create_entity().build();
+
+        let command = parse_line(console, "stop").unwrap();
+        assert_eq!(command.name, "stop");
+        let command = parse_line(console, " /timings report").unwrap();
+        assert_eq!(command.name, "timings");
+        assert_eq!(command.args, vec!["report"]);
+        assert!(parse_line(console, "").is_none());
+    }
+
+    #[test]
+    fn test_console_system() {
+        let (mut w, mut d) = t::builder().with(ConsoleSystem::default(), "").build();
+        let mut reader = t::reader::<CommandEvent>(&w);
+        w.fetch_mut::<CommandRegistry>().register("stop");
+        let player = t::add_player(&mut w);
+
+        let (sender, lines) = crossbeam::unbounded();
+        let completions = Arc::new(Mutex::new(Completions::default()));
+        w.insert(Console {
+            lines,
+            completions: Arc::clone(&completions),
+        });
+
+        sender.send(String::from("stop")).unwrap();
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<CommandEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "stop");
+        assert!(w
+            .read_component::<ConsoleComponent>()
+            .contains(events[0].sender));
+
+        let completions = completions.lock();
+        assert_eq!(completions.commands, vec!["stop"]);
+        let name = &w
+            .read_component::<NamedComponent>()
+            .get(player.entity)
+            .unwrap()
+            .display_name;
+        assert_eq!(completions.players, vec![name.clone()]);
+    }
+}
@@ -0,0 +1,2592 @@
+//! Containers: blocks, such as chests, furnaces and shulker boxes,
+//! which hold items and are opened in a window.
+//!
+//! The items of a container are stored in the `Items` list of
+//! its block entity in the vanilla format, so they are saved
+//! with the chunk. Clicks in a container window are applied to
+//! the block entity directly, which means that players viewing
+//! the same container share its contents. A player has at most
+//! one container window open, described by their
+//! `OpenContainerComponent`. Both halves of a double chest are
+//! opened in the same window; see the `chest` module.
+//!
+//! Some containers, such as beacons, don't store items. The
+//! slots of their windows belong to the player viewing them,
+//! and their items are given back when the window is closed.
+//!
+//! Containers generated with a loot table, such as the chests of
+//! vanilla dungeons, store the table in their `LootTable` tag. They
+//! are filled from it when first opened or broken; see `fill_from_loot_table`.
+//!
+//! Shulker boxes keep their contents when broken: the dropped
+//! item stores them in its `BlockEntityTag`, and they are moved
+//! back into the block entity when the box is placed again.
+//! Shulker boxes can't be put into shulker boxes.
+//!
+//! Crafting tables are containers without a block entity. Like
+//! beacons, their slots belong to the player using them. Clicks in
+//! the player's own inventory window are handled here as well, since
+//! its crafting grid works like that of crafting tables; see the
+//! `crafting` module.
+//!
+//! All click modes are supported in windows: plain clicks,
+//! shift-clicks, number key swaps, cloning stacks in creative mode,
+//! dropping items, dragging and collecting items by double-clicking.
+//! When a click is refused, or when the client saw a different item
+//! in the clicked slot, the window is sent again, and the player's
+//! clicks in it are ignored until they acknowledge the refusal.
+
+use crate::beacon::{self, is_payment_item, load_beacon};
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::chest::chest_halves;
+use crate::crafting::{self, CRAFTING_RESULT};
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::furnace::{self, is_fuel, load_furnace, FURNACE_FUEL, FURNACE_INPUT};
+use crate::loot::{drop_at_block, LootContext, LootTables};
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::player::{armor_slot, InventoryComponent, InventoryUpdateEvent, PlayerItemDropEvent};
+use crate::recipe::RecipeRegistry;
+use crate::systems::{
+    BEACON_EFFECT, CLICK_CONFIRM, CONTAINER_BREAK, CONTAINER_CLICK, CONTAINER_CLOSE,
+    CONTAINER_OPEN, INVENTORY_WINDOW,
+};
+use crate::timings::DispatcherBuilderExt;
+use crate::TickCount;
+use feather_core::inventory::{
+    max_size, Inventory, SlotIndex, HOTBAR_SIZE, INVENTORY_SIZE, SLOT_ARMOR_MAX, SLOT_ARMOR_MIN,
+    SLOT_INVENTORY_OFFSET, SLOT_OFFHAND,
+};
+use feather_core::item_tag::BlockEntityTag;
+use feather_core::nbt;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    ClickWindow, CloseWindowClientbound, CloseWindowServerbound, ConfirmTransactionClientbound,
+    ConfirmTransactionServerbound, OpenWindow, SetSlot, WindowItems,
+};
+use feather_core::player_data::InventorySlot;
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, BlockExt, Gamemode, Item, ItemStack, PacketType};
+use feather_item_block::BlockToItem;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use shrev::{EventChannel, ReaderId};
+use smallvec::SmallVec;
+use specs::storage::MaskedStorage;
+use specs::{
+    Component, DispatcherBuilder, Entities, Entity, HashMapStorage, Join, LazyUpdate, Read,
+    ReadStorage, Storage, System, VecStorage, Write, WriteStorage,
+};
+use std::collections::HashMap;
+use std::mem;
+use std::ops::{Deref, Range};
+
+/// The ID of container windows. Since players have at most one
+/// container window open, the same ID is used for all of them.
+pub const WINDOW_ID: u8 = 1;
+
+/// The ID of the player's inventory window.
+pub const INVENTORY_WINDOW_ID: u8 = 0;
+
+/// The number of slots in a chest, or in
+/// one half of a double chest.
+pub const CHEST_SIZE: usize = 27;
+
+/// The number of slots in a furnace: the
+/// input, fuel and output slots.
+pub const FURNACE_SIZE: usize = 3;
+
+/// The number of slots in a shulker box.
+pub const SHULKER_BOX_SIZE: usize = 27;
+
+/// The number of slots in a hopper.
+pub const HOPPER_SIZE: usize = 5;
+
+/// The number of slots in a dispenser or dropper.
+pub const DISPENSER_SIZE: usize = 9;
+
+/// The number of slots in a beacon, which
+/// only has a slot for the payment item.
+pub const BEACON_SIZE: usize = 1;
+
+/// The number of slots in a crafting table:
+/// the result and the 3x3 crafting grid.
+pub const CRAFTING_TABLE_SIZE: usize = 10;
+
+/// The slot of the Click Window packet for
+/// clicks outside of the window.
+const SLOT_OUTSIDE: i16 = -999;
+
+/// The number of player inventory slots shown
+/// below the container in a container window.
+const PLAYER_SLOTS: usize = INVENTORY_SIZE + HOTBAR_SIZE;
+
+/// The maximum squared distance from the center of a
+/// container within which a player can keep using it.
+const MAX_DISTANCE_SQUARED: f64 = 64.0;
+
+/// The kinds of containers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContainerKind {
+    Chest,
+    Furnace,
+    ShulkerBox,
+    Beacon,
+    Hopper,
+    Dispenser,
+    Dropper,
+    CraftingTable,
+}
+
+impl ContainerKind {
+    /// Returns the kind of container of a block,
+    /// or `None` if it isn't a container.
+    pub fn of(block: Block) -> Option<Self> {
+        if let Block::Chest(_) = block {
+            Some(ContainerKind::Chest)
+        } else if let Block::Furnace(_) = block {
+            Some(ContainerKind::Furnace)
+        } else if is_shulker_box(block) {
+            Some(ContainerKind::ShulkerBox)
+        } else if block == Block::Beacon {
+            Some(ContainerKind::Beacon)
+        } else if let Block::Hopper(_) = block {
+            Some(ContainerKind::Hopper)
+        } else if let Block::Dispenser(_) = block {
+            Some(ContainerKind::Dispenser)
+        } else if let Block::Dropper(_) = block {
+            Some(ContainerKind::Dropper)
+        } else if block == Block::CraftingTable {
+            Some(ContainerKind::CraftingTable)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of slots of this kind of container.
+    pub fn size(self) -> usize {
+        match self {
+            ContainerKind::Chest => CHEST_SIZE,
+            ContainerKind::Furnace => FURNACE_SIZE,
+            ContainerKind::ShulkerBox => SHULKER_BOX_SIZE,
+            ContainerKind::Beacon => BEACON_SIZE,
+            ContainerKind::Hopper => HOPPER_SIZE,
+            ContainerKind::Dispenser | ContainerKind::Dropper => DISPENSER_SIZE,
+            ContainerKind::CraftingTable => CRAFTING_TABLE_SIZE,
+        }
+    }
+
+    /// Returns whether this kind of container keeps its items
+    /// in its block entity. The slots of other containers
+    /// are emptied when their window is closed.
+    pub fn stores_items(self) -> bool {
+        match self {
+            ContainerKind::Beacon | ContainerKind::CraftingTable => false,
+            _ => true,
+        }
+    }
+
+    /// Returns whether this kind of container has a block entity.
+    pub fn has_block_entity(self) -> bool {
+        self != ContainerKind::CraftingTable
+    }
+
+    /// Returns the maximum size of the stacks
+    /// in the slots of this kind of container.
+    pub fn slot_limit(self) -> u8 {
+        match self {
+            ContainerKind::Beacon => 1,
+            _ => 64,
+        }
+    }
+
+    /// Returns the ID of the block entity of this kind of container.
+    /// Crafting tables, which don't have one, use their block ID.
+    pub fn block_entity_id(self) -> &'static str {
+        match self {
+            ContainerKind::Chest => "minecraft:chest",
+            ContainerKind::Furnace => "minecraft:furnace",
+            ContainerKind::ShulkerBox => "minecraft:shulker_box",
+            ContainerKind::Beacon => "minecraft:beacon",
+            ContainerKind::Hopper => "minecraft:hopper",
+            ContainerKind::Dispenser => "minecraft:dispenser",
+            ContainerKind::Dropper => "minecraft:dropper",
+            ContainerKind::CraftingTable => "minecraft:crafting_table",
+        }
+    }
+
+    fn window_type(self) -> &'static str {
+        match self {
+            ContainerKind::Chest => "minecraft:chest",
+            ContainerKind::Furnace => "minecraft:furnace",
+            ContainerKind::ShulkerBox => "minecraft:shulker_box",
+            ContainerKind::Beacon => "minecraft:beacon",
+            ContainerKind::Hopper => "minecraft:hopper",
+            ContainerKind::Dispenser => "minecraft:dispenser",
+            ContainerKind::Dropper => "minecraft:dropper",
+            ContainerKind::CraftingTable => "minecraft:crafting_table",
+        }
+    }
+
+    fn default_title(self, parts: usize) -> &'static str {
+        match self {
+            ContainerKind::Chest if parts > 1 => "container.chestDouble",
+            ContainerKind::Chest => "container.chest",
+            ContainerKind::Furnace => "container.furnace",
+            ContainerKind::ShulkerBox => "container.shulkerBox",
+            ContainerKind::Beacon => "container.beacon",
+            ContainerKind::Hopper => "container.hopper",
+            ContainerKind::Dispenser => "container.dispenser",
+            ContainerKind::Dropper => "container.dropper",
+            ContainerKind::CraftingTable => "container.crafting",
+        }
+    }
+
+    /// Returns whether the given item may be put
+    /// into a slot of this kind of container.
+    pub fn accepts(self, slot: usize, item: Item) -> bool {
+        match self {
+            ContainerKind::Chest
+            | ContainerKind::Hopper
+            | ContainerKind::Dispenser
+            | ContainerKind::Dropper => true,
+            ContainerKind::Furnace => match slot {
+                FURNACE_INPUT => true,
+                FURNACE_FUEL => is_fuel(item) || item == Item::Bucket,
+                _ => false,
+            },
+            ContainerKind::ShulkerBox => !is_shulker_box_item(item),
+            ContainerKind::Beacon => is_payment_item(item),
+            ContainerKind::CraftingTable => slot != CRAFTING_RESULT,
+        }
+    }
+}
+
+/// Returns whether the given block is a shulker box.
+pub fn is_shulker_box(block: Block) -> bool {
+    block.to_name_and_props().0.ends_with("shulker_box")
+}
+
+/// Returns whether the given item is a shulker box.
+pub fn is_shulker_box_item(item: Item) -> bool {
+    item.identifier().ends_with("shulker_box")
+}
+
+/// Returns whether a container can't be opened because its
+/// lid is blocked: by the block a shulker box faces, or by the
+/// block above a chest, if that block is solid.
+fn is_lid_blocked(chunk_map: &ChunkMap, kind: ContainerKind, pos: BlockPosition) -> bool {
+    let block = match chunk_map.block_at(pos) {
+        Some(block) => block,
+        None => return false,
+    };
+    let facing = block
+        .to_name_and_props()
+        .1
+        .into_iter()
+        .find(|(name, _)| *name == "facing")
+        .map(|(_, value)| value);
+
+    let offset = match (kind, facing.as_ref().map(String::as_str)) {
+        (ContainerKind::Chest, _) => BlockPosition::new(0, 1, 0),
+        (ContainerKind::ShulkerBox, Some("down")) => BlockPosition::new(0, -1, 0),
+        (ContainerKind::ShulkerBox, Some("north")) => BlockPosition::new(0, 0, -1),
+        (ContainerKind::ShulkerBox, Some("south")) => BlockPosition::new(0, 0, 1),
+        (ContainerKind::ShulkerBox, Some("west")) => BlockPosition::new(-1, 0, 0),
+        (ContainerKind::ShulkerBox, Some("east")) => BlockPosition::new(1, 0, 0),
+        (ContainerKind::ShulkerBox, _) => BlockPosition::new(0, 1, 0),
+        _ => return false,
+    };
+
+    chunk_map
+        .block_at(pos + offset)
+        .map_or(false, |block| block.is_solid())
+}
+
+/// The block entity of a container, as saved in chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContainerEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    #[serde(rename = "Items", default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<InventorySlot>,
+    /// The custom name of the container as a JSON text component.
+    #[serde(
+        rename = "CustomName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_name: Option<String>,
+    /// The loot table the container is to be filled from.
+    #[serde(rename = "LootTable", default, skip_serializing_if = "Option::is_none")]
+    pub loot_table: Option<String>,
+    /// The seed used to generate the loot, or 0 for a random seed.
+    #[serde(rename = "LootTableSeed", default, skip_serializing_if = "is_zero")]
+    pub loot_table_seed: i64,
+    /// The other tags of the block entity, such as
+    /// the progress of a furnace, which are kept as is.
+    #[serde(flatten)]
+    pub other: HashMap<String, nbt::Value>,
+}
+
+impl ContainerEntity {
+    pub fn new(kind: ContainerKind, pos: BlockPosition) -> Self {
+        Self {
+            id: kind.block_entity_id().to_string(),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the items of this container, indexed by slot.
+    /// Items in slots outside of the container are ignored.
+    pub fn slots(&self, size: usize) -> Vec<Option<ItemStack>> {
+        let mut slots = vec![None; size];
+        for item in &self.items {
+            let stack = item.to_stack();
+            if item.slot >= 0
+                && (item.slot as usize) < size
+                && stack.ty != Item::Air
+                && stack.amount > 0
+            {
+                slots[item.slot as usize] = Some(stack);
+            }
+        }
+        slots
+    }
+
+    /// Replaces the items of this container.
+    pub fn set_slots(&mut self, slots: &[Option<ItemStack>]) {
+        self.items = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.as_ref()
+                    .map(|stack| InventorySlot::from_container_index(index, stack))
+            })
+            .collect();
+    }
+}
+
+fn is_zero(x: &i64) -> bool {
+    *x == 0
+}
+
+/// Fills the container at the given position from its loot
+/// table, if it has one, and removes the table from it. The
+/// generated stacks are put into random empty slots; those
+/// which don't fit are discarded, as in vanilla.
+pub fn fill_from_loot_table(
+    chunk_map: &mut ChunkMap,
+    kind: ContainerKind,
+    pos: BlockPosition,
+    tables: &LootTables,
+) {
+    let mut entity = load_container(chunk_map, kind, pos);
+    let table = match entity.loot_table.take() {
+        Some(table) => table,
+        None => return,
+    };
+    let mut rng = match mem::take(&mut entity.loot_table_seed) {
+        0 => StdRng::from_entropy(),
+        seed => StdRng::seed_from_u64(seed as u64),
+    };
+
+    let mut slots = entity.slots(kind.size());
+    let mut empty: Vec<usize> = (0..slots.len()).filter(|&i| slots[i].is_none()).collect();
+    empty.shuffle(&mut rng);
+    let stacks = tables.generate(&table, &LootContext::default(), &mut rng);
+    for (slot, stack) in empty.into_iter().zip(stacks) {
+        slots[slot] = Some(stack);
+    }
+
+    entity.set_slots(&slots);
+    store_container(chunk_map, pos, &entity);
+}
+
+/// Returns the block entity of the container at the given position.
+/// Containers which don't have a block entity yet, such as those
+/// placed using WorldEdit, are empty.
+pub fn load_container(
+    chunk_map: &ChunkMap,
+    kind: ContainerKind,
+    pos: BlockPosition,
+) -> ContainerEntity {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value(data.clone()).ok())
+        .unwrap_or_else(|| ContainerEntity::new(kind, pos))
+}
+
+/// Stores the block entity of a container.
+pub fn store_container(chunk_map: &mut ChunkMap, pos: BlockPosition, entity: &ContainerEntity) {
+    match nbt::to_value(entity) {
+        Ok(data) => {
+            chunk_map.set_block_entity_at(pos, data).ok();
+        }
+        Err(e) => warn!("Failed to store container at {:?}: {}", pos, e),
+    }
+}
+
+/// Returns the positions of the blocks holding the slots of the
+/// container at the given position, in the order of their slots:
+/// both halves of a double chest, or only the given position.
+pub fn container_parts(chunk_map: &ChunkMap, pos: BlockPosition) -> Vec<BlockPosition> {
+    match chest_halves(chunk_map, pos) {
+        Some(halves) => halves.to_vec(),
+        None => vec![pos],
+    }
+}
+
+/// Returns the items of the container at the given position,
+/// indexed by window slot. For double chests, these are the
+/// items of both halves.
+pub fn load_slots(
+    chunk_map: &ChunkMap,
+    kind: ContainerKind,
+    pos: BlockPosition,
+) -> Vec<Option<ItemStack>> {
+    container_parts(chunk_map, pos)
+        .into_iter()
+        .flat_map(|part| load_container(chunk_map, kind, part).slots(kind.size()))
+        .collect()
+}
+
+/// Replaces the items of the container at the given
+/// position, indexed by window slot as by `load_slots`.
+pub fn store_slots(
+    chunk_map: &mut ChunkMap,
+    kind: ContainerKind,
+    pos: BlockPosition,
+    slots: &[Option<ItemStack>],
+) {
+    let parts = container_parts(chunk_map, pos);
+    for (part, slots) in parts.into_iter().zip(slots.chunks(kind.size())) {
+        let mut entity = load_container(chunk_map, kind, part);
+        entity.set_slots(slots);
+        store_container(chunk_map, part, &entity);
+    }
+}
+
+/// Creates the block entity of a container placed by a player
+/// from the placed item, moving the item's contents and custom
+/// name into the container.
+pub fn place_container(
+    chunk_map: &mut ChunkMap,
+    kind: ContainerKind,
+    pos: BlockPosition,
+    item: &ItemStack,
+) {
+    let mut entity = ContainerEntity::new(kind, pos);
+    if let Some(tag) = item.tag().and_then(|tag| tag.block_entity_tag.as_ref()) {
+        entity.items = tag.items.clone();
+    }
+    entity.custom_name = item.custom_name().map(str::to_string);
+    store_container(chunk_map, pos, &entity);
+}
+
+/// Returns the item dropped by a broken shulker box, which keeps
+/// the contents and custom name of the box.
+pub fn shulker_box_item(block: Block, entity: ContainerEntity) -> Option<ItemStack> {
+    let mut stack = ItemStack::new(block.to_item()?, 1);
+    if !entity.items.is_empty() {
+        stack.tag_mut().block_entity_tag = Some(BlockEntityTag {
+            items: entity.items,
+            patterns: vec![],
+            other: HashMap::new(),
+        });
+    }
+    if entity.custom_name.is_some() {
+        stack.set_custom_name(entity.custom_name);
+    }
+    Some(stack)
+}
+
+/// Sends the given slots of the container at the given position
+/// to the players viewing it. `slots` are all the slots of the
+/// container, indexed as by `load_slots`.
+pub fn send_slots_to_viewers<D>(
+    open_containers: &Storage<OpenContainerComponent, D>,
+    networks: &ReadStorage<NetworkComponent>,
+    pos: BlockPosition,
+    slots: &[Option<ItemStack>],
+    changed_slots: &[usize],
+) where
+    D: Deref<Target = MaskedStorage<OpenContainerComponent>>,
+{
+    for (open, network) in (open_containers, networks).join() {
+        if open.pos != pos || !open.kind.stores_items() {
+            continue;
+        }
+        for slot in changed_slots {
+            send_packet_to_player(
+                network,
+                SetSlot::new(WINDOW_ID as i8, *slot as i16, slots[*slot].clone()),
+            );
+        }
+    }
+}
+
+/// Component for players who have a container window open.
+#[derive(Debug, Clone)]
+pub struct OpenContainerComponent {
+    /// The position of the container. For double
+    /// chests, this is the position of the right half.
+    pub pos: BlockPosition,
+    pub kind: ContainerKind,
+    /// The number of container slots in the window.
+    pub size: usize,
+    /// The item held by the player's cursor.
+    pub cursor: Option<ItemStack>,
+    /// The slots of the window, if the
+    /// container doesn't store its items.
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+impl Component for OpenContainerComponent {
+    type Storage = VecStorage<Self>;
+}
+
+/// The kinds of windows in which clicks are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowKind {
+    /// The player's inventory window.
+    Inventory,
+    Container(ContainerKind),
+}
+
+impl WindowKind {
+    /// Returns the window slots of the crafting grid of this
+    /// kind of window with the width of the grid, or `None`
+    /// if it doesn't have a crafting grid.
+    pub fn crafting_grid(self) -> Option<(Range<usize>, usize)> {
+        match self {
+            WindowKind::Inventory => Some((1..5, 2)),
+            WindowKind::Container(ContainerKind::CraftingTable) => Some((1..10, 3)),
+            WindowKind::Container(_) => None,
+        }
+    }
+}
+
+/// The mouse buttons with which items are dragged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DragKind {
+    /// Spreads the held stack evenly over the slots.
+    Left,
+    /// Puts one item into each slot.
+    Right,
+    /// Fills each slot with a full stack without using
+    /// up the held stack. Only allowed in creative mode.
+    Middle,
+}
+
+/// A drag in progress, with the slots dragged over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Drag {
+    pub kind: DragKind,
+    pub slots: Vec<usize>,
+}
+
+/// The slots of a window. A container window has the container's
+/// slots, followed by the player's main inventory and hotbar. The
+/// slots of the player's inventory window are those of their
+/// inventory: the crafting grid, the armor, the main inventory,
+/// the hotbar and the off-hand.
+pub struct Window<'a> {
+    pub kind: WindowKind,
+    /// The slots of the container, which
+    /// are empty for the inventory window.
+    pub slots: &'a mut [Option<ItemStack>],
+    pub inventory: &'a mut Inventory,
+    /// Whether the player is in creative mode,
+    /// which allows cloning stacks.
+    pub creative: bool,
+    /// The drag in progress in this window.
+    pub drag: Option<Drag>,
+}
+
+impl<'a> Window<'a> {
+    /// Returns the number of slots before the player's
+    /// main inventory.
+    fn container_len(&self) -> usize {
+        match self.kind {
+            WindowKind::Inventory => SLOT_INVENTORY_OFFSET,
+            WindowKind::Container(_) => self.slots.len(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self.kind {
+            // The off-hand comes after the hotbar.
+            WindowKind::Inventory => SLOT_OFFHAND + 1,
+            WindowKind::Container(_) => self.slots.len() + PLAYER_SLOTS,
+        }
+    }
+
+    fn container_slots(&self) -> Range<usize> {
+        0..self.container_len()
+    }
+
+    /// Returns the window slots of the player's main inventory and hotbar.
+    pub fn player_slots(&self) -> Range<usize> {
+        self.container_len()..self.container_len() + PLAYER_SLOTS
+    }
+
+    /// Returns the window slot of the hotbar slot with the given index.
+    pub fn hotbar_slot(&self, index: usize) -> usize {
+        self.container_len() + INVENTORY_SIZE + index
+    }
+
+    fn is_container_slot(&self, slot: usize) -> bool {
+        match self.kind {
+            WindowKind::Inventory => false,
+            WindowKind::Container(_) => slot < self.slots.len(),
+        }
+    }
+
+    /// Returns the inventory slot of a window slot
+    /// which doesn't belong to the container.
+    fn inventory_index(&self, slot: usize) -> SlotIndex {
+        slot - self.container_len() + SLOT_INVENTORY_OFFSET
+    }
+
+    pub fn get(&self, slot: usize) -> Option<ItemStack> {
+        if self.is_container_slot(slot) {
+            self.slots[slot].clone()
+        } else {
+            self.inventory.item_at(self.inventory_index(slot)).cloned()
+        }
+    }
+
+    pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) {
+        let stack = stack.filter(|stack| stack.amount > 0);
+        if self.is_container_slot(slot) {
+            self.slots[slot] = stack;
+        } else {
+            let index = self.inventory_index(slot);
+            match stack {
+                Some(stack) => self.inventory.set_item_at(index, stack),
+                None => {
+                    self.inventory.clear_item_at(index);
+                }
+            }
+        }
+    }
+
+    fn accepts(&self, slot: usize, stack: &ItemStack) -> bool {
+        match self.kind {
+            WindowKind::Container(kind) if slot < self.slots.len() => kind.accepts(slot, stack.ty),
+            WindowKind::Inventory if slot == CRAFTING_RESULT => false,
+            WindowKind::Inventory if slot >= SLOT_ARMOR_MIN && slot <= SLOT_ARMOR_MAX => {
+                armor_slot(stack.ty) == Some(slot)
+            }
+            _ => true,
+        }
+    }
+
+    fn is_crafting_result(&self, slot: usize) -> bool {
+        self.kind.crafting_grid().is_some() && slot == CRAFTING_RESULT
+    }
+
+    /// Returns whether a stack can be dragged into a slot.
+    fn can_drag_into(&self, slot: usize, stack: &ItemStack) -> bool {
+        self.accepts(slot, stack)
+            && self
+                .get(slot)
+                .map_or(true, |existing| existing.stacks_with(stack))
+    }
+
+    /// Returns the slots into which the given item is moved
+    /// by shift-clicking it in the player's main inventory
+    /// or hotbar.
+    fn shift_click_slots(&self, slot: usize, item: Item) -> Range<usize> {
+        // Windows without container slots for the item move
+        // it between the hotbar and the main inventory.
+        let other_part = if slot >= self.hotbar_slot(0) {
+            self.container_len()..self.hotbar_slot(0)
+        } else {
+            self.hotbar_slot(0)..self.hotbar_slot(HOTBAR_SIZE)
+        };
+
+        match self.kind {
+            WindowKind::Container(ContainerKind::Furnace) if is_fuel(item) => {
+                FURNACE_FUEL..FURNACE_FUEL + 1
+            }
+            WindowKind::Container(ContainerKind::Furnace) => FURNACE_INPUT..FURNACE_INPUT + 1,
+            WindowKind::Container(ContainerKind::Beacon)
+                if is_payment_item(item) && self.get(0).is_none() =>
+            {
+                0..1
+            }
+            // Armor is put on if its slot is empty.
+            WindowKind::Inventory => match armor_slot(item) {
+                Some(armor) if self.get(armor).is_none() => armor..armor + 1,
+                _ => other_part,
+            },
+            WindowKind::Container(ContainerKind::Beacon)
+            | WindowKind::Container(ContainerKind::CraftingTable) => other_part,
+            WindowKind::Container(_) => self.container_slots(),
+        }
+    }
+
+    /// Returns the maximum size of a stack of the given item in a slot.
+    pub fn limit(&self, slot: usize, item: Item) -> u8 {
+        match self.kind {
+            WindowKind::Container(kind) if slot < self.slots.len() => {
+                max_size(item).min(kind.slot_limit())
+            }
+            WindowKind::Inventory if slot >= SLOT_ARMOR_MIN && slot <= SLOT_ARMOR_MAX => 1,
+            _ => max_size(item),
+        }
+    }
+
+    /// Returns whether all of a stack can be
+    /// moved into the given slots.
+    pub fn fits(&self, stack: &ItemStack, range: Range<usize>) -> bool {
+        let room: u32 = range
+            .filter(|slot| self.accepts(*slot, stack))
+            .map(|slot| match self.get(slot) {
+                Some(existing) if existing.stacks_with(stack) => {
+                    u32::from(self.limit(slot, stack.ty).saturating_sub(existing.amount))
+                }
+                Some(_) => 0,
+                None => u32::from(self.limit(slot, stack.ty)),
+            })
+            .sum();
+        room >= u32::from(stack.amount)
+    }
+
+    /// Moves as much of a stack as possible into the given slots,
+    /// first onto matching stacks and then into empty slots.
+    /// Returns the amount which could not be moved.
+    pub fn move_stack(&mut self, stack: &ItemStack, range: Range<usize>, reverse: bool) -> u8 {
+        let slots: Vec<usize> = if reverse {
+            range.rev().collect()
+        } else {
+            range.collect()
+        };
+        let mut remaining = stack.amount;
+
+        for &slot in &slots {
+            if remaining == 0 {
+                break;
+            }
+            let max = self.limit(slot, stack.ty);
+            if let Some(existing) = self.get(slot) {
+                if existing.stacks_with(stack) && existing.amount < max && self.accepts(slot, stack)
+                {
+                    let moved = remaining.min(max - existing.amount);
+                    self.set(slot, Some(existing.with_amount(existing.amount + moved)));
+                    remaining -= moved;
+                }
+            }
+        }
+
+        for &slot in &slots {
+            if remaining == 0 {
+                break;
+            }
+            if self.get(slot).is_none() && self.accepts(slot, stack) {
+                let moved = remaining.min(self.limit(slot, stack.ty));
+                self.set(slot, Some(stack.with_amount(moved)));
+                remaining -= moved;
+            }
+        }
+
+        remaining
+    }
+}
+
+/// Applies a click in a window. Returns the items
+/// thrown out of the window, or `Err` if the click is refused.
+///
+/// When a click is refused, neither the slots of the window nor
+/// the cursor are modified, but any drag in progress is cancelled.
+fn click(
+    window: &mut Window,
+    cursor: &mut Option<ItemStack>,
+    slot: i16,
+    button: u8,
+    mode: i32,
+) -> Result<Vec<ItemStack>, ()> {
+    // Any other click cancels a drag in progress.
+    if mode != 5 {
+        window.drag = None;
+    }
+
+    match mode {
+        // Left or right click
+        0 => {
+            if slot == SLOT_OUTSIDE {
+                let held = match cursor.take() {
+                    Some(held) => held,
+                    None => return Ok(vec![]),
+                };
+                return match button {
+                    0 => Ok(vec![held]),
+                    1 => {
+                        if held.amount > 1 {
+                            *cursor = Some(held.with_amount(held.amount - 1));
+                        }
+                        Ok(vec![held.with_amount(1)])
+                    }
+                    _ => {
+                        *cursor = Some(held);
+                        Err(())
+                    }
+                };
+            }
+
+            let slot = window_slot(window, slot)?;
+            if button > 1 {
+                return Err(());
+            }
+            let right = button == 1;
+
+            match (cursor.clone(), window.get(slot)) {
+                (None, None) => (),
+                (None, Some(stack)) => {
+                    let taken = if right {
+                        (stack.amount + 1) / 2
+                    } else {
+                        stack.amount
+                    };
+                    window.set(slot, Some(stack.with_amount(stack.amount - taken)));
+                    *cursor = Some(stack.with_amount(taken));
+                }
+                (Some(held), None) => {
+                    if !window.accepts(slot, &held) {
+                        return Err(());
+                    }
+                    let placed = if right {
+                        1
+                    } else {
+                        held.amount.min(window.limit(slot, held.ty))
+                    };
+                    window.set(slot, Some(held.with_amount(placed)));
+                    *cursor = Some(held.with_amount(held.amount - placed)).filter(|s| s.amount > 0);
+                }
+                // Clicking slots which don't accept items, such as
+                // the output of a furnace, takes their items.
+                (Some(held), Some(stack))
+                    if held.stacks_with(&stack) && !window.accepts(slot, &held) =>
+                {
+                    let moved = stack
+                        .amount
+                        .min(max_size(held.ty).saturating_sub(held.amount));
+                    window.set(slot, Some(stack.with_amount(stack.amount - moved)));
+                    *cursor = Some(held.with_amount(held.amount + moved));
+                }
+                (Some(held), Some(stack)) if held.stacks_with(&stack) => {
+                    let room = window.limit(slot, stack.ty).saturating_sub(stack.amount);
+                    let wanted = if right { 1 } else { held.amount };
+                    let moved = room.min(wanted);
+                    window.set(slot, Some(stack.with_amount(stack.amount + moved)));
+                    *cursor = Some(held.with_amount(held.amount - moved)).filter(|s| s.amount > 0);
+                }
+                (Some(held), Some(stack)) => {
+                    if !window.accepts(slot, &held) || held.amount > window.limit(slot, held.ty) {
+                        return Err(());
+                    }
+                    window.set(slot, Some(held));
+                    *cursor = Some(stack);
+                }
+            }
+
+            Ok(vec![])
+        }
+        // Shift-click
+        1 => {
+            let slot = window_slot(window, slot)?;
+            let stack = match window.get(slot) {
+                Some(stack) => stack,
+                None => return Ok(vec![]),
+            };
+
+            let remaining = if window.player_slots().contains(&slot) {
+                let range = window.shift_click_slots(slot, stack.ty);
+                window.move_stack(&stack, range, false)
+            } else {
+                let range = window.player_slots();
+                window.move_stack(&stack, range, true)
+            };
+            window.set(slot, Some(stack.with_amount(remaining)));
+
+            Ok(vec![])
+        }
+        // Number key, swapping with a hotbar slot
+        2 => {
+            let slot = window_slot(window, slot)?;
+            if button as usize >= HOTBAR_SIZE {
+                return Err(());
+            }
+            let hotbar_slot = window.hotbar_slot(button as usize);
+
+            let stack = window.get(slot);
+            let hotbar_stack = window.get(hotbar_slot);
+            if let Some(hotbar_stack) = &hotbar_stack {
+                if !window.accepts(slot, hotbar_stack)
+                    || hotbar_stack.amount > window.limit(slot, hotbar_stack.ty)
+                {
+                    return Err(());
+                }
+            }
+            window.set(slot, hotbar_stack);
+            window.set(hotbar_slot, stack);
+
+            Ok(vec![])
+        }
+        // Middle click, cloning a stack in creative mode
+        3 => {
+            let slot = window_slot(window, slot)?;
+            if let Some(stack) = window.get(slot) {
+                if window.creative && cursor.is_none() {
+                    *cursor = Some(stack.with_amount(max_size(stack.ty)));
+                }
+            }
+            Ok(vec![])
+        }
+        // Drop key, throwing one item or, with control,
+        // the whole stack out of a slot
+        4 => {
+            if slot == SLOT_OUTSIDE {
+                return Ok(vec![]);
+            }
+            let slot = window_slot(window, slot)?;
+            if cursor.is_some() {
+                return Ok(vec![]);
+            }
+            let stack = match window.get(slot) {
+                Some(stack) => stack,
+                None => return Ok(vec![]),
+            };
+            let dropped = if button == 0 { 1 } else { stack.amount };
+            window.set(slot, Some(stack.with_amount(stack.amount - dropped)));
+            Ok(vec![stack.with_amount(dropped)])
+        }
+        // Drag, with a packet when it starts, for each
+        // slot dragged over and when it ends
+        5 => {
+            let kind = match button / 4 {
+                0 => DragKind::Left,
+                1 => DragKind::Right,
+                2 if window.creative => DragKind::Middle,
+                _ => {
+                    window.drag = None;
+                    return Err(());
+                }
+            };
+            let held = match cursor.clone() {
+                Some(held) => held,
+                None => {
+                    window.drag = None;
+                    return Err(());
+                }
+            };
+            let drag = window.drag.take().filter(|drag| drag.kind == kind);
+
+            match (button % 4, drag) {
+                (0, _) if slot == SLOT_OUTSIDE => {
+                    window.drag = Some(Drag {
+                        kind,
+                        slots: vec![],
+                    });
+                    Ok(vec![])
+                }
+                (1, Some(mut drag)) => {
+                    let slot = window_slot(window, slot)?;
+                    // Left and right drags need an item for each slot.
+                    let enough =
+                        kind == DragKind::Middle || held.amount as usize > drag.slots.len();
+                    if enough && window.can_drag_into(slot, &held) && !drag.slots.contains(&slot) {
+                        drag.slots.push(slot);
+                    }
+                    window.drag = Some(drag);
+                    Ok(vec![])
+                }
+                (2, Some(ref drag)) if slot == SLOT_OUTSIDE && drag.slots.is_empty() => Ok(vec![]),
+                // A drag over a single slot is a click.
+                (2, Some(ref drag))
+                    if slot == SLOT_OUTSIDE
+                        && drag.slots.len() == 1
+                        && kind != DragKind::Middle =>
+                {
+                    let button = if kind == DragKind::Right { 1 } else { 0 };
+                    click(window, cursor, drag.slots[0] as i16, button, 0)
+                }
+                (2, Some(drag)) if slot == SLOT_OUTSIDE => {
+                    let count = drag.slots.len() as u8;
+                    let mut remaining = held.amount;
+                    for slot in drag.slots {
+                        if !window.can_drag_into(slot, &held) {
+                            continue;
+                        }
+                        let existing = window.get(slot).map_or(0, |stack| stack.amount);
+                        let room = window.limit(slot, held.ty).saturating_sub(existing);
+                        let placed = match kind {
+                            DragKind::Left => (held.amount / count).min(remaining),
+                            DragKind::Right => remaining.min(1),
+                            DragKind::Middle => room,
+                        }
+                        .min(room);
+                        if kind != DragKind::Middle {
+                            remaining -= placed;
+                        }
+                        window.set(slot, Some(held.with_amount(existing + placed)));
+                    }
+                    *cursor = Some(held.with_amount(remaining)).filter(|s| s.amount > 0);
+                    Ok(vec![])
+                }
+                _ => Err(()),
+            }
+        }
+        // Double click, collecting items matching
+        // the held stack onto the cursor
+        6 => {
+            let slot = window_slot(window, slot)?;
+            let held = match cursor.clone() {
+                Some(held) if window.get(slot).is_none() => held,
+                _ => return Ok(vec![]),
+            };
+            let max = max_size(held.ty);
+            let mut slots: Vec<usize> = (0..window.len())
+                .filter(|slot| !window.is_crafting_result(*slot))
+                .collect();
+            if button == 1 {
+                slots.reverse();
+            }
+
+            // Stacks which aren't full are collected first.
+            let mut amount = held.amount;
+            for &full_stacks in &[false, true] {
+                for &slot in &slots {
+                    if amount >= max {
+                        break;
+                    }
+                    let stack = continue_if_none!(window.get(slot));
+                    if !stack.stacks_with(&held) || (!full_stacks && stack.amount >= max) {
+                        continue;
+                    }
+                    let taken = stack.amount.min(max - amount);
+                    window.set(slot, Some(stack.with_amount(stack.amount - taken)));
+                    amount += taken;
+                }
+            }
+            *cursor = Some(held.with_amount(amount));
+            Ok(vec![])
+        }
+        _ => Err(()),
+    }
+}
+
+/// Applies the click of a Click Window packet like `click`,
+/// crafting items when the result slot of a crafting grid is
+/// clicked. The crafting result is updated after every click.
+///
+/// Plain clicks and shift-clicks are refused if the client
+/// saw a different item in the clicked slot, since the window
+/// is then out of sync.
+pub fn click_window(
+    window: &mut Window,
+    cursor: &mut Option<ItemStack>,
+    packet: &ClickWindow,
+    recipes: &RecipeRegistry,
+) -> Result<Vec<ItemStack>, ()> {
+    let slot = packet.slot as i16;
+    let (button, mode) = (packet.button, packet.mode);
+
+    if mode == 0 || mode == 1 {
+        if let Ok(slot) = window_slot(window, slot) {
+            if window.get(slot) != packet.clicked_item {
+                return Err(());
+            }
+        }
+    }
+
+    if window.kind.crafting_grid().is_none() {
+        return click(window, cursor, slot, button, mode);
+    }
+
+    let drops = match mode {
+        0 | 1 | 2 | 4 if slot == CRAFTING_RESULT as i16 => {
+            crafting::click_result(window, cursor, button, mode, recipes)?
+        }
+        _ => click(window, cursor, slot, button, mode)?,
+    };
+    crafting::update_result(window, recipes);
+    Ok(drops)
+}
+
+fn window_slot(window: &Window, slot: i16) -> Result<usize, ()> {
+    if slot >= 0 && (slot as usize) < window.len() {
+        Ok(slot as usize)
+    } else {
+        Err(())
+    }
+}
+
+/// Returns the slots of a container window as sent
+/// in the Window Items packet.
+fn window_items(slots: &[Option<ItemStack>], inventory: &Inventory) -> Vec<Option<ItemStack>> {
+    let player_slots =
+        &inventory.items()[SLOT_INVENTORY_OFFSET..SLOT_INVENTORY_OFFSET + PLAYER_SLOTS];
+    slots.iter().chain(player_slots).cloned().collect()
+}
+
+/// Moves the item held by the cursor of a player whose container
+/// window was closed back into their inventory, along with the
+/// items in the window if the container doesn't store them.
+/// Items which don't fit into the inventory are dropped.
+fn return_items(
+    player: Entity,
+    open: OpenContainerComponent,
+    inventory: &mut InventoryComponent,
+    update_events: &mut EventChannel<InventoryUpdateEvent>,
+    drop_events: &mut EventChannel<PlayerItemDropEvent>,
+) {
+    let mut window_slots = open.slots;
+    // The crafting result isn't given to the player.
+    if open.kind == ContainerKind::CraftingTable && !window_slots.is_empty() {
+        window_slots[CRAFTING_RESULT] = None;
+    }
+    let items: Vec<ItemStack> = open
+        .cursor
+        .into_iter()
+        .chain(window_slots.into_iter().flatten())
+        .collect();
+    give_items(player, items, inventory, update_events, drop_events);
+}
+
+/// Moves items into the inventory of a player,
+/// dropping those which don't fit.
+fn give_items(
+    player: Entity,
+    items: Vec<ItemStack>,
+    inventory: &mut InventoryComponent,
+    update_events: &mut EventChannel<InventoryUpdateEvent>,
+    drop_events: &mut EventChannel<PlayerItemDropEvent>,
+) {
+    let mut slots: SmallVec<[SlotIndex; 2]> = SmallVec::new();
+    for stack in items {
+        let (affected, remaining) = inventory.collect_item(stack.clone());
+        slots.extend(affected);
+        if remaining > 0 {
+            drop_events.single_write(PlayerItemDropEvent {
+                slot: None,
+                stack: stack.with_amount(remaining),
+                player,
+            });
+        }
+    }
+
+    if !slots.is_empty() {
+        slots.sort();
+        slots.dedup();
+        update_events.single_write(InventoryUpdateEvent { slots, player });
+    }
+}
+
+/// Event triggered when a player right-clicks a container.
+#[derive(Debug, Clone)]
+pub struct ContainerOpenEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+}
+
+/// System which opens the window of containers
+/// which players right-click.
+///
+/// This system listens to `ContainerOpenEvent`s.
+#[derive(Default)]
+pub struct ContainerOpenSystem {
+    reader: Option<ReaderId<ContainerOpenEvent>>,
+}
+
+impl<'a> System<'a> for ContainerOpenSystem {
+    type SystemData = (
+        Read<'a, EventChannel<ContainerOpenEvent>>,
+        Write<'a, ChunkMap>,
+        WriteStorage<'a, OpenContainerComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Write<'a, EventChannel<PlayerItemDropEvent>>,
+        WriteStorage<'a, DragComponent>,
+        WriteStorage<'a, RefusedClickComponent>,
+        Read<'a, LootTables>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            mut chunk_map,
+            mut open_containers,
+            mut inventories,
+            networks,
+            mut update_events,
+            mut drop_events,
+            mut drags,
+            mut refused,
+            tables,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let block = continue_if_none!(chunk_map.block_at(event.pos));
+            let kind = continue_if_none!(ContainerKind::of(block));
+            let parts = container_parts(&chunk_map, event.pos);
+            if parts
+                .iter()
+                .any(|&part| is_lid_blocked(&chunk_map, kind, part))
+            {
+                continue;
+            }
+            let network = continue_if_none!(networks.get(event.player));
+            let inventory = continue_if_none!(inventories.get_mut(event.player));
+
+            drags.remove(event.player);
+            refused.remove(event.player);
+            if let Some(open) = open_containers.remove(event.player) {
+                return_items(
+                    event.player,
+                    open,
+                    inventory,
+                    &mut update_events,
+                    &mut drop_events,
+                );
+            }
+
+            let pos = parts[0];
+            let size = parts.len() * kind.size();
+            let slots = if kind.stores_items() {
+                for &part in &parts {
+                    fill_from_loot_table(&mut chunk_map, kind, part, &tables);
+                }
+                load_slots(&chunk_map, kind, pos)
+            } else {
+                vec![None; size]
+            };
+            let custom_name = parts
+                .iter()
+                .find_map(|&part| load_container(&chunk_map, kind, part).custom_name);
+            let title = match custom_name {
+                Some(name) => name,
+                None => json!({ "translate": kind.default_title(parts.len()) }).to_string(),
+            };
+
+            send_packet_to_player(
+                network,
+                OpenWindow {
+                    window_id: WINDOW_ID,
+                    window_type: kind.window_type().to_string(),
+                    window_title: title,
+                    number_of_slots: size as u8,
+                    entity_id: 0,
+                },
+            );
+            send_packet_to_player(
+                network,
+                WindowItems {
+                    window_id: WINDOW_ID,
+                    slots: window_items(&slots, inventory),
+                },
+            );
+            match kind {
+                ContainerKind::Beacon => {
+                    beacon::send_window_properties(network, &load_beacon(&chunk_map, pos))
+                }
+                ContainerKind::Furnace => {
+                    furnace::send_window_properties(network, &load_furnace(&chunk_map, pos))
+                }
+                _ => (),
+            }
+
+            open_containers
+                .insert(
+                    event.player,
+                    OpenContainerComponent {
+                        pos,
+                        kind,
+                        size,
+                        cursor: None,
+                        slots: if kind.stores_items() { vec![] } else { slots },
+                    },
+                )
+                .unwrap();
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// Event triggered when a player changes the
+/// items stored in a container.
+#[derive(Debug, Clone)]
+pub struct ContainerChangeEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+    /// The changed slots with their old and new items.
+    pub slots: Vec<(usize, Option<ItemStack>, Option<ItemStack>)>,
+}
+
+/// Component for players dragging items in a window.
+#[derive(Debug, Clone)]
+pub struct DragComponent {
+    pub window_id: u8,
+    pub drag: Drag,
+}
+
+impl Component for DragComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Component for players whose click in a window was refused.
+/// Like in vanilla, their clicks in the window are ignored until
+/// they acknowledge the refusal with a Confirm Transaction packet.
+#[derive(Debug, Clone)]
+pub struct RefusedClickComponent {
+    pub window_id: u8,
+    pub action_number: i16,
+}
+
+impl Component for RefusedClickComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+fn is_refused(
+    refused: &WriteStorage<RefusedClickComponent>,
+    player: Entity,
+    window_id: u8,
+) -> bool {
+    refused
+        .get(player)
+        .map_or(false, |refused| refused.window_id == window_id)
+}
+
+fn is_creative(players: &ReadStorage<PlayerComponent>, player: Entity) -> bool {
+    players
+        .get(player)
+        .map_or(false, |player| player.gamemode == Gamemode::Creative)
+}
+
+/// Takes the drag in progress of a player in the given window.
+fn take_drag(
+    drags: &mut WriteStorage<DragComponent>,
+    player: Entity,
+    window_id: u8,
+) -> Option<Drag> {
+    drags
+        .remove(player)
+        .filter(|drag| drag.window_id == window_id)
+        .map(|drag| drag.drag)
+}
+
+/// Confirms a click to the client, and keeps the drag in progress.
+fn accept_click(
+    player: Entity,
+    network: &NetworkComponent,
+    packet: &ClickWindow,
+    drag: Option<Drag>,
+    drags: &mut WriteStorage<DragComponent>,
+) {
+    send_packet_to_player(
+        network,
+        ConfirmTransactionClientbound {
+            window_id: packet.window_id as i8,
+            action_number: packet.action_number,
+            accepted: true,
+        },
+    );
+    if let Some(drag) = drag {
+        drags
+            .insert(
+                player,
+                DragComponent {
+                    window_id: packet.window_id,
+                    drag,
+                },
+            )
+            .unwrap();
+    }
+}
+
+/// Refuses a click and reverts the client's prediction by sending
+/// the slots of the window and the held item again.
+fn refuse_click(
+    player: Entity,
+    network: &NetworkComponent,
+    packet: &ClickWindow,
+    slots: Vec<Option<ItemStack>>,
+    cursor: Option<ItemStack>,
+    refused: &mut WriteStorage<RefusedClickComponent>,
+) {
+    send_packet_to_player(
+        network,
+        ConfirmTransactionClientbound {
+            window_id: packet.window_id as i8,
+            action_number: packet.action_number,
+            accepted: false,
+        },
+    );
+    send_packet_to_player(
+        network,
+        WindowItems {
+            window_id: packet.window_id,
+            slots,
+        },
+    );
+    send_packet_to_player(network, SetSlot::new(-1, -1, cursor));
+    refused
+        .insert(
+            player,
+            RefusedClickComponent {
+                window_id: packet.window_id,
+                action_number: packet.action_number,
+            },
+        )
+        .unwrap();
+}
+
+/// System which handles Confirm Transaction packets, with
+/// which clients acknowledge that a click was refused.
+pub struct ClickConfirmSystem;
+
+impl<'a> System<'a> for ClickConfirmSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        WriteStorage<'a, RefusedClickComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (packet_queue, mut refused) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::ConfirmTransactionServerbound) {
+            let packet = cast_packet::<ConfirmTransactionServerbound>(&*packet);
+            let acknowledged = refused.get(player).map_or(false, |refused| {
+                refused.window_id == packet.window_id
+                    && refused.action_number == packet.action_number as i16
+            });
+            if acknowledged {
+                refused.remove(player);
+            }
+        }
+    }
+}
+
+/// System which handles Click Window packets
+/// for container windows.
+pub struct ContainerClickSystem;
+
+impl<'a> System<'a> for ContainerClickSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        Write<'a, ChunkMap>,
+        WriteStorage<'a, OpenContainerComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Write<'a, EventChannel<PlayerItemDropEvent>>,
+        Write<'a, EventChannel<ContainerChangeEvent>>,
+        Read<'a, RecipeRegistry>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, DragComponent>,
+        WriteStorage<'a, RefusedClickComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            mut chunk_map,
+            mut open_containers,
+            mut inventories,
+            networks,
+            mut update_events,
+            mut drop_events,
+            mut change_events,
+            recipes,
+            players,
+            mut drags,
+            mut refused,
+        ) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::ClickWindow) {
+            let packet = cast_packet::<ClickWindow>(&*packet);
+            if packet.window_id != WINDOW_ID || is_refused(&refused, player, WINDOW_ID) {
+                continue;
+            }
+
+            let (pos, kind, size, cursor, open_slots) = {
+                let open = continue_if_none!(open_containers.get(player));
+                (
+                    open.pos,
+                    open.kind,
+                    open.size,
+                    open.cursor.clone(),
+                    open.slots.clone(),
+                )
+            };
+            let network = continue_if_none!(networks.get(player));
+            let inventory = continue_if_none!(inventories.get_mut(player));
+
+            let old_slots = if kind.stores_items() {
+                load_slots(&chunk_map, kind, pos)
+            } else {
+                open_slots
+            };
+            // A half of the double chest was broken or placed,
+            // so the window is closed.
+            if old_slots.len() != size {
+                continue;
+            }
+            let mut slots = old_slots.clone();
+            let mut new_inventory = inventory.inventory.clone();
+            let mut new_cursor = cursor.clone();
+
+            let mut window = Window {
+                kind: WindowKind::Container(kind),
+                slots: &mut slots,
+                inventory: &mut new_inventory,
+                creative: is_creative(&players, player),
+                drag: take_drag(&mut drags, player, WINDOW_ID),
+            };
+            let result = click_window(&mut window, &mut new_cursor, packet, &recipes);
+            let drag = window.drag.take();
+
+            let drops = match result {
+                Ok(drops) => drops,
+                Err(()) => {
+                    let items = window_items(&old_slots, inventory);
+                    refuse_click(player, network, packet, items, cursor, &mut refused);
+                    continue;
+                }
+            };
+            accept_click(player, network, packet, drag, &mut drags);
+
+            let changed_inventory: SmallVec<[SlotIndex; 2]> = (SLOT_INVENTORY_OFFSET
+                ..SLOT_INVENTORY_OFFSET + PLAYER_SLOTS)
+                .filter(|index| inventory.item_at(*index) != new_inventory.item_at(*index))
+                .collect();
+            inventory.inventory = new_inventory;
+            if !changed_inventory.is_empty() {
+                update_events.single_write(InventoryUpdateEvent {
+                    slots: changed_inventory,
+                    player,
+                });
+            }
+
+            let changed_slots: Vec<usize> = (0..slots.len())
+                .filter(|slot| old_slots[*slot] != slots[*slot])
+                .collect();
+            if !kind.stores_items() {
+                // Clients don't know the results of recipes.
+                if kind == ContainerKind::CraftingTable {
+                    for slot in changed_slots {
+                        send_packet_to_player(
+                            network,
+                            SetSlot::new(WINDOW_ID as i8, slot as i16, slots[slot].clone()),
+                        );
+                    }
+                }
+                open_containers.get_mut(player).unwrap().slots = slots;
+            } else if !changed_slots.is_empty() {
+                store_slots(&mut chunk_map, kind, pos, &slots);
+
+                // Update other players viewing the container.
+                send_slots_to_viewers(&open_containers, &networks, pos, &slots, &changed_slots);
+
+                // Changes are recorded for the block storing the slot.
+                let parts = container_parts(&chunk_map, pos);
+                for (index, part) in parts.into_iter().enumerate() {
+                    let offset = index * kind.size();
+                    let part_slots: Vec<_> = changed_slots
+                        .iter()
+                        .filter(|slot| **slot >= offset && **slot < offset + kind.size())
+                        .map(|slot| {
+                            (
+                                *slot - offset,
+                                old_slots[*slot].clone(),
+                                slots[*slot].clone(),
+                            )
+                        })
+                        .collect();
+                    if !part_slots.is_empty() {
+                        change_events.single_write(ContainerChangeEvent {
+                            player,
+                            pos: part,
+                            slots: part_slots,
+                        });
+                    }
+                }
+            }
+
+            for stack in drops {
+                drop_events.single_write(PlayerItemDropEvent {
+                    slot: None,
+                    stack,
+                    player,
+                });
+            }
+
+            open_containers.get_mut(player).unwrap().cursor = new_cursor;
+        }
+    }
+}
+
+/// System which closes container windows when players close
+/// them, move too far away, or the container is broken.
+pub struct ContainerCloseSystem;
+
+impl<'a> System<'a> for ContainerCloseSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        Read<'a, ChunkMap>,
+        WriteStorage<'a, OpenContainerComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Write<'a, EventChannel<PlayerItemDropEvent>>,
+        Entities<'a>,
+        WriteStorage<'a, DragComponent>,
+        WriteStorage<'a, RefusedClickComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            chunk_map,
+            mut open_containers,
+            mut inventories,
+            positions,
+            networks,
+            mut update_events,
+            mut drop_events,
+            entities,
+            mut drags,
+            mut refused,
+        ) = data;
+
+        let mut closed = vec![];
+
+        for (player, packet) in packet_queue.for_packet(PacketType::CloseWindowServerbound) {
+            let packet = cast_packet::<CloseWindowServerbound>(&*packet);
+            if packet.window_id == WINDOW_ID && open_containers.get(player).is_some() {
+                closed.push((player, false));
+            }
+        }
+
+        for (player, open, position) in (&entities, &open_containers, &positions).join() {
+            let parts = container_parts(&chunk_map, open.pos);
+            let valid = chunk_map.block_at(open.pos).and_then(ContainerKind::of) == Some(open.kind)
+                && parts[0] == open.pos
+                && parts.len() * open.kind.size() == open.size;
+            let center = open.pos.world_pos() + position!(0.5, 0.5, 0.5);
+            let in_range = position.current.distance_squared(center) <= MAX_DISTANCE_SQUARED;
+            if !(valid && in_range) && !closed.iter().any(|(entity, _)| *entity == player) {
+                closed.push((player, true));
+            }
+        }
+
+        for (player, notify) in closed {
+            let open = continue_if_none!(open_containers.remove(player));
+            drags.remove(player);
+            refused.remove(player);
+            if notify {
+                if let Some(network) = networks.get(player) {
+                    send_packet_to_player(network, CloseWindowClientbound::new(WINDOW_ID));
+                }
+            }
+            if let Some(inventory) = inventories.get_mut(player) {
+                return_items(
+                    player,
+                    open,
+                    inventory,
+                    &mut update_events,
+                    &mut drop_events,
+                );
+            }
+        }
+    }
+}
+
+/// System which handles Click Window and Close Window packets
+/// for the player's inventory window. When the window is closed,
+/// the items in its crafting grid and the held item are moved back
+/// into the inventory.
+pub struct InventoryWindowSystem;
+
+impl<'a> System<'a> for InventoryWindowSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Write<'a, EventChannel<PlayerItemDropEvent>>,
+        Read<'a, RecipeRegistry>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, DragComponent>,
+        WriteStorage<'a, RefusedClickComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            mut inventories,
+            networks,
+            mut update_events,
+            mut drop_events,
+            recipes,
+            players,
+            mut drags,
+            mut refused,
+        ) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::ClickWindow) {
+            let packet = cast_packet::<ClickWindow>(&*packet);
+            if packet.window_id != INVENTORY_WINDOW_ID
+                || is_refused(&refused, player, INVENTORY_WINDOW_ID)
+            {
+                continue;
+            }
+            let network = continue_if_none!(networks.get(player));
+            let inventory = continue_if_none!(inventories.get_mut(player));
+
+            let mut new_inventory = inventory.inventory.clone();
+            let mut new_cursor = inventory.cursor.clone();
+            let mut window = Window {
+                kind: WindowKind::Inventory,
+                slots: &mut [],
+                inventory: &mut new_inventory,
+                creative: is_creative(&players, player),
+                drag: take_drag(&mut drags, player, INVENTORY_WINDOW_ID),
+            };
+            let result = click_window(&mut window, &mut new_cursor, packet, &recipes);
+            let drag = window.drag.take();
+
+            let drops = match result {
+                Ok(drops) => drops,
+                Err(()) => {
+                    let items = inventory.items().to_vec();
+                    let cursor = inventory.cursor.clone();
+                    refuse_click(player, network, packet, items, cursor, &mut refused);
+                    continue;
+                }
+            };
+            accept_click(player, network, packet, drag, &mut drags);
+
+            let changed: SmallVec<[SlotIndex; 2]> = (0..=SLOT_OFFHAND)
+                .filter(|index| inventory.item_at(*index) != new_inventory.item_at(*index))
+                .collect();
+            inventory.inventory = new_inventory;
+            inventory.cursor = new_cursor;
+            if !changed.is_empty() {
+                update_events.single_write(InventoryUpdateEvent {
+                    slots: changed,
+                    player,
+                });
+            }
+
+            for stack in drops {
+                drop_events.single_write(PlayerItemDropEvent {
+                    slot: None,
+                    stack,
+                    player,
+                });
+            }
+        }
+
+        for (player, packet) in packet_queue.for_packet(PacketType::CloseWindowServerbound) {
+            let packet = cast_packet::<CloseWindowServerbound>(&*packet);
+            if packet.window_id != INVENTORY_WINDOW_ID {
+                continue;
+            }
+            drags.remove(player);
+            refused.remove(player);
+            let inventory = continue_if_none!(inventories.get_mut(player));
+
+            let (grid, _) = WindowKind::Inventory.crafting_grid().unwrap();
+            let mut cleared: SmallVec<[SlotIndex; 2]> = SmallVec::new();
+            let mut items: Vec<ItemStack> = inventory.cursor.take().into_iter().collect();
+            for slot in std::iter::once(CRAFTING_RESULT).chain(grid) {
+                if let Some(stack) = inventory.clear_item_at(slot) {
+                    cleared.push(slot);
+                    // The crafting result isn't given to the player.
+                    if slot != CRAFTING_RESULT {
+                        items.push(stack);
+                    }
+                }
+            }
+
+            if !cleared.is_empty() {
+                update_events.single_write(InventoryUpdateEvent {
+                    slots: cleared,
+                    player,
+                });
+            }
+            give_items(
+                player,
+                items,
+                inventory,
+                &mut update_events,
+                &mut drop_events,
+            );
+        }
+    }
+}
+
+/// System which removes the block entities of broken
+/// containers. Broken shulker boxes drop themselves with
+/// their contents, even in creative mode unless they are
+/// empty, and other containers storing items drop their contents.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct ContainerBreakSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for ContainerBreakSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, ChunkMap>,
+        ReadStorage<'a, PlayerComponent>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+        Read<'a, LootTables>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, mut chunk_map, players, lazy, entities, tick, tables) = data;
+
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let kind = continue_if_none!(ContainerKind::of(event.old_block));
+            // Only the state of the block changed.
+            if mem::discriminant(&event.old_block) == mem::discriminant(&event.new_block) {
+                continue;
+            }
+
+            if kind.stores_items() {
+                fill_from_loot_table(&mut chunk_map, kind, event.pos, &tables);
+            }
+            let entity = load_container(&chunk_map, kind, event.pos);
+            chunk_map.remove_block_entity_at(event.pos);
+            match kind {
+                ContainerKind::ShulkerBox => {
+                    let gamemode = match event.cause {
+                        BlockUpdateCause::Player(player) => {
+                            continue_if_none!(players.get(player)).gamemode
+                        }
+                        _ => continue,
+                    };
+                    if gamemode == Gamemode::Creative && entity.items.is_empty() {
+                        continue;
+                    }
+
+                    let stack = continue_if_none!(shulker_box_item(event.old_block, entity));
+                    drop_at_block(&lazy, &entities, event.pos, vec![stack], tick.0, &mut rng);
+                }
+                ContainerKind::Beacon | ContainerKind::CraftingTable => (),
+                _ => {
+                    let stacks = entity.slots(kind.size()).into_iter().flatten().collect();
+                    drop_at_block(&lazy, &entities, event.pos, stacks, tick.0, &mut rng);
+                }
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ClickConfirmSystem, CLICK_CONFIRM, &[]);
+    dispatcher.add_timed(ContainerClickSystem, CONTAINER_CLICK, &[CLICK_CONFIRM]);
+    dispatcher.add_timed(InventoryWindowSystem, INVENTORY_WINDOW, &[CLICK_CONFIRM]);
+    dispatcher.add_timed(
+        ContainerCloseSystem,
+        CONTAINER_CLOSE,
+        &[CONTAINER_CLICK, BEACON_EFFECT],
+    );
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ContainerOpenSystem::default(), CONTAINER_OPEN, &[]);
+    dispatcher.add_timed(ContainerBreakSystem::default(), CONTAINER_BREAK, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::ItemComponent;
+    use crate::testframework as t;
+    use feather_blocks::{ChestData, ChestType, ShulkerBoxData};
+    use feather_core::inventory::{InventoryType, SLOT_HOTBAR_OFFSET};
+    use specs::{World, WorldExt};
+
+    fn window_with<'a>(
+        slots: &'a mut [Option<ItemStack>],
+        inventory: &'a mut Inventory,
+    ) -> Window<'a> {
+        Window {
+            kind: WindowKind::Container(ContainerKind::ShulkerBox),
+            slots,
+            inventory,
+            creative: false,
+            drag: None,
+        }
+    }
+
+    #[test]
+    fn test_click_stacks() {
+        let mut slots = vec![None; SHULKER_BOX_SIZE];
+        slots[0] = Some(ItemStack::new(Item::Stone, 5));
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        let mut window = window_with(&mut slots, &mut inventory);
+        let mut cursor = None;
+
+        // Right-click picks up half of the stack.
+        click(&mut window, &mut cursor, 0, 1, 0).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::Stone, 3)));
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Stone, 2)));
+
+        // Right-click places a single item.
+        click(&mut window, &mut cursor, 1, 1, 0).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::Stone, 2)));
+        assert_eq!(window.get(1), Some(ItemStack::new(Item::Stone, 1)));
+
+        // Left-click merges the held stack.
+        click(&mut window, &mut cursor, 0, 0, 0).unwrap();
+        assert_eq!(cursor, None);
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Stone, 4)));
+
+        // Shift-click moves the stack to the end of the hotbar.
+        click(&mut window, &mut cursor, 0, 0, 1).unwrap();
+        assert_eq!(window.get(0), None);
+        assert_eq!(
+            window
+                .inventory
+                .item_at(SLOT_HOTBAR_OFFSET + HOTBAR_SIZE - 1),
+            Some(&ItemStack::new(Item::Stone, 4))
+        );
+
+        // Clicking outside the window drops the held stack.
+        click(&mut window, &mut cursor, 1, 0, 0).unwrap();
+        let drops = click(&mut window, &mut cursor, SLOT_OUTSIDE, 0, 0).unwrap();
+        assert_eq!(drops, vec![ItemStack::new(Item::Stone, 1)]);
+        assert_eq!(cursor, None);
+
+        assert!(click(&mut window, &mut cursor, 63, 0, 0).is_err());
+        assert!(click(&mut window, &mut cursor, 0, 0, 7).is_err());
+    }
+
+    #[test]
+    fn test_click_drag() {
+        let mut slots = vec![None; SHULKER_BOX_SIZE];
+        slots[2] = Some(ItemStack::new(Item::Stone, 60));
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        let mut window = window_with(&mut slots, &mut inventory);
+        let mut cursor = Some(ItemStack::new(Item::Stone, 10));
+
+        // A left drag spreads the stack evenly,
+        // keeping the rest on the cursor.
+        click(&mut window, &mut cursor, SLOT_OUTSIDE, 0, 5).unwrap();
+        for &slot in &[0, 1, 2] {
+            click(&mut window, &mut cursor, slot, 1, 5).unwrap();
+        }
+        click(&mut window, &mut cursor, SLOT_OUTSIDE, 2, 5).unwrap();
+        assert_eq!(window.drag, None);
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Stone, 3)));
+        assert_eq!(window.get(1), Some(ItemStack::new(Item::Stone, 3)));
+        assert_eq!(window.get(2), Some(ItemStack::new(Item::Stone, 63)));
+        assert_eq!(cursor, Some(ItemStack::new(Item::Stone, 1)));
+
+        // A right drag places one item into each slot,
+        // and isn't continued without items left.
+        cursor = Some(ItemStack::new(Item::Stone, 2));
+        click(&mut window, &mut cursor, SLOT_OUTSIDE, 4, 5).unwrap();
+        for &slot in &[3, 4, 5] {
+            click(&mut window, &mut cursor, slot, 5, 5).unwrap();
+        }
+        assert_eq!(window.drag.as_ref().unwrap().slots, vec![3, 4]);
+        click(&mut window, &mut cursor, SLOT_OUTSIDE, 6, 5).unwrap();
+        assert_eq!(window.get(3), Some(ItemStack::new(Item::Stone, 1)));
+        assert_eq!(window.get(4), Some(ItemStack::new(Item::Stone, 1)));
+        assert_eq!(window.get(5), None);
+        assert_eq!(cursor, None);
+
+        // Middle drags are only allowed in creative mode,
+        // and other clicks cancel a drag.
+        cursor = Some(ItemStack::new(Item::Dirt, 1));
+        assert!(click(&mut window, &mut cursor, SLOT_OUTSIDE, 8, 5).is_err());
+        click(&mut window, &mut cursor, SLOT_OUTSIDE, 0, 5).unwrap();
+        click(&mut window, &mut cursor, 6, 0, 0).unwrap();
+        assert_eq!(window.drag, None);
+    }
+
+    #[test]
+    fn test_click_double_click_and_drop() {
+        let mut slots = vec![None; SHULKER_BOX_SIZE];
+        slots[0] = Some(ItemStack::new(Item::Stone, 64));
+        slots[1] = Some(ItemStack::new(Item::Stone, 20));
+        slots[2] = Some(ItemStack::new(Item::Dirt, 5));
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        let mut window = window_with(&mut slots, &mut inventory);
+        let mut cursor = Some(ItemStack::new(Item::Stone, 10));
+
+        // Stacks which aren't full are collected first.
+        click(&mut window, &mut cursor, 3, 0, 6).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::Stone, 64)));
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Stone, 30)));
+        assert_eq!(window.get(1), None);
+
+        // Items can only be dropped with an empty cursor.
+        assert_eq!(click(&mut window, &mut cursor, 2, 0, 4), Ok(vec![]));
+        cursor = None;
+        assert_eq!(
+            click(&mut window, &mut cursor, 2, 0, 4),
+            Ok(vec![ItemStack::new(Item::Dirt, 1)])
+        );
+        assert_eq!(
+            click(&mut window, &mut cursor, 2, 1, 4),
+            Ok(vec![ItemStack::new(Item::Dirt, 4)])
+        );
+        assert_eq!(window.get(2), None);
+
+        // Stacks are only cloned in creative mode.
+        click(&mut window, &mut cursor, 0, 2, 3).unwrap();
+        assert_eq!(cursor, None);
+        window.creative = true;
+        click(&mut window, &mut cursor, 0, 2, 3).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::Stone, 64)));
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Stone, 30)));
+    }
+
+    #[test]
+    fn test_click_refuses_nested_shulker_boxes() {
+        let mut slots = vec![None; SHULKER_BOX_SIZE];
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        inventory.set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::RedShulkerBox, 1));
+        let mut window = window_with(&mut slots, &mut inventory);
+        let held = Some(ItemStack::new(Item::ShulkerBox, 1));
+        let mut cursor = held.clone();
+
+        assert!(click(&mut window, &mut cursor, 0, 0, 0).is_err());
+        assert_eq!(cursor, held);
+        assert!(click(&mut window, &mut cursor, 0, 0, 2).is_err());
+
+        // Shift-clicking a shulker box leaves it in place.
+        let hotbar_slot = window.hotbar_slot(0);
+        click(&mut window, &mut cursor, hotbar_slot as i16, 0, 1).unwrap();
+        assert_eq!(
+            window.get(hotbar_slot),
+            Some(ItemStack::new(Item::RedShulkerBox, 1))
+        );
+        assert!(window.slots.iter().all(Option::is_none));
+
+        // Shulker boxes can still be put into the player's inventory.
+        click(&mut window, &mut cursor, SHULKER_BOX_SIZE as i16, 0, 0).unwrap();
+        assert_eq!(cursor, None);
+        assert_eq!(
+            window.get(SHULKER_BOX_SIZE),
+            Some(ItemStack::new(Item::ShulkerBox, 1))
+        );
+    }
+
+    #[test]
+    fn test_click_beacon_payment() {
+        let mut slots = vec![None; BEACON_SIZE];
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        let mut window = Window {
+            kind: WindowKind::Container(ContainerKind::Beacon),
+            slots: &mut slots,
+            inventory: &mut inventory,
+            creative: false,
+            drag: None,
+        };
+        let mut cursor = Some(ItemStack::new(Item::Stone, 1));
+
+        // Only payment items are accepted.
+        assert!(click(&mut window, &mut cursor, 0, 0, 0).is_err());
+
+        // A single item is placed from the held stack.
+        cursor = Some(ItemStack::new(Item::Diamond, 5));
+        click(&mut window, &mut cursor, 0, 0, 0).unwrap();
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Diamond, 1)));
+        assert_eq!(cursor, Some(ItemStack::new(Item::Diamond, 4)));
+        click(&mut window, &mut cursor, 0, 0, 0).unwrap();
+        assert_eq!(window.get(0), Some(ItemStack::new(Item::Diamond, 1)));
+
+        // Stacks can't be swapped into the slot.
+        cursor = Some(ItemStack::new(Item::Emerald, 2));
+        assert!(click(&mut window, &mut cursor, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_break_and_place() {
+        let (mut w, mut d) = t::builder()
+            .with(ContainerBreakSystem::default(), "")
+            .build();
+        w.register::<ItemComponent>();
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+
+        let pos = BlockPosition::new(0, 64, 0);
+        let block = Block::ShulkerBox(ShulkerBoxData::default());
+        let mut entity = ContainerEntity::new(ContainerKind::ShulkerBox, pos);
+        let mut slots = vec![None; SHULKER_BOX_SIZE];
+        slots[3] = Some(ItemStack::new(Item::Diamond, 7));
+        entity.set_slots(&slots);
+        entity.custom_name = Some(String::from(r#"{"text":"Loot"}"#));
+        store_container(&mut w.fetch_mut::<ChunkMap>(), pos, &entity);
+
+        t::trigger_event(
+            &w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Player(player.entity),
+                pos,
+                old_block: block,
+                new_block: Block::Air,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w.fetch::<ChunkMap>().block_entity_at(pos).is_none());
+
+        let stack = {
+            let items = w.read_component::<ItemComponent>();
+            let item = items.join().next().unwrap();
+            item.stack.clone()
+        };
+        assert_eq!(stack.ty, Item::ShulkerBox);
+        assert_eq!(stack.custom_name(), Some(r#"{"text":"Loot"}"#));
+
+        let new_pos = BlockPosition::new(5, 64, 5);
+        place_container(
+            &mut w.fetch_mut::<ChunkMap>(),
+            ContainerKind::ShulkerBox,
+            new_pos,
+            &stack,
+        );
+        let placed = load_container(&w.fetch::<ChunkMap>(), ContainerKind::ShulkerBox, new_pos);
+        assert_eq!(placed.slots(SHULKER_BOX_SIZE), slots);
+        assert_eq!(placed.custom_name, entity.custom_name);
+        assert_eq!((placed.x, placed.y, placed.z), (5, 64, 5));
+    }
+
+    #[test]
+    fn test_open_and_click() {
+        let (mut w, mut d) = t::builder()
+            .with(ContainerOpenSystem::default(), "open")
+            .with(ContainerClickSystem, "")
+            .build();
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::Stone, 16));
+
+        let pos = BlockPosition::new(0, 1, 0);
+        t::set_block(0, 1, 0, Block::ShulkerBox(ShulkerBoxData::default()), &w);
+
+        t::trigger_event(
+            &w,
+            ContainerOpenEvent {
+                player: player.entity,
+                pos,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::OpenWindow);
+        let packet = cast_packet::<OpenWindow>(&*packet);
+        assert_eq!(packet.window_type, "minecraft:shulker_box");
+        assert_eq!(packet.number_of_slots, SHULKER_BOX_SIZE as u8);
+        let packet = t::assert_packet_received(&player, PacketType::WindowItems);
+        let packet = cast_packet::<WindowItems>(&*packet);
+        assert_eq!(packet.slots.len(), SHULKER_BOX_SIZE + PLAYER_SLOTS);
+        assert!(w
+            .read_component::<OpenContainerComponent>()
+            .get(player.entity)
+            .is_some());
+
+        // Shift-click the stack from the hotbar into the box.
+        let mut change_reader = t::reader::<ContainerChangeEvent>(&w);
+        let hotbar_slot = SHULKER_BOX_SIZE + INVENTORY_SIZE;
+        t::receive_packet(
+            &player,
+            &w,
+            ClickWindow::new(
+                WINDOW_ID,
+                hotbar_slot as u16,
+                0,
+                1,
+                1,
+                Some(ItemStack::new(Item::Stone, 16)),
+            ),
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::ConfirmTransactionClientbound);
+        assert!(cast_packet::<ConfirmTransactionClientbound>(&*packet).accepted);
+
+        let container = load_container(&w.fetch::<ChunkMap>(), ContainerKind::ShulkerBox, pos);
+        assert_eq!(
+            container.slots(SHULKER_BOX_SIZE)[0],
+            Some(ItemStack::new(Item::Stone, 16))
+        );
+        assert!(w
+            .read_component::<InventoryComponent>()
+            .get(player.entity)
+            .unwrap()
+            .item_at(SLOT_HOTBAR_OFFSET)
+            .is_none());
+
+        let changes = t::triggered_events(&w, &mut change_reader);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].pos, pos);
+        assert_eq!(
+            changes[0].slots,
+            vec![(0, None, Some(ItemStack::new(Item::Stone, 16)))]
+        );
+    }
+
+    #[test]
+    fn test_inventory_window_armor() {
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        inventory.set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::IronHelmet, 1));
+        inventory.set_item_at(SLOT_HOTBAR_OFFSET + 1, ItemStack::new(Item::Stone, 1));
+        let mut window = Window {
+            kind: WindowKind::Inventory,
+            slots: &mut [],
+            inventory: &mut inventory,
+            creative: false,
+            drag: None,
+        };
+        let mut cursor = None;
+
+        // Shift-clicking armor puts it on.
+        click(&mut window, &mut cursor, SLOT_HOTBAR_OFFSET as i16, 0, 1).unwrap();
+        assert_eq!(
+            window.get(SLOT_ARMOR_MIN),
+            Some(ItemStack::new(Item::IronHelmet, 1))
+        );
+
+        // Other items move between the hotbar and the main inventory.
+        click(
+            &mut window,
+            &mut cursor,
+            SLOT_HOTBAR_OFFSET as i16 + 1,
+            0,
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            window.get(SLOT_INVENTORY_OFFSET),
+            Some(ItemStack::new(Item::Stone, 1))
+        );
+
+        // Only armor is accepted in armor slots.
+        cursor = Some(ItemStack::new(Item::Stone, 1));
+        assert!(click(&mut window, &mut cursor, SLOT_ARMOR_MAX as i16, 0, 0).is_err());
+        // The crafting result can't be filled.
+        assert!(click(&mut window, &mut cursor, CRAFTING_RESULT as i16, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_inventory_window_crafting() {
+        let (mut w, mut d) = t::builder().with(InventoryWindowSystem, "").build();
+        w.insert(RecipeRegistry::bundled());
+
+        let player = t::add_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::OakLog, 2));
+        let inventory = |w: &World| {
+            w.read_component::<InventoryComponent>()
+                .get(player.entity)
+                .unwrap()
+                .clone()
+        };
+        let click = |w: &World, slot: usize, button: u8, mode: i32, item: Option<ItemStack>| {
+            t::receive_packet(
+                &player,
+                w,
+                ClickWindow::new(INVENTORY_WINDOW_ID, slot as u16, button, 1, mode, item),
+            );
+        };
+        let planks = |amount| Some(ItemStack::new(Item::OakPlanks, amount));
+
+        // Move the logs into the crafting grid.
+        click(
+            &w,
+            SLOT_HOTBAR_OFFSET,
+            0,
+            0,
+            Some(ItemStack::new(Item::OakLog, 2)),
+        );
+        click(&w, 1, 0, 0, None);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(
+            inventory(&w).item_at(CRAFTING_RESULT),
+            Some(&ItemStack::new(Item::OakPlanks, 4))
+        );
+
+        // Shift-clicking the result crafts all logs.
+        click(&w, CRAFTING_RESULT, 0, 1, planks(4));
+        d.dispatch(&w);
+        w.maintain();
+        let inv = inventory(&w);
+        assert_eq!(inv.item_at(1), None);
+        assert_eq!(inv.item_at(CRAFTING_RESULT), None);
+        assert_eq!(
+            inv.item_at(SLOT_HOTBAR_OFFSET + 8),
+            Some(&ItemStack::new(Item::OakPlanks, 8))
+        );
+
+        // Closing the window gives back the grid and the held item.
+        click(&w, SLOT_HOTBAR_OFFSET + 8, 0, 0, planks(8));
+        click(&w, 4, 1, 0, None);
+        t::receive_packet(
+            &player,
+            &w,
+            CloseWindowServerbound::new(INVENTORY_WINDOW_ID),
+        );
+        d.dispatch(&w);
+        w.maintain();
+        let inv = inventory(&w);
+        assert_eq!(inv.item_at(4), None);
+        assert_eq!(inv.cursor, None);
+        assert_eq!(
+            inv.item_at(SLOT_HOTBAR_OFFSET),
+            Some(&ItemStack::new(Item::OakPlanks, 8))
+        );
+    }
+
+    #[test]
+    fn test_refused_click() {
+        let (mut w, mut d) = t::builder()
+            .with(ClickConfirmSystem, "confirm")
+            .with_dep(InventoryWindowSystem, "", &["confirm"])
+            .build();
+        w.insert(RecipeRegistry::bundled());
+
+        let player = t::add_player(&mut w);
+        let stone = Some(ItemStack::new(Item::Stone, 5));
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, stone.clone().unwrap());
+        let cursor = |w: &World| {
+            w.read_component::<InventoryComponent>()
+                .get(player.entity)
+                .unwrap()
+                .cursor
+                .clone()
+        };
+        let click = |w: &World, action: i16, item: Option<ItemStack>| {
+            t::receive_packet(
+                &player,
+                w,
+                ClickWindow::new(
+                    INVENTORY_WINDOW_ID,
+                    SLOT_HOTBAR_OFFSET as u16,
+                    0,
+                    action,
+                    0,
+                    item,
+                ),
+            );
+        };
+
+        // The client saw an empty slot, so the window is sent again.
+        click(&w, 1, None);
+        d.dispatch(&w);
+        w.maintain();
+        let packet = t::assert_packet_received(&player, PacketType::ConfirmTransactionClientbound);
+        assert!(!cast_packet::<ConfirmTransactionClientbound>(&*packet).accepted);
+        t::assert_packet_received(&player, PacketType::WindowItems);
+        t::assert_packet_received(&player, PacketType::SetSlot);
+        assert_eq!(cursor(&w), None);
+
+        // Clicks are ignored until the refusal is acknowledged.
+        click(&w, 2, stone.clone());
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_packet_not_received(&player, PacketType::ConfirmTransactionClientbound);
+        assert_eq!(cursor(&w), None);
+
+        t::receive_packet(
+            &player,
+            &w,
+            ConfirmTransactionServerbound::new(INVENTORY_WINDOW_ID, 1, false),
+        );
+        d.dispatch(&w);
+        w.maintain();
+        assert!(w
+            .read_component::<RefusedClickComponent>()
+            .get(player.entity)
+            .is_none());
+
+        click(&w, 3, stone.clone());
+        d.dispatch(&w);
+        w.maintain();
+        let packet = t::assert_packet_received(&player, PacketType::ConfirmTransactionClientbound);
+        assert!(cast_packet::<ConfirmTransactionClientbound>(&*packet).accepted);
+        assert_eq!(cursor(&w), stone);
+    }
+
+    #[test]
+    fn test_open_fills_from_loot_table() {
+        let (mut w, mut d) = t::builder()
+            .with(ContainerOpenSystem::default(), "open")
+            .build();
+        t::populate_with_air(&mut w);
+
+        let mut tables = LootTables::default();
+        let table = r#"{"pools": [{"rolls": 2, "entries": [
+            {"type": "item", "name": "minecraft:diamond"}
+        ]}]}"#;
+        tables.insert(
+            String::from("test:chest"),
+            serde_json::from_str(table).unwrap(),
+        );
+        w.insert(tables);
+
+        let player = t::add_player(&mut w);
+        let pos = BlockPosition::new(0, 1, 0);
+        t::set_block(0, 1, 0, Block::Chest(ChestData::default()), &w);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let mut entity = ContainerEntity::new(ContainerKind::Chest, pos);
+            entity.loot_table = Some(String::from("test:chest"));
+            entity.loot_table_seed = 5;
+            store_container(&mut chunk_map, pos, &entity);
+        }
+
+        t::trigger_event(
+            &w,
+            ContainerOpenEvent {
+                player: player.entity,
+                pos,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        // Each roll fills a random slot, and the table is only used once.
+        let entity = load_container(&w.fetch::<ChunkMap>(), ContainerKind::Chest, pos);
+        assert!(entity.loot_table.is_none());
+        assert_eq!(entity.loot_table_seed, 0);
+        let slots = entity.slots(CHEST_SIZE);
+        let diamonds: Vec<_> = slots.iter().flatten().collect();
+        assert_eq!(diamonds, vec![&ItemStack::new(Item::Diamond, 1); 2]);
+
+        let packet = t::assert_packet_received(&player, PacketType::WindowItems);
+        let packet = cast_packet::<WindowItems>(&*packet);
+        assert_eq!(packet.slots.iter().flatten().count(), 2);
+    }
+
+    #[test]
+    fn test_open_double_chest() {
+        let (mut w, mut d) = t::builder()
+            .with(ContainerOpenSystem::default(), "open")
+            .with(ContainerClickSystem, "")
+            .build();
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::Stone, 16));
+
+        // Facing north, the right half is on the east side.
+        let right = BlockPosition::new(1, 1, 0);
+        let left = BlockPosition::new(0, 1, 0);
+        let chest = |ty| {
+            Block::Chest(ChestData {
+                ty,
+                ..ChestData::default()
+            })
+        };
+        t::set_block(1, 1, 0, chest(ChestType::Right), &w);
+        t::set_block(0, 1, 0, chest(ChestType::Left), &w);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let mut entity = ContainerEntity::new(ContainerKind::Chest, left);
+            let mut slots = vec![None; CHEST_SIZE];
+            slots[0] = Some(ItemStack::new(Item::Diamond, 3));
+            entity.set_slots(&slots);
+            store_container(&mut chunk_map, left, &entity);
+        }
+
+        t::trigger_event(
+            &w,
+            ContainerOpenEvent {
+                player: player.entity,
+                pos: left,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::OpenWindow);
+        let packet = cast_packet::<OpenWindow>(&*packet);
+        assert_eq!(packet.window_type, "minecraft:chest");
+        assert_eq!(packet.number_of_slots, 2 * CHEST_SIZE as u8);
+        let packet = t::assert_packet_received(&player, PacketType::WindowItems);
+        let packet = cast_packet::<WindowItems>(&*packet);
+        assert_eq!(packet.slots.len(), 2 * CHEST_SIZE + PLAYER_SLOTS);
+        assert_eq!(
+            packet.slots[CHEST_SIZE],
+            Some(ItemStack::new(Item::Diamond, 3))
+        );
+        assert_eq!(
+            w.read_component::<OpenContainerComponent>()
+                .get(player.entity)
+                .unwrap()
+                .pos,
+            right
+        );
+
+        // Swap the stack from the hotbar into the left half.
+        let mut change_reader = t::reader::<ContainerChangeEvent>(&w);
+        t::receive_packet(
+            &player,
+            &w,
+            ClickWindow::new(WINDOW_ID, CHEST_SIZE as u16 + 1, 0, 1, 2, None),
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let container = load_container(&w.fetch::<ChunkMap>(), ContainerKind::Chest, left);
+        assert_eq!(
+            container.slots(CHEST_SIZE)[1],
+            Some(ItemStack::new(Item::Stone, 16))
+        );
+        let changes = t::triggered_events(&w, &mut change_reader);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].pos, left);
+        assert_eq!(
+            changes[0].slots,
+            vec![(1, None, Some(ItemStack::new(Item::Stone, 16)))]
+        );
+    }
+}
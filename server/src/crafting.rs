@@ -0,0 +1,301 @@
+//! Crafting in the 2x2 grid of the player's inventory window
+//! and in the 3x3 grid of crafting tables.
+//!
+//! The result slot of a crafting grid shows the result of the
+//! shaped or shapeless recipe matching the items in the grid, and
+//! is updated after every click in the window. Taking the result
+//! uses up one item from each slot of the grid; items with a
+//! container, such as milk buckets, leave it behind. Shift-clicking
+//! the result crafts as many times as the ingredients allow, as long
+//! as the results fit into the player's inventory, and pressing
+//! the drop key over the result throws it out of the window.
+//!
+//! Special recipes, such as dyeing armor, aren't crafted.
+
+use crate::container::Window;
+use crate::recipe::RecipeRegistry;
+use feather_core::inventory::{max_size, HOTBAR_SIZE};
+use feather_core::{Item, ItemStack};
+
+/// The window slot of the result of a crafting grid.
+pub const CRAFTING_RESULT: usize = 0;
+
+/// The maximum number of times the result is
+/// crafted when shift-clicking it.
+const MAX_CRAFTS: usize = 64;
+
+/// Returns the result of crafting the items in a crafting
+/// grid of the given width, with slots in row-major order.
+pub fn craft(
+    recipes: &RecipeRegistry,
+    grid: &[Option<ItemStack>],
+    width: usize,
+) -> Option<ItemStack> {
+    let items: Vec<Option<Item>> = grid
+        .iter()
+        .map(|slot| slot.as_ref().map(|stack| stack.ty))
+        .collect();
+    recipes.find_crafting(&items, width)?.result().cloned()
+}
+
+/// Returns the item left behind when the
+/// given item is used in a recipe.
+pub fn remainder(item: Item) -> Option<Item> {
+    match item {
+        Item::MilkBucket | Item::WaterBucket | Item::LavaBucket => Some(Item::Bucket),
+        Item::DragonBreath => Some(Item::GlassBottle),
+        _ => None,
+    }
+}
+
+/// Sets the result slot of a window's crafting
+/// grid to the result of the items in the grid.
+pub fn update_result(window: &mut Window, recipes: &RecipeRegistry) {
+    let (grid, width) = match window.kind.crafting_grid() {
+        Some(grid) => grid,
+        None => return,
+    };
+    let items: Vec<_> = grid.map(|slot| window.get(slot)).collect();
+    window.set(CRAFTING_RESULT, craft(recipes, &items, width));
+}
+
+/// Uses up one item from each slot of a window's crafting grid.
+/// Remainders which don't fit into their slot are moved into the
+/// player's inventory, or returned if they don't fit there either.
+fn use_ingredients(window: &mut Window) -> Vec<ItemStack> {
+    let (grid, _) = match window.kind.crafting_grid() {
+        Some(grid) => grid,
+        None => return vec![],
+    };
+
+    let mut drops = vec![];
+    for slot in grid {
+        let stack = continue_if_none!(window.get(slot));
+        window.set(slot, Some(stack.with_amount(stack.amount - 1)));
+
+        let remainder = ItemStack::new(continue_if_none!(remainder(stack.ty)), 1);
+        if window.get(slot).is_none() {
+            window.set(slot, Some(remainder));
+        } else {
+            let range = window.player_slots();
+            if window.move_stack(&remainder, range, false) > 0 {
+                drops.push(remainder);
+            }
+        }
+    }
+    drops
+}
+
+/// Applies a click on the result slot of a window's crafting grid.
+/// Returns the items thrown out of the window, or `Err` if the
+/// click is refused.
+pub fn click_result(
+    window: &mut Window,
+    cursor: &mut Option<ItemStack>,
+    button: u8,
+    mode: i32,
+    recipes: &RecipeRegistry,
+) -> Result<Vec<ItemStack>, ()> {
+    let mut drops = vec![];
+
+    match mode {
+        // Left or right click, taking the result
+        0 => {
+            if button > 1 {
+                return Err(());
+            }
+            let result = match window.get(CRAFTING_RESULT) {
+                Some(result) => result,
+                None => return Ok(drops),
+            };
+            match cursor.clone() {
+                None => *cursor = Some(result),
+                Some(held)
+                    if held.stacks_with(&result)
+                        && held.amount + result.amount <= max_size(held.ty) =>
+                {
+                    *cursor = Some(held.with_amount(held.amount + result.amount));
+                }
+                // The result doesn't fit onto the held stack.
+                Some(_) => return Ok(drops),
+            }
+            drops.extend(use_ingredients(window));
+        }
+        // Shift-click, crafting as many times as possible
+        1 => {
+            for _ in 0..MAX_CRAFTS {
+                let result = match window.get(CRAFTING_RESULT) {
+                    Some(result) => result,
+                    None => break,
+                };
+                let range = window.player_slots();
+                if !window.fits(&result, range.clone()) {
+                    break;
+                }
+                window.move_stack(&result, range, true);
+                drops.extend(use_ingredients(window));
+                update_result(window, recipes);
+            }
+        }
+        // Number key, moving the result into an empty hotbar slot
+        2 => {
+            if button as usize >= HOTBAR_SIZE {
+                return Err(());
+            }
+            let hotbar_slot = window.hotbar_slot(button as usize);
+            let result = match window.get(CRAFTING_RESULT) {
+                Some(result) => result,
+                None => return Ok(drops),
+            };
+            if window.get(hotbar_slot).is_some() {
+                return Ok(drops);
+            }
+            window.set(hotbar_slot, Some(result));
+            drops.extend(use_ingredients(window));
+        }
+        // Drop key, throwing the result out of the window
+        4 => {
+            if cursor.is_some() {
+                return Ok(drops);
+            }
+            let result = match window.get(CRAFTING_RESULT) {
+                Some(result) => result,
+                None => return Ok(drops),
+            };
+            drops.push(result);
+            drops.extend(use_ingredients(window));
+        }
+        _ => return Err(()),
+    }
+
+    Ok(drops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{ContainerKind, WindowKind, CRAFTING_TABLE_SIZE};
+    use feather_core::inventory::{Inventory, InventoryType, SLOT_HOTBAR_OFFSET};
+    use feather_core::recipe::{Ingredient, Recipe, RecipeKind};
+
+    fn recipes() -> RecipeRegistry {
+        let mut recipes = RecipeRegistry::bundled();
+        recipes.insert(Recipe {
+            id: "test:cake".to_string(),
+            group: String::new(),
+            kind: RecipeKind::Shapeless {
+                ingredients: vec![
+                    Ingredient(vec![Item::MilkBucket]),
+                    Ingredient(vec![Item::Sugar]),
+                ],
+                result: ItemStack::new(Item::Cake, 1),
+            },
+        });
+        recipes
+    }
+
+    #[test]
+    fn test_craft() {
+        let recipes = recipes();
+        let log = Some(ItemStack::new(Item::OakLog, 1));
+        assert_eq!(
+            craft(&recipes, &[None, log.clone(), None, None], 2),
+            Some(ItemStack::new(Item::OakPlanks, 4))
+        );
+        assert_eq!(craft(&recipes, &[log.clone(), log, None, None], 2), None);
+        let empty: Vec<Option<ItemStack>> = vec![None; 4];
+        assert_eq!(craft(&recipes, &empty, 2), None);
+    }
+
+    #[test]
+    fn test_take_result() {
+        let recipes = recipes();
+        let mut slots = vec![None; CRAFTING_TABLE_SIZE];
+        slots[5] = Some(ItemStack::new(Item::OakLog, 2));
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        let mut window = Window {
+            kind: WindowKind::Container(ContainerKind::CraftingTable),
+            slots: &mut slots,
+            inventory: &mut inventory,
+            creative: false,
+            drag: None,
+        };
+        update_result(&mut window, &recipes);
+        assert_eq!(
+            window.get(CRAFTING_RESULT),
+            Some(ItemStack::new(Item::OakPlanks, 4))
+        );
+
+        let mut cursor = None;
+        click_result(&mut window, &mut cursor, 0, 0, &recipes).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::OakPlanks, 4)));
+        assert_eq!(window.get(5), Some(ItemStack::new(Item::OakLog, 1)));
+
+        // The result of the last click is taken
+        // onto the cursor after updating it.
+        update_result(&mut window, &recipes);
+        click_result(&mut window, &mut cursor, 0, 0, &recipes).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::OakPlanks, 8)));
+        assert_eq!(window.get(5), None);
+    }
+
+    #[test]
+    fn test_shift_click_result() {
+        let recipes = recipes();
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        inventory.set_item_at(2, ItemStack::new(Item::OakLog, 20));
+        let mut window = Window {
+            kind: WindowKind::Inventory,
+            slots: &mut [],
+            inventory: &mut inventory,
+            creative: false,
+            drag: None,
+        };
+        update_result(&mut window, &recipes);
+
+        let mut cursor = None;
+        click_result(&mut window, &mut cursor, 0, 1, &recipes).unwrap();
+        assert_eq!(cursor, None);
+        assert_eq!(window.get(2), None);
+        assert_eq!(window.get(CRAFTING_RESULT), None);
+        // The planks fill the hotbar from its end.
+        assert_eq!(
+            window.get(SLOT_HOTBAR_OFFSET + 8),
+            Some(ItemStack::new(Item::OakPlanks, 64))
+        );
+        assert_eq!(
+            window.get(SLOT_HOTBAR_OFFSET + 7),
+            Some(ItemStack::new(Item::OakPlanks, 16))
+        );
+    }
+
+    #[test]
+    fn test_remainder() {
+        let recipes = recipes();
+        let mut inventory = Inventory::new(InventoryType::Player, 46);
+        inventory.set_item_at(1, ItemStack::new(Item::MilkBucket, 1));
+        inventory.set_item_at(4, ItemStack::new(Item::Sugar, 3));
+        let mut window = Window {
+            kind: WindowKind::Inventory,
+            slots: &mut [],
+            inventory: &mut inventory,
+            creative: false,
+            drag: None,
+        };
+        update_result(&mut window, &recipes);
+        assert_eq!(
+            window.get(CRAFTING_RESULT),
+            Some(ItemStack::new(Item::Cake, 1))
+        );
+
+        let mut cursor = None;
+        click_result(&mut window, &mut cursor, 0, 0, &recipes).unwrap();
+        assert_eq!(cursor, Some(ItemStack::new(Item::Cake, 1)));
+        assert_eq!(window.get(1), Some(ItemStack::new(Item::Bucket, 1)));
+        assert_eq!(window.get(4), Some(ItemStack::new(Item::Sugar, 2)));
+
+        // The bucket left behind doesn't match the recipe.
+        update_result(&mut window, &recipes);
+        assert_eq!(window.get(CRAFTING_RESULT), None);
+    }
+}
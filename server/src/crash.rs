@@ -0,0 +1,275 @@
+//! Crash report generation.
+//!
+//! A panic hook writes a crash report to `crash-reports/`
+//! whenever any thread panics. Systems can annotate what
+//! they are currently working on using `context`, which is
+//! included in the report if a panic occurs while the
+//! returned guard is alive.
+//!
+//! Panics in the main loop additionally trigger an
+//! emergency save of the world before the server aborts.
+
+use crate::config::Config;
+use crate::{shutdown, PROTOCOL_VERSION, SERVER_VERSION};
+use backtrace::Backtrace;
+use feather_core::{BlockPosition, ChunkPosition};
+use parking_lot::Mutex;
+use specs::{Entity, World};
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe, PanicInfo};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Directory to which crash reports are written.
+pub const CRASH_REPORT_DIR: &str = "crash-reports";
+
+static TICK: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref CONFIG: Mutex<Option<Arc<Config>>> = Mutex::new(None);
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<Context>> = RefCell::new(vec![]);
+}
+
+/// Something a thread is working on,
+/// included in crash reports.
+#[derive(Debug, Clone, Copy)]
+pub enum Context {
+    Chunk(ChunkPosition),
+    Block(BlockPosition),
+    Entity(Entity),
+}
+
+/// Guard returned by `context`. The context
+/// is removed when the guard is dropped.
+pub struct ContextGuard {
+    _private: (),
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|context| {
+            context.borrow_mut().pop();
+        });
+    }
+}
+
+/// Marks the current thread as working on the given
+/// chunk, block, or entity until the guard is dropped.
+pub fn context(context: Context) -> ContextGuard {
+    CONTEXT.with(|c| c.borrow_mut().push(context));
+    ContextGuard { _private: () }
+}
+
+/// Sets the current tick number, shown in crash reports.
+pub fn set_tick(tick: u64) {
+    TICK.store(tick, Ordering::Relaxed);
+}
+
+/// Sets the configuration summarized in crash reports.
+pub fn set_config(config: Arc<Config>) {
+    *CONFIG.lock() = Some(config);
+}
+
+/// Installs the panic hook which writes crash reports.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let report = CrashReport::from_panic(info);
+        match report.write() {
+            Ok(path) => error!("A crash report has been saved to {}", path.display()),
+            Err(e) => error!("Failed to write crash report: {}", e),
+        }
+    }));
+}
+
+/// Attempts to save the world after a panic
+/// in the main loop. Any panic during saving
+/// is caught so that the caller can abort.
+pub fn emergency_save(world: &mut World) {
+    error!("The server has crashed! Attempting to save the world");
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        shutdown::save_chunks(world);
+        shutdown::save_level(world);
+        shutdown::save_maps(world);
+        shutdown::save_player_data(world);
+    }));
+
+    match result {
+        Ok(()) => info!("Emergency save completed"),
+        Err(_) => error!("Emergency save failed; the world may not have been saved"),
+    }
+}
+
+/// A crash report.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub description: String,
+    pub location: Option<String>,
+    pub thread: String,
+    pub backtrace: String,
+    pub tick: u64,
+    pub context: Vec<Context>,
+    pub config: Option<Arc<Config>>,
+    pub time: SystemTime,
+}
+
+impl CrashReport {
+    /// Creates a crash report for a panic
+    /// on the current thread.
+    pub fn from_panic(info: &PanicInfo) -> Self {
+        let description = if let Some(s) = info.payload().downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = info.payload().downcast_ref::<String>() {
+            s.clone()
+        } else {
+            String::from("Unknown panic")
+        };
+
+        Self {
+            description,
+            location: info.location().map(ToString::to_string),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("<unnamed>")
+                .to_string(),
+            backtrace: format!("{:?}", Backtrace::new()),
+            tick: TICK.load(Ordering::Relaxed),
+            context: CONTEXT.with(|context| context.borrow().clone()),
+            config: CONFIG.try_lock().and_then(|config| config.clone()),
+            time: SystemTime::now(),
+        }
+    }
+
+    /// Renders the report in the vanilla crash report layout.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "---- Feather Crash Report ----");
+        let _ = writeln!(out, "// Something went wrong. Sorry :(");
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "Time: {}",
+            humantime::format_rfc3339_seconds(self.time)
+        );
+        let _ = writeln!(out, "Description: {}", self.description);
+        let _ = writeln!(out);
+
+        let _ = writeln!(out, "-- Head --");
+        let _ = writeln!(out, "Thread: {}", self.thread);
+        if let Some(location) = &self.location {
+            let _ = writeln!(out, "Location: {}", location);
+        }
+        let _ = writeln!(out, "Stacktrace:");
+        let _ = writeln!(out, "{}", self.backtrace);
+        let _ = writeln!(out);
+
+        if !self.context.is_empty() {
+            let _ = writeln!(out, "-- Affected context --");
+            let _ = writeln!(out, "Details:");
+            for context in &self.context {
+                let _ = match context {
+                    Context::Chunk(pos) => writeln!(out, "\tChunk: {}, {}", pos.x, pos.z),
+                    Context::Block(pos) => {
+                        writeln!(out, "\tBlock: {}, {}, {}", pos.x, pos.y, pos.z)
+                    }
+                    Context::Entity(entity) => writeln!(out, "\tEntity: {:?}", entity),
+                };
+            }
+            let _ = writeln!(out);
+        }
+
+        let _ = writeln!(out, "-- System Details --");
+        let _ = writeln!(out, "Details:");
+        let _ = writeln!(out, "\tServer Version: {}", SERVER_VERSION);
+        let _ = writeln!(out, "\tProtocol Version: {}", PROTOCOL_VERSION);
+        let _ = writeln!(out, "\tTick: {}", self.tick);
+        let _ = writeln!(
+            out,
+            "\tOperating System: {} ({})",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        if let Some(config) = &self.config {
+            let _ = writeln!(out, "\tOnline Mode: {}", config.server.online_mode);
+            let _ = writeln!(out, "\tView Distance: {}", config.server.view_distance);
+            let _ = writeln!(out, "\tMax Players: {}", config.server.max_players);
+            let _ = writeln!(out, "\tWorld: {}", config.world.name);
+            let _ = writeln!(out, "\tGenerator: {}", config.world.generator);
+        }
+
+        out
+    }
+
+    /// Writes the report to `CRASH_REPORT_DIR`,
+    /// returning the path of the file.
+    pub fn write(&self) -> std::io::Result<PathBuf> {
+        fs::create_dir_all(CRASH_REPORT_DIR)?;
+
+        let timestamp = humantime::format_rfc3339_seconds(self.time)
+            .to_string()
+            .replace(':', ".")
+            .replace('T', "_")
+            .replace('Z', "");
+        let path = PathBuf::from(CRASH_REPORT_DIR).join(format!("crash-{}-server.txt", timestamp));
+
+        fs::write(&path, self.render())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> CrashReport {
+        CrashReport {
+            description: String::from("test panic"),
+            location: Some(String::from("src/lib.rs:1:1")),
+            thread: String::from("main"),
+            backtrace: String::from("<backtrace>"),
+            tick: 42,
+            context: vec![Context::Chunk(ChunkPosition::new(1, -2))],
+            config: Some(Arc::new(Config::default())),
+            time: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = report().render();
+
+        assert!(rendered.starts_with("---- Feather Crash Report ----"));
+        assert!(rendered.contains("Time: 1970-01-01T00:00:00Z"));
+        assert!(rendered.contains("Description: test panic"));
+        assert!(rendered.contains("Location: src/lib.rs:1:1"));
+        assert!(rendered.contains("\tChunk: 1, -2"));
+        assert!(rendered.contains("\tTick: 42"));
+        assert!(rendered.contains(SERVER_VERSION));
+        assert!(rendered.contains("\tWorld: world"));
+    }
+
+    #[test]
+    fn test_context() {
+        let current = || CONTEXT.with(|context| context.borrow().len());
+        assert_eq!(current(), 0);
+
+        {
+            let _chunk = context(Context::Chunk(ChunkPosition::new(0, 0)));
+            let _block = context(Context::Block(BlockPosition::new(0, 0, 0)));
+            assert_eq!(current(), 2);
+        }
+
+        assert_eq!(current(), 0);
+    }
+}
@@ -0,0 +1,611 @@
+//! Loading of datapack functions.
+//!
+//! Datapacks are directories in the world's `datapacks` folder.
+//! Functions are read from `data/<namespace>/functions/<path>.mcfunction`
+//! and are identified as `<namespace>:<path>`. Each non-empty line
+//! which is not a `#` comment is a command.
+//!
+//! Function tags are read from `data/<namespace>/tags/functions/<path>.json`.
+//! The functions tagged with `#minecraft:load` are executed once when
+//! the server starts, and those tagged with `#minecraft:tick` are
+//! executed every tick. Operators can execute functions and tags using
+//! `/function <name>`.
+//!
+//! Functions run with the permissions of the console. A `function`
+//! command inside a function is expanded in place, as in vanilla.
+//! Loot tables, recipes and item tags are loaded separately by the
+//! `loot` and `recipe` modules. Zipped datapacks and other datapack
+//! contents, such as advancements, are not yet supported.
+
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::entity::NamedComponent;
+use crate::lang::{Locale, Message};
+use crate::network::NetworkComponent;
+use crate::systems::FUNCTIONS;
+use crate::timings::DispatcherBuilderExt;
+use crossbeam::channel::Receiver;
+use hashbrown::{HashMap, HashSet};
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Builder, DispatcherBuilder, Entity, Read, ReadStorage, System, World, WorldExt, Write,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The tag of functions executed when the server starts.
+pub const LOAD_TAG: &str = "minecraft:load";
+/// The tag of functions executed every tick.
+pub const TICK_TAG: &str = "minecraft:tick";
+/// The maximum number of commands a single function
+/// may expand to, as with vanilla's `maxCommandChainLength`.
+pub const MAX_COMMANDS: usize = 65536;
+
+#[derive(Deserialize, Debug)]
+struct TagFile {
+    #[serde(default)]
+    replace: bool,
+    values: Vec<String>,
+}
+
+/// The tags of one kind of object, such as functions or items.
+/// Each tag has a list of entries, which are either object
+/// names or other tags prefixed with `#`.
+#[derive(Debug, Default)]
+pub struct Tags {
+    tags: HashMap<String, Vec<String>>,
+}
+
+impl Tags {
+    /// Loads the tag files in the given directory of a namespace,
+    /// for example `data/minecraft/tags/items`.
+    pub(crate) fn load_dir(&mut self, dir: &Path, namespace: &str) {
+        for (path, id) in files(dir, namespace, "json") {
+            let result = fs::read_to_string(&path)
+                .map_err(failure::Error::from)
+                .and_then(|s| self.add_json(id, &s).map_err(Into::into));
+            if let Err(e) = result {
+                warn!("Failed to read tag {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Adds the entries of a tag file. Tags from later datapacks
+    /// are merged with earlier ones unless they set `replace`.
+    pub(crate) fn add_json(&mut self, id: String, json: &str) -> Result<(), serde_json::Error> {
+        let tag: TagFile = serde_json::from_str(json)?;
+
+        let values = self.tags.entry(id).or_default();
+        if tag.replace {
+            values.clear();
+        }
+        for value in tag.values {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tags.contains_key(name)
+    }
+
+    /// Returns the entries of a tag, including
+    /// those of nested tags, in order.
+    pub fn get(&self, name: &str) -> Vec<&str> {
+        let mut values = vec![];
+        let mut visited = HashSet::new();
+        self.collect(name, &mut values, &mut visited);
+        values
+    }
+
+    fn collect<'a>(&'a self, name: &str, values: &mut Vec<&'a str>, visited: &mut HashSet<String>) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+
+        for value in self.tags.get(name).into_iter().flatten() {
+            match value.strip_prefix('#') {
+                Some(tag) => self.collect(tag, values, visited),
+                None => {
+                    if !values.contains(&value.as_str()) {
+                        values.push(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Resource containing the functions and
+/// function tags of all loaded datapacks.
+#[derive(Debug, Default)]
+pub struct Datapacks {
+    /// Names of the loaded datapacks.
+    packs: Vec<String>,
+    /// Commands of each function, without leading slashes.
+    functions: HashMap<String, Vec<String>>,
+    tags: Tags,
+}
+
+impl Datapacks {
+    /// Loads all datapacks in the `datapacks` folder
+    /// of the given world directory, in alphabetical order.
+    /// Files which fail to load are skipped.
+    pub fn load(world_dir: &Path) -> Self {
+        let mut datapacks = Self::default();
+        for pack in pack_dirs(world_dir) {
+            datapacks.load_pack(&pack);
+        }
+        datapacks
+    }
+
+    fn load_pack(&mut self, pack: &Path) {
+        let namespaces = match fs::read_dir(pack.join("data")) {
+            Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()),
+            Err(_) => return,
+        };
+
+        for namespace in namespaces {
+            let name = match namespace.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            for (path, id) in files(&namespace.join("functions"), &name, "mcfunction") {
+                match fs::read_to_string(&path) {
+                    Ok(source) => {
+                        self.functions.insert(id, parse_function(&source));
+                    }
+                    Err(e) => warn!("Failed to read function {}: {}", path.display(), e),
+                }
+            }
+
+            self.tags
+                .load_dir(&namespace.join("tags").join("functions"), &name);
+        }
+
+        self.packs.push(
+            pack.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        );
+    }
+
+    /// Returns the names of the loaded datapacks.
+    pub fn packs(&self) -> &[String] {
+        &self.packs
+    }
+
+    /// Returns the number of loaded functions.
+    pub fn function_count(&self) -> usize {
+        self.functions.len()
+    }
+
+    /// Returns the commands of a function.
+    pub fn function(&self, name: &str) -> Option<&[String]> {
+        self.functions.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns the functions with a tag, including
+    /// those of nested tags, in order.
+    pub fn tag(&self, name: &str) -> Vec<&str> {
+        self.tags.get(name)
+    }
+
+    /// Expands a function, or a tag if the name starts with `#`,
+    /// into the commands it executes. Nested `function` commands
+    /// are expanded in place. Returns `None` if the function or
+    /// tag does not exist.
+    pub fn expand(&self, name: &str) -> Option<Vec<String>> {
+        let name = qualify(name);
+        let mut commands = vec![];
+
+        match name.strip_prefix('#') {
+            Some(tag) => {
+                if !self.tags.contains(tag) {
+                    return None;
+                }
+                for function in self.tag(tag) {
+                    self.expand_into(function, &mut commands, 0);
+                }
+            }
+            None => {
+                self.function(&name)?;
+                self.expand_into(&name, &mut commands, 0);
+            }
+        }
+
+        Some(commands)
+    }
+
+    fn expand_into(&self, name: &str, commands: &mut Vec<String>, depth: usize) {
+        // Guard against functions which call themselves.
+        if depth > 64 {
+            return;
+        }
+
+        for command in self.function(name).into_iter().flatten() {
+            if commands.len() >= MAX_COMMANDS {
+                return;
+            }
+
+            let mut parts = command.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("function"), Some(nested)) => {
+                    let nested = qualify(nested.trim());
+                    match nested.strip_prefix('#') {
+                        Some(tag) => {
+                            for function in self.tag(tag) {
+                                self.expand_into(function, commands, depth + 1);
+                            }
+                        }
+                        None => self.expand_into(&nested, commands, depth + 1),
+                    }
+                }
+                _ => commands.push(command.clone()),
+            }
+        }
+    }
+}
+
+/// Parses the source of a `.mcfunction` file into commands.
+fn parse_function(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.trim_start_matches('/').to_string())
+        .collect()
+}
+
+/// Adds the `minecraft` namespace to a function
+/// or tag name if it has none.
+fn qualify(name: &str) -> String {
+    let (prefix, name) = match name.strip_prefix('#') {
+        Some(name) => ("#", name),
+        None => ("", name),
+    };
+    if name.contains(':') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}minecraft:{}", prefix, name)
+    }
+}
+
+/// Returns the directories of the datapacks in
+/// the given world directory, in alphabetical order.
+pub(crate) fn pack_dirs(world_dir: &Path) -> Vec<PathBuf> {
+    let mut packs: Vec<_> = match fs::read_dir(world_dir.join("datapacks")) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.join("pack.mcmeta").is_file())
+            .collect(),
+        Err(_) => return vec![],
+    };
+    packs.sort();
+    packs
+}
+
+/// Returns the files with the given extension in a directory
+/// and its subdirectories, along with their identifiers.
+pub(crate) fn files(root: &Path, namespace: &str, extension: &str) -> Vec<(PathBuf, String)> {
+    let mut res = vec![];
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+                continue;
+            }
+
+            let relative = match path.with_extension("").strip_prefix(root) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => continue,
+            };
+            let parts: Option<Vec<&str>> = relative
+                .components()
+                .map(|component| component.as_os_str().to_str())
+                .collect();
+            if let Some(parts) = parts {
+                let id = format!("{}:{}", namespace, parts.join("/"));
+                res.push((path, id));
+            }
+        }
+    }
+
+    res.sort();
+    res
+}
+
+/// Sender of the commands executed by functions.
+struct FunctionSender {
+    entity: Entity,
+    output: Receiver<String>,
+}
+
+/// System which executes functions: those tagged with
+/// `#minecraft:load` on the first tick, those tagged with
+/// `#minecraft:tick` every tick, and those requested
+/// using `/function`.
+#[derive(Default)]
+pub struct FunctionSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+    sender: Option<FunctionSender>,
+    loaded: bool,
+}
+
+impl<'a> System<'a> for FunctionSystem {
+    type SystemData = (
+        Write<'a, EventChannel<CommandEvent>>,
+        Read<'a, Datapacks>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut command_events, datapacks, config, locale, nameds, networks, consoles) = data;
+        let sender = self.sender.as_ref().unwrap();
+
+        // Output of commands run by functions is only of
+        // interest when debugging a datapack.
+        while let Ok(line) = sender.output.try_recv() {
+            debug!("[function] {}", line);
+        }
+
+        let mut commands = vec![];
+
+        if !self.loaded {
+            self.loaded = true;
+            commands.extend(
+                datapacks
+                    .expand(&format!("#{}", LOAD_TAG))
+                    .unwrap_or_default(),
+            );
+        }
+        commands.extend(
+            datapacks
+                .expand(&format!("#{}", TICK_TAG))
+                .unwrap_or_default(),
+        );
+
+        let requests: Vec<CommandEvent> = command_events
+            .read(self.reader.as_mut().unwrap())
+            .filter(|event| event.name == "function" && event.sender != sender.entity)
+            .cloned()
+            .collect();
+        for event in requests {
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let message = match event.args.as_slice() {
+                [name] => match datapacks.expand(name) {
+                    Some(expanded) => {
+                        let message = Message::translate("feather.command.function.success")
+                            .with(expanded.len())
+                            .with(name);
+                        commands.extend(expanded);
+                        message
+                    }
+                    None => Message::translate("feather.command.function.unknown").with(name),
+                },
+                _ => usage("/function <name>"),
+            };
+            reply(event.sender, &networks, &consoles, &locale, message);
+        }
+
+        command_events.iter_write(
+            commands
+                .iter()
+                .filter_map(|command| CommandEvent::parse(sender.entity, &format!("/{}", command))),
+        );
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+
+        let (tx, rx) = crossbeam::unbounded();
+        world.register::<ConsoleComponent>();
+        let entity = world
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+        self.sender = Some(FunctionSender { entity, output: rx });
+
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("function");
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(FunctionSystem::default(), FUNCTIONS, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Creates a world directory with two datapacks.
+    fn world_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("feather-datapacks-{}", uuid::Uuid::new_v4()));
+        let a = dir.join("datapacks").join("a");
+        let b = dir.join("datapacks").join("b");
+
+        write(&a.join("pack.mcmeta"), "{}");
+        write(
+            &a.join("data/test/functions/setup.mcfunction"),
+            "# Comment\n\nsay Hello\n/time set day\nfunction test:util/inner\n",
+        );
+        write(
+            &a.join("data/test/functions/util/inner.mcfunction"),
+            "say Inner",
+        );
+        write(
+            &a.join("data/test/functions/recursive.mcfunction"),
+            "say Again\nfunction test:recursive",
+        );
+        write(
+            &a.join("data/minecraft/tags/functions/load.json"),
+            r#"{"values": ["test:setup"]}"#,
+        );
+        write(
+            &a.join("data/minecraft/tags/functions/tick.json"),
+            r#"{"values": ["test:util/inner"]}"#,
+        );
+
+        write(&b.join("pack.mcmeta"), "{}");
+        write(
+            &b.join("data/minecraft/tags/functions/load.json"),
+            r##"{"values": ["#test:nested", "test:util/inner"]}"##,
+        );
+        write(
+            &b.join("data/test/tags/functions/nested.json"),
+            r##"{"values": ["test:setup", "#minecraft:load"]}"##,
+        );
+        // Not a datapack, since it has no pack.mcmeta.
+        write(
+            &dir.join("datapacks/c/data/test/functions/ignored.mcfunction"),
+            "say Ignored",
+        );
+
+        dir
+    }
+
+    #[test]
+    fn test_parse_function() {
+        assert_eq!(
+            parse_function("# Comment\n  say Hi  \n\n/kill @e\n"),
+            vec![String::from("say Hi"), String::from("kill @e")]
+        );
+    }
+
+    #[test]
+    fn test_qualify() {
+        assert_eq!(qualify("foo"), "minecraft:foo");
+        assert_eq!(qualify("test:foo/bar"), "test:foo/bar");
+        assert_eq!(qualify("#tick"), "#minecraft:tick");
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = world_dir();
+        let datapacks = Datapacks::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(datapacks.packs(), &[String::from("a"), String::from("b")]);
+        assert_eq!(datapacks.function_count(), 3);
+        assert_eq!(
+            datapacks.function("test:setup").unwrap(),
+            &["say Hello", "time set day", "function test:util/inner"]
+        );
+        assert!(datapacks.function("test:ignored").is_none());
+
+        // Tags are merged across datapacks, and recursive tags are ignored.
+        assert_eq!(
+            datapacks.tag(LOAD_TAG),
+            vec!["test:setup", "test:util/inner"]
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = world_dir();
+        let datapacks = Datapacks::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            datapacks.expand("test:setup").unwrap(),
+            vec!["say Hello", "time set day", "say Inner"]
+        );
+        assert_eq!(
+            datapacks.expand("#load").unwrap(),
+            vec!["say Hello", "time set day", "say Inner", "say Inner"]
+        );
+        assert!(datapacks.expand("test:missing").is_none());
+        assert!(datapacks.expand("#test:missing").is_none());
+
+        // Recursion is cut off.
+        let recursive = datapacks.expand("test:recursive").unwrap();
+        assert!(recursive.len() > 1 && recursive.len() < MAX_COMMANDS);
+    }
+
+    #[test]
+    fn test_function_system() {
+        let dir = world_dir();
+        let datapacks = Datapacks::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (mut w, mut d) = t::builder().with(FunctionSystem::default(), "").build();
+        w.insert(datapacks);
+        let mut reader = t::reader::<CommandEvent>(&w);
+
+        d.dispatch(&w);
+        let names: Vec<String> = t::triggered_events::<CommandEvent>(&w, &mut reader)
+            .into_iter()
+            .map(|event| event.name)
+            .collect();
+        // The load tag, then the tick tag.
+        assert_eq!(names, vec!["say", "time", "say", "say", "say"]);
+
+        d.dispatch(&w);
+        assert_eq!(
+            t::triggered_events::<CommandEvent>(&w, &mut reader).len(),
+            1
+        );
+
+        let (tx, rx) = crossbeam::unbounded();
+        let console = w
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+        t::trigger_event(
+            &w,
+            CommandEvent::parse(console, "/function test:setup").unwrap(),
+        );
+        t::trigger_event(
+            &w,
+            CommandEvent::parse(console, "/function nothing").unwrap(),
+        );
+        d.dispatch(&w);
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            "Executed 3 commands from test:setup."
+        );
+        assert_eq!(rx.try_recv().unwrap(), "Unknown function nothing.");
+    }
+}
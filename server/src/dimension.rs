@@ -0,0 +1,882 @@
+//! Dimensions hosted by the server.
+//!
+//! The primary dimension is the world configured by `world.name`.
+//! Additional dimensions, each with its own generator, world folder
+//! and environment settings, are added using `Dimensions::register`.
+//! Players are moved between dimensions by triggering a
+//! `DimensionChangeEvent`: they are sent a Respawn packet followed
+//! by the chunks around their new position.
+//!
+//! Each dimension keeps its own time, which advances every
+//! tick and is sent to players entering the dimension. The time
+//! of a dimension other than the primary one is saved to the
+//! `level.dat` file in its world folder on shutdown.
+//!
+//! Only the primary dimension is simulated for now. The chunks of
+//! other dimensions are loaded, held by the players inside them,
+//! autosaved and unloaded like those of the primary dimension, but
+//! the entities stored in them are not spawned: their data is kept
+//! as loaded and saved again with the chunk. Players cannot modify
+//! blocks there: the blocks they break or place are sent back to
+//! them unchanged. Only players can leave the primary dimension.
+
+use crate::chunk_logic::{
+    self, ChunkHolderComponent, ChunkHolderReleaseEvent, ChunkHolders, ChunkUnloadQueue,
+    ChunkWorkerHandle,
+};
+use crate::chunkworker;
+use crate::config::Config;
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::{self, ChunkPendingComponent, LoadedChunksComponent};
+use crate::systems::{DIMENSION_CHANGE, DIMENSION_CHUNKS};
+use crate::time::{self, Time};
+use crate::timings::DispatcherBuilderExt;
+use crate::view_distance::ViewDistance;
+use crate::weather::{self, Weather};
+use crate::worldgen::WorldGenerator;
+use crate::{current_time_in_millis, TickCount, TICK_TIME};
+use feather_core::entity::EntityData;
+use feather_core::level::{deserialize_level_file, save_level_file, LevelData, Root};
+use feather_core::network::packet::implementation::{
+    BlockChange, PlayerPositionAndLookClientbound, Respawn,
+};
+use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition, Position};
+use feather_core::{BlockExt, Chunk, Difficulty, Dimension};
+use hashbrown::HashMap;
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DispatcherBuilder, Entity, HashMapStorage, Join, LazyUpdate, Read, ReadExpect,
+    ReadStorage, System, Write, WriteExpect, WriteStorage,
+};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The ID of the primary dimension.
+pub const PRIMARY_DIMENSION: DimensionId = DimensionId(0);
+
+/// Identifies a dimension registered in `Dimensions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DimensionId(pub usize);
+
+/// The settings of a dimension.
+#[derive(Clone)]
+pub struct DimensionSettings {
+    /// A unique name, such as `the_nether`.
+    pub name: String,
+    /// The environment sent to clients, which
+    /// determines the sky and fog they render.
+    pub environment: Dimension,
+    /// Whether sky light is calculated. Chunks of
+    /// dimensions without it are sent unlit by the sky.
+    pub has_skylight: bool,
+    /// The brightness of blocks with no light, from 0 to 1.
+    pub ambient_light: f32,
+    /// The generator used for chunks not in the world folder.
+    pub generator: Arc<dyn WorldGenerator>,
+    /// The folder containing the dimension's region files.
+    pub dir: PathBuf,
+}
+
+impl DimensionSettings {
+    /// Creates settings with the skylight and ambient
+    /// light of the vanilla dimension with the given environment.
+    pub fn new(
+        name: &str,
+        environment: Dimension,
+        generator: Arc<dyn WorldGenerator>,
+        dir: impl Into<PathBuf>,
+    ) -> Self {
+        let (has_skylight, ambient_light) = match environment {
+            Dimension::Overwold => (true, 0.0),
+            Dimension::Nether => (false, 0.1),
+            Dimension::End => (false, 0.0),
+        };
+
+        Self {
+            name: name.to_string(),
+            environment,
+            has_skylight,
+            ambient_light,
+            generator,
+            dir: dir.into(),
+        }
+    }
+
+    pub fn with_skylight(mut self, has_skylight: bool) -> Self {
+        self.has_skylight = has_skylight;
+        self
+    }
+
+    pub fn with_ambient_light(mut self, ambient_light: f32) -> Self {
+        self.ambient_light = ambient_light;
+        self
+    }
+
+    /// Returns the brightness, from 0 to 1, of a
+    /// block with the given light level.
+    pub fn brightness(&self, light: u8) -> f32 {
+        let darkness = 1.0 - f32::from(light.min(15)) / 15.0;
+        let brightness = (1.0 - darkness) / (darkness * 3.0 + 1.0);
+        brightness * (1.0 - self.ambient_light) + self.ambient_light
+    }
+}
+
+/// The chunks of a dimension other than the primary
+/// one, which uses the `ChunkMap`, `ChunkWorkerHandle`
+/// and `ChunkHolders` resources instead.
+pub struct DimensionWorld {
+    pub chunk_map: ChunkMap,
+    pub worker: ChunkWorkerHandle,
+    /// The time of the dimension, which starts at zero
+    /// when the dimension is first registered.
+    pub time: Time,
+    holders: ChunkHolders,
+    release_events: EventChannel<ChunkHolderReleaseEvent>,
+    release_reader: ReaderId<ChunkHolderReleaseEvent>,
+    unload_queue: ChunkUnloadQueue,
+    /// The entities stored in each loaded chunk, which
+    /// are saved again along with the chunk.
+    entities: HashMap<ChunkPosition, Vec<EntityData>>,
+}
+
+impl DimensionWorld {
+    fn new(worker: ChunkWorkerHandle) -> Self {
+        let mut release_events = EventChannel::new();
+        let release_reader = release_events.register_reader();
+
+        Self {
+            chunk_map: ChunkMap::new(),
+            worker,
+            time: Time::default(),
+            holders: ChunkHolders::default(),
+            release_events,
+            release_reader,
+            unload_queue: ChunkUnloadQueue::default(),
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Returns the entities holding the chunks of the dimension.
+    pub fn holders(&self) -> &ChunkHolders {
+        &self.holders
+    }
+
+    pub fn insert_holder(&mut self, chunk: ChunkPosition, holder: Entity) {
+        self.holders.insert_holder(chunk, holder);
+    }
+
+    /// Removes a hold on a chunk. Chunks without holders
+    /// are unloaded after `world.chunk_unload_delay`.
+    pub fn remove_holder(&mut self, chunk: ChunkPosition, holder: Entity) {
+        self.holders
+            .remove_holder(chunk, holder, &mut self.release_events);
+    }
+
+    /// Sends the block at the given position to a player, reverting
+    /// any change their client predicted, since blocks in this
+    /// dimension can't be modified yet.
+    pub fn revert_block(&self, pos: BlockPosition, network: &NetworkComponent) {
+        if let Some(block) = self.chunk_map.block_at(pos) {
+            send_packet_to_player(
+                network,
+                BlockChange::new(pos, i32::from(block.native_state_id())),
+            );
+        }
+    }
+
+    /// Returns the data of the entities stored in a loaded chunk.
+    pub fn stored_entities(&self, chunk: ChunkPosition) -> &[EntityData] {
+        self.entities.get(&chunk).map_or(&[], Vec::as_slice)
+    }
+
+    fn chunk_loaded(&mut self, chunk: Chunk, entities: Vec<EntityData>) {
+        let pos = chunk.position();
+        // Entities of unknown types can't be written back.
+        let entities: Vec<_> = entities
+            .into_iter()
+            .filter(|entity| match entity {
+                EntityData::Unknown => false,
+                _ => true,
+            })
+            .collect();
+        if !entities.is_empty() {
+            self.entities.insert(pos, entities);
+        }
+        self.chunk_map.set_chunk_at(pos, chunk);
+    }
+
+    /// Unloads the chunks whose holders were released at least
+    /// `unload_delay` ticks ago, saving those which were modified.
+    fn unload_chunks(&mut self, tick: u64, unload_delay: u64) {
+        for event in self.release_events.read(&mut self.release_reader) {
+            if !self.holders.chunk_has_holders(event.chunk) {
+                self.unload_queue.push(event.chunk, tick + unload_delay);
+            }
+        }
+
+        while let Some(pos) = self.unload_queue.pop_due(tick, &self.holders) {
+            if let Some(mut chunk) = self.chunk_map.unload_chunk_at(pos) {
+                let entities = self.entities.remove(&pos).unwrap_or_default();
+                if chunk.check_modified() {
+                    chunk_logic::save_chunk(&self.worker, Arc::new(chunk), entities);
+                }
+            }
+        }
+    }
+
+    /// Saves all modified chunks along with their stored
+    /// entities. Returns the number of chunks queued for saving.
+    pub fn save_chunks(&mut self) -> usize {
+        let mut count = 0;
+        for (pos, chunk) in self.chunk_map.chunks_mut() {
+            if chunk.check_modified() {
+                let entities = self.entities.get(pos).cloned().unwrap_or_default();
+                chunk_logic::save_chunk(&self.worker, Arc::new(chunk.clone()), entities);
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+struct Entry {
+    settings: DimensionSettings,
+    world: Option<DimensionWorld>,
+}
+
+#[derive(Debug, Fail)]
+pub enum DimensionError {
+    #[fail(display = "a dimension named {} already exists", _0)]
+    DuplicateName(String),
+    #[fail(display = "failed to access world folder: {}", _0)]
+    Io(#[fail(cause)] std::io::Error),
+    #[fail(display = "failed to read or write level.dat: {}", _0)]
+    Level(#[fail(cause)] nbt::Error),
+}
+
+/// Resource containing the registered dimensions.
+pub struct Dimensions {
+    entries: Vec<Entry>,
+}
+
+impl Dimensions {
+    /// Creates a registry containing only the primary dimension.
+    pub fn new(primary: DimensionSettings) -> Self {
+        Self {
+            entries: vec![Entry {
+                settings: primary,
+                world: None,
+            }],
+        }
+    }
+
+    /// Registers a dimension, creating its world folder
+    /// and starting a chunk worker for it. The time of the
+    /// dimension is loaded from the `level.dat` file in
+    /// its world folder, if it exists.
+    pub fn register(&mut self, settings: DimensionSettings) -> Result<DimensionId, DimensionError> {
+        if self.find(&settings.name).is_some() {
+            return Err(DimensionError::DuplicateName(settings.name));
+        }
+
+        fs::create_dir_all(&settings.dir).map_err(DimensionError::Io)?;
+        let time = load_time(&settings.dir)?;
+
+        info!(
+            "Registering dimension {} in {}",
+            settings.name,
+            settings.dir.display()
+        );
+        let (sender, receiver) = chunkworker::start(
+            &settings.dir,
+            Arc::clone(&settings.generator),
+            settings.has_skylight,
+        );
+
+        let mut world = DimensionWorld::new(ChunkWorkerHandle { sender, receiver });
+        world.time = time;
+        self.entries.push(Entry {
+            settings,
+            world: Some(world),
+        });
+        Ok(DimensionId(self.entries.len() - 1))
+    }
+
+    pub fn get(&self, id: DimensionId) -> Option<&DimensionSettings> {
+        self.entries.get(id.0).map(|entry| &entry.settings)
+    }
+
+    /// Returns the dimension with the given name.
+    pub fn find(&self, name: &str) -> Option<DimensionId> {
+        self.entries
+            .iter()
+            .position(|entry| entry.settings.name == name)
+            .map(DimensionId)
+    }
+
+    /// Returns the first registered dimension with the given
+    /// environment, preferring the primary dimension.
+    pub fn with_environment(&self, environment: Dimension) -> Option<DimensionId> {
+        self.entries
+            .iter()
+            .position(|entry| entry.settings.environment == environment)
+            .map(DimensionId)
+    }
+
+    /// Returns the chunks of a dimension, or `None`
+    /// for the primary dimension.
+    pub fn world(&self, id: DimensionId) -> Option<&DimensionWorld> {
+        self.entries
+            .get(id.0)
+            .and_then(|entry| entry.world.as_ref())
+    }
+
+    pub fn world_mut(&mut self, id: DimensionId) -> Option<&mut DimensionWorld> {
+        self.entries
+            .get_mut(id.0)
+            .and_then(|entry| entry.world.as_mut())
+    }
+
+    /// Returns the settings and chunks of a dimension,
+    /// or `None` for the primary dimension.
+    pub fn entry_mut(
+        &mut self,
+        id: DimensionId,
+    ) -> Option<(&DimensionSettings, &mut DimensionWorld)> {
+        self.entries.get_mut(id.0).and_then(|entry| {
+            let settings = &entry.settings;
+            entry.world.as_mut().map(|world| (settings, world))
+        })
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = DimensionId> {
+        (0..self.entries.len()).map(DimensionId)
+    }
+
+    /// Returns the settings and chunks of all
+    /// dimensions other than the primary one.
+    pub fn worlds_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (DimensionId, &DimensionSettings, &mut DimensionWorld)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(id, entry)| {
+                let settings = &entry.settings;
+                entry
+                    .world
+                    .as_mut()
+                    .map(|world| (DimensionId(id), settings, world))
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Saves the modified chunks and the time of all dimensions
+    /// other than the primary one and stops their chunk workers,
+    /// blocking until they have finished. Returns the number
+    /// of chunks saved.
+    pub fn shut_down(&mut self) -> usize {
+        let mut saved = 0;
+        for (_, settings, world) in self.worlds_mut() {
+            if let Err(e) = save_time(&settings.dir, world.time) {
+                error!(
+                    "Failed to save the time of dimension {}: {}",
+                    settings.name, e
+                );
+            }
+
+            world.save_chunks();
+            world
+                .worker
+                .sender
+                .send(chunkworker::Request::ShutDown)
+                .unwrap();
+
+            while let Ok(reply) = world.worker.receiver.recv() {
+                match reply {
+                    chunkworker::Reply::SavedChunk(_) => saved += 1,
+                    chunkworker::Reply::ShutDown => break,
+                    _ => (),
+                }
+            }
+        }
+        saved
+    }
+}
+
+/// Loads the time of a dimension from the `level.dat`
+/// file in its world folder, or returns zero if
+/// the file does not exist.
+fn load_time(dir: &Path) -> Result<Time, DimensionError> {
+    let file = match File::open(dir.join("level.dat")) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Time::default()),
+        Err(e) => return Err(DimensionError::Io(e)),
+    };
+    let level = deserialize_level_file(file).map_err(DimensionError::Level)?;
+    Ok(Time(level.day_time as u64))
+}
+
+/// Saves the time of a dimension to the
+/// `level.dat` file in its world folder.
+fn save_time(dir: &Path, time: Time) -> Result<(), DimensionError> {
+    let level = LevelData {
+        day_time: time.0 as i64,
+        time: time.0 as i64,
+        last_played: current_time_in_millis() as i64,
+        ..LevelData::default()
+    };
+    let mut file = File::create(dir.join("level.dat")).map_err(DimensionError::Io)?;
+    save_level_file(&Root { data: level }, &mut file).map_err(DimensionError::Level)
+}
+
+/// Component storing the dimension of an entity
+/// outside of the primary dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DimensionComponent(pub DimensionId);
+
+impl DimensionComponent {
+    /// Returns the dimension of an entity
+    /// given its dimension component.
+    pub fn of(component: Option<&Self>) -> DimensionId {
+        component.map_or(PRIMARY_DIMENSION, |component| component.0)
+    }
+}
+
+impl Component for DimensionComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Event which can be triggered to move a player
+/// into a dimension at the given position.
+#[derive(Debug, Clone)]
+pub struct DimensionChangeEvent {
+    pub entity: Entity,
+    pub dimension: DimensionId,
+    pub position: Position,
+}
+
+/// System which moves players into other
+/// dimensions in response to `DimensionChangeEvent`s.
+#[derive(Default)]
+pub struct DimensionChangeSystem {
+    reader: Option<ReaderId<DimensionChangeEvent>>,
+}
+
+impl<'a> System<'a> for DimensionChangeSystem {
+    type SystemData = (
+        Read<'a, EventChannel<DimensionChangeEvent>>,
+        WriteExpect<'a, Dimensions>,
+        WriteStorage<'a, DimensionComponent>,
+        WriteStorage<'a, PositionComponent>,
+        WriteStorage<'a, LoadedChunksComponent>,
+        WriteStorage<'a, ChunkPendingComponent>,
+        WriteStorage<'a, ChunkHolderComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        Write<'a, ChunkHolders>,
+        Write<'a, EventChannel<ChunkHolderReleaseEvent>>,
+        Read<'a, ChunkMap>,
+        ReadExpect<'a, ChunkWorkerHandle>,
+        Read<'a, LevelData>,
+        Read<'a, Weather>,
+        Read<'a, Time>,
+        ReadExpect<'a, ViewDistance>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            mut dimensions,
+            mut dimension_comps,
+            mut positions,
+            mut loaded_chunks_comps,
+            mut pendings,
+            mut holder_comps,
+            networks,
+            players,
+            mut holders,
+            mut release_events,
+            chunk_map,
+            worker_handle,
+            level,
+            weather,
+            primary_time,
+            view_distance,
+            lazy,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let entity = event.entity;
+            let (network, player) = match (networks.get(entity), players.get(entity)) {
+                (Some(network), Some(player)) => (network, player),
+                _ => continue,
+            };
+            let settings = match dimensions.get(event.dimension) {
+                Some(settings) => settings.clone(),
+                None => {
+                    warn!("Attempted to move a player into an unknown dimension");
+                    continue;
+                }
+            };
+            let from = DimensionComponent::of(dimension_comps.get(entity));
+
+            // The client ignores Respawn packets for the environment
+            // it is already in, so respawn it elsewhere first.
+            let respawn = |environment: Dimension| {
+                Respawn::new(
+                    environment.get_id(),
+                    Difficulty::Medium.get_id(),
+                    player.gamemode.get_id(),
+                    level.generator_name.clone(),
+                )
+            };
+            let from_environment = dimensions.get(from).map(|from| from.environment);
+            if from_environment == Some(settings.environment) {
+                let other = match settings.environment {
+                    Dimension::Nether => Dimension::Overwold,
+                    _ => Dimension::Nether,
+                };
+                send_packet_to_player(network, respawn(other));
+            }
+            send_packet_to_player(network, respawn(settings.environment));
+            if event.dimension == PRIMARY_DIMENSION && weather.is_raining() {
+                weather::send_weather(network, *weather);
+            }
+            let time = dimensions
+                .world(event.dimension)
+                .map_or(*primary_time, |world| world.time);
+            time::send_time(network, time);
+
+            // The client discards all chunks on respawn.
+            let holder = holder_comps.get_mut(entity);
+            let loaded_chunks = loaded_chunks_comps.get_mut(entity);
+            let (holder, loaded_chunks) = match (holder, loaded_chunks) {
+                (Some(holder), Some(loaded_chunks)) => (holder, loaded_chunks),
+                _ => continue,
+            };
+            for chunk in holder.holds.drain() {
+                match dimensions.world_mut(from) {
+                    Some(world) => world.remove_holder(chunk, entity),
+                    None => holders.remove_holder(chunk, entity, &mut release_events),
+                }
+            }
+            loaded_chunks.clear();
+            if let Some(pending) = pendings.get_mut(entity) {
+                pending.clear();
+            }
+
+            if event.dimension == PRIMARY_DIMENSION {
+                dimension_comps.remove(entity);
+            } else {
+                dimension_comps
+                    .insert(entity, DimensionComponent(event.dimension))
+                    .unwrap();
+            }
+
+            if let Some(position) = positions.get_mut(entity) {
+                position.previous = event.position;
+                position.current = event.position;
+            }
+
+            let center = event.position.chunk_pos();
+            let mut chunks: Vec<_> = player::chunks_within_view_distance(*view_distance, center)
+                .into_iter()
+                .collect();
+            chunks.sort_unstable_by_key(|chunk| chunk.manhattan_distance(center));
+
+            for chunk in chunks {
+                match dimensions.world_mut(event.dimension) {
+                    Some(world) => player::send_dimension_chunk_to_player(
+                        chunk,
+                        network,
+                        entity,
+                        world,
+                        &settings,
+                        holder,
+                        loaded_chunks,
+                        &lazy,
+                    ),
+                    None => player::send_chunk_to_player(
+                        chunk,
+                        network,
+                        entity,
+                        &chunk_map,
+                        &worker_handle,
+                        &mut holders,
+                        holder,
+                        loaded_chunks,
+                        &lazy,
+                    ),
+                }
+            }
+
+            let position_and_look = PlayerPositionAndLookClientbound::new(
+                event.position.x,
+                event.position.y,
+                event.position.z,
+                event.position.yaw,
+                event.position.pitch,
+                0, // Flags - unused by us
+                0, // Teleport ID - unused by us
+            );
+            send_packet_to_player(network, position_and_look);
+
+            debug!("Moved player {:?} into dimension {}", entity, settings.name);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System for receiving the chunks of dimensions other than
+/// the primary one from their chunk workers and sending
+/// them to the players waiting for them, and for unloading
+/// chunks of those dimensions once they have no holders.
+pub struct DimensionChunkSystem;
+
+impl<'a> System<'a> for DimensionChunkSystem {
+    type SystemData = (
+        WriteExpect<'a, Dimensions>,
+        WriteStorage<'a, ChunkPendingComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Read<'a, TickCount>,
+        Read<'a, Arc<Config>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut dimensions, mut pendings, networks, dimension_comps, tick_count, config) = data;
+        let unload_delay = config.world.chunk_unload_delay.as_millis() as u64 / TICK_TIME;
+
+        for (id, settings, world) in dimensions.worlds_mut() {
+            world.unload_chunks(tick_count.0, unload_delay);
+
+            while let Ok(reply) = world.worker.receiver.try_recv() {
+                let (pos, result) = match reply {
+                    chunkworker::Reply::LoadedChunk(pos, result) => (pos, result),
+                    _ => continue,
+                };
+
+                match result {
+                    Ok((chunk, entities)) => world.chunk_loaded(chunk, entities),
+                    Err(err) => warn!(
+                        "Failed to load chunk at {:?} in dimension {}: {}",
+                        pos, settings.name, err
+                    ),
+                }
+
+                let chunk = world.chunk_map.chunk_at(pos);
+                for (network, pending, dimension) in
+                    (&networks, &mut pendings, &dimension_comps).join()
+                {
+                    if dimension.0 != id || !pending.remove(&pos) {
+                        continue;
+                    }
+                    if let Some(chunk) = chunk {
+                        let sky_light = settings.environment == Dimension::Overwold;
+                        player::send_chunk_data(chunk, network, sky_light);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(DimensionChangeSystem::default(), DIMENSION_CHANGE, &[]);
+    dispatcher.add_timed(DimensionChunkSystem, DIMENSION_CHUNKS, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use crate::worldgen::EmptyWorldGenerator;
+    use feather_core::entity::{AnimalData, BaseEntityData};
+    use feather_core::network::cast_packet;
+    use feather_core::packet::TimeUpdate;
+    use feather_core::PacketType;
+    use specs::WorldExt;
+
+    fn settings(name: &str, environment: Dimension) -> DimensionSettings {
+        let dir = std::env::temp_dir().join(format!("feather-{}-{}", name, uuid::Uuid::new_v4()));
+        DimensionSettings::new(name, environment, Arc::new(EmptyWorldGenerator {}), dir)
+    }
+
+    #[test]
+    fn test_register() {
+        let mut dimensions = Dimensions::new(settings("overworld", Dimension::Overwold));
+
+        let nether = settings("the_nether", Dimension::Nether);
+        let dir = nether.dir.clone();
+        let id = dimensions.register(nether).unwrap();
+        assert!(dir.is_dir());
+
+        assert_eq!(dimensions.len(), 2);
+        assert_eq!(dimensions.find("the_nether"), Some(id));
+        assert_eq!(dimensions.with_environment(Dimension::Nether), Some(id));
+        assert_eq!(
+            dimensions.with_environment(Dimension::Overwold),
+            Some(PRIMARY_DIMENSION)
+        );
+        assert_eq!(dimensions.with_environment(Dimension::End), None);
+        assert!(dimensions.world(PRIMARY_DIMENSION).is_none());
+        assert!(dimensions.world(id).is_some());
+        assert!(!dimensions.get(id).unwrap().has_skylight);
+
+        assert!(dimensions
+            .register(settings("the_nether", Dimension::Nether))
+            .is_err());
+
+        dimensions.shut_down();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_time_persistence() {
+        let nether = settings("the_nether", Dimension::Nether);
+        let dir = nether.dir.clone();
+
+        let mut dimensions = Dimensions::new(settings("overworld", Dimension::Overwold));
+        let id = dimensions.register(nether.clone()).unwrap();
+        assert_eq!(dimensions.world(id).unwrap().time, Time(0));
+        dimensions.world_mut(id).unwrap().time = Time(25_000);
+        dimensions.shut_down();
+
+        let mut dimensions = Dimensions::new(settings("overworld", Dimension::Overwold));
+        let id = dimensions.register(nether).unwrap();
+        assert_eq!(dimensions.world(id).unwrap().time, Time(25_000));
+
+        dimensions.shut_down();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_chunk_unload() {
+        let (sender, requests) = crossbeam::unbounded();
+        let (_replies, receiver) = crossbeam::unbounded();
+        let mut world = DimensionWorld::new(ChunkWorkerHandle { sender, receiver });
+
+        let pos = ChunkPosition::new(0, 0);
+        let cow = EntityData::Cow(AnimalData::new(BaseEntityData::new(
+            position!(1.0, 64.0, 1.0),
+            glm::vec3(0.0, 0.0, 0.0),
+        )));
+        world.chunk_loaded(Chunk::new(pos), vec![cow, EntityData::Unknown]);
+        assert_eq!(world.stored_entities(pos).len(), 1);
+
+        let holder = specs::World::new().entities().create();
+        world.insert_holder(pos, holder);
+        world.unload_chunks(0, 10);
+        assert!(world.chunk_map.chunk_at(pos).is_some());
+
+        // Autosaving keeps the stored entities.
+        assert_eq!(world.save_chunks(), 1);
+        match requests.try_recv().unwrap() {
+            chunkworker::Request::SaveChunk(_, entities) => assert_eq!(entities.len(), 1),
+            _ => panic!(),
+        }
+        assert_eq!(world.save_chunks(), 0);
+
+        world.remove_holder(pos, holder);
+        world.unload_chunks(0, 10);
+        assert!(world.chunk_map.chunk_at(pos).is_some());
+        world.unload_chunks(10, 10);
+        assert!(world.chunk_map.chunk_at(pos).is_none());
+        assert!(world.stored_entities(pos).is_empty());
+
+        // The chunk wasn't modified since it was saved.
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_brightness() {
+        let overworld = settings("overworld", Dimension::Overwold);
+        assert!(overworld.brightness(0).abs() < 0.001);
+        assert!((overworld.brightness(15) - 1.0).abs() < 0.001);
+
+        let nether = settings("the_nether", Dimension::Nether);
+        assert!((nether.brightness(0) - 0.1).abs() < 0.001);
+        assert!((nether.with_ambient_light(1.0).brightness(3) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_dimension_change() {
+        let (mut w, mut d) = t::builder()
+            .with(DimensionChangeSystem::default(), "")
+            .build();
+
+        let nether = settings("the_nether", Dimension::Nether);
+        let dir = nether.dir.clone();
+        let id = w.fetch_mut::<Dimensions>().register(nether).unwrap();
+        w.fetch_mut::<Dimensions>().world_mut(id).unwrap().time = Time(25_000);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<ChunkHolderComponent>()
+            .insert(player.entity, ChunkHolderComponent::new())
+            .unwrap();
+        w.write_component::<LoadedChunksComponent>()
+            .insert(player.entity, LoadedChunksComponent::default())
+            .unwrap();
+        w.write_component::<ChunkPendingComponent>()
+            .insert(
+                player.entity,
+                ChunkPendingComponent {
+                    pending: Default::default(),
+                },
+            )
+            .unwrap();
+
+        t::trigger_event(
+            &w,
+            DimensionChangeEvent {
+                entity: player.entity,
+                dimension: id,
+                position: position!(16.0, 64.0, 16.0),
+            },
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::Respawn);
+        let respawn = cast_packet::<Respawn>(&*packet);
+        assert_eq!(respawn.dimension, Dimension::Nether.get_id());
+
+        let packet = t::assert_packet_received(&player, PacketType::TimeUpdate);
+        let time = cast_packet::<TimeUpdate>(&*packet);
+        assert_eq!(time.world_age, 25_000);
+        assert_eq!(time.time_of_day, 1000);
+
+        assert_eq!(
+            DimensionComponent::of(w.read_component::<DimensionComponent>().get(player.entity)),
+            id
+        );
+        assert_eq!(
+            w.read_component::<PositionComponent>()
+                .get(player.entity)
+                .unwrap()
+                .current,
+            position!(16.0, 64.0, 16.0)
+        );
+        assert!(!w
+            .read_component::<ChunkPendingComponent>()
+            .get(player.entity)
+            .unwrap()
+            .is_empty());
+
+        w.fetch_mut::<Dimensions>().shut_down();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! Status effects, such as those given by beacons.
+//!
+//! The effects of an entity are kept in its `EffectsComponent`,
+//! which is added when it is first given an effect. Effects
+//! count down each tick and are removed once they run out.
+//! Players are notified of their effects. Haste and mining fatigue
+//! change how fast blocks are broken; other effects don't have any
+//! consequences on the server yet.
+
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::EFFECT_TICK;
+use crate::timings::DispatcherBuilderExt;
+use feather_core::network::packet::implementation::{EntityEffect, RemoveEntityEffect};
+use specs::{
+    Component, DenseVecStorage, DispatcherBuilder, Entities, Entity, Join, ReadStorage, System,
+    WriteStorage,
+};
+use std::collections::HashMap;
+
+/// A status effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StatusEffect {
+    Speed = 1,
+    Slowness,
+    Haste,
+    MiningFatigue,
+    Strength,
+    InstantHealth,
+    InstantDamage,
+    JumpBoost,
+    Nausea,
+    Regeneration,
+    Resistance,
+    FireResistance,
+    WaterBreathing,
+    Invisibility,
+    Blindness,
+    NightVision,
+    Hunger,
+    Weakness,
+    Poison,
+    Wither,
+    HealthBoost,
+    Absorption,
+    Saturation,
+    Glowing,
+    Levitation,
+    Luck,
+    Unluck,
+    SlowFalling,
+    ConduitPower,
+    DolphinsGrace,
+}
+
+const EFFECTS: [StatusEffect; 30] = [
+    StatusEffect::Speed,
+    StatusEffect::Slowness,
+    StatusEffect::Haste,
+    StatusEffect::MiningFatigue,
+    StatusEffect::Strength,
+    StatusEffect::InstantHealth,
+    StatusEffect::InstantDamage,
+    StatusEffect::JumpBoost,
+    StatusEffect::Nausea,
+    StatusEffect::Regeneration,
+    StatusEffect::Resistance,
+    StatusEffect::FireResistance,
+    StatusEffect::WaterBreathing,
+    StatusEffect::Invisibility,
+    StatusEffect::Blindness,
+    StatusEffect::NightVision,
+    StatusEffect::Hunger,
+    StatusEffect::Weakness,
+    StatusEffect::Poison,
+    StatusEffect::Wither,
+    StatusEffect::HealthBoost,
+    StatusEffect::Absorption,
+    StatusEffect::Saturation,
+    StatusEffect::Glowing,
+    StatusEffect::Levitation,
+    StatusEffect::Luck,
+    StatusEffect::Unluck,
+    StatusEffect::SlowFalling,
+    StatusEffect::ConduitPower,
+    StatusEffect::DolphinsGrace,
+];
+
+impl StatusEffect {
+    /// Returns the effect with the given numeric ID,
+    /// or `None` if there is no such effect.
+    pub fn from_id(id: i32) -> Option<Self> {
+        if id >= 1 && id as usize <= EFFECTS.len() {
+            Some(EFFECTS[id as usize - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the numeric ID of this effect.
+    pub fn id(self) -> i32 {
+        self as i32
+    }
+}
+
+/// An effect applied to an entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EffectInstance {
+    pub effect: StatusEffect,
+    /// The level of the effect minus one.
+    pub amplifier: u8,
+    /// The remaining duration, in ticks.
+    pub duration: u32,
+    /// Whether the effect comes from a beacon or
+    /// a conduit, in which case its particles are
+    /// less visible.
+    pub ambient: bool,
+    pub show_particles: bool,
+}
+
+impl EffectInstance {
+    pub fn new(effect: StatusEffect, amplifier: u8, duration: u32) -> Self {
+        Self {
+            effect,
+            amplifier,
+            duration,
+            ambient: false,
+            show_particles: true,
+        }
+    }
+
+    /// Returns this effect as sent in the Entity Effect packet.
+    fn to_packet(self, entity_id: i32) -> EntityEffect {
+        let mut flags = 0;
+        if self.ambient {
+            flags |= 0x01;
+        }
+        if self.show_particles {
+            flags |= 0x02;
+        }
+        EntityEffect {
+            entity_id,
+            effect_id: self.effect.id() as i8,
+            amplifier: self.amplifier as i8,
+            duration: self.duration as i32,
+            flags,
+        }
+    }
+}
+
+/// The effects of an entity.
+#[derive(Clone, Debug, Default)]
+pub struct EffectsComponent {
+    effects: HashMap<StatusEffect, EffectInstance>,
+}
+
+impl EffectsComponent {
+    /// Returns the instance of the given effect, if the entity has it.
+    pub fn get(&self, effect: StatusEffect) -> Option<&EffectInstance> {
+        self.effects.get(&effect)
+    }
+
+    /// Adds an effect. As in vanilla, an existing instance of the
+    /// same effect is only replaced by a stronger one, or by an
+    /// equally strong one which lasts longer.
+    ///
+    /// Returns whether the effect was added.
+    pub fn add(&mut self, instance: EffectInstance) -> bool {
+        if let Some(existing) = self.effects.get(&instance.effect) {
+            let stronger = instance.amplifier > existing.amplifier;
+            let longer =
+                instance.amplifier == existing.amplifier && instance.duration > existing.duration;
+            if !stronger && !longer {
+                return false;
+            }
+        }
+        self.effects.insert(instance.effect, instance);
+        true
+    }
+
+    /// Removes an effect, returning its instance if the entity had it.
+    pub fn remove(&mut self, effect: StatusEffect) -> Option<EffectInstance> {
+        self.effects.remove(&effect)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EffectInstance> {
+        self.effects.values()
+    }
+}
+
+impl Component for EffectsComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Gives an entity an effect, notifying it
+/// if it is a player and the effect was added.
+pub fn add_effect(
+    entity: Entity,
+    instance: EffectInstance,
+    effects: &mut WriteStorage<EffectsComponent>,
+    networks: &ReadStorage<NetworkComponent>,
+) {
+    let added = match effects.entry(entity) {
+        Ok(entry) => entry
+            .or_insert_with(EffectsComponent::default)
+            .add(instance),
+        Err(_) => false,
+    };
+
+    if added {
+        if let Some(network) = networks.get(entity) {
+            send_packet_to_player(network, instance.to_packet(entity.id() as i32));
+        }
+    }
+}
+
+/// System which counts down the durations of effects
+/// and removes those which have run out.
+pub struct EffectSystem;
+
+impl<'a> System<'a> for EffectSystem {
+    type SystemData = (
+        WriteStorage<'a, EffectsComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, (mut effects, networks, entities): Self::SystemData) {
+        for (entity, effects) in (&entities, &mut effects).join() {
+            let network = networks.get(entity);
+
+            effects.effects.retain(|effect, instance| {
+                instance.duration = instance.duration.saturating_sub(1);
+                if instance.duration > 0 {
+                    return true;
+                }
+
+                if let Some(network) = network {
+                    send_packet_to_player(
+                        network,
+                        RemoveEntityEffect::new(entity.id() as i32, effect.id() as i8),
+                    );
+                }
+                false
+            });
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(EffectSystem, EFFECT_TICK, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::PacketType;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_status_effect_ids() {
+        assert_eq!(StatusEffect::from_id(1), Some(StatusEffect::Speed));
+        assert_eq!(StatusEffect::from_id(10), Some(StatusEffect::Regeneration));
+        assert_eq!(StatusEffect::from_id(30), Some(StatusEffect::DolphinsGrace));
+        assert_eq!(StatusEffect::from_id(0), None);
+        assert_eq!(StatusEffect::from_id(31), None);
+
+        for (index, effect) in EFFECTS.iter().enumerate() {
+            assert_eq!(effect.id(), index as i32 + 1);
+        }
+    }
+
+    #[test]
+    fn test_combine_effects() {
+        let mut effects = EffectsComponent::default();
+        assert!(effects.add(EffectInstance::new(StatusEffect::Speed, 0, 100)));
+        // A weaker or shorter effect doesn't replace the existing one.
+        assert!(!effects.add(EffectInstance::new(StatusEffect::Speed, 0, 50)));
+        // A longer one does.
+        assert!(effects.add(EffectInstance::new(StatusEffect::Speed, 0, 200)));
+        // So does a stronger one, even if it is shorter.
+        assert!(effects.add(EffectInstance::new(StatusEffect::Speed, 1, 20)));
+        assert!(!effects.add(EffectInstance::new(StatusEffect::Speed, 0, 1000)));
+
+        let speed = effects.get(StatusEffect::Speed).unwrap();
+        assert_eq!((speed.amplifier, speed.duration), (1, 20));
+    }
+
+    #[test]
+    fn test_effects_expire() {
+        let (mut w, mut d) = t::builder().with(EffectSystem, "").build();
+        let player = t::add_player(&mut w);
+
+        {
+            let mut effects = w.write_component::<EffectsComponent>();
+            let networks = w.read_component::<NetworkComponent>();
+            add_effect(
+                player.entity,
+                EffectInstance::new(StatusEffect::Haste, 1, 2),
+                &mut effects,
+                &networks,
+            );
+        }
+
+        let packet = t::assert_packet_received(&player, PacketType::EntityEffect);
+        let packet = cast_packet::<EntityEffect>(&*packet);
+        assert_eq!(packet.effect_id, StatusEffect::Haste.id() as i8);
+        assert_eq!(packet.amplifier, 1);
+        assert_eq!(packet.flags, 0x02);
+
+        d.dispatch(&w);
+        assert!(w
+            .read_component::<EffectsComponent>()
+            .get(player.entity)
+            .unwrap()
+            .get(StatusEffect::Haste)
+            .is_some());
+
+        d.dispatch(&w);
+        assert!(w
+            .read_component::<EffectsComponent>()
+            .get(player.entity)
+            .unwrap()
+            .get(StatusEffect::Haste)
+            .is_none());
+
+        let packet = t::assert_packet_received(&player, PacketType::RemoveEntityEffect);
+        let packet = cast_packet::<RemoveEntityEffect>(&*packet);
+        assert_eq!(packet.effect_id, StatusEffect::Haste.id() as i8);
+    }
+}
@@ -0,0 +1,471 @@
+//! Elytra gliding.
+//!
+//! Clients start gliding by sending an Entity Action packet
+//! while falling and simulate the glide themselves. The server
+//! checks that the player is allowed to glide, wears down
+//! the elytra while they do and rejects movements which are
+//! too fast to be possible. Firework rockets used while gliding
+//! boost the player: see `entity::firework`.
+
+use crate::entity::metadata::EntityBitMask;
+use crate::entity::{FireworkLaunchEvent, Metadata, PlayerComponent, PositionComponent};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::{
+    InventoryComponent, InventoryUpdateEvent, PlayerActionEvent, PlayerUseItemEvent,
+};
+use crate::systems::{
+    ELYTRA_BOOST, ELYTRA_START, ELYTRA_UPDATE, PLAYER_ACTION, PLAYER_MOVEMENT, PLAYER_USE_ITEM,
+};
+use crate::timings::DispatcherBuilderExt;
+use feather_core::inventory::SLOT_ARMOR_CHEST;
+use feather_core::network::packet::implementation::{
+    EntityActionType, PlayerPositionAndLookClientbound,
+};
+use feather_core::{Gamemode, Item, ItemStack};
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DenseVecStorage, DispatcherBuilder, Entities, Join, Read, ReadStorage, System,
+    Write, WriteStorage,
+};
+
+/// The number of damage points an elytra can take.
+/// An elytra with one point left is broken and
+/// can't be used until it is repaired.
+pub const ELYTRA_DURABILITY: i32 = 432;
+
+/// The number of ticks between each
+/// damage point taken by a gliding elytra.
+const DAMAGE_INTERVAL: u32 = 20;
+
+/// The squared distance above which a movement
+/// of a gliding player is rejected, as in vanilla.
+const MAX_MOVEMENT_SQUARED: f64 = 300.0;
+
+/// Component for players gliding with an elytra.
+#[derive(Debug, Clone, Default)]
+pub struct GlidingComponent {
+    /// The number of ticks spent gliding.
+    pub ticks: u32,
+}
+
+impl Component for GlidingComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Returns whether the given item is an
+/// elytra which can be used to glide.
+pub fn is_usable_elytra(stack: &ItemStack) -> bool {
+    stack.ty == Item::Elytra && stack.damage() < ELYTRA_DURABILITY - 1
+}
+
+/// Sets whether a player is shown as gliding.
+fn set_gliding(metadata: &mut Metadata, gliding: bool) {
+    if let Metadata::Player(meta) = metadata {
+        let mut mask = EntityBitMask::from_bits_truncate(meta.bit_mask());
+        mask.set(EntityBitMask::FLYING_WITH_ELYTRA, gliding);
+        meta.set_bit_mask(mask.bits());
+    }
+}
+
+/// System which starts gliding when a falling
+/// player wearing an elytra requests it.
+///
+/// This system listens to `PlayerActionEvent`s.
+#[derive(Default)]
+pub struct ElytraStartSystem {
+    reader: Option<ReaderId<PlayerActionEvent>>,
+}
+
+impl<'a> System<'a> for ElytraStartSystem {
+    type SystemData = (
+        Read<'a, EventChannel<PlayerActionEvent>>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, InventoryComponent>,
+        WriteStorage<'a, GlidingComponent>,
+        WriteStorage<'a, Metadata>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (action_events, positions, inventories, mut glidings, mut metadatas) = data;
+
+        for event in action_events.read(self.reader.as_mut().unwrap()) {
+            if event.action != EntityActionType::StartFlyingWithElytra {
+                continue;
+            }
+            let player = event.player;
+
+            if glidings.get(player).is_some()
+                || continue_if_none!(positions.get(player)).current.on_ground
+            {
+                continue;
+            }
+            let inventory = continue_if_none!(inventories.get(player));
+            if !inventory
+                .item_at(SLOT_ARMOR_CHEST)
+                .map_or(false, is_usable_elytra)
+            {
+                continue;
+            }
+
+            glidings
+                .insert(player, GlidingComponent::default())
+                .unwrap();
+            if let Some(metadata) = metadatas.get_mut(player) {
+                set_gliding(metadata, true);
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which stops gliding once a player lands or
+/// their elytra breaks, damages elytras and validates
+/// the movement of gliding players.
+pub struct ElytraUpdateSystem;
+
+impl<'a> System<'a> for ElytraUpdateSystem {
+    type SystemData = (
+        WriteStorage<'a, GlidingComponent>,
+        WriteStorage<'a, PositionComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        WriteStorage<'a, Metadata>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut glidings,
+            mut positions,
+            mut inventories,
+            mut metadatas,
+            players,
+            networks,
+            mut inventory_updates,
+            entities,
+        ) = data;
+
+        let mut stopped = vec![];
+
+        for (player, gliding, position, inventory) in
+            (&entities, &mut glidings, &mut positions, &mut inventories).join()
+        {
+            let elytra = match inventory.item_at(SLOT_ARMOR_CHEST) {
+                Some(stack) if is_usable_elytra(stack) => stack.clone(),
+                _ => {
+                    stopped.push(player);
+                    continue;
+                }
+            };
+            if position.current.on_ground {
+                stopped.push(player);
+                continue;
+            }
+
+            gliding.ticks += 1;
+
+            let creative = players
+                .get(player)
+                .map_or(false, |player| player.gamemode == Gamemode::Creative);
+            if gliding.ticks % DAMAGE_INTERVAL == 0 && !creative {
+                let mut elytra = elytra;
+                elytra.set_damage(elytra.damage() + 1);
+                inventory.set_item_at(SLOT_ARMOR_CHEST, elytra);
+                inventory_updates.single_write(InventoryUpdateEvent {
+                    slots: smallvec![SLOT_ARMOR_CHEST],
+                    player,
+                });
+            }
+
+            let previous = position.previous;
+            if position.current.distance_squared(previous) > MAX_MOVEMENT_SQUARED {
+                debug!("Player {:?} glided too quickly", player);
+                position.current = previous;
+                if let Some(network) = networks.get(player) {
+                    send_packet_to_player(
+                        network,
+                        PlayerPositionAndLookClientbound::new(
+                            previous.x,
+                            previous.y,
+                            previous.z,
+                            previous.yaw,
+                            previous.pitch,
+                            0,
+                            0,
+                        ),
+                    );
+                }
+            }
+        }
+
+        for player in stopped {
+            glidings.remove(player);
+            if let Some(metadata) = metadatas.get_mut(player) {
+                set_gliding(metadata, false);
+            }
+        }
+    }
+}
+
+/// System which launches a firework rocket boosting
+/// a gliding player when they use one.
+///
+/// This system listens to `PlayerUseItemEvent`s.
+#[derive(Default)]
+pub struct ElytraBoostSystem {
+    reader: Option<ReaderId<PlayerUseItemEvent>>,
+}
+
+impl<'a> System<'a> for ElytraBoostSystem {
+    type SystemData = (
+        Read<'a, EventChannel<PlayerUseItemEvent>>,
+        ReadStorage<'a, GlidingComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        Write<'a, EventChannel<FireworkLaunchEvent>>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            use_events,
+            glidings,
+            positions,
+            players,
+            mut inventories,
+            mut launch_events,
+            mut inventory_updates,
+        ) = data;
+
+        for event in use_events.read(self.reader.as_mut().unwrap()) {
+            let player = event.player;
+            if glidings.get(player).is_none() {
+                continue;
+            }
+            let inventory = continue_if_none!(inventories.get_mut(player));
+            let slot = inventory.hand_slot(event.hand);
+            let stack = match inventory.item_at(slot) {
+                Some(stack) if stack.ty == Item::FireworkRocket => stack.clone(),
+                _ => continue,
+            };
+            let position = continue_if_none!(positions.get(player)).current;
+
+            launch_events.single_write(FireworkLaunchEvent {
+                stack: stack.with_amount(1),
+                position,
+                boosted: Some(player),
+            });
+
+            let creative = players
+                .get(player)
+                .map_or(false, |player| player.gamemode == Gamemode::Creative);
+            if !creative {
+                inventory.set_item_at(slot, stack.with_amount(stack.amount - 1));
+                inventory_updates.single_write(InventoryUpdateEvent {
+                    slots: smallvec![slot],
+                    player,
+                });
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ElytraStartSystem::default(), ELYTRA_START, &[PLAYER_ACTION]);
+    dispatcher.add_timed(
+        ElytraUpdateSystem,
+        ELYTRA_UPDATE,
+        &[PLAYER_MOVEMENT, ELYTRA_START],
+    );
+    dispatcher.add_timed(
+        ElytraBoostSystem::default(),
+        ELYTRA_BOOST,
+        &[PLAYER_USE_ITEM, ELYTRA_UPDATE],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+    use feather_core::PacketType;
+    use specs::{World, WorldExt};
+
+    fn start_gliding(w: &World, player: &t::Player) {
+        t::trigger_event(
+            w,
+            PlayerActionEvent {
+                player: player.entity,
+                action: EntityActionType::StartFlyingWithElytra,
+            },
+        );
+    }
+
+    fn falling_player(w: &mut World) -> t::Player {
+        let player = t::add_player(w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_ARMOR_CHEST, ItemStack::new(Item::Elytra, 1));
+        let mut positions = w.write_component::<PositionComponent>();
+        let position = positions.get_mut(player.entity).unwrap();
+        position.current = position!(0.0, 100.0, 0.0, false);
+        position.previous = position.current;
+        drop(positions);
+        player
+    }
+
+    fn is_gliding(w: &World, player: &t::Player) -> bool {
+        let gliding = w
+            .read_component::<GlidingComponent>()
+            .get(player.entity)
+            .is_some();
+        match w.read_component::<Metadata>().get(player.entity).unwrap() {
+            Metadata::Player(meta) => assert_eq!(
+                meta.bit_mask() & EntityBitMask::FLYING_WITH_ELYTRA.bits() != 0,
+                gliding
+            ),
+            _ => panic!("expected player metadata"),
+        }
+        gliding
+    }
+
+    #[test]
+    fn test_is_usable_elytra() {
+        let mut elytra = ItemStack::new(Item::Elytra, 1);
+        assert!(is_usable_elytra(&elytra));
+        elytra.set_damage(ELYTRA_DURABILITY - 1);
+        assert!(!is_usable_elytra(&elytra));
+        assert!(!is_usable_elytra(&ItemStack::new(Item::Diamond, 1)));
+    }
+
+    #[test]
+    fn test_start_and_stop_gliding() {
+        let (mut w, mut d) = t::builder()
+            .with(ElytraStartSystem::default(), "start")
+            .with(ElytraUpdateSystem, "")
+            .build();
+        let player = falling_player(&mut w);
+
+        start_gliding(&w, &player);
+        d.dispatch(&w);
+        assert!(is_gliding(&w, &player));
+
+        // Landing stops the glide.
+        w.write_component::<PositionComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .current
+            .on_ground = true;
+        d.dispatch(&w);
+        assert!(!is_gliding(&w, &player));
+
+        // Players without an elytra can't glide.
+        let other = falling_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(other.entity)
+            .unwrap()
+            .clear_item_at(SLOT_ARMOR_CHEST);
+        start_gliding(&w, &other);
+        d.dispatch(&w);
+        assert!(!is_gliding(&w, &other));
+    }
+
+    #[test]
+    fn test_elytra_durability() {
+        let (mut w, mut d) = t::builder()
+            .with(ElytraStartSystem::default(), "start")
+            .with(ElytraUpdateSystem, "")
+            .build();
+        let player = falling_player(&mut w);
+
+        start_gliding(&w, &player);
+        for _ in 0..DAMAGE_INTERVAL * 2 {
+            d.dispatch(&w);
+        }
+
+        let damage = w
+            .read_component::<InventoryComponent>()
+            .get(player.entity)
+            .unwrap()
+            .item_at(SLOT_ARMOR_CHEST)
+            .unwrap()
+            .damage();
+        assert_eq!(damage, 2);
+    }
+
+    #[test]
+    fn test_reject_fast_movement() {
+        let (mut w, mut d) = t::builder()
+            .with(ElytraStartSystem::default(), "start")
+            .with(ElytraUpdateSystem, "")
+            .build();
+        let player = falling_player(&mut w);
+        start_gliding(&w, &player);
+        d.dispatch(&w);
+
+        w.write_component::<PositionComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .current = position!(50.0, 100.0, 0.0, false);
+        d.dispatch(&w);
+
+        let position = w
+            .read_component::<PositionComponent>()
+            .get(player.entity)
+            .unwrap()
+            .current;
+        assert_eq!(position, position!(0.0, 100.0, 0.0, false));
+        t::assert_packet_received(&player, PacketType::PlayerPositionAndLookClientbound);
+    }
+
+    #[test]
+    fn test_firework_boost() {
+        let (mut w, mut d) = t::builder()
+            .with(ElytraStartSystem::default(), "start")
+            .with(ElytraUpdateSystem, "update")
+            .with_dep(ElytraBoostSystem::default(), "", &["update"])
+            .build();
+        let mut reader = t::reader::<FireworkLaunchEvent>(&w);
+        let player = falling_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::FireworkRocket, 2));
+
+        start_gliding(&w, &player);
+        d.dispatch(&w);
+        t::trigger_event(
+            &w,
+            PlayerUseItemEvent {
+                player: player.entity,
+                hand: 0,
+            },
+        );
+        d.dispatch(&w);
+
+        let events = t::triggered_events(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].boosted, Some(player.entity));
+        assert_eq!(
+            w.read_component::<InventoryComponent>()
+                .get(player.entity)
+                .unwrap()
+                .item_in_main_hand()
+                .unwrap()
+                .amount,
+            1
+        );
+    }
+}
@@ -4,8 +4,8 @@
 
 use crate::chunk_logic::ChunkLoadEvent;
 use crate::entity::{
-    arrow, chicken, cow, donkey, horse, item, llama, mooshroom, pig, rabbit, sheep, squid,
-    EntityDestroyEvent, EntitySpawnEvent, PositionComponent,
+    arrow, chicken, cow, donkey, horse, item, llama, mooshroom, pig, rabbit, sheep, skeleton,
+    squid, zombie, zombie_pigman, EntityDestroyEvent, EntitySpawnEvent, PositionComponent,
 };
 use crate::TickCount;
 use feather_core::entity::EntityData;
@@ -22,8 +22,16 @@ use std::sync::atomic::{AtomicBool, Ordering};
 /// Keeps track of which entities are in which chunk.
 /// Also has a boolean for each chunk which indicates
 /// whether its entities have been updated recently.
-#[derive(Debug, Deref, DerefMut, Default)]
-pub struct ChunkEntities(HashMap<ChunkPosition, (AtomicBool, Vec<Entity>)>);
+///
+/// Range queries should go through this index so that
+/// they only iterate over entities in nearby chunks.
+#[derive(Debug, Default)]
+pub struct ChunkEntities {
+    /// The entities in each chunk, along with the chunk's dirty flag.
+    chunks: HashMap<ChunkPosition, (AtomicBool, Vec<Entity>)>,
+    /// The chunk each entity is indexed in.
+    entity_chunks: HashMap<Entity, ChunkPosition>,
+}
 
 lazy_static! {
     static ref EMPTY_VEC: Vec<Entity> = Vec::with_capacity(0);
@@ -32,18 +40,34 @@ lazy_static! {
 impl ChunkEntities {
     /// Returns all entities in a given chunk.
     pub fn entities_in_chunk(&self, chunk: ChunkPosition) -> &Vec<Entity> {
-        if let Some((_, entities)) = self.0.get(&chunk) {
+        if let Some((_, entities)) = self.chunks.get(&chunk) {
             entities
         } else {
             &EMPTY_VEC
         }
     }
 
+    /// Returns an iterator over all entities in the given chunks.
+    pub fn entities_in_chunks<'a, I>(&'a self, chunks: I) -> impl Iterator<Item = Entity> + 'a
+    where
+        I: IntoIterator<Item = ChunkPosition>,
+        I::IntoIter: 'a,
+    {
+        chunks
+            .into_iter()
+            .flat_map(move |chunk| self.entities_in_chunk(chunk).iter().copied())
+    }
+
+    /// Returns the chunk an entity is indexed in, if any.
+    pub fn chunk_of(&self, entity: Entity) -> Option<ChunkPosition> {
+        self.entity_chunks.get(&entity).copied()
+    }
+
     /// Returns all entities in the chunk, in addition to
     /// a boolean indicating whether the entities have been
     /// updated since the last call to this function.
     pub fn entities_in_chunk_and_modified(&self, chunk: ChunkPosition) -> (bool, &[Entity]) {
-        if let Some((dirty, entities)) = self.0.get(&chunk) {
+        if let Some((dirty, entities)) = self.chunks.get(&chunk) {
             let d = dirty.load(Ordering::SeqCst);
             dirty.store(false, Ordering::SeqCst);
             (d, entities)
@@ -52,24 +76,32 @@ impl ChunkEntities {
         }
     }
 
-    /// Adds an entity to a chunk.
+    /// Adds an entity to a chunk. If the entity was
+    /// already in another chunk, it is removed from that chunk.
     pub fn add_to_chunk(&mut self, chunk: ChunkPosition, entity: Entity) {
-        self.0
+        if let Some(old_chunk) = self.chunk_of(entity) {
+            if old_chunk == chunk {
+                return;
+            }
+            self.remove_from_chunk(old_chunk, entity);
+        }
+
+        self.chunks
             .entry(chunk)
             .and_modify(|(dirty, vec)| {
                 dirty.store(true, Ordering::SeqCst);
                 vec.push(entity)
             })
             .or_insert_with(|| (AtomicBool::new(true), vec![entity]));
+        self.entity_chunks.insert(entity, chunk);
     }
 
     /// Removes an entity from a chunk.
     ///
-    /// # Panics
-    /// May panic in some cases if the entity is not contained
-    /// within the given chunk.
+    /// Does nothing if the entity is not
+    /// contained within the given chunk.
     pub fn remove_from_chunk(&mut self, chunk: ChunkPosition, entity: Entity) {
-        let (dirty, vec) = match self.0.get_mut(&chunk) {
+        let (dirty, vec) = match self.chunks.get_mut(&chunk) {
             Some(vec) => vec,
             _ => return,
         };
@@ -79,11 +111,19 @@ impl ChunkEntities {
             None => return,
         };
         vec.swap_remove(index);
+        self.entity_chunks.remove(&entity);
 
         dirty.store(true, Ordering::SeqCst);
 
         if vec.is_empty() {
-            self.0.remove(&chunk);
+            self.chunks.remove(&chunk);
+        }
+    }
+
+    /// Removes an entity from whichever chunk it is in.
+    pub fn remove_entity(&mut self, entity: Entity) {
+        if let Some(chunk) = self.chunk_of(entity) {
+            self.remove_from_chunk(chunk, entity);
         }
     }
 
@@ -94,22 +134,17 @@ impl ChunkEntities {
         chunk: ChunkPosition,
         view_distance: u8,
     ) -> HashSet<Entity> {
-        let mut result = HashSet::new();
-
         // 1 is subtracted from the view distance because of some odd
         // client-side glitch (or maybe it's our fault?) where the last chunk within the view distance
         // is not loaded correctly.
         let view_distance = i32::from(view_distance) - 1;
 
-        for x_offset in -view_distance..=view_distance {
-            for z_offset in -view_distance..=view_distance {
-                let chunk = ChunkPosition::new(chunk.x + x_offset, chunk.z + z_offset);
+        let chunks = (-view_distance..=view_distance).flat_map(move |x_offset| {
+            (-view_distance..=view_distance)
+                .map(move |z_offset| ChunkPosition::new(chunk.x + x_offset, chunk.z + z_offset))
+        });
 
-                result.extend(self.entities_in_chunk(chunk));
-            }
-        }
-
-        result
+        self.entities_in_chunks(chunks).collect()
     }
 }
 
@@ -149,7 +184,6 @@ impl<'a> System<'a> for ChunkEntityUpdateSystem {
             let old_pos = position.previous.chunk_pos();
 
             if new_pos != old_pos {
-                entity_chunks.remove_from_chunk(old_pos, entity);
                 entity_chunks.add_to_chunk(new_pos, entity);
             }
         }
@@ -161,9 +195,7 @@ impl<'a> System<'a> for ChunkEntityUpdateSystem {
         }
 
         for event in destroy_events.read(self.destroy_reader.as_mut().unwrap()) {
-            if let Some(pos) = positions.get(event.entity) {
-                entity_chunks.remove_from_chunk(pos.current.chunk_pos(), event.entity);
-            }
+            entity_chunks.remove_entity(event.entity);
         }
     }
 
@@ -265,6 +297,21 @@ impl<'a> System<'a> for EntityChunkLoadSystem {
                             debug!("Error while loading donkey entity")
                         }
                     }
+                    EntityData::ZombiePigman(data) => {
+                        if zombie_pigman::create_from_data(&lazy, &entities, data).is_none() {
+                            debug!("Error while loading zombie pigman entity")
+                        }
+                    }
+                    EntityData::Zombie(data) => {
+                        if zombie::create_from_data(&lazy, &entities, data).is_none() {
+                            debug!("Error while loading zombie entity")
+                        }
+                    }
+                    EntityData::Skeleton(data) => {
+                        if skeleton::create_from_data(&lazy, &entities, data).is_none() {
+                            debug!("Error while loading skeleton entity")
+                        }
+                    }
                     // TODO: Spawn remaining entity types here.
                     EntityData::Unknown => {
                         trace!("Chunk {:?} contains an unknown entity type", event.pos);
@@ -385,6 +432,35 @@ mod tests {
         assert!(chunk_entities.entities_in_chunk(pos.chunk_pos()).is_empty());
     }
 
+    #[test]
+    fn test_chunk_of() {
+        let mut chunk_entities = ChunkEntities::default();
+
+        let mut world = World::new();
+        let entity = world.create_entity().build();
+
+        let chunk1 = ChunkPosition::new(0, 0);
+        let chunk2 = ChunkPosition::new(1, -1);
+
+        chunk_entities.add_to_chunk(chunk1, entity);
+        assert_eq!(chunk_entities.chunk_of(entity), Some(chunk1));
+
+        // Adding the entity to another chunk moves it.
+        chunk_entities.add_to_chunk(chunk2, entity);
+        assert_eq!(chunk_entities.chunk_of(entity), Some(chunk2));
+        assert!(chunk_entities.entities_in_chunk(chunk1).is_empty());
+        assert_eq!(
+            chunk_entities
+                .entities_in_chunks(vec![chunk1, chunk2])
+                .collect::<Vec<_>>(),
+            vec![entity]
+        );
+
+        chunk_entities.remove_entity(entity);
+        assert_eq!(chunk_entities.chunk_of(entity), None);
+        assert!(chunk_entities.entities_in_chunk(chunk2).is_empty());
+    }
+
     #[test]
     fn test_entities_within_view_distance() {
         let mut chunk_entities = ChunkEntities::default();
@@ -53,5 +53,5 @@ fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
 
 fn serialize(world: &World, entity: Entity) -> EntityData {
     let base = base_data(world, entity);
-    EntityData::Horse(AnimalData { base })
+    EntityData::Horse(AnimalData::new(base))
 }
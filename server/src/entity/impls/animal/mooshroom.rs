@@ -49,5 +49,5 @@ pub fn create_from_data(
 
 fn serialize(world: &World, entity: Entity) -> EntityData {
     let base = base_data(world, entity);
-    EntityData::Mooshroom(AnimalData { base })
+    EntityData::Mooshroom(AnimalData::new(base))
 }
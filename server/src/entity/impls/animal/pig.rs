@@ -1,8 +1,8 @@
 use crate::entity::{
-    base_data, create_mob_packet, PacketCreatorComponent, PositionComponent, SerializerComponent,
+    create_mob_packet, PacketCreatorComponent, PositionComponent, SerializerComponent,
     VelocityComponent,
 };
-use crate::lazy::LazyUpdateExt;
+use crate::mob::{self, MobKind};
 use crate::physics::PhysicsBuilder;
 use feather_core::entity::{AnimalData, EntityData};
 use feather_core::Packet;
@@ -17,7 +17,7 @@ impl Component for PigComponent {
 }
 
 pub fn create<'a>(lazy: &'a LazyUpdate, entities: &'a EntitiesRes) -> LazyBuilder<'a> {
-    lazy.spawn_entity(entities)
+    mob::create(lazy, entities, MobKind::Pig)
         .with(PigComponent)
         .with(PhysicsBuilder::for_living().bbox(0.9, 0.9, 0.9).build())
         .with(PacketCreatorComponent(&create_packet))
@@ -33,7 +33,7 @@ pub fn create_from_data(
     let velocity = data.base.read_velocity()?;
 
     Some(
-        create(lazy, entities)
+        mob::load_data(create(lazy, entities), data)
             .with(PositionComponent {
                 current: position,
                 previous: position,
@@ -48,6 +48,5 @@ fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
 }
 
 fn serialize(world: &World, entity: Entity) -> EntityData {
-    let base = base_data(world, entity);
-    EntityData::Pig(AnimalData { base })
+    EntityData::Pig(mob::save_data(world, entity))
 }
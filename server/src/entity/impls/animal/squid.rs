@@ -49,5 +49,5 @@ fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
 
 fn serialize(world: &World, entity: Entity) -> EntityData {
     let base = base_data(world, entity);
-    EntityData::Squid(AnimalData { base })
+    EntityData::Squid(AnimalData::new(base))
 }
@@ -1,7 +1,7 @@
 use shrev::EventChannel;
 use specs::{
-    Builder, Component, Entities, Entity, LazyUpdate, NullStorage, Read, ReaderId, System, World,
-    WorldExt,
+    Builder, Component, DenseVecStorage, Entities, Entity, LazyUpdate, Read, ReaderId, System,
+    World, WorldExt,
 };
 
 use feather_core::packet::SpawnObject;
@@ -21,10 +21,13 @@ use uuid::Uuid;
 
 /// Component for arrow entities.
 #[derive(Default)]
-pub struct ArrowComponent;
+pub struct ArrowComponent {
+    /// The entity which shot the arrow, if known.
+    pub shooter: Option<Entity>,
+}
 
 impl Component for ArrowComponent {
-    type Storage = NullStorage<Self>;
+    type Storage = DenseVecStorage<Self>;
 }
 
 /// Event triggered when arrow is shot.
@@ -60,9 +63,7 @@ impl<'a> System<'a> for ShootArrowSystem {
             // TODO: Scale velocity based on power
             let velocity = pos.direction();
 
-            // TODO: shooter
-
-            create(&lazy, &entities, false)
+            create(&lazy, &entities, false, event.shooter)
                 .with(PositionComponent {
                     current: pos,
                     previous: pos,
@@ -75,7 +76,12 @@ impl<'a> System<'a> for ShootArrowSystem {
     setup_impl!(reader);
 }
 
-pub fn create<'a>(lazy: &'a LazyUpdate, entities: &EntitiesRes, critical: bool) -> LazyBuilder<'a> {
+pub fn create<'a>(
+    lazy: &'a LazyUpdate,
+    entities: &EntitiesRes,
+    critical: bool,
+    shooter: Option<Entity>,
+) -> LazyBuilder<'a> {
     let meta = {
         let mut meta_arrow = crate::entity::metadata::Arrow::default();
         let mask = if critical {
@@ -89,7 +95,7 @@ pub fn create<'a>(lazy: &'a LazyUpdate, entities: &EntitiesRes, critical: bool)
     };
 
     lazy.spawn_entity(entities)
-        .with(ArrowComponent)
+        .with(ArrowComponent { shooter })
         .with(
             PhysicsBuilder::new()
                 .bbox(0.5, 0.5, 0.5)
@@ -111,15 +117,10 @@ pub fn create_from_data(
     let pos = data.entity.read_position()?;
     let vel = data.entity.read_velocity()?;
 
-    let critical = match data.critical {
-        0 => false,
-        _ => true,
-    };
-
     // TODO: load other attributes
 
     Some(
-        create(lazy, entities, critical)
+        create(lazy, entities, data.critical, None)
             .with(PositionComponent {
                 current: pos,
                 previous: pos,
@@ -132,9 +133,11 @@ pub fn create_from_data(
 fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
     let positions = world.read_component::<PositionComponent>();
     let velocities = world.read_component::<VelocityComponent>();
+    let arrows = world.read_component::<ArrowComponent>();
 
     let position = positions.get(entity).unwrap().current;
     let (velocity_x, velocity_y, velocity_z) = protocol_velocity(velocities.get(entity).unwrap().0);
+    let shooter = arrows.get(entity).and_then(|arrow| arrow.shooter);
 
     let packet = SpawnObject {
         entity_id: entity.id() as i32,
@@ -145,7 +148,9 @@ fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
         z: position.z,
         pitch: degrees_to_stops(position.pitch),
         yaw: degrees_to_stops(position.yaw),
-        data: 1, // TODO: Shooter entity ID
+        // The entity ID of the shooter plus one. Clients
+        // ignore the velocity of arrows if this is zero.
+        data: shooter.map_or(1, |shooter| shooter.id() as i32 + 1),
         velocity_x,
         velocity_y,
         velocity_z,
@@ -163,6 +168,6 @@ fn serialize(world: &World, entity: Entity) -> EntityData {
             positions.get(entity).unwrap().current,
             velocities.get(entity).unwrap().0,
         ),
-        critical: 0, // TODO
+        critical: false, // TODO
     })
 }
@@ -1,23 +1,26 @@
 use shrev::ReaderId;
 use specs::shrev::EventChannel;
 use specs::{
-    Builder, Component, DenseVecStorage, Entity, LazyUpdate, Read, ReadStorage, System, World,
-    WorldExt, Write,
+    Builder, Component, DenseVecStorage, Entities, Entity, LazyUpdate, Read, ReadStorage, System,
+    World, WorldExt, Write,
 };
 
 use feather_blocks::{Block, BlockExt};
 use feather_core::packet::SpawnObject;
 use feather_core::world::ChunkMap;
 
-use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::blocks::{can_fall_through, BlockUpdateCause, BlockUpdateEvent};
 use crate::entity::component::PacketCreatorComponent;
 use crate::entity::metadata::Metadata;
 use crate::entity::movement::degrees_to_stops;
 use crate::entity::{EntityDestroyEvent, PositionComponent, VelocityComponent};
 use crate::lazy::LazyUpdateExt;
+use crate::loot;
 use crate::physics::{EntityPhysicsLandEvent, PhysicsBuilder};
 use crate::util::protocol_velocity;
-use feather_core::{Packet, Position};
+use crate::TickCount;
+use feather_core::{ItemStack, Packet, Position};
+use feather_item_block::BlockToItem;
 use specs::world::{EntitiesRes, LazyBuilder};
 use uuid::Uuid;
 
@@ -38,14 +41,18 @@ impl Component for FallingBlockComponent {
     type Storage = DenseVecStorage<Self>;
 }
 
+/// System for handling when a falling block lands
+/// on the ground, destroying the entity and setting the block.
+///
+/// If the falling block lands inside a block it can't
+/// replace, such as a torch, it drops as an item instead.
+///
 /// This system listens to `EntityPhysicsLandEvent`s.
 #[derive(Default)]
 pub struct FallingBlockLandSystem {
     reader: Option<ReaderId<EntityPhysicsLandEvent>>,
 }
 
-/// System for handling when a falling block lands
-/// on the ground, destroying the entity and setting the block.
 impl<'a> System<'a> for FallingBlockLandSystem {
     type SystemData = (
         Read<'a, EventChannel<EntityPhysicsLandEvent>>,
@@ -53,10 +60,22 @@ impl<'a> System<'a> for FallingBlockLandSystem {
         Write<'a, EventChannel<EntityDestroyEvent>>,
         Write<'a, EventChannel<BlockUpdateEvent>>,
         Write<'a, ChunkMap>,
+        Read<'a, LazyUpdate>,
+        Read<'a, TickCount>,
+        Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (events, falling_blocks, mut destroy_events, mut block_updates, mut chunk_map) = data;
+        let (
+            events,
+            falling_blocks,
+            mut destroy_events,
+            mut block_updates,
+            mut chunk_map,
+            lazy,
+            tick,
+            entities,
+        ) = data;
 
         // Process events
         for event in events.read(&mut self.reader.as_mut().unwrap()) {
@@ -71,7 +90,17 @@ impl<'a> System<'a> for FallingBlockLandSystem {
             destroy_events.single_write(destroy_event);
 
             let pos = event.pos.block_pos();
-            let old_block = chunk_map.block_at(pos).unwrap();
+            let old_block = continue_if_none!(chunk_map.block_at(pos));
+
+            if !can_fall_through(old_block) {
+                if let Some(item) = falling_block.block.to_item() {
+                    let stack = ItemStack::new(item, 1);
+                    let mut rng = rand::thread_rng();
+                    loot::drop_at_block(&lazy, &entities, pos, vec![stack], tick.0, &mut rng);
+                }
+                continue;
+            }
+
             chunk_map.set_block_at(pos, falling_block.block).unwrap();
 
             let update_event = BlockUpdateEvent {
@@ -140,3 +169,62 @@ fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
 
     Box::new(packet)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::ItemComponent;
+    use crate::testframework as t;
+    use feather_core::world::BlockPosition;
+    use feather_core::Item;
+    use specs::Join;
+
+    fn land(w: &mut World, d: &mut specs::Dispatcher, block: Block, pos: Position) {
+        let entity = create(&w.fetch(), &w.fetch(), block, pos)
+            .with(PositionComponent {
+                current: pos,
+                previous: pos,
+            })
+            .with(VelocityComponent::default())
+            .build();
+        w.maintain();
+
+        t::trigger_event(w, EntityPhysicsLandEvent { entity, pos });
+        d.dispatch(w);
+        w.maintain();
+    }
+
+    #[test]
+    fn test_falling_block_land() {
+        let (mut w, mut d) = t::builder()
+            .with(FallingBlockLandSystem::default(), "")
+            .build();
+        t::populate_with_air(&mut w);
+
+        land(&mut w, &mut d, Block::Sand, position!(0.5, 64.0, 0.5));
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::Sand)
+        );
+        assert!(w.read_component::<ItemComponent>().join().next().is_none());
+    }
+
+    #[test]
+    fn test_falling_block_drops_item() {
+        let (mut w, mut d) = t::builder()
+            .with(FallingBlockLandSystem::default(), "")
+            .build();
+        t::populate_with_air(&mut w);
+        t::set_block(0, 64, 0, Block::Torch, &w);
+
+        land(&mut w, &mut d, Block::Gravel, position!(0.5, 64.0, 0.5));
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::Torch)
+        );
+
+        let items = w.read_component::<ItemComponent>();
+        let stacks: Vec<_> = items.join().map(|item| item.stack.clone()).collect();
+        assert_eq!(stacks, vec![ItemStack::new(Item::Gravel, 1)]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Firework rockets.
+//!
+//! A rocket flies upwards, or follows the player it boosts
+//! if it was launched while gliding, until its lifetime is
+//! over. It then explodes: clients render the explosions
+//! described by the rocket's item, which is sent in its
+//! metadata. Explosions don't damage entities yet. Firework
+//! rockets are never saved.
+
+use crate::entity::metadata::{self, Metadata};
+use crate::entity::movement::degrees_to_stops;
+use crate::entity::{EntityDestroyEvent, PacketCreatorComponent, PositionComponent};
+use crate::lazy::LazyUpdateExt;
+use crate::util::{protocol_velocity, Util};
+use feather_core::network::packet::implementation::{EntityStatus, SpawnObject};
+use feather_core::{ItemStack, Packet, Position};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{
+    Builder, Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, System, World,
+    WorldExt, Write, WriteStorage,
+};
+use uuid::Uuid;
+
+/// The object type ID of firework rockets.
+const FIREWORK_TYPE_ID: i8 = 76;
+
+/// The entity status which makes clients
+/// render the explosion of a rocket.
+const STATUS_EXPLODE: i8 = 17;
+
+/// The horizontal acceleration and vertical
+/// speed gained by a rocket each tick.
+const HORIZONTAL_ACCELERATION: f64 = 1.15;
+const VERTICAL_ACCELERATION: f64 = 0.04;
+
+/// Component for firework rocket entities.
+#[derive(Debug, Clone)]
+pub struct FireworkComponent {
+    /// The number of ticks since the rocket was launched.
+    pub age: u32,
+    /// The number of ticks after which the rocket explodes.
+    pub lifetime: u32,
+    /// The velocity of the rocket, if it isn't boosting a player.
+    pub velocity: glm::DVec3,
+    /// The gliding player boosted by the rocket.
+    pub boosted: Option<Entity>,
+}
+
+impl Component for FireworkComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Returns the lifetime of a rocket with the given flight
+/// duration. As in vanilla, it is randomized slightly.
+pub fn lifetime(flight: i8, rng: &mut impl Rng) -> u32 {
+    let flight = u32::from(flight.max(0) as u8);
+    10 * (flight + 1) + rng.gen_range(0, 6) + rng.gen_range(0, 7)
+}
+
+/// Event triggered when a firework rocket is launched.
+#[derive(Debug, Clone)]
+pub struct FireworkLaunchEvent {
+    /// The rocket item.
+    pub stack: ItemStack,
+    pub position: Position,
+    /// The gliding player to boost, if any.
+    pub boosted: Option<Entity>,
+}
+
+pub fn create<'a>(
+    lazy: &'a LazyUpdate,
+    entities: &'a EntitiesRes,
+    stack: ItemStack,
+    boosted: Option<Entity>,
+    rng: &mut impl Rng,
+) -> LazyBuilder<'a> {
+    let flight = stack.fireworks().map_or(0, |fireworks| fireworks.flight);
+
+    let mut meta = metadata::FireworkRocket::default();
+    meta.set_boosted_entity(boosted.map_or(0, |entity| entity.id() as i32));
+    meta.set_firework_info(Some(stack.with_amount(1)));
+
+    // Unboosted rockets drift slightly.
+    let velocity = if boosted.is_some() {
+        glm::vec3(0.0, 0.0, 0.0)
+    } else {
+        glm::vec3(
+            rng.gen_range(-0.001, 0.001),
+            0.05,
+            rng.gen_range(-0.001, 0.001),
+        )
+    };
+
+    lazy.spawn_entity(entities)
+        .with(FireworkComponent {
+            age: 0,
+            lifetime: lifetime(flight, rng),
+            velocity,
+            boosted,
+        })
+        .with(Metadata::FireworkRocket(meta))
+        .with(PacketCreatorComponent(&create_packet))
+}
+
+fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
+    let position = world
+        .read_component::<PositionComponent>()
+        .get(entity)
+        .map(|position| position.current)
+        .unwrap_or_default();
+    let velocity = world
+        .read_component::<FireworkComponent>()
+        .get(entity)
+        .map(|firework| firework.velocity)
+        .unwrap_or_else(|| glm::vec3(0.0, 0.0, 0.0));
+    let (velocity_x, velocity_y, velocity_z) = protocol_velocity(velocity);
+
+    Box::new(SpawnObject {
+        entity_id: entity.id() as i32,
+        object_uuid: Uuid::new_v4(),
+        ty: FIREWORK_TYPE_ID,
+        x: position.x,
+        y: position.y,
+        z: position.z,
+        pitch: degrees_to_stops(position.pitch),
+        yaw: degrees_to_stops(position.yaw),
+        data: 0,
+        velocity_x,
+        velocity_y,
+        velocity_z,
+    })
+}
+
+/// System which spawns launched firework rockets.
+///
+/// This system listens to `FireworkLaunchEvent`s.
+#[derive(Default)]
+pub struct FireworkLaunchSystem {
+    reader: Option<ReaderId<FireworkLaunchEvent>>,
+}
+
+impl<'a> System<'a> for FireworkLaunchSystem {
+    type SystemData = (
+        Read<'a, EventChannel<FireworkLaunchEvent>>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, (events, lazy, entities): Self::SystemData) {
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            create(
+                &lazy,
+                &entities,
+                event.stack.clone(),
+                event.boosted,
+                &mut rng,
+            )
+            .with(PositionComponent {
+                current: event.position,
+                previous: event.position,
+            })
+            .build();
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which moves firework rockets and
+/// makes them explode at the end of their lifetime.
+pub struct FireworkUpdateSystem;
+
+impl<'a> System<'a> for FireworkUpdateSystem {
+    type SystemData = (
+        WriteStorage<'a, FireworkComponent>,
+        WriteStorage<'a, PositionComponent>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Read<'a, Util>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut fireworks, mut positions, mut destroy_events, util, entities) = data;
+
+        for (entity, firework) in (&entities, &mut fireworks).join() {
+            firework.age += 1;
+
+            // The explosion is sent a tick before the rocket is
+            // destroyed, since clients can't render the explosion
+            // of a rocket which no longer exists.
+            if firework.age > firework.lifetime {
+                destroy_events.single_write(EntityDestroyEvent { entity });
+                continue;
+            }
+            if firework.age == firework.lifetime {
+                util.broadcast_entity_update(
+                    entity,
+                    EntityStatus::new(entity.id() as i32, STATUS_EXPLODE),
+                    None,
+                );
+            }
+
+            let boosted_position = firework
+                .boosted
+                .and_then(|boosted| positions.get(boosted))
+                .map(|position| position.current);
+            let position = continue_if_none!(positions.get_mut(entity));
+
+            match boosted_position {
+                // Clients apply the boost to their own player.
+                Some(boosted_position) => position.current = boosted_position,
+                None => {
+                    firework.velocity.x *= HORIZONTAL_ACCELERATION;
+                    firework.velocity.z *= HORIZONTAL_ACCELERATION;
+                    firework.velocity.y += VERTICAL_ACCELERATION;
+                    position.current = position.current + firework.velocity;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::item_tag::Fireworks;
+    use feather_core::Item;
+
+    #[test]
+    fn test_lifetime() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let lifetime = lifetime(2, &mut rng);
+            assert!(lifetime >= 30 && lifetime <= 41);
+        }
+        assert!(super::lifetime(0, &mut rng) <= 21);
+    }
+
+    #[test]
+    fn test_launch_and_explode() {
+        let (mut w, mut d) = t::builder()
+            .with(FireworkLaunchSystem::default(), "launch")
+            .with(FireworkUpdateSystem, "")
+            .build();
+        let mut destroy_reader = t::reader::<EntityDestroyEvent>(&w);
+
+        let mut stack = ItemStack::new(Item::FireworkRocket, 3);
+        stack.tag_mut().fireworks = Some(Fireworks {
+            flight: 1,
+            explosions: vec![],
+        });
+
+        let position = position!(0.5, 65.0, 0.5);
+        t::trigger_event(
+            &w,
+            FireworkLaunchEvent {
+                stack,
+                position,
+                boosted: None,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let entity = {
+            let fireworks = w.read_component::<FireworkComponent>();
+            let (entity, firework) = (&w.entities(), &fireworks).join().next().unwrap();
+            assert!(firework.lifetime >= 20 && firework.lifetime <= 31);
+            entity
+        };
+        match w.read_component::<Metadata>().get(entity).unwrap() {
+            Metadata::FireworkRocket(meta) => {
+                assert_eq!(meta.firework_info().unwrap().amount, 1);
+                assert_eq!(meta.boosted_entity(), 0);
+            }
+            _ => panic!("expected firework metadata"),
+        }
+
+        // The rocket rises until it is destroyed.
+        w.write_component::<FireworkComponent>()
+            .get_mut(entity)
+            .unwrap()
+            .lifetime = 3;
+        d.dispatch(&w);
+        d.dispatch(&w);
+        let height = w
+            .read_component::<PositionComponent>()
+            .get(entity)
+            .unwrap()
+            .current
+            .y;
+        assert!(height > position.y);
+        assert!(t::triggered_events(&w, &mut destroy_reader).is_empty());
+
+        d.dispatch(&w);
+        let destroyed = t::triggered_events(&w, &mut destroy_reader);
+        assert_eq!(destroyed.len(), 1);
+        assert_eq!(destroyed[0].entity, entity);
+    }
+}
@@ -9,6 +9,7 @@ use crate::player::{
 };
 use crate::util::{protocol_velocity, Util};
 use crate::{TickCount, TPS};
+use feather_core::inventory::max_size;
 use feather_core::network::packet::implementation::CollectItem;
 use feather_core::{Item, ItemStack, Packet};
 use rand::Rng;
@@ -28,6 +29,9 @@ use feather_core::packet::SpawnObject;
 use specs::world::{EntitiesRes, LazyBuilder};
 use uuid::Uuid;
 
+/// The age, in ticks, at which items despawn (five minutes).
+pub const DESPAWN_AGE: u64 = 6000;
+
 /// Component for item entities.
 pub struct ItemComponent {
     /// The tick at which this item is collectable
@@ -35,6 +39,8 @@ pub struct ItemComponent {
     pub collectable_at: u64,
     /// This item's stack.
     pub stack: ItemStack,
+    /// The number of ticks since this item was spawned.
+    pub age: u64,
 }
 
 impl Component for ItemComponent {
@@ -110,7 +116,8 @@ impl<'a> System<'a> for ItemSpawnSystem {
 }
 
 /// System for merging item entities of the same
-/// type.
+/// type, as long as the merged stack isn't larger
+/// than the maximum stack size.
 #[derive(Default)]
 pub struct ItemMergeSystem {
     dirty: BitSet,
@@ -120,7 +127,7 @@ pub struct ItemMergeSystem {
 impl<'a> System<'a> for ItemMergeSystem {
     type SystemData = (
         ReadStorage<'a, PositionComponent>,
-        ReadStorage<'a, ItemComponent>,
+        WriteStorage<'a, ItemComponent>,
         WriteStorage<'a, Metadata>,
         Write<'a, EventChannel<EntityDestroyEvent>>,
         Read<'a, ChunkEntities>,
@@ -128,7 +135,7 @@ impl<'a> System<'a> for ItemMergeSystem {
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (positions, item_markers, mut metadatas, mut destroy_events, chunk_entities, entities) =
+        let (positions, mut items, mut metadatas, mut destroy_events, chunk_entities, entities) =
             data;
 
         self.dirty.clear();
@@ -142,11 +149,11 @@ impl<'a> System<'a> for ItemMergeSystem {
             }
         }
 
-        let mut metadatas_to_update: SmallVec<[(Entity, Metadata); 2]> = smallvec![];
+        let mut stacks_to_update: SmallVec<[(Entity, ItemStack, u64); 2]> = smallvec![];
         // Used to not destroy both entities
         let mut destroyed: SmallVec<[Entity; 2]> = smallvec![];
 
-        for (position, entity, _, _) in (&positions, &entities, &item_markers, &self.dirty).join() {
+        for (position, entity, item, _) in (&positions, &entities, &items, &self.dirty).join() {
             if !entities.is_alive(entity) {
                 continue;
             }
@@ -156,6 +163,7 @@ impl<'a> System<'a> for ItemMergeSystem {
             }
 
             let mut stack = item_stack_from_meta(metadatas.get(entity).unwrap());
+            let mut age = item.age;
 
             // Find nearby entities and check if they are of the same item
             // type. If so, merge the two item stacks.
@@ -176,14 +184,18 @@ impl<'a> System<'a> for ItemMergeSystem {
                     continue;
                 }
 
-                // Skip if it's not an item.
-                if item_markers.get(other).is_none() {
+                // Skip if it's not an item or has already been merged.
+                let other_item = continue_if_none!(items.get(other));
+                if destroyed.iter().any(|x| *x == other) {
                     continue;
                 }
 
                 let other_stack = item_stack_from_meta(metadatas.get(other).unwrap());
 
-                if other_stack.ty != stack.ty {
+                if !other_stack.stacks_with(&stack)
+                    || u32::from(stack.amount) + u32::from(other_stack.amount)
+                        > u32::from(max_size(stack.ty))
+                {
                     continue;
                 }
 
@@ -195,17 +207,25 @@ impl<'a> System<'a> for ItemMergeSystem {
                 let event = EntityDestroyEvent { entity: other };
                 destroy_events.single_write(event);
 
-                // TODO this could overflow...
                 stack.amount += other_stack.amount;
+                // As in vanilla, the merged item despawns
+                // when the younger of the two would have.
+                age = age.min(other_item.age);
 
-                metadatas_to_update.push((entity, item_meta(stack.clone())));
+                stacks_to_update.push((entity, stack.clone(), age));
                 destroyed.push(other);
             }
         }
 
-        metadatas_to_update.into_iter().for_each(|(entity, meta)| {
-            metadatas.insert(entity, meta).unwrap();
-        });
+        stacks_to_update
+            .into_iter()
+            .for_each(|(entity, stack, age)| {
+                if let Some(item) = items.get_mut(entity) {
+                    item.stack = stack.clone();
+                    item.age = age;
+                }
+                metadatas.insert(entity, item_meta(stack)).unwrap();
+            });
     }
 
     flagged_setup_impl!(PositionComponent, reader);
@@ -224,7 +244,7 @@ impl<'a> System<'a> for ItemCollectSystem {
         WriteStorage<'a, InventoryComponent>,
         ReadStorage<'a, PositionComponent>,
         ReadStorage<'a, PlayerComponent>,
-        ReadStorage<'a, ItemComponent>,
+        WriteStorage<'a, ItemComponent>,
         WriteStorage<'a, Metadata>,
         Write<'a, EventChannel<InventoryUpdateEvent>>,
         Write<'a, EventChannel<EntityDestroyEvent>>,
@@ -239,7 +259,7 @@ impl<'a> System<'a> for ItemCollectSystem {
             mut inventories,
             positions,
             players,
-            items,
+            mut items,
             mut metadatas,
             mut inventory_events,
             mut destroy_events,
@@ -312,6 +332,9 @@ impl<'a> System<'a> for ItemCollectSystem {
                     destroy_events.single_write(event);
                 } else {
                     stack.amount = amount_left;
+                    if let Some(item) = items.get_mut(other) {
+                        item.stack = stack.clone();
+                    }
                     let meta = item_meta(stack);
                     metadatas.insert(other, meta).unwrap();
                 }
@@ -329,11 +352,46 @@ impl<'a> System<'a> for ItemCollectSystem {
     flagged_setup_impl!(PositionComponent, reader);
 }
 
+/// System which despawns items once they
+/// are older than `DESPAWN_AGE`.
+pub struct ItemDespawnSystem;
+
+impl<'a> System<'a> for ItemDespawnSystem {
+    type SystemData = (
+        WriteStorage<'a, ItemComponent>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut items, mut destroy_events, entities) = data;
+
+        for (entity, item) in (&entities, &mut items).join() {
+            item.age += 1;
+            if item.age >= DESPAWN_AGE {
+                entities.delete(entity).unwrap();
+                destroy_events.single_write(EntityDestroyEvent { entity });
+            }
+        }
+    }
+}
+
 pub fn create<'a>(
     lazy: &'a LazyUpdate,
     entities: &EntitiesRes,
     stack: ItemStack,
     collectable_at: u64,
+) -> LazyBuilder<'a> {
+    create_with_age(lazy, entities, stack, collectable_at, 0)
+}
+
+/// Creates an item which was spawned `age` ticks ago.
+fn create_with_age<'a>(
+    lazy: &'a LazyUpdate,
+    entities: &EntitiesRes,
+    stack: ItemStack,
+    collectable_at: u64,
+    age: u64,
 ) -> LazyBuilder<'a> {
     let meta = {
         let mut meta_item = crate::entity::metadata::Item::default();
@@ -345,6 +403,7 @@ pub fn create<'a>(
         .with(ItemComponent {
             stack,
             collectable_at,
+            age,
         })
         .with(
             PhysicsBuilder::new()
@@ -371,9 +430,10 @@ pub fn create_from_data(
     let stack = ItemStack::new(Item::from_identifier(&data.item.item)?, data.item.count);
 
     let collectable_at = data.pickup_delay as u64 + tick.0;
+    let age = data.age.max(0) as u64;
 
     Some(
-        create(lazy, entities, stack, collectable_at)
+        create_with_age(lazy, entities, stack, collectable_at, age)
             .with(PositionComponent {
                 current: pos,
                 previous: pos,
@@ -412,6 +472,7 @@ fn serialize(world: &World, entity: Entity) -> EntityData {
     let positions = world.read_component::<PositionComponent>();
     let velocities = world.read_component::<VelocityComponent>();
     let items = world.read_component::<ItemComponent>();
+    let tick = world.fetch::<TickCount>().0;
 
     let item = items.get(entity).unwrap();
     let position = positions.get(entity).unwrap();
@@ -419,8 +480,11 @@ fn serialize(world: &World, entity: Entity) -> EntityData {
 
     EntityData::Item(ItemEntityData {
         entity: BaseEntityData::new(position.current, velocity.0),
-        age: 0,          // TODO
-        pickup_delay: 0, // TODO
+        age: item.age.min(i16::max_value() as u64) as i16,
+        pickup_delay: item
+            .collectable_at
+            .saturating_sub(tick)
+            .min(u64::from(u8::max_value())) as u8,
         item: ItemData {
             item: item.stack.ty.identifier().to_string(),
             count: item.stack.amount,
@@ -530,6 +594,76 @@ mod tests {
         let stack = item_stack_from_meta(&metadata);
         assert_eq!(stack.ty, Item::EnderPearl);
         assert_eq!(stack.amount, 11);
+        assert_eq!(
+            w.read_component::<ItemComponent>()
+                .get(item1)
+                .unwrap()
+                .stack,
+            stack
+        );
+    }
+
+    #[test]
+    fn test_item_merge_stack_size() {
+        let (mut w, mut d) = t::builder()
+            .with_dep(ItemMergeSystem::default(), "item_merge", &[])
+            .build();
+
+        // Ender pearls stack up to 16.
+        let item1 = create(
+            &w.fetch(),
+            &w.fetch(),
+            ItemStack::new(Item::EnderPearl, 10),
+            0,
+        )
+        .with(PositionComponent::default())
+        .build();
+        let item2 = create(
+            &w.fetch(),
+            &w.fetch(),
+            ItemStack::new(Item::EnderPearl, 10),
+            0,
+        )
+        .with(PositionComponent::default())
+        .build();
+
+        let mut updater = ChunkEntityUpdateSystem::default();
+        updater.setup(&mut w);
+        w.maintain();
+        specs::RunNow::run_now(&mut updater, &w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w.is_alive(item1));
+        assert!(w.is_alive(item2));
+    }
+
+    #[test]
+    fn test_item_despawn_system() {
+        let (mut w, mut d) = t::builder().with(ItemDespawnSystem, "").build();
+
+        let item = create(&w.fetch(), &w.fetch(), ItemStack::new(Item::Stone, 1), 0)
+            .with(PositionComponent::default())
+            .build();
+        w.maintain();
+        w.write_component::<ItemComponent>()
+            .get_mut(item)
+            .unwrap()
+            .age = DESPAWN_AGE - 2;
+
+        let mut destroy_reader = t::reader::<EntityDestroyEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+        assert!(w.is_alive(item));
+
+        d.dispatch(&w);
+        w.maintain();
+        assert!(!w.is_alive(item));
+        let events = t::triggered_events::<EntityDestroyEvent>(&w, &mut destroy_reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, item);
     }
 
     #[test]
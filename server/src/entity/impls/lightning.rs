@@ -0,0 +1,111 @@
+//! Lightning bolts.
+//!
+//! Lightning bolts are sent to clients using the
+//! `Spawn Global Entity` packet. Clients render the bolt
+//! and play the thunder sound themselves, so the entity
+//! is only kept around for a few ticks before being
+//! destroyed. Lightning bolts are never saved.
+
+use crate::entity::{EntityDestroyEvent, PacketCreatorComponent, PositionComponent};
+use crate::lazy::LazyUpdateExt;
+use feather_core::network::packet::implementation::SpawnGlobalEntity;
+use feather_core::Packet;
+use shrev::EventChannel;
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{
+    Builder, Component, Entities, Entity, HashMapStorage, Join, LazyUpdate, System, World,
+    WorldExt, Write, WriteStorage,
+};
+
+/// The number of ticks a lightning bolt exists for.
+pub const LIGHTNING_LIFETIME: u32 = 5;
+
+/// The global entity type ID of lightning bolts.
+const LIGHTNING_TYPE_ID: u8 = 1;
+
+/// Component for lightning bolt entities.
+#[derive(Debug, Clone)]
+pub struct LightningComponent {
+    /// The number of ticks until the bolt is destroyed.
+    pub ticks_left: u32,
+}
+
+impl Component for LightningComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+pub fn create<'a>(lazy: &'a LazyUpdate, entities: &'a EntitiesRes) -> LazyBuilder<'a> {
+    lazy.spawn_entity(entities)
+        .with(LightningComponent {
+            ticks_left: LIGHTNING_LIFETIME,
+        })
+        .with(PacketCreatorComponent(&create_packet))
+}
+
+fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
+    let position = world
+        .read_component::<PositionComponent>()
+        .get(entity)
+        .map(|position| position.current)
+        .unwrap_or_default();
+
+    Box::new(SpawnGlobalEntity {
+        entity_id: entity.id() as i32,
+        ty: LIGHTNING_TYPE_ID,
+        x: position.x,
+        y: position.y,
+        z: position.z,
+    })
+}
+
+/// System which destroys lightning bolts
+/// once their lifetime is over.
+pub struct LightningDespawnSystem;
+
+impl<'a> System<'a> for LightningDespawnSystem {
+    type SystemData = (
+        WriteStorage<'a, LightningComponent>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut lightnings, mut destroy_events, entities) = data;
+
+        for (entity, lightning) in (&entities, &mut lightnings).join() {
+            if lightning.ticks_left == 0 {
+                destroy_events.single_write(EntityDestroyEvent { entity });
+            } else {
+                lightning.ticks_left -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+
+    #[test]
+    fn test_lightning_despawn() {
+        let (mut w, mut d) = t::builder().with(LightningDespawnSystem, "").build();
+
+        let bolt = w
+            .create_entity()
+            .with(LightningComponent { ticks_left: 1 })
+            .build();
+
+        let mut reader = t::reader::<EntityDestroyEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+        assert!(t::triggered_events::<EntityDestroyEvent>(&w, &mut reader).is_empty());
+
+        d.dispatch(&w);
+        w.maintain();
+        let events = t::triggered_events::<EntityDestroyEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, bolt);
+    }
+}
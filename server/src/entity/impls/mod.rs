@@ -21,11 +21,16 @@
 
 pub mod arrow;
 pub mod falling_block;
+pub mod firework;
 pub mod item;
+pub mod lightning;
 
 mod animal;
 pub use animal::*;
 
+mod monster;
+pub use monster::*;
+
 use crate::entity::{
     degrees_to_stops, metadata::EMPTY_METADATA, Metadata, NamedComponent, PositionComponent,
     VelocityComponent,
@@ -68,7 +73,8 @@ pub fn create_mob_packet(world: &World, entity: Entity, type_id: i32) -> Box<dyn
         z: position.current.z,
         yaw: degrees_to_stops(position.current.yaw),
         pitch: degrees_to_stops(position.current.pitch),
-        head_pitch: degrees_to_stops(position.current.pitch), // FIXME: is this correct?
+        // Despite its name, this is the yaw of the head.
+        head_pitch: degrees_to_stops(position.current.yaw),
         velocity_x,
         velocity_y,
         velocity_z,
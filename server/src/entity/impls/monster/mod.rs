@@ -0,0 +1,5 @@
+//! Implementations for monsters: zombies, skeletons, zombie pigmen, etc.
+
+pub mod skeleton;
+pub mod zombie;
+pub mod zombie_pigman;
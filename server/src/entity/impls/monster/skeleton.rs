@@ -0,0 +1,52 @@
+use crate::entity::{
+    create_mob_packet, PacketCreatorComponent, PositionComponent, SerializerComponent,
+    VelocityComponent,
+};
+use crate::mob::{self, MobKind};
+use crate::physics::PhysicsBuilder;
+use feather_core::entity::{AnimalData, EntityData};
+use feather_core::Packet;
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{Builder, Component, Entity, LazyUpdate, NullStorage, World};
+
+#[derive(Default)]
+pub struct SkeletonComponent;
+
+impl Component for SkeletonComponent {
+    type Storage = NullStorage<Self>;
+}
+
+pub fn create<'a>(lazy: &'a LazyUpdate, entities: &'a EntitiesRes) -> LazyBuilder<'a> {
+    mob::create(lazy, entities, MobKind::Skeleton)
+        .with(SkeletonComponent)
+        .with(PhysicsBuilder::for_living().bbox(0.6, 1.99, 0.6).build())
+        .with(PacketCreatorComponent(&create_packet))
+        .with(SerializerComponent(&serialize))
+}
+
+pub fn create_from_data(
+    lazy: &LazyUpdate,
+    entities: &EntitiesRes,
+    data: &AnimalData,
+) -> Option<Entity> {
+    let position = data.base.read_position()?;
+    let velocity = data.base.read_velocity()?;
+
+    Some(
+        mob::load_data(create(lazy, entities), data)
+            .with(PositionComponent {
+                current: position,
+                previous: position,
+            })
+            .with(VelocityComponent(velocity))
+            .build(),
+    )
+}
+
+fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
+    create_mob_packet(world, entity, 62)
+}
+
+fn serialize(world: &World, entity: Entity) -> EntityData {
+    EntityData::Skeleton(mob::save_data(world, entity))
+}
@@ -0,0 +1,53 @@
+use crate::entity::{
+    base_data, create_mob_packet, PacketCreatorComponent, PositionComponent, SerializerComponent,
+    VelocityComponent,
+};
+use crate::lazy::LazyUpdateExt;
+use crate::physics::PhysicsBuilder;
+use feather_core::entity::{AnimalData, EntityData};
+use feather_core::Packet;
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{Builder, Component, Entity, LazyUpdate, NullStorage, World};
+
+#[derive(Default)]
+pub struct ZombiePigmanComponent;
+
+impl Component for ZombiePigmanComponent {
+    type Storage = NullStorage<Self>;
+}
+
+pub fn create<'a>(lazy: &'a LazyUpdate, entities: &'a EntitiesRes) -> LazyBuilder<'a> {
+    lazy.spawn_entity(entities)
+        .with(ZombiePigmanComponent)
+        .with(PhysicsBuilder::for_living().bbox(0.6, 1.95, 0.6).build())
+        .with(PacketCreatorComponent(&create_packet))
+        .with(SerializerComponent(&serialize))
+}
+
+pub fn create_from_data(
+    lazy: &LazyUpdate,
+    entities: &EntitiesRes,
+    data: &AnimalData,
+) -> Option<Entity> {
+    let position = data.base.read_position()?;
+    let velocity = data.base.read_velocity()?;
+
+    Some(
+        create(lazy, entities)
+            .with(PositionComponent {
+                current: position,
+                previous: position,
+            })
+            .with(VelocityComponent(velocity))
+            .build(),
+    )
+}
+
+fn create_packet(world: &World, entity: Entity) -> Box<dyn Packet> {
+    create_mob_packet(world, entity, 53)
+}
+
+fn serialize(world: &World, entity: Entity) -> EntityData {
+    let base = base_data(world, entity);
+    EntityData::ZombiePigman(AnimalData::new(base))
+}
@@ -62,6 +62,26 @@ entity_metadata! {
         displayed_skin_parts: u8() = 13,
         main_hand: u8(1) = 14,
     },
+    Mob: Living {
+        mob_bit_mask: u8() = 11,
+    },
+    Ageable: Mob {
+        is_baby: bool() = 12,
+    },
+    Pig: Ageable {
+        has_saddle: bool() = 13,
+        boost_time: VarInt() = 14,
+    },
+    Sheep: Ageable {
+        wool: u8() = 13,
+    },
+    Zombie: Mob {
+        is_baby: bool() = 12,
+        hands_held_up: bool() = 14,
+    },
+    Skeleton: Mob {
+        swinging_arms: bool() = 12,
+    },
     Arrow: Entity {
         arrow_bit_mask: u8() = 6,
         shooter: OptUuid() = 7,
@@ -72,12 +92,55 @@ entity_metadata! {
     FallingBlock: Entity {
         spawn_position: BlockPosition() = 6,
     },
+    FireworkRocket: Entity {
+        firework_info: Slot() = 6,
+        boosted_entity: VarInt() = 7,
+    },
 }
 
 impl Component for Metadata {
     type Storage = FlaggedStorage<Self, VecStorage<Self>>;
 }
 
+impl Metadata {
+    /// Sets the health of a living entity. Has no
+    /// effect on the metadata of other entities.
+    pub fn set_health(&mut self, health: f32) {
+        match self {
+            Metadata::Living(meta) => meta.set_health(health),
+            Metadata::Player(meta) => meta.set_health(health),
+            Metadata::Mob(meta) => meta.set_health(health),
+            Metadata::Ageable(meta) => meta.set_health(health),
+            Metadata::Pig(meta) => meta.set_health(health),
+            Metadata::Sheep(meta) => meta.set_health(health),
+            Metadata::Zombie(meta) => meta.set_health(health),
+            Metadata::Skeleton(meta) => meta.set_health(health),
+            _ => (),
+        }
+    }
+
+    /// Sets whether a living entity is shown to be on fire.
+    /// Has no effect on the metadata of other entities.
+    pub fn set_on_fire(&mut self, on_fire: bool) {
+        let with_fire = |bit_mask| {
+            let mut bit_mask = EntityBitMask::from_bits_truncate(bit_mask);
+            bit_mask.set(EntityBitMask::ON_FIRE, on_fire);
+            bit_mask.bits()
+        };
+        match self {
+            Metadata::Living(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Player(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Mob(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Ageable(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Pig(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Sheep(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Zombie(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            Metadata::Skeleton(meta) => meta.set_bit_mask(with_fire(meta.bit_mask())),
+            _ => (),
+        }
+    }
+}
+
 /// System for broadcasting entity metadata updates.
 #[derive(Default)]
 pub struct MetadataBroadcastSystem {
@@ -15,10 +15,12 @@ pub use impls::*;
 
 use crate::systems::{
     BLOCK_FALLING_LANDING, CHUNK_CROSS, CHUNK_ENTITIES_LOAD, CHUNK_ENTITIES_UPDATE, CHUNK_SAVE,
-    ENTITY_DESTROY, ENTITY_DESTROY_BROADCAST, ENTITY_METADATA_BROADCAST, ENTITY_MOVE_BROADCAST,
-    ENTITY_PHYSICS, ENTITY_SPAWN_BROADCAST, ENTITY_VELOCITY_BROADCAST, ITEM_COLLECT, ITEM_MERGE,
-    ITEM_SPAWN, JOIN_BROADCAST, SHOOT_ARROW,
+    CHUNK_SEND, COMPONENT_RESET, ENTITY_DESTROY, ENTITY_DESTROY_BROADCAST,
+    ENTITY_METADATA_BROADCAST, ENTITY_MOVE_BROADCAST, ENTITY_PHYSICS, ENTITY_SPAWN_BROADCAST,
+    ENTITY_VELOCITY_BROADCAST, FIREWORK_LAUNCH, FIREWORK_UPDATE, ITEM_COLLECT, ITEM_DESPAWN,
+    ITEM_MERGE, ITEM_SPAWN, JOIN_BROADCAST, LIGHTNING_DESPAWN, SHOOT_ARROW,
 };
+use crate::timings::DispatcherBuilderExt;
 pub use arrow::{ArrowComponent, ShootArrowEvent};
 pub use broadcast::send_entity_to_player;
 pub use broadcast::{EntitySendEvent, EntitySpawnEvent};
@@ -30,7 +32,9 @@ pub use component::{
 };
 pub use destroy::EntityDestroyEvent;
 pub use falling_block::FallingBlockComponent;
+pub use firework::{FireworkComponent, FireworkLaunchEvent};
 pub use item::ItemComponent;
+pub use lightning::LightningComponent;
 pub use metadata::{EntityBitMask, Metadata};
 pub use movement::{degrees_to_stops, LastKnownPositionComponent};
 
@@ -40,7 +44,9 @@ use crate::entity::arrow::ShootArrowSystem;
 use crate::entity::chunk::EntityChunkLoadSystem;
 use crate::entity::destroy::EntityDestroyBroadcastSystem;
 use crate::entity::falling_block::FallingBlockLandSystem;
-use crate::entity::item::ItemCollectSystem;
+use crate::entity::firework::{FireworkLaunchSystem, FireworkUpdateSystem};
+use crate::entity::item::{ItemCollectSystem, ItemDespawnSystem};
+use crate::entity::lightning::LightningDespawnSystem;
 use crate::entity::metadata::MetadataBroadcastSystem;
 use crate::entity::save::ChunkSaveSystem;
 use broadcast::EntityBroadcastSystem;
@@ -51,53 +57,61 @@ use movement::{EntityMoveBroadcastSystem, EntityVelocityBroadcastSystem};
 use specs::DispatcherBuilder;
 
 pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(ItemCollectSystem::default(), ITEM_COLLECT, &[]);
+    dispatcher.add_timed(ItemCollectSystem::default(), ITEM_COLLECT, &[]);
+    dispatcher.add_timed(ItemDespawnSystem, ITEM_DESPAWN, &[]);
+    dispatcher.add_timed(LightningDespawnSystem, LIGHTNING_DESPAWN, &[]);
+    dispatcher.add_timed(FireworkUpdateSystem, FIREWORK_UPDATE, &[]);
 }
 
 pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(
+    dispatcher.add_timed(
         ChunkEntityUpdateSystem::default(),
         CHUNK_ENTITIES_UPDATE,
         &[],
     );
-    dispatcher.add(EntityChunkLoadSystem::default(), CHUNK_ENTITIES_LOAD, &[]);
-    dispatcher.add(EntityDestroySystem::default(), ENTITY_DESTROY, &[]);
-    dispatcher.add(ItemSpawnSystem::default(), ITEM_SPAWN, &[]);
-    dispatcher.add(ItemMergeSystem::default(), ITEM_MERGE, &[]);
-    dispatcher.add(
+    dispatcher.add_timed(EntityChunkLoadSystem::default(), CHUNK_ENTITIES_LOAD, &[]);
+    dispatcher.add_timed(EntityDestroySystem::default(), ENTITY_DESTROY, &[]);
+    dispatcher.add_timed(ItemSpawnSystem::default(), ITEM_SPAWN, &[]);
+    dispatcher.add_timed(ItemMergeSystem::default(), ITEM_MERGE, &[]);
+    dispatcher.add_timed(
         MetadataBroadcastSystem::default(),
         ENTITY_METADATA_BROADCAST,
         &[],
     );
-    dispatcher.add(ShootArrowSystem::default(), SHOOT_ARROW, &[]);
-    dispatcher.add(ChunkSaveSystem::default(), CHUNK_SAVE, &[]);
+    dispatcher.add_timed(ShootArrowSystem::default(), SHOOT_ARROW, &[]);
+    dispatcher.add_timed(FireworkLaunchSystem::default(), FIREWORK_LAUNCH, &[]);
+    dispatcher.add_timed(ChunkSaveSystem::default(), CHUNK_SAVE, &[]);
 }
 
 pub fn init_broadcast(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(
+    dispatcher.add_timed(
         EntityMoveBroadcastSystem::default(),
         ENTITY_MOVE_BROADCAST,
         &[],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         EntityBroadcastSystem::default(),
         ENTITY_SPAWN_BROADCAST,
-        &[JOIN_BROADCAST, CHUNK_CROSS],
+        &[JOIN_BROADCAST, CHUNK_CROSS, CHUNK_SEND],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         EntityVelocityBroadcastSystem::default(),
         ENTITY_VELOCITY_BROADCAST,
         &[],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         EntityDestroyBroadcastSystem::default(),
         ENTITY_DESTROY_BROADCAST,
-        &[],
+        &[
+            ENTITY_SPAWN_BROADCAST,
+            ENTITY_MOVE_BROADCAST,
+            ENTITY_VELOCITY_BROADCAST,
+        ],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         FallingBlockLandSystem::default(),
         BLOCK_FALLING_LANDING,
         &[ENTITY_PHYSICS],
     );
-    dispatcher.add_thread_local(ComponentResetSystem);
+    dispatcher.add_timed_thread_local(ComponentResetSystem, COMPONENT_RESET);
 }
@@ -5,7 +5,8 @@ use specs::{
 };
 
 use feather_core::network::packet::implementation::{
-    EntityHeadLook, EntityLook, EntityLookAndRelativeMove, EntityRelativeMove, EntityVelocity,
+    EntityHeadLook, EntityLook, EntityLookAndRelativeMove, EntityRelativeMove, EntityTeleport,
+    EntityVelocity,
 };
 use feather_core::world::Position;
 
@@ -29,7 +30,15 @@ impl Component for LastKnownPositionComponent {
     type Storage = DenseVecStorage<Self>;
 }
 
+/// The maximum distance, in blocks, an entity can move
+/// along each axis in a single relative move packet.
+const MAX_RELATIVE_MOVE: f64 = 8.0;
+
 /// System for broadcasting when an entity moves.
+///
+/// Movement is sent at most once per tick: each player
+/// receives a single update for the movement since the last
+/// position of the entity which was sent to that player.
 #[derive(Default)]
 pub struct EntityMoveBroadcastSystem {
     dirty: BitSet,
@@ -112,7 +121,18 @@ pub fn packet_for_movement_update(
     let has_moved = old_pos.x != new_pos.x || old_pos.y != new_pos.y || old_pos.z != new_pos.z;
     let has_looked = old_pos.pitch != new_pos.pitch || old_pos.yaw != new_pos.yaw;
 
-    if has_moved {
+    if has_moved && !can_move_relative(old_pos, new_pos) {
+        let packet: Box<dyn Packet> = Box::new(EntityTeleport::new(
+            entity.id() as i32,
+            new_pos.x,
+            new_pos.y,
+            new_pos.z,
+            degrees_to_stops(new_pos.yaw),
+            degrees_to_stops(new_pos.pitch),
+            new_pos.on_ground,
+        ));
+        packets.push(packet);
+    } else if has_moved {
         let (rx, ry, rz) = calculate_relative_move(old_pos, new_pos);
 
         if (rx == 0 && ry == 0 && rz == 0) && !has_looked {
@@ -212,6 +232,14 @@ impl<'a> System<'a> for EntityVelocityBroadcastSystem {
     flagged_setup_impl!(VelocityComponent, reader);
 }
 
+/// Returns whether the movement between two positions
+/// can be sent using a relative move packet.
+fn can_move_relative(old: Position, current: Position) -> bool {
+    (current.x - old.x).abs() < MAX_RELATIVE_MOVE
+        && (current.y - old.y).abs() < MAX_RELATIVE_MOVE
+        && (current.z - old.z).abs() < MAX_RELATIVE_MOVE
+}
+
 /// Calculates the relative move fields
 /// as used in the Entity Relative Move packets.
 pub fn calculate_relative_move(old: Position, current: Position) -> (i16, i16, i16) {
@@ -261,4 +289,52 @@ mod tests {
         assert_eq!(packet.velocity_y, 0);
         assert_eq!(packet.velocity_z, 0);
     }
+
+    fn move_entity(old_pos: Position, new_pos: Position) -> t::Player {
+        let (mut w, mut d) = t::builder()
+            .with(EntityMoveBroadcastSystem::default(), "")
+            .build();
+
+        let player = t::add_player(&mut w);
+        let entity = test::create(&mut w, old_pos).build();
+
+        w.write_component::<LastKnownPositionComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .0
+            .insert(entity, old_pos);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        w.write_component::<PositionComponent>()
+            .get_mut(entity)
+            .unwrap()
+            .current = new_pos;
+
+        d.dispatch(&w);
+        w.maintain();
+
+        player
+    }
+
+    #[test]
+    fn test_relative_move_broadcast() {
+        let player = move_entity(position!(0.0, 64.0, 0.0), position!(1.0, 64.0, -7.5));
+
+        let packet = t::assert_packet_received(&player, PacketType::EntityRelativeMove);
+        let packet = cast_packet::<EntityRelativeMove>(&*packet);
+        assert_eq!(packet.delta_x, 4096);
+        assert_eq!(packet.delta_z, -30720);
+    }
+
+    #[test]
+    fn test_teleport_broadcast() {
+        let player = move_entity(position!(0.0, 64.0, 0.0), position!(8.0, 64.0, 0.0));
+
+        let packet = t::assert_packet_received(&player, PacketType::EntityTeleport);
+        let packet = cast_packet::<EntityTeleport>(&*packet);
+        assert_eq!(packet.x as i32, 8);
+        assert_eq!(packet.y as i32, 64);
+    }
 }
@@ -1,70 +1,116 @@
 //! Saving of entity data (and chunk data along with it).
 
+use crate::blocks::BlockTicks;
 use crate::chunk_logic;
 use crate::chunk_logic::{ChunkUnloadEvent, ChunkWorkerHandle};
 use crate::config::Config;
-use crate::entity::{ChunkEntities, SerializerComponent};
+use crate::dimension::Dimensions;
+use crate::entity::{ChunkEntities, EntityDestroyEvent, SerializerComponent};
+use crate::scheduler::Scheduler;
+use crate::TICK_TIME;
+use feather_core::entity::EntityData;
 use feather_core::world::ChunkMap;
 use rayon::prelude::*;
 use shrev::{EventChannel, ReaderId};
-use specs::{Entity, LazyUpdate, Read, ReadExpect, System, WorldExt, Write};
+use specs::{Entity, LazyUpdate, Read, System, World, WorldExt};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
 
-/// System to save chunk and entity data upon a chunk unload
-/// and periodically.
+/// System to save chunk and entity data upon a chunk unload.
 ///
-/// This system listens to `ChunkUnloadEvent`s.
+/// Entities in the chunk which are saved are destroyed, so that
+/// they don't exist twice once the chunk is loaded again.
+///
+/// This system listens to `ChunkUnloadEvent`s. Periodic saving
+/// is performed by a task on the `Scheduler`, which is scheduled
+/// when this system is set up.
 #[derive(Default)]
 pub struct ChunkSaveSystem {
     reader: Option<ReaderId<ChunkUnloadEvent>>,
 }
 
-/// Previous time at which chunks were saved.
-pub struct PreviousSaveTime(Instant);
-
-impl Default for PreviousSaveTime {
-    fn default() -> Self {
-        Self(Instant::now())
-    }
-}
-
 impl<'a> System<'a> for ChunkSaveSystem {
     type SystemData = (
-        Write<'a, PreviousSaveTime>,
-        Write<'a, ChunkMap>,
-        Read<'a, ChunkEntities>,
         Read<'a, EventChannel<ChunkUnloadEvent>>,
-        Read<'a, Arc<Config>>,
+        Read<'a, ChunkEntities>,
         Read<'a, LazyUpdate>,
-        ReadExpect<'a, ChunkWorkerHandle>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (
-            mut prev_save_time,
-            mut chunk_map,
-            chunk_entities,
-            unload_events,
-            config,
-            lazy,
-            worker_handle,
-        ) = data;
+        let (unload_events, chunk_entities, lazy) = data;
 
         for event in unload_events.read(self.reader.as_mut().unwrap()) {
-            let entities = vec![]; // TODO
-            chunk_logic::save_chunk(&worker_handle, Arc::clone(&event.chunk), entities);
+            let chunk = Arc::clone(&event.chunk);
+            let entities = chunk_entities.entities_in_chunk(chunk.position()).clone();
+
+            // As in `save_chunks`, serialization requires world access.
+            lazy.exec(move |world| {
+                let (saved, entity_data) = serialize_entities(world, entities);
+
+                let mut destroy_events = world.fetch_mut::<EventChannel<EntityDestroyEvent>>();
+                destroy_events.iter_write(
+                    saved
+                        .into_iter()
+                        .map(|entity| EntityDestroyEvent { entity }),
+                );
+                drop(destroy_events);
+
+                let handle = world.fetch::<ChunkWorkerHandle>();
+                chunk_logic::save_chunk(&handle, chunk, entity_data);
+            });
         }
+    }
 
-        if prev_save_time.0.elapsed() >= config.world.save_interval {
-            // Save chunks
-            save_chunks(&mut chunk_map, &chunk_entities, &lazy);
-            prev_save_time.0 = Instant::now();
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<ChunkUnloadEvent>>()
+                .register_reader(),
+        );
+        schedule_autosave(world);
+    }
+}
+
+/// Schedules the next periodic save of all chunks.
+///
+/// The save interval is read from the configuration each
+/// time, so changes take effect after the next save.
+pub fn schedule_autosave(world: &mut World) {
+    let interval = world
+        .entry::<Arc<Config>>()
+        .or_insert_with(Arc::default)
+        .world
+        .save_interval;
+    let ticks = interval.as_millis() as u64 / TICK_TIME;
+
+    world
+        .entry::<Scheduler>()
+        .or_insert_with(Scheduler::default)
+        .schedule_delayed(ticks, autosave);
+}
+
+/// Saves all modified chunks, including those of
+/// other dimensions, and schedules the next save.
+pub fn autosave(world: &mut World) {
+    let mut chunk_map = world.fetch_mut::<ChunkMap>();
+    save_chunks(
+        &mut chunk_map,
+        &world.fetch(),
+        &world.fetch(),
+        &world.fetch(),
+    );
+    drop(chunk_map);
+
+    if let Some(mut dimensions) = world.try_fetch_mut::<Dimensions>() {
+        for (_, _, dimension) in dimensions.worlds_mut() {
+            dimension.save_chunks();
         }
     }
 
-    setup_impl!(reader);
+    schedule_autosave(world);
 }
 
 /// Saves all modified chunks.
@@ -75,13 +121,18 @@ impl<'a> System<'a> for ChunkSaveSystem {
 pub fn save_chunks(
     chunk_map: &mut ChunkMap,
     chunk_entities: &ChunkEntities,
+    block_ticks: &BlockTicks,
     lazy: &LazyUpdate,
 ) -> u32 {
     let count = AtomicUsize::new(0);
+    let ticks_by_chunk = block_ticks.ticks_by_chunk();
     chunk_map
         .chunks_mut()
         .par_iter_mut()
         .map(|(_, chunk)| {
+            let ticks = ticks_by_chunk.get(&chunk.position()).cloned();
+            chunk.set_scheduled_ticks(ticks.unwrap_or_default());
+
             let (dirty, entities) = chunk_entities.entities_in_chunk_and_modified(chunk.position());
             (chunk, entities, dirty)
         })
@@ -99,20 +150,7 @@ pub fn save_chunks(
             let chunk = Arc::new(chunk.clone());
             let entities: Vec<Entity> = entities.to_vec();
             lazy.exec(move |world| {
-                // Compute entity data.
-                let entity_data = entities
-                    .into_iter()
-                    .filter_map(|entity| {
-                        let serializers = world.read_component::<SerializerComponent>();
-                        let serializer = match serializers.get(entity) {
-                            Some(serializer) => serializer,
-                            None => return None, // Entity not serialized
-                        };
-
-                        let serialize = serializer.0;
-                        Some(serialize(world, entity))
-                    })
-                    .collect();
+                let (_, entity_data) = serialize_entities(world, entities);
 
                 let handle = world.fetch::<ChunkWorkerHandle>();
                 chunk_logic::save_chunk(&handle, chunk, entity_data);
@@ -126,12 +164,27 @@ pub fn save_chunks(
     count as u32
 }
 
+/// Computes the data of the given entities which have a
+/// `SerializerComponent`, returning the entities serialized
+/// and their data. Other entities, such as players, are skipped.
+fn serialize_entities(world: &World, entities: Vec<Entity>) -> (Vec<Entity>, Vec<EntityData>) {
+    let serializers = world.read_component::<SerializerComponent>();
+    entities
+        .into_iter()
+        .filter_map(|entity| {
+            let serialize = serializers.get(entity)?.0;
+            Some((entity, serialize(world, entity)))
+        })
+        .unzip()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::entity::{cow, PositionComponent};
     use crate::{chunkworker, testframework as t};
-    use failure::_core::time::Duration;
     use feather_core::{Chunk, ChunkPosition};
+    use specs::Builder;
 
     #[test]
     fn test_chunk_unload() {
@@ -144,6 +197,20 @@ mod tests {
             receiver: rx2,
         });
 
+        // The player is added to the chunk as well.
+        let pos = ChunkPosition::new(0, 0);
+        world.register::<cow::CowComponent>();
+        let cow = cow::create(&world.fetch::<LazyUpdate>(), &world.entities())
+            .with(PositionComponent {
+                current: position!(1.0, 64.0, 1.0),
+                previous: position!(1.0, 64.0, 1.0),
+            })
+            .build();
+        t::add_player(&mut world);
+        world.maintain();
+        world.fetch_mut::<ChunkEntities>().add_to_chunk(pos, cow);
+        let mut destroy_reader = t::reader::<EntityDestroyEvent>(&world);
+
         let event = ChunkUnloadEvent {
             chunk: Arc::new(Chunk::default()),
         };
@@ -151,21 +218,33 @@ mod tests {
         t::trigger_event(&world, event);
 
         dispatcher.dispatch(&world);
+        world.maintain();
 
         let msg = rx.try_recv().unwrap();
 
         match msg {
             chunkworker::Request::SaveChunk(chunk, entities) => {
-                assert_eq!(chunk.position(), ChunkPosition::new(0, 0));
-                assert!(entities.is_empty()); // TODO
+                assert_eq!(chunk.position(), pos);
+                assert_eq!(entities.len(), 1);
+                match &entities[0] {
+                    EntityData::Cow(data) => {
+                        assert_eq!(data.base.read_position(), Some(position!(1.0, 64.0, 1.0)))
+                    }
+                    data => panic!("unexpected entity {:?}", data),
+                }
             }
             _ => panic!(),
         }
+
+        // The cow is destroyed, but players aren't saved with chunks.
+        let destroyed = t::triggered_events::<EntityDestroyEvent>(&world, &mut destroy_reader);
+        assert_eq!(destroyed.len(), 1);
+        assert_eq!(destroyed[0].entity, cow);
     }
 
     #[test]
-    fn test_periodic() {
-        let (mut world, mut dispatcher) = t::builder().with(ChunkSaveSystem::default(), "").build();
+    fn test_autosave() {
+        let (mut world, _) = t::builder().with(ChunkSaveSystem::default(), "").build();
 
         let (tx, rx) = crossbeam::unbounded();
         let (_tx2, rx2) = crossbeam::unbounded();
@@ -174,15 +253,15 @@ mod tests {
             receiver: rx2,
         });
 
-        let last_save_time = PreviousSaveTime(Instant::now() - Duration::from_secs(120));
-        world.insert(last_save_time);
+        // The first save is scheduled on setup.
+        assert_eq!(world.fetch::<Scheduler>().len(), 1);
 
         let pos = ChunkPosition::new(0, 0);
         world
             .fetch_mut::<ChunkMap>()
             .set_chunk_at(pos, Chunk::new(pos));
 
-        dispatcher.dispatch(&world);
+        autosave(&mut world);
         world.maintain();
 
         let msg = rx.try_recv().unwrap();
@@ -194,5 +273,8 @@ mod tests {
             }
             _ => panic!(),
         }
+
+        // The next save has been scheduled.
+        assert_eq!(world.fetch::<Scheduler>().len(), 2);
     }
 }
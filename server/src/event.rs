@@ -0,0 +1,298 @@
+//! A synchronous, cancellable event bus.
+//!
+//! Unlike the `EventChannel`s used throughout the server, which
+//! notify systems *after* something has happened, events on the
+//! `EventBus` are emitted by the core systems *before* applying
+//! their effects. Handlers run immediately, in order of priority,
+//! and may modify or cancel the event. This is the primary extension
+//! point for plugins and scripts.
+//!
+//! Handlers are registered on the `EventBus` resource, usually
+//! in the `setup` function of a system:
+//!
+//! ```ignore
+//! world
+//!     .fetch_mut::<EventBus>()
+//!     .register(EventPriority::Normal, |event: &mut ChatEvent| {
+//!         if event.message.contains("spam") {
+//!             event.set_cancelled(true);
+//!         }
+//!     });
+//! ```
+
+use crate::lang::Message;
+use feather_core::{Block, BlockPosition};
+use hashbrown::HashMap;
+use specs::Entity;
+use std::any::{Any, TypeId};
+
+/// An event which can be emitted on the `EventBus`.
+pub trait Event: Send + Sync + 'static {
+    /// Returns whether the event has been cancelled.
+    fn is_cancelled(&self) -> bool;
+
+    /// Sets whether the event is cancelled. A cancelled
+    /// event can be uncancelled by a handler with a higher
+    /// priority.
+    fn set_cancelled(&mut self, cancelled: bool);
+}
+
+macro_rules! impl_event {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Event for $ty {
+                fn is_cancelled(&self) -> bool {
+                    self.cancelled
+                }
+
+                fn set_cancelled(&mut self, cancelled: bool) {
+                    self.cancelled = cancelled;
+                }
+            }
+        )*
+    };
+}
+
+/// The priority of an event handler. Handlers with
+/// lower priorities run first, so handlers with higher
+/// priorities have the final say on the outcome.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventPriority {
+    Lowest,
+    Low,
+    Normal,
+    High,
+    Highest,
+    /// Runs last. Handlers with this priority should
+    /// only observe the outcome of the event and
+    /// must not modify it.
+    Monitor,
+}
+
+impl Default for EventPriority {
+    fn default() -> Self {
+        EventPriority::Normal
+    }
+}
+
+struct Handler<E> {
+    priority: EventPriority,
+    func: Box<dyn Fn(&mut E) + Send + Sync>,
+}
+
+/// Resource containing all registered event handlers.
+#[derive(Default)]
+pub struct EventBus {
+    /// Mapping from `TypeId` of an event to a
+    /// `Vec<Handler<E>>`, sorted by priority.
+    handlers: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl EventBus {
+    /// Registers a handler for events of type `E`.
+    ///
+    /// Handlers with the same priority run in
+    /// the order in which they were registered.
+    pub fn register<E, F>(&mut self, priority: EventPriority, func: F)
+    where
+        E: Event,
+        F: Fn(&mut E) + Send + Sync + 'static,
+    {
+        let handlers = self
+            .handlers
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Vec::<Handler<E>>::new()))
+            .downcast_mut::<Vec<Handler<E>>>()
+            .unwrap();
+
+        // Insert after all handlers with an equal or lower priority.
+        let index = handlers
+            .iter()
+            .position(|handler| handler.priority > priority)
+            .unwrap_or_else(|| handlers.len());
+        handlers.insert(
+            index,
+            Handler {
+                priority,
+                func: Box::new(func),
+            },
+        );
+    }
+
+    /// Emits an event, running all handlers for its type.
+    ///
+    /// Returns `true` if the event was not cancelled,
+    /// i.e. if the emitter should go ahead and apply it.
+    pub fn emit<E: Event>(&self, event: &mut E) -> bool {
+        if let Some(handlers) = self.handlers.get(&TypeId::of::<E>()) {
+            let handlers = handlers.downcast_ref::<Vec<Handler<E>>>().unwrap();
+
+            for handler in handlers {
+                (handler.func)(event);
+            }
+        }
+
+        !event.is_cancelled()
+    }
+
+    /// Returns the number of handlers registered
+    /// for events of type `E`.
+    pub fn handler_count<E: Event>(&self) -> usize {
+        self.handlers
+            .get(&TypeId::of::<E>())
+            .and_then(|handlers| handlers.downcast_ref::<Vec<Handler<E>>>())
+            .map(Vec::len)
+            .unwrap_or(0)
+    }
+}
+
+/// Emitted when a player has finished loading
+/// but before they join the game. If cancelled,
+/// the player is disconnected with `kick_message`.
+///
+/// The event starts out cancelled if the whitelist
+/// is enabled and the player is not whitelisted.
+#[derive(Debug, Clone)]
+pub struct PlayerLoginEvent {
+    pub player: Entity,
+    pub username: String,
+    pub kick_message: Message,
+    pub cancelled: bool,
+}
+
+/// Emitted when a player sends a chat message
+/// (not a command). The message may be modified
+/// by handlers. If cancelled, the message is not broadcasted.
+#[derive(Debug, Clone)]
+pub struct ChatEvent {
+    pub player: Entity,
+    pub message: String,
+    pub cancelled: bool,
+}
+
+/// Emitted when a player breaks a block, before
+/// the block is removed.
+#[derive(Debug, Clone)]
+pub struct BlockBreakEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+    pub block: Block,
+    pub cancelled: bool,
+}
+
+/// Emitted when a player places a block, before
+/// the block is set. Handlers may change the placed block.
+#[derive(Debug, Clone)]
+pub struct BlockPlaceEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+    pub block: Block,
+    pub cancelled: bool,
+}
+
+/// Emitted when an entity is about to take damage.
+/// Handlers may change the amount of damage.
+#[derive(Debug, Clone)]
+pub struct EntityDamageEvent {
+    pub entity: Entity,
+    /// The entity which caused the damage, if any.
+    pub damager: Option<Entity>,
+    /// The damage, in half-hearts.
+    pub damage: f32,
+    pub cancelled: bool,
+}
+
+impl_event!(
+    PlayerLoginEvent,
+    ChatEvent,
+    BlockBreakEvent,
+    BlockPlaceEvent,
+    EntityDamageEvent,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, World, WorldExt};
+    use std::sync::{Arc, Mutex};
+
+    fn chat_event(message: &str) -> ChatEvent {
+        let mut world = World::new();
+        ChatEvent {
+            player: world.create_entity().build(),
+            message: message.to_string(),
+            cancelled: false,
+        }
+    }
+
+    #[test]
+    fn test_emit_without_handlers() {
+        let bus = EventBus::default();
+        let mut event = chat_event("test");
+
+        assert!(bus.emit(&mut event));
+        assert_eq!(bus.handler_count::<ChatEvent>(), 0);
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let mut bus = EventBus::default();
+        let order = Arc::new(Mutex::new(vec![]));
+
+        for (priority, name) in &[
+            (EventPriority::Monitor, "monitor"),
+            (EventPriority::Low, "low"),
+            (EventPriority::Normal, "normal1"),
+            (EventPriority::Lowest, "lowest"),
+            (EventPriority::Normal, "normal2"),
+        ] {
+            let order = Arc::clone(&order);
+            let name = *name;
+            bus.register(*priority, move |_: &mut ChatEvent| {
+                order.lock().unwrap().push(name)
+            });
+        }
+
+        bus.emit(&mut chat_event("test"));
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["lowest", "low", "normal1", "normal2", "monitor"]
+        );
+        assert_eq!(bus.handler_count::<ChatEvent>(), 5);
+        assert_eq!(bus.handler_count::<BlockBreakEvent>(), 0);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let mut bus = EventBus::default();
+
+        bus.register(EventPriority::Low, |event: &mut ChatEvent| {
+            if event.message.contains("bad") {
+                event.set_cancelled(true);
+            }
+        });
+
+        assert!(bus.emit(&mut chat_event("good")));
+        assert!(!bus.emit(&mut chat_event("bad")));
+
+        // A higher priority handler may uncancel the event.
+        bus.register(EventPriority::High, |event: &mut ChatEvent| {
+            event.set_cancelled(false);
+        });
+        assert!(bus.emit(&mut chat_event("bad")));
+    }
+
+    #[test]
+    fn test_modification() {
+        let mut bus = EventBus::default();
+
+        bus.register(EventPriority::Normal, |event: &mut ChatEvent| {
+            event.message = event.message.to_uppercase();
+        });
+
+        let mut event = chat_event("hello");
+        assert!(bus.emit(&mut event));
+        assert_eq!(event.message, "HELLO");
+    }
+}
@@ -0,0 +1,652 @@
+//! Furnaces, which smelt items using fuel.
+//!
+//! A furnace is a container with an input, a fuel and an output
+//! slot; see the `container` module. Whenever its input can be
+//! smelted, the furnace burns fuel and, while it burns, cooks
+//! the input according to its smelting recipe. The furnace block
+//! is lit while it burns, and the progress is kept in the block
+//! entity and shown in the furnace window using window properties.
+//!
+//! The recipes used by a furnace are counted in its block entity.
+//! Their experience is given to the next player taking items
+//! out of the output slot.
+
+use crate::blocks::{self, BlockUpdateCause, BlockUpdateEvent};
+use crate::container::{
+    send_slots_to_viewers, ContainerChangeEvent, ContainerEntity, ContainerKind,
+    OpenContainerComponent, FURNACE_SIZE, WINDOW_ID,
+};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::PlayerStatsComponent;
+use crate::recipe::RecipeRegistry;
+use crate::systems::{CONTAINER_CLICK, FURNACE_CHANGE, FURNACE_TICK};
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::{Block, FurnaceData};
+use feather_core::inventory::max_size;
+use feather_core::nbt;
+use feather_core::network::packet::implementation::{SetExperience, WindowProperty};
+use feather_core::recipe::{CookingMethod, RecipeKind};
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Item, ItemStack};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Join, Read, ReadStorage, System, Write, WriteStorage};
+use std::collections::HashMap;
+
+/// The ID of the block entity of furnaces.
+const BLOCK_ENTITY_ID: &str = "minecraft:furnace";
+
+/// The slot holding the item to smelt.
+pub const FURNACE_INPUT: usize = 0;
+/// The slot holding the fuel.
+pub const FURNACE_FUEL: usize = 1;
+/// The slot holding the smelted items.
+pub const FURNACE_OUTPUT: usize = 2;
+
+/// The window properties of furnaces.
+const PROPERTY_BURN_TIME: i16 = 0;
+const PROPERTY_BURN_TIME_TOTAL: i16 = 1;
+const PROPERTY_COOK_TIME: i16 = 2;
+const PROPERTY_COOK_TIME_TOTAL: i16 = 3;
+
+/// The block entity of a furnace, as saved in chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FurnaceEntity {
+    /// The slots and custom name of the furnace.
+    #[serde(flatten)]
+    pub container: ContainerEntity,
+    /// The number of ticks the furnace keeps burning for.
+    #[serde(rename = "BurnTime", default)]
+    pub burn_time: i16,
+    /// The number of ticks the last fuel burns for in total.
+    /// Vanilla doesn't save this, but guesses it from the fuel slot.
+    #[serde(rename = "BurnTimeTotal", default)]
+    pub burn_time_total: i16,
+    /// The number of ticks the input has been cooked for.
+    #[serde(rename = "CookTime", default)]
+    pub cook_time: i16,
+    /// The number of ticks the input needs to be cooked for.
+    #[serde(rename = "CookTimeTotal", default)]
+    pub cook_time_total: i16,
+    /// The number of times each recipe was used since
+    /// the experience was last given to a player.
+    #[serde(
+        rename = "RecipesUsed",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub recipes_used: HashMap<String, i32>,
+}
+
+impl FurnaceEntity {
+    pub fn new(pos: BlockPosition) -> Self {
+        Self {
+            container: ContainerEntity::new(ContainerKind::Furnace, pos),
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether the furnace is burning.
+    pub fn is_burning(&self) -> bool {
+        self.burn_time > 0
+    }
+
+    /// Returns the experience gained from the recipes used by the
+    /// furnace, which are then forgotten. Fractions of a point are
+    /// rounded up with a chance equal to the fraction.
+    pub fn take_experience<R: Rng + ?Sized>(
+        &mut self,
+        recipes: &RecipeRegistry,
+        rng: &mut R,
+    ) -> i32 {
+        let experience: f32 = self
+            .recipes_used
+            .drain()
+            .filter_map(
+                |(id, count)| match recipes.get(&id).map(|recipe| &recipe.kind) {
+                    Some(RecipeKind::Cooking { experience, .. }) => Some(count as f32 * experience),
+                    _ => None,
+                },
+            )
+            .sum();
+
+        let points = experience.floor();
+        if rng.gen::<f32>() < experience - points {
+            points as i32 + 1
+        } else {
+            points as i32
+        }
+    }
+}
+
+/// Returns the block entity of the furnace at the given position.
+pub fn load_furnace(chunk_map: &ChunkMap, pos: BlockPosition) -> FurnaceEntity {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value(data.clone()).ok())
+        .unwrap_or_else(|| FurnaceEntity::new(pos))
+}
+
+/// Stores the block entity of a furnace.
+pub fn store_furnace(chunk_map: &mut ChunkMap, pos: BlockPosition, furnace: &FurnaceEntity) {
+    match nbt::to_value(furnace) {
+        Ok(data) => {
+            chunk_map.set_block_entity_at(pos, data).ok();
+        }
+        Err(e) => warn!("Failed to store furnace at {:?}: {}", pos, e),
+    }
+}
+
+fn is_furnace_entity(data: &nbt::Value) -> bool {
+    match data {
+        nbt::Value::Compound(map) => {
+            map.get("id") == Some(&nbt::Value::String(BLOCK_ENTITY_ID.to_string()))
+        }
+        _ => false,
+    }
+}
+
+/// The kinds of wood, as used in the names of wooden items.
+const WOODS: [&str; 6] = [
+    "oak_",
+    "spruce_",
+    "birch_",
+    "jungle_",
+    "acacia_",
+    "dark_oak_",
+];
+
+/// Returns the number of ticks the given item burns
+/// for in a furnace, or `None` if it isn't a fuel.
+pub fn burn_time(item: Item) -> Option<i16> {
+    let time = match item {
+        Item::LavaBucket => 20000,
+        Item::CoalBlock => 16000,
+        Item::DriedKelpBlock => 4001,
+        Item::BlazeRod => 2400,
+        Item::Coal | Item::Charcoal => 1600,
+        Item::NoteBlock
+        | Item::Bookshelf
+        | Item::Jukebox
+        | Item::Chest
+        | Item::TrappedChest
+        | Item::CraftingTable
+        | Item::DaylightDetector
+        | Item::Bow
+        | Item::FishingRod
+        | Item::Ladder => 300,
+        Item::Sign => 200,
+        Item::Stick | Item::Bowl => 100,
+        _ => {
+            let name = item.identifier().trim_start_matches("minecraft:");
+            let name = name.trim_start_matches("stripped_");
+            let wooden = WOODS.iter().any(|wood| name.starts_with(wood));
+
+            if name.ends_with("_wool") {
+                100
+            } else if name.ends_with("_carpet") {
+                67
+            } else if name.ends_with("_banner") {
+                300
+            } else if name.starts_with("wooden_") {
+                200
+            } else if !wooden {
+                return None;
+            } else if name.ends_with("_log")
+                || name.ends_with("_wood")
+                || name.ends_with("_planks")
+                || name.ends_with("_stairs")
+                || name.ends_with("_fence")
+                || name.ends_with("_fence_gate")
+                || name.ends_with("_trapdoor")
+                || name.ends_with("_pressure_plate")
+            {
+                300
+            } else if name.ends_with("_boat") {
+                1200
+            } else if name.ends_with("_door") {
+                200
+            } else if name.ends_with("_slab") {
+                150
+            } else if name.ends_with("_button") || name.ends_with("_sapling") {
+                100
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(time)
+}
+
+/// Returns whether the given item can be burned in a furnace.
+pub fn is_fuel(item: Item) -> bool {
+    burn_time(item).is_some()
+}
+
+/// Returns whether the result of a recipe can be
+/// added to the given stack in the output slot.
+fn fits(output: &Option<ItemStack>, result: &ItemStack) -> bool {
+    match output {
+        Some(output) => {
+            output.stacks_with(result)
+                && u32::from(output.amount) + u32::from(result.amount)
+                    <= u32::from(max_size(output.ty))
+        }
+        None => true,
+    }
+}
+
+/// Advances a furnace by one tick, changing the given
+/// slots of the furnace as it burns fuel and smelts items.
+pub fn tick(
+    furnace: &mut FurnaceEntity,
+    slots: &mut [Option<ItemStack>],
+    recipes: &RecipeRegistry,
+) {
+    if furnace.is_burning() {
+        furnace.burn_time -= 1;
+    }
+
+    let recipe = slots[FURNACE_INPUT]
+        .as_ref()
+        .and_then(|input| recipes.find_cooking(CookingMethod::Smelting, input.ty));
+    let smelting = recipe.and_then(|recipe| match &recipe.kind {
+        RecipeKind::Cooking {
+            result,
+            cooking_time,
+            ..
+        } if fits(&slots[FURNACE_OUTPUT], result) => Some((recipe, result, *cooking_time)),
+        _ => None,
+    });
+
+    let can_light = slots[FURNACE_FUEL].is_some() && slots[FURNACE_INPUT].is_some();
+    if !furnace.is_burning() && !can_light {
+        // The progress is lost slowly once the fire is out
+        furnace.cook_time = (furnace.cook_time - 2).max(0);
+        return;
+    }
+
+    let (recipe, result, cooking_time) = match smelting {
+        Some(smelting) => smelting,
+        None => {
+            furnace.cook_time = 0;
+            return;
+        }
+    };
+
+    if !furnace.is_burning() {
+        let fuel = slots[FURNACE_FUEL].take().unwrap();
+        let time = match burn_time(fuel.ty) {
+            Some(time) => time,
+            None => {
+                slots[FURNACE_FUEL] = Some(fuel);
+                furnace.cook_time = 0;
+                return;
+            }
+        };
+        furnace.burn_time = time;
+        furnace.burn_time_total = time;
+        slots[FURNACE_FUEL] = if fuel.ty == Item::LavaBucket {
+            Some(ItemStack::new(Item::Bucket, 1))
+        } else {
+            Some(fuel.with_amount(fuel.amount - 1)).filter(|fuel| fuel.amount > 0)
+        };
+    }
+
+    furnace.cook_time_total = cooking_time as i16;
+    furnace.cook_time += 1;
+    if furnace.cook_time < furnace.cook_time_total {
+        return;
+    }
+
+    furnace.cook_time = 0;
+    let input = slots[FURNACE_INPUT].take().unwrap();
+    slots[FURNACE_INPUT] = Some(input.with_amount(input.amount - 1)).filter(|s| s.amount > 0);
+    slots[FURNACE_OUTPUT] = Some(match slots[FURNACE_OUTPUT].take() {
+        Some(output) => output.with_amount(output.amount + result.amount),
+        None => result.clone(),
+    });
+    *furnace.recipes_used.entry(recipe.id.clone()).or_insert(0) += 1;
+}
+
+/// Sends the progress of a furnace to a player viewing it.
+pub fn send_window_properties(network: &NetworkComponent, furnace: &FurnaceEntity) {
+    for (property, value) in &[
+        (PROPERTY_BURN_TIME, furnace.burn_time),
+        (PROPERTY_BURN_TIME_TOTAL, furnace.burn_time_total),
+        (PROPERTY_COOK_TIME, furnace.cook_time),
+        (PROPERTY_COOK_TIME_TOTAL, furnace.cook_time_total),
+    ] {
+        send_packet_to_player(network, WindowProperty::new(WINDOW_ID, *property, *value));
+    }
+}
+
+/// System which advances the furnaces in loaded chunks
+/// every tick, lighting them while they burn.
+pub struct FurnaceTickSystem;
+
+impl<'a> System<'a> for FurnaceTickSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, RecipeRegistry>,
+        ReadStorage<'a, OpenContainerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut chunk_map, mut events, recipes, open_containers, networks) = data;
+
+        let positions_of_furnaces: Vec<BlockPosition> = chunk_map
+            .chunks()
+            .values()
+            .flat_map(|chunk| {
+                let chunk_pos = chunk.position();
+                chunk
+                    .block_entities()
+                    .filter(|(_, data)| is_furnace_entity(data))
+                    .map(move |((x, y, z), _)| {
+                        BlockPosition::new(
+                            chunk_pos.x * 16 + x as i32,
+                            y as i32,
+                            chunk_pos.z * 16 + z as i32,
+                        )
+                    })
+            })
+            .collect();
+
+        for pos in positions_of_furnaces {
+            let data = match chunk_map.block_at(pos) {
+                Some(Block::Furnace(data)) => data,
+                _ => continue,
+            };
+
+            let mut furnace = load_furnace(&chunk_map, pos);
+            let old_furnace = furnace.clone();
+            let old_slots = furnace.container.slots(FURNACE_SIZE);
+            let mut slots = old_slots.clone();
+            tick(&mut furnace, &mut slots, &recipes);
+
+            let changed_slots: Vec<usize> = (0..FURNACE_SIZE)
+                .filter(|slot| old_slots[*slot] != slots[*slot])
+                .collect();
+            if changed_slots.is_empty() && furnace == old_furnace {
+                continue;
+            }
+            furnace.container.set_slots(&slots);
+            store_furnace(&mut chunk_map, pos, &furnace);
+
+            if furnace.is_burning() != data.lit {
+                let block = Block::Furnace(FurnaceData {
+                    lit: furnace.is_burning(),
+                    ..data
+                });
+                blocks::set_block(
+                    &mut chunk_map,
+                    &mut events,
+                    pos,
+                    block,
+                    BlockUpdateCause::Furnace,
+                );
+            }
+
+            send_slots_to_viewers(&open_containers, &networks, pos, &slots, &changed_slots);
+            for (open, network) in (&open_containers, &networks).join() {
+                if open.pos == pos && open.kind == ContainerKind::Furnace {
+                    send_window_properties(network, &furnace);
+                }
+            }
+        }
+    }
+}
+
+/// System which restarts the cooking of furnaces whose input
+/// was replaced, and gives players the experience of the
+/// recipes used by furnaces when they take items out of them.
+///
+/// This system listens to `ContainerChangeEvent`s.
+#[derive(Default)]
+pub struct FurnaceChangeSystem {
+    reader: Option<ReaderId<ContainerChangeEvent>>,
+}
+
+impl<'a> System<'a> for FurnaceChangeSystem {
+    type SystemData = (
+        Read<'a, EventChannel<ContainerChangeEvent>>,
+        Write<'a, ChunkMap>,
+        Read<'a, RecipeRegistry>,
+        WriteStorage<'a, PlayerStatsComponent>,
+        ReadStorage<'a, NetworkComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, mut chunk_map, recipes, mut stats, networks) = data;
+
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            match chunk_map.block_at(event.pos) {
+                Some(Block::Furnace(_)) => (),
+                _ => continue,
+            }
+            let mut furnace = load_furnace(&chunk_map, event.pos);
+            let old_furnace = furnace.clone();
+
+            let mut experience = 0;
+            for (slot, old, new) in &event.slots {
+                let old_amount = old.as_ref().map_or(0, |stack| stack.amount);
+                let new_amount = new.as_ref().map_or(0, |stack| stack.amount);
+                match *slot {
+                    FURNACE_INPUT if old.as_ref().map(|s| s.ty) != new.as_ref().map(|s| s.ty) => {
+                        furnace.cook_time = 0;
+                    }
+                    FURNACE_OUTPUT if new_amount < old_amount => {
+                        experience += furnace.take_experience(&recipes, &mut rng);
+                    }
+                    _ => (),
+                }
+            }
+
+            if furnace != old_furnace {
+                store_furnace(&mut chunk_map, event.pos, &furnace);
+            }
+            if experience == 0 {
+                continue;
+            }
+            let player_stats = continue_if_none!(stats.get_mut(event.player));
+            player_stats.add_experience(experience);
+            if let Some(network) = networks.get(event.player) {
+                send_packet_to_player(
+                    network,
+                    SetExperience::new(
+                        player_stats.xp_progress,
+                        player_stats.xp_level,
+                        player_stats.xp_total,
+                    ),
+                );
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(FurnaceTickSystem, FURNACE_TICK, &[CONTAINER_CLICK]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(FurnaceChangeSystem::default(), FURNACE_CHANGE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::store_container;
+    use crate::testframework as t;
+    use feather_blocks::FurnaceFacing;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::implementation::SetSlot;
+    use feather_core::PacketType;
+    use rand::rngs::mock::StepRng;
+    use specs::WorldExt;
+
+    fn furnace(lit: bool) -> Block {
+        Block::Furnace(FurnaceData {
+            lit,
+            facing: FurnaceFacing::North,
+        })
+    }
+
+    fn slots(input: Option<ItemStack>, fuel: Option<ItemStack>) -> Vec<Option<ItemStack>> {
+        vec![input, fuel, None]
+    }
+
+    #[test]
+    fn test_burn_time() {
+        assert_eq!(burn_time(Item::Coal), Some(1600));
+        assert_eq!(burn_time(Item::OakPlanks), Some(300));
+        assert_eq!(burn_time(Item::StrippedDarkOakLog), Some(300));
+        assert_eq!(burn_time(Item::BirchSlab), Some(150));
+        assert_eq!(burn_time(Item::RedWool), Some(100));
+        assert_eq!(burn_time(Item::WoodenPickaxe), Some(200));
+        assert_eq!(burn_time(Item::OakLeaves), None);
+        assert_eq!(burn_time(Item::Stone), None);
+    }
+
+    #[test]
+    fn test_smelt() {
+        let recipes = RecipeRegistry::bundled();
+        let mut furnace = FurnaceEntity::new(BlockPosition::new(0, 0, 0));
+        let mut slots = slots(
+            Some(ItemStack::new(Item::IronOre, 2)),
+            Some(ItemStack::new(Item::Coal, 1)),
+        );
+
+        tick(&mut furnace, &mut slots, &recipes);
+        assert!(furnace.is_burning());
+        assert_eq!(furnace.burn_time, 1600);
+        assert_eq!(furnace.cook_time_total, 200);
+        assert_eq!(slots[FURNACE_FUEL], None);
+
+        for _ in 1..200 {
+            tick(&mut furnace, &mut slots, &recipes);
+        }
+        assert_eq!(slots[FURNACE_INPUT], Some(ItemStack::new(Item::IronOre, 1)));
+        assert_eq!(
+            slots[FURNACE_OUTPUT],
+            Some(ItemStack::new(Item::IronIngot, 1))
+        );
+        assert_eq!(furnace.cook_time, 0);
+        assert_eq!(furnace.recipes_used.get("minecraft:iron_ingot"), Some(&1));
+
+        // 0.7 experience, rounded down by the mock generator.
+        let mut rng = StepRng::new(u64::max_value(), 0);
+        assert_eq!(furnace.take_experience(&recipes, &mut rng), 0);
+        assert!(furnace.recipes_used.is_empty());
+    }
+
+    #[test]
+    fn test_no_recipe() {
+        let recipes = RecipeRegistry::bundled();
+        let mut furnace = FurnaceEntity::new(BlockPosition::new(0, 0, 0));
+        let mut slots = slots(
+            Some(ItemStack::new(Item::Diamond, 1)),
+            Some(ItemStack::new(Item::Coal, 1)),
+        );
+
+        tick(&mut furnace, &mut slots, &recipes);
+        assert!(!furnace.is_burning());
+        assert_eq!(slots[FURNACE_FUEL], Some(ItemStack::new(Item::Coal, 1)));
+    }
+
+    #[test]
+    fn test_furnace_lit() {
+        let (mut w, mut d) = t::builder().with(FurnaceTickSystem, "").build();
+        t::populate_with_air(&mut w);
+        w.insert(RecipeRegistry::bundled());
+
+        let player = t::add_player(&mut w);
+        let pos = BlockPosition::new(0, 64, 0);
+        t::set_block(0, 64, 0, furnace(false), &w);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let mut entity = ContainerEntity::new(ContainerKind::Furnace, pos);
+            entity.set_slots(&slots(
+                Some(ItemStack::new(Item::IronOre, 1)),
+                Some(ItemStack::new(Item::Coal, 1)),
+            ));
+            store_container(&mut chunk_map, pos, &entity);
+        }
+        w.write_component::<OpenContainerComponent>()
+            .insert(
+                player.entity,
+                OpenContainerComponent {
+                    pos,
+                    kind: ContainerKind::Furnace,
+                    size: FURNACE_SIZE,
+                    cursor: None,
+                    slots: vec![],
+                },
+            )
+            .unwrap();
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(w.fetch::<ChunkMap>().block_at(pos), Some(furnace(true)));
+        let furnace = load_furnace(&w.fetch::<ChunkMap>(), pos);
+        assert_eq!(furnace.cook_time, 1);
+        assert_eq!(furnace.container.slots(FURNACE_SIZE)[FURNACE_FUEL], None);
+
+        let packet = t::assert_packet_received(&player, PacketType::SetSlot);
+        let packet = cast_packet::<SetSlot>(&*packet);
+        assert_eq!(packet.slot, FURNACE_FUEL as i16);
+        t::assert_packet_received(&player, PacketType::WindowProperty);
+    }
+
+    #[test]
+    fn test_take_output() {
+        let (mut w, mut d) = t::builder()
+            .with(FurnaceChangeSystem::default(), "")
+            .build();
+        t::populate_with_air(&mut w);
+        w.insert(RecipeRegistry::bundled());
+
+        let player = t::add_player(&mut w);
+        let pos = BlockPosition::new(0, 64, 0);
+        t::set_block(0, 64, 0, furnace(false), &w);
+        let mut furnace = FurnaceEntity::new(pos);
+        furnace
+            .recipes_used
+            .insert(String::from("minecraft:iron_ingot"), 10);
+        store_furnace(&mut w.fetch_mut::<ChunkMap>(), pos, &furnace);
+
+        t::trigger_event(
+            &w,
+            ContainerChangeEvent {
+                player: player.entity,
+                pos,
+                slots: vec![(
+                    FURNACE_OUTPUT,
+                    Some(ItemStack::new(Item::IronIngot, 10)),
+                    None,
+                )],
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(
+            w.read_component::<PlayerStatsComponent>()
+                .get(player.entity)
+                .unwrap()
+                .xp_total,
+            7
+        );
+        t::assert_packet_received(&player, PacketType::SetExperience);
+        assert!(load_furnace(&w.fetch::<ChunkMap>(), pos)
+            .recipes_used
+            .is_empty());
+    }
+}
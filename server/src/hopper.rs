@@ -0,0 +1,620 @@
+//! Hoppers, which move items between containers.
+//!
+//! Every `TRANSFER_COOLDOWN` ticks, a hopper pushes one item into
+//! the container it faces and pulls one item out of the container
+//! above it. Without a container above, it picks up the item
+//! entities resting on top of it instead. Hoppers powered by
+//! redstone are locked; see the `redstone` module.
+//!
+//! Like in vanilla, hoppers only fill the input slot of a furnace
+//! from above and its fuel slot from the sides, and only take
+//! smelted items and empty buckets out of furnaces.
+
+use crate::container::{
+    container_parts, load_slots, send_slots_to_viewers, store_slots, ContainerEntity,
+    ContainerKind, OpenContainerComponent, HOPPER_SIZE,
+};
+use crate::entity::item::{item_meta, item_stack_from_meta};
+use crate::entity::{
+    ChunkEntities, EntityDestroyEvent, ItemComponent, Metadata, PositionComponent,
+};
+use crate::furnace::{FURNACE_FUEL, FURNACE_INPUT, FURNACE_OUTPUT};
+use crate::network::NetworkComponent;
+use crate::physics::nearby_entities;
+use crate::redstone::{facing, Direction};
+use crate::systems::{FURNACE_TICK, HOPPER};
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::Block;
+use feather_core::inventory::max_size;
+use feather_core::nbt;
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Item, ItemStack};
+use shrev::EventChannel;
+use specs::{DispatcherBuilder, Entities, Read, ReadStorage, System, Write, WriteStorage};
+
+/// The ID of the block entity of hoppers.
+const BLOCK_ENTITY_ID: &str = "minecraft:hopper";
+
+/// The number of ticks a hopper waits after moving items.
+const TRANSFER_COOLDOWN: i32 = 8;
+
+/// The block entity of a hopper, as saved in chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HopperEntity {
+    /// The slots and custom name of the hopper.
+    #[serde(flatten)]
+    pub container: ContainerEntity,
+    /// The number of ticks until the hopper moves items again.
+    #[serde(rename = "TransferCooldown", default)]
+    pub transfer_cooldown: i32,
+}
+
+impl HopperEntity {
+    pub fn new(pos: BlockPosition) -> Self {
+        Self {
+            container: ContainerEntity::new(ContainerKind::Hopper, pos),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the block entity of the hopper at the given position.
+pub fn load_hopper(chunk_map: &ChunkMap, pos: BlockPosition) -> HopperEntity {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value(data.clone()).ok())
+        .unwrap_or_else(|| HopperEntity::new(pos))
+}
+
+/// Stores the block entity of a hopper.
+pub fn store_hopper(chunk_map: &mut ChunkMap, pos: BlockPosition, hopper: &HopperEntity) {
+    match nbt::to_value(hopper) {
+        Ok(data) => {
+            chunk_map.set_block_entity_at(pos, data).ok();
+        }
+        Err(e) => warn!("Failed to store hopper at {:?}: {}", pos, e),
+    }
+}
+
+fn is_hopper_entity(data: &nbt::Value) -> bool {
+    match data {
+        nbt::Value::Compound(map) => {
+            map.get("id") == Some(&nbt::Value::String(BLOCK_ENTITY_ID.to_string()))
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether a hopper facing in the given direction
+/// may put an item into a slot of the container it faces.
+fn can_push(kind: ContainerKind, slot: usize, item: Item, facing: Direction) -> bool {
+    match (kind, facing) {
+        (ContainerKind::Furnace, Direction::Down) => slot == FURNACE_INPUT,
+        (ContainerKind::Furnace, _) => slot == FURNACE_FUEL && kind.accepts(slot, item),
+        _ => kind.accepts(slot, item),
+    }
+}
+
+/// Returns whether a hopper may take the item in
+/// a slot of the container above it.
+fn can_pull(kind: ContainerKind, slot: usize, item: Item) -> bool {
+    match kind {
+        ContainerKind::Furnace => {
+            slot == FURNACE_OUTPUT || (slot == FURNACE_FUEL && item == Item::Bucket)
+        }
+        _ => true,
+    }
+}
+
+/// Adds as many items of the given stack as possible to the
+/// first slots which accept them, returning the number of
+/// items which were added.
+fn insert(
+    slots: &mut [Option<ItemStack>],
+    stack: &ItemStack,
+    limit: u8,
+    accepts: impl Fn(usize) -> bool,
+) -> u8 {
+    let max = max_size(stack.ty).min(limit);
+    let mut remaining = stack.amount;
+    for index in 0..slots.len() {
+        if remaining == 0 {
+            break;
+        }
+        if !accepts(index) {
+            continue;
+        }
+        let added = match &slots[index] {
+            Some(slot) if slot.stacks_with(stack) => remaining.min(max.saturating_sub(slot.amount)),
+            Some(_) => 0,
+            None => remaining.min(max),
+        };
+        if added == 0 {
+            continue;
+        }
+        let amount = slots[index].as_ref().map_or(0, |slot| slot.amount) + added;
+        slots[index] = Some(stack.with_amount(amount));
+        remaining -= added;
+    }
+    stack.amount - remaining
+}
+
+/// Moves a single item out of the first slot of `from` allowed by
+/// `can_take` whose item can be added to `to`, returning whether
+/// an item was moved.
+fn move_one(
+    from: &mut [Option<ItemStack>],
+    to: &mut [Option<ItemStack>],
+    can_take: impl Fn(usize, Item) -> bool,
+    limit: u8,
+    accepts: impl Fn(usize, Item) -> bool,
+) -> bool {
+    for index in 0..from.len() {
+        let stack = match &from[index] {
+            Some(stack) if can_take(index, stack.ty) => stack.clone(),
+            _ => continue,
+        };
+        if insert(to, &stack.with_amount(1), limit, |slot| {
+            accepts(slot, stack.ty)
+        }) == 0
+        {
+            continue;
+        }
+        from[index] = Some(stack.with_amount(stack.amount - 1)).filter(|s| s.amount > 0);
+        return true;
+    }
+    false
+}
+
+/// Returns the kind and the position of the container at the
+/// given position, if it stores items. For double chests, the
+/// position is that of the right half, as used by `load_slots`.
+fn container_at(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+) -> Option<(ContainerKind, BlockPosition)> {
+    let kind = ContainerKind::of(chunk_map.block_at(pos)?)?;
+    if !kind.stores_items() {
+        return None;
+    }
+    Some((kind, container_parts(chunk_map, pos)[0]))
+}
+
+/// The containers whose slots were changed by hoppers.
+#[derive(Default)]
+struct Changes(Vec<(BlockPosition, Vec<Option<ItemStack>>, Vec<usize>)>);
+
+impl Changes {
+    /// Stores the slots of a container, remembering which of them changed.
+    fn store(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        kind: ContainerKind,
+        pos: BlockPosition,
+        old_slots: &[Option<ItemStack>],
+        slots: Vec<Option<ItemStack>>,
+    ) {
+        let changed: Vec<usize> = (0..slots.len())
+            .filter(|slot| old_slots[*slot] != slots[*slot])
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+        store_slots(chunk_map, kind, pos, &slots);
+        self.0.push((pos, slots, changed));
+    }
+}
+
+/// Pushes an item from a hopper into the container
+/// it faces, returning whether an item was moved.
+fn push(
+    chunk_map: &mut ChunkMap,
+    slots: &mut [Option<ItemStack>],
+    facing: Direction,
+    hopper_pos: BlockPosition,
+    changes: &mut Changes,
+) -> bool {
+    let (kind, pos) = match container_at(chunk_map, facing.of(hopper_pos)) {
+        Some(container) => container,
+        None => return false,
+    };
+
+    let old_slots = load_slots(chunk_map, kind, pos);
+    let mut target = old_slots.clone();
+    let moved = move_one(
+        slots,
+        &mut target,
+        |_, _| true,
+        kind.slot_limit(),
+        |slot, item| can_push(kind, slot % kind.size(), item, facing),
+    );
+    changes.store(chunk_map, kind, pos, &old_slots, target);
+    moved
+}
+
+/// Pulls an item into a hopper from the container above it,
+/// returning whether an item was moved, or `None` if there
+/// is no container above the hopper.
+fn pull(
+    chunk_map: &mut ChunkMap,
+    slots: &mut [Option<ItemStack>],
+    hopper_pos: BlockPosition,
+    changes: &mut Changes,
+) -> Option<bool> {
+    let (kind, pos) = container_at(chunk_map, Direction::Up.of(hopper_pos))?;
+
+    let old_slots = load_slots(chunk_map, kind, pos);
+    let mut source = old_slots.clone();
+    let moved = move_one(
+        &mut source,
+        slots,
+        |slot, item| can_pull(kind, slot % kind.size(), item),
+        ContainerKind::Hopper.slot_limit(),
+        |_, _| true,
+    );
+    changes.store(chunk_map, kind, pos, &old_slots, source);
+    Some(moved)
+}
+
+/// System which moves items through the hoppers in loaded chunks.
+pub struct HopperSystem;
+
+impl<'a> System<'a> for HopperSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        ReadStorage<'a, OpenContainerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, PositionComponent>,
+        WriteStorage<'a, ItemComponent>,
+        WriteStorage<'a, Metadata>,
+        Read<'a, ChunkEntities>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut chunk_map,
+            open_containers,
+            networks,
+            positions,
+            mut items,
+            mut metadatas,
+            chunk_entities,
+            mut destroy_events,
+            entities,
+        ) = data;
+
+        let positions_of_hoppers: Vec<BlockPosition> = chunk_map
+            .chunks()
+            .values()
+            .flat_map(|chunk| {
+                let chunk_pos = chunk.position();
+                chunk
+                    .block_entities()
+                    .filter(|(_, data)| is_hopper_entity(data))
+                    .map(move |((x, y, z), _)| {
+                        BlockPosition::new(
+                            chunk_pos.x * 16 + x as i32,
+                            y as i32,
+                            chunk_pos.z * 16 + z as i32,
+                        )
+                    })
+            })
+            .collect();
+
+        let mut changes = Changes::default();
+        // Item entities picked up this tick, which
+        // are only deleted at the end of the tick.
+        let mut collected = vec![];
+
+        for pos in positions_of_hoppers {
+            let data = match chunk_map.block_at(pos) {
+                Some(Block::Hopper(data)) => data,
+                _ => continue,
+            };
+            let facing = continue_if_none!(facing(Block::Hopper(data)));
+
+            let mut hopper = load_hopper(&chunk_map, pos);
+            let old_hopper = hopper.clone();
+            let old_slots = hopper.container.slots(HOPPER_SIZE);
+            let mut slots = old_slots.clone();
+
+            hopper.transfer_cooldown -= 1;
+            if hopper.transfer_cooldown <= 0 {
+                hopper.transfer_cooldown = 0;
+                if data.enabled {
+                    let mut moved = false;
+                    if slots.iter().any(Option::is_some) {
+                        moved |= push(&mut chunk_map, &mut slots, facing, pos, &mut changes);
+                    }
+                    moved |= match pull(&mut chunk_map, &mut slots, pos, &mut changes) {
+                        Some(moved) => moved,
+                        None => {
+                            let center = pos.world_pos() + position!(0.5, 1.34375, 0.5);
+                            let nearby = nearby_entities(
+                                &chunk_entities,
+                                &positions,
+                                center,
+                                glm::vec3(0.5, 0.65625, 0.5),
+                            );
+
+                            let mut picked_up = false;
+                            for entity in nearby {
+                                if collected.contains(&entity) || items.get(entity).is_none() {
+                                    continue;
+                                }
+                                let mut stack =
+                                    item_stack_from_meta(continue_if_none!(metadatas.get(entity)));
+                                let added = insert(
+                                    &mut slots,
+                                    &stack,
+                                    ContainerKind::Hopper.slot_limit(),
+                                    |_| true,
+                                );
+                                if added == 0 {
+                                    continue;
+                                }
+                                picked_up = true;
+
+                                stack.amount -= added;
+                                if stack.amount == 0 {
+                                    collected.push(entity);
+                                } else {
+                                    if let Some(item) = items.get_mut(entity) {
+                                        item.stack = stack.clone();
+                                    }
+                                    metadatas.insert(entity, item_meta(stack)).unwrap();
+                                }
+                            }
+                            picked_up
+                        }
+                    };
+                    if moved {
+                        hopper.transfer_cooldown = TRANSFER_COOLDOWN;
+                    }
+                }
+            }
+
+            let changed_slots: Vec<usize> = (0..HOPPER_SIZE)
+                .filter(|slot| old_slots[*slot] != slots[*slot])
+                .collect();
+            if changed_slots.is_empty() && hopper == old_hopper {
+                continue;
+            }
+            hopper.container.set_slots(&slots);
+            store_hopper(&mut chunk_map, pos, &hopper);
+            if !changed_slots.is_empty() {
+                changes.0.push((pos, slots, changed_slots));
+            }
+        }
+
+        for entity in collected {
+            entities.delete(entity).unwrap();
+            destroy_events.single_write(EntityDestroyEvent { entity });
+        }
+
+        for (pos, slots, changed_slots) in changes.0 {
+            send_slots_to_viewers(&open_containers, &networks, pos, &slots, &changed_slots);
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(HopperSystem, HOPPER, &[FURNACE_TICK]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{load_container, store_container, CHEST_SIZE};
+    use crate::entity::{item, ChunkEntityUpdateSystem};
+    use crate::furnace::load_furnace;
+    use crate::testframework as t;
+    use feather_blocks::{
+        ChestData, ChestFacing, ChestType, FurnaceData, FurnaceFacing, HopperData, HopperFacing,
+    };
+    use specs::{Builder, System, World, WorldExt};
+
+    fn hopper(facing: HopperFacing) -> Block {
+        Block::Hopper(HopperData {
+            enabled: true,
+            facing,
+        })
+    }
+
+    fn chest() -> Block {
+        Block::Chest(ChestData {
+            facing: ChestFacing::North,
+            ty: ChestType::Single,
+            waterlogged: false,
+        })
+    }
+
+    fn set_slots(w: &World, kind: ContainerKind, pos: BlockPosition, slots: &[Option<ItemStack>]) {
+        let mut chunk_map = w.fetch_mut::<ChunkMap>();
+        let mut entity = load_container(&chunk_map, kind, pos);
+        entity.set_slots(slots);
+        store_container(&mut chunk_map, pos, &entity);
+    }
+
+    fn slots(w: &World, kind: ContainerKind, pos: BlockPosition) -> Vec<Option<ItemStack>> {
+        load_slots(&w.fetch::<ChunkMap>(), kind, pos)
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut slots = vec![Some(ItemStack::new(Item::Stone, 63)), None];
+        let added = insert(&mut slots, &ItemStack::new(Item::Stone, 3), 64, |_| true);
+        assert_eq!(added, 3);
+        assert_eq!(slots[0], Some(ItemStack::new(Item::Stone, 64)));
+        assert_eq!(slots[1], Some(ItemStack::new(Item::Stone, 2)));
+
+        let added = insert(&mut slots, &ItemStack::new(Item::Dirt, 1), 64, |_| true);
+        assert_eq!(added, 0);
+    }
+
+    #[test]
+    fn test_chest_to_furnace() {
+        let (mut w, mut d) = t::builder().with(HopperSystem, "").build();
+        t::populate_with_air(&mut w);
+
+        let hopper_pos = BlockPosition::new(0, 64, 0);
+        let chest_pos = BlockPosition::new(0, 65, 0);
+        let furnace_pos = BlockPosition::new(0, 63, 0);
+        t::set_block(0, 64, 0, hopper(HopperFacing::Down), &w);
+        t::set_block(0, 65, 0, chest(), &w);
+        t::set_block(
+            0,
+            63,
+            0,
+            Block::Furnace(FurnaceData {
+                lit: false,
+                facing: FurnaceFacing::North,
+            }),
+            &w,
+        );
+        store_hopper(
+            &mut w.fetch_mut::<ChunkMap>(),
+            hopper_pos,
+            &HopperEntity::new(hopper_pos),
+        );
+
+        let mut chest_slots = vec![None; CHEST_SIZE];
+        chest_slots[3] = Some(ItemStack::new(Item::IronOre, 2));
+        set_slots(&w, ContainerKind::Chest, chest_pos, &chest_slots);
+
+        // The first item is pulled in, then pushed on
+        // once the cooldown is over, while the second
+        // one is pulled in.
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            slots(&w, ContainerKind::Chest, chest_pos)[3],
+            Some(ItemStack::new(Item::IronOre, 1))
+        );
+        assert_eq!(
+            load_hopper(&w.fetch::<ChunkMap>(), hopper_pos).transfer_cooldown,
+            8
+        );
+
+        t::run_ticks(&mut w, &mut d, 7);
+        assert_eq!(
+            slots(&w, ContainerKind::Chest, chest_pos)[3]
+                .as_ref()
+                .unwrap()
+                .amount,
+            1
+        );
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(slots(&w, ContainerKind::Chest, chest_pos)[3], None);
+        assert_eq!(
+            slots(&w, ContainerKind::Hopper, hopper_pos)[0],
+            Some(ItemStack::new(Item::IronOre, 1))
+        );
+        let furnace = load_furnace(&w.fetch::<ChunkMap>(), furnace_pos);
+        assert_eq!(
+            furnace.container.slots(3)[FURNACE_INPUT],
+            Some(ItemStack::new(Item::IronOre, 1))
+        );
+    }
+
+    #[test]
+    fn test_fuel_from_side() {
+        let (mut w, mut d) = t::builder().with(HopperSystem, "").build();
+        t::populate_with_air(&mut w);
+
+        let hopper_pos = BlockPosition::new(0, 64, 0);
+        let furnace_pos = BlockPosition::new(1, 64, 0);
+        t::set_block(0, 64, 0, hopper(HopperFacing::East), &w);
+        t::set_block(
+            1,
+            64,
+            0,
+            Block::Furnace(FurnaceData {
+                lit: false,
+                facing: FurnaceFacing::North,
+            }),
+            &w,
+        );
+        set_slots(
+            &w,
+            ContainerKind::Hopper,
+            hopper_pos,
+            &[
+                Some(ItemStack::new(Item::IronOre, 1)),
+                Some(ItemStack::new(Item::Coal, 1)),
+            ],
+        );
+
+        t::run_ticks(&mut w, &mut d, 1);
+        let furnace = slots(&w, ContainerKind::Furnace, furnace_pos);
+        assert_eq!(furnace[FURNACE_INPUT], None);
+        assert_eq!(furnace[FURNACE_FUEL], Some(ItemStack::new(Item::Coal, 1)));
+    }
+
+    #[test]
+    fn test_locked() {
+        let (mut w, mut d) = t::builder().with(HopperSystem, "").build();
+        t::populate_with_air(&mut w);
+
+        let hopper_pos = BlockPosition::new(0, 64, 0);
+        t::set_block(
+            0,
+            64,
+            0,
+            Block::Hopper(HopperData {
+                enabled: false,
+                facing: HopperFacing::Down,
+            }),
+            &w,
+        );
+        t::set_block(0, 63, 0, chest(), &w);
+        set_slots(
+            &w,
+            ContainerKind::Hopper,
+            hopper_pos,
+            &[Some(ItemStack::new(Item::Stone, 1))],
+        );
+
+        t::run_ticks(&mut w, &mut d, 10);
+        assert_eq!(
+            slots(&w, ContainerKind::Hopper, hopper_pos)[0],
+            Some(ItemStack::new(Item::Stone, 1))
+        );
+    }
+
+    #[test]
+    fn test_pick_up_items() {
+        let (mut w, mut d) = t::builder().with(HopperSystem, "").build();
+        t::populate_with_air(&mut w);
+
+        let hopper_pos = BlockPosition::new(0, 64, 0);
+        t::set_block(0, 64, 0, hopper(HopperFacing::Down), &w);
+        store_hopper(
+            &mut w.fetch_mut::<ChunkMap>(),
+            hopper_pos,
+            &HopperEntity::new(hopper_pos),
+        );
+
+        let pos = position!(0.5, 65.0, 0.5);
+        let entity = item::create(&w.fetch(), &w.fetch(), ItemStack::new(Item::Stone, 3), 0)
+            .with(PositionComponent {
+                current: pos,
+                previous: pos,
+            })
+            .build();
+
+        let mut updater = ChunkEntityUpdateSystem::default();
+        updater.setup(&mut w);
+        w.maintain();
+        // Update chunk entities so `nearby_entities` works
+        specs::RunNow::run_now(&mut updater, &w);
+
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            slots(&w, ContainerKind::Hopper, hopper_pos)[0],
+            Some(ItemStack::new(Item::Stone, 3))
+        );
+        assert!(!w.is_alive(entity));
+    }
+}
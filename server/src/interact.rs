@@ -0,0 +1,349 @@
+//! Blocks which players use by right-clicking them: doors,
+//! trapdoors, fence gates, levers and buttons.
+//!
+//! Doors, trapdoors and fence gates are opened and closed, with
+//! both halves of a door changing together. Iron doors and iron
+//! trapdoors are only opened by redstone. Levers are toggled,
+//! and buttons stay pressed until a block tick `STONE_BUTTON_TICKS`
+//! or `WOODEN_BUTTON_TICKS` after they were pressed. Redstone
+//! reacts to the changes like to any other block update.
+
+use crate::blocks::{self, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent};
+use crate::entity::PositionComponent;
+use crate::redstone::{property, with_property, Direction};
+use crate::systems::BLOCK_INTERACT;
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use feather_blocks::Block;
+use feather_core::network::packet::implementation::NamedSoundEffect;
+use feather_core::world::{BlockPosition, ChunkMap};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entity, Read, ReadStorage, System, Write};
+
+/// The number of ticks stone buttons stay pressed for.
+pub const STONE_BUTTON_TICKS: u64 = 20;
+/// The number of ticks wooden buttons stay pressed for.
+pub const WOODEN_BUTTON_TICKS: u64 = 30;
+
+/// The sound category of block sounds.
+const SOUND_CATEGORY_BLOCKS: i32 = 4;
+
+/// Event triggered when a player right-clicks
+/// a block which `is_interactive`.
+#[derive(Debug, Clone)]
+pub struct BlockInteractEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+}
+
+/// Returns whether players use the given block by right-clicking it,
+/// instead of placing a block against it.
+pub fn is_interactive(block: Block) -> bool {
+    let name = block.to_name_and_props().0;
+    if name.starts_with("minecraft:iron_") {
+        return false;
+    }
+    name.ends_with("_door")
+        || name.ends_with("_trapdoor")
+        || name.ends_with("_fence_gate")
+        || name.ends_with("_button")
+        || name == "minecraft:lever"
+}
+
+/// Plays a sound at the given block to the players who can see it,
+/// except for `neq`, if set.
+pub fn broadcast_sound(
+    util: &Util,
+    pos: BlockPosition,
+    sound: &str,
+    volume: f32,
+    pitch: f32,
+    neq: Option<Entity>,
+) {
+    // Positions are sent as fixed-point numbers
+    let packet = NamedSoundEffect::new(
+        sound.to_string(),
+        SOUND_CATEGORY_BLOCKS,
+        pos.x * 8 + 4,
+        pos.y * 8 + 4,
+        pos.z * 8 + 4,
+        volume,
+        pitch,
+    );
+    util.broadcast_chunk_update(pos.chunk_pos(), packet, neq);
+}
+
+fn is_set(block: Block, key: &str) -> bool {
+    property(block, key).map_or(false, |value| value == "true")
+}
+
+/// Returns the horizontal direction a player with the given yaw looks in.
+fn looking_direction(yaw: f32) -> Direction {
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::South,
+        Direction::West,
+        Direction::North,
+        Direction::East,
+    ];
+    let index = ((f64::from(yaw) / 90.0 + 0.5).floor() as i32).rem_euclid(4);
+    DIRECTIONS[index as usize]
+}
+
+/// System which lets players use doors, trapdoors,
+/// fence gates, levers and buttons, and which pops
+/// buttons out again.
+///
+/// This system listens to `BlockInteractEvent`s
+/// and `BlockTickEvent`s.
+#[derive(Default)]
+pub struct BlockInteractSystem {
+    interact_reader: Option<ReaderId<BlockInteractEvent>>,
+    tick_reader: Option<ReaderId<BlockTickEvent>>,
+}
+
+impl<'a> System<'a> for BlockInteractSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockInteractEvent>>,
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, BlockTicks>,
+        ReadStorage<'a, PositionComponent>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (interact_events, tick_events, mut chunk_map, mut events, mut ticks, positions, util) =
+            data;
+        let mut rng = rand::thread_rng();
+
+        for event in interact_events.read(self.interact_reader.as_mut().unwrap()) {
+            let block = continue_if_none!(chunk_map.block_at(event.pos));
+            if !is_interactive(block) {
+                continue;
+            }
+
+            let pos = event.pos;
+            let cause = BlockUpdateCause::Player(event.player);
+            let name = block.to_name_and_props().0;
+            let neq = Some(event.player);
+
+            if name == "minecraft:lever" {
+                let powered = !is_set(block, "powered");
+                let lever = with_property(block, "powered", &powered.to_string());
+                blocks::set_block(&mut chunk_map, &mut events, pos, lever, cause);
+                let pitch = if powered { 0.6 } else { 0.5 };
+                broadcast_sound(&util, pos, "block.lever.click", 0.3, pitch, neq);
+            } else if name.ends_with("_button") {
+                if is_set(block, "powered") {
+                    continue;
+                }
+                let button = with_property(block, "powered", "true");
+                blocks::set_block(&mut chunk_map, &mut events, pos, button, cause);
+
+                let (delay, sound) = if name == "minecraft:stone_button" {
+                    (STONE_BUTTON_TICKS, "block.stone_button.click_on")
+                } else {
+                    (WOODEN_BUTTON_TICKS, "block.wooden_button.click_on")
+                };
+                ticks.schedule_block_tick(pos, delay, 0);
+                broadcast_sound(&util, pos, sound, 0.3, 0.6, neq);
+            } else {
+                let open = !is_set(block, "open");
+                let mut toggled = with_property(block, "open", &open.to_string());
+
+                let sound = if name.ends_with("_trapdoor") {
+                    "block.wooden_trapdoor"
+                } else if name.ends_with("_fence_gate") {
+                    // Fence gates open away from the player
+                    let looking = positions
+                        .get(event.player)
+                        .map(|position| looking_direction(position.current.yaw));
+                    let facing = property(block, "facing").and_then(|f| Direction::from_name(&f));
+                    if let (true, Some(looking), Some(facing)) = (open, looking, facing) {
+                        if facing == looking.opposite() {
+                            toggled = with_property(toggled, "facing", looking.name());
+                        }
+                    }
+                    "block.fence_gate"
+                } else {
+                    let other = match property(block, "half") {
+                        Some(ref half) if half == "upper" => Direction::Down.of(pos),
+                        _ => Direction::Up.of(pos),
+                    };
+                    if let Some(other_block) = chunk_map.block_at(other) {
+                        if other_block.to_name_and_props().0 == name {
+                            let toggled = with_property(other_block, "open", &open.to_string());
+                            blocks::set_block(
+                                &mut chunk_map,
+                                &mut events,
+                                other,
+                                toggled,
+                                cause.clone(),
+                            );
+                        }
+                    }
+                    "block.wooden_door"
+                };
+                blocks::set_block(&mut chunk_map, &mut events, pos, toggled, cause);
+
+                let sound = format!("{}.{}", sound, if open { "open" } else { "close" });
+                let pitch = rng.gen_range(0.9, 1.0);
+                broadcast_sound(&util, pos, &sound, 1.0, pitch, neq);
+            }
+        }
+
+        for event in tick_events.read(self.tick_reader.as_mut().unwrap()) {
+            let name = event.block.to_name_and_props().0;
+            if !name.ends_with("_button") || !is_set(event.block, "powered") {
+                continue;
+            }
+
+            let button = with_property(event.block, "powered", "false");
+            blocks::set_block(
+                &mut chunk_map,
+                &mut events,
+                event.pos,
+                button,
+                BlockUpdateCause::Button,
+            );
+            let sound = if name == "minecraft:stone_button" {
+                "block.stone_button.click_off"
+            } else {
+                "block.wooden_button.click_off"
+            };
+            broadcast_sound(&util, event.pos, sound, 0.3, 0.5, None);
+        }
+    }
+
+    setup_impl!(interact_reader, tick_reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(BlockInteractSystem::default(), BLOCK_INTERACT, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use feather_blocks::{
+        IronDoorData, LeverData, OakDoorData, OakFenceGateData, OakFenceGateFacing, StoneButtonData,
+    };
+    use feather_core::PacketType;
+    use specs::{Dispatcher, World};
+
+    fn world<'a, 'b>() -> (World, Dispatcher<'a, 'b>) {
+        let (mut w, d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(BlockInteractSystem::default(), "interact", &["ticks"])
+            .build();
+        t::populate_with_air(&mut w);
+        (w, d)
+    }
+
+    #[test]
+    fn test_is_interactive() {
+        assert!(is_interactive(Block::OakDoor(OakDoorData::default())));
+        assert!(is_interactive(Block::Lever(LeverData::default())));
+        assert!(!is_interactive(Block::IronDoor(IronDoorData::default())));
+        assert!(!is_interactive(Block::Stone));
+    }
+
+    #[test]
+    fn test_looking_direction() {
+        assert_eq!(looking_direction(0.0), Direction::South);
+        assert_eq!(looking_direction(100.0), Direction::West);
+        assert_eq!(looking_direction(-90.0), Direction::East);
+        assert_eq!(looking_direction(530.0), Direction::North);
+    }
+
+    #[test]
+    fn test_open_door() {
+        let (mut w, mut d) = world();
+        let player = t::add_player(&mut w);
+        let other = t::add_player(&mut w);
+
+        let lower = Block::OakDoor(OakDoorData::default());
+        let upper = with_property(lower, "half", "upper");
+        t::set_block(0, 64, 0, lower, &w);
+        t::set_block(0, 65, 0, upper, &w);
+
+        t::trigger_event(
+            &w,
+            BlockInteractEvent {
+                player: player.entity,
+                pos: BlockPosition::new(0, 65, 0),
+            },
+        );
+        t::run_ticks(&mut w, &mut d, 1);
+
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(lower, "open", "true")
+        );
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 65, 0)),
+            with_property(upper, "open", "true")
+        );
+        t::assert_packet_received(&other, PacketType::NamedSoundEffect);
+    }
+
+    #[test]
+    fn test_open_fence_gate() {
+        let (mut w, mut d) = world();
+        let player = t::add_player(&mut w);
+        t::set_entity_pos(&w, player.entity, position!(0.5, 64.0, -2.0, 0.0, 0.0));
+
+        let gate = Block::OakFenceGate(OakFenceGateData {
+            facing: OakFenceGateFacing::North,
+            ..OakFenceGateData::default()
+        });
+        t::set_block(0, 64, 0, gate, &w);
+        t::trigger_event(
+            &w,
+            BlockInteractEvent {
+                player: player.entity,
+                pos: BlockPosition::new(0, 64, 0),
+            },
+        );
+        t::run_ticks(&mut w, &mut d, 1);
+
+        let open = with_property(gate, "open", "true");
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(open, "facing", "south")
+        );
+    }
+
+    #[test]
+    fn test_press_button() {
+        let (mut w, mut d) = world();
+        let player = t::add_player(&mut w);
+
+        let button = Block::StoneButton(StoneButtonData::default());
+        t::set_block(0, 64, 0, button, &w);
+        t::trigger_event(
+            &w,
+            BlockInteractEvent {
+                player: player.entity,
+                pos: BlockPosition::new(0, 64, 0),
+            },
+        );
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(button, "powered", "true")
+        );
+
+        t::run_ticks(&mut w, &mut d, STONE_BUTTON_TICKS as usize - 1);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(button, "powered", "true")
+        );
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), button);
+    }
+}
@@ -29,8 +29,9 @@ use feather_core::network::packet::implementation::{
 };
 use feather_core::network::packet::{Packet, PacketStage, PacketType};
 
-use crate::config::Config;
-use crate::{PlayerCount, PROTOCOL_VERSION, SERVER_VERSION};
+use crate::config::SharedConfig;
+use crate::lang::{Locale, Message};
+use crate::{PlayerCount, MINECRAFT_VERSION, PROTOCOL_VERSION, SERVER_VERSION};
 
 /// The key used for symmetric encryption.
 pub type Key = [u8; 16];
@@ -97,7 +98,7 @@ pub struct InitialHandler {
     verify_token: VerifyToken,
 
     /// The server's configuration.
-    config: Arc<Config>,
+    config: Arc<SharedConfig>,
     /// The server's player count.
     player_count: Arc<PlayerCount>,
     /// The server's icon, if any was loaded.
@@ -118,7 +119,7 @@ pub struct InitialHandler {
 
 impl InitialHandler {
     pub fn new(
-        config: Arc<Config>,
+        config: Arc<SharedConfig>,
         player_count: Arc<PlayerCount>,
         server_icon: Arc<Option<String>>,
     ) -> Self {
@@ -153,7 +154,7 @@ impl InitialHandler {
 
         if let Err(e) = _handle_packet(self, packet).await {
             // Disconnect
-            disconnect_login(self, &format!("{}", e));
+            disconnect_login(self, e.message());
             info!(
                 "Player {} disconnected: {}",
                 self.username.as_ref().unwrap_or(&"unknown".to_string()),
@@ -219,17 +220,18 @@ fn handle_request(ih: &mut InitialHandler, packet: &Request) -> Result<(), Error
     let server_icon = (*ih.server_icon).clone().unwrap_or_default();
 
     // Send response packet
+    let config = ih.config.get();
     let json = json!({
         "version": {
             "name": SERVER_VERSION,
             "protocol": PROTOCOL_VERSION,
         },
         "players": {
-            "max": ih.config.server.max_players,
+            "max": config.server.max_players,
             "online": ih.player_count.0.load(Ordering::SeqCst),
         },
         "description": {
-            "text": ih.config.server.motd,
+            "text": config.server.motd,
         },
         "favicon": server_icon,
     });
@@ -265,7 +267,7 @@ fn handle_login_start(ih: &mut InitialHandler, packet: &LoginStart) -> Result<()
     // If not in online mode, the login sequence is
     // already finished, so we can call `finish` after
     // setting the player's info.
-    if ih.config.server.online_mode {
+    if ih.config.get().server.online_mode {
         use num_bigint::{BigInt, Sign::Plus};
         // Start enabling encryption
         let der = der::public_key_to_der(
@@ -283,9 +285,10 @@ fn handle_login_start(ih: &mut InitialHandler, packet: &LoginStart) -> Result<()
         ih.stage = Stage::AwaitEncryptionResponse;
     } else {
         // Finished - set info and join
+        let username = ih.username.clone().unwrap();
         ih.info = Some(JoinResult {
-            username: ih.username.clone().unwrap(),
-            uuid: Uuid::new_v4(),
+            uuid: offline_uuid(&username),
+            username,
             props: vec![],
         });
         finish(ih);
@@ -356,6 +359,18 @@ async fn handle_encryption_response(
     Ok(())
 }
 
+/// Returns the UUID of a player in offline mode.
+///
+/// As in vanilla, this is a version 3 UUID derived from
+/// the player's username, so a player's data is kept
+/// across joins as long as their username stays the same.
+pub fn offline_uuid(username: &str) -> Uuid {
+    let mut bytes = md5::compute(format!("OfflinePlayer:{}", username)).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30; // Version 3
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+    Uuid::from_bytes(bytes)
+}
+
 fn decrypt_using_rsa(data: &[u8], key: &RSAPrivateKey) -> Result<Vec<u8>, Error> {
     let buf = key
         .decrypt(PaddingScheme::PKCS1v15, data)
@@ -375,7 +390,7 @@ fn finish(ih: &mut InitialHandler) {
     assert!(ih.info.is_some());
 
     // Enable compression if necessary
-    let compression_threshold = ih.config.io.compression_threshold;
+    let compression_threshold = ih.config.get().io.compression_threshold;
     if compression_threshold > 0 {
         enable_compression(ih, compression_threshold);
     }
@@ -414,11 +429,12 @@ fn check_stage(ih: &InitialHandler, expected: Stage, packet_ty: PacketType) -> R
 
 /// Disconnects the initial handler, sending
 /// a disconnect packet containing the reason.
-fn disconnect_login(ih: &mut InitialHandler, reason: &str) {
-    let json = json!({
-        "text": reason,
-    })
-    .to_string();
+///
+/// The server's locale isn't available on the network
+/// thread, so only vanilla keys, which are translated
+/// by the client, should be used.
+fn disconnect_login(ih: &mut InitialHandler, reason: Message) {
+    let json = Locale::default().component(&reason).to_string();
 
     let packet = DisconnectLogin::new(json);
     send_packet(ih, packet);
@@ -447,6 +463,25 @@ enum Error {
     AuthenticationFailed(mojang_api::Error),
 }
 
+impl Error {
+    /// Returns the message with which the
+    /// client is disconnected.
+    fn message(&self) -> Message {
+        match self {
+            Error::InvalidProtocol(version) if *version < PROTOCOL_VERSION => {
+                Message::translate("multiplayer.disconnect.outdated_client").with(MINECRAFT_VERSION)
+            }
+            Error::InvalidProtocol(_) => {
+                Message::translate("multiplayer.disconnect.outdated_server").with(MINECRAFT_VERSION)
+            }
+            Error::AuthenticationFailed(_) => {
+                Message::translate("multiplayer.disconnect.unverified_username")
+            }
+            _ => Message::Text(self.to_string()),
+        }
+    }
+}
+
 /// The stage of an initial handler.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Stage {
@@ -471,6 +506,7 @@ mod tests {
     use crate::PROTOCOL_VERSION;
 
     use super::*;
+    use crate::config::Config;
 
     #[test]
     fn test_initial_handler_new() {
@@ -544,6 +580,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_offline_uuid() {
+        assert_eq!(
+            offline_uuid("Notch").to_string(),
+            "b50ad385-829d-3141-a216-7e7d7539ba7f"
+        );
+        assert_eq!(
+            offline_uuid("test").to_string(),
+            "530fa97a-357f-3c19-94d3-0c5c65c18fe8"
+        );
+    }
+
     #[tokio::test]
     async fn test_login_sequence() {
         let mut config = Config::default();
@@ -600,6 +648,10 @@ mod tests {
 
                 let login_success = cast_packet::<LoginSuccess>(&*_login_success);
                 assert_eq!(login_success.username, username.to_string());
+                assert_eq!(
+                    login_success.uuid,
+                    offline_uuid(username).to_hyphenated_ref().to_string()
+                );
             }
             _ => panic!(),
         }
@@ -618,7 +670,7 @@ mod tests {
 
     fn ih() -> InitialHandler {
         InitialHandler::new(
-            Arc::new(Config::default()),
+            Arc::new(SharedConfig::default()),
             Arc::new(PlayerCount(AtomicUsize::new(0))),
             Arc::new(Some(String::from("test"))),
         )
@@ -626,7 +678,7 @@ mod tests {
 
     fn ih_with_player_count(count: usize) -> InitialHandler {
         InitialHandler::new(
-            Arc::new(Config::default()),
+            Arc::new(SharedConfig::default()),
             Arc::new(PlayerCount(AtomicUsize::new(count))),
             Arc::new(Some(String::from("test"))),
         )
@@ -634,7 +686,7 @@ mod tests {
 
     fn ih_with_config(config: Config) -> InitialHandler {
         InitialHandler::new(
-            Arc::new(config),
+            Arc::new(SharedConfig::new(Arc::new(config))),
             Arc::new(PlayerCount(AtomicUsize::new(0))),
             Arc::new(Some(String::from("test"))),
         )
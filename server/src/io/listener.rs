@@ -3,23 +3,28 @@
 //! This task listens on a `TcpListener` and accepts
 //! connections, spawning worker tasks to handle them,4.
 
-use crate::config::Config;
+use crate::config::{Listener, SharedConfig};
 use crate::io::worker::run_worker;
 use crate::io::ListenerToServerMessage;
 use crate::PlayerCount;
+use net2::TcpBuilder;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io;
 use tokio::net::TcpListener;
+use tokio_net::driver::Handle;
+
+/// The maximum number of connections waiting to be accepted.
+const BACKLOG: i32 = 1024;
 
 pub async fn run_listener(
-    address: SocketAddr,
+    config_listener: Listener,
     sender: crossbeam::Sender<ListenerToServerMessage>,
-    config: Arc<Config>,
+    config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     server_icon: Arc<Option<String>>,
 ) -> Result<(), io::Error> {
-    let mut listener = TcpListener::bind(address).await?;
+    let mut listener = TcpListener::from_std(bind(&config_listener)?, &Handle::default())?;
 
     loop {
         let (stream, ip) = match listener.accept().await {
@@ -35,6 +40,7 @@ pub async fn run_listener(
         tokio::spawn(run_worker(
             stream,
             ip,
+            config_listener.proxy_protocol,
             sender.clone(),
             Arc::clone(&config),
             Arc::clone(&player_count),
@@ -42,3 +48,25 @@ pub async fn run_listener(
         ));
     }
 }
+
+/// Binds a socket to the listener's address. IPv6
+/// sockets are set to accept IPv4 connections as well
+/// if the listener is dual-stack, rather than depending
+/// on the operating system's default.
+fn bind(listener: &Listener) -> Result<std::net::TcpListener, io::Error> {
+    let builder = match listener.address {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(!listener.dual_stack)?;
+            builder
+        }
+    };
+
+    // Matches the behavior of the standard library,
+    // which allows restarting the server immediately.
+    #[cfg(unix)]
+    builder.reuse_address(true)?;
+
+    builder.bind(listener.address)?.listen(BACKLOG)
+}
@@ -1,19 +1,28 @@
-use crate::config::Config;
+use crate::config::{Listener, SharedConfig};
 use crate::PlayerCount;
-use feather_core::network::packet::Packet;
+use feather_core::network::packet::{EncodedPacket, Packet};
+use futures::channel::oneshot;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use uuid::Uuid;
 
 mod initialhandler;
 mod listener;
+mod proxy;
 mod worker;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Client(usize);
 
 pub enum ServerToWorkerMessage {
+    /// Queues a packet to be sent on the next `Flush`.
     SendPacket(Box<dyn Packet>),
+    /// Queues a packet which is being serialized on another
+    /// thread. Packets sent after it are only written once
+    /// it has been received, so that packets stay in order.
+    SendPendingPacket(oneshot::Receiver<EncodedPacket>),
+    /// Writes all queued packets to the connection.
+    Flush,
     NotifyPacketReceived(Box<dyn Packet>),
     NotifyDisconnect(String),
     Disconnect,
@@ -40,25 +49,46 @@ pub struct NetworkIoManager {
 }
 
 impl NetworkIoManager {
-    /// Starts a new IO listener.
+    /// Starts an IO listener for each of the given listeners.
     pub fn start(
-        addr: SocketAddr,
-        config: Arc<Config>,
+        listeners: Vec<Listener>,
+        config: Arc<SharedConfig>,
         player_count: Arc<PlayerCount>,
         server_icon: Arc<Option<String>>,
     ) -> Self {
-        info!("Starting IO listener on {}", addr,);
-
         let (sender, receiver) = crossbeam::unbounded();
 
-        let future = run_listener(addr, sender.clone(), config, player_count, server_icon);
+        for listener in listeners {
+            info!("Starting IO listener on {}", listener.address);
+
+            let future = run_listener(
+                listener,
+                sender.clone(),
+                Arc::clone(&config),
+                Arc::clone(&player_count),
+                Arc::clone(&server_icon),
+            );
 
-        if cfg!(test) {
-            let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
-            rt.spawn(future);
-        } else {
-            tokio::spawn(future);
+            if cfg!(test) {
+                let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+                rt.spawn(future);
+            } else {
+                tokio::spawn(future);
+            }
+        }
+
+        Self {
+            receiver,
+            listener_sender: sender,
         }
+    }
+
+    /// Creates an IO manager without a listener. Clients
+    /// can be added by sending `ListenerToServerMessage::NewClient`
+    /// over `listener_sender`, which allows the server to be
+    /// run in-process without binding to a socket.
+    pub fn in_memory() -> Self {
+        let (sender, receiver) = crossbeam::unbounded();
 
         Self {
             receiver,
@@ -79,14 +109,20 @@ pub fn init() {
 }
 
 async fn run_listener(
-    addr: SocketAddr,
+    listener: Listener,
     sender: crossbeam::Sender<ListenerToServerMessage>,
-    config: Arc<Config>,
+    config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     server_icon: Arc<Option<String>>,
 ) {
-    if let Err(e) = listener::run_listener(addr, sender, config, player_count, server_icon).await {
-        error!("An error occurred while binding to socket: {:?}", e);
+    let address = listener.address;
+    if let Err(e) =
+        listener::run_listener(listener, sender, config, player_count, server_icon).await
+    {
+        error!(
+            "An error occurred while binding to socket {}: {:?}",
+            address, e
+        );
         std::process::exit(1);
     }
 }
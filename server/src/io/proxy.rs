@@ -0,0 +1,115 @@
+//! Support for version 1 of the PROXY protocol.
+//!
+//! Proxies such as HAProxy send a single line of text
+//! at the start of each connection which contains the
+//! address of the original client, for example:
+//!
+//! ```text
+//! PROXY TCP4 192.168.0.1 192.168.0.11 56324 25565\r\n
+//! ```
+//!
+//! The binary version 2 of the protocol is not supported.
+
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// The maximum length of a header, including the line ending.
+const MAX_HEADER_LENGTH: usize = 107;
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum ProxyError {
+    #[fail(display = "PROXY protocol header too long")]
+    TooLong,
+    #[fail(display = "Invalid PROXY protocol header: {}", _0)]
+    Invalid(String),
+}
+
+/// Reads the PROXY header from the start of a connection,
+/// returning the address of the client. For connections
+/// which the proxy made itself, such as health checks,
+/// `peer` is returned.
+pub async fn read_header(
+    stream: &mut TcpStream,
+    peer: SocketAddr,
+) -> Result<SocketAddr, failure::Error> {
+    // Read one byte at a time so that no data
+    // after the header is consumed.
+    let mut line = Vec::with_capacity(MAX_HEADER_LENGTH);
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        if line.len() == MAX_HEADER_LENGTH {
+            return Err(ProxyError::TooLong.into());
+        }
+        stream.read_exact(&mut byte).await?;
+        line.push(byte[0]);
+    }
+
+    let line = String::from_utf8_lossy(&line);
+    Ok(parse_header(&line, peer)?)
+}
+
+/// Parses a PROXY header, including its line ending.
+fn parse_header(line: &str, peer: SocketAddr) -> Result<SocketAddr, ProxyError> {
+    let invalid = || ProxyError::Invalid(line.trim_end().to_string());
+
+    let line = line.strip_suffix("\r\n").ok_or_else(invalid)?;
+    let parts: Vec<&str> = line.split(' ').collect();
+
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(peer),
+        ["PROXY", protocol, source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source.parse().map_err(|_| invalid())?;
+            let port: u16 = source_port.parse().map_err(|_| invalid())?;
+
+            match (*protocol, ip) {
+                ("TCP4", IpAddr::V4(_)) | ("TCP6", IpAddr::V6(_)) => Ok(SocketAddr::new(ip, port)),
+                _ => Err(invalid()),
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        "10.0.0.1:40000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header(
+                "PROXY TCP4 192.168.0.1 192.168.0.11 56324 25565\r\n",
+                peer()
+            ),
+            Ok("192.168.0.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_header("PROXY TCP6 2001:db8::1 2001:db8::2 56324 25565\r\n", peer()),
+            Ok("[2001:db8::1]:56324".parse().unwrap())
+        );
+        assert_eq!(parse_header("PROXY UNKNOWN\r\n", peer()), Ok(peer()));
+        assert_eq!(
+            parse_header("PROXY UNKNOWN 192.168.0.1 192.168.0.11 1 2\r\n", peer()),
+            Ok(peer())
+        );
+    }
+
+    #[test]
+    fn test_parse_header_invalid() {
+        for line in &[
+            "PROXY TCP4 192.168.0.1 192.168.0.11 56324 25565\n",
+            "PROXY TCP4 192.168.0.1 192.168.0.11 56324\r\n",
+            "PROXY TCP4 2001:db8::1 2001:db8::2 56324 25565\r\n",
+            "PROXY TCP4 192.168.0.1 192.168.0.11 port 25565\r\n",
+            "PROXY UDP4 192.168.0.1 192.168.0.11 56324 25565\r\n",
+            "GET / HTTP/1.1\r\n",
+        ] {
+            assert!(parse_header(line, peer()).is_err(), "{}", line);
+        }
+    }
+}
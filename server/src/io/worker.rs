@@ -4,29 +4,45 @@
 //! both sending and receiving packets.
 //!
 //! Packet send requests are sent over a channel from the server threads
-//! to the worker for any given client.
+//! to the worker for any given client. Packets sent by the server
+//! are buffered and only written to the socket when the server
+//! requests a flush, which it does at the end of each tick; this
+//! way, all packets sent to a client during a tick are written
+//! using as few system calls and TCP segments as possible.
 
-use crate::config::Config;
+use crate::config::SharedConfig;
 use crate::io::initialhandler::{Action, InitialHandler};
+use crate::io::proxy;
 use crate::io::{ListenerToServerMessage, NewClientInfo, ServerToWorkerMessage};
 use crate::PlayerCount;
 use feather_core::network::codec::MinecraftCodec;
+use feather_core::network::packet::Packet;
 use feather_core::network::packet::PacketDirection;
+use futures::future::poll_fn;
 use futures::{select, StreamExt};
-use futures::{FutureExt, SinkExt};
+use futures::{FutureExt, Sink, SinkExt};
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::codec::Framed;
 use tokio::net::TcpStream;
 use tokio::timer::Timeout;
 
+/// The time to wait for a PROXY protocol header.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Runs a worker task for the given client.
+///
+/// If `proxy_protocol` is set, the connection must start
+/// with a PROXY protocol header, and the address it contains
+/// is used instead of `ip`.
 pub async fn run_worker(
     stream: TcpStream,
     ip: SocketAddr,
+    proxy_protocol: bool,
     global_sender: crossbeam::Sender<ListenerToServerMessage>,
-    config: Arc<Config>,
+    config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     server_icon: Arc<Option<String>>,
 ) {
@@ -35,6 +51,7 @@ pub async fn run_worker(
     let msg = match _run_worker(
         stream,
         ip,
+        proxy_protocol,
         global_sender,
         config,
         player_count,
@@ -53,15 +70,21 @@ pub async fn run_worker(
 
 #[allow(clippy::too_many_arguments)]
 async fn _run_worker(
-    stream: TcpStream,
-    ip: SocketAddr,
+    mut stream: TcpStream,
+    mut ip: SocketAddr,
+    proxy_protocol: bool,
     global_sender: crossbeam::Sender<ListenerToServerMessage>,
-    config: Arc<Config>,
+    config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     server_icon: Arc<Option<String>>,
     tx_worker_to_server: crossbeam::Sender<ServerToWorkerMessage>,
     rx_worker_to_server: crossbeam::Receiver<ServerToWorkerMessage>,
 ) -> Result<(), failure::Error> {
+    if proxy_protocol {
+        ip = Timeout::new(proxy::read_header(&mut stream, ip), PROXY_HEADER_TIMEOUT).await??;
+        debug!("Connection proxied for {}", ip);
+    }
+
     let codec = MinecraftCodec::new(PacketDirection::Serverbound);
 
     let mut framed = Framed::new(stream, codec);
@@ -83,8 +106,19 @@ async fn _run_worker(
         if let Some(msg) = server_message {
             if let Some(msg) = msg {
                 match msg {
-                    ServerToWorkerMessage::SendPacket(packet) => framed.send(packet).await?,
-                    ServerToWorkerMessage::Disconnect => return Ok(()),
+                    ServerToWorkerMessage::SendPacket(packet) => {
+                        buffer_packet(&mut framed, packet).await?
+                    }
+                    ServerToWorkerMessage::SendPendingPacket(pending) => {
+                        if let Ok(packet) = pending.await {
+                            buffer_packet(&mut framed, Box::new(packet)).await?
+                        }
+                    }
+                    ServerToWorkerMessage::Flush => framed.flush().await?,
+                    ServerToWorkerMessage::Disconnect => {
+                        framed.flush().await?;
+                        return Ok(());
+                    }
                     _ => unreachable!(),
                 }
             }
@@ -144,3 +178,14 @@ async fn _run_worker(
         }
     }
 }
+
+/// Queues a packet to be written on the next flush. If the write
+/// buffer is full, it is written to the socket first.
+async fn buffer_packet(
+    framed: &mut Framed<TcpStream, MinecraftCodec>,
+    packet: Box<dyn Packet>,
+) -> Result<(), failure::Error> {
+    poll_fn(|cx| Pin::new(&mut *framed).poll_ready(cx)).await?;
+    Pin::new(framed).start_send(packet)?;
+    Ok(())
+}
@@ -21,12 +21,16 @@ use feather_core::network::packet::implementation::{
 use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition};
 use feather_core::{Difficulty, Dimension};
 
+use crate::bans::{self, BanList};
 use crate::chunk_logic::{ChunkHolderComponent, ChunkHolders, ChunkWorkerHandle};
 use crate::config::Config;
-use crate::entity::{EntitySpawnEvent, PlayerComponent, PositionComponent};
+use crate::entity::{EntitySpawnEvent, NamedComponent, PlayerComponent, PositionComponent};
+use crate::event::{EventBus, PlayerLoginEvent};
+use crate::lang::Message;
 use crate::network::NetworkComponent;
 use crate::player::{ChunkPendingComponent, InventoryUpdateEvent, LoadedChunksComponent};
-use crate::PlayerCount;
+use crate::view_distance::ViewDistance;
+use crate::{disconnect_player, PlayerCount};
 
 #[derive(Default)]
 pub struct JoinHandlerComponent {
@@ -79,6 +83,7 @@ impl<'a> System<'a> for JoinHandlerSystem {
         Entities<'a>,
         Read<'a, LazyUpdate>,
         Read<'a, Arc<Config>>,
+        ReadExpect<'a, ViewDistance>,
         Read<'a, Arc<PlayerCount>>,
         Read<'a, ChunkMap>,
         Write<'a, ChunkHolders>,
@@ -87,6 +92,9 @@ impl<'a> System<'a> for JoinHandlerSystem {
         WriteStorage<'a, LoadedChunksComponent>,
         ReadStorage<'a, PlayerComponent>,
         ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NamedComponent>,
+        Read<'a, EventBus>,
+        Read<'a, BanList>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -101,6 +109,7 @@ impl<'a> System<'a> for JoinHandlerSystem {
             entities,
             lazy,
             config,
+            view_distance,
             player_count,
             chunk_map,
             mut holders,
@@ -109,6 +118,9 @@ impl<'a> System<'a> for JoinHandlerSystem {
             mut loaded_chunks_comps,
             playercomps,
             positions,
+            nameds,
+            bus,
+            ban_list,
         ) = data;
 
         let mut to_remove = vec![];
@@ -144,7 +156,7 @@ impl<'a> System<'a> for JoinHandlerSystem {
                     let chunk_offset_z = player_pos.z;
 
                     // Queue chunks for sending.
-                    let view_distance = i32::from(config.server.view_distance);
+                    let view_distance = i32::from(view_distance.0);
                     let mut chunks = Vec::with_capacity((view_distance * view_distance) as usize);
                     for x in -view_distance..=view_distance {
                         for z in -view_distance..=view_distance {
@@ -191,6 +203,32 @@ impl<'a> System<'a> for JoinHandlerSystem {
                         continue;
                     }
 
+                    let username = nameds.get(player).unwrap().display_name.clone();
+                    let whitelisted = !config.server.whitelist
+                        || config
+                            .server
+                            .whitelisted_players
+                            .iter()
+                            .any(|name| name.eq_ignore_ascii_case(&username));
+
+                    let ban = ban_list.get(&username);
+                    let kick_message = match ban {
+                        Some(ban) => bans::kick_message(ban),
+                        None => Message::translate("multiplayer.disconnect.not_whitelisted"),
+                    };
+
+                    let mut login_event = PlayerLoginEvent {
+                        cancelled: !whitelisted || ban.is_some(),
+                        player,
+                        username,
+                        kick_message,
+                    };
+                    if !bus.emit(&mut login_event) {
+                        disconnect_player(player, login_event.kick_message, &lazy);
+                        to_remove.push(player);
+                        continue;
+                    }
+
                     // SpawnPosition packet: world spawn (used for compass)
                     let level_spawn_block_pos =
                         BlockPosition::new(level.spawn_x, level.spawn_y, level.spawn_z);
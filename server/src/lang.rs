@@ -0,0 +1,290 @@
+//! Localization of messages sent by the server.
+//!
+//! Messages are identified by translation keys. Keys which exist
+//! in vanilla, such as `multiplayer.player.joined`, are sent to players
+//! as translation components so that each client displays them in its
+//! own language. Keys specific to Feather begin with `feather.` and
+//! are translated by the server using the locale set by `server.locale`.
+//!
+//! English translations are bundled with the server. Any key, including
+//! vanilla ones, may be translated by placing a `lang/<locale>.json`
+//! file in the server directory, using the same format as vanilla
+//! language files. Keys missing from that file fall back to English.
+
+use hashbrown::{HashMap, HashSet};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The directory containing server language files.
+pub const LANG_DIR: &str = "lang";
+
+/// The locale used if none is configured.
+pub const DEFAULT_LOCALE: &str = "en_us";
+
+const ENGLISH: &str = include_str!("../locales/en_us.json");
+
+/// A message which can be sent to a player or the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A message identified by a translation key,
+    /// with its arguments in order.
+    Translate { key: String, args: Vec<String> },
+    /// Text which is not translated, such as
+    /// a kick reason given by an operator.
+    Text(String),
+}
+
+impl Message {
+    /// Creates a message with the given translation key.
+    pub fn translate(key: &str) -> Self {
+        Message::Translate {
+            key: key.to_string(),
+            args: vec![],
+        }
+    }
+
+    /// Appends an argument to the message.
+    /// Has no effect on plain text.
+    pub fn with(mut self, arg: impl ToString) -> Self {
+        if let Message::Translate { args, .. } = &mut self {
+            args.push(arg.to_string());
+        }
+        self
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Message::Text(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Message::Text(text.to_string())
+    }
+}
+
+/// Resource containing the translations
+/// of the server's configured locale.
+#[derive(Debug, Clone)]
+pub struct Locale {
+    name: String,
+    translations: HashMap<String, String>,
+    /// Keys translated by the server's language file,
+    /// which are never left to the client to translate.
+    overridden: HashSet<String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_LOCALE.to_string(),
+            translations: serde_json::from_str(ENGLISH).expect("invalid bundled en_us.json"),
+            overridden: HashSet::new(),
+        }
+    }
+}
+
+impl Locale {
+    /// Loads the locale with the given name from
+    /// the language directory. Errors are logged,
+    /// falling back to English.
+    pub fn load(name: &str) -> Self {
+        Self::load_from(Path::new(LANG_DIR), name)
+    }
+
+    /// Loads the locale with the given name
+    /// from a language directory.
+    pub fn load_from(dir: &Path, name: &str) -> Self {
+        let mut locale = Self::default();
+        locale.name = name.to_string();
+
+        let path = dir.join(format!("{}.json", name));
+        let translations = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str::<HashMap<String, String>>(&s),
+            Err(_) => {
+                if name != DEFAULT_LOCALE {
+                    warn!("Language file {} does not exist", path.display());
+                }
+                return locale;
+            }
+        };
+
+        match translations {
+            Ok(translations) => {
+                for (key, value) in translations {
+                    locale.overridden.insert(key.clone());
+                    locale.translations.insert(key, value);
+                }
+            }
+            Err(e) => warn!("Failed to read language file {}: {}", path.display(), e),
+        }
+
+        locale
+    }
+
+    /// Returns the name of this locale, such as `en_us`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns a message translated into this locale.
+    /// Unknown keys are returned as-is.
+    pub fn text(&self, message: &Message) -> String {
+        match message {
+            Message::Translate { key, args } => {
+                let template = self.translations.get(key).unwrap_or(key);
+                format(template, args)
+            }
+            Message::Text(text) => text.clone(),
+        }
+    }
+
+    /// Returns the JSON text component for a message. Vanilla
+    /// keys not overridden by the server's language file are
+    /// left to the client to translate.
+    pub fn component(&self, message: &Message) -> Value {
+        match message {
+            Message::Translate { key, args }
+                if !key.starts_with("feather.") && !self.overridden.contains(key) =>
+            {
+                json!({
+                    "translate": key,
+                    "with": args,
+                })
+            }
+            _ => json!({ "text": self.text(message) }),
+        }
+    }
+}
+
+/// Formats a template using the syntax of vanilla
+/// language files: `%s` is replaced with the next
+/// argument, `%1$s` with the first and `%%` with `%`.
+fn format(template: &str, args: &[String]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut chars = template.char_indices();
+
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+
+        let rest = &template[start + 1..];
+        if rest.starts_with('%') {
+            chars.next();
+            result.push('%');
+        } else if rest.starts_with('s') {
+            chars.next();
+            result.push_str(args.get(next).map_or("", String::as_str));
+            next += 1;
+        } else {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            match rest[digits..].strip_prefix("$s") {
+                Some(_) if digits > 0 => {
+                    let index: usize = rest[..digits].parse().unwrap_or(0);
+                    let arg = index.checked_sub(1).and_then(|index| args.get(index));
+                    result.push_str(arg.map_or("", String::as_str));
+                    for _ in 0..digits + 2 {
+                        chars.next();
+                    }
+                }
+                _ => result.push('%'),
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format("%s joined", &args(&["Steve"])), "Steve joined");
+        assert_eq!(format("%2$s, %1$s", &args(&["a", "b"])), "b, a");
+        assert_eq!(format("100%%", &[]), "100%");
+        assert_eq!(format("%s and %s", &args(&["a"])), "a and ");
+        assert_eq!(format("50% off", &[]), "50% off");
+    }
+
+    #[test]
+    fn test_english() {
+        let locale = Locale::default();
+        assert_eq!(locale.name(), DEFAULT_LOCALE);
+        assert_eq!(
+            locale.text(&Message::translate("command.unknown.command")),
+            "Unknown command."
+        );
+        assert_eq!(
+            locale.text(&Message::translate("no.such.key")),
+            "no.such.key"
+        );
+        assert_eq!(locale.text(&Message::from("Hello")), "Hello");
+    }
+
+    #[test]
+    fn test_component() {
+        let locale = Locale::default();
+
+        let vanilla = Message::translate("multiplayer.player.joined").with("Steve");
+        assert_eq!(
+            locale.component(&vanilla),
+            json!({ "translate": "multiplayer.player.joined", "with": ["Steve"] })
+        );
+
+        let feather = Message::translate("feather.command.no_permission");
+        assert_eq!(
+            locale.component(&feather),
+            json!({ "text": "You do not have permission to use this command." })
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("feather-lang-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("de_de.json"),
+            r#"{
+                "feather.command.no_permission": "Keine Berechtigung.",
+                "multiplayer.player.joined": "%s ist beigetreten"
+            }"#,
+        )
+        .unwrap();
+
+        let locale = Locale::load_from(&dir, "de_de");
+        assert_eq!(
+            locale.text(&Message::translate("feather.command.no_permission")),
+            "Keine Berechtigung."
+        );
+        assert_eq!(
+            locale.component(&Message::translate("multiplayer.player.joined").with("Steve")),
+            json!({ "text": "Steve ist beigetreten" })
+        );
+        // Missing keys fall back to English.
+        assert_eq!(
+            locale.text(&Message::translate("command.unknown.command")),
+            "Unknown command."
+        );
+
+        let missing = Locale::load_from(&dir, "fr_fr");
+        assert_eq!(missing.name(), "fr_fr");
+        assert_eq!(
+            missing.text(&Message::translate("command.unknown.command")),
+            "Unknown command."
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -32,7 +32,7 @@ use crossbeam::Receiver;
 use std::alloc::System;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use specs::{Builder, Dispatcher, DispatcherBuilder, Entity, LazyUpdate, World, WorldExt};
 
@@ -40,6 +40,8 @@ use feather_core::network::packet::implementation::DisconnectPlay;
 use prelude::*;
 
 use crate::chunk_logic::{ChunkHolders, ChunkWorkerHandle};
+use crate::clock::{NextTick, TickClock};
+use crate::config::SharedConfig;
 use crate::entity::chicken::ChickenComponent;
 use crate::entity::cow::CowComponent;
 use crate::entity::donkey::DonkeyComponent;
@@ -49,25 +51,28 @@ use crate::entity::mooshroom::MooshroomComponent;
 use crate::entity::pig::PigComponent;
 use crate::entity::rabbit::RabbitComponent;
 use crate::entity::sheep::SheepComponent;
+use crate::entity::skeleton::SkeletonComponent;
 use crate::entity::squid::SquidComponent;
+use crate::entity::zombie::ZombieComponent;
+use crate::entity::zombie_pigman::ZombiePigmanComponent;
 use crate::entity::{
     EntityDestroyEvent, NamedComponent, PacketCreatorComponent, SerializerComponent,
 };
-use crate::network::send_packet_to_player;
+use crate::lang::{Locale, Message};
+use crate::network::send_packet_immediately_to_player;
 use crate::player::PlayerDisconnectEvent;
 use crate::systems::{BROADCASTER, JOIN_HANDLER, NETWORK, PLAYER_INIT};
+use crate::timings::DispatcherBuilderExt;
 use crate::util::Util;
-use crate::worldgen::{
-    ComposableGenerator, EmptyWorldGenerator, SuperflatWorldGenerator, WorldGenerator,
-};
 use feather_core::level;
-use feather_core::level::{deserialize_level_file, save_level_file, LevelData, LevelGeneratorType};
+use feather_core::level::{deserialize_level_file, save_level_file, LevelData, LevelVersion};
 use rand::Rng;
 use shrev::EventChannel;
 use std::collections::hash_map::DefaultHasher;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::process::exit;
 
@@ -76,29 +81,73 @@ static ALLOC: System = System;
 
 #[macro_use]
 pub mod util;
+pub mod admin;
+pub mod bans;
+pub mod beacon;
 pub mod blocks;
+pub mod cauldron;
+pub mod chest;
 pub mod chunk_logic;
 pub mod chunkworker;
+pub mod clock;
+pub mod combat;
+pub mod commands;
 pub mod config;
+pub mod console;
+pub mod container;
+pub mod crafting;
+pub mod crash;
+pub mod datapack;
+pub mod dimension;
+pub mod effect;
+pub mod elytra;
 pub mod entity;
+pub mod event;
+pub mod furnace;
+pub mod hopper;
+pub mod interact;
 pub mod io;
 pub mod joinhandler;
+pub mod lang;
 pub mod lazy;
 pub mod lighting;
+pub mod loot;
+pub mod map;
+pub mod metrics;
+pub mod mob;
 pub mod network;
 pub mod physics;
 pub mod player;
+pub mod plugin;
+pub mod portal;
 pub mod prelude;
+pub mod recipe;
+pub mod redstone;
+pub mod reload;
+pub mod rollback;
+pub mod scheduler;
+pub mod script;
 pub mod shutdown;
+pub mod sign;
+pub mod sleep;
+pub mod spawning;
+pub mod structure_block;
 pub mod systems;
 #[cfg(test)]
 pub mod testframework;
 pub mod time;
+pub mod timings;
+pub mod tool;
+pub mod view_distance;
+pub mod weather;
+pub mod worldedit;
 pub mod worldgen;
 
 pub const TPS: u64 = 20;
 pub const PROTOCOL_VERSION: u32 = 404;
 pub const SERVER_VERSION: &str = "Feather 1.13.2";
+pub const MINECRAFT_VERSION: &str = "1.13.2";
+pub const DATA_VERSION: i32 = 1631;
 pub const TICK_TIME: u64 = 1000 / TPS;
 
 #[derive(Default, Debug)]
@@ -110,15 +159,29 @@ pub struct TickCount(u64);
 pub fn main() {
     let config = Arc::new(load_config());
     init_log(&config);
+    crash::install_hook();
+    crash::set_config(Arc::clone(&config));
+    let shared_config = Arc::new(SharedConfig::new(Arc::clone(&config)));
 
     info!("Starting Feather; please wait...");
 
     let server_icon = Arc::new(load_server_icon());
 
+    if config.metrics.enabled {
+        let addr = config.metrics.address.parse().unwrap_or_else(|e| {
+            error!("Invalid metrics address {}: {}", config.metrics.address, e);
+            exit(1)
+        });
+        if let Err(e) = metrics::start_server(addr) {
+            error!("Failed to start metrics server: {}", e);
+            exit(1);
+        }
+    }
+
     let player_count = Arc::new(PlayerCount(AtomicUsize::new(0)));
 
     let io_manager = init_io_manager(
-        Arc::clone(&config),
+        Arc::clone(&shared_config),
         Arc::clone(&player_count),
         Arc::clone(&server_icon),
     );
@@ -147,12 +210,54 @@ pub fn main() {
         exit(1)
     });
 
-    let (mut world, mut dispatcher) = init_world(config, player_count, io_manager, level);
+    let (mut world, mut dispatcher) = init_world(shared_config, player_count, io_manager, level);
+    world.insert(console::start());
+    world.insert(Locale::load(&config.server.locale));
+    world.insert(
+        bans::BanList::load(bans::BAN_LIST_PATH).unwrap_or_else(|e| {
+            error!("Failed to load {}: {}", bans::BAN_LIST_PATH, e);
+            exit(1)
+        }),
+    );
+    let datapacks = datapack::Datapacks::load(world_dir);
+    if !datapacks.packs().is_empty() {
+        info!(
+            "Loaded {} functions from {} datapacks",
+            datapacks.function_count(),
+            datapacks.packs().len()
+        );
+    }
+    world.insert(datapacks);
+    world.insert(loot::LootTables::load(world_dir));
+    let recipes = recipe::RecipeRegistry::load(world_dir);
+    info!("Loaded {} recipes", recipes.len());
+    world.insert(recipes);
+    world.insert(map::MapRegistry::load(world_dir));
+    if config.rollback.enabled {
+        let path = world_dir.join(rollback::LOG_FILE);
+        let log = rollback::ChangeLog::open(&path, config.rollback.max_age).unwrap_or_else(|e| {
+            error!("Failed to open {}: {}", path.display(), e);
+            exit(1)
+        });
+        info!("Loaded {} logged changes", log.changes().len());
+        world.insert(log);
+    }
+    if config.admin_api.enabled {
+        match admin::start_server(&config.admin_api) {
+            Ok(requests) => world.insert(requests),
+            Err(e) => {
+                error!("Failed to start admin API: {}", e);
+                exit(1);
+            }
+        }
+    }
 
     // Channel used by the shutdown handler to notify the server thread.
     let (shutdown_tx, shutdown_rx) = crossbeam::unbounded();
 
+    world.insert(shutdown::ShutdownHandle(shutdown_tx.clone()));
     shutdown::init(shutdown_tx);
+    reload::register_signal_handler(&world);
 
     info!("Initialized world");
 
@@ -167,28 +272,34 @@ pub fn main() {
 
     info!("Shutting down");
 
+    info!("Kicking players");
+    shutdown::kick_players(&world);
+
     info!("Saving chunks");
     shutdown::save_chunks(&mut world);
     info!("Saving level.dat");
     shutdown::save_level(&world);
+    info!("Saving maps");
+    shutdown::save_maps(&world);
     info!("Saving player data");
     shutdown::save_player_data(&world);
 
     info!("Goodbye");
+    console::restore();
     exit(0);
 }
 
 /// Loads the configuration file, creating a default
 /// one if it does not exist.
 fn load_config() -> Config {
-    match config::load_from_file("feather.toml") {
+    match config::load_from_file(config::CONFIG_PATH) {
         Ok(config) => config,
         Err(e) => match e {
             config::ConfigError::Io(_) => {
                 // Use default config
                 println!("Config not found - creating it");
                 let config = Config::default();
-                let mut file = File::create("feather.toml").unwrap();
+                let mut file = File::create(config::CONFIG_PATH).unwrap();
                 file.write_all(config::DEFAULT_CONFIG_STR.as_bytes())
                     .unwrap();
                 config
@@ -200,7 +311,8 @@ fn load_config() -> Config {
     }
 }
 
-fn create_level(config: &Config) -> LevelData {
+/// Creates the level data for a new world.
+pub fn create_level(config: &Config) -> LevelData {
     let seed = get_seed(config);
     let world_name = &config.world.name;
     debug!("Using seed {} for world '{}'", seed, world_name);
@@ -210,11 +322,15 @@ fn create_level(config: &Config) -> LevelData {
         allow_commands: false,
         border_center_x: 0.0,
         border_center_z: 0.0,
-        border_damage_per_block: 0.0,
-        border_safe_zone: 0.0,
-        border_size: 0.0,
+        border_damage_per_block: 0.2,
+        border_safe_zone: 5.0,
+        border_size: 60_000_000.0,
+        border_size_lerp_target: 60_000_000.0,
+        border_size_lerp_time: 0,
+        border_warning_blocks: 5.0,
+        border_warning_time: 15.0,
         clear_weather_time: 0,
-        data_version: 0,
+        data_version: DATA_VERSION,
         day_time: 0,
         difficulty: 0,
         difficulty_locked: 0,
@@ -231,9 +347,14 @@ fn create_level(config: &Config) -> LevelData {
         thundering: false,
         thunder_time: 0,
         time: 0,
-        version: Default::default(),
+        version: LevelVersion {
+            id: DATA_VERSION,
+            name: MINECRAFT_VERSION.to_string(),
+            snapshot: false,
+        },
         generator_name: config.world.generator.to_string(),
         generator_options: None,
+        game_rules: LevelData::default_game_rules(),
     }
 }
 
@@ -271,7 +392,7 @@ fn load_level(path: &Path) -> Result<LevelData, failure::Error> {
 ///
 /// Note that these chunks are loaded asynchronously,
 /// and this function will return before loading is complete.
-fn load_spawn_chunks(world: &mut World) {
+pub fn load_spawn_chunks(world: &mut World) {
     let view_distance = i32::from(world.fetch::<Arc<Config>>().server.view_distance);
 
     // Create an entity for the server and
@@ -297,68 +418,91 @@ fn load_spawn_chunks(world: &mut World) {
 /// Runs the server loop, blocking until the server
 /// is shut down.
 fn run_loop(world: &mut World, dispatcher: &mut Dispatcher, shutdown_rx: Receiver<()>) {
+    let mut clock = TickClock::new(Instant::now());
     loop {
         if shutdown_rx.try_recv().is_ok() {
             // Shut down
             return;
         }
 
-        let start_time = current_time_in_millis();
-
-        dispatcher.dispatch(&world);
-        world.maintain();
-
-        world.fetch_mut::<Util>().reset();
-
-        // Increment tick count
-        let mut tick_count = world.write_resource::<TickCount>();
-        tick_count.0 += 1;
+        let start_time = Instant::now();
+        timings::TIMINGS.lock().record_tick_start(start_time);
 
-        // Sleep correct amount
-        let end_time = current_time_in_millis();
-        let elapsed = end_time - start_time;
-        if elapsed > TICK_TIME {
-            debug!("Running behind! Starting next tick immediately");
-            continue; // Behind - start next tick immediately
+        let result = panic::catch_unwind(AssertUnwindSafe(|| tick(world, dispatcher)));
+        if result.is_err() {
+            // The crash report has already been
+            // written by the panic hook.
+            crash::emergency_save(world);
+            console::restore();
+            std::process::abort();
         }
 
-        // Sleep in 1ms increments until we've slept enough
-        let mut sleep_time = (TICK_TIME - elapsed) as i64;
-        let mut last_sleep_time = current_time_in_millis();
-        while sleep_time > 0 {
-            std::thread::sleep(Duration::from_millis(1));
-            sleep_time -= (current_time_in_millis() - last_sleep_time) as i64;
-            last_sleep_time = current_time_in_millis();
+        let end_time = Instant::now();
+        let elapsed = end_time - start_time;
+        metrics::METRICS
+            .tick_duration
+            .observe(elapsed.as_millis() as u64);
+        timings::TIMINGS.lock().record_tick_duration(elapsed);
+
+        match clock.advance(end_time) {
+            NextTick::Sleep(duration) => std::thread::sleep(duration),
+            NextTick::CatchUp => debug!("Running behind! Starting next tick immediately"),
+            NextTick::Skip(ticks) => {
+                warn!(
+                    "Server overloaded, skipping {} ticks (last tick took {} ms)",
+                    ticks,
+                    elapsed.as_millis()
+                );
+                for line in timings::TIMINGS.lock().tick_report() {
+                    warn!("{}", line);
+                }
+            }
         }
     }
 }
 
+/// Runs a single tick of the server.
+pub fn tick(world: &mut World, dispatcher: &mut Dispatcher) {
+    dispatcher.dispatch(&world);
+    world.maintain();
+    network::flush_packets(world);
+
+    world.fetch_mut::<Util>().reset();
+
+    // Increment tick count
+    let mut tick_count = world.write_resource::<TickCount>();
+    tick_count.0 += 1;
+    crash::set_tick(tick_count.0);
+}
+
 /// Starts the IO threads.
 fn init_io_manager(
-    config: Arc<Config>,
+    shared_config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     server_icon: Arc<Option<String>>,
 ) -> io::NetworkIoManager {
-    io::NetworkIoManager::start(
-        format!("{}:{}", config.server.address, config.server.port)
-            .parse()
-            .unwrap(),
-        config,
-        player_count,
-        server_icon,
-    )
+    let config = shared_config.get();
+    let listeners = config.server.listeners().unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1)
+    });
+    io::NetworkIoManager::start(listeners, shared_config, player_count, server_icon)
 }
 
 /// Initializes the Specs world and dispatchers.
-fn init_world<'a, 'b>(
-    config: Arc<Config>,
+pub fn init_world<'a, 'b>(
+    shared_config: Arc<SharedConfig>,
     player_count: Arc<PlayerCount>,
     ioman: io::NetworkIoManager,
     level: LevelData,
 ) -> (World, Dispatcher<'a, 'b>) {
     let mut world = World::new();
     time::init_time(&mut world, &level);
-    world.insert(config);
+    weather::init_weather(&mut world, &level);
+    let config = shared_config.get();
+    world.insert(Arc::clone(&config));
+    view_distance::init_view_distance(&mut world, &config);
+    world.insert(shared_config);
     world.insert(player_count);
     world.insert(ioman);
     world.insert(TickCount::default());
@@ -366,21 +510,34 @@ fn init_world<'a, 'b>(
     world.register::<PacketCreatorComponent>();
     world.register::<SerializerComponent>();
 
-    let generator: Arc<dyn WorldGenerator> = match level.generator_type() {
-        LevelGeneratorType::Flat => Arc::new(SuperflatWorldGenerator {
-            options: level.clone().generator_options.unwrap_or_default(),
-        }),
-        LevelGeneratorType::Default => {
-            Arc::new(ComposableGenerator::default_with_seed(level.seed as u64))
-        }
-        _ => Arc::new(EmptyWorldGenerator {}),
-    };
+    let generator = worldgen::generator_for_level(&level);
+    world.insert(dimension::Dimensions::new(
+        dimension::DimensionSettings::new(
+            "overworld",
+            Dimension::Overwold,
+            Arc::clone(&generator),
+            &config.world.name,
+        ),
+    ));
     world.insert(level);
     world.insert(generator);
 
-    let mut dispatcher = DispatcherBuilder::new();
-
-    dispatcher.add(network::NetworkSystem, NETWORK, &[]);
+    // Systems run in parallel on the worker pool unless they
+    // access the same resources or components mutably. Barriers
+    // separate the stages of a tick: logic, which handles packets
+    // and ticks the world; handlers, which react to events from the
+    // logic stage in the same tick; and broadcast, which sends packets
+    // to clients. Within the broadcast stage, systems sending packets
+    // which clients must receive in a particular order declare
+    // explicit dependencies on each other.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.server.worker_threads)
+        .thread_name(|i| format!("Feather Worker #{}", i))
+        .build()
+        .expect("failed to create worker thread pool");
+    let mut dispatcher = DispatcherBuilder::new().with_pool(Arc::new(pool));
+
+    dispatcher.add_timed(network::NetworkSystem, NETWORK, &[]);
 
     blocks::init_logic(&mut dispatcher);
     physics::init_logic(&mut dispatcher);
@@ -388,7 +545,29 @@ fn init_world<'a, 'b>(
     player::init_logic(&mut dispatcher);
     chunk_logic::init_logic(&mut dispatcher);
     time::init_logic(&mut dispatcher);
+    worldedit::init_logic(&mut dispatcher);
     lighting::init_logic(&mut dispatcher);
+    scheduler::init_logic(&mut dispatcher);
+    console::init_logic(&mut dispatcher);
+    datapack::init_logic(&mut dispatcher);
+    admin::init_logic(&mut dispatcher);
+    dimension::init_logic(&mut dispatcher);
+    sleep::init_logic(&mut dispatcher);
+    portal::init_logic(&mut dispatcher);
+    weather::init_logic(&mut dispatcher);
+    view_distance::init_logic(&mut dispatcher);
+    map::init_logic(&mut dispatcher);
+    structure_block::init_logic(&mut dispatcher);
+    effect::init_logic(&mut dispatcher);
+    elytra::init_logic(&mut dispatcher);
+    sign::init_logic(&mut dispatcher);
+    beacon::init_logic(&mut dispatcher);
+    container::init_logic(&mut dispatcher);
+    furnace::init_logic(&mut dispatcher);
+    hopper::init_logic(&mut dispatcher);
+    combat::init_logic(&mut dispatcher);
+    mob::init_logic(&mut dispatcher);
+    spawning::init_logic(&mut dispatcher);
 
     dispatcher.add_barrier();
 
@@ -397,10 +576,34 @@ fn init_world<'a, 'b>(
     entity::init_handlers(&mut dispatcher);
     player::init_handlers(&mut dispatcher);
     chunk_logic::init_handlers(&mut dispatcher);
+    commands::init_handlers(&mut dispatcher);
+    reload::init_handlers(&mut dispatcher);
+    metrics::init_handlers(&mut dispatcher);
+    timings::init_handlers(&mut dispatcher);
+    shutdown::init_handlers(&mut dispatcher);
+    worldedit::init_handlers(&mut dispatcher);
+    loot::init_handlers(&mut dispatcher);
+    tool::init_handlers(&mut dispatcher);
+    recipe::init_handlers(&mut dispatcher);
+    portal::init_handlers(&mut dispatcher);
+    sleep::init_handlers(&mut dispatcher);
+    weather::init_handlers(&mut dispatcher);
+    structure_block::init_handlers(&mut dispatcher);
+    container::init_handlers(&mut dispatcher);
+    chest::init_handlers(&mut dispatcher);
+    furnace::init_handlers(&mut dispatcher);
+    cauldron::init_handlers(&mut dispatcher);
+    sign::init_handlers(&mut dispatcher);
+    rollback::init_handlers(&mut dispatcher);
+    lighting::init_handlers(&mut dispatcher);
+    redstone::init_handlers(&mut dispatcher);
+    interact::init_handlers(&mut dispatcher);
+    combat::init_handlers(&mut dispatcher);
+    mob::init_handlers(&mut dispatcher);
 
     // Player init dependency is so that player position is loaded
     // before the join handle runs.
-    dispatcher.add(
+    dispatcher.add_timed(
         joinhandler::JoinHandlerSystem,
         JOIN_HANDLER,
         &[NETWORK, PLAYER_INIT],
@@ -410,10 +613,15 @@ fn init_world<'a, 'b>(
 
     player::init_broadcast(&mut dispatcher);
     entity::init_broadcast(&mut dispatcher);
+    worldedit::init_broadcast(&mut dispatcher);
+    lighting::init_broadcast(&mut dispatcher);
 
     // Broadcast system needs to run last.
     dispatcher.add_barrier();
-    dispatcher.add(util::BroadcasterSystem, BROADCASTER, &[]);
+    dispatcher.add_timed(util::BroadcasterSystem, BROADCASTER, &[]);
+
+    plugin::init(&mut dispatcher);
+    script::init(&mut dispatcher);
 
     let mut dispatcher = dispatcher.build();
     dispatcher.setup(&mut world);
@@ -433,7 +641,10 @@ fn register_components(world: &mut World) {
     world.register::<PigComponent>();
     world.register::<RabbitComponent>();
     world.register::<SheepComponent>();
+    world.register::<SkeletonComponent>();
     world.register::<SquidComponent>();
+    world.register::<ZombieComponent>();
+    world.register::<ZombiePigmanComponent>();
 }
 
 fn init_log(config: &Config) {
@@ -446,7 +657,7 @@ fn init_log(config: &Config) {
         _ => panic!("Unknown log level {}", config.log.level),
     };
 
-    simple_logger::init_with_level(level).unwrap();
+    console::init_log(level);
 }
 
 /// Tries to load a server icon from the current directory.
@@ -488,14 +699,16 @@ pub fn current_time_in_millis() -> u64 {
 
 /// Disconnects the given player, removing them from the world.
 /// This operation is performed lazily.
-pub fn disconnect_player(player: Entity, reason: String, lazy: &LazyUpdate) {
+pub fn disconnect_player(player: Entity, reason: impl Into<Message>, lazy: &LazyUpdate) {
+    let reason = reason.into();
     lazy.exec_mut(move |world| {
-        let json = json!({
-            "text": reason,
-        });
+        let locale = world.entry::<Locale>().or_insert_with(Locale::default);
+        let json = locale.component(&reason);
+        let reason = locale.text(&reason);
+        drop(locale);
 
         let packet = DisconnectPlay::new(json.to_string());
-        send_packet_to_player(world.read_component().get(player).unwrap(), packet);
+        send_packet_immediately_to_player(world.read_component().get(player).unwrap(), packet);
 
         disconnect_player_without_packet(player, world, reason);
     })
@@ -538,7 +751,7 @@ mod tests {
 
     #[test]
     fn test_init_world() {
-        let config = Arc::new(Config::default());
+        let config = Arc::new(SharedConfig::new(Arc::new(Config::default())));
         let player_count = Arc::new(PlayerCount(AtomicUsize::new(0)));
         let server_icon = Arc::new(Some(String::from("server_icon")));
         let ioman = init_io_manager(
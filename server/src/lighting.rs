@@ -25,71 +25,137 @@
 //! Each algorithm is implemented in a separate function, and `LightingSystem`
 //! determines which to use based on the values of the block update event.
 //!
+//! Light decreases by one level for each block it travels, plus the
+//! opacity of the block it enters (see `BlockExt::opacity`). Water, ice
+//! and leaves are partially transparent, while slabs and stairs only stop
+//! light if they fill the whole block. Algorithms #3 and #4 are also used
+//! when a block becomes more or less opaque, respectively.
+//!
 //! If we are recalculating light for an entire chunk, e.g. when a chunk is generated,
 //! we first zero out light, then find all light sources in the chunk and perform
-//! algorithm #1 on them as if they had just been placed.
+//! algorithm #1 on them as if they had just been placed. This only depends on
+//! the chunk itself, so batches of chunks are lit in parallel.
+//!
+//! The same is done when a `BulkBlockUpdateEvent` indicates that too many
+//! blocks in a chunk have changed to handle them individually: the chunk and
+//! its neighbors are lit from scratch, after which light spreads across
+//! their borders.
+//!
+//! # Algorithms: sky light
+//! Sky light enters each column from the top at level 15 and travels
+//! straight down without losing strength until it reaches a block with
+//! a nonzero opacity, which reduces it by that many levels. From there,
+//! it spreads sideways and downwards like block light, which lights
+//! caves and the space below overhangs.
+//!
+//! * When a chunk is generated, the light of each column is filled
+//! from the top until it reaches the terrain. The light then spreads
+//! from the columns below which it can reach, i.e. those lower than
+//! one of their neighbors.
+//!
+//! * When a chunk is loaded, light spreads across its borders in both
+//! directions, since the chunk and its neighbors were lit without
+//! knowing about each other. The same is done for block light, which
+//! would otherwise leave dark seams at the borders.
+//!
+//! * When the opacity of a block changes, the block's new sky light
+//! is calculated from its neighbors. If it decreases, we perform flood
+//! fill and set any blocks which were lit through this block to 0, including
+//! the column of full light below it. Light then spreads again from the
+//! blocks at the edge of the darkened area. If it increases, light simply
+//! spreads from the block.
+//!
+//! Chunks changed in bulk are lit from scratch along with their neighbors,
+//! after which light spreads across the borders of those chunks.
+//!
+//! # Dimensions
+//! Dimensions without sky light, such as the Nether and the End, skip
+//! every sky light algorithm: their chunks are generated and loaded with
+//! no sky light, and block updates there only affect block light. How
+//! bright a light level appears depends on the dimension's ambient light,
+//! which clients apply themselves (see `DimensionSettings::brightness`).
+//!
+//! # Budget
+//! Block updates and chunks changed in bulk are queued in a `LightingQueue`,
+//! which coalesces repeated updates of the same block. Each tick, only as
+//! many updates as allowed by `lighting.max_updates_per_tick` are performed,
+//! so that large changes such as explosions are lit over several ticks
+//! instead of causing a lag spike.
+//!
+//! # Broadcasting
+//! Chunks keep track of the sections whose light changed. At the end of
+//! each tick, `LightBroadcastSystem` sends those sections to the players
+//! who have the chunk loaded, using a Chunk Data packet which isn't a full
+//! chunk. The protocol has no packet for light alone, so the blocks of the
+//! sections are sent as well, but the rest of the chunk is not.
+//!
+//! # Verification
+//! `verify_light` strips the light of chunks whose light is known to be
+//! correct, such as chunks of a vanilla world, recalculates it and reports
+//! every block whose light differs. The `test_vanilla_light` test runs it
+//! against the world in the directory given by `FEATHER_VANILLA_WORLD`.
 
-use crate::blocks::BlockUpdateEvent;
+use crate::blocks::{BlockUpdateEvent, BulkBlockUpdateEvent};
 use crate::chunk_logic::ChunkLoadEvent;
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::crash;
+use crate::dimension::{DimensionComponent, Dimensions, PRIMARY_DIMENSION};
+use crate::entity::{NamedComponent, PositionComponent};
+use crate::lang::{Locale, Message};
+use crate::metrics::METRICS;
+use crate::network::{send_packet_to_player, NetworkComponent};
 use crate::physics::chunks_within_distance;
-use crate::systems::LIGHTING;
+use crate::player::{ChunkPendingComponent, LoadedChunksComponent};
+use crate::systems::{LIGHTING, LIGHT_BROADCAST, RELIGHT_COMMAND, WORLDEDIT_FLUSH};
+use crate::timings::DispatcherBuilderExt;
 use arrayvec::ArrayVec;
-use failure::_core::marker::PhantomData;
+use feather_blocks::properties::MAX_OPACITY;
 use feather_blocks::{Block, BlockExt};
+use feather_core::network::packet::implementation::ChunkData;
 use feather_core::prelude::ChunkMap;
-use feather_core::world::chunk_relative_pos;
-use feather_core::{BlockPosition, Chunk, ChunkPosition};
-use hashbrown::HashSet;
+use feather_core::world::chunk::{BitArray, ChunkSection, HeightmapKind};
+use feather_core::world::{chunk_relative_pos, ChunkCache};
+use feather_core::{BlockPosition, Chunk, ChunkPosition, Dimension};
+use hashbrown::{HashMap, HashSet};
 use multimap::MultiMap;
+use rayon::prelude::*;
 use shrev::{EventChannel, ReaderId};
 use smallvec::SmallVec;
-use specs::{DispatcherBuilder, Read, System, Write};
+use specs::{DispatcherBuilder, Entity, Join, Read, ReadExpect, ReadStorage, System, World, Write};
 use std::collections::VecDeque;
+use std::iter;
+use std::mem;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-const MAX_TRAVEL_DISTANCE: u8 = 15;
+/// The maximum distance travelled by light from its source.
+pub const MAX_LIGHT_RADIUS: u8 = 15;
+
+/// The light level of blocks exposed to the sky.
+const MAX_SKY_LIGHT: u8 = 15;
 
 /// Lighter context, used to cache things during
 /// a lighting iteration.
 struct Context<'a> {
-    /// Reference to the current cached chunk.
-    /// This is used to avoid repetitive hashmap
-    /// accesses in the chunk map when groups
-    /// of clustered blocks are queried for.
-    current_chunk: *mut Chunk,
-    /// Chunk map. Raw pointers are used to bypass the borrow
-    /// checker, since `current_chunk` refers to the chunk map,
-    /// which isn't allowed.
-    chunk_map: *mut ChunkMap,
-    _phantom: PhantomData<&'a ()>,
+    /// Cache of the chunk map, which avoids repetitive
+    /// hashmap accesses when groups of clustered
+    /// blocks are queried for.
+    chunks: ChunkCache<'a>,
 }
 
 impl<'a> Context<'a> {
     fn new(chunk_map: &'a mut ChunkMap, start_chunk: ChunkPosition) -> Option<Self> {
-        let chunk_map = chunk_map as *mut ChunkMap;
-
-        // Safety: `chunk_map` is a valid pointer
-        // made from a mutable reference.
-        // It has not been modified since.
-        let current_chunk = unsafe { (*chunk_map).chunk_at_mut(start_chunk)? as *mut Chunk };
+        let mut chunks = ChunkCache::new(chunk_map);
+        chunks.chunk_at_mut(start_chunk)?;
 
-        Some(Self {
-            current_chunk,
-            chunk_map,
-            _phantom: PhantomData,
-        })
+        Some(Self { chunks })
     }
 
-    fn chunk_at_mut(&mut self, pos: ChunkPosition) -> Option<&'a mut Chunk> {
-        if pos == (unsafe { &*self.current_chunk }).position() {
-            Some(unsafe { &mut *self.current_chunk })
-        } else {
-            // Safety: While `self.current_chunk` refers to the chunk map,
-            // it is never accessed between mutations of the chunk
-            // map itself, since `Context` holds a unique reference to the
-            // map and never mutates it.
-            self.current_chunk = unsafe { (*self.chunk_map).chunk_at_mut(pos)? };
-            Some(unsafe { &mut *self.current_chunk })
-        }
+    fn chunk_at_mut(&mut self, pos: ChunkPosition) -> Option<&mut Chunk> {
+        self.chunks.chunk_at_mut(pos)
     }
 
     fn block_light_at(&mut self, pos: BlockPosition) -> u8 {
@@ -109,22 +175,34 @@ impl<'a> Context<'a> {
         }
     }
 
-    fn block_at(&mut self, pos: BlockPosition) -> Block {
+    fn sky_light_at(&mut self, pos: BlockPosition) -> u8 {
         match self.chunk_at_mut(pos.chunk_pos()) {
             Some(chunk) => {
                 let (x, y, z) = chunk_relative_pos(pos);
-                chunk.block_at(x, y, z)
+                chunk.sky_light_at(x, y, z)
             }
-            None => Block::Air,
+            None => 0,
         }
     }
 
-    fn set_block_at(&mut self, pos: BlockPosition, block: Block) {
+    fn set_sky_light_at(&mut self, pos: BlockPosition, value: u8) {
         if let Some(chunk) = self.chunk_at_mut(pos.chunk_pos()) {
             let (x, y, z) = chunk_relative_pos(pos);
-            chunk.set_block_at(x, y, z, block);
+            chunk.set_sky_light_at(x, y, z, value);
         }
     }
+
+    fn is_loaded(&mut self, pos: BlockPosition) -> bool {
+        self.chunks.is_loaded(pos.chunk_pos())
+    }
+
+    fn block_at(&mut self, pos: BlockPosition) -> Block {
+        self.chunks.block_at(pos).unwrap_or(Block::Air)
+    }
+
+    fn set_block_at(&mut self, pos: BlockPosition, block: Block) {
+        let _ = self.chunks.set_block_at(pos, block);
+    }
 }
 
 /// Contains a map storing light sources for each chunk.
@@ -153,11 +231,106 @@ impl ChunkLights {
     }
 }
 
+/// The cost of relighting a chunk changed in bulk,
+/// in terms of the per-tick lighting budget.
+const RELIGHT_COST: usize = 64;
+
+/// Lighting work which has not been performed yet.
+///
+/// Updates of the same block are coalesced: only the
+/// block before the first and after the last update
+/// matter. Updates in chunks which are pending a relight
+/// are dropped, since the relight covers them.
+#[derive(Debug, Default)]
+pub struct LightingQueue {
+    order: VecDeque<BlockPosition>,
+    /// The old and new block of each pending update.
+    updates: HashMap<BlockPosition, (Block, Block)>,
+    chunk_order: VecDeque<ChunkPosition>,
+    chunks: HashSet<ChunkPosition>,
+}
+
+impl LightingQueue {
+    /// Queues an update of the block at `pos`.
+    pub fn push_update(&mut self, pos: BlockPosition, old_block: Block, new_block: Block) {
+        if self.chunks.contains(&pos.chunk_pos()) {
+            return;
+        }
+
+        match self.updates.get_mut(&pos) {
+            Some(update) => update.1 = new_block,
+            None => {
+                self.updates.insert(pos, (old_block, new_block));
+                self.order.push_back(pos);
+            }
+        }
+    }
+
+    /// Queues a relight of the given chunk.
+    pub fn push_chunk(&mut self, chunk: ChunkPosition) {
+        if self.chunks.insert(chunk) {
+            self.chunk_order.push_back(chunk);
+            self.updates.retain(|pos, _| pos.chunk_pos() != chunk);
+        }
+    }
+
+    /// Removes the oldest pending update, returning the
+    /// position of the block along with its old and new value.
+    pub fn pop_update(&mut self) -> Option<(BlockPosition, Block, Block)> {
+        while let Some(pos) = self.order.pop_front() {
+            // Updates dropped by a relight are still in `order`.
+            if let Some((old_block, new_block)) = self.updates.remove(&pos) {
+                return Some((pos, old_block, new_block));
+            }
+        }
+        None
+    }
+
+    /// Removes the oldest chunk pending a relight.
+    pub fn pop_chunk(&mut self) -> Option<ChunkPosition> {
+        let chunk = self.chunk_order.pop_front()?;
+        self.chunks.remove(&chunk);
+        Some(chunk)
+    }
+
+    /// Returns the number of pending updates,
+    /// counting relights as `RELIGHT_COST` updates.
+    pub fn len(&self) -> usize {
+        self.updates.len() + self.chunks.len() * RELIGHT_COST
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.chunks.is_empty()
+    }
+}
+
+/// Event triggered to recalculate the light of a
+/// chunk from scratch, e.g. because it is broken.
+#[derive(Debug, Clone)]
+pub struct RelightEvent {
+    pub chunk: ChunkPosition,
+}
+
+/// Event triggered when the light of a chunk has been
+/// recalculated from scratch, either because of a
+/// `RelightEvent` or a `BulkBlockUpdateEvent`.
+#[derive(Debug, Clone)]
+pub struct ChunkRelitEvent {
+    pub chunk: ChunkPosition,
+}
+
 /// System for handling all lighting tasks.
+///
+/// Block updates and relights are queued and performed
+/// within the budget set by `lighting.max_updates_per_tick`,
+/// so that large changes are spread across several ticks.
 #[derive(Default)]
 pub struct LightingSystem {
     update_reader: Option<ReaderId<BlockUpdateEvent>>,
     load_reader: Option<ReaderId<ChunkLoadEvent>>,
+    bulk_reader: Option<ReaderId<BulkBlockUpdateEvent>>,
+    relight_reader: Option<ReaderId<RelightEvent>>,
+    queue: LightingQueue,
 }
 
 impl<'a> System<'a> for LightingSystem {
@@ -166,13 +339,33 @@ impl<'a> System<'a> for LightingSystem {
         Write<'a, ChunkLights>,
         Read<'a, EventChannel<ChunkLoadEvent>>,
         Read<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, EventChannel<BulkBlockUpdateEvent>>,
+        Read<'a, EventChannel<RelightEvent>>,
+        Write<'a, EventChannel<ChunkRelitEvent>>,
+        Read<'a, Arc<Config>>,
+        ReadExpect<'a, Dimensions>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut chunk_map, mut chunk_lights, load_events, update_events) = data;
+        let (
+            mut chunk_map,
+            mut chunk_lights,
+            load_events,
+            update_events,
+            bulk_events,
+            relight_events,
+            mut relit_events,
+            config,
+            dimensions,
+        ) = data;
+
+        let sky_light = dimensions
+            .get(PRIMARY_DIMENSION)
+            .map_or(true, |settings| settings.has_skylight);
 
         // Update `ChunkLights` with newly loaded chunks
         for load in load_events.read(self.load_reader.as_mut().unwrap()) {
+            let _context = crash::context(crash::Context::Chunk(load.pos));
             // Find all lights within this chunk.
             if let Some(chunk) = chunk_map.chunk_at(load.pos) {
                 let lights = find_lights_in_chunk(chunk);
@@ -180,67 +373,562 @@ impl<'a> System<'a> for LightingSystem {
                     .into_iter()
                     .for_each(|light| chunk_lights.0.insert(load.pos, light));
             }
+
+            // Connect the light of the chunk with its neighbors,
+            // which were lit without knowing about each other.
+            if let Some(mut ctx) = Context::new(&mut chunk_map, load.pos) {
+                spread_block_light_across_borders(&mut ctx, load.pos);
+                if sky_light {
+                    spread_sky_light_across_borders(&mut ctx, load.pos);
+                }
+            }
         }
 
-        // Perform lighting updates.
+        for event in bulk_events.read(self.bulk_reader.as_mut().unwrap()) {
+            self.queue.push_chunk(event.chunk);
+        }
+        for event in relight_events.read(self.relight_reader.as_mut().unwrap()) {
+            self.queue.push_chunk(event.chunk);
+        }
         for event in update_events.read(self.update_reader.as_mut().unwrap()) {
-            let mut ctx = match Context::new(&mut chunk_map, event.pos.chunk_pos()) {
-                Some(ctx) => ctx,
-                None => continue, // Unloaded chunk
+            self.queue
+                .push_update(event.pos, event.old_block, event.new_block);
+        }
+
+        // Relight chunks changed in bulk. At least one chunk
+        // is relit each tick, even if that exceeds the budget.
+        let budget = config.lighting.max_updates_per_tick.max(1);
+        let mut updates = 0;
+        let mut bulk_chunks = HashSet::new();
+        while updates == 0 || updates + RELIGHT_COST <= budget {
+            let chunk = match self.queue.pop_chunk() {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            bulk_chunks.insert(chunk);
+            updates += RELIGHT_COST;
+        }
+        if !bulk_chunks.is_empty() {
+            relight_chunks(&mut chunk_map, &mut chunk_lights, &bulk_chunks, sky_light);
+            relit_events.iter_write(
+                bulk_chunks
+                    .into_iter()
+                    .map(|chunk| ChunkRelitEvent { chunk }),
+            );
+        }
+
+        // Perform lighting updates.
+        while updates < budget {
+            let (pos, old_block, new_block) = match self.queue.pop_update() {
+                Some(update) => update,
+                None => break,
             };
+            updates += 1;
+            update_light(
+                &mut chunk_map,
+                &mut chunk_lights,
+                pos,
+                old_block,
+                new_block,
+                sky_light,
+            );
+        }
+
+        METRICS
+            .lighting_updates
+            .store(updates as u64, Ordering::Relaxed);
+        METRICS
+            .lighting_queue_depth
+            .store(self.queue.len() as u64, Ordering::Relaxed);
+    }
+
+    setup_impl!(update_reader, load_reader, bulk_reader, relight_reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(LightingSystem::default(), LIGHTING, &[WORLDEDIT_FLUSH]);
+}
+
+/// The radius used by `/relight` if none is given.
+pub const DEFAULT_RELIGHT_RADIUS: i32 = 2;
+
+/// The largest radius accepted by `/relight`.
+pub const MAX_RELIGHT_RADIUS: i32 = 8;
+
+/// A `/relight` command which hasn't completed yet.
+struct PendingRelight {
+    sender: Entity,
+    /// The chunks which haven't been relit yet.
+    remaining: HashSet<ChunkPosition>,
+    total: usize,
+    /// The number of quarters of the chunks
+    /// whose completion was reported.
+    reported: usize,
+}
+
+/// System implementing `/relight [radius]`, which recalculates
+/// the light of the chunks around the sender from scratch.
+///
+/// Chunks are relit by `LightingSystem` within its budget,
+/// so the sender is told about the progress in quarters.
+#[derive(Default)]
+pub struct RelightCommandSystem {
+    command_reader: Option<ReaderId<CommandEvent>>,
+    relit_reader: Option<ReaderId<ChunkRelitEvent>>,
+    pending: Vec<PendingRelight>,
+}
+
+impl<'a> System<'a> for RelightCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, EventChannel<ChunkRelitEvent>>,
+        Write<'a, EventChannel<RelightEvent>>,
+        Read<'a, ChunkMap>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            commands,
+            relit_events,
+            mut relight_events,
+            chunk_map,
+            config,
+            locale,
+            positions,
+            nameds,
+            networks,
+            consoles,
+        ) = data;
+
+        for event in relit_events.read(self.relit_reader.as_mut().unwrap()) {
+            for pending in &mut self.pending {
+                pending.remaining.remove(&event.chunk);
+            }
+        }
+        for pending in &mut self.pending {
+            let done = pending.total - pending.remaining.len();
+            let quarters = done * 4 / pending.total;
+            if quarters == pending.reported {
+                continue;
+            }
+            pending.reported = quarters;
 
-            // Determine which algorithm to use.
-            if event.old_block.light_emission() < event.new_block.light_emission() {
-                ctx.set_block_light_at(event.pos, event.new_block.light_emission());
-                emitting_creation(&mut ctx, event.pos);
-            } else if event.new_block.light_emission() == 0 && event.old_block.light_emission() > 0
-            {
-                ctx.set_block_light_at(event.pos, 0);
-                emitting_removal(&mut ctx, &chunk_lights, event.pos, event.old_block);
-            } else if event.old_block.is_opaque() && !event.new_block.is_opaque() {
-                opaque_non_emitting_removal(&mut ctx, event.pos);
+            let message = if pending.remaining.is_empty() {
+                Message::translate("feather.relight.done").with(pending.total)
             } else {
-                opaque_non_emitting_creation(&mut ctx, &chunk_lights, event.pos, event.new_block);
-            }
-
-            // Update `ChunkLights`.
-            if event.old_block.light_emission() != event.new_block.light_emission() {
-                if event.new_block.light_emission() == 0 {
-                    chunk_lights
-                        .0
-                        .get_vec_mut(&event.pos.chunk_pos())
-                        .unwrap()
-                        .retain(|pos| *pos != event.pos);
-                } else if event.old_block.light_emission() == 0 {
-                    chunk_lights.0.insert(event.pos.chunk_pos(), event.pos);
+                Message::translate("feather.relight.progress")
+                    .with(done)
+                    .with(pending.total)
+            };
+            reply(pending.sender, &networks, &consoles, &locale, message);
+        }
+        self.pending.retain(|pending| !pending.remaining.is_empty());
+
+        for event in commands.read(self.command_reader.as_mut().unwrap()) {
+            if event.name != "relight" {
+                continue;
+            }
+
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let chunks = match relight_chunks_for(event, &positions, &chunk_map) {
+                Ok(chunks) => chunks,
+                Err(message) => {
+                    reply(event.sender, &networks, &consoles, &locale, message);
+                    continue;
                 }
+            };
+
+            if chunks.is_empty() {
+                let message = Message::translate("feather.relight.done").with(0);
+                reply(event.sender, &networks, &consoles, &locale, message);
+                continue;
             }
+
+            relight_events.iter_write(chunks.iter().map(|chunk| RelightEvent { chunk: *chunk }));
+            let message = Message::translate("feather.relight.started").with(chunks.len());
+            reply(event.sender, &networks, &consoles, &locale, message);
+
+            self.pending.push(PendingRelight {
+                sender: event.sender,
+                total: chunks.len(),
+                remaining: chunks,
+                reported: 0,
+            });
         }
     }
 
-    setup_impl!(update_reader, load_reader);
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.command_reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+        self.relit_reader = Some(
+            world
+                .fetch_mut::<EventChannel<ChunkRelitEvent>>()
+                .register_reader(),
+        );
+
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("relight");
+    }
 }
 
-pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(LightingSystem::default(), LIGHTING, &[]);
+/// Returns the loaded chunks to relight for a
+/// `/relight` command, or the error to reply with.
+fn relight_chunks_for(
+    event: &CommandEvent,
+    positions: &ReadStorage<PositionComponent>,
+    chunk_map: &ChunkMap,
+) -> Result<HashSet<ChunkPosition>, Message> {
+    let radius = match event.args.as_slice() {
+        [] => DEFAULT_RELIGHT_RADIUS,
+        [radius] => match radius.parse() {
+            Ok(radius) if radius >= 0 && radius <= MAX_RELIGHT_RADIUS => radius,
+            _ => {
+                return Err(Message::translate("feather.relight.invalid_radius")
+                    .with(radius)
+                    .with(MAX_RELIGHT_RADIUS))
+            }
+        },
+        _ => return Err(usage("/relight [radius]")),
+    };
+    let center = positions
+        .get(event.sender)
+        .map(|position| position.current.chunk_pos())
+        .ok_or_else(|| Message::translate("feather.relight.no_position"))?;
+
+    let mut chunks = HashSet::new();
+    chunks.insert(center);
+    Ok(chunks_around(&chunks, radius)
+        .into_iter()
+        .filter(|chunk| chunk_map.chunk_at(*chunk).is_some())
+        .collect())
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(RelightCommandSystem::default(), RELIGHT_COMMAND, &[]);
+}
+
+/// System which sends the sections of chunks whose light
+/// changed during this tick to the players who have them loaded.
+///
+/// Only players in the primary dimension, which is the only one
+/// simulated, receive the updates. Players who haven't been sent
+/// a chunk yet receive its new light along with the rest of it.
+pub struct LightBroadcastSystem;
+
+impl<'a> System<'a> for LightBroadcastSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        ReadExpect<'a, Dimensions>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, LoadedChunksComponent>,
+        ReadStorage<'a, ChunkPendingComponent>,
+        ReadStorage<'a, DimensionComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut chunk_map, dimensions, networks, loaded_chunks, pendings, dimension_comps) = data;
+
+        let sky_light = dimensions
+            .get(PRIMARY_DIMENSION)
+            .map_or(true, |settings| settings.environment == Dimension::Overwold);
+
+        // Batch the changes of each chunk into a single packet.
+        let mut packets = HashMap::new();
+        for chunk in chunk_map.chunks_mut().values_mut() {
+            let dirty = chunk.take_dirty_light();
+            if dirty != 0 {
+                packets.insert(chunk.position(), light_update(chunk, dirty, sky_light));
+            }
+        }
+        if packets.is_empty() {
+            return;
+        }
+
+        for (network, loaded_chunks, pending, dimension) in (
+            &networks,
+            &loaded_chunks,
+            pendings.maybe(),
+            dimension_comps.maybe(),
+        )
+            .join()
+        {
+            if DimensionComponent::of(dimension) != PRIMARY_DIMENSION {
+                continue;
+            }
+            for (pos, packet) in &packets {
+                let pending = pending.map_or(false, |pending| pending.pending.contains(pos));
+                if loaded_chunks.is_loaded(*pos) && !pending {
+                    send_packet_to_player(network, packet.clone());
+                }
+            }
+        }
+    }
+}
+
+pub fn init_broadcast(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(LightBroadcastSystem, LIGHT_BROADCAST, &[]);
+}
+
+/// Returns a Chunk Data packet containing only the
+/// sections of `chunk` in the bit mask `sections`.
+fn light_update(chunk: &Chunk, sections: u16, sky_light: bool) -> ChunkData {
+    let mut partial = Chunk::new(chunk.position());
+    for (i, section) in chunk.sections().into_iter().enumerate() {
+        if sections & (1 << i) == 0 {
+            continue;
+        }
+        // Sections may have been elided since their light changed,
+        // in which case clients still need to receive their new light.
+        let section = section.cloned().unwrap_or_else(ChunkSection::elided);
+        partial.set_section_at(i, Some(section));
+    }
+
+    let mut packet = ChunkData::new(partial, sky_light);
+    packet.full_chunk = false;
+    packet
+}
+
+/// Updates block and sky light after the block
+/// at `pos` changed from `old_block` to `new_block`.
+fn update_light(
+    chunk_map: &mut ChunkMap,
+    chunk_lights: &mut ChunkLights,
+    pos: BlockPosition,
+    old_block: Block,
+    new_block: Block,
+    sky_light: bool,
+) {
+    if old_block == new_block {
+        return;
+    }
+
+    let _context = crash::context(crash::Context::Block(pos));
+    let mut ctx = match Context::new(chunk_map, pos.chunk_pos()) {
+        Some(ctx) => ctx,
+        None => return, // Unloaded chunk
+    };
+
+    // Determine which algorithm to use.
+    if old_block.emitted_light() < new_block.emitted_light() {
+        ctx.set_block_light_at(pos, new_block.emitted_light());
+        emitting_creation(&mut ctx, pos);
+    } else if new_block.emitted_light() == 0 && old_block.emitted_light() > 0 {
+        ctx.set_block_light_at(pos, 0);
+        emitting_removal(&mut ctx, chunk_lights, pos, old_block);
+    } else if new_block.opacity() < old_block.opacity() {
+        opaque_non_emitting_removal(&mut ctx, pos);
+    } else {
+        opaque_non_emitting_creation(&mut ctx, chunk_lights, pos, new_block);
+    }
+
+    if sky_light && old_block.opacity() != new_block.opacity() {
+        update_sky_light(&mut ctx, pos);
+    }
+
+    // Update `ChunkLights`.
+    if old_block.emitted_light() != new_block.emitted_light() {
+        if new_block.emitted_light() == 0 {
+            if let Some(lights) = chunk_lights.0.get_vec_mut(&pos.chunk_pos()) {
+                lights.retain(|light| *light != pos);
+            }
+        } else if old_block.emitted_light() == 0 {
+            chunk_lights.0.insert(pos.chunk_pos(), pos);
+        }
+    }
 }
 
+/// Returns the absolute positions of all light sources in a chunk.
 fn find_lights_in_chunk(chunk: &Chunk) -> Vec<BlockPosition> {
     let mut res = vec![];
+    let offset_x = chunk.position().x * 16;
+    let offset_z = chunk.position().z * 16;
 
     for x in 0..16 {
         for y in 0..256 {
             for z in 0..16 {
                 let block = chunk.block_at(x, y, z);
 
-                let emission = block.light_emission();
+                let emission = block.emitted_light();
                 if emission > 0 {
-                    res.push(BlockPosition::new(x as i32, y as i32, z as i32));
+                    res.push(BlockPosition::new(
+                        offset_x + x as i32,
+                        y as i32,
+                        offset_z + z as i32,
+                    ));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Recalculates block light from scratch around chunks
+/// in which many blocks have changed.
+fn relight_chunks(
+    chunk_map: &mut ChunkMap,
+    chunk_lights: &mut ChunkLights,
+    chunks: &HashSet<ChunkPosition>,
+    sky_light: bool,
+) {
+    // Find the lights in the changed chunks.
+    for pos in chunks {
+        chunk_lights.0.remove(pos);
+        if let Some(chunk) = chunk_map.chunk_at(*pos) {
+            for light in find_lights_in_chunk(chunk) {
+                chunk_lights.0.insert(*pos, light);
+            }
+        }
+    }
+
+    // Light travels at most 15 blocks, so only the changed
+    // chunks and their neighbors can be affected. They are
+    // taken out of the chunk map and lit from scratch in
+    // parallel, ignoring each other.
+    let cleared = chunks_around(chunks, 1);
+    let mut lit: Vec<Chunk> = cleared
+        .iter()
+        .filter_map(|pos| chunk_map.unload_chunk_at(*pos))
+        .collect();
+    light_chunks(&mut lit, sky_light);
+    for chunk in lit {
+        chunk_map.set_chunk_at(chunk.position(), chunk);
+    }
+
+    // Then, light spreads across their borders, connecting them
+    // with each other and with the chunks further away, which
+    // the changes can't have affected.
+    for pos in &cleared {
+        let _context = crash::context(crash::Context::Chunk(*pos));
+        let mut ctx = continue_if_none!(Context::new(chunk_map, *pos));
+        spread_block_light_across_borders(&mut ctx, *pos);
+        if sky_light {
+            spread_sky_light_across_borders(&mut ctx, *pos);
+        }
+    }
+}
+
+/// The kinds of light compared by `verify_light`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    Block,
+    Sky,
+}
+
+/// A block whose recalculated light differs from its expected light.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightMismatch {
+    pub pos: BlockPosition,
+    pub kind: LightKind,
+    pub expected: u8,
+    pub actual: u8,
+}
+
+/// Strips the light of chunks whose light is known to be correct,
+/// recalculates it as if the chunks had been changed in bulk,
+/// and returns the blocks whose light differs, ordered by position.
+///
+/// Only chunks whose neighbors are all among the given chunks are
+/// compared, since the light near the edge of the area depends on
+/// chunks which aren't there.
+pub fn verify_light(chunks: Vec<Chunk>, sky_light: bool) -> Vec<LightMismatch> {
+    let expected: HashMap<ChunkPosition, Chunk> = chunks
+        .iter()
+        .map(|chunk| (chunk.position(), chunk.clone()))
+        .collect();
+    let positions: HashSet<ChunkPosition> = expected.keys().copied().collect();
+
+    let mut chunk_map = ChunkMap::new();
+    for mut chunk in chunks {
+        for section in chunk.sections_mut().into_iter().flatten() {
+            *section.block_light_mut() = BitArray::new(4, 4096);
+            *section.sky_light_mut() = BitArray::new(4, 4096);
+        }
+        chunk_map.set_chunk_at(chunk.position(), chunk);
+    }
+    relight_chunks(
+        &mut chunk_map,
+        &mut ChunkLights::default(),
+        &positions,
+        sky_light,
+    );
+
+    let mut mismatches = vec![];
+    for (pos, expected) in &expected {
+        let complete = chunks_around(&iter::once(*pos).collect(), 1)
+            .iter()
+            .all(|neighbor| positions.contains(neighbor));
+        if !complete {
+            continue;
+        }
+
+        let actual = chunk_map.chunk_at(*pos).unwrap();
+        for x in 0..16 {
+            for y in 0..256 {
+                for z in 0..16 {
+                    let block_pos =
+                        BlockPosition::new(pos.x * 16 + x as i32, y as i32, pos.z * 16 + z as i32);
+                    let mut compare = |kind, expected, actual| {
+                        if expected != actual {
+                            mismatches.push(LightMismatch {
+                                pos: block_pos,
+                                kind,
+                                expected,
+                                actual,
+                            });
+                        }
+                    };
+
+                    compare(
+                        LightKind::Block,
+                        expected.block_light_at(x, y, z),
+                        actual.block_light_at(x, y, z),
+                    );
+                    if sky_light {
+                        compare(
+                            LightKind::Sky,
+                            expected.sky_light_at(x, y, z),
+                            actual.sky_light_at(x, y, z),
+                        );
+                    }
                 }
             }
         }
     }
 
+    mismatches.sort_by_key(|mismatch| (mismatch.pos.x, mismatch.pos.z, mismatch.pos.y));
+    mismatches
+}
+
+/// Returns the chunks within `radius` chunks
+/// of any of the given chunks.
+fn chunks_around(chunks: &HashSet<ChunkPosition>, radius: i32) -> HashSet<ChunkPosition> {
+    let mut res = HashSet::new();
+    for chunk in chunks {
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                res.insert(ChunkPosition::new(chunk.x + x, chunk.z + z));
+            }
+        }
+    }
     res
 }
 
@@ -264,13 +952,13 @@ fn emitting_removal(
     old_block: Block,
 ) {
     // Perform flood fill and set all blocks affected by the old light to 0 light.
-    flood_fill(context, position, old_block.light_emission(), |ctx, pos| {
+    flood_fill(context, position, old_block.emitted_light(), |ctx, pos| {
         ctx.set_block_light_at(pos, 0);
     });
 
     // For all lights which could have affected the blocks we just set to 0,
     // recalculate lighting using algorithm #1.
-    let nearby_lights = chunk_lights.lights_within_distance(position, MAX_TRAVEL_DISTANCE * 2);
+    let nearby_lights = chunk_lights.lights_within_distance(position, MAX_LIGHT_RADIUS * 2);
 
     nearby_lights.into_iter().for_each(|light| {
         if light != position {
@@ -291,7 +979,7 @@ fn opaque_non_emitting_creation(
     // faking that the block was never created.
     context.set_block_at(position, Block::Air);
 
-    let nearby_lights = chunk_lights.lights_within_distance(position, MAX_TRAVEL_DISTANCE);
+    let nearby_lights = chunk_lights.lights_within_distance(position, MAX_LIGHT_RADIUS);
 
     nearby_lights.iter().for_each(|light| {
         let block = context.block_at(*light);
@@ -319,30 +1007,361 @@ fn opaque_non_emitting_removal(context: &mut Context, position: BlockPosition) {
     }
 }
 
+/// Returns the light received by a block with the given
+/// opacity from an adjacent block with the given light level.
+fn light_through(light: u8, opacity: u8) -> u8 {
+    light.saturating_sub(opacity.saturating_add(1))
+}
+
+/// Returns the sky light received by a block with the given opacity
+/// from an adjacent block with the given light level. Full sky light
+/// travelling downwards only decreases by the opacity of the block.
+fn sky_light_through(light: u8, downwards: bool, opacity: u8) -> u8 {
+    if downwards && light == MAX_SKY_LIGHT {
+        light.saturating_sub(opacity)
+    } else {
+        light_through(light, opacity)
+    }
+}
+
+/// Calculates the block and sky light of a chunk from
+/// scratch. Its neighbors are ignored, since they may
+/// not have been generated yet; light spreads across the
+/// borders of the chunk once it is loaded.
+///
+/// If `sky_light` is `false`, the chunk is left
+/// without any sky light instead.
+pub fn calculate_light(chunk: &mut Chunk, sky_light: bool) {
+    let pos = chunk.position();
+    let mut chunk_map = ChunkMap::new();
+    chunk_map.set_chunk_at(pos, mem::replace(chunk, Chunk::new(pos)));
+
+    let mut ctx = Context::new(&mut chunk_map, pos).unwrap();
+    fill_block_light(&mut ctx, pos);
+    if sky_light {
+        fill_sky_light(&mut ctx, pos);
+    }
+    drop(ctx);
+
+    *chunk = chunk_map.unload_chunk_at(pos).unwrap();
+    if !sky_light {
+        clear_sky_light(chunk);
+    }
+}
+
+/// Calculates the light of a batch of chunks using
+/// `calculate_light`, in parallel on the Rayon thread pool.
+pub fn light_chunks(chunks: &mut [Chunk], sky_light: bool) {
+    chunks
+        .par_iter_mut()
+        .for_each(|chunk| calculate_light(chunk, sky_light));
+}
+
+/// Sets the sky light of every section of a chunk to 0, for
+/// dimensions without sky light such as the Nether and the End.
+///
+/// Elided sections are not allocated, so they still report
+/// full sky light.
+pub fn clear_sky_light(chunk: &mut Chunk) {
+    for section in chunk.sections_mut().into_iter().flatten() {
+        *section.sky_light_mut() = BitArray::new(4, 4096);
+    }
+}
+
+/// Clears the block light of a chunk and propagates
+/// the light of the light sources within it.
+fn fill_block_light(ctx: &mut Context, chunk_pos: ChunkPosition) {
+    let chunk = match ctx.chunk_at_mut(chunk_pos) {
+        Some(chunk) => chunk,
+        None => return,
+    };
+
+    for section in chunk.sections_mut().into_iter().flatten() {
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    section.set_block_light_at(x, y, z, 0);
+                }
+            }
+        }
+    }
+
+    for light in find_lights_in_chunk(chunk) {
+        let emission = ctx.block_at(light).emitted_light();
+        ctx.set_block_light_at(light, emission);
+        emitting_creation(ctx, light);
+    }
+}
+
+/// Fills the sky light of each column of a chunk from the
+/// top, and then spreads it to the blocks next to the columns.
+fn fill_sky_light(ctx: &mut Context, chunk_pos: ChunkPosition) {
+    let chunk = match ctx.chunk_at_mut(chunk_pos) {
+        Some(chunk) => chunk,
+        None => return,
+    };
+
+    // The height of each column, above which
+    // all blocks are exposed to the sky.
+    let mut heights = [[0; 16]; 16];
+    for x in 0..16 {
+        for z in 0..16 {
+            // Above the world surface there are only air
+            // blocks, so light doesn't change there.
+            let surface = chunk.height_at(HeightmapKind::WorldSurface, x, z);
+            for y in surface..256 {
+                chunk.set_sky_light_at(x, y, z, MAX_SKY_LIGHT);
+            }
+
+            let mut light = MAX_SKY_LIGHT;
+            for y in (0..surface).rev() {
+                if light > 0 {
+                    let opacity = chunk.block_at(x, y, z).opacity();
+                    if light == MAX_SKY_LIGHT && opacity > 0 {
+                        heights[x][z] = y + 1;
+                    }
+                    light = sky_light_through(light, true, opacity);
+                }
+                chunk.set_sky_light_at(x, y, z, light);
+            }
+        }
+    }
+
+    // Light only spreads sideways below the highest of
+    // a column and its neighbors; above, all of them
+    // are fully lit.
+    let (offset_x, offset_z) = (chunk_pos.x * 16, chunk_pos.z * 16);
+    let mut queue = VecDeque::new();
+    for x in 0..16 {
+        for z in 0..16 {
+            let top = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .filter_map(|(dx, dz)| {
+                    let row = heights.get((x as i32 + dx) as usize)?;
+                    row.get((z as i32 + dz) as usize)
+                })
+                .max()
+                .copied()
+                .unwrap_or(0);
+
+            for y in 0..top {
+                if chunk.sky_light_at(x, y, z) > 1 {
+                    queue.push_back(BlockPosition::new(
+                        offset_x + x as i32,
+                        y as i32,
+                        offset_z + z as i32,
+                    ));
+                }
+            }
+        }
+    }
+
+    spread_sky_light(ctx, queue);
+}
+
+/// Spreads sky light from the given blocks
+/// to their neighbors, only ever increasing it.
+fn spread_sky_light(ctx: &mut Context, mut queue: VecDeque<BlockPosition>) {
+    while let Some(pos) = queue.pop_front() {
+        let light = ctx.sky_light_at(pos);
+        if light <= 1 {
+            continue;
+        }
+
+        for neighbor in adjacent_blocks(pos) {
+            if !ctx.is_loaded(neighbor) {
+                continue;
+            }
+            let opacity = ctx.block_at(neighbor).opacity();
+            let value = sky_light_through(light, neighbor.y < pos.y, opacity);
+            if value > ctx.sky_light_at(neighbor) {
+                ctx.set_sky_light_at(neighbor, value);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+}
+
+/// Spreads sky light across the borders between a chunk
+/// and its loaded neighbors, in both directions.
+fn spread_sky_light_across_borders(ctx: &mut Context, chunk_pos: ChunkPosition) {
+    let queue = border_seeds(ctx, chunk_pos, |ctx, pos| ctx.sky_light_at(pos));
+    spread_sky_light(ctx, queue);
+}
+
+/// Spreads block light across the borders between a chunk
+/// and its loaded neighbors, in both directions.
+fn spread_block_light_across_borders(ctx: &mut Context, chunk_pos: ChunkPosition) {
+    let queue = border_seeds(ctx, chunk_pos, |ctx, pos| ctx.block_light_at(pos));
+    spread_block_light(ctx, queue);
+}
+
+/// Returns the blocks at the borders of a chunk whose light,
+/// as returned by `light_at`, should spread to the adjacent
+/// block on the other side of the border.
+fn border_seeds<F>(
+    ctx: &mut Context,
+    chunk_pos: ChunkPosition,
+    mut light_at: F,
+) -> VecDeque<BlockPosition>
+where
+    F: FnMut(&mut Context, BlockPosition) -> u8,
+{
+    let (min_x, min_z) = (chunk_pos.x * 16, chunk_pos.z * 16);
+    let mut queue = VecDeque::new();
+
+    for i in 0..16 {
+        // Pairs of columns inside and outside the chunk.
+        let edges = [
+            ((min_x, min_z + i), (min_x - 1, min_z + i)),
+            ((min_x + 15, min_z + i), (min_x + 16, min_z + i)),
+            ((min_x + i, min_z), (min_x + i, min_z - 1)),
+            ((min_x + i, min_z + 15), (min_x + i, min_z + 16)),
+        ];
+
+        for ((inside_x, inside_z), (outside_x, outside_z)) in edges.iter().copied() {
+            if !ctx.is_loaded(BlockPosition::new(outside_x, 0, outside_z)) {
+                continue;
+            }
+
+            for y in 0..256 {
+                let inside = BlockPosition::new(inside_x, y, inside_z);
+                let outside = BlockPosition::new(outside_x, y, outside_z);
+                let inside_light = light_at(ctx, inside);
+                let outside_light = light_at(ctx, outside);
+                if inside_light > outside_light + 1 {
+                    queue.push_back(inside);
+                } else if outside_light > inside_light + 1 {
+                    queue.push_back(outside);
+                }
+            }
+        }
+    }
+
+    queue
+}
+
+/// Spreads block light from the given blocks
+/// to their neighbors, only ever increasing it.
+fn spread_block_light(ctx: &mut Context, mut queue: VecDeque<BlockPosition>) {
+    while let Some(pos) = queue.pop_front() {
+        let light = ctx.block_light_at(pos);
+        if light <= 1 {
+            continue;
+        }
+
+        for neighbor in adjacent_blocks(pos) {
+            if !ctx.is_loaded(neighbor) {
+                continue;
+            }
+            let value = light_through(light, ctx.block_at(neighbor).opacity());
+            if value > ctx.block_light_at(neighbor) {
+                ctx.set_block_light_at(neighbor, value);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+}
+
+/// Returns the sky light of the block at `position`
+/// as determined by the blocks around it.
+fn sky_light_for_block(ctx: &mut Context, position: BlockPosition) -> u8 {
+    let opacity = ctx.block_at(position).opacity();
+    if opacity >= MAX_OPACITY {
+        return 0;
+    }
+
+    // The top of the world is exposed to the sky.
+    let sky = if position.y == 255 {
+        sky_light_through(MAX_SKY_LIGHT, true, opacity)
+    } else {
+        0
+    };
+
+    adjacent_blocks(position)
+        .into_iter()
+        .map(|pos| {
+            let light = ctx.sky_light_at(pos);
+            sky_light_through(light, pos.y > position.y, opacity)
+        })
+        .max()
+        .unwrap_or(0)
+        .max(sky)
+}
+
+/// Updates sky light after the block at `position`
+/// changed its opacity.
+fn update_sky_light(ctx: &mut Context, position: BlockPosition) {
+    let old = ctx.sky_light_at(position);
+    let new = sky_light_for_block(ctx, position);
+
+    if new > old {
+        ctx.set_sky_light_at(position, new);
+        spread_sky_light(ctx, vec![position].into());
+        return;
+    }
+    if new == old {
+        return;
+    }
+
+    // Remove the light which passed through the block. Blocks
+    // which are at least as bright as the removed light have
+    // another source, so light spreads from them again.
+    let mut removal = VecDeque::new();
+    let mut relight = VecDeque::new();
+    ctx.set_sky_light_at(position, 0);
+    removal.push_back((position, old));
+
+    while let Some((pos, level)) = removal.pop_front() {
+        for neighbor in adjacent_blocks(pos) {
+            let light = ctx.sky_light_at(neighbor);
+            if light == 0 {
+                continue;
+            }
+
+            let column = neighbor.y < pos.y && level == MAX_SKY_LIGHT && light == MAX_SKY_LIGHT;
+            if light < level || column {
+                ctx.set_sky_light_at(neighbor, 0);
+                removal.push_back((neighbor, light));
+            } else {
+                relight.push_back(neighbor);
+            }
+        }
+    }
+
+    let value = sky_light_for_block(ctx, position);
+    if value > 0 {
+        ctx.set_sky_light_at(position, value);
+        relight.push_back(position);
+    }
+    spread_sky_light(ctx, relight);
+}
+
 /// Returns the light value for the block at `position`,
 /// equivalent to the maximum light value of an adjacent block
-/// minus 1.
+/// minus 1 and the opacity of the block.
 fn light_value_for_block(context: &mut Context, position: BlockPosition) -> u8 {
     // Find highest light value of 6 adjacent blocks.
     let adjacent = adjacent_blocks(position);
-    let mut value = adjacent
+    let value = adjacent
         .into_iter()
         .map(|pos| context.block_light_at(pos))
         .max()
         .unwrap();
 
-    if value > 0 {
-        value -= 1;
-    }
-
-    value
+    light_through(value, context.block_at(position).opacity())
 }
 
 /// Performs flood fill starting at `start` and travelling up
-/// to `max_dist` blocks.
+/// to `max_dist` blocks, measured in Manhattan distance.
 ///
 /// For each block iterated over, the provided closure will be invoked.
-/// No block will be iterated more than once.
+/// No block will be iterated more than once. Opaque blocks and blocks
+/// further than `max_dist` from `start` are skipped, but the fill
+/// continues through every other block in range.
+///
+/// Light never travels further than `MAX_LIGHT_RADIUS`, but larger
+/// distances may be used for light with a greater range.
 fn flood_fill<F>(context: &mut Context, start: BlockPosition, max_dist: u8, mut func: F)
 where
     F: FnMut(&mut Context, BlockPosition),
@@ -357,20 +1376,12 @@ where
 
     queue.push_back(start);
 
-    let mut finished = false;
-
     while let Some(pos) = queue.pop_front() {
-        if finished {
-            break;
-        }
-
         let blocks = adjacent_blocks(pos);
 
         blocks.into_iter().for_each(|pos| {
             if pos.manhattan_distance(start) > max_dist as i32 {
-                // Finished
-                finished = true;
-                return;
+                return; // Out of range
             }
 
             // Skip if we already went over this block
@@ -405,13 +1416,21 @@ fn adjacent_blocks(to: BlockPosition) -> ArrayVec<[BlockPosition; 6]> {
     offsets
         .iter()
         .map(|(x, y, z)| BlockPosition::new(to.x + *x, to.y + *y, to.z + *z))
-        .filter(|pos| pos.y >= 0 && pos.y <= 256)
+        .filter(|pos| pos.y >= 0 && pos.y <= 255)
         .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::BlockUpdateCause;
+    use crate::testframework as t;
+    use feather_blocks::{StoneSlabData, StoneSlabType, WaterData};
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::implementation::ChatMessageClientbound;
+    use feather_core::network::packet::PacketType;
+    use feather_core::Packet;
+    use specs::WorldExt;
 
     #[test]
     fn test_context() {
@@ -436,7 +1455,7 @@ mod tests {
 
         let pos = BlockPosition::new(0, 100, 0);
         ctx.set_block_at(pos, Block::Glowstone);
-        ctx.set_block_light_at(pos, Block::Glowstone.light_emission());
+        ctx.set_block_light_at(pos, Block::Glowstone.emitted_light());
 
         emitting_creation(&mut ctx, pos);
 
@@ -479,6 +1498,13 @@ mod tests {
         });
 
         assert_eq!(count, 6);
+
+        // The fill doesn't stop at the first block out of range.
+        let mut count = 0;
+        flood_fill(&mut ctx, BlockPosition::new(100, 100, 100), 2, |_, _| {
+            count += 1
+        });
+        assert_eq!(count, 24);
     }
 
     #[test]
@@ -499,6 +1525,356 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_lights_in_chunk() {
+        let pos = ChunkPosition::new(-1, 2);
+        let mut chunk = Chunk::new(pos);
+        chunk.set_block_at(3, 10, 5, Block::Glowstone);
+
+        assert_eq!(
+            find_lights_in_chunk(&chunk),
+            vec![BlockPosition::new(-13, 10, 37)]
+        );
+    }
+
+    #[test]
+    fn test_relight_chunks() {
+        let mut chunk_map = chunk_map();
+        let mut chunk_lights = ChunkLights::default();
+
+        let light = BlockPosition::new(-2, 100, 0);
+        chunk_map.set_block_at(light, Block::Glowstone).unwrap();
+        // Stale light values which should be cleared.
+        chunk_map
+            .chunk_at_mut(ChunkPosition::new(0, 0))
+            .unwrap()
+            .set_block_light_at(10, 50, 10, 15);
+
+        let mut chunks = HashSet::new();
+        chunks.insert(ChunkPosition::new(-1, 0));
+        relight_chunks(&mut chunk_map, &mut chunk_lights, &chunks, true);
+
+        let mut ctx = Context::new(&mut chunk_map, ChunkPosition::new(0, 0)).unwrap();
+        assert_eq!(ctx.block_light_at(light), 15);
+        assert_eq!(ctx.block_light_at(BlockPosition::new(0, 100, 0)), 13);
+        assert_eq!(ctx.block_light_at(BlockPosition::new(10, 50, 10)), 0);
+        assert_eq!(
+            chunk_lights.lights_within_distance(light, 1).as_slice(),
+            &[light]
+        );
+    }
+
+    #[test]
+    fn test_calculate_sky_light() {
+        let pos = ChunkPosition::new(0, 0);
+        let mut chunk = ground(pos);
+        // An overhang over half of the chunk.
+        for x in 0..8 {
+            for z in 0..16 {
+                chunk.set_block_at(x, 70, z, Block::Stone);
+            }
+        }
+
+        calculate_light(&mut chunk, true);
+
+        assert_eq!(chunk.sky_light_at(0, 100, 0), 15);
+        assert_eq!(chunk.sky_light_at(0, 71, 0), 15);
+        assert_eq!(chunk.sky_light_at(0, 70, 0), 0);
+        assert_eq!(chunk.sky_light_at(12, 61, 3), 15);
+        assert_eq!(chunk.sky_light_at(12, 60, 3), 0);
+        // Light spreads sideways under the overhang.
+        assert_eq!(chunk.sky_light_at(7, 65, 3), 14);
+        assert_eq!(chunk.sky_light_at(7, 61, 3), 14);
+        assert_eq!(chunk.sky_light_at(0, 65, 3), 7);
+    }
+
+    #[test]
+    fn test_light_chunks() {
+        let mut chunks: Vec<Chunk> = (0..4)
+            .map(|x| {
+                let mut chunk = ground(ChunkPosition::new(x, 0));
+                chunk.set_block_at(8, 61, 8, Block::Glowstone);
+                // Stale light values which should be cleared.
+                chunk.set_block_light_at(0, 50, 0, 12);
+                chunk
+            })
+            .collect();
+        let mut expected = chunks[0].clone();
+        calculate_light(&mut expected, true);
+
+        light_chunks(&mut chunks, true);
+
+        for chunk in &chunks {
+            assert_eq!(chunk.block_light_at(8, 61, 8), 15);
+            assert_eq!(chunk.block_light_at(8, 62, 8), 14);
+            assert_eq!(chunk.block_light_at(0, 50, 0), 0);
+            for y in 55..70 {
+                assert_eq!(
+                    chunk.block_light_at(5, y, 8),
+                    expected.block_light_at(5, y, 8)
+                );
+                assert_eq!(chunk.sky_light_at(5, y, 8), expected.sky_light_at(5, y, 8));
+            }
+        }
+    }
+
+    #[test]
+    fn test_calculate_light_without_sky() {
+        let mut chunk = ground(ChunkPosition::new(0, 0));
+        chunk.set_block_at(8, 61, 8, Block::Glowstone);
+        calculate_light(&mut chunk, false);
+
+        assert_eq!(chunk.block_light_at(8, 62, 8), 14);
+        assert_eq!(chunk.sky_light_at(8, 62, 8), 0);
+        assert_eq!(chunk.sky_light_at(0, 63, 0), 0);
+    }
+
+    #[test]
+    fn test_opacity() {
+        let mut chunk = ground(ChunkPosition::new(0, 0));
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in 61..=63 {
+                    chunk.set_block_at(x, y, z, Block::Water(WaterData { level: 0 }));
+                }
+            }
+        }
+        calculate_light(&mut chunk, true);
+        assert_eq!(chunk.sky_light_at(5, 64, 5), 15);
+        assert_eq!(chunk.sky_light_at(5, 63, 5), 13);
+        assert_eq!(chunk.sky_light_at(5, 62, 5), 10);
+        assert_eq!(chunk.sky_light_at(5, 61, 5), 7);
+
+        let mut chunk_map = chunk_map();
+        let mut ctx = Context::new(&mut chunk_map, ChunkPosition::new(0, 0)).unwrap();
+        let light = BlockPosition::new(0, 100, 0);
+        let water = BlockPosition::new(1, 100, 0);
+        let slab = BlockPosition::new(0, 99, 0);
+        ctx.set_block_at(water, Block::Water(WaterData { level: 0 }));
+        ctx.set_block_at(
+            slab,
+            Block::StoneSlab(StoneSlabData {
+                ty: StoneSlabType::Bottom,
+                waterlogged: false,
+            }),
+        );
+        ctx.set_block_at(light, Block::Glowstone);
+        ctx.set_block_light_at(light, 15);
+        emitting_creation(&mut ctx, light);
+
+        assert_eq!(ctx.block_light_at(water), 12);
+        assert_eq!(ctx.block_light_at(slab), 14);
+    }
+
+    #[test]
+    fn test_update_sky_light() {
+        let mut chunk_map = chunk_map();
+        let mut ctx = Context::new(&mut chunk_map, ChunkPosition::new(0, 0)).unwrap();
+
+        let pos = BlockPosition::new(0, 100, 0);
+        ctx.set_block_at(pos, Block::Stone);
+        update_sky_light(&mut ctx, pos);
+
+        assert_eq!(ctx.sky_light_at(pos), 0);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(0, 101, 0)), 15);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(0, 99, 0)), 14);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(0, 10, 0)), 14);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(1, 99, 0)), 15);
+
+        ctx.set_block_at(pos, Block::Air);
+        update_sky_light(&mut ctx, pos);
+
+        assert_eq!(ctx.sky_light_at(pos), 15);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(0, 10, 0)), 15);
+    }
+
+    #[test]
+    fn test_spread_sky_light_across_borders() {
+        let roofed = ChunkPosition::new(0, 0);
+        let open = ChunkPosition::new(1, 0);
+
+        let mut roofed_chunk = ground(roofed);
+        for x in 0..16 {
+            for z in 0..16 {
+                roofed_chunk.set_block_at(x, 70, z, Block::Stone);
+            }
+        }
+        calculate_light(&mut roofed_chunk, true);
+        assert_eq!(roofed_chunk.sky_light_at(15, 65, 5), 0);
+
+        let mut open_chunk = ground(open);
+        calculate_light(&mut open_chunk, true);
+
+        let mut chunk_map = ChunkMap::new();
+        chunk_map.set_chunk_at(roofed, roofed_chunk);
+        chunk_map.set_chunk_at(open, open_chunk);
+
+        let mut ctx = Context::new(&mut chunk_map, roofed).unwrap();
+        spread_sky_light_across_borders(&mut ctx, roofed);
+
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(16, 65, 5)), 15);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(15, 65, 5)), 14);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(10, 65, 5)), 9);
+        assert_eq!(ctx.sky_light_at(BlockPosition::new(1, 65, 5)), 0);
+    }
+
+    #[test]
+    fn test_light_broadcast() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0));
+        chunk.set_block_light_at(0, 40, 0, 7);
+        chunk.set_sky_light_at(0, 100, 0, 3);
+        // Elided once its light is restored.
+        chunk.set_sky_light_at(0, 100, 0, 15);
+        chunk.optimize();
+
+        let packet = light_update(&chunk, chunk.dirty_light(), true);
+        assert!(!packet.full_chunk);
+        let sections = packet.chunk.sections();
+        assert_eq!(sections[2].unwrap().block_light_at(0, 8, 0), 7);
+        assert_eq!(sections[6].unwrap().sky_light_at(0, 4, 0), 15);
+        assert_eq!(sections.iter().flatten().count(), 2);
+
+        let (mut w, mut d) = t::builder().with(LightBroadcastSystem, "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let other = t::add_player(&mut w);
+        let mut loaded_chunks = LoadedChunksComponent::default();
+        loaded_chunks.mark_loaded(ChunkPosition::new(0, 0));
+        w.write_component()
+            .insert(player.entity, loaded_chunks)
+            .unwrap();
+        w.write_component()
+            .insert(other.entity, LoadedChunksComponent::default())
+            .unwrap();
+        d.dispatch(&w);
+        t::received_packets(&player, None);
+
+        w.fetch_mut::<ChunkMap>()
+            .chunk_at_mut(ChunkPosition::new(0, 0))
+            .unwrap()
+            .set_block_light_at(0, 40, 0, 7);
+        d.dispatch(&w);
+        let packets = t::received_packets(&player, None);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].ty(), PacketType::ChunkData);
+        // Players who haven't loaded the chunk don't receive its light.
+        assert!(t::received_packets(&other, None).is_empty());
+
+        // Nothing is sent once the light has been broadcast.
+        d.dispatch(&w);
+        assert!(t::received_packets(&player, None).is_empty());
+    }
+
+    #[test]
+    fn test_lighting_queue() {
+        let mut queue = LightingQueue::default();
+        let a = BlockPosition::new(0, 64, 0);
+        let b = BlockPosition::new(20, 64, 0);
+
+        queue.push_update(a, Block::Air, Block::Stone);
+        queue.push_update(b, Block::Air, Block::Glowstone);
+        queue.push_update(a, Block::Stone, Block::Glowstone);
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop_update(), Some((a, Block::Air, Block::Glowstone)));
+
+        // The relight of the chunk covers the update of `b`.
+        queue.push_chunk(ChunkPosition::new(1, 0));
+        queue.push_update(b, Block::Glowstone, Block::Air);
+        assert_eq!(queue.len(), RELIGHT_COST);
+        assert_eq!(queue.pop_update(), None);
+        assert_eq!(queue.pop_chunk(), Some(ChunkPosition::new(1, 0)));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_lighting_budget() {
+        let (mut w, mut d) = t::builder().with(LightingSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        let mut config = Config::default();
+        config.lighting.max_updates_per_tick = 1;
+        w.insert(Arc::new(config));
+
+        let first = BlockPosition::new(0, 64, 0);
+        let second = BlockPosition::new(8, 64, 8);
+        for &pos in &[first, second] {
+            t::set_block(pos.x, pos.y, pos.z, Block::Glowstone, &w);
+            t::trigger_event(
+                &w,
+                BlockUpdateEvent {
+                    cause: BlockUpdateCause::Test,
+                    pos,
+                    old_block: Block::Air,
+                    new_block: Block::Glowstone,
+                },
+            );
+        }
+
+        let block_light = |w: &World, pos: BlockPosition| {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let mut ctx = Context::new(&mut chunk_map, pos.chunk_pos()).unwrap();
+            ctx.block_light_at(pos)
+        };
+
+        d.dispatch(&w);
+        assert_eq!(block_light(&w, first), 15);
+        assert_eq!(block_light(&w, second), 0);
+
+        d.dispatch(&w);
+        assert_eq!(block_light(&w, second), 15);
+    }
+
+    #[test]
+    fn test_block_light_seams() {
+        let (mut w, mut d) = t::builder().with(LightingSystem::default(), "").build();
+
+        let lit = ChunkPosition::new(0, 0);
+        let light = BlockPosition::new(14, 64, 5);
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            chunk_map.set_chunk_at(lit, Chunk::new(lit));
+            chunk_map.set_block_at(light, Block::Glowstone).unwrap();
+            let mut ctx = Context::new(&mut chunk_map, lit).unwrap();
+            ctx.set_block_light_at(light, 15);
+            emitting_creation(&mut ctx, light);
+        }
+
+        // Load a chunk next to the lit one.
+        let loaded = ChunkPosition::new(1, 0);
+        let wall = BlockPosition::new(16, 64, 7);
+        w.fetch_mut::<ChunkMap>()
+            .set_chunk_at(loaded, Chunk::new(loaded));
+        t::set_block(wall.x, wall.y, wall.z, Block::Stone, &w);
+        t::trigger_event(
+            &w,
+            ChunkLoadEvent {
+                pos: loaded,
+                entities: vec![],
+            },
+        );
+        d.dispatch(&w);
+
+        let mut chunk_map = w.fetch_mut::<ChunkMap>();
+        let mut ctx = Context::new(&mut chunk_map, loaded).unwrap();
+        assert_eq!(ctx.block_light_at(BlockPosition::new(16, 64, 5)), 13);
+        assert_eq!(ctx.block_light_at(BlockPosition::new(20, 64, 5)), 9);
+        assert_eq!(ctx.block_light_at(BlockPosition::new(16, 65, 5)), 12);
+        assert_eq!(ctx.block_light_at(wall), 0);
+    }
+
+    /// Returns a chunk filled with stone up to y = 60.
+    fn ground(pos: ChunkPosition) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for x in 0..16 {
+            for y in 0..=60 {
+                for z in 0..16 {
+                    chunk.set_block_at(x, y, z, Block::Stone);
+                }
+            }
+        }
+        chunk
+    }
+
     fn chunk_map() -> ChunkMap {
         let mut chunk_map = ChunkMap::new();
 
@@ -511,4 +1887,143 @@ mod tests {
 
         chunk_map
     }
+
+    #[test]
+    fn test_verify_light() {
+        let mut chunk_map = chunk_map();
+        for x in -16..32 {
+            for z in -16..32 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(x, 63, z), Block::Stone)
+                    .unwrap();
+            }
+        }
+        chunk_map
+            .set_block_at(BlockPosition::new(15, 64, 0), Block::Glowstone)
+            .unwrap();
+        let positions = chunks_around(&iter::once(ChunkPosition::new(0, 0)).collect(), 1);
+        relight_chunks(
+            &mut chunk_map,
+            &mut ChunkLights::default(),
+            &positions,
+            true,
+        );
+
+        let mut chunks: Vec<Chunk> = positions
+            .iter()
+            .map(|pos| chunk_map.chunk_at(*pos).unwrap().clone())
+            .collect();
+        assert_eq!(verify_light(chunks.clone(), true), vec![]);
+
+        // Corrupt the expected light of the center chunk.
+        let center = chunks
+            .iter_mut()
+            .find(|chunk| chunk.position() == ChunkPosition::new(0, 0))
+            .unwrap();
+        center.set_block_light_at(14, 64, 0, 3);
+        assert_eq!(
+            verify_light(chunks, true),
+            vec![LightMismatch {
+                pos: BlockPosition::new(14, 64, 0),
+                kind: LightKind::Block,
+                expected: 3,
+                actual: 14,
+            }]
+        );
+    }
+
+    /// Compares the light calculated by Feather with the light of
+    /// the vanilla region in `test-data/vanilla-light`. It is ignored
+    /// until the region is committed; see the README there.
+    #[test]
+    #[ignore]
+    fn test_vanilla_light() {
+        use feather_core::region::{self, RegionPosition};
+        use std::path::PathBuf;
+
+        let dir = PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test-data/vanilla-light"
+        ));
+        let mut handle =
+            region::load_region(&dir, RegionPosition::from_chunk(ChunkPosition::new(0, 0)))
+                .unwrap();
+
+        let mut chunks = vec![];
+        for x in 0..32 {
+            for z in 0..32 {
+                if let Ok((chunk, _)) = handle.load_chunk(ChunkPosition::new(x, z)) {
+                    chunks.push(chunk);
+                }
+            }
+        }
+        assert!(!chunks.is_empty(), "region 0, 0 contains no chunks");
+
+        let mismatches = verify_light(chunks, true);
+        assert!(
+            mismatches.is_empty(),
+            "{} blocks have the wrong light, e.g. {:?}",
+            mismatches.len(),
+            &mismatches[..mismatches.len().min(20)]
+        );
+    }
+
+    #[test]
+    fn test_relight_command() {
+        let (mut w, mut d) = t::builder()
+            .with(LightingSystem::default(), "lighting")
+            .with_dep(RelightCommandSystem::default(), "", &["lighting"])
+            .build();
+        assert!(w.fetch::<CommandRegistry>().contains("relight"));
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = String::from("admin");
+        let mut config = Config::default();
+        config.server.operators.push(String::from("admin"));
+        w.insert(Arc::new(config));
+
+        let chat = |player: &t::Player| {
+            let packet = t::assert_packet_received(player, PacketType::ChatMessageClientbound);
+            cast_packet::<ChatMessageClientbound>(&*packet)
+                .json_data
+                .clone()
+        };
+
+        t::trigger_event(
+            &w,
+            CommandEvent::parse(player.entity, "/relight 99").unwrap(),
+        );
+        d.dispatch(&w);
+        assert!(chat(&player).contains("Invalid radius 99"));
+
+        // Corrupt the light of the chunks around the player. Lights
+        // placed with `t::set_block` aren't lit until relit.
+        t::set_block(8, 64, 8, Block::Glowstone, &w);
+        t::set_block(40, 64, 8, Block::Glowstone, &w);
+        w.fetch_mut::<ChunkMap>()
+            .chunk_at_mut(ChunkPosition::new(0, 0))
+            .unwrap()
+            .set_block_light_at(3, 100, 3, 12);
+
+        t::trigger_event(
+            &w,
+            CommandEvent::parse(player.entity, "/relight 1").unwrap(),
+        );
+        d.dispatch(&w);
+        assert!(chat(&player).contains("Relighting 9 chunks"));
+
+        d.dispatch(&w);
+        assert!(chat(&player).contains("Relit 9 chunks"));
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        let chunk = chunk_map.chunk_at(ChunkPosition::new(0, 0)).unwrap();
+        assert_eq!(chunk.block_light_at(3, 100, 3), 0);
+        assert_eq!(chunk.block_light_at(8, 65, 8), 14);
+        // Chunks outside the radius are left alone.
+        let far = chunk_map.chunk_at(ChunkPosition::new(2, 0)).unwrap();
+        assert_eq!(far.block_light_at(8, 64, 8), 0);
+    }
 }
@@ -0,0 +1,1748 @@
+//! Loot tables, which determine the items dropped by blocks and
+//! entities and generated in chests.
+//!
+//! Tables use the vanilla JSON format and are identified as
+//! `<namespace>:<path>`, for example `minecraft:blocks/stone`.
+//! A subset of the vanilla tables is bundled with the server;
+//! datapacks can add tables or replace bundled ones by placing them
+//! in `data/<namespace>/loot_tables/<path>.json`.
+//!
+//! A table consists of pools. Each pool is rolled a number of times,
+//! and each roll selects one of the pool's entries at random
+//! according to their weights. Entries and pools can be guarded by
+//! conditions, and item functions modify the generated stacks.
+//! Composite entries (`alternatives`, `sequence` and `group`) combine
+//! other entries. Entries and conditions which are not yet supported
+//! never generate loot or pass, and functions which are not yet
+//! supported have no effect. Notably, `enchant_with_levels` is
+//! ignored, since the enchanting table isn't implemented yet.
+//! `enchant_randomly` adds one random enchantment applicable to the
+//! item, turning books into enchanted books.
+//!
+//! Blocks broken by players who are not in creative mode drop the
+//! loot from `<namespace>:blocks/<name>`. The tool used to break the
+//! block is matched by `match_tool` conditions, for example for silk
+//! touch, and its fortune level affects `table_bonus` conditions and
+//! `apply_bonus` functions. Blocks without a table drop themselves,
+//! which is the vanilla behavior for most blocks. Blocks which need
+//! a tool to be harvested, such as ores, drop nothing when broken
+//! without it; see the `tool` module.
+//!
+//! Killed mobs drop the loot from `minecraft:entities/<type>`; see
+//! the `combat` module.
+//!
+//! Containers whose block entity has a `LootTable` tag, such as the
+//! chests of vanilla dungeons, are filled from that table when they
+//! are first opened or broken; see the `container` module.
+//!
+//! Fishing isn't implemented yet, so no fishing tables are bundled.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::container::is_shulker_box;
+use crate::datapack;
+use crate::entity::item;
+use crate::entity::{PlayerComponent, PositionComponent, VelocityComponent};
+use crate::player::{armor_slot, InventoryComponent};
+use crate::systems::BLOCK_DROPS;
+use crate::timings::DispatcherBuilderExt;
+use crate::tool::{self, ToolKind};
+use crate::TickCount;
+use feather_blocks::Block;
+use feather_core::inventory::{max_durability, SLOT_ARMOR_FEET, SLOT_ARMOR_HEAD};
+use feather_core::item_tag::Enchantment;
+use feather_core::{BlockPosition, Gamemode, Item, ItemStack, Position};
+use feather_item_block::BlockToItem;
+use hashbrown::HashMap;
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::world::EntitiesRes;
+use specs::{Builder, DispatcherBuilder, Entities, LazyUpdate, Read, ReadStorage, System};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The maximum depth of nested `loot_table` entries,
+/// which guards against tables referencing themselves.
+pub const MAX_DEPTH: usize = 32;
+/// The maximum number of items in a generated stack.
+pub const MAX_STACK_SIZE: u8 = 64;
+/// The number of ticks after which dropped
+/// blocks can be picked up.
+const PICKUP_DELAY: u64 = 10;
+
+/// The tables bundled with the server.
+const BUNDLED: &[(&str, &str)] = &[
+    (
+        "minecraft:blocks/acacia_leaves",
+        include_str!("../loot_tables/blocks/acacia_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/birch_leaves",
+        include_str!("../loot_tables/blocks/birch_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/bookshelf",
+        include_str!("../loot_tables/blocks/bookshelf.json"),
+    ),
+    (
+        "minecraft:blocks/clay",
+        include_str!("../loot_tables/blocks/clay.json"),
+    ),
+    (
+        "minecraft:blocks/coal_ore",
+        include_str!("../loot_tables/blocks/coal_ore.json"),
+    ),
+    (
+        "minecraft:blocks/dark_oak_leaves",
+        include_str!("../loot_tables/blocks/dark_oak_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/diamond_ore",
+        include_str!("../loot_tables/blocks/diamond_ore.json"),
+    ),
+    (
+        "minecraft:blocks/emerald_ore",
+        include_str!("../loot_tables/blocks/emerald_ore.json"),
+    ),
+    (
+        "minecraft:blocks/glass",
+        include_str!("../loot_tables/blocks/glass.json"),
+    ),
+    (
+        "minecraft:blocks/glowstone",
+        include_str!("../loot_tables/blocks/glowstone.json"),
+    ),
+    (
+        "minecraft:blocks/grass_block",
+        include_str!("../loot_tables/blocks/grass_block.json"),
+    ),
+    (
+        "minecraft:blocks/gravel",
+        include_str!("../loot_tables/blocks/gravel.json"),
+    ),
+    (
+        "minecraft:blocks/jungle_leaves",
+        include_str!("../loot_tables/blocks/jungle_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/lapis_ore",
+        include_str!("../loot_tables/blocks/lapis_ore.json"),
+    ),
+    (
+        "minecraft:blocks/melon",
+        include_str!("../loot_tables/blocks/melon.json"),
+    ),
+    (
+        "minecraft:blocks/nether_quartz_ore",
+        include_str!("../loot_tables/blocks/nether_quartz_ore.json"),
+    ),
+    (
+        "minecraft:blocks/oak_leaves",
+        include_str!("../loot_tables/blocks/oak_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/redstone_ore",
+        include_str!("../loot_tables/blocks/redstone_ore.json"),
+    ),
+    (
+        "minecraft:blocks/snow_block",
+        include_str!("../loot_tables/blocks/snow_block.json"),
+    ),
+    (
+        "minecraft:blocks/spruce_leaves",
+        include_str!("../loot_tables/blocks/spruce_leaves.json"),
+    ),
+    (
+        "minecraft:blocks/stone",
+        include_str!("../loot_tables/blocks/stone.json"),
+    ),
+    (
+        "minecraft:chests/simple_dungeon",
+        include_str!("../loot_tables/chests/simple_dungeon.json"),
+    ),
+    (
+        "minecraft:entities/chicken",
+        include_str!("../loot_tables/entities/chicken.json"),
+    ),
+    (
+        "minecraft:entities/cow",
+        include_str!("../loot_tables/entities/cow.json"),
+    ),
+    (
+        "minecraft:entities/pig",
+        include_str!("../loot_tables/entities/pig.json"),
+    ),
+    (
+        "minecraft:entities/sheep",
+        include_str!("../loot_tables/entities/sheep.json"),
+    ),
+    (
+        "minecraft:entities/sheep/black",
+        include_str!("../loot_tables/entities/sheep/black.json"),
+    ),
+    (
+        "minecraft:entities/sheep/blue",
+        include_str!("../loot_tables/entities/sheep/blue.json"),
+    ),
+    (
+        "minecraft:entities/sheep/brown",
+        include_str!("../loot_tables/entities/sheep/brown.json"),
+    ),
+    (
+        "minecraft:entities/sheep/cyan",
+        include_str!("../loot_tables/entities/sheep/cyan.json"),
+    ),
+    (
+        "minecraft:entities/sheep/gray",
+        include_str!("../loot_tables/entities/sheep/gray.json"),
+    ),
+    (
+        "minecraft:entities/sheep/green",
+        include_str!("../loot_tables/entities/sheep/green.json"),
+    ),
+    (
+        "minecraft:entities/sheep/light_blue",
+        include_str!("../loot_tables/entities/sheep/light_blue.json"),
+    ),
+    (
+        "minecraft:entities/sheep/light_gray",
+        include_str!("../loot_tables/entities/sheep/light_gray.json"),
+    ),
+    (
+        "minecraft:entities/sheep/lime",
+        include_str!("../loot_tables/entities/sheep/lime.json"),
+    ),
+    (
+        "minecraft:entities/sheep/magenta",
+        include_str!("../loot_tables/entities/sheep/magenta.json"),
+    ),
+    (
+        "minecraft:entities/sheep/orange",
+        include_str!("../loot_tables/entities/sheep/orange.json"),
+    ),
+    (
+        "minecraft:entities/sheep/pink",
+        include_str!("../loot_tables/entities/sheep/pink.json"),
+    ),
+    (
+        "minecraft:entities/sheep/purple",
+        include_str!("../loot_tables/entities/sheep/purple.json"),
+    ),
+    (
+        "minecraft:entities/sheep/red",
+        include_str!("../loot_tables/entities/sheep/red.json"),
+    ),
+    (
+        "minecraft:entities/sheep/white",
+        include_str!("../loot_tables/entities/sheep/white.json"),
+    ),
+    (
+        "minecraft:entities/sheep/yellow",
+        include_str!("../loot_tables/entities/sheep/yellow.json"),
+    ),
+    (
+        "minecraft:entities/skeleton",
+        include_str!("../loot_tables/entities/skeleton.json"),
+    ),
+    (
+        "minecraft:entities/zombie",
+        include_str!("../loot_tables/entities/zombie.json"),
+    ),
+];
+
+/// Information about the circumstances
+/// in which loot is generated.
+#[derive(Debug, Clone, Default)]
+pub struct LootContext {
+    /// Whether the entity was killed by a player.
+    pub killed_by_player: bool,
+    /// The looting level of the killer's weapon.
+    pub looting: u32,
+    /// The luck of the player, which affects
+    /// the weights of entries with a quality.
+    pub luck: f32,
+    /// The radius of the explosion which
+    /// destroyed the block, if any.
+    pub explosion_radius: Option<f32>,
+    /// The block whose loot is generated, if any.
+    pub block: Option<Block>,
+    /// The tool used to break the block, if any.
+    pub tool: Option<ItemStack>,
+}
+
+impl LootContext {
+    /// Returns the level of an enchantment
+    /// on the tool, or 0 if there is no tool.
+    pub fn tool_enchantment(&self, id: &str) -> u32 {
+        self.tool
+            .as_ref()
+            .and_then(|tool| tool.enchantment_level(id))
+            .map_or(0, |level| level.max(0) as u32)
+    }
+}
+
+/// A number which is either constant
+/// or chosen at random.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum NumberProvider {
+    Constant(f32),
+    /// The number of successes in `n` trials
+    /// with probability `p`.
+    Binomial {
+        n: u32,
+        p: f32,
+    },
+    /// A number in the range `[min, max]`.
+    Uniform {
+        min: f32,
+        max: f32,
+    },
+}
+
+impl NumberProvider {
+    pub fn gen_float<R: Rng + ?Sized>(self, rng: &mut R) -> f32 {
+        match self {
+            NumberProvider::Constant(value) => value,
+            NumberProvider::Binomial { .. } => self.gen_int(rng) as f32,
+            NumberProvider::Uniform { min, max } if min < max => rng.gen_range(min, max),
+            NumberProvider::Uniform { min, .. } => min,
+        }
+    }
+
+    pub fn gen_int<R: Rng + ?Sized>(self, rng: &mut R) -> i32 {
+        match self {
+            NumberProvider::Constant(value) => value.round() as i32,
+            NumberProvider::Binomial { n, p } => {
+                (0..n).filter(|_| rng.gen::<f32>() < p).count() as i32
+            }
+            NumberProvider::Uniform { min, max } => {
+                let (min, max) = (min.floor() as i32, max.floor() as i32);
+                if min < max {
+                    rng.gen_range(min, max + 1)
+                } else {
+                    min
+                }
+            }
+        }
+    }
+}
+
+impl Default for NumberProvider {
+    fn default() -> Self {
+        NumberProvider::Constant(0.0)
+    }
+}
+
+/// A range of integers, which is either a single
+/// number or bounded by an optional minimum and maximum.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum IntRange {
+    Exact(i32),
+    Bounds { min: Option<i32>, max: Option<i32> },
+}
+
+impl IntRange {
+    pub fn contains(self, value: i32) -> bool {
+        match self {
+            IntRange::Exact(exact) => value == exact,
+            IntRange::Bounds { min, max } => {
+                min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max)
+            }
+        }
+    }
+
+    /// Returns the value closest to
+    /// `value` which is in this range.
+    pub fn clamp(self, value: i32) -> i32 {
+        match self {
+            IntRange::Exact(exact) => exact,
+            IntRange::Bounds { min, max } => {
+                let value = min.map_or(value, |min| value.max(min));
+                max.map_or(value, |max| value.min(max))
+            }
+        }
+    }
+}
+
+impl Default for IntRange {
+    fn default() -> Self {
+        IntRange::Bounds {
+            min: None,
+            max: None,
+        }
+    }
+}
+
+/// A predicate matching item stacks, as used by `match_tool`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ItemPredicate {
+    pub item: Option<String>,
+    /// Item tags are not yet supported, so
+    /// predicates with a tag never match.
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub count: IntRange,
+    #[serde(default)]
+    pub enchantments: Vec<EnchantmentPredicate>,
+}
+
+impl ItemPredicate {
+    pub fn test(&self, stack: &ItemStack) -> bool {
+        if self.tag.is_some() || !self.count.contains(i32::from(stack.amount)) {
+            return false;
+        }
+        if let Some(item) = &self.item {
+            if Item::from_identifier(item) != Some(stack.ty) {
+                return false;
+            }
+        }
+        self.enchantments
+            .iter()
+            .all(|predicate| predicate.test(stack))
+    }
+}
+
+/// A predicate matching an enchantment of an item stack.
+/// Without an enchantment ID, any enchantment matches.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EnchantmentPredicate {
+    pub enchantment: Option<String>,
+    #[serde(default)]
+    pub levels: IntRange,
+}
+
+impl EnchantmentPredicate {
+    pub fn test(&self, stack: &ItemStack) -> bool {
+        stack.enchantments().iter().any(|enchantment| {
+            self.enchantment
+                .as_ref()
+                .map_or(true, |id| *id == enchantment.id)
+                && self.levels.contains(i32::from(enchantment.level))
+        })
+    }
+}
+
+/// A loot table.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LootTable {
+    #[serde(default)]
+    pub pools: Vec<Pool>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Pool {
+    pub rolls: NumberProvider,
+    #[serde(default)]
+    pub bonus_rolls: NumberProvider,
+    pub entries: Vec<Entry>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub functions: Vec<Function>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub kind: EntryKind,
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    #[serde(default)]
+    pub quality: i32,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub functions: Vec<Function>,
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum EntryKind {
+    /// Generates a single item, which
+    /// functions can turn into a larger stack.
+    #[serde(rename = "item", alias = "minecraft:item")]
+    Item { name: String },
+    /// Generates the loot of another table.
+    #[serde(rename = "loot_table", alias = "minecraft:loot_table")]
+    LootTable { name: String },
+    /// Generates nothing.
+    #[serde(rename = "empty", alias = "minecraft:empty")]
+    Empty,
+    /// Generates the first child whose conditions pass.
+    #[serde(rename = "alternatives", alias = "minecraft:alternatives")]
+    Alternatives { children: Vec<Entry> },
+    /// Generates children in order until
+    /// the conditions of one of them fail.
+    #[serde(rename = "sequence", alias = "minecraft:sequence")]
+    Sequence { children: Vec<Entry> },
+    /// Generates all children whose conditions pass.
+    #[serde(rename = "group", alias = "minecraft:group")]
+    Group { children: Vec<Entry> },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "condition")]
+pub enum Condition {
+    #[serde(rename = "random_chance", alias = "minecraft:random_chance")]
+    RandomChance { chance: f32 },
+    #[serde(
+        rename = "random_chance_with_looting",
+        alias = "minecraft:random_chance_with_looting"
+    )]
+    RandomChanceWithLooting {
+        chance: f32,
+        looting_multiplier: f32,
+    },
+    #[serde(rename = "killed_by_player", alias = "minecraft:killed_by_player")]
+    KilledByPlayer {
+        #[serde(default)]
+        inverse: bool,
+    },
+    /// Passes with a probability of one divided
+    /// by the radius of the explosion, if any.
+    #[serde(rename = "survives_explosion", alias = "minecraft:survives_explosion")]
+    SurvivesExplosion,
+    #[serde(rename = "inverted", alias = "minecraft:inverted")]
+    Inverted { term: Box<Condition> },
+    #[serde(rename = "alternative", alias = "minecraft:alternative")]
+    Alternative { terms: Vec<Condition> },
+    /// Passes if the tool matches the predicate.
+    #[serde(rename = "match_tool", alias = "minecraft:match_tool")]
+    MatchTool {
+        #[serde(default)]
+        predicate: ItemPredicate,
+    },
+    /// Passes with the chance at the index of the
+    /// level of an enchantment on the tool, or the
+    /// last chance if the level is higher.
+    #[serde(rename = "table_bonus", alias = "minecraft:table_bonus")]
+    TableBonus {
+        enchantment: String,
+        chances: Vec<f32>,
+    },
+    /// Passes if the block has the given name
+    /// and values of the given properties.
+    #[serde(
+        rename = "block_state_property",
+        alias = "minecraft:block_state_property"
+    )]
+    BlockStateProperty {
+        block: String,
+        #[serde(default)]
+        properties: BTreeMap<String, String>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+impl Condition {
+    pub fn test<R: Rng + ?Sized>(&self, ctx: &LootContext, rng: &mut R) -> bool {
+        match self {
+            Condition::RandomChance { chance } => rng.gen::<f32>() < *chance,
+            Condition::RandomChanceWithLooting {
+                chance,
+                looting_multiplier,
+            } => rng.gen::<f32>() < chance + ctx.looting as f32 * looting_multiplier,
+            Condition::KilledByPlayer { inverse } => ctx.killed_by_player != *inverse,
+            Condition::SurvivesExplosion => match ctx.explosion_radius {
+                Some(radius) => rng.gen::<f32>() <= 1.0 / radius,
+                None => true,
+            },
+            Condition::Inverted { term } => !term.test(ctx, rng),
+            Condition::Alternative { terms } => terms.iter().any(|term| term.test(ctx, rng)),
+            Condition::MatchTool { predicate } => {
+                ctx.tool.as_ref().map_or(false, |tool| predicate.test(tool))
+            }
+            Condition::TableBonus {
+                enchantment,
+                chances,
+            } => {
+                let level = ctx.tool_enchantment(enchantment) as usize;
+                match chances.get(level).or_else(|| chances.last()) {
+                    Some(chance) => rng.gen::<f32>() < *chance,
+                    None => false,
+                }
+            }
+            Condition::BlockStateProperty { block, properties } => match ctx.block {
+                Some(state) => {
+                    let (name, props) = state.to_name_and_props();
+                    name == block.as_str()
+                        && properties.iter().all(|(key, value)| {
+                            props.iter().any(|(k, v)| *k == key.as_str() && v == value)
+                        })
+                }
+                None => false,
+            },
+            Condition::Unsupported => false,
+        }
+    }
+}
+
+fn all<R: Rng + ?Sized>(conditions: &[Condition], ctx: &LootContext, rng: &mut R) -> bool {
+    conditions.iter().all(|condition| condition.test(ctx, rng))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Function {
+    #[serde(flatten)]
+    pub kind: FunctionKind,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "function")]
+pub enum FunctionKind {
+    #[serde(rename = "set_count", alias = "minecraft:set_count")]
+    SetCount { count: NumberProvider },
+    /// Adds `count` items per level of looting,
+    /// up to `limit` items if it is positive.
+    #[serde(rename = "looting_enchant", alias = "minecraft:looting_enchant")]
+    LootingEnchant {
+        count: NumberProvider,
+        #[serde(default)]
+        limit: i32,
+    },
+    /// Limits the amount to a range.
+    #[serde(rename = "limit_count", alias = "minecraft:limit_count")]
+    LimitCount { limit: IntRange },
+    /// Increases the amount according to the level
+    /// of an enchantment on the tool, usually fortune.
+    #[serde(rename = "apply_bonus", alias = "minecraft:apply_bonus")]
+    ApplyBonus {
+        enchantment: String,
+        formula: BonusFormula,
+        #[serde(default)]
+        parameters: BonusParameters,
+    },
+    /// Removes each item with a probability of one
+    /// minus one divided by the explosion radius.
+    #[serde(rename = "explosion_decay", alias = "minecraft:explosion_decay")]
+    ExplosionDecay,
+    /// Adds a random enchantment from `enchantments`, or from all
+    /// enchantments applicable to the item if empty, with a random level.
+    #[serde(rename = "enchant_randomly", alias = "minecraft:enchant_randomly")]
+    EnchantRandomly {
+        #[serde(default)]
+        enchantments: Vec<String>,
+    },
+    #[serde(
+        rename = "enchant_with_levels",
+        alias = "minecraft:enchant_with_levels"
+    )]
+    EnchantWithLevels {
+        levels: NumberProvider,
+        #[serde(default)]
+        treasure: bool,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+/// The formula used by `apply_bonus`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BonusFormula {
+    /// Multiplies the amount by a random number from 1 to
+    /// the level plus one, favoring a multiplier of 1.
+    #[serde(rename = "minecraft:ore_drops", alias = "ore_drops")]
+    OreDrops,
+    /// Adds a random number from 0 to
+    /// `bonusMultiplier` times the level.
+    #[serde(
+        rename = "minecraft:uniform_bonus_count",
+        alias = "uniform_bonus_count"
+    )]
+    UniformBonusCount,
+    /// Adds the number of successes in `extra` plus
+    /// the level trials with probability `probability`.
+    #[serde(
+        rename = "minecraft:binomial_with_bonus_count",
+        alias = "binomial_with_bonus_count"
+    )]
+    BinomialWithBonusCount,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct BonusParameters {
+    #[serde(rename = "bonusMultiplier", default)]
+    pub bonus_multiplier: f32,
+    #[serde(default)]
+    pub extra: u32,
+    #[serde(default)]
+    pub probability: f32,
+}
+
+impl BonusFormula {
+    pub fn apply<R: Rng + ?Sized>(
+        self,
+        amount: i32,
+        level: u32,
+        parameters: BonusParameters,
+        rng: &mut R,
+    ) -> i32 {
+        match self {
+            BonusFormula::OreDrops => {
+                if level == 0 {
+                    return amount;
+                }
+                let bonus = (rng.gen_range(0, level as i32 + 2) - 1).max(0);
+                amount * (bonus + 1)
+            }
+            BonusFormula::UniformBonusCount => {
+                let max = (parameters.bonus_multiplier * level as f32).round() as i32;
+                amount + rng.gen_range(0, max.max(0) + 1)
+            }
+            BonusFormula::BinomialWithBonusCount => {
+                let trials = parameters.extra + level;
+                amount
+                    + (0..trials)
+                        .filter(|_| rng.gen::<f32>() < parameters.probability)
+                        .count() as i32
+            }
+        }
+    }
+}
+
+/// The kinds of items an enchantment can be applied to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnchantmentTarget {
+    Armor,
+    Helmet,
+    Boots,
+    /// Armor, pumpkins and heads.
+    Wearable,
+    Weapon,
+    /// Pickaxes, axes and shovels.
+    Digger,
+    /// Any item with durability.
+    Breakable,
+    Bow,
+    FishingRod,
+    Trident,
+}
+
+impl EnchantmentTarget {
+    fn accepts(self, item: Item) -> bool {
+        let armor = armor_slot(item).filter(|_| max_durability(item).is_some());
+        let tool = tool::tool(item).map(|tool| tool.kind);
+        match self {
+            EnchantmentTarget::Armor => armor.is_some(),
+            EnchantmentTarget::Helmet => armor == Some(SLOT_ARMOR_HEAD),
+            EnchantmentTarget::Boots => armor == Some(SLOT_ARMOR_FEET),
+            EnchantmentTarget::Wearable => armor_slot(item).is_some(),
+            EnchantmentTarget::Weapon => tool == Some(ToolKind::Sword),
+            EnchantmentTarget::Digger => match tool {
+                Some(ToolKind::Pickaxe) | Some(ToolKind::Axe) | Some(ToolKind::Shovel) => true,
+                _ => false,
+            },
+            EnchantmentTarget::Breakable => max_durability(item).is_some(),
+            EnchantmentTarget::Bow => item == Item::Bow,
+            EnchantmentTarget::FishingRod => item == Item::FishingRod,
+            EnchantmentTarget::Trident => item == Item::Trident,
+        }
+    }
+}
+
+/// The enchantments chosen from by `enchant_randomly`,
+/// with their maximum levels and the items they apply to.
+const ENCHANTMENTS: &[(&str, i16, EnchantmentTarget)] = &[
+    ("minecraft:protection", 4, EnchantmentTarget::Armor),
+    ("minecraft:fire_protection", 4, EnchantmentTarget::Armor),
+    ("minecraft:feather_falling", 4, EnchantmentTarget::Boots),
+    ("minecraft:blast_protection", 4, EnchantmentTarget::Armor),
+    (
+        "minecraft:projectile_protection",
+        4,
+        EnchantmentTarget::Armor,
+    ),
+    ("minecraft:respiration", 3, EnchantmentTarget::Helmet),
+    ("minecraft:aqua_affinity", 1, EnchantmentTarget::Helmet),
+    ("minecraft:thorns", 3, EnchantmentTarget::Armor),
+    ("minecraft:depth_strider", 3, EnchantmentTarget::Boots),
+    ("minecraft:frost_walker", 2, EnchantmentTarget::Boots),
+    ("minecraft:binding_curse", 1, EnchantmentTarget::Wearable),
+    ("minecraft:sharpness", 5, EnchantmentTarget::Weapon),
+    ("minecraft:smite", 5, EnchantmentTarget::Weapon),
+    ("minecraft:bane_of_arthropods", 5, EnchantmentTarget::Weapon),
+    ("minecraft:knockback", 2, EnchantmentTarget::Weapon),
+    ("minecraft:fire_aspect", 2, EnchantmentTarget::Weapon),
+    ("minecraft:looting", 3, EnchantmentTarget::Weapon),
+    ("minecraft:sweeping", 3, EnchantmentTarget::Weapon),
+    ("minecraft:efficiency", 5, EnchantmentTarget::Digger),
+    ("minecraft:silk_touch", 1, EnchantmentTarget::Digger),
+    ("minecraft:unbreaking", 3, EnchantmentTarget::Breakable),
+    ("minecraft:fortune", 3, EnchantmentTarget::Digger),
+    ("minecraft:power", 5, EnchantmentTarget::Bow),
+    ("minecraft:punch", 2, EnchantmentTarget::Bow),
+    ("minecraft:flame", 1, EnchantmentTarget::Bow),
+    ("minecraft:infinity", 1, EnchantmentTarget::Bow),
+    (
+        "minecraft:luck_of_the_sea",
+        3,
+        EnchantmentTarget::FishingRod,
+    ),
+    ("minecraft:lure", 3, EnchantmentTarget::FishingRod),
+    ("minecraft:loyalty", 3, EnchantmentTarget::Trident),
+    ("minecraft:impaling", 5, EnchantmentTarget::Trident),
+    ("minecraft:riptide", 3, EnchantmentTarget::Trident),
+    ("minecraft:channeling", 1, EnchantmentTarget::Trident),
+    ("minecraft:mending", 1, EnchantmentTarget::Breakable),
+    ("minecraft:vanishing_curse", 1, EnchantmentTarget::Breakable),
+];
+
+/// Adds a random enchantment with a random level to a stack, chosen
+/// from `ids` or, if empty, from the enchantments applicable to the
+/// stack. Books are turned into enchanted books storing the enchantment.
+fn enchant_randomly<R: Rng + ?Sized>(stack: &mut ItemStack, ids: &[String], rng: &mut R) {
+    let is_book = stack.ty == Item::Book;
+    let candidates: Vec<(&str, i16)> = ENCHANTMENTS
+        .iter()
+        .filter(|(id, _, target)| {
+            if ids.is_empty() {
+                is_book || target.accepts(stack.ty)
+            } else {
+                let id = id.trim_start_matches("minecraft:");
+                ids.iter()
+                    .any(|listed| listed.trim_start_matches("minecraft:") == id)
+            }
+        })
+        .map(|(id, max_level, _)| (*id, *max_level))
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+
+    let (id, max_level) = candidates[rng.gen_range(0, candidates.len())];
+    let level = rng.gen_range(1, max_level + 1);
+    if is_book {
+        stack.ty = Item::EnchantedBook;
+        stack.tag_mut().stored_enchantments = vec![Enchantment {
+            id: id.to_string(),
+            level,
+        }];
+    } else {
+        stack.add_enchantment(id, level);
+    }
+}
+
+impl Function {
+    /// Applies this function to a generated item, whose
+    /// amount is `amount`. The amount may be left at or
+    /// out of range, which is fixed up by `LootTable::generate`.
+    pub fn apply<R: Rng + ?Sized>(
+        &self,
+        stack: &mut ItemStack,
+        amount: &mut i32,
+        ctx: &LootContext,
+        rng: &mut R,
+    ) {
+        if !all(&self.conditions, ctx, rng) {
+            return;
+        }
+
+        match &self.kind {
+            FunctionKind::SetCount { count } => *amount = count.gen_int(rng),
+            FunctionKind::LootingEnchant { count, limit } => {
+                if ctx.looting > 0 {
+                    *amount += (ctx.looting as f32 * count.gen_float(rng)).round() as i32;
+                    if *limit > 0 {
+                        *amount = (*amount).min(*limit);
+                    }
+                }
+            }
+            FunctionKind::LimitCount { limit } => *amount = limit.clamp(*amount),
+            FunctionKind::ApplyBonus {
+                enchantment,
+                formula,
+                parameters,
+            } => {
+                let level = ctx.tool_enchantment(enchantment);
+                *amount = formula.apply(*amount, level, *parameters, rng);
+            }
+            FunctionKind::ExplosionDecay => {
+                if let Some(radius) = ctx.explosion_radius {
+                    *amount = (0..*amount)
+                        .filter(|_| rng.gen::<f32>() <= 1.0 / radius)
+                        .count() as i32;
+                }
+            }
+            FunctionKind::EnchantRandomly { enchantments } => {
+                enchant_randomly(stack, enchantments, rng)
+            }
+            FunctionKind::EnchantWithLevels { .. } | FunctionKind::Unsupported => (),
+        }
+    }
+}
+
+impl LootTable {
+    /// Generates the loot of this table. Nested tables
+    /// are looked up in `tables`.
+    pub fn generate<R: Rng + ?Sized>(
+        &self,
+        tables: &LootTables,
+        ctx: &LootContext,
+        rng: &mut R,
+    ) -> Vec<ItemStack> {
+        let mut loot = vec![];
+        self.generate_into(tables, ctx, rng, 0, &mut loot);
+
+        let mut stacks = vec![];
+        for (stack, mut amount) in loot {
+            amount = amount.min(i32::from(std::u8::MAX));
+            while amount > 0 {
+                let size = amount.min(i32::from(MAX_STACK_SIZE));
+                let mut split = stack.clone();
+                split.amount = size as u8;
+                stacks.push(split);
+                amount -= size;
+            }
+        }
+        stacks
+    }
+
+    fn generate_into<R: Rng + ?Sized>(
+        &self,
+        tables: &LootTables,
+        ctx: &LootContext,
+        rng: &mut R,
+        depth: usize,
+        loot: &mut Vec<(ItemStack, i32)>,
+    ) {
+        for pool in &self.pools {
+            if !all(&pool.conditions, ctx, rng) {
+                continue;
+            }
+
+            let rolls = pool.rolls.gen_int(rng)
+                + (pool.bonus_rolls.gen_float(rng) * ctx.luck).floor() as i32;
+            for _ in 0..rolls {
+                let start = loot.len();
+                pool.roll(tables, ctx, rng, depth, loot);
+                for (stack, amount) in &mut loot[start..] {
+                    for function in &pool.functions {
+                        function.apply(stack, amount, ctx, rng);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Pool {
+    /// Selects an entry and generates its loot.
+    fn roll<R: Rng + ?Sized>(
+        &self,
+        tables: &LootTables,
+        ctx: &LootContext,
+        rng: &mut R,
+        depth: usize,
+        loot: &mut Vec<(ItemStack, i32)>,
+    ) {
+        let mut leaves = vec![];
+        for entry in &self.entries {
+            entry.expand(ctx, rng, &mut leaves);
+        }
+
+        let candidates: Vec<(&Entry, i32)> = leaves
+            .into_iter()
+            .map(|entry| {
+                let weight = entry.weight as f32 + entry.quality as f32 * ctx.luck;
+                (entry, weight.floor().max(0.0) as i32)
+            })
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+
+        let total: i32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return;
+        }
+
+        let mut choice = rng.gen_range(0, total);
+        let entry = candidates
+            .iter()
+            .find(|(_, weight)| {
+                choice -= weight;
+                choice < 0
+            })
+            .map(|(entry, _)| *entry)
+            .unwrap();
+
+        let start = loot.len();
+        match &entry.kind {
+            EntryKind::Item { name } => match Item::from_identifier(name) {
+                Some(item) => loot.push((ItemStack::new(item, 1), 1)),
+                None => warn!("Unknown item {} in loot table", name),
+            },
+            EntryKind::LootTable { name } => {
+                if depth >= MAX_DEPTH {
+                    warn!("Loot table {} is nested too deeply", name);
+                    return;
+                }
+                if let Some(table) = tables.get(name) {
+                    table.generate_into(tables, ctx, rng, depth + 1, loot);
+                }
+            }
+            EntryKind::Empty
+            | EntryKind::Alternatives { .. }
+            | EntryKind::Sequence { .. }
+            | EntryKind::Group { .. }
+            | EntryKind::Unsupported => (),
+        }
+
+        for (stack, amount) in &mut loot[start..] {
+            for function in &entry.functions {
+                function.apply(stack, amount, ctx, rng);
+            }
+        }
+    }
+}
+
+impl Entry {
+    /// Adds the entries which this entry expands to: itself
+    /// for single entries, and the expanded children for
+    /// composite entries. Returns whether the entry expanded,
+    /// which for `alternatives` requires one child to expand
+    /// and for `sequence` requires all children to expand.
+    fn expand<'a, R: Rng + ?Sized>(
+        &'a self,
+        ctx: &LootContext,
+        rng: &mut R,
+        leaves: &mut Vec<&'a Entry>,
+    ) -> bool {
+        if !all(&self.conditions, ctx, rng) {
+            return false;
+        }
+
+        match &self.kind {
+            EntryKind::Alternatives { children } => {
+                children.iter().any(|child| child.expand(ctx, rng, leaves))
+            }
+            EntryKind::Sequence { children } => {
+                children.iter().all(|child| child.expand(ctx, rng, leaves))
+            }
+            EntryKind::Group { children } => {
+                for child in children {
+                    child.expand(ctx, rng, leaves);
+                }
+                true
+            }
+            _ => {
+                leaves.push(self);
+                true
+            }
+        }
+    }
+}
+
+/// Resource containing all loaded loot tables.
+#[derive(Debug, Default)]
+pub struct LootTables {
+    tables: HashMap<String, LootTable>,
+}
+
+impl LootTables {
+    /// Loads the bundled tables, followed by the tables of the
+    /// datapacks in the given world directory. Tables which
+    /// fail to load are skipped.
+    pub fn load(world_dir: &Path) -> Self {
+        let mut tables = Self::bundled();
+
+        for pack in datapack::pack_dirs(world_dir) {
+            let namespaces = match fs::read_dir(pack.join("data")) {
+                Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()),
+                Err(_) => continue,
+            };
+
+            for namespace in namespaces {
+                let name = match namespace.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+
+                let dir = namespace.join("loot_tables");
+                for (path, id) in datapack::files(&dir, &name, "json") {
+                    let table = fs::read_to_string(&path)
+                        .map_err(failure::Error::from)
+                        .and_then(|s| serde_json::from_str::<LootTable>(&s).map_err(Into::into));
+                    match table {
+                        Ok(table) => tables.insert(id, table),
+                        Err(e) => warn!("Failed to read loot table {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+
+        tables
+    }
+
+    /// Returns the tables bundled with the server.
+    pub fn bundled() -> Self {
+        let mut tables = Self::default();
+        for (id, json) in BUNDLED {
+            let table = serde_json::from_str(json).expect("invalid bundled loot table");
+            tables.insert(id.to_string(), table);
+        }
+        tables
+    }
+
+    pub fn insert(&mut self, id: String, table: LootTable) {
+        self.tables.insert(id, table);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LootTable> {
+        self.tables.get(id)
+    }
+
+    /// Returns the number of loaded tables.
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Generates the loot of the table with the given identifier,
+    /// or nothing if there is no such table.
+    pub fn generate<R: Rng + ?Sized>(
+        &self,
+        id: &str,
+        ctx: &LootContext,
+        rng: &mut R,
+    ) -> Vec<ItemStack> {
+        match self.get(id) {
+            Some(table) => table.generate(self, ctx, rng),
+            None => vec![],
+        }
+    }
+
+    /// Generates the items dropped by a block. Blocks
+    /// without a loot table drop themselves.
+    pub fn block_drops<R: Rng + ?Sized>(
+        &self,
+        block: Block,
+        ctx: &LootContext,
+        rng: &mut R,
+    ) -> Vec<ItemStack> {
+        let (name, _) = block.to_name_and_props();
+        let id = match name.find(':') {
+            Some(index) => format!("{}:blocks/{}", &name[..index], &name[index + 1..]),
+            None => format!("minecraft:blocks/{}", name),
+        };
+
+        let ctx = LootContext {
+            block: Some(block),
+            ..ctx.clone()
+        };
+        match self.get(&id) {
+            Some(table) => table.generate(self, &ctx, rng),
+            None => block
+                .to_item()
+                .filter(|item| *item != Item::Air)
+                .map(|item| vec![ItemStack::new(item, 1)])
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Spawns item entities for the given stacks
+/// at the center of a block.
+pub fn drop_at_block<R: Rng + ?Sized>(
+    lazy: &LazyUpdate,
+    entities: &EntitiesRes,
+    pos: BlockPosition,
+    stacks: Vec<ItemStack>,
+    tick: u64,
+    rng: &mut R,
+) {
+    for stack in stacks {
+        // Offsets and velocity as used by vanilla
+        // in `Block.spawnAsEntity`.
+        let pos = position!(
+            f64::from(pos.x) + rng.gen_range(0.25, 0.75),
+            f64::from(pos.y) + rng.gen_range(0.25, 0.75),
+            f64::from(pos.z) + rng.gen_range(0.25, 0.75),
+            0.0,
+            0.0,
+            false
+        );
+        let velocity = glm::vec3(rng.gen_range(-0.1, 0.1), 0.2, rng.gen_range(-0.1, 0.1));
+
+        item::create(lazy, entities, stack, tick + PICKUP_DELAY)
+            .with(PositionComponent {
+                current: pos,
+                previous: pos,
+            })
+            .with(VelocityComponent(velocity))
+            .build();
+    }
+}
+
+/// Spawns item entities for the given stacks at the
+/// position of an entity, such as a killed mob.
+pub fn drop_at_entity<R: Rng + ?Sized>(
+    lazy: &LazyUpdate,
+    entities: &EntitiesRes,
+    pos: Position,
+    stacks: Vec<ItemStack>,
+    tick: u64,
+    rng: &mut R,
+) {
+    for stack in stacks {
+        let pos = position!(pos.x, pos.y, pos.z, 0.0, 0.0, false);
+        let velocity = glm::vec3(rng.gen_range(-0.1, 0.1), 0.2, rng.gen_range(-0.1, 0.1));
+
+        item::create(lazy, entities, stack, tick + PICKUP_DELAY)
+            .with(PositionComponent {
+                current: pos,
+                previous: pos,
+            })
+            .with(VelocityComponent(velocity))
+            .build();
+    }
+}
+
+/// System which drops the loot of blocks broken by players
+/// who are not in creative mode and can harvest them.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct BlockDropSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for BlockDropSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        ReadStorage<'a, PlayerComponent>,
+        Read<'a, LootTables>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+        ReadStorage<'a, InventoryComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, players, tables, lazy, entities, tick, inventories) = data;
+
+        let mut rng = rand::thread_rng();
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let player = match event.cause {
+                BlockUpdateCause::Player(player) => player,
+                _ => continue,
+            };
+            if event.new_block != Block::Air || event.old_block == Block::Air {
+                continue;
+            }
+            match players.get(player) {
+                Some(player) if player.gamemode != Gamemode::Creative => (),
+                _ => continue,
+            }
+            // Shulker boxes are dropped with their contents
+            // by the `ContainerBreakSystem`.
+            if is_shulker_box(event.old_block) {
+                continue;
+            }
+            let held = inventories
+                .get(player)
+                .and_then(|inventory| inventory.item_in_main_hand());
+            if !tool::can_harvest(event.old_block, held) {
+                continue;
+            }
+
+            let ctx = LootContext {
+                tool: held.cloned(),
+                ..Default::default()
+            };
+            let drops = tables.block_drops(event.old_block, &ctx, &mut rng);
+            drop_at_block(&lazy, &entities, event.pos, drops, tick.0, &mut rng);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(BlockDropSystem::default(), BLOCK_DROPS, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::ItemComponent;
+    use crate::testframework as t;
+    use feather_blocks::RedstoneOreData;
+    use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use specs::{Join, WorldExt};
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::seed_from_u64(0)
+    }
+
+    fn table(json: &str) -> LootTable {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_bundled() {
+        let tables = LootTables::bundled();
+        assert_eq!(tables.len(), BUNDLED.len());
+
+        let mut rng = rng();
+        let ctx = LootContext::default();
+        for (id, _) in BUNDLED {
+            for stack in tables.generate(id, &ctx, &mut rng) {
+                assert!(stack.amount > 0 && stack.amount <= MAX_STACK_SIZE);
+            }
+        }
+
+        assert_eq!(
+            tables.generate("minecraft:blocks/bookshelf", &ctx, &mut rng),
+            vec![ItemStack::new(Item::Book, 3)]
+        );
+        assert!(tables
+            .generate("minecraft:blocks/glass", &ctx, &mut rng)
+            .is_empty());
+        assert!(tables
+            .generate("minecraft:nonexistent", &ctx, &mut rng)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_number_provider() {
+        let mut rng = rng();
+
+        assert_eq!(NumberProvider::Constant(3.0).gen_int(&mut rng), 3);
+        assert_eq!(
+            NumberProvider::Uniform { min: 2.0, max: 2.0 }.gen_int(&mut rng),
+            2
+        );
+        for _ in 0..100 {
+            let value = NumberProvider::Uniform { min: 1.0, max: 3.0 }.gen_int(&mut rng);
+            assert!(value >= 1 && value <= 3);
+            let value = NumberProvider::Binomial { n: 4, p: 0.5 }.gen_int(&mut rng);
+            assert!(value >= 0 && value <= 4);
+        }
+
+        let table = table(
+            r#"{"pools": [{"rolls": {"type": "binomial", "n": 2, "p": 1.0}, "entries": [{"type": "item", "name": "minecraft:stick"}]}]}"#,
+        );
+        assert_eq!(
+            table.pools[0].rolls,
+            NumberProvider::Binomial { n: 2, p: 1.0 }
+        );
+    }
+
+    #[test]
+    fn test_weights() {
+        let table = table(
+            r#"{"pools": [{"rolls": 1000, "entries": [
+                {"type": "item", "name": "minecraft:stick", "weight": 3},
+                {"type": "minecraft:item", "name": "minecraft:diamond"},
+                {"type": "empty", "weight": 0, "quality": 10}
+            ]}]}"#,
+        );
+
+        let tables = LootTables::default();
+        let mut rng = rng();
+        let loot = table.generate(&tables, &LootContext::default(), &mut rng);
+        let count = |item| -> u32 {
+            loot.iter()
+                .filter(|stack| stack.ty == item)
+                .map(|stack| u32::from(stack.amount))
+                .sum()
+        };
+        assert_eq!(count(Item::Stick) + count(Item::Diamond), 1000);
+        assert!(count(Item::Stick) > 650 && count(Item::Stick) < 850);
+
+        // The empty entry now has a weight of 10 * 100 - 0.
+        let ctx = LootContext {
+            luck: 100.0,
+            ..Default::default()
+        };
+        let loot = table.generate(&tables, &ctx, &mut rng);
+        assert!(loot.len() < 100);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:bone"}],
+                "conditions": [{"condition": "killed_by_player"}]},
+                {"rolls": 1, "entries": [{"type": "item", "name": "minecraft:stick",
+                "conditions": [{"condition": "minecraft:random_chance", "chance": 0.0}]}]},
+                {"rolls": 1, "entries": [{"type": "item", "name": "minecraft:coal"}],
+                "conditions": [{"condition": "entity_properties", "entity": "this"}]}]}"#,
+        );
+
+        let tables = LootTables::default();
+        let mut rng = rng();
+        assert!(table
+            .generate(&tables, &LootContext::default(), &mut rng)
+            .is_empty());
+
+        let ctx = LootContext {
+            killed_by_player: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            table.generate(&tables, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Bone, 1)]
+        );
+    }
+
+    #[test]
+    fn test_enchant_randomly() {
+        let tables = LootTables::default();
+        let mut rng = rng();
+
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:book",
+                "functions": [{"function": "enchant_randomly"}]}]}]}"#,
+        );
+        let loot = table.generate(&tables, &LootContext::default(), &mut rng);
+        assert_eq!(loot.len(), 1);
+        assert_eq!(loot[0].ty, Item::EnchantedBook);
+        let stored = &loot[0].tag().unwrap().stored_enchantments;
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].level >= 1);
+
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:iron_sword",
+                "functions": [{"function": "enchant_randomly",
+                "enchantments": ["sharpness"]}]}]}]}"#,
+        );
+        for _ in 0..20 {
+            let loot = table.generate(&tables, &LootContext::default(), &mut rng);
+            let level = loot[0].enchantment_level("minecraft:sharpness").unwrap();
+            assert!(level >= 1 && level <= 5);
+        }
+
+        // Only applicable enchantments are chosen.
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:bow",
+                "functions": [{"function": "enchant_randomly"}]}]}]}"#,
+        );
+        for _ in 0..20 {
+            let loot = table.generate(&tables, &LootContext::default(), &mut rng);
+            let id = &loot[0].enchantments()[0].id;
+            let target = ENCHANTMENTS
+                .iter()
+                .find(|(other, _, _)| other == id)
+                .map(|(_, _, target)| *target)
+                .unwrap();
+            assert!(target == EnchantmentTarget::Bow || target == EnchantmentTarget::Breakable);
+        }
+    }
+
+    #[test]
+    fn test_functions() {
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:stick", "functions": [
+                {"function": "set_count", "count": 100},
+                {"function": "looting_enchant", "count": 2, "limit": 105},
+                {"function": "enchant_randomly"},
+                {"function": "set_nbt", "tag": "{}"}
+            ]}]}]}"#,
+        );
+
+        let tables = LootTables::default();
+        let mut rng = rng();
+        assert_eq!(
+            table.generate(&tables, &LootContext::default(), &mut rng),
+            vec![
+                ItemStack::new(Item::Stick, 64),
+                ItemStack::new(Item::Stick, 36)
+            ]
+        );
+
+        let ctx = LootContext {
+            looting: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            table.generate(&tables, &ctx, &mut rng),
+            vec![
+                ItemStack::new(Item::Stick, 64),
+                ItemStack::new(Item::Stick, 41)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_tables() {
+        let mut tables = LootTables::default();
+        tables.insert(
+            String::from("test:outer"),
+            table(
+                r#"{"pools": [{"rolls": 2, "entries": [{"type": "loot_table", "name": "test:inner"}],
+                "functions": [{"function": "set_count", "count": 5}]}]}"#,
+            ),
+        );
+        tables.insert(
+            String::from("test:inner"),
+            table(r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:apple"}]}]}"#),
+        );
+        tables.insert(
+            String::from("test:recursive"),
+            table(r#"{"pools": [{"rolls": 1, "entries": [{"type": "loot_table", "name": "test:recursive"}]}]}"#),
+        );
+
+        let mut rng = rng();
+        let ctx = LootContext::default();
+        assert_eq!(
+            tables.generate("test:outer", &ctx, &mut rng),
+            vec![
+                ItemStack::new(Item::Apple, 5),
+                ItemStack::new(Item::Apple, 5)
+            ]
+        );
+        assert!(tables.generate("test:recursive", &ctx, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_block_drops() {
+        let tables = LootTables::bundled();
+        let mut rng = rng();
+        let ctx = LootContext::default();
+
+        assert_eq!(
+            tables.block_drops(Block::Stone, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Cobblestone, 1)]
+        );
+        assert_eq!(
+            tables.block_drops(Block::Dirt, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Dirt, 1)]
+        );
+        assert!(tables.block_drops(Block::Air, &ctx, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_composite_entries() {
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "alternatives", "children": [
+                {"type": "item", "name": "minecraft:bone", "conditions": [{"condition": "killed_by_player"}]},
+                {"type": "sequence", "children": [
+                    {"type": "item", "name": "minecraft:stick", "conditions": [{"condition": "random_chance", "chance": 0.0}]},
+                    {"type": "item", "name": "minecraft:apple"}
+                ]},
+                {"type": "item", "name": "minecraft:coal"}
+            ]}]}, {"rolls": 1, "entries": [{"type": "minecraft:dynamic", "name": "minecraft:contents"}]}]}"#,
+        );
+
+        let tables = LootTables::default();
+        let mut rng = rng();
+        // The sequence fails on its first child,
+        // so the last alternative is generated.
+        assert_eq!(
+            table.generate(&tables, &LootContext::default(), &mut rng),
+            vec![ItemStack::new(Item::Coal, 1)]
+        );
+
+        let ctx = LootContext {
+            killed_by_player: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            table.generate(&tables, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Bone, 1)]
+        );
+    }
+
+    #[test]
+    fn test_match_tool() {
+        let tables = LootTables::bundled();
+        let mut rng = rng();
+
+        let mut pickaxe = ItemStack::new(Item::DiamondPickaxe, 1);
+        pickaxe.add_enchantment("minecraft:silk_touch", 1);
+        let ctx = LootContext {
+            tool: Some(pickaxe),
+            ..Default::default()
+        };
+        assert_eq!(
+            tables.block_drops(Block::Stone, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Stone, 1)]
+        );
+        assert_eq!(
+            tables.block_drops(Block::Glass, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Glass, 1)]
+        );
+
+        let ctx = LootContext {
+            tool: Some(ItemStack::new(Item::Shears, 1)),
+            ..Default::default()
+        };
+        assert_eq!(
+            tables.block_drops(Block::OakLeaves(Default::default()), &ctx, &mut rng),
+            vec![ItemStack::new(Item::OakLeaves, 1)]
+        );
+
+        let predicate: ItemPredicate = serde_json::from_str(
+            r#"{"item": "minecraft:shears", "count": {"max": 1}, "enchantments": [{"levels": 2}]}"#,
+        )
+        .unwrap();
+        let mut shears = ItemStack::new(Item::Shears, 1);
+        assert!(!predicate.test(&shears));
+        shears.add_enchantment("minecraft:efficiency", 2);
+        assert!(predicate.test(&shears));
+        assert!(!predicate.test(&shears.with_amount(2)));
+    }
+
+    #[test]
+    fn test_fortune() {
+        let tables = LootTables::bundled();
+        let mut rng = rng();
+        let mut pickaxe = ItemStack::new(Item::IronPickaxe, 1);
+        pickaxe.add_enchantment("minecraft:fortune", 3);
+        let ctx = LootContext {
+            tool: Some(pickaxe),
+            ..Default::default()
+        };
+
+        let mut diamonds = 0;
+        for _ in 0..100 {
+            let drops = tables.block_drops(Block::DiamondOre, &ctx, &mut rng);
+            assert_eq!(drops.len(), 1);
+            assert!(drops[0].amount >= 1 && drops[0].amount <= 4);
+            diamonds += u32::from(drops[0].amount);
+        }
+        // Fortune III yields 2.2 diamonds on average.
+        assert!(diamonds > 180 && diamonds < 260);
+
+        // Glowstone is limited to 4 dust.
+        for _ in 0..100 {
+            let drops = tables.block_drops(Block::Glowstone, &ctx, &mut rng);
+            assert!(drops[0].amount >= 2 && drops[0].amount <= 4);
+        }
+
+        // The chance of flint is 1 for fortune III.
+        assert_eq!(
+            tables.block_drops(Block::Gravel, &ctx, &mut rng),
+            vec![ItemStack::new(Item::Flint, 1)]
+        );
+
+        let bonus = BonusParameters {
+            extra: 3,
+            probability: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            BonusFormula::BinomialWithBonusCount.apply(1, 2, bonus, &mut rng),
+            6
+        );
+        assert_eq!(BonusFormula::OreDrops.apply(2, 0, bonus, &mut rng), 2);
+    }
+
+    #[test]
+    fn test_block_state_property() {
+        let table = table(
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:redstone",
+                "conditions": [{"condition": "block_state_property", "block": "minecraft:redstone_ore",
+                "properties": {"lit": "true"}}]}]}]}"#,
+        );
+
+        let mut tables = LootTables::default();
+        tables.insert(String::from("minecraft:blocks/redstone_ore"), table);
+        let mut rng = rng();
+        let ctx = LootContext::default();
+        assert!(tables
+            .block_drops(
+                Block::RedstoneOre(RedstoneOreData { lit: false }),
+                &ctx,
+                &mut rng
+            )
+            .is_empty());
+        assert_eq!(
+            tables.block_drops(
+                Block::RedstoneOre(RedstoneOreData { lit: true }),
+                &ctx,
+                &mut rng
+            ),
+            vec![ItemStack::new(Item::Redstone, 1)]
+        );
+    }
+
+    #[test]
+    fn test_load_datapack() {
+        let dir = std::env::temp_dir().join(format!("feather-loot-{}", uuid::Uuid::new_v4()));
+        let pack = dir.join("datapacks").join("a");
+        fs::create_dir_all(pack.join("data/minecraft/loot_tables/blocks")).unwrap();
+        fs::create_dir_all(pack.join("data/test/loot_tables")).unwrap();
+        fs::write(pack.join("pack.mcmeta"), "{}").unwrap();
+        fs::write(
+            pack.join("data/minecraft/loot_tables/blocks/stone.json"),
+            r#"{"pools": [{"rolls": 1, "entries": [{"type": "item", "name": "minecraft:diamond"}]}]}"#,
+        )
+        .unwrap();
+        fs::write(pack.join("data/test/loot_tables/broken.json"), "{").unwrap();
+
+        let tables = LootTables::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tables.len(), BUNDLED.len());
+        assert!(tables.get("test:broken").is_none());
+        assert_eq!(
+            tables.block_drops(Block::Stone, &LootContext::default(), &mut rng()),
+            vec![ItemStack::new(Item::Diamond, 1)]
+        );
+    }
+
+    #[test]
+    fn test_block_drop_system() {
+        let (mut w, mut d) = t::builder().with(BlockDropSystem::default(), "").build();
+        w.insert(LootTables::bundled());
+        w.register::<ItemComponent>();
+
+        let player = t::add_player(&mut w);
+        let event = |cause| BlockUpdateEvent {
+            cause,
+            pos: BlockPosition::new(0, 64, 0),
+            old_block: Block::Stone,
+            new_block: Block::Air,
+        };
+
+        // Creative mode
+        t::trigger_event(&w, event(BlockUpdateCause::Player(player.entity)));
+        t::trigger_event(&w, event(BlockUpdateCause::FallingBlock));
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.read_component::<ItemComponent>().join().count(), 0);
+
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+
+        // Stone isn't harvested without a pickaxe.
+        t::trigger_event(&w, event(BlockUpdateCause::Player(player.entity)));
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.read_component::<ItemComponent>().join().count(), 0);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::WoodenPickaxe, 1));
+        t::trigger_event(&w, event(BlockUpdateCause::Player(player.entity)));
+        d.dispatch(&w);
+        w.maintain();
+
+        let items = w.read_component::<ItemComponent>();
+        let positions = w.read_component::<PositionComponent>();
+        let (item, pos) = (&items, &positions).join().next().unwrap();
+        assert_eq!(item.stack, ItemStack::new(Item::Cobblestone, 1));
+        assert_eq!(pos.current.block_pos(), BlockPosition::new(0, 64, 0));
+    }
+}
@@ -0,0 +1,920 @@
+//! Filled maps.
+//!
+//! A filled map item refers by ID to map data, which stores the
+//! area shown by the map and the color of each of its 128 by 128
+//! pixels. Using an empty map creates a new map of the grid cell
+//! containing the player; like in vanilla, the grid is aligned so
+//! that maps of the same scale tile the world.
+//!
+//! Maps are drawn from the terrain around players holding them.
+//! Each tick, one in sixteen pixel columns within 128 blocks of a
+//! holder is rendered, so the map fills in as its holder explores.
+//! Each pixel takes the most common color of the top blocks in the
+//! area it covers and is shaded by its height relative to the pixel
+//! to its north, or by depth for water.
+//!
+//! Holders are sent the pixels which changed since they were last
+//! sent the map, or the whole map when they start holding it, along
+//! with markers for the players on the map.
+//!
+//! Map data is loaded from `data/map_<id>.dat` when first used and
+//! saved periodically and at shutdown. Maps are zoomed out using the
+//! map extending recipe, which surrounds a filled map with paper and
+//! produces a new map of the next scale; see `extend_map`.
+
+use crate::config::Config;
+use crate::dimension::DimensionComponent;
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::{InventoryComponent, InventoryUpdateEvent, PlayerUseItemEvent};
+use crate::systems::{MAP_CREATE, MAP_SAVE, MAP_UPDATE, PLAYER_USE_ITEM};
+use crate::timings::DispatcherBuilderExt;
+use crate::TickCount;
+use feather_core::inventory::SLOT_OFFHAND;
+use feather_core::map::{self, IdCounts, MapData as SavedMap, MAP_SIZE, MAX_SCALE};
+use feather_core::network::packet::implementation::{MapData, MapIcon, MapPixels};
+use feather_core::world::ChunkMap;
+use feather_core::{BlockExt, Chunk, ChunkPosition, Gamemode, Item, ItemStack, Position};
+use hashbrown::{HashMap, HashSet};
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    DispatcherBuilder, Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The distance in blocks from a holder
+/// within which a map is rendered.
+const RENDER_RADIUS: i32 = 128;
+
+/// The number of ticks taken to render
+/// every column of pixels around a holder.
+const RENDER_STEPS: u64 = 16;
+
+/// The interval in ticks at which holders are sent
+/// player markers when no pixels have changed.
+const ICON_INTERVAL: u64 = 10;
+
+/// The interval in ticks at which modified maps are saved.
+const SAVE_INTERVAL: u64 = 6000;
+
+/// The distance in pixels from the center of a map within
+/// which a holder off the map is shown at its edge.
+const OFF_MAP_DISTANCE: f64 = 320.0;
+
+/// Map icon types.
+const ICON_PLAYER: i32 = 0;
+const ICON_PLAYER_OFF_MAP: i32 = 6;
+
+/// Base map colors used in rendering.
+const COLOR_AIR: u8 = 0;
+const COLOR_WATER: u8 = 12;
+
+/// The contents of a map.
+#[derive(Debug, Clone)]
+pub struct MapState {
+    pub scale: u8,
+    pub x_center: i32,
+    pub z_center: i32,
+    /// Whether player markers are shown.
+    pub tracking_position: bool,
+    /// The color of each pixel, row by row.
+    colors: Vec<u8>,
+    /// Whether the map has changed since it was last saved.
+    modified: bool,
+    /// The rectangle of pixels which changed since holders were
+    /// last sent the map, as its minimum and maximum coordinates.
+    dirty: Option<(usize, usize, usize, usize)>,
+    /// The players who were sent the whole map
+    /// and have held it ever since.
+    viewers: HashSet<Entity>,
+}
+
+impl MapState {
+    /// Creates a blank map of the given scale showing
+    /// the grid cell which contains the given position.
+    pub fn new(x: f64, z: f64, scale: u8) -> Self {
+        let size = (MAP_SIZE as i32) << scale;
+        let center = |coord: f64| {
+            let cell = ((coord + 64.0) / f64::from(size)).floor() as i32;
+            cell * size + size / 2 - 64
+        };
+
+        Self {
+            scale,
+            x_center: center(x),
+            z_center: center(z),
+            tracking_position: true,
+            colors: vec![0; MAP_SIZE * MAP_SIZE],
+            modified: true,
+            dirty: None,
+            viewers: HashSet::new(),
+        }
+    }
+
+    pub fn from_saved(data: SavedMap) -> Self {
+        let mut colors: Vec<u8> = data.colors.into_iter().map(|x| x as u8).collect();
+        colors.resize(MAP_SIZE * MAP_SIZE, 0);
+
+        Self {
+            scale: (data.scale.max(0) as u8).min(MAX_SCALE),
+            x_center: data.x_center,
+            z_center: data.z_center,
+            tracking_position: data.tracking_position,
+            colors,
+            modified: false,
+            dirty: None,
+            viewers: HashSet::new(),
+        }
+    }
+
+    pub fn to_saved(&self) -> SavedMap {
+        SavedMap {
+            scale: self.scale as i8,
+            dimension: 0,
+            tracking_position: self.tracking_position,
+            unlimited_tracking: false,
+            x_center: self.x_center,
+            z_center: self.z_center,
+            colors: self.colors.iter().map(|x| *x as i8).collect(),
+        }
+    }
+
+    /// Returns the width in blocks of the area covered by a pixel.
+    pub fn blocks_per_pixel(&self) -> i32 {
+        1 << self.scale
+    }
+
+    /// Returns the color of a pixel, which is its base
+    /// color multiplied by four plus its shade.
+    pub fn color_at(&self, x: usize, z: usize) -> u8 {
+        self.colors[z * MAP_SIZE + x]
+    }
+
+    fn set_color(&mut self, x: usize, z: usize, color: u8) {
+        let index = z * MAP_SIZE + x;
+        if self.colors[index] == color {
+            return;
+        }
+
+        self.colors[index] = color;
+        self.modified = true;
+        self.dirty = Some(match self.dirty {
+            Some((min_x, min_z, max_x, max_z)) => {
+                (min_x.min(x), min_z.min(z), max_x.max(x), max_z.max(z))
+            }
+            None => (x, z, x, z),
+        });
+    }
+
+    /// Returns the offset in pixels of a position from the center of the map.
+    fn pixel_offset(&self, pos: Position) -> (f64, f64) {
+        let scale = f64::from(self.blocks_per_pixel());
+        (
+            (pos.x - f64::from(self.x_center)) / scale,
+            (pos.z - f64::from(self.z_center)) / scale,
+        )
+    }
+
+    /// Returns the coordinates of the first block
+    /// in the area covered by a pixel.
+    fn pixel_origin(&self, x: usize, z: usize) -> (i32, i32) {
+        let scale = self.blocks_per_pixel();
+        (
+            (self.x_center / scale + x as i32 - 64) * scale,
+            (self.z_center / scale + z as i32 - 64) * scale,
+        )
+    }
+
+    /// Returns the markers shown to a holder of the map at the
+    /// given position: one for each player on the map and,
+    /// if the holder is off the map, one for the holder at its edge.
+    fn icons(&self, players: &[Position], holder: Position) -> Vec<MapIcon> {
+        if !self.tracking_position {
+            return vec![];
+        }
+
+        let mut icons: Vec<MapIcon> = players
+            .iter()
+            .filter_map(|pos| self.player_icon(*pos))
+            .collect();
+
+        let (x, z) = self.pixel_offset(holder);
+        let on_map = x.abs() < 64.0 && z.abs() < 64.0;
+        if !on_map && x.abs() < OFF_MAP_DISTANCE && z.abs() < OFF_MAP_DISTANCE {
+            icons.push(MapIcon {
+                ty: ICON_PLAYER_OFF_MAP,
+                x: icon_coordinate(x),
+                z: icon_coordinate(z),
+                direction: 0,
+                display_name: None,
+            });
+        }
+
+        icons
+    }
+
+    /// Returns the marker of a player at the given
+    /// position, or `None` if the player is off the map.
+    fn player_icon(&self, pos: Position) -> Option<MapIcon> {
+        let (x, z) = self.pixel_offset(pos);
+        if x.abs() >= 64.0 || z.abs() >= 64.0 {
+            return None;
+        }
+
+        let direction = (f64::from(pos.yaw) * 16.0 / 360.0).round() as i32;
+        Some(MapIcon {
+            ty: ICON_PLAYER,
+            x: icon_coordinate(x),
+            z: icon_coordinate(z),
+            direction: direction.rem_euclid(16) as u8,
+            display_name: None,
+        })
+    }
+
+    /// Returns the pixels which changed since this
+    /// was last called, clearing the changes.
+    fn take_changed_pixels(&mut self) -> Option<MapPixels> {
+        let (min_x, min_z, max_x, max_z) = self.dirty.take()?;
+
+        let mut data = Vec::with_capacity((max_x - min_x + 1) * (max_z - min_z + 1));
+        for z in min_z..=max_z {
+            data.extend_from_slice(&self.colors[z * MAP_SIZE + min_x..=z * MAP_SIZE + max_x]);
+        }
+
+        Some(MapPixels {
+            columns: (max_x - min_x + 1) as u8,
+            rows: (max_z - min_z + 1) as u8,
+            x: min_x as u8,
+            z: min_z as u8,
+            data,
+        })
+    }
+
+    fn all_pixels(&self) -> MapPixels {
+        MapPixels {
+            columns: MAP_SIZE as u8,
+            rows: MAP_SIZE as u8,
+            x: 0,
+            z: 0,
+            data: self.colors.clone(),
+        }
+    }
+}
+
+/// Converts an offset in pixels from the center of
+/// a map to a marker coordinate, clamped to the map.
+fn icon_coordinate(offset: f64) -> i8 {
+    (offset * 2.0 + 0.5).floor().max(-128.0).min(127.0) as i8
+}
+
+/// The maps of the world, which are loaded as they are used.
+#[derive(Default)]
+pub struct MapRegistry {
+    maps: HashMap<i32, MapState>,
+    /// Maps whose data could not be loaded.
+    missing: HashSet<i32>,
+    /// The ID of the most recently created map.
+    last_id: Option<i32>,
+    /// Whether `last_id` changed since it was last saved.
+    ids_modified: bool,
+}
+
+impl MapRegistry {
+    /// Creates a registry allocating IDs after
+    /// those of the maps in the given world.
+    pub fn load(world_dir: &Path) -> Self {
+        let counts = map::load_id_counts(world_dir).unwrap_or_else(|e| {
+            warn!("Failed to load map IDs: {}", e);
+            IdCounts::default()
+        });
+
+        Self {
+            last_id: counts.map,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a new map, returning its ID.
+    pub fn create(&mut self, mut map: MapState) -> i32 {
+        let id = self.last_id.map_or(0, |id| id + 1);
+        self.last_id = Some(id);
+        self.ids_modified = true;
+
+        map.modified = true;
+        self.maps.insert(id, map);
+        id
+    }
+
+    /// Returns the map with the given ID, loading it
+    /// from the world directory if necessary.
+    pub fn get_mut(&mut self, world_dir: &Path, id: i32) -> Option<&mut MapState> {
+        if !self.maps.contains_key(&id) && !self.missing.contains(&id) {
+            match map::load_map(world_dir, id) {
+                Ok(data) => {
+                    self.maps.insert(id, MapState::from_saved(data));
+                }
+                Err(e) => {
+                    warn!("Failed to load map {}: {}", id, e);
+                    self.missing.insert(id);
+                }
+            }
+        }
+
+        self.maps.get_mut(&id)
+    }
+
+    /// Saves the maps which changed since they were
+    /// last saved, along with the last map ID.
+    pub fn save(&mut self, world_dir: &Path) {
+        for (id, map) in &mut self.maps {
+            if !map.modified {
+                continue;
+            }
+            match map::save_map(world_dir, *id, map.to_saved()) {
+                Ok(()) => map.modified = false,
+                Err(e) => warn!("Failed to save map {}: {}", id, e),
+            }
+        }
+
+        if self.ids_modified {
+            match map::save_id_counts(world_dir, IdCounts { map: self.last_id }) {
+                Ok(()) => self.ids_modified = false,
+                Err(e) => warn!("Failed to save map IDs: {}", e),
+            }
+        }
+    }
+}
+
+/// Returns the result of the map extending recipe
+/// (`crafting_special_mapextending`) for a 3x3 crafting grid:
+/// a filled map surrounded by paper produces a new map of
+/// the next scale, covering the area around the original.
+///
+/// Returns `None` if the grid does not match the recipe or the
+/// map is already at the largest scale. A new map is created
+/// each time this succeeds, so it should only be called when
+/// the result is taken.
+pub fn extend_map(
+    grid: &[Option<ItemStack>],
+    maps: &mut MapRegistry,
+    world_dir: &Path,
+) -> Option<ItemStack> {
+    if grid.len() != 9 {
+        return None;
+    }
+    for (i, slot) in grid.iter().enumerate() {
+        let expected = if i == 4 { Item::FilledMap } else { Item::Paper };
+        match slot {
+            Some(stack) if stack.ty == expected => (),
+            _ => return None,
+        }
+    }
+
+    let original = grid[4].as_ref()?;
+    let map = maps.get_mut(world_dir, original.map_id()?)?;
+    if map.scale >= MAX_SCALE {
+        return None;
+    }
+
+    let mut zoomed = MapState::new(
+        f64::from(map.x_center),
+        f64::from(map.z_center),
+        map.scale + 1,
+    );
+    zoomed.tracking_position = map.tracking_position;
+
+    let mut result = original.with_amount(1);
+    result.set_map_id(maps.create(zoomed));
+    Some(result)
+}
+
+/// The color and height of the area covered by a pixel.
+struct PixelSample {
+    base_color: u8,
+    height: f64,
+    water_depth: f64,
+}
+
+/// Returns the base color, height and water depth of
+/// the top block with a color in a column of a chunk.
+fn top_block(chunk: &Chunk, x: usize, z: usize) -> (u8, usize, usize) {
+    let top_section = match (0..16).rev().find(|&i| chunk.section(i).is_some()) {
+        Some(section) => section,
+        None => return (COLOR_AIR, 0, 0),
+    };
+
+    for y in (0..top_section * 16 + 16).rev() {
+        let block = chunk.block_at(x, y, z);
+        let color = block.map_color();
+        if color == COLOR_AIR {
+            continue;
+        }
+
+        let mut depth = 0;
+        if color == COLOR_WATER {
+            while y > depth && chunk.block_at(x, y - depth - 1, z).is_fluid() {
+                depth += 1;
+            }
+        }
+        return (color, y, depth);
+    }
+
+    (COLOR_AIR, 0, 0)
+}
+
+/// Samples the `size` by `size` blocks starting at the given
+/// position. Returns `None` if any of them are not loaded.
+fn sample_pixel(chunk_map: &ChunkMap, x: i32, z: i32, size: i32) -> Option<PixelSample> {
+    let mut counts = [0u16; 64];
+    let mut height = 0;
+    let mut water_depth = 0;
+
+    for x in x..x + size {
+        for z in z..z + size {
+            let chunk = chunk_map.chunk_at(ChunkPosition::new(x >> 4, z >> 4))?;
+            let (color, y, depth) = top_block(chunk, (x & 15) as usize, (z & 15) as usize);
+            counts[color as usize] += 1;
+            height += y;
+            water_depth += depth;
+        }
+    }
+
+    // The most common color; the first one in case of a tie.
+    let base_color = (0..counts.len())
+        .rev()
+        .max_by_key(|&color| counts[color])
+        .unwrap_or(0) as u8;
+
+    let columns = f64::from(size * size);
+    Some(PixelSample {
+        base_color,
+        height: height as f64 / columns,
+        water_depth: water_depth as f64 / columns,
+    })
+}
+
+/// Returns the shade of a pixel from 0 (darkest) to 2 (brightest).
+/// Land is shaded by its height relative to the pixel to its north;
+/// water is shaded by depth. Neighboring pixels are dithered.
+fn shade(sample: &PixelSample, north_height: f64, scale: i32, parity: usize) -> u8 {
+    if sample.base_color == COLOR_WATER {
+        let depth = sample.water_depth * 0.1 + parity as f64 * 0.2;
+        if depth < 0.5 {
+            2
+        } else if depth > 0.9 {
+            0
+        } else {
+            1
+        }
+    } else {
+        let slope = (sample.height - north_height) * 4.0 / f64::from(scale + 4)
+            + (parity as f64 - 0.5) * 0.4;
+        if slope > 0.6 {
+            2
+        } else if slope < -0.6 {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Renders the given rows of a column of pixels.
+fn render_column(map: &mut MapState, chunk_map: &ChunkMap, x: usize, rows: (usize, usize)) {
+    let scale = map.blocks_per_pixel();
+    let (start, end) = rows;
+
+    // The pixel before the first row is sampled
+    // only to shade the first row.
+    let mut north_height = None;
+    for z in start.saturating_sub(1)..end {
+        let (block_x, block_z) = map.pixel_origin(x, z);
+        let sample = match sample_pixel(chunk_map, block_x, block_z, scale) {
+            Some(sample) => sample,
+            None => {
+                north_height = None;
+                continue;
+            }
+        };
+
+        if z >= start {
+            let color = if sample.base_color == COLOR_AIR {
+                0
+            } else {
+                let north = north_height.unwrap_or(sample.height);
+                sample.base_color * 4 + shade(&sample, north, scale, (x + z) & 1)
+            };
+            map.set_color(x, z, color);
+        }
+        north_height = Some(sample.height);
+    }
+}
+
+/// Renders this tick's share of the pixels around a holder of a map.
+fn render_around(map: &mut MapState, chunk_map: &ChunkMap, holder: Position, tick: u64) {
+    let radius = RENDER_RADIUS / map.blocks_per_pixel();
+    let (offset_x, offset_z) = map.pixel_offset(holder);
+    let center_x = offset_x.floor() as i32 + 64;
+    let center_z = offset_z.floor() as i32 + 64;
+
+    let step = (tick % RENDER_STEPS) as i32;
+    for x in (center_x - radius).max(0)..(center_x + radius).min(MAP_SIZE as i32) {
+        if x.rem_euclid(RENDER_STEPS as i32) != step {
+            continue;
+        }
+
+        let dx = x - center_x;
+        let extent = f64::from(radius * radius - dx * dx).sqrt() as i32;
+        let start = (center_z - extent).max(0);
+        let end = (center_z + extent).min(MAP_SIZE as i32);
+        if start < end {
+            render_column(map, chunk_map, x as usize, (start as usize, end as usize));
+        }
+    }
+}
+
+/// System which creates a filled map when a
+/// player uses an empty map.
+#[derive(Default)]
+pub struct MapCreateSystem {
+    reader: Option<ReaderId<PlayerUseItemEvent>>,
+}
+
+impl<'a> System<'a> for MapCreateSystem {
+    type SystemData = (
+        Read<'a, EventChannel<PlayerUseItemEvent>>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Write<'a, MapRegistry>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            use_events,
+            mut inventories,
+            positions,
+            players,
+            dimensions,
+            mut maps,
+            mut inventory_updates,
+        ) = data;
+
+        for event in use_events.read(self.reader.as_mut().unwrap()) {
+            let player = event.player;
+
+            // Maps can only be created in the primary dimension.
+            if dimensions.get(player).is_some() {
+                continue;
+            }
+
+            let inventory = continue_if_none!(inventories.get_mut(player));
+            let slot = inventory.hand_slot(event.hand);
+            let stack = match inventory.item_at(slot) {
+                Some(stack) if stack.ty == Item::Map => stack.clone(),
+                _ => continue,
+            };
+            let pos = continue_if_none!(positions.get(player)).current;
+
+            let id = maps.create(MapState::new(pos.x, pos.z, 0));
+            let mut filled = ItemStack::new(Item::FilledMap, 1);
+            filled.set_map_id(id);
+            debug!("Created map {} at {:?}", id, pos);
+
+            // Like in vanilla, players in creative mode keep the empty map.
+            let creative = players
+                .get(player)
+                .map_or(false, |player| player.gamemode == Gamemode::Creative);
+
+            let mut slots = smallvec![slot];
+            if stack.amount == 1 && !creative {
+                inventory.set_item_at(slot, filled);
+            } else {
+                if !creative {
+                    inventory.set_item_at(slot, stack.with_amount(stack.amount - 1));
+                }
+                let (affected, _) = inventory.collect_item(filled);
+                slots.extend(affected);
+            }
+
+            inventory_updates.single_write(InventoryUpdateEvent { slots, player });
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which renders maps held by players
+/// and sends them to their holders.
+pub struct MapUpdateSystem;
+
+impl<'a> System<'a> for MapUpdateSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, InventoryComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Read<'a, ChunkMap>,
+        Write<'a, MapRegistry>,
+        Read<'a, TickCount>,
+        Read<'a, Arc<Config>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            inventories,
+            positions,
+            players,
+            networks,
+            dimensions,
+            chunk_map,
+            mut maps,
+            tick,
+            config,
+        ) = data;
+
+        let tick = tick.0;
+        let world_dir = Path::new(&config.world.name);
+
+        // Players in the primary dimension, who are
+        // the only ones shown on maps.
+        let mut shown = vec![];
+        let mut holders: HashMap<i32, Vec<(Entity, Position)>> = HashMap::new();
+        for (entity, inventory, position, _, dimension) in (
+            &entities,
+            &inventories,
+            &positions,
+            &players,
+            dimensions.maybe(),
+        )
+            .join()
+        {
+            if dimension.is_some() {
+                continue;
+            }
+            shown.push(position.current);
+
+            let main_hand = inventory.hand_slot(0);
+            let mut held: Vec<i32> = [main_hand, SLOT_OFFHAND]
+                .iter()
+                .filter_map(|slot| inventory.item_at(*slot))
+                .filter(|stack| stack.ty == Item::FilledMap)
+                .filter_map(ItemStack::map_id)
+                .collect();
+            held.dedup();
+
+            for id in held {
+                holders
+                    .entry(id)
+                    .or_default()
+                    .push((entity, position.current));
+            }
+        }
+
+        // Players who stopped holding a map must
+        // be sent all of it when they hold it again.
+        for (id, map) in &mut maps.maps {
+            let map_holders = holders.get(id);
+            map.viewers.retain(|viewer| {
+                map_holders.map_or(false, |holders| holders.iter().any(|(h, _)| h == viewer))
+            });
+        }
+
+        for (id, holders) in holders {
+            let map = continue_if_none!(maps.get_mut(world_dir, id));
+
+            for (_, pos) in &holders {
+                render_around(map, &chunk_map, *pos, tick);
+            }
+
+            let changed = map.take_changed_pixels();
+            for (player, pos) in &holders {
+                let network = continue_if_none!(networks.get(*player));
+
+                let pixels = if map.viewers.insert(*player) {
+                    Some(map.all_pixels())
+                } else if changed.is_some() {
+                    changed.clone()
+                } else if tick % ICON_INTERVAL == 0 {
+                    None
+                } else {
+                    continue;
+                };
+
+                send_packet_to_player(
+                    network,
+                    MapData::new(
+                        id,
+                        map.scale as i8,
+                        map.tracking_position,
+                        map.icons(&shown, *pos),
+                        pixels,
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// System which periodically saves modified maps.
+pub struct MapSaveSystem;
+
+impl<'a> System<'a> for MapSaveSystem {
+    type SystemData = (
+        Write<'a, MapRegistry>,
+        Read<'a, TickCount>,
+        Read<'a, Arc<Config>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut maps, tick, config) = data;
+
+        if tick.0 % SAVE_INTERVAL == 0 {
+            maps.save(Path::new(&config.world.name));
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(MapCreateSystem::default(), MAP_CREATE, &[PLAYER_USE_ITEM]);
+    dispatcher.add_timed(MapUpdateSystem, MAP_UPDATE, &[MAP_CREATE]);
+    dispatcher.add_timed(MapSaveSystem, MAP_SAVE, &[MAP_UPDATE]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::{Block, BlockPosition, PacketType};
+    use specs::WorldExt;
+
+    #[test]
+    fn test_map_center() {
+        let map = MapState::new(0.0, 0.0, 0);
+        assert_eq!((map.x_center, map.z_center), (0, 0));
+
+        let map = MapState::new(70.0, -70.0, 0);
+        assert_eq!((map.x_center, map.z_center), (128, -128));
+
+        let map = MapState::new(100.0, 100.0, 1);
+        assert_eq!((map.x_center, map.z_center), (64, 64));
+        assert_eq!(map.pixel_origin(0, 0), (-64, -64));
+    }
+
+    #[test]
+    fn test_icons() {
+        let map = MapState::new(0.0, 0.0, 0);
+
+        let mut pos = position!(10.0, 64.0, -20.0);
+        pos.yaw = 90.0;
+        let icons = map.icons(&[pos], pos);
+        assert_eq!(icons.len(), 1);
+        assert_eq!(icons[0].ty, ICON_PLAYER);
+        assert_eq!((icons[0].x, icons[0].z), (20, -39));
+        assert_eq!(icons[0].direction, 4);
+
+        // Holder off the map
+        let far = position!(200.0, 64.0, 0.0);
+        let icons = map.icons(&[pos, far], far);
+        assert_eq!(icons.len(), 2);
+        assert_eq!(icons[1].ty, ICON_PLAYER_OFF_MAP);
+        assert_eq!(icons[1].x, 127);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut chunk_map = ChunkMap::new();
+        for x in -4..4 {
+            for z in -4..4 {
+                chunk_map.set_chunk_at(
+                    ChunkPosition::new(x, z),
+                    Chunk::new(ChunkPosition::new(x, z)),
+                );
+            }
+        }
+        for x in -64..64 {
+            for z in -64..64 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(x, 60, z), Block::Stone)
+                    .unwrap();
+            }
+        }
+        for y in 61..=62 {
+            chunk_map
+                .set_block_at(BlockPosition::new(1, y, 1), Block::Stone)
+                .unwrap();
+        }
+
+        let mut map = MapState::new(0.0, 0.0, 0);
+        for tick in 0..RENDER_STEPS {
+            render_around(&mut map, &chunk_map, position!(0.0, 61.0, 0.0), tick);
+        }
+
+        let stone = 11 * 4;
+        // Flat stone is shaded normally, up to dithering.
+        assert_eq!(map.color_at(64, 64) / 4 * 4, stone);
+        // The raised block is brighter; the block to its
+        // south, which is lower, is darker.
+        assert_eq!(map.color_at(65, 65), stone + 2);
+        assert_eq!(map.color_at(65, 66), stone);
+
+        let pixels = map.take_changed_pixels().unwrap();
+        assert_eq!((pixels.columns, pixels.rows), (128, 128));
+        assert!(map.take_changed_pixels().is_none());
+    }
+
+    #[test]
+    fn test_create_and_send() {
+        let (mut w, mut d) = t::builder()
+            .with(MapCreateSystem::default(), MAP_CREATE)
+            .with_dep(MapUpdateSystem, MAP_UPDATE, &[MAP_CREATE])
+            .build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_in_main_hand(ItemStack::new(Item::Map, 1));
+
+        t::trigger_event(
+            &w,
+            PlayerUseItemEvent {
+                player: player.entity,
+                hand: 0,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let inventories = w.read_component::<InventoryComponent>();
+        let held = inventories
+            .get(player.entity)
+            .unwrap()
+            .item_in_main_hand()
+            .unwrap();
+        assert_eq!(held.ty, Item::FilledMap);
+        assert_eq!(held.map_id(), Some(0));
+
+        let packet = t::assert_packet_received(&player, PacketType::MapData);
+        let packet = cast_packet::<MapData>(&*packet);
+        assert_eq!(packet.map_id, 0);
+        assert_eq!(packet.icons.len(), 1);
+        assert_eq!(
+            packet.pixels.as_ref().unwrap().data.len(),
+            MAP_SIZE * MAP_SIZE
+        );
+    }
+
+    #[test]
+    fn test_extend_map() {
+        let mut maps = MapRegistry::default();
+        let world_dir = Path::new("nonexistent");
+        let id = maps.create(MapState::new(0.0, 0.0, 0));
+
+        let mut filled = ItemStack::new(Item::FilledMap, 1);
+        filled.set_map_id(id);
+        let paper = Some(ItemStack::new(Item::Paper, 1));
+        let mut grid = vec![paper; 9];
+        grid[4] = Some(filled);
+
+        let result = extend_map(&grid, &mut maps, world_dir).unwrap();
+        let zoomed = result.map_id().unwrap();
+        assert_ne!(zoomed, id);
+        let map = maps.get_mut(world_dir, zoomed).unwrap();
+        assert_eq!(map.scale, 1);
+        assert_eq!((map.x_center, map.z_center), (64, 64));
+
+        grid[0] = None;
+        assert!(extend_map(&grid, &mut maps, world_dir).is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let world_dir = std::env::temp_dir().join(format!("feather-maps-{}", uuid::Uuid::new_v4()));
+
+        let mut maps = MapRegistry::default();
+        let mut map = MapState::new(0.0, 0.0, 2);
+        map.set_color(3, 4, 45);
+        let id = maps.create(map);
+        maps.save(&world_dir);
+
+        let mut maps = MapRegistry::load(&world_dir);
+        let map = maps.get_mut(&world_dir, id).unwrap();
+        assert_eq!(map.scale, 2);
+        assert_eq!(map.color_at(3, 4), 45);
+        assert_eq!(maps.create(MapState::new(0.0, 0.0, 0)), id + 1);
+
+        std::fs::remove_dir_all(&world_dir).unwrap();
+    }
+}
@@ -0,0 +1,419 @@
+//! Server metrics, exposed over HTTP in the
+//! Prometheus text format.
+//!
+//! Metrics are collected into the global `METRICS`
+//! regardless of whether the endpoint is enabled,
+//! since updating them is cheap. The endpoint itself
+//! is enabled through the `[metrics]` section
+//! of the configuration.
+
+use crate::chunk_logic::{ChunkHolders, ChunkUnloadQueue};
+use crate::systems::METRICS_UPDATE;
+use crate::timings::DispatcherBuilderExt;
+use crate::timings::TIMINGS;
+use crate::{PlayerCount, TPS};
+use feather_core::network::pool::BUFFER_POOL;
+use feather_core::world::ChunkMap;
+use specs::{DispatcherBuilder, Entities, Join, Read, System};
+use std::fmt::Write as _;
+use std::io::{Read as _, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// The global metrics.
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// The maximum time spent serving a single connection.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+/// The maximum number of connections served at once.
+/// Further connections are closed immediately.
+const MAX_CONNECTIONS: usize = 8;
+
+/// Upper bounds of the tick duration histogram buckets, in milliseconds.
+const TICK_DURATION_BUCKETS: [u64; BUCKET_COUNT] = [5, 10, 25, 50, 100, 250, 500, 1000];
+const BUCKET_COUNT: usize = 8;
+
+/// A histogram with fixed buckets.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Number of observations in each bucket, not cumulative.
+    /// The last entry is the `+Inf` bucket.
+    buckets: [AtomicU64; BUCKET_COUNT + 1],
+    /// Sum of all observations, in milliseconds.
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    /// Records an observation, in milliseconds.
+    pub fn observe(&self, value: u64) {
+        let index = TICK_DURATION_BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(BUCKET_COUNT);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let bound = match TICK_DURATION_BUCKETS.get(i) {
+                Some(bound) => format!("{}", *bound as f64 / 1000.0),
+                None => String::from("+Inf"),
+            };
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+
+        let sum = self.sum.load(Ordering::Relaxed) as f64 / 1000.0;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count.load(Ordering::Relaxed));
+    }
+}
+
+/// Collection of all server metrics.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Ticks per second over the last second, stored as `f64` bits.
+    pub tps: AtomicU64,
+    pub tick_duration: Histogram,
+    pub loaded_chunks: AtomicU64,
+    /// Number of chunks held by players or forced to stay loaded.
+    pub held_chunks: AtomicU64,
+    /// Number of chunks waiting to be unloaded.
+    pub queued_chunk_unloads: AtomicU64,
+    pub entities: AtomicU64,
+    pub players: AtomicU64,
+    pub packets_received: AtomicU64,
+    pub packets_sent: AtomicU64,
+    /// Number of lighting updates performed during the last tick.
+    pub lighting_updates: AtomicU64,
+    /// Number of lighting updates deferred to later ticks.
+    pub lighting_queue_depth: AtomicU64,
+    /// Number of chunks requested from the chunk worker
+    /// which have not yet been loaded or generated.
+    pub pending_chunk_loads: AtomicU64,
+}
+
+impl Metrics {
+    pub fn set_tps(&self, tps: f64) {
+        self.tps.store(tps.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn tps(&self) -> f64 {
+        f64::from_bits(self.tps.load(Ordering::Relaxed))
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        gauge(&mut out, "feather_tps", "Ticks per second", self.tps());
+        self.tick_duration.render(
+            &mut out,
+            "feather_tick_duration_seconds",
+            "Duration of server ticks",
+        );
+        gauge(
+            &mut out,
+            "feather_loaded_chunks",
+            "Number of loaded chunks",
+            load(&self.loaded_chunks),
+        );
+        gauge(
+            &mut out,
+            "feather_held_chunks",
+            "Number of chunks held by players or forced to stay loaded",
+            load(&self.held_chunks),
+        );
+        gauge(
+            &mut out,
+            "feather_chunk_unload_queue_depth",
+            "Chunks waiting to be unloaded",
+            load(&self.queued_chunk_unloads),
+        );
+        gauge(
+            &mut out,
+            "feather_entities",
+            "Number of entities",
+            load(&self.entities),
+        );
+        gauge(
+            &mut out,
+            "feather_players",
+            "Number of connected players",
+            load(&self.players),
+        );
+        counter(
+            &mut out,
+            "feather_packets_received_total",
+            "Packets received from clients",
+            load(&self.packets_received),
+        );
+        counter(
+            &mut out,
+            "feather_packets_sent_total",
+            "Packets sent to clients",
+            load(&self.packets_sent),
+        );
+        counter(
+            &mut out,
+            "feather_buffer_pool_hits_total",
+            "Packet buffer requests served from the buffer pool",
+            BUFFER_POOL.hits() as f64,
+        );
+        counter(
+            &mut out,
+            "feather_buffer_pool_misses_total",
+            "Packet buffer requests which required an allocation",
+            BUFFER_POOL.misses() as f64,
+        );
+        gauge(
+            &mut out,
+            "feather_lighting_updates",
+            "Lighting updates performed during the last tick",
+            load(&self.lighting_updates),
+        );
+        gauge(
+            &mut out,
+            "feather_lighting_queue_depth",
+            "Lighting updates deferred to later ticks",
+            load(&self.lighting_queue_depth),
+        );
+        gauge(
+            &mut out,
+            "feather_chunk_load_queue_depth",
+            "Chunks waiting to be loaded or generated",
+            load(&self.pending_chunk_loads),
+        );
+
+        out
+    }
+}
+
+fn load(value: &AtomicU64) -> f64 {
+    value.load(Ordering::Relaxed) as f64
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    metric(out, name, help, "gauge", value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: f64) {
+    metric(out, name, help, "counter", value);
+}
+
+fn metric(out: &mut String, name: &str, help: &str, ty: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, ty);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Starts the metrics HTTP server on a new thread.
+pub fn start_server(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving metrics on http://{}/metrics", addr);
+
+    std::thread::Builder::new()
+        .name(String::from("Metrics Server"))
+        .spawn(move || {
+            let active = Arc::new(AtomicUsize::new(0));
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => spawn_connection(stream, &active),
+                    Err(e) => debug!("Failed to accept metrics connection: {}", e),
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Serves a connection on a new thread, so that slow
+/// clients don't delay other scrapes. Connections beyond
+/// `MAX_CONNECTIONS` are closed without a response.
+fn spawn_connection(stream: TcpStream, active: &Arc<AtomicUsize>) {
+    if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+        active.fetch_sub(1, Ordering::SeqCst);
+        debug!("Too many metrics connections; closing connection");
+        return;
+    }
+
+    let active = Arc::clone(active);
+    let result = std::thread::Builder::new()
+        .name(String::from("Metrics Connection"))
+        .spawn(move || {
+            if let Err(e) = handle_connection(stream) {
+                debug!("Failed to serve metrics: {}", e);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    if let Err(e) = result {
+        debug!("Failed to start metrics connection thread: {}", e);
+    }
+}
+
+fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
+    // Bound the total time spent on the connection, not just
+    // each read, so that clients sending the request slowly
+    // are disconnected too.
+    let deadline = Instant::now() + CONNECTION_TIMEOUT;
+    let remaining = || {
+        let now = Instant::now();
+        if now < deadline {
+            Ok(deadline - now)
+        } else {
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut))
+        }
+    };
+
+    // Read until the end of the request headers.
+    // The request itself is ignored: all paths
+    // return the metrics.
+    let mut request = Vec::new();
+    let mut buf = [0u8; 512];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < 8192 {
+        stream.set_read_timeout(Some(remaining()?))?;
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let body = METRICS.render();
+    stream.set_write_timeout(Some(remaining()?))?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// System which updates metrics gauges each tick.
+pub struct MetricsSystem;
+
+impl<'a> System<'a> for MetricsSystem {
+    type SystemData = (
+        Read<'a, ChunkMap>,
+        Read<'a, ChunkHolders>,
+        Read<'a, ChunkUnloadQueue>,
+        Read<'a, Arc<PlayerCount>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (chunk_map, holders, unload_queue, player_count, entities) = data;
+
+        METRICS.set_tps(TIMINGS.lock().tps(TPS as usize));
+        METRICS
+            .loaded_chunks
+            .store(chunk_map.inner().len() as u64, Ordering::Relaxed);
+        METRICS
+            .held_chunks
+            .store(holders.held_chunk_count() as u64, Ordering::Relaxed);
+        METRICS
+            .queued_chunk_unloads
+            .store(unload_queue.len() as u64, Ordering::Relaxed);
+        METRICS
+            .entities
+            .store((&entities).join().count() as u64, Ordering::Relaxed);
+        METRICS.players.store(
+            player_count.0.load(Ordering::SeqCst) as u64,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(MetricsSystem, METRICS_UPDATE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::default();
+        histogram.observe(3);
+        histogram.observe(40);
+        histogram.observe(5000);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test", "Test");
+
+        assert!(out.contains("test_bucket{le=\"0.005\"} 1"));
+        assert!(out.contains("test_bucket{le=\"0.05\"} 2"));
+        assert!(out.contains("test_bucket{le=\"1\"} 2"));
+        assert!(out.contains("test_bucket{le=\"+Inf\"} 3"));
+        assert!(out.contains("test_sum 5.043"));
+        assert!(out.contains("test_count 3"));
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.set_tps(19.5);
+        metrics.packets_sent.store(42, Ordering::Relaxed);
+
+        let out = metrics.render();
+        assert!(out.contains("# TYPE feather_tps gauge\nfeather_tps 19.5\n"));
+        assert!(out.contains("feather_packets_sent_total 42\n"));
+        assert!(out.contains("feather_chunk_load_queue_depth 0\n"));
+    }
+
+    #[test]
+    fn test_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        start_server(addr).unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("feather_tps"));
+    }
+
+    #[test]
+    fn test_server_stalled_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        start_server(addr).unwrap();
+
+        // A client which never sends its request
+        // doesn't delay other scrapes.
+        let _stalled = TcpStream::connect(addr).unwrap();
+
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(start.elapsed() < CONNECTION_TIMEOUT);
+    }
+}
@@ -0,0 +1,1034 @@
+//! The AI of mobs.
+//!
+//! The `GoalSelector` of a mob holds its goals in order of priority.
+//! Every tick, running goals which are done are stopped, and the
+//! goals which aren't running are asked whether they want to start.
+//! A goal only starts if no running goal of a higher priority uses
+//! the same controls, and starting it stops running goals of a lower
+//! priority which do. As in vanilla, this lets mobs look at players
+//! while they wander around, and makes them stop wandering when they
+//! panic.
+//!
+//! Hostile mobs also have a goal which targets the nearest player
+//! they can see, or the player who last hurt them, and goals which
+//! chase their target and attack it: zombies hit it once they reach
+//! it, while skeletons shoot arrows at it from a distance. Players in
+//! creative or spectator mode are never targeted.
+//!
+//! Mobs walk toward the targets of their goals along paths found by
+//! their `Navigator` (see the `pathfinding` module), jumping up blocks
+//! in their way. Mobs without a navigator walk in a straight line.
+
+use crate::combat::{DamageEvent, HealthComponent};
+use crate::entity::{PlayerComponent, PositionComponent, ShootArrowEvent, VelocityComponent};
+use crate::mob::pathfinding::Navigator;
+use crate::mob::MobComponent;
+use crate::physics::{block_impacted_by_ray, AABBExt, PhysicsComponent};
+use crate::player::PLAYER_EYE_HEIGHT;
+use crate::TickCount;
+use feather_core::world::ChunkMap;
+use feather_core::{BlockExt, BlockPosition, Gamemode, Item, Position};
+use glm::DVec3;
+use rand::Rng;
+use shrev::EventChannel;
+use specs::{
+    Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, Write,
+    WriteStorage,
+};
+
+/// Converts the movement speed of mobs to blocks per tick.
+const SPEED_SCALE: f64 = 0.45;
+/// The vertical velocity of mobs when they jump.
+const JUMP_VELOCITY: f64 = 0.42;
+/// The horizontal distance within which a target counts as reached.
+const REACHED_DISTANCE: f64 = 0.5;
+/// The number of ticks after which mobs give
+/// up on reaching the target of a goal.
+const MAX_MOVE_TICKS: u64 = 100;
+/// One in this many ticks, a mob starts wandering.
+const WANDER_CHANCE: u32 = 120;
+/// The chance per tick that a mob starts looking
+/// at a player or in a random direction.
+const LOOK_CHANCE: f32 = 0.02;
+/// The number of columns tried when looking
+/// for a random position to move to.
+const RANDOM_POSITION_ATTEMPTS: usize = 10;
+/// The eyes of mobs are at this fraction of their height.
+pub const EYE_HEIGHT: f64 = 0.85;
+/// The squared distance within which mobs hit their target,
+/// as calculated by vanilla for mobs as wide as zombies.
+const MELEE_REACH_SQUARED: f64 = 2.04;
+/// The number of ticks between two melee attacks.
+const MELEE_COOLDOWN: u64 = 20;
+
+bitflags! {
+    /// The controls of a mob used by a goal.
+    pub struct Controls: u8 {
+        const MOVE = 0x01;
+        const LOOK = 0x02;
+        const TARGET = 0x04;
+    }
+}
+
+/// A goal of a mob.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// Runs to a random position nearby after being hurt.
+    Panic { speed: f64 },
+    /// Occasionally walks to a random position nearby.
+    Wander { speed: f64 },
+    /// Occasionally looks at the nearest player within `range` blocks.
+    LookAtPlayer { range: f64 },
+    /// Occasionally looks in a random direction.
+    LookAround,
+    /// Targets the player who last hurt the mob or, failing that,
+    /// the nearest player it can see, as long as the player stays
+    /// within `range` blocks.
+    TargetPlayer { range: f64 },
+    /// Chases the target and hits it once it is in reach.
+    MeleeAttack { speed: f64 },
+    /// Shoots an arrow at the target every `interval` ticks while
+    /// it is visible within `range` blocks, and chases it otherwise.
+    RangedAttack {
+        speed: f64,
+        interval: u64,
+        range: f64,
+    },
+}
+
+/// What a running goal does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    /// Moves toward a position, with the speed
+    /// multiplied by the given modifier.
+    MoveTo { target: DVec3, speed: f64 },
+    /// Looks at the eyes of a player.
+    LookAt(Entity),
+    /// Looks at a position.
+    LookToward(DVec3),
+    /// Makes a player the target of the mob.
+    Target(Entity),
+    /// Attacks the target, moving with the
+    /// speed multiplied by the given modifier.
+    Attack { target: Entity, speed: f64 },
+}
+
+/// The state of a mob and its surroundings
+/// on which goals base their decisions.
+pub struct Context<'a, R: Rng> {
+    pub position: Position,
+    /// The height of the mob's eyes above its position.
+    pub eye_height: f64,
+    pub health: &'a HealthComponent,
+    /// The players which mobs can notice,
+    /// with the positions of their eyes.
+    pub players: &'a [(Entity, DVec3)],
+    /// The players among `players` which hostile mobs may attack.
+    pub targets: &'a [(Entity, DVec3)],
+    /// The target of the mob, which is set
+    /// by the running `TargetPlayer` goal.
+    pub target: Option<Entity>,
+    pub chunk_map: &'a ChunkMap,
+    pub tick: u64,
+    pub rng: &'a mut R,
+}
+
+impl<'a, R: Rng> Context<'a, R> {
+    /// Returns the position of the mob's eyes.
+    pub fn eyes(&self) -> DVec3 {
+        self.position.as_vec() + glm::vec3(0.0, self.eye_height, 0.0)
+    }
+
+    /// Returns the nearest player within the given range.
+    pub fn nearest_player(&self, range: f64) -> Option<(Entity, DVec3)> {
+        let eyes = self.eyes();
+        self.players
+            .iter()
+            .map(|(player, pos)| (*player, *pos, glm::distance2(&eyes, pos)))
+            .filter(|(_, _, distance)| *distance <= range * range)
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+            .map(|(player, pos, _)| (player, pos))
+    }
+
+    /// Returns the eyes of a player, if the
+    /// player is within the given range.
+    pub fn player_in_range(&self, player: Entity, range: f64) -> Option<DVec3> {
+        let eyes = self.eyes();
+        self.players
+            .iter()
+            .find(|(entity, _)| *entity == player)
+            .map(|(_, pos)| *pos)
+            .filter(|pos| glm::distance2(&eyes, pos) <= range * range)
+    }
+
+    /// Returns the eyes of a player which may be targeted,
+    /// if the player is within the given range.
+    pub fn target_in_range(&self, player: Entity, range: f64) -> Option<DVec3> {
+        let eyes = self.eyes();
+        self.targets
+            .iter()
+            .find(|(entity, _)| *entity == player)
+            .map(|(_, pos)| *pos)
+            .filter(|pos| glm::distance2(&eyes, pos) <= range * range)
+    }
+
+    /// Returns the nearest player within the given
+    /// range which may be targeted and can be seen.
+    pub fn nearest_visible_target(&self, range: f64) -> Option<Entity> {
+        let eyes = self.eyes();
+        self.targets
+            .iter()
+            .map(|(player, pos)| (*player, *pos, glm::distance2(&eyes, pos)))
+            .filter(|(_, pos, distance)| {
+                *distance <= range * range && can_see(self.chunk_map, eyes, *pos)
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+            .map(|(player, _, _)| player)
+    }
+
+    /// Returns a random position within the given horizontal and
+    /// vertical distance which the mob can stand at, if one is found.
+    /// The highest such position in a random column is chosen.
+    pub fn random_position(&mut self, horizontal: i32, vertical: i32) -> Option<DVec3> {
+        let origin = self.position.block_pos();
+        for _ in 0..RANDOM_POSITION_ATTEMPTS {
+            let x = origin.x + self.rng.gen_range(-horizontal, horizontal + 1);
+            let z = origin.z + self.rng.gen_range(-horizontal, horizontal + 1);
+            let found = (origin.y - vertical..=origin.y + vertical)
+                .rev()
+                .map(|y| BlockPosition::new(x, y, z))
+                .find(|pos| can_stand_at(self.chunk_map, *pos));
+            if let Some(pos) = found {
+                return Some(glm::vec3(
+                    f64::from(pos.x) + 0.5,
+                    f64::from(pos.y),
+                    f64::from(pos.z) + 0.5,
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// Returns whether a mob can stand in the given
+/// block, which must be free of solid blocks and
+/// fluids, and on top of a solid block.
+pub fn can_stand_at(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    let is_free = |pos| {
+        chunk_map
+            .block_at(pos)
+            .map_or(false, |block| !block.is_solid() && !block.is_fluid())
+    };
+    let below = BlockPosition::new(pos.x, pos.y - 1, pos.z);
+    let above = BlockPosition::new(pos.x, pos.y + 1, pos.z);
+    chunk_map
+        .block_at(below)
+        .map_or(false, |block| block.is_solid())
+        && is_free(pos)
+        && is_free(above)
+}
+
+/// Returns whether no solid block is in the
+/// way of a line between the given positions.
+pub fn can_see(chunk_map: &ChunkMap, from: DVec3, to: DVec3) -> bool {
+    let distance_squared = glm::distance2(&from, &to);
+    match block_impacted_by_ray(chunk_map, from, to - from, distance_squared) {
+        Some(impact) => glm::distance2(&from, &impact.pos.as_vec()) >= distance_squared,
+        None => true,
+    }
+}
+
+/// Returns the distance between two positions, ignoring their heights.
+pub fn horizontal_distance(a: DVec3, b: DVec3) -> f64 {
+    ((a.x - b.x).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+impl Goal {
+    /// Returns the controls used by this goal.
+    pub fn controls(self) -> Controls {
+        match self {
+            Goal::Panic { .. } | Goal::Wander { .. } => Controls::MOVE,
+            Goal::LookAtPlayer { .. } | Goal::LookAround => Controls::LOOK,
+            Goal::TargetPlayer { .. } => Controls::TARGET,
+            Goal::MeleeAttack { .. } | Goal::RangedAttack { .. } => Controls::MOVE | Controls::LOOK,
+        }
+    }
+
+    /// Returns the task to start and the maximum number
+    /// of ticks to run it for, if this goal wants to start.
+    pub fn start<R: Rng>(self, ctx: &mut Context<R>) -> Option<(Task, u64)> {
+        match self {
+            Goal::Panic { speed } => {
+                let hurt = ctx.health.hurt_tick?;
+                if ctx.tick > hurt + 1 {
+                    return None;
+                }
+                let target = ctx.random_position(5, 4)?;
+                Some((Task::MoveTo { target, speed }, MAX_MOVE_TICKS))
+            }
+            Goal::Wander { speed } => {
+                if ctx.rng.gen_range(0, WANDER_CHANCE) != 0 {
+                    return None;
+                }
+                let target = ctx.random_position(10, 7)?;
+                Some((Task::MoveTo { target, speed }, MAX_MOVE_TICKS))
+            }
+            Goal::LookAtPlayer { range } => {
+                if ctx.rng.gen::<f32>() >= LOOK_CHANCE {
+                    return None;
+                }
+                let (player, _) = ctx.nearest_player(range)?;
+                let duration = 40 + ctx.rng.gen_range(0, 40);
+                Some((Task::LookAt(player), duration))
+            }
+            Goal::LookAround => {
+                if ctx.rng.gen::<f32>() >= LOOK_CHANCE {
+                    return None;
+                }
+                let angle = ctx.rng.gen_range(0.0, 2.0 * std::f64::consts::PI);
+                let target = ctx.eyes() + glm::vec3(angle.cos(), 0.0, angle.sin());
+                let duration = 20 + ctx.rng.gen_range(0, 20);
+                Some((Task::LookToward(target), duration))
+            }
+            Goal::TargetPlayer { range } => {
+                let attacker = ctx
+                    .health
+                    .last_damager
+                    .filter(|attacker| ctx.target_in_range(*attacker, range).is_some());
+                let target = attacker.or_else(|| ctx.nearest_visible_target(range))?;
+                Some((Task::Target(target), std::u64::MAX))
+            }
+            Goal::MeleeAttack { speed } | Goal::RangedAttack { speed, .. } => {
+                let target = ctx.target?;
+                Some((Task::Attack { target, speed }, std::u64::MAX))
+            }
+        }
+    }
+
+    /// Returns whether a running task of this goal should continue.
+    pub fn should_continue<R: Rng>(self, task: Task, ctx: &Context<R>) -> bool {
+        match (self, task) {
+            (_, Task::MoveTo { target, .. }) => {
+                horizontal_distance(ctx.position.as_vec(), target) > REACHED_DISTANCE
+            }
+            (Goal::LookAtPlayer { range }, Task::LookAt(player)) => {
+                ctx.player_in_range(player, range).is_some()
+            }
+            (Goal::TargetPlayer { range }, Task::Target(player)) => {
+                ctx.target_in_range(player, range).is_some()
+            }
+            (_, Task::Attack { target, .. }) => ctx.target == Some(target),
+            _ => true,
+        }
+    }
+}
+
+/// A goal which is running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Running {
+    /// The index of the goal.
+    goal: usize,
+    task: Task,
+    /// The tick at which the task is given up.
+    end_tick: u64,
+}
+
+/// What a mob should do in a tick, as decided by its goals.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Steering {
+    /// The position to move toward, with
+    /// the modifier of the movement speed.
+    pub move_to: Option<(DVec3, f64)>,
+    /// The position to look at.
+    pub look_at: Option<DVec3>,
+    /// The attack to perform, if any.
+    pub attack: Option<Attack>,
+}
+
+/// An attack of a mob on its target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attack {
+    /// Hits the target.
+    Melee(Entity),
+    /// Shoots an arrow at the eyes of the target.
+    Ranged(Entity, DVec3),
+}
+
+/// Chooses the goals of a mob which are running.
+#[derive(Debug, Clone)]
+pub struct GoalSelector {
+    goals: Vec<Goal>,
+    running: Vec<Running>,
+    /// The tick at which the mob last attacked, if ever.
+    attack_tick: Option<u64>,
+}
+
+impl GoalSelector {
+    /// Creates a selector for the given goals,
+    /// in order of priority.
+    pub fn new(goals: Vec<Goal>) -> Self {
+        Self {
+            goals,
+            running: vec![],
+            attack_tick: None,
+        }
+    }
+
+    pub fn goals(&self) -> &[Goal] {
+        &self.goals
+    }
+
+    /// Returns the tasks of the running goals.
+    pub fn tasks(&self) -> impl Iterator<Item = Task> + '_ {
+        self.running.iter().map(|running| running.task)
+    }
+
+    /// Returns the target of the mob, if it has one.
+    pub fn target(&self) -> Option<Entity> {
+        target_of(&self.running)
+    }
+
+    /// Stops and starts goals, returning
+    /// what the mob should do this tick.
+    pub fn tick<R: Rng>(&mut self, ctx: &mut Context<R>) -> Steering {
+        let GoalSelector {
+            goals,
+            running,
+            attack_tick,
+        } = self;
+
+        // Targets are checked first, so that attacks
+        // stop in the same tick as their target is lost.
+        running.retain(|r| match r.task {
+            Task::Target(_) => ctx.tick < r.end_tick && goals[r.goal].should_continue(r.task, ctx),
+            _ => true,
+        });
+        ctx.target = target_of(running);
+        running.retain(|r| ctx.tick < r.end_tick && goals[r.goal].should_continue(r.task, ctx));
+
+        for (index, goal) in goals.iter().enumerate() {
+            let controls = goal.controls();
+            let blocked = running
+                .iter()
+                .any(|r| r.goal <= index && goals[r.goal].controls().intersects(controls));
+            if blocked {
+                continue;
+            }
+
+            if let Some((task, duration)) = goal.start(ctx) {
+                running.retain(|r| !goals[r.goal].controls().intersects(controls));
+                running.push(Running {
+                    goal: index,
+                    task,
+                    end_tick: ctx.tick.saturating_add(duration),
+                });
+                ctx.target = target_of(running);
+            }
+        }
+
+        let mut steering = Steering::default();
+        for r in running.iter() {
+            match r.task {
+                Task::MoveTo { target, speed } => steering.move_to = Some((target, speed)),
+                Task::LookAt(player) => {
+                    steering.look_at = ctx
+                        .players
+                        .iter()
+                        .find(|(entity, _)| *entity == player)
+                        .map(|(_, eyes)| *eyes);
+                }
+                Task::LookToward(target) => steering.look_at = Some(target),
+                Task::Target(_) => (),
+                Task::Attack { target, speed } => {
+                    let eyes = continue_if_none!(ctx
+                        .targets
+                        .iter()
+                        .find(|(entity, _)| *entity == target)
+                        .map(|(_, eyes)| *eyes));
+                    let feet = eyes - glm::vec3(0.0, PLAYER_EYE_HEIGHT, 0.0);
+                    steering.look_at = Some(eyes);
+
+                    let ready =
+                        |cooldown| attack_tick.map_or(true, |last| ctx.tick >= last + cooldown);
+                    match goals[r.goal] {
+                        Goal::RangedAttack {
+                            interval, range, ..
+                        } => {
+                            let mob_eyes = ctx.eyes();
+                            let visible = glm::distance2(&mob_eyes, &eyes) <= range * range
+                                && can_see(ctx.chunk_map, mob_eyes, eyes);
+                            if !visible {
+                                steering.move_to = Some((feet, speed));
+                            } else if ready(interval) {
+                                steering.attack = Some(Attack::Ranged(target, eyes));
+                            }
+                        }
+                        _ => {
+                            steering.move_to = Some((feet, speed));
+                            let distance = glm::distance2(&ctx.position.as_vec(), &feet);
+                            if distance <= MELEE_REACH_SQUARED && ready(MELEE_COOLDOWN) {
+                                steering.attack = Some(Attack::Melee(target));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if steering.attack.is_some() {
+            *attack_tick = Some(ctx.tick);
+        }
+        steering
+    }
+}
+
+/// Returns the target of the running `TargetPlayer` goal.
+fn target_of(running: &[Running]) -> Option<Entity> {
+    running.iter().find_map(|r| match r.task {
+        Task::Target(target) => Some(target),
+        _ => None,
+    })
+}
+
+impl Component for GoalSelector {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Returns the yaw and pitch, in degrees, of
+/// a direction in Minecraft's coordinate system.
+pub fn rotation_toward(direction: DVec3) -> (f32, f32) {
+    let horizontal = (direction.x * direction.x + direction.z * direction.z).sqrt();
+    let yaw = (-direction.x).atan2(direction.z).to_degrees();
+    let pitch = (-direction.y).atan2(horizontal).to_degrees();
+    (yaw as f32, pitch as f32)
+}
+
+/// Applies the steering of a mob to its position and
+/// velocity. `speed` is the movement speed of the mob.
+pub fn steer(
+    steering: Steering,
+    speed: f64,
+    eye_height: f64,
+    position: &mut Position,
+    velocity: &mut DVec3,
+    chunk_map: &ChunkMap,
+) {
+    if let Some((target, modifier)) = steering.move_to {
+        let offset = target - position.as_vec();
+        let distance = horizontal_distance(target, position.as_vec());
+        if distance > std::f64::EPSILON {
+            let speed = (speed * modifier * SPEED_SCALE).min(distance);
+            velocity.x = offset.x / distance * speed;
+            velocity.z = offset.z / distance * speed;
+
+            let (yaw, _) = rotation_toward(offset);
+            position.yaw = yaw;
+            position.pitch = 0.0;
+
+            // Jump up blocks in the way.
+            let ahead = position.as_vec() + glm::vec3(offset.x, 0.0, offset.z) / distance * 0.6;
+            let ahead = position!(ahead.x, ahead.y, ahead.z).block_pos();
+            let above = BlockPosition::new(ahead.x, ahead.y + 1, ahead.z);
+            let is_solid = |pos| chunk_map.block_at(pos).map_or(false, |b| b.is_solid());
+            if position.on_ground && is_solid(ahead) && !is_solid(above) {
+                velocity.y = JUMP_VELOCITY;
+            }
+        }
+    }
+
+    if let Some(target) = steering.look_at {
+        let eyes = position.as_vec() + glm::vec3(0.0, eye_height, 0.0);
+        let (yaw, pitch) = rotation_toward(target - eyes);
+        position.yaw = yaw;
+        position.pitch = pitch;
+    }
+}
+
+/// Returns the direction in which a skeleton at `eyes` aims to hit
+/// a player with the given eyes, aiming higher the farther away the
+/// player is to make up for gravity, as in vanilla.
+pub fn aim_direction(eyes: DVec3, target_eyes: DVec3) -> DVec3 {
+    // Vanilla aims at a third of the height of the player.
+    let target = target_eyes - glm::vec3(0.0, PLAYER_EYE_HEIGHT - 0.6, 0.0);
+    let offset = target - eyes;
+    let horizontal = (offset.x * offset.x + offset.z * offset.z).sqrt();
+    glm::vec3(offset.x, offset.y + horizontal * 0.2, offset.z)
+}
+
+/// System which ticks the goals of mobs, steers
+/// them, and performs the attacks they decide on.
+pub struct AiSystem;
+
+impl<'a> System<'a> for AiSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, GoalSelector>,
+        ReadStorage<'a, MobComponent>,
+        ReadStorage<'a, HealthComponent>,
+        WriteStorage<'a, PositionComponent>,
+        WriteStorage<'a, VelocityComponent>,
+        ReadStorage<'a, PhysicsComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, Navigator>,
+        Read<'a, ChunkMap>,
+        Read<'a, TickCount>,
+        Write<'a, EventChannel<DamageEvent>>,
+        Write<'a, EventChannel<ShootArrowEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut selectors,
+            mobs,
+            healths,
+            mut positions,
+            mut velocities,
+            physics,
+            players,
+            mut navigators,
+            chunk_map,
+            tick,
+            mut damage_events,
+            mut shoot_arrow_events,
+        ) = data;
+
+        // Player bounding boxes aren't known here,
+        // so the vanilla eye height is used.
+        let mut eyes = vec![];
+        let mut targets = vec![];
+        for (entity, player, pos) in (&entities, &players, &positions).join() {
+            let player_eyes = pos.current.as_vec() + glm::vec3(0.0, PLAYER_EYE_HEIGHT, 0.0);
+            match player.gamemode {
+                Gamemode::Spectator => continue,
+                Gamemode::Creative => (),
+                Gamemode::Survival | Gamemode::Adventure => targets.push((entity, player_eyes)),
+            }
+            eyes.push((entity, player_eyes));
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut steerings = vec![];
+
+        for (entity, selector, mob, health, position, physics) in (
+            &entities,
+            &mut selectors,
+            &mobs,
+            &healths,
+            &positions,
+            &physics,
+        )
+            .join()
+        {
+            if health.is_dead() {
+                continue;
+            }
+
+            let eye_height = physics.bbox.size().y * EYE_HEIGHT;
+            let mut ctx = Context {
+                position: position.current,
+                eye_height,
+                health,
+                players: &eyes,
+                targets: &targets,
+                target: None,
+                chunk_map: &chunk_map,
+                tick: tick.0,
+                rng: &mut rng,
+            };
+            let mut steering = selector.tick(&mut ctx);
+            if let Some(navigator) = navigators.get_mut(entity) {
+                steering.move_to = match steering.move_to {
+                    Some((target, speed)) => navigator
+                        .follow(target, position.current.as_vec())
+                        .map(|waypoint| (waypoint, speed)),
+                    None => {
+                        navigator.stop();
+                        None
+                    }
+                };
+            }
+            if steering != Steering::default() {
+                steerings.push((entity, steering, mob.kind, eye_height));
+            }
+        }
+
+        for (entity, steering, kind, eye_height) in steerings {
+            let position = continue_if_none!(positions.get_mut(entity));
+            let velocity = continue_if_none!(velocities.get_mut(entity));
+            steer(
+                steering,
+                kind.movement_speed(),
+                eye_height,
+                &mut position.current,
+                &mut velocity.0,
+                &chunk_map,
+            );
+            let position = position.current;
+
+            match steering.attack {
+                Some(Attack::Melee(target)) => {
+                    let target_pos = continue_if_none!(positions.get(target)).current;
+                    damage_events.single_write(DamageEvent {
+                        entity: target,
+                        damager: Some(entity),
+                        damage: kind.attack_damage(),
+                        knockback: Some((target_pos.x - position.x, target_pos.z - position.z)),
+                    });
+                }
+                Some(Attack::Ranged(_, target_eyes)) => {
+                    // Arrows are shot from the eye height of players.
+                    let from = position.as_vec() + glm::vec3(0.0, PLAYER_EYE_HEIGHT, 0.0);
+                    let (yaw, pitch) = rotation_toward(aim_direction(from, target_eyes));
+                    shoot_arrow_events.single_write(ShootArrowEvent {
+                        arrow_type: Item::Arrow,
+                        shooter: Some(entity),
+                        position: Position {
+                            yaw,
+                            pitch,
+                            ..position
+                        },
+                        critical: false,
+                    });
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mob::{self, MobKind};
+    use crate::testframework as t;
+    use feather_core::world::chunk::Chunk;
+    use feather_core::{Block, ChunkPosition};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use specs::{Builder, LazyUpdate, World, WorldExt};
+
+    /// Returns a chunk map with a floor of stone at Y = 63.
+    fn floor() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new();
+        for x in -2..=2 {
+            for z in -2..=2 {
+                chunk_map.set_chunk_at(
+                    ChunkPosition::new(x, z),
+                    Chunk::new(ChunkPosition::new(x, z)),
+                );
+            }
+        }
+        for x in -32..32 {
+            for z in -32..32 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(x, 63, z), Block::Stone)
+                    .unwrap();
+            }
+        }
+        chunk_map
+    }
+
+    fn tick(
+        selector: &mut GoalSelector,
+        health: &HealthComponent,
+        players: &[(Entity, DVec3)],
+        chunk_map: &ChunkMap,
+        tick: u64,
+        rng: &mut XorShiftRng,
+    ) -> Steering {
+        let mut ctx = Context {
+            position: position!(0.5, 64.0, 0.5),
+            eye_height: 0.765,
+            health,
+            players,
+            targets: players,
+            target: None,
+            chunk_map,
+            tick,
+            rng,
+        };
+        selector.tick(&mut ctx)
+    }
+
+    fn move_speed(selector: &GoalSelector) -> Option<f64> {
+        selector.tasks().find_map(|task| match task {
+            Task::MoveTo { speed, .. } => Some(speed),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_rotation_toward() {
+        let close = |(yaw, pitch): (f32, f32), expected: (f32, f32)| {
+            (yaw - expected.0).abs() < 1e-4 && (pitch - expected.1).abs() < 1e-4
+        };
+        assert!(close(rotation_toward(glm::vec3(0.0, 0.0, 1.0)), (0.0, 0.0)));
+        assert!(close(
+            rotation_toward(glm::vec3(-1.0, 0.0, 0.0)),
+            (90.0, 0.0)
+        ));
+        assert!(close(
+            rotation_toward(glm::vec3(1.0, 0.0, 0.0)),
+            (-90.0, 0.0)
+        ));
+        assert!(close(
+            rotation_toward(glm::vec3(0.0, -1.0, 1.0)),
+            (0.0, 45.0)
+        ));
+    }
+
+    #[test]
+    fn test_can_stand_at() {
+        let mut chunk_map = floor();
+        assert!(can_stand_at(&chunk_map, BlockPosition::new(0, 64, 0)));
+        assert!(!can_stand_at(&chunk_map, BlockPosition::new(0, 65, 0)));
+        assert!(!can_stand_at(&chunk_map, BlockPosition::new(0, 63, 0)));
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 65, 0), Block::Stone)
+            .unwrap();
+        assert!(!can_stand_at(&chunk_map, BlockPosition::new(0, 64, 0)));
+    }
+
+    #[test]
+    fn test_panic_interrupts_wander() {
+        let chunk_map = floor();
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut selector = GoalSelector::new(MobKind::Pig.goals());
+        let mut health = HealthComponent::new(10.0);
+
+        let mut now = 0;
+        while move_speed(&selector).is_none() {
+            now += 1;
+            assert!(now < 10_000, "the pig never started wandering");
+            tick(&mut selector, &health, &[], &chunk_map, now, &mut rng);
+        }
+        assert_eq!(move_speed(&selector), Some(1.0));
+
+        health.hurt_tick = Some(now);
+        let steering = tick(&mut selector, &health, &[], &chunk_map, now + 1, &mut rng);
+        assert_eq!(move_speed(&selector), Some(1.25));
+        assert_eq!(
+            selector
+                .tasks()
+                .filter(|task| match task {
+                    Task::MoveTo { .. } => true,
+                    _ => false,
+                })
+                .count(),
+            1
+        );
+        let (target, speed) = steering.move_to.unwrap();
+        assert!((speed - 1.25).abs() < 1e-6);
+        assert!((target.y - 64.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_look_at_player() {
+        let chunk_map = floor();
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut selector = GoalSelector::new(MobKind::Pig.goals());
+        let health = HealthComponent::new(10.0);
+        let mut world = World::new();
+        let player = world.create_entity().build();
+        let eyes = glm::vec3(3.5, 65.62, 0.5);
+
+        let mut now = 0;
+        let steering = loop {
+            now += 1;
+            assert!(now < 10_000, "the pig never looked at the player");
+            let steering = tick(
+                &mut selector,
+                &health,
+                &[(player, eyes)],
+                &chunk_map,
+                now,
+                &mut rng,
+            );
+            if selector.tasks().any(|task| task == Task::LookAt(player)) {
+                break steering;
+            }
+        };
+        assert_eq!(steering.look_at, Some(eyes));
+
+        // The pig stops looking once the player is out of range.
+        let far = glm::vec3(30.5, 65.62, 0.5);
+        tick(
+            &mut selector,
+            &health,
+            &[(player, far)],
+            &chunk_map,
+            now + 1,
+            &mut rng,
+        );
+        assert!(!selector.tasks().any(|task| task == Task::LookAt(player)));
+    }
+
+    #[test]
+    fn test_steer() {
+        let mut chunk_map = floor();
+        let steering = Steering {
+            move_to: Some((glm::vec3(0.5, 64.0, 5.5), 1.0)),
+            look_at: None,
+            attack: None,
+        };
+        let mut position = position!(0.5, 64.0, 0.5);
+        let mut velocity = glm::vec3(0.0, 0.0, 0.0);
+        steer(
+            steering,
+            0.25,
+            0.765,
+            &mut position,
+            &mut velocity,
+            &chunk_map,
+        );
+        assert!(velocity.x.abs() < 1e-6);
+        assert!((velocity.z - 0.25 * SPEED_SCALE).abs() < 1e-6);
+        assert!(velocity.y.abs() < 1e-6);
+        assert!(position.yaw.abs() < 1e-4);
+
+        // Mobs jump up blocks in their way.
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 64, 1), Block::Stone)
+            .unwrap();
+        steer(
+            steering,
+            0.25,
+            0.765,
+            &mut position,
+            &mut velocity,
+            &chunk_map,
+        );
+        assert!((velocity.y - JUMP_VELOCITY).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ai_system() {
+        let (mut w, mut d) = t::builder().with(AiSystem, "").build();
+        t::populate_with_air(&mut w);
+        for x in -8..8 {
+            for z in -8..8 {
+                t::set_block(x, 63, z, Block::Stone, &w);
+            }
+        }
+        let pig = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Pig,
+            position!(0.5, 64.0, 0.5),
+        );
+        w.maintain();
+        w.write_component::<HealthComponent>()
+            .get_mut(pig)
+            .unwrap()
+            .hurt_tick = Some(0);
+
+        w.fetch_mut::<TickCount>().0 = 1;
+        d.dispatch(&w);
+        let selectors = w.read_component::<GoalSelector>();
+        assert_eq!(move_speed(selectors.get(pig).unwrap()), Some(1.25));
+    }
+
+    #[test]
+    fn test_melee_attack() {
+        let chunk_map = floor();
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut selector = GoalSelector::new(MobKind::Zombie.goals());
+        let health = HealthComponent::new(20.0);
+        let mut world = World::new();
+        let player = world.create_entity().build();
+        let players = [(player, glm::vec3(1.5, 64.0 + PLAYER_EYE_HEIGHT, 0.5))];
+
+        let steering = tick(&mut selector, &health, &players, &chunk_map, 1, &mut rng);
+        assert_eq!(selector.target(), Some(player));
+        assert_eq!(steering.attack, Some(Attack::Melee(player)));
+
+        // Zombies wait between attacks.
+        let steering = tick(&mut selector, &health, &players, &chunk_map, 2, &mut rng);
+        assert_eq!(steering.attack, None);
+        assert!(steering.move_to.is_some());
+        let steering = tick(
+            &mut selector,
+            &health,
+            &players,
+            &chunk_map,
+            1 + MELEE_COOLDOWN,
+            &mut rng,
+        );
+        assert_eq!(steering.attack, Some(Attack::Melee(player)));
+
+        // The target is lost once out of range.
+        let far = [(player, glm::vec3(40.5, 64.0 + PLAYER_EYE_HEIGHT, 0.5))];
+        let steering = tick(&mut selector, &health, &far, &chunk_map, 100, &mut rng);
+        assert_eq!(selector.target(), None);
+        assert_eq!(steering.attack, None);
+    }
+
+    #[test]
+    fn test_target_line_of_sight() {
+        let mut chunk_map = floor();
+        for y in 64..67 {
+            for z in -4..4 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(2, y, z), Block::Stone)
+                    .unwrap();
+            }
+        }
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut selector = GoalSelector::new(MobKind::Zombie.goals());
+        let mut health = HealthComponent::new(20.0);
+        let mut world = World::new();
+        let player = world.create_entity().build();
+        let players = [(player, glm::vec3(4.5, 64.0 + PLAYER_EYE_HEIGHT, 0.5))];
+
+        tick(&mut selector, &health, &players, &chunk_map, 1, &mut rng);
+        assert_eq!(selector.target(), None);
+
+        // Mobs target players who hurt them even if they can't see them.
+        health.last_damager = Some(player);
+        tick(&mut selector, &health, &players, &chunk_map, 2, &mut rng);
+        assert_eq!(selector.target(), Some(player));
+    }
+
+    #[test]
+    fn test_skeleton_shoots() {
+        let (mut w, mut d) = t::builder().with(AiSystem, "").build();
+        t::populate_with_air(&mut w);
+        for x in -4..12 {
+            for z in -4..4 {
+                t::set_block(x, 63, z, Block::Stone, &w);
+            }
+        }
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        t::set_entity_pos(&w, player.entity, position!(8.5, 64.0, 0.5));
+        let skeleton = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Skeleton,
+            position!(0.5, 64.0, 0.5),
+        );
+        w.maintain();
+        let mut reader = t::reader::<ShootArrowEvent>(&w);
+
+        w.fetch_mut::<TickCount>().0 = 1;
+        d.dispatch(&w);
+        let events = t::triggered_events(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].shooter, Some(skeleton));
+        // The player is to the east, so the skeleton aims slightly upwards.
+        assert!((events[0].position.yaw + 90.0).abs() < 1.0);
+        assert!(events[0].position.pitch < 0.0);
+
+        // Creative players are never targeted.
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Creative;
+        w.fetch_mut::<TickCount>().0 = 100;
+        d.dispatch(&w);
+        assert!(t::triggered_events(&w, &mut reader).is_empty());
+    }
+}
@@ -0,0 +1,742 @@
+//! Mobs: living entities which are controlled by an AI.
+//!
+//! Every mob has a `MobComponent` identifying its kind, a
+//! `HealthComponent` (see the `combat` module), a `GoalSelector`,
+//! which chooses the goals steering the mob each tick (see the `ai`
+//! module), and a `Navigator`, which finds paths to the destinations
+//! of its goals (see the `pathfinding` module). Pigs, cows, sheep and
+//! chickens wander around, look at nearby players and panic when they
+//! are hurt. Zombies and skeletons
+//! attack players in survival and adventure mode, and catch fire when
+//! exposed to daylight.
+//!
+//! Mobs spawn naturally around players (see the `spawning` module),
+//! and operators spawn mobs with `/summon <type> [<x> <y> <z>]`, where
+//! coordinates prefixed with `~` are relative to the sender. Mobs
+//! despawn when far away from players, unless they are marked with
+//! `PersistenceRequired` or have a custom name. The health of mobs
+//! isn't saved yet, so mobs loaded from chunks have full health.
+
+pub mod ai;
+pub mod pathfinding;
+
+use crate::combat::{self, FireComponent, HealthComponent};
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::entity::{base_data, chicken, cow, metadata, pig, sheep, skeleton, zombie};
+use crate::entity::{Metadata, NamedComponent, PositionComponent, VelocityComponent};
+use crate::lang::{Locale, Message};
+use crate::lazy::LazyUpdateExt;
+use crate::network::NetworkComponent;
+use crate::physics::{AABBExt, PhysicsComponent};
+use crate::systems::{DAYLIGHT_BURN, ENTITY_PHYSICS, MOB_AI, MOB_NAVIGATION, SUMMON_COMMAND};
+use crate::time::Time;
+use crate::timings::DispatcherBuilderExt;
+use crate::weather::{self, Weather};
+use ai::{AiSystem, Goal, GoalSelector};
+use feather_core::entity::AnimalData;
+use feather_core::world::{sky_darkening, ChunkMap};
+use feather_core::{BlockExt, Position};
+use pathfinding::{NavigationSystem, Navigator};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{
+    Builder, Component, DenseVecStorage, DispatcherBuilder, Entities, Entity, HashMapStorage, Join,
+    LazyUpdate, NullStorage, Read, ReadStorage, System, World, WorldExt, WriteStorage,
+};
+use std::sync::Arc;
+
+/// The colors of wool, in the order of their IDs.
+pub const WOOL_COLORS: [&str; 16] = [
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// The bit of the wool metadata of sheep which is set once they are sheared.
+pub const SHEARED: u8 = 0x10;
+/// The number of ticks for which mobs burn after being exposed to daylight.
+const DAYLIGHT_FIRE_TICKS: u64 = 160;
+
+/// The kinds of mobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MobKind {
+    Chicken,
+    Cow,
+    Pig,
+    Sheep,
+    Skeleton,
+    Zombie,
+}
+
+impl MobKind {
+    pub fn values() -> &'static [MobKind] {
+        &[
+            MobKind::Chicken,
+            MobKind::Cow,
+            MobKind::Pig,
+            MobKind::Sheep,
+            MobKind::Skeleton,
+            MobKind::Zombie,
+        ]
+    }
+
+    /// Returns the mob with the given identifier, such
+    /// as `minecraft:pig`. The namespace may be omitted.
+    pub fn from_identifier(id: &str) -> Option<Self> {
+        let id = id.strip_prefix("minecraft:").unwrap_or(id);
+        Self::values()
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == id)
+    }
+
+    pub fn identifier(self) -> &'static str {
+        match self {
+            MobKind::Chicken => "minecraft:chicken",
+            MobKind::Cow => "minecraft:cow",
+            MobKind::Pig => "minecraft:pig",
+            MobKind::Sheep => "minecraft:sheep",
+            MobKind::Skeleton => "minecraft:skeleton",
+            MobKind::Zombie => "minecraft:zombie",
+        }
+    }
+
+    /// Returns the identifier without its namespace.
+    pub fn name(self) -> &'static str {
+        &self.identifier()["minecraft:".len()..]
+    }
+
+    pub fn max_health(self) -> f32 {
+        match self {
+            MobKind::Chicken => 4.0,
+            MobKind::Cow | MobKind::Pig => 10.0,
+            MobKind::Sheep => 8.0,
+            MobKind::Skeleton | MobKind::Zombie => 20.0,
+        }
+    }
+
+    /// Returns the movement speed attribute of this
+    /// mob, which is multiplied by the speed of goals.
+    pub fn movement_speed(self) -> f64 {
+        match self {
+            MobKind::Chicken | MobKind::Pig | MobKind::Skeleton => 0.25,
+            MobKind::Cow => 0.2,
+            MobKind::Sheep | MobKind::Zombie => 0.23,
+        }
+    }
+
+    /// Returns whether this mob attacks players.
+    pub fn is_hostile(self) -> bool {
+        match self {
+            MobKind::Skeleton | MobKind::Zombie => true,
+            MobKind::Chicken | MobKind::Cow | MobKind::Pig | MobKind::Sheep => false,
+        }
+    }
+
+    /// Returns the category of this mob, which
+    /// is capped separately by natural spawning.
+    pub fn category(self) -> MobCategory {
+        if self.is_hostile() {
+            MobCategory::Monster
+        } else {
+            MobCategory::Creature
+        }
+    }
+
+    /// Returns whether this mob despawns when
+    /// far away from players. Animals never do.
+    pub fn can_despawn(self) -> bool {
+        self.category() != MobCategory::Creature
+    }
+
+    /// Returns whether this mob catches fire in daylight.
+    pub fn burns_in_daylight(self) -> bool {
+        self.is_hostile()
+    }
+
+    /// Returns the damage dealt by melee attacks of this mob.
+    pub fn attack_damage(self) -> f32 {
+        match self {
+            MobKind::Zombie => 3.0,
+            _ => 2.0,
+        }
+    }
+
+    /// Returns the goals of this mob, in order of priority.
+    pub fn goals(self) -> Vec<Goal> {
+        let panic_speed = match self {
+            MobKind::Chicken => 1.4,
+            MobKind::Cow => 2.0,
+            MobKind::Pig | MobKind::Sheep => 1.25,
+            MobKind::Skeleton | MobKind::Zombie => return self.hostile_goals(),
+        };
+        vec![
+            Goal::Panic { speed: panic_speed },
+            Goal::Wander { speed: 1.0 },
+            Goal::LookAtPlayer { range: 6.0 },
+            Goal::LookAround,
+        ]
+    }
+
+    /// Returns the goals of a hostile mob, in order of priority.
+    fn hostile_goals(self) -> Vec<Goal> {
+        let (range, attack) = match self {
+            MobKind::Skeleton => (
+                16.0,
+                Goal::RangedAttack {
+                    speed: 1.0,
+                    interval: 40,
+                    range: 15.0,
+                },
+            ),
+            _ => (35.0, Goal::MeleeAttack { speed: 1.0 }),
+        };
+        vec![
+            Goal::TargetPlayer { range },
+            attack,
+            Goal::Wander { speed: 1.0 },
+            Goal::LookAtPlayer { range: 8.0 },
+            Goal::LookAround,
+        ]
+    }
+
+    /// Returns the initial metadata of this mob.
+    pub fn metadata<R: Rng + ?Sized>(self, rng: &mut R) -> Metadata {
+        let mut metadata = match self {
+            MobKind::Pig => Metadata::Pig(metadata::Pig::default()),
+            MobKind::Sheep => {
+                let mut sheep = metadata::Sheep::default();
+                sheep.set_wool(random_sheep_color(rng));
+                Metadata::Sheep(sheep)
+            }
+            MobKind::Chicken | MobKind::Cow => Metadata::Ageable(metadata::Ageable::default()),
+            MobKind::Skeleton => Metadata::Skeleton(metadata::Skeleton::default()),
+            MobKind::Zombie => Metadata::Zombie(metadata::Zombie::default()),
+        };
+        metadata.set_health(self.max_health());
+        metadata
+    }
+
+    /// Returns the identifier of the loot table
+    /// dropped by this mob when it is killed.
+    pub fn loot_table(self, metadata: Option<&Metadata>) -> String {
+        match (self, metadata) {
+            (MobKind::Sheep, Some(Metadata::Sheep(sheep))) if sheep.wool() & SHEARED == 0 => {
+                let color = WOOL_COLORS[usize::from(sheep.wool() & 0x0f)];
+                format!("minecraft:entities/sheep/{}", color)
+            }
+            _ => format!("minecraft:entities/{}", self.name()),
+        }
+    }
+}
+
+/// Returns the color of a newly spawned sheep,
+/// with the probabilities used by vanilla.
+fn random_sheep_color<R: Rng + ?Sized>(rng: &mut R) -> u8 {
+    let color = match rng.gen_range(0, 100) {
+        0..=4 => "black",
+        5..=9 => "gray",
+        10..=14 => "light_gray",
+        15..=17 => "brown",
+        _ if rng.gen_range(0, 500) == 0 => "pink",
+        _ => "white",
+    };
+    WOOL_COLORS.iter().position(|c| *c == color).unwrap() as u8
+}
+
+/// The categories of mobs, whose numbers are
+/// capped separately by natural spawning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MobCategory {
+    Monster,
+    Creature,
+}
+
+impl MobCategory {
+    pub fn values() -> &'static [MobCategory] {
+        &[MobCategory::Monster, MobCategory::Creature]
+    }
+
+    /// Returns the number of mobs of this category which may
+    /// exist per 289 chunks around players, as in vanilla.
+    pub fn cap(self) -> usize {
+        match self {
+            MobCategory::Monster => 70,
+            MobCategory::Creature => 10,
+        }
+    }
+}
+
+/// Marks an entity as a mob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MobComponent {
+    pub kind: MobKind,
+}
+
+impl Component for MobComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks a mob which never despawns and isn't
+/// counted towards the caps of natural spawning.
+#[derive(Debug, Default)]
+pub struct PersistenceRequired;
+
+impl Component for PersistenceRequired {
+    type Storage = NullStorage<Self>;
+}
+
+/// The custom name of a mob as a JSON text component, which
+/// keeps it from despawning. Name tags can't be used on mobs
+/// yet, and custom names aren't shown to clients yet, so
+/// custom names are only kept when mobs are loaded from
+/// and saved to chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomNameComponent(pub String);
+
+impl Component for CustomNameComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Applies the components common to all mobs. This is called by
+/// the `create` functions of mob entity implementations, such as
+/// `pig::create`.
+pub fn create<'a>(
+    lazy: &'a LazyUpdate,
+    entities: &'a EntitiesRes,
+    kind: MobKind,
+) -> LazyBuilder<'a> {
+    lazy.spawn_entity(entities)
+        .with(MobComponent { kind })
+        .with(HealthComponent::new(kind.max_health()))
+        .with(GoalSelector::new(kind.goals()))
+        .with(Navigator::default())
+        .with(kind.metadata(&mut rand::thread_rng()))
+}
+
+/// Applies the persistence and custom name of saved mob
+/// data to a mob being loaded. This is called by the
+/// `create_from_data` functions of mob entity implementations.
+pub fn load_data<'a>(builder: LazyBuilder<'a>, data: &AnimalData) -> LazyBuilder<'a> {
+    let builder = if data.persistence_required {
+        builder.with(PersistenceRequired)
+    } else {
+        builder
+    };
+    match &data.custom_name {
+        Some(name) => builder.with(CustomNameComponent(name.clone())),
+        None => builder,
+    }
+}
+
+/// Returns the data to save for a mob, including
+/// its persistence and custom name.
+pub fn save_data(world: &World, entity: Entity) -> AnimalData {
+    AnimalData {
+        persistence_required: world
+            .read_component::<PersistenceRequired>()
+            .contains(entity),
+        custom_name: world
+            .read_component::<CustomNameComponent>()
+            .get(entity)
+            .map(|name| name.0.clone()),
+        ..AnimalData::new(base_data(world, entity))
+    }
+}
+
+/// Spawns a mob at the given position, facing in a random direction.
+pub fn spawn(lazy: &LazyUpdate, entities: &EntitiesRes, kind: MobKind, pos: Position) -> Entity {
+    let builder = match kind {
+        MobKind::Chicken => chicken::create(lazy, entities),
+        MobKind::Cow => cow::create(lazy, entities),
+        MobKind::Pig => pig::create(lazy, entities),
+        MobKind::Sheep => sheep::create(lazy, entities),
+        MobKind::Skeleton => skeleton::create(lazy, entities),
+        MobKind::Zombie => zombie::create(lazy, entities),
+    };
+    let pos = Position {
+        yaw: rand::thread_rng().gen_range(0.0, 360.0),
+        pitch: 0.0,
+        ..pos
+    };
+    builder
+        .with(PositionComponent {
+            current: pos,
+            previous: pos,
+        })
+        .with(VelocityComponent::default())
+        .build()
+}
+
+/// System which sets mobs burning in daylight on fire when
+/// they aren't burning yet and are exposed to the sky,
+/// unless they are in water or it rains on them.
+pub struct DaylightBurnSystem;
+
+impl<'a> System<'a> for DaylightBurnSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, MobComponent>,
+        ReadStorage<'a, HealthComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PhysicsComponent>,
+        WriteStorage<'a, FireComponent>,
+        WriteStorage<'a, Metadata>,
+        Read<'a, ChunkMap>,
+        Read<'a, Time>,
+        Read<'a, Weather>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mobs,
+            healths,
+            positions,
+            physics,
+            mut fires,
+            mut metadatas,
+            chunk_map,
+            time,
+            weather,
+        ) = data;
+
+        // Vanilla considers it day while the sky
+        // light is darkened by less than 4 levels.
+        if sky_darkening(time.time_of_day()) >= 4 {
+            return;
+        }
+
+        let mut ignited = vec![];
+        for (entity, mob, health, position, physics, _) in
+            (&entities, &mobs, &healths, &positions, &physics, !&fires).join()
+        {
+            if !mob.kind.burns_in_daylight() || health.is_dead() {
+                continue;
+            }
+
+            let pos = position.current;
+            let eyes = position!(pos.x, pos.y + physics.bbox.size().y * ai::EYE_HEIGHT, pos.z);
+            let eyes = eyes.block_pos();
+            let in_water = [pos.block_pos(), eyes].iter().any(|pos| {
+                chunk_map
+                    .block_at(*pos)
+                    .map_or(false, |block| block.is_fluid())
+            });
+            if chunk_map.sky_light_at(eyes) == Some(15)
+                && !in_water
+                && !weather::is_raining_at(*weather, &chunk_map, eyes)
+            {
+                ignited.push(entity);
+            }
+        }
+
+        for entity in ignited {
+            combat::ignite(entity, DAYLIGHT_FIRE_TICKS, &mut fires, &mut metadatas);
+        }
+    }
+}
+
+const SUMMON_SYNTAX: &str = "/summon <type> [<x> <y> <z>]";
+
+/// System implementing `/summon`.
+#[derive(Default)]
+pub struct SummonCommandSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl<'a> System<'a> for SummonCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+        ReadStorage<'a, PositionComponent>,
+        Read<'a, ChunkMap>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            config,
+            locale,
+            nameds,
+            networks,
+            consoles,
+            positions,
+            chunk_map,
+            lazy,
+            entities,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if event.name != "summon" {
+                continue;
+            }
+
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let message = match summon_for(event, &positions, &chunk_map) {
+                Ok((kind, pos)) => {
+                    spawn(&lazy, &entities, kind, pos);
+                    Message::translate("feather.summon.success").with(kind.identifier())
+                }
+                Err(message) => message,
+            };
+            reply(event.sender, &networks, &consoles, &locale, message);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("summon");
+    }
+}
+
+/// Returns the mob and position to spawn it at
+/// for a `/summon` command, or the error to reply with.
+fn summon_for(
+    event: &CommandEvent,
+    positions: &ReadStorage<PositionComponent>,
+    chunk_map: &ChunkMap,
+) -> Result<(MobKind, Position), Message> {
+    let origin = positions.get(event.sender).map(|position| position.current);
+
+    let (ty, coordinates) = match event.args.as_slice() {
+        [ty] => (ty, None),
+        [ty, x, y, z] => (ty, Some((x, y, z))),
+        _ => return Err(usage(SUMMON_SYNTAX)),
+    };
+    let kind = MobKind::from_identifier(ty)
+        .ok_or_else(|| Message::translate("feather.summon.unknown_type").with(ty))?;
+
+    let pos = match coordinates {
+        Some((x, y, z)) => position!(
+            coordinate(x, origin.map(|origin| origin.x))?,
+            coordinate(y, origin.map(|origin| origin.y))?,
+            coordinate(z, origin.map(|origin| origin.z))?
+        ),
+        None => origin.ok_or_else(|| Message::translate("feather.summon.no_position"))?,
+    };
+    if pos.y < 0.0 || pos.y >= 256.0 || chunk_map.chunk_at(pos.chunk_pos()).is_none() {
+        return Err(Message::translate("feather.summon.invalid_position"));
+    }
+
+    Ok((kind, pos))
+}
+
+/// Parses a coordinate of a command, which is relative
+/// to the given origin if it is prefixed with `~`.
+fn coordinate(arg: &str, origin: Option<f64>) -> Result<f64, Message> {
+    match arg.strip_prefix('~') {
+        Some(offset) => {
+            let origin = origin.ok_or_else(|| Message::translate("feather.summon.no_position"))?;
+            if offset.is_empty() {
+                Ok(origin)
+            } else {
+                offset
+                    .parse::<f64>()
+                    .map(|offset| origin + offset)
+                    .map_err(|_| usage(SUMMON_SYNTAX))
+            }
+        }
+        None => arg.parse().map_err(|_| usage(SUMMON_SYNTAX)),
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(AiSystem, MOB_AI, &[ENTITY_PHYSICS]);
+    dispatcher.add_timed(NavigationSystem::default(), MOB_NAVIGATION, &[MOB_AI]);
+    dispatcher.add_timed(DaylightBurnSystem, DAYLIGHT_BURN, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(SummonCommandSystem::default(), SUMMON_COMMAND, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loot::LootTables;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::implementation::ChatMessageClientbound;
+    use feather_core::PacketType;
+    use specs::{Join, WorldExt};
+
+    #[test]
+    fn test_from_identifier() {
+        assert_eq!(
+            MobKind::from_identifier("minecraft:pig"),
+            Some(MobKind::Pig)
+        );
+        assert_eq!(MobKind::from_identifier("sheep"), Some(MobKind::Sheep));
+        assert_eq!(MobKind::from_identifier("minecraft:creeper"), None);
+        for kind in MobKind::values() {
+            assert_eq!(MobKind::from_identifier(kind.identifier()), Some(*kind));
+        }
+    }
+
+    #[test]
+    fn test_loot_table() {
+        let sheep = |wool| {
+            let mut sheep = metadata::Sheep::default();
+            sheep.set_wool(wool);
+            Metadata::Sheep(sheep)
+        };
+        assert_eq!(
+            MobKind::Sheep.loot_table(Some(&sheep(14))),
+            "minecraft:entities/sheep/red"
+        );
+        assert_eq!(
+            MobKind::Sheep.loot_table(Some(&sheep(14 | SHEARED))),
+            "minecraft:entities/sheep"
+        );
+        assert_eq!(MobKind::Pig.loot_table(None), "minecraft:entities/pig");
+
+        let tables = LootTables::bundled();
+        for kind in MobKind::values() {
+            assert!(tables.get(&kind.loot_table(None)).is_some());
+        }
+        for wool in 0..WOOL_COLORS.len() as u8 {
+            let table = MobKind::Sheep.loot_table(Some(&sheep(wool)));
+            assert!(tables.get(&table).is_some());
+        }
+    }
+
+    #[test]
+    fn test_summon_command() {
+        let (mut w, mut d) = t::builder()
+            .with(SummonCommandSystem::default(), "")
+            .build();
+        assert!(w.fetch::<CommandRegistry>().contains("summon"));
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = String::from("admin");
+        let mut config = Config::default();
+        config.server.operators.push(String::from("admin"));
+        w.insert(Arc::new(config));
+
+        let mut summon = |w: &mut specs::World, command: &str| {
+            t::trigger_event(w, CommandEvent::parse(player.entity, command).unwrap());
+            d.dispatch(w);
+            w.maintain();
+            let packet = t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+            cast_packet::<ChatMessageClientbound>(&*packet)
+                .json_data
+                .clone()
+        };
+
+        assert!(summon(&mut w, "/summon minecraft:creeper").contains("Unknown mob type"));
+        assert!(summon(&mut w, "/summon cow 1000 64 0").contains("Invalid position"));
+        assert!(summon(&mut w, "/summon pig ~2 ~ 5").contains("Summoned new minecraft:pig"));
+
+        let mobs = w.read_component::<MobComponent>();
+        let positions = w.read_component::<PositionComponent>();
+        let healths = w.read_component::<HealthComponent>();
+        let (mob, pos, health) = (&mobs, &positions, &healths).join().next().unwrap();
+        assert_eq!(mob.kind, MobKind::Pig);
+        assert!((pos.current.x - 2.0).abs() < 1e-6);
+        assert!(pos.current.y.abs() < 1e-6);
+        assert!((pos.current.z - 5.0).abs() < 1e-6);
+        assert!((health.health - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_daylight_burn() {
+        let (mut w, mut d) = t::builder().with(DaylightBurnSystem, "").build();
+        t::populate_with_air(&mut w);
+
+        let mut burning = |w: &mut specs::World, kind: MobKind, time: u64| {
+            w.insert(Time(time));
+            let entity = spawn(
+                &w.fetch::<LazyUpdate>(),
+                &w.entities(),
+                kind,
+                position!(0.5, 64.0, 0.5),
+            );
+            w.maintain();
+            d.dispatch(w);
+            w.maintain();
+            let burning = w.read_component::<FireComponent>().get(entity).is_some();
+            w.delete_entity(entity).unwrap();
+            burning
+        };
+
+        assert!(burning(&mut w, MobKind::Zombie, 6000));
+        assert!(burning(&mut w, MobKind::Skeleton, 6000));
+        assert!(!burning(&mut w, MobKind::Pig, 6000));
+        assert!(!burning(&mut w, MobKind::Zombie, 18000));
+    }
+
+    #[test]
+    fn test_persistence_saved() {
+        let (mut w, _) = t::builder().build();
+        let entity = spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Pig,
+            position!(0.0, 64.0, 0.0),
+        );
+        w.maintain();
+        assert!(!save_data(&w, entity).persistence_required);
+
+        w.write_component::<PersistenceRequired>()
+            .insert(entity, PersistenceRequired)
+            .unwrap();
+        w.write_component::<CustomNameComponent>()
+            .insert(
+                entity,
+                CustomNameComponent(String::from(r#"{"text":"Bob"}"#)),
+            )
+            .unwrap();
+        let data = save_data(&w, entity);
+        assert!(data.persistence_required);
+        assert_eq!(data.custom_name.as_ref().unwrap(), r#"{"text":"Bob"}"#);
+
+        let loaded = pig::create_from_data(&w.fetch::<LazyUpdate>(), &w.entities(), &data).unwrap();
+        w.maintain();
+        assert!(w.read_component::<PersistenceRequired>().contains(loaded));
+        assert_eq!(
+            w.read_component::<CustomNameComponent>().get(loaded),
+            Some(&CustomNameComponent(String::from(r#"{"text":"Bob"}"#)))
+        );
+    }
+}
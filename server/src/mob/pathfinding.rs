@@ -0,0 +1,755 @@
+//! Pathfinding for mobs.
+//!
+//! Paths are found with A* over the blocks a mob can stand in. From
+//! a block, mobs walk to the four neighbouring blocks at the same
+//! height, jump up at most `STEP_HEIGHT` blocks or drop down at most
+//! `MAX_FALL` blocks. Mobs neither walk through nor stand on fluids,
+//! fences, walls, fire and cacti, so paths lead around water, lava and
+//! fences. Paths are then smoothed by skipping the blocks which the
+//! mob walks past anyway when it heads for a later block in a straight
+//! line.
+//!
+//! The `Navigator` of a mob holds the path it follows to the
+//! destination of its current goal. When the destination changes,
+//! the `NavigationSystem` copies the blocks around the mob and its
+//! destination into a `Region` and finds a path through it on the
+//! `rayon` thread pool, so that long searches don't delay the tick.
+//! The path is handed to the navigator once it has been found,
+//! usually in the next tick.
+
+use super::ai::horizontal_distance;
+use crate::entity::PositionComponent;
+use crate::TickCount;
+use crossbeam::channel::{Receiver, Sender};
+use feather_core::world::ChunkMap;
+use feather_core::{Block, BlockExt, BlockPosition};
+use glm::DVec3;
+use smallvec::SmallVec;
+use specs::{
+    Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, WriteStorage,
+};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The number of blocks mobs can jump up.
+pub const STEP_HEIGHT: i32 = 1;
+/// The number of blocks mobs are willing to drop down.
+pub const MAX_FALL: i32 = 3;
+/// The maximum horizontal distance, in blocks,
+/// to the destination of a path.
+pub const MAX_DISTANCE: i32 = 48;
+/// The maximum number of blocks visited when searching for a path.
+const MAX_NODES: usize = 2048;
+/// The number of blocks copied around the start and
+/// the destination of a path in each horizontal direction.
+const MARGIN: i32 = 8;
+/// The minimum number of ticks between two
+/// paths requested by a navigator.
+const REPATH_INTERVAL: u64 = 10;
+/// The maximum number of paths requested in a tick.
+const MAX_REQUESTS_PER_TICK: usize = 8;
+/// The horizontal distance within which a waypoint counts as reached.
+const WAYPOINT_REACHED: f64 = 0.3;
+/// Half the width of the area a mob needs to walk through,
+/// which is checked when smoothing paths.
+const HALF_WIDTH: f64 = 0.3;
+/// The cost of walking to a neighbouring block.
+/// Jumping up or dropping down a block costs as much again.
+const MOVE_COST: u32 = 10;
+
+/// What a block is to pathfinding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    /// A block mobs can walk through.
+    Open,
+    /// A block mobs can stand on.
+    Solid,
+    /// A block mobs neither walk through nor stand on:
+    /// fluids, fences, walls and blocks which hurt mobs.
+    /// Unloaded blocks are avoided as well.
+    Avoided,
+}
+
+impl Cell {
+    /// Returns the cell of a block.
+    pub fn of(block: Block) -> Self {
+        if is_avoided(block) || block.is_fluid() {
+            Cell::Avoided
+        } else if block.is_solid() {
+            Cell::Solid
+        } else {
+            Cell::Open
+        }
+    }
+}
+
+/// Returns whether mobs avoid a block other than fluids,
+/// either because it is too high for them to jump over
+/// or because it hurts them.
+fn is_avoided(block: Block) -> bool {
+    match block {
+        Block::OakFence(_)
+        | Block::SpruceFence(_)
+        | Block::BirchFence(_)
+        | Block::JungleFence(_)
+        | Block::AcaciaFence(_)
+        | Block::DarkOakFence(_)
+        | Block::NetherBrickFence(_)
+        | Block::OakFenceGate(_)
+        | Block::SpruceFenceGate(_)
+        | Block::BirchFenceGate(_)
+        | Block::JungleFenceGate(_)
+        | Block::AcaciaFenceGate(_)
+        | Block::DarkOakFenceGate(_)
+        | Block::CobblestoneWall(_)
+        | Block::MossyCobblestoneWall(_)
+        | Block::Fire(_)
+        | Block::Cactus(_)
+        | Block::MagmaBlock => true,
+        _ => false,
+    }
+}
+
+fn offset(pos: BlockPosition, x: i32, y: i32, z: i32) -> BlockPosition {
+    BlockPosition::new(pos.x + x, pos.y + y, pos.z + z)
+}
+
+fn block_of(pos: DVec3) -> BlockPosition {
+    BlockPosition::new(
+        pos.x.floor() as i32,
+        pos.y.floor() as i32,
+        pos.z.floor() as i32,
+    )
+}
+
+/// Returns the center of the bottom of a block.
+fn center(pos: BlockPosition) -> DVec3 {
+    glm::vec3(
+        f64::from(pos.x) + 0.5,
+        f64::from(pos.y),
+        f64::from(pos.z) + 0.5,
+    )
+}
+
+/// A copy of the blocks in a cuboid of the world, which
+/// paths can be searched in on another thread.
+#[derive(Debug, Clone)]
+pub struct Region {
+    /// The block with the lowest coordinates.
+    min: BlockPosition,
+    /// The number of blocks along the X, Y and Z axes.
+    size: [i32; 3],
+    /// The cells, ordered by X, then Y, then Z.
+    cells: Vec<Cell>,
+}
+
+impl Region {
+    /// Copies the blocks between `min` and `max`, inclusive.
+    pub fn new(chunk_map: &ChunkMap, min: BlockPosition, max: BlockPosition) -> Self {
+        let size = [max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1];
+        let mut cells = Vec::with_capacity((size[0] * size[1] * size[2]).max(0) as usize);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let block = chunk_map.block_at(BlockPosition::new(x, y, z));
+                    cells.push(block.map_or(Cell::Avoided, Cell::of));
+                }
+            }
+        }
+        Self { min, size, cells }
+    }
+
+    /// Copies the blocks which may be walked through
+    /// on the way between two positions.
+    pub fn around(chunk_map: &ChunkMap, a: BlockPosition, b: BlockPosition) -> Self {
+        let min = BlockPosition::new(
+            a.x.min(b.x) - MARGIN,
+            a.y.min(b.y) - MAX_FALL - 1,
+            a.z.min(b.z) - MARGIN,
+        );
+        let max = BlockPosition::new(
+            a.x.max(b.x) + MARGIN,
+            a.y.max(b.y) + STEP_HEIGHT + 2,
+            a.z.max(b.z) + MARGIN,
+        );
+        Self::new(chunk_map, min, max)
+    }
+
+    fn index(&self, pos: BlockPosition) -> Option<usize> {
+        let (x, y, z) = (pos.x - self.min.x, pos.y - self.min.y, pos.z - self.min.z);
+        if x < 0 || y < 0 || z < 0 || x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return None;
+        }
+        Some(((x * self.size[1] + y) * self.size[2] + z) as usize)
+    }
+
+    fn position(&self, index: usize) -> BlockPosition {
+        let index = index as i32;
+        let z = index % self.size[2];
+        let y = index / self.size[2] % self.size[1];
+        let x = index / (self.size[2] * self.size[1]);
+        offset(self.min, x, y, z)
+    }
+
+    /// Returns the cell at the given position. Blocks
+    /// outside of the region are avoided.
+    pub fn cell(&self, pos: BlockPosition) -> Cell {
+        self.index(pos)
+            .map_or(Cell::Avoided, |index| self.cells[index])
+    }
+
+    fn is_open(&self, pos: BlockPosition) -> bool {
+        self.cell(pos) == Cell::Open
+    }
+
+    /// Returns whether a mob can stand in the given block,
+    /// which must be open, as must be the block above it,
+    /// and on top of a solid block.
+    pub fn can_stand_at(&self, pos: BlockPosition) -> bool {
+        self.cell(offset(pos, 0, -1, 0)) == Cell::Solid
+            && self.is_open(pos)
+            && self.is_open(offset(pos, 0, 1, 0))
+    }
+
+    /// Returns the block a mob at the given position would
+    /// land in, if it is at most `MAX_FALL` blocks below.
+    pub fn ground(&self, pos: BlockPosition) -> Option<BlockPosition> {
+        (0..=MAX_FALL)
+            .map(|fall| offset(pos, 0, -fall, 0))
+            .take_while(|pos| self.is_open(*pos))
+            .find(|pos| self.can_stand_at(*pos))
+    }
+
+    /// Returns the blocks a mob standing in the given
+    /// block can move to, with the costs of the moves.
+    fn moves(&self, pos: BlockPosition) -> SmallVec<[(BlockPosition, u32); 4]> {
+        let mut moves = SmallVec::new();
+        for (x, z) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let side = offset(pos, *x, 0, *z);
+            if self.can_stand_at(side) {
+                moves.push((side, MOVE_COST));
+            } else if self.is_open(side) && self.is_open(offset(side, 0, 1, 0)) {
+                if let Some(ground) = self.ground(side) {
+                    let fall = (pos.y - ground.y) as u32;
+                    moves.push((ground, MOVE_COST * (1 + fall)));
+                }
+            } else {
+                // Jumping needs room above the head of the mob.
+                let jump = (1..=STEP_HEIGHT)
+                    .take_while(|step| self.is_open(offset(pos, 0, step + 1, 0)))
+                    .find(|step| self.can_stand_at(offset(side, 0, *step, 0)));
+                if let Some(step) = jump {
+                    moves.push((offset(side, 0, step, 0), MOVE_COST * (1 + step as u32)));
+                }
+            }
+        }
+        moves
+    }
+
+    /// Returns whether a mob can walk in a straight line from
+    /// the center of one block to the center of another block
+    /// at the same height without leaving the blocks it can
+    /// stand in.
+    fn can_walk_straight(&self, from: BlockPosition, to: BlockPosition) -> bool {
+        let (start, end) = (center(from), center(to));
+        let steps = (glm::distance(&start, &end) / 0.25).ceil().max(1.0) as usize;
+        (0..=steps).all(|step| {
+            let point = start + (end - start) * (step as f64 / steps as f64);
+            [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+                .iter()
+                .all(|(x, z)| {
+                    let pos = BlockPosition::new(
+                        (point.x + x * HALF_WIDTH).floor() as i32,
+                        from.y,
+                        (point.z + z * HALF_WIDTH).floor() as i32,
+                    );
+                    self.can_stand_at(pos)
+                })
+        })
+    }
+}
+
+/// Estimates the cost of moving between two blocks.
+/// Moves never cost less than the estimate.
+fn heuristic(a: BlockPosition, b: BlockPosition) -> u32 {
+    let distance = (a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs();
+    MOVE_COST * distance as u32
+}
+
+/// Searches a path from `start` to `goal`, returning the blocks
+/// along it, including both. Mobs in the air start from the
+/// block they would land in.
+///
+/// If `goal` can't be reached, the path leads to the reachable
+/// block closest to it instead. Returns `None` if no block
+/// closer to `goal` than `start` was found.
+pub fn find_path(
+    region: &Region,
+    start: BlockPosition,
+    goal: BlockPosition,
+) -> Option<Vec<BlockPosition>> {
+    // Mobs on slabs and other partial blocks are in the block above.
+    let start = region
+        .ground(start)
+        .or_else(|| region.ground(offset(start, 0, 1, 0)))?;
+    let goal = region.ground(goal).unwrap_or(goal);
+    let start_index = region.index(start)?;
+
+    let mut costs = vec![std::u32::MAX; region.cells.len()];
+    let mut parents: Vec<Option<usize>> = vec![None; region.cells.len()];
+    let mut closed = vec![false; region.cells.len()];
+    let mut queue = BinaryHeap::new();
+
+    let start_heuristic = heuristic(start, goal);
+    costs[start_index] = 0;
+    queue.push(Reverse((start_heuristic, start_heuristic, start_index)));
+    // The visited block closest to the goal.
+    let mut closest = (start_heuristic, start_index);
+    let mut visited = 0;
+
+    while let Some(Reverse((_, estimate, index))) = queue.pop() {
+        if closed[index] {
+            continue;
+        }
+        closed[index] = true;
+        if estimate < closest.0 || estimate == 0 {
+            closest = (estimate, index);
+        }
+        if estimate == 0 {
+            break;
+        }
+        visited += 1;
+        if visited > MAX_NODES {
+            break;
+        }
+
+        let pos = region.position(index);
+        for (neighbour, cost) in region.moves(pos) {
+            let neighbour_index = continue_if_none!(region.index(neighbour));
+            let cost = costs[index] + cost;
+            if cost < costs[neighbour_index] {
+                costs[neighbour_index] = cost;
+                parents[neighbour_index] = Some(index);
+                let estimate = heuristic(neighbour, goal);
+                queue.push(Reverse((cost + estimate, estimate, neighbour_index)));
+            }
+        }
+    }
+
+    if closest.1 == start_index && start != goal {
+        return None;
+    }
+
+    let mut path = vec![];
+    let mut next = Some(closest.1);
+    while let Some(index) = next {
+        path.push(region.position(index));
+        next = parents[index];
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Smooths a path by leaving out the blocks which a mob walks past
+/// when it heads straight for a later block at the same height.
+/// Returns the centers of the bottoms of the remaining blocks,
+/// except for the first one, in which the mob starts.
+pub fn smooth(region: &Region, path: &[BlockPosition]) -> Vec<DVec3> {
+    let mut waypoints = vec![];
+    let mut from = 0;
+    while from + 1 < path.len() {
+        let mut to = from + 1;
+        while to + 1 < path.len()
+            && path[to + 1].y == path[from].y
+            && region.can_walk_straight(path[from], path[to + 1])
+        {
+            to += 1;
+        }
+        waypoints.push(center(path[to]));
+        from = to;
+    }
+    waypoints
+}
+
+/// Finds and smooths a path from `start` to `destination`,
+/// returning the waypoints a mob walks to in order. If the path
+/// reaches the block of `destination`, the last waypoint is
+/// `destination` itself, at the height of the ground.
+pub fn navigate(region: &Region, start: DVec3, destination: DVec3) -> Vec<DVec3> {
+    let goal = block_of(destination);
+    let path = match find_path(region, block_of(start), goal) {
+        Some(path) => path,
+        None => return vec![],
+    };
+
+    let mut waypoints = smooth(region, &path);
+    let last = path[path.len() - 1];
+    if last == region.ground(goal).unwrap_or(goal) {
+        waypoints.pop();
+        waypoints.push(glm::vec3(destination.x, f64::from(last.y), destination.z));
+    }
+    waypoints
+}
+
+/// The path a mob follows to the destination of its current goal.
+#[derive(Debug, Clone, Default)]
+pub struct Navigator {
+    /// The position the mob navigates to.
+    destination: Option<DVec3>,
+    /// Whether the path leads to a block other
+    /// than the one containing `destination`.
+    outdated: bool,
+    /// The waypoints of the path which haven't been
+    /// reached yet, in reverse, so the next one is last.
+    waypoints: Vec<DVec3>,
+    /// The ID of the path being searched for the navigator.
+    pending: Option<u64>,
+    /// The tick at which a path was last requested.
+    request_tick: Option<u64>,
+}
+
+impl Navigator {
+    /// Sets the destination of the navigator, returning the next
+    /// waypoint toward it for a mob at the given position. The
+    /// mob keeps following its previous path, if any, while a
+    /// path to a new destination is searched.
+    pub fn follow(&mut self, destination: DVec3, position: DVec3) -> Option<DVec3> {
+        if self.destination.map(block_of) != Some(block_of(destination)) {
+            self.outdated = true;
+        }
+        self.destination = Some(destination);
+
+        while let Some(next) = self.waypoints.last() {
+            let reached = horizontal_distance(*next, position) <= WAYPOINT_REACHED
+                && (next.y - position.y).abs() < 1.0;
+            if !reached {
+                break;
+            }
+            self.waypoints.pop();
+        }
+        self.waypoints.last().copied()
+    }
+
+    /// Stops navigating, throwing the path away.
+    pub fn stop(&mut self) {
+        *self = Navigator {
+            request_tick: self.request_tick,
+            ..Navigator::default()
+        };
+    }
+
+    /// Returns the waypoints of the path which
+    /// haven't been reached yet, in order.
+    pub fn waypoints(&self) -> impl Iterator<Item = DVec3> + '_ {
+        self.waypoints.iter().rev().copied()
+    }
+
+    /// Returns whether a path is being searched for the navigator.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn should_request(&self, tick: u64) -> bool {
+        self.outdated
+            && self.pending.is_none()
+            && self
+                .request_tick
+                .map_or(true, |last| tick >= last + REPATH_INTERVAL)
+    }
+}
+
+impl Component for Navigator {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A path found on the thread pool.
+struct FoundPath {
+    entity: Entity,
+    id: u64,
+    waypoints: Vec<DVec3>,
+}
+
+/// System which searches paths for navigators whose destination
+/// changed, and hands the paths to the navigators once found.
+pub struct NavigationSystem {
+    sender: Sender<FoundPath>,
+    receiver: Receiver<FoundPath>,
+    next_id: u64,
+}
+
+impl Default for NavigationSystem {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam::unbounded();
+        Self {
+            sender,
+            receiver,
+            next_id: 0,
+        }
+    }
+}
+
+impl<'a> System<'a> for NavigationSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Navigator>,
+        ReadStorage<'a, PositionComponent>,
+        Read<'a, ChunkMap>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut navigators, positions, chunk_map, tick) = data;
+
+        for found in self.receiver.try_iter() {
+            let navigator = continue_if_none!(navigators.get_mut(found.entity));
+            // Paths of stopped navigators are dropped.
+            if navigator.pending != Some(found.id) {
+                continue;
+            }
+            navigator.pending = None;
+            navigator.waypoints = found.waypoints;
+            navigator.waypoints.reverse();
+        }
+
+        let mut requests = 0;
+        for (entity, navigator, position) in (&entities, &mut navigators, &positions).join() {
+            if requests >= MAX_REQUESTS_PER_TICK {
+                break;
+            }
+            if !navigator.should_request(tick.0) {
+                continue;
+            }
+            let destination = continue_if_none!(navigator.destination);
+            navigator.outdated = false;
+            navigator.request_tick = Some(tick.0);
+
+            let start = position.current.as_vec();
+            let (from, to) = (block_of(start), block_of(destination));
+            if (from.x - to.x).abs() > MAX_DISTANCE || (from.z - to.z).abs() > MAX_DISTANCE {
+                navigator.waypoints.clear();
+                continue;
+            }
+
+            let region = Region::around(&chunk_map, from, to);
+            let id = self.next_id;
+            self.next_id += 1;
+            navigator.pending = Some(id);
+            requests += 1;
+
+            let sender = self.sender.clone();
+            rayon::spawn(move || {
+                let waypoints = navigate(&region, start, destination);
+                // The receiver is gone once the server shuts down.
+                let _ = sender.send(FoundPath {
+                    entity,
+                    id,
+                    waypoints,
+                });
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mob::{self, MobKind};
+    use crate::testframework as t;
+    use feather_blocks::{LavaData, OakFenceData, WaterData};
+    use feather_core::world::chunk::Chunk;
+    use feather_core::ChunkPosition;
+    use specs::{LazyUpdate, World, WorldExt};
+    use std::time::Duration;
+
+    /// Returns a chunk map with a floor of stone at Y = 63.
+    fn floor() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new();
+        for x in -2..=2 {
+            for z in -2..=2 {
+                chunk_map.set_chunk_at(
+                    ChunkPosition::new(x, z),
+                    Chunk::new(ChunkPosition::new(x, z)),
+                );
+            }
+        }
+        fill(&mut chunk_map, (-32, 63, -32), (31, 63, 31), Block::Stone);
+        chunk_map
+    }
+
+    fn fill(chunk_map: &mut ChunkMap, min: (i32, i32, i32), max: (i32, i32, i32), block: Block) {
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    chunk_map
+                        .set_block_at(BlockPosition::new(x, y, z), block)
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    fn path(chunk_map: &ChunkMap, start: BlockPosition, goal: BlockPosition) -> Vec<BlockPosition> {
+        let region = Region::around(chunk_map, start, goal);
+        find_path(&region, start, goal).unwrap()
+    }
+
+    #[test]
+    fn test_straight_path() {
+        let chunk_map = floor();
+        let start = BlockPosition::new(0, 64, 0);
+        let goal = BlockPosition::new(0, 64, 6);
+        let path = path(&chunk_map, start, goal);
+        assert_eq!(path.len(), 7);
+        assert_eq!(path[0], start);
+        assert_eq!(path[6], goal);
+
+        // The path is smoothed into a single straight line.
+        let region = Region::around(&chunk_map, start, goal);
+        let waypoints = smooth(&region, &path);
+        assert_eq!(waypoints.len(), 1);
+        assert!(glm::distance(&waypoints[0], &glm::vec3(0.5, 64.0, 6.5)) < 1e-6);
+
+        let waypoints = navigate(
+            &region,
+            glm::vec3(0.5, 64.0, 0.5),
+            glm::vec3(0.2, 64.3, 6.7),
+        );
+        assert_eq!(waypoints.len(), 1);
+        assert!(glm::distance(&waypoints[0], &glm::vec3(0.2, 64.0, 6.7)) < 1e-6);
+    }
+
+    #[test]
+    fn test_obstacles() {
+        let obstacles = vec![
+            (64, 65, Block::Stone),
+            (64, 64, Block::OakFence(OakFenceData::default())),
+            (63, 63, Block::Water(WaterData { level: 0 })),
+            (63, 63, Block::Lava(LavaData { level: 0 })),
+        ];
+        let start = BlockPosition::new(0, 64, 0);
+        let goal = BlockPosition::new(0, 64, 6);
+        for (min_y, max_y, block) in obstacles {
+            let mut chunk_map = floor();
+            fill(&mut chunk_map, (-3, min_y, 3), (3, max_y, 3), block);
+            let path = path(&chunk_map, start, goal);
+            assert_eq!(path.last(), Some(&goal));
+            assert!(path.iter().all(|pos| pos.z != 3 || pos.x.abs() > 3));
+
+            let region = Region::around(&chunk_map, start, goal);
+            let waypoints = smooth(&region, &path);
+            assert!(waypoints.len() > 1);
+            assert!(waypoints.len() < path.len() - 1);
+        }
+    }
+
+    #[test]
+    fn test_step_height() {
+        let mut chunk_map = floor();
+        let start = BlockPosition::new(0, 64, 0);
+        fill(&mut chunk_map, (-16, 64, 3), (15, 64, 15), Block::Stone);
+        let goal = BlockPosition::new(0, 65, 6);
+        assert_eq!(path(&chunk_map, start, goal).last(), Some(&goal));
+
+        // Two blocks are too high to jump up, so the
+        // path leads to the closest block instead.
+        fill(&mut chunk_map, (-16, 65, 3), (15, 65, 15), Block::Stone);
+        let goal = BlockPosition::new(0, 66, 6);
+        assert_eq!(
+            path(&chunk_map, start, goal).last(),
+            Some(&BlockPosition::new(0, 64, 2))
+        );
+    }
+
+    #[test]
+    fn test_max_fall() {
+        let mut chunk_map = floor();
+        let goal = BlockPosition::new(0, 64, 0);
+        fill(&mut chunk_map, (-16, 64, 3), (15, 66, 15), Block::Stone);
+        let start = BlockPosition::new(0, 67, 6);
+        assert_eq!(path(&chunk_map, start, goal).last(), Some(&goal));
+
+        fill(&mut chunk_map, (-16, 67, 3), (15, 67, 15), Block::Stone);
+        let start = BlockPosition::new(0, 68, 6);
+        assert_eq!(
+            path(&chunk_map, start, goal).last(),
+            Some(&BlockPosition::new(0, 68, 3))
+        );
+    }
+
+    #[test]
+    fn test_navigator_follow() {
+        let mut navigator = Navigator::default();
+        let destination = glm::vec3(0.5, 64.0, 5.5);
+        assert_eq!(
+            navigator.follow(destination, glm::vec3(0.5, 64.0, 0.5)),
+            None
+        );
+        assert!(navigator.should_request(0));
+
+        navigator.outdated = false;
+        navigator.waypoints = vec![destination, glm::vec3(2.5, 64.0, 2.5)];
+        let next = navigator.follow(destination, glm::vec3(0.5, 64.0, 0.5));
+        assert_eq!(next, Some(glm::vec3(2.5, 64.0, 2.5)));
+        // Reached waypoints are dropped.
+        let next = navigator.follow(destination, glm::vec3(2.4, 64.0, 2.6));
+        assert_eq!(next, Some(destination));
+        assert!(!navigator.should_request(0));
+
+        // Moving the destination to another block requests a new path.
+        navigator.follow(glm::vec3(0.5, 64.0, 8.5), glm::vec3(2.4, 64.0, 2.6));
+        assert!(navigator.should_request(0));
+        navigator.stop();
+        assert_eq!(navigator.waypoints().count(), 0);
+        assert!(!navigator.should_request(0));
+    }
+
+    #[test]
+    fn test_navigation_system() {
+        let (mut w, mut d) = t::builder().with(NavigationSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        for x in -8..8 {
+            for z in -8..8 {
+                t::set_block(x, 63, z, Block::Stone, &w);
+            }
+        }
+        t::set_block(0, 64, 3, Block::Stone, &w);
+        t::set_block(0, 65, 3, Block::Stone, &w);
+        let pig = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Pig,
+            position!(0.5, 64.0, 0.5),
+        );
+        w.maintain();
+
+        let destination = glm::vec3(0.5, 64.0, 5.5);
+        let next = w
+            .write_component::<Navigator>()
+            .get_mut(pig)
+            .unwrap()
+            .follow(destination, glm::vec3(0.5, 64.0, 0.5));
+        assert_eq!(next, None);
+
+        let waypoints = |w: &World| -> Vec<DVec3> {
+            let navigators = w.read_component::<Navigator>();
+            navigators.get(pig).unwrap().waypoints().collect()
+        };
+        // Paths are found on the thread pool.
+        let mut attempts = 0;
+        while waypoints(&w).is_empty() {
+            attempts += 1;
+            assert!(attempts < 1000, "no path was found");
+            d.dispatch(&w);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let waypoints = waypoints(&w);
+        assert!(waypoints.len() > 1);
+        assert!(glm::distance(&waypoints[waypoints.len() - 1], &destination) < 1e-6);
+        assert!(!w
+            .read_component::<Navigator>()
+            .get(pig)
+            .unwrap()
+            .is_pending());
+    }
+}
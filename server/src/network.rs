@@ -2,17 +2,20 @@ use parking_lot::Mutex;
 
 use crossbeam::Receiver;
 use futures::channel::mpsc::UnboundedSender as Sender;
+use futures::channel::oneshot;
 use shrev::EventChannel;
 use specs::{
     Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System,
-    WorldExt, Write, WriteStorage,
+    World, WorldExt, Write, WriteStorage,
 };
+use std::sync::atomic::Ordering;
 
-use feather_core::network::packet::{implementation::*, Packet, PacketType};
+use feather_core::network::packet::{implementation::*, EncodedPacket, Packet, PacketType};
 
 use crate::entity::PlayerComponent;
 use crate::io::{ListenerToServerMessage, NetworkIoManager, ServerToWorkerMessage};
 use crate::joinhandler::JoinHandlerComponent;
+use crate::metrics::METRICS;
 use crate::prelude::*;
 use crate::{disconnect_player_without_packet, TickCount};
 use strum::EnumCount;
@@ -100,6 +103,21 @@ impl NetworkComponent {
             chunks_to_send: vec![],
         }
     }
+
+    /// Instructs the IO worker to write queued packets
+    /// to the connection. Packets sent to the IO worker
+    /// are buffered until this is called.
+    pub fn flush(&self) {
+        let _ = self.sender.unbounded_send(ServerToWorkerMessage::Flush);
+    }
+
+    /// Instructs the IO worker to close the connection
+    /// after sending all queued packets.
+    pub fn close(&self) {
+        let _ = self
+            .sender
+            .unbounded_send(ServerToWorkerMessage::Disconnect);
+    }
 }
 
 impl Component for NetworkComponent {
@@ -183,6 +201,7 @@ impl<'a> System<'a> for NetworkSystem {
             while let Ok(msg) = netcomp.receiver.try_recv() {
                 match msg {
                     ServerToWorkerMessage::NotifyPacketReceived(packet) => {
+                        METRICS.packets_received.fetch_add(1, Ordering::Relaxed);
                         packet_queue.add_for_packet(player, packet);
                     }
                     ServerToWorkerMessage::NotifyDisconnect(reason) => {
@@ -203,7 +222,7 @@ impl<'a> System<'a> for NetworkSystem {
         // TODO check that player hasn't timed out
         if tick_count.0 % TPS == 0 {
             for (netcomp, _) in (&netcomps, &pcomps).join() {
-                send_packet_to_player(netcomp, KeepAliveClientbound::new(0));
+                send_packet_immediately_to_player(netcomp, KeepAliveClientbound::new(0));
             }
         }
     }
@@ -211,12 +230,15 @@ impl<'a> System<'a> for NetworkSystem {
 
 /// Sends a packet to all players on the server, excluding
 /// `neq`, if it exists.
-pub fn send_packet_to_all_players<P: Packet + Clone + 'static>(
+pub fn send_packet_to_all_players<P: Packet + 'static>(
     net_comps: &ReadStorage<NetworkComponent>,
     entities: &Entities,
     packet: P,
     neq: Option<Entity>,
 ) {
+    // Serialize the packet once and share the data between players.
+    let packet = EncodedPacket::new(Box::new(packet));
+
     for (entity, net) in (entities, net_comps).join() {
         if let Some(e) = neq.as_ref() {
             if *e == entity {
@@ -235,11 +257,47 @@ pub fn send_packet_to_player<P: Packet + 'static>(comp: &NetworkComponent, packe
 
 /// Sends a packet to the given player.
 pub fn send_packet_boxed_to_player(comp: &NetworkComponent, packet: Box<dyn Packet>) {
+    METRICS.packets_sent.fetch_add(1, Ordering::Relaxed);
     let _ = comp
         .sender
         .unbounded_send(ServerToWorkerMessage::SendPacket(packet));
 }
 
+/// Sends a packet to the given player, serializing it
+/// on the Rayon thread pool. This should be used for large
+/// packets, such as chunk data, which would otherwise take
+/// a considerable amount of time to serialize.
+///
+/// The packet is still sent in order with the other
+/// packets sent to the player.
+pub fn send_packet_serialized_off_thread<P: Packet + 'static>(comp: &NetworkComponent, packet: P) {
+    METRICS.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+    let (sender, receiver) = oneshot::channel();
+    rayon::spawn(move || {
+        let _ = sender.send(EncodedPacket::new(Box::new(packet)));
+    });
+
+    let _ = comp
+        .sender
+        .unbounded_send(ServerToWorkerMessage::SendPendingPacket(receiver));
+}
+
+/// Sends a packet to the given player without waiting
+/// for the end of the tick. Packets previously sent to
+/// the player are sent as well.
+pub fn send_packet_immediately_to_player<P: Packet + 'static>(comp: &NetworkComponent, packet: P) {
+    send_packet_to_player(comp, packet);
+    comp.flush();
+}
+
+/// Sends all packets sent during this tick to the players.
+pub fn flush_packets(world: &World) {
+    for netcomp in world.read_component::<NetworkComponent>().join() {
+        netcomp.flush();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -385,6 +443,40 @@ mod tests {
         t::assert_packet_received(&player2, PacketType::KeepAliveClientbound);
     }
 
+    #[test]
+    fn test_flush_packets() {
+        let (mut w, _) = t::init_world();
+
+        let player = t::add_player(&mut w);
+        flush_packets(&w);
+
+        let mut flushed = false;
+        while let Ok(Some(msg)) = player.network_receiver.borrow_mut().try_next() {
+            if let ServerToWorkerMessage::Flush = msg {
+                flushed = true;
+            }
+        }
+        assert!(flushed);
+    }
+
+    #[test]
+    fn test_send_packet_serialized_off_thread() {
+        let (mut w, _) = t::init_world();
+
+        let player = t::add_player(&mut w);
+        {
+            let networks = w.read_component::<NetworkComponent>();
+            let network = networks.get(player.entity).unwrap();
+            send_packet_serialized_off_thread(network, LoginStart::new("test".to_string()));
+            send_packet_to_player(network, KeepAliveClientbound::new(0));
+        }
+
+        let packets = t::received_packets(&player, None);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].ty(), PacketType::LoginStart);
+        assert_eq!(packets[1].ty(), PacketType::KeepAliveClientbound);
+    }
+
     #[test]
     fn test_send_packet_to_all_players() {
         let (mut w, _) = t::init_world();
@@ -3,6 +3,7 @@
 
 use specs::{Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage};
 
+use crate::crash;
 use crate::entity::{EntityDestroyEvent, PositionComponent, VelocityComponent};
 use crate::physics::{
     block_impacted_by_ray, blocks_intersecting_bbox, AABBExt, PhysicsComponent, Side,
@@ -62,6 +63,7 @@ impl<'a> System<'a> for EntityPhysicsSystem {
         )
             .join()
         {
+            let _context = crash::context(crash::Context::Entity(entity));
             let mut velocity = *restrict_velocity.get_unchecked();
 
             let mut pending_position = position.current + velocity.0;
@@ -247,28 +247,20 @@ where
     assert!(radius.y >= 0.0);
     assert!(radius.z >= 0.0);
 
-    let mut result = smallvec![];
-
-    for chunk in chunks_within_distance(pos, radius) {
-        let entities = chunk_entities.entities_in_chunk(chunk);
-        entities
-            .iter()
-            .copied()
-            .filter(|e| {
-                let epos = positions.get(*e);
-                if let Some(epos) = epos {
-                    let epos = epos.current;
-                    (epos.x - pos.x).abs() <= radius.x
-                        && (epos.y - pos.y).abs() <= radius.y
-                        && (epos.z - pos.z).abs() <= radius.z
-                } else {
-                    false
-                }
-            })
-            .for_each(|e| result.push(e));
-    }
-
-    result
+    chunk_entities
+        .entities_in_chunks(chunks_within_distance(pos, radius))
+        .filter(|e| {
+            let epos = positions.get(*e);
+            if let Some(epos) = epos {
+                let epos = epos.current;
+                (epos.x - pos.x).abs() <= radius.x
+                    && (epos.y - pos.y).abs() <= radius.y
+                    && (epos.z - pos.z).abs() <= radius.z
+            } else {
+                false
+            }
+        })
+        .collect()
 }
 
 /// The offsets which need to be applied to a position
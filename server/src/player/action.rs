@@ -0,0 +1,95 @@
+use crate::network::PacketQueue;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{EntityAction, EntityActionType, UseItem};
+use feather_core::network::packet::PacketType;
+use shrev::EventChannel;
+use specs::{Entity, Read, System, Write};
+
+/// Event which is triggered when a player sends
+/// an Entity Action packet, e.g. to leave a bed
+/// or to start gliding.
+#[derive(Debug, Clone)]
+pub struct PlayerActionEvent {
+    pub player: Entity,
+    pub action: EntityActionType,
+}
+
+/// Event which is triggered when a player uses
+/// the item in one of their hands without
+/// targeting a block.
+#[derive(Debug, Clone)]
+pub struct PlayerUseItemEvent {
+    pub player: Entity,
+    /// The hand used, as sent in the Use Item packet:
+    /// 0 for the main hand and 1 for the off hand.
+    pub hand: i32,
+}
+
+/// System for handling Entity Action packets
+/// and then triggering a `PlayerActionEvent`.
+pub struct PlayerActionSystem;
+
+impl<'a> System<'a> for PlayerActionSystem {
+    type SystemData = (
+        Write<'a, EventChannel<PlayerActionEvent>>,
+        Read<'a, PacketQueue>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut events, packet_queue) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::EntityAction) {
+            let packet = cast_packet::<EntityAction>(&*packet);
+
+            events.single_write(PlayerActionEvent {
+                player,
+                action: packet.action_id,
+            });
+        }
+    }
+}
+
+/// System for handling Use Item packets
+/// and then triggering a `PlayerUseItemEvent`.
+pub struct PlayerUseItemSystem;
+
+impl<'a> System<'a> for PlayerUseItemSystem {
+    type SystemData = (
+        Write<'a, EventChannel<PlayerUseItemEvent>>,
+        Read<'a, PacketQueue>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut events, packet_queue) = data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::UseItem) {
+            let packet = cast_packet::<UseItem>(&*packet);
+
+            events.single_write(PlayerUseItemEvent {
+                player,
+                hand: packet.hand,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+
+    #[test]
+    fn test_use_item_event() {
+        let (mut w, mut d) = t::builder().with(PlayerUseItemSystem, "").build();
+        let player = t::add_player(&mut w);
+        let mut reader = t::reader(&w);
+
+        t::receive_packet(&player, &w, UseItem::new(1));
+        d.dispatch(&w);
+
+        let events = t::triggered_events::<PlayerUseItemEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].player, player.entity);
+        assert_eq!(events[0].hand, 1);
+    }
+}
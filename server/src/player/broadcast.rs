@@ -1,15 +1,17 @@
-use crate::config::Config;
 use crate::entity::{ChunkEntities, NamedComponent, PlayerComponent, PositionComponent};
 use crate::joinhandler::PlayerJoinEvent;
+use crate::lang::{Locale, Message};
 use crate::lazy::LazyUpdateExt;
 use crate::network::{send_packet_to_all_players, send_packet_to_player, NetworkComponent};
 use crate::player::chat::ChatBroadcastEvent;
+use crate::view_distance::ViewDistance;
 use feather_core::network::packet::implementation::{PlayerInfo, PlayerInfoAction};
 use feather_core::Gamemode;
 use shrev::EventChannel;
-use specs::{Entities, Entity, Join, Read, ReadStorage, ReaderId, System, World, Write};
+use specs::{
+    Entities, Entity, Join, Read, ReadExpect, ReadStorage, ReaderId, System, World, Write,
+};
 use specs::{LazyUpdate, SystemData};
-use std::sync::Arc;
 use uuid::Uuid;
 
 /// System for broadcasting when a player joins
@@ -34,7 +36,8 @@ impl<'a> System<'a> for JoinBroadcastSystem {
         Write<'a, EventChannel<ChatBroadcastEvent>>,
         Read<'a, ChunkEntities>,
         Read<'a, LazyUpdate>,
-        Read<'a, Arc<Config>>,
+        ReadExpect<'a, ViewDistance>,
+        Read<'a, Locale>,
         Entities<'a>,
     );
 
@@ -48,7 +51,8 @@ impl<'a> System<'a> for JoinBroadcastSystem {
             mut chat,
             chunk_entities,
             lazy,
-            config,
+            view_distance,
+            locale,
             entities,
         ) = data;
 
@@ -76,25 +80,21 @@ impl<'a> System<'a> for JoinBroadcastSystem {
             }
 
             // Send entities within view distance to new player
-            for entity in chunk_entities.entites_within_view_distance(
-                position.current.chunk_pos(),
-                config.server.view_distance,
-            ) {
+            for entity in chunk_entities
+                .entites_within_view_distance(position.current.chunk_pos(), view_distance.0)
+            {
                 if entity != event.player {
                     lazy.send_entity_to_player(event.player, entity);
                 }
             }
 
             // Broadcast join message in chat
-            let message = json!({
-                "translate": "multiplayer.player.joined",
-                "color": "yellow",
-                "with": [
-                    {"text": named.display_name},
-                ],
-            })
-            .to_string();
-            chat.single_write(ChatBroadcastEvent { message });
+            let message = Message::translate("multiplayer.player.joined").with(&named.display_name);
+            let mut message = locale.component(&message);
+            message["color"] = json!("yellow");
+            chat.single_write(ChatBroadcastEvent {
+                message: message.to_string(),
+            });
         }
     }
 
@@ -159,10 +159,11 @@ impl<'a> System<'a> for DisconnectBroadcastSystem {
         ReadStorage<'a, NetworkComponent>,
         Write<'a, EventChannel<ChatBroadcastEvent>>,
         Read<'a, EventChannel<PlayerDisconnectEvent>>,
+        Read<'a, Locale>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (nameds, networks, mut chat, disconnect_events) = data;
+        let (nameds, networks, mut chat, disconnect_events, locale) = data;
 
         for event in disconnect_events.read(&mut self.reader.as_mut().unwrap()) {
             // Broadcast disconnect.
@@ -178,15 +179,12 @@ impl<'a> System<'a> for DisconnectBroadcastSystem {
             let named = nameds.get(event.player).unwrap();
 
             // Broadcast chat message.
-            let message = json!({
-                "translate": "multiplayer.player.left",
-                "color": "yellow",
-                "with": [
-                    {"text": named.display_name},
-                ],
-            })
-            .to_string();
-            let event = ChatBroadcastEvent { message };
+            let message = Message::translate("multiplayer.player.left").with(&named.display_name);
+            let mut message = locale.component(&message);
+            message["color"] = json!("yellow");
+            let event = ChatBroadcastEvent {
+                message: message.to_string(),
+            };
             chat.single_write(event);
         }
     }
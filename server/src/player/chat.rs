@@ -8,7 +8,9 @@ use feather_core::network::packet::implementation::{
 };
 use feather_core::network::packet::PacketType;
 
+use crate::commands::CommandEvent;
 use crate::entity::NamedComponent;
+use crate::event::{ChatEvent, EventBus};
 use crate::network::{send_packet_to_all_players, NetworkComponent, PacketQueue};
 
 /// Event which is triggered when a new chat message is to be broadcasted to the whole server.
@@ -19,17 +21,23 @@ pub struct ChatBroadcastEvent {
 
 /// System for handling Chat Message Serverbound packets
 /// and then triggering a `ChatBroadcastEvent`.
+///
+/// Messages starting with a slash trigger a `CommandEvent` instead.
+/// Other messages are passed through a `ChatEvent` on the `EventBus`
+/// before being broadcasted.
 pub struct PlayerChatSystem;
 
 impl<'a> System<'a> for PlayerChatSystem {
     type SystemData = (
         Write<'a, EventChannel<ChatBroadcastEvent>>,
+        Write<'a, EventChannel<CommandEvent>>,
         ReadStorage<'a, NamedComponent>,
         Read<'a, PacketQueue>,
+        Read<'a, EventBus>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut events, nameds, packet_queue) = data;
+        let (mut events, mut command_events, nameds, packet_queue, bus) = data;
 
         // Handle Chat Message Serverbound packets.
         let packets = packet_queue.for_packet(PacketType::ChatMessageServerbound);
@@ -39,6 +47,22 @@ impl<'a> System<'a> for PlayerChatSystem {
             let message = packet.message.clone();
             let player_name = &nameds.get(player).unwrap().display_name;
 
+            if let Some(command) = CommandEvent::parse(player, &message) {
+                info!("{} issued server command: {}", player_name, message);
+                command_events.single_write(command);
+                continue;
+            }
+
+            let mut chat_event = ChatEvent {
+                player,
+                message,
+                cancelled: false,
+            };
+            if !bus.emit(&mut chat_event) {
+                continue;
+            }
+            let message = chat_event.message;
+
             // TODO: could use a more robust chat-component library.
             let message_json = json!({
                 "translate": "chat.type.text",
@@ -104,6 +128,7 @@ impl<'a> System<'a> for ChatBroadcastSystem {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event::{Event, EventPriority};
     use crate::testframework as t;
     use feather_core::network::packet::implementation::ChatMessageServerbound;
     use specs::WorldExt;
@@ -130,6 +155,53 @@ mod tests {
         assert_eq!(events.len(), 1);
     }
 
+    #[test]
+    fn test_chat_command() {
+        let (mut w, mut d) = t::init_world();
+
+        let player = t::add_player(&mut w);
+
+        let packet = ChatMessageServerbound {
+            message: String::from("/test arg"),
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut chat_reader = t::reader::<ChatBroadcastEvent>(&w);
+        let mut command_reader = t::reader::<CommandEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(t::triggered_events(&w, &mut chat_reader).is_empty());
+        let commands = t::triggered_events(&w, &mut command_reader);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].name, "test");
+        assert_eq!(commands[0].args, vec!["arg"]);
+    }
+
+    #[test]
+    fn test_chat_event_cancelled() {
+        let (mut w, mut d) = t::init_world();
+        w.fetch_mut::<EventBus>()
+            .register(EventPriority::Normal, |event: &mut ChatEvent| {
+                event.set_cancelled(true)
+            });
+
+        let player = t::add_player(&mut w);
+
+        let packet = ChatMessageServerbound {
+            message: String::from("test"),
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut event_reader = t::reader::<ChatBroadcastEvent>(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(t::triggered_events(&w, &mut event_reader).is_empty());
+    }
+
     #[test]
     fn test_chat_broadcast_system() {
         let (mut w, mut d) = t::init_world();
@@ -3,8 +3,16 @@
 //! The packet's name is rather misleading, as it is also sent
 //! for completely unrelated actions, including eating, shooting bows,
 //! swapping items out the the offhand, and dropping items.
-
-use specs::{Entity, LazyUpdate, Read, ReadStorage, ReaderId, System, World, Write, WriteStorage};
+//!
+//! Players who are not in creative mode send a packet when they
+//! start breaking a block and another one when they are done. The
+//! block is only broken if enough ticks have passed in between for
+//! the player to break it with their tool; see the `tool` module.
+
+use specs::{
+    Component, Entity, HashMapStorage, LazyUpdate, Read, ReadExpect, ReadStorage, ReaderId, System,
+    World, Write, WriteStorage,
+};
 
 use feather_core::network::cast_packet;
 use feather_core::network::packet::implementation::{
@@ -12,16 +20,24 @@ use feather_core::network::packet::implementation::{
 };
 use feather_core::network::packet::PacketType;
 use feather_core::world::block::{Block, BlockExt};
-use feather_core::world::ChunkMap;
+use feather_core::world::{BlockPosition, ChunkMap};
 use feather_core::{Gamemode, Item, Position};
 
 use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::dimension::{DimensionComponent, Dimensions};
 use crate::disconnect_player;
+use crate::effect::EffectsComponent;
 use crate::entity::{PlayerComponent, PositionComponent, ShootArrowEvent};
-use crate::network::PacketQueue;
+use crate::event::{BlockBreakEvent, EventBus};
+use crate::lang::Message;
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
 use crate::player::{InventoryComponent, InventoryUpdateEvent};
+use crate::tool;
 use crate::util::Util;
-use feather_core::inventory::{ItemStack, SlotIndex, SLOT_HOTBAR_OFFSET, SLOT_OFFHAND};
+use crate::TickCount;
+use feather_core::inventory::{
+    ItemStack, SlotIndex, SLOT_ARMOR_HEAD, SLOT_HOTBAR_OFFSET, SLOT_OFFHAND,
+};
 use shrev::EventChannel;
 use specs::SystemData;
 
@@ -40,6 +56,28 @@ pub struct PlayerItemDropEvent {
     pub player: Entity,
 }
 
+/// The fraction of a block which must have been broken when a
+/// player finishes breaking it. As in vanilla, this is less than
+/// the whole block to allow for latency.
+const DIG_TOLERANCE: f32 = 0.7;
+
+/// The height of a player's eyes above their feet.
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Component for players who are breaking a block
+/// and are not in creative mode.
+#[derive(Debug, Clone)]
+pub struct DiggingComponent {
+    /// The block being broken.
+    pub pos: BlockPosition,
+    /// The tick at which the player started breaking it.
+    pub start_tick: u64,
+}
+
+impl Component for DiggingComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
 /// System responsible for polling for PlayerDigging
 /// packets and writing the corresponding events.
 pub struct PlayerDiggingSystem;
@@ -49,6 +87,7 @@ impl<'a> System<'a> for PlayerDiggingSystem {
         WriteStorage<'a, InventoryComponent>,
         ReadStorage<'a, PlayerComponent>, // For gamemodes
         ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NetworkComponent>,
         Write<'a, EventChannel<BlockUpdateEvent>>,
         Write<'a, EventChannel<PlayerItemDropEvent>>,
         Write<'a, EventChannel<InventoryUpdateEvent>>,
@@ -56,6 +95,12 @@ impl<'a> System<'a> for PlayerDiggingSystem {
         Write<'a, ChunkMap>,
         Read<'a, PacketQueue>,
         Read<'a, LazyUpdate>,
+        Read<'a, EventBus>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadExpect<'a, Dimensions>,
+        WriteStorage<'a, DiggingComponent>,
+        ReadStorage<'a, EffectsComponent>,
+        Read<'a, TickCount>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -65,6 +110,7 @@ impl<'a> System<'a> for PlayerDiggingSystem {
             mut inventories,
             players,
             positions,
+            networks,
             mut block_breaks,
             mut item_drops,
             mut inventory_updates,
@@ -72,23 +118,54 @@ impl<'a> System<'a> for PlayerDiggingSystem {
             mut chunk_map,
             packet_queue,
             lazy,
+            bus,
+            dimensions,
+            dimension_worlds,
+            mut diggings,
+            effects,
+            tick,
         ) = data;
 
         let packets = packet_queue.for_packet(PacketType::PlayerDigging);
 
         for (player, packet) in packets {
             let packet = cast_packet::<PlayerDigging>(&*packet);
+            let world = dimensions
+                .get(player)
+                .and_then(|dimension| dimension_worlds.world(dimension.0));
 
             match packet.status {
-                StartedDigging | FinishedDigging | CancelledDigging => handle_digging(
-                    packet,
-                    players.get(player).unwrap(),
-                    inventories.get(player).unwrap().item_in_main_hand(),
-                    player,
-                    &mut block_breaks,
-                    &mut chunk_map,
-                    &lazy,
-                ),
+                // Blocks outside of the primary dimension can't be modified yet.
+                StartedDigging | FinishedDigging | CancelledDigging if world.is_some() => {
+                    if let (Some(world), Some(network)) = (world, networks.get(player)) {
+                        world.revert_block(packet.location, network);
+                    }
+                }
+                StartedDigging | FinishedDigging | CancelledDigging => {
+                    let inventory = inventories.get(player).unwrap();
+                    let progress = dig_progress(
+                        packet.location,
+                        inventory,
+                        effects.get(player),
+                        positions.get(player).unwrap().current,
+                        &chunk_map,
+                    );
+                    let gamemode = players.get(player).unwrap().gamemode;
+                    let broken = gamemode != Gamemode::Creative
+                        && track_digging(packet, player, progress, &mut diggings, tick.0);
+                    handle_digging(
+                        packet,
+                        players.get(player).unwrap(),
+                        inventory.item_in_main_hand(),
+                        broken,
+                        player,
+                        networks.get(player),
+                        &mut block_breaks,
+                        &mut chunk_map,
+                        &lazy,
+                        &bus,
+                    )
+                }
                 DropItem | DropItemStack => handle_drop_item_stack(
                     packet,
                     player,
@@ -105,29 +182,122 @@ impl<'a> System<'a> for PlayerDiggingSystem {
                     positions.get(player).unwrap().current,
                     &mut shoot_arrow_events,
                 ),
+                SwapItemInHand => handle_swap_item_in_hand(
+                    player,
+                    &mut inventory_updates,
+                    inventories.get_mut(player).unwrap(),
+                ),
                 status => warn!("Unhandled Player Digging status {:?}", status),
             }
         }
     }
 }
 
+/// Returns the fraction of the block at the given position which
+/// a player breaks each tick.
+fn dig_progress(
+    pos: BlockPosition,
+    inventory: &InventoryComponent,
+    effects: Option<&EffectsComponent>,
+    position: Position,
+    chunk_map: &ChunkMap,
+) -> f32 {
+    let block = match chunk_map.block_at(pos) {
+        Some(block) => block,
+        None => return 0.0,
+    };
+
+    let mut eyes = position;
+    eyes.y += EYE_HEIGHT;
+    let aqua_affinity = inventory
+        .item_at(SLOT_ARMOR_HEAD)
+        .and_then(|helmet| helmet.enchantment_level("minecraft:aqua_affinity"))
+        .map_or(false, |level| level > 0);
+    let underwater = match chunk_map.block_at(eyes.block_pos()) {
+        Some(Block::Water(_)) => !aqua_affinity,
+        _ => false,
+    };
+
+    tool::break_progress(
+        block,
+        inventory.item_in_main_hand(),
+        effects,
+        position.on_ground,
+        underwater,
+    )
+}
+
+/// Keeps track of the block a player is breaking, and returns
+/// whether the player has broken it when not in creative mode.
+fn track_digging(
+    packet: &PlayerDigging,
+    entity: Entity,
+    progress: f32,
+    diggings: &mut WriteStorage<DiggingComponent>,
+    tick: u64,
+) -> bool {
+    let digging = diggings.remove(entity);
+    match packet.status {
+        // Some blocks are broken as soon as players start breaking them.
+        PlayerDiggingStatus::StartedDigging if progress >= 1.0 => true,
+        PlayerDiggingStatus::StartedDigging => {
+            diggings
+                .insert(
+                    entity,
+                    DiggingComponent {
+                        pos: packet.location,
+                        start_tick: tick,
+                    },
+                )
+                .unwrap();
+            false
+        }
+        PlayerDiggingStatus::FinishedDigging => match digging {
+            Some(digging) if digging.pos == packet.location => {
+                let ticks = tick.saturating_sub(digging.start_tick) + 1;
+                progress * ticks as f32 >= DIG_TOLERANCE
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Sends the block at a position to a player,
+/// reverting their prediction that they broke it.
+fn revert_block(network: Option<&NetworkComponent>, pos: BlockPosition, block: Block) {
+    if let Some(network) = network {
+        send_packet_to_player(
+            network,
+            BlockChange::new(pos, i32::from(block.native_state_id())),
+        );
+    }
+}
+
 fn handle_digging(
     packet: &PlayerDigging,
     player: &PlayerComponent,
     item_in_main_hand: Option<&ItemStack>,
+    broken: bool,
     entity: Entity,
+    network: Option<&NetworkComponent>,
     events: &mut EventChannel<BlockUpdateEvent>,
     chunk_map: &mut ChunkMap,
     lazy: &LazyUpdate,
+    bus: &EventBus,
 ) {
     // Return early if needed
     match packet.status {
-        PlayerDiggingStatus::StartedDigging => {
-            if player.gamemode != Gamemode::Creative {
-                return;
+        PlayerDiggingStatus::CancelledDigging => return,
+        _ if player.gamemode == Gamemode::Creative => (),
+        PlayerDiggingStatus::FinishedDigging if !broken => {
+            // The player broke the block too fast.
+            if let Some(block) = chunk_map.block_at(packet.location) {
+                revert_block(network, packet.location, block);
             }
+            return;
         }
-        PlayerDiggingStatus::CancelledDigging => return,
+        _ if !broken => return,
         _ => (),
     }
 
@@ -147,10 +317,23 @@ fn handle_digging(
 
     let old = chunk_map.block_at(packet.location);
 
+    if let Some(block) = old {
+        let mut break_event = BlockBreakEvent {
+            player: entity,
+            pos: packet.location,
+            block,
+            cancelled: false,
+        };
+        if !bus.emit(&mut break_event) {
+            revert_block(network, packet.location, block);
+            return;
+        }
+    }
+
     if chunk_map.set_block_at(packet.location, Block::Air).is_err() {
         disconnect_player(
             entity,
-            "Attempted to break block in unloaded chunk".to_string(),
+            Message::translate("feather.disconnect.break_in_unloaded_chunk"),
             lazy,
         );
         return;
@@ -198,7 +381,7 @@ fn handle_drop_item_stack(
                 inventory.clear_item_at(slot);
                 1
             } else {
-                inventory.set_item_at(slot, ItemStack::new(stack.ty, stack.amount - 1));
+                inventory.set_item_at(slot, stack.with_amount(stack.amount - 1));
                 1
             }
         }
@@ -218,13 +401,39 @@ fn handle_drop_item_stack(
     if amnt != 0 {
         let item_drop = PlayerItemDropEvent {
             slot: Some(slot),
-            stack: ItemStack::new(stack.ty, amnt),
+            stack: stack.with_amount(amnt),
             player: entity,
         };
         item_drops.single_write(item_drop);
     }
 }
 
+/// Swaps the items in the player's main hand and off-hand.
+fn handle_swap_item_in_hand(
+    entity: Entity,
+    inventory_updates: &mut EventChannel<InventoryUpdateEvent>,
+    inventory: &mut InventoryComponent,
+) {
+    let slot = inventory.held_item + SLOT_HOTBAR_OFFSET;
+
+    let main_hand = inventory.clear_item_at(slot);
+    let off_hand = inventory.clear_item_at(SLOT_OFFHAND);
+    if main_hand.is_none() && off_hand.is_none() {
+        return;
+    }
+    if let Some(stack) = main_hand {
+        inventory.set_item_at(SLOT_OFFHAND, stack);
+    }
+    if let Some(stack) = off_hand {
+        inventory.set_item_at(slot, stack);
+    }
+
+    inventory_updates.single_write(InventoryUpdateEvent {
+        slots: smallvec![slot, SLOT_OFFHAND],
+        player: entity,
+    });
+}
+
 /// Handles food consumption and shooting arrows.
 fn handle_consume_item(
     packet: &PlayerDigging,
@@ -376,7 +585,7 @@ mod tests {
     use crate::testframework as t;
     use feather_core::item::Item;
     use feather_core::world::chunk::Chunk;
-    use feather_core::world::{BlockPosition, ChunkPosition};
+    use feather_core::world::ChunkPosition;
     use specs::WorldExt;
 
     #[test]
@@ -490,6 +699,13 @@ mod tests {
 
         w.fetch_mut::<ChunkMap>().set_chunk_at(cpos, chunk);
 
+        let packet = PlayerDigging::new(PlayerDiggingStatus::StartedDigging, bpos, 0);
+        t::receive_packet(&player, &w, packet);
+        d.dispatch(&w);
+        w.maintain();
+
+        // Breaking stone by hand takes 150 ticks.
+        w.fetch_mut::<TickCount>().0 += 150;
         let packet = PlayerDigging::new(PlayerDiggingStatus::FinishedDigging, bpos, 0);
         t::receive_packet(&player, &w, packet);
 
@@ -513,6 +729,66 @@ mod tests {
         assert_eq!(first.pos, bpos);
     }
 
+    #[test]
+    fn test_digging_too_fast() {
+        let (mut w, mut d) = t::init_world();
+
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::DiamondPickaxe, 1));
+
+        let bpos = BlockPosition::new(0, 0, 0);
+        let cpos = bpos.chunk_pos();
+        let mut chunk = Chunk::new(cpos);
+        chunk.set_block_at(0, 0, 0, Block::Stone);
+        chunk.set_block_at(1, 0, 0, Block::Torch);
+        w.fetch_mut::<ChunkMap>().set_chunk_at(cpos, chunk);
+
+        let dig = |w: &World, status, pos| {
+            t::receive_packet(&player, w, PlayerDigging::new(status, pos, 0));
+        };
+
+        // Finishing without having started is refused.
+        dig(&w, PlayerDiggingStatus::FinishedDigging, bpos);
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_packet_received(&player, PacketType::BlockChange);
+        assert_eq!(w.fetch::<ChunkMap>().block_at(bpos), Some(Block::Stone));
+
+        // A diamond pickaxe breaks 18% of a stone block each
+        // tick, and 70% of it must have been broken.
+        dig(&w, PlayerDiggingStatus::StartedDigging, bpos);
+        d.dispatch(&w);
+        w.maintain();
+        w.fetch_mut::<TickCount>().0 += 2;
+        dig(&w, PlayerDiggingStatus::FinishedDigging, bpos);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.fetch::<ChunkMap>().block_at(bpos), Some(Block::Stone));
+
+        dig(&w, PlayerDiggingStatus::StartedDigging, bpos);
+        d.dispatch(&w);
+        w.maintain();
+        w.fetch_mut::<TickCount>().0 += 3;
+        dig(&w, PlayerDiggingStatus::FinishedDigging, bpos);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.fetch::<ChunkMap>().block_at(bpos), Some(Block::Air));
+
+        // Torches break as soon as players start breaking them.
+        let torch = BlockPosition::new(1, 0, 0);
+        dig(&w, PlayerDiggingStatus::StartedDigging, torch);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.fetch::<ChunkMap>().block_at(torch), Some(Block::Air));
+    }
+
     #[test]
     fn test_block_break_in_unloaded_chunk() {
         let (mut w, mut d) = t::init_world();
@@ -585,6 +861,45 @@ mod tests {
         ); // 1 was removed
     }
 
+    #[test]
+    fn test_swap_item_in_hand() {
+        let (mut w, mut d) = t::init_world();
+
+        let player = t::add_player(&mut w);
+
+        let slot = SLOT_HOTBAR_OFFSET + 2;
+        {
+            let mut invs = w.write_component::<InventoryComponent>();
+            let inv = invs.get_mut(player.entity).unwrap();
+            inv.held_item = 2;
+            inv.set_item_at(slot, ItemStack::new(Item::Shield, 1));
+        }
+
+        let mut update_reader = t::reader(&w);
+
+        let packet = PlayerDigging::new(
+            PlayerDiggingStatus::SwapItemInHand,
+            BlockPosition::default(),
+            0,
+        );
+        t::receive_packet(&player, &w, packet);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let update_events = t::triggered_events::<InventoryUpdateEvent>(&w, &mut update_reader);
+        assert_eq!(update_events.len(), 1);
+        assert_eq!(update_events[0].slots.as_slice(), &[slot, SLOT_OFFHAND]);
+
+        let invs = w.read_component::<InventoryComponent>();
+        let inv = invs.get(player.entity).unwrap();
+        assert_eq!(inv.item_at(slot), None);
+        assert_eq!(
+            inv.item_at(SLOT_OFFHAND),
+            Some(&ItemStack::new(Item::Shield, 1))
+        );
+    }
+
     #[test]
     fn test_drop_item_no_stack() {
         // This should be a no-op.
@@ -716,6 +1031,7 @@ mod tests {
             ItemStack {
                 ty: Item::Arrow,
                 amount: 1,
+                tag: None,
             },
         );
         inv.set_item_at(
@@ -723,6 +1039,7 @@ mod tests {
             ItemStack {
                 ty: Item::Arrow,
                 amount: 1,
+                tag: None,
             },
         );
         inv.set_item_at(
@@ -730,6 +1047,7 @@ mod tests {
             ItemStack {
                 ty: Item::Arrow,
                 amount: 1,
+                tag: None,
             },
         );
 
@@ -1,10 +1,13 @@
+use crate::combat::{HealthComponent, PLAYER_MAX_HEALTH};
 use crate::entity::{
     degrees_to_stops, LastKnownPositionComponent, PacketCreatorComponent, PlayerComponent,
     VelocityComponent,
 };
 use crate::entity::{Metadata, NamedComponent, PositionComponent};
 use crate::network::PlayerPreJoinEvent;
-use crate::player::{ChunkPendingComponent, InventoryComponent, LoadedChunksComponent};
+use crate::player::{
+    ChunkPendingComponent, InventoryComponent, LoadedChunksComponent, PlayerStatsComponent,
+};
 use crate::prelude::*;
 use feather_core::level::LevelData;
 use feather_core::packet::SpawnPlayer;
@@ -36,6 +39,8 @@ impl<'a> System<'a> for PlayerInitSystem {
         WriteStorage<'a, Metadata>,
         WriteStorage<'a, LastKnownPositionComponent>,
         WriteStorage<'a, PacketCreatorComponent>,
+        WriteStorage<'a, PlayerStatsComponent>,
+        WriteStorage<'a, HealthComponent>,
         Read<'a, LevelData>,
         Read<'a, Arc<Config>>,
     );
@@ -53,6 +58,8 @@ impl<'a> System<'a> for PlayerInitSystem {
             mut metadata,
             mut last_positions,
             mut packet_creators,
+            mut stats_comps,
+            mut health_comps,
             level,
             config,
         ) = data;
@@ -66,18 +73,20 @@ impl<'a> System<'a> for PlayerInitSystem {
             let world_dir = Path::new(&config.world.name);
 
             debug!("Loading player data for UUID {}", uuid);
-            let (gamemode, pos, velocity, inventory_slots) =
+            let (gamemode, pos, velocity, stats, inventory_slots) =
                 match feather_core::player_data::load_player_data(world_dir, uuid) {
                     Ok(data) => (
                         Gamemode::from_id(data.gamemode as u8),
                         data.entity.read_position(),
                         data.entity.read_velocity(),
+                        PlayerStatsComponent::from_data(&data),
                         data.inventory,
                     ),
                     Err(_) => (
                         Gamemode::from_string(default_gamemode.as_str()),
                         None, // Invalid position will default to world spawn
                         None,
+                        PlayerStatsComponent::default(),
                         vec![], // Empty inventory
                     ),
                 };
@@ -131,10 +140,16 @@ impl<'a> System<'a> for PlayerInitSystem {
                 .insert(event.player, inventory_comp)
                 .unwrap();
 
+            let mut health = HealthComponent::new(PLAYER_MAX_HEALTH);
+            health.health = stats.health;
+            health_comps.insert(event.player, health).unwrap();
+            stats_comps.insert(event.player, stats).unwrap();
+
             let last_position = LastKnownPositionComponent::default();
             last_positions.insert(event.player, last_position).unwrap();
 
-            let meta = Metadata::Player(crate::entity::metadata::Player::default());
+            let mut meta = Metadata::Player(crate::entity::metadata::Player::default());
+            meta.set_health(stats.health);
             metadata.insert(event.player, meta).unwrap();
 
             let packet_creator = PacketCreatorComponent(&create_packet);
@@ -1,5 +1,6 @@
 use crate::disconnect_player;
 use crate::entity::{EntitySendEvent, PlayerComponent};
+use crate::lang::Message;
 use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
 use crate::player::digging::PlayerItemDropEvent;
 use crate::util::Util;
@@ -12,7 +13,7 @@ use feather_core::network::packet::implementation::{
     CreativeInventoryAction, EntityEquipment, HeldItemChangeServerbound, SetSlot,
 };
 use feather_core::network::packet::PacketType;
-use feather_core::{Gamemode, ItemStack};
+use feather_core::{Gamemode, Item, ItemStack};
 use num_traits::ToPrimitive;
 use shrev::EventChannel;
 use smallvec::SmallVec;
@@ -28,6 +29,8 @@ pub struct InventoryComponent {
     /// The player's held item.
     /// This is stored as an index in the range 0..9.
     pub held_item: SlotIndex,
+    /// The item held by the cursor in the player's inventory window.
+    pub cursor: Option<ItemStack>,
 }
 
 impl InventoryComponent {
@@ -35,6 +38,7 @@ impl InventoryComponent {
         Self {
             inventory: Inventory::new(InventoryType::Player, 46),
             held_item: 0,
+            cursor: None,
         }
     }
 
@@ -49,6 +53,15 @@ impl InventoryComponent {
         self.inventory
             .set_item_at(SLOT_HOTBAR_OFFSET + self.held_item, item);
     }
+
+    /// Returns the slot of the given hand, as
+    /// sent in Use Item packets.
+    pub fn hand_slot(&self, hand: i32) -> SlotIndex {
+        match hand {
+            0 => SLOT_HOTBAR_OFFSET + self.held_item,
+            _ => SLOT_OFFHAND,
+        }
+    }
 }
 
 impl Default for InventoryComponent {
@@ -112,6 +125,26 @@ impl Equipment {
     }
 }
 
+/// Returns the armor slot in which the given item can be worn.
+pub fn armor_slot(item: Item) -> Option<SlotIndex> {
+    let name = item.identifier();
+    if name.ends_with("_helmet")
+        || name.ends_with("_head")
+        || name.ends_with("_skull")
+        || item == Item::CarvedPumpkin
+    {
+        Some(SLOT_ARMOR_HEAD)
+    } else if name.ends_with("_chestplate") || item == Item::Elytra {
+        Some(SLOT_ARMOR_CHEST)
+    } else if name.ends_with("_leggings") {
+        Some(SLOT_ARMOR_LEGS)
+    } else if name.ends_with("_boots") {
+        Some(SLOT_ARMOR_FEET)
+    } else {
+        None
+    }
+}
+
 /// Event which is triggered when a player
 /// updates their inventory.
 ///
@@ -154,8 +187,7 @@ impl<'a> System<'a> for CreativeInventorySystem {
             if player_comp.gamemode != Gamemode::Creative {
                 disconnect_player(
                     player,
-                    "Attempted to use Creative Inventory Action while not in creative mode"
-                        .to_string(),
+                    Message::translate("feather.disconnect.creative_inventory"),
                     &lazy,
                 );
                 continue;
@@ -185,7 +217,11 @@ impl<'a> System<'a> for CreativeInventorySystem {
             }
 
             if packet.slot >= inventory.slot_count() as i16 || packet.slot < -1 {
-                disconnect_player(player, "Slot index out of bounds".to_string(), &lazy);
+                disconnect_player(
+                    player,
+                    Message::translate("feather.disconnect.invalid_slot"),
+                    &lazy,
+                );
                 continue;
             }
 
@@ -228,7 +264,11 @@ impl<'a> System<'a> for HeldItemChangeSystem {
             let packet = cast_packet::<HeldItemChangeServerbound>(&*packet);
 
             if packet.slot as usize >= HOTBAR_SIZE {
-                disconnect_player(player, "Hotbar index out of bounds".to_string(), &lazy);
+                disconnect_player(
+                    player,
+                    Message::translate("feather.disconnect.invalid_hotbar_slot"),
+                    &lazy,
+                );
                 continue;
             }
 
@@ -756,4 +796,14 @@ mod tests {
             Ok(Equipment::OffHand)
         );
     }
+
+    #[test]
+    fn test_armor_slot() {
+        assert_eq!(armor_slot(Item::IronHelmet), Some(SLOT_ARMOR_HEAD));
+        assert_eq!(armor_slot(Item::CarvedPumpkin), Some(SLOT_ARMOR_HEAD));
+        assert_eq!(armor_slot(Item::Elytra), Some(SLOT_ARMOR_CHEST));
+        assert_eq!(armor_slot(Item::LeatherLeggings), Some(SLOT_ARMOR_LEGS));
+        assert_eq!(armor_slot(Item::DiamondBoots), Some(SLOT_ARMOR_FEET));
+        assert_eq!(armor_slot(Item::Stone), None);
+    }
 }
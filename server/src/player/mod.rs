@@ -2,6 +2,8 @@
 //! relating to players, including player movement
 //! and inventory handling.
 
+/// Module for handling Entity Action and Use Item packets.
+mod action;
 /// Module for handling player animation broadcasting
 /// (e.g. when a player swings their arm).
 mod animation;
@@ -23,32 +25,41 @@ mod movement;
 mod placement;
 mod resource_pack;
 mod save;
+/// Module for the health, hunger and experience of players.
+mod stats;
 mod view;
 
 pub use broadcast::PlayerDisconnectEvent;
+pub use chat::ChatBroadcastEvent;
 pub use init::create_packet;
 
 pub use movement::{
-    send_chunk_to_player, ChunkCrossSystem, ChunkPendingComponent, LoadedChunksComponent,
+    chunks_within_view_distance, send_chunk_data, send_chunk_to_player,
+    send_dimension_chunk_to_player, ChunkCrossSystem, ChunkPendingComponent, LoadedChunksComponent,
 };
 
+pub use action::{PlayerActionEvent, PlayerUseItemEvent};
 pub use animation::PlayerAnimationEvent;
 
 pub use digging::PlayerItemDropEvent;
-pub use inventory::{InventoryComponent, InventoryUpdateEvent};
-pub use save::save_player_data;
+pub use inventory::{armor_slot, InventoryComponent, InventoryUpdateEvent};
+pub use save::{save_all_player_data, save_player_data};
+pub use stats::PlayerStatsComponent;
 
 use crate::player::inventory::SetSlotSystem;
 use crate::player::placement::BlockPlacementSystem;
 use crate::player::save::PlayerDataSaveSystem;
+use crate::player::stats::StatsSendSystem;
 use crate::player::view::ViewUpdateSystem;
 use crate::systems::{
     ANIMATION_BROADCAST, BLOCK_BREAK_BROADCAST, BLOCK_PLACEMENT, CHAT_BROADCAST, CHUNK_CROSS,
     CHUNK_SEND, CLIENT_CHUNK_UNLOAD, CREATIVE_INVENTORY, DISCONNECT_BROADCAST, EQUIPMENT_SEND,
-    HELD_ITEM_BROADCAST, HELD_ITEM_CHANGE, JOIN_BROADCAST, NETWORK, PLAYER_ANIMATION, PLAYER_CHAT,
-    PLAYER_DATA_SAVE, PLAYER_DIGGING, PLAYER_INIT, PLAYER_MOVEMENT, RESOURCE_PACK_SEND, SET_SLOT,
-    VIEW_UPDATE,
+    HELD_ITEM_BROADCAST, HELD_ITEM_CHANGE, JOIN_BROADCAST, NETWORK, PLAYER_ACTION,
+    PLAYER_ANIMATION, PLAYER_CHAT, PLAYER_DATA_SAVE, PLAYER_DIGGING, PLAYER_INIT, PLAYER_MOVEMENT,
+    PLAYER_USE_ITEM, RESOURCE_PACK_SEND, SET_SLOT, STATS_SEND, VIEW_UPDATE,
 };
+use crate::timings::DispatcherBuilderExt;
+use action::{PlayerActionSystem, PlayerUseItemSystem};
 use animation::{AnimationBroadcastSystem, PlayerAnimationSystem};
 use broadcast::{DisconnectBroadcastSystem, JoinBroadcastSystem};
 use chat::{ChatBroadcastSystem, PlayerChatSystem};
@@ -66,14 +77,16 @@ pub const PLAYER_EYE_HEIGHT: f64 = 1.62;
 pub const PLAYER_EYE_HEIGHT_WHILE_SNEAKING: f64 = 1.54;
 
 pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(PlayerDiggingSystem, PLAYER_DIGGING, &[NETWORK]);
-    dispatcher.add(PlayerAnimationSystem, PLAYER_ANIMATION, &[NETWORK]);
-    dispatcher.add(CreativeInventorySystem, CREATIVE_INVENTORY, &[NETWORK]);
-    dispatcher.add(HeldItemChangeSystem, HELD_ITEM_CHANGE, &[NETWORK]);
-    dispatcher.add(PlayerMovementSystem, PLAYER_MOVEMENT, &[NETWORK]);
-    dispatcher.add(PlayerChatSystem, PLAYER_CHAT, &[NETWORK]);
-    dispatcher.add(BlockPlacementSystem, BLOCK_PLACEMENT, &[NETWORK]);
-    dispatcher.add(
+    dispatcher.add_timed(PlayerDiggingSystem, PLAYER_DIGGING, &[NETWORK]);
+    dispatcher.add_timed(PlayerAnimationSystem, PLAYER_ANIMATION, &[NETWORK]);
+    dispatcher.add_timed(PlayerActionSystem, PLAYER_ACTION, &[NETWORK]);
+    dispatcher.add_timed(PlayerUseItemSystem, PLAYER_USE_ITEM, &[NETWORK]);
+    dispatcher.add_timed(CreativeInventorySystem, CREATIVE_INVENTORY, &[NETWORK]);
+    dispatcher.add_timed(HeldItemChangeSystem, HELD_ITEM_CHANGE, &[NETWORK]);
+    dispatcher.add_timed(PlayerMovementSystem, PLAYER_MOVEMENT, &[NETWORK]);
+    dispatcher.add_timed(PlayerChatSystem, PLAYER_CHAT, &[NETWORK]);
+    dispatcher.add_timed(BlockPlacementSystem, BLOCK_PLACEMENT, &[NETWORK]);
+    dispatcher.add_timed(
         PlayerDataSaveSystem::default(),
         PLAYER_DATA_SAVE,
         &[NETWORK],
@@ -81,33 +94,39 @@ pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
 }
 
 pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(ViewUpdateSystem::default(), VIEW_UPDATE, &[]);
-    dispatcher.add(ChunkCrossSystem::default(), CHUNK_CROSS, &[]);
-    dispatcher.add(ClientChunkUnloadSystem, CLIENT_CHUNK_UNLOAD, &[]);
-    dispatcher.add(PlayerInitSystem::default(), PLAYER_INIT, &[]);
+    dispatcher.add_timed(ViewUpdateSystem::default(), VIEW_UPDATE, &[]);
+    dispatcher.add_timed(ChunkCrossSystem::default(), CHUNK_CROSS, &[]);
+    dispatcher.add_timed(ClientChunkUnloadSystem, CLIENT_CHUNK_UNLOAD, &[]);
+    dispatcher.add_timed(PlayerInitSystem::default(), PLAYER_INIT, &[]);
 }
 
 pub fn init_broadcast(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(HeldItemBroadcastSystem::default(), HELD_ITEM_BROADCAST, &[]);
-    dispatcher.add(JoinBroadcastSystem::default(), JOIN_BROADCAST, &[]);
-    dispatcher.add(
+    dispatcher.add_timed(JoinBroadcastSystem::default(), JOIN_BROADCAST, &[]);
+    dispatcher.add_timed(
+        HeldItemBroadcastSystem::default(),
+        HELD_ITEM_BROADCAST,
+        &[JOIN_BROADCAST],
+    );
+    dispatcher.add_timed(
         DisconnectBroadcastSystem::default(),
         DISCONNECT_BROADCAST,
-        &[],
+        &[JOIN_BROADCAST],
     );
-    dispatcher.add(
+    dispatcher.add_timed(
         AnimationBroadcastSystem::default(),
         ANIMATION_BROADCAST,
         &[],
     );
-    dispatcher.add(EquipmentSendSystem::default(), EQUIPMENT_SEND, &[]);
-    dispatcher.add(ResourcePackSendSystem::default(), RESOURCE_PACK_SEND, &[]);
-    dispatcher.add(ChunkSendSystem::default(), CHUNK_SEND, &[]);
-    dispatcher.add(
+    dispatcher.add_timed(EquipmentSendSystem::default(), EQUIPMENT_SEND, &[]);
+    dispatcher.add_timed(ResourcePackSendSystem::default(), RESOURCE_PACK_SEND, &[]);
+    dispatcher.add_timed(StatsSendSystem::default(), STATS_SEND, &[]);
+    dispatcher.add_timed(ChunkSendSystem::default(), CHUNK_SEND, &[]);
+    // Block changes must be sent after the chunks they are in.
+    dispatcher.add_timed(
         BlockUpdateBroadcastSystem::default(),
         BLOCK_BREAK_BROADCAST,
-        &[],
+        &[CHUNK_SEND],
     );
-    dispatcher.add(SetSlotSystem::default(), SET_SLOT, &[]);
-    dispatcher.add(ChatBroadcastSystem::default(), CHAT_BROADCAST, &[]);
+    dispatcher.add_timed(SetSlotSystem::default(), SET_SLOT, &[]);
+    dispatcher.add_timed(ChatBroadcastSystem::default(), CHAT_BROADCAST, &[]);
 }
@@ -1,6 +1,5 @@
 use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
 
 use hashbrown::HashSet;
 use rayon::prelude::*;
@@ -8,7 +7,7 @@ use shrev::{EventChannel, ReaderId};
 use specs::storage::{BTreeStorage, ComponentEvent};
 use specs::{
     BitSet, Component, Entities, Entity, Join, LazyUpdate, ParJoin, Read, ReadExpect, ReadStorage,
-    System, WorldExt, Write, WriteStorage,
+    System, WorldExt, Write, WriteExpect, WriteStorage,
 };
 
 use feather_core::network::cast_packet;
@@ -18,14 +17,18 @@ use feather_core::network::packet::implementation::{
 use feather_core::network::packet::{Packet, PacketType};
 use feather_core::world::chunk::Chunk;
 use feather_core::world::{ChunkMap, ChunkPosition, Position};
+use feather_core::Dimension;
 
 use crate::chunk_logic::{
     load_chunk, ChunkHolderComponent, ChunkHolderReleaseEvent, ChunkHolders, ChunkLoadEvent,
     ChunkLoadFailEvent, ChunkWorkerHandle,
 };
-use crate::config::Config;
+use crate::dimension::{DimensionComponent, DimensionSettings, DimensionWorld, Dimensions};
 use crate::entity::PositionComponent;
-use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::network::{
+    send_packet_serialized_off_thread, send_packet_to_player, NetworkComponent, PacketQueue,
+};
+use crate::view_distance::ViewDistance;
 use crate::{TickCount, TPS};
 
 // MOVEMENT HANDLING
@@ -120,6 +123,37 @@ pub struct LoadedChunksComponent {
     unload_queue: VecDeque<(ChunkPosition, u64)>,
 }
 
+impl LoadedChunksComponent {
+    /// Forgets all chunks loaded on the client,
+    /// for example after it changes dimension.
+    pub fn clear(&mut self) {
+        self.loaded_chunks.clear();
+        self.unload_queue.clear();
+    }
+
+    /// Marks the given chunk as loaded on the client.
+    pub fn mark_loaded(&mut self, chunk: ChunkPosition) {
+        self.loaded_chunks.insert(chunk);
+    }
+
+    /// Returns whether the given chunk is loaded on the client.
+    pub fn is_loaded(&self, chunk: ChunkPosition) -> bool {
+        self.loaded_chunks.contains(&chunk)
+    }
+
+    /// Queues all loaded chunks which are not in `chunks`
+    /// for unloading, unless they are already queued.
+    pub fn queue_unload_outside(&mut self, chunks: &HashSet<ChunkPosition>, tick_count: u64) {
+        let time = tick_count + CHUNK_UNLOAD_TIME;
+        for chunk in &self.loaded_chunks {
+            let queued = self.unload_queue.iter().any(|(queued, _)| queued == chunk);
+            if !chunks.contains(chunk) && !queued {
+                self.unload_queue.push_back((*chunk, time));
+            }
+        }
+    }
+}
+
 impl Component for LoadedChunksComponent {
     type Storage = BTreeStorage<Self>;
 }
@@ -181,13 +215,15 @@ impl<'a> System<'a> for ChunkCrossSystem {
         ReadStorage<'a, PositionComponent>,
         Read<'a, ChunkMap>,
         Read<'a, TickCount>,
-        Read<'a, Arc<Config>>,
+        ReadExpect<'a, ViewDistance>,
         WriteStorage<'a, LoadedChunksComponent>,
         WriteStorage<'a, ChunkHolderComponent>,
         ReadStorage<'a, NetworkComponent>,
         Write<'a, ChunkHolders>,
         Write<'a, EventChannel<ChunkCrossEvent>>,
         ReadExpect<'a, ChunkWorkerHandle>,
+        WriteExpect<'a, Dimensions>,
+        ReadStorage<'a, DimensionComponent>,
         Read<'a, LazyUpdate>,
         Entities<'a>,
     );
@@ -197,13 +233,15 @@ impl<'a> System<'a> for ChunkCrossSystem {
             positions,
             chunk_map,
             tick_count,
-            config,
+            view_distance,
             mut loaded_chunks_comps,
             mut chunk_holder_comps,
             net_comps,
             mut holders,
             mut cross_events,
             chunk_handle,
+            mut dimensions,
+            dimension_comps,
             lazy,
             entities,
         ) = data;
@@ -235,7 +273,8 @@ impl<'a> System<'a> for ChunkCrossSystem {
 
             if old_chunk_pos != new_chunk_pos {
                 // Player has moved across chunk boundaries. Handle accordingly.
-                let chunks = chunks_within_view_distance(&config, new_chunk_pos);
+                let chunks = chunks_within_view_distance(*view_distance, new_chunk_pos);
+                let dimension = DimensionComponent::of(dimension_comps.get(player));
 
                 for chunk in &chunks {
                     if loaded_chunks.loaded_chunks.contains(chunk) {
@@ -243,21 +282,33 @@ impl<'a> System<'a> for ChunkCrossSystem {
                         continue;
                     }
 
-                    send_chunk_to_player(
-                        *chunk,
-                        net,
-                        player,
-                        &chunk_map,
-                        &chunk_handle,
-                        &mut holders,
-                        chunk_holder,
-                        loaded_chunks,
-                        &lazy,
-                    );
+                    match dimensions.entry_mut(dimension) {
+                        Some((settings, world)) => send_dimension_chunk_to_player(
+                            *chunk,
+                            net,
+                            player,
+                            world,
+                            settings,
+                            chunk_holder,
+                            loaded_chunks,
+                            &lazy,
+                        ),
+                        None => send_chunk_to_player(
+                            *chunk,
+                            net,
+                            player,
+                            &chunk_map,
+                            &chunk_handle,
+                            &mut holders,
+                            chunk_holder,
+                            loaded_chunks,
+                            &lazy,
+                        ),
+                    }
                 }
 
                 // Now, queue all chunks which need to be unloaded for unloading.
-                let old_chunks = chunks_within_view_distance(&config, old_chunk_pos);
+                let old_chunks = chunks_within_view_distance(*view_distance, old_chunk_pos);
 
                 for chunk in old_chunks {
                     if chunks.contains(&chunk) {
@@ -285,6 +336,8 @@ impl<'a> System<'a> for ChunkCrossSystem {
 }
 
 /// System for sending chunks to players once they're loaded.
+/// Players outside of the primary dimension are handled
+/// by `DimensionChunkSystem` instead.
 ///
 /// This system listens to `ChunkLoadEvent`s.
 #[derive(Default)]
@@ -300,21 +353,22 @@ impl<'a> System<'a> for ChunkSendSystem {
         Read<'a, ChunkMap>,
         Read<'a, EventChannel<ChunkLoadEvent>>,
         Read<'a, EventChannel<ChunkLoadFailEvent>>,
+        ReadStorage<'a, DimensionComponent>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut pendings, netcomps, chunk_map, load_events, fail_events) = data;
+        let (mut pendings, netcomps, chunk_map, load_events, fail_events, dimension_comps) = data;
 
         for event in load_events.read(&mut self.load_event_reader.as_mut().unwrap()) {
             // TODO perhaps this is slightly inefficient?
-            (&netcomps, &mut pendings)
+            (&netcomps, &mut pendings, !&dimension_comps)
                 .par_join()
-                .for_each(|(net, pending)| {
+                .for_each(|(net, pending, _)| {
                     if pending.contains(&event.pos) {
                         // It's safe to unwrap the chunk value now,
                         // because we know it's been loaded.
                         let chunk = chunk_map.chunk_at(event.pos).unwrap();
-                        send_chunk_data(chunk, net);
+                        send_chunk_data(chunk, net, true);
 
                         pending.remove(&event.pos);
                     }
@@ -322,13 +376,15 @@ impl<'a> System<'a> for ChunkSendSystem {
         }
 
         for event in fail_events.read(self.fail_event_reader.as_mut().unwrap()) {
-            (&mut pendings).par_join().for_each(|pending| {
-                if pending.contains(&event.pos) {
-                    // The chunk failed to load - skip sending it.
-                    // See issue #71
-                    pending.remove(&event.pos);
-                }
-            });
+            (&mut pendings, !&dimension_comps)
+                .par_join()
+                .for_each(|(pending, _)| {
+                    if pending.contains(&event.pos) {
+                        // The chunk failed to load - skip sending it.
+                        // See issue #71
+                        pending.remove(&event.pos);
+                    }
+                });
         }
     }
 
@@ -347,8 +403,10 @@ impl<'a> System<'a> for ClientChunkUnloadSystem {
         Entities<'a>,
         Write<'a, ChunkHolders>,
         Read<'a, TickCount>,
-        Read<'a, Arc<Config>>,
+        ReadExpect<'a, ViewDistance>,
         Write<'a, EventChannel<ChunkHolderReleaseEvent>>,
+        WriteExpect<'a, Dimensions>,
+        ReadStorage<'a, DimensionComponent>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -360,8 +418,10 @@ impl<'a> System<'a> for ClientChunkUnloadSystem {
             entities,
             mut chunk_holders,
             tick_count,
-            config,
+            view_distance,
             mut holder_release_events,
+            mut dimensions,
+            dimension_comps,
         ) = data;
 
         (
@@ -380,8 +440,10 @@ impl<'a> System<'a> for ClientChunkUnloadSystem {
                         if tick_count.0 >= *time {
                             // Unload if needed.
 
-                            let chunks_within_view_distance =
-                                chunks_within_view_distance(&config, position.current.chunk_pos());
+                            let chunks_within_view_distance = chunks_within_view_distance(
+                                *view_distance,
+                                position.current.chunk_pos(),
+                            );
 
                             if chunks_within_view_distance.contains(&chunk) {
                                 // Chunk is within view distance again - don't unload it.
@@ -397,7 +459,15 @@ impl<'a> System<'a> for ClientChunkUnloadSystem {
                             // Remove from loaded chunk list.
                             loaded_chunks_comp.loaded_chunks.remove(&chunk);
                             // Remove hold on chunk so it can be unloaded.
-                            chunk_holders.remove_holder(chunk, player, &mut holder_release_events);
+                            let dimension = DimensionComponent::of(dimension_comps.get(player));
+                            match dimensions.world_mut(dimension) {
+                                Some(world) => world.remove_holder(chunk, player),
+                                None => chunk_holders.remove_holder(
+                                    chunk,
+                                    player,
+                                    &mut holder_release_events,
+                                ),
+                            }
                             // Remove hold from chunk holder component.
                             chunk_holder_comp.holds.remove(&chunk);
                         } else {
@@ -414,8 +484,11 @@ impl<'a> System<'a> for ClientChunkUnloadSystem {
 /// Returns the set of all chunk positions
 /// within the server view distance of a given
 /// chunk.
-fn chunks_within_view_distance(config: &Config, chunk: ChunkPosition) -> HashSet<ChunkPosition> {
-    let view_distance = i32::from(config.server.view_distance);
+pub fn chunks_within_view_distance(
+    view_distance: ViewDistance,
+    chunk: ChunkPosition,
+) -> HashSet<ChunkPosition> {
+    let view_distance = i32::from(view_distance.0);
     let mut results = HashSet::with_capacity((view_distance * view_distance) as usize);
 
     for x in -view_distance..=view_distance {
@@ -445,25 +518,60 @@ pub fn send_chunk_to_player(
 ) {
     holders.insert_holder(chunk_pos, player);
     holder.holds.insert(chunk_pos);
-    loaded_chunks.loaded_chunks.insert(chunk_pos);
+    loaded_chunks.mark_loaded(chunk_pos);
 
     if let Some(chunk) = chunk_map.chunk_at(chunk_pos) {
-        send_chunk_data(chunk, net);
+        send_chunk_data(chunk, net, true);
     } else {
         // Queue for loading
         load_chunk(chunk_handle, chunk_pos);
-        lazy.exec_mut(move |world| {
-            world
-                .write_component::<ChunkPendingComponent>()
-                .get_mut(player)
-                .unwrap()
-                .pending
-                .insert(chunk_pos);
-        });
+        queue_chunk_send(player, chunk_pos, lazy);
+    }
+}
+
+/// Equivalent of `send_chunk_to_player` for players in
+/// a dimension other than the primary one.
+#[allow(clippy::too_many_arguments)]
+pub fn send_dimension_chunk_to_player(
+    chunk_pos: ChunkPosition,
+    net: &NetworkComponent,
+    player: Entity,
+    world: &mut DimensionWorld,
+    settings: &DimensionSettings,
+    holder: &mut ChunkHolderComponent,
+    loaded_chunks: &mut LoadedChunksComponent,
+    lazy: &LazyUpdate,
+) {
+    world.insert_holder(chunk_pos, player);
+    holder.holds.insert(chunk_pos);
+    loaded_chunks.mark_loaded(chunk_pos);
+
+    if let Some(chunk) = world.chunk_map.chunk_at(chunk_pos) {
+        send_chunk_data(chunk, net, settings.environment == Dimension::Overwold);
+    } else {
+        load_chunk(&world.worker, chunk_pos);
+        queue_chunk_send(player, chunk_pos, lazy);
     }
 }
 
-fn send_chunk_data(chunk: &Chunk, net: &NetworkComponent) {
-    let packet = ChunkData::new(chunk.clone());
-    send_packet_to_player(net, packet);
+fn queue_chunk_send(player: Entity, chunk_pos: ChunkPosition, lazy: &LazyUpdate) {
+    lazy.exec_mut(move |world| {
+        world
+            .write_component::<ChunkPendingComponent>()
+            .get_mut(player)
+            .unwrap()
+            .pending
+            .insert(chunk_pos);
+    });
+}
+
+/// Sends a Chunk Data packet. Sky light is only sent to
+/// clients in the Overworld environment, which expect it.
+///
+/// The packet is serialized off the server thread, since
+/// serializing chunks is expensive and many chunks are
+/// sent at once when a player joins.
+pub fn send_chunk_data(chunk: &Chunk, net: &NetworkComponent, sky_light: bool) {
+    let packet = ChunkData::new(chunk.clone(), sky_light);
+    send_packet_serialized_off_thread(net, packet);
 }
@@ -1,17 +1,31 @@
-use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::blocks::{
+    can_support_fire, fire_state, placed_by_player, BlockUpdateCause, BlockUpdateEvent,
+};
+use crate::cauldron::{use_item, CauldronUseEvent};
+use crate::container::{place_container, ContainerKind, ContainerOpenEvent};
+use crate::dimension::{DimensionComponent, Dimensions};
 use crate::disconnect_player;
-use crate::entity::PlayerComponent;
-use crate::network::PacketQueue;
+use crate::entity::{FireworkLaunchEvent, PlayerComponent};
+use crate::event::{BlockPlaceEvent, EventBus};
+use crate::interact::{is_interactive, BlockInteractEvent};
+use crate::lang::Message;
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
 use crate::player::{InventoryComponent, InventoryUpdateEvent};
 use crate::prelude::Gamemode;
+use crate::sign::{is_sign, place_sign};
+use crate::sleep::{is_bed, BedEnterEvent};
+use crate::structure_block::StructureBlockUseEvent;
+use crate::tool;
+use crate::util::Util;
+use feather_blocks::{EndPortalFrameData, FireData};
 use feather_core::inventory::SLOT_HOTBAR_OFFSET;
 use feather_core::network::cast_packet;
-use feather_core::network::packet::implementation::PlayerBlockPlacement;
+use feather_core::network::packet::implementation::{BlockChange, PlayerBlockPlacement};
 use feather_core::world::ChunkMap;
-use feather_core::{Block, ItemStack, PacketType};
+use feather_core::{Block, BlockExt, Item, PacketType};
 use feather_item_block::ItemToBlock;
 use shrev::EventChannel;
-use specs::{LazyUpdate, Read, ReadStorage, System, Write, WriteStorage};
+use specs::{Entity, LazyUpdate, Read, ReadExpect, ReadStorage, System, Write, WriteStorage};
 
 /// System for handling Player Block Placement packets
 /// and updating the world accordingly.
@@ -21,22 +35,44 @@ impl<'a> System<'a> for BlockPlacementSystem {
     type SystemData = (
         WriteStorage<'a, InventoryComponent>,
         ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NetworkComponent>,
         Write<'a, ChunkMap>,
         Write<'a, EventChannel<BlockUpdateEvent>>,
         Write<'a, EventChannel<InventoryUpdateEvent>>,
         Read<'a, PacketQueue>,
         Read<'a, LazyUpdate>,
+        Read<'a, EventBus>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadExpect<'a, Dimensions>,
+        Write<'a, EventChannel<BedEnterEvent>>,
+        Write<'a, EventChannel<StructureBlockUseEvent>>,
+        Write<'a, EventChannel<ContainerOpenEvent>>,
+        Write<'a, EventChannel<FireworkLaunchEvent>>,
+        Write<'a, EventChannel<CauldronUseEvent>>,
+        Write<'a, EventChannel<BlockInteractEvent>>,
+        Read<'a, Util>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             mut inventories,
             players,
+            networks,
             mut chunk_map,
             mut block_update_events,
             mut inventory_update_events,
             packet_queue,
             lazy,
+            bus,
+            dimensions,
+            dimension_worlds,
+            mut bed_events,
+            mut structure_block_events,
+            mut container_events,
+            mut firework_events,
+            mut cauldron_events,
+            mut interact_events,
+            util,
         ) = data;
 
         let packets = packet_queue.for_packet(PacketType::PlayerBlockPlacement);
@@ -44,19 +80,140 @@ impl<'a> System<'a> for BlockPlacementSystem {
         for (player, packet) in packets {
             let packet = cast_packet::<PlayerBlockPlacement>(&*packet);
 
+            // Blocks outside of the primary dimension can't be modified yet,
+            // so the block the client predicted is placed is reverted.
+            if let Some(dimension) = dimensions.get(player) {
+                let world = dimension_worlds.world(dimension.0);
+                if let (Some(world), Some(network)) = (world, networks.get(player)) {
+                    world.revert_block(packet.location, network);
+                    world.revert_block(packet.location + packet.face.placement_offset(), network);
+                }
+                continue;
+            }
+
+            // Right-clicking a bed sleeps in it
+            // instead of placing a block.
+            if chunk_map.block_at(packet.location).map_or(false, is_bed) {
+                bed_events.single_write(BedEnterEvent {
+                    player,
+                    pos: packet.location,
+                });
+                continue;
+            }
+
+            // Right-clicking a container opens it.
+            if chunk_map
+                .block_at(packet.location)
+                .and_then(ContainerKind::of)
+                .is_some()
+            {
+                container_events.single_write(ContainerOpenEvent {
+                    player,
+                    pos: packet.location,
+                });
+                continue;
+            }
+
+            // Right-clicking a door, trapdoor, fence gate,
+            // lever or button uses it.
+            if chunk_map
+                .block_at(packet.location)
+                .map_or(false, is_interactive)
+            {
+                interact_events.single_write(BlockInteractEvent {
+                    player,
+                    pos: packet.location,
+                });
+                continue;
+            }
+
+            // Items which can be used on cauldrons are
+            // used instead of being placed against them.
+            if let Some(Block::Cauldron(data)) = chunk_map.block_at(packet.location) {
+                let usable = inventories
+                    .get(player)
+                    .and_then(|inventory| inventory.item_at(inventory.hand_slot(packet.hand)))
+                    .map_or(false, |stack| use_item(data.level, stack).is_some());
+                if usable {
+                    cauldron_events.single_write(CauldronUseEvent {
+                        player,
+                        pos: packet.location,
+                        hand: packet.hand,
+                    });
+                    continue;
+                }
+            }
+
+            // Players in creative mode use structure blocks
+            // instead of placing blocks against them.
+            if let Some(Block::StructureBlock(_)) = chunk_map.block_at(packet.location) {
+                if players.get(player).map(|player| player.gamemode) == Some(Gamemode::Creative) {
+                    structure_block_events.single_write(StructureBlockUseEvent {
+                        player,
+                        pos: packet.location,
+                    });
+                    continue;
+                }
+            }
+
             // TODO: handle slabs, blocks with directions, etc.
             let inventory = inventories.get_mut(player).unwrap();
 
             let item = continue_if_none!(inventory.item_in_main_hand());
+            let gamemode = players.get(player).unwrap().gamemode;
 
-            let block = continue_if_none!(item.ty.to_block());
+            // Eyes of ender are inserted into end portal
+            // frames instead of being placed.
+            if item.ty == Item::EnderEye {
+                if let Some(Block::EndPortalFrame(data)) = chunk_map.block_at(packet.location) {
+                    if !data.eye {
+                        let block = Block::EndPortalFrame(EndPortalFrameData { eye: true, ..data });
+                        chunk_map.set_block_at(packet.location, block).unwrap();
+                        block_update_events.single_write(BlockUpdateEvent {
+                            cause: BlockUpdateCause::Player(player),
+                            pos: packet.location,
+                            old_block: Block::EndPortalFrame(data),
+                            new_block: block,
+                        });
+
+                        if gamemode == Gamemode::Survival {
+                            consume_held_item(inventory, player, &mut inventory_update_events);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // Firework rockets are launched from the
+            // clicked point instead of being placed.
+            if item.ty == Item::FireworkRocket {
+                firework_events.single_write(FireworkLaunchEvent {
+                    stack: item.with_amount(1),
+                    position: position!(
+                        f64::from(packet.location.x) + f64::from(packet.cursor_position_x),
+                        f64::from(packet.location.y) + f64::from(packet.cursor_position_y),
+                        f64::from(packet.location.z) + f64::from(packet.cursor_position_z)
+                    ),
+                    boosted: None,
+                });
+
+                if gamemode == Gamemode::Survival {
+                    consume_held_item(inventory, player, &mut inventory_update_events);
+                }
+                continue;
+            }
+
+            let block = match item.ty {
+                Item::FlintAndSteel => Block::Fire(FireData::default()),
+                ty => placed_by_player(continue_if_none!(ty.to_block())),
+            };
 
             let placed_on = match chunk_map.block_at(packet.location) {
                 Some(block) => block,
                 None => {
                     disconnect_player(
                         player,
-                        String::from("Attempted to place block in unloaded chunk"),
+                        Message::translate("feather.disconnect.place_in_unloaded_chunk"),
                         &lazy,
                     );
                     continue;
@@ -76,15 +233,53 @@ impl<'a> System<'a> for BlockPlacementSystem {
                 None => {
                     disconnect_player(
                         player,
-                        String::from("Attempted to place block in unloaded chunk"),
+                        Message::translate("feather.disconnect.place_in_unloaded_chunk"),
                         &lazy,
                     );
                     continue;
                 }
             };
 
+            // Fire is only lit in air on solid or next to
+            // flammable blocks, and attaches to the latter.
+            let block = if item.ty == Item::FlintAndSteel {
+                if old != Block::Air || !can_support_fire(&chunk_map, pos) {
+                    continue;
+                }
+                fire_state(&chunk_map, pos, 0)
+            } else {
+                block
+            };
+
+            let mut place_event = BlockPlaceEvent {
+                player,
+                pos,
+                block,
+                cancelled: false,
+            };
+            if !bus.emit(&mut place_event) {
+                // Revert the client's prediction.
+                if let Some(network) = networks.get(player) {
+                    send_packet_to_player(
+                        network,
+                        BlockChange::new(pos, i32::from(old.native_state_id())),
+                    );
+                }
+                continue;
+            }
+            let block = place_event.block;
+
             chunk_map.set_block_at(pos, block).unwrap();
 
+            if let Some(kind) = ContainerKind::of(block).filter(|kind| kind.has_block_entity()) {
+                place_container(&mut chunk_map, kind, pos, item);
+            }
+            if is_sign(block) {
+                if let Some(network) = networks.get(player) {
+                    place_sign(&mut chunk_map, pos, player, network, &lazy);
+                }
+            }
+
             let event = BlockUpdateEvent {
                 cause: BlockUpdateCause::Player(player),
                 pos,
@@ -94,31 +289,57 @@ impl<'a> System<'a> for BlockPlacementSystem {
 
             block_update_events.single_write(event);
 
-            let gamemode = players.get(player).unwrap().gamemode;
-
-            // Update player's inventory if in survival
-            if gamemode == Gamemode::Survival {
-                let item = ItemStack::new(item.ty, item.amount - 1);
-                inventory.set_item_in_main_hand(item);
-
-                let event = InventoryUpdateEvent {
-                    slots: smallvec![SLOT_HOTBAR_OFFSET + inventory.held_item],
-                    player,
-                };
-                inventory_update_events.single_write(event);
+            // Update player's inventory if in survival. Flint
+            // and steel is damaged instead of being used up.
+            if item.ty == Item::FlintAndSteel {
+                if gamemode != Gamemode::Creative {
+                    tool::damage_held_item(
+                        player,
+                        inventory,
+                        1,
+                        &networks,
+                        &mut inventory_update_events,
+                        &util,
+                    );
+                }
+            } else if gamemode == Gamemode::Survival {
+                consume_held_item(inventory, player, &mut inventory_update_events);
             }
         }
     }
 }
 
+/// Removes one item from the stack in a player's main hand.
+fn consume_held_item(
+    inventory: &mut InventoryComponent,
+    player: Entity,
+    events: &mut EventChannel<InventoryUpdateEvent>,
+) {
+    let item = match inventory.item_in_main_hand() {
+        Some(item) => item,
+        None => return,
+    };
+    inventory.set_item_in_main_hand(item.with_amount(item.amount - 1));
+
+    let event = InventoryUpdateEvent {
+        slots: smallvec![SLOT_HOTBAR_OFFSET + inventory.held_item],
+        player,
+    };
+    events.single_write(event);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dimension::DimensionSettings;
     use crate::testframework as t;
+    use crate::worldgen::EmptyWorldGenerator;
+    use feather_blocks::{CauldronData, LeverData};
     use feather_core::inventory::SLOT_HOTBAR_OFFSET;
     use feather_core::network::packet::implementation::Face;
-    use feather_core::{Block, BlockPosition, Item, ItemStack};
+    use feather_core::{Block, BlockPosition, Chunk, Dimension, Item, ItemStack};
     use specs::WorldExt;
+    use std::sync::Arc;
 
     #[test]
     fn test_block_placement_system() {
@@ -212,4 +433,290 @@ mod tests {
 
         assert!(t::triggered_events::<BlockUpdateEvent>(&w, &mut reader).is_empty());
     }
+
+    #[test]
+    fn test_block_placement_in_other_dimension() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::Cobblestone, 1));
+
+        let dir = std::env::temp_dir().join(format!("feather-nether-{}", uuid::Uuid::new_v4()));
+        let nether = DimensionSettings::new(
+            "the_nether",
+            Dimension::Nether,
+            Arc::new(EmptyWorldGenerator {}),
+            &dir,
+        );
+        let id = w.fetch_mut::<Dimensions>().register(nether).unwrap();
+        w.write_component::<DimensionComponent>()
+            .insert(player.entity, DimensionComponent(id))
+            .unwrap();
+
+        let pos = BlockPosition::new(10, 20, 30);
+        {
+            let mut dimensions = w.fetch_mut::<Dimensions>();
+            let chunk_map = &mut dimensions.world_mut(id).unwrap().chunk_map;
+            chunk_map.set_chunk_at(pos.chunk_pos(), Chunk::new(pos.chunk_pos()));
+            chunk_map.set_block_at(pos, Block::Netherrack).unwrap();
+        }
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.0,
+            cursor_position_y: 0.0,
+            cursor_position_z: 0.0,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut reader = t::reader(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(t::triggered_events::<BlockUpdateEvent>(&w, &mut reader).is_empty());
+
+        // Both the clicked block and the predicted one are reverted.
+        for (pos, block) in &[
+            (pos, Block::Netherrack),
+            (pos + BlockPosition::new(0, 1, 0), Block::Air),
+        ] {
+            let packet = t::assert_packet_received(&player, PacketType::BlockChange);
+            let change = cast_packet::<BlockChange>(&*packet);
+            assert_eq!(change.location, *pos);
+            assert_eq!(change.block_id, i32::from(block.native_state_id()));
+        }
+
+        w.fetch_mut::<Dimensions>().shut_down();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_insert_ender_eye() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::EnderEye, 1));
+
+        let pos = BlockPosition::new(10, 20, 30);
+        let frame = EndPortalFrameData::default();
+        t::set_block(pos.x, pos.y, pos.z, Block::EndPortalFrame(frame), &w);
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.0,
+            cursor_position_y: 0.0,
+            cursor_position_z: 0.0,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut reader = t::reader(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<BlockUpdateEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].pos, pos);
+        assert_eq!(
+            events[0].new_block,
+            Block::EndPortalFrame(EndPortalFrameData { eye: true, ..frame })
+        );
+        assert_eq!(
+            w.fetch::<ChunkMap>()
+                .block_at(pos + BlockPosition::new(0, 1, 0)),
+            Some(Block::Air)
+        );
+    }
+
+    #[test]
+    fn test_launch_firework() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::FireworkRocket, 1));
+
+        let pos = BlockPosition::new(10, 20, 30);
+        t::set_block(pos.x, pos.y, pos.z, Block::Stone, &w);
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.5,
+            cursor_position_y: 1.0,
+            cursor_position_z: 0.5,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut reader = t::reader(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<FireworkLaunchEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].position, position!(10.5, 21.0, 30.5));
+        assert_eq!(events[0].boosted, None);
+        assert_eq!(
+            w.fetch::<ChunkMap>()
+                .block_at(pos + BlockPosition::new(0, 1, 0)),
+            Some(Block::Air)
+        );
+    }
+
+    #[test]
+    fn test_light_fire() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::FlintAndSteel, 1));
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+
+        let pos = BlockPosition::new(10, 20, 30);
+        t::set_block(pos.x, pos.y, pos.z, Block::Stone, &w);
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.5,
+            cursor_position_y: 1.0,
+            cursor_position_z: 0.5,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(match w
+            .fetch::<ChunkMap>()
+            .block_at(pos + BlockPosition::new(0, 1, 0))
+        {
+            Some(Block::Fire(_)) => true,
+            _ => false,
+        });
+        let inventory = w
+            .read_component::<InventoryComponent>()
+            .get(player.entity)
+            .unwrap()
+            .clone();
+        let stack = inventory.item_in_main_hand().unwrap();
+        assert_eq!(stack.ty, Item::FlintAndSteel);
+        assert_eq!(stack.damage(), 1);
+    }
+
+    #[test]
+    fn test_interact_with_block() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::Stone, 1));
+
+        let pos = BlockPosition::new(10, 20, 30);
+        let lever = Block::Lever(LeverData::default());
+        t::set_block(pos.x, pos.y, pos.z, lever, &w);
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.5,
+            cursor_position_y: 1.0,
+            cursor_position_z: 0.5,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut reader = t::reader(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<BlockInteractEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].pos, pos);
+        assert_eq!(
+            w.fetch::<ChunkMap>()
+                .block_at(pos + BlockPosition::new(0, 1, 0)),
+            Some(Block::Air)
+        );
+    }
+
+    #[test]
+    fn test_use_cauldron() {
+        let (mut w, mut d) = t::builder().with(BlockPlacementSystem, "").build();
+
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, ItemStack::new(Item::WaterBucket, 1));
+
+        let pos = BlockPosition::new(10, 20, 30);
+        let cauldron = Block::Cauldron(CauldronData { level: 0 });
+        t::set_block(pos.x, pos.y, pos.z, cauldron, &w);
+
+        let packet = PlayerBlockPlacement {
+            location: pos,
+            face: Face::Top,
+            hand: 0,
+            cursor_position_x: 0.5,
+            cursor_position_y: 1.0,
+            cursor_position_z: 0.5,
+        };
+        t::receive_packet(&player, &w, packet);
+
+        let mut reader = t::reader(&w);
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<CauldronUseEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].pos, pos);
+        assert_eq!(
+            w.fetch::<ChunkMap>()
+                .block_at(pos + BlockPosition::new(0, 1, 0)),
+            Some(Block::Air)
+        );
+    }
 }
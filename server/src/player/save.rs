@@ -1,23 +1,27 @@
-//! Saving of player data files and a system to save
-//! player data on disconnect.
+//! Saving of player data files, a system to save
+//! player data on disconnect and periodic saving.
 
 use crate::entity::{NamedComponent, PlayerComponent, PositionComponent};
-use crate::player::{InventoryComponent, PlayerDisconnectEvent};
+use crate::player::{InventoryComponent, PlayerDisconnectEvent, PlayerStatsComponent};
 use crate::prelude::Config;
+use crate::scheduler::Scheduler;
+use crate::TICK_TIME;
 use crossbeam::Receiver;
 use feather_core::entity::BaseEntityData;
 use feather_core::inventory::Inventory;
 use feather_core::player_data::{InventorySlot, PlayerData};
 use feather_core::{player_data, Gamemode, Position};
 use shrev::{EventChannel, ReaderId};
-use specs::{Read, ReadStorage, System};
+use specs::{Join, Read, ReadStorage, System, World, WorldExt};
 use std::path::Path;
 use std::sync::Arc;
 use uuid::Uuid;
 
 /// System to save player data upon disconnect.
 ///
-/// This system listens to `PlayerDisconnectEvent`s.
+/// This system listens to `PlayerDisconnectEvent`s. The data
+/// of online players is also saved periodically by a task on
+/// the `Scheduler`, which is scheduled when this system is set up.
 #[derive(Default)]
 pub struct PlayerDataSaveSystem {
     reader: Option<ReaderId<PlayerDisconnectEvent>>,
@@ -30,11 +34,12 @@ impl<'a> System<'a> for PlayerDataSaveSystem {
         ReadStorage<'a, PlayerComponent>,
         ReadStorage<'a, NamedComponent>,
         ReadStorage<'a, InventoryComponent>,
+        ReadStorage<'a, PlayerStatsComponent>,
         Read<'a, EventChannel<PlayerDisconnectEvent>>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (config, positions, players, nameds, inventories, disconnect_events) = data;
+        let (config, positions, players, nameds, inventories, stats, disconnect_events) = data;
 
         for event in disconnect_events.read(self.reader.as_mut().unwrap()) {
             let player = players.get(event.player).unwrap();
@@ -43,12 +48,81 @@ impl<'a> System<'a> for PlayerDataSaveSystem {
                 positions.get(event.player).unwrap().current,
                 player.gamemode,
                 &inventories.get(event.player).unwrap().inventory,
+                stats.get(event.player).copied().unwrap_or_default(),
                 nameds.get(event.player).unwrap().uuid,
             );
         }
     }
 
-    setup_impl!(reader);
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<PlayerDisconnectEvent>>()
+                .register_reader(),
+        );
+        schedule_autosave(world);
+    }
+}
+
+/// Schedules the next periodic save of player data,
+/// which uses the same interval as chunk saves.
+pub fn schedule_autosave(world: &mut World) {
+    let interval = world
+        .entry::<Arc<Config>>()
+        .or_insert_with(Arc::default)
+        .world
+        .save_interval;
+    let ticks = interval.as_millis() as u64 / TICK_TIME;
+
+    world
+        .entry::<Scheduler>()
+        .or_insert_with(Scheduler::default)
+        .schedule_delayed(ticks, autosave);
+}
+
+/// Saves the data of all online players and schedules the next save.
+pub fn autosave(world: &mut World) {
+    let count = save_all_player_data(world).len();
+    debug!("Saving data of {} players", count);
+
+    schedule_autosave(world);
+}
+
+/// Saves the data of all online players.
+///
+/// Returns a channel for each player, which will
+/// receive a message once their data is saved.
+pub fn save_all_player_data(world: &World) -> Vec<Receiver<()>> {
+    let config = world.fetch::<Arc<Config>>();
+
+    let positions = world.read_component::<PositionComponent>();
+    let nameds = world.read_component::<NamedComponent>();
+    let players = world.read_component::<PlayerComponent>();
+    let inventories = world.read_component::<InventoryComponent>();
+    let stats = world.read_component::<PlayerStatsComponent>();
+
+    (
+        &*world.entities(),
+        &positions,
+        &nameds,
+        &players,
+        &inventories,
+    )
+        .join()
+        .map(|(entity, position, named, player, inventory)| {
+            save_player_data(
+                &config,
+                position.current,
+                player.gamemode,
+                &inventory.inventory,
+                stats.get(entity).copied().unwrap_or_default(),
+                named.uuid,
+            )
+        })
+        .collect()
 }
 
 /// Saves a player's data.
@@ -61,6 +135,7 @@ pub fn save_player_data(
     position: Position,
     gamemode: Gamemode,
     inventory: &Inventory,
+    stats: PlayerStatsComponent,
     uuid: Uuid,
 ) -> Receiver<()> {
     let data = PlayerData {
@@ -80,6 +155,12 @@ pub fn save_player_data(
             })
             .map(|(index, item)| InventorySlot::from_network_index(index, item))
             .collect(),
+        health: stats.health,
+        food_level: stats.food_level,
+        food_saturation: stats.food_saturation,
+        xp_level: stats.xp_level,
+        xp_progress: stats.xp_progress,
+        xp_total: stats.xp_total,
     };
 
     // Channel used to communicate with Tokio task
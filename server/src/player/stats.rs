@@ -0,0 +1,178 @@
+//! Health, hunger and experience of players, which
+//! are saved in player data files and sent on join.
+//!
+//! Experience is gained from furnaces. Health is lowered by damage
+//! and restored on respawn; see the `combat` module. Nothing modifies
+//! hunger yet, so it is only kept so that it persists across
+//! reconnects.
+
+use crate::joinhandler::PlayerJoinEvent;
+use crate::network::{send_packet_to_player, NetworkComponent};
+use feather_core::network::packet::implementation::{SetExperience, UpdateHealth};
+use feather_core::player_data::PlayerData;
+use shrev::{EventChannel, ReaderId};
+use specs::{Component, DenseVecStorage, Read, ReadStorage, System};
+
+/// Holds the health, hunger and experience of a player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerStatsComponent {
+    pub health: f32,
+    pub food_level: i32,
+    pub food_saturation: f32,
+    pub xp_level: i32,
+    /// Progress towards the next level, between 0 and 1.
+    pub xp_progress: f32,
+    pub xp_total: i32,
+}
+
+impl PlayerStatsComponent {
+    /// Returns the stats saved in the given player data.
+    pub fn from_data(data: &PlayerData) -> Self {
+        Self {
+            health: data.health,
+            food_level: data.food_level,
+            food_saturation: data.food_saturation,
+            xp_level: data.xp_level,
+            xp_progress: data.xp_progress,
+            xp_total: data.xp_total,
+        }
+    }
+
+    /// Adds experience points, leveling up as
+    /// many times as the points are enough for.
+    pub fn add_experience(&mut self, points: i32) {
+        self.xp_total = self.xp_total.saturating_add(points);
+        self.xp_progress += points as f32 / xp_to_next_level(self.xp_level) as f32;
+        while self.xp_progress >= 1.0 {
+            self.xp_progress = (self.xp_progress - 1.0) * xp_to_next_level(self.xp_level) as f32;
+            self.xp_level += 1;
+            self.xp_progress /= xp_to_next_level(self.xp_level) as f32;
+        }
+    }
+}
+
+/// Returns the number of experience points needed
+/// to get from the given level to the next one.
+pub fn xp_to_next_level(level: i32) -> i32 {
+    if level >= 30 {
+        112 + (level - 30) * 9
+    } else if level >= 15 {
+        37 + (level - 15) * 5
+    } else {
+        7 + level * 2
+    }
+}
+
+impl Default for PlayerStatsComponent {
+    /// Returns the stats of a player who joins for the first time.
+    fn default() -> Self {
+        Self {
+            health: 20.0,
+            food_level: 20,
+            food_saturation: 5.0,
+            xp_level: 0,
+            xp_progress: 0.0,
+            xp_total: 0,
+        }
+    }
+}
+
+impl Component for PlayerStatsComponent {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// System which sends the health, hunger and
+/// experience of players when they join.
+///
+/// This system listens to `PlayerJoinEvent`s.
+#[derive(Default)]
+pub struct StatsSendSystem {
+    reader: Option<ReaderId<PlayerJoinEvent>>,
+}
+
+impl<'a> System<'a> for StatsSendSystem {
+    type SystemData = (
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, PlayerStatsComponent>,
+        Read<'a, EventChannel<PlayerJoinEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (networks, stats, join_events) = data;
+
+        for event in join_events.read(self.reader.as_mut().unwrap()) {
+            let network = continue_if_none!(networks.get(event.player));
+            let stats = stats.get(event.player).copied().unwrap_or_default();
+
+            send_packet_to_player(
+                network,
+                UpdateHealth::new(stats.health, stats.food_level, stats.food_saturation),
+            );
+            send_packet_to_player(
+                network,
+                SetExperience::new(stats.xp_progress, stats.xp_level, stats.xp_total),
+            );
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::PacketType;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_add_experience() {
+        let mut stats = PlayerStatsComponent::default();
+        stats.add_experience(3);
+        assert_eq!(stats.xp_level, 0);
+        assert!((stats.xp_progress - 3.0 / 7.0).abs() < 1e-6);
+
+        // 4 points to reach level 1, then 9 more to reach level 2.
+        stats.add_experience(15);
+        assert_eq!(stats.xp_level, 2);
+        assert!((stats.xp_progress - 2.0 / 11.0).abs() < 1e-5);
+        assert_eq!(stats.xp_total, 18);
+    }
+
+    #[test]
+    fn test_stats_send() {
+        let (mut w, mut d) = t::builder().with(StatsSendSystem::default(), "").build();
+        let player = t::add_player(&mut w);
+
+        let stats = PlayerStatsComponent {
+            health: 12.0,
+            food_level: 7,
+            xp_level: 3,
+            xp_total: 40,
+            ..PlayerStatsComponent::default()
+        };
+        w.write_component::<PlayerStatsComponent>()
+            .insert(player.entity, stats)
+            .unwrap();
+
+        t::trigger_event(
+            &w,
+            PlayerJoinEvent {
+                player: player.entity,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::UpdateHealth);
+        let packet = cast_packet::<UpdateHealth>(&*packet);
+        assert_eq!(packet.food, 7);
+        assert!((packet.health - 12.0).abs() < std::f32::EPSILON);
+
+        let packet = t::assert_packet_received(&player, PacketType::SetExperience);
+        let packet = cast_packet::<SetExperience>(&*packet);
+        assert_eq!(packet.level, 3);
+        assert_eq!(packet.total_experience, 40);
+    }
+}
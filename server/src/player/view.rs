@@ -11,15 +11,14 @@
 //! This is handled by `ViewUpdateSystem`, which listens
 //! to `ChunkCrossEvent`s.
 
-use crate::config::Config;
 use crate::entity::ChunkEntities;
 use crate::lazy::LazyUpdateExt;
 use crate::network::{send_packet_to_player, NetworkComponent};
 use crate::player::movement::ChunkCrossEvent;
+use crate::view_distance::ViewDistance;
 use feather_core::network::packet::implementation::DestroyEntities;
 use shrev::EventChannel;
-use specs::{LazyUpdate, Read, ReadStorage, ReaderId, System};
-use std::sync::Arc;
+use specs::{LazyUpdate, Read, ReadExpect, ReadStorage, ReaderId, System};
 
 /// System for updating entities visible
 /// by the client.
@@ -33,19 +32,19 @@ impl<'a> System<'a> for ViewUpdateSystem {
         ReadStorage<'a, NetworkComponent>,
         Read<'a, EventChannel<ChunkCrossEvent>>,
         Read<'a, ChunkEntities>,
-        Read<'a, Arc<Config>>,
+        ReadExpect<'a, ViewDistance>,
         Read<'a, LazyUpdate>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (networks, cross_events, chunk_entities, config, lazy) = data;
+        let (networks, cross_events, chunk_entities, view_distance, lazy) = data;
 
         for event in cross_events.read(self.reader.as_mut().unwrap()) {
             // Find new and old entities.
             let old_entities =
-                chunk_entities.entites_within_view_distance(event.old, config.server.view_distance);
+                chunk_entities.entites_within_view_distance(event.old, view_distance.0);
             let new_entities =
-                chunk_entities.entites_within_view_distance(event.new, config.server.view_distance);
+                chunk_entities.entites_within_view_distance(event.new, view_distance.0);
 
             let mut to_destroy = vec![];
 
@@ -149,9 +148,7 @@ mod tests {
         .with(PositionComponent::default())
         .build();
 
-        let mut config = Config::default();
-        config.server.view_distance = 4;
-        world.insert(Arc::new(config));
+        world.insert(ViewDistance(4));
 
         {
             let mut chunk_entities = world.fetch_mut::<ChunkEntities>();
@@ -0,0 +1,475 @@
+//! The host API exposed to plugins.
+//!
+//! All functions live in the `feather` import module.
+//! Functions return `0` (or a non-negative value) on success
+//! and one of the `ERR_*` constants on failure.
+//!
+//! In addition, `env.gas` is called by the instruction counter
+//! injected into each plugin when it is loaded. It traps once the
+//! plugin exceeds its instruction limit for the current call.
+
+use super::{Capabilities, PluginError};
+use crate::worldedit::{Operation, Region};
+use feather_core::world::ChunkMap;
+use feather_core::{Block, BlockExt, BlockPosition};
+use std::cell::Cell;
+use wasmer_runtime::{func, imports, Ctx, Func, ImportObject, Instance, Memory};
+
+/// The plugin lacks the capability required by the function.
+pub const ERR_PERMISSION_DENIED: i32 = -1;
+/// An argument was invalid, e.g. a pointer out of bounds
+/// (or any pointer, if the plugin has no memory) or a
+/// block in an unloaded chunk.
+pub const ERR_INVALID_ARGUMENT: i32 = -2;
+/// The function was called outside of a server callback, or
+/// needs access to the world while the plugin is handling a
+/// cancellable event.
+pub const ERR_NO_CONTEXT: i32 = -3;
+
+/// An action requested by a plugin which needs
+/// to be applied by the plugin system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginAction {
+    /// A block was set. The chunk map has already
+    /// been updated; a `BlockUpdateEvent` needs to be triggered.
+    SetBlock {
+        pos: BlockPosition,
+        old: Block,
+        new: Block,
+    },
+    /// A chat message should be broadcasted to all players.
+    BroadcastMessage(String),
+    /// A task should be scheduled to call the plugin's
+    /// `feather_on_task` export.
+    ScheduleTask {
+        plugin: String,
+        task: i32,
+        ticks: u64,
+        repeating: bool,
+    },
+    /// A task scheduled by the plugin should be cancelled.
+    CancelTask { plugin: String, task: i32 },
+    /// A `WorldEdit` operation should be performed
+    /// on behalf of the plugin.
+    Edit {
+        plugin: String,
+        operation: Operation,
+    },
+}
+
+/// State available to host functions during a call
+/// into a plugin.
+pub struct HostState<'a> {
+    /// The name of the plugin being called.
+    pub plugin: &'a str,
+    pub capabilities: Capabilities,
+    /// Whether the plugin defines a linear memory.
+    pub has_memory: bool,
+    /// The number of instructions the plugin may
+    /// still execute before the call is stopped.
+    pub fuel: u64,
+    /// The world, unless the plugin is handling a cancellable
+    /// event, during which the world is held by the emitting system.
+    pub chunk_map: Option<&'a mut ChunkMap>,
+    pub actions: &'a mut Vec<PluginAction>,
+}
+
+/// Error with which `env.gas` traps once a plugin
+/// has run out of fuel.
+#[derive(Debug)]
+pub struct OutOfFuel;
+
+/// Returns the import object containing the host API.
+pub fn imports() -> ImportObject {
+    imports! {
+        "feather" => {
+            "feather_log" => func!(log),
+            "feather_block_at" => func!(block_at),
+            "feather_set_block_at" => func!(set_block_at),
+            "feather_block_light_at" => func!(block_light_at),
+            "feather_sky_light_at" => func!(sky_light_at),
+            "feather_light_level_at" => func!(light_level_at),
+            "feather_biome_at" => func!(biome_at),
+            "feather_broadcast_message" => func!(broadcast_message),
+            "feather_schedule_task" => func!(schedule_task),
+            "feather_cancel_task" => func!(cancel_task),
+            "feather_fill" => func!(fill),
+            "feather_replace" => func!(replace),
+            "feather_copy" => func!(copy),
+            "feather_paste" => func!(paste),
+            "feather_undo" => func!(undo),
+        },
+        "env" => {
+            "gas" => func!(gas),
+        },
+    }
+}
+
+/// Retrieves the host state for the current call. The state
+/// can't outlive the borrow of `ctx`, which in turn can't
+/// outlive the host function call.
+fn state(ctx: &mut Ctx) -> Option<&mut HostState<'_>> {
+    // Safety: `data` is only non-null while `Plugin::with_host`
+    // is executing, during which it points to a live `HostState`
+    // which isn't otherwise accessed.
+    unsafe { (ctx.data as *mut HostState).as_mut() }
+}
+
+/// Returns the plugin's memory, or `None` if it
+/// defines none or this isn't a call from the server.
+fn memory(ctx: &Ctx) -> Option<&Memory> {
+    // Safety: as in `state`; the state is only read here.
+    let state = unsafe { (ctx.data as *const HostState).as_ref() }?;
+    if state.has_memory {
+        Some(ctx.memory(0))
+    } else {
+        None
+    }
+}
+
+/// Reads a UTF-8 string from the plugin's memory.
+fn read_string(ctx: &Ctx, ptr: i32, len: i32) -> Option<String> {
+    if ptr < 0 || len < 0 {
+        return None;
+    }
+
+    let view = memory(ctx)?.view::<u8>();
+    let start = ptr as usize;
+    let end = start.checked_add(len as usize)?;
+    if end > view.len() {
+        return None;
+    }
+
+    let bytes: Vec<u8> = view[start..end].iter().map(Cell::get).collect();
+    String::from_utf8(bytes).ok()
+}
+
+/// Copies `bytes` into memory allocated by the
+/// plugin's `feather_alloc` export, returning the pointer.
+pub fn write_to_plugin(instance: &Instance, bytes: &[u8]) -> Result<i32, PluginError> {
+    let alloc: Func<i32, i32> = instance
+        .func("feather_alloc")
+        .map_err(|_| PluginError::Trap(String::from("missing feather_alloc export")))?;
+
+    let ptr = alloc
+        .call(bytes.len() as i32)
+        .map_err(|e| PluginError::Trap(e.to_string()))?;
+
+    let view = match memory(instance.context()) {
+        Some(memory) => memory.view::<u8>(),
+        None => return Err(PluginError::Trap(String::from("plugin has no memory"))),
+    };
+    let start = ptr as usize;
+    if ptr < 0 || start + bytes.len() > view.len() {
+        return Err(PluginError::Trap(String::from(
+            "feather_alloc returned an invalid pointer",
+        )));
+    }
+
+    for (cell, byte) in view[start..start + bytes.len()].iter().zip(bytes) {
+        cell.set(*byte);
+    }
+
+    Ok(ptr)
+}
+
+/// `env.gas(amount)`: consumes `amount` fuel, trapping
+/// if the plugin doesn't have that much left.
+fn gas(ctx: &mut Ctx, amount: i32) -> Result<(), OutOfFuel> {
+    let state = state(ctx).ok_or(OutOfFuel)?;
+    match state.fuel.checked_sub(amount.max(0) as u64) {
+        Some(fuel) => {
+            state.fuel = fuel;
+            Ok(())
+        }
+        None => {
+            state.fuel = 0;
+            Err(OutOfFuel)
+        }
+    }
+}
+
+/// `feather_log(ptr, len)`: writes a message to the server log.
+/// Requires no capabilities.
+fn log(ctx: &mut Ctx, ptr: i32, len: i32) -> i32 {
+    match read_string(ctx, ptr, len) {
+        Some(message) => {
+            info!("[plugin] {}", message);
+            0
+        }
+        None => ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// `feather_block_at(x, y, z)`: returns the native
+/// state ID of the block at the given position.
+/// Requires `read_blocks`.
+fn block_at(ctx: &mut Ctx, x: i32, y: i32, z: i32) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::READ_BLOCKS) {
+        return ERR_PERMISSION_DENIED;
+    }
+    let chunk_map = match state.chunk_map.as_ref() {
+        Some(chunk_map) => chunk_map,
+        None => return ERR_NO_CONTEXT,
+    };
+
+    match chunk_map.block_at(BlockPosition::new(x, y, z)) {
+        Some(block) => i32::from(block.native_state_id()),
+        None => ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// `feather_block_light_at(x, y, z)`: returns the block
+/// light at the given position. Requires `read_blocks`.
+fn block_light_at(ctx: &mut Ctx, x: i32, y: i32, z: i32) -> i32 {
+    read_light(ctx, |chunk_map| {
+        chunk_map.block_light_at(BlockPosition::new(x, y, z))
+    })
+}
+
+/// `feather_sky_light_at(x, y, z)`: returns the sky light at
+/// the given position, ignoring the time of day.
+/// Requires `read_blocks`.
+fn sky_light_at(ctx: &mut Ctx, x: i32, y: i32, z: i32) -> i32 {
+    read_light(ctx, |chunk_map| {
+        chunk_map.sky_light_at(BlockPosition::new(x, y, z))
+    })
+}
+
+/// `feather_light_level_at(x, y, z, time_of_day)`: returns the
+/// combined block and sky light at the given position at the
+/// given time of day. Requires `read_blocks`.
+fn light_level_at(ctx: &mut Ctx, x: i32, y: i32, z: i32, time_of_day: i32) -> i32 {
+    if time_of_day < 0 {
+        return ERR_INVALID_ARGUMENT;
+    }
+    read_light(ctx, |chunk_map| {
+        chunk_map.light_level_at(BlockPosition::new(x, y, z), time_of_day as u64)
+    })
+}
+
+/// `feather_biome_at(x, z)`: returns the protocol ID of the
+/// biome of the given column. Requires `read_blocks`.
+fn biome_at(ctx: &mut Ctx, x: i32, z: i32) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::READ_BLOCKS) {
+        return ERR_PERMISSION_DENIED;
+    }
+    let chunk_map = match state.chunk_map.as_ref() {
+        Some(chunk_map) => chunk_map,
+        None => return ERR_NO_CONTEXT,
+    };
+
+    match chunk_map.biome_at(BlockPosition::new(x, 0, z)) {
+        Some(biome) => biome.protocol_id(),
+        None => ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// Implements the light queries, which require `read_blocks`.
+fn read_light(ctx: &mut Ctx, query: impl FnOnce(&ChunkMap) -> Option<u8>) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::READ_BLOCKS) {
+        return ERR_PERMISSION_DENIED;
+    }
+
+    match state.chunk_map.as_ref() {
+        Some(chunk_map) => query(chunk_map).map_or(ERR_INVALID_ARGUMENT, i32::from),
+        None => ERR_NO_CONTEXT,
+    }
+}
+
+/// `feather_set_block_at(x, y, z, state_id)`: sets the block
+/// at the given position. Requires `write_blocks`.
+fn set_block_at(ctx: &mut Ctx, x: i32, y: i32, z: i32, state_id: i32) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::WRITE_BLOCKS) {
+        return ERR_PERMISSION_DENIED;
+    }
+
+    let new = match block_from_state_id(state_id) {
+        Some(block) => block,
+        None => return ERR_INVALID_ARGUMENT,
+    };
+
+    let chunk_map = match state.chunk_map.as_mut() {
+        Some(chunk_map) => chunk_map,
+        None => return ERR_NO_CONTEXT,
+    };
+
+    let pos = BlockPosition::new(x, y, z);
+    let old = match chunk_map.block_at(pos) {
+        Some(block) => block,
+        None => return ERR_INVALID_ARGUMENT,
+    };
+
+    if chunk_map.set_block_at(pos, new).is_err() {
+        return ERR_INVALID_ARGUMENT;
+    }
+    state.actions.push(PluginAction::SetBlock { pos, old, new });
+
+    0
+}
+
+/// `feather_broadcast_message(ptr, len)`: broadcasts a chat
+/// message to all players. Requires `send_messages`.
+fn broadcast_message(ctx: &mut Ctx, ptr: i32, len: i32) -> i32 {
+    let message = match read_string(ctx, ptr, len) {
+        Some(message) => message,
+        None => return ERR_INVALID_ARGUMENT,
+    };
+
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::SEND_MESSAGES) {
+        return ERR_PERMISSION_DENIED;
+    }
+
+    state.actions.push(PluginAction::BroadcastMessage(message));
+    0
+}
+
+/// `feather_schedule_task(task, ticks, repeating)`: schedules a
+/// call to the plugin's `feather_on_task(task)` export after `ticks`
+/// ticks, or every `ticks` ticks if `repeating` is nonzero. `task` is
+/// chosen by the plugin; scheduling a task with the same ID as a pending
+/// task replaces it. Requires no capabilities.
+fn schedule_task(ctx: &mut Ctx, task: i32, ticks: i32, repeating: i32) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if ticks < 0 {
+        return ERR_INVALID_ARGUMENT;
+    }
+
+    state.actions.push(PluginAction::ScheduleTask {
+        plugin: state.plugin.to_string(),
+        task,
+        ticks: ticks as u64,
+        repeating: repeating != 0,
+    });
+    0
+}
+
+/// `feather_cancel_task(task)`: cancels a task scheduled
+/// with `feather_schedule_task`. Requires no capabilities.
+fn cancel_task(ctx: &mut Ctx, task: i32) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+
+    state.actions.push(PluginAction::CancelTask {
+        plugin: state.plugin.to_string(),
+        task,
+    });
+    0
+}
+
+/// `feather_fill(x1, y1, z1, x2, y2, z2, state_id)`: sets all blocks
+/// in the region between two corners. The edit is performed after the
+/// callback returns and can be undone with `feather_undo`. Requires
+/// `write_blocks`.
+#[allow(clippy::too_many_arguments)]
+fn fill(ctx: &mut Ctx, x1: i32, y1: i32, z1: i32, x2: i32, y2: i32, z2: i32, state_id: i32) -> i32 {
+    let block = match block_from_state_id(state_id) {
+        Some(block) => block,
+        None => return ERR_INVALID_ARGUMENT,
+    };
+    let region = region(x1, y1, z1, x2, y2, z2);
+    edit(ctx, Operation::Fill { region, block })
+}
+
+/// `feather_replace(x1, y1, z1, x2, y2, z2, from, to)`: replaces
+/// all blocks with state `from` in the region with `to`. Like
+/// `feather_fill`, requires `write_blocks`.
+#[allow(clippy::too_many_arguments)]
+fn replace(
+    ctx: &mut Ctx,
+    x1: i32,
+    y1: i32,
+    z1: i32,
+    x2: i32,
+    y2: i32,
+    z2: i32,
+    from: i32,
+    to: i32,
+) -> i32 {
+    let (from, to) = match (block_from_state_id(from), block_from_state_id(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return ERR_INVALID_ARGUMENT,
+    };
+    let region = region(x1, y1, z1, x2, y2, z2);
+    edit(ctx, Operation::Replace { region, from, to })
+}
+
+/// `feather_copy(x1, y1, z1, x2, y2, z2)`: copies the region
+/// to the plugin's clipboard, relative to its first corner.
+/// Requires `write_blocks`.
+fn copy(ctx: &mut Ctx, x1: i32, y1: i32, z1: i32, x2: i32, y2: i32, z2: i32) -> i32 {
+    let region = region(x1, y1, z1, x2, y2, z2);
+    let origin = BlockPosition::new(x1, y1, z1);
+    edit(ctx, Operation::Copy { region, origin })
+}
+
+/// `feather_paste(x, y, z)`: pastes the plugin's clipboard
+/// with the first corner of the copied region at the given
+/// position. Requires `write_blocks`.
+fn paste(ctx: &mut Ctx, x: i32, y: i32, z: i32) -> i32 {
+    let origin = BlockPosition::new(x, y, z);
+    edit(ctx, Operation::Paste { origin })
+}
+
+/// `feather_undo()`: reverts the plugin's most recent
+/// fill, replace or paste. Requires `write_blocks`.
+fn undo(ctx: &mut Ctx) -> i32 {
+    edit(ctx, Operation::Undo)
+}
+
+/// Requests a `WorldEdit` operation on behalf of the plugin.
+fn edit(ctx: &mut Ctx, operation: Operation) -> i32 {
+    let state = match state(ctx) {
+        Some(state) => state,
+        None => return ERR_NO_CONTEXT,
+    };
+    if !state.capabilities.contains(Capabilities::WRITE_BLOCKS) {
+        return ERR_PERMISSION_DENIED;
+    }
+
+    state.actions.push(PluginAction::Edit {
+        plugin: state.plugin.to_string(),
+        operation,
+    });
+    0
+}
+
+fn region(x1: i32, y1: i32, z1: i32, x2: i32, y2: i32, z2: i32) -> Region {
+    Region::new(
+        BlockPosition::new(x1, y1, z1),
+        BlockPosition::new(x2, y2, z2),
+    )
+}
+
+fn block_from_state_id(state_id: i32) -> Option<Block> {
+    if state_id < 0 || state_id > i32::from(u16::max_value()) {
+        None
+    } else {
+        Block::from_native_state_id(state_id as u16)
+    }
+}
@@ -0,0 +1,916 @@
+//! Sandboxed WebAssembly plugin host.
+//!
+//! Plugins are WebAssembly modules placed in the `plugins` directory.
+//! Since they are compiled to WebAssembly, plugins can be written in
+//! any language with a WebAssembly target.
+//!
+//! Each `<name>.wasm` module may be accompanied by a `<name>.toml`
+//! manifest declaring which capabilities the plugin is granted:
+//!
+//! ```toml
+//! capabilities = ["read_blocks", "write_blocks", "send_messages", "listen_events"]
+//! ```
+//!
+//! A plugin without a manifest is granted no capabilities. Plugins
+//! can only interact with the server through the host functions in the
+//! `feather` import module (see `host`), and each host function checks
+//! for the corresponding capability before doing anything.
+//!
+//! A trap inside a plugin—for example, an out-of-bounds memory access
+//! or a panic compiled to `unreachable`—disables and unloads the plugin
+//! rather than bringing down the server.
+//!
+//! An instruction counter is injected into each plugin when it is loaded.
+//! A callback which executes more than `MAX_INSTRUCTIONS` instructions is
+//! stopped, and the plugin is unloaded, so that a plugin stuck in a loop
+//! can't hang the server. Since plugin code can only run during server
+//! callbacks, modules with a start function fail to load.
+//!
+//! # Plugin exports
+//! * `feather_on_enable()` - optional; called once after the plugin is loaded.
+//! * `feather_alloc(len: i32) -> i32` - required for event delivery. Allocates
+//! `len` bytes in the plugin's memory and returns a pointer to them.
+//! * `feather_on_event(kind: i32, ptr: i32, len: i32)` - optional; called for
+//! each event if the plugin has the `listen_events` capability. `kind` is one
+//! of the `EVENT_*` constants, and the payload is a UTF-8 JSON object.
+//! * `feather_on_cancellable_event(kind: i32, ptr: i32, len: i32) -> i32` - optional;
+//! called from the `EventBus` before the server applies a cancellable event if the
+//! plugin has the `listen_events` capability. `kind` is `EVENT_PLAYER_LOGIN` or one
+//! of the following constants. Returning a nonzero value cancels the event.
+//! * `feather_on_task(task: i32)` - optional; called when a task scheduled
+//! using `feather_schedule_task` is due.
+//!
+//! Cancellable events are emitted while the world is held by another
+//! system, so host functions reading or writing blocks return
+//! `ERR_NO_CONTEXT` during `feather_on_cancellable_event`. Other
+//! requests, such as messages and edits, are applied on the next tick.
+
+mod host;
+
+pub use host::PluginAction;
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::entity::NamedComponent;
+use crate::event::{
+    BlockBreakEvent, BlockPlaceEvent, ChatEvent, EntityDamageEvent, Event, EventBus, EventPriority,
+    PlayerLoginEvent,
+};
+use crate::joinhandler::PlayerJoinEvent;
+use crate::player::{ChatBroadcastEvent, PlayerDisconnectEvent};
+use crate::scheduler::{Scheduler, TaskId};
+use crate::systems::PLUGINS;
+use crate::timings::DispatcherBuilderExt;
+use crate::worldedit::{Editor, WorldEdit};
+use feather_core::world::ChunkMap;
+use feather_core::BlockExt;
+use hashbrown::HashMap;
+use host::HostState;
+use parking_lot::Mutex;
+use serde_json::Value;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Read, ReadStorage, System, World, Write};
+use std::ffi::c_void;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use wasmer_runtime::{Func, Instance};
+
+/// The directory from which plugins are loaded.
+pub const PLUGIN_DIR: &str = "plugins";
+
+/// The maximum number of instructions a plugin
+/// may execute during a single callback.
+pub const MAX_INSTRUCTIONS: u64 = 50_000_000;
+
+/// Event kind passed to `feather_on_event` when a player joins.
+pub const EVENT_PLAYER_JOIN: i32 = 0;
+/// Event kind passed to `feather_on_event` when a player leaves.
+pub const EVENT_PLAYER_LEAVE: i32 = 1;
+/// Event kind passed to `feather_on_event` when a block changes.
+pub const EVENT_BLOCK_UPDATE: i32 = 2;
+/// Cancellable event kind for a `PlayerLoginEvent`.
+pub const EVENT_PLAYER_LOGIN: i32 = 3;
+/// Cancellable event kind for a `ChatEvent`.
+pub const EVENT_CHAT: i32 = 4;
+/// Cancellable event kind for a `BlockBreakEvent`.
+pub const EVENT_BLOCK_BREAK: i32 = 5;
+/// Cancellable event kind for a `BlockPlaceEvent`.
+pub const EVENT_BLOCK_PLACE: i32 = 6;
+/// Cancellable event kind for an `EntityDamageEvent`.
+pub const EVENT_ENTITY_DAMAGE: i32 = 7;
+
+bitflags! {
+    /// The set of capabilities granted to a plugin.
+    #[derive(Default)]
+    pub struct Capabilities: u32 {
+        /// Allows reading blocks from the world.
+        const READ_BLOCKS = 0x01;
+        /// Allows modifying blocks in the world.
+        const WRITE_BLOCKS = 0x02;
+        /// Allows broadcasting chat messages.
+        const SEND_MESSAGES = 0x04;
+        /// Allows receiving events through `feather_on_event`.
+        const LISTEN_EVENTS = 0x08;
+    }
+}
+
+/// A capability as written in a plugin manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Capability {
+    ReadBlocks,
+    WriteBlocks,
+    SendMessages,
+    ListenEvents,
+}
+
+impl From<Capability> for Capabilities {
+    fn from(capability: Capability) -> Self {
+        match capability {
+            Capability::ReadBlocks => Capabilities::READ_BLOCKS,
+            Capability::WriteBlocks => Capabilities::WRITE_BLOCKS,
+            Capability::SendMessages => Capabilities::SEND_MESSAGES,
+            Capability::ListenEvents => Capabilities::LISTEN_EVENTS,
+        }
+    }
+}
+
+/// The contents of a plugin's `<name>.toml` manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    capabilities: Vec<Capability>,
+}
+
+impl Manifest {
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+            .iter()
+            .fold(Capabilities::empty(), |acc, cap| acc | (*cap).into())
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum PluginError {
+    #[fail(display = "failed to read plugin file: {}", _0)]
+    Io(#[fail(cause)] std::io::Error),
+    #[fail(display = "invalid plugin manifest: {}", _0)]
+    Manifest(#[fail(cause)] toml::de::Error),
+    #[fail(display = "failed to instantiate plugin: {}", _0)]
+    Instantiate(String),
+    #[fail(display = "plugin trapped: {}", _0)]
+    Trap(String),
+    #[fail(display = "plugin exceeded its instruction limit")]
+    OutOfFuel,
+}
+
+/// A loaded plugin.
+pub struct Plugin {
+    /// The name of this plugin, taken from its file name.
+    name: String,
+    capabilities: Capabilities,
+    instance: Instance,
+    /// Whether the module defines a linear memory.
+    has_memory: bool,
+    /// The number of instructions the plugin may
+    /// execute during a single callback.
+    max_instructions: u64,
+    /// Set when the plugin has trapped. Disabled plugins
+    /// are never called again and are unloaded.
+    disabled: bool,
+}
+
+impl Plugin {
+    /// Instantiates a plugin from the given WebAssembly
+    /// bytes, injecting the instruction counter.
+    pub fn new(name: String, wasm: &[u8], capabilities: Capabilities) -> Result<Self, PluginError> {
+        let module = parity_wasm::deserialize_buffer(wasm)
+            .map_err(|e| PluginError::Instantiate(e.to_string()))?;
+        let module =
+            pwasm_utils::inject_gas_counter(module, &pwasm_utils::rules::Set::default())
+                .map_err(|_| PluginError::Instantiate(String::from("failed to meter module")))?;
+        let wasm =
+            parity_wasm::serialize(module).map_err(|e| PluginError::Instantiate(e.to_string()))?;
+
+        let module =
+            wasmer_runtime::compile(&wasm).map_err(|e| PluginError::Instantiate(e.to_string()))?;
+        let info = module.info();
+        let has_memory = !info.memories.is_empty() || !info.imported_memories.is_empty();
+        let instance = module
+            .instantiate(&host::imports())
+            .map_err(|e| PluginError::Instantiate(e.to_string()))?;
+
+        Ok(Self {
+            name,
+            capabilities,
+            instance,
+            has_memory,
+            max_instructions: MAX_INSTRUCTIONS,
+            disabled: false,
+        })
+    }
+
+    /// Loads the plugin `<name>.wasm` and its manifest
+    /// from the given directory.
+    pub fn load(dir: &Path, name: &str) -> Result<Self, PluginError> {
+        let wasm = fs::read(dir.join(format!("{}.wasm", name))).map_err(PluginError::Io)?;
+
+        let manifest_path = dir.join(format!("{}.toml", name));
+        let manifest = if manifest_path.exists() {
+            let manifest = fs::read_to_string(manifest_path).map_err(PluginError::Io)?;
+            toml::from_str(&manifest).map_err(PluginError::Manifest)?
+        } else {
+            Manifest::default()
+        };
+
+        Self::new(name.to_string(), &wasm, manifest.capabilities())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Calls `feather_on_enable`, if the plugin exports it.
+    fn enable(&mut self, chunk_map: &mut ChunkMap, actions: &mut Vec<PluginAction>) {
+        let result = self.with_host(Some(chunk_map), actions, |instance| {
+            match instance.func::<(), ()>("feather_on_enable") {
+                Ok(func) => func.call().map_err(|e| PluginError::Trap(e.to_string())),
+                Err(_) => Ok(()),
+            }
+        });
+        self.handle_result(result);
+    }
+
+    /// Delivers an event to the plugin, if it is allowed
+    /// to listen to events and exports `feather_on_event`.
+    fn deliver_event(
+        &mut self,
+        kind: i32,
+        payload: &str,
+        chunk_map: &mut ChunkMap,
+        actions: &mut Vec<PluginAction>,
+    ) {
+        if !self.capabilities.contains(Capabilities::LISTEN_EVENTS) {
+            return;
+        }
+
+        let result = self.with_host(Some(chunk_map), actions, |instance| {
+            let on_event: Func<(i32, i32, i32), ()> = match instance.func("feather_on_event") {
+                Ok(func) => func,
+                Err(_) => return Ok(()),
+            };
+
+            let ptr = host::write_to_plugin(instance, payload.as_bytes())?;
+            on_event
+                .call(kind, ptr, payload.len() as i32)
+                .map_err(|e| PluginError::Trap(e.to_string()))
+        });
+        self.handle_result(result);
+    }
+
+    /// Delivers a cancellable event to the plugin, if it is allowed
+    /// to listen to events and exports `feather_on_cancellable_event`.
+    /// Returns whether the plugin cancelled the event.
+    fn deliver_cancellable_event(
+        &mut self,
+        kind: i32,
+        payload: &str,
+        actions: &mut Vec<PluginAction>,
+    ) -> bool {
+        if !self.capabilities.contains(Capabilities::LISTEN_EVENTS) {
+            return false;
+        }
+
+        let mut cancel = false;
+        let result = self.with_host(None, actions, |instance| {
+            let on_event: Func<(i32, i32, i32), i32> =
+                match instance.func("feather_on_cancellable_event") {
+                    Ok(func) => func,
+                    Err(_) => return Ok(()),
+                };
+
+            let ptr = host::write_to_plugin(instance, payload.as_bytes())?;
+            let result = on_event
+                .call(kind, ptr, payload.len() as i32)
+                .map_err(|e| PluginError::Trap(e.to_string()))?;
+            cancel = result != 0;
+            Ok(())
+        });
+        self.handle_result(result);
+
+        cancel
+    }
+
+    /// Calls `feather_on_task`, if the plugin exports it.
+    fn run_task(&mut self, task: i32, chunk_map: &mut ChunkMap, actions: &mut Vec<PluginAction>) {
+        let result = self.with_host(Some(chunk_map), actions, |instance| {
+            match instance.func::<i32, ()>("feather_on_task") {
+                Ok(func) => func
+                    .call(task)
+                    .map_err(|e| PluginError::Trap(e.to_string())),
+                Err(_) => Ok(()),
+            }
+        });
+        self.handle_result(result);
+    }
+
+    /// Runs `f`, making the host state available
+    /// to host functions invoked during the call.
+    fn with_host<F>(
+        &mut self,
+        chunk_map: Option<&mut ChunkMap>,
+        actions: &mut Vec<PluginAction>,
+        f: F,
+    ) -> Result<(), PluginError>
+    where
+        F: FnOnce(&Instance) -> Result<(), PluginError>,
+    {
+        let mut state = HostState {
+            plugin: &self.name,
+            capabilities: self.capabilities,
+            has_memory: self.has_memory,
+            fuel: self.max_instructions,
+            chunk_map,
+            actions,
+        };
+
+        // Safety: the pointer is only dereferenced by host functions,
+        // which can only be invoked during the call below. It is reset
+        // before `state` goes out of scope.
+        self.instance.context_mut().data = &mut state as *mut HostState as *mut c_void;
+        let result = f(&self.instance);
+        self.instance.context_mut().data = std::ptr::null_mut();
+
+        match result {
+            Err(PluginError::Trap(_)) if state.fuel == 0 => Err(PluginError::OutOfFuel),
+            result => result,
+        }
+    }
+
+    fn handle_result(&mut self, result: Result<(), PluginError>) {
+        if let Err(e) = result {
+            error!("Plugin {} failed and has been unloaded: {}", self.name, e);
+            self.disabled = true;
+        }
+    }
+}
+
+/// Resource containing all loaded plugins.
+#[derive(Default)]
+pub struct Plugins(Vec<Plugin>);
+
+impl Plugins {
+    /// Loads all plugins in the given directory. Plugins
+    /// which fail to load are skipped with an error message.
+    pub fn load_from_dir(dir: &Path) -> Self {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Self::default(), // No plugins directory
+        };
+
+        let mut plugins = vec![];
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("wasm") {
+                continue;
+            }
+
+            let name = continue_if_none!(path.file_stem().and_then(|stem| stem.to_str()));
+
+            match Plugin::load(dir, name) {
+                Ok(plugin) => {
+                    info!(
+                        "Loaded plugin {} with capabilities {:?}",
+                        plugin.name(),
+                        plugin.capabilities()
+                    );
+                    plugins.push(plugin);
+                }
+                Err(e) => error!("Failed to load plugin {}: {}", name, e),
+            }
+        }
+
+        Self(plugins)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plugin> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The loaded plugins, shared between the plugin
+/// system and the plugins' `EventBus` handlers.
+#[derive(Default)]
+struct SharedPlugins {
+    plugins: Plugins,
+    /// Actions requested while handling cancellable
+    /// events, applied by the next run of the plugin system.
+    actions: Vec<PluginAction>,
+    /// Whether `feather_on_enable` has been called yet.
+    enabled: bool,
+}
+
+// Safety: WebAssembly instances aren't `Send` since their contexts
+// contain raw pointers. The instances are owned by `SharedPlugins`
+// and only accessed while its mutex is held, so no two threads
+// ever access an instance at the same time.
+unsafe impl Send for SharedPlugins {}
+
+/// A cancellable event which is delivered to plugins
+/// through `feather_on_cancellable_event`.
+trait PluginEvent: Event {
+    /// The event kind passed to the plugin.
+    const KIND: i32;
+
+    /// Returns the JSON payload passed to the plugin.
+    fn payload(&self) -> Value;
+}
+
+impl PluginEvent for PlayerLoginEvent {
+    const KIND: i32 = EVENT_PLAYER_LOGIN;
+
+    fn payload(&self) -> Value {
+        json!({
+            "entity": self.player.id(),
+            "username": self.username,
+        })
+    }
+}
+
+impl PluginEvent for ChatEvent {
+    const KIND: i32 = EVENT_CHAT;
+
+    fn payload(&self) -> Value {
+        json!({
+            "entity": self.player.id(),
+            "message": self.message,
+        })
+    }
+}
+
+impl PluginEvent for BlockBreakEvent {
+    const KIND: i32 = EVENT_BLOCK_BREAK;
+
+    fn payload(&self) -> Value {
+        json!({
+            "entity": self.player.id(),
+            "x": self.pos.x,
+            "y": self.pos.y,
+            "z": self.pos.z,
+            "state": self.block.native_state_id(),
+        })
+    }
+}
+
+impl PluginEvent for BlockPlaceEvent {
+    const KIND: i32 = EVENT_BLOCK_PLACE;
+
+    fn payload(&self) -> Value {
+        json!({
+            "entity": self.player.id(),
+            "x": self.pos.x,
+            "y": self.pos.y,
+            "z": self.pos.z,
+            "state": self.block.native_state_id(),
+        })
+    }
+}
+
+impl PluginEvent for EntityDamageEvent {
+    const KIND: i32 = EVENT_ENTITY_DAMAGE;
+
+    fn payload(&self) -> Value {
+        json!({
+            "entity": self.entity.id(),
+            "damager": self.damager.map(|damager| damager.id()),
+            "damage": self.damage,
+        })
+    }
+}
+
+/// Registers an `EventBus` handler which delivers
+/// events of type `E` to all enabled plugins.
+fn register_handler<E: PluginEvent>(bus: &mut EventBus, shared: &Arc<Mutex<SharedPlugins>>) {
+    let shared = Arc::clone(shared);
+    bus.register(EventPriority::Normal, move |event: &mut E| {
+        let mut shared = shared.lock();
+        if !shared.enabled {
+            return;
+        }
+
+        let payload = event.payload().to_string();
+        let SharedPlugins {
+            plugins, actions, ..
+        } = &mut *shared;
+        for plugin in plugins.0.iter_mut() {
+            if !plugin.is_disabled() && plugin.deliver_cancellable_event(E::KIND, &payload, actions)
+            {
+                event.set_cancelled(true);
+            }
+        }
+    });
+}
+
+/// Event triggered by the scheduler when a
+/// task scheduled by a plugin is due.
+#[derive(Debug, Clone)]
+pub struct PluginTaskEvent {
+    pub plugin: String,
+    pub task: i32,
+}
+
+/// System which invokes plugins and applies the
+/// actions they requested.
+///
+/// WebAssembly instances cannot be shared between threads,
+/// so this system must be added as a thread-local system.
+#[derive(Default)]
+pub struct PluginSystem {
+    shared: Arc<Mutex<SharedPlugins>>,
+    /// Pending tasks scheduled by plugins, keyed
+    /// by plugin name and plugin-chosen task ID.
+    tasks: HashMap<(String, i32), TaskId>,
+    join_reader: Option<ReaderId<PlayerJoinEvent>>,
+    leave_reader: Option<ReaderId<PlayerDisconnectEvent>>,
+    block_reader: Option<ReaderId<BlockUpdateEvent>>,
+    task_reader: Option<ReaderId<PluginTaskEvent>>,
+}
+
+impl PluginSystem {
+    pub fn with_plugins(plugins: Plugins) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(SharedPlugins {
+                plugins,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+}
+
+impl<'a> System<'a> for PluginSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, EventChannel<ChatBroadcastEvent>>,
+        Read<'a, EventChannel<PlayerJoinEvent>>,
+        Read<'a, EventChannel<PlayerDisconnectEvent>>,
+        ReadStorage<'a, NamedComponent>,
+        Write<'a, Scheduler>,
+        Read<'a, EventChannel<PluginTaskEvent>>,
+        Write<'a, WorldEdit>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut chunk_map,
+            mut block_events,
+            mut chat_events,
+            join_events,
+            leave_events,
+            nameds,
+            mut scheduler,
+            task_events,
+            mut world_edit,
+        ) = data;
+
+        // Collect events first so that each plugin receives them in the same order.
+        let mut events = vec![];
+        for event in join_events.read(self.join_reader.as_mut().unwrap()) {
+            let named = continue_if_none!(nameds.get(event.player));
+            let payload = json!({
+                "entity": event.player.id(),
+                "username": named.display_name,
+                "uuid": named.uuid.to_string(),
+            });
+            events.push((EVENT_PLAYER_JOIN, payload.to_string()));
+        }
+        for event in leave_events.read(self.leave_reader.as_mut().unwrap()) {
+            let payload = json!({
+                "entity": event.player.id(),
+                "uuid": event.uuid.to_string(),
+                "reason": event.reason,
+            });
+            events.push((EVENT_PLAYER_LEAVE, payload.to_string()));
+        }
+        for event in block_events.read(self.block_reader.as_mut().unwrap()) {
+            let payload = json!({
+                "x": event.pos.x,
+                "y": event.pos.y,
+                "z": event.pos.z,
+                "old_state": event.old_block.native_state_id(),
+                "new_state": event.new_block.native_state_id(),
+                "by_plugin": event.cause == BlockUpdateCause::Plugin,
+            });
+            events.push((EVENT_BLOCK_UPDATE, payload.to_string()));
+        }
+
+        let mut shared = self.shared.lock();
+        let mut actions = std::mem::take(&mut shared.actions);
+        let enable = !shared.enabled;
+        shared.enabled = true;
+
+        for plugin in shared.plugins.0.iter_mut() {
+            if enable && !plugin.is_disabled() {
+                plugin.enable(&mut chunk_map, &mut actions);
+            }
+
+            for (kind, payload) in &events {
+                if plugin.is_disabled() {
+                    break;
+                }
+                plugin.deliver_event(*kind, payload, &mut chunk_map, &mut actions);
+            }
+        }
+
+        for event in task_events.read(self.task_reader.as_mut().unwrap()) {
+            let key = (event.plugin.clone(), event.task);
+            if let Some(id) = self.tasks.get(&key) {
+                if !scheduler.is_scheduled(*id) {
+                    self.tasks.remove(&key);
+                }
+            }
+
+            let plugin = shared
+                .plugins
+                .0
+                .iter_mut()
+                .find(|plugin| plugin.name == event.plugin);
+            let plugin = continue_if_none!(plugin);
+            if !plugin.is_disabled() {
+                plugin.run_task(event.task, &mut chunk_map, &mut actions);
+            }
+        }
+
+        // Unload plugins which failed, along with their tasks.
+        for plugin in shared
+            .plugins
+            .0
+            .iter()
+            .filter(|plugin| plugin.is_disabled())
+        {
+            self.tasks.retain(|(owner, _), id| {
+                if *owner == plugin.name {
+                    scheduler.cancel(*id);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        shared.plugins.0.retain(|plugin| !plugin.is_disabled());
+        drop(shared);
+
+        // Apply requested actions.
+        for action in actions {
+            match action {
+                PluginAction::SetBlock { pos, old, new } => {
+                    block_events.single_write(BlockUpdateEvent {
+                        cause: BlockUpdateCause::Plugin,
+                        pos,
+                        old_block: old,
+                        new_block: new,
+                    });
+                }
+                PluginAction::BroadcastMessage(text) => {
+                    let message = json!({ "text": text }).to_string();
+                    chat_events.single_write(ChatBroadcastEvent { message });
+                }
+                PluginAction::ScheduleTask {
+                    plugin,
+                    task,
+                    ticks,
+                    repeating,
+                } => {
+                    let event = PluginTaskEvent {
+                        plugin: plugin.clone(),
+                        task,
+                    };
+                    let id = if repeating {
+                        scheduler.schedule_repeating(ticks, move |world| {
+                            world
+                                .fetch_mut::<EventChannel<PluginTaskEvent>>()
+                                .single_write(event.clone());
+                        })
+                    } else {
+                        scheduler.schedule_delayed(ticks, move |world| {
+                            world
+                                .fetch_mut::<EventChannel<PluginTaskEvent>>()
+                                .single_write(event);
+                        })
+                    };
+                    if let Some(old) = self.tasks.insert((plugin, task), id) {
+                        scheduler.cancel(old);
+                    }
+                }
+                PluginAction::CancelTask { plugin, task } => {
+                    if let Some(id) = self.tasks.remove(&(plugin, task)) {
+                        scheduler.cancel(id);
+                    }
+                }
+                PluginAction::Edit { plugin, operation } => {
+                    let editor = Editor::Plugin(plugin.clone());
+                    if let Err(e) = world_edit.apply(&mut chunk_map, &editor, operation) {
+                        warn!("Edit requested by plugin {} failed: {}", plugin, e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.join_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.leave_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.block_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.task_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+
+        let mut bus = world.entry::<EventBus>().or_insert_with(EventBus::default);
+        register_handler::<PlayerLoginEvent>(&mut bus, &self.shared);
+        register_handler::<ChatEvent>(&mut bus, &self.shared);
+        register_handler::<BlockBreakEvent>(&mut bus, &self.shared);
+        register_handler::<BlockPlaceEvent>(&mut bus, &self.shared);
+        register_handler::<EntityDamageEvent>(&mut bus, &self.shared);
+    }
+}
+
+/// Loads plugins and adds the plugin system to the dispatcher.
+pub fn init(dispatcher: &mut DispatcherBuilder) {
+    let plugins = Plugins::load_from_dir(Path::new(PLUGIN_DIR));
+    if !plugins.is_empty() {
+        info!("Loaded {} plugins", plugins.len());
+    }
+
+    dispatcher.add_timed_thread_local(PluginSystem::with_plugins(plugins), PLUGINS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, WorldExt};
+
+    /// The smallest valid WebAssembly module: just the magic and version.
+    const EMPTY_MODULE: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn test_manifest_capabilities() {
+        let manifest: Manifest =
+            toml::from_str(r#"capabilities = ["read_blocks", "listen_events"]"#).unwrap();
+
+        assert_eq!(
+            manifest.capabilities(),
+            Capabilities::READ_BLOCKS | Capabilities::LISTEN_EVENTS
+        );
+        assert_eq!(Manifest::default().capabilities(), Capabilities::empty());
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        assert!(toml::from_str::<Manifest>(r#"capabilities = ["root_access"]"#).is_err());
+    }
+
+    #[test]
+    fn test_load_plugin() {
+        let plugin = Plugin::new(
+            String::from("test"),
+            EMPTY_MODULE,
+            Capabilities::LISTEN_EVENTS,
+        )
+        .unwrap();
+        assert_eq!(plugin.name(), "test");
+        assert!(!plugin.is_disabled());
+
+        assert!(Plugin::new(String::from("bad"), &[0, 1, 2, 3], Capabilities::empty()).is_err());
+    }
+
+    #[test]
+    fn test_missing_exports() {
+        let mut plugin =
+            Plugin::new(String::from("test"), EMPTY_MODULE, Capabilities::all()).unwrap();
+
+        let mut chunk_map = ChunkMap::new();
+        let mut actions = vec![];
+        plugin.enable(&mut chunk_map, &mut actions);
+        plugin.deliver_event(EVENT_PLAYER_JOIN, "{}", &mut chunk_map, &mut actions);
+        plugin.run_task(0, &mut chunk_map, &mut actions);
+
+        // Plugins without exports are simply never called.
+        assert!(!plugin.is_disabled());
+        assert!(actions.is_empty());
+    }
+
+    /// Returns a module exporting `feather_on_enable` as the last
+    /// function, given its type, import, function and code sections.
+    fn module_with_enable(sections: &[&[u8]], enable_index: u8) -> Vec<u8> {
+        let mut wasm = EMPTY_MODULE.to_vec();
+        for section in &sections[..sections.len() - 1] {
+            wasm.extend_from_slice(section);
+        }
+        wasm.extend_from_slice(b"\x07\x15\x01\x11feather_on_enable\x00");
+        wasm.push(enable_index);
+        wasm.extend_from_slice(sections[sections.len() - 1]);
+        wasm
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        // (func (export "feather_on_enable") (loop (br 0)))
+        let wasm = module_with_enable(
+            &[
+                b"\x01\x04\x01\x60\x00\x00",
+                b"\x03\x02\x01\x00",
+                b"\x0a\x09\x01\x07\x00\x03\x40\x0c\x00\x0b\x0b",
+            ],
+            0,
+        );
+        let mut plugin = Plugin::new(String::from("test"), &wasm, Capabilities::all()).unwrap();
+        plugin.max_instructions = 10_000;
+
+        let mut chunk_map = ChunkMap::new();
+        let mut actions = vec![];
+        plugin.enable(&mut chunk_map, &mut actions);
+
+        assert!(plugin.is_disabled());
+    }
+
+    #[test]
+    fn test_no_memory() {
+        // (import "feather" "feather_log" (func (param i32 i32) (result i32)))
+        // (func (export "feather_on_enable") (drop (call 0 (i32.const 0) (i32.const 5))))
+        let wasm = module_with_enable(
+            &[
+                b"\x01\x0a\x02\x60\x02\x7f\x7f\x01\x7f\x60\x00\x00",
+                b"\x02\x17\x01\x07feather\x0bfeather_log\x00\x00",
+                b"\x03\x02\x01\x01",
+                b"\x0a\x0b\x01\x09\x00\x41\x00\x41\x05\x10\x00\x1a\x0b",
+            ],
+            1,
+        );
+        let mut plugin = Plugin::new(String::from("test"), &wasm, Capabilities::all()).unwrap();
+
+        let mut chunk_map = ChunkMap::new();
+        let mut actions = vec![];
+        plugin.enable(&mut chunk_map, &mut actions);
+
+        // The host function fails instead of accessing missing memory.
+        assert!(!plugin.is_disabled());
+    }
+
+    #[test]
+    fn test_cancellable_event() {
+        // (memory 1)
+        // (func (export "feather_alloc") (param i32) (result i32) (i32.const 0))
+        // (func (export "feather_on_cancellable_event")
+        //     (param i32 i32 i32) (result i32) (i32.const 1))
+        let mut wasm = EMPTY_MODULE.to_vec();
+        wasm.extend_from_slice(b"\x01\x0d\x02\x60\x01\x7f\x01\x7f");
+        wasm.extend_from_slice(b"\x60\x03\x7f\x7f\x7f\x01\x7f");
+        wasm.extend_from_slice(b"\x03\x03\x02\x00\x01");
+        wasm.extend_from_slice(b"\x05\x03\x01\x00\x01");
+        wasm.extend_from_slice(b"\x07\x30\x02\x0dfeather_alloc\x00\x00");
+        wasm.extend_from_slice(b"\x1cfeather_on_cancellable_event\x00\x01");
+        wasm.extend_from_slice(b"\x0a\x0b\x02\x04\x00\x41\x00\x0b");
+        wasm.extend_from_slice(b"\x04\x00\x41\x01\x0b");
+        let plugin = Plugin::new(String::from("test"), &wasm, Capabilities::LISTEN_EVENTS).unwrap();
+
+        let shared = Arc::new(Mutex::new(SharedPlugins {
+            plugins: Plugins(vec![plugin]),
+            actions: vec![],
+            enabled: true,
+        }));
+        let mut bus = EventBus::default();
+        register_handler::<ChatEvent>(&mut bus, &shared);
+
+        let mut world = World::new();
+        let mut event = ChatEvent {
+            player: world.create_entity().build(),
+            message: String::from("test"),
+            cancelled: false,
+        };
+        assert!(!bus.emit(&mut event));
+        assert!(!shared.lock().plugins.0[0].is_disabled());
+
+        // Plugins which may not listen to events can't cancel them.
+        shared.lock().plugins.0[0].capabilities = Capabilities::empty();
+        event.cancelled = false;
+        assert!(bus.emit(&mut event));
+    }
+}
@@ -0,0 +1,1410 @@
+//! Nether and End portals.
+//!
+//! A Nether portal is lit when fire is placed inside an obsidian
+//! frame with an interior of between 2x3 and 21x21 blocks, and is
+//! destroyed when any of its blocks or its frame is broken. An End
+//! portal is completed when the last eye of ender is inserted into
+//! a ring of twelve end portal frames facing inwards.
+//!
+//! Entities standing in a portal for long enough trigger a
+//! `PortalTravelEvent`. `PortalTravelSystem` then moves players
+//! into the first registered dimension with the destination's
+//! environment. Travelling to the End places players on its spawn
+//! platform, which is rebuilt each time, and leaving it at the world
+//! spawn. Nether portals link to the nearest portal around the scaled
+//! position, and a new portal is created if there is none.
+//!
+//! Only players travel through portals. Other entities still trigger
+//! `PortalTravelEvent`s, but stay where they are: dimensions other
+//! than the primary one aren't simulated, so they couldn't exist there.
+//!
+//! The chunks of the destination are often not loaded yet when a
+//! player arrives. In that case the player is placed at the scaled
+//! position or the End's spawn, and moved onto the portal or platform
+//! once the chunks have been loaded and it has been created.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::dimension::{
+    DimensionChangeEvent, DimensionComponent, DimensionId, Dimensions, PRIMARY_DIMENSION,
+};
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::{PORTAL_BLOCKS, PORTAL_TIMER, PORTAL_TRAVEL};
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::{
+    EndPortalFrameData, EndPortalFrameFacing, NetherPortalAxis, NetherPortalData,
+};
+use feather_core::level::LevelData;
+use feather_core::network::packet::implementation::{
+    BlockChange, PlayerPositionAndLookClientbound,
+};
+use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition, Position};
+use feather_core::{Block, BlockExt, Dimension, Gamemode};
+use hashbrown::HashSet;
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DispatcherBuilder, Entities, Entity, HashMapStorage, Join, Read, ReadExpect,
+    ReadStorage, System, Write, WriteExpect, WriteStorage,
+};
+
+/// The minimum width of the interior of a Nether portal.
+pub const MIN_PORTAL_WIDTH: i32 = 2;
+/// The minimum height of the interior of a Nether portal.
+pub const MIN_PORTAL_HEIGHT: i32 = 3;
+/// The maximum width and height of the interior of a Nether portal.
+pub const MAX_PORTAL_SIZE: i32 = 21;
+
+/// The number of ticks a player in survival mode
+/// must stand in a Nether portal before travelling.
+pub const PORTAL_WAIT_TIME: u32 = 80;
+/// The number of ticks after travelling during which a player
+/// ignores portals. The cooldown only elapses outside of portals,
+/// so players don't travel back immediately.
+pub const PLAYER_PORTAL_COOLDOWN: u32 = 10;
+/// The portal cooldown of entities other than players.
+pub const ENTITY_PORTAL_COOLDOWN: u32 = 300;
+
+/// The horizontal distance from the scaled position
+/// within which an existing portal is linked to.
+pub const PORTAL_SEARCH_RADIUS: i32 = 128;
+/// The horizontal distance from the scaled position
+/// within which a new portal may be created.
+pub const PORTAL_CREATION_RADIUS: i32 = 16;
+
+/// The number of ticks for which the portal or platform at
+/// a destination whose chunks were not loaded when a player
+/// arrived is waited for before giving up.
+pub const PENDING_DESTINATION_TIMEOUT: u32 = 200;
+
+/// The factor by which horizontal coordinates
+/// are divided when travelling to the Nether.
+pub const NETHER_SCALE: f64 = 8.0;
+
+/// The position at which players arrive in the End.
+pub const END_SPAWN: Position = Position {
+    x: 100.5,
+    y: 49.0,
+    z: 0.5,
+    pitch: 0.0,
+    yaw: 0.0,
+    on_ground: true,
+};
+
+/// The kind of a portal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PortalKind {
+    Nether,
+    End,
+}
+
+impl PortalKind {
+    /// Returns the kind of portal the given block belongs to.
+    pub fn of(block: Block) -> Option<Self> {
+        match block {
+            Block::NetherPortal(_) => Some(PortalKind::Nether),
+            Block::EndPortal => Some(PortalKind::End),
+            _ => None,
+        }
+    }
+
+    /// Returns the dimension an entity in the
+    /// given dimension travels to through this portal.
+    pub fn destination(self, from: Dimension) -> Dimension {
+        match (self, from) {
+            (PortalKind::Nether, Dimension::Nether) => Dimension::Overwold,
+            (PortalKind::Nether, _) => Dimension::Nether,
+            (PortalKind::End, Dimension::End) => Dimension::Overwold,
+            (PortalKind::End, _) => Dimension::End,
+        }
+    }
+}
+
+/// Event triggered when an entity has stood in a portal
+/// for long enough to travel through it. Only players
+/// are moved by `PortalTravelSystem`.
+#[derive(Debug, Clone)]
+pub struct PortalTravelEvent {
+    pub entity: Entity,
+    pub kind: PortalKind,
+    /// The position of the entity in the portal.
+    pub position: Position,
+}
+
+/// Component tracking how long an entity has
+/// been inside a portal.
+#[derive(Debug, Clone, Default)]
+pub struct PortalComponent {
+    /// The number of ticks spent in the current portal.
+    pub ticks: u32,
+    /// The number of ticks until portals
+    /// can be used again.
+    pub cooldown: u32,
+}
+
+impl Component for PortalComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Converts a position in one dimension to the corresponding
+/// position in another. Horizontal coordinates are divided by
+/// eight in the Nether; the End is not scaled.
+pub fn scale_position(position: Position, from: Dimension, to: Dimension) -> Position {
+    let scale = match (from, to) {
+        (Dimension::Nether, Dimension::Overwold) => NETHER_SCALE,
+        (Dimension::Overwold, Dimension::Nether) => 1.0 / NETHER_SCALE,
+        _ => 1.0,
+    };
+    Position {
+        x: position.x * scale,
+        z: position.z * scale,
+        ..position
+    }
+}
+
+/// The interior of a Nether portal frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortalFrame {
+    /// The lowest interior block with the
+    /// smallest coordinate along `axis`.
+    pub corner: BlockPosition,
+    pub axis: NetherPortalAxis,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl PortalFrame {
+    /// Finds the obsidian frame enclosing the given
+    /// position, such as the position of placed fire.
+    pub fn find(chunk_map: &ChunkMap, pos: BlockPosition) -> Option<Self> {
+        Self::find_along(chunk_map, pos, NetherPortalAxis::X)
+            .or_else(|| Self::find_along(chunk_map, pos, NetherPortalAxis::Z))
+    }
+
+    fn find_along(
+        chunk_map: &ChunkMap,
+        pos: BlockPosition,
+        axis: NetherPortalAxis,
+    ) -> Option<Self> {
+        let block = |pos| chunk_map.block_at(pos);
+        let obsidian = |pos| block(pos) == Some(Block::Obsidian);
+        let inside = |pos| block(pos).map_or(false, can_contain_portal);
+
+        if !inside(pos) {
+            return None;
+        }
+
+        // Descend to the bottom of the frame.
+        let mut bottom = pos;
+        for _ in 0..MAX_PORTAL_SIZE {
+            if !inside(offset(bottom, axis, 0, -1)) {
+                break;
+            }
+            bottom = offset(bottom, axis, 0, -1);
+        }
+        if !obsidian(offset(bottom, axis, 0, -1)) {
+            return None;
+        }
+
+        // Find the corner, then measure the width along the floor.
+        let mut corner = bottom;
+        for _ in 0..MAX_PORTAL_SIZE {
+            let next = offset(corner, axis, -1, 0);
+            if !inside(next) || !obsidian(offset(next, axis, 0, -1)) {
+                break;
+            }
+            corner = next;
+        }
+        let mut width = 0;
+        while inside(offset(corner, axis, width, 0)) && obsidian(offset(corner, axis, width, -1)) {
+            width += 1;
+            if width > MAX_PORTAL_SIZE {
+                return None;
+            }
+        }
+        if width < MIN_PORTAL_WIDTH
+            || !obsidian(offset(corner, axis, -1, 0))
+            || !obsidian(offset(corner, axis, width, 0))
+        {
+            return None;
+        }
+
+        // Every row needs obsidian on both sides,
+        // until a row of obsidian closes the frame.
+        let mut height = 0;
+        loop {
+            if (0..width).all(|i| obsidian(offset(corner, axis, i, height))) {
+                break;
+            }
+            if height >= MAX_PORTAL_SIZE
+                || !(0..width).all(|i| inside(offset(corner, axis, i, height)))
+                || !obsidian(offset(corner, axis, -1, height))
+                || !obsidian(offset(corner, axis, width, height))
+            {
+                return None;
+            }
+            height += 1;
+        }
+        if height < MIN_PORTAL_HEIGHT {
+            return None;
+        }
+
+        Some(Self {
+            corner,
+            axis,
+            width,
+            height,
+        })
+    }
+
+    /// Returns the positions of the interior of the frame.
+    pub fn positions(&self) -> impl Iterator<Item = BlockPosition> + '_ {
+        (0..self.height)
+            .flat_map(move |dy| (0..self.width).map(move |i| offset(self.corner, self.axis, i, dy)))
+    }
+
+    /// Returns the portal block which fills the frame.
+    pub fn block(&self) -> Block {
+        Block::NetherPortal(NetherPortalData { axis: self.axis })
+    }
+}
+
+/// Returns whether a Nether portal can occupy
+/// the position of the given block.
+fn can_contain_portal(block: Block) -> bool {
+    match block {
+        Block::Air | Block::Fire(_) | Block::NetherPortal(_) => true,
+        _ => false,
+    }
+}
+
+/// Offsets a position along a portal axis and vertically.
+fn offset(pos: BlockPosition, axis: NetherPortalAxis, along: i32, up: i32) -> BlockPosition {
+    match axis {
+        NetherPortalAxis::X => BlockPosition::new(pos.x + along, pos.y + up, pos.z),
+        NetherPortalAxis::Z => BlockPosition::new(pos.x, pos.y + up, pos.z + along),
+    }
+}
+
+/// Returns the direction an end portal frame faces.
+fn facing_offset(facing: EndPortalFrameFacing) -> (i32, i32) {
+    match facing {
+        EndPortalFrameFacing::North => (0, -1),
+        EndPortalFrameFacing::South => (0, 1),
+        EndPortalFrameFacing::West => (-1, 0),
+        EndPortalFrameFacing::East => (1, 0),
+    }
+}
+
+/// Returns the center of the End portal completed by the
+/// frame at the given position, if all twelve frames of
+/// its ring contain an eye of ender.
+pub fn find_end_portal(chunk_map: &ChunkMap, pos: BlockPosition) -> Option<BlockPosition> {
+    let facing = match chunk_map.block_at(pos)? {
+        Block::EndPortalFrame(data) => data.facing,
+        _ => return None,
+    };
+    let (dx, dz) = facing_offset(facing);
+
+    // The frame may be at any of the three
+    // positions along its side of the ring.
+    (-1..=1)
+        .map(|k| BlockPosition::new(pos.x + 2 * dx + k * dz, pos.y, pos.z + 2 * dz + k * dx))
+        .find(|&center| {
+            let sides = [
+                EndPortalFrameFacing::North,
+                EndPortalFrameFacing::South,
+                EndPortalFrameFacing::West,
+                EndPortalFrameFacing::East,
+            ];
+            sides.iter().all(|&facing| {
+                let (dx, dz) = facing_offset(facing);
+                (-1..=1).all(|k| {
+                    let frame = BlockPosition::new(
+                        center.x - 2 * dx + k * dz,
+                        center.y,
+                        center.z - 2 * dz + k * dx,
+                    );
+                    chunk_map.block_at(frame)
+                        == Some(Block::EndPortalFrame(EndPortalFrameData {
+                            eye: true,
+                            facing,
+                        }))
+                })
+            })
+        })
+}
+
+/// Finds the Nether portal nearest to the given position
+/// in loaded chunks within a horizontal radius. Returns
+/// the lowest portal block of the column found.
+pub fn find_portal(
+    chunk_map: &ChunkMap,
+    near: BlockPosition,
+    radius: i32,
+) -> Option<BlockPosition> {
+    let mut nearest: Option<(i64, BlockPosition)> = None;
+
+    let (min, max) = (
+        BlockPosition::new(near.x - radius, 0, near.z - radius).chunk_pos(),
+        BlockPosition::new(near.x + radius, 0, near.z + radius).chunk_pos(),
+    );
+    for cx in min.x..=max.x {
+        for cz in min.z..=max.z {
+            let chunk = match chunk_map.chunk_at(ChunkPosition::new(cx, cz)) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            for index in 0..16 {
+                let section = match chunk.section(index) {
+                    Some(section) if !section.empty() => section,
+                    _ => continue,
+                };
+                for y in 0..16 {
+                    for z in 0..16 {
+                        for x in 0..16 {
+                            if PortalKind::of(section.block_at(x, y, z)) != Some(PortalKind::Nether)
+                            {
+                                continue;
+                            }
+
+                            let pos = BlockPosition::new(
+                                cx * 16 + x as i32,
+                                index as i32 * 16 + y as i32,
+                                cz * 16 + z as i32,
+                            );
+                            if (pos.x - near.x).abs() > radius || (pos.z - near.z).abs() > radius {
+                                continue;
+                            }
+                            let (dx, dy, dz) = (
+                                i64::from(pos.x - near.x),
+                                i64::from(pos.y - near.y),
+                                i64::from(pos.z - near.z),
+                            );
+                            let distance = dx * dx + dy * dy + dz * dz;
+                            if nearest.map_or(true, |(nearest, _)| distance < nearest) {
+                                nearest = Some((distance, pos));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut pos = nearest?.1;
+    while pos.y > 0
+        && PortalKind::of(chunk_map.block_at(BlockPosition::new(pos.x, pos.y - 1, pos.z))?)
+            == Some(PortalKind::Nether)
+    {
+        pos.y -= 1;
+    }
+    Some(pos)
+}
+
+/// Creates a Nether portal near the given position, returning
+/// the position of its lowest interior block. A portal is built
+/// on solid ground within `PORTAL_CREATION_RADIUS` if possible;
+/// otherwise it is built at `near` on an obsidian platform.
+/// Returns `None` if the chunks at the position are not loaded.
+///
+/// The blocks changed are appended to `changes`.
+pub fn create_portal(
+    chunk_map: &mut ChunkMap,
+    near: BlockPosition,
+    changes: &mut Vec<BlockUpdateEvent>,
+) -> Option<BlockPosition> {
+    let axis = NetherPortalAxis::X;
+    let mut best: Option<(i64, BlockPosition)> = None;
+
+    for dx in -PORTAL_CREATION_RADIUS..=PORTAL_CREATION_RADIUS {
+        for dz in -PORTAL_CREATION_RADIUS..=PORTAL_CREATION_RADIUS {
+            for y in (1..=(255 - MIN_PORTAL_HEIGHT - 2)).rev() {
+                let corner = BlockPosition::new(near.x + dx, y, near.z + dz);
+                if !fits_portal(chunk_map, corner, axis) {
+                    continue;
+                }
+
+                let dy = i64::from(y - near.y);
+                let distance = i64::from(dx * dx + dz * dz) + dy * dy;
+                if best.map_or(true, |(best, _)| distance < best) {
+                    best = Some((distance, corner));
+                }
+                break;
+            }
+        }
+    }
+
+    let corner = match best {
+        Some((_, corner)) => corner,
+        None => {
+            let corner = BlockPosition::new(near.x, near.y.max(70).min(245), near.z);
+            chunk_map.block_at(corner)?;
+            // Build a platform with room to
+            // step out on either side.
+            for along in -1..=MIN_PORTAL_WIDTH {
+                for side in -1..=1 {
+                    let floor = offset(side_offset(corner, axis, side), axis, along, -1);
+                    set_portal_block(chunk_map, floor, Block::Obsidian, changes)?;
+                    for up in 0..MIN_PORTAL_HEIGHT {
+                        let pos = offset(side_offset(corner, axis, side), axis, along, up);
+                        set_portal_block(chunk_map, pos, Block::Air, changes)?;
+                    }
+                }
+            }
+            corner
+        }
+    };
+
+    build_portal(chunk_map, corner, axis, changes)?;
+    Some(corner)
+}
+
+/// Builds the 5x5 obsidian platform players arrive on in
+/// the End and clears the space above it. Returns `None`
+/// if the chunks of the platform are not loaded.
+///
+/// The blocks changed are appended to `changes`.
+pub fn create_end_platform(
+    chunk_map: &mut ChunkMap,
+    changes: &mut Vec<BlockUpdateEvent>,
+) -> Option<()> {
+    let center = END_SPAWN.block_pos();
+    let positions = || {
+        (-2..=2).flat_map(move |dx| {
+            (-2..=2).map(move |dz| BlockPosition::new(center.x + dx, center.y, center.z + dz))
+        })
+    };
+
+    // The platform may span two chunks, so check
+    // both are loaded before building anything.
+    if positions().any(|pos| chunk_map.block_at(pos).is_none()) {
+        return None;
+    }
+
+    for pos in positions() {
+        for dy in -1..=2 {
+            let block = if dy == -1 {
+                Block::Obsidian
+            } else {
+                Block::Air
+            };
+            let pos = BlockPosition::new(pos.x, pos.y + dy, pos.z);
+            set_portal_block(chunk_map, pos, block, changes)?;
+        }
+    }
+    Some(())
+}
+
+/// Sets a block while creating a portal or platform,
+/// appending the change to `changes` if the block changed.
+fn set_portal_block(
+    chunk_map: &mut ChunkMap,
+    pos: BlockPosition,
+    block: Block,
+    changes: &mut Vec<BlockUpdateEvent>,
+) -> Option<()> {
+    let old_block = chunk_map.block_at(pos)?;
+    if old_block != block {
+        chunk_map.set_block_at(pos, block).ok()?;
+        changes.push(BlockUpdateEvent {
+            cause: BlockUpdateCause::Portal,
+            pos,
+            old_block,
+            new_block: block,
+        });
+    }
+    Some(())
+}
+
+/// Offsets a position perpendicular to a portal axis.
+fn side_offset(pos: BlockPosition, axis: NetherPortalAxis, side: i32) -> BlockPosition {
+    match axis {
+        NetherPortalAxis::X => BlockPosition::new(pos.x, pos.y, pos.z + side),
+        NetherPortalAxis::Z => BlockPosition::new(pos.x + side, pos.y, pos.z),
+    }
+}
+
+/// Returns whether a minimum-sized portal, including its
+/// frame, fits at the given corner on solid ground.
+fn fits_portal(chunk_map: &ChunkMap, corner: BlockPosition, axis: NetherPortalAxis) -> bool {
+    (-1..=MIN_PORTAL_WIDTH).all(|along| {
+        (-1..=1).all(|side| {
+            let base = side_offset(corner, axis, side);
+            let ground = chunk_map
+                .block_at(offset(base, axis, along, -1))
+                .map_or(false, |block| block.is_solid());
+            ground
+                && (0..=MIN_PORTAL_HEIGHT)
+                    .all(|up| chunk_map.block_at(offset(base, axis, along, up)) == Some(Block::Air))
+        })
+    })
+}
+
+/// Builds an obsidian frame with a lit portal
+/// of the minimum size at the given corner.
+fn build_portal(
+    chunk_map: &mut ChunkMap,
+    corner: BlockPosition,
+    axis: NetherPortalAxis,
+    changes: &mut Vec<BlockUpdateEvent>,
+) -> Option<()> {
+    let portal = Block::NetherPortal(NetherPortalData { axis });
+    for along in -1..=MIN_PORTAL_WIDTH {
+        for up in -1..=MIN_PORTAL_HEIGHT {
+            let frame =
+                along == -1 || along == MIN_PORTAL_WIDTH || up == -1 || up == MIN_PORTAL_HEIGHT;
+            let block = if frame { Block::Obsidian } else { portal };
+            set_portal_block(chunk_map, offset(corner, axis, along, up), block, changes)?;
+        }
+    }
+    Some(())
+}
+
+/// Returns the connected Nether portal blocks
+/// adjacent to the given position.
+fn connected_portal(chunk_map: &ChunkMap, pos: BlockPosition) -> Vec<BlockPosition> {
+    let neighbours = |pos: BlockPosition| {
+        vec![
+            BlockPosition::new(pos.x + 1, pos.y, pos.z),
+            BlockPosition::new(pos.x - 1, pos.y, pos.z),
+            BlockPosition::new(pos.x, pos.y + 1, pos.z),
+            BlockPosition::new(pos.x, pos.y - 1, pos.z),
+            BlockPosition::new(pos.x, pos.y, pos.z + 1),
+            BlockPosition::new(pos.x, pos.y, pos.z - 1),
+        ]
+    };
+    let is_portal =
+        |pos| chunk_map.block_at(pos).and_then(PortalKind::of) == Some(PortalKind::Nether);
+
+    let mut found = HashSet::new();
+    let mut stack: Vec<BlockPosition> = neighbours(pos)
+        .into_iter()
+        .filter(|&p| is_portal(p))
+        .collect();
+    while let Some(pos) = stack.pop() {
+        if found.len() >= (MAX_PORTAL_SIZE * MAX_PORTAL_SIZE) as usize || !found.insert(pos) {
+            continue;
+        }
+        stack.extend(neighbours(pos).into_iter().filter(|&p| is_portal(p)));
+    }
+    found.into_iter().collect()
+}
+
+/// System which counts how long entities have stood in
+/// portals and triggers `PortalTravelEvent`s.
+#[derive(Default)]
+pub struct PortalTimerSystem;
+
+impl<'a> System<'a> for PortalTimerSystem {
+    type SystemData = (
+        WriteStorage<'a, PortalComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        Read<'a, ChunkMap>,
+        ReadExpect<'a, Dimensions>,
+        ReadStorage<'a, DimensionComponent>,
+        Write<'a, EventChannel<PortalTravelEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut portals,
+            positions,
+            players,
+            chunk_map,
+            dimensions,
+            dimension_comps,
+            mut travel_events,
+            entities,
+        ) = data;
+
+        let mut finished = vec![];
+        for (entity, position, player, dimension) in (
+            &entities,
+            &positions,
+            players.maybe(),
+            dimension_comps.maybe(),
+        )
+            .join()
+        {
+            let chunk_map = dimensions
+                .world(DimensionComponent::of(dimension))
+                .map_or(&*chunk_map, |world| &world.chunk_map);
+            let kind = chunk_map
+                .block_at(position.current.block_pos())
+                .and_then(PortalKind::of);
+            let kind = match (kind, portals.get_mut(entity)) {
+                (Some(kind), _) => kind,
+                (None, Some(portal)) => {
+                    portal.ticks = 0;
+                    portal.cooldown = portal.cooldown.saturating_sub(1);
+                    if portal.cooldown == 0 {
+                        finished.push(entity);
+                    }
+                    continue;
+                }
+                (None, None) => continue,
+            };
+
+            let cooldown = if player.is_some() {
+                PLAYER_PORTAL_COOLDOWN
+            } else {
+                ENTITY_PORTAL_COOLDOWN
+            };
+            let wait_time = match (kind, player) {
+                (PortalKind::Nether, Some(player)) if player.gamemode != Gamemode::Creative => {
+                    PORTAL_WAIT_TIME
+                }
+                _ => 1,
+            };
+
+            let portal = match portals.entry(entity) {
+                Ok(entry) => entry.or_insert_with(PortalComponent::default),
+                Err(_) => continue,
+            };
+            if portal.cooldown > 0 {
+                portal.cooldown = cooldown;
+                continue;
+            }
+
+            portal.ticks += 1;
+            if portal.ticks >= wait_time {
+                portal.ticks = 0;
+                portal.cooldown = cooldown;
+                travel_events.single_write(PortalTravelEvent {
+                    entity,
+                    kind,
+                    position: position.current,
+                });
+            }
+        }
+
+        for entity in finished {
+            portals.remove(entity);
+        }
+    }
+}
+
+/// System which lights Nether portals when fire is placed in
+/// their frame, completes End portals when their last eye is
+/// inserted and destroys Nether portals when they are broken.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct PortalBlockSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for PortalBlockSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut chunk_map, mut block_events) = data;
+
+        let events: Vec<BlockUpdateEvent> = block_events
+            .read(self.reader.as_mut().unwrap())
+            .cloned()
+            .collect();
+
+        let mut changes = vec![];
+        for event in events {
+            match (event.old_block, event.new_block) {
+                (_, Block::Fire(_)) => {
+                    if let Some(frame) = PortalFrame::find(&chunk_map, event.pos) {
+                        changes.extend(frame.positions().map(|pos| (pos, frame.block())));
+                    }
+                }
+                (_, Block::EndPortalFrame(data)) if data.eye => {
+                    if let Some(center) = find_end_portal(&chunk_map, event.pos) {
+                        for dx in -1..=1 {
+                            for dz in -1..=1 {
+                                let pos =
+                                    BlockPosition::new(center.x + dx, center.y, center.z + dz);
+                                changes.push((pos, Block::EndPortal));
+                            }
+                        }
+                    }
+                }
+                (Block::Obsidian, new) | (Block::NetherPortal(_), new)
+                    if PortalKind::of(new) != Some(PortalKind::Nether) =>
+                {
+                    changes.extend(
+                        connected_portal(&chunk_map, event.pos)
+                            .into_iter()
+                            .map(|pos| (pos, Block::Air)),
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        for (pos, block) in changes {
+            let old_block = match chunk_map.block_at(pos) {
+                Some(old_block) if old_block != block => old_block,
+                _ => continue,
+            };
+            chunk_map.set_block_at(pos, block).unwrap();
+            block_events.single_write(BlockUpdateEvent {
+                cause: BlockUpdateCause::Portal,
+                pos,
+                old_block,
+                new_block: block,
+            });
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// Where a player travelling through a portal arrives.
+#[derive(Debug, Clone, Copy)]
+enum Destination {
+    /// At the portal nearest to the given scaled
+    /// position, which is created if there is none.
+    Portal(Position),
+    /// On the spawn platform of the End.
+    EndPlatform,
+}
+
+impl Destination {
+    /// Finds or creates the portal or platform at the
+    /// destination, returning the position players arrive
+    /// at, or `None` if the chunks there are not loaded.
+    fn prepare(
+        self,
+        chunk_map: &mut ChunkMap,
+        changes: &mut Vec<BlockUpdateEvent>,
+    ) -> Option<Position> {
+        match self {
+            Destination::Portal(scaled) => {
+                let near = scaled.block_pos();
+                let portal = find_portal(chunk_map, near, PORTAL_SEARCH_RADIUS)
+                    .or_else(|| create_portal(chunk_map, near, changes))?;
+                Some(Position {
+                    x: f64::from(portal.x) + 0.5,
+                    y: f64::from(portal.y),
+                    z: f64::from(portal.z) + 0.5,
+                    ..scaled
+                })
+            }
+            Destination::EndPlatform => {
+                create_end_platform(chunk_map, changes)?;
+                Some(END_SPAWN)
+            }
+        }
+    }
+
+    /// Returns the position players arrive at
+    /// before the destination has been prepared.
+    fn fallback(self) -> Position {
+        match self {
+            Destination::Portal(scaled) => scaled,
+            Destination::EndPlatform => END_SPAWN,
+        }
+    }
+}
+
+/// A destination which couldn't be prepared
+/// because its chunks were not loaded.
+#[derive(Debug)]
+struct PendingDestination {
+    entity: Entity,
+    dimension: DimensionId,
+    destination: Destination,
+    ticks: u32,
+}
+
+/// System which moves players travelling through
+/// portals into the destination dimension.
+///
+/// This system listens to `PortalTravelEvent`s.
+#[derive(Default)]
+pub struct PortalTravelSystem {
+    reader: Option<ReaderId<PortalTravelEvent>>,
+    pending: Vec<PendingDestination>,
+}
+
+impl<'a> System<'a> for PortalTravelSystem {
+    type SystemData = (
+        Read<'a, EventChannel<PortalTravelEvent>>,
+        Write<'a, EventChannel<DimensionChangeEvent>>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        WriteExpect<'a, Dimensions>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, PositionComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, ChunkMap>,
+        Read<'a, LevelData>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            travel_events,
+            mut change_events,
+            mut block_events,
+            mut dimensions,
+            dimension_comps,
+            players,
+            mut positions,
+            networks,
+            mut chunk_map,
+            level,
+        ) = data;
+
+        // Prepare the destinations of players who arrived
+        // before the chunks there were loaded.
+        for mut pending in std::mem::replace(&mut self.pending, vec![]) {
+            let entity = pending.entity;
+            if DimensionComponent::of(dimension_comps.get(entity)) != pending.dimension {
+                continue;
+            }
+            let (position, network) = match (positions.get_mut(entity), networks.get(entity)) {
+                (Some(position), Some(network)) => (position, network),
+                _ => continue,
+            };
+
+            let mut changes = vec![];
+            let arrival = match dimensions.world_mut(pending.dimension) {
+                Some(world) => pending
+                    .destination
+                    .prepare(&mut world.chunk_map, &mut changes),
+                None => pending.destination.prepare(&mut chunk_map, &mut changes),
+            };
+            let arrival = match arrival {
+                Some(arrival) => arrival,
+                None => {
+                    pending.ticks += 1;
+                    if pending.ticks < PENDING_DESTINATION_TIMEOUT {
+                        self.pending.push(pending);
+                    }
+                    continue;
+                }
+            };
+
+            // Block updates are only handled in the primary dimension,
+            // so players elsewhere are sent the changes directly.
+            if pending.dimension == PRIMARY_DIMENSION {
+                block_events.iter_write(changes);
+            } else {
+                for change in changes {
+                    let state = i32::from(change.new_block.native_state_id());
+                    send_packet_to_player(network, BlockChange::new(change.pos, state));
+                }
+            }
+
+            position.previous = arrival;
+            position.current = arrival;
+            send_packet_to_player(
+                network,
+                PlayerPositionAndLookClientbound::new(
+                    arrival.x,
+                    arrival.y,
+                    arrival.z,
+                    arrival.yaw,
+                    arrival.pitch,
+                    0,
+                    0,
+                ),
+            );
+        }
+
+        for event in travel_events.read(self.reader.as_mut().unwrap()) {
+            // Other entities can't exist outside of the
+            // primary dimension; see the module docs.
+            if players.get(event.entity).is_none() {
+                continue;
+            }
+
+            let from = DimensionComponent::of(dimension_comps.get(event.entity));
+            let from_environment = continue_if_none!(dimensions.get(from)).environment;
+            let environment = event.kind.destination(from_environment);
+            let dimension = match dimensions.with_environment(environment) {
+                Some(dimension) => dimension,
+                None => {
+                    debug!(
+                        "No dimension with environment {:?} to travel to",
+                        environment
+                    );
+                    continue;
+                }
+            };
+
+            let destination = match (event.kind, environment) {
+                (PortalKind::End, Dimension::End) => Destination::EndPlatform,
+                (PortalKind::End, _) => {
+                    change_events.single_write(DimensionChangeEvent {
+                        entity: event.entity,
+                        dimension,
+                        position: position!(
+                            f64::from(level.spawn_x) + 0.5,
+                            f64::from(level.spawn_y),
+                            f64::from(level.spawn_z) + 0.5
+                        ),
+                    });
+                    continue;
+                }
+                (PortalKind::Nether, _) => Destination::Portal(scale_position(
+                    event.position,
+                    from_environment,
+                    environment,
+                )),
+            };
+
+            // The player hasn't received the chunks of another dimension
+            // yet, so changes there don't need to be sent.
+            let mut changes = vec![];
+            let arrival = match dimensions.world_mut(dimension) {
+                Some(world) => destination.prepare(&mut world.chunk_map, &mut changes),
+                None => destination.prepare(&mut chunk_map, &mut changes),
+            };
+            if dimension == PRIMARY_DIMENSION {
+                block_events.iter_write(changes);
+            }
+
+            let position = match arrival {
+                Some(arrival) => arrival,
+                None => {
+                    self.pending.push(PendingDestination {
+                        entity: event.entity,
+                        dimension,
+                        destination,
+                        ticks: 0,
+                    });
+                    destination.fallback()
+                }
+            };
+
+            change_events.single_write(DimensionChangeEvent {
+                entity: event.entity,
+                dimension,
+                position,
+            });
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(PortalTimerSystem, PORTAL_TIMER, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(PortalBlockSystem::default(), PORTAL_BLOCKS, &[]);
+    dispatcher.add_timed(PortalTravelSystem::default(), PORTAL_TRAVEL, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimension::DimensionSettings;
+    use crate::testframework as t;
+    use crate::worldgen::EmptyWorldGenerator;
+    use feather_blocks::FireData;
+    use feather_core::{Chunk, PacketType};
+    use specs::WorldExt;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    /// Registers a dimension with the given environment,
+    /// returning its ID and world folder.
+    fn register(w: &specs::World, environment: Dimension) -> (DimensionId, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("feather-portal-{}", uuid::Uuid::new_v4()));
+        let settings = DimensionSettings::new(
+            &format!("{:?}", environment),
+            environment,
+            Arc::new(EmptyWorldGenerator {}),
+            &dir,
+        );
+        let id = w.fetch_mut::<Dimensions>().register(settings).unwrap();
+        (id, dir)
+    }
+
+    /// Builds an obsidian frame with an interior of the
+    /// given size along the X axis, starting at (0, 64, 0).
+    fn build_frame(w: &specs::World, width: i32, height: i32) {
+        for x in -1..=width {
+            for y in -1..=height {
+                let frame = x == -1 || x == width || y == -1 || y == height;
+                if frame {
+                    t::set_block(x, 64 + y, 0, Block::Obsidian, w);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_frame() {
+        let (mut w, _) = t::builder().build();
+        t::populate_with_air(&mut w);
+        build_frame(&w, 2, 3);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        let frame = PortalFrame::find(&chunk_map, BlockPosition::new(1, 66, 0)).unwrap();
+        assert_eq!(frame.corner, BlockPosition::new(0, 64, 0));
+        assert_eq!(frame.axis, NetherPortalAxis::X);
+        assert_eq!((frame.width, frame.height), (2, 3));
+        assert_eq!(frame.positions().count(), 6);
+
+        assert!(PortalFrame::find(&chunk_map, BlockPosition::new(5, 64, 5)).is_none());
+        drop(chunk_map);
+
+        // An incomplete frame
+        t::set_block(2, 65, 0, Block::Air, &w);
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert!(PortalFrame::find(&chunk_map, BlockPosition::new(0, 64, 0)).is_none());
+    }
+
+    #[test]
+    fn test_frame_too_small() {
+        let (mut w, _) = t::builder().build();
+        t::populate_with_air(&mut w);
+        build_frame(&w, 1, 3);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert!(PortalFrame::find(&chunk_map, BlockPosition::new(0, 64, 0)).is_none());
+    }
+
+    #[test]
+    fn test_light_and_break_portal() {
+        let (mut w, mut d) = t::builder().with(PortalBlockSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        build_frame(&w, 2, 3);
+
+        let fire = Block::Fire(FireData::default());
+        t::set_block(0, 64, 0, fire, &w);
+        t::trigger_event(
+            &w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Test,
+                pos: BlockPosition::new(0, 64, 0),
+                old_block: Block::Air,
+                new_block: fire,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let portal = Block::NetherPortal(NetherPortalData {
+            axis: NetherPortalAxis::X,
+        });
+        for x in 0..2 {
+            for y in 64..67 {
+                assert_eq!(
+                    w.fetch::<ChunkMap>().block_at(BlockPosition::new(x, y, 0)),
+                    Some(portal)
+                );
+            }
+        }
+
+        t::set_block(-1, 65, 0, Block::Air, &w);
+        t::trigger_event(
+            &w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Test,
+                pos: BlockPosition::new(-1, 65, 0),
+                old_block: Block::Obsidian,
+                new_block: Block::Air,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        for x in 0..2 {
+            for y in 64..67 {
+                assert_eq!(
+                    w.fetch::<ChunkMap>().block_at(BlockPosition::new(x, y, 0)),
+                    Some(Block::Air)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_end_portal() {
+        let (mut w, _) = t::builder().build();
+        t::populate_with_air(&mut w);
+
+        for &facing in &[
+            EndPortalFrameFacing::North,
+            EndPortalFrameFacing::South,
+            EndPortalFrameFacing::West,
+            EndPortalFrameFacing::East,
+        ] {
+            let (dx, dz) = facing_offset(facing);
+            for k in -1..=1 {
+                let block = Block::EndPortalFrame(EndPortalFrameData { eye: true, facing });
+                t::set_block(-2 * dx + k * dz, 64, -2 * dz + k * dx, block, &w);
+            }
+        }
+
+        {
+            let chunk_map = w.fetch::<ChunkMap>();
+            assert_eq!(
+                find_end_portal(&chunk_map, BlockPosition::new(1, 64, -2)),
+                Some(BlockPosition::new(0, 64, 0))
+            );
+            assert_eq!(
+                find_end_portal(&chunk_map, BlockPosition::new(-2, 64, 0)),
+                Some(BlockPosition::new(0, 64, 0))
+            );
+        }
+
+        let block = Block::EndPortalFrame(EndPortalFrameData {
+            eye: false,
+            facing: EndPortalFrameFacing::South,
+        });
+        t::set_block(0, 64, -2, block, &w);
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert!(find_end_portal(&chunk_map, BlockPosition::new(1, 64, -2)).is_none());
+    }
+
+    #[test]
+    fn test_scale_position() {
+        let pos = position!(80.0, 64.0, -160.0);
+        let nether = scale_position(pos, Dimension::Overwold, Dimension::Nether);
+        assert_eq!((nether.x, nether.y, nether.z), (10.0, 64.0, -20.0));
+        assert_eq!(
+            scale_position(nether, Dimension::Nether, Dimension::Overwold),
+            pos
+        );
+        assert_eq!(
+            scale_position(pos, Dimension::Overwold, Dimension::End),
+            pos
+        );
+
+        assert_eq!(
+            PortalKind::Nether.destination(Dimension::Overwold),
+            Dimension::Nether
+        );
+        assert_eq!(
+            PortalKind::Nether.destination(Dimension::Nether),
+            Dimension::Overwold
+        );
+        assert_eq!(
+            PortalKind::End.destination(Dimension::End),
+            Dimension::Overwold
+        );
+    }
+
+    #[test]
+    fn test_find_and_create_portal() {
+        let (mut w, _) = t::builder().build();
+        t::populate_with_air(&mut w);
+        let mut chunk_map = w.fetch_mut::<ChunkMap>();
+
+        assert!(find_portal(&chunk_map, BlockPosition::new(0, 64, 0), 32).is_none());
+
+        // No solid ground, so a platform is built.
+        let mut changes = vec![];
+        let corner =
+            create_portal(&mut chunk_map, BlockPosition::new(10, 64, 10), &mut changes).unwrap();
+        assert_eq!(corner, BlockPosition::new(10, 70, 10));
+        // The platform, and the frame and portal above it
+        assert_eq!(changes.len(), 4 * 3 + 4 * 4);
+        assert!(changes
+            .iter()
+            .all(|change| change.cause == BlockUpdateCause::Portal));
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(10, 69, 10)),
+            Some(Block::Obsidian)
+        );
+        assert_eq!(
+            PortalKind::of(chunk_map.block_at(corner).unwrap()),
+            Some(PortalKind::Nether)
+        );
+
+        assert_eq!(
+            find_portal(&chunk_map, BlockPosition::new(0, 64, 0), 32),
+            Some(corner)
+        );
+        assert!(find_portal(&chunk_map, BlockPosition::new(-40, 64, 0), 32).is_none());
+
+        // A portal is placed on the ground if possible.
+        for x in 30..50 {
+            for z in 30..50 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(x, 63, z), Block::Stone)
+                    .unwrap();
+            }
+        }
+        let corner =
+            create_portal(&mut chunk_map, BlockPosition::new(40, 80, 40), &mut vec![]).unwrap();
+        assert_eq!(corner, BlockPosition::new(40, 64, 40));
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(40, 63, 40)),
+            Some(Block::Obsidian)
+        );
+    }
+
+    #[test]
+    fn test_portal_timer() {
+        let (mut w, mut d) = t::builder().with(PortalTimerSystem, "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let mut reader = t::reader::<PortalTravelEvent>(&w);
+
+        let portal = Block::NetherPortal(NetherPortalData {
+            axis: NetherPortalAxis::X,
+        });
+        t::set_block(0, 64, 0, portal, &w);
+        t::set_entity_pos(&w, player.entity, position!(0.5, 64.0, 0.5));
+
+        // Creative players travel immediately.
+        d.dispatch(&w);
+        w.maintain();
+        let events = t::triggered_events::<PortalTravelEvent>(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].entity, player.entity);
+        assert_eq!(events[0].kind, PortalKind::Nether);
+
+        // The cooldown doesn't elapse while in the portal.
+        for _ in 0..PLAYER_PORTAL_COOLDOWN * 2 {
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert!(t::triggered_events::<PortalTravelEvent>(&w, &mut reader).is_empty());
+
+        t::set_entity_pos(&w, player.entity, position!(5.5, 64.0, 0.5));
+        for _ in 0..PLAYER_PORTAL_COOLDOWN {
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert!(w
+            .read_component::<PortalComponent>()
+            .get(player.entity)
+            .is_none());
+
+        // Survival players need to wait.
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        t::set_entity_pos(&w, player.entity, position!(0.5, 64.0, 0.5));
+        for _ in 0..PORTAL_WAIT_TIME - 1 {
+            d.dispatch(&w);
+            w.maintain();
+        }
+        assert!(t::triggered_events::<PortalTravelEvent>(&w, &mut reader).is_empty());
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(
+            t::triggered_events::<PortalTravelEvent>(&w, &mut reader).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_travel_creates_portal() {
+        let (mut w, mut d) = t::builder().with(PortalTravelSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        let (nether, dir) = register(&w, Dimension::Nether);
+        let player = t::add_player(&mut w);
+        w.write_component::<DimensionComponent>()
+            .insert(player.entity, DimensionComponent(nether))
+            .unwrap();
+        let mut change_reader = t::reader::<DimensionChangeEvent>(&w);
+        let mut block_reader = t::reader::<BlockUpdateEvent>(&w);
+
+        // Leaving the Nether, the position is scaled up
+        // and a portal created in the primary dimension.
+        t::trigger_event(
+            &w,
+            PortalTravelEvent {
+                entity: player.entity,
+                kind: PortalKind::Nether,
+                position: position!(1.5, 64.0, 1.5),
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        let events = t::triggered_events::<DimensionChangeEvent>(&w, &mut change_reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].dimension, PRIMARY_DIMENSION);
+        let portal = events[0].position.block_pos();
+        assert_eq!((portal.x, portal.z), (12, 12));
+        assert_eq!(
+            w.fetch::<ChunkMap>()
+                .block_at(portal)
+                .and_then(PortalKind::of),
+            Some(PortalKind::Nether)
+        );
+        assert!(!t::triggered_events::<BlockUpdateEvent>(&w, &mut block_reader).is_empty());
+
+        w.fetch_mut::<Dimensions>().shut_down();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_end_platform_once_loaded() {
+        let (mut w, mut d) = t::builder().with(PortalTravelSystem::default(), "").build();
+        let (end, dir) = register(&w, Dimension::End);
+        let player = t::add_player(&mut w);
+        let mut change_reader = t::reader::<DimensionChangeEvent>(&w);
+
+        t::trigger_event(
+            &w,
+            PortalTravelEvent {
+                entity: player.entity,
+                kind: PortalKind::End,
+                position: position!(0.5, 64.0, 0.5),
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        // The chunks of the End aren't loaded, so the
+        // player arrives before the platform is built.
+        let events = t::triggered_events::<DimensionChangeEvent>(&w, &mut change_reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].dimension, end);
+        assert_eq!(events[0].position, END_SPAWN);
+        w.write_component::<DimensionComponent>()
+            .insert(player.entity, DimensionComponent(end))
+            .unwrap();
+        t::set_entity_pos(&w, player.entity, position!(100.5, 20.0, 0.5));
+
+        for pos in &[ChunkPosition::new(6, -1), ChunkPosition::new(6, 0)] {
+            w.fetch_mut::<Dimensions>()
+                .world_mut(end)
+                .unwrap()
+                .chunk_map
+                .set_chunk_at(*pos, Chunk::new(*pos));
+        }
+        d.dispatch(&w);
+        w.maintain();
+
+        {
+            let dimensions = w.fetch::<Dimensions>();
+            let chunk_map = &dimensions.world(end).unwrap().chunk_map;
+            for x in 98..=102 {
+                for z in -2..=2 {
+                    assert_eq!(
+                        chunk_map.block_at(BlockPosition::new(x, 48, z)),
+                        Some(Block::Obsidian)
+                    );
+                }
+            }
+        }
+        t::assert_packet_received(&player, PacketType::BlockChange);
+        t::assert_packet_received(&player, PacketType::PlayerPositionAndLookClientbound);
+        assert_eq!(
+            w.read_component::<PositionComponent>()
+                .get(player.entity)
+                .unwrap()
+                .current,
+            END_SPAWN
+        );
+
+        w.fetch_mut::<Dimensions>().shut_down();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,718 @@
+//! The recipe registry, which contains the crafting, cooking,
+//! stonecutting and smithing recipes known to the server.
+//!
+//! Recipes use the vanilla JSON format and are identified as
+//! `<namespace>:<path>`. A subset of the vanilla recipes is bundled
+//! with the server; datapacks can add recipes or replace bundled ones
+//! by placing them in `data/<namespace>/recipes/<path>.json`. Ingredients
+//! may refer to item tags, which are read from the bundled tags and
+//! from `data/<namespace>/tags/items/<path>.json`.
+//!
+//! The recipes are sent to players when they join using the Declare
+//! Recipes packet, and the crafting recipes are unlocked using the
+//! Unlock Recipes packet, so that they appear in the recipe book.
+//! Crafting itself is handled in the `crafting` module.
+
+use crate::datapack::{self, Tags};
+use crate::joinhandler::PlayerJoinEvent;
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::RECIPE_SEND;
+use crate::timings::DispatcherBuilderExt;
+use feather_core::network::packet::implementation::{DeclareRecipes, UnlockRecipes};
+use feather_core::recipe::{CookingMethod, Ingredient, Recipe, RecipeKind};
+use feather_core::{Item, ItemStack};
+use hashbrown::HashMap;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Read, ReadStorage, System};
+use std::fs;
+use std::path::Path;
+
+/// The recipes bundled with the server.
+const BUNDLED: &[(&str, &str)] = &[
+    (
+        "minecraft:armor_dye",
+        include_str!("../recipes/armor_dye.json"),
+    ),
+    ("minecraft:book", include_str!("../recipes/book.json")),
+    ("minecraft:chest", include_str!("../recipes/chest.json")),
+    (
+        "minecraft:crafting_table",
+        include_str!("../recipes/crafting_table.json"),
+    ),
+    ("minecraft:furnace", include_str!("../recipes/furnace.json")),
+    ("minecraft:glass", include_str!("../recipes/glass.json")),
+    (
+        "minecraft:iron_ingot",
+        include_str!("../recipes/iron_ingot.json"),
+    ),
+    (
+        "minecraft:oak_planks",
+        include_str!("../recipes/oak_planks.json"),
+    ),
+    ("minecraft:stick", include_str!("../recipes/stick.json")),
+    ("minecraft:stone", include_str!("../recipes/stone.json")),
+    (
+        "minecraft:stone_bricks_from_stone_stonecutting",
+        include_str!("../recipes/stone_bricks_from_stone_stonecutting.json"),
+    ),
+    ("minecraft:torch", include_str!("../recipes/torch.json")),
+];
+
+/// The item tags bundled with the server.
+const BUNDLED_TAGS: &[(&str, &str)] = &[
+    (
+        "minecraft:oak_logs",
+        include_str!("../tags/items/oak_logs.json"),
+    ),
+    (
+        "minecraft:planks",
+        include_str!("../tags/items/planks.json"),
+    ),
+    ("minecraft:sand", include_str!("../tags/items/sand.json")),
+];
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "invalid JSON: {}", _0)]
+    Json(serde_json::Error),
+    #[fail(display = "unknown item {}", _0)]
+    UnknownItem(String),
+    #[fail(display = "unknown tag {}", _0)]
+    UnknownTag(String),
+    #[fail(display = "pattern uses undefined key {}", _0)]
+    UndefinedKey(char),
+    #[fail(display = "pattern is empty or not rectangular")]
+    InvalidPattern,
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum IngredientJson {
+    One(IngredientChoice),
+    Any(Vec<IngredientChoice>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum IngredientChoice {
+    Item { item: String },
+    Tag { tag: String },
+}
+
+/// A recipe result, which is only an item
+/// name for smelting recipes.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ResultJson {
+    Name(String),
+    Stack {
+        item: String,
+        #[serde(default = "default_count")]
+        count: u8,
+    },
+}
+
+fn default_count() -> u8 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+struct CookingJson {
+    #[serde(default)]
+    group: String,
+    ingredient: IngredientJson,
+    result: ResultJson,
+    #[serde(default)]
+    experience: f32,
+    cookingtime: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+enum RecipeJson {
+    #[serde(rename = "crafting_shaped", alias = "minecraft:crafting_shaped")]
+    Shaped {
+        #[serde(default)]
+        group: String,
+        pattern: Vec<String>,
+        key: HashMap<String, IngredientJson>,
+        result: ResultJson,
+    },
+    #[serde(rename = "crafting_shapeless", alias = "minecraft:crafting_shapeless")]
+    Shapeless {
+        #[serde(default)]
+        group: String,
+        ingredients: Vec<IngredientJson>,
+        result: ResultJson,
+    },
+    #[serde(rename = "smelting", alias = "minecraft:smelting")]
+    Smelting(CookingJson),
+    #[serde(rename = "blasting", alias = "minecraft:blasting")]
+    Blasting(CookingJson),
+    #[serde(rename = "smoking", alias = "minecraft:smoking")]
+    Smoking(CookingJson),
+    #[serde(rename = "campfire_cooking", alias = "minecraft:campfire_cooking")]
+    CampfireCooking(CookingJson),
+    #[serde(rename = "stonecutting", alias = "minecraft:stonecutting")]
+    Stonecutting {
+        #[serde(default)]
+        group: String,
+        ingredient: IngredientJson,
+        result: String,
+        #[serde(default = "default_count")]
+        count: u8,
+    },
+    #[serde(rename = "smithing", alias = "minecraft:smithing")]
+    Smithing {
+        #[serde(default)]
+        group: String,
+        base: IngredientJson,
+        addition: IngredientJson,
+        result: ResultJson,
+    },
+}
+
+/// Parses a recipe from its JSON representation,
+/// resolving item tags using `tags`.
+pub fn parse_recipe(id: &str, json: &str, tags: &Tags) -> Result<Recipe, Error> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+
+    // Special recipes have no data besides their type.
+    if let Some(ty) = value.get("type").and_then(|ty| ty.as_str()) {
+        let ty = ty.strip_prefix("minecraft:").unwrap_or(ty);
+        if ty.starts_with("crafting_special_") {
+            return Ok(Recipe {
+                id: id.to_string(),
+                group: String::new(),
+                kind: RecipeKind::Special(ty.to_string()),
+            });
+        }
+    }
+
+    let (group, kind) = match serde_json::from_value(value)? {
+        RecipeJson::Shaped {
+            group,
+            pattern,
+            key,
+            result,
+        } => {
+            let width = pattern.first().map(|row| row.chars().count()).unwrap_or(0);
+            if width == 0 || pattern.iter().any(|row| row.chars().count() != width) {
+                return Err(Error::InvalidPattern);
+            }
+
+            let mut keys = HashMap::new();
+            for (key, ingredient) in key {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != ' ' => {
+                        keys.insert(c, ingredient.resolve(tags)?);
+                    }
+                    _ => return Err(Error::InvalidPattern),
+                }
+            }
+
+            let ingredients = pattern
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|c| match c {
+                    ' ' => Ok(Ingredient::default()),
+                    c => keys.get(&c).cloned().ok_or(Error::UndefinedKey(c)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            (
+                group,
+                RecipeKind::Shaped {
+                    width: width as u32,
+                    height: pattern.len() as u32,
+                    ingredients,
+                    result: result.resolve()?,
+                },
+            )
+        }
+        RecipeJson::Shapeless {
+            group,
+            ingredients,
+            result,
+        } => (
+            group,
+            RecipeKind::Shapeless {
+                ingredients: ingredients
+                    .iter()
+                    .map(|ingredient| ingredient.resolve(tags))
+                    .collect::<Result<_, _>>()?,
+                result: result.resolve()?,
+            },
+        ),
+        RecipeJson::Smelting(json) => json.resolve(CookingMethod::Smelting, tags)?,
+        RecipeJson::Blasting(json) => json.resolve(CookingMethod::Blasting, tags)?,
+        RecipeJson::Smoking(json) => json.resolve(CookingMethod::Smoking, tags)?,
+        RecipeJson::CampfireCooking(json) => json.resolve(CookingMethod::CampfireCooking, tags)?,
+        RecipeJson::Stonecutting {
+            group,
+            ingredient,
+            result,
+            count,
+        } => (
+            group,
+            RecipeKind::Stonecutting {
+                ingredient: ingredient.resolve(tags)?,
+                result: ItemStack::new(item(&result)?, count),
+            },
+        ),
+        RecipeJson::Smithing {
+            group,
+            base,
+            addition,
+            result,
+        } => (
+            group,
+            RecipeKind::Smithing {
+                base: base.resolve(tags)?,
+                addition: addition.resolve(tags)?,
+                result: result.resolve()?,
+            },
+        ),
+    };
+
+    Ok(Recipe {
+        id: id.to_string(),
+        group,
+        kind,
+    })
+}
+
+fn item(name: &str) -> Result<Item, Error> {
+    let qualified = if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("minecraft:{}", name)
+    };
+    Item::from_identifier(&qualified).ok_or_else(|| Error::UnknownItem(name.to_string()))
+}
+
+impl IngredientJson {
+    fn resolve(&self, tags: &Tags) -> Result<Ingredient, Error> {
+        let choices = match self {
+            IngredientJson::One(choice) => std::slice::from_ref(choice),
+            IngredientJson::Any(choices) => choices.as_slice(),
+        };
+
+        let mut items = vec![];
+        for choice in choices {
+            match choice {
+                IngredientChoice::Item { item: name } => items.push(item(name)?),
+                IngredientChoice::Tag { tag } => {
+                    if !tags.contains(tag) {
+                        return Err(Error::UnknownTag(tag.clone()));
+                    }
+                    for name in tags.get(tag) {
+                        items.push(item(name)?);
+                    }
+                }
+            }
+        }
+        items.dedup();
+
+        Ok(Ingredient(items))
+    }
+}
+
+impl ResultJson {
+    fn resolve(&self) -> Result<ItemStack, Error> {
+        match self {
+            ResultJson::Name(name) => Ok(ItemStack::new(item(name)?, 1)),
+            ResultJson::Stack { item: name, count } => Ok(ItemStack::new(item(name)?, *count)),
+        }
+    }
+}
+
+impl CookingJson {
+    fn resolve(&self, method: CookingMethod, tags: &Tags) -> Result<(String, RecipeKind), Error> {
+        let default_time = match method {
+            CookingMethod::Smelting => 200,
+            CookingMethod::Blasting | CookingMethod::Smoking => 100,
+            CookingMethod::CampfireCooking => 600,
+        };
+
+        Ok((
+            self.group.clone(),
+            RecipeKind::Cooking {
+                method,
+                ingredient: self.ingredient.resolve(tags)?,
+                result: self.result.resolve()?,
+                experience: self.experience,
+                cooking_time: self.cookingtime.unwrap_or(default_time),
+            },
+        ))
+    }
+}
+
+/// Resource containing all loaded recipes.
+#[derive(Debug, Default)]
+pub struct RecipeRegistry {
+    recipes: Vec<Recipe>,
+    by_id: HashMap<String, usize>,
+}
+
+impl RecipeRegistry {
+    /// Loads the bundled recipes, followed by the recipes of the
+    /// datapacks in the given world directory. Recipes which
+    /// fail to load are skipped.
+    pub fn load(world_dir: &Path) -> Self {
+        let mut tags = bundled_tags();
+        let mut sources: Vec<(String, String)> = BUNDLED
+            .iter()
+            .map(|(id, json)| (id.to_string(), json.to_string()))
+            .collect();
+
+        for pack in datapack::pack_dirs(world_dir) {
+            let namespaces = match fs::read_dir(pack.join("data")) {
+                Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()),
+                Err(_) => continue,
+            };
+
+            for namespace in namespaces {
+                let name = match namespace.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+
+                tags.load_dir(&namespace.join("tags").join("items"), &name);
+
+                for (path, id) in datapack::files(&namespace.join("recipes"), &name, "json") {
+                    match fs::read_to_string(&path) {
+                        Ok(json) => sources.push((id, json)),
+                        Err(e) => warn!("Failed to read recipe {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+
+        // Tags are resolved once all datapacks are loaded,
+        // since later datapacks may add to them.
+        let mut registry = Self::default();
+        for (id, json) in sources {
+            match parse_recipe(&id, &json, &tags) {
+                Ok(recipe) => registry.insert(recipe),
+                Err(e) => warn!("Failed to load recipe {}: {}", id, e),
+            }
+        }
+        registry
+    }
+
+    /// Returns the recipes bundled with the server.
+    pub fn bundled() -> Self {
+        let tags = bundled_tags();
+        let mut registry = Self::default();
+        for (id, json) in BUNDLED {
+            registry.insert(parse_recipe(id, json, &tags).expect("invalid bundled recipe"));
+        }
+        registry
+    }
+
+    /// Adds a recipe, replacing any
+    /// existing recipe with the same ID.
+    pub fn insert(&mut self, recipe: Recipe) {
+        match self.by_id.get(&recipe.id) {
+            Some(index) => self.recipes[*index] = recipe,
+            None => {
+                self.by_id.insert(recipe.id.clone(), self.recipes.len());
+                self.recipes.push(recipe);
+            }
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Recipe> {
+        self.by_id.get(id).map(|index| &self.recipes[*index])
+    }
+
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    /// Finds the crafting recipe matching a crafting grid
+    /// of the given width, with slots in row-major order.
+    pub fn find_crafting(&self, grid: &[Option<Item>], width: usize) -> Option<&Recipe> {
+        self.recipes
+            .iter()
+            .find(|recipe| recipe.matches_grid(grid, width))
+    }
+
+    /// Finds the cooking recipe with the given
+    /// method which accepts an item.
+    pub fn find_cooking(&self, method: CookingMethod, item: Item) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| match &recipe.kind {
+            RecipeKind::Cooking {
+                method: m,
+                ingredient,
+                ..
+            } => *m == method && ingredient.test(Some(item)),
+            _ => false,
+        })
+    }
+
+    /// Returns the stonecutting recipes which accept an item.
+    pub fn find_stonecutting(&self, item: Item) -> Vec<&Recipe> {
+        self.recipes
+            .iter()
+            .filter(|recipe| match &recipe.kind {
+                RecipeKind::Stonecutting { ingredient, .. } => ingredient.test(Some(item)),
+                _ => false,
+            })
+            .collect()
+    }
+}
+
+fn bundled_tags() -> Tags {
+    let mut tags = Tags::default();
+    for (id, json) in BUNDLED_TAGS {
+        tags.add_json(id.to_string(), json)
+            .expect("invalid bundled tag");
+    }
+    tags
+}
+
+/// System which sends the recipes to players when they join
+/// and unlocks all crafting recipes in their recipe book.
+///
+/// This system listens to `PlayerJoinEvent`s.
+#[derive(Default)]
+pub struct RecipeSendSystem {
+    reader: Option<ReaderId<PlayerJoinEvent>>,
+}
+
+impl<'a> System<'a> for RecipeSendSystem {
+    type SystemData = (
+        ReadStorage<'a, NetworkComponent>,
+        Read<'a, RecipeRegistry>,
+        Read<'a, EventChannel<PlayerJoinEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (networks, registry, join_events) = data;
+
+        for event in join_events.read(self.reader.as_mut().unwrap()) {
+            if let Some(network) = networks.get(event.player) {
+                send_packet_to_player(network, DeclareRecipes::new(registry.recipes().to_vec()));
+
+                let unlocked = registry
+                    .recipes()
+                    .iter()
+                    .filter(|recipe| match recipe.kind {
+                        RecipeKind::Shaped { .. } | RecipeKind::Shapeless { .. } => {
+                            DeclareRecipes::is_supported(recipe)
+                        }
+                        _ => false,
+                    })
+                    .map(|recipe| recipe.id.clone())
+                    .collect();
+                send_packet_to_player(
+                    network,
+                    UnlockRecipes {
+                        action: 0,
+                        crafting_book_open: false,
+                        filtering_craftable: false,
+                        recipe_ids: unlocked,
+                        highlighted_ids: vec![],
+                    },
+                );
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(RecipeSendSystem::default(), RECIPE_SEND, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::PacketType;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_bundled() {
+        let registry = RecipeRegistry::bundled();
+        assert_eq!(registry.len(), BUNDLED.len());
+
+        let torch = registry.get("minecraft:torch").unwrap();
+        assert_eq!(
+            torch.kind,
+            RecipeKind::Shaped {
+                width: 1,
+                height: 2,
+                ingredients: vec![
+                    Ingredient(vec![Item::Coal, Item::Charcoal]),
+                    Ingredient(vec![Item::Stick]),
+                ],
+                result: ItemStack::new(Item::Torch, 4),
+            }
+        );
+
+        let glass = registry.get("minecraft:glass").unwrap();
+        assert_eq!(
+            glass.kind,
+            RecipeKind::Cooking {
+                method: CookingMethod::Smelting,
+                ingredient: Ingredient(vec![Item::Sand, Item::RedSand]),
+                result: ItemStack::new(Item::Glass, 1),
+                experience: 0.1,
+                cooking_time: 200,
+            }
+        );
+
+        assert_eq!(
+            registry.get("minecraft:armor_dye").unwrap().kind,
+            RecipeKind::Special(String::from("crafting_special_armordye"))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tags = bundled_tags();
+        let parse = |json| parse_recipe("test:recipe", json, &tags);
+
+        assert!(match parse(
+            r#"{"type": "crafting_shapeless", "ingredients": [{"item": "minecraft:nonexistent"}], "result": {"item": "minecraft:stone"}}"#
+        ) {
+            Err(Error::UnknownItem(_)) => true,
+            _ => false,
+        });
+        assert!(match parse(
+            r#"{"type": "smelting", "ingredient": {"tag": "minecraft:nonexistent"}, "result": "minecraft:stone"}"#
+        ) {
+            Err(Error::UnknownTag(_)) => true,
+            _ => false,
+        });
+        assert!(match parse(
+            r##"{"type": "crafting_shaped", "pattern": ["#X"], "key": {"#": {"item": "minecraft:stick"}}, "result": {"item": "minecraft:stone"}}"##
+        ) {
+            Err(Error::UndefinedKey('X')) => true,
+            _ => false,
+        });
+        assert!(match parse(
+            r##"{"type": "crafting_shaped", "pattern": ["##", "#"], "key": {"#": {"item": "minecraft:stick"}}, "result": {"item": "minecraft:stone"}}"##
+        ) {
+            Err(Error::InvalidPattern) => true,
+            _ => false,
+        });
+        assert!(parse(r#"{"type": "crafting_unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let registry = RecipeRegistry::bundled();
+
+        let p = Some(Item::SprucePlanks);
+        let recipe = registry
+            .find_crafting(&[None, p, None, None, p, None, None, None, None], 3)
+            .unwrap();
+        assert_eq!(recipe.id, "minecraft:stick");
+        assert!(registry.find_crafting(&[p, None, None, p], 2).is_none());
+
+        let recipe = registry
+            .find_cooking(CookingMethod::Smelting, Item::IronOre)
+            .unwrap();
+        assert_eq!(recipe.result(), Some(&ItemStack::new(Item::IronIngot, 1)));
+        assert!(registry
+            .find_cooking(CookingMethod::Blasting, Item::IronOre)
+            .is_none());
+
+        assert_eq!(registry.find_stonecutting(Item::Stone).len(), 1);
+    }
+
+    #[test]
+    fn test_load_datapack() {
+        let dir = std::env::temp_dir().join(format!("feather-recipes-{}", uuid::Uuid::new_v4()));
+        let data = dir.join("datapacks").join("a").join("data");
+        fs::create_dir_all(data.join("minecraft/recipes")).unwrap();
+        fs::create_dir_all(data.join("minecraft/tags/items")).unwrap();
+        fs::create_dir_all(data.join("test/recipes")).unwrap();
+        fs::write(dir.join("datapacks/a/pack.mcmeta"), "{}").unwrap();
+        fs::write(
+            data.join("minecraft/tags/items/planks.json"),
+            r#"{"values": ["minecraft:dead_bush"]}"#,
+        )
+        .unwrap();
+        fs::write(
+            data.join("minecraft/recipes/stone.json"),
+            r#"{"type": "smelting", "ingredient": {"item": "minecraft:gravel"}, "result": "minecraft:stone"}"#,
+        )
+        .unwrap();
+        fs::write(
+            data.join("test/recipes/planks.json"),
+            r#"{"type": "crafting_shapeless", "ingredients": [{"tag": "minecraft:planks"}], "result": {"item": "minecraft:stick", "count": 2}}"#,
+        )
+        .unwrap();
+        fs::write(data.join("test/recipes/broken.json"), "{").unwrap();
+
+        let registry = RecipeRegistry::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(registry.len(), BUNDLED.len() + 1);
+        assert!(registry.get("test:broken").is_none());
+        assert_eq!(
+            registry.get("minecraft:stone").unwrap().kind,
+            RecipeKind::Cooking {
+                method: CookingMethod::Smelting,
+                ingredient: Ingredient(vec![Item::Gravel]),
+                result: ItemStack::new(Item::Stone, 1),
+                experience: 0.0,
+                cooking_time: 200,
+            }
+        );
+
+        // Tags are merged with the bundled ones.
+        let recipe = registry.get("test:planks").unwrap();
+        assert!(recipe.matches_grid(&[Some(Item::DeadBush)], 1));
+        assert!(recipe.matches_grid(&[Some(Item::OakPlanks)], 1));
+    }
+
+    #[test]
+    fn test_recipe_send_system() {
+        let (mut w, mut d) = t::builder().with(RecipeSendSystem::default(), "").build();
+        w.insert(RecipeRegistry::bundled());
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(
+            &w,
+            PlayerJoinEvent {
+                player: player.entity,
+            },
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::DeclareRecipes);
+        let packet = cast_packet::<DeclareRecipes>(&*packet);
+        assert_eq!(packet.recipes.len(), BUNDLED.len());
+
+        let packet = t::assert_packet_received(&player, PacketType::UnlockRecipes);
+        let packet = cast_packet::<UnlockRecipes>(&*packet);
+        assert_eq!(packet.action, 0);
+        assert!(packet.recipe_ids.contains(&"minecraft:book".to_string()));
+        assert!(packet.highlighted_ids.is_empty());
+    }
+}
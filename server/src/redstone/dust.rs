@@ -0,0 +1,220 @@
+//! Power propagation through networks of redstone dust.
+
+use super::power::{is_conductor, received_power, Component, Direction, MAX_POWER};
+use feather_blocks::{
+    Block, RedstoneWireData, RedstoneWireEast, RedstoneWireNorth, RedstoneWireSouth,
+    RedstoneWireWest,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// The maximum number of dust blocks in a network which
+/// are updated at once. Larger networks are updated in parts.
+const MAX_NETWORK_SIZE: usize = 4096;
+
+/// How redstone dust connects to its neighbour in one direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    None,
+    Side,
+    Up,
+}
+
+fn is_dust(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    match chunk_map.block_at(pos) {
+        Some(Block::RedstoneWire(_)) => true,
+        _ => false,
+    }
+}
+
+fn conducts(chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    chunk_map.block_at(pos).map_or(false, is_conductor)
+}
+
+/// Returns whether dust next to the given block
+/// in the given direction points towards it.
+fn connects_to(block: Option<Block>, dir: Direction) -> bool {
+    match block.and_then(Component::of) {
+        Some(Component::Repeater { facing, .. }) => facing == dir || facing == dir.opposite(),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Returns the dust connected to the dust at `pos`: dust next to it,
+/// dust one block higher if the block above `pos` doesn't cut the
+/// connection, and dust one block lower if the block next to `pos`
+/// doesn't.
+fn connected_dust(chunk_map: &ChunkMap, pos: BlockPosition) -> Vec<BlockPosition> {
+    let above_conducts = conducts(chunk_map, Direction::Up.of(pos));
+    let below_conducts = conducts(chunk_map, Direction::Down.of(pos));
+
+    let mut connected = Vec::with_capacity(4);
+    for &dir in Direction::HORIZONTAL.iter() {
+        let side = dir.of(pos);
+        if is_dust(chunk_map, side) {
+            connected.push(side);
+            continue;
+        }
+
+        let side_conducts = conducts(chunk_map, side);
+        let up = Direction::Up.of(side);
+        let down = Direction::Down.of(side);
+        if side_conducts && !above_conducts && is_dust(chunk_map, up) {
+            connected.push(up);
+        } else if !side_conducts && below_conducts && is_dust(chunk_map, down) {
+            connected.push(down);
+        }
+    }
+    connected
+}
+
+/// Returns how the dust at `pos` connects in each horizontal direction.
+fn shape(chunk_map: &ChunkMap, pos: BlockPosition) -> [Side; 4] {
+    let above_conducts = conducts(chunk_map, Direction::Up.of(pos));
+    let below_conducts = conducts(chunk_map, Direction::Down.of(pos));
+
+    let mut shape = [Side::None; 4];
+    for (i, &dir) in Direction::HORIZONTAL.iter().enumerate() {
+        let side = dir.of(pos);
+        let side_conducts = conducts(chunk_map, side);
+        shape[i] = if side_conducts && !above_conducts && is_dust(chunk_map, Direction::Up.of(side))
+        {
+            Side::Up
+        } else if connects_to(chunk_map.block_at(side), dir)
+            || (!side_conducts && below_conducts && is_dust(chunk_map, Direction::Down.of(side)))
+        {
+            Side::Side
+        } else {
+            Side::None
+        };
+    }
+    shape
+}
+
+/// Returns redstone dust with the given power and shape.
+fn dust(power: u8, shape: [Side; 4]) -> Block {
+    macro_rules! side {
+        ($side:expr, $ty:ident) => {
+            match $side {
+                Side::Up => $ty::Up,
+                Side::Side => $ty::Side,
+                Side::None => $ty::None,
+            }
+        };
+    }
+
+    Block::RedstoneWire(RedstoneWireData {
+        power: i32::from(power),
+        north: side!(shape[0], RedstoneWireNorth),
+        east: side!(shape[1], RedstoneWireEast),
+        south: side!(shape[2], RedstoneWireSouth),
+        west: side!(shape[3], RedstoneWireWest),
+    })
+}
+
+/// Computes the power and shape of each block in the network of
+/// dust containing `start`. Returns the blocks of the network,
+/// in a deterministic order, along with their new state.
+///
+/// Each dust block is powered by the components and conductors
+/// next to it, ignoring other dust, and the signal then loses
+/// one level of strength per block it travels along the dust.
+pub fn update_network(chunk_map: &ChunkMap, start: BlockPosition) -> Vec<(BlockPosition, Block)> {
+    if !is_dust(chunk_map, start) {
+        return vec![];
+    }
+
+    // Find the network
+    let mut nodes = vec![start];
+    let mut indices = HashMap::new();
+    indices.insert(start, 0);
+    let mut edges: Vec<Vec<usize>> = vec![];
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    while let Some(index) = queue.pop_front() {
+        let mut node_edges = vec![];
+        for pos in connected_dust(chunk_map, nodes[index]) {
+            let next = match indices.get(&pos) {
+                Some(&next) => next,
+                None if nodes.len() < MAX_NETWORK_SIZE => {
+                    indices.insert(pos, nodes.len());
+                    queue.push_back(nodes.len());
+                    nodes.push(pos);
+                    nodes.len() - 1
+                }
+                None => continue,
+            };
+            node_edges.push(next);
+        }
+        // Nodes are visited in the order they were found
+        edges.push(node_edges);
+    }
+
+    // Propagate the power of the sources along the network,
+    // strongest first
+    let mut power: Vec<u8> = nodes
+        .iter()
+        .map(|&pos| {
+            Direction::ALL
+                .iter()
+                .map(|&dir| received_power(chunk_map, pos, dir, false))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut heap: BinaryHeap<(u8, Reverse<usize>)> = power
+        .iter()
+        .enumerate()
+        .filter(|(_, &p)| p > 1)
+        .map(|(i, &p)| (p, Reverse(i)))
+        .collect();
+    while let Some((p, Reverse(index))) = heap.pop() {
+        if p < power[index] {
+            continue;
+        }
+        for &next in &edges[index] {
+            if p - 1 > power[next] {
+                power[next] = p - 1;
+                if p - 1 > 1 {
+                    heap.push((p - 1, Reverse(next)));
+                }
+            }
+        }
+    }
+
+    nodes
+        .iter()
+        .zip(power.iter())
+        .map(|(&pos, &p)| (pos, dust(p.min(MAX_POWER), shape(chunk_map, pos))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_update_network() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+        for x in 0..20 {
+            t::set_block(x, 64, 0, Block::RedstoneWire(Default::default()), &w);
+        }
+        t::set_block(0, 64, 1, Block::RedstoneBlock, &w);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        let network = update_network(&chunk_map, BlockPosition::new(10, 64, 0));
+        assert_eq!(network.len(), 20);
+        for (pos, block) in network {
+            let expected = 15u8.saturating_sub(pos.x as u8);
+            match block {
+                Block::RedstoneWire(data) => assert_eq!(data.power, i32::from(expected)),
+                _ => panic!(),
+            }
+        }
+    }
+}
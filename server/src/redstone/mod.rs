@@ -0,0 +1,856 @@
+//! Redstone: blocks which emit power and the blocks powered by them.
+//!
+//! Power is emitted by components: redstone dust, torches,
+//! repeaters, comparators, levers, buttons, pressure plates and
+//! blocks of redstone. Components power the blocks next to them,
+//! and conductors (opaque full blocks) which are strongly powered
+//! pass the power on to their own neighbours. A signal loses one
+//! level of strength for each block it travels along dust.
+//!
+//! Whenever a block changes, the blocks up to two blocks away from
+//! it are queued for an update, in a fixed order, and the queue is
+//! worked through until the circuit settles. Dust networks are
+//! recomputed as a whole. Changes with a delay, such as those of
+//! torches, repeaters and comparators, are scheduled as block ticks;
+//! ticks due in the same tick run in the order of their priority,
+//! so the simulation is deterministic.
+//!
+//! Redstone lamps, doors, trapdoors and fence gates follow their
+//! power, and hoppers are locked while powered. Dispensers and
+//! droppers are triggered when powered, and pistons are told to
+//! extend or retract, by `RedstoneSignalEvent`s; see the `piston`
+//! module for the latter.
+
+mod dust;
+mod piston;
+mod power;
+
+pub use piston::{push_reaction, pushed_blocks, PistonSystem, PushReaction};
+pub use power::{
+    facing, is_conductor, power_at, property, received_power, with_property, Component, Direction,
+    MAX_POWER,
+};
+
+use crate::blocks::{self, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent};
+use crate::entity::{ItemComponent, PositionComponent};
+use crate::systems::{PISTON, REDSTONE};
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::Block;
+use feather_core::nbt;
+use feather_core::world::{BlockPosition, ChunkMap};
+use power::{
+    comparator_output, container_signal, diode_input, diode_side_power, weak_power,
+    ComparatorEntity,
+};
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Join, Read, ReadStorage, System, Write};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The maximum number of blocks updated in one tick.
+const MAX_UPDATES: usize = 65_536;
+/// The number of ticks it takes a redstone torch to toggle.
+const TORCH_DELAY: u64 = 2;
+/// The number of ticks it takes a comparator to change its output.
+const COMPARATOR_DELAY: u64 = 2;
+/// The number of ticks it takes a redstone lamp to turn off.
+const LAMP_OFF_DELAY: u64 = 4;
+/// The number of ticks between the checks of whether
+/// entities are still on a powered pressure plate.
+const PLATE_DELAY: u64 = 20;
+/// The number of ticks between the checks of weighted pressure plates.
+const WEIGHTED_PLATE_DELAY: u64 = 10;
+
+lazy_static! {
+    /// The offsets of the blocks updated when a block changes, in
+    /// order: its neighbours, then the blocks two blocks away.
+    static ref UPDATE_OFFSETS: Vec<BlockPosition> = {
+        let mut offsets: Vec<BlockPosition> =
+            Direction::ALL.iter().map(|dir| dir.offset()).collect();
+        for &first in Direction::ALL.iter() {
+            for &second in Direction::ALL.iter() {
+                let offset = first.offset() + second.offset();
+                if offset != BlockPosition::new(0, 0, 0) && !offsets.contains(&offset) {
+                    offsets.push(offset);
+                }
+            }
+        }
+        offsets
+    };
+}
+
+/// Event triggered when a piston, dispenser or dropper
+/// is powered or unpowered by redstone.
+#[derive(Debug, Clone)]
+pub struct RedstoneSignalEvent {
+    /// The position of the block.
+    pub pos: BlockPosition,
+    /// The block, which the redstone engine doesn't change
+    /// in the case of pistons.
+    pub block: Block,
+    /// Whether the block is now powered.
+    pub powered: bool,
+}
+
+/// Returns the block with its `powered`
+/// property set to the given value.
+fn with_powered(block: Block, powered: bool) -> Block {
+    with_property(block, "powered", &powered.to_string())
+}
+
+fn is_true(block: Block, key: &str) -> bool {
+    property(block, key).map_or(false, |value| value == "true")
+}
+
+/// Returns whether a piston facing in the given direction is powered.
+/// Like in vanilla, pistons are also powered by the power which reaches
+/// the block above them.
+fn piston_powered(chunk_map: &ChunkMap, pos: BlockPosition, facing: Direction) -> bool {
+    let above = Direction::Up.of(pos);
+    Direction::ALL
+        .iter()
+        .any(|&dir| dir != facing && received_power(chunk_map, pos, dir, true) > 0)
+        || Direction::ALL
+            .iter()
+            .any(|&dir| dir != Direction::Down && received_power(chunk_map, above, dir, true) > 0)
+}
+
+/// Returns whether a repeater is locked by a powered
+/// diode facing into its side.
+fn repeater_locked(chunk_map: &ChunkMap, pos: BlockPosition, facing: Direction) -> bool {
+    facing.perpendicular().iter().any(|&dir| {
+        let source = dir.of(pos);
+        match chunk_map.block_at(source).and_then(Component::of) {
+            Some(component) if component.diode_output() == Some(dir.opposite()) => {
+                weak_power(chunk_map, source, component, dir.opposite()) > 0
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Returns whether the output of a repeater or comparator
+/// with the given facing goes into another diode.
+fn faces_diode(chunk_map: &ChunkMap, pos: BlockPosition, facing: Direction) -> bool {
+    let output = facing.opposite();
+    match chunk_map.block_at(output.of(pos)).and_then(Component::of) {
+        Some(Component::Repeater { facing: other, .. })
+        | Some(Component::Comparator { facing: other, .. }) => other != output,
+        _ => false,
+    }
+}
+
+/// Returns the output signal of a comparator for its current inputs.
+///
+/// A comparator reads the fullness of a container behind it, or
+/// behind the conductor behind it, in place of the power there.
+pub fn comparator_signal(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    facing: Direction,
+    subtract: bool,
+) -> u8 {
+    let behind = facing.of(pos);
+    let mut rear = diode_input(chunk_map, pos, facing);
+    if let Some(signal) = container_signal(chunk_map, behind) {
+        rear = signal;
+    } else if rear < MAX_POWER && chunk_map.block_at(behind).map_or(false, is_conductor) {
+        if let Some(signal) = container_signal(chunk_map, facing.of(behind)) {
+            rear = signal;
+        }
+    }
+
+    let side = facing
+        .perpendicular()
+        .iter()
+        .map(|&dir| diode_side_power(chunk_map, pos, dir))
+        .max()
+        .unwrap_or(0);
+    if subtract {
+        rear.saturating_sub(side)
+    } else if rear >= side {
+        rear
+    } else {
+        0
+    }
+}
+
+/// Returns the power of a pressure plate with the given number
+/// of entities on it, `mobs` of which aren't items.
+fn plate_power(block: Block, entities: usize, mobs: usize) -> u8 {
+    let power = match block.to_name_and_props().0 {
+        "minecraft:light_weighted_pressure_plate" => entities,
+        "minecraft:heavy_weighted_pressure_plate" => (entities + 9) / 10,
+        "minecraft:stone_pressure_plate" if mobs > 0 => usize::from(MAX_POWER),
+        "minecraft:stone_pressure_plate" => 0,
+        _ if entities > 0 => usize::from(MAX_POWER),
+        _ => 0,
+    };
+    power.min(usize::from(MAX_POWER)) as u8
+}
+
+/// Returns the pressure plate with the given power.
+fn with_plate_power(block: Block, power: u8) -> Block {
+    match property(block, "power") {
+        Some(_) => with_property(block, "power", &power.to_string()),
+        None => with_powered(block, power > 0),
+    }
+}
+
+fn plate_delay(block: Block) -> u64 {
+    match property(block, "power") {
+        Some(_) => WEIGHTED_PLATE_DELAY,
+        None => PLATE_DELAY,
+    }
+}
+
+/// The state of the redstone simulation during one tick.
+struct RedstoneWorld<'a> {
+    chunk_map: &'a mut ChunkMap,
+    events: &'a mut EventChannel<BlockUpdateEvent>,
+    ticks: &'a mut BlockTicks,
+    signals: &'a mut EventChannel<RedstoneSignalEvent>,
+    /// The positions waiting for an update, in order.
+    queue: VecDeque<BlockPosition>,
+    /// The positions in `queue`.
+    queued: HashSet<BlockPosition>,
+    /// The number of updates run in this tick.
+    updates: usize,
+    /// The pistons which received a `RedstoneSignalEvent` in this tick.
+    signalled: HashSet<BlockPosition>,
+}
+
+impl<'a> RedstoneWorld<'a> {
+    fn set_block(&mut self, pos: BlockPosition, block: Block) {
+        if blocks::set_block(
+            self.chunk_map,
+            self.events,
+            pos,
+            block,
+            BlockUpdateCause::Redstone,
+        ) {
+            self.notify(pos);
+        }
+    }
+
+    /// Queues an update of the block at the given position.
+    fn enqueue(&mut self, pos: BlockPosition) {
+        if self.queued.insert(pos) {
+            self.queue.push_back(pos);
+        }
+    }
+
+    /// Queues updates of the blocks around the given position.
+    fn notify(&mut self, pos: BlockPosition) {
+        for &offset in UPDATE_OFFSETS.iter() {
+            self.enqueue(pos + offset);
+        }
+    }
+
+    /// Updates the queued blocks until the queue is empty.
+    fn run(&mut self) {
+        while let Some(pos) = self.queue.pop_front() {
+            if !self.queued.remove(&pos) {
+                continue;
+            }
+
+            self.updates += 1;
+            if self.updates > MAX_UPDATES {
+                warn!("Too many redstone updates in one tick; skipping the rest");
+                self.queue.clear();
+                self.queued.clear();
+                return;
+            }
+            self.update(pos);
+        }
+    }
+
+    /// Updates the block at the given position after
+    /// a block near it changed.
+    fn update(&mut self, pos: BlockPosition) {
+        let block = match self.chunk_map.block_at(pos) {
+            Some(Block::Air) | None => return,
+            Some(block) => block,
+        };
+
+        match Component::of(block) {
+            Some(Component::Dust { .. }) => {
+                let network = dust::update_network(self.chunk_map, pos);
+                for &(node, block) in &network {
+                    self.set_block(node, block);
+                }
+                for (node, _) in network {
+                    self.queued.remove(&node);
+                }
+            }
+            Some(Component::Torch { lit, attached }) => {
+                let on = received_power(self.chunk_map, pos, attached, true) == 0;
+                if on != lit {
+                    self.ticks.schedule_block_tick(pos, TORCH_DELAY, 0);
+                }
+            }
+            Some(Component::Repeater {
+                facing,
+                delay,
+                locked,
+                powered,
+            }) => {
+                let now_locked = repeater_locked(self.chunk_map, pos, facing);
+                if now_locked != locked {
+                    self.set_block(pos, with_property(block, "locked", &now_locked.to_string()));
+                }
+                if now_locked {
+                    return;
+                }
+
+                let input = diode_input(self.chunk_map, pos, facing) > 0;
+                if input != powered {
+                    let priority = if faces_diode(self.chunk_map, pos, facing) {
+                        -3
+                    } else if powered {
+                        -2
+                    } else {
+                        -1
+                    };
+                    self.ticks
+                        .schedule_block_tick(pos, u64::from(delay) * 2, priority);
+                }
+            }
+            Some(Component::Comparator {
+                facing,
+                subtract,
+                powered,
+            }) => {
+                let signal = comparator_signal(self.chunk_map, pos, facing, subtract);
+                if signal != comparator_output(self.chunk_map, pos) || (signal > 0) != powered {
+                    let priority = if faces_diode(self.chunk_map, pos, facing) {
+                        -1
+                    } else {
+                        0
+                    };
+                    self.ticks
+                        .schedule_block_tick(pos, COMPARATOR_DELAY, priority);
+                }
+            }
+            Some(_) => (),
+            None => self.update_powered(pos, block),
+        }
+    }
+
+    /// Updates a block which reacts to the power it receives.
+    fn update_powered(&mut self, pos: BlockPosition, block: Block) {
+        if let Block::RedstoneLamp(data) = block {
+            let powered = power_at(self.chunk_map, pos) > 0;
+            if powered && !data.lit {
+                self.set_block(pos, with_property(block, "lit", "true"));
+            } else if !powered && data.lit {
+                self.ticks.schedule_block_tick(pos, LAMP_OFF_DELAY, 0);
+            }
+            return;
+        }
+
+        let name = block.to_name_and_props().0;
+        match name {
+            name if name.ends_with("_trapdoor") || name.ends_with("_fence_gate") => {
+                let powered = power_at(self.chunk_map, pos) > 0;
+                if is_true(block, "powered") != powered {
+                    let block = with_property(block, "open", &powered.to_string());
+                    self.set_block(pos, with_powered(block, powered));
+                }
+            }
+            name if name.ends_with("_door") => self.update_door(pos, block),
+            "minecraft:piston" | "minecraft:sticky_piston" => {
+                let facing = match facing(block) {
+                    Some(facing) => facing,
+                    None => return,
+                };
+                let powered = piston_powered(self.chunk_map, pos, facing);
+                if powered != is_true(block, "extended") && self.signalled.insert(pos) {
+                    self.signals.single_write(RedstoneSignalEvent {
+                        pos,
+                        block,
+                        powered,
+                    });
+                }
+            }
+            "minecraft:dispenser" | "minecraft:dropper" => {
+                let powered = power_at(self.chunk_map, pos) > 0
+                    || power_at(self.chunk_map, Direction::Up.of(pos)) > 0;
+                if powered != is_true(block, "triggered") {
+                    let triggered = with_property(block, "triggered", &powered.to_string());
+                    self.set_block(pos, triggered);
+                    if powered {
+                        self.signals.single_write(RedstoneSignalEvent {
+                            pos,
+                            block: triggered,
+                            powered,
+                        });
+                    }
+                }
+            }
+            "minecraft:hopper" => {
+                let enabled = power_at(self.chunk_map, pos) == 0;
+                if enabled != is_true(block, "enabled") {
+                    self.set_block(pos, with_property(block, "enabled", &enabled.to_string()));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Opens or closes both halves of a door if its power changed.
+    fn update_door(&mut self, pos: BlockPosition, block: Block) {
+        let other = match property(block, "half") {
+            Some(ref half) if half == "upper" => Direction::Down.of(pos),
+            _ => Direction::Up.of(pos),
+        };
+        let powered = power_at(self.chunk_map, pos) > 0 || power_at(self.chunk_map, other) > 0;
+        if is_true(block, "powered") == powered {
+            return;
+        }
+
+        let with_power =
+            |block| with_powered(with_property(block, "open", &powered.to_string()), powered);
+        self.set_block(pos, with_power(block));
+        if let Some(other_block) = self.chunk_map.block_at(other) {
+            if other_block.to_name_and_props().0 == block.to_name_and_props().0 {
+                self.set_block(other, with_power(other_block));
+            }
+        }
+    }
+
+    /// Runs a scheduled block tick of a component.
+    fn tick(&mut self, pos: BlockPosition, block: Block) {
+        if let Block::RedstoneLamp(data) = block {
+            if data.lit && power_at(self.chunk_map, pos) == 0 {
+                self.set_block(pos, with_property(block, "lit", "false"));
+            }
+            return;
+        }
+
+        match Component::of(block) {
+            Some(Component::Torch { lit, attached }) => {
+                let on = received_power(self.chunk_map, pos, attached, true) == 0;
+                if on != lit {
+                    self.set_block(pos, with_property(block, "lit", &on.to_string()));
+                }
+            }
+            Some(Component::Repeater {
+                facing,
+                delay,
+                locked: false,
+                powered,
+            }) => {
+                let input = diode_input(self.chunk_map, pos, facing) > 0;
+                if !powered {
+                    self.set_block(pos, with_powered(block, true));
+                    if !input {
+                        // Extend short pulses to the delay of the repeater
+                        self.ticks
+                            .schedule_block_tick(pos, u64::from(delay) * 2, -2);
+                    }
+                } else if !input {
+                    self.set_block(pos, with_powered(block, false));
+                }
+            }
+            Some(Component::Comparator {
+                facing,
+                subtract,
+                powered,
+            }) => {
+                let signal = comparator_signal(self.chunk_map, pos, facing, subtract);
+                if signal != comparator_output(self.chunk_map, pos) {
+                    match nbt::to_value(&ComparatorEntity::new(pos, signal)) {
+                        Ok(data) => {
+                            self.chunk_map.set_block_entity_at(pos, data).ok();
+                        }
+                        Err(e) => warn!("Failed to store comparator at {:?}: {}", pos, e),
+                    }
+                    self.notify(pos);
+                }
+                if (signal > 0) != powered {
+                    self.set_block(pos, with_powered(block, signal > 0));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Updates the power of a pressure plate with the given number of
+    /// entities on it, `mobs` of which aren't items. Plates are powered
+    /// as soon as an entity is on them, but only unpowered once they
+    /// are checked again on a block tick.
+    fn update_plate(
+        &mut self,
+        pos: BlockPosition,
+        block: Block,
+        entities: (usize, usize),
+        tick: bool,
+    ) {
+        let current = match Component::of(block) {
+            Some(Component::Plate { power }) => power,
+            _ => return,
+        };
+
+        let power = plate_power(block, entities.0, entities.1);
+        if power > current || (tick && power != current) {
+            self.set_block(pos, with_plate_power(block, power));
+        }
+        if power > 0 {
+            self.ticks.schedule_block_tick(pos, plate_delay(block), 0);
+        }
+    }
+}
+
+/// System which simulates redstone circuits.
+///
+/// This system listens to `BlockTickEvent`s and to `BlockUpdateEvent`s
+/// not caused by redstone itself.
+#[derive(Default)]
+pub struct RedstoneSystem {
+    tick_reader: Option<ReaderId<BlockTickEvent>>,
+    update_reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for RedstoneSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, BlockTicks>,
+        Write<'a, EventChannel<RedstoneSignalEvent>>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, ItemComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_events, mut events, mut chunk_map, mut ticks, mut signals, positions, items) =
+            data;
+
+        let updates: Vec<BlockUpdateEvent> = events
+            .read(self.update_reader.as_mut().unwrap())
+            .filter(|event| event.cause != BlockUpdateCause::Redstone)
+            .cloned()
+            .collect();
+
+        // Count the entities on each pressure plate
+        let mut plates: HashMap<BlockPosition, (usize, usize)> = HashMap::new();
+        for (position, item) in (&positions, items.maybe()).join() {
+            let pos = position.current.block_pos();
+            match chunk_map.block_at(pos).and_then(Component::of) {
+                Some(Component::Plate { .. }) => (),
+                _ => continue,
+            }
+            let count = plates.entry(pos).or_default();
+            count.0 += 1;
+            if item.is_none() {
+                count.1 += 1;
+            }
+        }
+
+        let mut world = RedstoneWorld {
+            chunk_map: &mut *chunk_map,
+            events: &mut *events,
+            ticks: &mut *ticks,
+            signals: &mut *signals,
+            queue: VecDeque::new(),
+            queued: HashSet::new(),
+            updates: 0,
+            signalled: HashSet::new(),
+        };
+
+        for event in tick_events.read(self.tick_reader.as_mut().unwrap()) {
+            let block = continue_if_none!(world.chunk_map.block_at(event.pos));
+            if let Some(Component::Plate { .. }) = Component::of(block) {
+                let entities = plates.get(&event.pos).copied().unwrap_or_default();
+                world.update_plate(event.pos, block, entities, true);
+            } else {
+                world.tick(event.pos, block);
+            }
+            world.run();
+        }
+
+        let mut pressed: Vec<_> = plates.into_iter().collect();
+        pressed.sort_by_key(|(pos, _)| (pos.x, pos.y, pos.z));
+        for (pos, entities) in pressed {
+            let block = continue_if_none!(world.chunk_map.block_at(pos));
+            world.update_plate(pos, block, entities, false);
+            world.run();
+        }
+
+        for event in updates {
+            if let Some(Component::Comparator { .. }) = Component::of(event.old_block) {
+                match Component::of(event.new_block) {
+                    Some(Component::Comparator { .. }) => (),
+                    _ => {
+                        world.chunk_map.remove_block_entity_at(event.pos);
+                    }
+                }
+            }
+
+            world.enqueue(event.pos);
+            world.notify(event.pos);
+            world.run();
+        }
+    }
+
+    setup_impl!(tick_reader, update_reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(RedstoneSystem::default(), REDSTONE, &[]);
+    dispatcher.add_timed(PistonSystem::default(), PISTON, &[REDSTONE]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use feather_blocks::{
+        ComparatorData, ComparatorFacing, ComparatorMode, HopperData, IronDoorData, LeverData,
+        LeverFace, LeverFacing, RedstoneLampData, RedstoneWallTorchData, RedstoneWallTorchFacing,
+        RedstoneWireData, RepeaterData, RepeaterFacing,
+    };
+    use specs::{Builder, Dispatcher, World, WorldExt};
+
+    fn world<'a, 'b>() -> (World, Dispatcher<'a, 'b>) {
+        let (mut w, d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(RedstoneSystem::default(), "redstone", &["ticks"])
+            .build();
+        t::populate_with_air(&mut w);
+        (w, d)
+    }
+
+    fn dust_power(w: &World, x: i32, y: i32, z: i32) -> i32 {
+        match t::block_at(w, BlockPosition::new(x, y, z)) {
+            Block::RedstoneWire(data) => data.power,
+            block => panic!("expected dust, found {:?}", block),
+        }
+    }
+
+    fn lamp(lit: bool) -> Block {
+        Block::RedstoneLamp(RedstoneLampData { lit })
+    }
+
+    fn lever(powered: bool) -> Block {
+        Block::Lever(LeverData {
+            face: LeverFace::Floor,
+            facing: LeverFacing::North,
+            powered,
+        })
+    }
+
+    #[test]
+    fn test_update_offsets() {
+        assert_eq!(UPDATE_OFFSETS.len(), 24);
+        assert_eq!(UPDATE_OFFSETS[0], BlockPosition::new(-1, 0, 0));
+    }
+
+    #[test]
+    fn test_dust_signal_strength() {
+        let (mut w, mut d) = world();
+
+        for x in 1..=17 {
+            t::place(
+                &w,
+                BlockPosition::new(x, 64, 0),
+                Block::RedstoneWire(RedstoneWireData::default()),
+            );
+        }
+        t::place(&w, BlockPosition::new(0, 64, 0), Block::RedstoneBlock);
+        t::run_ticks(&mut w, &mut d, 1);
+
+        for x in 1..=15 {
+            assert_eq!(dust_power(&w, x, 64, 0), 16 - x);
+        }
+        assert_eq!(dust_power(&w, 16, 64, 0), 0);
+
+        // Removing the source unpowers the whole line
+        t::place(&w, BlockPosition::new(0, 64, 0), Block::Air);
+        t::run_ticks(&mut w, &mut d, 1);
+        for x in 1..=17 {
+            assert_eq!(dust_power(&w, x, 64, 0), 0);
+        }
+    }
+
+    #[test]
+    fn test_torch_inversion() {
+        let (mut w, mut d) = world();
+
+        let torch = |lit| {
+            Block::RedstoneWallTorch(RedstoneWallTorchData {
+                facing: RedstoneWallTorchFacing::East,
+                lit,
+            })
+        };
+        t::place(&w, BlockPosition::new(1, 64, 0), Block::Stone);
+        t::place(&w, BlockPosition::new(2, 64, 0), torch(true));
+        t::place(&w, BlockPosition::new(1, 65, 0), lever(false));
+        t::run_ticks(&mut w, &mut d, 5);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), torch(true));
+
+        t::place(&w, BlockPosition::new(1, 65, 0), lever(true));
+        t::run_ticks(&mut w, &mut d, 2);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), torch(true));
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), torch(false));
+
+        t::place(&w, BlockPosition::new(1, 65, 0), lever(false));
+        t::run_ticks(&mut w, &mut d, 3);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), torch(true));
+    }
+
+    #[test]
+    fn test_repeater_delay() {
+        let (mut w, mut d) = world();
+
+        let repeater = Block::Repeater(RepeaterData {
+            delay: 2,
+            locked: false,
+            facing: RepeaterFacing::West,
+            powered: false,
+        });
+        t::place(&w, BlockPosition::new(1, 64, 0), repeater);
+        t::place(&w, BlockPosition::new(2, 64, 0), lamp(false));
+        t::place(&w, BlockPosition::new(0, 64, 0), Block::RedstoneBlock);
+
+        t::run_ticks(&mut w, &mut d, 4);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), lamp(false));
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), lamp(true));
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(1, 64, 0)),
+            with_powered(repeater, true)
+        );
+
+        // The lamp turns off after the repeater and its own delay
+        t::place(&w, BlockPosition::new(0, 64, 0), Block::Air);
+        t::run_ticks(&mut w, &mut d, 6);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), lamp(true));
+        t::run_ticks(&mut w, &mut d, 3);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), lamp(false));
+    }
+
+    #[test]
+    fn test_comparator_signal() {
+        let (mut w, _) = world();
+
+        let dust = |power| {
+            Block::RedstoneWire(RedstoneWireData {
+                power,
+                ..RedstoneWireData::default()
+            })
+        };
+        let comparator = Block::Comparator(ComparatorData {
+            mode: ComparatorMode::Compare,
+            facing: ComparatorFacing::West,
+            powered: false,
+        });
+        t::set_block(1, 64, 0, comparator, &w);
+        t::set_block(0, 64, 0, Block::RedstoneBlock, &w);
+        t::set_block(1, 64, 1, dust(5), &w);
+
+        let pos = BlockPosition::new(1, 64, 0);
+        {
+            let chunk_map = w.fetch::<ChunkMap>();
+            assert_eq!(
+                comparator_signal(&chunk_map, pos, Direction::West, false),
+                15
+            );
+            assert_eq!(
+                comparator_signal(&chunk_map, pos, Direction::West, true),
+                10
+            );
+        }
+
+        t::set_block(0, 64, 0, dust(4), &w);
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(
+            comparator_signal(&chunk_map, pos, Direction::West, false),
+            0
+        );
+        assert_eq!(comparator_signal(&chunk_map, pos, Direction::West, true), 0);
+    }
+
+    #[test]
+    fn test_powered_door() {
+        let (mut w, mut d) = world();
+
+        let lower = Block::IronDoor(IronDoorData::default());
+        let upper = with_property(lower, "half", "upper");
+        t::place(&w, BlockPosition::new(0, 64, 0), lower);
+        t::place(&w, BlockPosition::new(0, 65, 0), upper);
+        t::place(&w, BlockPosition::new(1, 64, 0), lever(true));
+        t::run_ticks(&mut w, &mut d, 1);
+
+        let open = |block| with_powered(with_property(block, "open", "true"), true);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), open(lower));
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 65, 0)), open(upper));
+
+        t::place(&w, BlockPosition::new(1, 64, 0), lever(false));
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), lower);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 65, 0)), upper);
+    }
+
+    #[test]
+    fn test_hopper_lock() {
+        let (mut w, mut d) = world();
+
+        let hopper = Block::Hopper(HopperData::default());
+        t::place(&w, BlockPosition::new(0, 64, 0), hopper);
+        t::place(&w, BlockPosition::new(1, 64, 0), lever(true));
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(hopper, "enabled", "false")
+        );
+
+        t::place(&w, BlockPosition::new(1, 64, 0), lever(false));
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), hopper);
+    }
+
+    #[test]
+    fn test_pressure_plate() {
+        let (mut w, mut d) = world();
+
+        let plate = Block::OakPressurePlate(Default::default());
+        t::place(&w, BlockPosition::new(0, 64, 0), plate);
+        t::place(&w, BlockPosition::new(1, 64, 0), lamp(false));
+        let entity = w
+            .create_entity()
+            .with(PositionComponent {
+                current: position!(0.5, 64.0, 0.5),
+                previous: position!(0.5, 64.0, 0.5),
+            })
+            .build();
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_powered(plate, true)
+        );
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), lamp(true));
+
+        w.delete_entity(entity).unwrap();
+        t::run_ticks(&mut w, &mut d, 10);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_powered(plate, true)
+        );
+        t::run_ticks(&mut w, &mut d, 20);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), plate);
+    }
+
+    #[test]
+    fn test_plate_power() {
+        let light = Block::LightWeightedPressurePlate(Default::default());
+        let heavy = Block::HeavyWeightedPressurePlate(Default::default());
+        let stone = Block::StonePressurePlate(Default::default());
+        assert_eq!(plate_power(light, 20, 0), 15);
+        assert_eq!(plate_power(heavy, 11, 0), 2);
+        assert_eq!(plate_power(stone, 3, 0), 0);
+        assert_eq!(plate_power(stone, 3, 1), 15);
+    }
+}
@@ -0,0 +1,595 @@
+//! Pistons, which push the blocks in front of them when powered.
+//!
+//! A piston extending pushes the line of up to `MAX_PUSHED`
+//! blocks in front of it one block forward and breaks the blocks
+//! which are destroyed by pistons, such as plants and torches,
+//! dropping their items. Sticky pistons pull the block in front
+//! of their head back when retracting. Immovable blocks, such as
+//! obsidian and blocks with block entities, stop pistons.
+//!
+//! While they move, blocks are replaced by moving pistons, whose
+//! block entities store the moving block as in vanilla. They are
+//! put in place on a block tick `MOVE_TICKS` later. Players see
+//! the movement through the `BlockAction` packet.
+
+use super::power::{facing, with_property, Direction};
+use super::{is_true, RedstoneSignalEvent};
+use crate::blocks::{self, BlockTickEvent, BlockTicks, BlockUpdateCause, BlockUpdateEvent};
+use crate::container::ContainerKind;
+use crate::loot::{drop_at_block, LootContext, LootTables};
+use crate::util::Util;
+use crate::TickCount;
+use feather_blocks::{Block, BlockExt, MovingPistonData, PistonHeadData};
+use feather_core::nbt;
+use feather_core::network::packet::implementation::BlockAction;
+use feather_core::world::{BlockPosition, ChunkMap};
+use shrev::{EventChannel, ReaderId};
+use specs::{Entities, LazyUpdate, Read, System, Write};
+use std::collections::HashMap;
+
+/// The maximum number of blocks a piston pushes.
+pub const MAX_PUSHED: usize = 12;
+/// The number of ticks blocks moved by pistons are moving for.
+const MOVE_TICKS: u64 = 2;
+/// The IDs of the pistons in the block registry, used by `BlockAction`.
+const PISTON_ID: i32 = 99;
+const STICKY_PISTON_ID: i32 = 92;
+/// The `BlockAction` action IDs of pistons.
+const ACTION_EXTEND: u8 = 0;
+const ACTION_RETRACT: u8 = 1;
+
+/// How a block reacts to being pushed by a piston.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushReaction {
+    /// The block is moved.
+    Normal,
+    /// The block is broken when pushed.
+    Destroy,
+    /// The block stops the piston.
+    Block,
+    /// The block is pushed, but not pulled by sticky pistons.
+    PushOnly,
+    /// There is no block to push.
+    Empty,
+}
+
+/// Returns how the given block reacts to being pushed by a piston.
+pub fn push_reaction(block: Block) -> PushReaction {
+    match block {
+        Block::Air | Block::CaveAir | Block::VoidAir => return PushReaction::Empty,
+        Block::Obsidian
+        | Block::Bedrock
+        | Block::Barrier
+        | Block::EndPortal
+        | Block::EndGateway
+        | Block::EnchantingTable
+        | Block::Beacon
+        | Block::Spawner
+        | Block::Jukebox(_)
+        | Block::PistonHead(_)
+        | Block::MovingPiston(_) => return PushReaction::Block,
+        Block::Cobweb | Block::DragonEgg | Block::Pumpkin | Block::Melon => {
+            return PushReaction::Destroy
+        }
+        _ => (),
+    }
+    match ContainerKind::of(block) {
+        Some(ContainerKind::ShulkerBox) => return PushReaction::Destroy,
+        Some(ContainerKind::CraftingTable) | None => (),
+        Some(_) => return PushReaction::Block,
+    }
+
+    let (name, props) = block.to_name_and_props();
+    let extended = props
+        .iter()
+        .any(|(key, value)| *key == "extended" && value == "true");
+    match name {
+        "minecraft:piston" | "minecraft:sticky_piston" if extended => PushReaction::Block,
+        "minecraft:end_portal_frame"
+        | "minecraft:nether_portal"
+        | "minecraft:command_block"
+        | "minecraft:chain_command_block"
+        | "minecraft:repeating_command_block"
+        | "minecraft:structure_block"
+        | "minecraft:chest"
+        | "minecraft:trapped_chest"
+        | "minecraft:ender_chest"
+        | "minecraft:furnace"
+        | "minecraft:dispenser"
+        | "minecraft:dropper"
+        | "minecraft:hopper"
+        | "minecraft:brewing_stand"
+        | "minecraft:sign"
+        | "minecraft:wall_sign"
+        | "minecraft:daylight_detector"
+        | "minecraft:conduit" => PushReaction::Block,
+        name if name.ends_with("_banner") => PushReaction::Block,
+        name if name.ends_with("_glazed_terracotta") => PushReaction::PushOnly,
+        name if name.ends_with("_door") || name.ends_with("_bed") => PushReaction::Destroy,
+        name if name.ends_with("rail") => PushReaction::Normal,
+        _ if !block.is_solid() => PushReaction::Destroy,
+        _ => PushReaction::Normal,
+    }
+}
+
+/// Returns the blocks moved by a piston at `pos` extending in the
+/// given direction, nearest first, and the blocks it breaks, or
+/// `None` if the piston can't extend.
+pub fn pushed_blocks(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    facing: Direction,
+) -> Option<(Vec<BlockPosition>, Vec<BlockPosition>)> {
+    let mut moved = vec![];
+    let mut current = facing.of(pos);
+    loop {
+        match push_reaction(chunk_map.block_at(current)?) {
+            PushReaction::Empty => return Some((moved, vec![])),
+            PushReaction::Destroy => return Some((moved, vec![current])),
+            PushReaction::Block => return None,
+            PushReaction::Normal | PushReaction::PushOnly => {
+                if moved.len() == MAX_PUSHED {
+                    return None;
+                }
+                moved.push(current);
+                current = facing.of(current);
+            }
+        }
+    }
+}
+
+/// Returns whether the given block is a sticky piston.
+fn is_sticky(block: Block) -> bool {
+    match block {
+        Block::StickyPiston(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns whether the given block is a piston.
+fn is_piston(block: Block) -> bool {
+    match block {
+        Block::Piston(_) | Block::StickyPiston(_) => true,
+        _ => false,
+    }
+}
+
+/// The state of a block, as stored in block entities.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockState {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Properties", default)]
+    pub props: HashMap<String, String>,
+}
+
+impl BlockState {
+    pub fn new(block: Block) -> Self {
+        let (name, props) = block.to_name_and_props();
+        Self {
+            name: name.to_string(),
+            props: props
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        }
+    }
+
+    pub fn block(&self) -> Option<Block> {
+        Block::from_name_and_props(&self.name, &self.props)
+    }
+}
+
+/// The block entity of a moving piston, as saved in chunks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PistonEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// The block being moved.
+    #[serde(rename = "blockState")]
+    pub block_state: BlockState,
+    /// The direction the piston faces.
+    pub facing: i32,
+    /// How far the block has moved, from 0 to 1.
+    pub progress: f32,
+    /// Whether the piston is extending.
+    pub extending: bool,
+    /// Whether the block is the piston itself or its head.
+    pub source: bool,
+}
+
+/// The blocks changed by a piston.
+struct PistonWorld<'a> {
+    chunk_map: &'a mut ChunkMap,
+    events: &'a mut EventChannel<BlockUpdateEvent>,
+    ticks: &'a mut BlockTicks,
+    /// The blocks broken by pistons in this tick.
+    destroyed: Vec<(BlockPosition, Block)>,
+}
+
+impl<'a> PistonWorld<'a> {
+    fn set_block(&mut self, pos: BlockPosition, block: Block) {
+        blocks::set_block(
+            self.chunk_map,
+            self.events,
+            pos,
+            block,
+            BlockUpdateCause::Piston,
+        );
+    }
+
+    /// Replaces the block at `pos` by a moving piston moving the given block.
+    fn set_moving(
+        &mut self,
+        pos: BlockPosition,
+        block: Block,
+        facing: Direction,
+        sticky: bool,
+        extending: bool,
+        source: bool,
+    ) {
+        let moving = Block::MovingPiston(MovingPistonData::default());
+        let moving = with_property(moving, "facing", facing.name());
+        let moving = with_property(moving, "type", if sticky { "sticky" } else { "normal" });
+        self.set_block(pos, moving);
+
+        let entity = PistonEntity {
+            id: String::from("minecraft:piston"),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            block_state: BlockState::new(block),
+            facing: facing as i32,
+            progress: 0.0,
+            extending,
+            source,
+        };
+        match nbt::to_value(&entity) {
+            Ok(data) => {
+                self.chunk_map.set_block_entity_at(pos, data).ok();
+            }
+            Err(e) => warn!("Failed to store moving piston at {:?}: {}", pos, e),
+        }
+        self.ticks.schedule_block_tick(pos, MOVE_TICKS, 0);
+    }
+
+    /// Puts the block moved by the moving piston at `pos` in place.
+    fn finish(&mut self, pos: BlockPosition) {
+        match self.chunk_map.block_at(pos) {
+            Some(Block::MovingPiston(_)) => (),
+            _ => return,
+        }
+
+        let block = self
+            .chunk_map
+            .remove_block_entity_at(pos)
+            .and_then(|data| nbt::from_value::<PistonEntity>(data).ok())
+            .and_then(|entity| entity.block_state.block())
+            .unwrap_or(Block::Air);
+        self.set_block(pos, block);
+    }
+
+    /// Puts the blocks still moving in front of a piston in place.
+    fn finish_line(&mut self, pos: BlockPosition, facing: Direction) {
+        let mut current = facing.of(pos);
+        for _ in 0..=MAX_PUSHED {
+            match self.chunk_map.block_at(current) {
+                Some(Block::MovingPiston(_)) => self.finish(current),
+                _ => return,
+            }
+            current = facing.of(current);
+        }
+    }
+
+    /// Extends a piston, returning whether it extended.
+    fn extend(&mut self, pos: BlockPosition, piston: Block, facing: Direction) -> bool {
+        let (moved, destroyed) = match pushed_blocks(self.chunk_map, pos, facing) {
+            Some(blocks) => blocks,
+            None => return false,
+        };
+
+        let sticky = is_sticky(piston);
+        for pos in destroyed {
+            let block = self.chunk_map.block_at(pos).unwrap();
+            self.set_block(pos, Block::Air);
+            self.destroyed.push((pos, block));
+        }
+
+        // Each block moves into the position of the block in front
+        // of it, so moving the farthest block first keeps them all
+        let blocks: Vec<Block> = moved
+            .iter()
+            .map(|&pos| self.chunk_map.block_at(pos).unwrap())
+            .collect();
+        for (&from, &block) in moved.iter().zip(blocks.iter()).rev() {
+            self.set_moving(facing.of(from), block, facing, sticky, true, false);
+        }
+
+        let head = Block::PistonHead(PistonHeadData::default());
+        let head = with_property(head, "facing", facing.name());
+        let head = with_property(head, "type", if sticky { "sticky" } else { "normal" });
+        self.set_moving(facing.of(pos), head, facing, sticky, true, true);
+        self.set_block(pos, with_property(piston, "extended", "true"));
+        true
+    }
+
+    /// Retracts a piston, pulling the block in front
+    /// of it back if it is sticky.
+    fn retract(&mut self, pos: BlockPosition, piston: Block, facing: Direction) {
+        self.finish_line(pos, facing);
+
+        let sticky = is_sticky(piston);
+        let head = facing.of(pos);
+        self.set_moving(
+            pos,
+            with_property(piston, "extended", "false"),
+            facing,
+            sticky,
+            false,
+            true,
+        );
+        if let Some(Block::PistonHead(_)) = self.chunk_map.block_at(head) {
+            self.set_block(head, Block::Air);
+        }
+
+        if !sticky {
+            return;
+        }
+        let pulled = facing.of(head);
+        let block = match self.chunk_map.block_at(pulled) {
+            Some(block) => block,
+            None => return,
+        };
+        if push_reaction(block) == PushReaction::Normal
+            && self.chunk_map.block_at(head) == Some(Block::Air)
+        {
+            self.set_block(pulled, Block::Air);
+            self.set_moving(head, block, facing, sticky, false, false);
+        }
+    }
+}
+
+/// System which extends and retracts pistons
+/// when they are powered or unpowered.
+///
+/// This system listens to `RedstoneSignalEvent`s
+/// and `BlockTickEvent`s.
+#[derive(Default)]
+pub struct PistonSystem {
+    signal_reader: Option<ReaderId<RedstoneSignalEvent>>,
+    tick_reader: Option<ReaderId<BlockTickEvent>>,
+}
+
+impl<'a> System<'a> for PistonSystem {
+    type SystemData = (
+        Read<'a, EventChannel<RedstoneSignalEvent>>,
+        Read<'a, EventChannel<BlockTickEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, BlockTicks>,
+        Read<'a, Util>,
+        Read<'a, LootTables>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            signals,
+            tick_events,
+            mut chunk_map,
+            mut events,
+            mut ticks,
+            util,
+            tables,
+            lazy,
+            entities,
+            tick,
+        ) = data;
+
+        let mut world = PistonWorld {
+            chunk_map: &mut *chunk_map,
+            events: &mut *events,
+            ticks: &mut *ticks,
+            destroyed: vec![],
+        };
+
+        for event in tick_events.read(self.tick_reader.as_mut().unwrap()) {
+            world.finish(event.pos);
+        }
+
+        for event in signals.read(self.signal_reader.as_mut().unwrap()) {
+            let piston = continue_if_none!(world.chunk_map.block_at(event.pos));
+            if !is_piston(piston) {
+                continue;
+            }
+            let facing = continue_if_none!(facing(piston));
+            let extended = is_true(piston, "extended");
+            if event.powered == extended {
+                continue;
+            }
+
+            let action = if event.powered {
+                if !world.extend(event.pos, piston, facing) {
+                    continue;
+                }
+                ACTION_EXTEND
+            } else {
+                world.retract(event.pos, piston, facing);
+                ACTION_RETRACT
+            };
+
+            let block_type = if is_sticky(piston) {
+                STICKY_PISTON_ID
+            } else {
+                PISTON_ID
+            };
+            let packet = BlockAction::new(event.pos, action, facing as u8, block_type);
+            util.broadcast_chunk_update(event.pos.chunk_pos(), packet, None);
+        }
+
+        let mut rng = rand::thread_rng();
+        for (pos, block) in world.destroyed {
+            let drops = tables.block_drops(block, &LootContext::default(), &mut rng);
+            drop_at_block(&lazy, &entities, pos, drops, tick.0, &mut rng);
+        }
+    }
+
+    setup_impl!(signal_reader, tick_reader);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockTickSystem;
+    use crate::testframework as t;
+    use feather_blocks::{PistonData, PistonFacing, StickyPistonData, StickyPistonFacing};
+    use specs::{Dispatcher, World, WorldExt};
+
+    fn world<'a, 'b>() -> (World, Dispatcher<'a, 'b>) {
+        let (mut w, d) = t::builder()
+            .with(BlockTickSystem, "ticks")
+            .with_dep(PistonSystem::default(), "pistons", &["ticks"])
+            .build();
+        t::populate_with_air(&mut w);
+        (w, d)
+    }
+
+    fn signal(w: &World, x: i32, y: i32, z: i32, powered: bool) {
+        t::trigger_event(
+            w,
+            RedstoneSignalEvent {
+                pos: BlockPosition::new(x, y, z),
+                block: t::block_at(w, BlockPosition::new(x, y, z)),
+                powered,
+            },
+        );
+    }
+
+    fn piston() -> Block {
+        Block::Piston(PistonData {
+            extended: false,
+            facing: PistonFacing::East,
+        })
+    }
+
+    #[test]
+    fn test_push_reaction() {
+        assert_eq!(push_reaction(Block::Stone), PushReaction::Normal);
+        assert_eq!(push_reaction(Block::Obsidian), PushReaction::Block);
+        assert_eq!(push_reaction(Block::Air), PushReaction::Empty);
+        assert_eq!(push_reaction(Block::Cobweb), PushReaction::Destroy);
+        assert_eq!(
+            push_reaction(with_property(piston(), "extended", "true")),
+            PushReaction::Block
+        );
+        assert_eq!(push_reaction(piston()), PushReaction::Normal);
+    }
+
+    #[test]
+    fn test_pushed_blocks() {
+        let (w, _) = world();
+        let pushed = |w: &World| {
+            let chunk_map = w.fetch::<ChunkMap>();
+            pushed_blocks(&chunk_map, BlockPosition::new(0, 64, 0), Direction::East)
+        };
+        t::set_block(0, 64, 0, piston(), &w);
+        for x in 1..=12 {
+            t::set_block(x, 64, 0, Block::Stone, &w);
+        }
+        t::set_block(13, 64, 0, Block::Cobweb, &w);
+
+        let (moved, destroyed) = pushed(&w).unwrap();
+        assert_eq!(moved.len(), 12);
+        assert_eq!(moved[0], BlockPosition::new(1, 64, 0));
+        assert_eq!(destroyed, vec![BlockPosition::new(13, 64, 0)]);
+
+        // Too many blocks
+        t::set_block(13, 64, 0, Block::Stone, &w);
+        assert!(pushed(&w).is_none());
+
+        // Immovable block
+        t::set_block(13, 64, 0, Block::Air, &w);
+        t::set_block(5, 64, 0, Block::Obsidian, &w);
+        assert!(pushed(&w).is_none());
+    }
+
+    #[test]
+    fn test_piston_extend_retract() {
+        let (mut w, mut d) = world();
+        t::set_block(0, 64, 0, piston(), &w);
+        t::set_block(1, 64, 0, Block::Stone, &w);
+        t::set_block(2, 64, 0, Block::Dirt, &w);
+
+        signal(&w, 0, 64, 0, true);
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(0, 64, 0)),
+            with_property(piston(), "extended", "true")
+        );
+        match t::block_at(&w, BlockPosition::new(2, 64, 0)) {
+            Block::MovingPiston(_) => (),
+            block => panic!("expected moving piston, found {:?}", block),
+        }
+
+        t::run_ticks(&mut w, &mut d, MOVE_TICKS as usize);
+        let head = with_property(
+            Block::PistonHead(PistonHeadData::default()),
+            "facing",
+            "east",
+        );
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), head);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), Block::Stone);
+        assert_eq!(t::block_at(&w, BlockPosition::new(3, 64, 0)), Block::Dirt);
+        assert!(w
+            .fetch::<ChunkMap>()
+            .block_entity_at(BlockPosition::new(2, 64, 0))
+            .is_none());
+
+        // A normal piston doesn't pull blocks back
+        signal(&w, 0, 64, 0, false);
+        t::run_ticks(&mut w, &mut d, 1 + MOVE_TICKS as usize);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), piston());
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), Block::Air);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), Block::Stone);
+    }
+
+    #[test]
+    fn test_sticky_piston() {
+        let (mut w, mut d) = world();
+        let piston = Block::StickyPiston(StickyPistonData {
+            extended: false,
+            facing: StickyPistonFacing::East,
+        });
+        t::set_block(0, 64, 0, piston, &w);
+        t::set_block(1, 64, 0, Block::Stone, &w);
+
+        signal(&w, 0, 64, 0, true);
+        t::run_ticks(&mut w, &mut d, 1 + MOVE_TICKS as usize);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), Block::Stone);
+
+        signal(&w, 0, 64, 0, false);
+        t::run_ticks(&mut w, &mut d, 1 + MOVE_TICKS as usize);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), piston);
+        assert_eq!(t::block_at(&w, BlockPosition::new(1, 64, 0)), Block::Stone);
+        assert_eq!(t::block_at(&w, BlockPosition::new(2, 64, 0)), Block::Air);
+    }
+
+    #[test]
+    fn test_piston_blocked() {
+        let (mut w, mut d) = world();
+        t::set_block(0, 64, 0, piston(), &w);
+        t::set_block(1, 64, 0, Block::Obsidian, &w);
+
+        signal(&w, 0, 64, 0, true);
+        t::run_ticks(&mut w, &mut d, 1);
+        assert_eq!(t::block_at(&w, BlockPosition::new(0, 64, 0)), piston());
+        assert_eq!(
+            t::block_at(&w, BlockPosition::new(1, 64, 0)),
+            Block::Obsidian
+        );
+    }
+}
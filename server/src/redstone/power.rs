@@ -0,0 +1,596 @@
+//! The redstone power emitted and received by blocks.
+
+use crate::container::{load_slots, ContainerKind};
+use feather_blocks::{Block, BlockExt, RedstoneWireData};
+use feather_core::inventory::max_size;
+use feather_core::nbt;
+use feather_core::world::{BlockPosition, ChunkMap};
+use std::collections::HashMap;
+
+/// The maximum redstone signal strength.
+pub const MAX_POWER: u8 = 15;
+
+/// The six directions from a block to its neighbours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    /// All directions, in the order in which
+    /// neighbours are updated.
+    pub const ALL: [Direction; 6] = [
+        Direction::West,
+        Direction::East,
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+    ];
+
+    /// The horizontal directions, in the order in which
+    /// redstone dust connects to its neighbours.
+    pub const HORIZONTAL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    /// Returns the direction with the given name,
+    /// as used by the `facing` block property.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "down" => Some(Direction::Down),
+            "up" => Some(Direction::Up),
+            "north" => Some(Direction::North),
+            "south" => Some(Direction::South),
+            "west" => Some(Direction::West),
+            "east" => Some(Direction::East),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of this direction, as used
+    /// by the `facing` block property.
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Down => "down",
+            Direction::Up => "up",
+            Direction::North => "north",
+            Direction::South => "south",
+            Direction::West => "west",
+            Direction::East => "east",
+        }
+    }
+
+    /// Returns the offset from a block to its neighbour in this direction.
+    pub fn offset(self) -> BlockPosition {
+        match self {
+            Direction::Down => BlockPosition::new(0, -1, 0),
+            Direction::Up => BlockPosition::new(0, 1, 0),
+            Direction::North => BlockPosition::new(0, 0, -1),
+            Direction::South => BlockPosition::new(0, 0, 1),
+            Direction::West => BlockPosition::new(-1, 0, 0),
+            Direction::East => BlockPosition::new(1, 0, 0),
+        }
+    }
+
+    /// Returns the position of the neighbour of `pos` in this direction.
+    pub fn of(self, pos: BlockPosition) -> BlockPosition {
+        pos + self.offset()
+    }
+
+    /// Returns the opposite direction.
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+
+    /// Returns the two horizontal directions perpendicular to
+    /// this horizontal direction.
+    pub fn perpendicular(self) -> [Direction; 2] {
+        match self {
+            Direction::North | Direction::South => [Direction::West, Direction::East],
+            _ => [Direction::North, Direction::South],
+        }
+    }
+
+    /// Returns the horizontal direction clockwise from
+    /// this horizontal direction, seen from above.
+    pub fn clockwise(self) -> Self {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+            dir => dir,
+        }
+    }
+}
+
+/// Returns the value of a property of the given block.
+pub fn property(block: Block, key: &str) -> Option<String> {
+    block
+        .to_name_and_props()
+        .1
+        .into_iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
+
+/// Returns the given block with a property set to the given value.
+/// Blocks which don't have the property are returned unchanged.
+pub fn with_property(block: Block, key: &str, value: &str) -> Block {
+    let (name, props) = block.to_name_and_props();
+    let mut props: HashMap<String, String> = props
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    if !props.contains_key(key) {
+        return block;
+    }
+
+    props.insert(key.to_string(), value.to_string());
+    Block::from_name_and_props(name, &props).unwrap_or(block)
+}
+
+/// Returns the `facing` property of the given block.
+pub fn facing(block: Block) -> Option<Direction> {
+    property(block, "facing").and_then(|name| Direction::from_name(&name))
+}
+
+fn prop<'a>(props: &'a [(&'static str, String)], key: &str) -> Option<&'a str> {
+    props
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// A block which emits redstone power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    /// Redstone dust with its power level and whether
+    /// it connects to the north, east, south and west.
+    Dust { power: u8, connected: [bool; 4] },
+    /// A redstone torch attached to the block in the given direction.
+    Torch { lit: bool, attached: Direction },
+    /// A repeater, which takes its input from the `facing` side.
+    Repeater {
+        facing: Direction,
+        delay: u8,
+        locked: bool,
+        powered: bool,
+    },
+    /// A comparator, which takes its input from the `facing` side.
+    Comparator {
+        facing: Direction,
+        subtract: bool,
+        powered: bool,
+    },
+    /// A lever or button attached to the block in the given direction.
+    Switch { powered: bool, attached: Direction },
+    /// A pressure plate with its power level.
+    Plate { power: u8 },
+    /// A block of redstone.
+    RedstoneBlock,
+}
+
+impl Component {
+    /// Returns the component of the given block,
+    /// or `None` if it doesn't emit power.
+    pub fn of(block: Block) -> Option<Self> {
+        match block {
+            Block::Air => return None,
+            Block::RedstoneWire(data) => return Some(dust(data)),
+            Block::RedstoneTorch(data) => {
+                return Some(Component::Torch {
+                    lit: data.lit,
+                    attached: Direction::Down,
+                })
+            }
+            Block::RedstoneBlock => return Some(Component::RedstoneBlock),
+            _ => (),
+        }
+
+        let (name, props) = block.to_name_and_props();
+        let facing = || prop(&props, "facing").and_then(Direction::from_name);
+        let powered = prop(&props, "powered") == Some("true");
+        let component = match name {
+            "minecraft:redstone_wall_torch" => Component::Torch {
+                lit: prop(&props, "lit") == Some("true"),
+                attached: facing()?.opposite(),
+            },
+            "minecraft:repeater" => Component::Repeater {
+                facing: facing()?,
+                delay: prop(&props, "delay")?.parse().ok()?,
+                locked: prop(&props, "locked") == Some("true"),
+                powered,
+            },
+            "minecraft:comparator" => Component::Comparator {
+                facing: facing()?,
+                subtract: prop(&props, "mode") == Some("subtract"),
+                powered,
+            },
+            name if name == "minecraft:lever" || name.ends_with("_button") => {
+                let attached = match prop(&props, "face")? {
+                    "floor" => Direction::Down,
+                    "ceiling" => Direction::Up,
+                    _ => facing()?.opposite(),
+                };
+                Component::Switch { powered, attached }
+            }
+            name if name.ends_with("_pressure_plate") => {
+                let power = match prop(&props, "power") {
+                    Some(power) => power.parse().ok()?,
+                    None if powered => MAX_POWER,
+                    None => 0,
+                };
+                Component::Plate { power }
+            }
+            _ => return None,
+        };
+        Some(component)
+    }
+
+    /// Returns whether this component is redstone dust.
+    pub fn is_dust(self) -> bool {
+        match self {
+            Component::Dust { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the direction in which this component
+    /// outputs power if it is a repeater or comparator.
+    pub fn diode_output(self) -> Option<Direction> {
+        match self {
+            Component::Repeater { facing, .. } | Component::Comparator { facing, .. } => {
+                Some(facing.opposite())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn dust(data: RedstoneWireData) -> Component {
+    use feather_blocks::{
+        RedstoneWireEast, RedstoneWireNorth, RedstoneWireSouth, RedstoneWireWest,
+    };
+
+    Component::Dust {
+        power: data.power as u8,
+        connected: [
+            data.north != RedstoneWireNorth::None,
+            data.east != RedstoneWireEast::None,
+            data.south != RedstoneWireSouth::None,
+            data.west != RedstoneWireWest::None,
+        ],
+    }
+}
+
+/// Returns whether the given block conducts redstone power:
+/// whether components can power it and it then powers its
+/// neighbours.
+pub fn is_conductor(block: Block) -> bool {
+    if !block.is_solid() || !block.is_opaque() || Component::of(block).is_some() {
+        return false;
+    }
+    match block.to_name_and_props().0 {
+        "minecraft:piston" | "minecraft:sticky_piston" => false,
+        _ => true,
+    }
+}
+
+/// Returns the power a comparator outputs, which is stored in its block entity.
+pub fn comparator_output(chunk_map: &ChunkMap, pos: BlockPosition) -> u8 {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value::<ComparatorEntity>(data.clone()).ok())
+        .map_or(0, |entity| {
+            entity.output_signal.max(0).min(i32::from(MAX_POWER)) as u8
+        })
+}
+
+/// The block entity of a comparator, as saved in chunks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ComparatorEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// The power the comparator outputs.
+    #[serde(rename = "OutputSignal", default)]
+    pub output_signal: i32,
+}
+
+impl ComparatorEntity {
+    pub fn new(pos: BlockPosition, output_signal: u8) -> Self {
+        Self {
+            id: String::from("minecraft:comparator"),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            output_signal: i32::from(output_signal),
+        }
+    }
+}
+
+/// Returns the weak power the component at `pos` emits
+/// into its neighbour in the given direction. Weak power
+/// powers the neighbour itself, but isn't passed on to
+/// further blocks if the neighbour is a conductor.
+pub fn weak_power(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    component: Component,
+    dir: Direction,
+) -> u8 {
+    match component {
+        Component::Dust { power, connected } => match dir {
+            Direction::Down => power,
+            Direction::Up => 0,
+            dir => {
+                let index = |dir| Direction::HORIZONTAL.iter().position(|&d| d == dir);
+                let connects = |dir| index(dir).map_or(false, |i| connected[i]);
+                let [left, right] = dir.perpendicular();
+                let points = !connected.iter().any(|&c| c)
+                    || (connects(dir) && !connects(left) && !connects(right));
+                if points {
+                    power
+                } else {
+                    0
+                }
+            }
+        },
+        Component::Torch { lit, attached } => {
+            if lit && dir != attached {
+                MAX_POWER
+            } else {
+                0
+            }
+        }
+        Component::Repeater {
+            facing, powered, ..
+        } => {
+            if powered && dir == facing.opposite() {
+                MAX_POWER
+            } else {
+                0
+            }
+        }
+        Component::Comparator { facing, .. } => {
+            if dir == facing.opposite() {
+                comparator_output(chunk_map, pos)
+            } else {
+                0
+            }
+        }
+        Component::Switch { powered, .. } => {
+            if powered {
+                MAX_POWER
+            } else {
+                0
+            }
+        }
+        Component::Plate { power } => power,
+        Component::RedstoneBlock => MAX_POWER,
+    }
+}
+
+/// Returns the strong power the component at `pos` emits
+/// into its neighbour in the given direction. Conductors
+/// which are strongly powered power their own neighbours.
+pub fn strong_power(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    component: Component,
+    dir: Direction,
+) -> u8 {
+    match component {
+        Component::Dust { .. } | Component::Repeater { .. } | Component::Comparator { .. } => {
+            weak_power(chunk_map, pos, component, dir)
+        }
+        Component::Torch { lit, .. } => {
+            if lit && dir == Direction::Up {
+                MAX_POWER
+            } else {
+                0
+            }
+        }
+        Component::Switch { powered, attached } => {
+            if powered && dir == attached {
+                MAX_POWER
+            } else {
+                0
+            }
+        }
+        Component::Plate { power } => {
+            if dir == Direction::Down {
+                power
+            } else {
+                0
+            }
+        }
+        Component::RedstoneBlock => 0,
+    }
+}
+
+/// Returns the power a conductor at the given position
+/// is strongly powered with by its neighbours.
+pub fn conducted_power(chunk_map: &ChunkMap, pos: BlockPosition, include_dust: bool) -> u8 {
+    Direction::ALL
+        .iter()
+        .filter_map(|&dir| {
+            let source = dir.of(pos);
+            let component = Component::of(chunk_map.block_at(source)?)?;
+            if !include_dust && component.is_dust() {
+                return None;
+            }
+            Some(strong_power(chunk_map, source, component, dir.opposite()))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the power the block at `pos` receives from
+/// its neighbour in the given direction. If `include_dust`
+/// is false, power from redstone dust is ignored.
+pub fn received_power(
+    chunk_map: &ChunkMap,
+    pos: BlockPosition,
+    dir: Direction,
+    include_dust: bool,
+) -> u8 {
+    let source = dir.of(pos);
+    let block = match chunk_map.block_at(source) {
+        Some(block) => block,
+        None => return 0,
+    };
+
+    match Component::of(block) {
+        Some(component) if include_dust || !component.is_dust() => {
+            weak_power(chunk_map, source, component, dir.opposite())
+        }
+        Some(_) => 0,
+        None if is_conductor(block) => conducted_power(chunk_map, source, include_dust),
+        None => 0,
+    }
+}
+
+/// Returns the highest power the block at `pos`
+/// receives from any of its neighbours.
+pub fn power_at(chunk_map: &ChunkMap, pos: BlockPosition) -> u8 {
+    Direction::ALL
+        .iter()
+        .map(|&dir| received_power(chunk_map, pos, dir, true))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the input power of a repeater or comparator
+/// at `pos` which takes its input from the `facing` side.
+pub fn diode_input(chunk_map: &ChunkMap, pos: BlockPosition, facing: Direction) -> u8 {
+    let power = received_power(chunk_map, pos, facing, true);
+    match chunk_map.block_at(facing.of(pos)) {
+        Some(Block::RedstoneWire(data)) => power.max(data.power as u8),
+        _ => power,
+    }
+}
+
+/// Returns the power a repeater or comparator at `pos`
+/// receives from the side in the given direction.
+/// Only dust, redstone blocks and other diodes
+/// power diodes from their sides.
+pub fn diode_side_power(chunk_map: &ChunkMap, pos: BlockPosition, dir: Direction) -> u8 {
+    let source = dir.of(pos);
+    let component = match chunk_map.block_at(source).and_then(Component::of) {
+        Some(component) => component,
+        None => return 0,
+    };
+    match component {
+        Component::Dust { power, .. } => power,
+        Component::RedstoneBlock => MAX_POWER,
+        Component::Repeater { .. } | Component::Comparator { .. } => {
+            weak_power(chunk_map, source, component, dir.opposite())
+        }
+        _ => 0,
+    }
+}
+
+/// Returns the signal a comparator reads from a container,
+/// depending on how full it is, or `None` if the block at
+/// the given position isn't a container holding items.
+pub fn container_signal(chunk_map: &ChunkMap, pos: BlockPosition) -> Option<u8> {
+    let kind = ContainerKind::of(chunk_map.block_at(pos)?)?;
+    if !kind.stores_items() {
+        return None;
+    }
+
+    let slots = load_slots(chunk_map, kind, pos);
+    let fullness: f32 = slots
+        .iter()
+        .flatten()
+        .map(|stack| f32::from(stack.amount) / f32::from(max_size(stack.ty).min(kind.slot_limit())))
+        .sum();
+    if fullness <= 0.0 {
+        return Some(0);
+    }
+    Some((1.0 + fullness / slots.len() as f32 * 14.0).floor() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feather_blocks::{LeverData, LeverFace, LeverFacing, RedstoneWallTorchData};
+
+    #[test]
+    fn test_with_property() {
+        let lever = Block::Lever(LeverData::default());
+        let powered = with_property(lever, "powered", "true");
+        assert_eq!(property(powered, "powered"), Some(String::from("true")));
+        assert_eq!(with_property(lever, "lit", "true"), lever);
+    }
+
+    #[test]
+    fn test_components() {
+        let torch = Block::RedstoneWallTorch(RedstoneWallTorchData {
+            lit: true,
+            facing: feather_blocks::RedstoneWallTorchFacing::North,
+        });
+        assert_eq!(
+            Component::of(torch),
+            Some(Component::Torch {
+                lit: true,
+                attached: Direction::South,
+            })
+        );
+
+        let lever = Block::Lever(LeverData {
+            face: LeverFace::Wall,
+            facing: LeverFacing::East,
+            powered: true,
+        });
+        assert_eq!(
+            Component::of(lever),
+            Some(Component::Switch {
+                powered: true,
+                attached: Direction::West,
+            })
+        );
+        assert_eq!(Component::of(Block::Stone), None);
+        assert!(is_conductor(Block::Stone));
+        assert!(!is_conductor(Block::Glass));
+    }
+
+    #[test]
+    fn test_dust_power() {
+        let chunk_map = ChunkMap::new();
+        let pos = BlockPosition::new(0, 64, 0);
+        let line = Component::Dust {
+            power: 10,
+            connected: [true, false, true, false],
+        };
+        assert_eq!(weak_power(&chunk_map, pos, line, Direction::North), 10);
+        assert_eq!(weak_power(&chunk_map, pos, line, Direction::East), 0);
+        assert_eq!(weak_power(&chunk_map, pos, line, Direction::Down), 10);
+        assert_eq!(weak_power(&chunk_map, pos, line, Direction::Up), 0);
+
+        let dot = Component::Dust {
+            power: 3,
+            connected: [false; 4],
+        };
+        assert_eq!(weak_power(&chunk_map, pos, dot, Direction::East), 3);
+    }
+}
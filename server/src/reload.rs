@@ -0,0 +1,346 @@
+//! Configuration hot reloading.
+//!
+//! The configuration file is re-read when an operator executes
+//! `/reload` or, on Unix, when the server receives SIGHUP.
+//! Only some settings can be changed at runtime; changes
+//! to other settings are reported and ignored until the
+//! server is restarted.
+
+use crate::commands::{
+    is_privileged, no_permission, reply, send_message, CommandEvent, CommandRegistry,
+    ConsoleComponent,
+};
+use crate::config::{self, Config, SharedConfig};
+use crate::crash;
+use crate::entity::NamedComponent;
+use crate::lang::{Locale, Message};
+use crate::network::NetworkComponent;
+use crate::systems::RELOAD;
+use crate::timings::DispatcherBuilderExt;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Read, ReadStorage, System, World, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Resource set when a reload has been
+/// requested by a signal.
+#[derive(Debug, Default, Clone)]
+pub struct ReloadFlag(pub Arc<AtomicBool>);
+
+/// The outcome of merging a reloaded configuration
+/// into the current one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReloadReport {
+    /// Settings which were changed and applied.
+    pub applied: Vec<&'static str>,
+    /// Settings which were changed but only
+    /// take effect after a restart.
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ReloadReport {
+    /// Returns messages describing the report.
+    pub fn messages(&self) -> Vec<Message> {
+        let mut messages = vec![];
+
+        if self.applied.is_empty() {
+            messages.push(Message::translate("feather.command.reload.unchanged"));
+        } else {
+            messages.push(
+                Message::translate("feather.command.reload.applied").with(self.applied.join(", ")),
+            );
+        }
+
+        if !self.restart_required.is_empty() {
+            messages.push(
+                Message::translate("feather.command.reload.restart_required")
+                    .with(self.restart_required.join(", ")),
+            );
+        }
+
+        messages
+    }
+}
+
+/// Merges a newly loaded configuration into the current one.
+///
+/// Settings which can be changed at runtime are
+/// taken from `new`; all other settings retain their
+/// current values so that the running server stays consistent.
+pub fn merge(current: &Config, new: &Config) -> (Config, ReloadReport) {
+    let mut merged = current.clone();
+    let mut report = ReloadReport::default();
+
+    macro_rules! apply {
+        ($($field:ident).+) => {
+            if current.$($field).+ != new.$($field).+ {
+                merged.$($field).+ = new.$($field).+.clone();
+                report.applied.push(stringify!($($field).+));
+            }
+        };
+    }
+
+    macro_rules! restart {
+        ($($field:ident).+) => {
+            if current.$($field).+ != new.$($field).+ {
+                report.restart_required.push(stringify!($($field).+));
+            }
+        };
+    }
+
+    apply!(server.motd);
+    apply!(server.max_players);
+    apply!(server.view_distance);
+    apply!(server.dynamic_view_distance);
+    apply!(server.default_gamemode);
+    apply!(server.whitelist);
+    apply!(server.whitelisted_players);
+    apply!(server.operators);
+    apply!(server.shutdown_message);
+    apply!(server.locale);
+    apply!(gameplay.monster_spawning);
+    apply!(gameplay.animal_spawning);
+    apply!(gameplay.pvp);
+    apply!(gameplay.nerf_spawner_mobs);
+    apply!(gameplay.sleeping_percentage);
+    apply!(gameplay.max_mobs);
+    apply!(resource_pack.url);
+    apply!(resource_pack.hash);
+    apply!(world.save_interval);
+    apply!(world.chunk_unload_delay);
+    apply!(lighting.max_updates_per_tick);
+    apply!(scripting.max_operations);
+    apply!(scripting.max_call_levels);
+
+    restart!(io.compression_threshold);
+    restart!(server.online_mode);
+    restart!(server.address);
+    restart!(server.port);
+    restart!(server.listeners);
+    restart!(server.worker_threads);
+    restart!(log.level);
+    restart!(world.name);
+    restart!(world.generator);
+    restart!(world.seed);
+    restart!(metrics.enabled);
+    restart!(metrics.address);
+    restart!(admin_api.enabled);
+    restart!(admin_api.address);
+    restart!(admin_api.token);
+    restart!(rollback.enabled);
+
+    (merged, report)
+}
+
+/// Registers a SIGHUP handler which requests
+/// a configuration reload.
+#[cfg(unix)]
+pub fn register_signal_handler(world: &World) {
+    let flag = world.fetch::<ReloadFlag>();
+    if let Err(e) = signal_hook::flag::register(signal_hook::SIGHUP, Arc::clone(&flag.0)) {
+        warn!("Failed to register SIGHUP handler: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn register_signal_handler(_world: &World) {}
+
+/// System which reloads the configuration
+/// on `/reload` or SIGHUP.
+pub struct ReloadSystem {
+    path: String,
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl ReloadSystem {
+    /// Creates a system reloading the configuration
+    /// file at the given path.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            reader: None,
+        }
+    }
+}
+
+impl<'a> System<'a> for ReloadSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, ReloadFlag>,
+        Write<'a, Arc<Config>>,
+        Read<'a, Arc<SharedConfig>>,
+        Write<'a, Locale>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, flag, mut config, shared_config, mut locale, networks, nameds, consoles) =
+            data;
+
+        let mut senders = vec![];
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if event.name != "reload" {
+                continue;
+            }
+
+            if is_privileged(&config, event.sender, &nameds, &consoles) {
+                senders.push(event.sender);
+            } else {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+            }
+        }
+        let signalled = flag.0.swap(false, Ordering::SeqCst);
+
+        if senders.is_empty() && !signalled {
+            return;
+        }
+
+        let messages = match config::load_from_file(&self.path) {
+            Ok(new) => {
+                let (merged, report) = merge(&config, &new);
+                let merged = Arc::new(merged);
+                *config = Arc::clone(&merged);
+                crash::set_config(Arc::clone(&merged));
+                // The language file is re-read even if
+                // the locale is unchanged.
+                *locale = Locale::load(&merged.server.locale);
+                shared_config.set(merged);
+                report.messages()
+            }
+            Err(e) => vec![Message::translate("feather.command.reload.failed").with(e)],
+        };
+
+        for message in &messages {
+            info!("{}", locale.text(message));
+        }
+
+        // Messages are already logged, so
+        // the console doesn't need a reply.
+        for sender in senders {
+            if let Some(network) = networks.get(sender) {
+                for message in &messages {
+                    send_message(network, &locale, message.clone());
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("reload");
+    }
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ReloadSystem::new(config::CONFIG_PATH), RELOAD, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::implementation::ChatMessageClientbound;
+    use feather_core::PacketType;
+    use specs::WorldExt;
+    use std::fs;
+
+    #[test]
+    fn test_merge() {
+        let current = Config::default();
+        let mut new = Config::default();
+        new.server.motd = String::from("New MOTD");
+        new.server.max_players = 32;
+        new.server.port = 25566;
+
+        let (merged, report) = merge(&current, &new);
+
+        assert_eq!(merged.server.motd, "New MOTD");
+        assert_eq!(merged.server.max_players, 32);
+        assert_eq!(merged.server.port, current.server.port);
+        assert_eq!(report.applied, vec!["server.motd", "server.max_players"]);
+        assert_eq!(report.restart_required, vec!["server.port"]);
+    }
+
+    #[test]
+    fn test_merge_unchanged() {
+        let (_, report) = merge(&Config::default(), &Config::default());
+        assert_eq!(report, ReloadReport::default());
+        assert_eq!(report.messages().len(), 1);
+    }
+
+    #[test]
+    fn test_reload_command() {
+        let path = std::env::temp_dir().join("feather-reload-test.toml");
+        let input = config::DEFAULT_CONFIG_STR.replace("A Feather server", "Reloaded");
+        fs::write(&path, input).unwrap();
+
+        let (mut w, mut d) = t::builder()
+            .with(ReloadSystem::new(path.to_str().unwrap()), "")
+            .build();
+        assert!(w.fetch::<CommandRegistry>().contains("reload"));
+
+        let player = t::add_player(&mut w);
+
+        // Only operators may reload.
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/reload").unwrap());
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(w.fetch::<Arc<Config>>().server.motd, "A Feather server");
+        t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = String::from("admin");
+        let mut config = Config::default();
+        config.server.operators.push(String::from("admin"));
+        w.insert(Arc::new(config));
+
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/reload").unwrap());
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(w.fetch::<Arc<Config>>().server.motd, "Reloaded");
+        assert_eq!(w.fetch::<Arc<SharedConfig>>().get().server.motd, "Reloaded");
+
+        let packet = t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+        let packet = cast_packet::<ChatMessageClientbound>(&*packet);
+        assert!(packet.json_data.contains("server.motd"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_signal() {
+        let path = std::env::temp_dir().join("feather-reload-signal-test.toml");
+        let input = config::DEFAULT_CONFIG_STR.replace("max_players = 16", "max_players = 4");
+        fs::write(&path, input).unwrap();
+
+        let (w, mut d) = t::builder()
+            .with(ReloadSystem::new(path.to_str().unwrap()), "")
+            .build();
+
+        d.dispatch(&w);
+        assert_eq!(w.fetch::<Arc<Config>>().server.max_players, 16);
+
+        w.fetch::<ReloadFlag>().0.store(true, Ordering::SeqCst);
+        d.dispatch(&w);
+        assert_eq!(w.fetch::<Arc<Config>>().server.max_players, 4);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1034 @@
+//! Logging and rollback of changes made by players.
+//!
+//! If `rollback.enabled` is set, the `ChangeLog` records each block
+//! changed by a player and each container slot changed in a container
+//! window, along with the player who changed it and when. Changes are
+//! appended to `changes.log` in the world directory, which is loaded
+//! again at startup.
+//!
+//! Changes older than `rollback.max_age` are dropped from memory as
+//! time passes. The file is only appended to while the server runs;
+//! it is rewritten without the dropped changes when it is loaded.
+//!
+//! The log is a sequence of records, each starting with a tag byte:
+//! actor records assign an ID to a player's UUID and name, so that
+//! change records only need to store the ID. Block states are stored
+//! by their native state ID and items in the slot format of the
+//! protocol.
+//!
+//! Operators revert griefing using `/rollback <player> <time>`, which
+//! reverts the changes made by a player within the given time, such as
+//! `30m` or `2h`, and `/rollback region <time>`, which reverts all changes
+//! in the region selected using `//pos1` and `//pos2`. Changes are reverted
+//! from newest to oldest, and a change is only reverted if the block or
+//! slot wasn't changed again since. Blocks are set using `WorldEdit`, so
+//! large rollbacks are relit and resent per chunk, and a rollback can be
+//! undone using `//undo`.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::container::{
+    load_container, store_container, ContainerChangeEvent, ContainerKind, OpenContainerComponent,
+    WINDOW_ID,
+};
+use crate::entity::NamedComponent;
+use crate::lang::{Locale, Message};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::{CHANGE_LOG, ROLLBACK_COMMAND};
+use crate::timings::DispatcherBuilderExt;
+use crate::worldedit::{self, Editor, WorldEdit};
+use bytes::BytesMut;
+use feather_core::bytes_ext::{BytesExt, BytesMutExt, TryGetError};
+use feather_core::network::mctypes::{McTypeRead, McTypeWrite};
+use feather_core::network::packet::implementation::SetSlot;
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, BlockExt, ItemStack};
+use hashbrown::HashMap;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Join, Read, ReadStorage, System, World, Write};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, ErrorKind, Read as _, Write as _};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// The name of the change log file in the world directory.
+pub const LOG_FILE: &str = "changes.log";
+
+/// The tags identifying the kinds of records in the log.
+const TAG_ACTOR: u8 = 0;
+const TAG_BLOCK: u8 = 1;
+const TAG_SLOT: u8 = 2;
+
+/// The number of bytes read from the file at a time when loading it.
+const READ_CHUNK_BYTES: u64 = 64 * 1024;
+/// Records are much smaller than this, so data which doesn't
+/// start with a valid record of at most this size is invalid.
+const MAX_RECORD_BYTES: usize = 1024 * 1024;
+
+/// A player who made changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Actor {
+    pub uuid: Uuid,
+    /// The name of the player when they last made a change.
+    pub name: String,
+}
+
+/// A change to a block or to a slot of a container.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    Block {
+        old: Block,
+        new: Block,
+    },
+    Slot {
+        slot: u8,
+        old: Option<ItemStack>,
+        new: Option<ItemStack>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The time of the change in seconds since the Unix epoch.
+    pub time: u64,
+    /// The ID of the player who made the change.
+    pub actor: u32,
+    pub pos: BlockPosition,
+    pub kind: ChangeKind,
+}
+
+/// Resource recording the changes made by players.
+///
+/// The default log is disabled and doesn't record anything.
+#[derive(Default)]
+pub struct ChangeLog {
+    enabled: bool,
+    /// The age in seconds after which changes
+    /// are dropped, or 0 to keep them forever.
+    max_age: u64,
+    actors: Vec<Actor>,
+    actor_ids: HashMap<Uuid, u32>,
+    changes: Vec<Change>,
+    /// Records which haven't been written to the file yet.
+    pending: BytesMut,
+    file: Option<File>,
+}
+
+impl ChangeLog {
+    /// Returns an enabled log which isn't backed by a file.
+    pub fn in_memory() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Loads the log from the given file, creating it if it doesn't
+    /// exist. Changes are appended to the file when the log is flushed.
+    ///
+    /// Changes older than `max_age` are dropped, unless it is 0, and
+    /// the file is rewritten without them. The file is read in chunks,
+    /// so only the parsed changes are kept in memory.
+    ///
+    /// If the file ends with an incomplete record, e.g. because the
+    /// server crashed while writing it, the record is discarded.
+    pub fn open(path: &Path, max_age: Duration) -> Result<Self, io::Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => Some(file),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut log = Self::in_memory();
+        log.max_age = max_age.as_secs();
+
+        // Bytes read from the file which haven't been parsed
+        // yet, because they don't contain a complete record.
+        let mut data = vec![];
+        let mut valid = 0;
+        loop {
+            let mut cursor = Cursor::new(&data[..]);
+            let mut parsed = 0;
+            while parsed < data.len() && log.read_record(&mut cursor).is_ok() {
+                parsed = cursor.position() as usize;
+            }
+            data.drain(..parsed);
+            valid += parsed as u64;
+
+            let read = match &mut file {
+                Some(file) if data.len() <= MAX_RECORD_BYTES => {
+                    file.take(READ_CHUNK_BYTES).read_to_end(&mut data)?
+                }
+                _ => 0,
+            };
+            if read == 0 {
+                break;
+            }
+        }
+
+        let len = match &file {
+            Some(file) => file.metadata()?.len(),
+            None => 0,
+        };
+        // Close the file so that it can be replaced.
+        drop(file);
+        if valid < len {
+            warn!(
+                "Discarding {} bytes of invalid records at the end of {}",
+                len - valid,
+                path.display()
+            );
+        }
+
+        let pruned = log.prune(now());
+        if pruned > 0 {
+            info!(
+                "Dropping {} changes older than {} from {}",
+                pruned,
+                humantime::format_duration(max_age),
+                path.display()
+            );
+            log.rewrite(path)?;
+        } else if valid < len {
+            OpenOptions::new().write(true).open(path)?.set_len(valid)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log.file = Some(file);
+
+        Ok(log)
+    }
+
+    /// Drops the changes which are older than the
+    /// maximum age at the given time, returning the
+    /// number of dropped changes.
+    ///
+    /// Changes are only dropped from memory;
+    /// they stay in the file until it is loaded again.
+    pub fn prune(&mut self, now: u64) -> usize {
+        if self.max_age == 0 {
+            return 0;
+        }
+
+        // Changes are recorded in order, so the old ones are first.
+        let cutoff = now.saturating_sub(self.max_age);
+        let count = self
+            .changes
+            .iter()
+            .position(|change| change.time >= cutoff)
+            .unwrap_or_else(|| self.changes.len());
+        self.changes.drain(..count);
+        count
+    }
+
+    /// Replaces the file at the given path with the
+    /// actors and changes currently in the log.
+    ///
+    /// All actors are kept, since changes refer to them by ID.
+    fn rewrite(&self, path: &Path) -> Result<(), io::Error> {
+        let mut buf = BytesMut::new();
+        for (id, actor) in self.actors.iter().enumerate() {
+            write_actor(&mut buf, id as u32, actor);
+        }
+        for change in &self.changes {
+            write_change(&mut buf, change);
+        }
+
+        // Write to a temporary file first, so that the
+        // log isn't lost if the server crashes meanwhile.
+        let temp = path.with_extension("log.tmp");
+        fs::write(&temp, &buf)?;
+        fs::rename(&temp, path)
+    }
+
+    /// Returns whether changes are recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns all recorded changes, from oldest to newest.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns the player with the given ID.
+    pub fn actor(&self, id: u32) -> Option<&Actor> {
+        self.actors.get(id as usize)
+    }
+
+    /// Returns the IDs of the players who made
+    /// changes using the given name.
+    pub fn actors_named(&self, name: &str) -> Vec<u32> {
+        (0..self.actors.len() as u32)
+            .filter(|id| self.actors[*id as usize].name.eq_ignore_ascii_case(name))
+            .collect()
+    }
+
+    /// Records a change made by a player
+    /// at the given time, if the log is enabled.
+    pub fn record(
+        &mut self,
+        player: &NamedComponent,
+        time: u64,
+        pos: BlockPosition,
+        kind: ChangeKind,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let actor = self.actor_id(player);
+        let change = Change {
+            time,
+            actor,
+            pos,
+            kind,
+        };
+        write_change(&mut self.pending, &change);
+        self.changes.push(change);
+    }
+
+    /// Writes the changes recorded since the
+    /// last flush to the file, if any.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(&self.pending)?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Returns the ID of a player, recording
+    /// the player if they are new or renamed.
+    fn actor_id(&mut self, player: &NamedComponent) -> u32 {
+        let id = match self.actor_ids.get(&player.uuid) {
+            Some(id) if self.actors[*id as usize].name == player.display_name => return *id,
+            Some(id) => *id,
+            None => self.actors.len() as u32,
+        };
+
+        let actor = Actor {
+            uuid: player.uuid,
+            name: player.display_name.clone(),
+        };
+        write_actor(&mut self.pending, id, &actor);
+        self.define_actor(id, actor);
+
+        id
+    }
+
+    fn define_actor(&mut self, id: u32, actor: Actor) {
+        self.actor_ids.insert(actor.uuid, id);
+        match self.actors.get_mut(id as usize) {
+            Some(existing) => *existing = actor,
+            None => self.actors.push(actor),
+        }
+    }
+
+    /// Reads one record, adding the actor or change it contains.
+    fn read_record(&mut self, cursor: &mut Cursor<&[u8]>) -> Result<(), TryGetError> {
+        let tag = cursor.try_get_u8()?;
+        if tag == TAG_ACTOR {
+            let id = cursor.try_get_var_int()? as u32;
+            let uuid = cursor.try_get_uuid()?;
+            let name = cursor.try_get_string()?;
+            // IDs are assigned in order.
+            if id as usize > self.actors.len() {
+                return Err(TryGetError::InvalidValue);
+            }
+            self.define_actor(id, Actor { uuid, name });
+            return Ok(());
+        }
+
+        let time = cursor.try_get_u64()?;
+        let actor = cursor.try_get_var_int()? as u32;
+        let pos = cursor.try_get_position()?;
+        let kind = match tag {
+            TAG_BLOCK => ChangeKind::Block {
+                old: read_block(cursor)?,
+                new: read_block(cursor)?,
+            },
+            TAG_SLOT => ChangeKind::Slot {
+                slot: cursor.try_get_u8()?,
+                old: cursor.try_get_slot()?,
+                new: cursor.try_get_slot()?,
+            },
+            _ => return Err(TryGetError::InvalidValue),
+        };
+        if actor as usize >= self.actors.len() {
+            return Err(TryGetError::InvalidValue);
+        }
+
+        self.changes.push(Change {
+            time,
+            actor,
+            pos,
+            kind,
+        });
+        Ok(())
+    }
+}
+
+fn write_actor(buf: &mut BytesMut, id: u32, actor: &Actor) {
+    buf.push_u8(TAG_ACTOR);
+    buf.push_var_int(id as i32);
+    buf.push_uuid(&actor.uuid);
+    buf.push_string(&actor.name);
+}
+
+fn write_change(buf: &mut BytesMut, change: &Change) {
+    let tag = match change.kind {
+        ChangeKind::Block { .. } => TAG_BLOCK,
+        ChangeKind::Slot { .. } => TAG_SLOT,
+    };
+    buf.push_u8(tag);
+    buf.push_u64(change.time);
+    buf.push_var_int(change.actor as i32);
+    buf.push_position(&change.pos);
+
+    match &change.kind {
+        ChangeKind::Block { old, new } => {
+            buf.push_u16(old.native_state_id());
+            buf.push_u16(new.native_state_id());
+        }
+        ChangeKind::Slot { slot, old, new } => {
+            buf.push_u8(*slot);
+            buf.push_slot(old);
+            buf.push_slot(new);
+        }
+    }
+}
+
+fn read_block(cursor: &mut Cursor<&[u8]>) -> Result<Block, TryGetError> {
+    Block::from_native_state_id(cursor.try_get_u16()?).ok_or(TryGetError::InvalidValue)
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// The blocks and container slots restored by a rollback.
+#[derive(Debug, Default)]
+pub struct Rollback {
+    /// The number of blocks restored.
+    pub blocks: usize,
+    /// The restored slots with their new items.
+    pub slots: Vec<(BlockPosition, u8, Option<ItemStack>)>,
+}
+
+/// Reverts the logged changes for which `filter` returns `true`,
+/// from newest to oldest. Changes to blocks and slots which were
+/// changed again since are skipped. Blocks are restored as an
+/// edit by `editor`, so the rollback can be undone.
+pub fn rollback(
+    log: &ChangeLog,
+    chunk_map: &mut ChunkMap,
+    world_edit: &mut WorldEdit,
+    editor: &Editor,
+    mut filter: impl FnMut(&Change) -> bool,
+) -> Rollback {
+    let mut blocks: HashMap<BlockPosition, Block> = HashMap::new();
+    let mut slots: HashMap<(BlockPosition, u8), Option<ItemStack>> = HashMap::new();
+
+    // Track the value each block and slot will have after
+    // the rollback, so that several changes to the same
+    // block or slot are reverted one after another.
+    let block_at = |blocks: &HashMap<BlockPosition, Block>, pos: BlockPosition| {
+        blocks
+            .get(&pos)
+            .copied()
+            .or_else(|| chunk_map.block_at(pos))
+    };
+    for change in log.changes().iter().rev().filter(|change| filter(change)) {
+        match &change.kind {
+            ChangeKind::Block { old, new } => {
+                if block_at(&blocks, change.pos) == Some(*new) {
+                    blocks.insert(change.pos, *old);
+                }
+            }
+            ChangeKind::Slot { slot, old, new } => {
+                let kind = match block_at(&blocks, change.pos).and_then(ContainerKind::of) {
+                    Some(kind) if kind.stores_items() && (*slot as usize) < kind.size() => kind,
+                    _ => continue,
+                };
+                let current = slots.entry((change.pos, *slot)).or_insert_with(|| {
+                    load_container(chunk_map, kind, change.pos).slots(kind.size())[*slot as usize]
+                        .clone()
+                });
+                if *current == *new {
+                    *current = old.clone();
+                }
+            }
+        }
+    }
+
+    let count = world_edit.set_blocks(chunk_map, editor, blocks);
+
+    let mut restored = vec![];
+    for ((pos, slot), stack) in slots {
+        let kind = continue_if_none!(chunk_map.block_at(pos).and_then(ContainerKind::of));
+        let mut entity = load_container(chunk_map, kind, pos);
+        let mut items = entity.slots(kind.size());
+        if items[slot as usize] == stack {
+            continue;
+        }
+        items[slot as usize] = stack.clone();
+        entity.set_slots(&items);
+        store_container(chunk_map, pos, &entity);
+        restored.push((pos, slot, stack));
+    }
+
+    Rollback {
+        blocks: count,
+        slots: restored,
+    }
+}
+
+/// System which records the changes made by players.
+///
+/// This system listens to `BlockUpdateEvent`s
+/// and `ContainerChangeEvent`s.
+#[derive(Default)]
+pub struct ChangeLogSystem {
+    block_reader: Option<ReaderId<BlockUpdateEvent>>,
+    container_reader: Option<ReaderId<ContainerChangeEvent>>,
+}
+
+impl<'a> System<'a> for ChangeLogSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Read<'a, EventChannel<ContainerChangeEvent>>,
+        Write<'a, ChangeLog>,
+        ReadStorage<'a, NamedComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (block_events, container_events, mut log, nameds) = data;
+
+        let block_events = block_events.read(self.block_reader.as_mut().unwrap());
+        let container_events = container_events.read(self.container_reader.as_mut().unwrap());
+        if !log.is_enabled() {
+            return;
+        }
+
+        let time = now();
+        log.prune(time);
+        for event in block_events {
+            let player = match event.cause {
+                BlockUpdateCause::Player(player) => continue_if_none!(nameds.get(player)),
+                _ => continue,
+            };
+            log.record(
+                player,
+                time,
+                event.pos,
+                ChangeKind::Block {
+                    old: event.old_block,
+                    new: event.new_block,
+                },
+            );
+        }
+
+        for event in container_events {
+            let player = continue_if_none!(nameds.get(event.player));
+            for (slot, old, new) in &event.slots {
+                log.record(
+                    player,
+                    time,
+                    event.pos,
+                    ChangeKind::Slot {
+                        slot: *slot as u8,
+                        old: old.clone(),
+                        new: new.clone(),
+                    },
+                );
+            }
+        }
+
+        if let Err(e) = log.flush() {
+            warn!("Failed to write to {}: {}", LOG_FILE, e);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.block_reader = Some(
+            world
+                .fetch_mut::<EventChannel<BlockUpdateEvent>>()
+                .register_reader(),
+        );
+        self.container_reader = Some(
+            world
+                .fetch_mut::<EventChannel<ContainerChangeEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+/// System implementing `/rollback`.
+#[derive(Default)]
+pub struct RollbackCommandSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl<'a> System<'a> for RollbackCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, ChangeLog>,
+        Write<'a, ChunkMap>,
+        Write<'a, WorldEdit>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+        ReadStorage<'a, OpenContainerComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            log,
+            mut chunk_map,
+            mut world_edit,
+            config,
+            locale,
+            nameds,
+            networks,
+            consoles,
+            open_containers,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if event.name != "rollback" {
+                continue;
+            }
+
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let result = execute(event, &log, &mut chunk_map, &mut world_edit);
+            let message = match result {
+                Ok(rollback) => {
+                    // Update players viewing the restored containers.
+                    for (open, network) in (&open_containers, &networks).join() {
+                        for (pos, slot, stack) in &rollback.slots {
+                            if open.pos == *pos {
+                                send_packet_to_player(
+                                    network,
+                                    SetSlot::new(WINDOW_ID as i8, *slot as i16, stack.clone()),
+                                );
+                            }
+                        }
+                    }
+
+                    Message::translate("feather.rollback.done")
+                        .with(rollback.blocks)
+                        .with(rollback.slots.len())
+                }
+                Err(message) => message,
+            };
+            reply(event.sender, &networks, &consoles, &locale, message);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("rollback");
+    }
+}
+
+/// Executes the `/rollback` command.
+fn execute(
+    event: &CommandEvent,
+    log: &ChangeLog,
+    chunk_map: &mut ChunkMap,
+    world_edit: &mut WorldEdit,
+) -> Result<Rollback, Message> {
+    let (target, time) = match event.args.as_slice() {
+        [target, time] => (target, time),
+        _ => return Err(usage("/rollback <player|region> <time>")),
+    };
+    if !log.is_enabled() {
+        return Err(Message::translate("feather.rollback.disabled"));
+    }
+
+    let duration = humantime::parse_duration(time)
+        .map_err(|_| Message::translate("feather.rollback.invalid_time").with(time))?;
+    let since = now().saturating_sub(duration.as_secs());
+
+    let editor = Editor::Entity(event.sender);
+    let result = if target == "region" {
+        let region = worldedit::selection(world_edit, event.sender)?;
+        rollback(log, chunk_map, world_edit, &editor, |change| {
+            change.time >= since && region.contains(change.pos)
+        })
+    } else {
+        let actors = log.actors_named(target);
+        if actors.is_empty() {
+            return Err(Message::translate("feather.rollback.unknown_player").with(target));
+        }
+        rollback(log, chunk_map, world_edit, &editor, |change| {
+            change.time >= since && actors.contains(&change.actor)
+        })
+    };
+
+    Ok(result)
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ChangeLogSystem::default(), CHANGE_LOG, &[]);
+    dispatcher.add_timed(RollbackCommandSystem::default(), ROLLBACK_COMMAND, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_blocks::ShulkerBoxData;
+    use feather_core::world::chunk::Chunk;
+    use feather_core::{ChunkPosition, Item};
+    use specs::{Builder, WorldExt};
+    use std::path::PathBuf;
+
+    fn player(name: &str) -> NamedComponent {
+        NamedComponent {
+            display_name: name.to_string(),
+            uuid: Uuid::new_v4(),
+        }
+    }
+
+    fn block_change(old: Block, new: Block) -> ChangeKind {
+        ChangeKind::Block { old, new }
+    }
+
+    fn temp_log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("feather-rollback-{}-{}", name, Uuid::new_v4()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut log = ChangeLog::default();
+        log.record(
+            &player("Steve"),
+            0,
+            BlockPosition::new(0, 0, 0),
+            block_change(Block::Air, Block::Stone),
+        );
+        assert!(log.changes().is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_log_path("save");
+        let steve = player("Steve");
+        let mut stack = ItemStack::new(Item::DiamondSword, 1);
+        stack.set_custom_name(Some(String::from(r#"{"text":"Sword"}"#)));
+
+        let mut log = ChangeLog::open(&path, Duration::default()).unwrap();
+        log.record(
+            &steve,
+            100,
+            BlockPosition::new(-5, 64, 300),
+            block_change(Block::Stone, Block::Air),
+        );
+        log.record(
+            &player("Alex"),
+            101,
+            BlockPosition::new(0, 10, 0),
+            ChangeKind::Slot {
+                slot: 3,
+                old: Some(stack),
+                new: None,
+            },
+        );
+        log.flush().unwrap();
+
+        // A renamed player keeps their ID.
+        let renamed = NamedComponent {
+            display_name: "Herobrine".to_string(),
+            uuid: steve.uuid,
+        };
+        log.record(
+            &renamed,
+            102,
+            BlockPosition::new(1, 2, 3),
+            block_change(Block::Air, Block::Dirt),
+        );
+        log.flush().unwrap();
+
+        let loaded = ChangeLog::open(&path, Duration::default()).unwrap();
+        assert_eq!(loaded.changes(), log.changes());
+        assert_eq!(loaded.changes()[2].actor, 0);
+        assert_eq!(loaded.actor(0).unwrap().name, "Herobrine");
+        assert_eq!(loaded.actor(1).unwrap().name, "Alex");
+        assert_eq!(loaded.actors_named("herobrine"), vec![0]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let path = temp_log_path("truncated");
+        let mut log = ChangeLog::open(&path, Duration::default()).unwrap();
+        log.record(
+            &player("Steve"),
+            100,
+            BlockPosition::new(0, 0, 0),
+            block_change(Block::Air, Block::Stone),
+        );
+        log.flush().unwrap();
+        drop(log);
+
+        let valid = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[TAG_BLOCK, 0, 0])
+            .unwrap();
+
+        let mut log = ChangeLog::open(&path, Duration::default()).unwrap();
+        assert_eq!(log.changes().len(), 1);
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid);
+
+        // New records are appended after the valid ones.
+        log.record(
+            &player("Alex"),
+            101,
+            BlockPosition::new(0, 1, 0),
+            block_change(Block::Air, Block::Dirt),
+        );
+        log.flush().unwrap();
+        assert_eq!(
+            ChangeLog::open(&path, Duration::default())
+                .unwrap()
+                .changes()
+                .len(),
+            2
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_large_log() {
+        let path = temp_log_path("large");
+        let steve = player("Steve");
+        let mut log = ChangeLog::open(&path, Duration::default()).unwrap();
+        // Records span several of the chunks the file is read in.
+        for x in 0..10_000 {
+            log.record(
+                &steve,
+                100,
+                BlockPosition::new(x, 64, 0),
+                block_change(Block::Air, Block::Stone),
+            );
+        }
+        log.flush().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 2 * READ_CHUNK_BYTES);
+
+        let loaded = ChangeLog::open(&path, Duration::default()).unwrap();
+        assert_eq!(loaded.changes(), log.changes());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_max_age() {
+        let path = temp_log_path("max_age");
+        let max_age = Duration::from_secs(60 * 60);
+        let time = now();
+        let mut log = ChangeLog::open(&path, max_age).unwrap();
+        log.record(
+            &player("Steve"),
+            time - 2 * 60 * 60,
+            BlockPosition::new(0, 0, 0),
+            block_change(Block::Air, Block::Stone),
+        );
+        log.record(
+            &player("Alex"),
+            time,
+            BlockPosition::new(0, 1, 0),
+            block_change(Block::Air, Block::Dirt),
+        );
+        log.flush().unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+
+        // Old changes are dropped from memory...
+        assert_eq!(log.prune(time), 1);
+        assert_eq!(log.changes()[0].time, time);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+
+        // ...and from the file when it is loaded.
+        let loaded = ChangeLog::open(&path, max_age).unwrap();
+        assert_eq!(loaded.changes(), log.changes());
+        assert_eq!(loaded.actor(1).unwrap().name, "Alex");
+        assert!(fs::metadata(&path).unwrap().len() < len);
+        assert_eq!(
+            ChangeLog::open(&path, max_age).unwrap().changes(),
+            log.changes()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut chunk_map = ChunkMap::new();
+        chunk_map.set_chunk_at(
+            ChunkPosition::new(0, 0),
+            Chunk::new(ChunkPosition::new(0, 0)),
+        );
+        let mut world_edit = WorldEdit::default();
+        let mut log = ChangeLog::in_memory();
+        let (steve, alex) = (player("Steve"), player("Alex"));
+        let pos = |x| BlockPosition::new(x, 1, 0);
+
+        let mut change = |log: &mut ChangeLog,
+                          who: &NamedComponent,
+                          time: u64,
+                          x: i32,
+                          old: Block,
+                          new: Block| {
+            chunk_map.set_block_at(pos(x), new).unwrap();
+            log.record(who, time, pos(x), block_change(old, new));
+        };
+        change(&mut log, &steve, 10, 0, Block::Air, Block::Stone);
+        change(&mut log, &steve, 20, 0, Block::Stone, Block::Dirt);
+        change(&mut log, &steve, 20, 1, Block::Air, Block::Stone);
+        // Alex changed the block again, so it isn't reverted.
+        change(&mut log, &alex, 30, 1, Block::Stone, Block::Glass);
+        change(&mut log, &alex, 30, 2, Block::Air, Block::Glass);
+
+        let editor = Editor::Plugin("test".to_string());
+        let result = rollback(&log, &mut chunk_map, &mut world_edit, &editor, |change| {
+            change.actor == 0
+        });
+        assert_eq!(result.blocks, 1);
+        assert_eq!(chunk_map.block_at(pos(0)), Some(Block::Air));
+        assert_eq!(chunk_map.block_at(pos(1)), Some(Block::Glass));
+        assert_eq!(chunk_map.block_at(pos(2)), Some(Block::Glass));
+
+        // Only changes after the given time are reverted.
+        let result = rollback(&log, &mut chunk_map, &mut world_edit, &editor, |change| {
+            change.time >= 30
+        });
+        assert_eq!(result.blocks, 2);
+        assert_eq!(chunk_map.block_at(pos(1)), Some(Block::Stone));
+        assert_eq!(chunk_map.block_at(pos(2)), Some(Block::Air));
+
+        // The rollback can be undone.
+        world_edit.undo(&mut chunk_map, &editor).unwrap();
+        assert_eq!(chunk_map.block_at(pos(2)), Some(Block::Glass));
+    }
+
+    #[test]
+    fn test_rollback_command() {
+        let (mut w, mut d) = t::builder()
+            .with(ChangeLogSystem::default(), "log")
+            .with_dep(RollbackCommandSystem::default(), "", &["log"])
+            .build();
+        w.insert(ChangeLog::in_memory());
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let (tx, rx) = crossbeam::unbounded();
+        let console = w
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+
+        let stone_pos = BlockPosition::new(0, 64, 0);
+        let box_pos = BlockPosition::new(2, 64, 0);
+        t::set_block(2, 64, 0, Block::ShulkerBox(ShulkerBoxData::default()), &w);
+        let old_block = w.fetch::<ChunkMap>().block_at(stone_pos).unwrap();
+        t::set_block(0, 64, 0, Block::Stone, &w);
+        t::trigger_event(
+            &w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Player(player.entity),
+                pos: stone_pos,
+                old_block,
+                new_block: Block::Stone,
+            },
+        );
+        // The player moves an item out of the box.
+        t::trigger_event(
+            &w,
+            ContainerChangeEvent {
+                player: player.entity,
+                pos: box_pos,
+                slots: vec![(4, Some(ItemStack::new(Item::Diamond, 2)), None)],
+            },
+        );
+        d.dispatch(&w);
+        assert_eq!(w.fetch::<ChangeLog>().changes().len(), 2);
+
+        let name = w
+            .read_component::<NamedComponent>()
+            .get(player.entity)
+            .unwrap()
+            .display_name
+            .clone();
+        let mut run = |command: &str| {
+            t::trigger_event(&w, CommandEvent::parse(console, command).unwrap());
+            d.dispatch(&w);
+            rx.try_recv().unwrap()
+        };
+        assert_eq!(run("/rollback"), "Usage: /rollback <player|region> <time>");
+        assert_eq!(
+            run("/rollback region 1h"),
+            "Select a region using //pos1 and //pos2 first."
+        );
+        assert_eq!(
+            run("/rollback Notch 1h"),
+            "No changes by Notch have been logged."
+        );
+        assert_eq!(
+            run(&format!("/rollback {} soon", name)),
+            "Invalid time soon. Use a duration such as 30m or 2h."
+        );
+        assert_eq!(
+            run(&format!("/rollback {} 1h", name)),
+            "Rolled back 1 blocks and 1 container slots."
+        );
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(chunk_map.block_at(stone_pos), Some(old_block));
+        let container = load_container(&chunk_map, ContainerKind::ShulkerBox, box_pos);
+        assert_eq!(
+            container.slots(ContainerKind::ShulkerBox.size())[4],
+            Some(ItemStack::new(Item::Diamond, 2))
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Tick-based scheduling of delayed and repeating tasks.
+//!
+//! Tasks are scheduled on the `Scheduler` resource, with delays
+//! and intervals measured in ticks. Synchronous tasks run on
+//! the server thread with mutable access to the `World`; they are
+//! executed lazily when the world is maintained at the end of the tick.
+//!
+//! Asynchronous tasks run on the Rayon thread pool. They have
+//! no access to the world and are intended for blocking or
+//! expensive work which should not stall the tick, such as file IO.
+//!
+//! ```ignore
+//! let mut scheduler = world.fetch_mut::<Scheduler>();
+//! scheduler.schedule_delayed(TPS, |world| {
+//!     info!("One second has passed");
+//! });
+//! ```
+
+use crate::systems::SCHEDULER;
+use crate::timings::DispatcherBuilderExt;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use specs::{DispatcherBuilder, LazyUpdate, Read, System, World, Write};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// Identifies a scheduled task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+type SyncTask = Arc<Mutex<Box<dyn FnMut(&mut World) + Send>>>;
+type AsyncTask = Arc<Mutex<Box<dyn FnMut() + Send>>>;
+
+#[derive(Clone)]
+enum TaskKind {
+    Sync(SyncTask),
+    Async(AsyncTask),
+}
+
+struct Task {
+    kind: TaskKind,
+    /// The tick at which the task next runs.
+    next_run: u64,
+    /// The interval at which the task repeats,
+    /// or `None` if it runs only once.
+    interval: Option<u64>,
+}
+
+/// Resource containing all scheduled tasks.
+#[derive(Default)]
+pub struct Scheduler {
+    /// The number of ticks the scheduler has run for.
+    tick: u64,
+    next_id: u64,
+    tasks: HashMap<TaskId, Task>,
+    /// Tasks ordered by the tick at which they next run.
+    /// Tasks due in the same tick run in the order
+    /// in which they were scheduled.
+    queue: BTreeSet<(u64, TaskId)>,
+}
+
+impl Scheduler {
+    /// Schedules a task to run on the server thread after
+    /// the given number of ticks. A delay of zero runs
+    /// the task during the next tick.
+    pub fn schedule_delayed<F>(&mut self, ticks: u64, task: F) -> TaskId
+    where
+        F: FnOnce(&mut World) + Send + 'static,
+    {
+        let mut task = Some(task);
+        let task: Box<dyn FnMut(&mut World) + Send> = Box::new(move |world| {
+            if let Some(task) = task.take() {
+                task(world);
+            }
+        });
+        self.insert(ticks, None, TaskKind::Sync(Arc::new(Mutex::new(task))))
+    }
+
+    /// Schedules a task to run on the server thread
+    /// every `interval` ticks, starting `interval`
+    /// ticks from now.
+    pub fn schedule_repeating<F>(&mut self, interval: u64, task: F) -> TaskId
+    where
+        F: FnMut(&mut World) + Send + 'static,
+    {
+        let interval = interval.max(1);
+        let task: Box<dyn FnMut(&mut World) + Send> = Box::new(task);
+        self.insert(
+            interval,
+            Some(interval),
+            TaskKind::Sync(Arc::new(Mutex::new(task))),
+        )
+    }
+
+    /// Schedules a task to run on the thread pool
+    /// after the given number of ticks.
+    pub fn schedule_delayed_async<F>(&mut self, ticks: u64, task: F) -> TaskId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut task = Some(task);
+        let task: Box<dyn FnMut() + Send> = Box::new(move || {
+            if let Some(task) = task.take() {
+                task();
+            }
+        });
+        self.insert(ticks, None, TaskKind::Async(Arc::new(Mutex::new(task))))
+    }
+
+    /// Schedules a task to run on the thread pool every
+    /// `interval` ticks, starting `interval` ticks from now.
+    ///
+    /// If a run of the task takes longer than the interval,
+    /// the next run waits for it to complete.
+    pub fn schedule_repeating_async<F>(&mut self, interval: u64, task: F) -> TaskId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let interval = interval.max(1);
+        let task: Box<dyn FnMut() + Send> = Box::new(task);
+        self.insert(
+            interval,
+            Some(interval),
+            TaskKind::Async(Arc::new(Mutex::new(task))),
+        )
+    }
+
+    /// Cancels a task. Returns `false` if the task
+    /// has already run or been cancelled.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        match self.tasks.remove(&id) {
+            Some(task) => {
+                self.queue.remove(&(task.next_run, id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the given task is still scheduled.
+    pub fn is_scheduled(&self, id: TaskId) -> bool {
+        self.tasks.contains_key(&id)
+    }
+
+    /// Returns the number of scheduled tasks.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn insert(&mut self, delay: u64, interval: Option<u64>, kind: TaskKind) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+
+        let next_run = self.tick + delay.max(1);
+        self.queue.insert((next_run, id));
+        self.tasks.insert(
+            id,
+            Task {
+                kind,
+                next_run,
+                interval,
+            },
+        );
+
+        id
+    }
+
+    /// Advances the scheduler by one tick, returning
+    /// the tasks which are due to run.
+    fn advance(&mut self) -> Vec<TaskKind> {
+        self.tick += 1;
+
+        let mut due = vec![];
+        while let Some(&(next_run, id)) = self.queue.iter().next() {
+            if next_run > self.tick {
+                break;
+            }
+            self.queue.remove(&(next_run, id));
+
+            let task = match self.tasks.get_mut(&id) {
+                Some(task) => task,
+                None => continue,
+            };
+            match task.interval {
+                Some(interval) => {
+                    task.next_run = self.tick + interval;
+                    self.queue.insert((task.next_run, id));
+                    due.push(task.kind.clone());
+                }
+                None => due.push(self.tasks.remove(&id).unwrap().kind),
+            }
+        }
+
+        due
+    }
+}
+
+/// System which runs due tasks.
+pub struct SchedulerSystem;
+
+impl<'a> System<'a> for SchedulerSystem {
+    type SystemData = (Write<'a, Scheduler>, Read<'a, LazyUpdate>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut scheduler, lazy) = data;
+
+        for task in scheduler.advance() {
+            match task {
+                TaskKind::Sync(task) => lazy.exec_mut(move |world| (task.lock())(world)),
+                TaskKind::Async(task) => rayon::spawn(move || (task.lock())()),
+            }
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(SchedulerSystem, SCHEDULER, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use specs::WorldExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn tick(w: &mut World, d: &mut specs::Dispatcher) {
+        d.dispatch(w);
+        w.maintain();
+    }
+
+    #[test]
+    fn test_delayed() {
+        let (mut w, mut d) = t::builder().with(SchedulerSystem, "").build();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let c = Arc::clone(&count);
+        let id = w.fetch_mut::<Scheduler>().schedule_delayed(2, move |_| {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+
+        tick(&mut w, &mut d);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert!(w.fetch::<Scheduler>().is_scheduled(id));
+
+        tick(&mut w, &mut d);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(!w.fetch::<Scheduler>().is_scheduled(id));
+
+        tick(&mut w, &mut d);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_repeating() {
+        let (mut w, mut d) = t::builder().with(SchedulerSystem, "").build();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let c = Arc::clone(&count);
+        let id = w.fetch_mut::<Scheduler>().schedule_repeating(2, move |_| {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+
+        for _ in 0..6 {
+            tick(&mut w, &mut d);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        assert!(w.fetch_mut::<Scheduler>().cancel(id));
+        assert!(!w.fetch_mut::<Scheduler>().cancel(id));
+
+        for _ in 0..6 {
+            tick(&mut w, &mut d);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        assert!(w.fetch::<Scheduler>().is_empty());
+    }
+
+    #[test]
+    fn test_world_access() {
+        let (mut w, mut d) = t::builder().with(SchedulerSystem, "").build();
+
+        // Tasks may schedule further tasks.
+        w.fetch_mut::<Scheduler>().schedule_delayed(0, |world| {
+            world
+                .fetch_mut::<Scheduler>()
+                .schedule_delayed(0, |world| world.insert(TaskId(42)));
+        });
+
+        tick(&mut w, &mut d);
+        assert_eq!(w.fetch::<Scheduler>().len(), 1);
+        tick(&mut w, &mut d);
+        assert_eq!(*w.fetch::<TaskId>(), TaskId(42));
+    }
+
+    #[test]
+    fn test_async() {
+        let (mut w, mut d) = t::builder().with(SchedulerSystem, "").build();
+        let (tx, rx) = crossbeam::unbounded();
+
+        w.fetch_mut::<Scheduler>()
+            .schedule_delayed_async(1, move || tx.send(()).unwrap());
+
+        tick(&mut w, &mut d);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_order() {
+        let mut scheduler = Scheduler::default();
+        let order = Arc::new(Mutex::new(vec![]));
+
+        for (delay, name) in &[(3, "c"), (1, "a"), (1, "b")] {
+            let order = Arc::clone(&order);
+            let name = *name;
+            scheduler.schedule_delayed_async(*delay, move || order.lock().push(name));
+        }
+
+        let mut run = 0;
+        for _ in 0..3 {
+            for task in scheduler.advance() {
+                run += 1;
+                if let TaskKind::Async(task) = task {
+                    (task.lock())();
+                }
+            }
+        }
+
+        assert_eq!(run, 3);
+        assert_eq!(*order.lock(), vec!["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,1001 @@
+//! Embedded scripting using Rhai.
+//!
+//! Every `*.rhai` file in the `scripts` directory is loaded
+//! as a separate script. Scripts are checked for changes once
+//! per second and are transparently recompiled when modified,
+//! so the server can be customized without compiling anything.
+//!
+//! When a script is (re)loaded, its top-level statements are executed.
+//! This is where commands should be registered. Scripts may then define
+//! any of the following event handlers:
+//!
+//! * `on_join(player)` - called when a player joins.
+//! * `on_leave(player)` - called when a player leaves.
+//! * `on_block_update(x, y, z, old_state, new_state)` - called when a block changes.
+//! * `on_command_<name>(player, args)` - called for a command registered
+//! using `register_command(name)`. `args` is a string containing the command arguments.
+//!
+//! The following handlers are called from the `EventBus` before the server
+//! applies the event. A handler returning `false` cancels the event:
+//!
+//! * `on_login(player)` - called when a player is about to join.
+//! * `on_chat(player, message)` - called when a player sends a chat message.
+//! * `on_block_break(player, x, y, z, state)` - called when a player breaks a block.
+//! * `on_block_place(player, x, y, z, state)` - called when a player places a block.
+//! * `on_player_damage(player, damage)` - called when a player is about to take damage.
+//!
+//! These events are emitted while the world is held by another system,
+//! so `get_block` and `set_block` fail inside their handlers. Other
+//! requests, such as messages, are applied on the next tick.
+//!
+//! The following functions are available to scripts:
+//!
+//! * `broadcast(message)` - broadcasts a chat message.
+//! * `send_message(player, message)` - sends a message to the player with the given name.
+//! * `register_command(name)` - registers a command handled by this script.
+//! * `get_block(x, y, z)` - returns the native state ID of a block, or -1 if it is not loaded.
+//! * `set_block(x, y, z, state)` - sets a block to the given native state ID.
+//! * `schedule(ticks, function)` - calls the function with the given name
+//! in this script after `ticks` ticks.
+//! * `schedule_repeating(interval, function)` - calls the function with the given
+//! name in this script every `interval` ticks until the script is unloaded.
+//!
+//! Each call into a script may perform at most `scripting.max_operations`
+//! operations and nest function calls `scripting.max_call_levels` deep.
+//! Calls exceeding either limit are stopped with an error.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::commands::{send_message, CommandEvent, CommandRegistry};
+use crate::config::Config;
+use crate::entity::NamedComponent;
+use crate::event::{
+    BlockBreakEvent, BlockPlaceEvent, ChatEvent, EntityDamageEvent, Event, EventBus, EventPriority,
+    PlayerLoginEvent,
+};
+use crate::joinhandler::PlayerJoinEvent;
+use crate::lang::Locale;
+use crate::network::NetworkComponent;
+use crate::player::{ChatBroadcastEvent, PlayerDisconnectEvent};
+use crate::scheduler::{Scheduler, TaskId};
+use crate::systems::SCRIPTS;
+use crate::timings::DispatcherBuilderExt;
+use crate::{TickCount, TPS};
+use feather_core::world::ChunkMap;
+use feather_core::{Block, BlockExt, BlockPosition};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use rhai::{Dynamic, Engine, EvalAltResult, FuncArgs, RegisterFn, Scope, AST};
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entities, Entity, Join, Read, ReadStorage, System, World, Write};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// The directory from which scripts are loaded.
+pub const SCRIPT_DIR: &str = "scripts";
+
+/// The interval, in ticks, at which the script
+/// directory is checked for changes.
+const RELOAD_CHECK_INTERVAL: u64 = TPS;
+
+/// An action requested by a script which needs to
+/// be applied once the script returns.
+#[derive(Debug, Clone, PartialEq)]
+enum ScriptAction {
+    Broadcast(String),
+    SendMessage {
+        player: String,
+        message: String,
+    },
+    RegisterCommand(String),
+    Schedule {
+        script: PathBuf,
+        function: String,
+        ticks: u64,
+        repeating: bool,
+    },
+}
+
+/// Event triggered by the scheduler when a function
+/// scheduled by a script is due to be called.
+#[derive(Debug, Clone)]
+pub struct ScheduledCallEvent {
+    pub script: PathBuf,
+    pub function: String,
+}
+
+/// State shared between the script system and
+/// the functions registered with the engine.
+#[derive(Default)]
+struct SharedState {
+    /// Pointer to the chunk map, valid only while a
+    /// script function is executing. Null otherwise.
+    chunk_map: Option<*mut ChunkMap>,
+    /// The script currently executing.
+    script: Option<PathBuf>,
+    actions: Vec<ScriptAction>,
+    block_updates: Vec<BlockUpdateEvent>,
+}
+
+impl SharedState {
+    fn chunk_map(&mut self) -> Option<&mut ChunkMap> {
+        // SAFETY: the pointer is only set by `with_context`, which
+        // derives it from a `&mut ChunkMap` borrowed for the whole
+        // script call, and `ContextGuard` unsets it when the call
+        // returns or unwinds. The returned reference borrows `self`,
+        // which is only reachable through the `RefCell`, so at most
+        // one reference exists at a time and none outlives the call.
+        self.chunk_map.map(|ptr| unsafe { &mut *ptr })
+    }
+}
+
+/// A loaded script.
+struct Script {
+    ast: AST,
+    /// The modification time of the file at the time it was loaded.
+    modified: SystemTime,
+    /// Commands registered by this script.
+    commands: Vec<String>,
+    /// Tasks scheduled by this script, cancelled when it is unloaded.
+    tasks: Vec<TaskId>,
+}
+
+/// The loaded scripts and the engine running them, shared
+/// between the script system and the scripts' `EventBus` handlers.
+struct SharedScripts {
+    engine: Engine,
+    scripts: HashMap<PathBuf, Script>,
+    state: Rc<RefCell<SharedState>>,
+    /// The display names of online players, by which
+    /// players are passed to cancellable event handlers.
+    names: HashMap<Entity, String>,
+}
+
+// Safety: the engine, the ASTs and the shared state aren't `Send`
+// since they contain `Rc`s. All clones of these `Rc`s are owned by
+// `SharedScripts` and only accessed while its mutex is held, so no two
+// threads ever access them at the same time.
+unsafe impl Send for SharedScripts {}
+
+/// System which loads, reloads, and invokes scripts.
+///
+/// The scripting engine is not thread-safe, so this
+/// system must be added as a thread-local system.
+pub struct ScriptSystem {
+    dir: PathBuf,
+    shared: Arc<Mutex<SharedScripts>>,
+    join_reader: Option<ReaderId<PlayerJoinEvent>>,
+    leave_reader: Option<ReaderId<PlayerDisconnectEvent>>,
+    block_reader: Option<ReaderId<BlockUpdateEvent>>,
+    command_reader: Option<ReaderId<CommandEvent>>,
+    scheduled_reader: Option<ReaderId<ScheduledCallEvent>>,
+}
+
+impl ScriptSystem {
+    pub fn new(dir: &Path) -> Self {
+        let state = Rc::new(RefCell::new(SharedState::default()));
+        let engine = create_engine(&state);
+
+        Self {
+            dir: dir.to_path_buf(),
+            shared: Arc::new(Mutex::new(SharedScripts {
+                engine,
+                scripts: HashMap::new(),
+                state,
+                names: HashMap::new(),
+            })),
+            join_reader: None,
+            leave_reader: None,
+            block_reader: None,
+            command_reader: None,
+            scheduled_reader: None,
+        }
+    }
+}
+
+impl SharedScripts {
+    /// Loads new and modified scripts in `dir` and
+    /// unloads scripts whose files were deleted.
+    fn reload_changed(
+        &mut self,
+        dir: &Path,
+        chunk_map: &mut ChunkMap,
+        registry: &mut CommandRegistry,
+        scheduler: &mut Scheduler,
+    ) {
+        let files = script_files(dir);
+
+        // Unload deleted scripts.
+        let deleted: Vec<PathBuf> = self
+            .scripts
+            .keys()
+            .filter(|path| !files.iter().any(|(file, _)| file == *path))
+            .cloned()
+            .collect();
+        for path in deleted {
+            info!("Unloading script {}", path.display());
+            self.unload(&path, registry, scheduler);
+        }
+
+        for (path, modified) in files {
+            let up_to_date = self
+                .scripts
+                .get(&path)
+                .map(|script| script.modified == modified)
+                .unwrap_or(false);
+            if up_to_date {
+                continue;
+            }
+
+            self.unload(&path, registry, scheduler);
+            self.load(path, modified, chunk_map, registry);
+        }
+    }
+
+    fn load(
+        &mut self,
+        path: PathBuf,
+        modified: SystemTime,
+        chunk_map: &mut ChunkMap,
+        registry: &mut CommandRegistry,
+    ) {
+        let ast = match self.engine.compile_file(path.clone()) {
+            Ok(ast) => ast,
+            Err(e) => {
+                error!("Failed to compile script {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        info!("Loading script {}", path.display());
+
+        // Run top-level statements.
+        let engine = &self.engine;
+        let result = with_context(&self.state, Some(chunk_map), &path, || {
+            engine.eval_ast_with_scope::<Dynamic>(&mut Scope::new(), &ast)
+        });
+        if let Err(e) = result {
+            error!("Error in script {}: {}", path.display(), e);
+        }
+
+        let mut commands = vec![];
+        let mut state = self.state.borrow_mut();
+        state.actions.retain(|action| match action {
+            ScriptAction::RegisterCommand(name) => {
+                commands.push(name.clone());
+                false
+            }
+            _ => true,
+        });
+        drop(state);
+
+        for command in &commands {
+            registry.register(command);
+        }
+
+        self.scripts.insert(
+            path,
+            Script {
+                ast,
+                modified,
+                commands,
+                tasks: vec![],
+            },
+        );
+    }
+
+    fn unload(&mut self, path: &Path, registry: &mut CommandRegistry, scheduler: &mut Scheduler) {
+        if let Some(script) = self.scripts.remove(path) {
+            for command in &script.commands {
+                registry.unregister(command);
+            }
+            for task in script.tasks {
+                scheduler.cancel(task);
+            }
+        }
+    }
+
+    /// Calls a function in all scripts which define it.
+    fn call_all<A>(&mut self, chunk_map: &mut ChunkMap, name: &str, args: A)
+    where
+        A: FuncArgs + Clone,
+    {
+        let engine = &self.engine;
+        let state = &self.state;
+        for (path, script) in &self.scripts {
+            call(
+                engine,
+                state,
+                Some(&mut *chunk_map),
+                path,
+                &script.ast,
+                name,
+                args.clone(),
+            );
+        }
+    }
+
+    /// Calls an event handler in all scripts which define it,
+    /// without access to the world. Returns whether any of
+    /// the handlers returned `false`.
+    fn call_cancellable<A>(&self, name: &str, args: A) -> bool
+    where
+        A: FuncArgs + Clone,
+    {
+        let mut cancel = false;
+        for (path, script) in &self.scripts {
+            let result = call(
+                &self.engine,
+                &self.state,
+                None,
+                path,
+                &script.ast,
+                name,
+                args.clone(),
+            );
+            if let Some(false) = result.and_then(|value| value.try_cast::<bool>()) {
+                cancel = true;
+            }
+        }
+        cancel
+    }
+}
+
+/// Calls a function in a script, returning its result. Logs
+/// any error other than the function not being defined.
+fn call<A>(
+    engine: &Engine,
+    state: &Rc<RefCell<SharedState>>,
+    chunk_map: Option<&mut ChunkMap>,
+    path: &Path,
+    ast: &AST,
+    name: &str,
+    args: A,
+) -> Option<Dynamic>
+where
+    A: FuncArgs,
+{
+    let result: Result<Dynamic, _> = with_context(state, chunk_map, path, || {
+        engine.call_fn(&mut Scope::new(), ast, name, args)
+    });
+
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            match *e {
+                EvalAltResult::ErrorFunctionNotFound(_, _) => (),
+                e => error!("Error in script {} ({}): {}", path.display(), name, e),
+            }
+            None
+        }
+    }
+}
+
+/// Makes the chunk map, if available, and the path of the executing
+/// script available to script functions while `f` executes.
+fn with_context<T>(
+    state: &Rc<RefCell<SharedState>>,
+    chunk_map: Option<&mut ChunkMap>,
+    path: &Path,
+    f: impl FnOnce() -> T,
+) -> T {
+    {
+        let mut state = state.borrow_mut();
+        state.chunk_map = chunk_map.map(|chunk_map| chunk_map as *mut ChunkMap);
+        state.script = Some(path.to_path_buf());
+    }
+    let _guard = ContextGuard(state);
+    f()
+}
+
+/// Unsets the context set by `with_context` when dropped,
+/// so that it is unset even if the script call panics.
+struct ContextGuard<'a>(&'a Rc<RefCell<SharedState>>);
+
+impl Drop for ContextGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.borrow_mut();
+        state.chunk_map = None;
+        state.script = None;
+    }
+}
+
+impl<'a> System<'a> for ScriptSystem {
+    type SystemData = (
+        Write<'a, ChunkMap>,
+        Write<'a, CommandRegistry>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, EventChannel<ChatBroadcastEvent>>,
+        Read<'a, EventChannel<PlayerJoinEvent>>,
+        Read<'a, EventChannel<PlayerDisconnectEvent>>,
+        Read<'a, EventChannel<CommandEvent>>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Entities<'a>,
+        Read<'a, TickCount>,
+        Write<'a, Scheduler>,
+        Read<'a, EventChannel<ScheduledCallEvent>>,
+        Read<'a, Locale>,
+        Read<'a, Arc<Config>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut chunk_map,
+            mut registry,
+            mut block_events,
+            mut chat_events,
+            join_events,
+            leave_events,
+            command_events,
+            nameds,
+            networks,
+            entities,
+            tick_count,
+            mut scheduler,
+            scheduled_calls,
+            locale,
+            config,
+        ) = data;
+
+        let mut shared = self.shared.lock();
+
+        // Applied each tick so that the limits can be reloaded.
+        shared
+            .engine
+            .set_max_operations(config.scripting.max_operations);
+        shared
+            .engine
+            .set_max_call_levels(config.scripting.max_call_levels);
+
+        if tick_count.0 % RELOAD_CHECK_INTERVAL == 0 {
+            shared.reload_changed(&self.dir, &mut chunk_map, &mut registry, &mut scheduler);
+        }
+
+        for event in join_events.read(self.join_reader.as_mut().unwrap()) {
+            let name = continue_if_none!(nameds.get(event.player))
+                .display_name
+                .clone();
+            shared.names.insert(event.player, name.clone());
+            shared.call_all(&mut chunk_map, "on_join", (name,));
+        }
+
+        for event in leave_events.read(self.leave_reader.as_mut().unwrap()) {
+            shared.names.remove(&event.player);
+            let name = continue_if_none!(nameds.get(event.player))
+                .display_name
+                .clone();
+            shared.call_all(&mut chunk_map, "on_leave", (name,));
+        }
+
+        let updates: Vec<BlockUpdateEvent> = block_events
+            .read(self.block_reader.as_mut().unwrap())
+            .filter(|event| event.cause != BlockUpdateCause::Script)
+            .cloned()
+            .collect();
+        for event in updates {
+            let args = (
+                i64::from(event.pos.x),
+                i64::from(event.pos.y),
+                i64::from(event.pos.z),
+                i64::from(event.old_block.native_state_id()),
+                i64::from(event.new_block.native_state_id()),
+            );
+            shared.call_all(&mut chunk_map, "on_block_update", args);
+        }
+
+        for event in command_events.read(self.command_reader.as_mut().unwrap()) {
+            let script = shared
+                .scripts
+                .iter()
+                .find(|(_, script)| script.commands.contains(&event.name));
+            let (path, script) = continue_if_none!(script);
+
+            let name = continue_if_none!(nameds.get(event.sender))
+                .display_name
+                .clone();
+            let args = event.args.join(" ");
+            let function = format!("on_command_{}", event.name);
+            call(
+                &shared.engine,
+                &shared.state,
+                Some(&mut *chunk_map),
+                path,
+                &script.ast,
+                &function,
+                (name, args),
+            );
+        }
+
+        for event in scheduled_calls.read(self.scheduled_reader.as_mut().unwrap()) {
+            let script = continue_if_none!(shared.scripts.get(&event.script));
+            call(
+                &shared.engine,
+                &shared.state,
+                Some(&mut *chunk_map),
+                &event.script,
+                &script.ast,
+                &event.function,
+                (),
+            );
+        }
+
+        // Apply actions requested by scripts.
+        let SharedScripts { scripts, state, .. } = &mut *shared;
+        let mut state = state.borrow_mut();
+        block_events.drain_vec_write(&mut state.block_updates);
+
+        for action in state.actions.drain(..) {
+            match action {
+                ScriptAction::Broadcast(text) => {
+                    let message = json!({ "text": text }).to_string();
+                    chat_events.single_write(ChatBroadcastEvent { message });
+                }
+                ScriptAction::SendMessage { player, message } => {
+                    let target = (&entities, &nameds, &networks)
+                        .join()
+                        .find(|(_, named, _)| named.display_name == player);
+                    if let Some((_, _, network)) = target {
+                        send_message(network, &locale, message);
+                    }
+                }
+                ScriptAction::RegisterCommand(name) => {
+                    warn!(
+                        "Command {} must be registered at the top level of a script",
+                        name
+                    );
+                }
+                ScriptAction::Schedule {
+                    script,
+                    function,
+                    ticks,
+                    repeating,
+                } => {
+                    let loaded = continue_if_none!(scripts.get_mut(&script));
+                    let event = ScheduledCallEvent { script, function };
+                    let task = if repeating {
+                        scheduler.schedule_repeating(ticks, move |world| {
+                            world
+                                .fetch_mut::<EventChannel<ScheduledCallEvent>>()
+                                .single_write(event.clone());
+                        })
+                    } else {
+                        scheduler.schedule_delayed(ticks, move |world| {
+                            world
+                                .fetch_mut::<EventChannel<ScheduledCallEvent>>()
+                                .single_write(event);
+                        })
+                    };
+                    loaded.tasks.push(task);
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.join_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.leave_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.block_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.command_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+        self.scheduled_reader = Some(world.fetch_mut::<EventChannel<_>>().register_reader());
+
+        let mut bus = world.entry::<EventBus>().or_insert_with(EventBus::default);
+        register_handler::<PlayerLoginEvent>(&mut bus, &self.shared);
+        register_handler::<ChatEvent>(&mut bus, &self.shared);
+        register_handler::<BlockBreakEvent>(&mut bus, &self.shared);
+        register_handler::<BlockPlaceEvent>(&mut bus, &self.shared);
+        register_handler::<EntityDamageEvent>(&mut bus, &self.shared);
+    }
+}
+
+/// A cancellable event which is passed to a script event handler.
+trait ScriptEvent: Event {
+    /// The arguments passed to the handler.
+    type Args: FuncArgs + Clone;
+
+    /// The name of the handler.
+    const HANDLER: &'static str;
+
+    /// Returns the arguments to the handler, or `None` if
+    /// the event doesn't concern an online player.
+    fn args(&self, names: &HashMap<Entity, String>) -> Option<Self::Args>;
+}
+
+impl ScriptEvent for PlayerLoginEvent {
+    type Args = (String,);
+    const HANDLER: &'static str = "on_login";
+
+    fn args(&self, _names: &HashMap<Entity, String>) -> Option<Self::Args> {
+        Some((self.username.clone(),))
+    }
+}
+
+impl ScriptEvent for ChatEvent {
+    type Args = (String, String);
+    const HANDLER: &'static str = "on_chat";
+
+    fn args(&self, names: &HashMap<Entity, String>) -> Option<Self::Args> {
+        Some((names.get(&self.player)?.clone(), self.message.clone()))
+    }
+}
+
+impl ScriptEvent for BlockBreakEvent {
+    type Args = (String, i64, i64, i64, i64);
+    const HANDLER: &'static str = "on_block_break";
+
+    fn args(&self, names: &HashMap<Entity, String>) -> Option<Self::Args> {
+        Some((
+            names.get(&self.player)?.clone(),
+            i64::from(self.pos.x),
+            i64::from(self.pos.y),
+            i64::from(self.pos.z),
+            i64::from(self.block.native_state_id()),
+        ))
+    }
+}
+
+impl ScriptEvent for BlockPlaceEvent {
+    type Args = (String, i64, i64, i64, i64);
+    const HANDLER: &'static str = "on_block_place";
+
+    fn args(&self, names: &HashMap<Entity, String>) -> Option<Self::Args> {
+        Some((
+            names.get(&self.player)?.clone(),
+            i64::from(self.pos.x),
+            i64::from(self.pos.y),
+            i64::from(self.pos.z),
+            i64::from(self.block.native_state_id()),
+        ))
+    }
+}
+
+impl ScriptEvent for EntityDamageEvent {
+    type Args = (String, f64);
+    const HANDLER: &'static str = "on_player_damage";
+
+    fn args(&self, names: &HashMap<Entity, String>) -> Option<Self::Args> {
+        Some((names.get(&self.entity)?.clone(), f64::from(self.damage)))
+    }
+}
+
+/// Registers an `EventBus` handler which calls the
+/// scripts' handlers for events of type `E`.
+fn register_handler<E: ScriptEvent>(bus: &mut EventBus, shared: &Arc<Mutex<SharedScripts>>) {
+    let shared = Arc::clone(shared);
+    bus.register(EventPriority::Normal, move |event: &mut E| {
+        let shared = shared.lock();
+        let args = match event.args(&shared.names) {
+            Some(args) => args,
+            None => return,
+        };
+        if shared.call_cancellable(E::HANDLER, args) {
+            event.set_cancelled(true);
+        }
+    });
+}
+
+/// Returns all script files in the given directory
+/// along with their modification times.
+fn script_files(dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("rhai"))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+/// Creates the scripting engine and registers
+/// the functions available to scripts.
+fn create_engine(state: &Rc<RefCell<SharedState>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let s = Rc::clone(state);
+    engine.register_fn("broadcast", move |message: String| {
+        s.borrow_mut()
+            .actions
+            .push(ScriptAction::Broadcast(message));
+    });
+
+    let s = Rc::clone(state);
+    engine.register_fn("send_message", move |player: String, message: String| {
+        s.borrow_mut()
+            .actions
+            .push(ScriptAction::SendMessage { player, message });
+    });
+
+    let s = Rc::clone(state);
+    engine.register_fn("register_command", move |name: String| {
+        s.borrow_mut()
+            .actions
+            .push(ScriptAction::RegisterCommand(name.to_lowercase()));
+    });
+
+    let s = Rc::clone(state);
+    engine.register_fn("get_block", move |x: i64, y: i64, z: i64| -> i64 {
+        let mut state = s.borrow_mut();
+        let pos = BlockPosition::new(x as i32, y as i32, z as i32);
+        state
+            .chunk_map()
+            .and_then(|chunk_map| chunk_map.block_at(pos))
+            .map(|block| i64::from(block.native_state_id()))
+            .unwrap_or(-1)
+    });
+
+    let s = Rc::clone(state);
+    engine.register_fn("schedule", move |ticks: i64, function: String| {
+        push_schedule(&s, ticks, function, false);
+    });
+
+    let s = Rc::clone(state);
+    engine.register_fn(
+        "schedule_repeating",
+        move |interval: i64, function: String| {
+            push_schedule(&s, interval, function, true);
+        },
+    );
+
+    let s = Rc::clone(state);
+    engine.register_fn(
+        "set_block",
+        move |x: i64, y: i64, z: i64, id: i64| -> bool {
+            let mut state = s.borrow_mut();
+            let pos = BlockPosition::new(x as i32, y as i32, z as i32);
+
+            let new = match Block::from_native_state_id(id as u16) {
+                Some(block) if id >= 0 && id <= i64::from(u16::max_value()) => block,
+                _ => return false,
+            };
+
+            let chunk_map = match state.chunk_map() {
+                Some(chunk_map) => chunk_map,
+                None => return false,
+            };
+            let old = match chunk_map.block_at(pos) {
+                Some(block) => block,
+                None => return false,
+            };
+            if chunk_map.set_block_at(pos, new).is_err() {
+                return false;
+            }
+
+            state.block_updates.push(BlockUpdateEvent {
+                cause: BlockUpdateCause::Script,
+                pos,
+                old_block: old,
+                new_block: new,
+            });
+            true
+        },
+    );
+
+    engine
+}
+
+/// Requests that a function in the executing script be scheduled.
+fn push_schedule(state: &Rc<RefCell<SharedState>>, ticks: i64, function: String, repeating: bool) {
+    let mut state = state.borrow_mut();
+    let script = match state.script.clone() {
+        Some(script) => script,
+        None => return,
+    };
+    state.actions.push(ScriptAction::Schedule {
+        script,
+        function,
+        ticks: ticks.max(0) as u64,
+        repeating,
+    });
+}
+
+pub fn init(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed_thread_local(ScriptSystem::new(Path::new(SCRIPT_DIR)), SCRIPTS);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::Chunk;
+    use feather_core::ChunkPosition;
+    use specs::{RunNow, WorldExt};
+    use std::io::Write as _;
+
+    fn script_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("feather-scripts-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_script_commands_and_blocks() {
+        let dir = script_dir("commands");
+        let mut file = fs::File::create(dir.join("test.rhai")).unwrap();
+        file.write_all(
+            br#"
+            register_command("stone");
+            fn on_command_stone(player, args) {
+                set_block(0, 64, 0, 1);
+                send_message(player, "done");
+            }
+            "#,
+        )
+        .unwrap();
+        drop(file);
+
+        let (mut w, mut d) = t::builder().build();
+        let mut system = ScriptSystem::new(&dir);
+        system.setup(&mut w);
+
+        let pos = ChunkPosition::new(0, 0);
+        w.fetch_mut::<ChunkMap>().set_chunk_at(pos, Chunk::new(pos));
+
+        let player = t::add_player(&mut w);
+        system.run_now(&w);
+        assert!(w.fetch::<CommandRegistry>().contains("stone"));
+
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/stone").unwrap());
+        let mut reader = t::reader::<BlockUpdateEvent>(&w);
+        system.run_now(&w);
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::Stone)
+        );
+        let events = t::triggered_events(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].cause, BlockUpdateCause::Script);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_operation_limit() {
+        let dir = script_dir("limit");
+        fs::write(
+            dir.join("test.rhai"),
+            r#"
+            let x = 0;
+            loop {
+                x += 1;
+            }
+            register_command("unreachable");
+            "#,
+        )
+        .unwrap();
+
+        let (mut w, _) = t::builder().build();
+        let mut config = Config::default();
+        config.scripting.max_operations = 10_000;
+        w.insert(Arc::new(config));
+        let mut system = ScriptSystem::new(&dir);
+        system.setup(&mut w);
+
+        // The loop is stopped instead of hanging the server.
+        system.run_now(&w);
+        assert!(!w.fetch::<CommandRegistry>().contains("unreachable"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_cancels_event() {
+        let dir = script_dir("cancel");
+        fs::write(
+            dir.join("test.rhai"),
+            r#"
+            fn on_chat(player, message) {
+                message != "bad"
+            }
+            "#,
+        )
+        .unwrap();
+
+        let (mut w, _) = t::builder().build();
+        let mut system = ScriptSystem::new(&dir);
+        system.setup(&mut w);
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(
+            &w,
+            PlayerJoinEvent {
+                player: player.entity,
+            },
+        );
+        system.run_now(&w);
+
+        let bus = w.fetch::<EventBus>();
+        let mut event = ChatEvent {
+            player: player.entity,
+            message: String::from("bad"),
+            cancelled: false,
+        };
+        assert!(!bus.emit(&mut event));
+
+        event.message = String::from("good");
+        event.cancelled = false;
+        assert!(bus.emit(&mut event));
+        drop(bus);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_unload() {
+        let dir = script_dir("unload");
+        fs::write(dir.join("test.rhai"), r#"register_command("foo");"#).unwrap();
+
+        let (mut w, _) = t::builder().build();
+        let mut system = ScriptSystem::new(&dir);
+        system.setup(&mut w);
+
+        system.run_now(&w);
+        assert!(w.fetch::<CommandRegistry>().contains("foo"));
+
+        fs::remove_file(dir.join("test.rhai")).unwrap();
+        w.fetch_mut::<TickCount>().0 += RELOAD_CHECK_INTERVAL;
+        system.run_now(&w);
+        assert!(!w.fetch::<CommandRegistry>().contains("foo"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_schedule() {
+        let dir = script_dir("schedule");
+        fs::write(
+            dir.join("test.rhai"),
+            r#"
+            schedule(1, "later");
+            schedule_repeating(1, "repeat");
+            fn later() {
+                broadcast("later");
+            }
+            fn repeat() {}
+            "#,
+        )
+        .unwrap();
+
+        let (mut w, mut d) = t::builder()
+            .with(crate::scheduler::SchedulerSystem, "")
+            .build();
+        let mut system = ScriptSystem::new(&dir);
+        system.setup(&mut w);
+        let mut reader = t::reader::<ChatBroadcastEvent>(&w);
+
+        system.run_now(&w);
+        assert_eq!(w.fetch::<Scheduler>().len(), 2);
+
+        d.dispatch(&w);
+        w.maintain();
+        system.run_now(&w);
+
+        let events = t::triggered_events(&w, &mut reader);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].message.contains("later"));
+
+        // Unloading the script cancels its remaining tasks.
+        fs::remove_file(dir.join("test.rhai")).unwrap();
+        w.fetch_mut::<TickCount>().0 += RELOAD_CHECK_INTERVAL;
+        system.run_now(&w);
+        assert!(w.fetch::<Scheduler>().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,30 +1,144 @@
 //! Shutdown behavior.
+//!
+//! The server shuts down on SIGINT, SIGTERM, or when an
+//! operator executes `/stop`. Players are kicked,
+//! all chunks, maps and player data are saved, and the
+//! chunk worker finishes its in-flight saves before
+//! the process exits.
 
 use crate::chunk_logic::ChunkWorkerHandle;
+use crate::commands::{
+    is_privileged, no_permission, reply, sender_name, CommandEvent, CommandRegistry,
+    ConsoleComponent,
+};
 use crate::config::Config;
-use crate::entity::{NamedComponent, PlayerComponent, PositionComponent};
+use crate::dimension::Dimensions;
+use crate::entity::NamedComponent;
+use crate::lang::Locale;
+use crate::map::MapRegistry;
+use crate::network::{send_packet_to_player, NetworkComponent};
 use crate::player;
-use crate::player::InventoryComponent;
+use crate::systems::STOP_COMMAND;
 use crate::time::Time;
-use crate::{chunkworker, entity};
+use crate::timings::DispatcherBuilderExt;
+use crate::{chunkworker, current_time_in_millis, entity, TickCount};
 use crossbeam::Sender;
 use feather_core::level::{save_level_file, LevelData, Root};
+use feather_core::network::packet::implementation::DisconnectPlay;
 use feather_core::prelude::ChunkMap;
-use specs::{Join, World, WorldExt};
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Join, Read, ReadStorage, System, World, WorldExt};
 use std::fs::File;
+use std::path::Path;
 use std::sync::Arc;
 
+/// Resource used to request a shutdown
+/// from within the server.
+#[derive(Clone)]
+pub struct ShutdownHandle(pub Sender<()>);
+
+/// Registers a handler for SIGINT and SIGTERM
+/// which requests a shutdown.
 pub fn init(tx: Sender<()>) {
+    let mut requested = false;
     ctrlc::set_handler(move || {
-        tx.send(()).unwrap();
+        if requested {
+            warn!("Shutdown already in progress; waiting for the world to finish saving");
+            return;
+        }
+        requested = true;
+        let _ = tx.send(());
     })
     .unwrap();
 }
 
+/// System implementing `/stop`.
+#[derive(Default)]
+pub struct StopCommandSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl<'a> System<'a> for StopCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Option<Read<'a, ShutdownHandle>>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, handle, config, locale, nameds, networks, consoles) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if event.name != "stop" {
+                continue;
+            }
+
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            info!(
+                "{} requested a shutdown",
+                sender_name(event.sender, &nameds)
+            );
+            if let Some(handle) = handle.as_ref() {
+                let _ = handle.0.send(());
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+        world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default)
+            .register("stop");
+    }
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(StopCommandSystem::default(), STOP_COMMAND, &[]);
+}
+
+/// Kicks all connected players with the
+/// configured shutdown message.
+///
+/// Player entities are not removed, so that
+/// their data can be saved afterwards.
+pub fn kick_players(world: &World) {
+    let config = world.fetch::<Arc<Config>>();
+    let json = json!({ "text": config.server.shutdown_message }).to_string();
+
+    let networks = world.read_component::<NetworkComponent>();
+    for network in networks.join() {
+        send_packet_to_player(network, DisconnectPlay::new(json.clone()));
+        network.close();
+    }
+}
+
+/// Saves all modified chunks, blocking until
+/// the chunk worker has written them to disk.
 pub fn save_chunks(world: &mut World) {
     let mut chunk_map = world.fetch_mut::<ChunkMap>();
     let handle = world.fetch::<ChunkWorkerHandle>();
-    let count = entity::save_chunks(&mut chunk_map, &world.fetch(), &world.fetch());
+    let count = entity::save_chunks(
+        &mut chunk_map,
+        &world.fetch(),
+        &world.fetch(),
+        &world.fetch(),
+    );
 
     drop(chunk_map);
     drop(handle);
@@ -36,32 +150,33 @@ pub fn save_chunks(world: &mut World) {
     let handle = world.fetch::<ChunkWorkerHandle>();
     handle.sender.send(chunkworker::Request::ShutDown).unwrap();
 
+    // The chunk worker handles requests in order, so it
+    // acknowledges the shutdown only after all saves,
+    // including those queued before the shutdown, have completed.
     let mut saved = 0;
-    // Wait for chunks to finish saving
     while let Ok(msg) = handle.receiver.recv() {
-        if let chunkworker::Reply::SavedChunk(_) = msg {
-            saved += 1;
-        }
-
-        if saved == count {
-            break;
+        match msg {
+            chunkworker::Reply::SavedChunk(_) => saved += 1,
+            chunkworker::Reply::ShutDown => break,
+            _ => (),
         }
     }
 
-    assert!(
-        count == saved,
-        "didn't save all chunks: {} != {}",
-        count,
-        saved
-    );
+    debug!("Saved {} chunks ({} modified at shutdown)", saved, count);
+
+    let saved = world.fetch_mut::<Dimensions>().shut_down();
+    debug!("Saved {} chunks in other dimensions", saved);
 }
 
 pub fn save_level(world: &World) {
     let mut level = (*world.fetch::<LevelData>()).clone();
 
-    // Sync world time + level time
+    // Sync world time + level time. Nothing modifies the
+    // level's `time`, so it is still the world age on startup.
     let time = world.fetch::<Time>();
-    level.time = time.0 as i64;
+    level.day_time = time.0 as i64;
+    level.time += world.fetch::<TickCount>().0 as i64;
+    level.last_played = current_time_in_millis() as i64;
 
     let config = world.fetch::<Arc<Config>>();
 
@@ -72,28 +187,65 @@ pub fn save_level(world: &World) {
         .expect("Failed to save level file");
 }
 
-pub fn save_player_data(world: &World) {
+pub fn save_maps(world: &World) {
     let config = world.fetch::<Arc<Config>>();
+    world
+        .fetch_mut::<MapRegistry>()
+        .save(Path::new(&config.world.name));
+}
 
-    let positions = world.read_component::<PositionComponent>();
-    let nameds = world.read_component::<NamedComponent>();
-    let players = world.read_component::<PlayerComponent>();
-    let inventories = world.read_component::<InventoryComponent>();
-
-    let mut channels = vec![];
-
-    for (position, named, player, inventory) in (&positions, &nameds, &players, &inventories).join()
-    {
-        let rx = player::save_player_data(
-            &config,
-            position.current,
-            player.gamemode,
-            &inventory.inventory,
-            named.uuid,
-        );
-        channels.push(rx);
+pub fn save_player_data(world: &World) {
+    // Wait for saving to complete
+    player::save_all_player_data(world)
+        .into_iter()
+        .for_each(|rx| rx.recv().unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::PacketType;
+
+    #[test]
+    fn test_stop_command() {
+        let (mut w, mut d) = t::builder().with(StopCommandSystem::default(), "").build();
+        let (tx, rx) = crossbeam::unbounded();
+        w.insert(ShutdownHandle(tx));
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/stop").unwrap());
+        d.dispatch(&w);
+        w.maintain();
+
+        // Not an operator
+        assert!(rx.try_recv().is_err());
+        t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = String::from("admin");
+        let mut config = Config::default();
+        config.server.operators.push(String::from("admin"));
+        w.insert(Arc::new(config));
+
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/stop").unwrap());
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(rx.try_recv().is_ok());
     }
 
-    // Wait for saving to complete
-    channels.into_iter().for_each(|rx| rx.recv().unwrap());
+    #[test]
+    fn test_kick_players() {
+        let (mut w, _) = t::builder().build();
+        let player = t::add_player(&mut w);
+
+        kick_players(&w);
+
+        let packet = t::assert_packet_received(&player, PacketType::DisconnectPlay);
+        let packet = feather_core::network::cast_packet::<DisconnectPlay>(&*packet);
+        assert!(packet.reason.contains("Server closed"));
+    }
 }
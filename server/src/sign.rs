@@ -0,0 +1,352 @@
+//! Signs, whose text is stored in their block entity.
+//!
+//! Placing a sign creates its block entity and opens the sign editor
+//! for the player who placed it. Once the player is done, the client
+//! sends an Update Sign packet, and the text is stored and sent to
+//! the players near the sign. As in vanilla, only the player who
+//! placed a sign may write on it, and only once.
+
+use crate::blocks::BlockUpdateEvent;
+use crate::dimension::{DimensionComponent, Dimensions};
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::systems::{SIGN_BREAK, SIGN_EDIT};
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use feather_core::nbt;
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{
+    OpenSignEditor, UpdateBlockEntity, UpdateSign,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, PacketType};
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DispatcherBuilder, Entity, HashMapStorage, LazyUpdate, Read, ReadExpect,
+    ReadStorage, System, Write, WriteStorage,
+};
+
+/// The maximum number of characters on a line of a sign.
+pub const MAX_LINE_LENGTH: usize = 384;
+
+/// The action of the Update Block Entity packet for signs.
+const BLOCK_ENTITY_ACTION: u8 = 9;
+
+/// The ID of sign block entities.
+const BLOCK_ENTITY_ID: &str = "minecraft:sign";
+
+/// The block entity of a sign, as saved in chunks.
+/// Lines are stored as JSON text components.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignEntity {
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    #[serde(rename = "Text1", default = "empty_line")]
+    pub text_1: String,
+    #[serde(rename = "Text2", default = "empty_line")]
+    pub text_2: String,
+    #[serde(rename = "Text3", default = "empty_line")]
+    pub text_3: String,
+    #[serde(rename = "Text4", default = "empty_line")]
+    pub text_4: String,
+}
+
+impl SignEntity {
+    pub fn new(pos: BlockPosition) -> Self {
+        Self {
+            id: BLOCK_ENTITY_ID.to_string(),
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            text_1: empty_line(),
+            text_2: empty_line(),
+            text_3: empty_line(),
+            text_4: empty_line(),
+        }
+    }
+
+    /// Sets the lines of this sign to the given plain text,
+    /// removing formatting codes and limiting their length.
+    pub fn set_lines(&mut self, lines: [&str; 4]) {
+        let [line_1, line_2, line_3, line_4] = lines;
+        self.text_1 = text_component(line_1);
+        self.text_2 = text_component(line_2);
+        self.text_3 = text_component(line_3);
+        self.text_4 = text_component(line_4);
+    }
+
+    fn to_packet(&self, pos: BlockPosition) -> Option<UpdateBlockEntity> {
+        let data = nbt::to_value(self).ok()?;
+        Some(UpdateBlockEntity::new(pos, BLOCK_ENTITY_ACTION, Some(data)))
+    }
+}
+
+fn empty_line() -> String {
+    text_component("")
+}
+
+/// Converts a line written by a player into a JSON text component.
+fn text_component(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            // Skip the formatting code.
+            chars.next();
+            continue;
+        }
+        text.push(c);
+    }
+    let text: String = text.chars().take(MAX_LINE_LENGTH).collect();
+
+    serde_json::json!({ "text": text }).to_string()
+}
+
+pub fn is_sign(block: Block) -> bool {
+    match block {
+        Block::Sign(_) | Block::WallSign(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns the block entity of the sign at the given position.
+pub fn load_sign(chunk_map: &ChunkMap, pos: BlockPosition) -> SignEntity {
+    chunk_map
+        .block_entity_at(pos)
+        .and_then(|data| nbt::from_value(data.clone()).ok())
+        .unwrap_or_else(|| SignEntity::new(pos))
+}
+
+/// Stores the block entity of a sign.
+pub fn store_sign(chunk_map: &mut ChunkMap, pos: BlockPosition, sign: &SignEntity) {
+    match nbt::to_value(sign) {
+        Ok(data) => {
+            chunk_map.set_block_entity_at(pos, data).ok();
+        }
+        Err(e) => warn!("Failed to store sign at {:?}: {}", pos, e),
+    }
+}
+
+/// Creates the block entity of a sign placed by a
+/// player and opens the sign editor for the player.
+pub fn place_sign(
+    chunk_map: &mut ChunkMap,
+    pos: BlockPosition,
+    player: Entity,
+    network: &NetworkComponent,
+    lazy: &LazyUpdate,
+) {
+    store_sign(chunk_map, pos, &SignEntity::new(pos));
+    lazy.insert(player, SignEditComponent { pos });
+    send_packet_to_player(network, OpenSignEditor::new(pos));
+}
+
+/// Component for players who may write on the sign they placed.
+#[derive(Debug, Clone, Copy)]
+pub struct SignEditComponent {
+    pub pos: BlockPosition,
+}
+
+impl Component for SignEditComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// System which handles Update Sign packets, storing the
+/// text of signs and sending it to nearby players.
+pub struct SignEditSystem;
+
+impl<'a> System<'a> for SignEditSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        WriteStorage<'a, SignEditComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadExpect<'a, Dimensions>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, ChunkMap>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (packet_queue, mut edits, dimensions, dimension_worlds, networks, mut chunk_map, util) =
+            data;
+
+        for (player, packet) in packet_queue.for_packet(PacketType::UpdateSign) {
+            let packet = cast_packet::<UpdateSign>(&*packet);
+            let pos = packet.location;
+
+            match edits.get(player) {
+                Some(edit) if edit.pos == pos => {
+                    edits.remove(player);
+                }
+                _ => continue,
+            }
+
+            // Blocks outside of the primary dimension can't be modified
+            // yet, so the client is sent the sign's actual text.
+            if let Some(dimension) = dimensions.get(player) {
+                let world = dimension_worlds.world(dimension.0);
+                if let (Some(world), Some(network)) = (world, networks.get(player)) {
+                    if world.chunk_map.block_at(pos).map_or(false, is_sign) {
+                        let sign = load_sign(&world.chunk_map, pos);
+                        if let Some(packet) = sign.to_packet(pos) {
+                            send_packet_to_player(network, packet);
+                        }
+                    }
+                }
+                continue;
+            }
+            if !chunk_map.block_at(pos).map_or(false, is_sign) {
+                continue;
+            }
+
+            let mut sign = load_sign(&chunk_map, pos);
+            sign.set_lines([
+                &packet.line_1,
+                &packet.line_2,
+                &packet.line_3,
+                &packet.line_4,
+            ]);
+            store_sign(&mut chunk_map, pos, &sign);
+
+            if let Some(packet) = sign.to_packet(pos) {
+                util.broadcast_chunk_update(pos.chunk_pos(), packet, None);
+            }
+        }
+    }
+}
+
+/// System which removes the block entities of broken signs.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct SignBreakSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for SignBreakSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, ChunkMap>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, mut chunk_map) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if is_sign(event.old_block) && !is_sign(event.new_block) {
+                chunk_map.remove_block_entity_at(event.pos);
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(SignEditSystem, SIGN_EDIT, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(SignBreakSystem::default(), SIGN_BREAK, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockUpdateCause;
+    use crate::testframework as t;
+    use feather_blocks::SignData;
+    use specs::WorldExt;
+
+    fn sign_block() -> Block {
+        Block::Sign(SignData {
+            rotation: 0,
+            waterlogged: false,
+        })
+    }
+
+    #[test]
+    fn test_text_component() {
+        assert_eq!(text_component("Hello"), r#"{"text":"Hello"}"#);
+        assert_eq!(text_component("§4Red§r text"), r#"{"text":"Red text"}"#);
+        assert_eq!(text_component(&"a".repeat(500)).len(), 384 + 11);
+    }
+
+    #[test]
+    fn test_edit_sign() {
+        let (mut w, mut d) = t::builder().with(SignEditSystem, "").build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        let other = t::add_player(&mut w);
+
+        let pos = BlockPosition::new(1, 64, 1);
+        t::set_block(pos.x, pos.y, pos.z, sign_block(), &w);
+        store_sign(&mut w.fetch_mut(), pos, &SignEntity::new(pos));
+
+        let packet = UpdateSign::new(
+            pos,
+            "First".to_string(),
+            "Second".to_string(),
+            String::new(),
+            String::new(),
+        );
+
+        // Players may only edit signs they placed.
+        t::receive_packet(&other, &w, packet.clone());
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(load_sign(&w.fetch(), pos), SignEntity::new(pos));
+
+        w.write_component::<SignEditComponent>()
+            .insert(player.entity, SignEditComponent { pos })
+            .unwrap();
+        t::receive_packet(&player, &w, packet.clone());
+        d.dispatch(&w);
+        w.maintain();
+
+        let sign = load_sign(&w.fetch(), pos);
+        assert_eq!(sign.text_1, r#"{"text":"First"}"#);
+        assert_eq!(sign.text_2, r#"{"text":"Second"}"#);
+        assert_eq!(sign.text_3, empty_line());
+        assert!(w
+            .read_component::<SignEditComponent>()
+            .get(player.entity)
+            .is_none());
+
+        let received = t::assert_packet_received(&other, PacketType::UpdateBlockEntity);
+        let received = cast_packet::<UpdateBlockEntity>(&*received);
+        assert_eq!(received.action, BLOCK_ENTITY_ACTION);
+
+        // Signs can only be written on once.
+        let mut packet = packet;
+        packet.line_1 = "Changed".to_string();
+        t::receive_packet(&player, &w, packet);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(load_sign(&w.fetch(), pos), sign);
+    }
+
+    #[test]
+    fn test_break_sign() {
+        let (mut w, mut d) = t::builder().with(SignBreakSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        let pos = BlockPosition::new(1, 64, 1);
+        store_sign(&mut w.fetch_mut(), pos, &SignEntity::new(pos));
+
+        t::trigger_event(
+            &w,
+            BlockUpdateEvent {
+                cause: BlockUpdateCause::Test,
+                pos,
+                old_block: sign_block(),
+                new_block: Block::Air,
+            },
+        );
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w.fetch::<ChunkMap>().block_entity_at(pos).is_none());
+    }
+}
@@ -0,0 +1,434 @@
+//! Sleeping in beds to skip the night.
+//!
+//! Players may sleep at night or during thunderstorms by
+//! right-clicking a bed. Once `gameplay.sleeping_percentage`
+//! percent of the players in the primary dimension have been
+//! asleep for `SLEEP_TIME` ticks, the time is advanced to the
+//! next morning, the weather is cleared and all sleeping
+//! players are woken up.
+
+use crate::commands::send_message;
+use crate::config::Config;
+use crate::dimension::DimensionComponent;
+use crate::entity::{PlayerComponent, PositionComponent};
+use crate::lang::{Locale, Message};
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::{PlayerActionEvent, PlayerAnimationEvent};
+use crate::systems::{BED_ENTER, PLAYER_ACTION, SLEEP};
+use crate::time::Time;
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use crate::weather::{self, Weather};
+use feather_core::level::LevelData;
+use feather_core::network::packet::implementation::{
+    AnimationClientbound, EntityActionType, TimeUpdate, UseBed,
+};
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, ClientboundAnimation, Gamemode};
+use shrev::{EventChannel, ReaderId};
+use specs::{
+    Component, DispatcherBuilder, Entities, Entity, HashMapStorage, Join, Read, ReadStorage,
+    System, Write, WriteStorage,
+};
+use std::sync::Arc;
+
+/// The number of ticks a player must sleep
+/// for before the night can be skipped.
+pub const SLEEP_TIME: u32 = 100;
+
+/// The maximum distance from which a bed can be used.
+pub const MAX_BED_DISTANCE: f64 = 3.0;
+
+/// The time of day at which players can start sleeping.
+pub const NIGHT_START: u64 = 12_541;
+/// The time of day after which players can no longer sleep.
+pub const NIGHT_END: u64 = 23_458;
+
+/// Component for players sleeping in a bed.
+#[derive(Debug, Clone)]
+pub struct SleepingComponent {
+    /// The head of the bed.
+    pub bed: BlockPosition,
+    /// The number of ticks spent sleeping.
+    pub ticks: u32,
+}
+
+impl Component for SleepingComponent {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Event triggered when a player right-clicks a bed.
+#[derive(Debug, Clone)]
+pub struct BedEnterEvent {
+    pub player: Entity,
+    /// The bed block which was clicked.
+    pub pos: BlockPosition,
+}
+
+/// Returns whether the given block is a bed.
+pub fn is_bed(block: Block) -> bool {
+    block.to_name_and_props().0.ends_with("_bed")
+}
+
+/// Returns the position of the head of the bed at
+/// the given position, or `None` if it isn't a bed.
+pub fn bed_head(block: Block, pos: BlockPosition) -> Option<BlockPosition> {
+    let (name, props) = block.to_name_and_props();
+    if !name.ends_with("_bed") {
+        return None;
+    }
+
+    let prop = |key: &str| {
+        props
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+    };
+    if prop("part")? == "head" {
+        return Some(pos);
+    }
+
+    let offset = match prop("facing")? {
+        "north" => BlockPosition::new(0, 0, -1),
+        "south" => BlockPosition::new(0, 0, 1),
+        "west" => BlockPosition::new(-1, 0, 0),
+        "east" => BlockPosition::new(1, 0, 0),
+        _ => return None,
+    };
+    Some(pos + offset)
+}
+
+/// Returns whether players can sleep at the given
+/// time, or at any time during thunderstorms.
+pub fn can_sleep(time: Time, thundering: bool) -> bool {
+    let time_of_day = time.time_of_day();
+    thundering || (NIGHT_START..=NIGHT_END).contains(&time_of_day)
+}
+
+/// Returns the number of sleeping players required
+/// to skip the night out of the given total.
+pub fn required_sleepers(players: usize, percentage: u8) -> usize {
+    let required = (players * usize::from(percentage) + 99) / 100;
+    required.max(1)
+}
+
+/// System which puts players into beds they right-click.
+///
+/// This system listens to `BedEnterEvent`s.
+#[derive(Default)]
+pub struct BedEnterSystem {
+    reader: Option<ReaderId<BedEnterEvent>>,
+}
+
+impl<'a> System<'a> for BedEnterSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BedEnterEvent>>,
+        WriteStorage<'a, SleepingComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Read<'a, ChunkMap>,
+        Read<'a, Time>,
+        Read<'a, LevelData>,
+        Read<'a, Locale>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            events,
+            mut sleepings,
+            positions,
+            networks,
+            dimensions,
+            chunk_map,
+            time,
+            level,
+            locale,
+            util,
+        ) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let player = event.player;
+            let network = continue_if_none!(networks.get(player));
+            let position = continue_if_none!(positions.get(player)).current;
+            let block = continue_if_none!(chunk_map.block_at(event.pos));
+            let bed = continue_if_none!(bed_head(block, event.pos));
+
+            // TODO: beds outside of the primary dimension explode
+            if dimensions.get(player).is_some() || sleepings.get(player).is_some() {
+                continue;
+            }
+
+            let message = if !can_sleep(*time, level.thundering) {
+                Some("block.minecraft.bed.no_sleep")
+            } else if position.distance(bed.world_pos() + position!(0.5, 0.0, 0.5))
+                > MAX_BED_DISTANCE + 0.5
+            {
+                Some("block.minecraft.bed.too_far_away")
+            } else if (&sleepings).join().any(|sleeping| sleeping.bed == bed) {
+                Some("block.minecraft.bed.occupied")
+            } else {
+                None
+            };
+            if let Some(key) = message {
+                send_message(network, &locale, Message::translate(key));
+                continue;
+            }
+
+            sleepings
+                .insert(player, SleepingComponent { bed, ticks: 0 })
+                .unwrap();
+            util.broadcast_entity_update(player, UseBed::new(player.id() as i32, bed), None);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which wakes players leaving their bed and
+/// skips the night once enough players are asleep.
+#[derive(Default)]
+pub struct SleepSystem {
+    reader: Option<ReaderId<PlayerActionEvent>>,
+}
+
+impl<'a> System<'a> for SleepSystem {
+    type SystemData = (
+        WriteStorage<'a, SleepingComponent>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Write<'a, Time>,
+        Write<'a, LevelData>,
+        Write<'a, Weather>,
+        Write<'a, EventChannel<PlayerAnimationEvent>>,
+        Read<'a, EventChannel<PlayerActionEvent>>,
+        Read<'a, Arc<Config>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut sleepings,
+            players,
+            networks,
+            dimensions,
+            mut time,
+            mut level,
+            mut weather,
+            mut animation_events,
+            action_events,
+            config,
+            entities,
+        ) = data;
+
+        let mut woken = vec![];
+
+        for event in action_events.read(self.reader.as_mut().unwrap()) {
+            if event.action == EntityActionType::LeaveBed {
+                woken.push(event.player);
+            }
+        }
+
+        for sleeping in (&mut sleepings).join() {
+            sleeping.ticks += 1;
+        }
+
+        if !can_sleep(*time, level.thundering) {
+            // Morning has come.
+            woken.extend((&entities, &sleepings).join().map(|(player, _)| player));
+        } else {
+            let mut total = 0;
+            let mut asleep = 0;
+            for (player, sleeping, _) in (&players, sleepings.maybe(), !&dimensions).join() {
+                if player.gamemode == Gamemode::Spectator {
+                    continue;
+                }
+                total += 1;
+                if sleeping.map_or(false, |sleeping| sleeping.ticks >= SLEEP_TIME) {
+                    asleep += 1;
+                }
+            }
+
+            let required = required_sleepers(total, config.gameplay.sleeping_percentage);
+            if asleep > 0 && asleep >= required {
+                skip_night(&mut time, &mut level, &mut weather, &networks);
+                woken.extend((&entities, &sleepings).join().map(|(player, _)| player));
+            }
+        }
+
+        for player in woken {
+            if sleepings.remove(player).is_none() {
+                continue;
+            }
+
+            // The player's own client must also be told to leave the bed.
+            if let Some(network) = networks.get(player) {
+                let packet =
+                    AnimationClientbound::new(player.id() as i32, ClientboundAnimation::LeaveBed);
+                send_packet_to_player(network, packet);
+            }
+            animation_events.single_write(PlayerAnimationEvent {
+                player,
+                animation: ClientboundAnimation::LeaveBed,
+            });
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// Advances the time to the next morning and clears the weather.
+fn skip_night(
+    time: &mut Time,
+    level: &mut LevelData,
+    weather: &mut Weather,
+    networks: &ReadStorage<NetworkComponent>,
+) {
+    time.0 += 24_000 - time.time_of_day();
+    let was_raining = weather::clear_weather(level, weather);
+
+    debug!("Skipping the night");
+
+    for network in networks.join() {
+        send_packet_to_player(
+            network,
+            TimeUpdate {
+                world_age: time.world_age() as i64,
+                time_of_day: time.time_of_day() as i64,
+            },
+        );
+        if was_raining {
+            weather::send_weather(network, *weather);
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(SleepSystem::default(), SLEEP, &[PLAYER_ACTION]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(BedEnterSystem::default(), BED_ENTER, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_blocks::{RedBedData, RedBedFacing, RedBedPart};
+    use feather_core::PacketType;
+    use specs::WorldExt;
+
+    fn bed(part: RedBedPart) -> Block {
+        Block::RedBed(RedBedData {
+            part,
+            facing: RedBedFacing::East,
+            occupied: false,
+        })
+    }
+
+    #[test]
+    fn test_bed_head() {
+        let pos = BlockPosition::new(0, 64, 0);
+        assert!(is_bed(bed(RedBedPart::Foot)));
+        assert!(!is_bed(Block::Stone));
+        assert_eq!(
+            bed_head(bed(RedBedPart::Foot), pos),
+            Some(BlockPosition::new(1, 64, 0))
+        );
+        assert_eq!(bed_head(bed(RedBedPart::Head), pos), Some(pos));
+        assert_eq!(bed_head(Block::Stone, pos), None);
+    }
+
+    #[test]
+    fn test_can_sleep() {
+        assert!(!can_sleep(Time(6000), false));
+        assert!(can_sleep(Time(6000), true));
+        assert!(can_sleep(Time(24_000 * 3 + 18_000), false));
+        assert!(!can_sleep(Time(23_500), false));
+    }
+
+    #[test]
+    fn test_required_sleepers() {
+        assert_eq!(required_sleepers(4, 100), 4);
+        assert_eq!(required_sleepers(4, 50), 2);
+        assert_eq!(required_sleepers(3, 50), 2);
+        assert_eq!(required_sleepers(4, 0), 1);
+        assert_eq!(required_sleepers(0, 100), 1);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let (mut w, mut d) = t::builder()
+            .with(BedEnterSystem::default(), "bed")
+            .with(SleepSystem::default(), "sleep")
+            .build();
+        t::populate_with_air(&mut w);
+        w.insert(Time(18_000));
+
+        let pos = BlockPosition::new(0, 0, 0);
+        t::set_block(0, 0, 0, bed(RedBedPart::Foot), &w);
+        t::set_block(1, 0, 0, bed(RedBedPart::Head), &w);
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(
+            &w,
+            BedEnterEvent {
+                player: player.entity,
+                pos,
+            },
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(
+            w.read_component::<SleepingComponent>()
+                .get(player.entity)
+                .unwrap()
+                .bed,
+            BlockPosition::new(1, 0, 0)
+        );
+
+        for _ in 0..SLEEP_TIME {
+            d.dispatch(&w);
+            w.maintain();
+        }
+
+        assert_eq!(w.fetch::<Time>().time_of_day(), 0);
+        assert!(w
+            .read_component::<SleepingComponent>()
+            .get(player.entity)
+            .is_none());
+        t::assert_packet_received(&player, PacketType::TimeUpdate);
+    }
+
+    #[test]
+    fn test_no_sleep_during_day() {
+        let (mut w, mut d) = t::builder().with(BedEnterSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+        w.insert(Time(6000));
+
+        t::set_block(0, 0, 0, bed(RedBedPart::Head), &w);
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(
+            &w,
+            BedEnterEvent {
+                player: player.entity,
+                pos: BlockPosition::new(0, 0, 0),
+            },
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w
+            .read_component::<SleepingComponent>()
+            .get(player.entity)
+            .is_none());
+        t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+    }
+}
@@ -0,0 +1,876 @@
+//! Natural spawning and despawning of mobs, and the
+//! rules determining where mobs may spawn.
+//!
+//! Each tick, monsters are spawned in packs at random positions
+//! in the loaded chunks around players; creatures are spawned
+//! only every 400 ticks. Each category of mobs is capped in
+//! proportion to the number of chunks around players, and
+//! natural spawning stops entirely once `gameplay.max_mobs`
+//! mobs exist. Which mobs spawn depends on the biome; monsters
+//! need darkness, while creatures need light and grass.
+//!
+//! Monsters despawn immediately once they are more than 128 blocks
+//! away from every player, and with a small chance each tick
+//! once they are more than 32 blocks away. Animals never despawn,
+//! but still count towards the cap of creatures. Mobs marked with
+//! `PersistenceRequired` or which have a custom name never
+//! despawn, and don't count towards the caps of natural spawning.
+//!
+//! Slimes spawn in two places: in swamps, where their spawn
+//! rate depends on the phase of the moon, and deep underground
+//! in slime chunks. Whether a chunk is a slime chunk is derived
+//! from the world seed in the same way as vanilla, so slime
+//! chunks are found in the same places as in vanilla worlds.
+//! Slimes themselves aren't implemented yet, so `can_slime_spawn`
+//! isn't used by natural spawning.
+//!
+//! Light levels passed to these rules should be obtained from
+//! `ChunkMap::light_level_at`, which accounts for the time of day.
+
+use crate::combat::HealthComponent;
+use crate::config::Config;
+use crate::dimension::{DimensionComponent, DimensionId, PRIMARY_DIMENSION};
+use crate::entity::{EntityDestroyEvent, PlayerComponent, PositionComponent};
+use crate::mob::{
+    self, ai, CustomNameComponent, MobCategory, MobComponent, MobKind, PersistenceRequired,
+};
+use crate::systems::{MOB_DESPAWN, NATURAL_SPAWN};
+use crate::time::Time;
+use crate::timings::DispatcherBuilderExt;
+use crate::TickCount;
+use feather_core::level::{LevelData, LevelGeneratorType};
+use feather_core::world::chunk::HeightmapKind;
+use feather_core::world::ChunkMap;
+use feather_core::{Biome, Block, BlockExt, BlockPosition, ChunkPosition, Gamemode, Position};
+use glm::DVec3;
+use hashbrown::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use shrev::EventChannel;
+use specs::{DispatcherBuilder, Entities, Join, LazyUpdate, Read, ReadStorage, System, Write};
+use std::sync::Arc;
+
+/// The value mixed into the seed of slime chunks.
+const SLIME_CHUNK_SALT: i64 = 987_234_911;
+
+/// Slimes spawn in slime chunks below this Y coordinate.
+const SLIME_CHUNK_MAX_Y: i32 = 40;
+
+/// Returns whether the given chunk is a slime chunk
+/// in a world with the given seed.
+pub fn is_slime_chunk(seed: i64, chunk: ChunkPosition) -> bool {
+    let (x, z) = (chunk.x, chunk.z);
+    // Reproduces the integer overflow of the vanilla formula.
+    let chunk_seed = seed
+        .wrapping_add(i64::from(x.wrapping_mul(x).wrapping_mul(4_987_142)))
+        .wrapping_add(i64::from(x.wrapping_mul(5_947_611)))
+        .wrapping_add(i64::from(z.wrapping_mul(z)).wrapping_mul(4_392_871))
+        .wrapping_add(i64::from(z.wrapping_mul(389_711)))
+        ^ SLIME_CHUNK_SALT;
+
+    JavaRandom::new(chunk_seed).next_int(10) == 0
+}
+
+/// Returns whether a slime may spawn at the given position,
+/// given its biome and light level.
+pub fn can_slime_spawn(
+    level: &LevelData,
+    time: Time,
+    pos: BlockPosition,
+    biome: Biome,
+    light: u8,
+    rng: &mut impl Rng,
+) -> bool {
+    if level.generator_type() == LevelGeneratorType::Flat && rng.gen_range(0, 4) != 1 {
+        return false;
+    }
+
+    let in_swamp = match biome {
+        Biome::Swamp | Biome::SwampHills => true,
+        _ => false,
+    };
+    if in_swamp
+        && pos.y > 50
+        && pos.y < 70
+        && rng.gen::<f32>() < 0.5
+        && rng.gen::<f32>() < time.moon_phase().brightness()
+        && light <= rng.gen_range(0, 8)
+    {
+        return true;
+    }
+
+    rng.gen_range(0, 10) == 0
+        && pos.y < SLIME_CHUNK_MAX_Y
+        && is_slime_chunk(level.seed, pos.chunk_pos())
+}
+
+/// The number of chunks around a single player, over which
+/// the cap of each category of mobs is spread.
+const CAP_CHUNKS: usize = 289;
+
+/// The radius, in chunks, of the square of chunks
+/// around each player in which mobs spawn.
+const CHUNK_RADIUS: i32 = 8;
+
+/// Mobs don't spawn closer than this to players or the world spawn.
+const MIN_SPAWN_DISTANCE: f64 = 24.0;
+
+/// The number of packs which may spawn in a chunk per attempt.
+const PACKS_PER_CHUNK: usize = 3;
+
+/// The number of positions tried for each pack.
+const PACK_SIZE: usize = 4;
+
+/// The maximum distance along each axis between two
+/// consecutive positions tried for a pack.
+const PACK_SPREAD: i32 = 6;
+
+/// The maximum number of mobs spawned in a chunk per attempt.
+const MAX_PER_CHUNK: usize = 4;
+
+/// The number of ticks between attempts to spawn creatures.
+const CREATURE_SPAWN_INTERVAL: u64 = 400;
+
+/// Mobs further than this from every player despawn immediately.
+const DESPAWN_DISTANCE: f64 = 128.0;
+
+/// Mobs further than this from every player
+/// despawn with a chance of one in
+/// `RANDOM_DESPAWN_CHANCE` each tick.
+const RANDOM_DESPAWN_DISTANCE: f64 = 32.0;
+
+const RANDOM_DESPAWN_CHANCE: u32 = 800;
+
+/// An entry of a biome's spawn list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnEntry {
+    pub kind: MobKind,
+    /// The chance of this entry being chosen,
+    /// relative to the other entries of the list.
+    pub weight: u32,
+}
+
+const fn entry(kind: MobKind, weight: u32) -> SpawnEntry {
+    SpawnEntry { kind, weight }
+}
+
+const CREATURES: &[SpawnEntry] = &[
+    entry(MobKind::Sheep, 12),
+    entry(MobKind::Pig, 10),
+    entry(MobKind::Chicken, 10),
+    entry(MobKind::Cow, 8),
+];
+
+const MONSTERS: &[SpawnEntry] = &[entry(MobKind::Zombie, 95), entry(MobKind::Skeleton, 100)];
+
+/// In deserts, husks replace most zombies; husks aren't
+/// implemented yet, so those zombies are left out.
+const DESERT_MONSTERS: &[SpawnEntry] = &[entry(MobKind::Zombie, 19), entry(MobKind::Skeleton, 100)];
+
+/// Returns the mobs of the given category which
+/// may spawn naturally in the given biome.
+pub fn spawn_list(biome: Biome, category: MobCategory) -> &'static [SpawnEntry] {
+    match biome {
+        Biome::Nether
+        | Biome::TheEnd
+        | Biome::SmallEndIslands
+        | Biome::EndMidlands
+        | Biome::EndHighlands
+        | Biome::EndBarrens
+        | Biome::TheVoid
+        | Biome::MushroomFields
+        | Biome::MushroomFieldShore => return &[],
+        _ => (),
+    }
+
+    match category {
+        MobCategory::Monster => match biome {
+            Biome::Desert | Biome::DesertHills | Biome::DesertLakes => DESERT_MONSTERS,
+            _ => MONSTERS,
+        },
+        MobCategory::Creature => match biome {
+            Biome::Ocean
+            | Biome::DeepOcean
+            | Biome::FrozenOcean
+            | Biome::DeepFrozenOcean
+            | Biome::ColdOcean
+            | Biome::DeepColdOcean
+            | Biome::LukewarmOcean
+            | Biome::DeepLukewarmOcean
+            | Biome::WarmOcean
+            | Biome::DeepWarmOcean
+            | Biome::River
+            | Biome::FrozenRiver
+            | Biome::Beach
+            | Biome::SnowyBeach
+            | Biome::StoneShore
+            | Biome::Desert
+            | Biome::DesertHills
+            | Biome::DesertLakes
+            | Biome::SnowyTundra
+            | Biome::SnowyMountains
+            | Biome::IceSpikes
+            | Biome::Badlands
+            | Biome::ErodedBadlands
+            | Biome::WoodedBadlandsPlateau
+            | Biome::ModifiedWoodedBadlandsPlateau
+            | Biome::BadlandsPlateau
+            | Biome::ModifiedBadlandsPlateau => &[],
+            _ => CREATURES,
+        },
+    }
+}
+
+/// Chooses an entry of a spawn list according to
+/// their weights, or `None` if the list is empty.
+fn choose(list: &[SpawnEntry], rng: &mut impl Rng) -> Option<MobKind> {
+    list.choose_weighted(rng, |entry| entry.weight)
+        .ok()
+        .map(|entry| entry.kind)
+}
+
+/// Returns whether the given position is dark enough for
+/// monsters to spawn. Positions exposed to the sky are
+/// rejected with a chance even at night, as in vanilla.
+fn is_dark_enough(
+    chunk_map: &ChunkMap,
+    time: Time,
+    pos: BlockPosition,
+    rng: &mut impl Rng,
+) -> bool {
+    match chunk_map.sky_light_at(pos) {
+        Some(sky_light) if sky_light <= rng.gen_range(0, 32) => (),
+        _ => return false,
+    }
+
+    chunk_map
+        .light_level_at(pos, time.time_of_day())
+        .map_or(false, |light| light <= rng.gen_range(0, 8))
+}
+
+/// Returns whether a mob of the given kind may spawn naturally
+/// in the given block, checking its surroundings and light level.
+pub fn can_spawn_at(
+    kind: MobKind,
+    chunk_map: &ChunkMap,
+    time: Time,
+    pos: BlockPosition,
+    rng: &mut impl Rng,
+) -> bool {
+    if !ai::can_stand_at(chunk_map, pos) {
+        return false;
+    }
+
+    let below = chunk_map.block_at(BlockPosition::new(pos.x, pos.y - 1, pos.z));
+    if below == Some(Block::Bedrock) {
+        return false;
+    }
+
+    match kind.category() {
+        MobCategory::Monster => is_dark_enough(chunk_map, time, pos, rng),
+        MobCategory::Creature => {
+            let on_grass = match below {
+                Some(Block::GrassBlock(_)) => true,
+                _ => false,
+            };
+            on_grass
+                && chunk_map
+                    .light_level_at(pos, time.time_of_day())
+                    .map_or(false, |light| light > 8)
+        }
+    }
+}
+
+/// Attempts to spawn packs of mobs of the given category
+/// around a random position in the given chunk, returning
+/// the kinds and positions of at most `limit` mobs to spawn.
+///
+/// `is_allowed` decides whether mobs may spawn at a position
+/// regardless of its surroundings; it is used to keep mobs
+/// away from players.
+fn spawn_packs(
+    chunk_map: &ChunkMap,
+    time: Time,
+    chunk: ChunkPosition,
+    category: MobCategory,
+    limit: usize,
+    is_allowed: impl Fn(DVec3) -> bool,
+    rng: &mut impl Rng,
+) -> Vec<(MobKind, Position)> {
+    let mut spawns = vec![];
+    let column = match chunk_map.chunk_at(chunk) {
+        Some(column) => column,
+        None => return spawns,
+    };
+
+    // Vanilla chooses heights below the top of the
+    // highest section containing blocks in the column.
+    let (x, z) = (rng.gen_range(0, 16), rng.gen_range(0, 16));
+    let height = column.height_at(HeightmapKind::WorldSurface, x, z) as i32;
+    let max_y = ((height - 1).max(0) / 16 + 1) * 16;
+    let start = BlockPosition::new(
+        chunk.x * 16 + x as i32,
+        rng.gen_range(0, max_y),
+        chunk.z * 16 + z as i32,
+    );
+    if chunk_map
+        .block_at(start)
+        .map_or(true, |block| block.is_solid())
+    {
+        return spawns;
+    }
+
+    for _ in 0..PACKS_PER_CHUNK {
+        let (mut x, mut z) = (start.x, start.z);
+        let mut kind = None;
+
+        for _ in 0..PACK_SIZE {
+            x += rng.gen_range(0, PACK_SPREAD) - rng.gen_range(0, PACK_SPREAD);
+            z += rng.gen_range(0, PACK_SPREAD) - rng.gen_range(0, PACK_SPREAD);
+            let pos = BlockPosition::new(x, start.y, z);
+            let spawn_pos = position!(f64::from(x) + 0.5, f64::from(pos.y), f64::from(z) + 0.5);
+            if !is_allowed(spawn_pos.as_vec()) {
+                continue;
+            }
+
+            // The kind of mob is chosen once for each pack,
+            // from the biome of its first allowed position.
+            let pack_kind = match kind {
+                Some(kind) => kind,
+                None => {
+                    let biome = continue_if_none!(chunk_map.biome_at(pos));
+                    match choose(spawn_list(biome, category), rng) {
+                        Some(chosen) => *kind.get_or_insert(chosen),
+                        None => break,
+                    }
+                }
+            };
+
+            if can_spawn_at(pack_kind, chunk_map, time, pos, rng) {
+                spawns.push((pack_kind, spawn_pos));
+                if spawns.len() >= limit {
+                    return spawns;
+                }
+            }
+        }
+    }
+
+    spawns
+}
+
+/// System which periodically spawns mobs in the
+/// loaded chunks around players in the overworld.
+pub struct NaturalSpawnSystem;
+
+impl<'a> System<'a> for NaturalSpawnSystem {
+    type SystemData = (
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadStorage<'a, MobComponent>,
+        ReadStorage<'a, PersistenceRequired>,
+        ReadStorage<'a, CustomNameComponent>,
+        Read<'a, ChunkMap>,
+        Read<'a, Time>,
+        Read<'a, LevelData>,
+        Read<'a, Arc<Config>>,
+        Read<'a, TickCount>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            players,
+            positions,
+            dimensions,
+            mobs,
+            persistent,
+            named,
+            chunk_map,
+            time,
+            level,
+            config,
+            tick,
+            lazy,
+            entities,
+        ) = data;
+
+        let gameplay = &config.gameplay;
+        let is_enabled = |category| match category {
+            MobCategory::Monster => gameplay.monster_spawning,
+            MobCategory::Creature => {
+                gameplay.animal_spawning && tick.0 % CREATURE_SPAWN_INTERVAL == 0
+            }
+        };
+        if !MobCategory::values()
+            .iter()
+            .any(|category| is_enabled(*category))
+        {
+            return;
+        }
+
+        let player_positions: Vec<Position> = (&players, &positions, dimensions.maybe())
+            .join()
+            .filter(|(player, _, dimension)| {
+                player.gamemode != Gamemode::Spectator
+                    && DimensionComponent::of(*dimension) == PRIMARY_DIMENSION
+            })
+            .map(|(_, position, _)| position.current)
+            .collect();
+        if player_positions.is_empty() {
+            return;
+        }
+
+        let mut chunks = HashSet::new();
+        for pos in &player_positions {
+            let center = pos.chunk_pos();
+            for x in -CHUNK_RADIUS..=CHUNK_RADIUS {
+                for z in -CHUNK_RADIUS..=CHUNK_RADIUS {
+                    let chunk = ChunkPosition::new(center.x + x, center.z + z);
+                    if chunk_map.chunk_at(chunk).is_some() {
+                        chunks.insert(chunk);
+                    }
+                }
+            }
+        }
+        let mut chunks: Vec<ChunkPosition> = chunks.into_iter().collect();
+        let mut rng = rand::thread_rng();
+        chunks.shuffle(&mut rng);
+
+        // Mobs which never despawn don't count towards the caps.
+        let mut counts: HashMap<MobCategory, usize> = HashMap::new();
+        let mut total = 0;
+        for (mob, dimension, _, _) in (&mobs, dimensions.maybe(), !&persistent, !&named).join() {
+            if DimensionComponent::of(dimension) == PRIMARY_DIMENSION {
+                *counts.entry(mob.kind.category()).or_default() += 1;
+                total += 1;
+            }
+        }
+
+        let world_spawn = glm::vec3(
+            f64::from(level.spawn_x),
+            f64::from(level.spawn_y),
+            f64::from(level.spawn_z),
+        );
+        let is_allowed = |pos: DVec3| {
+            glm::distance(&world_spawn, &pos) >= MIN_SPAWN_DISTANCE
+                && player_positions
+                    .iter()
+                    .all(|player| glm::distance(&player.as_vec(), &pos) >= MIN_SPAWN_DISTANCE)
+        };
+
+        for category in MobCategory::values() {
+            if !is_enabled(*category) {
+                continue;
+            }
+
+            let cap = category.cap() * chunks.len() / CAP_CHUNKS;
+            let count = counts.entry(*category).or_default();
+
+            for chunk in &chunks {
+                let limit = cap
+                    .saturating_sub(*count)
+                    .min(gameplay.max_mobs.saturating_sub(total))
+                    .min(MAX_PER_CHUNK);
+                if limit == 0 {
+                    break;
+                }
+
+                let spawns = spawn_packs(
+                    &chunk_map,
+                    *time,
+                    *chunk,
+                    *category,
+                    limit,
+                    &is_allowed,
+                    &mut rng,
+                );
+                for (kind, pos) in spawns {
+                    mob::spawn(&lazy, &entities, kind, pos);
+                    *count += 1;
+                    total += 1;
+                }
+            }
+        }
+    }
+}
+
+/// System which despawns mobs far away from the players in
+/// their dimension, unless they are marked with
+/// `PersistenceRequired` or have a custom name. Animals and
+/// mobs in dimensions without players don't despawn.
+pub struct MobDespawnSystem;
+
+impl<'a> System<'a> for MobDespawnSystem {
+    type SystemData = (
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadStorage<'a, MobComponent>,
+        ReadStorage<'a, HealthComponent>,
+        ReadStorage<'a, PersistenceRequired>,
+        ReadStorage<'a, CustomNameComponent>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            players,
+            positions,
+            dimensions,
+            mobs,
+            healths,
+            persistent,
+            named,
+            mut destroy_events,
+            entities,
+        ) = data;
+
+        let player_positions: Vec<(DimensionId, DVec3)> =
+            (&players, &positions, dimensions.maybe())
+                .join()
+                .filter(|(player, _, _)| player.gamemode != Gamemode::Spectator)
+                .map(|(_, position, dimension)| {
+                    (DimensionComponent::of(dimension), position.current.as_vec())
+                })
+                .collect();
+
+        let mut rng = rand::thread_rng();
+        for (entity, mob, health, position, dimension, _, _) in (
+            &entities,
+            &mobs,
+            &healths,
+            &positions,
+            dimensions.maybe(),
+            !&persistent,
+            !&named,
+        )
+            .join()
+        {
+            // Dead mobs are removed by `MobDeathSystem`.
+            if health.is_dead() || !mob.kind.can_despawn() {
+                continue;
+            }
+
+            let dimension = DimensionComponent::of(dimension);
+            let pos = position.current.as_vec();
+            let distance = continue_if_none!(player_positions
+                .iter()
+                .filter(|(player_dimension, _)| *player_dimension == dimension)
+                .map(|(_, player)| glm::distance(player, &pos))
+                .min_by(|a, b| a.partial_cmp(b).unwrap()));
+
+            let despawn = distance > DESPAWN_DISTANCE
+                || (distance > RANDOM_DESPAWN_DISTANCE
+                    && rng.gen_range(0, RANDOM_DESPAWN_CHANCE) == 0);
+            if despawn {
+                destroy_events.single_write(EntityDestroyEvent { entity });
+                entities.delete(entity).unwrap();
+            }
+        }
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(NaturalSpawnSystem, NATURAL_SPAWN, &[]);
+    dispatcher.add_timed(MobDespawnSystem, MOB_DESPAWN, &[]);
+}
+
+/// The linear congruential generator used by `java.util.Random`,
+/// needed to reproduce seed-derived features of vanilla worlds.
+struct JavaRandom(i64);
+
+impl JavaRandom {
+    const MULTIPLIER: i64 = 0x5_DEEC_E66D;
+    const MASK: i64 = (1 << 48) - 1;
+
+    fn new(seed: i64) -> Self {
+        JavaRandom((seed ^ Self::MULTIPLIER) & Self::MASK)
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.0 = self.0.wrapping_mul(Self::MULTIPLIER).wrapping_add(0xB) & Self::MASK;
+        (self.0 >> (48 - bits)) as i32
+    }
+
+    /// Returns a random integer in `0..bound`.
+    fn next_int(&mut self, bound: i32) -> i32 {
+        if bound & -bound == bound {
+            // Power of two
+            return ((i64::from(bound) * i64::from(self.next(31))) >> 31) as i32;
+        }
+
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+            if bits.wrapping_sub(value).wrapping_add(bound - 1) >= 0 {
+                return value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_blocks::GrassBlockData;
+    use feather_core::Chunk;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use specs::WorldExt;
+
+    fn grass() -> Block {
+        Block::GrassBlock(GrassBlockData { snowy: false })
+    }
+
+    fn chunk_map_with_floor(floor: Block) -> ChunkMap {
+        let mut chunk_map = ChunkMap::new();
+        let chunk = Chunk::new(ChunkPosition::new(0, 0));
+        chunk_map.set_chunk_at(chunk.position(), chunk);
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk_map
+                    .set_block_at(BlockPosition::new(x, 63, z), floor)
+                    .unwrap();
+            }
+        }
+        chunk_map
+    }
+
+    #[test]
+    fn test_slime_chunks() {
+        // Compared against vanilla.
+        assert!(is_slime_chunk(12345, ChunkPosition::new(-20, -2)));
+        assert!(is_slime_chunk(12345, ChunkPosition::new(-19, 5)));
+        assert!(!is_slime_chunk(12345, ChunkPosition::new(-20, -1)));
+        assert!(is_slime_chunk(
+            -4_172_144_997_902_289_642,
+            ChunkPosition::new(3006, -2000)
+        ));
+        assert!(!is_slime_chunk(
+            -4_172_144_997_902_289_642,
+            ChunkPosition::new(3005, -2000)
+        ));
+
+        let count = (-20..20)
+            .flat_map(|x| (-20..20).map(move |z| ChunkPosition::new(x, z)))
+            .filter(|&chunk| is_slime_chunk(12345, chunk))
+            .count();
+        assert_eq!(count, 163);
+    }
+
+    #[test]
+    fn test_swamp_slimes_need_moonlight() {
+        let level = LevelData::default();
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let pos = BlockPosition::new(0, 60, 0);
+        let new_moon = Time(4 * 24_000);
+
+        let spawned = (0..1000)
+            .filter(|_| can_slime_spawn(&level, Time(0), pos, Biome::Swamp, 0, &mut rng))
+            .count();
+        assert!(spawned > 0);
+
+        // Slime chunk spawns are impossible at this height,
+        // so no slimes spawn during a new moon.
+        let spawned = (0..1000)
+            .filter(|_| can_slime_spawn(&level, new_moon, pos, Biome::Swamp, 0, &mut rng))
+            .count();
+        assert_eq!(spawned, 0);
+    }
+
+    #[test]
+    fn test_spawn_lists() {
+        let plains = spawn_list(Biome::Plains, MobCategory::Creature);
+        assert!(plains.iter().any(|entry| entry.kind == MobKind::Pig));
+        assert!(plains
+            .iter()
+            .all(|entry| entry.kind.category() == MobCategory::Creature));
+        assert!(spawn_list(Biome::Ocean, MobCategory::Creature).is_empty());
+        assert!(!spawn_list(Biome::Ocean, MobCategory::Monster).is_empty());
+        assert!(spawn_list(Biome::MushroomFields, MobCategory::Monster).is_empty());
+        assert!(spawn_list(Biome::Nether, MobCategory::Creature).is_empty());
+
+        let zombies = |biome| {
+            spawn_list(biome, MobCategory::Monster)
+                .iter()
+                .find(|entry| entry.kind == MobKind::Zombie)
+                .map(|entry| entry.weight)
+        };
+        assert!(zombies(Biome::Desert) < zombies(Biome::Plains));
+    }
+
+    #[test]
+    fn test_can_spawn_at() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let pos = BlockPosition::new(8, 64, 8);
+        let noon = Time(6000);
+        let midnight = Time(18000);
+
+        let mut chunk_map = chunk_map_with_floor(grass());
+        assert!(can_spawn_at(MobKind::Pig, &chunk_map, noon, pos, &mut rng));
+        assert!(!can_spawn_at(
+            MobKind::Pig,
+            &chunk_map,
+            midnight,
+            pos,
+            &mut rng
+        ));
+        assert!((0..100).all(|_| !can_spawn_at(MobKind::Zombie, &chunk_map, noon, pos, &mut rng)));
+        // Mobs don't spawn inside blocks.
+        let floor = BlockPosition::new(8, 63, 8);
+        assert!(!can_spawn_at(
+            MobKind::Pig,
+            &chunk_map,
+            noon,
+            floor,
+            &mut rng
+        ));
+
+        // Monsters spawn wherever it's dark, even at noon.
+        chunk_map
+            .chunk_at_mut(pos.chunk_pos())
+            .unwrap()
+            .set_sky_light_at(8, 64, 8, 0);
+        assert!(can_spawn_at(
+            MobKind::Zombie,
+            &chunk_map,
+            noon,
+            pos,
+            &mut rng
+        ));
+        assert!(!can_spawn_at(MobKind::Pig, &chunk_map, noon, pos, &mut rng));
+
+        let chunk_map = chunk_map_with_floor(Block::Stone);
+        assert!(!can_spawn_at(MobKind::Pig, &chunk_map, noon, pos, &mut rng));
+
+        let mut chunk_map = chunk_map_with_floor(Block::Bedrock);
+        chunk_map
+            .chunk_at_mut(pos.chunk_pos())
+            .unwrap()
+            .set_sky_light_at(8, 64, 8, 0);
+        assert!(!can_spawn_at(
+            MobKind::Zombie,
+            &chunk_map,
+            noon,
+            pos,
+            &mut rng
+        ));
+    }
+
+    #[test]
+    fn test_natural_spawning() {
+        let (mut w, mut d) = t::builder().with(NaturalSpawnSystem, "").build();
+        t::populate_with_air(&mut w);
+        for x in -48..48 {
+            for z in -48..48 {
+                t::set_block(x, 0, z, grass(), &w);
+            }
+        }
+        t::add_player(&mut w);
+        *w.fetch_mut::<Time>() = Time(6000);
+
+        let mut spawned = vec![];
+        for _ in 0..100 {
+            w.fetch_mut::<TickCount>().0 += CREATURE_SPAWN_INTERVAL;
+            d.dispatch(&w);
+            w.maintain();
+
+            spawned = (
+                &w.read_storage::<MobComponent>(),
+                &w.read_storage::<PositionComponent>(),
+            )
+                .join()
+                .map(|(mob, position)| (mob.kind, position.current))
+                .collect();
+            if !spawned.is_empty() {
+                break;
+            }
+        }
+
+        assert!(!spawned.is_empty());
+        // Only creatures spawn in daylight.
+        for (kind, pos) in spawned {
+            assert_eq!(kind.category(), MobCategory::Creature);
+            assert!((pos.y - 1.0).abs() < 1e-6);
+            assert!(glm::length(&pos.as_vec()) >= MIN_SPAWN_DISTANCE);
+        }
+    }
+
+    #[test]
+    fn test_despawning() {
+        let (mut w, mut d) = t::builder().with(MobDespawnSystem, "").build();
+        t::add_player(&mut w);
+
+        let spawn = |w: &mut specs::World, kind: MobKind, x: f64| {
+            let entity = mob::spawn(
+                &w.fetch::<LazyUpdate>(),
+                &w.entities(),
+                kind,
+                position!(x, 0.0, 0.0),
+            );
+            w.maintain();
+            entity
+        };
+        let near = spawn(&mut w, MobKind::Zombie, 16.0);
+        let distant = spawn(&mut w, MobKind::Zombie, 64.0);
+        let far = spawn(&mut w, MobKind::Zombie, 200.0);
+        let persistent = spawn(&mut w, MobKind::Zombie, 200.0);
+        w.write_component::<PersistenceRequired>()
+            .insert(persistent, PersistenceRequired)
+            .unwrap();
+        let named = spawn(&mut w, MobKind::Zombie, 200.0);
+        w.write_component::<CustomNameComponent>()
+            .insert(
+                named,
+                CustomNameComponent(String::from(r#"{"text":"Bob"}"#)),
+            )
+            .unwrap();
+
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_removed(&w, far);
+        t::assert_not_removed(&w, persistent);
+        t::assert_not_removed(&w, named);
+
+        for _ in 0..10_000 {
+            if !w.entities().is_alive(distant) {
+                break;
+            }
+            d.dispatch(&w);
+            w.maintain();
+        }
+        t::assert_removed(&w, distant);
+        t::assert_not_removed(&w, near);
+    }
+
+    #[test]
+    fn test_animals_dont_despawn() {
+        let (mut w, mut d) = t::builder().with(MobDespawnSystem, "").build();
+        t::add_player(&mut w);
+
+        let distant = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Pig,
+            position!(40.0, 0.0, 0.0),
+        );
+        let far = mob::spawn(
+            &w.fetch::<LazyUpdate>(),
+            &w.entities(),
+            MobKind::Cow,
+            position!(200.0, 0.0, 0.0),
+        );
+        w.maintain();
+
+        for _ in 0..2000 {
+            d.dispatch(&w);
+            w.maintain();
+        }
+        t::assert_not_removed(&w, distant);
+        t::assert_not_removed(&w, far);
+    }
+}
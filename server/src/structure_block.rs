@@ -0,0 +1,948 @@
+//! Structure blocks.
+//!
+//! Structure blocks save boxes of blocks to structure files and
+//! place them back in the world. Operators in creative mode edit
+//! a structure block's settings through its GUI, which sends an
+//! Update Structure Block packet. What the block does depends on
+//! its mode:
+//! * in save mode, the box at the block's offset is saved as
+//! `generated/<namespace>/structures/<name>.nbt` in the world
+//! directory, in the vanilla structure format. The box can be
+//! detected from corner-mode blocks with the same name.
+//! * in load mode, the structure is loaded from the same file
+//! and placed at the block's offset, mirrored and rotated. The
+//! first load only sets the block's size to the structure's so
+//! that its outline is shown. Structures are placed through
+//! `WorldEdit`, so a load can be undone using `//undo`.
+//! * corner mode marks a corner of a box to save.
+//! * data mode only holds metadata for use by other structures.
+//!
+//! Structure blocks don't store their settings in block entities
+//! yet, so the settings of structure blocks are kept in the
+//! `StructureBlocks` resource and are lost when the server
+//! restarts. Settings are sent to players when they change
+//! and when a player uses the block.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::commands::{is_operator, send_message};
+use crate::config::Config;
+use crate::dimension::{DimensionComponent, Dimensions};
+use crate::entity::{NamedComponent, PlayerComponent};
+use crate::lang::{Locale, Message};
+use crate::network::{send_packet_to_player, NetworkComponent, PacketQueue};
+use crate::systems::{STRUCTURE_BLOCK_TRACK, STRUCTURE_BLOCK_UPDATE, STRUCTURE_BLOCK_USE};
+use crate::timings::DispatcherBuilderExt;
+use crate::worldedit::{copy_region, Editor, Region, WorldEdit};
+use feather_blocks::{StructureBlockData, StructureBlockMode};
+use feather_core::nbt::{self, Value};
+use feather_core::network::cast_packet;
+use feather_core::network::packet::implementation::{UpdateBlockEntity, UpdateStructureBlock};
+use feather_core::schematic::{Error as SchematicError, Mirror, Rotation, Schematic};
+use feather_core::world::{BlockPosition, ChunkMap};
+use feather_core::{Block, Gamemode, PacketType};
+use hashbrown::HashMap;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entity, Join, Read, ReadExpect, ReadStorage, System, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The directory within the world directory
+/// containing saved structures.
+pub const STRUCTURE_DIR: &str = "generated";
+
+/// The maximum size of a structure along each axis.
+const MAX_SIZE: i32 = 32;
+/// The maximum distance of a structure from its
+/// structure block along each axis.
+const MAX_OFFSET: i32 = 32;
+/// The distance along each axis within which corner blocks
+/// are found when detecting the size of a structure.
+const CORNER_DISTANCE: i32 = 80;
+
+/// The Update Block Entity action for structure blocks.
+const BLOCK_ENTITY_ACTION: u8 = 7;
+
+/// Actions of the Update Structure Block packet.
+const ACTION_UPDATE_DATA: i32 = 0;
+const ACTION_SAVE: i32 = 1;
+const ACTION_LOAD: i32 = 2;
+const ACTION_DETECT_SIZE: i32 = 3;
+
+/// Flags of the Update Structure Block packet.
+const FLAG_IGNORE_ENTITIES: u8 = 0x01;
+const FLAG_SHOW_AIR: u8 = 0x02;
+const FLAG_SHOW_BOUNDING_BOX: u8 = 0x04;
+
+/// The settings of a structure block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureBlock {
+    /// The name of the structure, e.g. `minecraft:house`.
+    pub name: String,
+    /// The name of the player who placed the block.
+    pub author: String,
+    /// The metadata of a data-mode block.
+    pub metadata: String,
+    pub mode: StructureBlockMode,
+    /// The position of the minimum corner of the
+    /// structure relative to the structure block.
+    pub offset: BlockPosition,
+    /// The size of the structure along each axis.
+    pub size: (i32, i32, i32),
+    pub mirror: Option<Mirror>,
+    pub rotation: Option<Rotation>,
+    pub ignore_entities: bool,
+    pub show_air: bool,
+    pub show_bounding_box: bool,
+    /// The fraction of blocks which are placed when loading.
+    pub integrity: f32,
+    /// The seed used to choose the blocks which are placed
+    /// if `integrity` is below 1, or 0 for a random seed.
+    pub seed: i64,
+}
+
+impl StructureBlock {
+    /// Returns the default settings of a structure block.
+    pub fn new(mode: StructureBlockMode) -> Self {
+        Self {
+            name: String::new(),
+            author: String::new(),
+            metadata: String::new(),
+            mode,
+            offset: BlockPosition::new(0, 1, 0),
+            size: (0, 0, 0),
+            mirror: None,
+            rotation: None,
+            ignore_entities: true,
+            show_air: false,
+            show_bounding_box: true,
+            integrity: 1.0,
+            seed: 0,
+        }
+    }
+
+    /// Applies the settings sent by a player, clamping
+    /// them to the values allowed by vanilla.
+    fn update(&mut self, packet: &UpdateStructureBlock) {
+        self.name = packet.name.clone();
+        self.metadata = packet.metadata.clone();
+        if let Some(mode) = mode_from_id(packet.mode) {
+            self.mode = mode;
+        }
+
+        let offset = |x: i8| i32::from(x).max(-MAX_OFFSET).min(MAX_OFFSET);
+        self.offset = BlockPosition::new(
+            offset(packet.offset_x),
+            offset(packet.offset_y),
+            offset(packet.offset_z),
+        );
+        let size = |x: i8| i32::from(x).max(0).min(MAX_SIZE);
+        self.size = (
+            size(packet.size_x),
+            size(packet.size_y),
+            size(packet.size_z),
+        );
+
+        self.mirror = mirror_from_id(packet.mirror);
+        self.rotation = rotation_from_id(packet.rotation);
+        self.ignore_entities = packet.flags & FLAG_IGNORE_ENTITIES != 0;
+        self.show_air = packet.flags & FLAG_SHOW_AIR != 0;
+        self.show_bounding_box = packet.flags & FLAG_SHOW_BOUNDING_BOX != 0;
+        self.integrity = packet.integrity.max(0.0).min(1.0);
+        self.seed = packet.seed;
+    }
+
+    /// Returns the block entity data of the
+    /// structure block at the given position.
+    pub fn to_nbt(&self, pos: BlockPosition) -> Value {
+        let data = StructureBlockNbt {
+            id: "minecraft:structure_block",
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+            name: &self.name,
+            author: &self.author,
+            metadata: &self.metadata,
+            pos_x: self.offset.x,
+            pos_y: self.offset.y,
+            pos_z: self.offset.z,
+            size_x: self.size.0,
+            size_y: self.size.1,
+            size_z: self.size.2,
+            rotation: match self.rotation {
+                None => "NONE",
+                Some(Rotation::Clockwise90) => "CLOCKWISE_90",
+                Some(Rotation::Clockwise180) => "CLOCKWISE_180",
+                Some(Rotation::Clockwise270) => "COUNTERCLOCKWISE_90",
+            },
+            mirror: match self.mirror {
+                None => "NONE",
+                Some(Mirror::Z) => "LEFT_RIGHT",
+                Some(Mirror::X) => "FRONT_BACK",
+            },
+            mode: match self.mode {
+                StructureBlockMode::Save => "SAVE",
+                StructureBlockMode::Load => "LOAD",
+                StructureBlockMode::Corner => "CORNER",
+                StructureBlockMode::Data => "DATA",
+            },
+            ignore_entities: self.ignore_entities,
+            powered: false,
+            show_air: self.show_air,
+            show_bounding_box: self.show_bounding_box,
+            integrity: self.integrity,
+            seed: self.seed,
+        };
+        nbt::to_value(&data).unwrap() // Structs always convert to compounds
+    }
+}
+
+/// The block entity data of a structure block,
+/// in the format expected by clients.
+#[derive(Serialize)]
+struct StructureBlockNbt<'a> {
+    id: &'static str,
+    x: i32,
+    y: i32,
+    z: i32,
+    name: &'a str,
+    author: &'a str,
+    metadata: &'a str,
+    #[serde(rename = "posX")]
+    pos_x: i32,
+    #[serde(rename = "posY")]
+    pos_y: i32,
+    #[serde(rename = "posZ")]
+    pos_z: i32,
+    #[serde(rename = "sizeX")]
+    size_x: i32,
+    #[serde(rename = "sizeY")]
+    size_y: i32,
+    #[serde(rename = "sizeZ")]
+    size_z: i32,
+    rotation: &'static str,
+    mirror: &'static str,
+    mode: &'static str,
+    #[serde(rename = "ignoreEntities")]
+    ignore_entities: bool,
+    powered: bool,
+    #[serde(rename = "showair")]
+    show_air: bool,
+    #[serde(rename = "showboundingbox")]
+    show_bounding_box: bool,
+    integrity: f32,
+    seed: i64,
+}
+
+fn mode_from_id(id: i32) -> Option<StructureBlockMode> {
+    match id {
+        0 => Some(StructureBlockMode::Save),
+        1 => Some(StructureBlockMode::Load),
+        2 => Some(StructureBlockMode::Corner),
+        3 => Some(StructureBlockMode::Data),
+        _ => None,
+    }
+}
+
+fn mirror_from_id(id: i32) -> Option<Mirror> {
+    match id {
+        1 => Some(Mirror::Z),
+        2 => Some(Mirror::X),
+        _ => None,
+    }
+}
+
+fn rotation_from_id(id: i32) -> Option<Rotation> {
+    match id {
+        1 => Some(Rotation::Clockwise90),
+        2 => Some(Rotation::Clockwise180),
+        3 => Some(Rotation::Clockwise270),
+        _ => None,
+    }
+}
+
+/// Resource containing the settings of structure blocks.
+#[derive(Default)]
+pub struct StructureBlocks {
+    blocks: HashMap<BlockPosition, StructureBlock>,
+}
+
+impl StructureBlocks {
+    pub fn get(&self, pos: BlockPosition) -> Option<&StructureBlock> {
+        self.blocks.get(&pos)
+    }
+
+    /// Returns the settings of the structure block at the given
+    /// position, or its default settings if it has none yet.
+    pub fn get_or_default(&self, pos: BlockPosition, state: StructureBlockData) -> StructureBlock {
+        self.blocks
+            .get(&pos)
+            .cloned()
+            .unwrap_or_else(|| StructureBlock::new(state.mode))
+    }
+
+    pub fn insert(&mut self, pos: BlockPosition, block: StructureBlock) {
+        self.blocks.insert(pos, block);
+    }
+
+    pub fn remove(&mut self, pos: BlockPosition) -> Option<StructureBlock> {
+        self.blocks.remove(&pos)
+    }
+}
+
+/// Returns the path of the structure file with the given name,
+/// or `None` if the name is not a valid resource location.
+pub fn structure_path(world_dir: &Path, name: &str) -> Option<PathBuf> {
+    let (namespace, path) = match name.find(':') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => ("minecraft", name),
+    };
+
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-.".contains(c);
+    if namespace.is_empty() || !namespace.chars().all(valid_char) {
+        return None;
+    }
+    if !path
+        .split('/')
+        .all(|part| !part.is_empty() && part != "." && part != ".." && part.chars().all(valid_char))
+    {
+        return None;
+    }
+
+    Some(
+        world_dir
+            .join(STRUCTURE_DIR)
+            .join(namespace)
+            .join("structures")
+            .join(format!("{}.nbt", path)),
+    )
+}
+
+/// Returns whether a player may use structure blocks,
+/// which requires being an operator in creative mode.
+fn can_use(
+    config: &Config,
+    player: Entity,
+    players: &ReadStorage<PlayerComponent>,
+    nameds: &ReadStorage<NamedComponent>,
+) -> bool {
+    players
+        .get(player)
+        .map_or(false, |player| player.gamemode == Gamemode::Creative)
+        && nameds
+            .get(player)
+            .map_or(false, |named| is_operator(config, &named.display_name))
+}
+
+/// Saves the box of a save-mode structure block
+/// to its structure file, returning whether it succeeded.
+fn save_structure(
+    block: &StructureBlock,
+    pos: BlockPosition,
+    chunk_map: &ChunkMap,
+    world_dir: &Path,
+) -> bool {
+    if block.mode != StructureBlockMode::Save {
+        return false;
+    }
+    let path = match structure_path(world_dir, &block.name) {
+        Some(path) => path,
+        None => return false,
+    };
+
+    let (size_x, size_y, size_z) = block.size;
+    if size_x <= 0 || size_y <= 0 || size_z <= 0 {
+        return false;
+    }
+    let min = pos + block.offset;
+    let max = min + BlockPosition::new(size_x - 1, size_y - 1, size_z - 1);
+    let schematic = match copy_region(chunk_map, Region::new(min, max), min) {
+        Ok(schematic) => schematic,
+        Err(_) => return false,
+    };
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .map_err(SchematicError::from)
+        .and_then(|_| schematic.save(&path));
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to save structure {}: {}", block.name, e);
+            false
+        }
+    }
+}
+
+/// The outcome of loading a structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoadOutcome {
+    NotFound,
+    /// The size of the structure block was set to the size of
+    /// the structure, which is placed on the next load.
+    Prepared,
+    Loaded,
+}
+
+/// Loads the structure of a load-mode structure block
+/// and places it at the block's offset.
+fn load_structure(
+    block: &mut StructureBlock,
+    pos: BlockPosition,
+    chunk_map: &mut ChunkMap,
+    world_edit: &mut WorldEdit,
+    editor: &Editor,
+    world_dir: &Path,
+) -> LoadOutcome {
+    if block.mode != StructureBlockMode::Load {
+        return LoadOutcome::NotFound;
+    }
+    let path = match structure_path(world_dir, &block.name) {
+        Some(path) if path.is_file() => path,
+        _ => return LoadOutcome::NotFound,
+    };
+
+    let mut schematic = match Schematic::load(&path) {
+        Ok(schematic) => schematic,
+        Err(e) => {
+            warn!("Failed to load structure {}: {}", block.name, e);
+            return LoadOutcome::NotFound;
+        }
+    };
+
+    if schematic.size() != block.size {
+        block.size = schematic.size();
+        return LoadOutcome::Prepared;
+    }
+
+    if let Some(mirror) = block.mirror {
+        schematic = schematic.mirrored(mirror);
+    }
+    if let Some(rotation) = block.rotation {
+        schematic = schematic.rotated(rotation);
+    }
+    if block.integrity < 1.0 {
+        schematic = with_integrity(&schematic, block.integrity, block.seed);
+    }
+
+    match world_edit.place(chunk_map, editor, &schematic, pos + block.offset) {
+        Ok(_) => LoadOutcome::Loaded,
+        Err(_) => LoadOutcome::NotFound,
+    }
+}
+
+/// Replaces blocks of a schematic with structure voids so that
+/// each block is kept with a probability of `integrity`.
+fn with_integrity(schematic: &Schematic, integrity: f32, seed: i64) -> Schematic {
+    let seed = if seed == 0 {
+        rand::random()
+    } else {
+        seed as u64
+    };
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+
+    let (size_x, size_y, size_z) = schematic.size();
+    let mut blocks = Vec::with_capacity(schematic.volume());
+    for y in 0..size_y {
+        for z in 0..size_z {
+            for x in 0..size_x {
+                if rng.gen::<f32>() < integrity {
+                    blocks.push(schematic.block_at(x, y, z));
+                } else {
+                    blocks.push(Block::StructureVoid);
+                }
+            }
+        }
+    }
+
+    Schematic::new(schematic.size(), schematic.offset(), blocks)
+}
+
+/// Sets the box of a save-mode structure block to the box between
+/// the corner blocks with the same name, returning whether any
+/// were found. If there is only one, the box is between it and
+/// the structure block.
+fn detect_size(
+    block: &mut StructureBlock,
+    pos: BlockPosition,
+    structure_blocks: &StructureBlocks,
+) -> bool {
+    if block.mode != StructureBlockMode::Save {
+        return false;
+    }
+
+    let corners: Vec<BlockPosition> = structure_blocks
+        .blocks
+        .iter()
+        .filter(|(corner, data)| {
+            data.mode == StructureBlockMode::Corner
+                && data.name == block.name
+                && (corner.x - pos.x).abs() <= CORNER_DISTANCE
+                && (corner.y - pos.y).abs() <= CORNER_DISTANCE
+                && (corner.z - pos.z).abs() <= CORNER_DISTANCE
+        })
+        .map(|(corner, _)| *corner)
+        .collect();
+
+    let (mut min, mut max) = match corners.as_slice() {
+        [] => return false,
+        [_] => (pos, pos),
+        [first, ..] => (*first, *first),
+    };
+    for corner in &corners {
+        min = BlockPosition::new(
+            min.x.min(corner.x),
+            min.y.min(corner.y),
+            min.z.min(corner.z),
+        );
+        max = BlockPosition::new(
+            max.x.max(corner.x),
+            max.y.max(corner.y),
+            max.z.max(corner.z),
+        );
+    }
+
+    // The corner blocks themselves are not part of the structure.
+    let size = (max.x - min.x - 1, max.y - min.y - 1, max.z - min.z - 1);
+    if size.0 < 1 || size.1 < 1 || size.2 < 1 {
+        return false;
+    }
+
+    block.offset = BlockPosition::new(min.x - pos.x + 1, min.y - pos.y + 1, min.z - pos.z + 1);
+    block.size = size;
+    true
+}
+
+fn block_entity_packet(pos: BlockPosition, block: &StructureBlock) -> UpdateBlockEntity {
+    UpdateBlockEntity::new(pos, BLOCK_ENTITY_ACTION, Some(block.to_nbt(pos)))
+}
+
+/// Sends the settings of a structure block to
+/// all players in the primary dimension.
+fn broadcast(
+    pos: BlockPosition,
+    block: &StructureBlock,
+    networks: &ReadStorage<NetworkComponent>,
+    dimensions: &ReadStorage<DimensionComponent>,
+) {
+    let packet = block_entity_packet(pos, block);
+    for (network, _) in (networks, !dimensions).join() {
+        send_packet_to_player(network, packet.clone());
+    }
+}
+
+/// Event triggered when a player in creative
+/// mode right-clicks a structure block.
+#[derive(Debug, Clone)]
+pub struct StructureBlockUseEvent {
+    pub player: Entity,
+    pub pos: BlockPosition,
+}
+
+/// System which sends the settings of a structure
+/// block to operators who use it.
+///
+/// This system listens to `StructureBlockUseEvent`s.
+#[derive(Default)]
+pub struct StructureBlockUseSystem {
+    reader: Option<ReaderId<StructureBlockUseEvent>>,
+}
+
+impl<'a> System<'a> for StructureBlockUseSystem {
+    type SystemData = (
+        Read<'a, EventChannel<StructureBlockUseEvent>>,
+        Read<'a, StructureBlocks>,
+        Read<'a, ChunkMap>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Read<'a, Arc<Config>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, structure_blocks, chunk_map, players, nameds, networks, config) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            if !can_use(&config, event.player, &players, &nameds) {
+                continue;
+            }
+            let state = match chunk_map.block_at(event.pos) {
+                Some(Block::StructureBlock(state)) => state,
+                _ => continue,
+            };
+            let network = continue_if_none!(networks.get(event.player));
+
+            let block = structure_blocks.get_or_default(event.pos, state);
+            send_packet_to_player(network, block_entity_packet(event.pos, &block));
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which handles Update Structure Block packets.
+pub struct StructureBlockUpdateSystem;
+
+impl<'a> System<'a> for StructureBlockUpdateSystem {
+    type SystemData = (
+        Read<'a, PacketQueue>,
+        Write<'a, StructureBlocks>,
+        Write<'a, ChunkMap>,
+        Write<'a, WorldEdit>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        ReadStorage<'a, PlayerComponent>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        ReadExpect<'a, Dimensions>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            packet_queue,
+            mut structure_blocks,
+            mut chunk_map,
+            mut world_edit,
+            mut block_updates,
+            players,
+            nameds,
+            networks,
+            dimensions,
+            dimension_worlds,
+            config,
+            locale,
+        ) = data;
+
+        let world_dir = Path::new(&config.world.name);
+
+        for (player, packet) in packet_queue.for_packet(PacketType::UpdateStructureBlock) {
+            let packet = cast_packet::<UpdateStructureBlock>(&*packet);
+            let pos = packet.location;
+
+            // Blocks outside of the primary dimension can't be modified yet.
+            if let Some(dimension) = dimensions.get(player) {
+                let world = dimension_worlds.world(dimension.0);
+                if let (Some(world), Some(network)) = (world, networks.get(player)) {
+                    world.revert_block(pos, network);
+                }
+                continue;
+            }
+            if !can_use(&config, player, &players, &nameds) {
+                continue;
+            }
+            let state = match chunk_map.block_at(pos) {
+                Some(Block::StructureBlock(state)) => state,
+                _ => continue,
+            };
+            let network = continue_if_none!(networks.get(player));
+
+            let mut block = structure_blocks.get_or_default(pos, state);
+            block.update(&packet);
+
+            // Keep the block state in sync with the mode.
+            if block.mode != state.mode {
+                let new_block = Block::StructureBlock(StructureBlockData { mode: block.mode });
+                if chunk_map.set_block_at(pos, new_block).is_ok() {
+                    block_updates.single_write(BlockUpdateEvent {
+                        cause: BlockUpdateCause::Player(player),
+                        pos,
+                        old_block: Block::StructureBlock(state),
+                        new_block,
+                    });
+                }
+            }
+
+            let message = match packet.action {
+                ACTION_SAVE | ACTION_LOAD | ACTION_DETECT_SIZE
+                    if structure_path(world_dir, &block.name).is_none() =>
+                {
+                    Some("structure_block.invalid_structure_name")
+                }
+                ACTION_SAVE => Some(if save_structure(&block, pos, &chunk_map, world_dir) {
+                    "structure_block.save_success"
+                } else {
+                    "structure_block.save_failure"
+                }),
+                ACTION_LOAD => {
+                    let outcome = load_structure(
+                        &mut block,
+                        pos,
+                        &mut chunk_map,
+                        &mut world_edit,
+                        &Editor::Entity(player),
+                        world_dir,
+                    );
+                    Some(match outcome {
+                        LoadOutcome::NotFound => "structure_block.load_not_found",
+                        LoadOutcome::Prepared => "structure_block.load_prepare",
+                        LoadOutcome::Loaded => "structure_block.load_success",
+                    })
+                }
+                ACTION_DETECT_SIZE => Some(if detect_size(&mut block, pos, &structure_blocks) {
+                    "structure_block.size_success"
+                } else {
+                    "structure_block.size_failure"
+                }),
+                ACTION_UPDATE_DATA => None,
+                // Unknown actions only update the settings.
+                _ => None,
+            };
+            if let Some(key) = message {
+                send_message(network, &locale, Message::translate(key).with(&block.name));
+            }
+
+            broadcast(pos, &block, &networks, &dimensions);
+            structure_blocks.insert(pos, block);
+        }
+    }
+}
+
+/// System which creates and removes the settings of
+/// structure blocks as they are placed and broken.
+///
+/// This system listens to `BlockUpdateEvent`s.
+#[derive(Default)]
+pub struct StructureBlockTrackSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for StructureBlockTrackSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, StructureBlocks>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, mut structure_blocks, nameds, networks, dimensions) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            match (event.old_block, event.new_block) {
+                // Only the mode changed.
+                (Block::StructureBlock(_), Block::StructureBlock(_)) => (),
+                (_, Block::StructureBlock(state)) => {
+                    let mut block = StructureBlock::new(state.mode);
+                    if let BlockUpdateCause::Player(player) = event.cause {
+                        if let Some(named) = nameds.get(player) {
+                            block.author = named.display_name.clone();
+                        }
+                    }
+
+                    broadcast(event.pos, &block, &networks, &dimensions);
+                    structure_blocks.insert(event.pos, block);
+                }
+                (Block::StructureBlock(_), _) => {
+                    structure_blocks.remove(event.pos);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(StructureBlockUpdateSystem, STRUCTURE_BLOCK_UPDATE, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(StructureBlockUseSystem::default(), STRUCTURE_BLOCK_USE, &[]);
+    dispatcher.add_timed(
+        StructureBlockTrackSystem::default(),
+        STRUCTURE_BLOCK_TRACK,
+        &[],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use specs::WorldExt;
+
+    fn packet(action: i32, mode: i32, size: i8) -> UpdateStructureBlock {
+        UpdateStructureBlock {
+            location: BlockPosition::new(0, 64, 0),
+            action,
+            mode,
+            name: String::from("test:house"),
+            offset_x: 1,
+            offset_y: 1,
+            offset_z: 1,
+            size_x: size,
+            size_y: size,
+            size_z: size,
+            integrity: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_structure_path() {
+        let dir = Path::new("world");
+        assert_eq!(
+            structure_path(dir, "house"),
+            Some(PathBuf::from(
+                "world/generated/minecraft/structures/house.nbt"
+            ))
+        );
+        assert_eq!(
+            structure_path(dir, "test:village/house_1"),
+            Some(PathBuf::from(
+                "world/generated/test/structures/village/house_1.nbt"
+            ))
+        );
+        assert_eq!(structure_path(dir, ""), None);
+        assert_eq!(structure_path(dir, "House"), None);
+        assert_eq!(structure_path(dir, "../house"), None);
+        assert_eq!(structure_path(dir, "a:b:c"), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let (mut w, mut d) = t::builder()
+            .with(StructureBlockUpdateSystem, STRUCTURE_BLOCK_UPDATE)
+            .build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        w.write_component::<NamedComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .display_name = String::from("admin");
+
+        let world_dir =
+            std::env::temp_dir().join(format!("feather-structures-{}", uuid::Uuid::new_v4()));
+        let mut config = Config::default();
+        config.server.operators.push(String::from("admin"));
+        config.world.name = world_dir.to_string_lossy().into_owned();
+        w.insert(Arc::new(config));
+
+        t::set_block(
+            0,
+            64,
+            0,
+            Block::StructureBlock(StructureBlockData::default()),
+            &w,
+        );
+        t::set_block(1, 65, 1, Block::Stone, &w);
+
+        t::receive_packet(&player, &w, packet(ACTION_SAVE, 0, 2));
+        d.dispatch(&w);
+        w.maintain();
+        t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+        assert!(world_dir
+            .join("generated/test/structures/house.nbt")
+            .is_file());
+
+        t::set_block(1, 65, 1, Block::Air, &w);
+
+        // The first load only sets the size.
+        t::receive_packet(&player, &w, packet(ACTION_LOAD, 1, 0));
+        d.dispatch(&w);
+        w.maintain();
+        {
+            let structure_blocks = w.fetch::<StructureBlocks>();
+            let block = structure_blocks.get(BlockPosition::new(0, 64, 0)).unwrap();
+            assert_eq!(block.mode, StructureBlockMode::Load);
+            assert_eq!(block.size, (2, 2, 2));
+        }
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::StructureBlock(StructureBlockData {
+                mode: StructureBlockMode::Load
+            }))
+        );
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(1, 65, 1)),
+            Some(Block::Air)
+        );
+
+        t::receive_packet(&player, &w, packet(ACTION_LOAD, 1, 2));
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(1, 65, 1)),
+            Some(Block::Stone)
+        );
+
+        fs::remove_dir_all(&world_dir).unwrap();
+    }
+
+    #[test]
+    fn test_not_operator() {
+        let (mut w, mut d) = t::builder()
+            .with(StructureBlockUpdateSystem, STRUCTURE_BLOCK_UPDATE)
+            .build();
+        t::populate_with_air(&mut w);
+        let player = t::add_player(&mut w);
+        t::set_block(
+            0,
+            64,
+            0,
+            Block::StructureBlock(StructureBlockData::default()),
+            &w,
+        );
+
+        t::receive_packet(&player, &w, packet(ACTION_UPDATE_DATA, 2, 2));
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w
+            .fetch::<StructureBlocks>()
+            .get(BlockPosition::new(0, 64, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_detect_size() {
+        let mut structure_blocks = StructureBlocks::default();
+        let corner = |name: &str| {
+            let mut block = StructureBlock::new(StructureBlockMode::Corner);
+            block.name = String::from(name);
+            block
+        };
+        structure_blocks.insert(BlockPosition::new(5, 60, 5), corner("house"));
+        structure_blocks.insert(BlockPosition::new(-2, 70, 10), corner("house"));
+        structure_blocks.insert(BlockPosition::new(20, 60, 20), corner("tower"));
+
+        let pos = BlockPosition::new(0, 64, 0);
+        let mut block = StructureBlock::new(StructureBlockMode::Save);
+        block.name = String::from("house");
+        assert!(detect_size(&mut block, pos, &structure_blocks));
+        assert_eq!(block.offset, BlockPosition::new(-1, -3, 6));
+        assert_eq!(block.size, (6, 9, 4));
+
+        // A single corner spans the box between it and the structure block.
+        block.name = String::from("tower");
+        assert!(detect_size(&mut block, pos, &structure_blocks));
+        assert_eq!(block.offset, BlockPosition::new(1, -3, 1));
+        assert_eq!(block.size, (19, 3, 19));
+
+        block.name = String::from("castle");
+        assert!(!detect_size(&mut block, pos, &structure_blocks));
+    }
+
+    #[test]
+    fn test_integrity() {
+        let schematic = Schematic::new(
+            (2, 2, 2),
+            BlockPosition::new(0, 0, 0),
+            vec![Block::Stone; 8],
+        );
+
+        let none = with_integrity(&schematic, 0.0, 1);
+        assert_eq!(none.block_at(1, 1, 1), Block::StructureVoid);
+
+        let all = with_integrity(&schematic, 1.0, 1);
+        assert_eq!(all, schematic);
+    }
+}
@@ -39,6 +39,7 @@ pub const CHAT_BROADCAST: &str = "chat_broadcast";
 
 // Entity
 pub const ITEM_COLLECT: &str = "item_collect";
+pub const LIGHTNING_DESPAWN: &str = "lightning_despawn";
 
 pub const CHUNK_ENTITIES_UPDATE: &str = "chunk_entities_update";
 pub const CHUNK_ENTITIES_LOAD: &str = "chunk_entities_load";
@@ -54,6 +55,7 @@ pub const ENTITY_VELOCITY_BROADCAST: &str = "entity_velocity_broadcast";
 pub const ENTITY_DESTROY_BROADCAST: &str = "entity_destroy_broadcast";
 pub const ENTITY_METADATA_BROADCAST: &str = "entity_metadata_broadcast";
 pub const BLOCK_FALLING_LANDING: &str = "block_falling_landing";
+pub const COMPONENT_RESET: &str = "component_reset";
 
 // Physics
 pub const ENTITY_PHYSICS: &str = "entity_physics";
@@ -68,3 +70,96 @@ pub const TIME_SEND: &str = "time_send";
 pub const BROADCASTER: &str = "broadcaster";
 
 pub const LIGHTING: &str = "lighting";
+
+pub const UNKNOWN_COMMAND: &str = "unknown_command";
+pub const RELOAD: &str = "reload";
+pub const METRICS_UPDATE: &str = "metrics_update";
+pub const TIMINGS_COMMAND: &str = "timings_command";
+pub const PLUGINS: &str = "plugins";
+pub const SCRIPTS: &str = "scripts";
+pub const STOP_COMMAND: &str = "stop_command";
+pub const SCHEDULER: &str = "scheduler";
+pub const CONSOLE: &str = "console";
+pub const ADMIN_API: &str = "admin_api";
+pub const WORLDEDIT_FLUSH: &str = "worldedit_flush";
+pub const WORLDEDIT_COMMANDS: &str = "worldedit_commands";
+pub const BULK_UPDATE_BROADCAST: &str = "bulk_update_broadcast";
+pub const FUNCTIONS: &str = "functions";
+pub const BLOCK_DROPS: &str = "block_drops";
+pub const RECIPE_SEND: &str = "recipe_send";
+pub const PORTAL_TIMER: &str = "portal_timer";
+pub const PORTAL_BLOCKS: &str = "portal_blocks";
+pub const PORTAL_TRAVEL: &str = "portal_travel";
+pub const DIMENSION_CHANGE: &str = "dimension_change";
+pub const DIMENSION_CHUNKS: &str = "dimension_chunks";
+pub const SLEEP: &str = "sleep";
+pub const BED_ENTER: &str = "bed_enter";
+pub const WEATHER: &str = "weather";
+pub const WEATHER_SEND: &str = "weather_send";
+pub const LIGHTNING: &str = "lightning";
+pub const LIGHTNING_STRIKE: &str = "lightning_strike";
+pub const VIEW_DISTANCE: &str = "view_distance";
+pub const MAP_CREATE: &str = "map_create";
+pub const MAP_UPDATE: &str = "map_update";
+pub const MAP_SAVE: &str = "map_save";
+pub const STRUCTURE_BLOCK_UPDATE: &str = "structure_block_update";
+pub const STRUCTURE_BLOCK_USE: &str = "structure_block_use";
+pub const STRUCTURE_BLOCK_TRACK: &str = "structure_block_track";
+pub const CONTAINER_OPEN: &str = "container_open";
+pub const CONTAINER_CLICK: &str = "container_click";
+pub const CONTAINER_CLOSE: &str = "container_close";
+pub const CONTAINER_BREAK: &str = "container_break";
+pub const EFFECT_TICK: &str = "effect_tick";
+pub const BEACON_EFFECT: &str = "beacon_effect";
+pub const BEACON_UPDATE: &str = "beacon_update";
+pub const FIREWORK_LAUNCH: &str = "firework_launch";
+pub const FIREWORK_UPDATE: &str = "firework_update";
+pub const ELYTRA_START: &str = "elytra_start";
+pub const ELYTRA_UPDATE: &str = "elytra_update";
+pub const ELYTRA_BOOST: &str = "elytra_boost";
+pub const PLAYER_ACTION: &str = "player_action";
+pub const PLAYER_USE_ITEM: &str = "player_use_item";
+pub const CAULDRON_USE: &str = "cauldron_use";
+pub const CHANGE_LOG: &str = "change_log";
+pub const ROLLBACK_COMMAND: &str = "rollback_command";
+pub const LIGHT_BROADCAST: &str = "light_broadcast";
+pub const RELIGHT_COMMAND: &str = "relight_command";
+pub const SIGN_EDIT: &str = "sign_edit";
+pub const SIGN_BREAK: &str = "sign_break";
+pub const STATS_SEND: &str = "stats_send";
+pub const ITEM_DESPAWN: &str = "item_despawn";
+pub const FLUID_TICK: &str = "fluid_tick";
+pub const FLUID_SCHEDULE: &str = "fluid_schedule";
+pub const BLOCK_TICK: &str = "block_tick";
+pub const RANDOM_TICK: &str = "random_tick";
+pub const CROP_GROWTH: &str = "crop_growth";
+pub const SAPLING_GROWTH: &str = "sapling_growth";
+pub const GRASS_SPREAD: &str = "grass_spread";
+pub const LEAVES_DISTANCE: &str = "leaves_distance";
+pub const LEAVES_SCHEDULE: &str = "leaves_schedule";
+pub const LEAVES_DECAY: &str = "leaves_decay";
+pub const FIRE_TICK: &str = "fire_tick";
+pub const FIRE_SCHEDULE: &str = "fire_schedule";
+pub const REDSTONE: &str = "redstone";
+pub const PISTON: &str = "piston";
+pub const BLOCK_INTERACT: &str = "block_interact";
+pub const CHEST_CONNECT: &str = "chest_connect";
+pub const CHEST_VIEWERS: &str = "chest_viewers";
+pub const FURNACE_TICK: &str = "furnace_tick";
+pub const FURNACE_CHANGE: &str = "furnace_change";
+pub const HOPPER: &str = "hopper";
+pub const INVENTORY_WINDOW: &str = "inventory_window";
+pub const CLICK_CONFIRM: &str = "click_confirm";
+pub const TOOL_DAMAGE: &str = "tool_damage";
+pub const PLAYER_ATTACK: &str = "player_attack";
+pub const DAMAGE: &str = "damage";
+pub const MOB_DEATH: &str = "mob_death";
+pub const MOB_AI: &str = "mob_ai";
+pub const SUMMON_COMMAND: &str = "summon_command";
+pub const ARROW_HIT: &str = "arrow_hit";
+pub const FIRE: &str = "fire";
+pub const PLAYER_RESPAWN: &str = "player_respawn";
+pub const DAYLIGHT_BURN: &str = "daylight_burn";
+pub const MOB_NAVIGATION: &str = "mob_navigation";
+pub const NATURAL_SPAWN: &str = "natural_spawn";
+pub const MOB_DESPAWN: &str = "mob_despawn";
@@ -15,23 +15,31 @@ use feather_core::network::packet::{Packet, PacketType};
 use feather_core::world::block::Block;
 use feather_core::world::chunk::Chunk;
 use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition, Position};
-use feather_core::Gamemode;
+use feather_core::{Dimension, Gamemode};
 
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
 use crate::chunk_logic::{ChunkHolders, ChunkLoadSystem};
-use crate::config::Config;
+use crate::combat::{FireComponent, HealthComponent, PLAYER_MAX_HEALTH};
+use crate::config::{Config, SharedConfig};
+use crate::dimension::{DimensionSettings, Dimensions};
 use crate::entity::metadata::{self, Metadata};
+use crate::entity::{chicken, cow, pig, sheep, skeleton, zombie};
 use crate::entity::{
     ArrowComponent, ChunkEntities, EntityDestroyEvent, EntitySendEvent, EntitySpawnEvent,
     ItemComponent, LastKnownPositionComponent, NamedComponent, PacketCreatorComponent,
     PlayerComponent, PositionComponent, SerializerComponent, VelocityComponent,
 };
 use crate::io::ServerToWorkerMessage;
+use crate::mob::ai::GoalSelector;
+use crate::mob::pathfinding::Navigator;
+use crate::mob::{CustomNameComponent, MobComponent, PersistenceRequired};
 use crate::network::{NetworkComponent, PacketQueue};
 use crate::physics::PhysicsComponent;
-use crate::player::{InventoryComponent, PlayerDisconnectEvent};
+use crate::player::{InventoryComponent, PlayerDisconnectEvent, PlayerStatsComponent};
 use crate::util::BroadcasterSystem;
+use crate::view_distance::ViewDistance;
 use crate::worldgen::{EmptyWorldGenerator, WorldGenerator};
-use crate::{player, PlayerCount};
+use crate::{player, PlayerCount, TickCount};
 use bitflags::_core::cell::RefCell;
 
 /// Initializes a Specs world and dispatcher
@@ -41,7 +49,7 @@ pub fn init_world<'a, 'b>() -> (World, Dispatcher<'a, 'b>) {
     let mut config = Config::default();
     config.server.port = find_open_port().unwrap();
 
-    let config = Arc::new(config);
+    let config = Arc::new(SharedConfig::new(Arc::new(config)));
 
     let player_count = Arc::new(PlayerCount(AtomicUsize::new(0)));
     let server_icon = Arc::new(None);
@@ -111,6 +119,7 @@ pub fn add_player_without_holder(world: &mut World) -> Player {
             uuid: Uuid::new_v4(),
         })
         .with(InventoryComponent::default())
+        .with(HealthComponent::new(PLAYER_MAX_HEALTH))
         .with(Metadata::Player(metadata::Player::default()))
         .with(LastKnownPositionComponent::default())
         .with(PacketCreatorComponent(&player::create_packet))
@@ -123,11 +132,23 @@ pub fn add_player_without_holder(world: &mut World) -> Player {
     }
 }
 
+/// Returns the packet sent by the given message, if any,
+/// waiting for it to be serialized if necessary.
+fn sent_packet(msg: ServerToWorkerMessage) -> Option<Box<dyn Packet>> {
+    match msg {
+        ServerToWorkerMessage::SendPacket(packet) => Some(packet),
+        ServerToWorkerMessage::SendPendingPacket(pending) => futures::executor::block_on(pending)
+            .ok()
+            .map(|packet| Box::new(packet) as Box<dyn Packet>),
+        _ => None,
+    }
+}
+
 /// Asserts that the given player has received
 /// a packet of the given type, returning the packet.
 pub fn assert_packet_received(player: &Player, ty: PacketType) -> Box<dyn Packet> {
     while let Ok(Some(msg)) = player.network_receiver.borrow_mut().try_next() {
-        if let ServerToWorkerMessage::SendPacket(packet) = msg {
+        if let Some(packet) = sent_packet(msg) {
             if packet.ty() == ty {
                 return packet;
             }
@@ -142,7 +163,7 @@ pub fn assert_packet_received(player: &Player, ty: PacketType) -> Box<dyn Packet
 /// Panics if not.
 pub fn assert_packet_not_received(player: &Player, ty: PacketType) {
     while let Ok(Some(msg)) = player.network_receiver.borrow_mut().try_next() {
-        if let ServerToWorkerMessage::SendPacket(packet) = msg {
+        if let Some(packet) = sent_packet(msg) {
             assert_ne!(packet.ty(), ty);
         }
     }
@@ -157,7 +178,7 @@ pub fn received_packets(player: &Player, cap: Option<usize>) -> Vec<Box<dyn Pack
     let mut result = vec![];
 
     while let Ok(Some(msg)) = player.network_receiver.borrow_mut().try_next() {
-        if let ServerToWorkerMessage::SendPacket(pack) = msg {
+        if let Some(pack) = sent_packet(msg) {
             result.push(pack);
         }
         if let Some(cap) = cap.as_ref() {
@@ -301,6 +322,43 @@ pub fn set_block(x: i32, y: i32, z: i32, block: Block, world: &World) {
         .unwrap();
 }
 
+/// Returns the block at the given position in the world.
+///
+/// # Panics
+/// Panics if the chunk containing the position isn't loaded.
+pub fn block_at(world: &World, pos: BlockPosition) -> Block {
+    world.fetch::<ChunkMap>().block_at(pos).unwrap()
+}
+
+/// Sets the block at the given position in the world,
+/// triggering a `BlockUpdateEvent` for it.
+pub fn place(world: &World, pos: BlockPosition, block: Block) {
+    let old_block = block_at(world, pos);
+    set_block(pos.x, pos.y, pos.z, block, world);
+    trigger_event(
+        world,
+        BlockUpdateEvent {
+            cause: BlockUpdateCause::Test,
+            pos,
+            old_block,
+            new_block: block,
+        },
+    );
+}
+
+/// Runs the given number of ticks, incrementing
+/// the `TickCount` before each of them.
+pub fn run_ticks(world: &mut World, dispatcher: &mut Dispatcher, ticks: usize) {
+    for _ in 0..ticks {
+        world
+            .entry::<TickCount>()
+            .or_insert_with(TickCount::default)
+            .0 += 1;
+        dispatcher.dispatch(world);
+        world.maintain();
+    }
+}
+
 /// A dispatcher builder for isolating tests.
 pub struct TestBuilder<'a, 'b> {
     world: World,
@@ -335,8 +393,16 @@ impl<'a, 'b> TestBuilder<'a, 'b> {
         self.world.insert(ChunkHolders::default());
         self.world.insert(ChunkEntities::default());
         self.world.insert(Arc::new(Config::default()));
+        self.world
+            .insert(ViewDistance::from_config(&Config::default()));
 
         let generator: Arc<dyn WorldGenerator> = Arc::new(EmptyWorldGenerator {});
+        self.world.insert(Dimensions::new(DimensionSettings::new(
+            "overworld",
+            Dimension::Overwold,
+            Arc::clone(&generator),
+            "world",
+        )));
         self.world.insert(generator);
 
         let mut chunk_system = ChunkLoadSystem {};
@@ -364,12 +430,26 @@ fn register_components(world: &mut World) {
     world.register::<ItemComponent>();
     world.register::<PlayerComponent>();
     world.register::<InventoryComponent>();
+    world.register::<PlayerStatsComponent>();
     world.register::<NetworkComponent>();
     world.register::<LastKnownPositionComponent>();
     world.register::<PhysicsComponent>();
     world.register::<ArrowComponent>();
     world.register::<PacketCreatorComponent>();
     world.register::<SerializerComponent>();
+    world.register::<MobComponent>();
+    world.register::<HealthComponent>();
+    world.register::<FireComponent>();
+    world.register::<GoalSelector>();
+    world.register::<Navigator>();
+    world.register::<PersistenceRequired>();
+    world.register::<CustomNameComponent>();
+    world.register::<chicken::ChickenComponent>();
+    world.register::<cow::CowComponent>();
+    world.register::<pig::PigComponent>();
+    world.register::<sheep::SheepComponent>();
+    world.register::<skeleton::SkeletonComponent>();
+    world.register::<zombie::ZombieComponent>();
 
     world
         .entry()
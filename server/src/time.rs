@@ -1,14 +1,17 @@
 //! Handles world time.
 
+use crate::dimension::Dimensions;
 use crate::joinhandler::PlayerJoinEvent;
 use crate::network::{send_packet_to_player, NetworkComponent};
 use crate::systems::{TIME_INCREMENT, TIME_SEND};
+use crate::timings::DispatcherBuilderExt;
 use feather_core::level::LevelData;
 use feather_core::packet::TimeUpdate;
 use shrev::EventChannel;
-use specs::{DispatcherBuilder, Read, ReadStorage, ReaderId, System, World, Write};
+use specs::{DispatcherBuilder, Read, ReadStorage, ReaderId, System, World, Write, WriteExpect};
 
-/// The current time of the world.
+/// The current time of the primary dimension. Other
+/// dimensions store their time in `DimensionWorld`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deref, DerefMut, Default)]
 pub struct Time(pub u64);
 
@@ -23,28 +26,107 @@ impl Time {
     pub fn world_age(self) -> u64 {
         self.0
     }
+
+    /// Returns the number of days which have passed.
+    pub fn day(self) -> u64 {
+        self.0 / 24_000
+    }
+
+    /// Returns the current phase of the moon.
+    pub fn moon_phase(self) -> MoonPhase {
+        MoonPhase::from_id((self.day() % 8) as u8).unwrap()
+    }
+}
+
+/// The phase of the moon, which advances each day
+/// in a cycle of eight days, starting at a full moon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MoonPhase {
+    FullMoon,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+}
+
+impl MoonPhase {
+    /// Returns the moon phase with the given ID, as
+    /// used by the client, or `None` if it is invalid.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => MoonPhase::FullMoon,
+            1 => MoonPhase::WaningGibbous,
+            2 => MoonPhase::LastQuarter,
+            3 => MoonPhase::WaningCrescent,
+            4 => MoonPhase::NewMoon,
+            5 => MoonPhase::WaxingCrescent,
+            6 => MoonPhase::FirstQuarter,
+            7 => MoonPhase::WaxingGibbous,
+            _ => return None,
+        })
+    }
+
+    /// Returns the ID of this moon phase.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the fraction of the moon which is lit,
+    /// from 0 at a new moon to 1 at a full moon. Spawn
+    /// rates of some mobs, such as slimes in swamps,
+    /// are multiplied by this value.
+    pub fn brightness(self) -> f32 {
+        match self {
+            MoonPhase::FullMoon => 1.0,
+            MoonPhase::WaningGibbous | MoonPhase::WaxingGibbous => 0.75,
+            MoonPhase::LastQuarter | MoonPhase::FirstQuarter => 0.5,
+            MoonPhase::WaningCrescent | MoonPhase::WaxingCrescent => 0.25,
+            MoonPhase::NewMoon => 0.0,
+        }
+    }
 }
 
 /// Initializes systems for this module.
 pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
-    dispatcher.add(TimeIncrementSystem, TIME_INCREMENT, &[]);
-    dispatcher.add(TimeSendSystem::default(), TIME_SEND, &[]);
+    dispatcher.add_timed(TimeIncrementSystem, TIME_INCREMENT, &[]);
+    dispatcher.add_timed(TimeSendSystem::default(), TIME_SEND, &[]);
 }
 
 /// Initializes the time for the world, given the
 /// level file.
+///
+/// As in vanilla, the time of day is read from `DayTime`, while
+/// `Time` only counts the ticks for which the world has been running.
 pub fn init_time(world: &mut World, level: &LevelData) {
-    world.insert(Time(level.time as u64))
+    world.insert(Time(level.day_time as u64))
+}
+
+/// Sends the given time to a player.
+pub fn send_time(network: &NetworkComponent, time: Time) {
+    let packet = TimeUpdate {
+        world_age: time.world_age() as i64,
+        time_of_day: time.time_of_day() as i64,
+    };
+    send_packet_to_player(network, packet);
 }
 
-/// System for incrementing time each tick.
+/// System for incrementing the time of
+/// each dimension each tick.
 pub struct TimeIncrementSystem;
 
 impl<'a> System<'a> for TimeIncrementSystem {
-    type SystemData = Write<'a, Time>;
+    type SystemData = (Write<'a, Time>, WriteExpect<'a, Dimensions>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut time, mut dimensions) = data;
 
-    fn run(&mut self, mut time: Self::SystemData) {
         time.0 += 1;
+        for (_, _, world) in dimensions.worlds_mut() {
+            world.time.0 += 1;
+        }
     }
 }
 
@@ -69,14 +151,7 @@ impl<'a> System<'a> for TimeSendSystem {
 
         for event in join_events.read(self.reader.as_mut().unwrap()) {
             let network = networks.get(event.player).unwrap();
-
-            // Send time to player.
-            let packet = TimeUpdate {
-                world_age: time.world_age() as i64,
-                time_of_day: time.time_of_day() as i64,
-            };
-
-            send_packet_to_player(network, packet);
+            send_time(network, *time);
         }
     }
 
@@ -95,7 +170,8 @@ mod tests {
     fn test_time_init() {
         let mut level = LevelData::default();
         let time = 29456;
-        level.time = time as i64;
+        level.day_time = time as i64;
+        level.time = 100_000;
 
         let mut world = World::new();
         init_time(&mut world, &level);
@@ -103,6 +179,21 @@ mod tests {
         assert_eq!(*world.fetch::<Time>(), Time(time));
     }
 
+    #[test]
+    fn test_moon_phase() {
+        assert_eq!(Time(0).moon_phase(), MoonPhase::FullMoon);
+        assert_eq!(Time(23_999).moon_phase(), MoonPhase::FullMoon);
+        assert_eq!(Time(24_000).moon_phase(), MoonPhase::WaningGibbous);
+        assert_eq!(Time(4 * 24_000 + 100).moon_phase(), MoonPhase::NewMoon);
+        assert_eq!(Time(8 * 24_000).moon_phase(), MoonPhase::FullMoon);
+
+        for id in 0..8 {
+            assert_eq!(MoonPhase::from_id(id).unwrap().id(), id);
+        }
+        assert_eq!(MoonPhase::from_id(8), None);
+        assert!(MoonPhase::NewMoon.brightness() < 0.001);
+    }
+
     #[test]
     fn test_time_increment_system() {
         let (mut w, mut d) = t::builder().with(TimeIncrementSystem, "").build();
@@ -0,0 +1,434 @@
+//! Per-system tick timings and TPS calculation.
+//!
+//! Systems are added to the dispatcher using
+//! `DispatcherBuilderExt::add_timed`, which wraps them
+//! in a `Timed` system recording how long each run takes.
+//! The main loop records the start of each tick so that
+//! a rolling TPS can be calculated.
+//!
+//! Timings can be viewed in-game using `/tps`
+//! and `/timings report`. Only operators and the
+//! console may use `/timings`.
+
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::entity::NamedComponent;
+use crate::lang::{Locale, Message};
+use crate::network::NetworkComponent;
+use crate::systems::TIMINGS_COMMAND;
+use crate::TPS;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Read, ReadStorage, System, World};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// The global timings.
+    pub static ref TIMINGS: Mutex<Timings> = Mutex::new(Timings::default());
+}
+
+/// Number of tick intervals to retain for TPS calculation:
+/// one minute's worth.
+const TICK_HISTORY: usize = 60 * TPS as usize;
+
+/// Number of systems to include in a timings report.
+const REPORT_LENGTH: usize = 10;
+
+/// Timing data for a single system.
+#[derive(Debug, Default, Clone, Copy)]
+struct SystemTiming {
+    total: Duration,
+}
+
+/// Collected timings.
+#[derive(Debug, Default)]
+pub struct Timings {
+    /// Timings for each system since the last reset.
+    systems: HashMap<&'static str, SystemTiming>,
+    /// Timings for each system during the current
+    /// or, between ticks, the most recent tick.
+    tick_systems: HashMap<&'static str, Duration>,
+    /// Durations of the most recent ticks, including sleep time.
+    tick_intervals: VecDeque<Duration>,
+    /// Durations of the most recent ticks, excluding sleep time.
+    tick_durations: VecDeque<Duration>,
+    last_tick_start: Option<Instant>,
+    /// Number of ticks since the last reset.
+    ticks: u64,
+}
+
+impl Timings {
+    /// Records a run of the system with the given name.
+    pub fn record_system(&mut self, name: &'static str, duration: Duration) {
+        self.systems.entry(name).or_default().total += duration;
+        *self.tick_systems.entry(name).or_default() += duration;
+    }
+
+    /// Records the start of a tick.
+    pub fn record_tick_start(&mut self, now: Instant) {
+        if let Some(last) = self.last_tick_start {
+            if self.tick_intervals.len() == TICK_HISTORY {
+                self.tick_intervals.pop_front();
+            }
+            self.tick_intervals.push_back(now - last);
+        }
+        self.last_tick_start = Some(now);
+        self.tick_systems.clear();
+        self.ticks += 1;
+    }
+
+    /// Records how long a tick took to run.
+    pub fn record_tick_duration(&mut self, duration: Duration) {
+        if self.tick_durations.len() == TICK_HISTORY {
+            self.tick_durations.pop_front();
+        }
+        self.tick_durations.push_back(duration);
+    }
+
+    /// Returns the average time taken to run each of
+    /// the last `ticks` ticks, or zero if no ticks have
+    /// been recorded yet.
+    pub fn tick_duration(&self, ticks: usize) -> Duration {
+        let count = ticks.min(self.tick_durations.len());
+        if count == 0 {
+            return Duration::default();
+        }
+
+        let total: Duration = self.tick_durations.iter().rev().take(count).sum();
+        total / count as u32
+    }
+
+    /// Returns the average TPS over the last `ticks` ticks,
+    /// or `TPS` if no ticks have been recorded yet.
+    pub fn tps(&self, ticks: usize) -> f64 {
+        let count = ticks.min(self.tick_intervals.len());
+        if count == 0 {
+            return TPS as f64;
+        }
+
+        let total: Duration = self.tick_intervals.iter().rev().take(count).sum();
+        let average = total.as_secs_f64() / count as f64;
+        if average <= 0.0 {
+            return TPS as f64;
+        }
+
+        (1.0 / average).min(TPS as f64)
+    }
+
+    /// Clears all system timings.
+    pub fn reset(&mut self) {
+        self.systems.clear();
+        self.ticks = 0;
+    }
+
+    /// Returns a breakdown of the systems which
+    /// consume the most time, most expensive first.
+    pub fn report(&self) -> Vec<String> {
+        let total: Duration = self.systems.values().map(|timing| timing.total).sum();
+        let ticks = self.ticks.max(1);
+
+        let mut systems: Vec<_> = self.systems.iter().collect();
+        systems.sort_by(|(_, a), (_, b)| b.total.cmp(&a.total));
+
+        let mut lines = vec![format!(
+            "Timings over {} ticks ({:.2} ms/tick in systems):",
+            self.ticks,
+            total.as_secs_f64() * 1000.0 / ticks as f64
+        )];
+
+        for (name, timing) in systems.into_iter().take(REPORT_LENGTH) {
+            let share = if total.as_nanos() == 0 {
+                0.0
+            } else {
+                timing.total.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            lines.push(format!(
+                "  {}: {:.3} ms/tick ({:.1}%)",
+                name,
+                timing.total.as_secs_f64() * 1000.0 / ticks as f64,
+                share
+            ));
+        }
+
+        lines
+    }
+
+    /// Returns the systems which took the most time
+    /// during the most recent tick, most expensive first.
+    pub fn tick_report(&self) -> Vec<String> {
+        let mut systems: Vec<_> = self.tick_systems.iter().collect();
+        systems.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        systems
+            .into_iter()
+            .take(REPORT_LENGTH)
+            .map(|(name, duration)| {
+                format!("  {}: {:.3} ms", name, duration.as_secs_f64() * 1000.0)
+            })
+            .collect()
+    }
+}
+
+/// Wrapper around a system which records
+/// its running time in `TIMINGS`.
+pub struct Timed<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S> Timed<S> {
+    pub fn new(inner: S, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+impl<'a, S: System<'a>> System<'a> for Timed<S> {
+    type SystemData = S::SystemData;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let start = Instant::now();
+        self.inner.run(data);
+        TIMINGS.lock().record_system(self.name, start.elapsed());
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        self.inner.setup(world);
+    }
+
+    fn dispose(self, world: &mut World)
+    where
+        Self: Sized,
+    {
+        self.inner.dispose(world);
+    }
+}
+
+/// Extension trait for adding timed systems to a dispatcher.
+pub trait DispatcherBuilderExt {
+    /// Adds a system whose running time is recorded
+    /// under its name. Equivalent to `DispatcherBuilder::add`.
+    fn add_timed<S>(&mut self, system: S, name: &'static str, dependencies: &[&str])
+    where
+        S: for<'c> System<'c> + Send + 'static;
+
+    /// Adds a timed thread-local system.
+    fn add_timed_thread_local<S>(&mut self, system: S, name: &'static str)
+    where
+        S: for<'c> System<'c> + 'static;
+}
+
+impl<'a, 'b> DispatcherBuilderExt for DispatcherBuilder<'a, 'b> {
+    fn add_timed<S>(&mut self, system: S, name: &'static str, dependencies: &[&str])
+    where
+        S: for<'c> System<'c> + Send + 'static,
+    {
+        self.add(Timed::new(system, name), name, dependencies);
+    }
+
+    fn add_timed_thread_local<S>(&mut self, system: S, name: &'static str)
+    where
+        S: for<'c> System<'c> + 'static,
+    {
+        self.add_thread_local(Timed::new(system, name));
+    }
+}
+
+/// System implementing `/tps` and `/timings`.
+#[derive(Default)]
+pub struct TimingsCommandSystem {
+    reader: Option<ReaderId<CommandEvent>>,
+}
+
+impl<'a> System<'a> for TimingsCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, Locale>,
+        Read<'a, Arc<Config>>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, locale, config, nameds, networks, consoles) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            // The report reveals the server's internals.
+            if event.name == "timings" && !is_privileged(&config, event.sender, &nameds, &consoles)
+            {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let lines = match (event.name.as_str(), event.args.first().map(String::as_str)) {
+                ("tps", _) => {
+                    let timings = TIMINGS.lock();
+                    vec![Message::translate("feather.command.tps")
+                        .with(format!("{:.1}", timings.tps(5 * TPS as usize)))
+                        .with(format!("{:.1}", timings.tps(TICK_HISTORY)))]
+                }
+                // The report is a table of system names
+                // and durations, which is not translated.
+                ("timings", Some("report")) => TIMINGS
+                    .lock()
+                    .report()
+                    .into_iter()
+                    .map(Message::from)
+                    .collect(),
+                ("timings", Some("reset")) => {
+                    TIMINGS.lock().reset();
+                    vec![Message::translate("feather.command.timings.reset")]
+                }
+                ("timings", _) => vec![usage("/timings <report|reset>")],
+                _ => continue,
+            };
+
+            for line in lines {
+                reply(event.sender, &networks, &consoles, &locale, line);
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+
+        let mut registry = world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default);
+        registry.register("tps");
+        registry.register("timings");
+    }
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(TimingsCommandSystem::default(), TIMINGS_COMMAND, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::network::packet::implementation::ChatMessageClientbound;
+    use feather_core::PacketType;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_tps() {
+        let mut timings = Timings::default();
+        assert_eq!(timings.tps(100), TPS as f64);
+
+        let start = Instant::now();
+        for i in 0..=10 {
+            timings.record_tick_start(start + Duration::from_millis(100 * i));
+        }
+
+        assert!((timings.tps(100) - 10.0).abs() < 0.001);
+
+        // Faster ticks than the target are capped.
+        let mut timings = Timings::default();
+        for i in 0..=10 {
+            timings.record_tick_start(start + Duration::from_millis(10 * i));
+        }
+        assert_eq!(timings.tps(100), TPS as f64);
+    }
+
+    #[test]
+    fn test_tick_duration() {
+        let mut timings = Timings::default();
+        assert_eq!(timings.tick_duration(20), Duration::default());
+
+        timings.record_tick_duration(Duration::from_millis(10));
+        timings.record_tick_duration(Duration::from_millis(30));
+        timings.record_tick_duration(Duration::from_millis(50));
+
+        assert_eq!(timings.tick_duration(20), Duration::from_millis(30));
+        assert_eq!(timings.tick_duration(2), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_report() {
+        let mut timings = Timings::default();
+        timings.record_tick_start(Instant::now());
+        timings.record_system("lighting", Duration::from_millis(3));
+        timings.record_system("chunk_load", Duration::from_millis(1));
+
+        let report = timings.report();
+        assert_eq!(report.len(), 3);
+        assert!(report[1].contains("lighting: 3.000 ms/tick (75.0%)"));
+        assert!(report[2].contains("chunk_load"));
+
+        timings.reset();
+        assert_eq!(timings.report().len(), 1);
+    }
+
+    #[test]
+    fn test_tick_report() {
+        let mut timings = Timings::default();
+        timings.record_tick_start(Instant::now());
+        timings.record_system("lighting", Duration::from_millis(3));
+        timings.record_system("chunk_load", Duration::from_millis(5));
+
+        let report = timings.tick_report();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].contains("chunk_load: 5.000 ms"));
+
+        // Only the most recent tick is included.
+        timings.record_tick_start(Instant::now());
+        timings.record_system("lighting", Duration::from_millis(1));
+        assert_eq!(
+            timings.tick_report(),
+            vec!["  lighting: 1.000 ms".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_tps_command() {
+        let (mut w, mut d) = t::builder()
+            .with(TimingsCommandSystem::default(), "")
+            .build();
+        assert!(w.fetch::<CommandRegistry>().contains("tps"));
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "/tps").unwrap());
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+        let packet = cast_packet::<ChatMessageClientbound>(&*packet);
+        assert!(packet.json_data.contains("TPS"));
+    }
+
+    #[test]
+    fn test_timings_command_requires_operator() {
+        let (mut w, mut d) = t::builder()
+            .with(TimingsCommandSystem::default(), "")
+            .build();
+
+        let player = t::add_player(&mut w);
+        t::trigger_event(
+            &w,
+            CommandEvent::parse(player.entity, "/timings report").unwrap(),
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        let packet = t::assert_packet_received(&player, PacketType::ChatMessageClientbound);
+        let packet = cast_packet::<ChatMessageClientbound>(&*packet);
+        assert!(packet.json_data.contains("permission"));
+    }
+}
@@ -0,0 +1,811 @@
+//! Tools, and how fast blocks are broken with them.
+//!
+//! Each block has a hardness, which determines how long it takes to
+//! break. Pickaxes, axes and shovels break the blocks they are made
+//! for faster, depending on their tier. Some blocks, such as stone
+//! and ores, only drop items when broken with a tool of a high enough
+//! tier; see `can_harvest`.
+//!
+//! Tools lose durability when they are used to break blocks or to
+//! attack mobs, and break once they have lost all of it. Enchantments
+//! other than efficiency and aqua affinity aren't taken into account
+//! yet.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::effect::{EffectsComponent, StatusEffect};
+use crate::entity::PlayerComponent;
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::player::{InventoryComponent, InventoryUpdateEvent};
+use crate::systems::{BLOCK_DROPS, TOOL_DAMAGE};
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use feather_blocks::Block;
+use feather_core::inventory::SLOT_HOTBAR_OFFSET;
+use feather_core::network::packet::implementation::EntityStatus;
+use feather_core::{Gamemode, Item, ItemStack};
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entity, Read, ReadStorage, System, Write, WriteStorage};
+
+/// The Entity Status shown when the item
+/// in an entity's main hand breaks.
+const STATUS_MAIN_HAND_BREAK: i8 = 47;
+
+/// The kinds of tools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolKind {
+    Pickaxe,
+    Axe,
+    Shovel,
+    Hoe,
+    Sword,
+    Shears,
+}
+
+/// The materials tools are made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolTier {
+    Wood,
+    Stone,
+    Iron,
+    Diamond,
+    Gold,
+}
+
+impl ToolTier {
+    /// Returns how many times faster than by hand a tool
+    /// of this tier breaks the blocks it is made for.
+    pub fn speed(self) -> f32 {
+        match self {
+            ToolTier::Wood => 2.0,
+            ToolTier::Stone => 4.0,
+            ToolTier::Iron => 6.0,
+            ToolTier::Diamond => 8.0,
+            ToolTier::Gold => 12.0,
+        }
+    }
+
+    /// Returns the harvest level of this tier, which
+    /// determines the ores a pickaxe can harvest.
+    pub fn level(self) -> u8 {
+        match self {
+            ToolTier::Wood | ToolTier::Gold => 0,
+            ToolTier::Stone => 1,
+            ToolTier::Iron => 2,
+            ToolTier::Diamond => 3,
+        }
+    }
+}
+
+/// A tool, with its tier if it has one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tool {
+    pub kind: ToolKind,
+    pub tier: Option<ToolTier>,
+}
+
+/// Returns the tool the given item is, if any.
+pub fn tool(item: Item) -> Option<Tool> {
+    if item == Item::Shears {
+        return Some(Tool {
+            kind: ToolKind::Shears,
+            tier: None,
+        });
+    }
+
+    let name = item.identifier().trim_start_matches("minecraft:");
+    let (tier, kind) = match name.find('_') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => return None,
+    };
+    let tier = match tier {
+        "wooden" => ToolTier::Wood,
+        "stone" => ToolTier::Stone,
+        "iron" => ToolTier::Iron,
+        "diamond" => ToolTier::Diamond,
+        "golden" => ToolTier::Gold,
+        _ => return None,
+    };
+    let kind = match kind {
+        "pickaxe" => ToolKind::Pickaxe,
+        "axe" => ToolKind::Axe,
+        "shovel" => ToolKind::Shovel,
+        "hoe" => ToolKind::Hoe,
+        "sword" => ToolKind::Sword,
+        _ => return None,
+    };
+    Some(Tool {
+        kind,
+        tier: Some(tier),
+    })
+}
+
+fn block_name(block: Block) -> String {
+    let name = block.to_name_and_props().0;
+    name.trim_start_matches("minecraft:").to_string()
+}
+
+/// Returns the hardness of a block, or `None`
+/// if the block can't be broken in survival mode.
+pub fn hardness(block: Block) -> Option<f32> {
+    let name = block_name(block);
+    let name = name.as_str();
+
+    let hardness = match name {
+        "bedrock"
+        | "barrier"
+        | "end_portal"
+        | "end_gateway"
+        | "end_portal_frame"
+        | "nether_portal"
+        | "command_block"
+        | "chain_command_block"
+        | "repeating_command_block"
+        | "structure_block"
+        | "moving_piston"
+        | "water"
+        | "lava"
+        | "bubble_column" => return None,
+        "obsidian" => 50.0,
+        "ender_chest" => 22.5,
+        "iron_block" | "diamond_block" | "emerald_block" | "redstone_block" | "coal_block"
+        | "iron_bars" | "iron_door" | "iron_trapdoor" | "anvil" | "chipped_anvil"
+        | "damaged_anvil" | "enchanting_table" | "spawner" => 5.0,
+        "cobweb" => 4.0,
+        "furnace" | "dispenser" | "dropper" => 3.5,
+        "end_stone" | "dragon_egg" | "beacon" | "gold_block" | "lapis_block" | "observer"
+        | "hopper" | "conduit" => 3.0,
+        "blue_ice" => 2.8,
+        "chest" | "trapped_chest" | "crafting_table" => 2.5,
+        "cobblestone"
+        | "mossy_cobblestone"
+        | "bricks"
+        | "nether_bricks"
+        | "red_nether_bricks"
+        | "bone_block"
+        | "smooth_stone"
+        | "smooth_sandstone"
+        | "smooth_red_sandstone"
+        | "smooth_quartz"
+        | "jukebox"
+        | "cauldron"
+        | "petrified_oak_slab"
+        | "stone_slab"
+        | "cobblestone_slab"
+        | "brick_slab"
+        | "stone_brick_slab"
+        | "nether_brick_slab"
+        | "quartz_slab"
+        | "sandstone_slab"
+        | "red_sandstone_slab"
+        | "purpur_slab" => 2.0,
+        "stone"
+        | "granite"
+        | "polished_granite"
+        | "diorite"
+        | "polished_diorite"
+        | "andesite"
+        | "polished_andesite"
+        | "bookshelf"
+        | "purpur_block"
+        | "purpur_pillar"
+        | "purpur_stairs"
+        | "prismarine"
+        | "prismarine_bricks"
+        | "dark_prismarine"
+        | "prismarine_slab"
+        | "prismarine_brick_slab"
+        | "dark_prismarine_slab"
+        | "prismarine_stairs"
+        | "prismarine_brick_stairs"
+        | "dark_prismarine_stairs"
+        | "stone_bricks"
+        | "mossy_stone_bricks"
+        | "cracked_stone_bricks"
+        | "chiseled_stone_bricks"
+        | "stone_brick_stairs"
+        | "piston"
+        | "sticky_piston"
+        | "piston_head" => 1.5,
+        "terracotta" => 1.25,
+        "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "melon" | "sign" | "wall_sign" => 1.0,
+        "sandstone"
+        | "chiseled_sandstone"
+        | "cut_sandstone"
+        | "sandstone_stairs"
+        | "red_sandstone"
+        | "chiseled_red_sandstone"
+        | "cut_red_sandstone"
+        | "red_sandstone_stairs"
+        | "quartz_block"
+        | "chiseled_quartz_block"
+        | "quartz_pillar"
+        | "quartz_stairs"
+        | "note_block"
+        | "end_stone_bricks" => 0.8,
+        "rail" | "powered_rail" | "detector_rail" | "activator_rail" => 0.7,
+        "grass_path" => 0.65,
+        "grass_block" | "mycelium" | "farmland" | "gravel" | "clay" | "sponge" | "wet_sponge" => {
+            0.6
+        }
+        "dirt"
+        | "coarse_dirt"
+        | "podzol"
+        | "sand"
+        | "red_sand"
+        | "soul_sand"
+        | "ice"
+        | "packed_ice"
+        | "frosted_ice"
+        | "magma_block"
+        | "hay_block"
+        | "cake"
+        | "turtle_egg"
+        | "dried_kelp_block"
+        | "lever"
+        | "brewing_stand"
+        | "stone_button"
+        | "stone_pressure_plate"
+        | "light_weighted_pressure_plate"
+        | "heavy_weighted_pressure_plate" => 0.5,
+        "cactus" | "ladder" | "netherrack" | "chorus_plant" | "chorus_flower" => 0.4,
+        "glass" | "glass_pane" | "glowstone" | "redstone_lamp" | "sea_lantern" => 0.3,
+        "snow_block"
+        | "vine"
+        | "cocoa"
+        | "daylight_detector"
+        | "brown_mushroom_block"
+        | "red_mushroom_block"
+        | "mushroom_stem" => 0.2,
+        "snow" => 0.1,
+        _ if name.ends_with("_glazed_terracotta") => 1.4,
+        _ if name.ends_with("_terracotta") => 1.25,
+        _ if name.ends_with("_concrete_powder") => 0.5,
+        _ if name.ends_with("_concrete") => 1.8,
+        _ if name.ends_with("_shulker_box") || name == "shulker_box" => 2.0,
+        _ if name.ends_with("_coral_block") => 1.5,
+        _ if name.ends_with("_ore") => 3.0,
+        _ if name.starts_with("infested_") => 0.75,
+        _ if name.ends_with("_wool") => 0.8,
+        _ if name.ends_with("_banner") || name.ends_with("_head") || name.ends_with("_skull") => {
+            1.0
+        }
+        _ if name.ends_with("_door") || name.ends_with("_trapdoor") => 3.0,
+        _ if name.ends_with("_planks")
+            || name.ends_with("_log")
+            || name.ends_with("_wood")
+            || name.ends_with("_fence")
+            || name.ends_with("_fence_gate")
+            || name.ends_with("_slab")
+            || name.ends_with("_stairs")
+            || name.ends_with("_wall") =>
+        {
+            2.0
+        }
+        _ if name.ends_with("_button") || name.ends_with("_pressure_plate") => 0.5,
+        _ if name.ends_with("_stained_glass") || name.ends_with("_stained_glass_pane") => 0.3,
+        _ if name.ends_with("_leaves") || name.ends_with("_bed") => 0.2,
+        _ if name.ends_with("_carpet") => 0.1,
+        // Plants, torches, redstone components
+        // and similar blocks break instantly.
+        _ => 0.0,
+    };
+    Some(hardness)
+}
+
+/// Returns the kind of tool which breaks a block faster.
+/// Swords and shears, which break few blocks faster, are
+/// handled by `break_speed`.
+pub fn effective_tool(block: Block) -> Option<ToolKind> {
+    let name = block_name(block);
+    let name = name.as_str();
+
+    if pickaxe_level(block).is_some()
+        || name.ends_with("ice")
+        || name.ends_with("rail")
+        || name == "stone_button"
+        || name.starts_with("piston")
+        || name.starts_with("sticky_piston")
+    {
+        return Some(ToolKind::Pickaxe);
+    }
+
+    match name {
+        "dirt" | "coarse_dirt" | "podzol" | "grass_block" | "mycelium" | "farmland"
+        | "grass_path" | "sand" | "red_sand" | "gravel" | "clay" | "snow" | "snow_block"
+        | "soul_sand" => Some(ToolKind::Shovel),
+        _ if name.ends_with("_concrete_powder") => Some(ToolKind::Shovel),
+        "bookshelf"
+        | "chest"
+        | "trapped_chest"
+        | "crafting_table"
+        | "pumpkin"
+        | "carved_pumpkin"
+        | "jack_o_lantern"
+        | "melon"
+        | "ladder"
+        | "sign"
+        | "wall_sign"
+        | "note_block"
+        | "jukebox"
+        | "daylight_detector"
+        | "cocoa"
+        | "vine"
+        | "brown_mushroom_block"
+        | "red_mushroom_block"
+        | "mushroom_stem"
+        | "chorus_plant"
+        | "chorus_flower"
+        | "petrified_oak_slab" => Some(ToolKind::Axe),
+        _ if name.ends_with("_banner")
+            || name.ends_with("_planks")
+            || name.ends_with("_log")
+            || name.ends_with("_wood")
+            || (name.ends_with("_fence") && name != "nether_brick_fence")
+            || name.ends_with("_fence_gate")
+            || (name.ends_with("_door") && name != "iron_door")
+            || (name.ends_with("_trapdoor") && name != "iron_trapdoor")
+            || (WOOD_TYPES.iter().any(|wood| name.starts_with(wood))
+                && !name.ends_with("_leaves")) =>
+        {
+            Some(ToolKind::Axe)
+        }
+        _ => None,
+    }
+}
+
+const WOOD_TYPES: [&str; 6] = [
+    "oak_",
+    "spruce_",
+    "birch_",
+    "jungle_",
+    "acacia_",
+    "dark_oak_",
+];
+
+/// Returns the harvest level a pickaxe needs to harvest a block,
+/// or `None` if the block can be harvested without a pickaxe.
+fn pickaxe_level(block: Block) -> Option<u8> {
+    let name = block_name(block);
+    let name = name.as_str();
+
+    let level = match name {
+        // Infested blocks break like clay.
+        _ if name.starts_with("infested_") => return None,
+        "obsidian" => 3,
+        "diamond_block" | "diamond_ore" | "emerald_block" | "emerald_ore" | "gold_block"
+        | "gold_ore" | "redstone_ore" => 2,
+        "iron_block" | "iron_ore" | "lapis_block" | "lapis_ore" => 1,
+        "stone"
+        | "granite"
+        | "polished_granite"
+        | "diorite"
+        | "polished_diorite"
+        | "andesite"
+        | "polished_andesite"
+        | "cobblestone"
+        | "mossy_cobblestone"
+        | "coal_ore"
+        | "nether_quartz_ore"
+        | "coal_block"
+        | "redstone_block"
+        | "bricks"
+        | "nether_bricks"
+        | "red_nether_bricks"
+        | "nether_brick_fence"
+        | "netherrack"
+        | "end_stone"
+        | "end_stone_bricks"
+        | "terracotta"
+        | "magma_block"
+        | "bone_block"
+        | "smooth_stone"
+        | "furnace"
+        | "dispenser"
+        | "dropper"
+        | "observer"
+        | "spawner"
+        | "enchanting_table"
+        | "ender_chest"
+        | "brewing_stand"
+        | "cauldron"
+        | "hopper"
+        | "iron_bars"
+        | "iron_door"
+        | "iron_trapdoor"
+        | "anvil"
+        | "chipped_anvil"
+        | "damaged_anvil"
+        | "stone_pressure_plate"
+        | "light_weighted_pressure_plate"
+        | "heavy_weighted_pressure_plate"
+        | "purpur_block"
+        | "purpur_pillar" => 0,
+        _ if name.contains("sandstone")
+            || name.contains("quartz")
+            || name.contains("prismarine")
+            || name.contains("stone_brick")
+            || name.contains("cobblestone")
+            || name.starts_with("purpur_")
+            || name.starts_with("brick_")
+            || name.starts_with("nether_brick_")
+            || name.ends_with("_terracotta")
+            || (name.ends_with("_concrete") && !name.ends_with("_concrete_powder"))
+            || name.ends_with("_coral_block")
+            || name == "stone_slab" =>
+        {
+            0
+        }
+        _ => return None,
+    };
+    Some(level)
+}
+
+/// Returns whether a block drops items when broken
+/// by a player holding the given item.
+pub fn can_harvest(block: Block, held: Option<&ItemStack>) -> bool {
+    let tool = held.and_then(|held| tool(held.ty));
+    let name = block_name(block);
+
+    if let Some(level) = pickaxe_level(block) {
+        return match tool {
+            Some(Tool {
+                kind: ToolKind::Pickaxe,
+                tier: Some(tier),
+            }) => tier.level() >= level,
+            _ => false,
+        };
+    }
+
+    match name.as_str() {
+        "snow" | "snow_block" => tool.map_or(false, |tool| tool.kind == ToolKind::Shovel),
+        "cobweb" => tool.map_or(false, |tool| {
+            tool.kind == ToolKind::Sword || tool.kind == ToolKind::Shears
+        }),
+        _ => true,
+    }
+}
+
+/// Returns how many times faster than by hand a
+/// block is broken with the given item, including
+/// the bonus of the efficiency enchantment.
+pub fn break_speed(block: Block, held: Option<&ItemStack>) -> f32 {
+    let held = match held {
+        Some(held) => held,
+        None => return 1.0,
+    };
+    let tool = match tool(held.ty) {
+        Some(tool) => tool,
+        None => return 1.0,
+    };
+    let name = block_name(block);
+    let name = name.as_str();
+
+    let speed = match tool.kind {
+        ToolKind::Sword if name == "cobweb" => 15.0,
+        ToolKind::Sword
+            if name.ends_with("_leaves")
+                || name.contains("coral")
+                || name == "vine"
+                || name == "pumpkin"
+                || name == "melon" =>
+        {
+            1.5
+        }
+        ToolKind::Shears if name == "cobweb" || name.ends_with("_leaves") => 15.0,
+        ToolKind::Shears if name.ends_with("_wool") => 5.0,
+        kind if effective_tool(block) == Some(kind) => tool.tier.map_or(1.0, |tier| tier.speed()),
+        _ => 1.0,
+    };
+
+    match held.enchantment_level("minecraft:efficiency") {
+        Some(level) if speed > 1.0 && level > 0 => {
+            let level = f32::from(level);
+            speed + level * level + 1.0
+        }
+        _ => speed,
+    }
+}
+
+/// Returns the fraction of a block which a player breaks each
+/// tick. Blocks are broken once this adds up to one, or in a
+/// single tick if it is one or more.
+///
+/// `underwater` is whether the player's head is in water without
+/// a helmet with aqua affinity.
+pub fn break_progress(
+    block: Block,
+    held: Option<&ItemStack>,
+    effects: Option<&EffectsComponent>,
+    on_ground: bool,
+    underwater: bool,
+) -> f32 {
+    let hardness = match hardness(block) {
+        Some(hardness) => hardness,
+        None => return 0.0,
+    };
+    if hardness <= 0.0 {
+        return 1.0;
+    }
+
+    let mut speed = break_speed(block, held);
+    if let Some(effects) = effects {
+        if let Some(haste) = effects.get(StatusEffect::Haste) {
+            speed *= 1.0 + f32::from(haste.amplifier + 1) * 0.2;
+        }
+        if let Some(fatigue) = effects.get(StatusEffect::MiningFatigue) {
+            speed *= match fatigue.amplifier {
+                0 => 0.3,
+                1 => 0.09,
+                2 => 0.0027,
+                _ => 0.00081,
+            };
+        }
+    }
+    if underwater {
+        speed /= 5.0;
+    }
+    if !on_ground {
+        speed /= 5.0;
+    }
+
+    let divisor = if can_harvest(block, held) {
+        30.0
+    } else {
+        100.0
+    };
+    speed / hardness / divisor
+}
+
+/// Returns the durability a tool loses when used to break a block.
+fn damage_for(tool: Tool, block: Block) -> i32 {
+    match tool.kind {
+        ToolKind::Shears => 1,
+        _ if hardness(block) == Some(0.0) => 0,
+        ToolKind::Sword => 2,
+        ToolKind::Pickaxe | ToolKind::Axe | ToolKind::Shovel => 1,
+        ToolKind::Hoe => 0,
+    }
+}
+
+/// Damages the item in a player's main hand, breaking it
+/// once it has lost all of its durability.
+pub fn damage_held_item(
+    player: Entity,
+    inventory: &mut InventoryComponent,
+    damage: i32,
+    networks: &ReadStorage<NetworkComponent>,
+    update_events: &mut EventChannel<InventoryUpdateEvent>,
+    util: &Util,
+) {
+    let mut stack = match inventory.item_in_main_hand() {
+        Some(stack) => stack.clone(),
+        None => return,
+    };
+
+    let slot = SLOT_HOTBAR_OFFSET + inventory.held_item;
+    if stack.add_damage(damage) {
+        inventory.clear_item_at(slot);
+        let status = EntityStatus::new(player.id() as i32, STATUS_MAIN_HAND_BREAK);
+        if let Some(network) = networks.get(player) {
+            send_packet_to_player(network, status.clone());
+        }
+        util.broadcast_entity_update(player, status, Some(player));
+    } else {
+        inventory.set_item_at(slot, stack);
+    }
+    update_events.single_write(InventoryUpdateEvent {
+        slots: smallvec![slot],
+        player,
+    });
+}
+
+/// System which damages the tools of players who
+/// break blocks and are not in creative mode.
+///
+/// This system listens to `BlockUpdateEvent`s. It runs after
+/// the `BlockDropSystem`, so that a tool which breaks while
+/// breaking a block can still harvest it.
+#[derive(Default)]
+pub struct ToolDamageSystem {
+    reader: Option<ReaderId<BlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for ToolDamageSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BlockUpdateEvent>>,
+        ReadStorage<'a, PlayerComponent>,
+        WriteStorage<'a, InventoryComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        Write<'a, EventChannel<InventoryUpdateEvent>>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, players, mut inventories, networks, mut update_events, util) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let player = match event.cause {
+                BlockUpdateCause::Player(player) => player,
+                _ => continue,
+            };
+            if event.new_block != Block::Air || event.old_block == Block::Air {
+                continue;
+            }
+            match players.get(player) {
+                Some(player) if player.gamemode != Gamemode::Creative => (),
+                _ => continue,
+            }
+
+            let inventory = continue_if_none!(inventories.get_mut(player));
+            let stack = continue_if_none!(inventory.item_in_main_hand());
+            let tool = continue_if_none!(tool(stack.ty));
+            let damage = damage_for(tool, event.old_block);
+            if damage == 0 {
+                continue;
+            }
+
+            damage_held_item(
+                player,
+                inventory,
+                damage,
+                &networks,
+                &mut update_events,
+                &util,
+            );
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ToolDamageSystem::default(), TOOL_DAMAGE, &[BLOCK_DROPS]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::EffectInstance;
+    use crate::testframework as t;
+    use feather_blocks::SnowData;
+    use feather_core::world::{BlockPosition, ChunkMap};
+    use feather_core::PacketType;
+    use specs::{World, WorldExt};
+
+    fn stack(item: Item) -> ItemStack {
+        ItemStack::new(item, 1)
+    }
+
+    #[test]
+    fn test_tool() {
+        assert_eq!(
+            tool(Item::IronPickaxe),
+            Some(Tool {
+                kind: ToolKind::Pickaxe,
+                tier: Some(ToolTier::Iron),
+            })
+        );
+        assert_eq!(
+            tool(Item::GoldenShovel),
+            Some(Tool {
+                kind: ToolKind::Shovel,
+                tier: Some(ToolTier::Gold),
+            })
+        );
+        assert_eq!(tool(Item::Shears).map(|tool| tool.tier), Some(None));
+        assert_eq!(tool(Item::IronIngot), None);
+        assert_eq!(tool(Item::Stone), None);
+    }
+
+    #[test]
+    fn test_hardness() {
+        assert_eq!(hardness(Block::Stone), Some(1.5));
+        assert_eq!(hardness(Block::Obsidian), Some(50.0));
+        assert_eq!(hardness(Block::Bedrock), None);
+        assert_eq!(hardness(Block::Dandelion), Some(0.0));
+        assert_eq!(hardness(Block::WhiteWool), Some(0.8));
+        assert_eq!(hardness(Block::OakPlanks), Some(2.0));
+    }
+
+    #[test]
+    fn test_can_harvest() {
+        let stone_pickaxe = stack(Item::StonePickaxe);
+        let iron_pickaxe = stack(Item::IronPickaxe);
+        assert!(!can_harvest(Block::Stone, None));
+        assert!(can_harvest(Block::Stone, Some(&stack(Item::WoodenPickaxe))));
+        assert!(!can_harvest(Block::DiamondOre, Some(&stone_pickaxe)));
+        assert!(can_harvest(Block::DiamondOre, Some(&iron_pickaxe)));
+        assert!(!can_harvest(Block::Obsidian, Some(&iron_pickaxe)));
+        assert!(can_harvest(Block::Dirt, None));
+        assert!(!can_harvest(
+            Block::Snow(SnowData::default()),
+            Some(&stone_pickaxe)
+        ));
+        assert!(can_harvest(Block::Cobweb, Some(&stack(Item::Shears))));
+    }
+
+    #[test]
+    fn test_break_progress() {
+        // Stone takes 7.5 seconds to break by hand.
+        let progress = break_progress(Block::Stone, None, None, true, false);
+        assert!((progress - 1.0 / 150.0).abs() < 1e-6);
+
+        let pickaxe = stack(Item::WoodenPickaxe);
+        let progress = break_progress(Block::Stone, Some(&pickaxe), None, true, false);
+        assert!((progress - 2.0 / 45.0).abs() < 1e-6);
+        let flying = break_progress(Block::Stone, Some(&pickaxe), None, false, false);
+        assert!((flying - progress / 5.0).abs() < 1e-6);
+
+        let mut efficient = stack(Item::WoodenPickaxe);
+        efficient.add_enchantment("minecraft:efficiency", 2);
+        assert!((break_speed(Block::Stone, Some(&efficient)) - 7.0).abs() < 1e-6);
+        // Efficiency only applies to blocks the tool is made for.
+        assert!((break_speed(Block::Dirt, Some(&efficient)) - 1.0).abs() < 1e-6);
+
+        let mut effects = EffectsComponent::default();
+        effects.add(EffectInstance::new(StatusEffect::Haste, 1, 100));
+        let hasted = break_progress(Block::Stone, Some(&pickaxe), Some(&effects), true, false);
+        assert!((hasted - progress * 1.4).abs() < 1e-6);
+
+        assert!(break_progress(Block::Torch, None, None, false, true) >= 1.0);
+        assert!(break_progress(Block::Bedrock, Some(&pickaxe), None, true, false) <= 0.0);
+    }
+
+    #[test]
+    fn test_tool_damage_system() {
+        let (mut w, mut d) = t::builder().with(ToolDamageSystem::default(), "").build();
+        t::populate_with_air(&mut w);
+
+        let player = t::add_player(&mut w);
+        w.write_component::<PlayerComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .gamemode = Gamemode::Survival;
+        let mut pickaxe = stack(Item::WoodenPickaxe);
+        pickaxe.set_damage(58);
+        w.write_component::<InventoryComponent>()
+            .get_mut(player.entity)
+            .unwrap()
+            .set_item_at(SLOT_HOTBAR_OFFSET, pickaxe);
+
+        let pos = BlockPosition::new(0, 64, 0);
+        let break_block = |w: &World, old_block: Block| {
+            w.fetch_mut::<ChunkMap>()
+                .set_block_at(pos, Block::Air)
+                .unwrap();
+            t::trigger_event(
+                w,
+                BlockUpdateEvent {
+                    cause: BlockUpdateCause::Player(player.entity),
+                    pos,
+                    old_block,
+                    new_block: Block::Air,
+                },
+            );
+        };
+        let held = |w: &World| {
+            w.read_component::<InventoryComponent>()
+                .get(player.entity)
+                .unwrap()
+                .item_in_main_hand()
+                .cloned()
+        };
+
+        // Blocks which break instantly don't damage tools.
+        break_block(&w, Block::Torch);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(held(&w).unwrap().damage(), 58);
+
+        break_block(&w, Block::Stone);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(held(&w).unwrap().damage(), 59);
+
+        break_block(&w, Block::Stone);
+        d.dispatch(&w);
+        w.maintain();
+        assert_eq!(held(&w), None);
+        t::assert_packet_received(&player, PacketType::EntityStatus);
+    }
+}
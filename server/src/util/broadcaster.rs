@@ -6,6 +6,7 @@ use crate::entity::PositionComponent;
 use crate::network::{send_packet_boxed_to_player, NetworkComponent};
 use crate::util::Util;
 use crossbeam::queue::SegQueue;
+use feather_core::network::packet::EncodedPacket;
 use feather_core::{ChunkPosition, Packet};
 use specs::{Entities, Entity, Read, ReadStorage, System};
 
@@ -119,6 +120,14 @@ impl<'a> System<'a> for BroadcasterSystem {
                 }
             };
             if let Some(holders) = chunk_holders.holders_for(chunk) {
+                // Serialize the packet only once when sending
+                // it to several players.
+                let packet: Box<dyn Packet> = if holders.len() > 1 {
+                    Box::new(EncodedPacket::new(request.packet))
+                } else {
+                    request.packet
+                };
+
                 for holder in holders {
                     if let Some(neq) = request.neq.as_ref() {
                         if *holder == *neq {
@@ -127,7 +136,7 @@ impl<'a> System<'a> for BroadcasterSystem {
                     }
 
                     if let Some(network) = networks.get(*holder) {
-                        send_packet_boxed_to_player(network, request.packet.box_clone());
+                        send_packet_boxed_to_player(network, packet.box_clone());
                     }
                 }
             }
@@ -0,0 +1,296 @@
+//! The effective view distance.
+//!
+//! Normally, the view distance equals the configured
+//! `server.view_distance`. If `server.dynamic_view_distance`
+//! is enabled, the view distance is lowered by one chunk at a
+//! time while the server is overloaded—that is, while ticks take
+//! too long or too much memory is used—and raised again once
+//! load has dropped, up to the configured view distance.
+//!
+//! Clients on 1.13.2 render whichever chunks they are sent and
+//! have no notion of the server's view distance. When the view
+//! distance is lowered, chunks which are no longer in range are
+//! queued for unloading and later removed from clients using Unload
+//! Chunk packets. When it is raised, chunks which came into range
+//! are sent.
+
+use crate::chunk_logic::{ChunkHolderComponent, ChunkHolders, ChunkWorkerHandle};
+use crate::config::{Config, Server};
+use crate::dimension::{DimensionComponent, Dimensions};
+use crate::entity::PositionComponent;
+use crate::network::NetworkComponent;
+use crate::player::{
+    chunks_within_view_distance, send_chunk_to_player, send_dimension_chunk_to_player,
+    LoadedChunksComponent,
+};
+use crate::systems::VIEW_DISTANCE;
+use crate::timings::{DispatcherBuilderExt, TIMINGS};
+use crate::{TickCount, TPS};
+use feather_core::world::ChunkMap;
+use specs::{
+    DispatcherBuilder, Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, World,
+    Write, WriteExpect, WriteStorage,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Interval in ticks at which the view distance may be adjusted.
+const ADJUST_INTERVAL: u64 = TPS * 5;
+
+/// The view distance is only raised once load
+/// has dropped below this fraction of the thresholds.
+const RAISE_THRESHOLD: f64 = 0.5;
+
+/// The view distance currently used by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewDistance(pub u8);
+
+impl ViewDistance {
+    pub fn from_config(config: &Config) -> Self {
+        ViewDistance(config.server.view_distance)
+    }
+}
+
+/// The load on the server, as used to
+/// adjust the view distance.
+#[derive(Debug, Clone, Copy, Default)]
+struct Load {
+    /// The average time taken to run a tick.
+    tick_time: Duration,
+    /// The memory usage of the server in megabytes,
+    /// if it is known.
+    memory: Option<u64>,
+}
+
+impl Load {
+    /// Measures the current load.
+    fn measure() -> Self {
+        Self {
+            tick_time: TIMINGS.lock().tick_duration(TPS as usize),
+            memory: memory_usage(),
+        }
+    }
+
+    /// Returns the highest ratio of this load to
+    /// the configured thresholds, where a value
+    /// greater than 1 indicates overload.
+    fn ratio(self, server: &Server) -> f64 {
+        let settings = &server.dynamic_view_distance;
+
+        let tick_ratio = if settings.max_tick_time.as_nanos() == 0 {
+            0.0
+        } else {
+            self.tick_time.as_secs_f64() / settings.max_tick_time.as_secs_f64()
+        };
+
+        let memory_ratio = match self.memory {
+            Some(memory) if settings.max_memory != 0 => memory as f64 / settings.max_memory as f64,
+            _ => 0.0,
+        };
+
+        tick_ratio.max(memory_ratio)
+    }
+}
+
+/// Returns the resident memory usage of the server in megabytes.
+#[cfg(target_os = "linux")]
+fn memory_usage() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes / 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_usage() -> Option<u64> {
+    None
+}
+
+/// Returns the view distance to use given the current
+/// view distance and load.
+fn adjusted_view_distance(current: u8, server: &Server, load: Load) -> u8 {
+    let settings = &server.dynamic_view_distance;
+    let max = server.view_distance;
+    let min = settings.min_view_distance.min(max);
+
+    if !settings.enabled {
+        return max;
+    }
+
+    let current = current.min(max);
+    let ratio = load.ratio(server);
+    if ratio > 1.0 && current > min {
+        current - 1
+    } else if ratio < RAISE_THRESHOLD && current < max {
+        current + 1
+    } else {
+        current
+    }
+}
+
+pub fn init_view_distance(world: &mut World, config: &Config) {
+    world.insert(ViewDistance::from_config(config));
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(ViewDistanceSystem, VIEW_DISTANCE, &[]);
+}
+
+/// System which adjusts the view distance to the load
+/// on the server and to changes in the configuration.
+pub struct ViewDistanceSystem;
+
+impl<'a> System<'a> for ViewDistanceSystem {
+    type SystemData = (
+        WriteExpect<'a, ViewDistance>,
+        Read<'a, Arc<Config>>,
+        Read<'a, TickCount>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        WriteStorage<'a, LoadedChunksComponent>,
+        WriteStorage<'a, ChunkHolderComponent>,
+        ReadStorage<'a, DimensionComponent>,
+        Write<'a, ChunkHolders>,
+        Read<'a, ChunkMap>,
+        ReadExpect<'a, ChunkWorkerHandle>,
+        WriteExpect<'a, Dimensions>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut view_distance,
+            config,
+            tick_count,
+            positions,
+            networks,
+            mut loaded_chunks_comps,
+            mut chunk_holder_comps,
+            dimension_comps,
+            mut holders,
+            chunk_map,
+            chunk_handle,
+            mut dimensions,
+            lazy,
+            entities,
+        ) = data;
+
+        let server = &config.server;
+        let old = view_distance.0;
+
+        // Changes to the configuration are applied straight away,
+        // while load is only taken into account periodically.
+        let new = if !server.dynamic_view_distance.enabled {
+            server.view_distance
+        } else if tick_count.0 % ADJUST_INTERVAL == 0 {
+            adjusted_view_distance(old, server, Load::measure())
+        } else {
+            old.min(server.view_distance)
+        };
+
+        if new == old {
+            return;
+        }
+
+        debug!("Changing view distance from {} to {}", old, new);
+        *view_distance = ViewDistance(new);
+
+        for (position, network, loaded_chunks, chunk_holder, player) in (
+            &positions,
+            &networks,
+            &mut loaded_chunks_comps,
+            &mut chunk_holder_comps,
+            &entities,
+        )
+            .join()
+        {
+            let center = position.current.chunk_pos();
+            let chunks = chunks_within_view_distance(*view_distance, center);
+
+            if new < old {
+                loaded_chunks.queue_unload_outside(&chunks, tick_count.0);
+                continue;
+            }
+
+            // Send chunks which came into range, closest first.
+            let mut chunks: Vec<_> = chunks
+                .into_iter()
+                .filter(|chunk| !loaded_chunks.is_loaded(*chunk))
+                .collect();
+            chunks.sort_unstable_by_key(|chunk| chunk.manhattan_distance(center));
+
+            let dimension = DimensionComponent::of(dimension_comps.get(player));
+            for chunk in chunks {
+                match dimensions.entry_mut(dimension) {
+                    Some((settings, world)) => send_dimension_chunk_to_player(
+                        chunk,
+                        network,
+                        player,
+                        world,
+                        settings,
+                        chunk_holder,
+                        loaded_chunks,
+                        &lazy,
+                    ),
+                    None => send_chunk_to_player(
+                        chunk,
+                        network,
+                        player,
+                        &chunk_map,
+                        &chunk_handle,
+                        &mut holders,
+                        chunk_holder,
+                        loaded_chunks,
+                        &lazy,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(enabled: bool) -> Server {
+        let mut server = Config::default().server;
+        server.view_distance = 8;
+        server.dynamic_view_distance.enabled = enabled;
+        server.dynamic_view_distance.min_view_distance = 4;
+        server.dynamic_view_distance.max_tick_time = Duration::from_millis(40);
+        server.dynamic_view_distance.max_memory = 1024;
+        server
+    }
+
+    fn load(tick_time: u64, memory: u64) -> Load {
+        Load {
+            tick_time: Duration::from_millis(tick_time),
+            memory: Some(memory),
+        }
+    }
+
+    #[test]
+    fn test_adjusted_view_distance() {
+        let server = server(true);
+
+        // Overloaded
+        assert_eq!(adjusted_view_distance(8, &server, load(45, 100)), 7);
+        assert_eq!(adjusted_view_distance(8, &server, load(10, 2000)), 7);
+        assert_eq!(adjusted_view_distance(4, &server, load(45, 100)), 4);
+
+        // Moderate load: unchanged
+        assert_eq!(adjusted_view_distance(6, &server, load(30, 100)), 6);
+
+        // Low load
+        assert_eq!(adjusted_view_distance(6, &server, load(10, 100)), 7);
+        assert_eq!(adjusted_view_distance(8, &server, load(10, 100)), 8);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let server = server(false);
+        assert_eq!(adjusted_view_distance(5, &server, load(45, 2000)), 8);
+    }
+}
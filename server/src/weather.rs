@@ -0,0 +1,704 @@
+//! Rain and thunderstorms.
+//!
+//! The weather follows the vanilla cycle, which is driven by
+//! the `rainTime` and `thunderTime` countdowns in the level file.
+//! When a countdown reaches zero, the corresponding weather is
+//! toggled and a new random duration is chosen. Rain and thunder
+//! fade in and out gradually; their current levels are kept in the
+//! `Weather` resource and sent to players as they change. Weather
+//! only exists in the primary dimension.
+//!
+//! During thunderstorms, each loaded chunk has a one in
+//! `LIGHTNING_CHANCE` chance each tick of being struck by
+//! lightning at the highest block of a random column. Lightning
+//! sets fire to the blocks around it and converts some entities
+//! into other types, such as pigs into zombie pigmen.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent};
+use crate::dimension::{DimensionComponent, PRIMARY_DIMENSION};
+use crate::entity::pig::PigComponent;
+use crate::entity::{
+    lightning, zombie_pigman, ChunkEntities, EntityDestroyEvent, PositionComponent,
+    VelocityComponent,
+};
+use crate::joinhandler::PlayerJoinEvent;
+use crate::network::{send_packet_to_player, NetworkComponent};
+use crate::systems::{LIGHTNING, LIGHTNING_STRIKE, WEATHER, WEATHER_SEND};
+use crate::timings::DispatcherBuilderExt;
+use feather_blocks::FireData;
+use feather_core::level::LevelData;
+use feather_core::network::packet::implementation::ChangeGameState;
+use feather_core::world::chunk::HeightmapKind;
+use feather_core::world::{chunk_relative_pos, ChunkMap};
+use feather_core::{Biome, Block, BlockExt, BlockPosition, Chunk, ChunkPosition, Position};
+use rand::Rng;
+use shrev::{EventChannel, ReaderId};
+use specs::world::{EntitiesRes, LazyBuilder};
+use specs::{
+    Builder, DispatcherBuilder, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System,
+    World, Write,
+};
+
+/// The chance, as one in this number, of a loaded
+/// chunk being struck by lightning each tick during
+/// a thunderstorm.
+pub const LIGHTNING_CHANCE: u32 = 100_000;
+
+/// The amount by which the rain and thunder
+/// levels change each tick.
+const LEVEL_STEP: f32 = 0.01;
+
+/// The number of attempts made to start a fire
+/// near a lightning strike, in addition to the
+/// block struck itself.
+const FIRE_ATTEMPTS: usize = 4;
+
+/// The horizontal distance from a lightning strike
+/// within which entities are affected by it.
+const STRIKE_RADIUS: f64 = 3.0;
+
+/// `Change Game State` reasons.
+const BEGIN_RAIN: u8 = 1;
+const END_RAIN: u8 = 2;
+const RAIN_LEVEL: u8 = 7;
+const THUNDER_LEVEL: u8 = 8;
+
+/// The current rain and thunder levels, each
+/// between 0 (none) and 1 (full strength).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Weather {
+    pub rain_level: f32,
+    pub thunder_level: f32,
+}
+
+impl Weather {
+    /// Returns the weather at full strength
+    /// as specified by the level file.
+    pub fn from_level(level: &LevelData) -> Self {
+        Self {
+            rain_level: if level.raining { 1.0 } else { 0.0 },
+            thunder_level: if level.thundering { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Returns whether it is raining.
+    pub fn is_raining(self) -> bool {
+        self.rain_level > 0.2
+    }
+
+    /// Returns whether there is a thunderstorm.
+    pub fn is_thundering(self) -> bool {
+        self.rain_level * self.thunder_level > 0.9
+    }
+}
+
+/// Event triggered when lightning strikes.
+#[derive(Debug, Clone)]
+pub struct LightningStrikeEvent {
+    /// The lightning bolt entity.
+    pub bolt: Entity,
+    /// The position of the bolt, which is
+    /// the block above the block struck.
+    pub pos: BlockPosition,
+}
+
+/// Initializes the weather for the world, given the level file.
+pub fn init_weather(world: &mut World, level: &LevelData) {
+    world.insert(Weather::from_level(level));
+}
+
+/// Clears the weather immediately, for example
+/// when the night is skipped. Returns whether it
+/// was raining, in which case players must be sent
+/// the new weather.
+pub fn clear_weather(level: &mut LevelData, weather: &mut Weather) -> bool {
+    let was_raining = weather.is_raining();
+
+    level.raining = false;
+    level.rain_time = 0;
+    level.thundering = false;
+    level.thunder_time = 0;
+    *weather = Weather::default();
+
+    was_raining
+}
+
+/// Sends the current weather to a player.
+pub fn send_weather(network: &NetworkComponent, weather: Weather) {
+    let reason = if weather.is_raining() {
+        BEGIN_RAIN
+    } else {
+        END_RAIN
+    };
+    send_packet_to_player(network, ChangeGameState::new(reason, 0.0));
+    send_packet_to_player(
+        network,
+        ChangeGameState::new(RAIN_LEVEL, weather.rain_level),
+    );
+    send_packet_to_player(
+        network,
+        ChangeGameState::new(THUNDER_LEVEL, weather.thunder_level),
+    );
+}
+
+/// Advances the weather cycle of the level by one tick.
+fn update_cycle(level: &mut LevelData, rng: &mut impl Rng) {
+    if level.clear_weather_time > 0 {
+        // Set by `/weather clear`.
+        level.clear_weather_time -= 1;
+        level.thunder_time = if level.thundering { 0 } else { 1 };
+        level.rain_time = if level.raining { 0 } else { 1 };
+        level.thundering = false;
+        level.raining = false;
+        return;
+    }
+
+    if level.thunder_time > 0 {
+        level.thunder_time -= 1;
+        if level.thunder_time == 0 {
+            level.thundering = !level.thundering;
+        }
+    } else if level.thundering {
+        level.thunder_time = rng.gen_range(3600, 15_600);
+    } else {
+        level.thunder_time = rng.gen_range(12_000, 180_000);
+    }
+
+    if level.rain_time > 0 {
+        level.rain_time -= 1;
+        if level.rain_time == 0 {
+            level.raining = !level.raining;
+        }
+    } else if level.raining {
+        level.rain_time = rng.gen_range(12_000, 24_000);
+    } else {
+        level.rain_time = rng.gen_range(12_000, 180_000);
+    }
+}
+
+/// Moves a weather level one step towards
+/// the given state. Returns whether it changed.
+fn step_level(value: &mut f32, active: bool) -> bool {
+    let target = if active { 1.0 } else { 0.0 };
+    let new = if active {
+        (*value + LEVEL_STEP).min(target)
+    } else {
+        (*value - LEVEL_STEP).max(target)
+    };
+
+    let changed = (new - *value).abs() > std::f32::EPSILON;
+    *value = new;
+    changed
+}
+
+/// Returns whether rain, and therefore
+/// lightning, falls in the given biome.
+fn has_rain(biome: Biome) -> bool {
+    match biome {
+        // Dry biomes
+        Biome::Desert
+        | Biome::DesertHills
+        | Biome::DesertLakes
+        | Biome::Savanna
+        | Biome::SavannaPlateau
+        | Biome::ShatteredSavanna
+        | Biome::ShatteredSavannaPlateau
+        | Biome::Badlands
+        | Biome::BadlandsPlateau
+        | Biome::ErodedBadlands
+        | Biome::ModifiedBadlandsPlateau
+        | Biome::ModifiedWoodedBadlandsPlateau
+        | Biome::WoodedBadlandsPlateau
+        | Biome::Nether
+        | Biome::TheEnd
+        | Biome::SmallEndIslands
+        | Biome::EndBarrens
+        | Biome::EndHighlands
+        | Biome::EndMidlands
+        | Biome::TheVoid => false,
+        // Snowy biomes
+        Biome::FrozenOcean
+        | Biome::DeepFrozenOcean
+        | Biome::FrozenRiver
+        | Biome::IceSpikes
+        | Biome::SnowyBeach
+        | Biome::SnowyMountains
+        | Biome::SnowyTaiga
+        | Biome::SnowyTaigaHills
+        | Biome::SnowyTaigaMountains
+        | Biome::SnowyTundra => false,
+        _ => true,
+    }
+}
+
+/// Returns whether rain falls on the given block: it must be
+/// raining, the biome must have rain and no block which blocks
+/// motion may be above. Returns `false` if the chunk isn't loaded.
+pub fn is_raining_at(weather: Weather, chunk_map: &ChunkMap, pos: BlockPosition) -> bool {
+    if !weather.is_raining() {
+        return false;
+    }
+
+    let chunk = match chunk_map.chunk_at(pos.chunk_pos()) {
+        Some(chunk) => chunk,
+        None => return false,
+    };
+    let (x, _, z) = chunk_relative_pos(pos);
+    has_rain(chunk.biome_at(x, z))
+        && pos.y >= chunk.height_at(HeightmapKind::MotionBlocking, x, z) as i32
+}
+
+/// Returns the position at which lightning striking the
+/// given column of a chunk lands: the block above the
+/// highest non-air block. Returns `None` if lightning
+/// cannot strike the column.
+fn strike_position(chunk: &Chunk, x: usize, z: usize) -> Option<BlockPosition> {
+    if !has_rain(chunk.biome_at(x, z)) {
+        return None;
+    }
+
+    let y = (0..255)
+        .rev()
+        .find(|&y| chunk.block_at(x, y, z) != Block::Air)
+        .map_or(0, |y| y + 1);
+
+    let chunk_pos = chunk.position();
+    Some(BlockPosition::new(
+        chunk_pos.x * 16 + x as i32,
+        y as i32,
+        chunk_pos.z * 16 + z as i32,
+    ))
+}
+
+/// Returns whether an entity at the given
+/// position is affected by a lightning strike.
+fn in_strike_range(strike: BlockPosition, pos: Position) -> bool {
+    let strike = strike.world_pos();
+    (pos.x - strike.x).abs() <= STRIKE_RADIUS
+        && (pos.z - strike.z).abs() <= STRIKE_RADIUS
+        && (strike.y - STRIKE_RADIUS..=strike.y + 6.0 + STRIKE_RADIUS).contains(&pos.y)
+}
+
+type EntityCreator = for<'a> fn(&'a LazyUpdate, &'a EntitiesRes) -> LazyBuilder<'a>;
+
+/// Returns the function creating the entity which an entity
+/// is converted into when struck by lightning, if any.
+fn struck_conversion(entity: Entity, pigs: &ReadStorage<PigComponent>) -> Option<EntityCreator> {
+    if pigs.get(entity).is_some() {
+        Some(zombie_pigman::create)
+    } else {
+        None
+    }
+}
+
+/// System which advances the weather cycle and
+/// sends changes in weather to players.
+pub struct WeatherSystem;
+
+impl<'a> System<'a> for WeatherSystem {
+    type SystemData = (
+        Write<'a, LevelData>,
+        Write<'a, Weather>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, DimensionComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut level, mut weather, networks, dimensions) = data;
+
+        update_cycle(&mut level, &mut rand::thread_rng());
+
+        let was_raining = weather.is_raining();
+        let rain_changed = step_level(&mut weather.rain_level, level.raining);
+        let thunder_changed = step_level(&mut weather.thunder_level, level.thundering);
+
+        if !rain_changed && !thunder_changed {
+            return;
+        }
+
+        let began = !was_raining && weather.is_raining();
+        let ended = was_raining && !weather.is_raining();
+        if began {
+            debug!("Rain has started");
+        } else if ended {
+            debug!("Rain has stopped");
+        }
+
+        for (network, dimension) in (&networks, dimensions.maybe()).join() {
+            if DimensionComponent::of(dimension) != PRIMARY_DIMENSION {
+                continue;
+            }
+
+            if began {
+                send_packet_to_player(network, ChangeGameState::new(BEGIN_RAIN, 0.0));
+            } else if ended {
+                send_packet_to_player(network, ChangeGameState::new(END_RAIN, 0.0));
+            }
+            if rain_changed {
+                send_packet_to_player(
+                    network,
+                    ChangeGameState::new(RAIN_LEVEL, weather.rain_level),
+                );
+            }
+            if thunder_changed {
+                send_packet_to_player(
+                    network,
+                    ChangeGameState::new(THUNDER_LEVEL, weather.thunder_level),
+                );
+            }
+        }
+    }
+}
+
+/// System which sends the weather to players when they join.
+///
+/// This system listens to `PlayerJoinEvent`s.
+#[derive(Default)]
+pub struct WeatherSendSystem {
+    reader: Option<ReaderId<PlayerJoinEvent>>,
+}
+
+impl<'a> System<'a> for WeatherSendSystem {
+    type SystemData = (
+        ReadStorage<'a, NetworkComponent>,
+        Read<'a, EventChannel<PlayerJoinEvent>>,
+        Read<'a, Weather>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (networks, join_events, weather) = data;
+
+        for event in join_events.read(self.reader.as_mut().unwrap()) {
+            if !weather.is_raining() {
+                continue;
+            }
+            if let Some(network) = networks.get(event.player) {
+                send_weather(network, *weather);
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which rolls for lightning strikes in
+/// each loaded chunk during thunderstorms,
+/// spawning lightning bolts.
+pub struct LightningSystem;
+
+impl<'a> System<'a> for LightningSystem {
+    type SystemData = (
+        Read<'a, Weather>,
+        Read<'a, ChunkMap>,
+        Write<'a, EventChannel<LightningStrikeEvent>>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (weather, chunk_map, mut strike_events, lazy, entities) = data;
+
+        if !weather.is_thundering() {
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        for chunk in chunk_map.chunks().values() {
+            if rng.gen_range(0, LIGHTNING_CHANCE) != 0 {
+                continue;
+            }
+
+            let pos = match strike_position(chunk, rng.gen_range(0, 16), rng.gen_range(0, 16)) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            let position = pos.world_pos();
+            let bolt = lightning::create(&lazy, &entities)
+                .with(PositionComponent {
+                    current: position,
+                    previous: position,
+                })
+                .build();
+
+            trace!("Lightning struck at {:?}", pos);
+            strike_events.single_write(LightningStrikeEvent { bolt, pos });
+        }
+    }
+}
+
+/// System which starts fires and converts entities
+/// around lightning strikes.
+///
+/// This system listens to `LightningStrikeEvent`s.
+#[derive(Default)]
+pub struct LightningStrikeSystem {
+    reader: Option<ReaderId<LightningStrikeEvent>>,
+}
+
+impl<'a> System<'a> for LightningStrikeSystem {
+    type SystemData = (
+        Read<'a, EventChannel<LightningStrikeEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, EventChannel<EntityDestroyEvent>>,
+        Read<'a, ChunkEntities>,
+        ReadStorage<'a, PositionComponent>,
+        ReadStorage<'a, VelocityComponent>,
+        ReadStorage<'a, PigComponent>,
+        Read<'a, LazyUpdate>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            strike_events,
+            mut chunk_map,
+            mut block_events,
+            mut destroy_events,
+            chunk_entities,
+            positions,
+            velocities,
+            pigs,
+            lazy,
+            entities,
+        ) = data;
+
+        let mut rng = rand::thread_rng();
+        for event in strike_events.read(self.reader.as_mut().unwrap()) {
+            ignite(&mut chunk_map, event.pos, &mut block_events);
+            for _ in 0..FIRE_ATTEMPTS {
+                let offset = BlockPosition::new(
+                    rng.gen_range(-1, 2),
+                    rng.gen_range(-1, 2),
+                    rng.gen_range(-1, 2),
+                );
+                ignite(&mut chunk_map, event.pos + offset, &mut block_events);
+            }
+
+            let center = event.pos.chunk_pos();
+            for x in center.x - 1..=center.x + 1 {
+                for z in center.z - 1..=center.z + 1 {
+                    let chunk = ChunkPosition::new(x, z);
+                    for &entity in chunk_entities.entities_in_chunk(chunk) {
+                        let position = match positions.get(entity) {
+                            Some(position) if in_strike_range(event.pos, position.current) => {
+                                *position
+                            }
+                            _ => continue,
+                        };
+                        let create = match struck_conversion(entity, &pigs) {
+                            Some(create) => create,
+                            None => continue,
+                        };
+
+                        destroy_events.single_write(EntityDestroyEvent { entity });
+                        create(&lazy, &entities)
+                            .with(position)
+                            .with(velocities.get(entity).copied().unwrap_or_default())
+                            .build();
+                    }
+                }
+            }
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// Sets fire to the given block if it is air
+/// and the block below can support fire.
+fn ignite(
+    chunk_map: &mut ChunkMap,
+    pos: BlockPosition,
+    block_events: &mut EventChannel<BlockUpdateEvent>,
+) {
+    let below = BlockPosition::new(pos.x, pos.y - 1, pos.z);
+    match (chunk_map.block_at(pos), chunk_map.block_at(below)) {
+        (Some(Block::Air), Some(below)) if below.is_solid() => (),
+        _ => return,
+    }
+
+    let fire = Block::Fire(FireData::default());
+    if chunk_map.set_block_at(pos, fire).is_ok() {
+        block_events.single_write(BlockUpdateEvent {
+            cause: BlockUpdateCause::Lightning,
+            pos,
+            old_block: Block::Air,
+            new_block: fire,
+        });
+    }
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(WeatherSystem, WEATHER, &[]);
+    dispatcher.add_timed(LightningSystem, LIGHTNING, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(WeatherSendSystem::default(), WEATHER_SEND, &[]);
+    dispatcher.add_timed(LightningStrikeSystem::default(), LIGHTNING_STRIKE, &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::pig;
+    use crate::entity::zombie_pigman::ZombiePigmanComponent;
+    use crate::testframework as t;
+    use feather_core::network::cast_packet;
+    use feather_core::PacketType;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use specs::WorldExt;
+
+    #[test]
+    fn test_update_cycle() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let mut level = LevelData::default();
+
+        level.rain_time = 1;
+        update_cycle(&mut level, &mut rng);
+        assert!(level.raining);
+        assert!(!level.thundering);
+        assert!((12_000..180_000).contains(&level.thunder_time));
+
+        update_cycle(&mut level, &mut rng);
+        assert!((12_000..24_000).contains(&level.rain_time));
+
+        level.clear_weather_time = 10;
+        update_cycle(&mut level, &mut rng);
+        assert!(!level.raining);
+        assert_eq!(level.clear_weather_time, 9);
+    }
+
+    #[test]
+    fn test_weather_levels() {
+        let mut weather = Weather::default();
+        assert!(!weather.is_raining());
+
+        for _ in 0..110 {
+            step_level(&mut weather.rain_level, true);
+            step_level(&mut weather.thunder_level, true);
+        }
+        assert!(weather.is_raining());
+        assert!(weather.is_thundering());
+        assert!(!step_level(&mut weather.rain_level, true));
+        assert!(step_level(&mut weather.rain_level, false));
+    }
+
+    #[test]
+    fn test_strike_position() {
+        let mut chunk = Chunk::new(ChunkPosition::new(1, -1));
+        assert_eq!(
+            strike_position(&chunk, 0, 0),
+            Some(BlockPosition::new(16, 0, -16))
+        );
+
+        chunk.set_block_at(3, 64, 5, Block::Stone);
+        assert_eq!(
+            strike_position(&chunk, 3, 5),
+            Some(BlockPosition::new(19, 65, -11))
+        );
+
+        chunk.set_biome_at(3, 5, Biome::Desert);
+        assert_eq!(strike_position(&chunk, 3, 5), None);
+    }
+
+    #[test]
+    fn test_is_raining_at() {
+        let (mut w, _) = t::init_world();
+        t::populate_with_air(&mut w);
+        t::set_block(0, 64, 0, Block::Stone, &w);
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        let rain = Weather {
+            rain_level: 1.0,
+            thunder_level: 0.0,
+        };
+        assert!(is_raining_at(
+            rain,
+            &chunk_map,
+            BlockPosition::new(0, 65, 0)
+        ));
+        assert!(!is_raining_at(
+            rain,
+            &chunk_map,
+            BlockPosition::new(0, 63, 0)
+        ));
+        assert!(!is_raining_at(
+            Weather::default(),
+            &chunk_map,
+            BlockPosition::new(0, 65, 0)
+        ));
+    }
+
+    #[test]
+    fn test_weather_system() {
+        let (mut w, mut d) = t::builder().with(WeatherSystem, "").build();
+        let player = t::add_player(&mut w);
+
+        w.fetch_mut::<LevelData>().raining = true;
+        w.fetch_mut::<LevelData>().rain_time = 1000;
+        w.fetch_mut::<Weather>().rain_level = 0.2;
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert!(w.fetch::<Weather>().is_raining());
+        let packet = t::assert_packet_received(&player, PacketType::ChangeGameState);
+        let packet = cast_packet::<ChangeGameState>(&*packet);
+        assert_eq!(packet.reason, BEGIN_RAIN);
+    }
+
+    #[test]
+    fn test_lightning_strike() {
+        let (mut w, mut d) = t::builder()
+            .with(LightningStrikeSystem::default(), "")
+            .build();
+        t::populate_with_air(&mut w);
+        t::set_block(0, 63, 0, Block::Stone, &w);
+
+        w.register::<ZombiePigmanComponent>();
+
+        let pig = {
+            let lazy = w.fetch::<LazyUpdate>();
+            let entities = w.entities();
+            pig::create(&lazy, &entities)
+                .with(PositionComponent {
+                    current: position!(1.0, 64.0, 1.0),
+                    previous: position!(1.0, 64.0, 1.0),
+                })
+                .build()
+        };
+        w.maintain();
+        w.fetch_mut::<ChunkEntities>()
+            .add_to_chunk(ChunkPosition::new(0, 0), pig);
+
+        let mut destroy_reader = t::reader::<EntityDestroyEvent>(&w);
+        let bolt = w.create_entity().build();
+        t::trigger_event(
+            &w,
+            LightningStrikeEvent {
+                bolt,
+                pos: BlockPosition::new(0, 64, 0),
+            },
+        );
+
+        d.dispatch(&w);
+        w.maintain();
+
+        assert_eq!(
+            w.fetch::<ChunkMap>().block_at(BlockPosition::new(0, 64, 0)),
+            Some(Block::Fire(FireData::default()))
+        );
+
+        let destroyed = t::triggered_events::<EntityDestroyEvent>(&w, &mut destroy_reader);
+        assert_eq!(destroyed.len(), 1);
+        assert_eq!(destroyed[0].entity, pig);
+        assert_eq!(
+            w.read_component::<ZombiePigmanComponent>().join().count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,1420 @@
+//! Bulk editing of regions of blocks.
+//!
+//! `WorldEdit` fills, replaces, copies and pastes regions of
+//! blocks directly in the chunk map, recording each edit so that
+//! it can be undone. Edits are made on behalf of an `Editor`, such
+//! as a player or a plugin, each of which has its own clipboard and
+//! undo history.
+//!
+//! Triggering a `BlockUpdateEvent` for every block of a large region
+//! would be slow, since each event is relit and sent to clients on its
+//! own. Instead, changes are batched per chunk and flushed at the start
+//! of the next tick: chunks with few changes receive a `BlockUpdateEvent`
+//! for each of them as usual, while chunks with many changes receive a
+//! single `BulkBlockUpdateEvent`, after which the chunk is relit as a
+//! whole and resent to clients.
+//!
+//! Operators can edit the world using the `//pos1`, `//pos2`, `//set`,
+//! `//replace`, `//copy`, `//paste` and `//undo` commands. Plugins use
+//! the corresponding host functions.
+//!
+//! Clipboards are `Schematic`s, so they can be rotated and mirrored
+//! using `//rotate` and `//flip`, and schematic files in the
+//! `schematics` directory can be loaded into a clipboard using
+//! `//schem load` and pasted as usual. `//schem save` saves the
+//! selected region to a file.
+
+use crate::blocks::{BlockUpdateCause, BlockUpdateEvent, BulkBlockUpdateEvent};
+use crate::commands::{
+    is_privileged, no_permission, reply, usage, CommandEvent, CommandRegistry, ConsoleComponent,
+};
+use crate::config::Config;
+use crate::entity::{NamedComponent, PositionComponent};
+use crate::lang::{Locale, Message};
+use crate::network::NetworkComponent;
+use crate::player::PlayerDisconnectEvent;
+use crate::systems::{BULK_UPDATE_BROADCAST, WORLDEDIT_COMMANDS, WORLDEDIT_FLUSH};
+use crate::timings::DispatcherBuilderExt;
+use crate::util::Util;
+use feather_blocks::Block;
+use feather_core::network::packet::implementation::ChunkData;
+use feather_core::schematic::{Error as SchematicError, Format, Mirror, Rotation, Schematic};
+use feather_core::world::{BlockPosition, ChunkMap, ChunkPosition};
+use hashbrown::HashMap;
+use shrev::{EventChannel, ReaderId};
+use specs::{DispatcherBuilder, Entity, Read, ReadStorage, System, World, Write};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The maximum number of blocks in a region
+/// which can be edited at once.
+pub const MAX_VOLUME: usize = 1 << 21;
+/// The number of edits kept in each editor's history.
+pub const HISTORY_SIZE: usize = 16;
+/// The number of changes in a chunk above which the
+/// chunk is relit and resent as a whole.
+const BULK_THRESHOLD: usize = 64;
+/// The directory containing schematic files.
+pub const SCHEMATIC_DIR: &str = "schematics";
+
+/// The commands handled by `WorldEditCommandSystem`,
+/// without their leading slash.
+const COMMANDS: [&str; 10] = [
+    "/pos1", "/pos2", "/set", "/replace", "/copy", "/paste", "/undo", "/rotate", "/flip", "/schem",
+];
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum WorldEditError {
+    #[fail(
+        display = "The region contains {} blocks, more than the maximum of {}.",
+        _0, _1
+    )]
+    TooLarge(usize, usize),
+    #[fail(display = "The clipboard is empty.")]
+    EmptyClipboard,
+    #[fail(display = "There is nothing to undo.")]
+    NothingToUndo,
+}
+
+impl From<WorldEditError> for Message {
+    fn from(error: WorldEditError) -> Self {
+        match error {
+            WorldEditError::TooLarge(volume, max) => {
+                Message::translate("feather.worldedit.too_large")
+                    .with(volume)
+                    .with(max)
+            }
+            WorldEditError::EmptyClipboard => {
+                Message::translate("feather.worldedit.empty_clipboard")
+            }
+            WorldEditError::NothingToUndo => {
+                Message::translate("feather.worldedit.nothing_to_undo")
+            }
+        }
+    }
+}
+
+/// A box of blocks, including both corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    min: BlockPosition,
+    max: BlockPosition,
+}
+
+impl Region {
+    /// Returns the region between two opposite corners, given
+    /// in any order. The region is limited to the height of
+    /// the world.
+    pub fn new(a: BlockPosition, b: BlockPosition) -> Self {
+        Self {
+            min: BlockPosition::new(a.x.min(b.x), a.y.min(b.y).max(0), a.z.min(b.z)),
+            max: BlockPosition::new(a.x.max(b.x), a.y.max(b.y).min(255), a.z.max(b.z)),
+        }
+    }
+
+    pub fn min(&self) -> BlockPosition {
+        self.min
+    }
+
+    pub fn max(&self) -> BlockPosition {
+        self.max
+    }
+
+    /// Returns the size of the region along each axis.
+    pub fn size(&self) -> (i32, i32, i32) {
+        (
+            self.max.x - self.min.x + 1,
+            (self.max.y - self.min.y + 1).max(0),
+            self.max.z - self.min.z + 1,
+        )
+    }
+
+    /// Returns the number of blocks in the region.
+    pub fn volume(&self) -> usize {
+        let (x, y, z) = self.size();
+        x as usize * y as usize * z as usize
+    }
+
+    pub fn contains(&self, pos: BlockPosition) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    /// Returns the chunks which overlap the region.
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPosition> {
+        let (min, max) = (self.min.chunk_pos(), self.max.chunk_pos());
+        (min.x..=max.x).flat_map(move |x| (min.z..=max.z).map(move |z| ChunkPosition::new(x, z)))
+    }
+
+    fn check_volume(&self) -> Result<(), WorldEditError> {
+        if self.volume() > MAX_VOLUME {
+            Err(WorldEditError::TooLarge(self.volume(), MAX_VOLUME))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The owner of a clipboard and undo history.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Editor {
+    /// A player or the console.
+    Entity(Entity),
+    /// A plugin, identified by its name.
+    Plugin(String),
+}
+
+/// An edit which can be requested by
+/// plugins and other deferred sources.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Fill {
+        region: Region,
+        block: Block,
+    },
+    Replace {
+        region: Region,
+        from: Block,
+        to: Block,
+    },
+    Copy {
+        region: Region,
+        origin: BlockPosition,
+    },
+    Paste {
+        origin: BlockPosition,
+    },
+    Undo,
+}
+
+/// Changes to a chunk which have not yet been flushed.
+#[derive(Debug)]
+enum PendingChunk {
+    /// Positions and the old and new blocks.
+    Changes(Vec<(BlockPosition, Block, Block)>),
+    /// Too many blocks changed to handle them individually.
+    Bulk,
+}
+
+/// The positions and previous blocks of the
+/// blocks changed by one edit.
+type Changes = Vec<(BlockPosition, Block)>;
+
+/// The corners selected by a player using `//pos1` and `//pos2`.
+#[derive(Debug, Clone, Copy, Default)]
+struct Selection {
+    pos1: Option<BlockPosition>,
+    pos2: Option<BlockPosition>,
+}
+
+/// Resource for bulk editing of blocks.
+#[derive(Default)]
+pub struct WorldEdit {
+    histories: HashMap<Editor, VecDeque<Changes>>,
+    clipboards: HashMap<Editor, Schematic>,
+    selections: HashMap<Entity, Selection>,
+    pending: HashMap<ChunkPosition, PendingChunk>,
+}
+
+impl WorldEdit {
+    /// Performs an operation, returning the number of blocks
+    /// changed or, for `Operation::Copy`, copied.
+    pub fn apply(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        operation: Operation,
+    ) -> Result<usize, WorldEditError> {
+        match operation {
+            Operation::Fill { region, block } => self.fill(chunk_map, editor, region, block),
+            Operation::Replace { region, from, to } => {
+                self.replace(chunk_map, editor, region, from, to)
+            }
+            Operation::Copy { region, origin } => self.copy(chunk_map, editor, region, origin),
+            Operation::Paste { origin } => self.paste(chunk_map, editor, origin),
+            Operation::Undo => self.undo(chunk_map, editor),
+        }
+    }
+
+    /// Sets every block in the region to `block`, returning
+    /// the number of blocks changed. Blocks in unloaded
+    /// chunks are skipped.
+    pub fn fill(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        region: Region,
+        block: Block,
+    ) -> Result<usize, WorldEditError> {
+        region.check_volume()?;
+        let changes = edit(chunk_map, &mut self.pending, region, |_, _| Some(block));
+        Ok(self.record(editor, changes))
+    }
+
+    /// Replaces every `from` block in the region with `to`,
+    /// returning the number of blocks changed.
+    pub fn replace(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        region: Region,
+        from: Block,
+        to: Block,
+    ) -> Result<usize, WorldEditError> {
+        region.check_volume()?;
+        let changes = edit(chunk_map, &mut self.pending, region, |_, old| {
+            if old == from {
+                Some(to)
+            } else {
+                None
+            }
+        });
+        Ok(self.record(editor, changes))
+    }
+
+    /// Copies the blocks in the region to the editor's clipboard,
+    /// replacing its contents. `origin` is the position which
+    /// will be placed at the target of `paste`. Blocks in
+    /// unloaded chunks are copied as air.
+    pub fn copy(
+        &mut self,
+        chunk_map: &ChunkMap,
+        editor: &Editor,
+        region: Region,
+        origin: BlockPosition,
+    ) -> Result<usize, WorldEditError> {
+        let clipboard = copy_region(chunk_map, region, origin)?;
+        self.clipboards.insert(editor.clone(), clipboard);
+
+        Ok(region.volume())
+    }
+
+    /// Pastes the editor's clipboard at `origin`, returning
+    /// the number of blocks changed. Structure voids in
+    /// the clipboard leave the existing blocks in place.
+    pub fn paste(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        origin: BlockPosition,
+    ) -> Result<usize, WorldEditError> {
+        let clipboard = self
+            .clipboards
+            .get(editor)
+            .ok_or(WorldEditError::EmptyClipboard)?;
+
+        let changes = place_schematic(chunk_map, &mut self.pending, clipboard, origin)?;
+        Ok(self.record(editor, changes))
+    }
+
+    /// Places a schematic at `origin` as if it were pasted
+    /// from the editor's clipboard, returning the number of
+    /// blocks changed. The edit can be undone as usual.
+    pub fn place(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        schematic: &Schematic,
+        origin: BlockPosition,
+    ) -> Result<usize, WorldEditError> {
+        let changes = place_schematic(chunk_map, &mut self.pending, schematic, origin)?;
+        Ok(self.record(editor, changes))
+    }
+
+    /// Sets each of the given blocks, returning the number
+    /// of blocks changed. Unlike the other edits, the blocks
+    /// don't have to form a region, which makes this suitable
+    /// for restoring scattered blocks. The edit can be undone
+    /// as usual.
+    pub fn set_blocks(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+        blocks: impl IntoIterator<Item = (BlockPosition, Block)>,
+    ) -> usize {
+        let mut changes = vec![];
+        for (pos, block) in blocks {
+            let old = continue_if_none!(chunk_map.block_at(pos));
+            if old != block && chunk_map.set_block_at(pos, block).is_ok() {
+                changes.push((pos, old));
+                mark_changed(&mut self.pending, pos, old, block);
+            }
+        }
+        self.record(editor, changes)
+    }
+
+    /// Returns the region selected by a player
+    /// using `//pos1` and `//pos2`, if any.
+    pub fn selection(&self, player: Entity) -> Option<Region> {
+        let selection = self.selections.get(&player)?;
+        Some(Region::new(selection.pos1?, selection.pos2?))
+    }
+
+    /// Returns the editor's clipboard.
+    pub fn clipboard(&self, editor: &Editor) -> Option<&Schematic> {
+        self.clipboards.get(editor)
+    }
+
+    /// Replaces the editor's clipboard, e.g.
+    /// with a schematic loaded from a file.
+    pub fn set_clipboard(&mut self, editor: &Editor, clipboard: Schematic) {
+        self.clipboards.insert(editor.clone(), clipboard);
+    }
+
+    /// Rotates the editor's clipboard around the
+    /// position it was copied from.
+    pub fn rotate(&mut self, editor: &Editor, rotation: Rotation) -> Result<(), WorldEditError> {
+        let clipboard = self
+            .clipboards
+            .get_mut(editor)
+            .ok_or(WorldEditError::EmptyClipboard)?;
+        *clipboard = clipboard.rotated(rotation);
+        Ok(())
+    }
+
+    /// Mirrors the editor's clipboard around the
+    /// position it was copied from.
+    pub fn flip(&mut self, editor: &Editor, mirror: Mirror) -> Result<(), WorldEditError> {
+        let clipboard = self
+            .clipboards
+            .get_mut(editor)
+            .ok_or(WorldEditError::EmptyClipboard)?;
+        *clipboard = clipboard.mirrored(mirror);
+        Ok(())
+    }
+
+    /// Reverts the editor's most recent edit, returning
+    /// the number of blocks restored.
+    pub fn undo(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        editor: &Editor,
+    ) -> Result<usize, WorldEditError> {
+        let changes = self
+            .histories
+            .get_mut(editor)
+            .and_then(VecDeque::pop_back)
+            .ok_or(WorldEditError::NothingToUndo)?;
+
+        let mut restored = 0;
+        for (pos, old) in changes {
+            let current = continue_if_none!(chunk_map.block_at(pos));
+            if chunk_map.set_block_at(pos, old).is_ok() {
+                mark_changed(&mut self.pending, pos, current, old);
+                restored += 1;
+            }
+        }
+
+        Ok(restored)
+    }
+
+    /// Removes the clipboard, history and
+    /// selection of an editor.
+    pub fn forget(&mut self, editor: &Editor) {
+        self.histories.remove(editor);
+        self.clipboards.remove(editor);
+        if let Editor::Entity(entity) = editor {
+            self.selections.remove(entity);
+        }
+    }
+
+    /// Adds an edit to the editor's history,
+    /// returning the number of changes.
+    fn record(&mut self, editor: &Editor, changes: Changes) -> usize {
+        let count = changes.len();
+        if count > 0 {
+            let history = self.histories.entry(editor.clone()).or_default();
+            history.push_back(changes);
+            if history.len() > HISTORY_SIZE {
+                history.pop_front();
+            }
+        }
+        count
+    }
+}
+
+/// Copies the blocks in the region to a schematic. `origin`
+/// is the position which will be placed at the target when
+/// pasting. Blocks in unloaded chunks are copied as air.
+pub fn copy_region(
+    chunk_map: &ChunkMap,
+    region: Region,
+    origin: BlockPosition,
+) -> Result<Schematic, WorldEditError> {
+    region.check_volume()?;
+
+    let (size_x, size_y, size_z) = region.size();
+    let min = region.min;
+    let mut blocks = Vec::with_capacity(region.volume());
+    for y in 0..size_y {
+        for z in 0..size_z {
+            for x in 0..size_x {
+                let pos = BlockPosition::new(min.x + x, min.y + y, min.z + z);
+                blocks.push(chunk_map.block_at(pos).unwrap_or(Block::Air));
+            }
+        }
+    }
+
+    let offset = BlockPosition::new(min.x - origin.x, min.y - origin.y, min.z - origin.z);
+    Ok(Schematic::new(region.size(), offset, blocks))
+}
+
+/// Places a schematic with its origin at `origin`,
+/// returning the changes.
+fn place_schematic(
+    chunk_map: &mut ChunkMap,
+    pending: &mut HashMap<ChunkPosition, PendingChunk>,
+    schematic: &Schematic,
+    origin: BlockPosition,
+) -> Result<Changes, WorldEditError> {
+    if schematic.volume() == 0 {
+        return Ok(vec![]);
+    }
+
+    let min = origin + schematic.offset();
+    let (size_x, size_y, size_z) = schematic.size();
+    let max = BlockPosition::new(min.x + size_x - 1, min.y + size_y - 1, min.z + size_z - 1);
+    let region = Region::new(min, max);
+    region.check_volume()?;
+
+    let changes = edit(chunk_map, pending, region, |pos, _| {
+        match schematic.block_at(pos.x - min.x, pos.y - min.y, pos.z - min.z) {
+            Block::StructureVoid => None,
+            block => Some(block),
+        }
+    });
+    Ok(changes)
+}
+
+/// Sets each block in the region for which `f` returns a
+/// different block, returning the changes. Each chunk is only
+/// looked up once.
+fn edit<F>(
+    chunk_map: &mut ChunkMap,
+    pending: &mut HashMap<ChunkPosition, PendingChunk>,
+    region: Region,
+    mut f: F,
+) -> Changes
+where
+    F: FnMut(BlockPosition, Block) -> Option<Block>,
+{
+    let mut changes = vec![];
+
+    for chunk_pos in region.chunks() {
+        let chunk = continue_if_none!(chunk_map.chunk_at_mut(chunk_pos));
+        let (offset_x, offset_z) = (chunk_pos.x * 16, chunk_pos.z * 16);
+
+        for y in region.min.y..=region.max.y {
+            for z in region.min.z.max(offset_z)..=region.max.z.min(offset_z + 15) {
+                for x in region.min.x.max(offset_x)..=region.max.x.min(offset_x + 15) {
+                    let (cx, cy, cz) =
+                        ((x - offset_x) as usize, y as usize, (z - offset_z) as usize);
+                    let pos = BlockPosition::new(x, y, z);
+                    let old = chunk.block_at(cx, cy, cz);
+
+                    match f(pos, old) {
+                        Some(new) if new != old => {
+                            chunk.set_block_at(cx, cy, cz, new);
+                            changes.push((pos, old));
+                            mark_changed(pending, pos, old, new);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+fn mark_changed(
+    pending: &mut HashMap<ChunkPosition, PendingChunk>,
+    pos: BlockPosition,
+    old: Block,
+    new: Block,
+) {
+    let chunk = pending
+        .entry(pos.chunk_pos())
+        .or_insert_with(|| PendingChunk::Changes(vec![]));
+
+    let bulk = match chunk {
+        PendingChunk::Changes(changes) => {
+            changes.push((pos, old, new));
+            changes.len() > BULK_THRESHOLD
+        }
+        PendingChunk::Bulk => false,
+    };
+    if bulk {
+        *chunk = PendingChunk::Bulk;
+    }
+}
+
+/// System which flushes the changes made by `WorldEdit`,
+/// triggering `BlockUpdateEvent`s and `BulkBlockUpdateEvent`s.
+pub struct WorldEditFlushSystem;
+
+impl<'a> System<'a> for WorldEditFlushSystem {
+    type SystemData = (
+        Write<'a, WorldEdit>,
+        Write<'a, EventChannel<BlockUpdateEvent>>,
+        Write<'a, EventChannel<BulkBlockUpdateEvent>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut world_edit, mut block_events, mut bulk_events) = data;
+
+        for (chunk, pending) in world_edit.pending.drain() {
+            match pending {
+                PendingChunk::Changes(changes) => {
+                    block_events.iter_write(changes.into_iter().map(|(pos, old, new)| {
+                        BlockUpdateEvent {
+                            cause: BlockUpdateCause::WorldEdit,
+                            pos,
+                            old_block: old,
+                            new_block: new,
+                        }
+                    }));
+                }
+                PendingChunk::Bulk => bulk_events.single_write(BulkBlockUpdateEvent { chunk }),
+            }
+        }
+    }
+}
+
+/// System which resends chunks changed in bulk
+/// to the players able to see them.
+#[derive(Default)]
+pub struct BulkBlockUpdateBroadcastSystem {
+    reader: Option<ReaderId<BulkBlockUpdateEvent>>,
+}
+
+impl<'a> System<'a> for BulkBlockUpdateBroadcastSystem {
+    type SystemData = (
+        Read<'a, EventChannel<BulkBlockUpdateEvent>>,
+        Read<'a, ChunkMap>,
+        Read<'a, Util>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (events, chunk_map, util) = data;
+
+        for event in events.read(self.reader.as_mut().unwrap()) {
+            let chunk = continue_if_none!(chunk_map.chunk_at(event.chunk));
+            util.broadcast_chunk_update(event.chunk, ChunkData::new(chunk.clone(), true), None);
+        }
+    }
+
+    setup_impl!(reader);
+}
+
+/// System which implements the WorldEdit commands.
+pub struct WorldEditCommandSystem {
+    command_reader: Option<ReaderId<CommandEvent>>,
+    disconnect_reader: Option<ReaderId<PlayerDisconnectEvent>>,
+    schematic_dir: PathBuf,
+}
+
+impl Default for WorldEditCommandSystem {
+    fn default() -> Self {
+        Self {
+            command_reader: None,
+            disconnect_reader: None,
+            schematic_dir: PathBuf::from(SCHEMATIC_DIR),
+        }
+    }
+}
+
+impl<'a> System<'a> for WorldEditCommandSystem {
+    type SystemData = (
+        Read<'a, EventChannel<CommandEvent>>,
+        Read<'a, EventChannel<PlayerDisconnectEvent>>,
+        Write<'a, ChunkMap>,
+        Write<'a, WorldEdit>,
+        Read<'a, Arc<Config>>,
+        Read<'a, Locale>,
+        ReadStorage<'a, NamedComponent>,
+        ReadStorage<'a, NetworkComponent>,
+        ReadStorage<'a, ConsoleComponent>,
+        ReadStorage<'a, PositionComponent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            command_events,
+            disconnect_events,
+            mut chunk_map,
+            mut world_edit,
+            config,
+            locale,
+            nameds,
+            networks,
+            consoles,
+            positions,
+        ) = data;
+
+        for event in disconnect_events.read(self.disconnect_reader.as_mut().unwrap()) {
+            world_edit.forget(&Editor::Entity(event.player));
+        }
+
+        for event in command_events.read(self.command_reader.as_mut().unwrap()) {
+            if !COMMANDS.contains(&event.name.as_str()) {
+                continue;
+            }
+
+            if !is_privileged(&config, event.sender, &nameds, &consoles) {
+                reply(event.sender, &networks, &consoles, &locale, no_permission());
+                continue;
+            }
+
+            let position = positions
+                .get(event.sender)
+                .map(|position| position.current.block_pos());
+            let message = match self.execute(event, position, &mut chunk_map, &mut world_edit) {
+                Ok(message) => message,
+                Err(message) => message,
+            };
+            reply(event.sender, &networks, &consoles, &locale, message);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        use specs::SystemData;
+        Self::SystemData::setup(world);
+
+        self.command_reader = Some(
+            world
+                .fetch_mut::<EventChannel<CommandEvent>>()
+                .register_reader(),
+        );
+        self.disconnect_reader = Some(
+            world
+                .fetch_mut::<EventChannel<PlayerDisconnectEvent>>()
+                .register_reader(),
+        );
+
+        let mut registry = world
+            .entry::<CommandRegistry>()
+            .or_insert_with(CommandRegistry::default);
+        for command in &COMMANDS {
+            registry.register(command);
+        }
+    }
+}
+
+impl WorldEditCommandSystem {
+    /// Executes a command, returning the message to
+    /// reply with. `position` is the position of the
+    /// sender, unless it is the console.
+    fn execute(
+        &self,
+        event: &CommandEvent,
+        position: Option<BlockPosition>,
+        chunk_map: &mut ChunkMap,
+        world_edit: &mut WorldEdit,
+    ) -> Result<Message, Message> {
+        let editor = Editor::Entity(event.sender);
+        let args = &event.args;
+
+        let (key, result) = match event.name.as_str() {
+            "/pos1" | "/pos2" => {
+                let pos = parse_position(args, position)
+                    .ok_or_else(|| usage(&format!("/{} [<x> <y> <z>]", event.name)))?;
+                let selection = world_edit.selections.entry(event.sender).or_default();
+                let key = if event.name == "/pos1" {
+                    selection.pos1 = Some(pos);
+                    "feather.worldedit.pos1"
+                } else {
+                    selection.pos2 = Some(pos);
+                    "feather.worldedit.pos2"
+                };
+                return Ok(Message::translate(key).with(pos.x).with(pos.y).with(pos.z));
+            }
+            "/rotate" => {
+                let rotation = match args.as_slice() {
+                    [degrees] => degrees.parse().ok().and_then(Rotation::from_degrees),
+                    _ => None,
+                };
+                let rotation = rotation.ok_or_else(|| usage("//rotate <90|180|270>"))?;
+                world_edit.rotate(&editor, rotation)?;
+                return Ok(Message::translate("feather.worldedit.rotated"));
+            }
+            "/flip" => {
+                let mirror = match args.as_slice() {
+                    [axis] if axis.eq_ignore_ascii_case("x") => Mirror::X,
+                    [axis] if axis.eq_ignore_ascii_case("z") => Mirror::Z,
+                    _ => return Err(usage("//flip <x|z>")),
+                };
+                world_edit.flip(&editor, mirror)?;
+                return Ok(Message::translate("feather.worldedit.flipped"));
+            }
+            "/schem" => return self.schematic(event, position, chunk_map, world_edit),
+            "/set" => {
+                let region = selection(world_edit, event.sender)?;
+                let block = match args.as_slice() {
+                    [block] => parse_block(block)?,
+                    _ => return Err(usage("//set <block>")),
+                };
+                (
+                    "feather.worldedit.set",
+                    world_edit.fill(chunk_map, &editor, region, block),
+                )
+            }
+            "/replace" => {
+                let region = selection(world_edit, event.sender)?;
+                let (from, to) = match args.as_slice() {
+                    [from, to] => (parse_block(from)?, parse_block(to)?),
+                    _ => return Err(usage("//replace <from> <to>")),
+                };
+                (
+                    "feather.worldedit.replaced",
+                    world_edit.replace(chunk_map, &editor, region, from, to),
+                )
+            }
+            "/copy" => {
+                let region = selection(world_edit, event.sender)?;
+                let origin = position.unwrap_or_else(|| region.min());
+                (
+                    "feather.worldedit.copied",
+                    world_edit.copy(chunk_map, &editor, region, origin),
+                )
+            }
+            "/paste" => {
+                let origin =
+                    parse_position(args, position).ok_or_else(|| usage("//paste [<x> <y> <z>]"))?;
+                (
+                    "feather.worldedit.pasted",
+                    world_edit.paste(chunk_map, &editor, origin),
+                )
+            }
+            "/undo" => (
+                "feather.worldedit.restored",
+                world_edit.undo(chunk_map, &editor),
+            ),
+            _ => unreachable!(),
+        };
+
+        let count = result?;
+        Ok(Message::translate(key).with(count))
+    }
+
+    /// Executes the `//schem` command.
+    fn schematic(
+        &self,
+        event: &CommandEvent,
+        position: Option<BlockPosition>,
+        chunk_map: &ChunkMap,
+        world_edit: &mut WorldEdit,
+    ) -> Result<Message, Message> {
+        let args: Vec<&str> = event.args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["load", name] => {
+                let path = find_schematic(&self.schematic_dir, name)?;
+                let schematic = Schematic::load(&path).map_err(|e| {
+                    Message::translate("feather.worldedit.schematic.load_failed")
+                        .with(name)
+                        .with(e)
+                })?;
+                if schematic.volume() > MAX_VOLUME {
+                    return Err(WorldEditError::TooLarge(schematic.volume(), MAX_VOLUME).into());
+                }
+
+                let count = schematic.volume();
+                world_edit.set_clipboard(&Editor::Entity(event.sender), schematic);
+                Ok(Message::translate("feather.worldedit.schematic.loaded")
+                    .with(count)
+                    .with(path.display()))
+            }
+            ["save", name] | ["save", name, _] => {
+                let format = match args.get(2) {
+                    None | Some(&"schem") => Format::Sponge,
+                    Some(&"nbt") => Format::Structure,
+                    Some(format) => {
+                        return Err(Message::translate(
+                            "feather.worldedit.schematic.unknown_format",
+                        )
+                        .with(format))
+                    }
+                };
+                check_schematic_name(name)?;
+                let path = self
+                    .schematic_dir
+                    .join(format!("{}.{}", name, format.extension()));
+
+                let region = selection(world_edit, event.sender)?;
+                let origin = position.unwrap_or_else(|| region.min());
+                let schematic = copy_region(chunk_map, region, origin)?;
+
+                fs::create_dir_all(&self.schematic_dir)
+                    .map_err(SchematicError::Io)
+                    .and_then(|_| schematic.save(&path))
+                    .map_err(|e| {
+                        Message::translate("feather.worldedit.schematic.save_failed")
+                            .with(name)
+                            .with(e)
+                    })?;
+                Ok(Message::translate("feather.worldedit.schematic.saved")
+                    .with(schematic.volume())
+                    .with(path.display()))
+            }
+            ["list"] => {
+                let mut names: Vec<String> = fs::read_dir(&self.schematic_dir)
+                    .map(|entries| {
+                        entries
+                            .filter_map(Result::ok)
+                            .map(|entry| entry.path())
+                            .filter(|path| Format::from_path(path).is_some())
+                            .filter_map(|path| path.file_name()?.to_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                names.sort();
+
+                if names.is_empty() {
+                    Ok(Message::translate("feather.worldedit.schematic.none"))
+                } else {
+                    Ok(Message::translate("feather.worldedit.schematic.list")
+                        .with(names.join(", ")))
+                }
+            }
+            _ => Err(usage(
+                "//schem load <name> | //schem save <name> [schem|nbt] | //schem list",
+            )),
+        }
+    }
+}
+
+/// Returns the region selected by a player, or the
+/// message to reply with if there is no selection.
+pub fn selection(world_edit: &WorldEdit, player: Entity) -> Result<Region, Message> {
+    world_edit
+        .selection(player)
+        .ok_or_else(|| Message::translate("feather.worldedit.no_selection"))
+}
+
+/// Parses a position from three arguments, or
+/// returns `default` if there are no arguments.
+fn parse_position(args: &[String], default: Option<BlockPosition>) -> Option<BlockPosition> {
+    match args {
+        [] => default,
+        [x, y, z] => Some(BlockPosition::new(
+            x.parse().ok()?,
+            y.parse().ok()?,
+            z.parse().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Returns an error if a schematic name could
+/// refer to a file outside the schematic directory.
+fn check_schematic_name(name: &str) -> Result<(), Message> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(Message::translate("feather.worldedit.schematic.invalid_name").with(name))
+    }
+}
+
+/// Finds the file of a schematic, which may be
+/// named with or without its extension.
+fn find_schematic(dir: &Path, name: &str) -> Result<PathBuf, Message> {
+    let path = Path::new(name);
+    let (stem, formats) = match Format::from_path(path) {
+        Some(format) => (
+            path.file_stem().and_then(|s| s.to_str()).unwrap_or(""),
+            vec![format],
+        ),
+        None => (name, vec![Format::Sponge, Format::Structure]),
+    };
+    check_schematic_name(stem)?;
+
+    formats
+        .into_iter()
+        .map(|format| dir.join(format!("{}.{}", stem, format.extension())))
+        .find(|path| path.is_file())
+        .ok_or_else(|| Message::translate("feather.worldedit.schematic.not_found").with(name))
+}
+
+/// Parses a block name, such as `stone` or `minecraft:stone`.
+/// The block's properties have their default values.
+fn parse_block(name: &str) -> Result<Block, Message> {
+    let identifier = if name.contains(':') {
+        name.to_lowercase()
+    } else {
+        format!("minecraft:{}", name.to_lowercase())
+    };
+    Block::from_name_and_default_props(&identifier)
+        .ok_or_else(|| Message::translate("feather.worldedit.unknown_block").with(name))
+}
+
+pub fn init_logic(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(WorldEditFlushSystem, WORLDEDIT_FLUSH, &[]);
+}
+
+pub fn init_handlers(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(WorldEditCommandSystem::default(), WORLDEDIT_COMMANDS, &[]);
+}
+
+pub fn init_broadcast(dispatcher: &mut DispatcherBuilder) {
+    dispatcher.add_timed(
+        BulkBlockUpdateBroadcastSystem::default(),
+        BULK_UPDATE_BROADCAST,
+        &[],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testframework as t;
+    use feather_core::world::chunk::Chunk;
+    use specs::{Builder, WorldExt};
+
+    fn chunk_map() -> ChunkMap {
+        let mut chunk_map = ChunkMap::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let pos = ChunkPosition::new(x, z);
+                chunk_map.set_chunk_at(pos, Chunk::new(pos));
+            }
+        }
+        chunk_map
+    }
+
+    fn editor() -> Editor {
+        Editor::Plugin(String::from("test"))
+    }
+
+    fn region(x1: i32, y1: i32, z1: i32, x2: i32, y2: i32, z2: i32) -> Region {
+        Region::new(
+            BlockPosition::new(x1, y1, z1),
+            BlockPosition::new(x2, y2, z2),
+        )
+    }
+
+    #[test]
+    fn test_region() {
+        let region = region(3, 300, -2, -1, 250, 4);
+        assert_eq!(region.min(), BlockPosition::new(-1, 250, -2));
+        assert_eq!(region.max(), BlockPosition::new(3, 255, 4));
+        assert_eq!(region.size(), (5, 6, 7));
+        assert_eq!(region.volume(), 5 * 6 * 7);
+        assert!(region.contains(BlockPosition::new(0, 255, 0)));
+        assert!(!region.contains(BlockPosition::new(0, 249, 0)));
+
+        let chunks: Vec<_> = region.chunks().collect();
+        assert_eq!(
+            chunks,
+            vec![
+                ChunkPosition::new(-1, -1),
+                ChunkPosition::new(-1, 0),
+                ChunkPosition::new(0, -1),
+                ChunkPosition::new(0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_and_undo() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+
+        // Crosses chunk borders and extends into unloaded chunks.
+        let region = region(-20, 0, -1, 40, 1, 1);
+        let count = world_edit
+            .fill(&mut chunk_map, &editor(), region, Block::Stone)
+            .unwrap();
+        assert_eq!(count, 48 * 2 * 3);
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(-16, 1, -1)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(31, 0, 1)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(0, 2, 0)),
+            Some(Block::Air)
+        );
+
+        // Blocks which already match are not changed.
+        let count = world_edit
+            .fill(&mut chunk_map, &editor(), region, Block::Stone)
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let restored = world_edit.undo(&mut chunk_map, &editor()).unwrap();
+        assert_eq!(restored, 48 * 2 * 3);
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(-16, 1, -1)),
+            Some(Block::Air)
+        );
+        assert_eq!(
+            world_edit.undo(&mut chunk_map, &editor()),
+            Err(WorldEditError::NothingToUndo)
+        );
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+
+        let region = region(0, 0, 0, 1000, 255, 1000);
+        assert_eq!(
+            world_edit.fill(&mut chunk_map, &editor(), region, Block::Stone),
+            Err(WorldEditError::TooLarge(region.volume(), MAX_VOLUME))
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+        chunk_map
+            .set_block_at(BlockPosition::new(1, 1, 1), Block::Dirt)
+            .unwrap();
+
+        let count = world_edit
+            .replace(
+                &mut chunk_map,
+                &editor(),
+                region(0, 0, 0, 2, 2, 2),
+                Block::Dirt,
+                Block::Stone,
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(1, 1, 1)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(0, 0, 0)),
+            Some(Block::Air)
+        );
+    }
+
+    #[test]
+    fn test_copy_and_paste() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+        chunk_map
+            .set_block_at(BlockPosition::new(1, 10, 1), Block::Stone)
+            .unwrap();
+        chunk_map
+            .set_block_at(BlockPosition::new(2, 11, 1), Block::Dirt)
+            .unwrap();
+
+        assert_eq!(
+            world_edit.paste(&mut chunk_map, &editor(), BlockPosition::new(0, 0, 0)),
+            Err(WorldEditError::EmptyClipboard)
+        );
+
+        let copied = world_edit
+            .copy(
+                &chunk_map,
+                &editor(),
+                region(1, 10, 1, 2, 11, 1),
+                BlockPosition::new(0, 10, 0),
+            )
+            .unwrap();
+        assert_eq!(copied, 4);
+
+        let pasted = world_edit
+            .paste(&mut chunk_map, &editor(), BlockPosition::new(-10, 20, -10))
+            .unwrap();
+        assert_eq!(pasted, 2);
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(-9, 20, -9)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(-8, 21, -9)),
+            Some(Block::Dirt)
+        );
+
+        // Clipboards are separate for each editor.
+        let other = Editor::Plugin(String::from("other"));
+        assert_eq!(
+            world_edit.paste(&mut chunk_map, &other, BlockPosition::new(0, 0, 0)),
+            Err(WorldEditError::EmptyClipboard)
+        );
+    }
+
+    #[test]
+    fn test_history_size() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+
+        for y in 0..HISTORY_SIZE as i32 + 1 {
+            world_edit
+                .fill(
+                    &mut chunk_map,
+                    &editor(),
+                    region(0, y, 0, 0, y, 0),
+                    Block::Stone,
+                )
+                .unwrap();
+        }
+        for _ in 0..HISTORY_SIZE {
+            world_edit.undo(&mut chunk_map, &editor()).unwrap();
+        }
+        assert!(world_edit.undo(&mut chunk_map, &editor()).is_err());
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(0, 0, 0)),
+            Some(Block::Stone)
+        );
+    }
+
+    #[test]
+    fn test_flush() {
+        let (mut w, mut d) = t::builder().with(WorldEditFlushSystem, "").build();
+        w.insert(chunk_map());
+
+        let mut block_reader = t::reader::<BlockUpdateEvent>(&w);
+        let mut bulk_reader = t::reader::<BulkBlockUpdateEvent>(&w);
+
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            let mut world_edit = w.fetch_mut::<WorldEdit>();
+            // Few changes in chunk (0, 0)...
+            world_edit
+                .fill(
+                    &mut chunk_map,
+                    &editor(),
+                    region(0, 0, 0, 1, 0, 0),
+                    Block::Stone,
+                )
+                .unwrap();
+            // ...and many in chunk (-1, 0).
+            world_edit
+                .fill(
+                    &mut chunk_map,
+                    &editor(),
+                    region(-16, 0, 0, -1, 15, 0),
+                    Block::Stone,
+                )
+                .unwrap();
+        }
+
+        d.dispatch(&w);
+
+        let block_events = t::triggered_events::<BlockUpdateEvent>(&w, &mut block_reader);
+        assert_eq!(block_events.len(), 2);
+        assert_eq!(block_events[0].cause, BlockUpdateCause::WorldEdit);
+        assert_eq!(block_events[0].new_block, Block::Stone);
+
+        let bulk_events = t::triggered_events::<BulkBlockUpdateEvent>(&w, &mut bulk_reader);
+        assert_eq!(bulk_events.len(), 1);
+        assert_eq!(bulk_events[0].chunk, ChunkPosition::new(-1, 0));
+
+        // Changes are only flushed once.
+        d.dispatch(&w);
+        assert!(t::triggered_events::<BlockUpdateEvent>(&w, &mut block_reader).is_empty());
+    }
+
+    #[test]
+    fn test_commands() {
+        let (mut w, mut d) = t::builder()
+            .with(WorldEditCommandSystem::default(), "")
+            .build();
+        w.insert(chunk_map());
+        let (tx, rx) = crossbeam::unbounded();
+        let console = w
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+
+        let mut run = |command: &str| {
+            t::trigger_event(&w, CommandEvent::parse(console, command).unwrap());
+            d.dispatch(&w);
+            rx.try_recv().unwrap()
+        };
+
+        assert_eq!(
+            run("//set stone"),
+            "Select a region using //pos1 and //pos2 first."
+        );
+        assert_eq!(run("//pos1"), "Usage: //pos1 [<x> <y> <z>]");
+        assert_eq!(run("//pos1 0 0 0"), "First position set to (0, 0, 0).");
+        assert_eq!(run("//pos2 1 1 1"), "Second position set to (1, 1, 1).");
+        assert_eq!(run("//set nonexistent"), "Unknown block nonexistent.");
+        assert_eq!(run("//set stone"), "Set 8 blocks.");
+        assert_eq!(run("//replace stone minecraft:dirt"), "Replaced 8 blocks.");
+        assert_eq!(run("//copy"), "Copied 8 blocks.");
+        assert_eq!(run("//paste 4 0 0"), "Pasted 8 blocks.");
+        assert_eq!(run("//undo"), "Restored 8 blocks.");
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(1, 1, 1)),
+            Some(Block::Dirt)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(4, 0, 0)),
+            Some(Block::Air)
+        );
+    }
+
+    #[test]
+    fn test_paste_structure_void() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+        chunk_map
+            .set_block_at(BlockPosition::new(1, 0, 0), Block::Dirt)
+            .unwrap();
+
+        let schematic = Schematic::new(
+            (2, 1, 1),
+            BlockPosition::new(0, 0, 0),
+            vec![Block::Stone, Block::StructureVoid],
+        );
+        world_edit.set_clipboard(&editor(), schematic);
+
+        let pasted = world_edit
+            .paste(&mut chunk_map, &editor(), BlockPosition::new(0, 0, 0))
+            .unwrap();
+        assert_eq!(pasted, 1);
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(1, 0, 0)),
+            Some(Block::Dirt)
+        );
+    }
+
+    #[test]
+    fn test_rotate_and_flip() {
+        let mut chunk_map = chunk_map();
+        let mut world_edit = WorldEdit::default();
+        assert_eq!(
+            world_edit.rotate(&editor(), Rotation::Clockwise90),
+            Err(WorldEditError::EmptyClipboard)
+        );
+
+        chunk_map
+            .set_block_at(BlockPosition::new(0, 0, -1), Block::Stone)
+            .unwrap();
+        world_edit
+            .copy(
+                &chunk_map,
+                &editor(),
+                region(0, 0, -1, 0, 0, -1),
+                BlockPosition::new(0, 0, 0),
+            )
+            .unwrap();
+
+        // North of the origin becomes east.
+        world_edit.rotate(&editor(), Rotation::Clockwise90).unwrap();
+        world_edit
+            .paste(&mut chunk_map, &editor(), BlockPosition::new(0, 10, 0))
+            .unwrap();
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(1, 10, 0)),
+            Some(Block::Stone)
+        );
+
+        // East becomes west.
+        world_edit.flip(&editor(), Mirror::X).unwrap();
+        world_edit
+            .paste(&mut chunk_map, &editor(), BlockPosition::new(0, 20, 0))
+            .unwrap();
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(-1, 20, 0)),
+            Some(Block::Stone)
+        );
+    }
+
+    #[test]
+    fn test_schematic_commands() {
+        let dir = std::env::temp_dir().join(format!("feather-schematics-{}", uuid::Uuid::new_v4()));
+        let mut system = WorldEditCommandSystem::default();
+        system.schematic_dir = dir.clone();
+
+        let (mut w, mut d) = t::builder().with(system, "").build();
+        w.insert(chunk_map());
+        {
+            let mut chunk_map = w.fetch_mut::<ChunkMap>();
+            chunk_map
+                .set_block_at(BlockPosition::new(0, 0, 0), Block::Stone)
+                .unwrap();
+            chunk_map
+                .set_block_at(BlockPosition::new(9, 0, 0), Block::Dirt)
+                .unwrap();
+        }
+        let (tx, rx) = crossbeam::unbounded();
+        let console = w
+            .create_entity()
+            .with(ConsoleComponent { output: Some(tx) })
+            .build();
+
+        let mut run = |command: &str| {
+            t::trigger_event(&w, CommandEvent::parse(console, command).unwrap());
+            d.dispatch(&w);
+            rx.try_recv().unwrap()
+        };
+
+        assert_eq!(run("//schem list"), "There are no schematics.");
+        run("//pos1 0 0 0");
+        run("//pos2 1 0 0");
+        assert_eq!(
+            run("//schem save house"),
+            format!("Saved 2 blocks to {}.", dir.join("house.schem").display())
+        );
+        assert_eq!(
+            run("//schem save house nbt"),
+            format!("Saved 2 blocks to {}.", dir.join("house.nbt").display())
+        );
+        assert_eq!(run("//schem list"), "Schematics: house.nbt, house.schem");
+
+        assert_eq!(
+            run("//schem load ../house"),
+            "Invalid schematic name ../house."
+        );
+        assert_eq!(run("//schem load shed"), "Schematic shed does not exist.");
+        assert_eq!(
+            run("//schem load house.nbt"),
+            format!(
+                "Loaded 2 blocks from {} into the clipboard.",
+                dir.join("house.nbt").display()
+            )
+        );
+        assert_eq!(run("//rotate 45"), "Usage: //rotate <90|180|270>");
+        assert_eq!(run("//rotate 180"), "Rotated the clipboard.");
+        assert_eq!(run("//flip z"), "Flipped the clipboard.");
+        assert_eq!(run("//paste 10 0 0"), "Pasted 2 blocks.");
+
+        let chunk_map = w.fetch::<ChunkMap>();
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(10, 0, 0)),
+            Some(Block::Stone)
+        );
+        assert_eq!(
+            chunk_map.block_at(BlockPosition::new(9, 0, 0)),
+            Some(Block::Air)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commands_permission() {
+        let (mut w, mut d) = t::builder()
+            .with(WorldEditCommandSystem::default(), "")
+            .build();
+        let player = t::add_player(&mut w);
+
+        t::trigger_event(&w, CommandEvent::parse(player.entity, "//pos1").unwrap());
+        d.dispatch(&w);
+        w.maintain();
+
+        t::assert_packet_received(&player, feather_core::PacketType::ChatMessageClientbound);
+    }
+}
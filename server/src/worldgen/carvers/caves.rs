@@ -0,0 +1,111 @@
+use super::{carve_tunnel, Tunnel, CARVE_RANGE};
+use crate::worldgen::util::shuffle_seed_for_chunk;
+use crate::worldgen::CarvingGenerator;
+use feather_core::{Chunk, ChunkPosition};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use std::f64::consts::PI;
+
+/// The chance that cave systems start in a chunk is one in this.
+const CAVE_RARITY: u32 = 7;
+
+/// Carver for winding caves, which sometimes
+/// branch or widen into rooms.
+#[derive(Default)]
+pub struct CaveCarver;
+
+impl CarvingGenerator for CaveCarver {
+    fn carve_chunk(&self, chunk: &mut Chunk, seed: u64) {
+        let pos = chunk.position();
+        for x in pos.x - CARVE_RANGE..=pos.x + CARVE_RANGE {
+            for z in pos.z - CARVE_RANGE..=pos.z + CARVE_RANGE {
+                carve_caves_from(chunk, ChunkPosition::new(x, z), seed);
+            }
+        }
+    }
+}
+
+/// Carves the caves starting in the chunk at `origin`
+/// into the given chunk.
+fn carve_caves_from(chunk: &mut Chunk, origin: ChunkPosition, seed: u64) {
+    let mut rng = XorShiftRng::seed_from_u64(shuffle_seed_for_chunk(seed, origin));
+    if rng.gen_range(0, CAVE_RARITY) != 0 {
+        return;
+    }
+
+    // Most chunks with caves have few cave systems.
+    let max_systems = rng.gen_range(0, 15) + 1;
+    let max_systems = rng.gen_range(0, max_systems) + 1;
+    let systems = rng.gen_range(0, max_systems);
+    for _ in 0..systems {
+        let x = f64::from(origin.x * 16 + rng.gen_range(0, 16));
+        // Caves are more common deep underground.
+        let max_y = rng.gen_range(0, 120) + 8;
+        let y = f64::from(rng.gen_range(0, max_y));
+        let z = f64::from(origin.z * 16 + rng.gen_range(0, 16));
+        let length = 112 - rng.gen_range(0, 28);
+
+        let mut tunnels = 1;
+        if rng.gen_range(0, 4) == 0 {
+            // A room: a short, wide tunnel.
+            let room = Tunnel {
+                x,
+                y,
+                z,
+                width: 1.0 + rng.gen::<f64>() * 6.0,
+                yaw: 0.0,
+                pitch: 0.0,
+                length: length / 4,
+                vertical_scale: 0.5,
+                branches: false,
+            };
+            carve_tunnel(chunk, room, rng.gen());
+            tunnels += rng.gen_range(0, 4);
+        }
+
+        for _ in 0..tunnels {
+            let mut width = rng.gen::<f64>() * 2.0 + rng.gen::<f64>();
+            if rng.gen_range(0, 10) == 0 {
+                width *= rng.gen::<f64>() * rng.gen::<f64>() * 3.0 + 1.0;
+            }
+            let tunnel = Tunnel {
+                x,
+                y,
+                z,
+                width,
+                yaw: rng.gen::<f64>() * PI * 2.0,
+                pitch: (rng.gen::<f64>() - 0.5) / 4.0,
+                length,
+                vertical_scale: 1.0,
+                branches: true,
+            };
+            carve_tunnel(chunk, tunnel, rng.gen());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::stone_chunk;
+    use super::*;
+    use feather_blocks::Block;
+
+    #[test]
+    fn test_caves_are_deterministic() {
+        let pos = ChunkPosition::new(2, -3);
+        let mut first = stone_chunk(pos);
+        let mut second = stone_chunk(pos);
+        CaveCarver.carve_chunk(&mut first, 1234);
+        CaveCarver.carve_chunk(&mut second, 1234);
+
+        for x in 0..16 {
+            for y in 0..128 {
+                for z in 0..16 {
+                    assert_eq!(first.block_at(x, y, z), second.block_at(x, y, z));
+                }
+            }
+        }
+        // Bedrock level is never carved.
+        assert_eq!(first.block_at(0, 0, 0), Block::Stone);
+    }
+}
@@ -0,0 +1,275 @@
+//! Carvers, which cut caves and ravines into generated terrain.
+//!
+//! Tunnels are started from the chunks around the chunk being
+//! carved, using random number generators seeded from the world
+//! seed and the position of the chunk they start in. Each chunk
+//! carves the parts of all nearby tunnels which pass through it,
+//! so tunnels line up across chunk borders no matter the order
+//! in which chunks are generated.
+
+mod caves;
+mod ravines;
+
+pub use caves::CaveCarver;
+pub use ravines::RavineCarver;
+
+use feather_blocks::{Block, LavaData};
+use feather_core::Chunk;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// The distance, in chunks, from which tunnels may
+/// reach into the chunk being carved.
+const CARVE_RANGE: i32 = 8;
+
+/// Carved blocks below this height are filled with lava.
+const LAVA_LEVEL: usize = 11;
+
+/// The highest height at which blocks are carved.
+const MAX_CARVE_HEIGHT: usize = 247;
+
+/// A tunnel carved by `carve_tunnel`.
+#[derive(Debug, Clone, Copy)]
+struct Tunnel {
+    /// The position at which the tunnel starts.
+    x: f64,
+    y: f64,
+    z: f64,
+    /// The maximum horizontal radius of the tunnel,
+    /// in addition to its minimum radius of 1.5.
+    width: f64,
+    /// The initial direction of the tunnel, in radians.
+    yaw: f64,
+    pitch: f64,
+    /// The number of blocks travelled by the tunnel.
+    length: u32,
+    /// The ratio of the vertical radius to the horizontal radius.
+    vertical_scale: f64,
+    /// Whether the tunnel may split into two narrower
+    /// tunnels, as caves do.
+    branches: bool,
+}
+
+/// Carves the given tunnel into the chunk. The tunnel winds
+/// randomly, following the random number generator seeded
+/// with `seed`, and widens towards its middle.
+fn carve_tunnel(chunk: &mut Chunk, mut tunnel: Tunnel, seed: u64) {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+
+    let center_x = f64::from(chunk.position().x * 16 + 8);
+    let center_z = f64::from(chunk.position().z * 16 + 8);
+
+    let branch_step = if tunnel.branches && tunnel.width > 1.0 && tunnel.length >= 4 {
+        Some(rng.gen_range(tunnel.length / 4, tunnel.length * 3 / 4))
+    } else {
+        None
+    };
+    let steep = rng.gen_range(0, 6) == 0;
+
+    let mut yaw_change = 0.0;
+    let mut pitch_change = 0.0;
+
+    for step in 0..tunnel.length {
+        let radius = 1.5 + (f64::from(step) * PI / f64::from(tunnel.length)).sin() * tunnel.width;
+        let vertical_radius = radius * tunnel.vertical_scale;
+
+        tunnel.x += tunnel.yaw.cos() * tunnel.pitch.cos();
+        tunnel.y += tunnel.pitch.sin();
+        tunnel.z += tunnel.yaw.sin() * tunnel.pitch.cos();
+
+        tunnel.pitch *= if steep { 0.92 } else { 0.7 };
+        tunnel.pitch += pitch_change * 0.1;
+        tunnel.yaw += yaw_change * 0.1;
+        pitch_change *= 0.9;
+        yaw_change *= 0.75;
+        pitch_change += (rng.gen::<f64>() - rng.gen::<f64>()) * rng.gen::<f64>() * 2.0;
+        yaw_change += (rng.gen::<f64>() - rng.gen::<f64>()) * rng.gen::<f64>() * 4.0;
+
+        if Some(step) == branch_step {
+            for side in &[-1.0, 1.0] {
+                let branch = Tunnel {
+                    width: rng.gen::<f64>() * 0.5 + 0.5,
+                    yaw: tunnel.yaw + side * FRAC_PI_2,
+                    pitch: tunnel.pitch / 3.0,
+                    length: tunnel.length - step,
+                    branches: false,
+                    ..tunnel
+                };
+                carve_tunnel(chunk, branch, rng.gen());
+            }
+            return;
+        }
+
+        // Stop once the rest of the tunnel can't reach the chunk.
+        let dx = tunnel.x - center_x;
+        let dz = tunnel.z - center_z;
+        let remaining = f64::from(tunnel.length - step);
+        let reach = tunnel.width + 2.0 + 16.0;
+        if dx * dx + dz * dz - remaining * remaining > reach * reach {
+            return;
+        }
+
+        if dx.abs() > 8.0 + radius * 2.0 || dz.abs() > 8.0 + radius * 2.0 {
+            continue;
+        }
+
+        carve_ellipsoid(
+            chunk,
+            (tunnel.x, tunnel.y, tunnel.z),
+            radius,
+            vertical_radius,
+        );
+    }
+}
+
+/// Carves the blocks of the chunk within the given ellipsoid,
+/// unless it would break into water.
+fn carve_ellipsoid(
+    chunk: &mut Chunk,
+    (x, y, z): (f64, f64, f64),
+    radius: f64,
+    vertical_radius: f64,
+) {
+    let base_x = chunk.position().x * 16;
+    let base_z = chunk.position().z * 16;
+
+    let min_x = ((x - radius).floor() as i32 - base_x).max(0);
+    let max_x = ((x + radius).floor() as i32 - base_x).min(15);
+    let min_z = ((z - radius).floor() as i32 - base_z).max(0);
+    let max_z = ((z + radius).floor() as i32 - base_z).min(15);
+    let min_y = ((y - vertical_radius).floor() as i32).max(1);
+    let max_y = ((y + vertical_radius).floor() as i32).min(MAX_CARVE_HEIGHT as i32);
+    if min_x > max_x || min_z > max_z || min_y > max_y {
+        return;
+    }
+    let (min_x, max_x) = (min_x as usize, max_x as usize);
+    let (min_z, max_z) = (min_z as usize, max_z as usize);
+    let (min_y, max_y) = (min_y as usize, max_y as usize);
+
+    // Tunnels stop short of oceans and lakes rather than draining them.
+    for lx in min_x..=max_x {
+        for lz in min_z..=max_z {
+            for ly in min_y..=(max_y + 1) {
+                if let Block::Water(_) = chunk.block_at(lx, ly, lz) {
+                    return;
+                }
+            }
+        }
+    }
+
+    for lx in min_x..=max_x {
+        let dx = (f64::from(lx as i32 + base_x) + 0.5 - x) / radius;
+        for lz in min_z..=max_z {
+            let dz = (f64::from(lz as i32 + base_z) + 0.5 - z) / radius;
+            if dx * dx + dz * dz >= 1.0 {
+                continue;
+            }
+
+            for ly in min_y..=max_y {
+                let dy = (ly as f64 + 0.5 - y) / vertical_radius;
+                // The floors of tunnels are flattened.
+                if dy > -0.7 && dx * dx + dy * dy + dz * dz < 1.0 {
+                    carve_block(chunk, lx, ly, lz);
+                }
+            }
+        }
+    }
+}
+
+/// Carves the block at the given position if it can be carved.
+fn carve_block(chunk: &mut Chunk, x: usize, y: usize, z: usize) {
+    if !is_carvable(chunk.block_at(x, y, z)) {
+        return;
+    }
+
+    let block = if y < LAVA_LEVEL {
+        Block::Lava(LavaData { level: 0 })
+    } else {
+        Block::Air
+    };
+    chunk.set_block_at(x, y, z, block);
+}
+
+/// Returns whether tunnels cut through the given block.
+fn is_carvable(block: Block) -> bool {
+    match block {
+        Block::Stone
+        | Block::Granite
+        | Block::Diorite
+        | Block::Andesite
+        | Block::Dirt
+        | Block::CoarseDirt
+        | Block::Podzol(_)
+        | Block::GrassBlock(_)
+        | Block::Mycelium(_)
+        | Block::Sandstone
+        | Block::RedSandstone
+        | Block::Terracotta => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feather_core::ChunkPosition;
+
+    pub(super) fn stone_chunk(pos: ChunkPosition) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for x in 0..16 {
+            for y in 0..128 {
+                for z in 0..16 {
+                    chunk.set_block_at(x, y, z, Block::Stone);
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn test_tunnel_across_border() {
+        // The tunnel's first step is centered on the border.
+        let tunnel = Tunnel {
+            x: 15.0,
+            y: 40.0,
+            z: 8.0,
+            width: 2.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            length: 24,
+            vertical_scale: 1.0,
+            branches: true,
+        };
+
+        let mut first = stone_chunk(ChunkPosition::new(0, 0));
+        let mut second = stone_chunk(ChunkPosition::new(1, 0));
+        carve_tunnel(&mut first, tunnel, 7);
+        carve_tunnel(&mut second, tunnel, 7);
+        assert_eq!(first.block_at(15, 40, 8), Block::Air);
+        assert_eq!(second.block_at(0, 40, 8), Block::Air);
+
+        // Carving is deterministic.
+        let mut again = stone_chunk(ChunkPosition::new(1, 0));
+        carve_tunnel(&mut again, tunnel, 7);
+        for x in 0..16 {
+            for y in 0..128 {
+                for z in 0..16 {
+                    assert_eq!(again.block_at(x, y, z), second.block_at(x, y, z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lava_and_water() {
+        let mut chunk = stone_chunk(ChunkPosition::new(0, 0));
+        carve_ellipsoid(&mut chunk, (8.0, 8.0, 8.0), 3.0, 3.0);
+        assert_eq!(chunk.block_at(8, 8, 8), Block::Lava(LavaData { level: 0 }));
+        assert_eq!(chunk.block_at(8, 0, 8), Block::Stone);
+
+        chunk.set_block_at(8, 61, 8, Block::Water(Default::default()));
+        carve_ellipsoid(&mut chunk, (8.0, 60.0, 8.0), 3.0, 3.0);
+        assert_eq!(chunk.block_at(8, 60, 8), Block::Stone);
+    }
+}
@@ -0,0 +1,54 @@
+use super::{carve_tunnel, Tunnel, CARVE_RANGE};
+use crate::worldgen::util::shuffle_seed_for_chunk;
+use crate::worldgen::CarvingGenerator;
+use feather_core::{Chunk, ChunkPosition};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use std::f64::consts::PI;
+
+/// The chance that a ravine starts in a chunk is one in this.
+const RAVINE_RARITY: u32 = 50;
+
+/// Carver for ravines: long, narrow and deep tunnels.
+#[derive(Default)]
+pub struct RavineCarver;
+
+impl CarvingGenerator for RavineCarver {
+    fn carve_chunk(&self, chunk: &mut Chunk, seed: u64) {
+        // Ravines use a different seed than caves,
+        // so they don't start at the same places.
+        let seed = seed.wrapping_add(0x5261_7669_6e65);
+
+        let pos = chunk.position();
+        for x in pos.x - CARVE_RANGE..=pos.x + CARVE_RANGE {
+            for z in pos.z - CARVE_RANGE..=pos.z + CARVE_RANGE {
+                carve_ravine_from(chunk, ChunkPosition::new(x, z), seed);
+            }
+        }
+    }
+}
+
+/// Carves the ravine starting in the chunk at `origin`,
+/// if there is one, into the given chunk.
+fn carve_ravine_from(chunk: &mut Chunk, origin: ChunkPosition, seed: u64) {
+    let mut rng = XorShiftRng::seed_from_u64(shuffle_seed_for_chunk(seed, origin));
+    if rng.gen_range(0, RAVINE_RARITY) != 0 {
+        return;
+    }
+
+    let x = f64::from(origin.x * 16 + rng.gen_range(0, 16));
+    let max_y = rng.gen_range(0, 40) + 8;
+    let y = f64::from(rng.gen_range(0, max_y) + 20);
+    let tunnel = Tunnel {
+        x,
+        y,
+        z: f64::from(origin.z * 16 + rng.gen_range(0, 16)),
+        width: (rng.gen::<f64>() * 2.0 + rng.gen::<f64>()) * 2.0,
+        yaw: rng.gen::<f64>() * PI * 2.0,
+        pitch: (rng.gen::<f64>() - 0.5) / 4.0,
+        length: 112 - rng.gen_range(0, 28),
+        vertical_scale: 3.0,
+        branches: false,
+    };
+    carve_tunnel(chunk, tunnel, rng.gen());
+}
@@ -1,10 +1,9 @@
-use crate::worldgen::util::shuffle_seed_for_chunk;
-use crate::worldgen::{ChunkBiomes, FinishingGenerator, TopBlocks};
+use super::{for_each_feature_origin, in_chunk};
+use crate::worldgen::{FinishingGenerator, NearbyBiomes, TopBlocks};
 use feather_blocks::Block;
 use feather_core::{Biome, Chunk};
-use rand::{Rng, SeedableRng};
-use rand_xorshift::XorShiftRng;
-use std::{cmp, iter};
+use rand::Rng;
+use std::iter;
 
 /// Clumped foliage generator.
 #[derive(Default)]
@@ -14,45 +13,47 @@ impl FinishingGenerator for ClumpedFoliageFinisher {
     fn generate_for_chunk(
         &self,
         chunk: &mut Chunk,
-        biomes: &ChunkBiomes,
+        biomes: &NearbyBiomes,
         top_blocks: &TopBlocks,
         seed: u64,
     ) {
         // Generate clumps of foliage for the biome.
-        // Note that we currently use a hack
-        // to ensure that clumps are within one
-        // chunk.
-        // The algorithm should be changed in the future
-        // to allow for cross-chunk clumps.
+        // Clumps centered near a border may reach into
+        // neighboring chunks, so the clumps of those
+        // chunks are generated as well.
+        for_each_feature_origin(chunk.position(), seed, |offset_x, offset_z, rng| {
+            for x in offset_x..offset_x + 16 {
+                for z in offset_z..offset_z + 16 {
+                    let biome = biomes.biome_at(x, z);
 
-        let mut rng = XorShiftRng::seed_from_u64(shuffle_seed_for_chunk(seed, chunk.position()));
+                    if let Some(block) = biome_clump_block(biome) {
+                        if rng.gen_range(0, 48) == 0 {
+                            // Generate clump with center at this position.
+                            iter::repeat(()).take(rng.gen_range(3, 6)).for_each(|_| {
+                                let pos_x = x + rng.gen_range(-2, 3);
+                                let pos_z = z + rng.gen_range(-2, 3);
 
-        for x in 0..16 {
-            for z in 0..16 {
-                let biome = biomes.biome_at(x, z);
+                                if !in_chunk(pos_x, pos_z) {
+                                    return;
+                                }
+                                let (pos_x, pos_z) = (pos_x as usize, pos_z as usize);
 
-                if let Some(block) = biome_clump_block(biome) {
-                    if rng.gen_range(0, 48) == 0 {
-                        // Generate clump with center at this position.
-                        iter::repeat(()).take(rng.gen_range(3, 6)).for_each(|_| {
-                            let offset_x = rng.gen_range(-2, 3);
-                            let offset_z = rng.gen_range(-2, 3);
+                                if chunk.biome_at(pos_x, pos_z) != biome {
+                                    return; // Don't generate block outside this biome
+                                }
 
-                            // Clamp value within chunk border
-                            let pos_x = cmp::max(0, cmp::min(x as i32 + offset_x, 15)) as usize;
-                            let pos_z = cmp::max(0, cmp::min(z as i32 + offset_z, 15)) as usize;
-
-                            if chunk.biome_at(pos_x, pos_z) != biome {
-                                return; // Don't generate block outside this biome
-                            }
-
-                            let top = top_blocks.top_block_at(pos_x, pos_z);
-                            chunk.set_block_at(pos_x, top + 1, pos_z, block);
-                        });
+                                // Only grow on grass blocks, which
+                                // keeps foliage off structures.
+                                let top = top_blocks.top_block_at(pos_x, pos_z);
+                                if let Block::GrassBlock(_) = chunk.block_at(pos_x, top, pos_z) {
+                                    chunk.set_block_at(pos_x, top + 1, pos_z, block);
+                                }
+                            });
+                        }
                     }
                 }
             }
-        }
+        });
     }
 }
 
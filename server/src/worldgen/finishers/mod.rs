@@ -1,9 +1,71 @@
 //! Various finishers for world generation, such as grass, snow, and trees.
+//!
+//! Some features, like ore veins and trees, may cross chunk borders.
+//! Finishers placing them generate the features starting in each
+//! neighboring chunk as well, keeping only the blocks inside the chunk
+//! being generated. Since a feature only depends on the seed and the
+//! chunk it starts in, every chunk it crosses places it the same way,
+//! regardless of the order in which chunks are generated.
+
+use crate::worldgen::util::shuffle_seed_for_chunk;
+use feather_blocks::Block;
+use feather_core::{Chunk, ChunkPosition};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
 
 mod clumped;
+mod ores;
 mod single;
 mod snow;
+mod trees;
 
 pub use clumped::ClumpedFoliageFinisher;
+pub use ores::OreFinisher;
 pub use single::SingleFoliageFinisher;
 pub use snow::SnowFinisher;
+pub use trees::TreeFinisher;
+
+/// Calls `f` for the given chunk and each of its neighbors,
+/// which are the chunks whose features may reach into it.
+///
+/// `f` is passed the offset of the origin chunk from the given
+/// chunk, in blocks, and a random number generator which only
+/// depends on the seed and the origin chunk.
+fn for_each_feature_origin<F>(chunk: ChunkPosition, seed: u64, mut f: F)
+where
+    F: FnMut(i32, i32, &mut XorShiftRng),
+{
+    for offset_x in -1..=1 {
+        for offset_z in -1..=1 {
+            let origin = ChunkPosition::new(chunk.x + offset_x, chunk.z + offset_z);
+            let mut rng = XorShiftRng::seed_from_u64(shuffle_seed_for_chunk(seed, origin));
+            f(offset_x * 16, offset_z * 16, &mut rng);
+        }
+    }
+}
+
+/// Returns whether the given chunk-local column
+/// is inside the chunk.
+fn in_chunk(x: i32, z: i32) -> bool {
+    (0..16).contains(&x) && (0..16).contains(&z)
+}
+
+/// Sets the block at the given chunk-local position if it is inside
+/// the chunk and `replaceable` returns `true` for the current block.
+/// Returns whether the block was set.
+fn place_block<F>(chunk: &mut Chunk, x: i32, y: i32, z: i32, block: Block, replaceable: F) -> bool
+where
+    F: FnOnce(Block) -> bool,
+{
+    if !in_chunk(x, z) || !(0..256).contains(&y) {
+        return false;
+    }
+
+    let (x, y, z) = (x as usize, y as usize, z as usize);
+    if replaceable(chunk.block_at(x, y, z)) {
+        chunk.set_block_at(x, y, z, block);
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,249 @@
+use super::{for_each_feature_origin, place_block};
+use crate::worldgen::{FinishingGenerator, NearbyBiomes, TopBlocks};
+use feather_blocks::{Block, RedstoneOreData};
+use feather_core::Chunk;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// How the heights of a vein's centers are distributed.
+#[derive(Clone, Copy)]
+enum Height {
+    /// Uniformly between the two heights, exclusive of the upper one.
+    Uniform(i32, i32),
+    /// Triangularly around the first height, spreading
+    /// up to the second on either side.
+    Triangular(i32, i32),
+}
+
+/// A kind of vein placed in stone.
+struct Vein {
+    block: Block,
+    /// The maximum number of blocks in a vein.
+    size: u32,
+    /// The number of veins tried in each chunk.
+    count: u32,
+    height: Height,
+}
+
+/// The veins generated, with vanilla's sizes and height distributions.
+const VEINS: &[Vein] = &[
+    Vein {
+        block: Block::Dirt,
+        size: 33,
+        count: 10,
+        height: Height::Uniform(0, 256),
+    },
+    Vein {
+        block: Block::Gravel,
+        size: 33,
+        count: 8,
+        height: Height::Uniform(0, 256),
+    },
+    Vein {
+        block: Block::CoalOre,
+        size: 17,
+        count: 20,
+        height: Height::Uniform(0, 128),
+    },
+    Vein {
+        block: Block::IronOre,
+        size: 9,
+        count: 20,
+        height: Height::Uniform(0, 64),
+    },
+    Vein {
+        block: Block::GoldOre,
+        size: 9,
+        count: 2,
+        height: Height::Uniform(0, 32),
+    },
+    Vein {
+        block: Block::RedstoneOre(RedstoneOreData { lit: false }),
+        size: 8,
+        count: 8,
+        height: Height::Uniform(0, 16),
+    },
+    Vein {
+        block: Block::DiamondOre,
+        size: 8,
+        count: 1,
+        height: Height::Uniform(0, 16),
+    },
+    Vein {
+        block: Block::LapisOre,
+        size: 7,
+        count: 1,
+        height: Height::Triangular(16, 16),
+    },
+];
+
+/// Finisher for ore veins and for patches
+/// of dirt and gravel underground.
+#[derive(Default)]
+pub struct OreFinisher;
+
+impl FinishingGenerator for OreFinisher {
+    fn generate_for_chunk(
+        &self,
+        chunk: &mut Chunk,
+        _biomes: &NearbyBiomes,
+        _top_blocks: &TopBlocks,
+        seed: u64,
+    ) {
+        for_each_feature_origin(chunk.position(), seed, |offset_x, offset_z, rng| {
+            for vein in VEINS {
+                for _ in 0..vein.count {
+                    let x = offset_x + rng.gen_range(0, 16);
+                    let y = match vein.height {
+                        Height::Uniform(min, max) => rng.gen_range(min, max),
+                        Height::Triangular(center, spread) => {
+                            let low = rng.gen_range(0, spread);
+                            let high = rng.gen_range(0, spread);
+                            center - spread + low + high
+                        }
+                    };
+                    let z = offset_z + rng.gen_range(0, 16);
+                    place_vein(chunk, vein, (x, y, z), rng);
+                }
+            }
+        });
+    }
+}
+
+/// Places a vein centered on the given chunk-local
+/// position, keeping only the blocks inside the chunk.
+///
+/// As in vanilla, a vein is a chain of small blobs
+/// along a randomly directed line.
+fn place_vein(chunk: &mut Chunk, vein: &Vein, center: (i32, i32, i32), rng: &mut impl Rng) {
+    let size = f64::from(vein.size);
+    let angle = rng.gen::<f64>() * PI;
+    let (center_x, center_y, center_z) = (
+        f64::from(center.0),
+        f64::from(center.1),
+        f64::from(center.2),
+    );
+
+    let start_x = center_x + angle.sin() * size / 8.0;
+    let end_x = center_x - angle.sin() * size / 8.0;
+    let start_z = center_z + angle.cos() * size / 8.0;
+    let end_z = center_z - angle.cos() * size / 8.0;
+    let start_y = center_y + f64::from(rng.gen_range(-2, 1));
+    let end_y = center_y + f64::from(rng.gen_range(-2, 1));
+
+    for i in 0..vein.size {
+        let progress = f64::from(i) / size;
+        let x = start_x + (end_x - start_x) * progress;
+        let y = start_y + (end_y - start_y) * progress;
+        let z = start_z + (end_z - start_z) * progress;
+        let scale = rng.gen::<f64>() * size / 16.0;
+        let radius = ((PI * progress).sin() + 1.0) * scale / 2.0 + 0.5;
+
+        let first = |v: f64| (v - radius).floor() as i32;
+        let last = |v: f64| (v + radius).floor() as i32;
+        for block_x in first(x)..=last(x) {
+            for block_y in first(y)..=last(y) {
+                for block_z in first(z)..=last(z) {
+                    let dx = (f64::from(block_x) + 0.5 - x) / radius;
+                    let dy = (f64::from(block_y) + 0.5 - y) / radius;
+                    let dz = (f64::from(block_z) + 0.5 - z) / radius;
+                    if dx * dx + dy * dy + dz * dz < 1.0 {
+                        place_block(chunk, block_x, block_y, block_z, vein.block, is_stone);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether veins may replace the given block.
+fn is_stone(block: Block) -> bool {
+    match block {
+        Block::Stone | Block::Granite | Block::Diorite | Block::Andesite => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feather_core::ChunkPosition;
+
+    fn stone_chunk(pos: ChunkPosition) -> Chunk {
+        let mut chunk = Chunk::new(pos);
+        for x in 0..16 {
+            for y in 0..128 {
+                for z in 0..16 {
+                    chunk.set_block_at(x, y, z, Block::Stone);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn ore_heights(chunk: &Chunk, ore: Block) -> Vec<usize> {
+        let mut heights = vec![];
+        for x in 0..16 {
+            for y in 0..256 {
+                for z in 0..16 {
+                    if chunk.block_at(x, y, z) == ore {
+                        heights.push(y);
+                    }
+                }
+            }
+        }
+        heights
+    }
+
+    #[test]
+    fn test_ore_heights() {
+        let biomes = NearbyBiomes::from_vec(vec![]);
+        let top_blocks = TopBlocks::new();
+
+        let mut coal = vec![];
+        let mut diamonds = vec![];
+        for x in 0..4 {
+            let mut chunk = stone_chunk(ChunkPosition::new(x, 0));
+            OreFinisher.generate_for_chunk(&mut chunk, &biomes, &top_blocks, 1024);
+            coal.extend(ore_heights(&chunk, Block::CoalOre));
+            diamonds.extend(ore_heights(&chunk, Block::DiamondOre));
+        }
+
+        assert!(!coal.is_empty());
+        assert!(coal.iter().all(|y| *y < 132));
+        assert!(diamonds.iter().all(|y| *y < 20));
+    }
+
+    #[test]
+    fn test_veins_cross_chunks() {
+        let biomes = NearbyBiomes::from_vec(vec![]);
+        let top_blocks = TopBlocks::new();
+        let seed = 8;
+
+        let mut west = stone_chunk(ChunkPosition::new(0, 0));
+        let mut east = stone_chunk(ChunkPosition::new(1, 0));
+        OreFinisher.generate_for_chunk(&mut west, &biomes, &top_blocks, seed);
+        OreFinisher.generate_for_chunk(&mut east, &biomes, &top_blocks, seed);
+
+        // A vein reaching into a chunk is placed there as well,
+        // so some veins touch both sides of the border.
+        let crossing = (0..128).any(|y| {
+            (0..16).any(|z| {
+                let a = west.block_at(15, y, z);
+                a != Block::Stone && a == east.block_at(0, y, z)
+            })
+        });
+        assert!(crossing);
+
+        // Generating a chunk again yields the same veins.
+        let mut again = stone_chunk(ChunkPosition::new(1, 0));
+        OreFinisher.generate_for_chunk(&mut again, &biomes, &top_blocks, seed);
+        for y in 0..128 {
+            for x in 0..16 {
+                for z in 0..16 {
+                    assert_eq!(east.block_at(x, y, z), again.block_at(x, y, z));
+                }
+            }
+        }
+    }
+}
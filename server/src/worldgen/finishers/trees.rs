@@ -0,0 +1,321 @@
+use super::{for_each_feature_origin, in_chunk, place_block};
+use crate::worldgen::{FinishingGenerator, NearbyBiomes, TopBlocks};
+use feather_blocks::{
+    BirchLeavesData, BirchLogAxis, BirchLogData, Block, OakLeavesData, OakLogAxis, OakLogData,
+};
+use feather_core::{Biome, Chunk};
+use rand::Rng;
+
+/// Finisher for small oak and birch trees.
+///
+/// The leaves of trees growing near a chunk border reach into
+/// the neighboring chunk. Since the terrain of neighboring chunks
+/// isn't known while generating a chunk, the part of a tree reaching
+/// into it is placed on the nearest column inside the chunk, which
+/// is usually as high as the column the tree grows on.
+#[derive(Default)]
+pub struct TreeFinisher;
+
+impl FinishingGenerator for TreeFinisher {
+    fn generate_for_chunk(
+        &self,
+        chunk: &mut Chunk,
+        biomes: &NearbyBiomes,
+        top_blocks: &TopBlocks,
+        seed: u64,
+    ) {
+        for_each_feature_origin(chunk.position(), seed, |offset_x, offset_z, rng| {
+            for column_x in offset_x..offset_x + 16 {
+                for column_z in offset_z..offset_z + 16 {
+                    let biome = biomes.biome_at(column_x, column_z);
+                    let (rarity, birch_chance) = continue_if_none!(biome_trees(biome));
+                    if rng.gen_range(0, rarity) != 0 {
+                        continue;
+                    }
+
+                    let kind = if rng.gen_bool(birch_chance) {
+                        TreeKind::Birch
+                    } else {
+                        TreeKind::Oak
+                    };
+                    let height = kind.min_height() + rng.gen_range(0, 3);
+
+                    // Draw the leaves before deciding whether the
+                    // tree grows, so the random numbers used by
+                    // later trees don't depend on the terrain.
+                    let leaves = LeafCorners::new(rng);
+
+                    let ground_x = column_x.max(0).min(15) as usize;
+                    let ground_z = column_z.max(0).min(15) as usize;
+                    let ground = top_blocks.top_block_at(ground_x, ground_z);
+                    if !can_grow_on(chunk.block_at(ground_x, ground, ground_z)) {
+                        continue;
+                    }
+
+                    let tree = Tree {
+                        kind,
+                        x: column_x,
+                        y: ground as i32 + 1,
+                        z: column_z,
+                        height,
+                        leaves,
+                    };
+                    tree.place(chunk);
+                }
+            }
+        });
+    }
+}
+
+/// Returns the chance that a tree grows on a column of the
+/// given biome, as one in the first value, and the probability
+/// that such a tree is a birch, or `None` if trees don't grow
+/// in the biome.
+fn biome_trees(biome: Biome) -> Option<(u32, f64)> {
+    match biome {
+        Biome::Forest | Biome::WoodedHills | Biome::FlowerForest => Some((24, 0.2)),
+        Biome::DarkForest | Biome::DarkForestHills => Some((12, 0.2)),
+        Biome::BirchForest
+        | Biome::BirchForestHills
+        | Biome::TallBirchForest
+        | Biome::TallBirchHills => Some((24, 1.0)),
+        Biome::Swamp | Biome::SwampHills => Some((96, 0.0)),
+        Biome::Mountains | Biome::WoodedMountains | Biome::MountainEdge => Some((192, 0.2)),
+        Biome::Plains | Biome::SunflowerPlains => Some((768, 0.2)),
+        _ => None,
+    }
+}
+
+fn can_grow_on(block: Block) -> bool {
+    match block {
+        Block::GrassBlock(_) | Block::Dirt => true,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TreeKind {
+    Oak,
+    Birch,
+}
+
+impl TreeKind {
+    fn min_height(self) -> i32 {
+        match self {
+            TreeKind::Oak => 4,
+            TreeKind::Birch => 5,
+        }
+    }
+
+    fn log(self) -> Block {
+        match self {
+            TreeKind::Oak => Block::OakLog(OakLogData {
+                axis: OakLogAxis::Y,
+            }),
+            TreeKind::Birch => Block::BirchLog(BirchLogData {
+                axis: BirchLogAxis::Y,
+            }),
+        }
+    }
+
+    /// Returns the leaves with the given distance from the trunk.
+    fn leaves(self, distance: i32) -> Block {
+        match self {
+            TreeKind::Oak => Block::OakLeaves(OakLeavesData {
+                distance,
+                persistent: false,
+            }),
+            TreeKind::Birch => Block::BirchLeaves(BirchLeavesData {
+                distance,
+                persistent: false,
+            }),
+        }
+    }
+}
+
+/// The number of layers of leaves.
+const LEAF_LAYERS: usize = 4;
+
+/// Which corners of the three lower layers of leaves are
+/// filled. As in vanilla, the top layer has no corners.
+struct LeafCorners([[bool; 4]; LEAF_LAYERS - 1]);
+
+impl LeafCorners {
+    fn new(rng: &mut impl Rng) -> Self {
+        let mut corners = [[false; 4]; LEAF_LAYERS - 1];
+        for layer in corners.iter_mut() {
+            for corner in layer.iter_mut() {
+                *corner = rng.gen();
+            }
+        }
+        LeafCorners(corners)
+    }
+
+    fn is_filled(&self, layer: usize, dx: i32, dz: i32) -> bool {
+        let corner = (dx > 0) as usize + 2 * (dz > 0) as usize;
+        self.0[layer][corner]
+    }
+}
+
+/// A tree, at chunk-local coordinates.
+struct Tree {
+    kind: TreeKind,
+    /// The position of the lowest log.
+    x: i32,
+    y: i32,
+    z: i32,
+    height: i32,
+    leaves: LeafCorners,
+}
+
+impl Tree {
+    /// Places the parts of the tree inside the given chunk.
+    fn place(&self, chunk: &mut Chunk) {
+        let top = self.y + self.height;
+
+        for layer in 0..LEAF_LAYERS {
+            let y = top - LEAF_LAYERS as i32 + 1 + layer as i32;
+            let radius = if layer < 2 { 2 } else { 1 };
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    let corner = dx.abs() == radius && dz.abs() == radius;
+                    if corner && (layer == LEAF_LAYERS - 1 || !self.leaves.is_filled(layer, dx, dz))
+                    {
+                        continue;
+                    }
+
+                    // Leaves decay unless they are close
+                    // enough to a log, counting steps.
+                    let mut distance = dx.abs() + dz.abs();
+                    if y >= top {
+                        distance += y - top + 1;
+                    }
+                    let leaves = self.kind.leaves(distance.max(1));
+                    place_block(chunk, self.x + dx, y, self.z + dz, leaves, is_replaceable);
+                }
+            }
+        }
+
+        if !in_chunk(self.x, self.z) {
+            return;
+        }
+        let log = self.kind.log();
+        for y in self.y..top {
+            place_block(chunk, self.x, y, self.z, log, |block| {
+                is_replaceable(block) || is_leaves(block)
+            });
+        }
+        place_block(chunk, self.x, self.y - 1, self.z, Block::Dirt, can_grow_on);
+    }
+}
+
+fn is_replaceable(block: Block) -> bool {
+    match block {
+        Block::Air | Block::Grass | Block::Fern | Block::Snow(_) => true,
+        _ => false,
+    }
+}
+
+fn is_leaves(block: Block) -> bool {
+    match block {
+        Block::OakLeaves(_) | Block::BirchLeaves(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::ChunkBiomes;
+    use feather_blocks::GrassBlockData;
+    use feather_core::ChunkPosition;
+
+    /// Returns a flat forest chunk with a grass surface at y = 64.
+    fn forest_chunk(pos: ChunkPosition) -> (Chunk, TopBlocks) {
+        let mut chunk = Chunk::new(pos);
+        let mut top_blocks = TopBlocks::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block_at(x, 64, z, Block::GrassBlock(GrassBlockData { snowy: false }));
+                top_blocks.set_top_block_at(x, z, 64);
+            }
+        }
+        (chunk, top_blocks)
+    }
+
+    fn forest_biomes() -> NearbyBiomes {
+        NearbyBiomes::from_vec(
+            (0..9)
+                .map(|_| ChunkBiomes::from_array([Biome::Forest; 256]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_trees() {
+        let (mut chunk, top_blocks) = forest_chunk(ChunkPosition::new(0, 0));
+        TreeFinisher.generate_for_chunk(&mut chunk, &forest_biomes(), &top_blocks, 42);
+
+        let mut trunks = 0;
+        for x in 0..16 {
+            for z in 0..16 {
+                match chunk.block_at(x, 65, z) {
+                    Block::OakLog(_) | Block::BirchLog(_) => {
+                        trunks += 1;
+                        assert_eq!(chunk.block_at(x, 64, z), Block::Dirt);
+                    }
+                    Block::Air => (),
+                    block => panic!("unexpected block {:?}", block),
+                }
+            }
+        }
+        assert!(trunks > 0);
+
+        for x in 0..16 {
+            for y in 65..80 {
+                for z in 0..16 {
+                    if let Block::OakLeaves(data) = chunk.block_at(x, y, z) {
+                        assert!(data.distance >= 1 && data.distance < 7);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trees_cross_chunks() {
+        let biomes = forest_biomes();
+        let mut checked = 0;
+
+        for seed in 0..16 {
+            let (mut west, top_blocks) = forest_chunk(ChunkPosition::new(0, 0));
+            TreeFinisher.generate_for_chunk(&mut west, &biomes, &top_blocks, seed);
+            let (mut east, top_blocks) = forest_chunk(ChunkPosition::new(1, 0));
+            TreeFinisher.generate_for_chunk(&mut east, &biomes, &top_blocks, seed);
+
+            // The lower leaves of a tree growing on the border
+            // of the western chunk reach into the eastern one.
+            for z in 0..16 {
+                let height = (65..80)
+                    .take_while(|y| is_log(west.block_at(15, *y, z)))
+                    .count();
+                if height == 0 {
+                    continue;
+                }
+
+                let y = 65 + height - 3;
+                let block = east.block_at(0, y, z);
+                assert!(is_leaves(block) || is_log(block));
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    fn is_log(block: Block) -> bool {
+        match block {
+            Block::OakLog(_) | Block::BirchLog(_) => true,
+            _ => false,
+        }
+    }
+}
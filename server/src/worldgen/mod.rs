@@ -3,21 +3,27 @@
 //! Generation is primarily based around the `ComposableGenerator`,
 //! which allows configuration of a world generator pipeline.
 
+use feather_core::level::{LevelData, LevelGeneratorType};
 use feather_core::{Biome, Block, Chunk, ChunkPosition};
 
 mod biomes;
+mod carvers;
 mod composition;
 mod density_map;
 mod finishers;
 pub mod noise;
+mod structures;
 mod superflat;
 mod util;
 pub mod voronoi;
 
-use crate::worldgen::finishers::{ClumpedFoliageFinisher, SingleFoliageFinisher, SnowFinisher};
+use crate::worldgen::finishers::{
+    ClumpedFoliageFinisher, OreFinisher, SingleFoliageFinisher, SnowFinisher, TreeFinisher,
+};
 pub use biomes::{DistortedVoronoiBiomeGenerator, TwoLevelBiomeGenerator};
 use bitvec::slice::BitSlice;
 use bitvec::vec::BitVec;
+pub use carvers::{CaveCarver, RavineCarver};
 pub use composition::BasicCompositionGenerator;
 pub use density_map::{DensityMapGeneratorImpl, HeightMapGenerator};
 pub use noise::NoiseLerper;
@@ -26,6 +32,10 @@ use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use smallvec::SmallVec;
 use std::fmt;
+use std::sync::Arc;
+pub use structures::{
+    BoundingBox, StructureGenerator, StructurePiece, StructurePlacement, VillageGenerator,
+};
 pub use superflat::SuperflatWorldGenerator;
 
 /// Sea-level height.
@@ -40,6 +50,26 @@ pub trait WorldGenerator: Send + Sync {
     fn generate_chunk(&self, position: ChunkPosition) -> Chunk;
 }
 
+/// Returns the generator for new chunks of the world described
+/// by the given level data, selected by its generator name.
+///
+/// Amplified and large biome worlds use the default generator,
+/// since their variants aren't implemented. Buffet and debug
+/// worlds are left empty.
+pub fn generator_for_level(level: &LevelData) -> Arc<dyn WorldGenerator> {
+    match level.generator_type() {
+        LevelGeneratorType::Flat => Arc::new(SuperflatWorldGenerator {
+            options: level.generator_options.clone().unwrap_or_default(),
+        }),
+        LevelGeneratorType::Default
+        | LevelGeneratorType::LargeBiomes
+        | LevelGeneratorType::Amplified => {
+            Arc::new(ComposableGenerator::default_with_seed(level.seed as u64))
+        }
+        LevelGeneratorType::Buffet | LevelGeneratorType::Debug => Arc::new(EmptyWorldGenerator {}),
+    }
+}
+
 pub struct EmptyWorldGenerator {}
 
 impl WorldGenerator for EmptyWorldGenerator {
@@ -58,7 +88,9 @@ impl WorldGenerator for EmptyWorldGenerator {
 /// * Biomes - generates a biome grid.
 /// * Terrain density - generates the terrain density values using Perlin noise.
 /// * Terrain composition - sets the correct block types based on the biome and terrain density.
-/// * Finishing generators - generates final elements, such as grass, snow, and trees.
+/// * Carvers - cut caves and ravines into the terrain.
+/// * Structures - places structures such as villages, which may span many chunks.
+/// * Finishing generators - generates final elements, such as ores, grass, snow, and trees.
 ///
 /// This generator is based on [this document](http://cuberite.xoft.cz/docs/Generator.html).
 pub struct ComposableGenerator {
@@ -68,6 +100,10 @@ pub struct ComposableGenerator {
     density_map: Box<dyn DensityMapGenerator>,
     /// The composition generator.
     composition: Box<dyn CompositionGenerator>,
+    /// The carvers, run in order after composition.
+    carvers: SmallVec<[Box<dyn CarvingGenerator>; 2]>,
+    /// The generators for structures, run after the carvers.
+    structures: SmallVec<[Box<dyn StructureGenerator>; 1]>,
     /// A vector of finishing generators used
     /// by this composable generator.
     finishers: SmallVec<[Box<dyn FinishingGenerator>; 8]>,
@@ -77,10 +113,12 @@ pub struct ComposableGenerator {
 
 impl ComposableGenerator {
     /// Creates a new `ComposableGenerator` with the given stages.
-    pub fn new<B, D, C, F>(
+    pub fn new<B, D, C, K, S, F>(
         biome: B,
         density_map: D,
         composition: C,
+        carvers: K,
+        structures: S,
         finishers: F,
         seed: u64,
     ) -> Self
@@ -88,12 +126,16 @@ impl ComposableGenerator {
         B: BiomeGenerator + 'static,
         D: DensityMapGenerator + 'static,
         C: CompositionGenerator + 'static,
+        K: IntoIterator<Item = Box<dyn CarvingGenerator>>,
+        S: IntoIterator<Item = Box<dyn StructureGenerator>>,
         F: IntoIterator<Item = Box<dyn FinishingGenerator>>,
     {
         Self {
             biome: Box::new(biome),
             density_map: Box::new(density_map),
             composition: Box::new(composition),
+            carvers: carvers.into_iter().collect(),
+            structures: structures.into_iter().collect(),
             finishers: finishers.into_iter().collect(),
             seed,
         }
@@ -102,15 +144,25 @@ impl ComposableGenerator {
     /// A default composable generator, used
     /// for worlds with "default" world type.
     pub fn default_with_seed(seed: u64) -> Self {
+        let carvers: Vec<Box<dyn CarvingGenerator>> = vec![
+            Box::new(CaveCarver::default()),
+            Box::new(RavineCarver::default()),
+        ];
+        let structures: Vec<Box<dyn StructureGenerator>> =
+            vec![Box::new(VillageGenerator::default())];
         let finishers: Vec<Box<dyn FinishingGenerator>> = vec![
+            Box::new(OreFinisher::default()),
             Box::new(SnowFinisher::default()),
             Box::new(SingleFoliageFinisher::default()),
             Box::new(ClumpedFoliageFinisher::default()),
+            Box::new(TreeFinisher::default()),
         ];
         Self::new(
             TwoLevelBiomeGenerator::default(),
             DensityMapGeneratorImpl::default(),
             BasicCompositionGenerator::default(),
+            carvers,
+            structures,
             finishers,
             seed,
         )
@@ -154,45 +206,37 @@ impl WorldGenerator for ComposableGenerator {
             seed_shuffler.gen(),
         );
 
-        // Calculate top blocks in chunk.
-        // TODO: perhaps this should be moved to `Chunk`?
-        let mut top_blocks = TopBlocks::new();
-        for x in 0..16 {
-            for z in 0..16 {
-                for y in (0..256).rev() {
-                    if chunk.block_at(x, y, z) != Block::Air {
-                        top_blocks.set_top_block_at(x, z, y);
-                        break;
-                    }
-                }
+        // Carvers use the world seed itself, rather than one
+        // drawn for this chunk, so tunnels started in other
+        // chunks match up with this one.
+        for carver in &self.carvers {
+            carver.carve_chunk(&mut chunk, self.seed);
+        }
+
+        // Structures are laid out from their starts, which
+        // may be in other chunks, and placed on the terrain.
+        let top_blocks = TopBlocks::from_chunk(&chunk);
+        for structure in &self.structures {
+            let starts =
+                structures::structures_reaching(&**structure, position, self.seed, |start| {
+                    self.biome
+                        .generate_for_chunk(start, biome_seed)
+                        .biome_at(8, 8)
+                });
+            for start in starts {
+                start.generate_into(&mut chunk, &top_blocks);
             }
         }
 
+        // Recalculate the top blocks, which
+        // structures may have changed.
+        let top_blocks = TopBlocks::from_chunk(&chunk);
+
         // Finishers.
         for finisher in &self.finishers {
-            finisher.generate_for_chunk(
-                &mut chunk,
-                &biomes.biomes[4],
-                &top_blocks,
-                seed_shuffler.gen(),
-            );
+            finisher.generate_for_chunk(&mut chunk, &biomes, &top_blocks, seed_shuffler.gen());
         }
 
-        // TODO: correct lighting.
-        // Fill chunk with 15 light levels.
-        chunk
-            .sections_mut()
-            .into_iter()
-            .filter(|section| section.is_some())
-            .map(|section| section.unwrap())
-            .for_each(|section| {
-                let sky_light = section.sky_light_mut();
-
-                (0..4096).for_each(|index| {
-                    sky_light.set(index, 15);
-                })
-            });
-
         chunk
     }
 }
@@ -229,16 +273,28 @@ pub trait CompositionGenerator: Send + Sync {
     );
 }
 
-/// A generator, run after composition,
+/// A generator, run after composition, which cuts
+/// tunnels such as caves into the terrain.
+pub trait CarvingGenerator: Send + Sync {
+    /// Carves the given chunk. The result must only depend
+    /// on the seed and the chunk's position and blocks, so
+    /// tunnels crossing chunk borders line up.
+    fn carve_chunk(&self, chunk: &mut Chunk, seed: u64);
+}
+
+/// A generator, run after composition and carving,
 /// which can add finishing elements to chunks,
 /// such as grass, trees, and snow.
 pub trait FinishingGenerator: Send + Sync {
     /// Populates the given chunk with any
     /// finishing blocks.
+    ///
+    /// The biomes of the neighboring chunks are
+    /// passed so that features can cross chunk borders.
     fn generate_for_chunk(
         &self,
         chunk: &mut Chunk,
-        biomes: &ChunkBiomes,
+        biomes: &NearbyBiomes,
         top_blocks: &TopBlocks,
         seed: u64,
     );
@@ -273,6 +329,23 @@ impl TopBlocks {
     pub fn set_top_block_at(&mut self, x: usize, z: usize, top: usize) {
         self.top_blocks[x + (z << 4)] = top as u8;
     }
+
+    /// Calculates the highest blocks of the given chunk.
+    // TODO: perhaps this should be moved to `Chunk`?
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        let mut top_blocks = Self::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in (0..256).rev() {
+                    if chunk.block_at(x, y, z) != Block::Air {
+                        top_blocks.set_top_block_at(x, z, y);
+                        break;
+                    }
+                }
+            }
+        }
+        top_blocks
+    }
 }
 
 /// Represents the biomes in a 3x3 grid of chunks,
@@ -308,21 +381,12 @@ impl NearbyBiomes {
     fn index<N: ToPrimitive>(&self, ox: N, oz: N) -> (usize, usize, usize) {
         let ox = ox.to_isize().unwrap();
         let oz = oz.to_isize().unwrap();
-        let x = ox + 16;
-        let z = oz + 16;
 
-        let chunk_x = (x / 16) as usize;
-        let chunk_z = (z / 16) as usize;
+        let chunk_x = (ox.div_euclid(16) + 1) as usize;
+        let chunk_z = (oz.div_euclid(16) + 1) as usize;
 
-        let mut local_x = (ox % 16).abs() as usize;
-        let mut local_z = (oz % 16).abs() as usize;
-
-        if ox < 0 {
-            local_x = 16 - local_x;
-        }
-        if oz < 0 {
-            local_z = 16 - local_z;
-        }
+        let local_x = ox.rem_euclid(16) as usize;
+        let local_z = oz.rem_euclid(16) as usize;
 
         (chunk_x + chunk_z * 3, local_x, local_z)
     }
@@ -438,6 +502,18 @@ mod tests {
         assert_eq!(chunk_pos, chunk.position());
     }
 
+    #[test]
+    fn test_generator_for_level() {
+        let mut level = LevelData::default();
+        level.generator_name = String::from("flat");
+        let chunk = generator_for_level(&level).generate_chunk(ChunkPosition::new(0, 0));
+        assert_eq!(chunk.block_at(0, 0, 0), Block::Bedrock);
+
+        level.generator_name = String::from("debug_all_block_states");
+        let chunk = generator_for_level(&level).generate_chunk(ChunkPosition::new(0, 0));
+        assert!(chunk.sections().iter().all(|sec| sec.is_none()));
+    }
+
     #[test]
     fn test_chunk_biomes() {
         let biomes = [Biome::Plains; 16 * 16];
@@ -485,5 +561,7 @@ mod tests {
         assert_eq!(biomes.biome_at(16, 16), Biome::Taiga);
         assert_eq!(biomes.biome_at(-1, -1), Biome::Plains);
         assert_eq!(biomes.biome_at(-1, 0), Biome::BirchForest);
+        assert_eq!(biomes.biome_at(-16, 0), Biome::BirchForest);
+        assert_eq!(biomes.biome_at(31, -16), Biome::Savanna);
     }
 }
@@ -0,0 +1,313 @@
+//! Structures, such as villages, which are built from
+//! pieces that may span many chunks.
+//!
+//! Where a structure starts only depends on the seed and on the
+//! biome of the chunk it starts in: the world is divided into square
+//! regions, each of which contains at most one start of a structure.
+//! When generating a chunk, the structures starting in nearby regions
+//! are laid out again, and the pieces reaching into the chunk are
+//! placed, keeping only the blocks inside it.
+//!
+//! As in vanilla, the chunk a structure starts in stores the start,
+//! and every chunk the structure reaches into stores a reference
+//! to that chunk.
+
+use crate::worldgen::util::shuffle_seed_for_chunk;
+use crate::worldgen::TopBlocks;
+use feather_core::nbt::Value;
+use feather_core::{Biome, Block, Chunk, ChunkPosition};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use std::collections::HashMap;
+
+mod village;
+
+pub use village::VillageGenerator;
+
+/// A generator which lays out the pieces of a structure.
+pub trait StructureGenerator: Send + Sync {
+    /// Returns the name of the structure, as saved in
+    /// chunks, such as `Village`.
+    fn name(&self) -> &'static str;
+
+    /// Returns how the starts of the structure are spread out.
+    fn placement(&self) -> StructurePlacement;
+
+    /// Returns the maximum distance, in chunks, which pieces
+    /// of the structure reach from the chunk it starts in.
+    fn max_radius(&self) -> i32;
+
+    /// Returns whether the structure may start in
+    /// a chunk with the given biome at its center.
+    fn can_start_in(&self, biome: Biome) -> bool;
+
+    /// Lays out the pieces of the structure starting in the
+    /// given chunk. This function should be deterministic.
+    fn generate_pieces(
+        &self,
+        start: ChunkPosition,
+        rng: &mut XorShiftRng,
+    ) -> Vec<Box<dyn StructurePiece>>;
+}
+
+/// A piece of a structure, such as a house.
+pub trait StructurePiece: Send + Sync {
+    /// Returns the ID of the piece as saved in structure starts.
+    fn id(&self) -> &'static str;
+
+    /// Returns the columns covered by the piece.
+    fn bounding_box(&self) -> BoundingBox;
+
+    /// Places the blocks of the piece which are inside the
+    /// given chunk. `top_blocks` contains the highest blocks
+    /// of the chunk before any structures were placed.
+    fn place(&self, chunk: &mut Chunk, top_blocks: &TopBlocks);
+}
+
+/// A box of columns in world coordinates, including both corners.
+///
+/// Pieces follow the terrain, so bounding boxes
+/// span the full height of the world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub min_x: i32,
+    pub min_z: i32,
+    pub max_x: i32,
+    pub max_z: i32,
+}
+
+impl BoundingBox {
+    pub fn new(min_x: i32, min_z: i32, max_x: i32, max_z: i32) -> Self {
+        Self {
+            min_x,
+            min_z,
+            max_x,
+            max_z,
+        }
+    }
+
+    /// Returns the smallest bounding box containing both boxes.
+    pub fn union(self, other: Self) -> Self {
+        Self::new(
+            self.min_x.min(other.min_x),
+            self.min_z.min(other.min_z),
+            self.max_x.max(other.max_x),
+            self.max_z.max(other.max_z),
+        )
+    }
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.min_x <= other.max_x
+            && self.max_x >= other.min_x
+            && self.min_z <= other.max_z
+            && self.max_z >= other.min_z
+    }
+
+    /// Returns the bounding box of the columns of a chunk.
+    pub fn of_chunk(chunk: ChunkPosition) -> Self {
+        let (x, z) = (chunk.x * 16, chunk.z * 16);
+        Self::new(x, z, x + 15, z + 15)
+    }
+
+    /// Returns whether the bounding box lies inside a single chunk.
+    pub fn is_in_one_chunk(self) -> bool {
+        self.min_x >> 4 == self.max_x >> 4 && self.min_z >> 4 == self.max_z >> 4
+    }
+
+    /// Converts the bounding box into the int
+    /// array stored in structure starts.
+    fn to_nbt(self) -> Value {
+        Value::IntArray(vec![self.min_x, 0, self.min_z, self.max_x, 255, self.max_z])
+    }
+}
+
+/// Describes how the starts of a structure are spread out.
+///
+/// The world is divided into regions of `spacing` by `spacing` chunks.
+/// Each region contains one start, which is at least `separation`
+/// chunks away from the starts in the neighboring regions. Structures
+/// only generate at starts in a valid biome.
+#[derive(Clone, Copy, Debug)]
+pub struct StructurePlacement {
+    pub spacing: i32,
+    pub separation: i32,
+    /// Added to the seed so different structures
+    /// don't start in the same chunks.
+    pub salt: u64,
+}
+
+impl StructurePlacement {
+    /// Returns the chunk in which the start of
+    /// the given region, in regions, is placed.
+    pub fn start_in_region(self, seed: u64, region_x: i32, region_z: i32) -> ChunkPosition {
+        let region_seed = (region_x as u64)
+            .wrapping_mul(341_873_128_712)
+            .wrapping_add((region_z as u64).wrapping_mul(132_897_987_541))
+            .wrapping_add(seed)
+            .wrapping_add(self.salt);
+        let mut rng = XorShiftRng::seed_from_u64(region_seed);
+
+        let range = self.spacing - self.separation;
+        ChunkPosition::new(
+            region_x * self.spacing + rng.gen_range(0, range),
+            region_z * self.spacing + rng.gen_range(0, range),
+        )
+    }
+}
+
+/// A structure laid out from its start.
+pub struct StructureStart {
+    pub name: &'static str,
+    /// The chunk the structure starts in.
+    pub chunk: ChunkPosition,
+    pub pieces: Vec<Box<dyn StructurePiece>>,
+    pub bounding_box: BoundingBox,
+}
+
+impl StructureStart {
+    /// Places the pieces of the structure inside the given
+    /// chunk and records the structure in the chunk.
+    pub fn generate_into(&self, chunk: &mut Chunk, top_blocks: &TopBlocks) {
+        let chunk_box = BoundingBox::of_chunk(chunk.position());
+        for piece in &self.pieces {
+            if piece.bounding_box().intersects(chunk_box) {
+                piece.place(chunk, top_blocks);
+            }
+        }
+
+        chunk.add_structure_reference(self.name, self.chunk);
+        if chunk.position() == self.chunk {
+            chunk.set_structure_start(self.name, self.to_nbt());
+        }
+    }
+
+    /// Converts the start into the NBT data saved
+    /// in the chunk the structure starts in.
+    pub fn to_nbt(&self) -> Value {
+        let children = self
+            .pieces
+            .iter()
+            .map(|piece| {
+                let mut child = HashMap::new();
+                child.insert(String::from("id"), Value::String(piece.id().to_string()));
+                child.insert(String::from("BB"), piece.bounding_box().to_nbt());
+                Value::Compound(child)
+            })
+            .collect();
+
+        let mut start = HashMap::new();
+        start.insert(String::from("id"), Value::String(self.name.to_string()));
+        start.insert(String::from("ChunkX"), Value::Int(self.chunk.x));
+        start.insert(String::from("ChunkZ"), Value::Int(self.chunk.z));
+        start.insert(String::from("BB"), self.bounding_box.to_nbt());
+        start.insert(String::from("Children"), Value::List(children));
+        start.insert(String::from("Valid"), Value::Byte(1));
+        Value::Compound(start)
+    }
+}
+
+/// Returns the structures generated by the given generator
+/// which reach into the given chunk.
+///
+/// `biome_at` returns the biome at the center of a chunk.
+pub fn structures_reaching<F>(
+    generator: &dyn StructureGenerator,
+    chunk: ChunkPosition,
+    seed: u64,
+    biome_at: F,
+) -> Vec<StructureStart>
+where
+    F: Fn(ChunkPosition) -> Biome,
+{
+    let placement = generator.placement();
+    let radius = generator.max_radius();
+    let region = |coord: i32| coord.div_euclid(placement.spacing);
+
+    let mut starts = vec![];
+    for region_x in region(chunk.x - radius)..=region(chunk.x + radius) {
+        for region_z in region(chunk.z - radius)..=region(chunk.z + radius) {
+            let start = placement.start_in_region(seed, region_x, region_z);
+            if (start.x - chunk.x).abs() > radius
+                || (start.z - chunk.z).abs() > radius
+                || !generator.can_start_in(biome_at(start))
+            {
+                continue;
+            }
+
+            let mut rng = XorShiftRng::seed_from_u64(shuffle_seed_for_chunk(
+                seed.wrapping_add(placement.salt),
+                start,
+            ));
+            let pieces = generator.generate_pieces(start, &mut rng);
+            let bounding_box = pieces
+                .iter()
+                .map(|piece| piece.bounding_box())
+                .fold(BoundingBox::of_chunk(start), BoundingBox::union);
+            if bounding_box.intersects(BoundingBox::of_chunk(chunk)) {
+                starts.push(StructureStart {
+                    name: generator.name(),
+                    chunk: start,
+                    pieces,
+                    bounding_box,
+                });
+            }
+        }
+    }
+    starts
+}
+
+/// Sets the block at the given world position
+/// if it is inside the chunk.
+fn set_block(chunk: &mut Chunk, x: i32, y: i32, z: i32, block: Block) {
+    if let Some((x, y, z)) = local_position(chunk, x, y, z) {
+        chunk.set_block_at(x, y, z, block);
+    }
+}
+
+/// Converts a world position to a position inside
+/// the given chunk, if the position is inside it.
+fn local_position(chunk: &Chunk, x: i32, y: i32, z: i32) -> Option<(usize, usize, usize)> {
+    let pos = chunk.position();
+    if x >> 4 != pos.x || z >> 4 != pos.z || !(0..256).contains(&y) {
+        return None;
+    }
+    Some(((x & 0xf) as usize, y as usize, (z & 0xf) as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_in_region() {
+        let placement = StructurePlacement {
+            spacing: 32,
+            separation: 8,
+            salt: 0,
+        };
+
+        for region_x in -3..3 {
+            for region_z in -3..3 {
+                let start = placement.start_in_region(1, region_x, region_z);
+                assert_eq!(start, placement.start_in_region(1, region_x, region_z));
+                assert_eq!(start.x.div_euclid(32), region_x);
+                assert!(start.x.rem_euclid(32) < 24);
+                assert_eq!(start.z.div_euclid(32), region_z);
+                assert!(start.z.rem_euclid(32) < 24);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let chunk = BoundingBox::of_chunk(ChunkPosition::new(-1, 2));
+        assert_eq!(chunk, BoundingBox::new(-16, 32, -1, 47));
+        assert!(chunk.is_in_one_chunk());
+        assert!(chunk.intersects(BoundingBox::new(-1, 47, 5, 50)));
+        assert!(!chunk.intersects(BoundingBox::new(0, 32, 5, 50)));
+
+        let union = chunk.union(BoundingBox::new(0, 0, 1, 1));
+        assert_eq!(union, BoundingBox::new(-16, 0, 1, 47));
+        assert!(!union.is_in_one_chunk());
+    }
+}
@@ -0,0 +1,526 @@
+//! Plains villages: a well with roads leading
+//! away from it, lined with small houses.
+
+use super::{
+    local_position, set_block, BoundingBox, StructureGenerator, StructurePiece, StructurePlacement,
+};
+use crate::worldgen::TopBlocks;
+use feather_blocks::{
+    OakDoorData, OakDoorFacing, OakDoorHalf, OakDoorHinge, OakFenceData, OakLogAxis, OakLogData,
+    WaterData,
+};
+use feather_core::{Biome, Block, Chunk, ChunkPosition};
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+
+/// The minimum and maximum length of roads, in blocks.
+const MIN_ROAD_LENGTH: i32 = 16;
+const MAX_ROAD_LENGTH: i32 = 40;
+
+/// The width and depth of houses, in blocks.
+const HOUSE_SIZE: i32 = 5;
+
+/// The maximum number of blocks filled below a piece
+/// to support it above lower terrain or water.
+const MAX_FOUNDATION_DEPTH: i32 = 16;
+
+/// Generator for plains villages.
+#[derive(Default)]
+pub struct VillageGenerator;
+
+impl StructureGenerator for VillageGenerator {
+    fn name(&self) -> &'static str {
+        "Village"
+    }
+
+    fn placement(&self) -> StructurePlacement {
+        StructurePlacement {
+            spacing: 32,
+            separation: 8,
+            salt: 10_387_312,
+        }
+    }
+
+    fn max_radius(&self) -> i32 {
+        // The well, then a road and a house.
+        (8 + 3 + MAX_ROAD_LENGTH + HOUSE_SIZE + 2) / 16 + 1
+    }
+
+    fn can_start_in(&self, biome: Biome) -> bool {
+        match biome {
+            Biome::Plains | Biome::SunflowerPlains => true,
+            _ => false,
+        }
+    }
+
+    fn generate_pieces(
+        &self,
+        start: ChunkPosition,
+        rng: &mut XorShiftRng,
+    ) -> Vec<Box<dyn StructurePiece>> {
+        let center_x = start.x * 16 + 8;
+        let center_z = start.z * 16 + 8;
+
+        let well = Well { center_x, center_z };
+        let mut boxes = vec![well.bounding_box()];
+        let mut pieces: Vec<Box<dyn StructurePiece>> = vec![Box::new(well)];
+
+        for direction in &DIRECTIONS {
+            let road = Road::from_well(center_x, center_z, *direction, rng);
+            boxes.push(road.bounding_box());
+
+            // Houses line both sides of the road, with their
+            // doors opening onto it. Each house is kept inside
+            // a single chunk, so the whole house stands at the
+            // same height.
+            let mut distance = 1;
+            while distance + HOUSE_SIZE <= road.length {
+                for side in &[direction.left(), direction.right()] {
+                    if !rng.gen_bool(0.6) {
+                        continue;
+                    }
+                    let house = road.house_at(distance, *side);
+                    let bounding_box = house.bounding_box();
+                    if bounding_box.is_in_one_chunk()
+                        && boxes.iter().all(|other| !other.intersects(bounding_box))
+                    {
+                        boxes.push(bounding_box);
+                        pieces.push(Box::new(house));
+                    }
+                }
+                distance += HOUSE_SIZE + rng.gen_range(1, 4);
+            }
+
+            pieces.push(Box::new(road));
+        }
+
+        pieces
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
+impl Direction {
+    fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+        }
+    }
+
+    fn left(self) -> Self {
+        match self {
+            Direction::North => Direction::West,
+            Direction::East => Direction::North,
+            Direction::South => Direction::East,
+            Direction::West => Direction::South,
+        }
+    }
+
+    fn right(self) -> Self {
+        self.left().left().left()
+    }
+
+    fn opposite(self) -> Self {
+        self.left().left()
+    }
+
+    /// Moves the given column `distance` blocks in this direction.
+    fn step(self, (x, z): (i32, i32), distance: i32) -> (i32, i32) {
+        let (dx, dz) = self.offset();
+        (x + dx * distance, z + dz * distance)
+    }
+}
+
+/// Returns the bounding box of the columns between the given corners.
+fn box_between((x1, z1): (i32, i32), (x2, z2): (i32, i32)) -> BoundingBox {
+    BoundingBox::new(x1.min(x2), z1.min(z2), x1.max(x2), z1.max(z2))
+}
+
+/// Returns the chunk-local position of the highest block
+/// of the given world column, or `None` if it's outside the chunk.
+fn ground_at(
+    chunk: &Chunk,
+    top_blocks: &TopBlocks,
+    x: i32,
+    z: i32,
+) -> Option<(usize, usize, usize)> {
+    let (x, _, z) = local_position(chunk, x, 0, z)?;
+    Some((x, top_blocks.top_block_at(x, z), z))
+}
+
+/// Fills the columns of the given bounding box with cobblestone
+/// below `y`, down to the ground, and sets the blocks from `y`
+/// up to and including `top` to air.
+fn prepare_site(chunk: &mut Chunk, bounding_box: BoundingBox, y: i32, top: i32) {
+    for x in bounding_box.min_x..=bounding_box.max_x {
+        for z in bounding_box.min_z..=bounding_box.max_z {
+            for block_y in y..=top {
+                set_block(chunk, x, block_y, z, Block::Air);
+            }
+            for block_y in (y - MAX_FOUNDATION_DEPTH..y).rev() {
+                match local_position(chunk, x, block_y, z) {
+                    Some((lx, ly, lz)) if !is_solid(chunk.block_at(lx, ly, lz)) => {
+                        chunk.set_block_at(lx, ly, lz, Block::Cobblestone)
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+fn is_solid(block: Block) -> bool {
+    match block {
+        Block::Air | Block::Water(_) | Block::Lava(_) | Block::Grass | Block::Fern => false,
+        _ => true,
+    }
+}
+
+/// The well at the center of a village.
+struct Well {
+    center_x: i32,
+    center_z: i32,
+}
+
+impl StructurePiece for Well {
+    fn id(&self) -> &'static str {
+        "ViW"
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            self.center_x - 2,
+            self.center_z - 2,
+            self.center_x + 1,
+            self.center_z + 1,
+        )
+    }
+
+    fn place(&self, chunk: &mut Chunk, top_blocks: &TopBlocks) {
+        let y = match ground_at(chunk, top_blocks, self.center_x, self.center_z) {
+            Some((_, ground, _)) => ground as i32,
+            None => return,
+        };
+        let bounding_box = self.bounding_box();
+        prepare_site(chunk, bounding_box, y, y + 4);
+
+        let fence = Block::OakFence(OakFenceData {
+            west: false,
+            east: false,
+            waterlogged: false,
+            south: false,
+            north: false,
+        });
+        for x in bounding_box.min_x..=bounding_box.max_x {
+            for z in bounding_box.min_z..=bounding_box.max_z {
+                let inner = (x == self.center_x - 1 || x == self.center_x)
+                    && (z == self.center_z - 1 || z == self.center_z);
+                let corner = (x == bounding_box.min_x || x == bounding_box.max_x)
+                    && (z == bounding_box.min_z || z == bounding_box.max_z);
+
+                if inner {
+                    set_block(chunk, x, y - 2, z, Block::Cobblestone);
+                    set_block(chunk, x, y - 1, z, Block::Water(WaterData::default()));
+                    set_block(chunk, x, y, z, Block::Water(WaterData::default()));
+                } else {
+                    set_block(chunk, x, y, z, Block::Cobblestone);
+                    set_block(chunk, x, y + 1, z, Block::Cobblestone);
+                }
+                if corner {
+                    set_block(chunk, x, y + 2, z, fence);
+                    set_block(chunk, x, y + 3, z, fence);
+                }
+                set_block(chunk, x, y + 4, z, Block::Cobblestone);
+            }
+        }
+    }
+}
+
+/// A straight gravel road, three blocks wide.
+struct Road {
+    /// The column at the center of the first row of the road.
+    start: (i32, i32),
+    direction: Direction,
+    length: i32,
+}
+
+impl Road {
+    /// Creates a road leading away from the well
+    /// at the given column in the given direction.
+    fn from_well(center_x: i32, center_z: i32, direction: Direction, rng: &mut impl Rng) -> Self {
+        // The well covers the columns from two blocks before
+        // the center to one block after it on each axis.
+        let distance = match direction {
+            Direction::North | Direction::West => 3,
+            Direction::East | Direction::South => 2,
+        };
+        Self {
+            start: direction.step((center_x, center_z), distance),
+            direction,
+            length: rng.gen_range(MIN_ROAD_LENGTH, MAX_ROAD_LENGTH + 1),
+        }
+    }
+
+    /// Returns the column at the center of the given row of the road.
+    fn row(&self, distance: i32) -> (i32, i32) {
+        self.direction.step(self.start, distance)
+    }
+
+    /// Returns a house next to the road, starting at
+    /// the given row, on the given side of the road.
+    fn house_at(&self, distance: i32, side: Direction) -> House {
+        let center = side.step(self.row(distance + HOUSE_SIZE / 2), 2 + HOUSE_SIZE / 2);
+        House {
+            center_x: center.0,
+            center_z: center.1,
+            entrance: side.opposite(),
+        }
+    }
+}
+
+impl StructurePiece for Road {
+    fn id(&self) -> &'static str {
+        "ViSR"
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let side = self.direction.left();
+        box_between(
+            side.step(self.row(0), 1),
+            side.opposite().step(self.row(self.length - 1), 1),
+        )
+    }
+
+    fn place(&self, chunk: &mut Chunk, top_blocks: &TopBlocks) {
+        let bounding_box = self.bounding_box();
+        for x in bounding_box.min_x..=bounding_box.max_x {
+            for z in bounding_box.min_z..=bounding_box.max_z {
+                let (lx, ground, lz) = continue_if_none!(ground_at(chunk, top_blocks, x, z));
+                let block = match chunk.block_at(lx, ground, lz) {
+                    // Bridges cross water.
+                    Block::Water(_) => Block::OakPlanks,
+                    Block::Lava(_) | Block::Air => continue,
+                    _ => Block::Gravel,
+                };
+                chunk.set_block_at(lx, ground, lz, block);
+            }
+        }
+    }
+}
+
+/// A small house with a flat roof.
+struct House {
+    center_x: i32,
+    center_z: i32,
+    /// The direction from the center of the house to its door.
+    entrance: Direction,
+}
+
+impl StructurePiece for House {
+    fn id(&self) -> &'static str {
+        "ViSH"
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let half = HOUSE_SIZE / 2;
+        BoundingBox::new(
+            self.center_x - half,
+            self.center_z - half,
+            self.center_x + half,
+            self.center_z + half,
+        )
+    }
+
+    fn place(&self, chunk: &mut Chunk, top_blocks: &TopBlocks) {
+        let y = match ground_at(chunk, top_blocks, self.center_x, self.center_z) {
+            Some((_, ground, _)) => ground as i32,
+            None => return,
+        };
+        let bounding_box = self.bounding_box();
+        prepare_site(chunk, bounding_box, y, y + 4);
+
+        let log = Block::OakLog(OakLogData {
+            axis: OakLogAxis::Y,
+        });
+        for x in bounding_box.min_x..=bounding_box.max_x {
+            for z in bounding_box.min_z..=bounding_box.max_z {
+                let edge_x = x == bounding_box.min_x || x == bounding_box.max_x;
+                let edge_z = z == bounding_box.min_z || z == bounding_box.max_z;
+
+                set_block(chunk, x, y, z, Block::Cobblestone);
+                if edge_x && edge_z {
+                    for wall_y in y + 1..=y + 3 {
+                        set_block(chunk, x, wall_y, z, log);
+                    }
+                } else if edge_x || edge_z {
+                    for wall_y in y + 1..=y + 3 {
+                        set_block(chunk, x, wall_y, z, Block::OakPlanks);
+                    }
+                }
+                set_block(chunk, x, y + 4, z, Block::OakPlanks);
+            }
+        }
+
+        // A window in the middle of each wall but the front one.
+        let center = (self.center_x, self.center_z);
+        let half = HOUSE_SIZE / 2;
+        for direction in &DIRECTIONS {
+            if *direction != self.entrance {
+                let (x, z) = direction.step(center, half);
+                set_block(chunk, x, y + 2, z, Block::Glass);
+            }
+        }
+
+        let (x, z) = self.entrance.step(center, half);
+        let door = |half| {
+            Block::OakDoor(OakDoorData {
+                facing: door_facing(self.entrance.opposite()),
+                hinge: OakDoorHinge::Left,
+                half,
+                powered: false,
+                open: false,
+            })
+        };
+        set_block(chunk, x, y + 1, z, door(OakDoorHalf::Lower));
+        set_block(chunk, x, y + 2, z, door(OakDoorHalf::Upper));
+    }
+}
+
+/// Returns the facing of a door entered
+/// by walking in the given direction.
+fn door_facing(direction: Direction) -> OakDoorFacing {
+    match direction {
+        Direction::North => OakDoorFacing::North,
+        Direction::East => OakDoorFacing::East,
+        Direction::South => OakDoorFacing::South,
+        Direction::West => OakDoorFacing::West,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::structures::structures_reaching;
+    use feather_blocks::GrassBlockData;
+    use rand::SeedableRng;
+
+    fn grass_chunk(pos: ChunkPosition) -> (Chunk, TopBlocks) {
+        let mut chunk = Chunk::new(pos);
+        let mut top_blocks = TopBlocks::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in 0..64 {
+                    chunk.set_block_at(x, y, z, Block::Dirt);
+                }
+                chunk.set_block_at(x, 64, z, Block::GrassBlock(GrassBlockData { snowy: false }));
+                top_blocks.set_top_block_at(x, z, 64);
+            }
+        }
+        (chunk, top_blocks)
+    }
+
+    #[test]
+    fn test_village_layout() {
+        let generator = VillageGenerator;
+        let start = ChunkPosition::new(3, -2);
+        let pieces = generator.generate_pieces(start, &mut XorShiftRng::seed_from_u64(5));
+
+        assert_eq!(pieces[0].id(), "ViW");
+        assert_eq!(
+            pieces.iter().filter(|piece| piece.id() == "ViSR").count(),
+            4
+        );
+        assert!(pieces.iter().any(|piece| piece.id() == "ViSH"));
+
+        let radius = generator.max_radius() * 16;
+        for (i, piece) in pieces.iter().enumerate() {
+            let bounding_box = piece.bounding_box();
+            assert!(bounding_box.min_x >= start.x * 16 - radius);
+            assert!(bounding_box.max_x < start.x * 16 + 16 + radius);
+            assert!(bounding_box.min_z >= start.z * 16 - radius);
+            assert!(bounding_box.max_z < start.z * 16 + 16 + radius);
+
+            // Houses don't overlap other pieces.
+            if piece.id() == "ViSH" {
+                assert!(bounding_box.is_in_one_chunk());
+                for (j, other) in pieces.iter().enumerate() {
+                    assert!(i == j || !other.bounding_box().intersects(bounding_box));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_place_village() {
+        let generator = VillageGenerator;
+        let seed = 77;
+        let placement = generator.placement();
+        let start = placement.start_in_region(seed, 0, 0);
+
+        let starts = structures_reaching(&generator, start, seed, |_| Biome::Plains);
+        assert_eq!(starts.len(), 1);
+        let village = &starts[0];
+        assert_eq!(village.chunk, start);
+
+        let (mut chunk, top_blocks) = grass_chunk(start);
+        village.generate_into(&mut chunk, &top_blocks);
+
+        // The well is at the center of the start chunk.
+        assert_eq!(chunk.block_at(7, 64, 7), Block::Water(WaterData::default()));
+        assert_eq!(chunk.block_at(6, 68, 6), Block::Cobblestone);
+        assert!(chunk.structure_start("Village").is_some());
+        assert_eq!(chunk.structure_references("Village"), &[start][..]);
+
+        // Roads lead away from the well.
+        assert_eq!(chunk.block_at(10, 64, 8), Block::Gravel);
+        assert_eq!(chunk.block_at(8, 64, 10), Block::Gravel);
+
+        // Chunks far away from the start don't contain the village.
+        let far = ChunkPosition::new(start.x + 10, start.z);
+        assert!(
+            structures_reaching(&generator, far, seed, |_| Biome::Plains)
+                .iter()
+                .all(|other| other.chunk != start)
+        );
+        assert!(structures_reaching(&generator, start, seed, |_| Biome::Desert).is_empty());
+    }
+
+    #[test]
+    fn test_house() {
+        let house = House {
+            center_x: 8,
+            center_z: 8,
+            entrance: Direction::South,
+        };
+        let (mut chunk, top_blocks) = grass_chunk(ChunkPosition::new(0, 0));
+        house.place(&mut chunk, &top_blocks);
+
+        assert_eq!(chunk.block_at(8, 64, 8), Block::Cobblestone);
+        assert_eq!(chunk.block_at(8, 65, 8), Block::Air);
+        assert_eq!(chunk.block_at(8, 68, 8), Block::OakPlanks);
+        assert_eq!(chunk.block_at(8, 66, 6), Block::Glass);
+        match chunk.block_at(8, 65, 10) {
+            Block::OakDoor(data) => {
+                assert_eq!(data.half, OakDoorHalf::Lower);
+                assert_eq!(data.facing, OakDoorFacing::North);
+            }
+            block => panic!("expected a door, got {:?}", block),
+        }
+    }
+}